@@ -185,6 +185,15 @@ pub struct PushOutputMessageReply {
     pub result: Result<(), (StateError, ic_types::messages::Request)>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AvailableOutputRequestSlotsRequest {
+    pub receiver: CanisterId,
+}
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AvailableOutputRequestSlotsReply {
+    pub slots: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CanisterStatusRequest {}
 #[derive(Serialize, Deserialize, Clone)]
@@ -216,6 +225,7 @@ pub enum Request {
     RegisterCallback(RegisterCallbackRequest),
     UnregisterCallback(UnregisterCallbackRequest),
     PushOutputMessage(PushOutputMessageRequest),
+    AvailableOutputRequestSlots(AvailableOutputRequestSlotsRequest),
     CanisterStatus(CanisterStatusRequest),
 }
 #[derive(Serialize, Deserialize, Clone)]
@@ -239,5 +249,6 @@ pub enum Reply {
     RegisterCallback(RegisterCallbackReply),
     UnregisterCallback(UnregisterCallbackReply),
     PushOutputMessage(PushOutputMessageReply),
+    AvailableOutputRequestSlots(AvailableOutputRequestSlotsReply),
     CanisterStatus(CanisterStatusReply),
 }
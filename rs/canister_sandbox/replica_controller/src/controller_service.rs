@@ -187,6 +187,7 @@ impl ControllerServer {
             | ApiType::Cleanup { .. }
             | ApiType::PreUpgrade { .. } => "update".to_owned(),
             ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::ReplyCallback { .. }
             | ApiType::RejectCallback { .. }
@@ -652,6 +653,13 @@ impl ControllerService for ControllerServer {
                         );
                         Reply::PushOutputMessage(PushOutputMessageReply { result })
                     }
+                    Request::AvailableOutputRequestSlots(req) => {
+                        let slots =
+                            system_state_accessor.available_output_request_slots(req.receiver);
+                        Reply::AvailableOutputRequestSlots(AvailableOutputRequestSlotsReply {
+                            slots,
+                        })
+                    }
                     Request::CanisterStatus(_req) => {
                         let status = system_state_accessor.canister_status();
                         Reply::CanisterStatus(CanisterStatusReply { status })
@@ -243,6 +243,7 @@ mod tests {
             last_executed_round: ExecutionRound::from(0),
             cow_mem_mgr: Arc::new(CowMemoryManagerImpl::open_readwrite(tmpdir.path().into())),
             mapped_state: None,
+            pending_compilation_trigger: None,
         };
         canister_state.execution_state = Some(execution_state);
 
@@ -571,11 +572,13 @@ mod tests {
                 public_key: vec![1, 2, 3, 4],
                 nodes: btreemap!{},
                 subnet_type: SubnetType::Application,
+                reserved_canister_id_ranges: Default::default(),
             },
             subnet_test_id(1) => SubnetTopology {
                 public_key: vec![5, 6, 7, 8],
                 nodes: btreemap!{},
                 subnet_type: SubnetType::Application,
+                reserved_canister_id_ranges: Default::default(),
             }
         };
         fn id_range(from: u64, to: u64) -> CanisterIdRange {
@@ -1,13 +1,13 @@
-use ic_crypto_tree_hash::LabeledTree;
-use ic_crypto_utils_threshold_sig::verify_combined;
+use ic_crypto_tree_hash::{lookup_path, LabeledTree};
+use ic_crypto_utils_threshold_sig::{threshold_sig_public_key_from_der, verify_combined};
 use ic_types::{
     consensus::certification::CertificationContent,
     crypto::{
         threshold_sig::ThresholdSigPublicKey, CombinedThresholdSig, CombinedThresholdSigOf,
         CryptoHash,
     },
-    messages::{Blob, Certificate},
-    CanisterId, CryptoHashOfPartialState, Time,
+    messages::{Blob, Certificate, CertificateDelegation, MessageId},
+    CanisterId, CryptoHashOfPartialState, PrincipalId, Time,
 };
 use serde::Deserialize;
 use std::collections::BTreeMap;
@@ -34,6 +34,9 @@ pub enum CertificateValidationError {
     /// The certification contains a subnet delegation, which is not allowed for
     /// certificates coming from the root subnet.
     SubnetDelegationNotAllowed,
+    /// The subnet delegation of the certificate is invalid or does not cover
+    /// the canister.
+    InvalidDelegation(String),
 }
 
 impl fmt::Display for CertificateValidationError {
@@ -58,6 +61,7 @@ impl fmt::Display for CertificateValidationError {
                 f,
                 "expected certificate from the root subnet but found delegations in the certificate"
             ),
+            Self::InvalidDelegation(err) => write!(f, "invalid subnet delegation: {}", err),
         }
     }
 }
@@ -73,37 +77,192 @@ pub fn verify_certificate(
     root_pk: &ThresholdSigPublicKey,
     certified_data: &[u8],
 ) -> Result<Time, CertificateValidationError> {
-    #[derive(Deserialize)]
-    struct CanisterView {
-        certified_data: Blob,
+    let certificate = decode_certificate(certificate, canister_id)?;
+
+    if certificate.delegation.is_some() {
+        return Err(CertificateValidationError::SubnetDelegationNotAllowed);
     }
 
+    verify_certified_data(certificate, canister_id, root_pk, certified_data)
+}
+
+/// Like `verify_certificate`, but also accepts certificates issued by the
+/// subnet of the canister rather than by the root subnet.
+///
+/// Such certificates carry a delegation: a certificate signed with `root_pk`
+/// that contains the public key of the issuing subnet and the canister ranges
+/// assigned to it. The delegation must not be delegated itself and the
+/// canister must be in one of the ranges of the subnet.
+pub fn verify_certificate_with_delegation(
+    certificate: &[u8],
+    canister_id: &CanisterId,
+    root_pk: &ThresholdSigPublicKey,
+    certified_data: &[u8],
+) -> Result<Time, CertificateValidationError> {
+    let certificate = decode_certificate(certificate, canister_id)?;
+
+    let subnet_pk = match &certificate.delegation {
+        None => *root_pk,
+        Some(delegation) => verify_delegation(delegation, canister_id, root_pk)?,
+    };
+
+    verify_certified_data(certificate, canister_id, &subnet_pk, certified_data)
+}
+
+/// Checks that `certificate` was signed with the public key `subnet_pk` of a
+/// subnet and returns the reply to the ingress message `message_id` that it
+/// certifies, together with the timestamp on the certificate.
+///
+/// The signature of the subnet is verified directly, so a delegation that the
+/// certificate may carry is not needed and ignored.
+pub fn verify_certified_reply(
+    certificate: &[u8],
+    subnet_pk: &ThresholdSigPublicKey,
+    message_id: &MessageId,
+) -> Result<(Time, Vec<u8>), CertificateValidationError> {
     #[derive(Deserialize)]
     struct ReplicaState {
         time: Leb128EncodedU64,
-        canister: BTreeMap<CanisterId, CanisterView>,
     }
 
     let certificate: Certificate = serde_cbor::from_slice(certificate).map_err(|err| {
         CertificateValidationError::DeserError(format!(
-            "failed to decode certificate from canister {}: {}",
-            canister_id, err
+            "failed to decode certificate of the reply to {}: {}",
+            message_id, err
         ))
     })?;
+    verify_signature(&certificate, subnet_pk)?;
 
-    if certificate.delegation.is_some() {
-        return Err(CertificateValidationError::SubnetDelegationNotAllowed);
-    }
+    let tree = LabeledTree::<Vec<u8>>::try_from(certificate.tree).map_err(|err| {
+        CertificateValidationError::MalformedHashTree(format!(
+            "failed to convert hash tree to labeled tree: {:?}",
+            err
+        ))
+    })?;
+    let replica_state =
+        ReplicaState::deserialize(LabeledTreeDeserializer::new(&tree)).map_err(|err| {
+            CertificateValidationError::DeserError(format!(
+                "failed to unpack replica state from a labeled tree: {}",
+                err
+            ))
+        })?;
+    let reply = match lookup_path(&tree, &[b"request_status", message_id.as_bytes(), b"reply"]) {
+        Some(LabeledTree::Leaf(reply)) => reply.clone(),
+        _ => {
+            return Err(CertificateValidationError::MalformedHashTree(format!(
+                "cannot find the reply to {} in the tree",
+                message_id
+            )))
+        }
+    };
+
+    Ok((
+        Time::from_nanos_since_unix_epoch(replica_state.time.0),
+        reply,
+    ))
+}
+
+fn decode_certificate(
+    certificate: &[u8],
+    canister_id: &CanisterId,
+) -> Result<Certificate, CertificateValidationError> {
+    serde_cbor::from_slice(certificate).map_err(|err| {
+        CertificateValidationError::DeserError(format!(
+            "failed to decode certificate from canister {}: {}",
+            canister_id, err
+        ))
+    })
+}
 
+fn verify_signature(
+    certificate: &Certificate,
+    pk: &ThresholdSigPublicKey,
+) -> Result<(), CertificateValidationError> {
     let digest = CryptoHashOfPartialState::from(CryptoHash(certificate.tree.digest().to_vec()));
     let content = CertificationContent::new(digest.clone());
     let sig = CombinedThresholdSigOf::new(CombinedThresholdSig(certificate.signature.to_vec()));
-    verify_combined(&content, &sig, root_pk).map_err(|err| {
+    verify_combined(&content, &sig, pk).map_err(|err| {
         CertificateValidationError::InvalidSignature(format!(
             "root_hash={:?}, sig={:?}, pk={:?}, error={:?}",
-            digest, certificate.signature, root_pk, err
+            digest, certificate.signature, pk, err
+        ))
+    })
+}
+
+// Verifies the delegation from the root subnet to the subnet that issued a
+// certificate for `canister_id`, and returns the public key of that subnet.
+fn verify_delegation(
+    delegation: &CertificateDelegation,
+    canister_id: &CanisterId,
+    root_pk: &ThresholdSigPublicKey,
+) -> Result<ThresholdSigPublicKey, CertificateValidationError> {
+    let invalid = |msg: String| CertificateValidationError::InvalidDelegation(msg);
+
+    let certificate: Certificate = serde_cbor::from_slice(&delegation.certificate)
+        .map_err(|err| invalid(format!("failed to decode delegation certificate: {}", err)))?;
+    if certificate.delegation.is_some() {
+        return Err(invalid(
+            "the delegation certificate is itself delegated".to_string(),
+        ));
+    }
+    verify_signature(&certificate, root_pk)?;
+
+    let tree = LabeledTree::<Vec<u8>>::try_from(certificate.tree).map_err(|err| {
+        CertificateValidationError::MalformedHashTree(format!(
+            "failed to convert delegation hash tree to labeled tree: {:?}",
+            err
         ))
     })?;
+    let subnet_id = PrincipalId::try_from(delegation.subnet_id.as_slice())
+        .map_err(|err| invalid(format!("malformed subnet id: {}", err)))?;
+    let lookup_leaf = |label: &[u8]| match lookup_path(
+        &tree,
+        &[b"subnet", delegation.subnet_id.as_slice(), label],
+    ) {
+        Some(LabeledTree::Leaf(leaf)) => Ok(leaf),
+        _ => Err(invalid(format!(
+            "cannot find subnet/{}/{} in the delegation certificate",
+            subnet_id,
+            String::from_utf8_lossy(label)
+        ))),
+    };
+
+    let canister_ranges: Vec<(PrincipalId, PrincipalId)> =
+        serde_cbor::from_slice(lookup_leaf(b"canister_ranges")?)
+            .map_err(|err| invalid(format!("failed to decode canister ranges: {}", err)))?;
+    let canister = canister_id.get_ref();
+    if !canister_ranges
+        .iter()
+        .any(|(start, end)| start <= canister && canister <= end)
+    {
+        return Err(invalid(format!(
+            "canister {} is not assigned to subnet {}",
+            canister_id, subnet_id
+        )));
+    }
+
+    threshold_sig_public_key_from_der(lookup_leaf(b"public_key")?)
+        .map_err(|err| invalid(format!("malformed subnet public key: {}", err)))
+}
+
+fn verify_certified_data(
+    certificate: Certificate,
+    canister_id: &CanisterId,
+    pk: &ThresholdSigPublicKey,
+    certified_data: &[u8],
+) -> Result<Time, CertificateValidationError> {
+    #[derive(Deserialize)]
+    struct CanisterView {
+        certified_data: Blob,
+    }
+
+    #[derive(Deserialize)]
+    struct ReplicaState {
+        time: Leb128EncodedU64,
+        canister: BTreeMap<CanisterId, CanisterView>,
+    }
+
+    verify_signature(&certificate, pk)?;
 
     let replica_labeled_tree =
         LabeledTree::<Vec<u8>>::try_from(certificate.tree).map_err(|err| {
@@ -145,3 +304,6 @@ pub fn verify_certificate(
 
     Ok(time)
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,174 @@
+#![allow(clippy::unwrap_used)]
+use super::*;
+use ic_crypto::{combined_threshold_signature_and_public_key, threshold_sig_public_key_to_der};
+use ic_crypto_tree_hash::{Label, MixedHashTree};
+use ic_types::{messages::Blob, Randomness, SubnetId};
+
+const CERTIFIED_DATA: &[u8] = b"certified data";
+const TIME_NANOS: u64 = 1_234_567_890;
+
+fn canister_id(id: u64) -> CanisterId {
+    CanisterId::from_u64(id)
+}
+
+fn subnet_id() -> SubnetId {
+    SubnetId::from(PrincipalId::new_subnet_test_id(1))
+}
+
+fn labeled(label: &[u8], tree: MixedHashTree) -> MixedHashTree {
+    MixedHashTree::Labeled(Label::from(label), Box::new(tree))
+}
+
+fn fork(left: MixedHashTree, right: MixedHashTree) -> MixedHashTree {
+    MixedHashTree::Fork(Box::new((left, right)))
+}
+
+// Signs `tree` with the key derived from `seed` and returns the certificate
+// together with the public key.
+fn sign_tree(
+    tree: MixedHashTree,
+    delegation: Option<CertificateDelegation>,
+    seed: u8,
+) -> (Certificate, ThresholdSigPublicKey) {
+    let root_hash = CryptoHashOfPartialState::from(CryptoHash(tree.digest().to_vec()));
+    let (signature, public_key) = combined_threshold_signature_and_public_key(
+        Randomness::from([seed; 32]),
+        &CertificationContent::new(root_hash),
+    );
+    let certificate = Certificate {
+        tree,
+        signature: Blob(signature.get().0),
+        delegation,
+    };
+    (certificate, public_key)
+}
+
+// Returns a delegation from the root subnet, whose key is derived from
+// `root_seed`, to `subnet_id` with `subnet_pk` and the single canister range
+// `canister_range`, together with the public key of the root subnet.
+fn delegation(
+    subnet_id: SubnetId,
+    subnet_pk: ThresholdSigPublicKey,
+    canister_range: (CanisterId, CanisterId),
+    root_seed: u8,
+) -> (CertificateDelegation, ThresholdSigPublicKey) {
+    let canister_ranges = vec![(canister_range.0.get(), canister_range.1.get())];
+    let tree = labeled(
+        b"subnet",
+        labeled(
+            subnet_id.get().as_slice(),
+            fork(
+                labeled(
+                    b"canister_ranges",
+                    MixedHashTree::Leaf(serde_cbor::to_vec(&canister_ranges).unwrap()),
+                ),
+                labeled(
+                    b"public_key",
+                    MixedHashTree::Leaf(threshold_sig_public_key_to_der(subnet_pk).unwrap()),
+                ),
+            ),
+        ),
+    );
+    let (certificate, root_pk) = sign_tree(tree, None, root_seed);
+    let delegation = CertificateDelegation {
+        subnet_id: Blob(subnet_id.get().to_vec()),
+        certificate: Blob(serde_cbor::to_vec(&certificate).unwrap()),
+    };
+    (delegation, root_pk)
+}
+
+// Returns a certificate of `CERTIFIED_DATA` of `canister_id` that is issued by
+// a subnet whose key is derived from `subnet_seed`, together with the public
+// key of that subnet.
+fn certificate(
+    canister_id: CanisterId,
+    delegation: Option<CertificateDelegation>,
+    subnet_seed: u8,
+) -> (Certificate, ThresholdSigPublicKey) {
+    let mut encoded_time = vec![];
+    leb128::write::unsigned(&mut encoded_time, TIME_NANOS).unwrap();
+    let tree = fork(
+        labeled(
+            b"canister",
+            labeled(
+                canister_id.get().as_slice(),
+                labeled(
+                    b"certified_data",
+                    MixedHashTree::Leaf(CERTIFIED_DATA.to_vec()),
+                ),
+            ),
+        ),
+        labeled(b"time", MixedHashTree::Leaf(encoded_time)),
+    );
+    sign_tree(tree, delegation, subnet_seed)
+}
+
+// Returns a certificate of `CERTIFIED_DATA` of `canister_id` issued by a
+// subnet that is assigned `canister_range` by the delegation, together with
+// the public key of the root subnet.
+fn delegated_certificate(
+    canister_id: CanisterId,
+    canister_range: (CanisterId, CanisterId),
+) -> (Vec<u8>, ThresholdSigPublicKey) {
+    // The public key of the subnet does not depend on what is signed with it.
+    let (_, subnet_pk) = certificate(canister_id, None, 2);
+    let (delegation, root_pk) = delegation(subnet_id(), subnet_pk, canister_range, 1);
+    let (certificate, _) = certificate(canister_id, Some(delegation), 2);
+    (serde_cbor::to_vec(&certificate).unwrap(), root_pk)
+}
+
+#[test]
+fn should_verify_certificate_with_valid_delegation() {
+    let (certificate, root_pk) =
+        delegated_certificate(canister_id(5), (canister_id(0), canister_id(10)));
+
+    let result =
+        verify_certificate_with_delegation(&certificate, &canister_id(5), &root_pk, CERTIFIED_DATA);
+
+    assert_eq!(
+        result.unwrap(),
+        Time::from_nanos_since_unix_epoch(TIME_NANOS)
+    );
+}
+
+#[test]
+fn should_verify_certificate_without_delegation_with_delegation_allowed() {
+    let (certificate, root_pk) = certificate(canister_id(5), None, 1);
+    let certificate = serde_cbor::to_vec(&certificate).unwrap();
+
+    let result =
+        verify_certificate_with_delegation(&certificate, &canister_id(5), &root_pk, CERTIFIED_DATA);
+
+    assert_eq!(
+        result.unwrap(),
+        Time::from_nanos_since_unix_epoch(TIME_NANOS)
+    );
+}
+
+#[test]
+fn should_fail_to_verify_certificate_if_canister_is_not_in_delegated_ranges() {
+    let (certificate, root_pk) =
+        delegated_certificate(canister_id(5), (canister_id(6), canister_id(10)));
+
+    let result =
+        verify_certificate_with_delegation(&certificate, &canister_id(5), &root_pk, CERTIFIED_DATA);
+
+    assert!(matches!(
+        result,
+        Err(CertificateValidationError::InvalidDelegation(err))
+            if err.contains("is not assigned to subnet")
+    ));
+}
+
+#[test]
+fn should_fail_to_verify_certificate_with_delegation_if_not_allowed() {
+    let (certificate, root_pk) =
+        delegated_certificate(canister_id(5), (canister_id(0), canister_id(10)));
+
+    let result = verify_certificate(&certificate, &canister_id(5), &root_pk, CERTIFIED_DATA);
+
+    assert!(matches!(
+        result,
+        Err(CertificateValidationError::SubnetDelegationNotAllowed)
+    ));
+}
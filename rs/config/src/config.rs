@@ -6,6 +6,7 @@
 use crate::{
     artifact_pool::ArtifactPoolTomlConfig,
     config_parser::{ConfigError, ConfigSource, ConfigValidate},
+    config_validation::{check_config, ConfigDiagnostic},
     consensus::ConsensusConfig,
     crypto::CryptoConfig,
     execution_environment::Config as HypervisorConfig,
@@ -16,9 +17,13 @@ use crate::{
     message_routing::Config as MessageRoutingConfig,
     metrics::Config as MetricsConfig,
     nns_registry_replicator::Config as NnsRegistryReplicatorConfig,
+    node_reward_reporter::Config as NodeRewardReporterConfig,
     registration::Config as RegistrationConfig,
     registry_client::Config as RegistryClientConfig,
+    replica_cgroup::Config as ReplicaCgroupConfig,
     state_manager::Config as StateManagerConfig,
+    time_sync::Config as TimeSyncConfig,
+    tracing::Config as TracingConfig,
 };
 use ic_types::{malicious_behaviour::MaliciousBehaviour, transport::TransportConfig};
 use serde::{Deserialize, Serialize};
@@ -46,6 +51,10 @@ pub struct Config {
     pub firewall: FirewallConfig,
     pub registration: RegistrationConfig,
     pub nns_registry_replicator: NnsRegistryReplicatorConfig,
+    pub node_reward_reporter: NodeRewardReporterConfig,
+    pub replica_cgroup: ReplicaCgroupConfig,
+    pub time_sync: TimeSyncConfig,
+    pub tracing: TracingConfig,
 }
 
 /// Mirrors the Config struct except that fields are made optional. This is
@@ -68,6 +77,10 @@ pub struct ConfigOptional {
     pub firewall: Option<FirewallConfig>,
     pub registration: Option<RegistrationConfig>,
     pub nns_registry_replicator: Option<NnsRegistryReplicatorConfig>,
+    pub node_reward_reporter: Option<NodeRewardReporterConfig>,
+    pub replica_cgroup: Option<ReplicaCgroupConfig>,
+    pub time_sync: Option<TimeSyncConfig>,
+    pub tracing: Option<TracingConfig>,
 }
 
 impl Config {
@@ -82,7 +95,10 @@ impl Config {
             registry_client: RegistryClientConfig::default(),
             transport: TransportConfig::default(),
             state_manager: StateManagerConfig::new(parent_dir.join("state")),
-            hypervisor: HypervisorConfig::default(),
+            hypervisor: HypervisorConfig {
+                compiled_artifacts_dir: Some(parent_dir.join("compiled_artifacts")),
+                ..HypervisorConfig::default()
+            },
             http_handler: HttpHandlerConfig::default(),
             metrics: MetricsConfig::default(),
             artifact_pool: ArtifactPoolTomlConfig::new(parent_dir.join("consensus_pool"), None),
@@ -95,6 +111,10 @@ impl Config {
             firewall: FirewallConfig::default(),
             registration: RegistrationConfig::default(),
             nns_registry_replicator: NnsRegistryReplicatorConfig::default(),
+            node_reward_reporter: NodeRewardReporterConfig::default(),
+            replica_cgroup: ReplicaCgroupConfig::default(),
+            time_sync: TimeSyncConfig::default(),
+            tracing: TracingConfig::default(),
         }
     }
 
@@ -119,9 +139,67 @@ impl Config {
 
     /// Load [Config] from the given 'config_descr' where if a section is
     /// omitted, its value is taken from the given 'default'.
+    ///
+    /// Fails if the strict validation of the config reports an error, see
+    /// [Config::load_with_diagnostics].
     pub fn load_with_default(source: &ConfigSource, default: Config) -> Result<Self, ConfigError> {
-        let cfg = source.load::<ConfigOptional>()?;
+        Self::load_with_diagnostics(source, default).map(|(config, _)| config)
+    }
+
+    /// Like [Config::load_with_default], but also returns the warnings of the
+    /// strict validation, e.g. about deprecated fields.
+    pub fn load_with_diagnostics(
+        source: &ConfigSource,
+        default: Config,
+    ) -> Result<(Self, Vec<ConfigDiagnostic>), ConfigError> {
+        let (config, diagnostics) = Self::diagnose(source, default)?;
+        let errors: Vec<String> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        if !errors.is_empty() {
+            return Err(ConfigError::ValidationError {
+                source: source.clone(),
+                message: errors.join("; "),
+            });
+        }
+        Ok((config, diagnostics))
+    }
+
+    /// Returns all problems found in the config from the given source,
+    /// including failures to read or parse it.
+    pub fn validate_source(source: &ConfigSource, default: Config) -> Vec<ConfigDiagnostic> {
+        match Self::diagnose(source, default) {
+            Ok((_, diagnostics)) => diagnostics,
+            Err(err) => vec![ConfigDiagnostic::from(&err)],
+        }
+    }
+
+    fn diagnose(
+        source: &ConfigSource,
+        default: Config,
+    ) -> Result<(Self, Vec<ConfigDiagnostic>), ConfigError> {
+        let contents = source.read()?;
+        let config = Self::from_optional(
+            source,
+            match &contents {
+                Some(contents) => source.parse::<ConfigOptional>(contents)?,
+                None => ConfigOptional::default(),
+            },
+            default,
+        )?;
+        let diagnostics = contents
+            .map(|contents| check_config(&contents, &config))
+            .unwrap_or_default();
+        Ok((config, diagnostics))
+    }
 
+    fn from_optional(
+        source: &ConfigSource,
+        cfg: ConfigOptional,
+        default: Config,
+    ) -> Result<Self, ConfigError> {
         let logger = cfg.logger.unwrap_or(default.logger);
         let nodemanager_logger = cfg.nodemanager_logger.unwrap_or_else(|| logger.clone());
 
@@ -151,6 +229,12 @@ impl Config {
             nns_registry_replicator: cfg
                 .nns_registry_replicator
                 .unwrap_or(default.nns_registry_replicator),
+            node_reward_reporter: cfg
+                .node_reward_reporter
+                .unwrap_or(default.node_reward_reporter),
+            replica_cgroup: cfg.replica_cgroup.unwrap_or(default.replica_cgroup),
+            time_sync: cfg.time_sync.unwrap_or(default.time_sync),
+            tracing: cfg.tracing.unwrap_or(default.tracing),
         })
     }
 
@@ -158,10 +242,15 @@ impl Config {
     pub fn load_with_tmpdir(config_source: ConfigSource, tmpdir: PathBuf) -> Config {
         let default_config = Config::new(tmpdir);
 
-        Config::load_with_default(&config_source, default_config).unwrap_or_else(|err| {
-            eprintln!("Failed to load config:\n  {}", err);
-            std::process::exit(1);
-        })
+        let (config, diagnostics) = Config::load_with_diagnostics(&config_source, default_config)
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to load config:\n  {}", err);
+                std::process::exit(1);
+            });
+        for diagnostic in diagnostics {
+            eprintln!("Config {}: {}", config_source, diagnostic);
+        }
+        config
     }
 }
 
@@ -78,8 +78,17 @@ impl ConfigSource {
     /// Loads a value from the provided config source.
     /// The source is expected to be a valid JSON5 document.
     pub fn load<T: DeserializeOwned + Default + ConfigValidate>(&self) -> Result<T, ConfigError> {
+        match self.read()? {
+            None => Ok(Default::default()),
+            Some(cfg_str) => self.parse(&cfg_str),
+        }
+    }
+
+    /// Reads the contents of the config source, or returns `None` for the
+    /// default configuration. Note that stdin can only be read once.
+    pub fn read(&self) -> Result<Option<String>, ConfigError> {
         let cfg_str = match &self {
-            ConfigSource::Default => return Ok(Default::default()),
+            ConfigSource::Default => return Ok(None),
             ConfigSource::Literal(literal) => literal.clone(),
 
             ConfigSource::StdIn => {
@@ -103,8 +112,16 @@ impl ConfigSource {
                 })?
             }
         };
+        Ok(Some(cfg_str))
+    }
 
-        let cfg = json5::from_str::<T>(&cfg_str).map_err(|err| ConfigError::ParseError {
+    /// Parses and validates a value from `cfg_str`, which was read from this
+    /// config source.
+    pub fn parse<T: DeserializeOwned + ConfigValidate>(
+        &self,
+        cfg_str: &str,
+    ) -> Result<T, ConfigError> {
+        let cfg = json5::from_str::<T>(cfg_str).map_err(|err| ConfigError::ParseError {
             source: self.clone(),
             message: err.to_string(),
         })?;
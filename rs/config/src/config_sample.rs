@@ -63,14 +63,6 @@
 
 pub const SAMPLE_CONFIG: &str = r#"
 {
-    // ============================================
-    // Global Replica Configuration
-    // ============================================
-
-    // Id of the subnet this node belongs to. This will be removed as soon as the replica can
-    // retrieve its own subnet id from the registry.
-    subnet_id: 0,
-
     // ============================================
     // Configuration of node transport
     // ============================================
@@ -80,6 +72,9 @@ pub const SAMPLE_CONFIG: &str = r#"
 
         // mapping of flow ids to TCP port number, also depth of send queue
         p2p_flows: [{flow_tag: 1, server_port: 3000, queue_size: 1024}],
+
+        // multiplex all flows with a peer over the connection of the first flow
+        multiplex_flows: false,
     },
     // ============================================
     // Configuration of registry client
@@ -106,7 +101,21 @@ pub const SAMPLE_CONFIG: &str = r#"
     // ============================================
     state_manager: {
         // The directory that should be used to persist node state.
-        state_root: "/tmp/ic_state"
+        state_root: "/tmp/ic_state",
+
+        // Limits on state sync downloads, so that a node catching up does not
+        // starve other traffic. All fields are optional.
+        state_sync: {
+            // Maximum number of chunks offered for download at once; 0 means
+            // no limit.
+            max_concurrent_chunks: 0,
+            // Sustained download bandwidth in bytes per second; 0 disables
+            // bandwidth throttling.
+            max_bandwidth_bytes_per_sec: 0,
+            // Capacity of the token bucket, i.e. the number of bytes that
+            // can be downloaded in a burst.
+            max_burst_bytes: 16777216,
+        },
     },
     // ============================================
     // Configuration of the node artifact pool persistence.
@@ -135,21 +144,12 @@ pub const SAMPLE_CONFIG: &str = r#"
     // ============================================
     crypto: {
         // The directory that should be used to persist node's cryptographic keys.
-        crypto_root: "/tmp/ic_crypto"
-    },
-    // ========================================
-    // Configuration of the message scheduling.
-    // ========================================
-    scheduler: {
-        // The max number of cores to use for canister code execution.
-        scheduler_cores: 1,
-
-        // Maximum amount of instructions a single round can consume.
-        max_instructions_per_round: 26843545600,
-
-        // Maximum amount of instructions a single message's execution can consume.
-        // This should be significantly smaller than `max_instructions_per_round`.
-        max_instructions_per_message: 5368709120,
+        crypto_root: "/tmp/ic_crypto",
+        // Seconds after which TLS handshakes that have not completed are aborted.
+        tls_handshake_timeout_secs: 30,
+        // Limits of TLS server handshakes, null for no limit.
+        tls_max_concurrent_handshakes: 1000,
+        tls_max_handshakes_per_source_per_second: 20
     },
     // ================================================
     // Configuration of the execution environment.
@@ -162,6 +162,8 @@ pub const SAMPLE_CONFIG: &str = r#"
         //   Use memory persistence based on mprotect + SIGSEGV.
         // - EXAMPLE: persistence_type: "pagemap",
         //   Use memory persistence based on /proc/pid/pagemap (Linux) or Mac OS equivalent.
+        // - EXAMPLE: persistence_type: "in_memory",
+        //   Copy memory in and out of plain allocations. Meant for tests and local tooling.
         persistence_type: "sigsegv",
     },
     // ====================================
@@ -317,6 +319,78 @@ pub const SAMPLE_CONFIG: &str = r#"
     nns_registry_replicator: {
       poll_delay_duration_ms: 5000
     },
+    // =================================
+    // Node Reward Reporter
+    // =================================
+    node_reward_reporter: {
+      // Whether the node manager reports signed heartbeats to the NNS.
+      enabled: false,
+      // The canister and its update method that receive the heartbeats.
+      // Heartbeats are only reported once both are set.
+      // canister_id: "<canister id>",
+      // method_name: "<method name>",
+      // The time between two consecutive heartbeats.
+      report_interval_secs: 600,
+      // Maximum number of attempts to deliver a single heartbeat.
+      max_attempts: 5,
+      // Delay before the first retry, doubled on each further retry.
+      initial_retry_backoff_ms: 1000,
+      // Upper bound for the delay between two retries.
+      max_retry_backoff_ms: 60000,
+    },
+    // =================================
+    // Replica cgroup
+    // =================================
+    replica_cgroup: {
+      // Whether the node manager runs the replica in a dedicated cgroup (v2).
+      enabled: false,
+      // The path of the cgroup. The parent cgroup must exist.
+      cgroup_path: "/sys/fs/cgroup/ic-replica",
+      // Hard memory limit of the replica (`memory.max`).
+      // EXAMPLE: memory_max_bytes: 34359738368,
+
+      // Relative CPU share of the replica in [1, 10000] (`cpu.weight`).
+      // EXAMPLE: cpu_weight: 500,
+
+      // Per-device IO limits (`io.max`).
+      // EXAMPLE: io_max: [{ device: "8:0", wbps: 524288000 }],
+      io_max: [],
+      // The time between two consecutive updates of the cgroup metrics.
+      metrics_interval_secs: 10,
+    },
+    // =================================
+    // Time synchronization monitor
+    // =================================
+    time_sync: {
+      // Whether the node manager monitors the offset of the local clock.
+      enabled: false,
+      // The NTP servers to query.
+      ntp_servers: ["pool.ntp.org:123"],
+      // The time between two consecutive checks of the clock offset.
+      check_interval_secs: 60,
+      // How long to wait for the response of a single NTP server.
+      query_timeout_ms: 2000,
+      // The largest tolerated offset of the local clock.
+      max_offset_ms: 500,
+      // Whether the replica is only started once the clock is synchronized.
+      delay_replica_start: false,
+      // Upper bound for the delay of the replica start.
+      max_replica_start_delay_secs: 600,
+    },
+    // =================================
+    // Tracing
+    // =================================
+    tracing: {
+      // The OTLP/HTTP collector to which spans are exported. Spans are
+      // only recorded if an endpoint is configured.
+      // EXAMPLE: otlp_endpoint: "http://localhost:4318",
+      // The service name under which spans are reported.
+      service_name: "replica",
+      // The time between two consecutive exports of recorded spans.
+      export_interval_ms: 5000,
+      // Maximum number of finished spans that are queued for export.
+      max_queued_spans: 10000,
+    },
 }
 "#;
 
@@ -0,0 +1,908 @@
+//! Strict validation of replica config files.
+//!
+//! Omitted sections and fields of a config file take their default values and
+//! keys that serde does not know are ignored, so a misspelled key silently
+//! results in the default being used. The checks in this module compare the
+//! config file against the schema of [`Config`] and report unknown keys,
+//! deprecated fields and values that are out of range as
+//! [`ConfigDiagnostic`]s.
+
+use crate::config::Config;
+use crate::config_parser::ConfigError;
+use crate::http_handler::ExternalConfig;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Keys that are not part of the serialized form of a section unless they are
+/// set, i.e. flattened enums and fields that skip serialization when `None`.
+/// A test checks that the list covers all such fields of the config structs.
+const OPTIONAL_KEYS: &[(&str, &[&str])] = &[
+    ("artifact_pool", &["consensus_pool_backend", "backup"]),
+    ("http_handler", &["port", "write_port_to"]),
+    (
+        "registry_client",
+        &[
+            "bootstrap",
+            "registry_canister_url",
+            "protobuf_file",
+            "local_store",
+        ],
+    ),
+    (
+        "registration",
+        &["nns_pub_key_pem", "nns_registry_snapshot_file"],
+    ),
+];
+
+/// Fields and sections that are still accepted but should be removed from
+/// config files, together with a hint on what to do instead.
+const DEPRECATED_FIELDS: &[(&str, &str)] = &[
+    (
+        "subnet_id",
+        "the field is ignored, the subnet id is read from the registry",
+    ),
+    (
+        "scheduler",
+        "the section is ignored, the scheduler is configured per subnet type",
+    ),
+    (
+        "http_handler.port",
+        "use `http_handler.listen_addr` instead",
+    ),
+    (
+        "http_handler.write_port_to",
+        "use `http_handler.listen_addr` instead",
+    ),
+    (
+        "registry_client.bootstrap",
+        "use `registry_client.local_store` instead",
+    ),
+    (
+        "registry_client.registry_canister_url",
+        "use `registry_client.local_store` instead",
+    ),
+    (
+        "registry_client.protobuf_file",
+        "use `registry_client.local_store` instead",
+    ),
+    ("hypervisor.create_funds_whitelist", "the field is ignored"),
+    ("logger.node_id", "the field is ignored"),
+    ("logger.dc_id", "the field is ignored"),
+    ("nodemanager_logger.node_id", "the field is ignored"),
+    ("nodemanager_logger.dc_id", "the field is ignored"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// The config could not be read.
+    Unreadable,
+    /// The config is not a valid JSON5 document or does not match the types
+    /// of the config fields.
+    InvalidSyntax,
+    /// A value is rejected when converting the config, e.g. an invalid
+    /// certificate.
+    InvalidValue,
+    /// A key is not part of the config schema.
+    UnknownKey,
+    /// A field is deprecated.
+    DeprecatedField,
+    /// A value is outside of its allowed range.
+    OutOfRange,
+}
+
+/// A problem found in a config file. `path` is the dot-separated path of the
+/// offending key, or empty if the problem concerns the whole file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConfigDiagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigDiagnostic {
+    fn error(kind: DiagnosticKind, path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(kind: DiagnosticKind, path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            kind,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        if self.path.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.path, self.message)
+        }
+    }
+}
+
+impl From<&ConfigError> for ConfigDiagnostic {
+    fn from(err: &ConfigError) -> Self {
+        let kind = match err {
+            ConfigError::IoError { .. } => DiagnosticKind::Unreadable,
+            ConfigError::ParseError { .. } => DiagnosticKind::InvalidSyntax,
+            ConfigError::ValidationError { .. } => DiagnosticKind::InvalidValue,
+        };
+        Self::error(kind, "", err.to_string())
+    }
+}
+
+/// Checks the raw contents of a config file against `config`, the config
+/// that was parsed from it.
+pub fn check_config(contents: &str, config: &Config) -> Vec<ConfigDiagnostic> {
+    let document: Value = match json5::from_str(contents) {
+        Ok(document) => document,
+        Err(err) => {
+            return vec![ConfigDiagnostic::error(
+                DiagnosticKind::InvalidSyntax,
+                "",
+                err.to_string(),
+            )]
+        }
+    };
+    let schema = config_schema(config);
+    let mut diagnostics = Vec::new();
+    if let (Some(document), Some(schema)) = (document.as_object(), schema.as_object()) {
+        check_keys(document, schema, "", &mut diagnostics);
+        check_deprecated_fields(document, &mut diagnostics);
+    }
+    check_ranges(config, &mut diagnostics);
+    diagnostics
+}
+
+/// Returns the serialized form of `config` as it appears in config files.
+fn config_schema(config: &Config) -> Value {
+    let mut schema = serde_json::to_value(config).expect("Failed to serialize config");
+    // The config file contains the external form of the HTTP handler config.
+    schema["http_handler"] =
+        serde_json::to_value(ExternalConfig::default()).expect("Failed to serialize config");
+    schema
+}
+
+/// Reports all keys of `document` that are not present in `schema`. Sections
+/// for which the schema has no fields, such as maps or unset optional
+/// sections, are not checked.
+fn check_keys(
+    document: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+    diagnostics: &mut Vec<ConfigDiagnostic>,
+) {
+    let optional_keys = optional_keys(path);
+    if schema.is_empty() && optional_keys.is_empty() {
+        return;
+    }
+    for (key, value) in document {
+        let key_path = join_path(path, key);
+        match schema.get(key) {
+            Some(Value::Object(schema)) => {
+                if let Value::Object(document) = value {
+                    check_keys(document, schema, &key_path, diagnostics);
+                }
+            }
+            Some(_) => (),
+            None if optional_keys.contains(key.as_str()) => (),
+            None if is_deprecated(&key_path) => (),
+            None => diagnostics.push(ConfigDiagnostic::error(
+                DiagnosticKind::UnknownKey,
+                key_path,
+                "unknown key",
+            )),
+        }
+    }
+}
+
+fn optional_keys(section: &str) -> BTreeSet<&'static str> {
+    OPTIONAL_KEYS
+        .iter()
+        .filter(|(optional_section, _)| *optional_section == section)
+        .flat_map(|(_, keys)| keys.iter().copied())
+        .collect()
+}
+
+fn check_deprecated_fields(document: &Map<String, Value>, diagnostics: &mut Vec<ConfigDiagnostic>) {
+    for (path, hint) in DEPRECATED_FIELDS {
+        let mut keys = path.split('.');
+        let mut value = keys.next().and_then(|key| document.get(key));
+        for key in keys {
+            value = value.and_then(|value| value.get(key));
+        }
+        if value.is_some() {
+            diagnostics.push(ConfigDiagnostic::warning(
+                DiagnosticKind::DeprecatedField,
+                *path,
+                format!("deprecated field, {}", hint),
+            ));
+        }
+    }
+}
+
+fn is_deprecated(path: &str) -> bool {
+    DEPRECATED_FIELDS
+        .iter()
+        .any(|(deprecated, _)| *deprecated == path)
+}
+
+fn check_ranges(config: &Config, diagnostics: &mut Vec<ConfigDiagnostic>) {
+    let mut out_of_range = |path: &str, message: String| {
+        diagnostics.push(ConfigDiagnostic::error(
+            DiagnosticKind::OutOfRange,
+            path,
+            message,
+        ))
+    };
+
+    let mut flow_tags = BTreeSet::new();
+    let mut server_ports = BTreeSet::new();
+    for (i, flow) in config.transport.p2p_flows.iter().enumerate() {
+        let path = format!("transport.p2p_flows[{}]", i);
+        if flow.queue_size == 0 {
+            out_of_range(&path, "queue_size must be positive".to_string());
+        }
+        if !flow_tags.insert(flow.flow_tag) {
+            out_of_range(&path, format!("duplicate flow_tag {}", flow.flow_tag));
+        }
+        if !server_ports.insert(flow.server_port) {
+            out_of_range(&path, format!("duplicate server_port {}", flow.server_port));
+        }
+    }
+
+    let hypervisor = &config.hypervisor;
+    if hypervisor.max_canister_memory_size > hypervisor.subnet_memory_capacity {
+        out_of_range(
+            "hypervisor.max_canister_memory_size",
+            format!(
+                "{} exceeds subnet_memory_capacity ({})",
+                hypervisor.max_canister_memory_size, hypervisor.subnet_memory_capacity
+            ),
+        );
+    }
+    if hypervisor.max_controllers == 0 {
+        out_of_range("hypervisor.max_controllers", "must be positive".to_string());
+    }
+
+    if config.nns_registry_replicator.poll_delay_duration_ms == 0 {
+        out_of_range(
+            "nns_registry_replicator.poll_delay_duration_ms",
+            "must be positive".to_string(),
+        );
+    }
+
+    let cgroup = &config.replica_cgroup;
+    if cgroup.memory_max_bytes == Some(0) {
+        out_of_range(
+            "replica_cgroup.memory_max_bytes",
+            "must be positive".to_string(),
+        );
+    }
+    if let Some(cpu_weight) = cgroup.cpu_weight {
+        if !(1..=10_000).contains(&cpu_weight) {
+            out_of_range(
+                "replica_cgroup.cpu_weight",
+                format!("{} is not in [1, 10000]", cpu_weight),
+            );
+        }
+    }
+    for (i, limit) in cgroup.io_max.iter().enumerate() {
+        let mut numbers = limit.device.split(':');
+        let valid = matches!(
+            (numbers.next(), numbers.next(), numbers.next()),
+            (Some(major), Some(minor), None)
+                if major.parse::<u32>().is_ok() && minor.parse::<u32>().is_ok()
+        );
+        if !valid {
+            out_of_range(
+                &format!("replica_cgroup.io_max[{}]", i),
+                format!(
+                    "device {:?} is not of the form <major>:<minor>",
+                    limit.device
+                ),
+            );
+        }
+    }
+    if cgroup.enabled && cgroup.metrics_interval_secs == 0 {
+        out_of_range(
+            "replica_cgroup.metrics_interval_secs",
+            "must be positive".to_string(),
+        );
+    }
+
+    let time_sync = &config.time_sync;
+    if time_sync.enabled {
+        if time_sync.ntp_servers.is_empty() {
+            out_of_range("time_sync.ntp_servers", "must not be empty".to_string());
+        }
+        if time_sync.check_interval_secs == 0 {
+            out_of_range(
+                "time_sync.check_interval_secs",
+                "must be positive".to_string(),
+            );
+        }
+        if time_sync.query_timeout_ms == 0 {
+            out_of_range("time_sync.query_timeout_ms", "must be positive".to_string());
+        }
+    }
+
+    let reporter = &config.node_reward_reporter;
+    if reporter.enabled && reporter.report_interval_secs == 0 {
+        out_of_range(
+            "node_reward_reporter.report_interval_secs",
+            "must be positive".to_string(),
+        );
+    }
+    if reporter.enabled && reporter.max_attempts == 0 {
+        out_of_range(
+            "node_reward_reporter.max_attempts",
+            "must be positive".to_string(),
+        );
+    }
+    if reporter.initial_retry_backoff_ms > reporter.max_retry_backoff_ms {
+        out_of_range(
+            "node_reward_reporter.initial_retry_backoff_ms",
+            format!(
+                "{} exceeds max_retry_backoff_ms ({})",
+                reporter.initial_retry_backoff_ms, reporter.max_retry_backoff_ms
+            ),
+        );
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_parser::ConfigSource;
+    use crate::SAMPLE_CONFIG;
+    use serde::ser::{self, SerializeStruct};
+
+    fn diagnose(contents: &str) -> Vec<ConfigDiagnostic> {
+        let (config, _tmpdir) = Config::temp_config();
+        Config::validate_source(&ConfigSource::Literal(contents.to_string()), config)
+    }
+
+    #[test]
+    fn sample_config_has_no_errors() {
+        let diagnostics = diagnose(SAMPLE_CONFIG);
+        assert!(
+            diagnostics.iter().all(|d| !d.is_error()),
+            "{:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let diagnostics = diagnose(
+            r#"{
+                hypervisor: { max_globlas: 10 },
+                transprot: {},
+                registry_client: { local_stroe: "/tmp" },
+            }"#,
+        );
+        let mut unknown: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::UnknownKey)
+            .map(|d| d.path.as_str())
+            .collect();
+        unknown.sort_unstable();
+        assert_eq!(
+            unknown,
+            vec![
+                "hypervisor.max_globlas",
+                "registry_client.local_stroe",
+                "transprot"
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_report_optional_keys_and_maps() {
+        let diagnostics = diagnose(
+            r#"{
+                http_handler: { port: 8080 },
+                registry_client: { local_store: "/tmp" },
+                logger: { sampling_rates: { "ic_consensus": 10 } },
+            }"#,
+        );
+        assert!(
+            diagnostics
+                .iter()
+                .all(|d| d.kind != DiagnosticKind::UnknownKey),
+            "{:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn reports_deprecated_fields_as_warnings() {
+        let diagnostics = diagnose(r#"{ http_handler: { port: 8080 } }"#);
+        assert_eq!(
+            diagnostics,
+            vec![ConfigDiagnostic::warning(
+                DiagnosticKind::DeprecatedField,
+                "http_handler.port",
+                "deprecated field, use `http_handler.listen_addr` instead",
+            )]
+        );
+    }
+
+    #[test]
+    fn accepts_sections_removed_from_sample_config() {
+        let (default, _tmpdir) = Config::temp_config();
+        let source = ConfigSource::Literal(
+            "{ subnet_id: 0, scheduler: { scheduler_cores: 1 } }".to_string(),
+        );
+        let (_, diagnostics) = Config::load_with_diagnostics(&source, default).unwrap();
+        let deprecated: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::DeprecatedField)
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(deprecated, vec!["subnet_id", "scheduler"]);
+        assert!(
+            diagnostics.iter().all(|d| !d.is_error()),
+            "{:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn reports_out_of_range_values() {
+        let diagnostics = diagnose(
+            r#"{
+                transport: {
+                    node_ip: "127.0.0.1",
+                    p2p_flows: [
+                        { flow_tag: 1, server_port: 4100, queue_size: 0 },
+                        { flow_tag: 1, server_port: 4101, queue_size: 10 },
+                    ],
+                },
+                nns_registry_replicator: { poll_delay_duration_ms: 0 },
+                node_reward_reporter: { enabled: false, max_attempts: 0 },
+            }"#,
+        );
+        let out_of_range: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::OutOfRange)
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            out_of_range,
+            vec![
+                "error: transport.p2p_flows[0]: queue_size must be positive",
+                "error: transport.p2p_flows[1]: duplicate flow_tag 1",
+                "error: nns_registry_replicator.poll_delay_duration_ms: must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_keys_cover_all_fields_missing_from_schema() {
+        let (config, _tmpdir) = Config::temp_config();
+        let mut fields = ConfigFields::default();
+        config
+            .serialize(FieldCollector::new("", &mut fields))
+            .unwrap();
+        // The config file contains the external form of the HTTP handler config.
+        let is_internal_http_handler_field = |path: &String| path.starts_with("http_handler.");
+        fields
+            .serialized
+            .retain(|path| !is_internal_http_handler_field(path));
+        fields
+            .skipped
+            .retain(|path| !is_internal_http_handler_field(path));
+        ExternalConfig::default()
+            .serialize(FieldCollector::new("http_handler", &mut fields))
+            .unwrap();
+
+        for path in &fields.skipped {
+            let (section, key) = match path.rfind('.') {
+                Some(i) => (&path[..i], &path[i + 1..]),
+                None => ("", path.as_str()),
+            };
+            assert!(
+                optional_keys(section).contains(key) || is_deprecated(path),
+                "{} is missing from the schema but not in OPTIONAL_KEYS",
+                path
+            );
+        }
+        for section in &fields.flattened {
+            assert!(
+                !optional_keys(section).is_empty(),
+                "the flattened fields of {} are not in OPTIONAL_KEYS",
+                section
+            );
+        }
+        for (section, keys) in OPTIONAL_KEYS {
+            for key in keys.iter() {
+                let path = join_path(section, key);
+                assert!(
+                    fields.serialized.contains(&path)
+                        || fields.skipped.contains(&path)
+                        || fields.flattened.contains(*section),
+                    "{} is in OPTIONAL_KEYS but not a field of the config",
+                    path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let diagnostics = diagnose("garbage");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::InvalidSyntax);
+    }
+
+    #[test]
+    fn loading_fails_on_errors_only() {
+        let (default, _tmpdir) = Config::temp_config();
+        let source = ConfigSource::Literal("{ logger: { node_id: 1 } }".to_string());
+        let (_, diagnostics) = Config::load_with_diagnostics(&source, default.clone()).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+
+        let source = ConfigSource::Literal("{ logger: { nod_id: 1 } }".to_string());
+        assert!(Config::load_with_default(&source, default).is_err());
+    }
+
+    /// The fields of the config structs, as reported by their `Serialize`
+    /// implementations.
+    #[derive(Default)]
+    struct ConfigFields {
+        /// Fields that are part of the serialized config.
+        serialized: BTreeSet<String>,
+        /// Fields that skip serialization, e.g. because they are `None`.
+        skipped: BTreeSet<String>,
+        /// Sections with flattened fields, which serde serializes as maps of
+        /// unknown length rather than as structs.
+        flattened: BTreeSet<String>,
+    }
+
+    /// A serializer that records the fields of the structs it serializes in
+    /// `fields` instead of producing any output.
+    struct FieldCollector<'a> {
+        path: String,
+        fields: &'a mut ConfigFields,
+    }
+
+    impl<'a> FieldCollector<'a> {
+        fn new(path: &str, fields: &'a mut ConfigFields) -> Self {
+            Self {
+                path: path.to_string(),
+                fields,
+            }
+        }
+    }
+
+    impl<'a> ser::Serializer for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+        type SerializeSeq = Self;
+        type SerializeTuple = Self;
+        type SerializeTupleStruct = Self;
+        type SerializeTupleVariant = Self;
+        type SerializeMap = Self;
+        type SerializeStruct = Self;
+        type SerializeStructVariant = Self;
+
+        fn serialize_bool(self, _v: bool) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_i8(self, _v: i8) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_i16(self, _v: i16) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_i32(self, _v: i32) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_i64(self, _v: i64) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_u8(self, _v: u8) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_u16(self, _v: u16) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_u32(self, _v: u32) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_u64(self, _v: u64) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_f32(self, _v: f32) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_f64(self, _v: f64) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_char(self, _v: char) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_str(self, _v: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_bytes(self, _v: &[u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_none(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Self::Error> {
+            value.serialize(self)
+        }
+
+        fn serialize_unit(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_unit_variant(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(
+            self,
+            _name: &'static str,
+            value: &T,
+        ) -> Result<(), Self::Error> {
+            value.serialize(self)
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+            Ok(self)
+        }
+
+        fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+            Ok(self)
+        }
+
+        fn serialize_tuple_struct(
+            self,
+            _name: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+            Ok(self)
+        }
+
+        fn serialize_tuple_variant(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+            Ok(self)
+        }
+
+        fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+            // Structs with flattened fields are serialized as maps of unknown
+            // length, whereas the maps of the config have a known length.
+            if len.is_none() {
+                self.fields.flattened.insert(self.path.clone());
+            }
+            Ok(self)
+        }
+
+        fn serialize_struct(
+            self,
+            _name: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeStruct, Self::Error> {
+            Ok(self)
+        }
+
+        fn serialize_struct_variant(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeStructVariant, Self::Error> {
+            Ok(self)
+        }
+    }
+
+    impl<'a> SerializeStruct for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(
+            &mut self,
+            key: &'static str,
+            value: &T,
+        ) -> Result<(), Self::Error> {
+            let path = join_path(&self.path, key);
+            self.fields.serialized.insert(path.clone());
+            value.serialize(FieldCollector {
+                path,
+                fields: &mut *self.fields,
+            })
+        }
+
+        fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+            self.fields.skipped.insert(join_path(&self.path, key));
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    // The contents of sequences, maps and enums are not part of the schema.
+    impl<'a> ser::SerializeSeq for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_element<T: ?Sized + Serialize>(
+            &mut self,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl<'a> ser::SerializeTuple for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_element<T: ?Sized + Serialize>(
+            &mut self,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl<'a> ser::SerializeTupleStruct for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(
+            &mut self,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl<'a> ser::SerializeTupleVariant for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(
+            &mut self,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl<'a> ser::SerializeMap for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_key<T: ?Sized + Serialize>(&mut self, _key: &T) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn serialize_value<T: ?Sized + Serialize>(
+            &mut self,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl<'a> ser::SerializeStructVariant for FieldCollector<'a> {
+        type Ok = ();
+        type Error = serde_json::Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(
+            &mut self,
+            _key: &'static str,
+            _value: &T,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn end(self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+}
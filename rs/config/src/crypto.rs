@@ -9,6 +9,7 @@ use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 use tempfile::TempDir;
 
 #[cfg(test)]
@@ -31,12 +32,58 @@ pub struct CryptoConfig {
         proptest(strategy = "any::<String>().prop_map(|x| PathBuf::from(x))")
     )]
     pub crypto_root: PathBuf,
+
+    /// Seconds after which TLS handshakes that have not completed are aborted
+    #[serde(default = "default_tls_handshake_timeout_secs")]
+    pub tls_handshake_timeout_secs: u64,
+
+    /// The maximum number of TLS server handshakes in flight at the same time,
+    /// or `None` for no limit
+    #[serde(default = "default_tls_max_concurrent_handshakes")]
+    pub tls_max_concurrent_handshakes: Option<usize>,
+
+    /// The maximum number of TLS server handshakes a single source IP may
+    /// start per second, or `None` for no limit
+    #[serde(default = "default_tls_max_handshakes_per_source_per_second")]
+    pub tls_max_handshakes_per_source_per_second: Option<u32>,
+}
+
+/// The default timeout for TLS handshakes, in seconds
+pub const DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS: u64 = 30;
+
+/// The default limit of TLS server handshakes in flight
+pub const DEFAULT_TLS_MAX_CONCURRENT_HANDSHAKES: usize = 1_000;
+
+/// The default limit of TLS server handshakes per source IP and second
+pub const DEFAULT_TLS_MAX_HANDSHAKES_PER_SOURCE_PER_SECOND: u32 = 20;
+
+fn default_tls_handshake_timeout_secs() -> u64 {
+    DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS
+}
+
+fn default_tls_max_concurrent_handshakes() -> Option<usize> {
+    Some(DEFAULT_TLS_MAX_CONCURRENT_HANDSHAKES)
+}
+
+fn default_tls_max_handshakes_per_source_per_second() -> Option<u32> {
+    Some(DEFAULT_TLS_MAX_HANDSHAKES_PER_SOURCE_PER_SECOND)
 }
 
 impl CryptoConfig {
     /// Return a new CryptoConfig with the given crypto_root path.
     pub fn new(crypto_root: PathBuf) -> Self {
-        Self { crypto_root }
+        Self {
+            crypto_root,
+            tls_handshake_timeout_secs: DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS,
+            tls_max_concurrent_handshakes: default_tls_max_concurrent_handshakes(),
+            tls_max_handshakes_per_source_per_second:
+                default_tls_max_handshakes_per_source_per_second(),
+        }
+    }
+
+    /// Returns the timeout for TLS handshakes.
+    pub fn tls_handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.tls_handshake_timeout_secs)
     }
 
     /// Creates a new CryptoConfig in a temporary directory and returns the
@@ -117,6 +164,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tls_handshake_timeout_defaults_if_missing() {
+        let config: CryptoConfig = json5::from_str("{ crypto_root: '/tmp/ic_crypto' }").unwrap();
+        assert_eq!(
+            config.tls_handshake_timeout(),
+            Duration::from_secs(DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS)
+        );
+    }
+
+    #[test]
+    fn tls_handshake_limits_default_if_missing() {
+        let config: CryptoConfig = json5::from_str("{ crypto_root: '/tmp/ic_crypto' }").unwrap();
+        assert_eq!(
+            config.tls_max_concurrent_handshakes,
+            Some(DEFAULT_TLS_MAX_CONCURRENT_HANDSHAKES)
+        );
+        assert_eq!(
+            config.tls_max_handshakes_per_source_per_second,
+            Some(DEFAULT_TLS_MAX_HANDSHAKES_PER_SOURCE_PER_SECOND)
+        );
+    }
+
     #[test]
     fn should_create_path_as_directory() {
         CryptoConfig::run_with_temp_config(|config| assert!(config.crypto_root.is_dir()));
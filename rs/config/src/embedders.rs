@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 // Defining 100000 globals in a module can result in significant overhead in
 // each message's execution time (about 40x), so set a limit 3 orders of
@@ -8,15 +9,29 @@ pub(crate) const MAX_GLOBALS: usize = 200;
 // Current max number of functions used by a canister on the Alpha network is
 // about 2800, so we set a limit at two times that.
 pub(crate) const MAX_FUNCTIONS: usize = 6000;
+// These match the Wasmtime defaults on 64-bit platforms.
+pub(crate) const STATIC_MEMORY_GUARD_SIZE: u64 = 2 << 30;
+pub(crate) const DYNAMIC_MEMORY_GUARD_SIZE: u64 = 64 << 10;
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub persistence_type: PersistenceType,
+    /// The native stack available to Wasm code. Exhausting it traps with a
+    /// stack overflow.
     pub max_wasm_stack_size: usize,
+    /// Size in bytes of the guard region after static linear memories.
+    pub static_memory_guard_size: u64,
+    /// Size in bytes of the guard region after dynamic linear memories.
+    pub dynamic_memory_guard_size: u64,
     pub num_runtime_generic_threads: usize,
     pub num_runtime_query_threads: usize,
     pub max_globals: usize,
     pub max_functions: usize,
+    /// Whether modules are instrumented with write barriers, which record the
+    /// dirty pages of small heaps instead of the signal-based tracking.
+    pub write_barriers: bool,
+    /// The directory in which compiled modules are persisted, if any.
+    pub compiled_artifacts_dir: Option<PathBuf>,
 }
 
 impl Config {
@@ -24,10 +39,14 @@ impl Config {
         Config {
             persistence_type: PersistenceType::Sigsegv,
             max_wasm_stack_size: 5 * 1024 * 1024,
+            static_memory_guard_size: STATIC_MEMORY_GUARD_SIZE,
+            dynamic_memory_guard_size: DYNAMIC_MEMORY_GUARD_SIZE,
             num_runtime_generic_threads: 1,
             num_runtime_query_threads: 4,
             max_globals: MAX_GLOBALS,
             max_functions: MAX_FUNCTIONS,
+            write_barriers: false,
+            compiled_artifacts_dir: None,
         }
     }
 }
@@ -43,4 +62,10 @@ impl Default for Config {
 pub enum PersistenceType {
     Sigsegv,
     Pagemap,
+    /// Wasm memories are plain allocations that are initialized from the
+    /// page map before each execution and compared against it afterwards.
+    /// Neither signal handlers nor file-backed mappings are involved, which
+    /// makes the executor easy to embed in unit tests and local tooling, at
+    /// the cost of copying the whole heap on every execution.
+    InMemory,
 }
@@ -7,6 +7,8 @@ use ic_types::{
     Cycles, NumBytes, NumInstructions, MAX_STABLE_MEMORY_IN_BYTES, MAX_WASM_MEMORY_IN_BYTES,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 const GB: u64 = 1024 * 1024 * 1024;
 
@@ -22,6 +24,12 @@ const GB: u64 = 1024 * 1024 * 1024;
 /// canister's data and the deltas.
 const SUBNET_MEMORY_CAPACITY: NumBytes = NumBytes::new(300 * GB);
 
+/// This is the upper limit on how much message memory the queues of a single
+/// canister can use. Every outstanding call reserves memory for the largest
+/// possible response, so this bounds the number of calls that a canister can
+/// have in flight at about 2000.
+const CANISTER_MESSAGE_MEMORY_CAPACITY: NumBytes = NumBytes::new(4 * GB);
+
 /// This is the upper limit on how big heap deltas all the canisters together
 /// can produce on a subnet in between checkpoints. Once, the total delta size
 /// is above this limit, no more canisters will be executed till the next
@@ -34,6 +42,16 @@ const SUBNET_MEMORY_CAPACITY: NumBytes = NumBytes::new(300 * GB);
 /// be used for storing other copies of the canister states.
 pub(crate) const SUBNET_HEAP_DELTA_CAPACITY: NumBytes = NumBytes::new(1024 * GB);
 
+/// The maximum number of instructions of the transform function of an HTTP
+/// outcall. Transforms only post-process the response of a remote server, so
+/// their budget is a small fraction of that of a message.
+const MAX_INSTRUCTIONS_PER_HTTP_TRANSFORM: NumInstructions = NumInstructions::new(100_000_000);
+
+/// The maximum size of the reply of the transform function of an HTTP outcall.
+/// The reply is agreed on in consensus, so it is kept well below the block
+/// size.
+const MAX_HTTP_TRANSFORM_RESPONSE_SIZE: NumBytes = NumBytes::new(2 * 1024 * 1024);
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 #[serde(default)]
 pub struct Config {
@@ -53,6 +71,10 @@ pub struct Config {
     /// The maximum amount of memory that can be utilized by a single canister.
     pub max_canister_memory_size: NumBytes,
 
+    /// The maximum amount of message memory that the queues of a single
+    /// canister can use, including the memory reserved for responses.
+    pub canister_message_memory_capacity: NumBytes,
+
     /// The maximum amount of cycles a canister can hold.
     /// If set to None, the canisters have no upper limit.
     pub max_cycles_per_canister: Option<Cycles>,
@@ -70,8 +92,65 @@ pub struct Config {
     /// Maximum number of functions allowed in a Wasm module.
     pub max_functions: usize,
 
+    /// Patterns of the functions that a Wasm module can import, keyed by the
+    /// name of the module they are imported from. A pattern ending with `*`
+    /// matches all names starting with the part before the `*`. Importing
+    /// from a module that is not listed is rejected.
+    pub wasm_import_allow_list: BTreeMap<String, Vec<String>>,
+
+    /// Patterns of the functions that a Wasm module can export, in the same
+    /// format as the imports. If not set, any function that is not reserved
+    /// can be exported.
+    pub wasm_export_allow_list: Option<Vec<String>>,
+
     /// Maximum number of controllers a canister can have.
     pub max_controllers: usize,
+
+    /// Maximum number of canisters that a single
+    /// `provisional_create_canisters_with_cycles` call can create.
+    pub max_canister_creation_batch_size: u64,
+
+    /// Whether calls to `raw_rand` made in non-replicated queries are answered
+    /// with pseudo-random bytes instead of being rejected. The bytes are
+    /// derived from the time of the state and the calling canister, so they
+    /// are predictable and must not be relied upon for security.
+    pub deterministic_raw_rand_in_queries: bool,
+
+    /// A canister that can allocate fewer than this many bytes of Wasm memory
+    /// gets its `canister_on_low_wasm_memory` method executed, so that it can
+    /// free memory before allocations start failing.
+    pub low_wasm_memory_threshold: NumBytes,
+
+    /// Whether `ic0.data_certificate_*` expose the data certificate that the
+    /// caller of a query executed in replicated mode passes. The interface
+    /// specification makes the certificate available in non-replicated
+    /// queries only; this switch restores the previous behavior while the
+    /// change is rolled out.
+    ///
+    /// The switch is disabled by default and will be removed, together with
+    /// the `data_certificate` argument of replicated queries, once no subnet
+    /// enables it any more.
+    pub legacy_data_certificate_in_replicated_queries: bool,
+
+    /// The maximum number of instructions the transform function of an HTTP
+    /// outcall can run for, independently of the instruction limit of the
+    /// message that made the outcall.
+    pub max_instructions_per_http_transform: NumInstructions,
+
+    /// The maximum size of the reply of the transform function of an HTTP
+    /// outcall.
+    pub max_http_transform_response_size: NumBytes,
+
+    /// Whether the scheduler records a summary of every round that the HTTP
+    /// handler serves at `/_/round_state`. This only takes effect if the
+    /// replica is built with the `round_state_dump` feature of the execution
+    /// environment, so that it cannot be enabled in production by accident.
+    pub round_state_dump_enabled: bool,
+
+    /// The directory in which compiled Wasm modules are persisted, so that
+    /// they are not compiled again after a restart or for queries. Modules
+    /// are always compiled if this is not set.
+    pub compiled_artifacts_dir: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -84,6 +163,7 @@ impl Default for Config {
             max_canister_memory_size: NumBytes::new(
                 MAX_STABLE_MEMORY_IN_BYTES + MAX_WASM_MEMORY_IN_BYTES,
             ),
+            canister_message_memory_capacity: CANISTER_MESSAGE_MEMORY_CAPACITY,
             // Canisters on the system subnet are not capped.
             // They can hold an amount of cycles that goes above this limit.
             // If this limit is set to None, canisters can hold any amount of cycles.
@@ -93,9 +173,25 @@ impl Default for Config {
             default_freeze_threshold: NumSeconds::from(30 * 24 * 60 * 60),
             max_globals: MAX_GLOBALS,
             max_functions: MAX_FUNCTIONS,
+            // All functions of the System API can be imported.
+            wasm_import_allow_list: vec![
+                ("ic0".to_string(), vec!["*".to_string()]),
+                ("method".to_string(), vec!["*".to_string()]),
+            ]
+            .into_iter()
+            .collect(),
+            wasm_export_allow_list: None,
             // Maximum number of controllers allowed in a request (specified in the public
             // Spec).
             max_controllers: 10,
+            max_canister_creation_batch_size: 1000,
+            deterministic_raw_rand_in_queries: false,
+            low_wasm_memory_threshold: NumBytes::new(100 * 1024 * 1024),
+            legacy_data_certificate_in_replicated_queries: false,
+            max_instructions_per_http_transform: MAX_INSTRUCTIONS_PER_HTTP_TRANSFORM,
+            max_http_transform_response_size: MAX_HTTP_TRANSFORM_RESPONSE_SIZE,
+            round_state_dump_enabled: false,
+            compiled_artifacts_dir: None,
         }
     }
 }
@@ -1,6 +1,7 @@
 pub mod config;
 pub mod config_parser;
 pub mod config_sample;
+pub mod config_validation;
 pub mod subnet_config;
 
 pub mod artifact_pool;
@@ -14,9 +15,13 @@ pub mod logger;
 pub mod message_routing;
 pub mod metrics;
 pub mod nns_registry_replicator;
+pub mod node_reward_reporter;
 pub mod registration;
 pub mod registry_client;
+pub mod replica_cgroup;
 pub mod state_manager;
+pub mod time_sync;
+pub mod tracing;
 
 pub use config::*;
 pub use config_parser::*;
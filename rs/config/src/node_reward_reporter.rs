@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration of the node reward reporter in the node manager.
+///
+/// When enabled, the node manager periodically submits a heartbeat signed
+/// with the node signing key to the NNS, which serves as verifiable evidence
+/// of the node's up-time for node rewards.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct Config {
+    /// Whether heartbeats are reported at all.
+    pub enabled: bool,
+
+    /// The textual id of the canister that accepts the heartbeats. No NNS
+    /// canister offers such a method yet, so heartbeats are only reported
+    /// once both this and `method_name` are set.
+    pub canister_id: Option<String>,
+
+    /// The update method of `canister_id` that receives the heartbeats.
+    pub method_name: Option<String>,
+
+    /// The time between two consecutive heartbeats.
+    pub report_interval_secs: u64,
+
+    /// Maximum number of attempts to deliver a single heartbeat before it is
+    /// dropped. A new heartbeat is created in the next reporting interval.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry of a failed delivery. The delay doubles
+    /// with each further retry, up to `max_retry_backoff_ms`.
+    pub initial_retry_backoff_ms: u64,
+
+    /// Upper bound for the delay between two retries.
+    pub max_retry_backoff_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            canister_id: None,
+            method_name: None,
+            report_interval_secs: 600,
+            max_attempts: 5,
+            initial_retry_backoff_ms: 1_000,
+            max_retry_backoff_ms: 60_000,
+        }
+    }
+}
@@ -33,6 +33,14 @@ pub struct Config {
     /// up the initial state of the registry's local store.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nns_pub_key_pem: Option<PathBuf>,
+
+    /// A registry snapshot certified by the NNS, usually shipped with the node
+    /// image. If present, it is used to initialize the registry's local store
+    /// after being verified against `nns_pub_key_pem`, so that the node does
+    /// not need to reach the NNS at first boot. If verification fails, the
+    /// NNS is contacted via `nns_url` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nns_registry_snapshot_file: Option<PathBuf>,
 }
 
 // We allow for the operator to only specify some of the fields while the others
@@ -64,6 +72,7 @@ impl Default for Config {
             eject_keycard_signal_file: PathBuf::from("/var/lib/dfinity-node/eject-hsm"),
             nns_url: None,
             nns_pub_key_pem: None,
+            nns_registry_snapshot_file: None,
         }
     }
 }
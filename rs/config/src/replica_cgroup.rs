@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Configuration of the cgroup (v2) in which the node manager runs the
+/// replica.
+///
+/// If enabled, the node manager creates the cgroup, applies the configured
+/// limits and moves the replica process into it before the replica binary is
+/// executed. Sandbox processes spawned by the replica inherit the cgroup. This
+/// bounds the resources a runaway replica can consume, so that the node
+/// manager and SSH access to the node remain responsive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Whether the replica is run in a dedicated cgroup.
+    pub enabled: bool,
+
+    /// The path of the cgroup in the cgroup v2 hierarchy. The parent cgroup
+    /// must exist and have the `memory`, `cpu` and `io` controllers available.
+    pub cgroup_path: PathBuf,
+
+    /// Hard memory limit of the replica, written to `memory.max`. If the
+    /// limit is reached, the kernel OOM killer is invoked within the cgroup.
+    /// `None` means no limit.
+    pub memory_max_bytes: Option<u64>,
+
+    /// Relative CPU share of the replica in the range `[1, 10000]`, written to
+    /// `cpu.weight`. `None` leaves the kernel default of 100.
+    pub cpu_weight: Option<u64>,
+
+    /// Per-device IO limits, written to `io.max`.
+    pub io_max: Vec<IoLimit>,
+
+    /// The time between two consecutive updates of the cgroup metrics.
+    pub metrics_interval_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cgroup_path: PathBuf::from("/sys/fs/cgroup/ic-replica"),
+            memory_max_bytes: None,
+            cpu_weight: None,
+            io_max: vec![],
+            metrics_interval_secs: 10,
+        }
+    }
+}
+
+/// IO limits of a single block device. Limits that are `None` are not
+/// enforced.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoLimit {
+    /// The device as `"<major>:<minor>"`, e.g. `"8:0"`.
+    pub device: String,
+    /// Read bytes per second.
+    pub rbps: Option<u64>,
+    /// Write bytes per second.
+    pub wbps: Option<u64>,
+    /// Read IO operations per second.
+    pub riops: Option<u64>,
+    /// Write IO operations per second.
+    pub wiops: Option<u64>,
+}
+
+impl IoLimit {
+    /// Returns the line to write to `io.max` for this device.
+    pub fn to_io_max_line(&self) -> String {
+        let limit = |value: Option<u64>| match value {
+            Some(value) => value.to_string(),
+            None => "max".to_string(),
+        };
+        format!(
+            "{} rbps={} wbps={} riops={} wiops={}",
+            self.device,
+            limit(self.rbps),
+            limit(self.wbps),
+            limit(self.riops),
+            limit(self.wiops)
+        )
+    }
+}
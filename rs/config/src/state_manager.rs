@@ -4,14 +4,61 @@ use std::path::PathBuf;
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
     state_root: PathBuf,
+    /// Limits applied when fetching a state from other nodes.
+    #[serde(default)]
+    state_sync: StateSyncConfig,
 }
 
 impl Config {
     pub fn new(state_root: PathBuf) -> Self {
-        Self { state_root }
+        Self {
+            state_root,
+            state_sync: StateSyncConfig::default(),
+        }
+    }
+
+    /// Returns a copy of this config using the given state sync limits.
+    pub fn with_state_sync(mut self, state_sync: StateSyncConfig) -> Self {
+        self.state_sync = state_sync;
+        self
     }
 
     pub fn state_root(&self) -> PathBuf {
         self.state_root.clone()
     }
+
+    pub fn state_sync(&self) -> &StateSyncConfig {
+        &self.state_sync
+    }
+}
+
+/// Throttling of state sync downloads.
+///
+/// A node that is catching up may otherwise saturate its network interface
+/// and starve consensus traffic on the same host.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateSyncConfig {
+    /// Maximum number of chunks offered for download at once. `0` means no
+    /// limit.
+    pub max_concurrent_chunks: usize,
+
+    /// Sustained download bandwidth, in bytes per second, that state sync is
+    /// allowed to use. `0` disables bandwidth throttling.
+    pub max_bandwidth_bytes_per_sec: u64,
+
+    /// Number of bytes that can be downloaded in a burst on top of the
+    /// sustained bandwidth, i.e. the capacity of the token bucket. Ignored if
+    /// bandwidth throttling is disabled.
+    pub max_burst_bytes: u64,
+}
+
+impl Default for StateSyncConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_chunks: 0,
+            max_bandwidth_bytes_per_sec: 0,
+            max_burst_bytes: 16 * 1024 * 1024,
+        }
+    }
 }
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration of the time synchronization monitor in the node manager.
+///
+/// When enabled, the node manager periodically queries the configured NTP
+/// servers (SNTP, RFC 4330) and exports the offset of the local clock to
+/// each of them. If the median offset exceeds `max_offset_ms`, the node is
+/// considered to be in a degraded state, because a badly skewed clock makes
+/// the replica propose and accept blocks with wrong timestamps.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Whether the clock offset is monitored at all.
+    pub enabled: bool,
+
+    /// The NTP servers to query, as `"<host>:<port>"`.
+    pub ntp_servers: Vec<String>,
+
+    /// The time between two consecutive checks of the clock offset.
+    pub check_interval_secs: u64,
+
+    /// How long to wait for the response of a single NTP server.
+    pub query_timeout_ms: u64,
+
+    /// The largest tolerated offset of the local clock, in either direction.
+    pub max_offset_ms: u64,
+
+    /// Whether the replica is only started once the clock offset is within
+    /// `max_offset_ms`.
+    pub delay_replica_start: bool,
+
+    /// Upper bound for the delay of the replica start. Once it has passed,
+    /// the replica is started even if the clock is still skewed, so that an
+    /// unreachable NTP server cannot keep the node down.
+    pub max_replica_start_delay_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ntp_servers: vec!["pool.ntp.org:123".to_string()],
+            check_interval_secs: 60,
+            query_timeout_ms: 2_000,
+            max_offset_ms: 500,
+            delay_replica_start: false,
+            max_replica_start_delay_secs: 600,
+        }
+    }
+}
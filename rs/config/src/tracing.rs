@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Configuration of the export of tracing spans, e.g., of the spans recorded
+/// for the execution of queries.
+///
+/// Spans are exported in the OpenTelemetry protocol (OTLP) over HTTP, so that
+/// they can be collected by any OTLP-compatible tracing backend. If no
+/// endpoint is configured, spans are not recorded at all.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct Config {
+    /// The base URL of the OTLP/HTTP collector, e.g.,
+    /// `http://localhost:4318`. Spans are sent to `<url>/v1/traces`.
+    pub otlp_endpoint: Option<Url>,
+
+    /// The service name under which the spans are reported.
+    pub service_name: String,
+
+    /// The time between two consecutive exports of the recorded spans.
+    pub export_interval_ms: u64,
+
+    /// Maximum number of finished spans that are queued for export. Further
+    /// spans are dropped until the queue has been exported.
+    pub max_queued_spans: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "replica".to_string(),
+            export_interval_ms: 5_000,
+            max_queued_spans: 10_000,
+        }
+    }
+}
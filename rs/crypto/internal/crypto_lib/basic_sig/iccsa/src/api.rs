@@ -41,7 +41,9 @@ pub fn public_key_bytes_from_der(pk_der: &[u8]) -> CryptoResult<PublicKeyBytes>
 /// * `msg` the message to verify
 /// * `sig` the signature
 /// * `pk` the canister public key
-/// * `root_pubkey` the root subnet public key
+/// * `root_pubkey` the root subnet public key. The certificate in the
+///   signature is either signed with this key or carries a delegation, signed
+///   with this key, to the subnet of the signing canister.
 ///
 /// # Errors
 /// * `MalformedPublicKey` if the public key cannot be parsed or has an
@@ -104,7 +106,7 @@ fn verify_certified_vars_certificate(
     sig: &SignatureBytes,
     pk: &PublicKeyBytes,
 ) -> CryptoResult<()> {
-    ic_certified_vars::verify_certificate(
+    ic_certified_vars::verify_certificate_with_delegation(
         certificate,
         &canister_id,
         root_pubkey,
@@ -119,14 +121,13 @@ fn verify_certified_vars_certificate(
         },
         CertificateValidationError::InvalidSignature(_)
         | CertificateValidationError::CertifiedDataMismatch { .. }
-        | CertificateValidationError::SubnetDelegationNotAllowed => {
-            CryptoError::SignatureVerification {
-                algorithm: AlgorithmId::IcCanisterSignature,
-                public_key_bytes: pk.0.clone(),
-                sig_bytes: sig.0.clone(),
-                internal_error: format!("certificate verification failed: {}", err),
-            }
-        }
+        | CertificateValidationError::SubnetDelegationNotAllowed
+        | CertificateValidationError::InvalidDelegation(_) => CryptoError::SignatureVerification {
+            algorithm: AlgorithmId::IcCanisterSignature,
+            public_key_bytes: pk.0.clone(),
+            sig_bytes: sig.0.clone(),
+            internal_error: format!("certificate verification failed: {}", err),
+        },
     })?;
     Ok(())
 }
@@ -6,6 +6,7 @@ use ic_crypto_internal_types::sign::threshold_sig::public_key::bls12_381;
 use ic_crypto_test_utils::canister_signatures::canister_sig_pub_key_to_bytes;
 use ic_interfaces::crypto::Signable;
 use ic_types::{messages::Delegation, time::Time, CanisterId};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 #[test]
@@ -187,6 +188,45 @@ fn should_fail_to_verify_on_wrong_root_pubkey() {
     );
 }
 
+#[test]
+fn should_fail_to_verify_if_delegation_does_not_cover_subnet() {
+    use serde_cbor::Value;
+
+    let (msg, sig, pk, root_pk) = test_vec(iccsa::TestVectorId::STABILITY_1);
+    // Attach a delegation to the certificate in the signature. The delegation
+    // certificate is validly signed by the root subnet but says nothing about
+    // the delegated-to subnet.
+    let sig_with_delegation = {
+        let field = |name: &str| Value::Text(name.to_string());
+        let mut sig_map: BTreeMap<Value, Value> = serde_cbor::from_slice(&sig.0).unwrap();
+        let cert_bytes = match sig_map.get(&field("certificate")) {
+            Some(Value::Bytes(bytes)) => bytes.clone(),
+            other => panic!("unexpected certificate: {:?}", other),
+        };
+        let mut cert_map: BTreeMap<Value, Value> = serde_cbor::from_slice(&cert_bytes).unwrap();
+        let mut delegation = BTreeMap::new();
+        delegation.insert(field("subnet_id"), Value::Bytes(vec![42]));
+        delegation.insert(field("certificate"), Value::Bytes(cert_bytes));
+        cert_map.insert(field("delegation"), Value::Map(delegation));
+        sig_map.insert(
+            field("certificate"),
+            Value::Bytes(serde_cbor::to_vec(&cert_map).unwrap()),
+        );
+        let mut bytes = vec![0xd9, 0xd9, 0xf7];
+        bytes.extend(serde_cbor::to_vec(&sig_map).unwrap());
+        SignatureBytes(bytes)
+    };
+
+    let result = verify(&msg, sig_with_delegation, pk, &root_pk);
+
+    assert!(
+        matches!(result, Err(CryptoError::SignatureVerification {  algorithm, public_key_bytes: _, sig_bytes: _, internal_error})
+            if internal_error.contains("invalid subnet delegation")
+            && algorithm == AlgorithmId::IcCanisterSignature
+        )
+    );
+}
+
 fn test_vec(
     testvec_id: iccsa::TestVectorId,
 ) -> (
@@ -1,7 +1,10 @@
 //! Arithmetic operations for BLS12-381 primitives
 
+use crate::hash::random_bls12_381_scalar;
+use ff::Field;
 use group::CurveProjective;
 use pairing::bls12_381::{Fr, FrRepr};
+use rand_core::RngCore;
 
 #[cfg(test)]
 mod tests;
@@ -20,6 +23,41 @@ pub fn scalar_multiply<G: CurveProjective<Scalar = Fr>, F: Into<FrRepr>>(
     base
 }
 
+/// Multiply an element of a group by a secret scalar, with the scalar blinded
+/// by additive splitting.
+///
+/// A fresh random `r` is drawn from `rng` and the product is computed as
+/// `[r]base + [factor - r]base`, so neither multiplication processes the
+/// secret scalar itself. The result is identical to
+/// `scalar_multiply(base, factor)`; only the sequence of operations that an
+/// attacker observing timing or power consumption can correlate with the
+/// secret changes on every call.
+pub fn scalar_multiply_blinded<G: CurveProjective<Scalar = Fr>, R: RngCore>(
+    base: G,
+    factor: Fr,
+    rng: &mut R,
+) -> G {
+    let blind = random_bls12_381_scalar(rng);
+    let mut blinded_factor = factor;
+    blinded_factor.sub_assign(&blind);
+    let mut product = scalar_multiply(base, blind);
+    product.add_assign(&scalar_multiply(base, blinded_factor));
+    product
+}
+
+/// Multiply an element of a group by a secret scalar, such as a secret key.
+///
+/// With the `side_channel_hardened` feature this uses
+/// `scalar_multiply_blinded` with a thread-local RNG; otherwise it is
+/// equivalent to `scalar_multiply`. Both variants yield the same result.
+pub fn scalar_multiply_secret<G: CurveProjective<Scalar = Fr>>(base: G, factor: Fr) -> G {
+    if cfg!(feature = "side_channel_hardened") {
+        scalar_multiply_blinded(base, factor, &mut rand::thread_rng())
+    } else {
+        scalar_multiply(base, factor)
+    }
+}
+
 /// Sum elements of a group.
 /// This can be applied to elements of any group over Fr; in particular G1 and
 /// G2.
@@ -2,8 +2,16 @@
 
 use super::*;
 use crate::test_utils::{uint_to_fr, uint_to_g2};
-use pairing::bls12_381::G2;
+use crate::{fr_from_bytes, g1_to_bytes, g2_to_bytes, hash_to_g1, FR_SIZE};
+use ff::PrimeField;
+use ic_crypto_internal_test_vectors::multi_bls12_381::{
+    TESTVEC_MULTI_BLS12_381_1_MSG, TESTVEC_MULTI_BLS12_381_1_PK, TESTVEC_MULTI_BLS12_381_1_SIG,
+    TESTVEC_MULTI_BLS12_381_1_SK,
+};
+use pairing::bls12_381::{G1, G2};
 use proptest::prelude::*;
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
 
 /// Verifies that `scalar_multiply(G(n), f) == G(n * f)`
 ///
@@ -43,6 +51,61 @@ fn test_sum(elements_as_uint: &[u16]) {
     );
 }
 
+/// Verifies that blinding does not change the result of a scalar
+/// multiplication.
+fn test_scalar_multiply_blinded(element_as_uint: u16, factor_as_uint: u16, seed: [u8; 32]) {
+    let element = uint_to_g2(element_as_uint as u32);
+    let factor = uint_to_fr(factor_as_uint as u32);
+    let mut rng = ChaChaRng::from_seed(seed);
+    assert_eq!(
+        scalar_multiply_blinded(element, factor, &mut rng),
+        scalar_multiply(element, factor)
+    );
+}
+
+fn test_vector_secret_key() -> Fr {
+    let bytes = hex::decode(TESTVEC_MULTI_BLS12_381_1_SK).expect("invalid hex");
+    let mut repr = [0u8; FR_SIZE];
+    repr.copy_from_slice(&bytes);
+    Fr::from_repr(fr_from_bytes(&repr)).expect("secret key is not reduced")
+}
+
+/// Known answer test: the public key of a BLS secret key computed with the
+/// secret scalar multiplication matches the test vector.
+#[test]
+fn scalar_multiply_secret_matches_public_key_test_vector() {
+    let secret_key = test_vector_secret_key();
+    let expected = TESTVEC_MULTI_BLS12_381_1_PK;
+    assert_eq!(
+        hex::encode(&g2_to_bytes(&scalar_multiply_secret(G2::one(), secret_key))[..]),
+        expected
+    );
+    let mut rng = ChaChaRng::from_seed([7; 32]);
+    assert_eq!(
+        hex::encode(&g2_to_bytes(&scalar_multiply_blinded(G2::one(), secret_key, &mut rng))[..]),
+        expected
+    );
+}
+
+/// Known answer test: a BLS signature computed with the secret scalar
+/// multiplication matches the test vector.
+#[test]
+fn scalar_multiply_secret_matches_signature_test_vector() {
+    let secret_key = test_vector_secret_key();
+    let message_hash = hash_to_g1(
+        b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_POP_",
+        TESTVEC_MULTI_BLS12_381_1_MSG.as_bytes(),
+    );
+    let expected = TESTVEC_MULTI_BLS12_381_1_SIG;
+    assert_eq!(
+        hex::encode(&g1_to_bytes(&scalar_multiply_secret(message_hash, secret_key))[..]),
+        expected
+    );
+    let mut rng = ChaChaRng::from_seed([7; 32]);
+    let signature: G1 = scalar_multiply_blinded(message_hash, secret_key, &mut rng);
+    assert_eq!(hex::encode(&g1_to_bytes(&signature)[..]), expected);
+}
+
 proptest! {
 
     /// Verifies that `scalar_multiply(G(n), f) == G(n * f)`
@@ -51,6 +114,13 @@ proptest! {
         test_scalar_multiply(element, factor);
     }
 
+    /// Verifies that blinded scalar multiplication yields the same result as
+    /// the unblinded one
+    #[test]
+    fn proptest_scalar_multiply_blinded( element: u16, factor: u16, seed: [u8; 32] ) {
+        test_scalar_multiply_blinded(element, factor, seed);
+    }
+
     /// Verifies that summing elements of G2 works
     #[test]
     fn proptest_sum(elements in proptest::collection::vec(any::<u16>(), 0..10)) {
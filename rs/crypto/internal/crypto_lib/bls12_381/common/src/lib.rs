@@ -10,7 +10,7 @@ pub use self::serde::pairing::{
 };
 
 mod arithmetic;
-pub use arithmetic::{scalar_multiply, sum};
+pub use arithmetic::{scalar_multiply, scalar_multiply_blinded, scalar_multiply_secret, sum};
 
 mod hash;
 pub use hash::{hash_to_fr, hash_to_g1, hash_to_miracl_g1, random_bls12_381_scalar, MiraclG1};
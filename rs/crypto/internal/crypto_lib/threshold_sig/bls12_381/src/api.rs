@@ -114,6 +114,34 @@ pub fn individual_public_key(
     Ok(PublicKeyBytes::from(public_key))
 }
 
+/// Derives the public keys of several signatories from the
+/// `public_coefficients`.
+///
+/// This is equivalent to calling `individual_public_key` for each index, but
+/// the `public_coefficients` are parsed only once, which dominates the cost
+/// of deriving a single key.
+///
+/// # Arguments
+/// * `public_coefficients` is the public output of a key generation.
+/// * `indices` are the positions of the signatories in the list of
+///   signatories.
+/// # Panics
+/// This method is not expected to panic.
+/// # Errors
+/// If the `public_coefficients` cannot be parsed, this will return an error.
+pub fn individual_public_keys(
+    public_coefficients: &PublicCoefficientsBytes,
+    indices: &[NodeIndex],
+) -> CryptoResult<Vec<PublicKeyBytes>> {
+    let public_coefficients = PublicCoefficients::try_from(public_coefficients)?;
+    Ok(indices
+        .iter()
+        .map(|index| {
+            PublicKeyBytes::from(crypto::individual_public_key(&public_coefficients, *index))
+        })
+        .collect())
+}
+
 /// Extracts the combined public key from the PublicCoefficients.
 ///
 /// The combined public key is used to verify combined threshold signatures.
@@ -153,6 +153,28 @@ fn test_threshold_sig_api_and_core_match(
     );
 }
 
+/// Deriving several individual public keys at once yields the same keys as
+/// deriving them one by one.
+fn test_individual_public_keys_match_individual_public_key(
+    seed: Randomness,
+    group_size: NumberOfNodes,
+    threshold: NumberOfNodes,
+) {
+    let (public_coefficients, _secret_keys) =
+        util::keygen(seed, threshold, group_size).expect("Failed to deal");
+    let indices: Vec<_> = (0..group_size.get()).rev().collect();
+    let public_keys = tsig::individual_public_keys(&public_coefficients, &indices)
+        .expect("failed to generate public keys");
+    assert_eq!(public_keys.len(), indices.len());
+    for (index, public_key) in indices.iter().zip(public_keys) {
+        assert_eq!(
+            tsig::individual_public_key(&public_coefficients, *index)
+                .expect("failed to generate public key"),
+            public_key
+        );
+    }
+}
+
 #[test]
 fn test_public_key_to_der() {
     // Test vectors generated from Haskell as follows:
@@ -218,6 +240,10 @@ proptest! {
             test_individual_signature_verifies(Randomness::from(seed), NumberOfNodes::from(threshold + redundancy), NumberOfNodes::from(threshold), &message);
         }
         #[test]
+        fn individual_public_keys_match_individual_public_key(seed: [u8;32], threshold in 0_u32..10, redundancy in 0_u32..10) {
+            test_individual_public_keys_match_individual_public_key(Randomness::from(seed), NumberOfNodes::from(threshold + redundancy), NumberOfNodes::from(threshold));
+        }
+        #[test]
         fn combined_signature_verifies(seed: [u8;32], threshold in 0_u32..20, redundancy in 0_u32..20, message: Vec<u8>) {
             test_combined_signature_verifies(Randomness::from(seed), NumberOfNodes::from(threshold + redundancy), NumberOfNodes::from(threshold), &message);
         }
@@ -5,7 +5,7 @@ use super::types::{
     Polynomial, PublicCoefficients, SecretKey, Signature,
 };
 use crate::api::dkg_errors::InvalidArgumentError;
-use ic_crypto_internal_bls12381_common::{hash_to_g1, scalar_multiply};
+use ic_crypto_internal_bls12381_common::{hash_to_g1, scalar_multiply, scalar_multiply_secret};
 
 use crate::types::PublicKey;
 use ff::{Field, PrimeField};
@@ -39,7 +39,7 @@ pub mod tests;
 
 /// Computes the public equivalent of a secret key.
 pub fn public_key_from_secret_key(secret_key: &SecretKey) -> PublicKey {
-    PublicKey(scalar_multiply(G2::one(), *secret_key))
+    PublicKey(scalar_multiply_secret(G2::one(), *secret_key))
 }
 
 /// Yields the polynomial-evaluation point `x` given the `index` of the
@@ -238,9 +238,7 @@ pub fn combined_public_key(public_coefficients: &PublicCoefficients) -> PublicKe
 /// it is better to hash the data separately and provide the digest to
 ///   sign_hash(digest: [u8: 32], secret_key: &SecretKey) // unimplemented.
 pub fn sign_message(message: &[u8], secret_key: &SecretKey) -> Signature {
-    let mut signature = hash_message_to_g1(message);
-    signature.mul_assign(*secret_key);
-    signature
+    scalar_multiply_secret(hash_message_to_g1(message), *secret_key)
 }
 
 /// Combines signature shares (i.e. evaluates the signature at `x=0`).
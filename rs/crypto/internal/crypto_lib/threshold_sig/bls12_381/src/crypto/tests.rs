@@ -5,12 +5,16 @@ use super::super::crypto;
 use super::super::test_utils::select_n;
 use super::super::types::{
     polynomial::arbitrary::poly, CombinedSignature, IndividualSignature, Polynomial,
-    PublicCoefficients, SecretKey,
+    PublicCoefficients, SecretKey, SecretKeyBytes,
 };
 use crate::crypto::hash_message_to_g1;
 use crate::types::PublicKey;
 use ff::Field;
-use ic_crypto_internal_bls12381_common::{g1_to_bytes, hash_to_fr};
+use group::CurveProjective;
+use ic_crypto_internal_bls12381_common::{g1_to_bytes, g2_to_bytes, hash_to_fr};
+use ic_crypto_internal_test_vectors::multi_bls12_381::{
+    TESTVEC_MULTI_BLS12_381_1_PK, TESTVEC_MULTI_BLS12_381_1_SK,
+};
 use ic_types::crypto::error::InvalidArgumentError;
 use ic_types::{NodeIndex, NumberOfNodes, Randomness};
 use pairing::bls12_381::Fr;
@@ -263,6 +267,30 @@ fn test_distinct_messages_yield_distinct_hashes() {
     assert_eq!(number_of_messages, points.len(), "Collisions found");
 }
 
+/// Known answer test: the signing and public key operations on secret keys
+/// produce the same values regardless of whether the secret scalar is blinded
+/// (feature `side_channel_hardened`) or not.
+#[test]
+fn secret_key_operations_match_known_answers() {
+    let mut bytes = SecretKeyBytes([0; 32]);
+    bytes
+        .0
+        .copy_from_slice(&hex::decode(TESTVEC_MULTI_BLS12_381_1_SK).unwrap());
+    let secret_key = SecretKey::try_from(&bytes).unwrap();
+    let public_key = crypto::public_key_from_secret_key(&secret_key);
+    assert_eq!(
+        hex::encode(&g2_to_bytes(&public_key.0)[..]),
+        TESTVEC_MULTI_BLS12_381_1_PK
+    );
+
+    let message = b"data";
+    let signature = crypto::sign_message(message, &secret_key);
+    let mut expected = hash_message_to_g1(message);
+    expected.mul_assign(secret_key);
+    assert_eq!(signature, expected);
+    assert!(crypto::verify_individual_sig(message, signature, public_key).is_ok());
+}
+
 /// This is a happy path test for the single dealer case.
 #[test]
 fn omnipotent_dealer() {
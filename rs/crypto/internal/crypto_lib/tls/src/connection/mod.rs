@@ -29,6 +29,14 @@ mod acceptor {
         /// certificate, the handshake only succeeds if the client's certificate
         /// can be verified against 'trusted_client_certs'.
         OptionalAuthentication { trusted_client_certs: Vec<X509> },
+        /// Client authentication is mandatory. The handshake only succeeds if
+        /// the client presents a certificate chain that ends in one of the
+        /// 'trusted_client_cas' and has at most 'max_intermediate_cas'
+        /// intermediate CA certificates.
+        MandatoryCaAuthentication {
+            trusted_client_cas: Vec<X509>,
+            max_intermediate_cas: u32,
+        },
     }
 
     /// Builds a TLS acceptor to establish TLS connections on the server side.
@@ -61,6 +69,19 @@ mod acceptor {
                 set_peer_verification_cert_store(trusted_client_certs, &mut builder)?;
                 set_maximum_number_of_intermediate_ca_certificates(1, &mut builder);
             }
+            ClientAuthentication::MandatoryCaAuthentication {
+                trusted_client_cas,
+                max_intermediate_cas,
+            } => {
+                ensure_root_self_signed_sigs_verified(&mut builder);
+                ensure_trusted_client_certs_not_empty(&trusted_client_cas)?;
+                enforce_client_authentication(&mut builder);
+                set_peer_verification_cert_store(trusted_client_cas, &mut builder)?;
+                set_maximum_number_of_intermediate_ca_certificates(
+                    max_intermediate_cas,
+                    &mut builder,
+                );
+            }
         }
 
         set_private_key(private_key, server_cert, &mut builder)?;
@@ -88,6 +109,10 @@ mod acceptor {
         builder.set_verify(SslVerifyMode::PEER);
     }
 
+    fn enforce_client_authentication(builder: &mut SslAcceptorBuilder) {
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+
     fn prohibit_client_authentication(builder: &mut SslAcceptorBuilder) {
         builder.set_verify(SslVerifyMode::NONE);
     }
@@ -102,6 +102,48 @@ mod acceptor {
         );
     }
 
+    #[test]
+    fn should_return_error_if_trusted_client_cas_empty() {
+        let (cert_key_pair, server_cert) = generate_ed25519_cert();
+
+        let error = tls_acceptor(
+            &cert_key_pair,
+            &server_cert,
+            ClientAuthentication::MandatoryCaAuthentication {
+                trusted_client_cas: vec![],
+                max_intermediate_cas: 1,
+            },
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.description,
+            "The trusted client certs must not be empty.".to_string()
+        );
+    }
+
+    #[test]
+    fn should_require_client_authentication_if_mandatory() {
+        let (key_pair, server_cert) = generate_ed25519_cert();
+        let (_ca_key_pair, ca_cert) = generate_ed25519_cert();
+
+        let acceptor = tls_acceptor(
+            &key_pair,
+            &server_cert,
+            ClientAuthentication::MandatoryCaAuthentication {
+                trusted_client_cas: vec![ca_cert],
+                max_intermediate_cas: 2,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            acceptor.context().verify_mode(),
+            SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT
+        );
+    }
+
     #[test]
     fn should_return_error_if_certificate_does_not_match_private_key() {
         let (_cert_key_pair, server_cert) = generate_ed25519_cert();
@@ -50,43 +50,77 @@ impl fmt::Debug for TlsEd25519SecretKeyDerBytes {
     }
 }
 
-/// Generate a key pair and return the certificate and private key in DER
-/// format.
+/// The notAfter date which indicates according to RFC5280 (section 4.1.2.5;
+/// see https://tools.ietf.org/html/rfc5280#section-4.1.2.5) that a
+/// certificate has no well-defined expiration date. Node certificates in the
+/// registry must have this date.
+pub const NO_WELL_DEFINED_EXPIRATION_DATE: &str = "99991231235959Z";
+
+/// Generates an Ed25519 key pair together with a self-signed X.509 v3
+/// certificate for its public key.
 ///
-/// Note that the certificate serial number must be at most 20 octets according
-/// to https://tools.ietf.org/html/rfc5280 Section 4.1.2.2. The 19 bytes serial
-/// number argument is interpreted as an unsigned integer and thus fits in 20
-/// bytes, encoded as a signed ASN1 integer.
-pub fn generate_tls_key_pair_der(
-    common_name: &str,
+/// The subject and issuer CN of the certificate are the common name, and the
+/// certificate is valid from the time it is built. Unless configured
+/// otherwise, it has no well-defined expiration date.
+///
+/// The CSP, the `ic-crypto-tls` crate and the test doubles of the TLS
+/// interfaces build node certificates with it. The `CertBuilder` of
+/// `ic-crypto-test-utils` does not: it builds arbitrary, possibly malformed,
+/// certificates for testing certificate validation directly with OpenSSL.
+pub struct TlsCertBuilder {
+    common_name: String,
     serial: [u8; 19],
-    not_after: &Asn1Time,
-) -> (TlsEd25519CertificateDerBytes, TlsEd25519SecretKeyDerBytes) {
-    let (x509_certificate, key_pair) = generate_tls_key_pair(common_name, serial, not_after);
-    der_encode_cert_and_secret_key(&key_pair, x509_certificate)
+    not_after: String,
 }
 
-/// Generate a key pair and return the certificate and private key.
-///
-/// Note that the certificate serial number must be at most 20 octets according
-/// to https://tools.ietf.org/html/rfc5280 Section 4.1.2.2. The 19 bytes serial
-/// number argument is interpreted as an unsigned integer and thus fits in 20
-/// bytes, encoded as a signed ASN1 integer.
-pub fn generate_tls_key_pair(
-    common_name: &str,
-    serial: [u8; 19],
-    not_after: &Asn1Time,
-) -> (X509, PKey<Private>) {
-    let key_pair = ed25519_key_pair();
-    let x509_certificate = x509_v3_certificate(
-        common_name,
-        serial,
-        &key_pair,
-        not_after,
-        // Digest must be null for Ed25519 (see https://www.openssl.org/docs/man1.1.1/man7/Ed25519.html)
-        MessageDigest::null(),
-    );
-    (x509_certificate, key_pair)
+impl TlsCertBuilder {
+    /// Note that the certificate serial number must be at most 20 octets
+    /// according to https://tools.ietf.org/html/rfc5280 Section 4.1.2.2. The
+    /// 19 bytes `serial` are interpreted as an unsigned integer and thus fit
+    /// in 20 bytes, encoded as a signed ASN1 integer.
+    pub fn new(common_name: &str, serial: [u8; 19]) -> Self {
+        Self {
+            common_name: common_name.to_string(),
+            serial,
+            not_after: NO_WELL_DEFINED_EXPIRATION_DATE.to_string(),
+        }
+    }
+
+    /// Sets the certificate's notAfter date, in one of the formats allowed by
+    /// RFC 5280, i.e. YYMMDDHHMMSSZ or YYYYMMDDHHMMSSZ.
+    pub fn with_not_after(mut self, not_after: &str) -> Self {
+        self.not_after = not_after.to_string();
+        self
+    }
+
+    /// Generates the key pair and returns the certificate and private key.
+    ///
+    /// # Panics
+    /// * if the notAfter date cannot be parsed or lies in the past
+    pub fn build(&self) -> (X509, PKey<Private>) {
+        let not_after = Asn1Time::from_str_x509(&self.not_after)
+            .expect("invalid X.509 certificate expiration date (not_after)");
+        let key_pair = ed25519_key_pair();
+        let x509_certificate = x509_v3_certificate(
+            &self.common_name,
+            self.serial,
+            &key_pair,
+            &not_after,
+            // Digest must be null for Ed25519 (see https://www.openssl.org/docs/man1.1.1/man7/Ed25519.html)
+            MessageDigest::null(),
+        );
+        (x509_certificate, key_pair)
+    }
+
+    /// Generates the key pair and returns the certificate and private key in
+    /// DER format.
+    ///
+    /// # Panics
+    /// * if the notAfter date cannot be parsed or lies in the past
+    pub fn build_der(&self) -> (TlsEd25519CertificateDerBytes, TlsEd25519SecretKeyDerBytes) {
+        let (x509_certificate, key_pair) = self.build();
+        der_encode_cert_and_secret_key(&key_pair, x509_certificate)
+    }
 }
 
 fn ed25519_key_pair() -> PKey<Private> {
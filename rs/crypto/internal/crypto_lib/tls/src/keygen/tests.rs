@@ -4,11 +4,11 @@ use openssl::pkey::{Id, Public};
 use openssl::x509::X509VerifyResult;
 
 const SERIAL: [u8; 19] = [42; 19];
-const VALIDITY_DAYS: u32 = 365;
+const NOT_AFTER: &str = "25670102030405Z";
 
 #[test]
 fn should_return_certificate_as_der() {
-    let (cert, _sk) = generate_tls_key_pair_der("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build_der();
 
     let result = X509::from_der(&cert.bytes);
 
@@ -17,7 +17,7 @@ fn should_return_certificate_as_der() {
 
 #[test]
 fn should_return_secret_key_as_der() {
-    let (_cert, sk) = generate_tls_key_pair_der("common name", SERIAL, &not_after());
+    let (_cert, sk) = builder().build_der();
 
     let result = PKey::private_key_from_der(&sk.bytes);
 
@@ -26,14 +26,14 @@ fn should_return_secret_key_as_der() {
 
 #[test]
 fn should_return_self_signed_certificate() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     assert_eq!(cert.issued(&cert), X509VerifyResult::OK);
 }
 
 #[test]
 fn should_validate_signature_with_own_public_key() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let public_key = cert.public_key().unwrap();
     assert_eq!(cert.verify(&public_key).ok(), Some(true));
@@ -41,7 +41,7 @@ fn should_validate_signature_with_own_public_key() {
 
 #[test]
 fn should_set_correct_signature_algorithm() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let signature_algorithm = cert.signature_algorithm().object();
     assert_eq!(signature_algorithm.nid().as_raw(), Id::ED25519.as_raw());
@@ -50,7 +50,7 @@ fn should_set_correct_signature_algorithm() {
 
 #[test]
 fn should_generate_public_key_with_correct_algorithm() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let public_key: &PKey<Public> = &cert.public_key().unwrap();
 
@@ -59,7 +59,7 @@ fn should_generate_public_key_with_correct_algorithm() {
 
 #[test]
 fn should_set_subject_cn_as_common_name() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let subject_name = cert.subject_name();
     assert_eq!(subject_name.entries_by_nid(Nid::COMMONNAME).count(), 1);
@@ -69,7 +69,7 @@ fn should_set_subject_cn_as_common_name() {
 
 #[test]
 fn should_set_issuer_cn_as_common_name() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let issuer_name = cert.issuer_name();
     assert_eq!(issuer_name.entries_by_nid(Nid::COMMONNAME).count(), 1);
@@ -79,7 +79,7 @@ fn should_set_issuer_cn_as_common_name() {
 
 #[test]
 fn should_set_issuer_cn_and_subject_cn_to_same_value() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let issuer_cn = cert
         .issuer_name()
@@ -96,7 +96,7 @@ fn should_set_issuer_cn_and_subject_cn_to_same_value() {
 
 #[test]
 fn should_set_serial_number() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let serial = cert.serial_number().to_bn().unwrap();
     let expected_serial = BigNum::from_slice(&SERIAL).unwrap();
@@ -106,7 +106,7 @@ fn should_set_serial_number() {
 #[test]
 fn should_set_max_serial_number() {
     let max_serial: [u8; 19] = [255; 19];
-    let (cert, _sk) = generate_tls_key_pair("common name", max_serial, &not_after());
+    let (cert, _sk) = TlsCertBuilder::new("common name", max_serial).build();
 
     let serial = cert.serial_number().to_bn().unwrap();
     let expected_serial = BigNum::from_slice(&max_serial).unwrap();
@@ -115,7 +115,7 @@ fn should_set_max_serial_number() {
 
 #[test]
 fn should_not_set_subject_alt_name() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let subject_alt_names = cert.subject_alt_names();
     assert!(subject_alt_names.is_none());
@@ -123,7 +123,7 @@ fn should_not_set_subject_alt_name() {
 
 #[test]
 fn should_set_not_before_to_now() {
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, &not_after());
+    let (cert, _sk) = builder().build();
 
     let now = Asn1Time::days_from_now(0).unwrap();
     let not_before = cert.not_before();
@@ -133,16 +133,32 @@ fn should_set_not_before_to_now() {
 #[test]
 #[should_panic(expected = "'not after' date must not be in the past")]
 fn should_panic_if_not_after_date_is_in_the_past() {
-    let date_in_the_past = Asn1Time::from_str_x509("20000102030405Z").unwrap();
-    let _panic = generate_tls_key_pair("common name", SERIAL, &date_in_the_past);
+    let _panic = TlsCertBuilder::new("common name", SERIAL)
+        .with_not_after("20000102030405Z")
+        .build();
+}
+
+#[test]
+#[should_panic(expected = "invalid X.509 certificate expiration date (not_after)")]
+fn should_panic_if_not_after_date_is_malformed() {
+    let _panic = TlsCertBuilder::new("common name", SERIAL)
+        .with_not_after("tomorrow")
+        .build();
 }
 
 #[test]
 fn should_set_not_after_correctly() {
-    let not_after = &not_after();
-    let (cert, _sk) = generate_tls_key_pair("common name", SERIAL, not_after);
+    let (cert, _sk) = builder().build();
+
+    assert!(cert.not_after() == Asn1Time::from_str_x509(NOT_AFTER).unwrap());
+}
+
+#[test]
+fn should_set_no_well_defined_expiration_date_by_default() {
+    let (cert, _sk) = TlsCertBuilder::new("common name", SERIAL).build();
 
-    assert!(cert.not_after() == not_after);
+    let expected_not_after = Asn1Time::from_str_x509(NO_WELL_DEFINED_EXPIRATION_DATE).unwrap();
+    assert!(cert.not_after() == expected_not_after);
 }
 
 #[test]
@@ -153,6 +169,6 @@ fn should_redact_tls_ed25519_secret_key_der_bytes_debug() {
     assert_eq!(format!("{:?}", sk), "REDACTED");
 }
 
-fn not_after() -> Asn1Time {
-    Asn1Time::days_from_now(VALIDITY_DAYS).expect("failed to construct Asn1Time date")
+fn builder() -> TlsCertBuilder {
+    TlsCertBuilder::new("common name", SERIAL).with_not_after(NOT_AFTER)
 }
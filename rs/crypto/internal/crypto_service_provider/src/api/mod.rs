@@ -8,7 +8,8 @@ mod tls_stub;
 pub use keygen::{CspKeyGenerator, CspSecretKeyStoreChecker, NodePublicKeyData};
 pub use sign::CspSigner;
 pub use threshold::{
-    threshold_sign_error::CspThresholdSignError, CspSecretKeyInjector,
-    DistributedKeyGenerationCspClient, NiDkgCspClient, ThresholdSignatureCspClient,
+    threshold_sign_error::{CspThresholdSignError, MessageHashPrefix, SecretKeyStoreHint},
+    CspSecretKeyInjector, DistributedKeyGenerationCspClient, NiDkgCspClient,
+    ThresholdSignatureCspClient,
 };
 pub use tls_stub::{tls_errors, CspTlsClientHandshake, CspTlsServerHandshake};
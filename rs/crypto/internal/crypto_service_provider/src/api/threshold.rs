@@ -123,6 +123,19 @@ pub trait ThresholdSignatureCspClient {
         public_coefficients: CspPublicCoefficients,
     ) -> CryptoResult<CspThresholdSigPublicKey>;
 
+    /// Gets the public keys of several individual signatories, in the order of
+    /// `node_indices`.
+    ///
+    /// Note: This parses the public coefficients only once and is therefore
+    /// considerably cheaper than calling `threshold_individual_public_key` for
+    /// each index.
+    fn threshold_individual_public_keys(
+        &self,
+        algorithm_id: AlgorithmId,
+        node_indices: &[NodeIndex],
+        public_coefficients: CspPublicCoefficients,
+    ) -> CryptoResult<Vec<CspThresholdSigPublicKey>>;
+
     /// Checks whether an individual node's signature is valid.
     fn threshold_verify_individual_signature(
         &self,
@@ -1,5 +1,6 @@
 use super::*;
 use ic_crypto_internal_threshold_sig_bls12381::api::threshold_sign_error::ClibThresholdSignError;
+use ic_crypto_sha::Sha256;
 
 /// Errors occuring while performing threshold signature generation
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -7,6 +8,10 @@ pub enum CspThresholdSignError {
     SecretKeyNotFound {
         algorithm: AlgorithmId,
         key_id: KeyId,
+        /// Identifies the message that was to be signed.
+        message_hash_prefix: MessageHashPrefix,
+        /// What the secret key store knows about the missing key.
+        secret_key_store_hint: SecretKeyStoreHint,
     },
     UnsupportedAlgorithm {
         algorithm: AlgorithmId,
@@ -17,6 +22,46 @@ pub enum CspThresholdSignError {
     },
 }
 
+/// The first bytes of the SHA-256 hash of a message to be signed.
+///
+/// This allows correlating a signing error with the message, e.g., in logs,
+/// without including the (potentially large) message itself in the error.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MessageHashPrefix(pub [u8; MessageHashPrefix::LEN]);
+
+impl MessageHashPrefix {
+    pub const LEN: usize = 8;
+
+    pub fn of_message(message: &[u8]) -> Self {
+        let mut prefix = [0; Self::LEN];
+        prefix.copy_from_slice(&Sha256::hash(message)[..Self::LEN]);
+        MessageHashPrefix(prefix)
+    }
+}
+
+impl fmt::Debug for MessageHashPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for MessageHashPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// What the secret key store knows about a key that was not found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SecretKeyStoreHint {
+    /// The key was recently removed from the store because its transcript
+    /// was no longer active.
+    RecentlyRetired,
+    /// Nothing is known about the key: it was either never stored, or
+    /// removed longer ago than the store keeps track of.
+    Unknown,
+}
+
 impl From<ClibThresholdSignError> for CspThresholdSignError {
     fn from(clib_error: ClibThresholdSignError) -> Self {
         match clib_error {
@@ -40,11 +85,16 @@ impl From<CspSecretKeyConversionError> for CspThresholdSignError {
 impl fmt::Display for CspThresholdSignError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            CspThresholdSignError::SecretKeyNotFound { algorithm, key_id } => write!(
+            CspThresholdSignError::SecretKeyNotFound {
+                algorithm,
+                key_id,
+                message_hash_prefix,
+                secret_key_store_hint,
+            } => write!(
                 f,
                 "The secret key with key id {:?} and algorithm id {:?} was not found in the \
-                secret key store.",
-                key_id, algorithm
+                secret key store when signing the message with hash prefix {} ({:?}).",
+                key_id, algorithm, message_hash_prefix, secret_key_store_hint
             ),
             CspThresholdSignError::UnsupportedAlgorithm { algorithm } => write!(
                 f,
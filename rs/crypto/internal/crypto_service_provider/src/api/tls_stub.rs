@@ -96,6 +96,54 @@ pub trait CspTlsServerHandshake {
         tcp_stream: TcpStream,
         self_cert: TlsPublicKeyCert,
     ) -> Result<TlsStream, CspTlsServerHandshakeError>;
+
+    /// Transforms a TCP stream into a TLS stream by performing a TLS server
+    /// handshake in which the client must authenticate with a certificate
+    /// chain that ends in one of the `trusted_client_cas`.
+    ///
+    /// The `self_cert` is used as server certificate and the corresponding
+    /// private key must be in the secret key store.
+    ///
+    /// For the handshake, the server uses the following configuration:
+    /// * Minimum protocol version: TLS 1.3
+    /// * Supported signature algorithms: ed25519
+    /// * Allowed cipher suites: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384
+    /// * Client authentication: mandatory, with ed25519 certificate
+    /// * Maximum number of intermediate CA certificates:
+    ///   `max_intermediate_cas`
+    ///
+    /// The given `tcp_stream` is consumed. If an error is returned, the TCP
+    /// connection is therefore dropped.
+    ///
+    /// The TLS stream is returned in a form that does not allow for extracting
+    /// the private key corresponding to `self_cert` or the TLS session keys.
+    ///
+    /// Returns the TLS stream, together with the verified certificate chain
+    /// of the client. The chain is only `None` if the TLS library did not
+    /// provide it despite the successful handshake.
+    ///
+    /// # Errors
+    /// * CspTlsServerHandshakeError::CreateAcceptorError if there is a problem
+    ///   configuring the server for accepting connections from clients, e.g.,
+    ///   if `trusted_client_cas` is empty.
+    /// * CspTlsServerHandshakeError::HandshakeError if there is an error during
+    ///   the TLS handshake, or the handshake fails.
+    /// * CspTlsServerHandshakeError::SecretKeyNotFound if the secret key
+    ///   corresponding to `self_cert` cannot be found in the secret key store.
+    /// * CspTlsServerHandshakeError::MalformedSecretKey if the secret key
+    ///   corresponding to `self_cert` is malformed in the secret key store.
+    /// * CspTlsServerHandshakeError::WrongSecretKeyType if the secret key
+    ///   corresponding to `self_cert` has the wrong type in the secret key
+    ///   store.
+    /// * CspTlsServerHandshakeError::MalformedClientCertificate if any
+    ///   certificate in the chain offered by the client is malformed.
+    async fn perform_tls_server_handshake_with_ca_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        self_cert: TlsPublicKeyCert,
+        trusted_client_cas: HashSet<TlsPublicKeyCert>,
+        max_intermediate_cas: u32,
+    ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError>;
 }
 
 /// A trait that exposes TLS client-side handshaking
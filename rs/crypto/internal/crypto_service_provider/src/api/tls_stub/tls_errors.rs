@@ -5,7 +5,8 @@ mod tests;
 
 use ic_crypto_internal_tls::{CreateTlsAcceptorError, CreateTlsConnectorError};
 use ic_crypto_tls_interfaces::{
-    MalformedPeerCertificateError, TlsClientHandshakeError, TlsServerHandshakeError,
+    HandshakeFailureKind, MalformedPeerCertificateError, TlsClientHandshakeError,
+    TlsServerHandshakeError,
 };
 
 use std::fmt;
@@ -21,6 +22,7 @@ pub enum CspTlsClientHandshakeError {
         internal_error: String,
     },
     HandshakeError {
+        kind: HandshakeFailureKind,
         internal_error: String,
     },
     SecretKeyNotFound,
@@ -53,10 +55,13 @@ impl fmt::Debug for CspTlsClientHandshakeError {
                           server_cert_der.as_ref().map(|der| base64::encode(&der)),
                           internal_error)
             }
-            HandshakeError { internal_error } => write!(
+            HandshakeError {
+                kind,
+                internal_error,
+            } => write!(
                 f,
-                "CspTlsClientHandshakeError::HandshakeError{{ internal_error: {} }}",
-                internal_error
+                "CspTlsClientHandshakeError::HandshakeError{{ kind: {:?}, internal_error: {} }}",
+                kind, internal_error
             ),
             SecretKeyNotFound => write!(f, "CspTlsClientHandshakeError::SecretKeyNotFound"),
             MalformedSecretKey => write!(f, "CspTlsClientHandshakeError::MalformedSecretKey"),
@@ -91,9 +96,13 @@ impl From<CspTlsClientHandshakeError> for TlsClientHandshakeError {
                 server_cert_der,
                 internal_error,
             },
-            CspTlsClientHandshakeError::HandshakeError { internal_error } => {
-                TlsClientHandshakeError::HandshakeError { internal_error }
-            }
+            CspTlsClientHandshakeError::HandshakeError {
+                kind,
+                internal_error,
+            } => TlsClientHandshakeError::HandshakeError {
+                kind,
+                internal_error,
+            },
             CspTlsClientHandshakeError::SecretKeyNotFound => {
                 // This would be a problem in the node's setup, so we panic:
                 panic!("{}The secret key was not found", panic_prefix);
@@ -131,6 +140,7 @@ pub enum CspTlsServerHandshakeError {
         internal_error: Option<String>,
     },
     HandshakeError {
+        kind: HandshakeFailureKind,
         internal_error: String,
     },
     SecretKeyNotFound,
@@ -151,7 +161,7 @@ impl fmt::Debug for CspTlsServerHandshakeError {
                                                                                  description,
                                                                                  cert_der.as_ref().map(|der| base64::encode(&der[..])),
                                                                                  internal_error),
-            HandshakeError{internal_error} => write!(f, "CspTlsServerHandshakeError::HandshakeError{{ internal_error: {} }}", internal_error),
+            HandshakeError{kind, internal_error} => write!(f, "CspTlsServerHandshakeError::HandshakeError{{ kind: {:?}, internal_error: {} }}", kind, internal_error),
             SecretKeyNotFound => write!(f, "CspTlsServerHandshakeError::SecretKeyNotFound"),
             MalformedSecretKey => write!(f, "CspTlsServerHandshakeError::MalformedSecretKey"),
             WrongSecretKeyType => write!(f, "CspTlsServerHandshakeError::WrongSecretKeyType"),
@@ -177,9 +187,13 @@ impl From<CspTlsServerHandshakeError> for TlsServerHandshakeError {
                 cert_der,
                 internal_error,
             },
-            CspTlsServerHandshakeError::HandshakeError { internal_error } => {
-                TlsServerHandshakeError::HandshakeError { internal_error }
-            }
+            CspTlsServerHandshakeError::HandshakeError {
+                kind,
+                internal_error,
+            } => TlsServerHandshakeError::HandshakeError {
+                kind,
+                internal_error,
+            },
             CspTlsServerHandshakeError::SecretKeyNotFound => {
                 // This would be a problem in the node's setup, so we panic:
                 panic!("{}The secret key was not found", panic_prefix);
@@ -14,7 +14,7 @@ fn csp_tls_client_handshake_error_debug() {
         server_cert_der: None,
         internal_error: "err".to_string(),
     }, "CspTlsClientHandshakeError::CreateConnectorError{ description: desc, client_cert_der: Some(\"AQIDBAUGBwgJ\"), server_cert_der: None, internal_error: err}"),
-    (CspTlsClientHandshakeError::HandshakeError{internal_error: "bat\"man".to_string()}, "CspTlsClientHandshakeError::HandshakeError{ internal_error: bat\"man }"),
+    (CspTlsClientHandshakeError::HandshakeError{kind: HandshakeFailureKind::PeerClosed, internal_error: "bat\"man".to_string()}, "CspTlsClientHandshakeError::HandshakeError{ kind: PeerClosed, internal_error: bat\"man }"),
     (CspTlsClientHandshakeError::SecretKeyNotFound, "CspTlsClientHandshakeError::SecretKeyNotFound"),
     (CspTlsClientHandshakeError::MalformedSecretKey, "CspTlsClientHandshakeError::MalformedSecretKey"),
     (CspTlsClientHandshakeError::WrongSecretKeyType, "CspTlsClientHandshakeError::WrongSecretKeyType")
@@ -2,16 +2,15 @@
 
 use crate::api::{CspKeyGenerator, CspSecretKeyStoreChecker};
 use crate::secret_key_store::{SecretKeyStore, SecretKeyStoreError};
-use crate::types::{CspPop, CspPublicKey, CspSecretKey};
+use crate::types::{CspPop, CspPublicKey, CspSecretKey, MEGaPublicKey};
 use crate::Csp;
 use ic_crypto_internal_basic_sig_ed25519 as ed25519;
 use ic_crypto_internal_multi_sig_bls12381 as multi_sig;
-use ic_crypto_internal_tls::keygen::generate_tls_key_pair_der;
+use ic_crypto_internal_tls::keygen::TlsCertBuilder;
 use ic_crypto_sha::{Context, DomainSeparationContext};
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_types::crypto::{AlgorithmId, CryptoError, KeyId};
 use ic_types::NodeId;
-use openssl::asn1::Asn1Time;
 use rand::{CryptoRng, Rng};
 use std::convert::TryFrom;
 
@@ -58,9 +57,9 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> CspKeyGenerator for Csp<R, S> {
     fn gen_tls_key_pair(&mut self, node: NodeId, not_after: &str) -> TlsPublicKeyCert {
         let serial = self.rng_write_lock().gen::<[u8; 19]>();
         let common_name = &node.get().to_string()[..];
-        let not_after = Asn1Time::from_str_x509(not_after)
-            .expect("invalid X.509 certificate expiration date (not_after)");
-        let (cert, secret_key) = generate_tls_key_pair_der(common_name, serial, &not_after);
+        let (cert, secret_key) = TlsCertBuilder::new(common_name, serial)
+            .with_not_after(not_after)
+            .build_der();
 
         let x509_pk_cert = TlsPublicKeyCert::new_from_der(cert.bytes)
             .expect("generated X509 certificate has malformed DER encoding");
@@ -124,6 +123,11 @@ pub fn public_key_hash_as_key_id(pk: &CspPublicKey) -> KeyId {
     bytes_hash_as_key_id(pk.algorithm_id(), pk.pk_bytes())
 }
 
+/// Compute the key identifier of the given MEGa public key
+pub fn mega_key_id(public_key: &MEGaPublicKey) -> KeyId {
+    bytes_hash_as_key_id(public_key.algorithm_id(), public_key.pk_bytes())
+}
+
 // KeyId is SHA256 computed on the bytes:
 //     domain_separator | algorithm_id | size(pk_bytes) | pk_bytes
 // where  domain_separator is DomainSeparationContext(KEY_ID_DOMAIN),
@@ -5,7 +5,7 @@ use ic_crypto_internal_test_vectors::unhex::{hex_to_32_bytes, hex_to_byte_vec};
 use ic_types_test_utils::ids::node_test_id;
 use openssl::x509::X509NameEntries;
 use openssl::{asn1::Asn1Time, bn::BigNum, nid::Nid, x509::X509};
-use rand::SeedableRng;
+use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 
 #[test]
@@ -29,6 +29,14 @@ fn should_correctly_generate_ed25519_keys() {
     );
 }
 
+#[test]
+#[should_panic(expected = "repetition_count health test failed")]
+fn should_refuse_to_generate_keys_with_stuck_csprng() {
+    let csp = Csp::of(StuckRng, volatile_key_store());
+
+    let _ = csp.gen_key_pair(AlgorithmId::Ed25519);
+}
+
 #[test]
 fn should_retrieve_newly_generated_secret_key_from_store() {
     let csprng = csprng_seeded_with(42);
@@ -91,6 +99,30 @@ fn volatile_key_store() -> VolatileSecretKeyStore {
     VolatileSecretKeyStore::new()
 }
 
+/// An RNG that only ever returns zeros.
+struct StuckRng;
+
+impl RngCore for StuckRng {
+    fn next_u32(&mut self) -> u32 {
+        0
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        0
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.iter_mut().for_each(|byte| *byte = 0);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for StuckRng {}
+
 mod multi {
     use super::*;
     use ic_crypto_internal_multi_sig_bls12381::types::{PopBytes, PublicKeyBytes};
@@ -21,9 +21,13 @@ use crate::api::{
     NiDkgCspClient, NodePublicKeyData, ThresholdSignatureCspClient,
 };
 use crate::keygen::{forward_secure_key_id, public_key_hash_as_key_id};
-use crate::public_key_store::read_node_public_keys;
 use crate::secret_key_store::volatile_store::VolatileSecretKeyStore;
 use crate::secret_key_store::SecretKeyStore;
+use crate::server::async_csp_server::SpawnBlockingCspServer;
+use crate::server::local_csp_server::rng_health::HealthCheckedRng;
+use crate::server::local_csp_server::version::ensure_csp_server_compatibility;
+use crate::server::local_csp_server::LocalCspServer;
+use crate::threshold::retired_keys::RetiredKeyIds;
 use crate::types::CspPublicKey;
 use ic_config::crypto::CryptoConfig;
 use ic_crypto_internal_logmon::metrics::CryptoMetrics;
@@ -34,11 +38,16 @@ use ic_types::crypto::KeyId;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use rand::rngs::OsRng;
 use rand::{CryptoRng, Rng};
-use secret_key_store::proto_store::ProtoSecretKeyStore;
+use secret_key_store::proto_store::{IntegrityCheckMode, ProtoSecretKeyStore};
 use std::convert::TryFrom;
 use std::sync::Arc;
 use std::time::Instant;
 
+pub use crate::server::api::{
+    AsyncBasicSignatureCspServer, AsyncThresholdSignatureCspServer, CspBasicSignatureError,
+    CspCancellableSignError,
+};
+
 /// Describes the interface of the crypto service provider (CSP), e.g. for
 /// signing and key generation. The Csp struct implements this trait.
 pub trait CryptoServiceProvider:
@@ -114,10 +123,10 @@ impl PublicKeyData {
 
 /// Implements the CryptoServiceProvider for an RNG and a SecretKeyStore.
 pub struct Csp<R: Rng + CryptoRng, S: SecretKeyStore> {
-    // CSPRNG stands for cryptographically secure random number generator.
-    csprng: CspRwLock<R>,
-    secret_key_store: CspRwLock<S>,
-    public_key_data: PublicKeyData,
+    // Holds the CSPRNG, the secret key store and the public key data. Shared
+    // with the asynchronous signers handed out by `async_signer`.
+    csp_server: Arc<LocalCspServer<R, S>>,
+    retired_threshold_key_ids: RwLock<RetiredKeyIds>,
     logger: ReplicaLogger,
 }
 
@@ -169,19 +178,19 @@ impl<T> CspRwLock<T> {
 }
 
 impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
-    fn rng_write_lock(&self) -> RwLockWriteGuard<'_, R> {
+    fn rng_write_lock(&self) -> RwLockWriteGuard<'_, HealthCheckedRng<R>> {
         // TODO (CRP-696): inline this method
-        self.csprng.write()
+        self.csp_server.rng_write_lock()
     }
 
     fn sks_write_lock(&self) -> RwLockWriteGuard<'_, S> {
         // TODO (CRP-696): inline this method
-        self.secret_key_store.write()
+        self.csp_server.sks_write_lock()
     }
 
     fn sks_read_lock(&self) -> RwLockReadGuard<'_, S> {
         // TODO (CRP-696): inline this method
-        self.secret_key_store.read()
+        self.csp_server.sks_read_lock()
     }
 }
 
@@ -193,18 +202,22 @@ impl Csp<OsRng, ProtoSecretKeyStore> {
         metrics: Arc<CryptoMetrics>,
     ) -> Self {
         let logger = logger.unwrap_or_else(no_op_logger);
-        let secret_key_store =
-            ProtoSecretKeyStore::open(&config.crypto_root, Some(new_logger!(&logger)));
-        let node_public_keys = match read_node_public_keys(&config.crypto_root) {
-            Ok(node_pks) => node_pks,
-            Err(_) => Default::default(),
-        };
-        let public_key_data = PublicKeyData::new(node_public_keys);
-
+        let secret_key_store = ProtoSecretKeyStore::open_with_integrity_check(
+            &config.crypto_root,
+            Some(new_logger!(&logger)),
+            IntegrityCheckMode::Quarantine,
+            Arc::clone(&metrics),
+        )
+        .unwrap_or_else(|e| panic!("Failed to open the secret key store: {}", e));
+        let csp_server = LocalCspServer::builder(secret_key_store)
+            .with_public_key_store(&config.crypto_root)
+            .with_metrics(Arc::clone(&metrics))
+            .with_logger(new_logger!(&logger))
+            .build();
+        ensure_csp_server_compatibility(&csp_server, &metrics);
         Csp {
-            csprng: CspRwLock::new_for_rng(OsRng::default(), Arc::clone(&metrics)),
-            public_key_data,
-            secret_key_store: CspRwLock::new_for_sks(secret_key_store, metrics),
+            csp_server: Arc::new(csp_server),
+            retired_threshold_key_ids: RwLock::new(RetiredKeyIds::default()),
             logger,
         }
     }
@@ -216,18 +229,14 @@ impl<R: Rng + CryptoRng> Csp<R, ProtoSecretKeyStore> {
     /// Note: This MUST NOT be used in production as the secrecy of the random
     /// number generator, hence the keys, is not guaranteed.
     pub fn new_with_rng(csprng: R, config: &CryptoConfig) -> Self {
-        let node_public_keys = match read_node_public_keys(&config.crypto_root) {
-            Ok(node_pks) => node_pks,
-            Err(_) => Default::default(),
-        };
-        let public_key_data = PublicKeyData::new(node_public_keys);
+        let csp_server =
+            LocalCspServer::builder(ProtoSecretKeyStore::open(&config.crypto_root, None))
+                .with_rng(csprng)
+                .with_public_key_store(&config.crypto_root)
+                .build();
         Csp {
-            csprng: CspRwLock::new_for_rng(csprng, Arc::new(CryptoMetrics::none())),
-            public_key_data,
-            secret_key_store: CspRwLock::new_for_sks(
-                ProtoSecretKeyStore::open(&config.crypto_root, None),
-                Arc::new(CryptoMetrics::none()),
-            ),
+            csp_server: Arc::new(csp_server),
+            retired_threshold_key_ids: RwLock::new(RetiredKeyIds::default()),
             logger: no_op_logger(),
         }
     }
@@ -237,18 +246,24 @@ impl<R: Rng + CryptoRng> Csp<R, VolatileSecretKeyStore> {
     /// Resets public key data according to the given `NodePublicKeys`.
     ///
     /// Note: This is for testing only and MUST NOT be used in production.
+    ///
+    /// # Panics
+    /// If an asynchronous signing is still running.
     pub fn reset_public_key_data(&mut self, node_public_keys: NodePublicKeys) {
-        self.public_key_data = PublicKeyData::new(node_public_keys);
+        Arc::get_mut(&mut self.csp_server)
+            .expect("Cannot reset the public key data while signing asynchronously")
+            .reset_public_key_data(node_public_keys);
     }
 }
 
 impl<R: Rng + CryptoRng, S: SecretKeyStore> NodePublicKeyData for Csp<R, S> {
     fn node_public_keys(&self) -> NodePublicKeys {
-        self.public_key_data.node_public_keys.clone()
+        self.csp_server.public_key_data().node_public_keys.clone()
     }
 
     fn node_signing_key_id(&self) -> KeyId {
-        self.public_key_data
+        self.csp_server
+            .public_key_data()
             .sks_key_ids
             .node_signing_key_id
             .to_owned()
@@ -256,7 +271,8 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> NodePublicKeyData for Csp<R, S> {
     }
 
     fn dkg_dealing_encryption_key_id(&self) -> KeyId {
-        self.public_key_data
+        self.csp_server
+            .public_key_data()
             .sks_key_ids
             .dkg_dealing_encryption_key_id
             .to_owned()
@@ -270,18 +286,34 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
     /// Note: This MUST NOT be used in production as the secrecy of the secret
     /// key store is not guaranteed.
     pub fn of(csprng: R, secret_key_store: S) -> Self {
-        let node_public_keys = Default::default();
-        let public_key_data = PublicKeyData::new(node_public_keys);
-        let metrics = Arc::new(CryptoMetrics::none());
         Csp {
-            csprng: CspRwLock::new_for_rng(csprng, Arc::clone(&metrics)),
-            public_key_data,
-            secret_key_store: CspRwLock::new_for_sks(secret_key_store, metrics),
+            csp_server: Arc::new(
+                LocalCspServer::builder(secret_key_store)
+                    .with_rng(csprng)
+                    .build(),
+            ),
+            retired_threshold_key_ids: RwLock::new(RetiredKeyIds::default()),
             logger: no_op_logger(),
         }
     }
 }
 
+impl<R, S> Csp<R, S>
+where
+    R: Rng + CryptoRng + Send + Sync + 'static,
+    S: SecretKeyStore + Send + Sync + 'static,
+{
+    /// Returns a signer for basic and threshold signatures that runs the
+    /// signing on the blocking thread pool of tokio, so that callers can
+    /// await the signature without blocking a thread and abandon it, e.g.,
+    /// once the round it was needed for is over.
+    pub fn async_signer(
+        &self,
+    ) -> impl AsyncBasicSignatureCspServer + AsyncThresholdSignatureCspServer {
+        SpawnBlockingCspServer::new(Arc::clone(&self.csp_server))
+    }
+}
+
 // Trait implementations:
 pub mod keygen;
 mod signer;
@@ -3,24 +3,32 @@
 use crate::secret_key_store::{Scope, SecretKeyStore, SecretKeyStoreError};
 use crate::threshold::ni_dkg::{NIDKG_FS_SCOPE, NIDKG_THRESHOLD_SCOPE};
 use crate::types::CspSecretKey;
+use ic_crypto_internal_logmon::metrics::CryptoMetrics;
 use ic_crypto_internal_threshold_sig_bls12381::ni_dkg::groth20_bls12_381::types::convert_keyset_to_keyset_with_pop;
 use ic_crypto_internal_threshold_sig_bls12381::ni_dkg::types::CspFsEncryptionKeySet;
-use ic_logger::{info, replica_logger::no_op_logger, ReplicaLogger};
+use ic_crypto_sha::{DomainSeparationContext, Sha256};
+use ic_logger::{info, replica_logger::no_op_logger, warn, ReplicaLogger};
 use ic_types::crypto::KeyId;
 use parking_lot::RwLock;
 use prost::Message;
 use std::borrow::{Borrow, BorrowMut};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
+use std::fmt;
 use std::fs;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 
 const SKS_DATA_FILENAME: &str = "sks_data.pb";
+const SKS_QUARANTINE_FILENAME: &str = "sks_data_quarantine.pb";
+// Checksums were added as new fields without bumping the version, so that the
+// previous release, which ignores them, can still read the store.
 const CURRENT_SKS_VERSION: u32 = 2;
+const RECORD_CHECKSUM_DOMAIN: &str = "ic-crypto-sks-record-checksum";
+const STORE_CHECKSUM_DOMAIN: &str = "ic-crypto-sks-store-checksum";
 
 // TODO(CRP-523): turn this to FromStr-trait once KeyId is not public.
 const KEY_ID_PREFIX: &str = "KeyId(0x";
@@ -57,37 +65,121 @@ pub mod pb;
 
 type SecretKeys = HashMap<KeyId, (CspSecretKey, Option<Scope>)>;
 
+/// How corrupted records found when opening a `ProtoSecretKeyStore` are
+/// handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityCheckMode {
+    /// Fail, leaving the store on disk untouched for manual inspection.
+    Strict,
+    /// Move corrupted records out of the store into a quarantine file next to
+    /// it, and rewrite the store without them.
+    Quarantine,
+}
+
+/// Errors when the integrity check of a `ProtoSecretKeyStore` fails on opening.
+/// In both cases the store on disk is left untouched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecretKeyStoreIntegrityError {
+    /// The checksum of the store does not match its records, i.e., records
+    /// were lost or added. This cannot be repaired.
+    StoreChecksumMismatch { path: PathBuf },
+    /// The store contains records whose checksum does not match, and the
+    /// integrity check mode is `IntegrityCheckMode::Strict`.
+    CorruptedRecords { path: PathBuf, key_ids: Vec<String> },
+}
+
+impl std::error::Error for SecretKeyStoreIntegrityError {}
+
+impl fmt::Display for SecretKeyStoreIntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretKeyStoreIntegrityError::StoreChecksumMismatch { path } => write!(
+                f,
+                "Checksum of secret key store {} does not match its records",
+                path.display()
+            ),
+            SecretKeyStoreIntegrityError::CorruptedRecords { path, key_ids } => write!(
+                f,
+                "Secret key store {} is corrupted: the records for key IDs {:?} do not match their checksums",
+                path.display(),
+                key_ids
+            ),
+        }
+    }
+}
+
 /// A secret key store that persists data to the filesystem, using protobufs for
 /// serialization
+///
+/// Every record is stored with a checksum of its key ID, scope, and key, and
+/// the store as a whole with a checksum over all records, which detects
+/// records that were lost or added. Both are verified when the store is
+/// opened, so that bitrot is detected on startup rather than when a corrupted
+/// key is used. Stores written without checksums, e.g. by a previous release,
+/// are loaded without verification.
+///
+/// The store reports the number of records per algorithm and scope, its size
+/// on disk and the duration of its writes as metrics.
 pub struct ProtoSecretKeyStore {
     proto_file: PathBuf,
     keys: Arc<RwLock<SecretKeys>>,
     logger: ReplicaLogger,
+    metrics: Arc<CryptoMetrics>,
 }
 
 impl ProtoSecretKeyStore {
-    /// Creates a database instance.
+    /// Creates a database instance, quarantining corrupted records.
+    ///
+    /// # Panics
+    /// * if the integrity check fails, see `open_with_integrity_check`.
     pub fn open(dir: &Path, logger: Option<ReplicaLogger>) -> Self {
+        Self::open_with_integrity_check(
+            dir,
+            logger,
+            IntegrityCheckMode::Quarantine,
+            Arc::new(CryptoMetrics::none()),
+        )
+        .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a database instance, handling corrupted records according to
+    /// `mode`.
+    ///
+    /// Returns an error if the checksum of the store does not match, or if
+    /// there are corrupted records and `mode` is `IntegrityCheckMode::Strict`.
+    ///
+    /// # Panics
+    /// * if the store cannot be parsed at all.
+    pub fn open_with_integrity_check(
+        dir: &Path,
+        logger: Option<ReplicaLogger>,
+        mode: IntegrityCheckMode,
+        metrics: Arc<CryptoMetrics>,
+    ) -> Result<Self, SecretKeyStoreIntegrityError> {
         Self::check_path(dir);
+        let logger = logger.unwrap_or_else(no_op_logger);
         let proto_file = dir.join(SKS_DATA_FILENAME);
         let secret_keys = match Self::read_sks_data_from_disk(&proto_file) {
-            Some(sks_proto) => sks_proto,
+            Some(sks_proto) => {
+                Self::verify_and_load(sks_proto, &proto_file, mode, &logger, &metrics)?
+            }
             None => SecretKeys::new(),
         };
-        ProtoSecretKeyStore {
+        observe_record_counts(&secret_keys, &metrics);
+        metrics.observe_secret_key_store_size_bytes(
+            fs::metadata(&proto_file).map_or(0, |metadata| metadata.len()),
+        );
+        Ok(ProtoSecretKeyStore {
             proto_file,
             keys: Arc::new(RwLock::new(secret_keys)),
-            logger: logger.unwrap_or_else(no_op_logger),
-        }
+            logger,
+            metrics,
+        })
     }
 
-    fn read_sks_data_from_disk(sks_data_file: &Path) -> Option<SecretKeys> {
+    fn read_sks_data_from_disk(sks_data_file: &Path) -> Option<pb::SecretKeyStore> {
         match fs::read(sks_data_file) {
-            Ok(data) => {
-                let sks_pb = pb::SecretKeyStore::decode(&*data).expect("error parsing SKS data");
-                let keys = ProtoSecretKeyStore::migrate_to_current_version(sks_pb);
-                Some(keys)
-            }
+            Ok(data) => Some(pb::SecretKeyStore::decode(&*data).expect("error parsing SKS data")),
             Err(err) => {
                 if err.kind() == ErrorKind::NotFound {
                     None
@@ -98,6 +190,94 @@ impl ProtoSecretKeyStore {
         }
     }
 
+    /// Verifies the checksums of the store and of its records, and returns the
+    /// keys of all records that are intact. Stores without checksums are
+    /// loaded without verification.
+    fn verify_and_load(
+        sks_proto: pb::SecretKeyStore,
+        proto_file: &Path,
+        mode: IntegrityCheckMode,
+        logger: &ReplicaLogger,
+        metrics: &CryptoMetrics,
+    ) -> Result<SecretKeys, SecretKeyStoreIntegrityError> {
+        if sks_proto.version != CURRENT_SKS_VERSION || !has_checksums(&sks_proto) {
+            metrics.observe_secret_key_store_integrity_check("unchecked_without_checksums");
+            return Ok(ProtoSecretKeyStore::migrate_to_current_version(sks_proto));
+        }
+        if sks_proto.checksum != store_checksum(&sks_proto.key_id_to_secret_key_v1) {
+            metrics.observe_secret_key_store_integrity_check("store_checksum_mismatch");
+            return Err(SecretKeyStoreIntegrityError::StoreChecksumMismatch {
+                path: proto_file.to_path_buf(),
+            });
+        }
+        let mut secret_keys = SecretKeys::new();
+        let mut corrupted_records = BTreeMap::new();
+        for (key_id_hex, sk_proto) in sks_proto.key_id_to_secret_key_v1 {
+            match verified_record(&key_id_hex, &sk_proto) {
+                Ok((key_id, csp_key, maybe_scope)) => {
+                    secret_keys.insert(key_id, (csp_key, maybe_scope));
+                }
+                Err(reason) => {
+                    warn!(
+                        logger,
+                        "Corrupted record for key ID {} in secret key store {}: {}",
+                        key_id_hex,
+                        proto_file.display(),
+                        reason
+                    );
+                    corrupted_records.insert(key_id_hex, sk_proto);
+                }
+            }
+        }
+        if corrupted_records.is_empty() {
+            metrics.observe_secret_key_store_integrity_check("ok");
+            return Ok(secret_keys);
+        }
+
+        metrics.observe_secret_key_store_integrity_check("corrupted_records");
+        if mode == IntegrityCheckMode::Strict {
+            return Err(SecretKeyStoreIntegrityError::CorruptedRecords {
+                path: proto_file.to_path_buf(),
+                key_ids: corrupted_records
+                    .into_iter()
+                    .map(|(key_id_hex, _)| key_id_hex)
+                    .collect(),
+            });
+        }
+        let quarantine_file = proto_file.with_file_name(SKS_QUARANTINE_FILENAME);
+        warn!(
+            logger,
+            "Moving {} corrupted records from secret key store {} to {}",
+            corrupted_records.len(),
+            proto_file.display(),
+            quarantine_file.display()
+        );
+        metrics.observe_secret_key_store_quarantined_records(corrupted_records.len());
+        ProtoSecretKeyStore::quarantine_records(&quarantine_file, corrupted_records);
+        ProtoSecretKeyStore::write_secret_keys_to_disk(proto_file, &secret_keys, metrics);
+        Ok(secret_keys)
+    }
+
+    /// Adds `records` to the quarantine file, keeping records that were
+    /// quarantined earlier.
+    fn quarantine_records(quarantine_file: &Path, records: BTreeMap<String, pb::SecretKeyV1>) {
+        let mut quarantine = fs::read(quarantine_file)
+            .ok()
+            .and_then(|data| pb::SecretKeyStore::decode(&*data).ok())
+            .unwrap_or_default();
+        quarantine.version = CURRENT_SKS_VERSION;
+        quarantine.key_id_to_secret_key_v1.extend(records);
+        ic_utils::fs::write_protobuf_using_tmp_file(quarantine_file, &quarantine).unwrap_or_else(
+            |e| {
+                panic!(
+                    "Error writing quarantined SKS records to {}: {}",
+                    quarantine_file.display(),
+                    e
+                )
+            },
+        );
+    }
+
     // TODO(CRP-532): remove support for the legacy format in a few weeks after
     // merging.
     fn migrate_to_current_version(sks_proto: pb::SecretKeyStore) -> SecretKeys {
@@ -167,6 +347,7 @@ impl ProtoSecretKeyStore {
         }
     }
 
+    /// Converts a store of the current version, ignoring its checksums.
     fn sks_proto_to_secret_keys(sks_proto: &pb::SecretKeyStore) -> SecretKeys {
         if sks_proto.version != CURRENT_SKS_VERSION {
             panic!(
@@ -201,24 +382,51 @@ impl ProtoSecretKeyStore {
             let key_id_hex = key_id_to_hex(key_id);
             let key_as_cbor = serde_cbor::to_vec(&csp_key)
                 .unwrap_or_else(|_| panic!("Error serializing key with ID {}", key_id));
-            let sk_pb = match maybe_scope {
+            let mut sk_pb = match maybe_scope {
                 Some(scope) => pb::SecretKeyV1 {
                     csp_secret_key: key_as_cbor,
                     scope: String::from(scope),
+                    checksum: vec![],
                 },
                 None => pb::SecretKeyV1 {
                     csp_secret_key: key_as_cbor,
                     scope: String::from(""),
+                    checksum: vec![],
                 },
             };
+            sk_pb.checksum = record_checksum(&key_id_hex, &sk_pb);
             sks_proto.key_id_to_secret_key_v1.insert(key_id_hex, sk_pb);
         }
+        sks_proto.checksum = store_checksum(&sks_proto.key_id_to_secret_key_v1);
         sks_proto
     }
 
-    fn write_secret_keys_to_disk(sks_data_file: &Path, secret_keys: &SecretKeys) {
+    /// Writes the store to a temporary file, syncs it and moves it in place
+    /// (see `ic_utils::fs::write_using_tmp_file`), timing the sync separately.
+    fn write_secret_keys_to_disk(
+        sks_data_file: &Path,
+        secret_keys: &SecretKeys,
+        metrics: &CryptoMetrics,
+    ) {
+        let start_time = metrics.now();
         let sks_proto = ProtoSecretKeyStore::secret_keys_to_sks_proto(secret_keys);
-        ic_utils::fs::write_protobuf_using_tmp_file(sks_data_file, &sks_proto).unwrap();
+        let mut data = Vec::new();
+        sks_proto
+            .encode(&mut data)
+            .expect("error serializing SKS data");
+        let tmp_file = ic_utils::fs::get_tmp_for_path(sks_data_file);
+        {
+            let mut file = fs::File::create(&tmp_file).unwrap();
+            file.write_all(&data).unwrap();
+            let fsync_start_time = metrics.now();
+            file.sync_all().unwrap();
+            metrics.observe_secret_key_store_write_duration_seconds("fsync", fsync_start_time);
+        }
+        fs::rename(&tmp_file, sks_data_file).unwrap();
+        ic_utils::fs::sync_path(sks_data_file.parent().unwrap_or_else(|| Path::new("/"))).unwrap();
+        metrics.observe_secret_key_store_write_duration_seconds("write", start_time);
+        metrics.observe_secret_key_store_size_bytes(data.len() as u64);
+        observe_record_counts(secret_keys, metrics);
     }
 
     fn check_path(path: &Path) {
@@ -267,7 +475,11 @@ impl SecretKeyStore for ProtoSecretKeyStore {
             Some(_) => Err(SecretKeyStoreError::DuplicateKeyId(id)),
             None => {
                 keys.insert(id, (key, scope));
-                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.proto_file, keys);
+                ProtoSecretKeyStore::write_secret_keys_to_disk(
+                    &self.proto_file,
+                    keys,
+                    &self.metrics,
+                );
                 Ok(())
             }
         })
@@ -287,7 +499,11 @@ impl SecretKeyStore for ProtoSecretKeyStore {
         let result = with_write_lock(&self.keys, |keys| match keys.get(id) {
             Some(_) => {
                 keys.remove(id);
-                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.proto_file, keys);
+                ProtoSecretKeyStore::write_secret_keys_to_disk(
+                    &self.proto_file,
+                    keys,
+                    &self.metrics,
+                );
                 Ok(true)
             }
             None => Ok(false),
@@ -314,7 +530,11 @@ impl SecretKeyStore for ProtoSecretKeyStore {
                 }
             }
             if keys.len() < orig_keys_count {
-                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.proto_file, keys);
+                ProtoSecretKeyStore::write_secret_keys_to_disk(
+                    &self.proto_file,
+                    keys,
+                    &self.metrics,
+                );
             }
             Ok(())
         })
@@ -322,6 +542,86 @@ impl SecretKeyStore for ProtoSecretKeyStore {
     }
 }
 
+/// Observes the number of records in `secret_keys` per algorithm and scope.
+fn observe_record_counts(secret_keys: &SecretKeys, metrics: &CryptoMetrics) {
+    let mut counts = BTreeMap::new();
+    for (csp_key, maybe_scope) in secret_keys.values() {
+        let algorithm = format!("{:?}", csp_key.algorithm_id());
+        let scope = maybe_scope
+            .as_ref()
+            .map_or_else(|| "none".to_string(), String::from);
+        *counts.entry((algorithm, scope)).or_insert(0) += 1;
+    }
+    metrics.observe_secret_key_store_records(&counts);
+}
+
+/// Returns the key ID, key, and scope of a record, or why the record is
+/// corrupted.
+fn verified_record(
+    key_id_hex: &str,
+    sk_proto: &pb::SecretKeyV1,
+) -> Result<(KeyId, CspSecretKey, Option<Scope>), String> {
+    if sk_proto.checksum != record_checksum(key_id_hex, sk_proto) {
+        return Err("checksum does not match".to_string());
+    }
+    // Given a matching checksum, the following only fail if the record was
+    // written corrupted in the first place.
+    let key_id_bytes: [u8; 32] = hex::decode(key_id_hex)
+        .ok()
+        .and_then(|bytes| bytes[..].try_into().ok())
+        .ok_or_else(|| "malformed key ID".to_string())?;
+    let csp_key = serde_cbor::from_slice(&sk_proto.csp_secret_key)
+        .map_err(|e| format!("error deserializing key: {}", e))?;
+    let maybe_scope = if sk_proto.scope.is_empty() {
+        None
+    } else {
+        Some(
+            Scope::from_str(&sk_proto.scope)
+                .map_err(|_| format!("unknown scope: {}", sk_proto.scope))?,
+        )
+    };
+    Ok((KeyId::from(key_id_bytes), csp_key, maybe_scope))
+}
+
+/// Whether the store was written with checksums. Stores written by releases
+/// before checksums were added have neither a store checksum nor record
+/// checksums.
+fn has_checksums(sks_proto: &pb::SecretKeyStore) -> bool {
+    !sks_proto.checksum.is_empty()
+        || sks_proto
+            .key_id_to_secret_key_v1
+            .values()
+            .any(|sk_proto| !sk_proto.checksum.is_empty())
+}
+
+/// Computes the checksum of a record over its key ID, scope, and key.
+fn record_checksum(key_id_hex: &str, sk_proto: &pb::SecretKeyV1) -> Vec<u8> {
+    let mut hasher =
+        Sha256::new_with_context(&DomainSeparationContext::new(RECORD_CHECKSUM_DOMAIN));
+    for field in &[
+        key_id_hex.as_bytes(),
+        sk_proto.scope.as_bytes(),
+        &sk_proto.csp_secret_key[..],
+    ] {
+        hasher.write(&(field.len() as u64).to_be_bytes());
+        hasher.write(field);
+    }
+    hasher.finish().to_vec()
+}
+
+/// Computes the checksum of a store over the key IDs and checksums of all its
+/// records, in the order of their key IDs.
+fn store_checksum(records: &HashMap<String, pb::SecretKeyV1>) -> Vec<u8> {
+    let mut hasher = Sha256::new_with_context(&DomainSeparationContext::new(STORE_CHECKSUM_DOMAIN));
+    let sorted_records: BTreeMap<_, _> = records.iter().collect();
+    for (key_id_hex, sk_proto) in sorted_records {
+        hasher.write(&(key_id_hex.len() as u64).to_be_bytes());
+        hasher.write(key_id_hex.as_bytes());
+        hasher.write(&sk_proto.checksum);
+    }
+    hasher.finish().to_vec()
+}
+
 fn with_write_lock<T, I, R, F>(v: T, f: F) -> Result<R, SecretKeyStoreError>
 where
     T: AsRef<RwLock<I>>,
@@ -346,6 +646,7 @@ pub mod tests {
     use super::*;
     use crate::secret_key_store::test_utils::TempSecretKeyStore;
     use ic_crypto_internal_csp_test_utils::files::mk_temp_dir_with_permissions;
+    use ic_metrics::MetricsRegistry;
     use proptest::prelude::*;
     use tempfile::tempdir as tempdir_deleted_at_end_of_scope;
 
@@ -413,7 +714,244 @@ pub mod tests {
         test_utils::should_retain_expected_keys(proto_key_store());
     }
 
+    #[test]
+    fn should_reopen_intact_store_in_strict_mode() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id_1, key_id_2) = store_with_two_keys(dir.path());
+
+        let store = open_strict(dir.path());
+
+        assert!(store.contains(&key_id_1));
+        assert!(store.contains(&key_id_2));
+    }
+
+    #[test]
+    fn should_return_error_on_corrupted_record_in_strict_mode() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id_1, _) = store_with_two_keys(dir.path());
+        corrupt_key_of_record(dir.path(), &key_id_1);
+        let sks_file = dir.path().join(SKS_DATA_FILENAME);
+        let sks_data = fs::read(&sks_file).unwrap();
+
+        let result = open_with_mode(dir.path(), IntegrityCheckMode::Strict);
+
+        assert_eq!(
+            result.err(),
+            Some(SecretKeyStoreIntegrityError::CorruptedRecords {
+                path: sks_file.clone(),
+                key_ids: vec![key_id_to_hex(&key_id_1)],
+            })
+        );
+        assert_eq!(fs::read(&sks_file).unwrap(), sks_data);
+    }
+
+    #[test]
+    fn should_quarantine_corrupted_record() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id_1, key_id_2) = store_with_two_keys(dir.path());
+        corrupt_key_of_record(dir.path(), &key_id_1);
+
+        let store = ProtoSecretKeyStore::open(dir.path(), None);
+
+        assert!(!store.contains(&key_id_1));
+        assert!(store.contains(&key_id_2));
+        let quarantine = read_sks_proto(&dir.path().join(SKS_QUARANTINE_FILENAME));
+        assert_eq!(
+            quarantine
+                .key_id_to_secret_key_v1
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&key_id_to_hex(&key_id_1)]
+        );
+        // The repaired store is intact.
+        let store = open_strict(dir.path());
+        assert!(!store.contains(&key_id_1));
+        assert!(store.contains(&key_id_2));
+    }
+
+    #[test]
+    fn should_return_error_on_store_with_lost_record_in_any_mode() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id_1, _) = store_with_two_keys(dir.path());
+        let sks_file = dir.path().join(SKS_DATA_FILENAME);
+        let mut sks_proto = read_sks_proto(&sks_file);
+        sks_proto
+            .key_id_to_secret_key_v1
+            .remove(&key_id_to_hex(&key_id_1));
+        ic_utils::fs::write_protobuf_using_tmp_file(&sks_file, &sks_proto).unwrap();
+        let sks_data = fs::read(&sks_file).unwrap();
+
+        for mode in &[IntegrityCheckMode::Strict, IntegrityCheckMode::Quarantine] {
+            let result = open_with_mode(dir.path(), *mode);
+
+            assert_eq!(
+                result.err(),
+                Some(SecretKeyStoreIntegrityError::StoreChecksumMismatch {
+                    path: sks_file.clone(),
+                })
+            );
+            assert_eq!(fs::read(&sks_file).unwrap(), sks_data);
+        }
+    }
+
+    #[test]
+    fn should_write_store_readable_by_release_without_checksums() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id_1, key_id_2) = store_with_two_keys(dir.path());
+
+        // The previous release only reads stores of version 2 and ignores the
+        // checksum fields, which it does not know.
+        let sks_proto = read_sks_proto(&dir.path().join(SKS_DATA_FILENAME));
+        assert_eq!(sks_proto.version, 2);
+        let secret_keys = ProtoSecretKeyStore::sks_proto_to_secret_keys(&sks_proto);
+
+        assert!(secret_keys.contains_key(&key_id_1));
+        assert!(secret_keys.contains_key(&key_id_2));
+    }
+
+    #[test]
+    fn should_load_store_written_without_checksums() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id_1, key_id_2) = store_with_two_keys(dir.path());
+        let sks_file = dir.path().join(SKS_DATA_FILENAME);
+        let mut sks_proto = read_sks_proto(&sks_file);
+        sks_proto.checksum = vec![];
+        for sk_proto in sks_proto.key_id_to_secret_key_v1.values_mut() {
+            sk_proto.checksum = vec![];
+        }
+        ic_utils::fs::write_protobuf_using_tmp_file(&sks_file, &sks_proto).unwrap();
+
+        let store = open_strict(dir.path());
+
+        assert!(store.contains(&key_id_1));
+        assert!(store.contains(&key_id_2));
+    }
+
+    #[test]
+    fn should_observe_records_and_size() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let metrics_registry = MetricsRegistry::new();
+        let mut store = ProtoSecretKeyStore::open_with_integrity_check(
+            dir.path(),
+            None,
+            IntegrityCheckMode::Strict,
+            Arc::new(CryptoMetrics::new(Some(&metrics_registry))),
+        )
+        .unwrap();
+        store
+            .insert(
+                test_utils::make_key_id(1),
+                test_utils::make_secret_key(1),
+                None,
+            )
+            .unwrap();
+        store
+            .insert(
+                test_utils::make_key_id(2),
+                test_utils::make_secret_key(2),
+                None,
+            )
+            .unwrap();
+        store
+            .insert(
+                test_utils::make_key_id(3),
+                test_utils::make_secret_key(3),
+                Some(NIDKG_THRESHOLD_SCOPE),
+            )
+            .unwrap();
+        assert!(store.remove(&test_utils::make_key_id(2)));
+
+        let families = metrics_registry.prometheus_registry().gather();
+        let family = |name: &str| {
+            families
+                .iter()
+                .find(|family| family.get_name() == name)
+                .unwrap_or_else(|| panic!("metric {} not found", name))
+        };
+        let mut records: Vec<_> = family("ic_crypto_secret_key_store_records")
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let labels: Vec<_> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| label.get_value().to_string())
+                    .collect();
+                (labels, metric.get_gauge().get_value() as u64)
+            })
+            .collect();
+        records.sort();
+        assert_eq!(
+            records,
+            vec![
+                (vec!["Ed25519".to_string(), "none".to_string()], 1),
+                (
+                    vec!["Ed25519".to_string(), String::from(&NIDKG_THRESHOLD_SCOPE)],
+                    1
+                ),
+            ]
+        );
+        let size = family("ic_crypto_secret_key_store_size_bytes").get_metric()[0]
+            .get_gauge()
+            .get_value() as u64;
+        assert_eq!(
+            size,
+            fs::metadata(dir.path().join(SKS_DATA_FILENAME))
+                .unwrap()
+                .len()
+        );
+        let writes = family("ic_crypto_secret_key_store_write_duration_seconds").get_metric();
+        assert_eq!(writes.len(), 2);
+        for stage in writes {
+            assert_eq!(stage.get_histogram().get_sample_count(), 4);
+        }
+    }
+
     fn proto_key_store() -> TempSecretKeyStore {
         TempSecretKeyStore::new()
     }
+
+    fn store_with_two_keys(dir: &Path) -> (KeyId, KeyId) {
+        let mut store = ProtoSecretKeyStore::open(dir, None);
+        let (key_id_1, key_id_2) = (test_utils::make_key_id(1), test_utils::make_key_id(2));
+        store
+            .insert(key_id_1, test_utils::make_secret_key(1), None)
+            .unwrap();
+        store
+            .insert(key_id_2, test_utils::make_secret_key(2), None)
+            .unwrap();
+        (key_id_1, key_id_2)
+    }
+
+    fn open_strict(dir: &Path) -> ProtoSecretKeyStore {
+        open_with_mode(dir, IntegrityCheckMode::Strict).unwrap()
+    }
+
+    fn open_with_mode(
+        dir: &Path,
+        mode: IntegrityCheckMode,
+    ) -> Result<ProtoSecretKeyStore, SecretKeyStoreIntegrityError> {
+        ProtoSecretKeyStore::open_with_integrity_check(
+            dir,
+            None,
+            mode,
+            Arc::new(CryptoMetrics::none()),
+        )
+    }
+
+    fn read_sks_proto(file: &Path) -> pb::SecretKeyStore {
+        pb::SecretKeyStore::decode(&*fs::read(file).unwrap()).unwrap()
+    }
+
+    fn corrupt_key_of_record(dir: &Path, key_id: &KeyId) {
+        let sks_file = dir.join(SKS_DATA_FILENAME);
+        let mut sks_proto = read_sks_proto(&sks_file);
+        let sk_proto = sks_proto
+            .key_id_to_secret_key_v1
+            .get_mut(&key_id_to_hex(key_id))
+            .unwrap();
+        let last = sk_proto.csp_secret_key.len() - 1;
+        sk_proto.csp_secret_key[last] ^= 1;
+        ic_utils::fs::write_protobuf_using_tmp_file(&sks_file, &sks_proto).unwrap();
+    }
 }
@@ -1,7 +1,10 @@
 use crate::api::CspThresholdSignError;
 #[cfg(test)]
 use crate::types::CspPublicCoefficients;
-use crate::types::{CspPop, CspPublicKey, CspSignature};
+use crate::types::{
+    CspPop, CspPublicKey, CspSignature, IDkgDealingCommitment, MEGaCiphertext, MEGaPublicKey,
+};
+use async_trait::async_trait;
 use ic_crypto_internal_threshold_sig_bls12381::api::ni_dkg_errors;
 use ic_crypto_internal_types::encrypt::forward_secure::groth20_bls12_381::FsEncryptionPublicKey;
 use ic_crypto_internal_types::encrypt::forward_secure::{
@@ -14,6 +17,8 @@ use ic_types::crypto::{AlgorithmId, KeyId};
 use ic_types::{NodeId, NodeIndex, NumberOfNodes};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CspBasicSignatureError {
@@ -64,6 +69,40 @@ pub enum CspThresholdSignatureKeygenError {
     InvalidArgument { message: String },
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CspCreateMEGaKeyError {
+    UnsupportedAlgorithm { algorithm: AlgorithmId },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CspMEGaDecryptionError {
+    SecretKeyNotFound {
+        algorithm: AlgorithmId,
+        key_id: KeyId,
+    },
+    WrongSecretKeyType {
+        algorithm: AlgorithmId,
+    },
+    MalformedSecretKey {
+        algorithm: AlgorithmId,
+    },
+    MalformedCiphertext {
+        algorithm: AlgorithmId,
+        internal_error: String,
+    },
+    InvalidRecipientIndex {
+        recipient_index: NodeIndex,
+        number_of_ciphertexts: usize,
+    },
+    MalformedCommitment {
+        algorithm: AlgorithmId,
+        internal_error: String,
+    },
+    InvalidShare {
+        recipient_index: NodeIndex,
+    },
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct CspNiDkgTranscriptForNode {
     node_id: NodeId,
@@ -77,7 +116,9 @@ pub trait CspServer:
     + MultiSignatureCspServer
     + ThresholdSignatureCspServer
     + NiDkgCspServer
+    + IDkgProtocolCspServer
     + SecretKeyStoreCspServer
+    + VersionCspServer
 {
 }
 
@@ -120,6 +161,41 @@ pub trait BasicSignatureCspServer {
     ) -> Result<(KeyId, CspPublicKey), CspSignatureKeygenError>;
 }
 
+/// Errors of signing operations that can be cancelled, see
+/// `AsyncBasicSignatureCspServer` and `AsyncThresholdSignatureCspServer`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CspCancellableSignError<E> {
+    /// The signing was abandoned because its cancellation token was
+    /// cancelled before the signature was available.
+    Cancelled,
+    /// The signing itself failed.
+    Sign(E),
+}
+
+/// Asynchronous variant of the signing operation of
+/// `BasicSignatureCspServer`.
+///
+/// A caller waiting for a signature from a server that runs out of process
+/// does not block a thread, and can abandon the signing, e.g., because the
+/// round the signature was needed for is over.
+#[async_trait]
+pub trait AsyncBasicSignatureCspServer {
+    /// Signs `message` like `BasicSignatureCspServer::sign`.
+    ///
+    /// # Errors
+    /// * `CspCancellableSignError::Cancelled` if `cancellation` is cancelled
+    ///   before the signature is available. The server may still complete
+    ///   the signing, but its result is discarded.
+    /// * `CspCancellableSignError::Sign` if the signing failed.
+    async fn sign_async(
+        &self,
+        algorithm_id: AlgorithmId,
+        message: Vec<u8>,
+        key_id: KeyId,
+        cancellation: CancellationToken,
+    ) -> Result<CspSignature, CspCancellableSignError<CspBasicSignatureError>>;
+}
+
 /// Operations of `CspServer` related to multi-signatures
 /// (cf. `CspSigner` and `CspKeyGenerator`).
 pub trait MultiSignatureCspServer {
@@ -208,6 +284,25 @@ pub trait ThresholdSignatureCspServer {
     ) -> Result<CspSignature, CspThresholdSignError>;
 }
 
+/// Asynchronous variant of the signing operation of
+/// `ThresholdSignatureCspServer`, see `AsyncBasicSignatureCspServer`.
+#[async_trait]
+pub trait AsyncThresholdSignatureCspServer {
+    /// Signs `message` like `ThresholdSignatureCspServer::threshold_sign`.
+    ///
+    /// # Errors
+    /// * `CspCancellableSignError::Cancelled` if `cancellation` is cancelled
+    ///   before the signature is available.
+    /// * `CspCancellableSignError::Sign` if the signing failed.
+    async fn threshold_sign_async(
+        &self,
+        algorithm_id: AlgorithmId,
+        message: Vec<u8>,
+        key_id: KeyId,
+        cancellation: CancellationToken,
+    ) -> Result<CspSignature, CspCancellableSignError<CspThresholdSignError>>;
+}
+
 /// Operations of `CspServer` related to NI-DKG (cf. `NiDkgCspClient`).
 pub trait NiDkgCspServer {
     /// Generates a forward-secure key pair used to encrypt threshold key shares
@@ -300,6 +395,62 @@ pub trait NiDkgCspServer {
     fn retain_threshold_keys_if_present(&mut self, active_key_ids: BTreeSet<KeyId>);
 }
 
+/// Operations of `CspServer` related to the interactive DKG protocol used for
+/// threshold ECDSA.
+///
+/// Shares dealt to a node are encrypted with the node's MEGa
+/// (multi-encryption gadget) key. Both the MEGa secret key and the decrypted
+/// shares remain in the secret key store.
+pub trait IDkgProtocolCspServer {
+    /// Generates a MEGa key pair used to encrypt shares of dealings in
+    /// transmission, and stores the secret key.
+    ///
+    /// # Arguments
+    /// * `algorithm_id` specifies the MEGa encryption algorithm
+    /// # Returns
+    /// The key ID and the public key of the key pair.
+    fn idkg_gen_mega_key_pair(
+        &self,
+        algorithm_id: AlgorithmId,
+    ) -> Result<(KeyId, MEGaPublicKey), CspCreateMEGaKeyError>;
+
+    /// Decrypts the share encrypted for the recipient with the given index,
+    /// checks it against the commitment of the dealing, and stores it in the
+    /// secret key store.
+    ///
+    /// # Arguments
+    /// * `algorithm_id` specifies the MEGa encryption algorithm
+    /// * `mega_key_id` identifies the MEGa secret key of the recipient
+    /// * `ciphertext` contains the encrypted shares of all recipients
+    /// * `commitment` is the commitment of the dealing to the shares
+    /// * `associated_data` is the data the ciphertext is bound to, e.g. the
+    ///   identifier of the transcript and the dealer
+    /// * `recipient_index` is the index of this node among the recipients
+    /// # Returns
+    /// The key ID under which the decrypted share is stored.
+    /// # Errors
+    /// * `CspMEGaDecryptionError::SecretKeyNotFound` if there is no MEGa
+    ///   secret key with the given key ID.
+    /// * `CspMEGaDecryptionError::MalformedCiphertext` if the ciphertext
+    ///   cannot be parsed.
+    /// * `CspMEGaDecryptionError::InvalidRecipientIndex` if the ciphertext
+    ///   contains no share for `recipient_index`.
+    /// * `CspMEGaDecryptionError::MalformedCommitment` if the commitment
+    ///   cannot be parsed.
+    /// * `CspMEGaDecryptionError::InvalidShare` if the decrypted share does
+    ///   not match the commitment, e.g., because the ciphertext was not
+    ///   created with `associated_data`. Nothing is stored in this case.
+    fn idkg_decrypt_mega_ciphertext(
+        &self,
+        algorithm_id: AlgorithmId,
+        mega_key_id: KeyId,
+        ciphertext: &MEGaCiphertext,
+        commitment: &IDkgDealingCommitment,
+        associated_data: &[u8],
+        recipient_index: NodeIndex,
+    ) -> Result<KeyId, CspMEGaDecryptionError>;
+}
+
 /// Operations of `CspServer` related querying SKS (cf.
 /// `CspSecretKeyStoreChecker`).
 pub trait SecretKeyStoreCspServer {
@@ -308,3 +459,63 @@ pub trait SecretKeyStoreCspServer {
     /// * `key_id` identifies the key whose presence should be checked.
     fn sks_contains(&self, key_id: &KeyId) -> bool;
 }
+
+/// A group of operations offered by a `CspServer`, corresponding to one of
+/// the `*CspServer` traits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum CspServerOperation {
+    BasicSignature,
+    MultiSignature,
+    ThresholdSignature,
+    NiDkg,
+    IDkgProtocol,
+    SecretKeyStore,
+}
+
+/// Build version and supported operations of a `CspServer`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CspServerVersion {
+    /// The version of the crate the server was built from.
+    pub version: String,
+    pub supported_operations: BTreeSet<CspServerOperation>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CspServerVersionError {
+    /// The server was built from a different version than the client.
+    VersionMismatch {
+        client_version: String,
+        server_version: String,
+    },
+    /// The server does not support all operations required by the client.
+    UnsupportedOperations {
+        operations: BTreeSet<CspServerOperation>,
+    },
+}
+
+impl fmt::Display for CspServerVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CspServerVersionError::VersionMismatch {
+                client_version,
+                server_version,
+            } => write!(
+                f,
+                "CSP server version {} is incompatible with client version {}",
+                server_version, client_version
+            ),
+            CspServerVersionError::UnsupportedOperations { operations } => write!(
+                f,
+                "CSP server does not support the required operations {:?}",
+                operations
+            ),
+        }
+    }
+}
+
+/// Operations of `CspServer` related to querying its build version and
+/// capabilities, e.g. to check that client and server are compatible.
+pub trait VersionCspServer {
+    /// Returns the build version and the supported operations of the server.
+    fn version(&self) -> CspServerVersion;
+}
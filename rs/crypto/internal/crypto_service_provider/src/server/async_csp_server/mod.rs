@@ -0,0 +1,93 @@
+//! Asynchronous signing with cancellation on top of a synchronous CSP server.
+//!
+//! The signing operations of an in-process server block the calling thread
+//! until the signature is computed. [`SpawnBlockingCspServer`] runs them on
+//! the blocking thread pool of tokio instead, so that they can be awaited and
+//! abandoned like the operations of a server that runs out of process.
+use crate::api::CspThresholdSignError;
+use crate::server::api::{
+    AsyncBasicSignatureCspServer, AsyncThresholdSignatureCspServer, BasicSignatureCspServer,
+    CspBasicSignatureError, CspCancellableSignError, ThresholdSignatureCspServer,
+};
+use crate::types::CspSignature;
+use async_trait::async_trait;
+use ic_types::crypto::{AlgorithmId, KeyId};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+#[cfg(test)]
+mod tests;
+
+/// Implements the asynchronous signing traits for a synchronous CSP server,
+/// see the module documentation.
+pub struct SpawnBlockingCspServer<T> {
+    server: Arc<T>,
+}
+
+impl<T> SpawnBlockingCspServer<T> {
+    pub fn new(server: Arc<T>) -> Self {
+        Self { server }
+    }
+}
+
+#[async_trait]
+impl<T: BasicSignatureCspServer + Send + Sync + 'static> AsyncBasicSignatureCspServer
+    for SpawnBlockingCspServer<T>
+{
+    async fn sign_async(
+        &self,
+        algorithm_id: AlgorithmId,
+        message: Vec<u8>,
+        key_id: KeyId,
+        cancellation: CancellationToken,
+    ) -> Result<CspSignature, CspCancellableSignError<CspBasicSignatureError>> {
+        let server = Arc::clone(&self.server);
+        run_cancellable(cancellation, move || {
+            server.sign(algorithm_id, &message, key_id)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<T: ThresholdSignatureCspServer + Send + Sync + 'static> AsyncThresholdSignatureCspServer
+    for SpawnBlockingCspServer<T>
+{
+    async fn threshold_sign_async(
+        &self,
+        algorithm_id: AlgorithmId,
+        message: Vec<u8>,
+        key_id: KeyId,
+        cancellation: CancellationToken,
+    ) -> Result<CspSignature, CspCancellableSignError<CspThresholdSignError>> {
+        let server = Arc::clone(&self.server);
+        run_cancellable(cancellation, move || {
+            server.threshold_sign(algorithm_id, &message, key_id)
+        })
+        .await
+    }
+}
+
+/// Runs `sign` on the blocking thread pool unless, or until, `cancellation`
+/// is cancelled.
+///
+/// A signing that already started cannot be interrupted: it runs to
+/// completion in the background and its result is dropped.
+async fn run_cancellable<E: Send + 'static>(
+    cancellation: CancellationToken,
+    sign: impl FnOnce() -> Result<CspSignature, E> + Send + 'static,
+) -> Result<CspSignature, CspCancellableSignError<E>> {
+    if cancellation.is_cancelled() {
+        return Err(CspCancellableSignError::Cancelled);
+    }
+    tokio::select! {
+        result = tokio::task::spawn_blocking(sign) => match result {
+            Ok(result) => result.map_err(CspCancellableSignError::Sign),
+            Err(join_error) if join_error.is_panic() => {
+                std::panic::resume_unwind(join_error.into_panic())
+            }
+            Err(join_error) => panic!("Signing task failed: {}", join_error),
+        },
+        _ = cancellation.cancelled() => Err(CspCancellableSignError::Cancelled),
+    }
+}
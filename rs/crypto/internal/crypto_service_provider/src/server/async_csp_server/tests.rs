@@ -0,0 +1,169 @@
+#![allow(clippy::unwrap_used)]
+//! Tests of asynchronous signing on top of a synchronous CSP server.
+use super::*;
+use crate::secret_key_store::test_utils::TempSecretKeyStore;
+use crate::server::api::CspSignatureKeygenError;
+use crate::server::local_csp_server::LocalCspServer;
+use crate::types::CspPublicKey;
+use ic_crypto_internal_basic_sig_ed25519::types as ed25519_types;
+use ic_types::NumberOfNodes;
+use parking_lot::Mutex;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+fn local_csp_server() -> LocalCspServer<ChaChaRng, TempSecretKeyStore> {
+    let key_store = TempSecretKeyStore::new();
+    let csprng = ChaChaRng::from_seed(thread_rng().gen::<[u8; 32]>());
+    LocalCspServer::new_for_test(csprng, key_store)
+}
+
+#[tokio::test]
+async fn should_sign_like_the_synchronous_server() {
+    let csp_server = Arc::new(local_csp_server());
+    let (key_id, _csp_pub_key) = csp_server.gen_key_pair(AlgorithmId::Ed25519).unwrap();
+    let message = b"message".to_vec();
+    let async_server = SpawnBlockingCspServer::new(Arc::clone(&csp_server));
+
+    let signature = async_server
+        .sign_async(
+            AlgorithmId::Ed25519,
+            message.clone(),
+            key_id,
+            CancellationToken::new(),
+        )
+        .await;
+
+    assert_eq!(
+        signature,
+        Ok(csp_server
+            .sign(AlgorithmId::Ed25519, &message, key_id)
+            .unwrap())
+    );
+}
+
+#[tokio::test]
+async fn should_threshold_sign_like_the_synchronous_server() {
+    let mut csp_server = local_csp_server();
+    let (_public_coefficients, key_ids) = csp_server
+        .threshold_keygen_for_test(AlgorithmId::ThresBls12_381, NumberOfNodes::from(1), &[true])
+        .unwrap();
+    let key_id = key_ids[0].unwrap();
+    let csp_server = Arc::new(csp_server);
+    let message = b"message".to_vec();
+    let async_server = SpawnBlockingCspServer::new(Arc::clone(&csp_server));
+
+    let signature = async_server
+        .threshold_sign_async(
+            AlgorithmId::ThresBls12_381,
+            message.clone(),
+            key_id,
+            CancellationToken::new(),
+        )
+        .await;
+
+    assert_eq!(
+        signature,
+        Ok(csp_server
+            .threshold_sign(AlgorithmId::ThresBls12_381, &message, key_id)
+            .unwrap())
+    );
+}
+
+#[tokio::test]
+async fn should_return_sign_error() {
+    let async_server = SpawnBlockingCspServer::new(Arc::new(local_csp_server()));
+    let key_id = KeyId::from([7; 32]);
+
+    let result = async_server
+        .sign_async(
+            AlgorithmId::Ed25519,
+            b"message".to_vec(),
+            key_id,
+            CancellationToken::new(),
+        )
+        .await;
+
+    assert_eq!(
+        result,
+        Err(CspCancellableSignError::Sign(
+            CspBasicSignatureError::SecretKeyNotFound {
+                algorithm: AlgorithmId::Ed25519,
+                key_id
+            }
+        ))
+    );
+}
+
+#[tokio::test]
+async fn should_not_sign_if_already_cancelled() {
+    let csp_server = Arc::new(local_csp_server());
+    let (key_id, _csp_pub_key) = csp_server.gen_key_pair(AlgorithmId::Ed25519).unwrap();
+    let async_server = SpawnBlockingCspServer::new(csp_server);
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+
+    let result = async_server
+        .sign_async(
+            AlgorithmId::Ed25519,
+            b"message".to_vec(),
+            key_id,
+            cancellation,
+        )
+        .await;
+
+    assert_eq!(result, Err(CspCancellableSignError::Cancelled));
+}
+
+/// A server whose signing blocks as long as `gate` is locked.
+struct GatedCspServer {
+    gate: Mutex<()>,
+}
+
+impl BasicSignatureCspServer for GatedCspServer {
+    fn sign(
+        &self,
+        _algorithm_id: AlgorithmId,
+        _message: &[u8],
+        _key_id: KeyId,
+    ) -> Result<CspSignature, CspBasicSignatureError> {
+        let _gate = self.gate.lock();
+        Ok(CspSignature::Ed25519(ed25519_types::SignatureBytes(
+            [0; ed25519_types::SignatureBytes::SIZE],
+        )))
+    }
+
+    fn gen_key_pair(
+        &self,
+        algorithm_id: AlgorithmId,
+    ) -> Result<(KeyId, CspPublicKey), CspSignatureKeygenError> {
+        Err(CspSignatureKeygenError::UnsupportedAlgorithm {
+            algorithm: algorithm_id,
+        })
+    }
+}
+
+#[tokio::test]
+async fn should_abandon_blocked_signing_when_cancelled() {
+    let csp_server = Arc::new(GatedCspServer {
+        gate: Mutex::new(()),
+    });
+    let gate = csp_server.gate.lock();
+    let async_server = SpawnBlockingCspServer::new(Arc::clone(&csp_server));
+    let cancellation = CancellationToken::new();
+
+    let signing = async_server.sign_async(
+        AlgorithmId::Ed25519,
+        b"message".to_vec(),
+        KeyId::from([0; 32]),
+        cancellation.clone(),
+    );
+    let cancel = async {
+        tokio::task::yield_now().await;
+        cancellation.cancel();
+    };
+    let (result, ()) = tokio::join!(signing, cancel);
+
+    assert_eq!(result, Err(CspCancellableSignError::Cancelled));
+    // Lets the abandoned signing complete, so that the runtime can shut down.
+    drop(gate);
+}
@@ -0,0 +1,99 @@
+//! Builder for [`LocalCspServer`].
+use super::rng_health::HealthCheckedRng;
+use super::LocalCspServer;
+use crate::public_key_store::read_node_public_keys;
+use crate::secret_key_store::SecretKeyStore;
+use crate::{CspRwLock, PublicKeyData};
+use ic_crypto_internal_logmon::metrics::CryptoMetrics;
+use ic_logger::replica_logger::no_op_logger;
+use ic_logger::ReplicaLogger;
+use ic_protobuf::crypto::v1::NodePublicKeys;
+use rand::rngs::OsRng;
+use rand::{CryptoRng, Rng};
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(test)]
+mod tests;
+
+/// Builds a [`LocalCspServer`] from independently chosen components.
+///
+/// The secret key store must always be given, so that a server can never
+/// silently lose its keys. All other components have a default, so that only
+/// the components that differ from it need to be set:
+/// * the CSPRNG is `OsRng`,
+/// * the node public keys are empty,
+/// * metrics are disabled, and
+/// * log messages are discarded.
+pub struct LocalCspServerBuilder<R: Rng + CryptoRng, S: SecretKeyStore> {
+    csprng: R,
+    secret_key_store: S,
+    node_public_keys: NodePublicKeys,
+    metrics: Arc<CryptoMetrics>,
+    logger: ReplicaLogger,
+}
+
+impl<S: SecretKeyStore> LocalCspServerBuilder<OsRng, S> {
+    pub fn new(secret_key_store: S) -> Self {
+        Self {
+            csprng: OsRng::default(),
+            secret_key_store,
+            node_public_keys: NodePublicKeys::default(),
+            metrics: Arc::new(CryptoMetrics::none()),
+            logger: no_op_logger(),
+        }
+    }
+}
+
+impl<R: Rng + CryptoRng, S: SecretKeyStore> LocalCspServerBuilder<R, S> {
+    /// Sets the CSPRNG.
+    ///
+    /// Note: Anything other than `OsRng` MUST NOT be used in production, as
+    /// the secrecy of the random number generator, hence the keys, is not
+    /// guaranteed.
+    pub fn with_rng<R2: Rng + CryptoRng>(self, csprng: R2) -> LocalCspServerBuilder<R2, S> {
+        LocalCspServerBuilder {
+            csprng,
+            secret_key_store: self.secret_key_store,
+            node_public_keys: self.node_public_keys,
+            metrics: self.metrics,
+            logger: self.logger,
+        }
+    }
+
+    /// Sets the node public keys.
+    pub fn with_node_public_keys(mut self, node_public_keys: NodePublicKeys) -> Self {
+        self.node_public_keys = node_public_keys;
+        self
+    }
+
+    /// Sets the node public keys to the ones in the public key store in
+    /// `crypto_root`. If they cannot be read, e.g. because the node did not
+    /// generate its keys yet, the node public keys are empty.
+    pub fn with_public_key_store(self, crypto_root: &Path) -> Self {
+        let node_public_keys = read_node_public_keys(crypto_root).unwrap_or_default();
+        self.with_node_public_keys(node_public_keys)
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<CryptoMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_logger(mut self, logger: ReplicaLogger) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    pub fn build(self) -> LocalCspServer<R, S> {
+        LocalCspServer {
+            csprng: CspRwLock::new_for_rng(
+                HealthCheckedRng::new(self.csprng, Arc::clone(&self.metrics)),
+                Arc::clone(&self.metrics),
+            ),
+            secret_key_store: CspRwLock::new_for_sks(self.secret_key_store, self.metrics),
+            public_key_data: PublicKeyData::new(self.node_public_keys),
+            logger: self.logger,
+        }
+    }
+}
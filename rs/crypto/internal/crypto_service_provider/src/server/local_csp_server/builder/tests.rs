@@ -0,0 +1,103 @@
+//! Tests of the local CSP server builder.
+use super::*;
+use crate::public_key_store::store_node_public_keys;
+use crate::secret_key_store::test_utils::TempSecretKeyStore;
+use crate::server::api::BasicSignatureCspServer;
+use ic_protobuf::registry::crypto::v1::PublicKey;
+use ic_types::crypto::AlgorithmId;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+#[test]
+fn should_build_with_defaults() {
+    let csp_server = LocalCspServer::builder(TempSecretKeyStore::new()).build();
+
+    assert_eq!(
+        csp_server.public_key_data.node_public_keys,
+        NodePublicKeys::default()
+    );
+    assert!(csp_server.gen_key_pair(AlgorithmId::Ed25519).is_ok());
+}
+
+#[test]
+fn should_store_generated_keys_in_given_secret_key_store() {
+    let csp_server = LocalCspServer::builder(TempSecretKeyStore::new())
+        .with_rng(ChaChaRng::seed_from_u64(42))
+        .build();
+
+    let (key_id, _public_key) = csp_server.gen_key_pair(AlgorithmId::Ed25519).unwrap();
+
+    assert!(csp_server.sks_read_lock().contains(&key_id));
+}
+
+#[test]
+fn should_use_injected_rng() {
+    let gen_key_pair_with_seed = |seed| {
+        LocalCspServer::builder(TempSecretKeyStore::new())
+            .with_rng(ChaChaRng::seed_from_u64(seed))
+            .build()
+            .gen_key_pair(AlgorithmId::Ed25519)
+            .unwrap()
+    };
+
+    assert_eq!(gen_key_pair_with_seed(42), gen_key_pair_with_seed(42));
+    assert_ne!(gen_key_pair_with_seed(42), gen_key_pair_with_seed(43));
+}
+
+#[test]
+fn should_use_injected_node_public_keys() {
+    let node_public_keys = node_public_keys();
+
+    let csp_server = LocalCspServer::builder(TempSecretKeyStore::new())
+        .with_node_public_keys(node_public_keys.clone())
+        .build();
+
+    assert_eq!(
+        csp_server.public_key_data.node_public_keys,
+        node_public_keys
+    );
+}
+
+#[test]
+fn should_read_node_public_keys_from_public_key_store() {
+    let crypto_root = tempfile::tempdir().unwrap();
+    store_node_public_keys(crypto_root.path(), &node_public_keys()).unwrap();
+
+    let csp_server = LocalCspServer::builder(TempSecretKeyStore::new())
+        .with_public_key_store(crypto_root.path())
+        .build();
+
+    assert_eq!(
+        csp_server.public_key_data.node_public_keys,
+        node_public_keys()
+    );
+}
+
+#[test]
+fn should_have_empty_node_public_keys_if_public_key_store_is_missing() {
+    let crypto_root = tempfile::tempdir().unwrap();
+
+    let csp_server = LocalCspServer::builder(TempSecretKeyStore::new())
+        .with_public_key_store(crypto_root.path())
+        .build();
+
+    assert_eq!(
+        csp_server.public_key_data.node_public_keys,
+        NodePublicKeys::default()
+    );
+}
+
+fn node_public_keys() -> NodePublicKeys {
+    NodePublicKeys {
+        version: 0,
+        node_signing_pk: None,
+        committee_signing_pk: Some(PublicKey {
+            version: 0,
+            algorithm: AlgorithmId::MultiBls12_381 as i32,
+            key_value: vec![1; 96],
+            proof_data: Some(vec![2; 48]),
+        }),
+        tls_certificate: None,
+        dkg_dealing_encryption_pk: None,
+    }
+}
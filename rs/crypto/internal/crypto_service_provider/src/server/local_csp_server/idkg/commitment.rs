@@ -0,0 +1,92 @@
+//! Checks of decrypted shares against the Feldman commitment of a dealing.
+use crate::server::api::CspMEGaDecryptionError;
+use crate::types::IDkgDealingCommitment;
+#[cfg(test)]
+use ic_crypto_internal_threshold_sig_bls12381::dkg::secp256k1::types::EphemeralPublicKeyBytes;
+use ic_crypto_internal_threshold_sig_bls12381::dkg::secp256k1::types::{
+    EphemeralPublicKey, EphemeralSecretKey, EphemeralSecretKeyBytes,
+};
+use ic_types::crypto::AlgorithmId;
+use ic_types::NodeIndex;
+use std::convert::TryFrom;
+
+/// Checks that `share` is the evaluation of the committed polynomial at the
+/// point of the recipient with the given index, i.e., that `g^share` equals
+/// the product of `C_j^(x^j)` with `x = recipient_index + 1`.
+pub(crate) fn verify_share(
+    commitment: &IDkgDealingCommitment,
+    recipient_index: NodeIndex,
+    share: &EphemeralSecretKeyBytes,
+) -> Result<(), CspMEGaDecryptionError> {
+    let IDkgDealingCommitment::K256 { coefficients } = commitment;
+    if coefficients.is_empty() {
+        return Err(malformed_commitment(
+            "there are no coefficients".to_string(),
+        ));
+    }
+    let x = evaluation_point(recipient_index);
+    // Horner's rule, starting with the highest coefficient.
+    let mut expected = EphemeralPublicKey::infinity();
+    for coefficient in coefficients.iter().rev() {
+        let coefficient = EphemeralPublicKey::try_from(coefficient)
+            .map_err(|e| malformed_commitment(format!("{:?}", e)))?;
+        expected = expected * &x + &coefficient;
+    }
+
+    let share = EphemeralSecretKey::try_from(share)
+        .map_err(|_| CspMEGaDecryptionError::InvalidShare { recipient_index })?;
+    if EphemeralPublicKey::from(&share) == expected {
+        Ok(())
+    } else {
+        Err(CspMEGaDecryptionError::InvalidShare { recipient_index })
+    }
+}
+
+/// Commits to the polynomial with the given coefficients and returns the
+/// commitment together with the shares of the first `number_of_recipients`
+/// recipients.
+///
+/// Dealings are created outside of the CSP server, so this is only needed
+/// here to test decryption.
+#[cfg(test)]
+pub(crate) fn commit(
+    coefficients: &[EphemeralSecretKey],
+    number_of_recipients: usize,
+) -> (IDkgDealingCommitment, Vec<EphemeralSecretKeyBytes>) {
+    let commitment = IDkgDealingCommitment::K256 {
+        coefficients: coefficients
+            .iter()
+            .map(|coefficient| EphemeralPublicKeyBytes::from(EphemeralPublicKey::from(coefficient)))
+            .collect(),
+    };
+    let shares = (0..number_of_recipients)
+        .map(|index| {
+            let x = evaluation_point(NodeIndex::try_from(index).expect("too many recipients"));
+            let share = coefficients
+                .iter()
+                .rev()
+                .fold(EphemeralSecretKey::zero(), |acc, coefficient| {
+                    acc * &x + coefficient
+                });
+            EphemeralSecretKeyBytes::from(share)
+        })
+        .collect();
+    (commitment, shares)
+}
+
+/// Returns `recipient_index + 1` as a scalar, so that no recipient gets the
+/// constant term of the polynomial.
+fn evaluation_point(recipient_index: NodeIndex) -> EphemeralSecretKey {
+    let mut bytes = [0; EphemeralSecretKeyBytes::SIZE];
+    bytes[EphemeralSecretKeyBytes::SIZE - 8..]
+        .copy_from_slice(&(u64::from(recipient_index) + 1).to_be_bytes());
+    EphemeralSecretKey::try_from(EphemeralSecretKeyBytes(bytes))
+        .expect("a 64-bit integer is smaller than the group order")
+}
+
+fn malformed_commitment(internal_error: String) -> CspMEGaDecryptionError {
+    CspMEGaDecryptionError::MalformedCommitment {
+        algorithm: AlgorithmId::MegaSecp256k1,
+        internal_error,
+    }
+}
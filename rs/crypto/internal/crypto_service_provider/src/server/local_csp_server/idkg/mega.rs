@@ -0,0 +1,154 @@
+//! MEGa (multi-encryption gadget) encryption on secp256k1.
+//!
+//! A dealer encrypts one secp256k1 scalar for each recipient, using a single
+//! ephemeral key `V = g^r` for all of them. The scalar of the recipient with
+//! index `i` and public key `pk_i` is masked with a scalar derived from the
+//! Diffie-Hellman value `pk_i^r`, which the recipient recomputes as `V^sk_i`.
+use crate::server::api::CspMEGaDecryptionError;
+#[cfg(test)]
+use crate::types::MEGaPublicKey;
+use crate::types::{MEGaCiphertext, MEGaKeySetK256Bytes};
+use ic_crypto_internal_threshold_sig_bls12381::dkg::secp256k1::types::{
+    EphemeralPublicKey, EphemeralPublicKeyBytes, EphemeralSecretKey, EphemeralSecretKeyBytes,
+};
+use ic_crypto_sha::{Context, DomainSeparationContext, Sha256};
+use ic_types::crypto::AlgorithmId;
+use ic_types::NodeIndex;
+use rand::{CryptoRng, Rng};
+use std::convert::TryFrom;
+
+const DOMAIN_MEGA_MASK: &str = "ic-crypto-idkg-mega-encryption-mask";
+
+/// Generates a MEGa key pair.
+pub(crate) fn gen_key_pair<R: Rng + CryptoRng>(rng: &mut R) -> MEGaKeySetK256Bytes {
+    let private_key = EphemeralSecretKey::random(rng);
+    let public_key = EphemeralPublicKey::from(&private_key);
+    MEGaKeySetK256Bytes {
+        public_key: EphemeralPublicKeyBytes::from(public_key),
+        private_key: EphemeralSecretKeyBytes::from(private_key),
+    }
+}
+
+/// Encrypts `plaintexts[i]` for `recipients[i]`.
+///
+/// Dealings are created outside of the CSP server, so encryption is only
+/// needed here to test decryption.
+///
+/// # Panics
+/// If a recipient public key is malformed, or if the number of plaintexts
+/// and recipients differ.
+#[cfg(test)]
+pub(crate) fn encrypt<R: Rng + CryptoRng>(
+    rng: &mut R,
+    plaintexts: &[EphemeralSecretKeyBytes],
+    recipients: &[MEGaPublicKey],
+    associated_data: &[u8],
+) -> MEGaCiphertext {
+    assert_eq!(plaintexts.len(), recipients.len());
+    let randomness = EphemeralSecretKey::random(rng);
+    let ephemeral_key = EphemeralPublicKeyBytes::from(EphemeralPublicKey::from(&randomness));
+    let ctexts = plaintexts
+        .iter()
+        .zip(recipients)
+        .enumerate()
+        .map(|(index, (plaintext, recipient))| {
+            let MEGaPublicKey::K256(recipient_public_key) = recipient;
+            let recipient_point = EphemeralPublicKey::try_from(recipient_public_key)
+                .expect("malformed recipient public key");
+            let shared_secret = EphemeralPublicKeyBytes::from(recipient_point * &randomness);
+            let mask = mask(
+                associated_data,
+                &ephemeral_key,
+                recipient_public_key,
+                NodeIndex::try_from(index).expect("too many recipients"),
+                &shared_secret,
+            );
+            let plaintext = EphemeralSecretKey::try_from(plaintext).expect("malformed plaintext");
+            EphemeralSecretKeyBytes::from(plaintext + mask).0
+        })
+        .collect();
+    MEGaCiphertext::K256 {
+        ephemeral_key,
+        ctexts,
+    }
+}
+
+/// Decrypts the scalar encrypted for the recipient with the given index.
+pub(crate) fn decrypt(
+    key_set: &MEGaKeySetK256Bytes,
+    ciphertext: &MEGaCiphertext,
+    associated_data: &[u8],
+    recipient_index: NodeIndex,
+) -> Result<EphemeralSecretKeyBytes, CspMEGaDecryptionError> {
+    let MEGaCiphertext::K256 {
+        ephemeral_key,
+        ctexts,
+    } = ciphertext;
+    let ctext = usize::try_from(recipient_index)
+        .ok()
+        .and_then(|index| ctexts.get(index))
+        .ok_or(CspMEGaDecryptionError::InvalidRecipientIndex {
+            recipient_index,
+            number_of_ciphertexts: ctexts.len(),
+        })?;
+
+    let private_key = EphemeralSecretKey::try_from(&key_set.private_key).map_err(|_| {
+        CspMEGaDecryptionError::MalformedSecretKey {
+            algorithm: AlgorithmId::MegaSecp256k1,
+        }
+    })?;
+    let ephemeral_point = EphemeralPublicKey::try_from(ephemeral_key)
+        .map_err(|e| malformed_ciphertext(format!("{:?}", e)))?;
+    if ephemeral_point.is_infinity() {
+        return Err(malformed_ciphertext(
+            "the ephemeral key is the point at infinity".to_string(),
+        ));
+    }
+    let ctext = EphemeralSecretKey::try_from(EphemeralSecretKeyBytes(*ctext))
+        .map_err(|e| malformed_ciphertext(format!("{:?}", e)))?;
+
+    let shared_secret = EphemeralPublicKeyBytes::from(ephemeral_point * &private_key);
+    let mask = mask(
+        associated_data,
+        ephemeral_key,
+        &key_set.public_key,
+        recipient_index,
+        &shared_secret,
+    );
+    Ok(EphemeralSecretKeyBytes::from(ctext + (-mask)))
+}
+
+fn malformed_ciphertext(internal_error: String) -> CspMEGaDecryptionError {
+    CspMEGaDecryptionError::MalformedCiphertext {
+        algorithm: AlgorithmId::MegaSecp256k1,
+        internal_error,
+    }
+}
+
+/// Derives the mask of the scalar encrypted for a recipient.
+///
+/// The digest is rehashed with an incremented counter until it is a valid
+/// scalar, which fails with a probability of about 2^-128 per attempt.
+fn mask(
+    associated_data: &[u8],
+    ephemeral_key: &EphemeralPublicKeyBytes,
+    recipient_public_key: &EphemeralPublicKeyBytes,
+    recipient_index: NodeIndex,
+    shared_secret: &EphemeralPublicKeyBytes,
+) -> EphemeralSecretKey {
+    let mut counter: u32 = 0;
+    loop {
+        let mut hash = Sha256::new_with_context(&DomainSeparationContext::new(DOMAIN_MEGA_MASK));
+        hash.write(&(associated_data.len() as u64).to_be_bytes());
+        hash.write(associated_data);
+        hash.write(&ephemeral_key.0);
+        hash.write(&recipient_public_key.0);
+        hash.write(&recipient_index.to_be_bytes());
+        hash.write(&shared_secret.0);
+        hash.write(&counter.to_be_bytes());
+        if let Ok(mask) = EphemeralSecretKey::try_from(EphemeralSecretKeyBytes(hash.finish())) {
+            return mask;
+        }
+        counter += 1;
+    }
+}
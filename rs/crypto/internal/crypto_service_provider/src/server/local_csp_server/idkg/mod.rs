@@ -0,0 +1,107 @@
+//! Interactive DKG operations provided by the CSP server.
+use crate::keygen::mega_key_id;
+use crate::secret_key_store::SecretKeyStore;
+use crate::secret_key_store::SecretKeyStoreError;
+use crate::server::api::{CspCreateMEGaKeyError, CspMEGaDecryptionError, IDkgProtocolCspServer};
+use crate::server::local_csp_server::LocalCspServer;
+use crate::types::{CspSecretKey, IDkgDealingCommitment, MEGaCiphertext, MEGaPublicKey};
+use ic_crypto_sha::{Context, DomainSeparationContext, Sha256};
+use ic_types::crypto::{AlgorithmId, KeyId};
+use ic_types::NodeIndex;
+use rand::{CryptoRng, Rng};
+
+mod commitment;
+mod mega;
+#[cfg(test)]
+mod tests;
+
+const IDKG_SHARE_KEY_ID_DOMAIN: &str = "ic-crypto-idkg-share-key-id";
+
+impl<R: Rng + CryptoRng, S: SecretKeyStore> IDkgProtocolCspServer for LocalCspServer<R, S> {
+    fn idkg_gen_mega_key_pair(
+        &self,
+        algorithm_id: AlgorithmId,
+    ) -> Result<(KeyId, MEGaPublicKey), CspCreateMEGaKeyError> {
+        let key_set = match algorithm_id {
+            AlgorithmId::MegaSecp256k1 => Ok(mega::gen_key_pair(&mut *self.rng_write_lock())),
+            _ => Err(CspCreateMEGaKeyError::UnsupportedAlgorithm {
+                algorithm: algorithm_id,
+            }),
+        }?;
+        let public_key = MEGaPublicKey::K256(key_set.public_key);
+        let key_id = mega_key_id(&public_key);
+        match &self
+            .sks_write_lock()
+            .insert(key_id, CspSecretKey::MEGaEncryptionK256(key_set), None)
+        {
+            Ok(()) => {}
+            Err(SecretKeyStoreError::DuplicateKeyId(key_id)) => {
+                panic!("A key with ID {} has already been inserted", key_id);
+            }
+        };
+        Ok((key_id, public_key))
+    }
+
+    fn idkg_decrypt_mega_ciphertext(
+        &self,
+        algorithm_id: AlgorithmId,
+        mega_key_id: KeyId,
+        ciphertext: &MEGaCiphertext,
+        commitment: &IDkgDealingCommitment,
+        associated_data: &[u8],
+        recipient_index: NodeIndex,
+    ) -> Result<KeyId, CspMEGaDecryptionError> {
+        let secret_key: CspSecretKey = self.sks_read_lock().get(&mega_key_id).ok_or(
+            CspMEGaDecryptionError::SecretKeyNotFound {
+                algorithm: algorithm_id,
+                key_id: mega_key_id,
+            },
+        )?;
+
+        let share = match (algorithm_id, &secret_key) {
+            (AlgorithmId::MegaSecp256k1, CspSecretKey::MEGaEncryptionK256(key_set)) => {
+                mega::decrypt(key_set, ciphertext, associated_data, recipient_index)
+            }
+            _ => Err(CspMEGaDecryptionError::WrongSecretKeyType {
+                algorithm: secret_key.algorithm_id(),
+            }),
+        }?;
+        // The decryption itself is not authenticated: a ciphertext that was
+        // created with other associated data, or by a dishonest dealer,
+        // decrypts to a share that does not match the commitment.
+        commitment::verify_share(commitment, recipient_index, &share)?;
+
+        let share_key_id =
+            idkg_share_key_id(mega_key_id, ciphertext, associated_data, recipient_index);
+        // Decrypting the same ciphertext again yields the same share, so an
+        // existing entry is kept.
+        let mut sks = self.sks_write_lock();
+        if !sks.contains(&share_key_id) {
+            match sks.insert(share_key_id, CspSecretKey::IDkgShareK256(share), None) {
+                Ok(()) => {}
+                Err(SecretKeyStoreError::DuplicateKeyId(key_id)) => {
+                    panic!("A key with ID {} has already been inserted", key_id);
+                }
+            };
+        }
+        Ok(share_key_id)
+    }
+}
+
+/// Computes the key identifier of the share decrypted from the given
+/// ciphertext.
+fn idkg_share_key_id(
+    mega_key_id: KeyId,
+    ciphertext: &MEGaCiphertext,
+    associated_data: &[u8],
+    recipient_index: NodeIndex,
+) -> KeyId {
+    let mut hash =
+        Sha256::new_with_context(&DomainSeparationContext::new(IDKG_SHARE_KEY_ID_DOMAIN));
+    hash.write(&mega_key_id.0);
+    hash.write(&serde_cbor::to_vec(ciphertext).expect("Failed to serialize to CBOR"));
+    hash.write(&(associated_data.len() as u64).to_be_bytes());
+    hash.write(associated_data);
+    hash.write(&recipient_index.to_be_bytes());
+    KeyId::from(hash.finish())
+}
@@ -0,0 +1,334 @@
+#![allow(clippy::unwrap_used)]
+//! Tests of interactive DKG operations in the CSP server.
+use super::{commitment, idkg_share_key_id, mega};
+use crate::secret_key_store::test_utils::TempSecretKeyStore;
+use crate::secret_key_store::SecretKeyStore;
+use crate::server::api::{CspCreateMEGaKeyError, CspMEGaDecryptionError, IDkgProtocolCspServer};
+use crate::server::local_csp_server::LocalCspServer;
+use crate::types::{CspSecretKey, IDkgDealingCommitment, MEGaCiphertext, MEGaPublicKey};
+use ic_crypto_internal_threshold_sig_bls12381::dkg::secp256k1::types::{
+    EphemeralPublicKeyBytes, EphemeralSecretKey, EphemeralSecretKeyBytes,
+};
+use ic_types::crypto::{AlgorithmId, KeyId};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use strum::IntoEnumIterator;
+
+const ASSOCIATED_DATA: &[u8] = b"transcript 1, dealer 3";
+
+fn csp_server() -> LocalCspServer<ChaChaRng, TempSecretKeyStore> {
+    let key_store = TempSecretKeyStore::new();
+    let csprng = ChaChaRng::from_seed(thread_rng().gen::<[u8; 32]>());
+    LocalCspServer::new_for_test(csprng, key_store)
+}
+
+/// Creates a dealing of a random polynomial of degree 1 for the given
+/// recipients and returns its ciphertext and commitment together with the
+/// encrypted shares.
+fn encrypt_for(
+    recipients: &[MEGaPublicKey],
+    associated_data: &[u8],
+) -> (
+    MEGaCiphertext,
+    IDkgDealingCommitment,
+    Vec<EphemeralSecretKeyBytes>,
+) {
+    let mut rng = ChaChaRng::from_seed(thread_rng().gen::<[u8; 32]>());
+    let coefficients = [
+        EphemeralSecretKey::random(&mut rng),
+        EphemeralSecretKey::random(&mut rng),
+    ];
+    let (commitment, plaintexts) = commitment::commit(&coefficients, recipients.len());
+    let ciphertext = mega::encrypt(&mut rng, &plaintexts, recipients, associated_data);
+    (ciphertext, commitment, plaintexts)
+}
+
+fn stored_share(
+    csp_server: &LocalCspServer<ChaChaRng, TempSecretKeyStore>,
+    key_id: &KeyId,
+) -> EphemeralSecretKeyBytes {
+    match csp_server.sks_read_lock().get(key_id) {
+        Some(CspSecretKey::IDkgShareK256(share)) => share,
+        _ => panic!("no share stored under key id {}", key_id),
+    }
+}
+
+#[test]
+fn should_generate_and_store_mega_key_pair() {
+    let csp_server = csp_server();
+
+    let (key_id, public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+
+    assert_eq!(public_key.algorithm_id(), AlgorithmId::MegaSecp256k1);
+    match csp_server.sks_read_lock().get(&key_id) {
+        Some(CspSecretKey::MEGaEncryptionK256(key_set)) => {
+            assert_eq!(MEGaPublicKey::K256(key_set.public_key), public_key)
+        }
+        _ => panic!("no MEGa key set stored under key id {}", key_id),
+    }
+}
+
+#[test]
+fn should_fail_to_generate_mega_key_pair_for_wrong_algorithm_id() {
+    let csp_server = csp_server();
+
+    for algorithm_id in AlgorithmId::iter() {
+        if algorithm_id != AlgorithmId::MegaSecp256k1 {
+            assert_eq!(
+                csp_server.idkg_gen_mega_key_pair(algorithm_id).unwrap_err(),
+                CspCreateMEGaKeyError::UnsupportedAlgorithm {
+                    algorithm: algorithm_id
+                }
+            );
+        }
+    }
+}
+
+#[test]
+fn should_decrypt_and_store_share_of_each_recipient() {
+    let recipients: Vec<_> = (0..4).map(|_| csp_server()).collect();
+    let keys: Vec<_> = recipients
+        .iter()
+        .map(|csp_server| {
+            csp_server
+                .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+                .unwrap()
+        })
+        .collect();
+    let public_keys: Vec<_> = keys.iter().map(|(_, public_key)| *public_key).collect();
+    let (ciphertext, commitment, plaintexts) = encrypt_for(&public_keys, ASSOCIATED_DATA);
+
+    for (index, (csp_server, (key_id, _))) in recipients.iter().zip(&keys).enumerate() {
+        let share_key_id = csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                *key_id,
+                &ciphertext,
+                &commitment,
+                ASSOCIATED_DATA,
+                index as u32,
+            )
+            .unwrap();
+
+        assert!(stored_share(csp_server, &share_key_id) == plaintexts[index]);
+    }
+}
+
+#[test]
+fn should_return_same_key_id_when_decrypting_twice() {
+    let csp_server = csp_server();
+    let (key_id, public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+    let (ciphertext, commitment, _) = encrypt_for(&[public_key], ASSOCIATED_DATA);
+    let decrypt = || {
+        csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                key_id,
+                &ciphertext,
+                &commitment,
+                ASSOCIATED_DATA,
+                0,
+            )
+            .unwrap()
+    };
+
+    assert_eq!(decrypt(), decrypt());
+}
+
+#[test]
+fn should_not_decrypt_share_with_wrong_associated_data() {
+    let csp_server = csp_server();
+    let (key_id, public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+    let (ciphertext, commitment, _) = encrypt_for(&[public_key], ASSOCIATED_DATA);
+    let wrong_associated_data = b"transcript 2, dealer 3";
+
+    assert_eq!(
+        csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                key_id,
+                &ciphertext,
+                &commitment,
+                wrong_associated_data,
+                0,
+            )
+            .unwrap_err(),
+        CspMEGaDecryptionError::InvalidShare { recipient_index: 0 }
+    );
+    assert!(!csp_server.sks_read_lock().contains(&idkg_share_key_id(
+        key_id,
+        &ciphertext,
+        wrong_associated_data,
+        0
+    )));
+}
+
+#[test]
+fn should_not_decrypt_share_that_does_not_match_commitment() {
+    let csp_server = csp_server();
+    let (key_id, public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+    let (ciphertext, _, _) = encrypt_for(&[public_key], ASSOCIATED_DATA);
+    let (_, other_commitment, _) = encrypt_for(&[public_key], ASSOCIATED_DATA);
+
+    assert_eq!(
+        csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                key_id,
+                &ciphertext,
+                &other_commitment,
+                ASSOCIATED_DATA,
+                0,
+            )
+            .unwrap_err(),
+        CspMEGaDecryptionError::InvalidShare { recipient_index: 0 }
+    );
+    assert!(!csp_server.sks_read_lock().contains(&idkg_share_key_id(
+        key_id,
+        &ciphertext,
+        ASSOCIATED_DATA,
+        0
+    )));
+}
+
+#[test]
+fn should_fail_to_decrypt_with_malformed_commitment() {
+    let csp_server = csp_server();
+    let (key_id, public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+    let (ciphertext, _, _) = encrypt_for(&[public_key], ASSOCIATED_DATA);
+    let commitment = IDkgDealingCommitment::K256 {
+        coefficients: vec![],
+    };
+
+    let result = csp_server.idkg_decrypt_mega_ciphertext(
+        AlgorithmId::MegaSecp256k1,
+        key_id,
+        &ciphertext,
+        &commitment,
+        ASSOCIATED_DATA,
+        0,
+    );
+
+    assert!(matches!(
+        result,
+        Err(CspMEGaDecryptionError::MalformedCommitment { .. })
+    ));
+}
+
+#[test]
+fn should_fail_to_decrypt_if_secret_key_not_found() {
+    let csp_server = csp_server();
+    let (ciphertext, commitment, _) = encrypt_for(&[], ASSOCIATED_DATA);
+    let key_id = KeyId::from([42; 32]);
+
+    assert_eq!(
+        csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                key_id,
+                &ciphertext,
+                &commitment,
+                ASSOCIATED_DATA,
+                0
+            )
+            .unwrap_err(),
+        CspMEGaDecryptionError::SecretKeyNotFound {
+            algorithm: AlgorithmId::MegaSecp256k1,
+            key_id
+        }
+    );
+}
+
+#[test]
+fn should_fail_to_decrypt_with_wrong_secret_key_type() {
+    let csp_server = csp_server();
+    let key_id = KeyId::from([42; 32]);
+    csp_server
+        .sks_write_lock()
+        .insert(
+            key_id,
+            CspSecretKey::IDkgShareK256(EphemeralSecretKeyBytes([1; 32])),
+            None,
+        )
+        .unwrap();
+    let (ciphertext, commitment, _) = encrypt_for(&[], ASSOCIATED_DATA);
+
+    assert_eq!(
+        csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                key_id,
+                &ciphertext,
+                &commitment,
+                ASSOCIATED_DATA,
+                0
+            )
+            .unwrap_err(),
+        CspMEGaDecryptionError::WrongSecretKeyType {
+            algorithm: AlgorithmId::MegaSecp256k1
+        }
+    );
+}
+
+#[test]
+fn should_fail_to_decrypt_for_invalid_recipient_index() {
+    let csp_server = csp_server();
+    let (key_id, public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+    let (ciphertext, commitment, _) = encrypt_for(&[public_key], ASSOCIATED_DATA);
+
+    assert_eq!(
+        csp_server
+            .idkg_decrypt_mega_ciphertext(
+                AlgorithmId::MegaSecp256k1,
+                key_id,
+                &ciphertext,
+                &commitment,
+                ASSOCIATED_DATA,
+                1
+            )
+            .unwrap_err(),
+        CspMEGaDecryptionError::InvalidRecipientIndex {
+            recipient_index: 1,
+            number_of_ciphertexts: 1
+        }
+    );
+}
+
+#[test]
+fn should_fail_to_decrypt_malformed_ephemeral_key() {
+    let csp_server = csp_server();
+    let (key_id, _public_key) = csp_server
+        .idkg_gen_mega_key_pair(AlgorithmId::MegaSecp256k1)
+        .unwrap();
+    let ciphertext = MEGaCiphertext::K256 {
+        ephemeral_key: EphemeralPublicKeyBytes([0xff; EphemeralPublicKeyBytes::SIZE]),
+        ctexts: vec![[1; EphemeralSecretKeyBytes::SIZE]],
+    };
+    let (commitment, _) = commitment::commit(&[EphemeralSecretKey::one()], 1);
+
+    let result = csp_server.idkg_decrypt_mega_ciphertext(
+        AlgorithmId::MegaSecp256k1,
+        key_id,
+        &ciphertext,
+        &commitment,
+        ASSOCIATED_DATA,
+        0,
+    );
+
+    assert!(matches!(
+        result,
+        Err(CspMEGaDecryptionError::MalformedCiphertext { .. })
+    ));
+}
@@ -1,31 +1,42 @@
 mod basic_sig;
+mod builder;
+mod idkg;
+pub mod rng_health;
 mod threshold_sig;
+pub mod version;
 
 use crate::secret_key_store::SecretKeyStore;
 use crate::{CspRwLock, PublicKeyData};
-#[cfg(test)]
-use ic_crypto_internal_logmon::metrics::CryptoMetrics;
-#[cfg(test)]
-use ic_logger::replica_logger::no_op_logger;
 use ic_logger::ReplicaLogger;
+use ic_protobuf::crypto::v1::NodePublicKeys;
 use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+use rand::rngs::OsRng;
 use rand::{CryptoRng, Rng};
-#[cfg(test)]
-use std::sync::Arc;
+use rng_health::HealthCheckedRng;
+
+pub use builder::LocalCspServerBuilder;
 
 /// An implementation of `CspServer`-trait that runs in-process
 /// and uses a local storage for the secret keys.
 pub struct LocalCspServer<R: Rng + CryptoRng, S: SecretKeyStore> {
     // CSPRNG stands for cryptographically secure random number generator.
-    #[allow(dead_code)]
-    csprng: CspRwLock<R>,
+    // Its output is continuously checked by health tests, see `rng_health`.
+    csprng: CspRwLock<HealthCheckedRng<R>>,
     secret_key_store: CspRwLock<S>,
-    #[allow(dead_code)]
     public_key_data: PublicKeyData,
     #[allow(dead_code)]
     logger: ReplicaLogger,
 }
 
+impl<S: SecretKeyStore> LocalCspServer<OsRng, S> {
+    /// Returns a builder for a local CSP server with the given secret key
+    /// store, see [`LocalCspServerBuilder`] for the defaults of the other
+    /// components.
+    pub fn builder(secret_key_store: S) -> LocalCspServerBuilder<OsRng, S> {
+        LocalCspServerBuilder::new(secret_key_store)
+    }
+}
+
 impl<R: Rng + CryptoRng, S: SecretKeyStore> LocalCspServer<R, S> {
     /// Creates a local CSP server for testing.
     ///
@@ -33,32 +44,36 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> LocalCspServer<R, S> {
     /// key store is not guaranteed.
     #[cfg(test)]
     pub fn new_for_test(csprng: R, secret_key_store: S) -> Self {
-        let node_public_keys = Default::default();
-        let public_key_data = PublicKeyData::new(node_public_keys);
-        let metrics = Arc::new(CryptoMetrics::none());
-        LocalCspServer {
-            csprng: CspRwLock::new_for_rng(csprng, Arc::clone(&metrics)),
-            public_key_data,
-            secret_key_store: CspRwLock::new_for_sks(secret_key_store, metrics),
-            logger: no_op_logger(),
-        }
+        LocalCspServer::builder(secret_key_store)
+            .with_rng(csprng)
+            .build()
     }
 
-    #[allow(dead_code)]
-    fn rng_write_lock(&self) -> RwLockWriteGuard<'_, R> {
+    /// Returns the CSPRNG.
+    ///
+    /// # Panics
+    /// If the CSPRNG fails a health test while generating output.
+    pub(crate) fn rng_write_lock(&self) -> RwLockWriteGuard<'_, HealthCheckedRng<R>> {
         // TODO (CRP-696): inline this method
         self.csprng.write()
     }
 
-    #[allow(dead_code)]
-    fn sks_write_lock(&self) -> RwLockWriteGuard<'_, S> {
+    pub(crate) fn sks_write_lock(&self) -> RwLockWriteGuard<'_, S> {
         // TODO (CRP-696): inline this method
         self.secret_key_store.write()
     }
 
-    #[allow(dead_code)]
-    fn sks_read_lock(&self) -> RwLockReadGuard<'_, S> {
+    pub(crate) fn sks_read_lock(&self) -> RwLockReadGuard<'_, S> {
         // TODO (CRP-696): inline this method
         self.secret_key_store.read()
     }
+
+    pub(crate) fn public_key_data(&self) -> &PublicKeyData {
+        &self.public_key_data
+    }
+
+    /// Resets the public key data according to the given `NodePublicKeys`.
+    pub(crate) fn reset_public_key_data(&mut self, node_public_keys: NodePublicKeys) {
+        self.public_key_data = PublicKeyData::new(node_public_keys);
+    }
 }
@@ -0,0 +1,281 @@
+//! Continuous health tests of the CSPRNG.
+//!
+//! The tests are modeled after the health tests of NIST SP 800-90B, Section
+//! 4.4, and detect an RNG that got stuck or whose output is heavily biased,
+//! e.g., due to a failing entropy source. As they are applied to the output of
+//! a CSPRNG rather than to a noise source, the cutoffs are chosen such that
+//! false positives are negligible:
+//! * The repetition count test fails if a 64-bit word of output equals the
+//!   previous word, which happens by chance with probability 2^-64.
+//! * The adaptive proportion test fails if the first byte of a window of 512
+//!   bytes occurs at least 32 times in the window, which happens by chance with
+//!   probability below 2^-80.
+//!
+//! Once a test failed, the RNG is poisoned and refuses to produce any further
+//! output, as keys generated from it could be predictable or reused.
+use ic_crypto_internal_logmon::metrics::CryptoMetrics;
+use rand::{CryptoRng, RngCore};
+use std::fmt;
+use std::sync::Arc;
+
+const WORD_LEN: usize = 8;
+const ADAPTIVE_PROPORTION_WINDOW: usize = 512;
+const ADAPTIVE_PROPORTION_CUTOFF: usize = 32;
+
+/// A failed health test of the CSPRNG.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngHealthError {
+    /// A 64-bit word of output was repeated.
+    RepetitionCount,
+    /// A byte value occurred too often within a window of output.
+    AdaptiveProportion,
+}
+
+impl RngHealthError {
+    fn test_name(&self) -> &'static str {
+        match self {
+            RngHealthError::RepetitionCount => "repetition_count",
+            RngHealthError::AdaptiveProportion => "adaptive_proportion",
+        }
+    }
+}
+
+impl fmt::Display for RngHealthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The CSPRNG is poisoned as its {} health test failed",
+            self.test_name()
+        )
+    }
+}
+
+impl std::error::Error for RngHealthError {}
+
+/// A CSPRNG whose output is continuously checked by health tests.
+///
+/// The infallible methods of `RngCore` panic if a health test fails, so that
+/// no output of a failing RNG is ever used, and `try_fill_bytes` returns an
+/// error.
+pub struct HealthCheckedRng<R> {
+    rng: R,
+    metrics: Arc<CryptoMetrics>,
+    poisoned: Option<RngHealthError>,
+    /// Output bytes that do not form a complete word yet.
+    partial_word: Vec<u8>,
+    previous_word: Option<[u8; WORD_LEN]>,
+    /// The first byte of the current adaptive proportion window, its number of
+    /// occurrences, and the number of bytes in the window so far.
+    window_sample: u8,
+    window_sample_count: usize,
+    window_len: usize,
+}
+
+impl<R: RngCore + CryptoRng> HealthCheckedRng<R> {
+    pub fn new(rng: R, metrics: Arc<CryptoMetrics>) -> Self {
+        Self {
+            rng,
+            metrics,
+            poisoned: None,
+            partial_word: Vec::with_capacity(WORD_LEN),
+            previous_word: None,
+            window_sample: 0,
+            window_sample_count: 0,
+            window_len: 0,
+        }
+    }
+
+    /// Returns the health test that failed, if any.
+    pub fn poisoned(&self) -> Option<RngHealthError> {
+        self.poisoned
+    }
+
+    fn checked_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), RngHealthError> {
+        if let Some(error) = self.poisoned {
+            return Err(error);
+        }
+        self.rng.fill_bytes(dest);
+        if let Err(error) = self.check(dest) {
+            self.metrics
+                .observe_rng_health_check_failure(error.test_name());
+            self.poisoned = Some(error);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn check(&mut self, output: &[u8]) -> Result<(), RngHealthError> {
+        for &byte in output {
+            self.repetition_count_test(byte)?;
+            self.adaptive_proportion_test(byte)?;
+        }
+        Ok(())
+    }
+
+    fn repetition_count_test(&mut self, byte: u8) -> Result<(), RngHealthError> {
+        self.partial_word.push(byte);
+        if self.partial_word.len() == WORD_LEN {
+            let mut word = [0; WORD_LEN];
+            word.copy_from_slice(&self.partial_word);
+            self.partial_word.clear();
+            if self.previous_word == Some(word) {
+                return Err(RngHealthError::RepetitionCount);
+            }
+            self.previous_word = Some(word);
+        }
+        Ok(())
+    }
+
+    fn adaptive_proportion_test(&mut self, byte: u8) -> Result<(), RngHealthError> {
+        if self.window_len == ADAPTIVE_PROPORTION_WINDOW {
+            self.window_len = 0;
+        }
+        if self.window_len == 0 {
+            self.window_sample = byte;
+            self.window_sample_count = 0;
+        }
+        self.window_len += 1;
+        if byte == self.window_sample {
+            self.window_sample_count += 1;
+            if self.window_sample_count >= ADAPTIVE_PROPORTION_CUTOFF {
+                return Err(RngHealthError::AdaptiveProportion);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: RngCore + CryptoRng> RngCore for HealthCheckedRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.checked_fill_bytes(dest)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.checked_fill_bytes(dest).map_err(rand::Error::new)
+    }
+}
+
+impl<R: RngCore + CryptoRng> CryptoRng for HealthCheckedRng<R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    /// An RNG that always returns the same byte.
+    struct StuckRng(u8);
+
+    impl RngCore for StuckRng {
+        fn next_u32(&mut self) -> u32 {
+            u32::from_le_bytes([self.0; 4])
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            u64::from_le_bytes([self.0; 8])
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.iter_mut().for_each(|byte| *byte = self.0);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for StuckRng {}
+
+    /// An RNG that returns the output of `ChaCha20Rng`, but with every other
+    /// byte replaced by zero.
+    struct BiasedRng(ChaCha20Rng);
+
+    impl RngCore for BiasedRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.0.fill_bytes(dest);
+            dest.iter_mut().step_by(2).for_each(|byte| *byte = 0);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for BiasedRng {}
+
+    fn health_checked<R: RngCore + CryptoRng>(rng: R) -> HealthCheckedRng<R> {
+        HealthCheckedRng::new(rng, Arc::new(CryptoMetrics::none()))
+    }
+
+    #[test]
+    fn should_pass_health_tests_for_csprng() {
+        let mut rng = health_checked(ChaCha20Rng::seed_from_u64(42));
+        let mut buffer = [0; 1000];
+        for _ in 0..1000 {
+            rng.try_fill_bytes(&mut buffer).unwrap();
+        }
+        assert_eq!(rng.poisoned(), None);
+    }
+
+    #[test]
+    fn should_fail_repetition_count_test_for_stuck_rng() {
+        let mut rng = health_checked(StuckRng(7));
+
+        let result = rng.try_fill_bytes(&mut [0; 16]);
+
+        assert!(result.is_err());
+        assert_eq!(rng.poisoned(), Some(RngHealthError::RepetitionCount));
+    }
+
+    #[test]
+    fn should_fail_adaptive_proportion_test_for_biased_rng() {
+        let mut rng = health_checked(BiasedRng(ChaCha20Rng::seed_from_u64(42)));
+
+        let result = rng.try_fill_bytes(&mut [0; ADAPTIVE_PROPORTION_WINDOW]);
+
+        assert!(result.is_err());
+        assert_eq!(rng.poisoned(), Some(RngHealthError::AdaptiveProportion));
+    }
+
+    #[test]
+    fn should_refuse_output_once_poisoned() {
+        let mut rng = health_checked(StuckRng(7));
+        assert!(rng.try_fill_bytes(&mut [0; 16]).is_err());
+
+        let result = rng.try_fill_bytes(&mut [0; 1]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "The CSPRNG is poisoned")]
+    fn should_panic_in_infallible_methods_if_health_test_fails() {
+        let mut rng = health_checked(StuckRng(7));
+
+        rng.next_u64();
+        rng.next_u64();
+    }
+}
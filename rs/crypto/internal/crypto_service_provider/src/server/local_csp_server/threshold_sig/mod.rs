@@ -1,4 +1,4 @@
-use crate::api::CspThresholdSignError;
+use crate::api::{CspThresholdSignError, MessageHashPrefix, SecretKeyStoreHint};
 use crate::secret_key_store::SecretKeyStore;
 #[cfg(test)]
 use crate::server::api::CspThresholdSignatureKeygenError;
@@ -87,10 +87,14 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> ThresholdSignatureCspServer for Loca
     ) -> Result<CspSignature, CspThresholdSignError> {
         match algorithm_id {
             AlgorithmId::ThresBls12_381 => {
-                let csp_key = self.sks_read_lock().get(&key_id).ok_or({
+                let csp_key = self.sks_read_lock().get(&key_id).ok_or_else(|| {
+                    // The server does not remove threshold keys yet, so it
+                    // cannot know whether a key was retired.
                     CspThresholdSignError::SecretKeyNotFound {
                         algorithm: AlgorithmId::ThresBls12_381,
                         key_id,
+                        message_hash_prefix: MessageHashPrefix::of_message(message),
+                        secret_key_store_hint: SecretKeyStoreHint::Unknown,
                     }
                 })?;
                 let clib_key = bls12381_clib::types::SecretKeyBytes::try_from(csp_key)?;
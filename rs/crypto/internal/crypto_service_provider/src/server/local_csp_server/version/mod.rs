@@ -0,0 +1,96 @@
+//! Build version of the CSP server and compatibility checks against it.
+use crate::secret_key_store::SecretKeyStore;
+use crate::server::api::{
+    CspServerOperation, CspServerVersion, CspServerVersionError, VersionCspServer,
+};
+use crate::server::local_csp_server::LocalCspServer;
+use ic_crypto_internal_logmon::metrics::CryptoMetrics;
+use rand::{CryptoRng, Rng};
+use std::collections::BTreeSet;
+
+#[cfg(test)]
+mod tests;
+
+/// The operations implemented by `LocalCspServer`, i.e., the `*CspServer`
+/// traits it implements. A test checks that the traits are implemented.
+const LOCAL_CSP_SERVER_OPERATIONS: &[CspServerOperation] = &[
+    CspServerOperation::BasicSignature,
+    CspServerOperation::ThresholdSignature,
+    CspServerOperation::IDkgProtocol,
+];
+
+/// The operations that the CSP client calls on its server.
+const REQUIRED_CSP_SERVER_OPERATIONS: &[CspServerOperation] = &[
+    CspServerOperation::BasicSignature,
+    CspServerOperation::ThresholdSignature,
+];
+
+impl<R: Rng + CryptoRng, S: SecretKeyStore> VersionCspServer for LocalCspServer<R, S> {
+    fn version(&self) -> CspServerVersion {
+        CspServerVersion {
+            version: client_version().to_string(),
+            supported_operations: LOCAL_CSP_SERVER_OPERATIONS.iter().copied().collect(),
+        }
+    }
+}
+
+/// Checks that a CSP client built from this crate can use `csp_server`,
+/// i.e., that both were built from the same version and that the server
+/// supports all operations the client calls.
+///
+/// The server version and the outcome of the check are recorded in
+/// `metrics`.
+pub fn check_csp_server_compatibility<V: VersionCspServer>(
+    csp_server: &V,
+    metrics: &CryptoMetrics,
+) -> Result<(), CspServerVersionError> {
+    let server_version = csp_server.version();
+    let result = check_compatibility(
+        client_version(),
+        &server_version,
+        &REQUIRED_CSP_SERVER_OPERATIONS.iter().copied().collect(),
+    );
+    metrics.observe_csp_server_version(&server_version.version, result.is_ok());
+    result
+}
+
+/// Fails fast if `csp_server` cannot serve this client, rather than failing
+/// on the first crypto operation.
+///
+/// # Panics
+/// If `csp_server` is incompatible, with a message describing why.
+pub fn ensure_csp_server_compatibility<V: VersionCspServer>(
+    csp_server: &V,
+    metrics: &CryptoMetrics,
+) {
+    if let Err(e) = check_csp_server_compatibility(csp_server, metrics) {
+        panic!("Incompatible CSP server: {}", e);
+    }
+}
+
+fn client_version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+fn check_compatibility(
+    client_version: &str,
+    server_version: &CspServerVersion,
+    required_operations: &BTreeSet<CspServerOperation>,
+) -> Result<(), CspServerVersionError> {
+    if server_version.version != client_version {
+        return Err(CspServerVersionError::VersionMismatch {
+            client_version: client_version.to_string(),
+            server_version: server_version.version.clone(),
+        });
+    }
+    let unsupported: BTreeSet<_> = required_operations
+        .difference(&server_version.supported_operations)
+        .copied()
+        .collect();
+    if !unsupported.is_empty() {
+        return Err(CspServerVersionError::UnsupportedOperations {
+            operations: unsupported,
+        });
+    }
+    Ok(())
+}
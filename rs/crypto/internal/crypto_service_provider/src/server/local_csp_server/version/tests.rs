@@ -0,0 +1,122 @@
+//! Tests of the CSP server version and compatibility checks.
+use super::*;
+use crate::secret_key_store::test_utils::TempSecretKeyStore;
+use crate::server::api::{
+    BasicSignatureCspServer, IDkgProtocolCspServer, ThresholdSignatureCspServer,
+};
+use ic_metrics::MetricsRegistry;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+/// A CSP server that reports the given version.
+struct FixedVersionCspServer(CspServerVersion);
+
+impl VersionCspServer for FixedVersionCspServer {
+    fn version(&self) -> CspServerVersion {
+        self.0.clone()
+    }
+}
+
+fn server_version(version: &str, operations: &[CspServerOperation]) -> CspServerVersion {
+    CspServerVersion {
+        version: version.to_string(),
+        supported_operations: operations.iter().copied().collect(),
+    }
+}
+
+fn local_csp_server() -> LocalCspServer<ChaChaRng, TempSecretKeyStore> {
+    LocalCspServer::new_for_test(ChaChaRng::seed_from_u64(42), TempSecretKeyStore::new())
+}
+
+#[test]
+fn should_report_crate_version_and_operations() {
+    let version = local_csp_server().version();
+
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        version.supported_operations,
+        LOCAL_CSP_SERVER_OPERATIONS.iter().copied().collect()
+    );
+}
+
+#[test]
+fn should_implement_the_traits_of_the_reported_operations() {
+    fn implements<T>(_: &T)
+    where
+        T: BasicSignatureCspServer + ThresholdSignatureCspServer + IDkgProtocolCspServer,
+    {
+    }
+    // Adding an operation to `LOCAL_CSP_SERVER_OPERATIONS` requires adding
+    // its trait to the bound above.
+    assert_eq!(LOCAL_CSP_SERVER_OPERATIONS.len(), 3);
+
+    implements(&local_csp_server());
+}
+
+#[test]
+fn should_accept_local_server() {
+    assert_eq!(
+        check_csp_server_compatibility(&local_csp_server(), &CryptoMetrics::none()),
+        Ok(())
+    );
+}
+
+#[test]
+fn should_reject_server_with_different_version() {
+    let csp_server = FixedVersionCspServer(server_version("0.0.1", LOCAL_CSP_SERVER_OPERATIONS));
+
+    assert_eq!(
+        check_csp_server_compatibility(&csp_server, &CryptoMetrics::none()),
+        Err(CspServerVersionError::VersionMismatch {
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            server_version: "0.0.1".to_string(),
+        })
+    );
+}
+
+#[test]
+fn should_reject_server_without_required_operations() {
+    let csp_server = FixedVersionCspServer(server_version(
+        env!("CARGO_PKG_VERSION"),
+        &[CspServerOperation::BasicSignature],
+    ));
+
+    assert_eq!(
+        check_csp_server_compatibility(&csp_server, &CryptoMetrics::none()),
+        Err(CspServerVersionError::UnsupportedOperations {
+            operations: vec![CspServerOperation::ThresholdSignature]
+                .into_iter()
+                .collect(),
+        })
+    );
+}
+
+#[test]
+fn should_record_incompatible_server_in_metrics() {
+    let registry = MetricsRegistry::new();
+    let metrics = CryptoMetrics::new(Some(&registry));
+    let csp_server = FixedVersionCspServer(server_version("0.0.1", LOCAL_CSP_SERVER_OPERATIONS));
+
+    let _ = check_csp_server_compatibility(&csp_server, &metrics);
+
+    let gauge = registry
+        .prometheus_registry()
+        .gather()
+        .into_iter()
+        .find(|family| family.get_name() == "ic_crypto_csp_server_info")
+        .expect("no CSP server info metric");
+    let labels: Vec<_> = gauge.get_metric()[0]
+        .get_label()
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .collect();
+    assert_eq!(labels, vec![("compatible", "false"), ("version", "0.0.1")]);
+}
+
+#[test]
+#[should_panic(expected = "Incompatible CSP server: CSP server version 0.0.1 is incompatible")]
+fn should_fail_startup_with_server_of_different_version() {
+    let csp_server = FixedVersionCspServer(server_version("0.0.1", LOCAL_CSP_SERVER_OPERATIONS));
+
+    ensure_csp_server_compatibility(&csp_server, &CryptoMetrics::none());
+}
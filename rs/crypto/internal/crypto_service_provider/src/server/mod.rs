@@ -1,2 +1,3 @@
 pub mod api;
+pub mod async_csp_server;
 pub mod local_csp_server;
@@ -5,6 +5,7 @@ use crate::imported_test_utils::ed25519::csp_testvec;
 use crate::imported_utilities::sign_utils::user_public_key_from_bytes;
 use crate::secret_key_store::test_utils::{MockSecretKeyStore, TempSecretKeyStore};
 use crate::types::{CspPublicKey, CspSecretKey, CspSignature};
+use crate::AsyncBasicSignatureCspServer;
 use ic_crypto_internal_multi_sig_bls12381::types as multi_types;
 use ic_crypto_internal_test_vectors::ed25519::Ed25519TestVector::{
     RFC8032_ED25519_1, RFC8032_ED25519_SHA_ABC,
@@ -54,6 +55,25 @@ mod sign_ed25519 {
         assert_eq!(csp.sign(Ed25519, &msg, KeyId::from(KEY_ID)).unwrap(), sig);
     }
 
+    #[tokio::test]
+    async fn should_correctly_sign_asynchronously() {
+        let (sk, _, msg, sig) = csp_testvec(RFC8032_ED25519_SHA_ABC);
+
+        let csp = Csp::of(csprng(), secret_key_store_with(KeyId::from(KEY_ID), sk));
+
+        assert_eq!(
+            csp.async_signer()
+                .sign_async(
+                    Ed25519,
+                    msg,
+                    KeyId::from(KEY_ID),
+                    tokio_util::sync::CancellationToken::new()
+                )
+                .await,
+            Ok(sig)
+        );
+    }
+
     #[test]
     fn should_fail_to_sign_if_secret_key_in_store_has_wrong_type() {
         let sk_with_wrong_type = CspSecretKey::MultiBls12_381(multi_types::SecretKeyBytes(
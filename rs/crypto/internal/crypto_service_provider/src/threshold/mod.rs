@@ -1,5 +1,8 @@
 //! Threshold signature implementation for the CSP
-use crate::api::{CspSecretKeyInjector, CspThresholdSignError, ThresholdSignatureCspClient};
+use crate::api::{
+    CspSecretKeyInjector, CspThresholdSignError, MessageHashPrefix, SecretKeyStoreHint,
+    ThresholdSignatureCspClient,
+};
 use crate::secret_key_store::SecretKeyStore;
 use crate::threshold::dkg::public_coefficients_key_id;
 use crate::types::{CspPublicCoefficients, CspSecretKey, CspSignature, ThresBls12_381_Signature};
@@ -12,6 +15,7 @@ use rand::{CryptoRng, Rng};
 use std::convert::TryFrom;
 pub mod dkg;
 pub mod ni_dkg;
+pub mod retired_keys;
 
 #[cfg(test)]
 mod tests;
@@ -72,10 +76,18 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> ThresholdSignatureCspClient for Csp<
     ) -> Result<CspSignature, CspThresholdSignError> {
         match algorithm_id {
             AlgorithmId::ThresBls12_381 => {
-                let csp_key = self.sks_read_lock().get(&key_id).ok_or({
+                let csp_key = self.sks_read_lock().get(&key_id).ok_or_else(|| {
+                    let secret_key_store_hint =
+                        if self.retired_threshold_key_ids.read().contains(&key_id) {
+                            SecretKeyStoreHint::RecentlyRetired
+                        } else {
+                            SecretKeyStoreHint::Unknown
+                        };
                     CspThresholdSignError::SecretKeyNotFound {
                         algorithm: AlgorithmId::ThresBls12_381,
                         key_id,
+                        message_hash_prefix: MessageHashPrefix::of_message(message),
+                        secret_key_store_hint,
                     }
                 })?;
                 let clib_key = clib::types::SecretKeyBytes::try_from(csp_key)?;
@@ -155,6 +167,31 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> ThresholdSignatureCspClient for Csp<
         }
     }
 
+    fn threshold_individual_public_keys(
+        &self,
+        algorithm_id: AlgorithmId,
+        node_indices: &[NodeIndex],
+        public_coefficients: CspPublicCoefficients,
+    ) -> CryptoResult<Vec<CspThresholdSigPublicKey>> {
+        match algorithm_id {
+            AlgorithmId::ThresBls12_381 => {
+                let clib_public_coefficients_bytes =
+                    PublicCoefficientsBytes::from(public_coefficients);
+                let public_keys_bytes = clib::api::individual_public_keys(
+                    &clib_public_coefficients_bytes,
+                    node_indices,
+                )?;
+                Ok(public_keys_bytes
+                    .into_iter()
+                    .map(CspThresholdSigPublicKey::ThresBls12_381)
+                    .collect())
+            }
+            _ => Err(CryptoError::InvalidArgument {
+                message: format!("Unsupported algorithm: {:?}", algorithm_id),
+            }),
+        }
+    }
+
     fn threshold_verify_individual_signature(
         &self,
         algorithm_id: AlgorithmId,
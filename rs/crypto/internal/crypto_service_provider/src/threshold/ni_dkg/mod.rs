@@ -29,6 +29,7 @@ use ic_types::crypto::threshold_sig::ni_dkg::NiDkgId;
 use ic_types::crypto::{AlgorithmId, KeyId};
 use ic_types::{NodeId, NodeIndex, NumberOfNodes, Randomness};
 use rand::{CryptoRng, Rng};
+use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
 
 #[cfg(test)]
@@ -432,10 +433,21 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> NiDkgCspClient for Csp<R, S> {
         debug!(self.logger; crypto.method_name => "retain_threshold_keys_if_present");
         let active_key_ids: BTreeSet<KeyId> =
             active_keys.iter().map(key_id_from_csp_pub_coeffs).collect();
+        let retired_key_ids = RefCell::new(vec![]);
         self.sks_write_lock().retain(
-            |key_id, _| active_key_ids.contains(key_id),
+            |key_id, _| {
+                let is_active = active_key_ids.contains(key_id);
+                if !is_active {
+                    retired_key_ids.borrow_mut().push(*key_id);
+                }
+                is_active
+            },
             NIDKG_THRESHOLD_SCOPE,
-        )
+        );
+        let mut retired_threshold_key_ids = self.retired_threshold_key_ids.write();
+        for key_id in retired_key_ids.into_inner() {
+            retired_threshold_key_ids.insert(key_id);
+        }
     }
 }
 
@@ -1,6 +1,6 @@
 #![allow(clippy::unwrap_used)]
 use super::fixtures::cache::STATE_WITH_TRANSCRIPT;
-use crate::api::NiDkgCspClient;
+use crate::api::{CspThresholdSignError, MessageHashPrefix, NiDkgCspClient, SecretKeyStoreHint};
 use crate::secret_key_store::volatile_store::VolatileSecretKeyStore;
 use crate::threshold::ni_dkg::tests::fixtures::StateWithTranscript;
 use crate::threshold::ThresholdSignatureCspClient;
@@ -96,13 +96,28 @@ fn test_retention() {
         let active_keys = vec![different_public_coefficients].into_iter().collect();
         csp.retain_threshold_keys_if_present(active_keys);
 
-        // The key should be unavailable
-        csp.threshold_sign(
-            AlgorithmId::ThresBls12_381,
-            &b"To her life she clings!"[..],
-            internal_public_coefficients.clone(),
-        )
-        .expect_err("The key should have been removed");
+        // The key should be unavailable, and known to have been retired:
+        let error = csp
+            .threshold_sign(
+                AlgorithmId::ThresBls12_381,
+                &b"To her life she clings!"[..],
+                internal_public_coefficients.clone(),
+            )
+            .expect_err("The key should have been removed");
+        match error {
+            CspThresholdSignError::SecretKeyNotFound {
+                message_hash_prefix,
+                secret_key_store_hint,
+                ..
+            } => {
+                assert_eq!(
+                    message_hash_prefix,
+                    MessageHashPrefix::of_message(&b"To her life she clings!"[..])
+                );
+                assert_eq!(secret_key_store_hint, SecretKeyStoreHint::RecentlyRetired);
+            }
+            _ => panic!("Unexpected error: {}", error),
+        }
     }
 
     // The FS-encryption key MUST be retained, so that it is still available for
@@ -0,0 +1,53 @@
+//! Bookkeeping of threshold keys removed from the secret key store.
+use ic_types::crypto::KeyId;
+use std::collections::VecDeque;
+
+/// The IDs of the threshold keys most recently removed from the secret key
+/// store because their transcripts were no longer active.
+///
+/// Signing with a key that was just removed is expected around epoch
+/// boundaries, when signing races with the removal of the keys of the previous
+/// epoch. Keeping track of the removed keys allows telling such races apart
+/// from keys that are missing for other reasons, e.g., a corrupted secret key
+/// store.
+#[derive(Debug)]
+pub struct RetiredKeyIds {
+    key_ids: VecDeque<KeyId>,
+    capacity: usize,
+}
+
+impl Default for RetiredKeyIds {
+    fn default() -> Self {
+        Self::new(Self::CAPACITY)
+    }
+}
+
+impl RetiredKeyIds {
+    /// The default number of key IDs that are remembered. Each epoch retires
+    /// the keys of only a few transcripts, so this covers several epochs.
+    pub const CAPACITY: usize = 64;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            key_ids: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records that the key with the given `key_id` was removed, forgetting
+    /// the least recently removed key if the capacity is exceeded.
+    pub fn insert(&mut self, key_id: KeyId) {
+        if self.key_ids.contains(&key_id) {
+            return;
+        }
+        if self.key_ids.len() == self.capacity {
+            self.key_ids.pop_front();
+        }
+        self.key_ids.push_back(key_id);
+    }
+
+    /// Returns whether the key with the given `key_id` was recently removed.
+    pub fn contains(&self, key_id: &KeyId) -> bool {
+        self.key_ids.contains(key_id)
+    }
+}
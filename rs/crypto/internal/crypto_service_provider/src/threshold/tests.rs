@@ -97,6 +97,14 @@ pub mod util {
         }
 
         // Verify each individual signature:
+        let node_indices: Vec<NodeIndex> = (0..signatures.len() as NodeIndex).collect();
+        let public_keys = verifier
+            .threshold_individual_public_keys(
+                AlgorithmId::ThresBls12_381,
+                &node_indices,
+                (*public_coefficients).clone(),
+            )
+            .expect("Could not calculate individual public keys");
         for (index, signature) in signatures.iter().enumerate() {
             let public_key = match verifier.threshold_individual_public_key(
                 AlgorithmId::ThresBls12_381,
@@ -106,6 +114,7 @@ pub mod util {
                 Ok(public_key) => public_key,
                 Err(error) => panic!("Could not calculate individual public key: {:?}", error),
             };
+            assert_eq!(public_keys[index], public_key);
 
             // Correct values validate:
             assert_eq!(
@@ -1,11 +1,13 @@
 use crate::api::tls_errors::CspTlsClientHandshakeError;
 use crate::api::CspTlsClientHandshake;
 use crate::secret_key_store::SecretKeyStore;
-use crate::tls_stub::{key_from_secret_key_store, peer_cert_from_stream, CspTlsSecretKeyError};
+use crate::tls_stub::{
+    handshake_failure_kind, key_from_secret_key_store, peer_cert_from_stream, CspTlsSecretKeyError,
+};
 use crate::Csp;
 use async_trait::async_trait;
-use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_crypto_tls_interfaces::TlsStream;
+use ic_crypto_tls_interfaces::{HandshakeFailureKind, TlsPublicKeyCert};
 use openssl::ssl::ConnectConfiguration;
 use rand::{CryptoRng, Rng};
 use std::pin::Pin;
@@ -31,12 +33,14 @@ impl<R: Rng + CryptoRng + Send + Sync, S: SecretKeyStore> CspTlsClientHandshake
         )?;
         Pin::new(&mut tls_stream).connect().await.map_err(|e| {
             CspTlsClientHandshakeError::HandshakeError {
+                kind: handshake_failure_kind(&e),
                 internal_error: format!("Handshake failed in tokio_openssl:connect: {}", e),
             }
         })?;
 
         let peer_cert = peer_cert_from_stream(&tls_stream)?.ok_or(
             CspTlsClientHandshakeError::HandshakeError {
+                kind: HandshakeFailureKind::Other,
                 internal_error: "Missing server certificate during handshake.".to_string(),
             },
         )?;
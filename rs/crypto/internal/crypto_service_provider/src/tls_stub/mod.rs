@@ -8,10 +8,12 @@ use crate::secret_key_store::SecretKeyStore;
 use crate::tls_stub::cert_chain::CspCertificateChainCreationError;
 use crate::types::CspSecretKey;
 use cert_chain::CspCertificateChain;
-use ic_crypto_tls_interfaces::TlsPublicKeyCert;
+use ic_crypto_tls_interfaces::{HandshakeFailureKind, TlsPublicKeyCert};
 use openssl::pkey::{PKey, Private};
+use openssl::ssl::ErrorCode;
 use openssl::x509::X509VerifyResult;
 use std::convert::TryFrom;
+use std::io;
 use tokio::net::TcpStream;
 use tokio_openssl::SslStream;
 
@@ -21,6 +23,8 @@ mod server_handshake;
 
 #[cfg(test)]
 mod test_utils;
+#[cfg(test)]
+mod tests;
 
 fn key_from_secret_key_store<S: SecretKeyStore>(
     secret_key_store: &S,
@@ -39,6 +43,75 @@ fn key_from_secret_key_store<S: SecretKeyStore>(
         .map_err(|_| CspTlsSecretKeyError::MalformedSecretKey)
 }
 
+/// Classifies an error returned by openssl during the handshake.
+///
+/// Openssl reports protocol failures only as a stack of error reasons, so the
+/// classification relies on the reason strings of openssl 1.1.
+fn handshake_failure_kind(error: &openssl::ssl::Error) -> HandshakeFailureKind {
+    let reasons: Vec<&str> = error
+        .ssl_error()
+        .map(|stack| stack.errors().iter().filter_map(|e| e.reason()).collect())
+        .unwrap_or_default();
+    classify_handshake_failure(error.code(), error.io_error(), &reasons)
+}
+
+fn classify_handshake_failure(
+    code: ErrorCode,
+    io_error: Option<&io::Error>,
+    reasons: &[&str],
+) -> HandshakeFailureKind {
+    if code == ErrorCode::ZERO_RETURN {
+        return HandshakeFailureKind::PeerClosed;
+    }
+    if let Some(io_error) = io_error {
+        return match io_error.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => HandshakeFailureKind::PeerClosed,
+            _ => HandshakeFailureKind::IoError,
+        };
+    }
+    if reasons.is_empty() {
+        // A syscall error without an error stack or `errno` is openssl's way
+        // of reporting an unexpected EOF.
+        return if code == ErrorCode::SYSCALL {
+            HandshakeFailureKind::PeerClosed
+        } else {
+            HandshakeFailureKind::Other
+        };
+    }
+    let any_reason_contains = |patterns: &[&str]| {
+        reasons
+            .iter()
+            .any(|r| patterns.iter().any(|p| r.contains(p)))
+    };
+    if any_reason_contains(&[
+        "unsupported protocol",
+        "protocol version",
+        "wrong version number",
+    ]) {
+        HandshakeFailureKind::UnsupportedVersion
+    } else if any_reason_contains(&[
+        "no shared cipher",
+        "no shared signature algorithms",
+        "no suitable signature algorithm",
+    ]) {
+        HandshakeFailureKind::CipherMismatch
+    } else if any_reason_contains(&[
+        "certificate verify failed",
+        "bad signature",
+        "bad certificate",
+        "decrypt error",
+    ]) {
+        HandshakeFailureKind::BadCertSignature
+    } else {
+        // This includes the generic "handshake failure" alert, which a peer
+        // sends for any handshake failure without a more specific alert.
+        HandshakeFailureKind::Other
+    }
+}
+
 enum CspTlsSecretKeyError {
     SecretKeyNotFound,
     MalformedSecretKey,
@@ -76,6 +149,7 @@ impl From<CspPeerCertFromStreamError> for CspTlsClientHandshakeError {
         match peer_cert_error {
             CspPeerCertFromStreamError::PeerCertificateNotVerified => {
                 CspTlsClientHandshakeError::HandshakeError {
+                    kind: HandshakeFailureKind::BadCertSignature,
                     internal_error: "The server certificate was not verified during the handshake."
                         .to_string(),
                 }
@@ -94,6 +168,7 @@ impl From<CspPeerCertFromStreamError> for CspTlsServerHandshakeError {
         match peer_cert_error {
             CspPeerCertFromStreamError::PeerCertificateNotVerified => {
                 CspTlsServerHandshakeError::HandshakeError {
+                    kind: HandshakeFailureKind::BadCertSignature,
                     internal_error: "The client certificate was not verified during the handshake."
                         .to_string(),
                 }
@@ -183,6 +258,7 @@ impl From<CspPeerCertChainFromStreamError> for CspTlsServerHandshakeError {
         match peer_cert_chain_error {
             CspPeerCertChainFromStreamError::UnverifiedCertChain => {
                 CspTlsServerHandshakeError::HandshakeError {
+                    kind: HandshakeFailureKind::BadCertSignature,
                     internal_error:
                         "The client certificate chain was not verified during the handshake."
                             .to_string(),
@@ -190,6 +266,7 @@ impl From<CspPeerCertChainFromStreamError> for CspTlsServerHandshakeError {
             }
             CspPeerCertChainFromStreamError::EmptyCertChain => {
                 CspTlsServerHandshakeError::HandshakeError {
+                    kind: HandshakeFailureKind::Other,
                     internal_error:
                         "The client certificate chain was present but empty during the handshake."
                             .to_string(),
@@ -197,6 +274,7 @@ impl From<CspPeerCertChainFromStreamError> for CspTlsServerHandshakeError {
             }
             CspPeerCertChainFromStreamError::CertChainLeafInconsistency(internal_error) => {
                 CspTlsServerHandshakeError::HandshakeError {
+                    kind: HandshakeFailureKind::Other,
                     internal_error: format!(
                         "Chain leaf consistency check failed: {}",
                         internal_error
@@ -205,6 +283,7 @@ impl From<CspPeerCertChainFromStreamError> for CspTlsServerHandshakeError {
             }
             CspPeerCertChainFromStreamError::PeerCertificateNotVerified => {
                 CspTlsServerHandshakeError::HandshakeError {
+                    kind: HandshakeFailureKind::BadCertSignature,
                     internal_error: "The client certificate was not verified during the handshake."
                         .to_string(),
                 }
@@ -3,13 +3,16 @@ use crate::api::CspTlsServerHandshake;
 use crate::secret_key_store::SecretKeyStore;
 use crate::tls_stub::cert_chain::CspCertificateChain;
 use crate::tls_stub::{
-    key_from_secret_key_store, peer_cert_chain_from_stream, CspTlsSecretKeyError,
+    handshake_failure_kind, key_from_secret_key_store, peer_cert_chain_from_stream,
+    CspTlsSecretKeyError,
 };
 use crate::Csp;
 use async_trait::async_trait;
+use ic_crypto_internal_tls::ClientAuthentication;
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_crypto_tls_interfaces::TlsStream;
 use openssl::ssl::{Ssl, SslAcceptor};
+use openssl::x509::X509;
 use rand::{CryptoRng, Rng};
 use std::collections::HashSet;
 use std::pin::Pin;
@@ -26,11 +29,17 @@ impl<R: Rng + CryptoRng + Send + Sync, S: SecretKeyStore> CspTlsServerHandshake
         self_cert: TlsPublicKeyCert,
         trusted_client_certs: HashSet<TlsPublicKeyCert>,
     ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError> {
-        let tls_acceptor = self.tls_acceptor(self_cert, Some(trusted_client_certs.clone()))?;
+        let tls_acceptor = self.tls_acceptor(
+            self_cert,
+            ClientAuthentication::OptionalAuthentication {
+                trusted_client_certs: x509_certs(&trusted_client_certs),
+            },
+        )?;
 
         let mut tls_stream = unconnected_tls_stream(tls_acceptor, tcp_stream)?;
         Pin::new(&mut tls_stream).accept().await.map_err(|e| {
             CspTlsServerHandshakeError::HandshakeError {
+                kind: handshake_failure_kind(&e),
                 internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
             }
         })?;
@@ -44,17 +53,45 @@ impl<R: Rng + CryptoRng + Send + Sync, S: SecretKeyStore> CspTlsServerHandshake
         tcp_stream: TcpStream,
         self_cert: TlsPublicKeyCert,
     ) -> Result<TlsStream, CspTlsServerHandshakeError> {
-        let tls_acceptor = self.tls_acceptor(self_cert, None)?;
+        let tls_acceptor = self.tls_acceptor(self_cert, ClientAuthentication::NoAuthentication)?;
 
         let mut tls_stream = unconnected_tls_stream(tls_acceptor, tcp_stream)?;
         Pin::new(&mut tls_stream).accept().await.map_err(|e| {
             CspTlsServerHandshakeError::HandshakeError {
+                kind: handshake_failure_kind(&e),
                 internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
             }
         })?;
 
         Ok(TlsStream::new(tls_stream))
     }
+
+    async fn perform_tls_server_handshake_with_ca_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        self_cert: TlsPublicKeyCert,
+        trusted_client_cas: HashSet<TlsPublicKeyCert>,
+        max_intermediate_cas: u32,
+    ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError> {
+        let tls_acceptor = self.tls_acceptor(
+            self_cert,
+            ClientAuthentication::MandatoryCaAuthentication {
+                trusted_client_cas: x509_certs(&trusted_client_cas),
+                max_intermediate_cas,
+            },
+        )?;
+
+        let mut tls_stream = unconnected_tls_stream(tls_acceptor, tcp_stream)?;
+        Pin::new(&mut tls_stream).accept().await.map_err(|e| {
+            CspTlsServerHandshakeError::HandshakeError {
+                kind: handshake_failure_kind(&e),
+                internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
+            }
+        })?;
+
+        let peer_cert_chain = peer_cert_chain_from_stream(&tls_stream)?;
+        Ok((TlsStream::new(tls_stream), peer_cert_chain))
+    }
 }
 
 impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
@@ -62,31 +99,30 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
     /// server. The `self_cert` is used as server certificate and the
     /// corresponding private key must be in the secret key store.
 
-    /// The server can be configured to require client authentication.
-    /// If trusted_client_certs is Some then the non-empty set will
-    /// list the client certificates that will be accepted. If it is None,
-    /// then no client authentication will be performed.
+    /// The `client_auth` determines whether and how clients authenticate.
     fn tls_acceptor(
         &self,
         self_cert: TlsPublicKeyCert,
-        trusted_client_certs: Option<HashSet<TlsPublicKeyCert>>,
+        client_auth: ClientAuthentication,
     ) -> Result<SslAcceptor, CspTlsServerHandshakeError> {
-        use ic_crypto_internal_tls::{tls_acceptor, ClientAuthentication};
+        use ic_crypto_internal_tls::tls_acceptor;
 
-        let trusted_client_certs_x509 = match trusted_client_certs {
-            Some(c) => ClientAuthentication::OptionalAuthentication {
-                trusted_client_certs: c.iter().map(TlsPublicKeyCert::as_x509).cloned().collect(),
-            },
-            None => ClientAuthentication::NoAuthentication,
-        };
         Ok(tls_acceptor(
             &key_from_secret_key_store(&*self.sks_read_lock(), &self_cert)?,
             &self_cert.as_x509(),
-            trusted_client_certs_x509,
+            client_auth,
         )?)
     }
 }
 
+fn x509_certs(certs: &HashSet<TlsPublicKeyCert>) -> Vec<X509> {
+    certs
+        .iter()
+        .map(TlsPublicKeyCert::as_x509)
+        .cloned()
+        .collect()
+}
+
 fn unconnected_tls_stream(
     tls_acceptor: SslAcceptor,
     tcp_stream: TcpStream,
@@ -1,4 +1,5 @@
 #![allow(clippy::unwrap_used)]
+use super::x509_certs;
 use crate::api::tls_errors::CspTlsServerHandshakeError;
 use crate::api::CspTlsServerHandshake;
 use crate::secret_key_store::test_utils::TempSecretKeyStore;
@@ -9,6 +10,7 @@ use crate::tls_stub::test_utils::{
 use crate::types::CspSecretKey;
 use crate::Csp;
 use ic_crypto_internal_multi_sig_bls12381::types::SecretKeyBytes;
+use ic_crypto_internal_tls::ClientAuthentication;
 use ic_crypto_test_utils::tls::x509_certificates::{generate_ed25519_tlscert, private_key_to_der};
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use openssl::ssl::SslVerifyMode;
@@ -25,7 +27,12 @@ fn should_return_acceptor_from_clib_if_no_error_occurs() {
     let mut trusted_certs_set = HashSet::new();
     assert!(trusted_certs_set.insert(trusted_client_cert));
     let acceptor = csp
-        .tls_acceptor(self_cert.clone(), Some(trusted_certs_set))
+        .tls_acceptor(
+            self_cert.clone(),
+            ClientAuthentication::OptionalAuthentication {
+                trusted_client_certs: x509_certs(&trusted_certs_set),
+            },
+        )
         .unwrap();
 
     // only check a few acceptor properties (details are tested in the CLib)
@@ -50,7 +57,12 @@ fn should_return_acceptor_with_correct_verify_peer_settings_with_auth() {
     let mut trusted_certs_set = HashSet::new();
     assert!(trusted_certs_set.insert(trusted_client_cert));
     let acceptor_with_auth = csp
-        .tls_acceptor(self_cert, Some(trusted_certs_set))
+        .tls_acceptor(
+            self_cert,
+            ClientAuthentication::OptionalAuthentication {
+                trusted_client_certs: x509_certs(&trusted_certs_set),
+            },
+        )
         .unwrap();
 
     assert_eq!(
@@ -65,7 +77,9 @@ fn should_return_acceptor_with_correct_verify_peer_settings_without_auth() {
     let sks = secret_key_store_with_key(&private_key, &self_cert);
     let csp = Csp::of(dummy_csprng(), sks);
 
-    let acceptor_no_auth = csp.tls_acceptor(self_cert, None).unwrap();
+    let acceptor_no_auth = csp
+        .tls_acceptor(self_cert, ClientAuthentication::NoAuthentication)
+        .unwrap();
 
     assert_eq!(
         acceptor_no_auth.context().verify_mode(),
@@ -73,6 +87,29 @@ fn should_return_acceptor_with_correct_verify_peer_settings_without_auth() {
     );
 }
 
+#[test]
+fn should_return_acceptor_with_correct_verify_peer_settings_with_ca_auth() {
+    let (private_key, self_cert) = generate_ed25519_tlscert();
+    let sks = secret_key_store_with_key(&private_key, &self_cert);
+    let csp = Csp::of(dummy_csprng(), sks);
+    let (_, trusted_client_ca) = generate_ed25519_tlscert();
+
+    let acceptor_with_ca_auth = csp
+        .tls_acceptor(
+            self_cert,
+            ClientAuthentication::MandatoryCaAuthentication {
+                trusted_client_cas: vec![trusted_client_ca.as_x509().clone()],
+                max_intermediate_cas: 1,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        acceptor_with_ca_auth.context().verify_mode(),
+        SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT
+    );
+}
+
 #[tokio::test]
 async fn should_return_create_acceptor_error_from_clib() {
     let (private_key, self_cert) = generate_ed25519_tlscert();
@@ -88,6 +125,26 @@ async fn should_return_create_acceptor_error_from_clib() {
             if description == "The trusted client certs must not be empty."));
 }
 
+#[tokio::test]
+async fn should_return_create_acceptor_error_from_clib_if_client_cas_empty() {
+    let (private_key, self_cert) = generate_ed25519_tlscert();
+    let sks = secret_key_store_with_key(&private_key, &self_cert);
+    let csp = Csp::of(dummy_csprng(), sks);
+
+    let result = csp
+        .perform_tls_server_handshake_with_ca_client_auth(
+            dummy_tcp_stream().await,
+            self_cert,
+            HashSet::new(),
+            1,
+        )
+        .await;
+
+    assert!(matches!(result,
+            Err(CspTlsServerHandshakeError::CreateAcceptorError{description, .. })
+            if description == "The trusted client certs must not be empty."));
+}
+
 #[tokio::test]
 async fn should_return_error_if_secret_key_not_found() {
     let (_, self_cert) = generate_ed25519_tlscert();
@@ -0,0 +1,107 @@
+use super::classify_handshake_failure;
+use ic_crypto_tls_interfaces::HandshakeFailureKind;
+use openssl::ssl::ErrorCode;
+use std::io;
+
+#[test]
+fn should_classify_close_notify_as_peer_closed() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::ZERO_RETURN, None, &[]),
+        HandshakeFailureKind::PeerClosed
+    );
+}
+
+#[test]
+fn should_classify_syscall_error_without_reasons_as_peer_closed() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SYSCALL, None, &[]),
+        HandshakeFailureKind::PeerClosed
+    );
+}
+
+#[test]
+fn should_classify_lost_connection_as_peer_closed() {
+    for kind in &[
+        io::ErrorKind::UnexpectedEof,
+        io::ErrorKind::ConnectionReset,
+        io::ErrorKind::ConnectionAborted,
+        io::ErrorKind::BrokenPipe,
+    ] {
+        let io_error = io::Error::from(*kind);
+
+        assert_eq!(
+            classify_handshake_failure(ErrorCode::SYSCALL, Some(&io_error), &[]),
+            HandshakeFailureKind::PeerClosed,
+            "{:?}",
+            kind
+        );
+    }
+}
+
+#[test]
+fn should_classify_other_io_errors_as_io_error() {
+    for kind in &[
+        io::ErrorKind::TimedOut,
+        io::ErrorKind::PermissionDenied,
+        io::ErrorKind::Other,
+    ] {
+        let io_error = io::Error::from(*kind);
+
+        assert_eq!(
+            classify_handshake_failure(ErrorCode::SYSCALL, Some(&io_error), &[]),
+            HandshakeFailureKind::IoError,
+            "{:?}",
+            kind
+        );
+    }
+}
+
+#[test]
+fn should_classify_io_error_before_reasons() {
+    let io_error = io::Error::from(io::ErrorKind::TimedOut);
+
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SYSCALL, Some(&io_error), &["no shared cipher"]),
+        HandshakeFailureKind::IoError
+    );
+}
+
+#[test]
+fn should_classify_ssl_error_without_reasons_as_other() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SSL, None, &[]),
+        HandshakeFailureKind::Other
+    );
+}
+
+#[test]
+fn should_classify_generic_handshake_failure_alert_as_other() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SSL, None, &["sslv3 alert handshake failure"]),
+        HandshakeFailureKind::Other
+    );
+}
+
+#[test]
+fn should_classify_no_shared_cipher_as_cipher_mismatch() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SSL, None, &["no shared cipher"]),
+        HandshakeFailureKind::CipherMismatch
+    );
+}
+
+#[test]
+fn should_classify_version_mismatch_as_unsupported_version() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SSL, None, &["unsupported protocol"]),
+        HandshakeFailureKind::UnsupportedVersion
+    );
+}
+
+#[test]
+fn should_classify_failed_certificate_verification_as_bad_cert_signature() {
+    assert_eq!(
+        classify_handshake_failure(ErrorCode::SSL, None, &["certificate verify failed"]),
+        HandshakeFailureKind::BadCertSignature
+    );
+}
@@ -14,7 +14,7 @@ use ic_crypto_internal_basic_sig_rsa_pkcs1 as rsa;
 use ic_crypto_internal_multi_sig_bls12381::types as multi_types;
 use ic_crypto_internal_threshold_sig_bls12381::dkg::secp256k1::types::{
     CLibResponseBytes, CLibTranscriptBytes, EncryptedShareBytes, EphemeralKeySetBytes,
-    EphemeralPopBytes,
+    EphemeralPopBytes, EphemeralPublicKeyBytes, EphemeralSecretKeyBytes,
 };
 use ic_crypto_internal_threshold_sig_bls12381::ni_dkg::types::CspFsEncryptionKeySet;
 use ic_crypto_internal_threshold_sig_bls12381::types as threshold_types;
@@ -39,9 +39,10 @@ use test_utils::{
     arbitrary_ecdsa_secp256k1_public_key, arbitrary_ecdsa_secp256r1_public_key,
     arbitrary_ecdsa_secp256r1_signature, arbitrary_ed25519_public_key,
     arbitrary_ed25519_secret_key, arbitrary_ed25519_signature, arbitrary_ephemeral_key_set,
-    arbitrary_fs_encryption_key_set, arbitrary_multi_bls12381_combined_signature,
-    arbitrary_multi_bls12381_individual_signature, arbitrary_multi_bls12381_public_key,
-    arbitrary_multi_bls12381_secret_key, arbitrary_rsa_public_key, arbitrary_secp256k1_signature,
+    arbitrary_fs_encryption_key_set, arbitrary_idkg_share_k256, arbitrary_mega_k256_key_set,
+    arbitrary_multi_bls12381_combined_signature, arbitrary_multi_bls12381_individual_signature,
+    arbitrary_multi_bls12381_public_key, arbitrary_multi_bls12381_secret_key,
+    arbitrary_rsa_public_key, arbitrary_secp256k1_signature,
     arbitrary_threshold_bls12381_combined_signature,
     arbitrary_threshold_bls12381_individual_signature, arbitrary_threshold_bls12381_secret_key,
     arbitrary_tls_ed25519_secret_key,
@@ -68,6 +69,10 @@ pub enum CspSecretKey {
     TlsEd25519(TlsEd25519SecretKeyDerBytes),
     #[cfg_attr(test, proptest(value(arbitrary_fs_encryption_key_set)))]
     FsEncryption(CspFsEncryptionKeySet),
+    #[cfg_attr(test, proptest(value(arbitrary_mega_k256_key_set)))]
+    MEGaEncryptionK256(MEGaKeySetK256Bytes),
+    #[cfg_attr(test, proptest(value(arbitrary_idkg_share_k256)))]
+    IDkgShareK256(EphemeralSecretKeyBytes),
 }
 
 impl CspSecretKey {
@@ -80,6 +85,8 @@ impl CspSecretKey {
             Self::Secp256k1WithPublicKey(_) => AlgorithmId::Secp256k1,
             Self::TlsEd25519(_) => AlgorithmId::Ed25519,
             Self::FsEncryption(_) => AlgorithmId::NiDkg_Groth20_Bls12_381,
+            Self::MEGaEncryptionK256(_) => AlgorithmId::MegaSecp256k1,
+            Self::IDkgShareK256(_) => AlgorithmId::MegaSecp256k1,
         }
     }
 }
@@ -99,10 +106,73 @@ impl std::fmt::Debug for CspSecretKey {
             ),
             CspSecretKey::TlsEd25519(_) => write!(f, "CspSecretKey::TlsEd25519 - REDACTED"),
             CspSecretKey::FsEncryption(_) => write!(f, "CspSecretKey::FsEncryption - REDACTED"),
+            CspSecretKey::MEGaEncryptionK256(key_set) => write!(
+                f,
+                "CspSecretKey::MEGaEncryptionK256 private_key: REDACTED public_key: {}",
+                hex::encode(&key_set.public_key.0[..])
+            ),
+            CspSecretKey::IDkgShareK256(_) => write!(f, "CspSecretKey::IDkgShareK256 - REDACTED"),
         }
     }
 }
 
+/// A MEGa (multi-encryption gadget) key pair on secp256k1.
+///
+/// The key pair is used to decrypt the shares that are sent to the node in
+/// dealings of the interactive DKG for threshold ECDSA.
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MEGaKeySetK256Bytes {
+    pub public_key: EphemeralPublicKeyBytes,
+    pub private_key: EphemeralSecretKeyBytes,
+}
+
+impl Zeroize for MEGaKeySetK256Bytes {
+    fn zeroize(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+/// The public key of a MEGa key pair, used by dealers to encrypt shares for
+/// the node.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MEGaPublicKey {
+    K256(EphemeralPublicKeyBytes),
+}
+
+impl MEGaPublicKey {
+    pub fn algorithm_id(&self) -> AlgorithmId {
+        match self {
+            MEGaPublicKey::K256(_) => AlgorithmId::MegaSecp256k1,
+        }
+    }
+
+    pub fn pk_bytes(&self) -> &[u8] {
+        match self {
+            MEGaPublicKey::K256(public_key) => &public_key.0[..],
+        }
+    }
+}
+
+/// A MEGa ciphertext: a batch of secp256k1 scalars, one per recipient, that
+/// are all encrypted using the same ephemeral key.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MEGaCiphertext {
+    K256 {
+        ephemeral_key: EphemeralPublicKeyBytes,
+        ctexts: Vec<[u8; EphemeralSecretKeyBytes::SIZE]>,
+    },
+}
+
+/// A Feldman commitment to the polynomial `f` of a dealing: the public keys
+/// `g^a_j` of its coefficients `a_j`, starting with the constant term. The
+/// share of the recipient with index `i` is `f(i + 1)`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum IDkgDealingCommitment {
+    K256 {
+        coefficients: Vec<EphemeralPublicKeyBytes>,
+    },
+}
+
 /// An encrypted threshold BLS12-381 key
 #[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum CspEncryptedSecretKey {
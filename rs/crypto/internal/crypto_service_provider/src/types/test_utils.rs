@@ -269,6 +269,33 @@ pub fn arbitrary_fs_encryption_key_set() -> CspSecretKey {
     ))
 }
 
+/// This function is only used for tests
+#[allow(unused)]
+pub fn arbitrary_mega_k256_key_set() -> CspSecretKey {
+    let mut random_sk_bytes = [0; EphemeralSecretKeyBytes::SIZE];
+    for b in random_sk_bytes.iter_mut() {
+        *b = rand::random();
+    }
+    let mut random_pk_bytes = [0; EphemeralPublicKeyBytes::SIZE];
+    for b in random_pk_bytes.iter_mut() {
+        *b = rand::random();
+    }
+    CspSecretKey::MEGaEncryptionK256(MEGaKeySetK256Bytes {
+        public_key: EphemeralPublicKeyBytes(random_pk_bytes),
+        private_key: EphemeralSecretKeyBytes(random_sk_bytes),
+    })
+}
+
+/// This function is only used for tests
+#[allow(unused)]
+pub fn arbitrary_idkg_share_k256() -> CspSecretKey {
+    let mut random_bytes = [0; EphemeralSecretKeyBytes::SIZE];
+    for b in random_bytes.iter_mut() {
+        *b = rand::random();
+    }
+    CspSecretKey::IDkgShareK256(EphemeralSecretKeyBytes(random_bytes))
+}
+
 /// This function is only used for tests
 #[allow(unused)]
 pub fn arbitrary_threshold_bls12381_combined_signature() -> ThresBls12_381_Signature {
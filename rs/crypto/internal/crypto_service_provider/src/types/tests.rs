@@ -134,6 +134,28 @@ fn should_redact_csp_secret_key_fs_encryption_debug() {
     );
 }
 
+#[test]
+fn should_redact_csp_secret_key_mega_encryption_debug() {
+    let cspsk_mega = CspSecretKey::MEGaEncryptionK256(MEGaKeySetK256Bytes {
+        public_key: EphemeralPublicKeyBytes([2u8; EphemeralPublicKeyBytes::SIZE]),
+        private_key: EphemeralSecretKeyBytes([1u8; EphemeralSecretKeyBytes::SIZE]),
+    });
+    let debug_string = format!("{:?}", cspsk_mega);
+    assert!(debug_string.contains("private_key: REDACTED"));
+    assert!(!debug_string.contains(&hex::encode([1u8; EphemeralSecretKeyBytes::SIZE])));
+}
+
+#[test]
+fn should_redact_csp_secret_key_idkg_share_debug() {
+    let cspsk_share = CspSecretKey::IDkgShareK256(EphemeralSecretKeyBytes(
+        [1u8; EphemeralSecretKeyBytes::SIZE],
+    ));
+    assert_eq!(
+        "CspSecretKey::IDkgShareK256 - REDACTED",
+        format!("{:?}", cspsk_share)
+    );
+}
+
 #[test]
 fn should_return_correct_algorithm_id() {
     // Ed25519
@@ -178,6 +200,18 @@ fn should_return_correct_algorithm_id() {
         },
     ));
     assert_eq!(key.algorithm_id(), AlgorithmId::NiDkg_Groth20_Bls12_381);
+
+    // MEGaEncryptionK256
+    let key = CspSecretKey::MEGaEncryptionK256(MEGaKeySetK256Bytes {
+        public_key: EphemeralPublicKeyBytes([0; EphemeralPublicKeyBytes::SIZE]),
+        private_key: EphemeralSecretKeyBytes([0; EphemeralSecretKeyBytes::SIZE]),
+    });
+    assert_eq!(key.algorithm_id(), AlgorithmId::MegaSecp256k1);
+
+    // IDkgShareK256
+    let key =
+        CspSecretKey::IDkgShareK256(EphemeralSecretKeyBytes([0; EphemeralSecretKeyBytes::SIZE]));
+    assert_eq!(key.algorithm_id(), AlgorithmId::MegaSecp256k1);
 }
 
 #[test]
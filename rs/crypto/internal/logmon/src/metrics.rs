@@ -1,7 +1,8 @@
 //! Metrics exported by crypto
 
 use ic_metrics::MetricsRegistry;
-use prometheus::HistogramVec;
+use prometheus::{HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec};
+use std::collections::BTreeMap;
 use std::time;
 use std::time::Instant;
 
@@ -71,6 +72,145 @@ impl CryptoMetrics {
                 .observe(start_time.elapsed().as_secs_f64());
         }
     }
+
+    /// Observes the result of checking the integrity of the secret key store
+    /// on startup, such as `ok` or `corrupted_records`.
+    pub fn observe_secret_key_store_integrity_check(&self, result: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_secret_key_store_integrity_checks_total
+                .with_label_values(&[result])
+                .inc();
+        }
+    }
+
+    /// Observes that `count` corrupted records were moved out of the secret
+    /// key store into quarantine.
+    pub fn observe_secret_key_store_quarantined_records(&self, count: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_secret_key_store_quarantined_records_total
+                .inc_by(count as u64);
+        }
+    }
+
+    /// Observes a failed health test of the CSPRNG, such as
+    /// `repetition_count`.
+    pub fn observe_rng_health_check_failure(&self, test: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_rng_health_check_failures_total
+                .with_label_values(&[test])
+                .inc();
+        }
+    }
+
+    /// Observes the number of records in the secret key store, keyed by the
+    /// algorithm and the scope of their keys. Label combinations that are not
+    /// in `counts` are reset.
+    pub fn observe_secret_key_store_records(&self, counts: &BTreeMap<(String, String), usize>) {
+        if let Some(metrics) = &self.metrics {
+            metrics.ic_crypto_secret_key_store_records.reset();
+            for ((algorithm, scope), count) in counts {
+                metrics
+                    .ic_crypto_secret_key_store_records
+                    .with_label_values(&[algorithm, scope])
+                    .set(*count as i64);
+            }
+        }
+    }
+
+    /// Observes the size of the secret key store on disk.
+    pub fn observe_secret_key_store_size_bytes(&self, size: u64) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_secret_key_store_size_bytes
+                .set(size as i64);
+        }
+    }
+
+    /// Observes the duration of writing the secret key store to disk. The
+    /// `stage` label is either 'write', for the whole write, or 'fsync', for
+    /// syncing the written data to disk.
+    ///
+    /// This only observes the duration if metrics are enabled and
+    /// `start_time` is `Some`.
+    pub fn observe_secret_key_store_write_duration_seconds(
+        &self,
+        stage: &str,
+        start_time: Option<Instant>,
+    ) {
+        if let (Some(metrics), Some(start_time)) = (&self.metrics, start_time) {
+            metrics
+                .ic_crypto_secret_key_store_write_duration_seconds
+                .with_label_values(&[stage])
+                .observe(start_time.elapsed().as_secs_f64());
+        }
+    }
+
+    /// Observes the version of the CSP server in use and whether it is
+    /// compatible with the CSP client.
+    pub fn observe_csp_server_version(&self, version: &str, compatible: bool) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_csp_server_info
+                .with_label_values(&[version, &compatible.to_string()])
+                .set(1);
+        }
+    }
+
+    /// Observes that a TLS server handshake was rejected because it exceeded
+    /// a handshake limit. The `reason` label indicates the limit, such as
+    /// `concurrency` or `source_rate`.
+    pub fn observe_tls_handshake_rejected(&self, reason: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_tls_handshakes_rejected_total
+                .with_label_values(&[reason])
+                .inc();
+        }
+    }
+
+    /// Observes that a TLS server handshake was rejected because the client
+    /// presented a revoked certificate. The `trust_path` label indicates how
+    /// the certificate was trusted, such as `explicit_cert` or `client_ca`.
+    pub fn observe_tls_revoked_cert_rejected(&self, trust_path: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .ic_crypto_tls_revoked_certs_rejected_total
+                .with_label_values(&[trust_path])
+                .inc();
+        }
+    }
+
+    /// Observes a TLS handshake. The `role` label is either 'client' or
+    /// 'server'.
+    ///
+    /// The handshake duration is only observed if metrics are enabled and
+    /// `start_time` is `Some`.
+    pub fn observe_tls_handshake(&self, role: &str, is_ok: bool, start_time: Option<Instant>) {
+        if let Some(metrics) = &self.metrics {
+            let result = if is_ok { "success" } else { "error" };
+            metrics
+                .ic_crypto_tls_handshakes_total
+                .with_label_values(&[role, result])
+                .inc();
+            if let Some(start_time) = start_time {
+                metrics
+                    .ic_crypto_tls_handshake_duration_seconds
+                    .with_label_values(&[role])
+                    .observe(start_time.elapsed().as_secs_f64());
+            }
+        }
+    }
+
+    /// Observes that a TLS server handshake failed because the client
+    /// authenticated, but is not an allowed client.
+    pub fn observe_tls_client_not_allowed(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.ic_crypto_tls_client_not_allowed_total.inc();
+        }
+    }
 }
 
 struct Metrics {
@@ -80,6 +220,43 @@ struct Metrics {
     /// Histogram of `NiDkgAlgorithm` method call times. The 'method_name' label
     /// indicates the method name, such as `load_transcript`.
     pub ic_crypto_ni_dkg_method_duration_seconds: HistogramVec,
+    /// Counter of secret key store integrity checks. The 'result' label
+    /// indicates the outcome, such as `ok` or `corrupted_records`.
+    pub ic_crypto_secret_key_store_integrity_checks_total: IntCounterVec,
+    /// Counter of corrupted secret key store records moved into quarantine.
+    pub ic_crypto_secret_key_store_quarantined_records_total: IntCounter,
+    /// Counter of failed health tests of the CSPRNG. The 'test' label
+    /// indicates the failed test, such as `repetition_count`.
+    pub ic_crypto_rng_health_check_failures_total: IntCounterVec,
+    /// Gauge of the number of records in the secret key store. The
+    /// 'algorithm' and 'scope' labels indicate the algorithm and the scope of
+    /// the keys.
+    pub ic_crypto_secret_key_store_records: IntGaugeVec,
+    /// Gauge of the size of the secret key store on disk.
+    pub ic_crypto_secret_key_store_size_bytes: IntGauge,
+    /// Histogram of secret key store write times. The 'stage' label is either
+    /// 'write' or 'fsync'.
+    pub ic_crypto_secret_key_store_write_duration_seconds: HistogramVec,
+    /// Info metric about the CSP server in use. The 'version' label indicates
+    /// the server's build version, the 'compatible' label whether it is
+    /// compatible with the client.
+    pub ic_crypto_csp_server_info: IntGaugeVec,
+    /// Counter of TLS server handshakes rejected to protect the node from
+    /// overload. The 'reason' label indicates the exceeded limit.
+    pub ic_crypto_tls_handshakes_rejected_total: IntCounterVec,
+    /// Counter of TLS server handshakes rejected because the client presented
+    /// a revoked certificate. The 'trust_path' label indicates how the
+    /// certificate was trusted.
+    pub ic_crypto_tls_revoked_certs_rejected_total: IntCounterVec,
+    /// Counter of TLS handshakes. The 'role' label is either 'client' or
+    /// 'server', the 'result' label either 'success' or 'error'.
+    pub ic_crypto_tls_handshakes_total: IntCounterVec,
+    /// Histogram of TLS handshake durations. The 'role' label is either
+    /// 'client' or 'server'.
+    pub ic_crypto_tls_handshake_duration_seconds: HistogramVec,
+    /// Counter of TLS server handshakes that failed because the client is not
+    /// an allowed client.
+    pub ic_crypto_tls_client_not_allowed_total: IntCounter,
 }
 
 impl Metrics {
@@ -100,6 +277,65 @@ impl Metrics {
                 ],
                 &["method_name"],
             ),
+            ic_crypto_secret_key_store_integrity_checks_total: r.int_counter_vec(
+                "ic_crypto_secret_key_store_integrity_checks_total",
+                "Number of secret key store integrity checks, by result",
+                &["result"],
+            ),
+            ic_crypto_secret_key_store_quarantined_records_total: r.int_counter(
+                "ic_crypto_secret_key_store_quarantined_records_total",
+                "Number of corrupted secret key store records moved into quarantine",
+            ),
+            ic_crypto_rng_health_check_failures_total: r.int_counter_vec(
+                "ic_crypto_rng_health_check_failures_total",
+                "Number of failed health tests of the CSPRNG, by test",
+                &["test"],
+            ),
+            ic_crypto_secret_key_store_records: r.int_gauge_vec(
+                "ic_crypto_secret_key_store_records",
+                "Number of records in the secret key store, by algorithm and scope",
+                &["algorithm", "scope"],
+            ),
+            ic_crypto_secret_key_store_size_bytes: r.int_gauge(
+                "ic_crypto_secret_key_store_size_bytes",
+                "Size of the secret key store on disk",
+            ),
+            ic_crypto_secret_key_store_write_duration_seconds: r.histogram_vec(
+                "ic_crypto_secret_key_store_write_duration_seconds",
+                "Histogram of secret key store write times, by stage",
+                vec![0.0001, 0.001, 0.01, 0.1, 1.0, 10.0],
+                &["stage"],
+            ),
+            ic_crypto_csp_server_info: r.int_gauge_vec(
+                "ic_crypto_csp_server_info",
+                "Version of the CSP server in use and its compatibility with the client",
+                &["version", "compatible"],
+            ),
+            ic_crypto_tls_handshakes_rejected_total: r.int_counter_vec(
+                "ic_crypto_tls_handshakes_rejected_total",
+                "Number of TLS server handshakes rejected because of a handshake limit, by reason",
+                &["reason"],
+            ),
+            ic_crypto_tls_revoked_certs_rejected_total: r.int_counter_vec(
+                "ic_crypto_tls_revoked_certs_rejected_total",
+                "Number of TLS server handshakes rejected because of a revoked client certificate, by trust path",
+                &["trust_path"],
+            ),
+            ic_crypto_tls_handshakes_total: r.int_counter_vec(
+                "ic_crypto_tls_handshakes_total",
+                "Number of TLS handshakes, by role and result",
+                &["role", "result"],
+            ),
+            ic_crypto_tls_handshake_duration_seconds: r.histogram_vec(
+                "ic_crypto_tls_handshake_duration_seconds",
+                "Histogram of TLS handshake durations, by role",
+                vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0],
+                &["role"],
+            ),
+            ic_crypto_tls_client_not_allowed_total: r.int_counter(
+                "ic_crypto_tls_client_not_allowed_total",
+                "Number of TLS server handshakes that failed because the client is not allowed",
+            ),
         }
     }
 }
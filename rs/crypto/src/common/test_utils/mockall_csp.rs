@@ -128,6 +128,13 @@ mock! {
             public_coefficients: CspPublicCoefficients,
         ) -> CryptoResult<CspThresholdSigPublicKey>;
 
+        fn threshold_individual_public_keys(
+            &self,
+            algorithm_id: AlgorithmId,
+            node_indices: &[NodeIndex],
+            public_coefficients: CspPublicCoefficients,
+        ) -> CryptoResult<Vec<CspThresholdSigPublicKey>>;
+
         fn threshold_verify_individual_signature(
             &self,
             algorithm_id: AlgorithmId,
@@ -350,6 +357,14 @@ mock! {
             tcp_stream: TcpStream,
             self_cert: TlsPublicKeyCert,
         ) -> Result<TlsStream, CspTlsServerHandshakeError>;
+
+        async fn perform_tls_server_handshake_with_ca_client_auth(
+            &self,
+            tcp_stream: TcpStream,
+            self_cert: TlsPublicKeyCert,
+            trusted_client_cas: HashSet<TlsPublicKeyCert>,
+            max_intermediate_cas: u32,
+        ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError>;
     }
 
     #[async_trait]
@@ -9,6 +9,7 @@ use ic_crypto_internal_csp::secret_key_store::proto_store::ProtoSecretKeyStore;
 use ic_crypto_internal_csp::types::{CspPop, CspPublicKey};
 use ic_crypto_internal_csp::Csp;
 use ic_crypto_internal_csp::{public_key_store, CryptoServiceProvider};
+use ic_crypto_internal_tls::keygen::NO_WELL_DEFINED_EXPIRATION_DATE;
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_interfaces::crypto::DkgAlgorithm;
 use ic_logger::replica_logger::no_op_logger;
@@ -104,7 +105,9 @@ pub fn get_node_keys_or_generate_if_missing(crypto_root: &Path) -> (NodePublicKe
             let node_id = derive_node_id(&node_signing_pk);
             let dkg_dealing_encryption_pk =
                 generate_dkg_dealing_encryption_keys(crypto_root, node_id);
-            let tls_certificate = generate_tls_keys(crypto_root, node_id).to_proto();
+            let tls_certificate = TlsKeypairBuilder::new(node_id)
+                .build(crypto_root)
+                .to_proto();
             let node_pks = NodePublicKeys {
                 version: 0,
                 node_signing_pk: Some(node_signing_pk),
@@ -241,21 +244,49 @@ fn generate_committee_signing_keys(crypto_root: &Path) -> PublicKeyProto {
     }
 }
 
-/// Generates TLS key material for a `node`.
+/// Builds TLS key material for a node: an Ed25519 key pair together with a
+/// self-signed X.509 certificate for its public key.
 ///
-/// Stores the secret key in the key store at `crypto_root` and uses it to
-/// create a self-signed public key certificate. If there exists no key store
-/// in `crypto_root` yet, a new key store is created.
+/// The certificate is an X.509 v3 certificate with a random serial number,
+/// whose subject and issuer CN are the node ID. It is valid from the time it
+/// is built. Unless configured otherwise, it has no well-defined expiration
+/// date, as required for node certificates in the registry.
 ///
-///
-/// The certificate's notAfter date indicates according to RFC5280 (section
-/// 4.1.2.5; see https://tools.ietf.org/html/rfc5280#section-4.1.2.5) that the
-/// certificate has no well-defined expiration date.
-///
-/// Returns the certificate.
-fn generate_tls_keys(crypto_root: &Path, node: NodeId) -> TlsPublicKeyCert {
-    let mut csp = csp_at_root(crypto_root);
-    csp.gen_tls_key_pair(node, "99991231235959Z")
+/// The key material is generated by the CSP with the `TlsCertBuilder` of
+/// the TLS crypto library, and the secret key never leaves the CSP.
+pub struct TlsKeypairBuilder {
+    node_id: NodeId,
+    not_after: String,
+}
+
+impl TlsKeypairBuilder {
+    pub fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            not_after: NO_WELL_DEFINED_EXPIRATION_DATE.to_string(),
+        }
+    }
+
+    /// Sets the certificate's notAfter date, in one of the formats allowed by
+    /// RFC 5280, i.e. YYMMDDHHMMSSZ or YYYYMMDDHHMMSSZ.
+    ///
+    /// Note that the registry only accepts node certificates without a
+    /// well-defined expiration date, so this is mostly useful for tests.
+    pub fn with_not_after(mut self, not_after: &str) -> Self {
+        self.not_after = not_after.to_string();
+        self
+    }
+
+    /// Generates the key material, stores the secret key in the key store at
+    /// `crypto_root` and returns the certificate. If there exists no key store
+    /// in `crypto_root` yet, a new key store is created.
+    ///
+    /// # Panics
+    /// * if the notAfter date cannot be parsed or lies in the past
+    pub fn build(self, crypto_root: &Path) -> TlsPublicKeyCert {
+        let mut csp = csp_at_root(crypto_root);
+        csp.gen_tls_key_pair(self.node_id, &self.not_after)
+    }
 }
 
 pub(crate) fn csp_at_root(crypto_root: &Path) -> Csp<OsRng, ProtoSecretKeyStore> {
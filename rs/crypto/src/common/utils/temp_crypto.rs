@@ -1,21 +1,23 @@
-use crate::common::utils::generate_tls_keys;
+use crate::common::utils::TlsKeypairBuilder;
 use crate::common::utils::{
     generate_committee_signing_keys, generate_dkg_dealing_encryption_keys,
     generate_node_signing_keys,
 };
-use crate::{CryptoComponent, CryptoComponentFatClient};
+use crate::{CryptoComponent, CryptoComponentFatClient, TlsHandshakeLimits};
 use async_trait::async_trait;
 use ic_config::crypto::CryptoConfig;
 use ic_crypto_internal_csp::secret_key_store::proto_store::ProtoSecretKeyStore;
 use ic_crypto_internal_csp::{public_key_store, CryptoServiceProvider, Csp};
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_crypto_tls_interfaces::{
-    AllowedClients, AuthenticatedPeer, Peer, TlsClientHandshakeError, TlsHandshake,
-    TlsServerHandshakeError, TlsStream,
+    AllowedClients, AllowedClientsProvider, AuthenticatedPeer, ClientCas, Peer,
+    PeerRevalidationError, TlsClientHandshakeError, TlsHandshake, TlsServerHandshakeError,
+    TlsStream,
 };
 use ic_interfaces::crypto::{
     BasicSigVerifier, BasicSigVerifierByPublicKey, CanisterSigVerifier, IDkgTranscriptGenerator,
-    MultiSigVerifier, Signable, ThresholdSigVerifier, ThresholdSigVerifierByPublicKey,
+    MultiSigVerifier, Signable, ThresholdSigPublicKeyShares, ThresholdSigVerifier,
+    ThresholdSigVerifierByPublicKey,
 };
 use ic_interfaces::registry::RegistryClient;
 use ic_logger::replica_logger::no_op_logger;
@@ -31,6 +33,7 @@ use ic_types::crypto::canister_threshold_sig::idkg::{
     IDkgTranscriptParams, VerifiedIDkgDealing,
 };
 use ic_types::crypto::threshold_sig::ni_dkg::DkgId;
+use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
 use ic_types::crypto::{
     BasicSigOf, CanisterSigOf, CombinedMultiSigOf, CombinedThresholdSigOf, CryptoResult,
     IndividualMultiSigOf, ThresholdSigShareOf, UserPublicKey,
@@ -42,6 +45,7 @@ use rand_chacha::ChaChaRng;
 use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 use tempfile::TempDir;
 use tokio::net::TcpStream;
 
@@ -67,6 +71,20 @@ pub struct TempCryptoComponentGeneric<C: CryptoServiceProvider> {
     temp_dir: TempDir,
 }
 
+impl<C: CryptoServiceProvider> TempCryptoComponentGeneric<C> {
+    /// See [`CryptoComponentFatClient::with_tls_handshake_limits`].
+    pub fn with_tls_handshake_limits(mut self, limits: TlsHandshakeLimits) -> Self {
+        self.crypto_component = self.crypto_component.with_tls_handshake_limits(limits);
+        self
+    }
+
+    /// See [`CryptoComponentFatClient::with_tls_handshake_timeout`].
+    pub fn with_tls_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.crypto_component = self.crypto_component.with_tls_handshake_timeout(timeout);
+        self
+    }
+}
+
 impl<C: CryptoServiceProvider> Deref for TempCryptoComponentGeneric<C> {
     type Target = CryptoComponentFatClient<C>;
 
@@ -126,7 +144,7 @@ impl TempCryptoComponent {
         node_id: NodeId,
     ) -> (Self, TlsPublicKeyCert) {
         let (config, temp_dir) = CryptoConfig::new_in_temp_dir();
-        let tls_pubkey = generate_tls_keys(&temp_dir.path().to_path_buf(), node_id);
+        let tls_pubkey = TlsKeypairBuilder::new(node_id).build(temp_dir.path());
 
         let temp_crypto =
             TempCryptoComponent::new_with(registry_client, node_id, &config, temp_dir);
@@ -167,7 +185,11 @@ impl TempCryptoComponent {
             false => None,
         };
         let tls_certificate = match selector.generate_tls_keys_and_certificate {
-            true => Some(generate_tls_keys(&temp_dir_path, node_id).to_proto()),
+            true => Some(
+                TlsKeypairBuilder::new(node_id)
+                    .build(&temp_dir_path)
+                    .to_proto(),
+            ),
             false => None,
         };
 
@@ -412,7 +434,7 @@ impl<C: CryptoServiceProvider + Send + Sync> TlsHandshake for TempCryptoComponen
     async fn perform_tls_server_handshake(
         &self,
         tcp_stream: TcpStream,
-        allowed_clients: AllowedClients,
+        allowed_clients: Arc<dyn AllowedClientsProvider>,
         registry_version: RegistryVersion,
     ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
         self.crypto_component
@@ -445,16 +467,51 @@ impl<C: CryptoServiceProvider + Send + Sync> TlsHandshake for TempCryptoComponen
             .await
     }
 
+    async fn perform_tls_server_handshake_with_ca_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        client_cas: ClientCas,
+        registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
+        self.crypto_component
+            .perform_tls_server_handshake_with_ca_client_auth(
+                tcp_stream,
+                client_cas,
+                registry_version,
+            )
+            .await
+    }
+
     async fn perform_tls_client_handshake(
         &self,
         tcp_stream: TcpStream,
         server: NodeId,
         registry_version: RegistryVersion,
-    ) -> Result<TlsStream, TlsClientHandshakeError> {
+    ) -> Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError> {
         self.crypto_component
             .perform_tls_client_handshake(tcp_stream, server, registry_version)
             .await
     }
+
+    async fn perform_tls_client_handshake_with_pinned_cert(
+        &self,
+        tcp_stream: TcpStream,
+        expected_cert: TlsPublicKeyCert,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsClientHandshakeError> {
+        self.crypto_component
+            .perform_tls_client_handshake_with_pinned_cert(tcp_stream, expected_cert)
+            .await
+    }
+
+    fn revalidate_peer(
+        &self,
+        peer: &AuthenticatedPeer,
+        handshake_registry_version: RegistryVersion,
+        registry_version: RegistryVersion,
+    ) -> Result<(), PeerRevalidationError> {
+        self.crypto_component
+            .revalidate_peer(peer, handshake_registry_version, registry_version)
+    }
 }
 
 impl<C: CryptoServiceProvider, T: Signable> BasicSigVerifier<T> for TempCryptoComponentGeneric<C> {
@@ -545,6 +602,17 @@ impl<C: CryptoServiceProvider, T: Signable> ThresholdSigVerifier<T>
     }
 }
 
+impl<C: CryptoServiceProvider> ThresholdSigPublicKeyShares for TempCryptoComponentGeneric<C> {
+    fn threshold_sig_public_key_share(
+        &self,
+        dkg_id: DkgId,
+        signer: NodeId,
+    ) -> CryptoResult<ThresholdSigPublicKey> {
+        self.crypto_component
+            .threshold_sig_public_key_share(dkg_id, signer)
+    }
+}
+
 impl<C: CryptoServiceProvider, T: Signable> ThresholdSigVerifierByPublicKey<T>
     for TempCryptoComponentGeneric<C>
 {
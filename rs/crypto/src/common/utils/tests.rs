@@ -127,7 +127,8 @@ fn should_succeed_check_keys_locally_if_all_keys_are_present() {
 }
 
 mod tls {
-    use super::super::generate_tls_keys;
+    use super::super::{csp_at_root, TlsKeypairBuilder};
+    use ic_crypto_internal_csp::api::CspSecretKeyStoreChecker;
     use ic_test_utilities::crypto::temp_dir::temp_dir;
     use ic_test_utilities::types::ids::node_test_id;
     use openssl::x509::X509VerifyResult;
@@ -139,7 +140,7 @@ mod tls {
     fn should_return_self_signed_certificate() {
         let temp_dir = temp_dir();
 
-        let cert = generate_tls_keys(&temp_dir.into_path(), node_test_id(NODE_ID));
+        let cert = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
 
         let x509_cert = cert.as_x509();
         let public_key = x509_cert.public_key().unwrap();
@@ -151,7 +152,7 @@ mod tls {
     fn should_not_set_subject_alt_name() {
         let temp_dir = temp_dir();
 
-        let cert = generate_tls_keys(&temp_dir.into_path(), node_test_id(NODE_ID));
+        let cert = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
 
         let x509_cert = cert.as_x509();
         let subject_alt_names = x509_cert.subject_alt_names();
@@ -162,7 +163,7 @@ mod tls {
     fn should_set_cert_issuer_and_subject_cn_as_node_id() {
         let temp_dir = temp_dir();
 
-        let cert = generate_tls_keys(&temp_dir.into_path(), node_test_id(NODE_ID));
+        let cert = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
 
         let x509_cert = cert.as_x509();
         let issuer_cn = issuer_cn(&x509_cert);
@@ -177,13 +178,56 @@ mod tls {
         const RFC5280_NO_WELL_DEFINED_CERTIFICATE_EXPIRATION_DATE: &str = "99991231235959Z";
         let temp_dir = temp_dir();
 
-        let cert = generate_tls_keys(&temp_dir.into_path(), node_test_id(NODE_ID));
+        let cert = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
 
         let expected_not_after =
             Asn1Time::from_str_x509(RFC5280_NO_WELL_DEFINED_CERTIFICATE_EXPIRATION_DATE).unwrap();
         assert!(cert.as_x509().not_after() == expected_not_after);
     }
 
+    #[test]
+    fn should_set_configured_not_after() {
+        const NOT_AFTER: &str = "25670102030405Z";
+        let temp_dir = temp_dir();
+
+        let cert = TlsKeypairBuilder::new(node_test_id(NODE_ID))
+            .with_not_after(NOT_AFTER)
+            .build(temp_dir.path());
+
+        assert!(cert.as_x509().not_after() == Asn1Time::from_str_x509(NOT_AFTER).unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_panic_if_not_after_is_in_the_past() {
+        let temp_dir = temp_dir();
+
+        let _panic = TlsKeypairBuilder::new(node_test_id(NODE_ID))
+            .with_not_after("20000101000000Z")
+            .build(temp_dir.path());
+    }
+
+    #[test]
+    fn should_set_random_serial_numbers() {
+        let temp_dir = temp_dir();
+
+        let cert_1 = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
+        let cert_2 = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
+
+        let serial_1 = cert_1.as_x509().serial_number().to_bn().unwrap();
+        let serial_2 = cert_2.as_x509().serial_number().to_bn().unwrap();
+        assert_ne!(serial_1, serial_2);
+    }
+
+    #[test]
+    fn should_store_secret_key_in_key_store() {
+        let temp_dir = temp_dir();
+
+        let cert = TlsKeypairBuilder::new(node_test_id(NODE_ID)).build(temp_dir.path());
+
+        assert!(csp_at_root(temp_dir.path()).sks_contains_tls_key(&cert));
+    }
+
     fn subject_cn(x509_cert: &X509) -> &X509NameEntryRef {
         x509_cert
             .subject_name()
@@ -0,0 +1,81 @@
+//! Domain-separated hashing for protocol components.
+//!
+//! Every function in this module requires a domain separator, so that
+//! digests computed for different purposes can never be confused with each
+//! other. Protocol components should use these functions instead of
+//! computing digests with openssl or other libraries directly.
+//!
+//! Unlike [`crate::crypto_hash`], the algorithms are part of the API and
+//! guaranteed not to change across registry/protocol versions.
+use ic_crypto_internal_bls12381_common::hash_to_miracl_g1;
+use ic_crypto_internal_bls12381_serde_miracl::{miracl_g1_to_bytes, G1Bytes};
+use ic_crypto_sha::Sha256;
+pub use ic_crypto_sha::{Context, DomainSeparationContext};
+use ic_types::crypto::{CryptoError, CryptoResult};
+use sha3::Digest;
+
+#[cfg(test)]
+mod tests;
+
+/// Returns the SHA-256 digest of `data` in the given domain.
+pub fn sha256(domain: &DomainSeparationContext, data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new_with_context(domain);
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Returns the SHA3-256 digest of `data` in the given domain.
+pub fn sha3_256(domain: &DomainSeparationContext, data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new_with_context(domain);
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Incremental SHA3-256 hasher, the counterpart of `ic_crypto_sha::Sha256`.
+pub struct Sha3_256 {
+    state: sha3::Sha3_256,
+}
+
+impl Sha3_256 {
+    /// Returns a new hasher whose input starts with the given domain.
+    pub fn new_with_context(context: &dyn Context) -> Self {
+        let mut hasher = Self {
+            state: sha3::Sha3_256::new(),
+        };
+        hasher.write(context.as_bytes());
+        hasher
+    }
+
+    pub fn write(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    pub fn finish(self) -> [u8; 32] {
+        self.state.finalize().into()
+    }
+}
+
+/// The size of a compressed BLS12-381 G1 point in bytes.
+pub const BLS12_381_G1_SIZE: usize = G1Bytes::SIZE;
+
+/// Hashes `msg` onto the BLS12-381 G1 curve and returns the compressed point.
+///
+/// This implements the `BLS12381G1_XMD:SHA-256_SSWU_RO_` suite of the
+/// internet draft <https://datatracker.ietf.org/doc/draft-irtf-cfrg-hash-to-curve/>.
+/// The point is encoded as in the `pairing` crate, i.e., the most significant
+/// bit of the first byte marks the point as compressed.
+///
+/// # Errors
+/// * `CryptoError::InvalidArgument` if the domain separation tag `dst` is
+///   empty or longer than 255 bytes, which the draft does not allow.
+pub fn hash_to_bls12_381_g1(dst: &[u8], msg: &[u8]) -> CryptoResult<[u8; BLS12_381_G1_SIZE]> {
+    if dst.is_empty() || dst.len() > 255 {
+        return Err(CryptoError::InvalidArgument {
+            message: format!(
+                "The domain separation tag must have 1 to 255 bytes, but has {}",
+                dst.len()
+            ),
+        });
+    }
+    Ok(miracl_g1_to_bytes(&hash_to_miracl_g1(dst, msg)).0)
+}
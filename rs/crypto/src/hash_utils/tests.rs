@@ -0,0 +1,97 @@
+#![allow(clippy::unwrap_used)]
+use super::*;
+
+const DOMAIN: &str = "ic-test-domain";
+
+#[test]
+fn should_produce_correct_sha256_digest() {
+    // SHA-256 of the byte 14 (the length of `DOMAIN`), `DOMAIN` and "abc".
+    assert_eq!(
+        hex::encode(sha256(&DomainSeparationContext::new(DOMAIN), b"abc")),
+        "a67b904b0f8707ebe74f33f075e298c0805a2bc825d6a989a3cf0dc68601e56a"
+    );
+}
+
+#[test]
+fn should_produce_correct_sha3_256_digest() {
+    // SHA3-256 of the byte 14 (the length of `DOMAIN`), `DOMAIN` and "abc".
+    assert_eq!(
+        hex::encode(sha3_256(&DomainSeparationContext::new(DOMAIN), b"abc")),
+        "f66132743685b6798eb3426d32da5f783136bd585bfa10d55cfdab69bd2c795d"
+    );
+}
+
+#[test]
+fn should_hash_length_of_empty_domain() {
+    // SHA3-256 of the single byte 0.
+    assert_eq!(
+        hex::encode(sha3_256(&DomainSeparationContext::new(""), b"")),
+        "5d53469f20fef4f8eab52b88044ede69c77a6a68a60728609fc4a65ff531e7d0"
+    );
+}
+
+#[test]
+fn should_separate_domains() {
+    let data = b"data";
+    assert_ne!(
+        sha256(&DomainSeparationContext::new("domain-1"), data),
+        sha256(&DomainSeparationContext::new("domain-2"), data)
+    );
+    assert_ne!(
+        sha3_256(&DomainSeparationContext::new("domain-1"), data),
+        sha3_256(&DomainSeparationContext::new("domain-2"), data)
+    );
+}
+
+#[test]
+fn should_hash_incrementally() {
+    let domain = DomainSeparationContext::new(DOMAIN);
+    let mut hasher = Sha3_256::new_with_context(&domain);
+    hasher.write(b"a");
+    hasher.write(b"bc");
+    assert_eq!(hasher.finish(), sha3_256(&domain, b"abc"));
+}
+
+mod hash_to_bls12_381_g1 {
+    use super::*;
+
+    // The test vectors of the BLS12381G1_XMD:SHA-256_SSWU_RO_ suite in the
+    // hash-to-curve draft. The draft gives the affine coordinates of P; the
+    // expected values are the compressed encoding of P.x, whose most
+    // significant bit is set. The y-coordinate is not the lexicographically
+    // largest for either vector, so the sign bit is not set.
+    const DST: &[u8] = b"QUUX-V01-CS02-with-BLS12381G1_XMD:SHA-256_SSWU_RO_";
+
+    #[test]
+    fn should_match_draft_test_vector_for_empty_message() {
+        assert_eq!(
+            hex::encode(&hash_to_bls12_381_g1(DST, b"").unwrap()[..]),
+            "852926add2207b76ca4fa57a8734416c8dc95e24501772c814278700eed6d1e4e8cf62d9c09db0fac349612b759e79a1"
+        );
+    }
+
+    #[test]
+    fn should_match_draft_test_vector_for_abc() {
+        assert_eq!(
+            hex::encode(&hash_to_bls12_381_g1(DST, b"abc").unwrap()[..]),
+            "83567bc5ef9c690c2ab2ecdf6a96ef1c139cc0b2f284dca0a9a7943388a49a3aee664ba5379a7655d3c68900be2f6903"
+        );
+    }
+
+    #[test]
+    fn should_reject_empty_dst() {
+        assert!(matches!(
+            hash_to_bls12_381_g1(b"", b"abc"),
+            Err(CryptoError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn should_reject_too_long_dst() {
+        assert!(matches!(
+            hash_to_bls12_381_g1(&[b'a'; 256], b"abc"),
+            Err(CryptoError::InvalidArgument { .. })
+        ));
+        assert!(hash_to_bls12_381_g1(&[b'a'; 255], b"abc").is_ok());
+    }
+}
@@ -12,6 +12,7 @@
 pub mod cli;
 mod common;
 mod hash;
+pub mod hash_utils;
 mod keygen;
 pub mod prng;
 mod sign;
@@ -25,10 +26,12 @@ pub use sign::utils::{
     threshold_sig_public_key_to_der, user_public_key_from_bytes, verify_combined_threshold_sig,
     KeyBytesContentType,
 };
+pub use tls_stub::TlsHandshakeLimits;
 
 use crate::common::utils::{derive_node_id, TempCryptoComponent};
 use crate::sign::ThresholdSigDataStoreImpl;
-use ic_config::crypto::CryptoConfig;
+use crate::tls_stub::HandshakeLimiter;
+use ic_config::crypto::{CryptoConfig, DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS};
 use ic_crypto_internal_csp::api::NodePublicKeyData;
 use ic_crypto_internal_csp::keygen::public_key_hash_as_key_id;
 use ic_crypto_internal_csp::secret_key_store::proto_store::ProtoSecretKeyStore;
@@ -36,7 +39,7 @@ use ic_crypto_internal_csp::{CryptoServiceProvider, Csp};
 use ic_crypto_internal_logmon::metrics::CryptoMetrics;
 use ic_crypto_tls_interfaces::TlsHandshake;
 use ic_interfaces::crypto::{
-    BasicSigVerifier, BasicSigVerifierByPublicKey, KeyManager, MultiSigVerifier,
+    BasicSigVerifier, BasicSigVerifierByPublicKey, BasicSigner, KeyManager, MultiSigVerifier,
     ThresholdSigVerifier, ThresholdSigVerifierByPublicKey,
 };
 use ic_interfaces::registry::RegistryClient;
@@ -48,12 +51,14 @@ use ic_types::consensus::{
 };
 use ic_types::crypto::{CryptoError, CryptoResult, KeyPurpose};
 use ic_types::messages::MessageId;
+use ic_types::node_rewards::NodeHeartbeatContent;
 use ic_types::{NodeId, PrincipalId, RegistryVersion};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use rand::rngs::OsRng;
 use rand::{CryptoRng, Rng};
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Defines the maximum number of entries contained in the
 /// `ThresholdSigDataStore`.
@@ -80,6 +85,7 @@ pub type CryptoComponent = CryptoComponentFatClient<Csp<OsRng, ProtoSecretKeySto
 /// modify the secret key store.
 pub trait CryptoComponentForNonReplicaProcess:
     KeyManager
+    + BasicSigner<NodeHeartbeatContent>
     + ThresholdSigVerifierByPublicKey<CatchUpContentProtobufBytes>
     + TlsHandshake
     + Send
@@ -91,6 +97,7 @@ pub trait CryptoComponentForNonReplicaProcess:
 // that fulfill the requirements.
 impl<T> CryptoComponentForNonReplicaProcess for T where
     T: KeyManager
+        + BasicSigner<NodeHeartbeatContent>
         + ThresholdSigVerifierByPublicKey<CatchUpContentProtobufBytes>
         + TlsHandshake
         + Send
@@ -137,6 +144,8 @@ pub struct CryptoComponentFatClient<C: CryptoServiceProvider> {
     node_id: NodeId,
     logger: ReplicaLogger,
     metrics: Arc<CryptoMetrics>,
+    tls_handshake_limiter: Option<HandshakeLimiter>,
+    tls_handshake_timeout: Duration,
 }
 
 /// A `ThresholdSigDataStore` that is wrapped by a `RwLock`.
@@ -182,6 +191,7 @@ impl<R: Rng + CryptoRng + Send + Sync> CryptoComponentFatClient<Csp<R, ProtoSecr
             registry_client,
             node_id,
         )
+        .with_tls_handshake_timeout(config.tls_handshake_timeout())
     }
 }
 
@@ -200,8 +210,28 @@ impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
             node_id,
             logger,
             metrics: Arc::new(CryptoMetrics::none()),
+            tls_handshake_limiter: None,
+            tls_handshake_timeout: Duration::from_secs(DEFAULT_TLS_HANDSHAKE_TIMEOUT_SECS),
         }
     }
+
+    /// Applies `limits` to the TLS server handshakes performed by this crypto
+    /// component instead of the limits of the `CryptoConfig`. Handshakes
+    /// exceeding a limit are rejected with
+    /// `TlsServerHandshakeError::HandshakeRejectedOverload`.
+    pub fn with_tls_handshake_limits(mut self, limits: TlsHandshakeLimits) -> Self {
+        self.tls_handshake_limiter = Some(HandshakeLimiter::new(limits));
+        self
+    }
+
+    /// Aborts TLS handshakes performed by this crypto component that do not
+    /// complete within `timeout` instead of the timeout of the `CryptoConfig`,
+    /// e.g., to exercise timeouts in tests. Such handshakes fail with
+    /// `TlsServerHandshakeError::Timeout` or `TlsClientHandshakeError::Timeout`.
+    pub fn with_tls_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.tls_handshake_timeout = timeout;
+        self
+    }
 }
 
 impl<C: CryptoServiceProvider> fmt::Debug for CryptoComponentFatClient<C> {
@@ -274,6 +304,10 @@ impl CryptoComponentFatClient<Csp<OsRng, ProtoSecretKeyStore>> {
             node_id,
             logger,
             metrics,
+            tls_handshake_limiter: Some(HandshakeLimiter::new(TlsHandshakeLimits::from_config(
+                config,
+            ))),
+            tls_handshake_timeout: config.tls_handshake_timeout(),
         }
     }
 
@@ -292,6 +326,8 @@ impl CryptoComponentFatClient<Csp<OsRng, ProtoSecretKeyStore>> {
             node_id,
             logger,
             metrics,
+            tls_handshake_limiter: None,
+            tls_handshake_timeout: config.tls_handshake_timeout(),
         }
     }
 
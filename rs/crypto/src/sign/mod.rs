@@ -9,8 +9,8 @@ use ic_crypto_internal_csp::types::{CspPublicKey, CspSignature};
 use ic_crypto_internal_csp::CryptoServiceProvider;
 use ic_interfaces::crypto::{
     BasicSigVerifier, BasicSigVerifierByPublicKey, BasicSigner, CanisterSigVerifier,
-    IDkgTranscriptGenerator, MultiSigVerifier, MultiSigner, Signable, ThresholdSigVerifier,
-    ThresholdSigVerifierByPublicKey, ThresholdSigner,
+    IDkgTranscriptGenerator, MultiSigVerifier, MultiSigner, Signable, ThresholdSigPublicKeyShares,
+    ThresholdSigVerifier, ThresholdSigVerifierByPublicKey, ThresholdSigner,
 };
 use ic_logger::{debug, new_logger};
 use ic_types::crypto::canister_threshold_sig::error::{
@@ -24,6 +24,7 @@ use ic_types::crypto::canister_threshold_sig::idkg::{
 };
 use ic_types::crypto::threshold_sig::errors::threshold_sign_error::ThresholdSignError;
 use ic_types::crypto::threshold_sig::ni_dkg::DkgId;
+use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
 use ic_types::crypto::KeyPurpose::CommitteeSigning;
 use ic_types::crypto::{
     AlgorithmId, BasicSig, BasicSigOf, CanisterSigOf, CombinedMultiSig, CombinedMultiSigOf,
@@ -376,6 +377,33 @@ impl<C: CryptoServiceProvider, T: Signable> ThresholdSigVerifier<T>
     }
 }
 
+impl<C: CryptoServiceProvider> ThresholdSigPublicKeyShares for CryptoComponentFatClient<C> {
+    fn threshold_sig_public_key_share(
+        &self,
+        dkg_id: DkgId,
+        signer: NodeId,
+    ) -> CryptoResult<ThresholdSigPublicKey> {
+        let logger = new_logger!(&self.logger;
+            crypto.trait_name => "ThresholdSigPublicKeyShares",
+            crypto.method_name => "threshold_sig_public_key_share",
+            crypto.dkg_id => format!("{}", dkg_id),
+        );
+        debug!(logger; crypto.description => format!("start; signer: {}", signer),);
+        let result = ThresholdSigVerifierInternal::individual_public_key(
+            &self.lockable_threshold_sig_data_store,
+            &self.csp,
+            dkg_id,
+            signer,
+        );
+        debug!(logger;
+            crypto.description => format!("end; signer: {}", signer),
+            crypto.is_ok => result.is_ok(),
+            crypto.error => log_err(result.as_ref().err()),
+        );
+        result
+    }
+}
+
 impl<C: CryptoServiceProvider, T: Signable> ThresholdSigVerifierByPublicKey<T>
     for CryptoComponentFatClient<C>
 {
@@ -2,12 +2,15 @@ use super::*;
 pub use crate::sign::threshold_sig::store::ThresholdSigDataStore;
 pub use crate::sign::threshold_sig::store::ThresholdSigDataStoreImpl;
 use crate::sign::threshold_sig::store::TranscriptData;
-use ic_crypto_internal_csp::api::{CspThresholdSignError, ThresholdSignatureCspClient};
+use ic_crypto_internal_csp::api::{
+    CspThresholdSignError, SecretKeyStoreHint, ThresholdSignatureCspClient,
+};
 use ic_crypto_internal_csp::types::CspPublicCoefficients;
 use ic_crypto_internal_types::sign::threshold_sig::public_key::CspThresholdSigPublicKey;
 use ic_registry_client::helper::crypto::CryptoRegistry;
 use ic_types::crypto::threshold_sig::errors::threshold_sig_data_not_found_error::ThresholdSigDataNotFoundError;
 use ic_types::crypto::threshold_sig::ni_dkg::{DkgId, NiDkgTag, NiDkgTranscript};
+use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
 use ic_types::crypto::{CombinedThresholdSigOf, ThresholdSigShareOf};
 use ic_types::{IDkgId, NodeIndex, SubnetId};
 use std::cmp;
@@ -86,7 +89,22 @@ fn map_threshold_sign_error_or_panic(
     dkg_id: DkgId,
 ) -> ThresholdSignError {
     match error {
-        CspThresholdSignError::SecretKeyNotFound { algorithm, key_id } => {
+        // The key was removed since the transcript was loaded because the
+        // transcript is no longer active, so signing raced with the removal of
+        // keys at an epoch boundary rather than the key being lost.
+        CspThresholdSignError::SecretKeyNotFound {
+            algorithm,
+            key_id,
+            secret_key_store_hint: SecretKeyStoreHint::RecentlyRetired,
+            ..
+        } => ThresholdSignError::SecretKeyRetired {
+            dkg_id,
+            algorithm,
+            key_id,
+        },
+        CspThresholdSignError::SecretKeyNotFound {
+            algorithm, key_id, ..
+        } => {
             // If the secret key was not found, reloading the transcript will not help
             // because we are sure at this point that the transcript was already
             // successfully loaded. Thus, we don't return a ThresholdSigDataNotFound error
@@ -146,9 +164,34 @@ impl ThresholdSigVerifierInternal {
     }
 }
 
+impl ThresholdSigVerifierInternal {
+    /// Returns the individual public key (i.e., the public key share) of
+    /// `signer` for the given `dkg_id`. See
+    /// `lazily_calculated_public_key_from_store` for errors and panics.
+    pub fn individual_public_key<C: ThresholdSignatureCspClient>(
+        lockable_threshold_sig_data_store: &LockableThresholdSigDataStore,
+        threshold_sig_csp_client: &C,
+        dkg_id: DkgId,
+        signer: NodeId,
+    ) -> CryptoResult<ThresholdSigPublicKey> {
+        lazily_calculated_public_key_from_store(
+            lockable_threshold_sig_data_store,
+            threshold_sig_csp_client,
+            dkg_id,
+            signer,
+        )
+        .map(ThresholdSigPublicKey::from)
+    }
+}
+
 /// Returns the individual public key for the given `node_id` and `dkg_id` from
 /// the store if present and otherwise calculates and stores it.
 ///
+/// If the key is not present, the individual public keys of _all_ nodes of the
+/// transcript for `dkg_id` are calculated and stored at once. Deriving a key is
+/// dominated by parsing the public coefficients, which this does only once per
+/// transcript rather than once per signer.
+///
 /// Note regarding concurrency: checking whether a public key is already
 /// available in the store is done with a read lock while inserting a
 /// key into the store is done with a write lock. This non-atomic approach
@@ -206,11 +249,17 @@ fn calculate_and_store_public_key_or_panic<C: ThresholdSignatureCspClient>(
     node_id: NodeId,
 ) -> CryptoResult<CspThresholdSigPublicKey> {
     let transcript_data = transcript_data_from_store(dkg_id, lockable_threshold_sig_data_store)?;
-    let (public_coeffs, node_index) = coeffs_and_index(transcript_data, dkg_id, node_id)?;
-    let public_key = threshold_sig_csp_client
-        .threshold_individual_public_key(
+    index_for_node_id(&transcript_data, node_id, dkg_id)?;
+    let public_coeffs = transcript_data.public_coefficients().clone();
+    let (node_ids, node_indices): (Vec<NodeId>, Vec<NodeIndex>) = transcript_data
+        .indices()
+        .iter()
+        .map(|(node_id, node_index)| (*node_id, *node_index))
+        .unzip();
+    let public_keys = threshold_sig_csp_client
+        .threshold_individual_public_keys(
             AlgorithmId::from(&public_coeffs),
-            node_index,
+            &node_indices,
             public_coeffs,
         )
         .unwrap_or_else(|error| {
@@ -221,23 +270,20 @@ fn calculate_and_store_public_key_or_panic<C: ThresholdSignatureCspClient>(
                 dkg_id, node_id, error
             )
         });
-    lockable_threshold_sig_data_store
-        .write()
-        .insert_individual_public_key(dkg_id, node_id, public_key);
-    Ok(public_key)
-}
-
-fn coeffs_and_index(
-    transcript_data: TranscriptData,
-    dkg_id: DkgId,
-    node_id: NodeId,
-) -> CryptoResult<(CspPublicCoefficients, NodeIndex)> {
-    let public_coeffs = transcript_data.public_coefficients().clone();
-    let node_index = transcript_data
-        .index(node_id)
-        .cloned()
-        .ok_or_else(|| node_id_missing_error(node_id, dkg_id))?;
-    Ok((public_coeffs, node_index))
+    assert_eq!(
+        public_keys.len(),
+        node_ids.len(),
+        "The CSP must return one individual threshold public key per node index"
+    );
+    let mut public_key_for_node_id = None;
+    let mut store = lockable_threshold_sig_data_store.write();
+    for (id, public_key) in node_ids.into_iter().zip(public_keys) {
+        if id == node_id {
+            public_key_for_node_id = Some(public_key);
+        }
+        store.insert_individual_public_key(dkg_id, id, public_key);
+    }
+    Ok(public_key_for_node_id.expect("the node index was checked to be present"))
 }
 
 fn panic_on_illegal_individual_sig_verification_state(error: CryptoError) -> CryptoError {
@@ -67,6 +67,11 @@ impl TranscriptData {
     pub fn index(&self, node_id: NodeId) -> Option<&NodeIndex> {
         self.indices.get(&node_id)
     }
+
+    /// Returns a reference to the indices of all nodes.
+    pub fn indices(&self) -> &BTreeMap<NodeId, NodeIndex> {
+        &self.indices
+    }
 }
 
 /// Threshold signature data store that limits the number of DKG IDs
@@ -4,6 +4,7 @@ use super::*;
 use crate::common::test_utils::mockall_csp::MockAllCryptoServiceProvider;
 use crate::sign::tests::KEY_ID;
 use crate::sign::threshold_sig::ThresholdSigDataStore;
+use ic_crypto_internal_csp::api::MessageHashPrefix;
 use ic_crypto_internal_csp::types::{CspPublicCoefficients, ThresBls12_381_Signature};
 use ic_crypto_internal_threshold_sig_bls12381::types::{
     CombinedSignatureBytes, IndividualSignatureBytes,
@@ -12,7 +13,7 @@ use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::ni_dkg_groth20_bls12_
 use ic_crypto_internal_types::sign::threshold_sig::public_key::bls12_381::PublicKeyBytes;
 use ic_crypto_internal_types::sign::threshold_sig::public_key::CspThresholdSigPublicKey;
 use ic_interfaces::crypto::SignableMock;
-use ic_test_utilities::types::ids::{NODE_1, SUBNET_0, SUBNET_1};
+use ic_test_utilities::types::ids::{NODE_1, NODE_2, SUBNET_0, SUBNET_1};
 use ic_types::crypto::threshold_sig::ni_dkg::{
     NiDkgId, NiDkgTag, NiDkgTargetId, NiDkgTargetSubnet,
 };
@@ -170,6 +171,32 @@ mod sign_threshold {
         )
     }
 
+    #[test]
+    fn should_return_secret_key_retired_error_if_csp_reports_recently_retired_key() {
+        let csp = csp_with_sign_returning_once(Err(secret_key_not_found_with_hint(
+            SecretKeyStoreHint::RecentlyRetired,
+        )));
+        let threshold_sig_data_store =
+            threshold_sig_data_store_with_coeffs(pub_coeffs(), DkgId::NiDkgId(NI_DKG_ID));
+
+        let sig_share_result = ThresholdSignerInternal::sign_threshold(
+            &threshold_sig_data_store,
+            &csp,
+            &signable_mock(),
+            DkgId::NiDkgId(NI_DKG_ID),
+        );
+
+        assert_eq!(
+            sig_share_result,
+            Err(ThresholdSignError::SecretKeyRetired {
+                dkg_id: DkgId::NiDkgId(NI_DKG_ID),
+                algorithm: AlgorithmId::Placeholder,
+                key_id: KeyId::from(KEY_ID),
+            })
+        );
+        assert!(CryptoError::from(sig_share_result.unwrap_err()).is_secret_key_retired());
+    }
+
     #[test]
     fn should_return_error_if_transcript_data_not_in_store() {
         let csp = MockAllCryptoServiceProvider::new();
@@ -216,9 +243,17 @@ mod sign_threshold {
     }
 
     fn secret_key_not_found() -> CspThresholdSignError {
+        secret_key_not_found_with_hint(SecretKeyStoreHint::Unknown)
+    }
+
+    fn secret_key_not_found_with_hint(
+        secret_key_store_hint: SecretKeyStoreHint,
+    ) -> CspThresholdSignError {
         CspThresholdSignError::SecretKeyNotFound {
             algorithm: AlgorithmId::Placeholder,
             key_id: KeyId::from(KEY_ID),
+            message_hash_prefix: MessageHashPrefix::of_message(&signable_mock().as_signed_bytes()),
+            secret_key_store_hint,
         }
     }
 
@@ -292,14 +327,14 @@ mod verify_threshold_sig_share {
         let mut csp = MockAllCryptoServiceProvider::new();
         csp.expect_threshold_verify_individual_signature()
             .return_const(Ok(()));
-        csp.expect_threshold_individual_public_key()
-            .withf(move |alg_id, node_index, public_coeffs| {
+        csp.expect_threshold_individual_public_keys()
+            .withf(move |alg_id, node_indices, public_coeffs| {
                 *alg_id == AlgorithmId::try_from(&pub_coeffs).unwrap()
-                    && *node_index == 3
+                    && *node_indices == [3]
                     && *public_coeffs == pub_coeffs
             })
             .times(1)
-            .return_const(Ok(csp_public_key));
+            .return_const(Ok(vec![csp_public_key]));
 
         let _ = ThresholdSigVerifierInternal::verify_threshold_sig_share(
             &threshold_sig_data_store,
@@ -323,9 +358,9 @@ mod verify_threshold_sig_share {
         let mut csp = MockAllCryptoServiceProvider::new();
         let (expected_msg, expected_sig, expected_pk) =
             (message.clone(), sig_share.clone(), csp_public_key);
-        csp.expect_threshold_individual_public_key()
+        csp.expect_threshold_individual_public_keys()
             .times(1)
-            .return_const(Ok(csp_public_key));
+            .return_const(Ok(vec![csp_public_key]));
         csp.expect_threshold_verify_individual_signature()
             .withf(move |alg_id, msg, sig, pubkey| {
                 *alg_id == AlgorithmId::from(expected_pk)
@@ -374,9 +409,9 @@ mod verify_threshold_sig_share {
         let threshold_sig_data_store =
             threshold_sig_data_store_with_non_empty_coeffs_and_indices_for_dkg_id(I_DKG_ID);
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key()
+        csp.expect_threshold_individual_public_keys()
             .times(1)
-            .return_const(Ok(csp_public_key));
+            .return_const(Ok(vec![csp_public_key]));
         csp.expect_threshold_verify_individual_signature()
             .times(1)
             .return_const(Ok(()));
@@ -424,9 +459,9 @@ mod verify_threshold_sig_share {
         let threshold_sig_data_store =
             threshold_sig_data_store_with_non_empty_coeffs_and_indices_for_dkg_id(I_DKG_ID);
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key()
+        csp.expect_threshold_individual_public_keys()
             .times(1)
-            .return_const(Ok(csp_public_key));
+            .return_const(Ok(vec![csp_public_key]));
         csp.expect_threshold_verify_individual_signature()
             .times(1)
             .return_const(Err(verification_error.clone()));
@@ -450,9 +485,9 @@ mod verify_threshold_sig_share {
         let threshold_sig_data_store =
             threshold_sig_data_store_with_non_empty_coeffs_and_indices_for_dkg_id(I_DKG_ID);
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key()
+        csp.expect_threshold_individual_public_keys()
             .times(1)
-            .return_const(Ok(csp_public_key));
+            .return_const(Ok(vec![csp_public_key]));
         csp.expect_threshold_verify_individual_signature()
             .times(1)
             .return_const(Ok(()));
@@ -474,6 +509,50 @@ mod verify_threshold_sig_share {
         );
     }
 
+    #[test]
+    fn should_store_public_keys_of_all_nodes_when_calculating_public_key() {
+        let (sig_share, message) = (sig_share(), signable_mock());
+        let threshold_sig_data_store = threshold_sig_data_store_with(
+            DkgId::IDkgId(I_DKG_ID),
+            pub_coeffs(),
+            indices(vec![(NODE_ID, 3), (NODE_2, 5)]),
+        );
+        let mut csp = MockAllCryptoServiceProvider::new();
+        csp.expect_threshold_individual_public_keys()
+            .times(1)
+            .returning(|_, node_indices, _| {
+                Ok(node_indices
+                    .iter()
+                    .map(|node_index| csp_public_key_for_index(*node_index))
+                    .collect())
+            });
+        csp.expect_threshold_verify_individual_signature()
+            .times(2)
+            .return_const(Ok(()));
+
+        for node_id in &[NODE_ID, NODE_2] {
+            let result = ThresholdSigVerifierInternal::verify_threshold_sig_share(
+                &threshold_sig_data_store,
+                &csp,
+                &sig_share,
+                &message,
+                DkgId::IDkgId(I_DKG_ID),
+                *node_id,
+            );
+            assert!(result.is_ok());
+        }
+
+        let store = threshold_sig_data_store.read();
+        assert_eq!(
+            store.individual_public_key(DkgId::IDkgId(I_DKG_ID), NODE_ID),
+            Some(&csp_public_key_for_index(3))
+        );
+        assert_eq!(
+            store.individual_public_key(DkgId::IDkgId(I_DKG_ID), NODE_2),
+            Some(&csp_public_key_for_index(5))
+        );
+    }
+
     #[test]
     fn should_not_regenerate_public_key_if_in_store_already() {
         let (sig_share, message, csp_public_key) = (sig_share(), signable_mock(), csp_public_key());
@@ -483,7 +562,7 @@ mod verify_threshold_sig_share {
             csp_public_key,
         );
         let mut csp = csp_with_verify_indiv_sig_returning_once(Ok(()));
-        csp.expect_threshold_individual_public_key().times(0);
+        csp.expect_threshold_individual_public_keys().times(0);
 
         let _ = ThresholdSigVerifierInternal::verify_threshold_sig_share(
             &threshold_sig_data_store,
@@ -500,7 +579,7 @@ mod verify_threshold_sig_share {
         let (sig_share, message) = (sig_share(), signable_mock());
         let threshold_sig_data_store = LockableThresholdSigDataStore::new();
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key().times(0);
+        csp.expect_threshold_individual_public_keys().times(0);
         csp.expect_threshold_verify_individual_signature().times(0);
 
         let verification_result = ThresholdSigVerifierInternal::verify_threshold_sig_share(
@@ -527,7 +606,7 @@ mod verify_threshold_sig_share {
         let threshold_sig_data_store =
             threshold_sig_data_store_with(DkgId::IDkgId(I_DKG_ID), pub_coeffs(), indices(vec![]));
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key().times(0);
+        csp.expect_threshold_individual_public_keys().times(0);
         csp.expect_threshold_verify_individual_signature().times(0);
 
         let verification_result = ThresholdSigVerifierInternal::verify_threshold_sig_share(
@@ -557,7 +636,7 @@ mod verify_threshold_sig_share {
         let threshold_sig_data_store =
             threshold_sig_data_store_with_non_empty_coeffs_and_indices_for_dkg_id(I_DKG_ID);
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key().times(0);
+        csp.expect_threshold_individual_public_keys().times(0);
         csp.expect_threshold_verify_individual_signature().times(0);
 
         let verification_result = ThresholdSigVerifierInternal::verify_threshold_sig_share(
@@ -666,10 +745,10 @@ mod verify_threshold_sig_share {
     }
 
     fn csp_with_indiv_pk_returning_once(
-        result: CryptoResult<CspThresholdSigPublicKey>,
+        result: CryptoResult<Vec<CspThresholdSigPublicKey>>,
     ) -> MockAllCryptoServiceProvider {
         let mut csp = MockAllCryptoServiceProvider::new();
-        csp.expect_threshold_individual_public_key()
+        csp.expect_threshold_individual_public_keys()
             .times(1)
             .return_const(result);
         csp
@@ -686,6 +765,79 @@ mod verify_threshold_sig_share {
     }
 }
 
+mod individual_public_key {
+    use super::*;
+
+    #[test]
+    fn should_return_public_key_from_store_without_calling_csp() {
+        let threshold_sig_data_store = threshold_sig_data_store_with_coeffs_and_pubkey(
+            DkgId::NiDkgId(NI_DKG_ID),
+            NODE_ID,
+            csp_public_key(),
+        );
+        let mut csp = MockAllCryptoServiceProvider::new();
+        csp.expect_threshold_individual_public_keys().times(0);
+
+        let public_key = ThresholdSigVerifierInternal::individual_public_key(
+            &threshold_sig_data_store,
+            &csp,
+            DkgId::NiDkgId(NI_DKG_ID),
+            NODE_ID,
+        );
+
+        assert_eq!(
+            public_key,
+            Ok(ThresholdSigPublicKey::from(csp_public_key()))
+        );
+    }
+
+    #[test]
+    fn should_calculate_public_key_if_not_in_store() {
+        let threshold_sig_data_store = threshold_sig_data_store_with(
+            DkgId::NiDkgId(NI_DKG_ID),
+            pub_coeffs(),
+            indices(vec![(NODE_ID, 3)]),
+        );
+        let mut csp = MockAllCryptoServiceProvider::new();
+        csp.expect_threshold_individual_public_keys()
+            .times(1)
+            .return_const(Ok(vec![csp_public_key()]));
+
+        let public_key = ThresholdSigVerifierInternal::individual_public_key(
+            &threshold_sig_data_store,
+            &csp,
+            DkgId::NiDkgId(NI_DKG_ID),
+            NODE_ID,
+        );
+
+        assert_eq!(
+            public_key,
+            Ok(ThresholdSigPublicKey::from(csp_public_key()))
+        );
+    }
+
+    #[test]
+    fn should_fail_with_data_not_found_if_transcript_data_missing() {
+        let threshold_sig_data_store = LockableThresholdSigDataStore::new();
+        let mut csp = MockAllCryptoServiceProvider::new();
+        csp.expect_threshold_individual_public_keys().times(0);
+
+        let result = ThresholdSigVerifierInternal::individual_public_key(
+            &threshold_sig_data_store,
+            &csp,
+            DkgId::NiDkgId(NI_DKG_ID),
+            NODE_ID,
+        );
+
+        assert_eq!(
+            result.unwrap_err(),
+            CryptoError::ThresholdSigDataNotFound {
+                dkg_id: DkgId::NiDkgId(NI_DKG_ID)
+            }
+        );
+    }
+}
+
 mod combine_threshold_sig_shares {
     use super::*;
     use ic_test_utilities::types::ids::{NODE_1, NODE_2, NODE_3};
@@ -1570,6 +1722,12 @@ fn csp_public_key() -> CspThresholdSigPublicKey {
     CspThresholdSigPublicKey::ThresBls12_381(PublicKeyBytes([42; PublicKeyBytes::SIZE]))
 }
 
+fn csp_public_key_for_index(node_index: NodeIndex) -> CspThresholdSigPublicKey {
+    CspThresholdSigPublicKey::ThresBls12_381(PublicKeyBytes(
+        [node_index as u8; PublicKeyBytes::SIZE],
+    ))
+}
+
 fn sig_share() -> ThresholdSigShareOf<SignableMock> {
     let csp_sig = CspSignature::thres_bls12381_indiv_from_array_of(42);
     ThresholdSigShareOf::try_from(csp_sig).unwrap()
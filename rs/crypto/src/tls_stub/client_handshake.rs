@@ -1,10 +1,11 @@
 use crate::tls_stub::{
     node_id_from_cert_subject_common_name, tls_cert_from_registry, TlsCertFromRegistryError,
 };
-use ic_crypto_internal_csp::api::CspTlsClientHandshake;
+use ic_crypto_internal_csp::api::{CspTlsClientHandshake, NodePublicKeyData};
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_crypto_tls_interfaces::{
-    MalformedPeerCertificateError, PeerNotAllowedError, TlsClientHandshakeError, TlsStream,
+    AuthenticatedPeer, MalformedPeerCertificateError, PeerNotAllowedError, TlsClientHandshakeError,
+    TlsStream,
 };
 use ic_interfaces::registry::RegistryClient;
 use ic_types::{NodeId, RegistryVersion};
@@ -18,7 +19,7 @@ pub async fn perform_tls_client_handshake<C: CspTlsClientHandshake>(
     tcp_stream: TcpStream,
     server: NodeId,
     registry_version: RegistryVersion,
-) -> Result<TlsStream, TlsClientHandshakeError> {
+) -> Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError> {
     let self_tls_cert = tls_cert_from_registry(registry_client, self_node_id, registry_version)
         .map_err(|e| map_cert_from_registry_error(e, CertFromRegistryOwner::Myself))?;
     let trusted_server_cert = tls_cert_from_registry(registry_client, server, registry_version)
@@ -29,7 +30,37 @@ pub async fn perform_tls_client_handshake<C: CspTlsClientHandshake>(
         .await?;
 
     check_cert(server, &trusted_server_cert, &peer_cert)?;
-    Ok(tls_stream)
+    Ok((tls_stream, trusted_server_cert))
+}
+
+pub async fn perform_tls_client_handshake_with_pinned_cert<
+    C: CspTlsClientHandshake + NodePublicKeyData,
+>(
+    csp: &C,
+    tcp_stream: TcpStream,
+    expected_cert: TlsPublicKeyCert,
+) -> Result<(TlsStream, AuthenticatedPeer), TlsClientHandshakeError> {
+    let self_tls_cert = csp
+        .node_public_keys()
+        .tls_certificate
+        .ok_or(TlsClientHandshakeError::SelfCertificateNotFound)?;
+    let self_tls_cert =
+        TlsPublicKeyCert::new_from_der(self_tls_cert.certificate_der).map_err(|e| {
+            TlsClientHandshakeError::MalformedSelfCertificate {
+                internal_error: e.internal_error,
+            }
+        })?;
+
+    let (tls_stream, peer_cert) = csp
+        .perform_tls_client_handshake(tcp_stream, self_tls_cert, expected_cert.clone())
+        .await?;
+
+    if peer_cert != expected_cert {
+        return Err(TlsClientHandshakeError::ServerNotAllowed(
+            PeerNotAllowedError::CertificatesDiffer,
+        ));
+    }
+    Ok((tls_stream, AuthenticatedPeer::Cert(expected_cert)))
 }
 
 fn check_cert(
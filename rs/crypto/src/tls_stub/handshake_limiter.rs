@@ -0,0 +1,226 @@
+//! Admission control for TLS server handshakes.
+//!
+//! Handshakes are comparatively expensive for the server, so a flood of new
+//! connections could exhaust the threads performing crypto operations. The
+//! limiter bounds the number of server handshakes in flight and the rate at
+//! which a single source IP may start new handshakes. Handshakes exceeding a
+//! limit are rejected before any cryptographic work is done.
+use ic_config::crypto::CryptoConfig;
+use ic_crypto_tls_interfaces::HandshakeOverload;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The length of the window in which the handshakes per source are counted.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// The maximum number of sources whose handshakes are counted. Beyond that,
+/// the least recently seen source is forgotten.
+const MAX_TRACKED_SOURCES: usize = 10_000;
+
+/// Limits applied to TLS server handshakes. `None` disables a limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TlsHandshakeLimits {
+    /// The maximum number of server handshakes that are in flight at the same
+    /// time.
+    pub max_concurrent_handshakes: Option<usize>,
+    /// The maximum number of server handshakes that a single source IP may
+    /// start per second.
+    pub max_handshakes_per_source_per_second: Option<u32>,
+}
+
+impl TlsHandshakeLimits {
+    /// Returns the limits configured in `config`.
+    pub fn from_config(config: &CryptoConfig) -> Self {
+        Self {
+            max_concurrent_handshakes: config.tls_max_concurrent_handshakes,
+            max_handshakes_per_source_per_second: config.tls_max_handshakes_per_source_per_second,
+        }
+    }
+}
+
+pub(crate) struct HandshakeLimiter {
+    limits: TlsHandshakeLimits,
+    in_flight: AtomicUsize,
+    // The start of the current window and the number of handshakes started
+    // in it, per source.
+    sources: Mutex<LruCache<IpAddr, (Instant, u32)>>,
+}
+
+impl HandshakeLimiter {
+    pub fn new(limits: TlsHandshakeLimits) -> Self {
+        Self {
+            limits,
+            in_flight: AtomicUsize::new(0),
+            sources: Mutex::new(LruCache::new(MAX_TRACKED_SOURCES)),
+        }
+    }
+
+    /// Admits a new handshake from `source` at time `now`. The handshake counts
+    /// as in flight until the returned permit is dropped. Only admitted
+    /// handshakes count towards the rate of their source.
+    pub fn admit(
+        &self,
+        source: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<HandshakePermit<'_>, HandshakeOverload> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        // Dropping the permit on rejection releases the slot again.
+        let permit = HandshakePermit {
+            in_flight: &self.in_flight,
+        };
+        if let Some(limit) = self.limits.max_concurrent_handshakes {
+            if in_flight >= limit {
+                return Err(HandshakeOverload::TooManyConcurrentHandshakes { limit });
+            }
+        }
+        if let (Some(limit), Some(source)) =
+            (self.limits.max_handshakes_per_source_per_second, source)
+        {
+            self.count_handshake_from(source, limit, now)?;
+        }
+        Ok(permit)
+    }
+
+    fn count_handshake_from(
+        &self,
+        source: IpAddr,
+        limit: u32,
+        now: Instant,
+    ) -> Result<(), HandshakeOverload> {
+        let mut sources = self.sources.lock();
+        let (mut window_start, mut count) = sources.pop(&source).unwrap_or((now, 0));
+        if now.duration_since(window_start) >= RATE_WINDOW {
+            window_start = now;
+            count = 0;
+        }
+        let result = if count >= limit {
+            Err(HandshakeOverload::SourceRateExceeded {
+                source_ip: source,
+                limit,
+            })
+        } else {
+            count += 1;
+            Ok(())
+        };
+        sources.put(source, (window_start, count));
+        result
+    }
+}
+
+/// A handshake admitted by a [`HandshakeLimiter`].
+pub(crate) struct HandshakePermit<'a> {
+    in_flight: &'a AtomicUsize,
+}
+
+impl Drop for HandshakePermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const SOURCE_1: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const SOURCE_2: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    #[test]
+    fn should_admit_everything_without_limits() {
+        let limiter = HandshakeLimiter::new(TlsHandshakeLimits::default());
+        let now = Instant::now();
+
+        let permits: Vec<_> = (0..100)
+            .map(|_| limiter.admit(Some(SOURCE_1), now).unwrap())
+            .collect();
+
+        assert_eq!(permits.len(), 100);
+    }
+
+    #[test]
+    fn should_reject_handshakes_exceeding_concurrency_limit() {
+        let limiter = HandshakeLimiter::new(TlsHandshakeLimits {
+            max_concurrent_handshakes: Some(2),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        let first = limiter.admit(Some(SOURCE_1), now).unwrap();
+        let _second = limiter.admit(Some(SOURCE_2), now).unwrap();
+        assert_eq!(
+            limiter.admit(None, now).err(),
+            Some(HandshakeOverload::TooManyConcurrentHandshakes { limit: 2 })
+        );
+
+        drop(first);
+        assert!(limiter.admit(None, now).is_ok());
+    }
+
+    #[test]
+    fn should_reject_handshakes_exceeding_rate_of_source() {
+        let limiter = HandshakeLimiter::new(TlsHandshakeLimits {
+            max_handshakes_per_source_per_second: Some(2),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.admit(Some(SOURCE_1), now).is_ok());
+        assert!(limiter.admit(Some(SOURCE_1), now).is_ok());
+        assert_eq!(
+            limiter.admit(Some(SOURCE_1), now).err(),
+            Some(HandshakeOverload::SourceRateExceeded {
+                source_ip: SOURCE_1,
+                limit: 2
+            })
+        );
+        // Other sources are not affected.
+        assert!(limiter.admit(Some(SOURCE_2), now).is_ok());
+        // The source may start new handshakes in the next window.
+        assert!(limiter.admit(Some(SOURCE_1), now + RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn should_not_count_handshakes_rejected_for_concurrency_towards_rate() {
+        let limiter = HandshakeLimiter::new(TlsHandshakeLimits {
+            max_concurrent_handshakes: Some(1),
+            max_handshakes_per_source_per_second: Some(2),
+        });
+        let now = Instant::now();
+
+        let first = limiter.admit(Some(SOURCE_1), now).unwrap();
+        for _ in 0..10 {
+            assert_eq!(
+                limiter.admit(Some(SOURCE_1), now).err(),
+                Some(HandshakeOverload::TooManyConcurrentHandshakes { limit: 1 })
+            );
+        }
+        drop(first);
+
+        assert!(limiter.admit(Some(SOURCE_1), now).is_ok());
+    }
+
+    #[test]
+    fn should_bound_the_number_of_tracked_sources() {
+        let limiter = HandshakeLimiter::new(TlsHandshakeLimits {
+            max_handshakes_per_source_per_second: Some(1),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        for i in 0..MAX_TRACKED_SOURCES as u32 + 1 {
+            let source = IpAddr::V4(Ipv4Addr::from(i));
+            assert!(limiter.admit(Some(source), now).is_ok());
+        }
+
+        assert_eq!(limiter.sources.lock().len(), MAX_TRACKED_SOURCES);
+        // The least recently seen source was forgotten.
+        assert!(limiter
+            .admit(Some(IpAddr::V4(Ipv4Addr::from(0))), now)
+            .is_ok());
+    }
+}
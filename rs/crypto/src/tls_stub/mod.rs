@@ -2,7 +2,8 @@ use super::*;
 use async_trait::async_trait;
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_crypto_tls_interfaces::{
-    AllowedClients, AuthenticatedPeer, MalformedPeerCertificateError, Peer,
+    AllowedClients, AllowedClientsProvider, AuthenticatedPeer, ClientCas, HandshakeOverload,
+    MalformedPeerCertificateError, Peer, PeerNotAllowedError, PeerRevalidationError,
     TlsClientHandshakeError, TlsHandshake, TlsServerHandshakeError, TlsStream,
 };
 use ic_logger::{debug, new_logger};
@@ -11,12 +12,99 @@ use ic_types::{NodeId, PrincipalId, RegistryVersion};
 use openssl::nid::Nid;
 use openssl::string::OpensslString;
 use openssl::x509::{X509NameEntries, X509NameEntryRef};
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
 
 mod client_handshake;
+mod handshake_limiter;
+mod peer_revalidation;
 mod server_handshake;
 
+pub use handshake_limiter::TlsHandshakeLimits;
+pub(crate) use handshake_limiter::{HandshakeLimiter, HandshakePermit};
+
+impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
+    /// Admits a server handshake on `tcp_stream` if handshake limits are
+    /// configured. The handshake counts as in flight until the returned permit
+    /// is dropped.
+    fn admit_tls_server_handshake(
+        &self,
+        tcp_stream: &TcpStream,
+    ) -> Result<Option<HandshakePermit<'_>>, TlsServerHandshakeError> {
+        let limiter = match &self.tls_handshake_limiter {
+            Some(limiter) => limiter,
+            None => return Ok(None),
+        };
+        let source = tcp_stream.peer_addr().ok().map(|addr| addr.ip());
+        match limiter.admit(source, Instant::now()) {
+            Ok(permit) => Ok(Some(permit)),
+            Err(overload) => {
+                self.metrics.observe_tls_handshake_rejected(match overload {
+                    HandshakeOverload::TooManyConcurrentHandshakes { .. } => "concurrency",
+                    HandshakeOverload::SourceRateExceeded { .. } => "source_rate",
+                });
+                Err(TlsServerHandshakeError::HandshakeRejectedOverload(overload))
+            }
+        }
+    }
+
+    /// Counts a server handshake that failed because the client presented a
+    /// certificate that is trusted via `trust_path` but revoked.
+    fn observe_revoked_cert_rejection<T>(
+        &self,
+        result: &Result<T, TlsServerHandshakeError>,
+        trust_path: &str,
+    ) {
+        if let Err(TlsServerHandshakeError::ClientNotAllowed(
+            PeerNotAllowedError::CertificateRevoked,
+        )) = result
+        {
+            self.metrics.observe_tls_revoked_cert_rejected(trust_path);
+        }
+    }
+
+    /// Observes a server handshake that started at `start_time` and
+    /// completed with `result`.
+    fn observe_tls_server_handshake<T>(
+        &self,
+        result: &Result<T, TlsServerHandshakeError>,
+        start_time: Option<Instant>,
+    ) {
+        self.metrics
+            .observe_tls_handshake("server", result.is_ok(), start_time);
+        if let Err(TlsServerHandshakeError::ClientNotAllowed(_)) = result {
+            self.metrics.observe_tls_client_not_allowed();
+        }
+    }
+
+    /// Observes a client handshake that started at `start_time` and
+    /// completed with `result`.
+    fn observe_tls_client_handshake<T>(
+        &self,
+        result: &Result<T, TlsClientHandshakeError>,
+        start_time: Option<Instant>,
+    ) {
+        self.metrics
+            .observe_tls_handshake("client", result.is_ok(), start_time);
+    }
+
+    /// Runs `handshake` and aborts it with the error returned by `on_timeout`
+    /// once the handshake timeout expires.
+    async fn run_with_tls_handshake_timeout<T, E>(
+        &self,
+        handshake: impl Future<Output = Result<T, E>>,
+        on_timeout: impl FnOnce(Duration) -> E,
+    ) -> Result<T, E> {
+        let timeout = self.tls_handshake_timeout;
+        tokio::time::timeout(timeout, handshake)
+            .await
+            .unwrap_or_else(|_elapsed| Err(on_timeout(timeout)))
+    }
+}
+
 #[async_trait]
 impl<CSP> TlsHandshake for CryptoComponentFatClient<CSP>
 where
@@ -25,25 +113,45 @@ where
     async fn perform_tls_server_handshake(
         &self,
         tcp_stream: TcpStream,
-        allowed_clients: AllowedClients,
+        allowed_clients: Arc<dyn AllowedClientsProvider>,
         registry_version: RegistryVersion,
     ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
         let logger = new_logger!(&self.logger;
             crypto.trait_name => "TlsHandshake",
             crypto.method_name => "perform_tls_server_handshake",
             crypto.registry_version => registry_version.get(),
-            crypto.allowed_tls_clients => format!("{:?}", allowed_clients),
         );
         debug!(logger; crypto.description => "start",);
-        let result = server_handshake::perform_tls_server_handshake(
-            &self.csp,
-            self.node_id,
-            &self.registry_client,
-            tcp_stream,
-            allowed_clients,
-            registry_version,
-        )
-        .await;
+        let start_time = self.metrics.now();
+        let result = match self
+            .admit_tls_server_handshake(&tcp_stream)
+            .and_then(|permit| {
+                let allowed_clients = allowed_clients
+                    .allowed_clients(registry_version)
+                    .map_err(TlsServerHandshakeError::AllowedClientsUnavailable)?;
+                Ok((permit, allowed_clients))
+            }) {
+            Ok((_permit, allowed_clients)) => {
+                debug!(logger;
+                    crypto.description => format!("allowed clients: {:?}", allowed_clients),
+                );
+                self.run_with_tls_handshake_timeout(
+                    server_handshake::perform_tls_server_handshake(
+                        &self.csp,
+                        self.node_id,
+                        &self.registry_client,
+                        tcp_stream,
+                        allowed_clients,
+                        registry_version,
+                    ),
+                    |timeout| TlsServerHandshakeError::Timeout { timeout },
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        self.observe_tls_server_handshake(&result, start_time);
+        self.observe_revoked_cert_rejection(&result, "explicit_cert");
         debug!(logger;
             crypto.description => "end",
             crypto.is_ok => result.is_ok(),
@@ -65,15 +173,26 @@ where
             crypto.allowed_tls_clients => format!("{:?}", allowed_authenticating_clients),
         );
         debug!(logger; crypto.description => "start",);
-        let result = server_handshake::perform_tls_server_handshake_temp_with_optional_client_auth(
-            &self.csp,
-            self.node_id,
-            &self.registry_client,
-            tcp_stream,
-            allowed_authenticating_clients,
-            registry_version,
-        )
-        .await;
+        let start_time = self.metrics.now();
+        let result = match self.admit_tls_server_handshake(&tcp_stream) {
+            Ok(_permit) => {
+                self.run_with_tls_handshake_timeout(
+                    server_handshake::perform_tls_server_handshake_temp_with_optional_client_auth(
+                        &self.csp,
+                        self.node_id,
+                        &self.registry_client,
+                        tcp_stream,
+                        allowed_authenticating_clients,
+                        registry_version,
+                    ),
+                    |timeout| TlsServerHandshakeError::Timeout { timeout },
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        self.observe_tls_server_handshake(&result, start_time);
+        self.observe_revoked_cert_rejection(&result, "explicit_cert");
         debug!(logger;
             crypto.description => "end",
             crypto.is_ok => result.is_ok(),
@@ -94,14 +213,65 @@ where
             crypto.allowed_tls_clients => "all clients allowed",
         );
         debug!(logger; crypto.description => "start",);
-        let result = server_handshake::perform_tls_server_handshake_without_client_auth(
-            &self.csp,
-            self.node_id,
-            &self.registry_client,
-            tcp_stream,
-            registry_version,
-        )
-        .await;
+        let start_time = self.metrics.now();
+        let result = match self.admit_tls_server_handshake(&tcp_stream) {
+            Ok(_permit) => {
+                self.run_with_tls_handshake_timeout(
+                    server_handshake::perform_tls_server_handshake_without_client_auth(
+                        &self.csp,
+                        self.node_id,
+                        &self.registry_client,
+                        tcp_stream,
+                        registry_version,
+                    ),
+                    |timeout| TlsServerHandshakeError::Timeout { timeout },
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        self.observe_tls_server_handshake(&result, start_time);
+        debug!(logger;
+            crypto.description => "end",
+            crypto.is_ok => result.is_ok(),
+            crypto.error => log_err(result.as_ref().err()),
+        );
+        result
+    }
+
+    async fn perform_tls_server_handshake_with_ca_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        client_cas: ClientCas,
+        registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
+        let logger = new_logger!(&self.logger;
+            crypto.trait_name => "TlsHandshake",
+            crypto.method_name => "perform_tls_server_handshake_with_ca_client_auth",
+            crypto.registry_version => registry_version.get(),
+            crypto.allowed_tls_clients => format!("{:?}", client_cas),
+        );
+        debug!(logger; crypto.description => "start",);
+        let start_time = self.metrics.now();
+        let result = match self.admit_tls_server_handshake(&tcp_stream) {
+            Ok(_permit) => {
+                self.run_with_tls_handshake_timeout(
+                    server_handshake::perform_tls_server_handshake_with_ca_client_auth(
+                        &self.csp,
+                        self.node_id,
+                        &self.registry_client,
+                        tcp_stream,
+                        client_cas,
+                        registry_version,
+                    ),
+                    |timeout| TlsServerHandshakeError::Timeout { timeout },
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        self.observe_tls_server_handshake(&result, start_time);
+        self.observe_revoked_cert_rejection(&result, "client_ca");
         debug!(logger;
             crypto.description => "end",
             crypto.is_ok => result.is_ok(),
@@ -115,7 +285,7 @@ where
         tcp_stream: TcpStream,
         server: NodeId,
         registry_version: RegistryVersion,
-    ) -> Result<TlsStream, TlsClientHandshakeError> {
+    ) -> Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError> {
         let logger = new_logger!(&self.logger;
             crypto.trait_name => "TlsHandshake",
             crypto.method_name => "perform_tls_client_handshake",
@@ -123,15 +293,84 @@ where
             crypto.tls_server => format!("{}", server),
         );
         debug!(logger; crypto.description => "start",);
-        let result = client_handshake::perform_tls_client_handshake(
-            &self.csp,
-            self.node_id,
+        let start_time = self.metrics.now();
+        let result = self
+            .run_with_tls_handshake_timeout(
+                client_handshake::perform_tls_client_handshake(
+                    &self.csp,
+                    self.node_id,
+                    &self.registry_client,
+                    tcp_stream,
+                    server,
+                    registry_version,
+                ),
+                |timeout| TlsClientHandshakeError::Timeout { timeout },
+            )
+            .await;
+        self.observe_tls_client_handshake(&result, start_time);
+        debug!(logger;
+            crypto.description => "end",
+            crypto.is_ok => result.is_ok(),
+            crypto.error => log_err(result.as_ref().err()),
+        );
+        result
+    }
+
+    async fn perform_tls_client_handshake_with_pinned_cert(
+        &self,
+        tcp_stream: TcpStream,
+        expected_cert: TlsPublicKeyCert,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsClientHandshakeError> {
+        let logger = new_logger!(&self.logger;
+            crypto.trait_name => "TlsHandshake",
+            crypto.method_name => "perform_tls_client_handshake_with_pinned_cert",
+        );
+        debug!(logger;
+            crypto.description => format!("start; expected server cert: {:?}", expected_cert),
+        );
+        let start_time = self.metrics.now();
+        let result = self
+            .run_with_tls_handshake_timeout(
+                client_handshake::perform_tls_client_handshake_with_pinned_cert(
+                    &self.csp,
+                    tcp_stream,
+                    expected_cert,
+                ),
+                |timeout| TlsClientHandshakeError::Timeout { timeout },
+            )
+            .await;
+        self.observe_tls_client_handshake(&result, start_time);
+        debug!(logger;
+            crypto.description => "end",
+            crypto.is_ok => result.is_ok(),
+            crypto.error => log_err(result.as_ref().err()),
+        );
+        result
+    }
+
+    fn revalidate_peer(
+        &self,
+        peer: &AuthenticatedPeer,
+        handshake_registry_version: RegistryVersion,
+        registry_version: RegistryVersion,
+    ) -> Result<(), PeerRevalidationError> {
+        let logger = new_logger!(&self.logger;
+            crypto.trait_name => "TlsHandshake",
+            crypto.method_name => "revalidate_peer",
+            crypto.registry_version => registry_version.get(),
+        );
+        debug!(logger;
+            crypto.description => format!(
+                "start; peer: {:?}, handshake registry version: {}",
+                peer, handshake_registry_version
+            ),
+        );
+        let result = peer_revalidation::revalidate_peer(
             &self.registry_client,
-            tcp_stream,
-            server,
+            peer,
+            handshake_registry_version,
             registry_version,
-        )
-        .await;
+        );
         debug!(logger;
             crypto.description => "end",
             crypto.is_ok => result.is_ok(),
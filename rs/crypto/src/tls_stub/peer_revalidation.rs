@@ -0,0 +1,62 @@
+use super::*;
+use ic_crypto_tls_interfaces::PeerRevalidationError;
+
+pub(super) fn revalidate_peer(
+    registry_client: &Arc<dyn RegistryClient>,
+    peer: &AuthenticatedPeer,
+    handshake_registry_version: RegistryVersion,
+    registry_version: RegistryVersion,
+) -> Result<(), PeerRevalidationError> {
+    let node_id = match peer {
+        AuthenticatedPeer::Node(node_id) => *node_id,
+        AuthenticatedPeer::Cert(_) => return Ok(()),
+    };
+    let current_cert = tls_cert_from_registry(registry_client, node_id, registry_version).map_err(
+        |e| match e {
+            TlsCertFromRegistryError::CertificateNotInRegistry { .. } => {
+                PeerRevalidationError::NodeRemoved {
+                    node_id,
+                    registry_version,
+                }
+            }
+            other => revalidation_error(other),
+        },
+    )?;
+    let handshake_cert =
+        tls_cert_from_registry(registry_client, node_id, handshake_registry_version).map_err(
+            |e| match e {
+                TlsCertFromRegistryError::CertificateNotInRegistry { .. } => {
+                    PeerRevalidationError::CertificateRotated {
+                        node_id,
+                        registry_version,
+                    }
+                }
+                other => revalidation_error(other),
+            },
+        )?;
+    if current_cert != handshake_cert {
+        return Err(PeerRevalidationError::CertificateRotated {
+            node_id,
+            registry_version,
+        });
+    }
+    Ok(())
+}
+
+fn revalidation_error(error: TlsCertFromRegistryError) -> PeerRevalidationError {
+    match error {
+        TlsCertFromRegistryError::RegistryError(e) => PeerRevalidationError::RegistryError(e),
+        TlsCertFromRegistryError::CertificateNotInRegistry {
+            node_id,
+            registry_version,
+        } => PeerRevalidationError::NodeRemoved {
+            node_id,
+            registry_version,
+        },
+        TlsCertFromRegistryError::CertificateMalformed { internal_error } => {
+            PeerRevalidationError::MalformedCertificate(MalformedPeerCertificateError {
+                internal_error,
+            })
+        }
+    }
+}
@@ -4,8 +4,9 @@ use crate::tls_stub::{
 use ic_crypto_internal_csp::api::CspTlsServerHandshake;
 use ic_crypto_internal_csp::tls_stub::cert_chain::CspCertificateChain;
 use ic_crypto_tls_interfaces::{
-    AllowedClients, AuthenticatedPeer, MalformedPeerCertificateError, Peer, PeerNotAllowedError,
-    SomeOrAllNodes, TlsPublicKeyCert, TlsServerHandshakeError, TlsStream,
+    AllowedClients, AuthenticatedPeer, ClientCas, MalformedPeerCertificateError, Peer,
+    PeerNotAllowedError, SomeOrAllNodes, TlsPublicKeyCert, TlsServerHandshakeError, TlsStream,
+    TrustStore,
 };
 use ic_interfaces::registry::RegistryClient;
 use ic_registry_client::helper::node::NodeRegistry;
@@ -14,6 +15,9 @@ use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::sync::Arc;
 use tokio::net::TcpStream;
 
+#[cfg(test)]
+mod tests;
+
 // TODO (CRP-772): Simplify handshake code by moving cert equality check to CSP
 pub async fn perform_tls_server_handshake<C: CspTlsServerHandshake>(
     csp: &C,
@@ -56,8 +60,10 @@ pub async fn perform_tls_server_handshake_temp_with_optional_client_auth<
         registry_version,
     )
     .map_err(|e| map_cert_from_registry_error(e, CertFromRegistryOwner::Client))?;
-    let trusted_client_certs =
-        combine_certs(&trusted_node_certs, allowed_authenticating_clients.certs());
+    let trusted_client_certs = combine_certs(
+        &trusted_node_certs,
+        allowed_authenticating_clients.trust_store(),
+    );
 
     let (tls_stream, peer_cert_chain) = csp
         .perform_tls_server_handshake(tcp_stream, self_tls_cert, trusted_client_certs)
@@ -67,7 +73,7 @@ pub async fn perform_tls_server_handshake_temp_with_optional_client_auth<
         Some(peer_cert_chain) => {
             let peer = authenticated_peer(
                 &peer_cert_chain,
-                allowed_authenticating_clients.certs(),
+                &allowed_authenticating_clients,
                 &trusted_node_certs,
             )?;
             Ok((tls_stream, Peer::Authenticated(peer)))
@@ -93,6 +99,53 @@ pub async fn perform_tls_server_handshake_without_client_auth<C: CspTlsServerHan
     Ok(tls_stream)
 }
 
+pub async fn perform_tls_server_handshake_with_ca_client_auth<C: CspTlsServerHandshake>(
+    csp: &C,
+    self_node_id: NodeId,
+    registry_client: &Arc<dyn RegistryClient>,
+    tcp_stream: TcpStream,
+    client_cas: ClientCas,
+    registry_version: RegistryVersion,
+) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
+    let self_tls_cert = tls_cert_from_registry(registry_client, self_node_id, registry_version)
+        .map_err(|e| map_cert_from_registry_error(e, CertFromRegistryOwner::Myself))?;
+
+    let (tls_stream, peer_cert_chain) = csp
+        .perform_tls_server_handshake_with_ca_client_auth(
+            tcp_stream,
+            self_tls_cert,
+            client_cas.cas().certs().clone(),
+            client_cas.max_intermediate_cas(),
+        )
+        .await?;
+
+    let peer_cert_chain = peer_cert_chain.ok_or(TlsServerHandshakeError::UnauthenticatedClient)?;
+    ensure_no_cert_revoked(&peer_cert_chain, &client_cas)?;
+    Ok((
+        tls_stream,
+        AuthenticatedPeer::Cert(peer_cert_chain.leaf().clone()),
+    ))
+}
+
+/// Fails if the revocation check of `client_cas` considers any certificate
+/// in the client's verified certificate chain revoked, including the CA
+/// itself.
+fn ensure_no_cert_revoked(
+    client_cert_chain_from_handshake: &CspCertificateChain,
+    client_cas: &ClientCas,
+) -> Result<(), TlsServerHandshakeError> {
+    if client_cert_chain_from_handshake
+        .chain()
+        .iter()
+        .any(|cert| client_cas.is_revoked(cert))
+    {
+        return Err(TlsServerHandshakeError::ClientNotAllowed(
+            PeerNotAllowedError::CertificateRevoked,
+        ));
+    }
+    Ok(())
+}
+
 fn tls_certs_from_registry(
     registry_client: &Arc<dyn RegistryClient>,
     nodes: &SomeOrAllNodes,
@@ -109,6 +162,14 @@ fn tls_certs_from_registry(
                 .collect();
             tls_certs_from_registry_for_nodes(&all_nodes, registry_client, registry_version)
         }
+        SomeOrAllNodes::AllExcept(excluded_nodes) => {
+            let remaining_nodes = registry_client
+                .get_node_ids(registry_version)?
+                .into_iter()
+                .filter(|node_id| !excluded_nodes.contains(node_id))
+                .collect();
+            tls_certs_from_registry_for_nodes(&remaining_nodes, registry_client, registry_version)
+        }
     }
 }
 
@@ -129,10 +190,10 @@ fn tls_certs_from_registry_for_nodes(
 
 fn combine_certs(
     node_certs: &BTreeMap<NodeId, TlsPublicKeyCert>,
-    certs: &HashSet<TlsPublicKeyCert>,
+    trust_store: &TrustStore,
 ) -> HashSet<TlsPublicKeyCert> {
     let mut node_certs_and_certs: HashSet<_> = node_certs.values().cloned().collect();
-    node_certs_and_certs.extend(certs.iter().cloned());
+    node_certs_and_certs.extend(trust_store.certs().iter().cloned());
     node_certs_and_certs
 }
 
@@ -149,16 +210,19 @@ fn combine_certs(
 ///    step 2 is taken.
 /// 2. Compare the root of the certificate chain that the peer presented during
 ///    the handshake (and for which the peer therefore knows the private key of
-///    the chain's leaf certificate) to all the certificates in
-///    `allowed_client_certs`. If there is a match, then the peer represented by
-///    the chain's leaf certificate successfully authenticated.
+///    the chain's leaf certificate) to all the certificates in the trust store
+///    of `allowed_clients`. If there is a match and the revocation check of
+///    `allowed_clients` considers no certificate of the chain revoked, then
+///    the peer represented by the chain's leaf certificate successfully
+///    authenticated. If a certificate of the chain is revoked, the peer is
+///    rejected.
 ///
 /// If neither an authenticated node nor an authenticated certificate can be
 /// determined, then the error produced when trying to authenticate a node is
 /// returned.
 fn authenticated_peer(
     client_cert_chain_from_handshake: &CspCertificateChain,
-    allowed_client_certs: &HashSet<TlsPublicKeyCert>,
+    allowed_clients: &AllowedClients,
     trusted_node_certs: &BTreeMap<NodeId, TlsPublicKeyCert>,
 ) -> Result<AuthenticatedPeer, TlsServerHandshakeError> {
     let authenticated_node = check_cert_and_get_authenticated_client_node_id(
@@ -168,7 +232,19 @@ fn authenticated_peer(
     match authenticated_node {
         Ok(authenticated_node) => Ok(AuthenticatedPeer::Node(authenticated_node)),
         Err(node_authentication_error) => {
-            if allowed_client_certs.contains(client_cert_chain_from_handshake.root()) {
+            if allowed_clients
+                .trust_store()
+                .contains(client_cert_chain_from_handshake.root())
+            {
+                if client_cert_chain_from_handshake
+                    .chain()
+                    .iter()
+                    .any(|cert| allowed_clients.is_revoked(cert))
+                {
+                    return Err(TlsServerHandshakeError::ClientNotAllowed(
+                        PeerNotAllowedError::CertificateRevoked,
+                    ));
+                }
                 Ok(AuthenticatedPeer::Cert(
                     client_cert_chain_from_handshake.leaf().clone(),
                 ))
@@ -0,0 +1,232 @@
+#![allow(clippy::unwrap_used)]
+use super::*;
+use ic_crypto_tls_interfaces::arbitrary::{
+    arb_cert_for_node, arb_node_with_cert, arb_tls_public_key_cert,
+};
+use ic_crypto_tls_interfaces::TlsCertRevocationCheck;
+use ic_protobuf::registry::node::v1::NodeRecord;
+use ic_registry_client::fake::FakeRegistryClient;
+use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
+use ic_registry_keys::{make_crypto_tls_cert_key, make_node_record_key};
+use openssl::stack::Stack;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::convert::TryFrom;
+use std::sync::RwLock;
+
+const REG_V1: RegistryVersion = RegistryVersion::new(1);
+
+/// A node in the registry, together with a certificate that claims to be the
+/// node's but differs from the one in the registry.
+#[derive(Clone, Debug)]
+struct RegistryNode {
+    node_id: NodeId,
+    cert: TlsPublicKeyCert,
+    imposter_cert: TlsPublicKeyCert,
+}
+
+fn arb_registry_node() -> impl Strategy<Value = RegistryNode> {
+    arb_node_with_cert().prop_flat_map(|(node_id, cert)| {
+        arb_cert_for_node(node_id).prop_map(move |imposter_cert| RegistryNode {
+            node_id,
+            cert: cert.clone(),
+            imposter_cert,
+        })
+    })
+}
+
+/// The certificate that a client presents in the handshake.
+#[derive(Clone, Debug)]
+enum PresentedCert {
+    RegistryNode(Index),
+    Imposter(Index),
+    TrustStore(Index),
+    Other(TlsPublicKeyCert),
+}
+
+fn arb_presented_cert() -> impl Strategy<Value = PresentedCert> {
+    prop_oneof![
+        any::<Index>().prop_map(PresentedCert::RegistryNode),
+        any::<Index>().prop_map(PresentedCert::Imposter),
+        any::<Index>().prop_map(PresentedCert::TrustStore),
+        arb_tls_public_key_cert().prop_map(PresentedCert::Other),
+    ]
+}
+
+fn registry_with_nodes(nodes: &[RegistryNode]) -> Arc<dyn RegistryClient> {
+    let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+    for node in nodes {
+        data_provider
+            .add(
+                &make_node_record_key(node.node_id),
+                REG_V1,
+                Some(NodeRecord::default()),
+            )
+            .unwrap();
+        data_provider
+            .add(
+                &make_crypto_tls_cert_key(node.node_id),
+                REG_V1,
+                Some(node.cert.to_proto()),
+            )
+            .unwrap();
+    }
+    let registry = Arc::new(FakeRegistryClient::new(data_provider as Arc<_>));
+    registry.update_to_latest_version();
+    registry
+}
+
+fn chain_with_single_cert(cert: &TlsPublicKeyCert) -> CspCertificateChain {
+    let mut stack = Stack::new().unwrap();
+    stack.push(cert.as_x509().clone()).unwrap();
+    CspCertificateChain::try_from(&*stack).unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn should_authenticate_exactly_the_allowed_clients(
+        registry_nodes in vec(arb_registry_node(), 1..5),
+        allowed_node_indices in proptest::option::of(vec(any::<Index>(), 0..5)),
+        allowed_node_indices_are_excluded in any::<bool>(),
+        trust_store_certs in vec(arb_tls_public_key_cert(), 0..3),
+        trust_store_certs_are_revoked in any::<bool>(),
+        presented_cert in arb_presented_cert(),
+    ) {
+        let node_ids: Option<BTreeSet<NodeId>> = allowed_node_indices.map(|indices| {
+            indices
+                .iter()
+                .map(|index| index.get(&registry_nodes).node_id)
+                .collect()
+        });
+        let nodes = match node_ids.clone() {
+            Some(node_ids) if allowed_node_indices_are_excluded => {
+                SomeOrAllNodes::AllExcept(node_ids)
+            }
+            Some(node_ids) => SomeOrAllNodes::Some(node_ids),
+            None => SomeOrAllNodes::All,
+        };
+        // Computed from the generated node IDs rather than with `nodes`, so
+        // that the test does not rely on `SomeOrAllNodes::contains`.
+        let node_is_allowed = |node_id: &NodeId| match &node_ids {
+            Some(node_ids) => node_ids.contains(node_id) != allowed_node_indices_are_excluded,
+            None => true,
+        };
+        let allowed_clients =
+            AllowedClients::new(nodes, trust_store_certs.iter().cloned().collect());
+        prop_assume!(allowed_clients.is_ok());
+        let mut allowed_clients = allowed_clients.unwrap();
+        if trust_store_certs_are_revoked {
+            let revoked_certs: HashSet<_> = trust_store_certs.iter().cloned().collect();
+            allowed_clients =
+                allowed_clients.with_revocation_check(Arc::new(RwLock::new(revoked_certs)));
+        }
+        let presented_cert = match presented_cert {
+            PresentedCert::RegistryNode(index) => index.get(&registry_nodes).cert.clone(),
+            PresentedCert::Imposter(index) => index.get(&registry_nodes).imposter_cert.clone(),
+            PresentedCert::TrustStore(index) => {
+                prop_assume!(!trust_store_certs.is_empty());
+                index.get(&trust_store_certs).clone()
+            }
+            PresentedCert::Other(cert) => cert,
+        };
+        let registry = registry_with_nodes(&registry_nodes);
+
+        let trusted_node_certs =
+            tls_certs_from_registry(&registry, allowed_clients.nodes(), REG_V1).unwrap();
+        let result = authenticated_peer(
+            &chain_with_single_cert(&presented_cert),
+            &allowed_clients,
+            &trusted_node_certs,
+        );
+
+        // Only the certificates generated for nodes have a node ID as CN.
+        let claimed_node = registry_nodes
+            .iter()
+            .find(|node| node.cert == presented_cert || node.imposter_cert == presented_cert);
+        match claimed_node {
+            Some(node) if node_is_allowed(&node.node_id) && node.cert == presented_cert => {
+                prop_assert_eq!(result, Ok(AuthenticatedPeer::Node(node.node_id)));
+            }
+            _ if trust_store_certs.contains(&presented_cert) => {
+                if trust_store_certs_are_revoked {
+                    prop_assert_eq!(
+                        result,
+                        Err(TlsServerHandshakeError::ClientNotAllowed(
+                            PeerNotAllowedError::CertificateRevoked
+                        ))
+                    );
+                } else {
+                    prop_assert_eq!(result, Ok(AuthenticatedPeer::Cert(presented_cert)));
+                }
+            }
+            Some(node) if node_is_allowed(&node.node_id) => {
+                prop_assert_eq!(
+                    result,
+                    Err(TlsServerHandshakeError::ClientNotAllowed(
+                        PeerNotAllowedError::CertificatesDiffer
+                    ))
+                );
+            }
+            Some(_) => {
+                prop_assert_eq!(
+                    result,
+                    Err(TlsServerHandshakeError::ClientNotAllowed(
+                        PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed
+                    ))
+                );
+            }
+            None => {
+                prop_assert!(matches!(
+                    result,
+                    Err(TlsServerHandshakeError::MalformedClientCertificate(_))
+                ));
+            }
+        }
+    }
+}
+
+/// Considers exactly the certificates in the trust store revoked.
+struct RevokedCerts(TrustStore);
+
+impl TlsCertRevocationCheck for RevokedCerts {
+    fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.0.contains(cert)
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn should_reject_client_cert_chain_exactly_if_a_cert_is_revoked(
+        ca in arb_tls_public_key_cert(),
+        client_cert in arb_tls_public_key_cert(),
+        revoked_certs in vec(arb_tls_public_key_cert(), 0..3),
+        client_cert_is_revoked in any::<bool>(),
+    ) {
+        let mut revoked_certs: TrustStore = revoked_certs.into_iter().collect();
+        if client_cert_is_revoked {
+            revoked_certs.add(client_cert.clone());
+        }
+        let is_revoked = revoked_certs.contains(&client_cert);
+        let client_cas = ClientCas::new(vec![ca].into_iter().collect(), 0)
+            .unwrap()
+            .with_revocation_check(Arc::new(RevokedCerts(revoked_certs)));
+
+        let result = ensure_no_cert_revoked(&chain_with_single_cert(&client_cert), &client_cas);
+
+        if is_revoked {
+            prop_assert_eq!(
+                result,
+                Err(TlsServerHandshakeError::ClientNotAllowed(
+                    PeerNotAllowedError::CertificateRevoked
+                ))
+            );
+        } else {
+            prop_assert_eq!(result, Ok(()));
+        }
+    }
+}
@@ -6,7 +6,7 @@ use ic_crypto_test_utils_threshold_sigs::non_interactive::{
 };
 use ic_interfaces::crypto::{
     LoadTranscriptResult, NiDkgAlgorithm, Signable, SignableMock, ThresholdSigVerifier,
-    ThresholdSigner,
+    ThresholdSigner, THRESHOLD_SIG_DOMAINS,
 };
 use ic_test_utilities::crypto::crypto_for;
 use ic_types::crypto::threshold_sig::ni_dkg::config::NiDkgConfig;
@@ -77,6 +77,52 @@ fn should_produce_valid_signature_shares() {
     });
 }
 
+#[test]
+// Test uses a random NI-DKG config.
+// A random receiver is chosen to be both combiner and verifier.
+fn should_not_verify_threshold_signature_in_other_domain() {
+    let subnet_size = thread_rng().gen_range(1, 7);
+    let (config, dkg_id, crypto_components) = setup_with_random_ni_dkg_config(subnet_size);
+
+    run_ni_dkg_and_load_transcript_for_receivers(&config, &crypto_components);
+
+    let verifier = crypto_for(
+        random_node_in(&config.receivers().get()),
+        &crypto_components,
+    );
+    for signing_domain in THRESHOLD_SIG_DOMAINS.iter() {
+        let msg = SignableMock::new_in_domain(signing_domain, b"message".to_vec());
+        let combined_sig = threshold_sign_and_combine(
+            SignersAndCombiner {
+                signers: n_random_nodes_in(&config.receivers().get(), config.threshold().get()),
+                combiner: random_node_in(&config.receivers().get()),
+            },
+            &msg,
+            dkg_id,
+            &crypto_components,
+        );
+        assert!(verifier
+            .verify_threshold_sig_combined(&combined_sig, &msg, dkg_id)
+            .is_ok());
+
+        for verifying_domain in THRESHOLD_SIG_DOMAINS.iter() {
+            if verifying_domain == signing_domain {
+                continue;
+            }
+            let msg_in_other_domain =
+                SignableMock::new_in_domain(verifying_domain, b"message".to_vec());
+            assert!(
+                verifier
+                    .verify_threshold_sig_combined(&combined_sig, &msg_in_other_domain, dkg_id)
+                    .is_err(),
+                "signature in domain {} verified in domain {}",
+                signing_domain,
+                verifying_domain
+            );
+        }
+    }
+}
+
 #[test]
 // Test uses a random NI-DKG config. A random receiver is chosen as combiner.
 fn should_fail_to_combine_insufficient_shares() {
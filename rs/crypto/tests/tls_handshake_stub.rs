@@ -4,14 +4,15 @@ use crate::tls_utils::temp_crypto_component_with_tls_keys;
 use crate::tls_utils::test_client::{Client, ClientBuilder};
 use crate::tls_utils::test_server::{Server, ServerBuilder};
 use ic_crypto_tls_interfaces::{
-    AuthenticatedPeer, MalformedPeerCertificateError, TlsClientHandshakeError, TlsPublicKeyCert,
-    TlsServerHandshakeError,
+    AuthenticatedPeer, HandshakeFailureKind, MalformedPeerCertificateError,
+    TlsClientHandshakeError, TlsPublicKeyCert, TlsServerHandshakeError,
 };
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_registry_client::fake::FakeRegistryClient;
 use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
 use ic_test_utilities::types::ids::{NODE_1, NODE_2, NODE_3, NODE_4, NODE_5};
 use ic_types::NodeId;
+use std::fmt::Debug;
 use std::sync::Arc;
 
 mod tls_utils;
@@ -25,7 +26,18 @@ const CLIENT_ID_3: NodeId = NODE_5;
 
 mod handshakes {
     use super::*;
+    use crate::tls_utils::REG_V1;
     use ic_crypto_test_utils::tls::x509_certificates::{x509_public_key_cert, CertWithPrivateKey};
+    use ic_crypto_tls_interfaces::{
+        AllowedClients, AllowedClientsError, AllowedClientsProvider, SomeOrAllNodes, TlsHandshake,
+    };
+    use ic_test_utilities::crypto::registry::CryptoRegistryBuilder;
+    use ic_test_utilities::types::ids::subnet_test_id;
+    use maplit::btreeset;
+    use std::collections::{BTreeSet, HashSet};
+    use std::sync::RwLock;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::oneshot;
 
     #[tokio::test]
     async fn should_perform_tls_handshake() {
@@ -42,6 +54,20 @@ mod handshakes {
         assert_peer_node_eq(authenticated_client.unwrap(), CLIENT_ID_1);
     }
 
+    #[tokio::test]
+    async fn should_return_registry_cert_of_server_to_client() {
+        let (server, client, registry) = matching_server_and_client(SERVER_ID_1, CLIENT_ID_1);
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let (server_cert, server_result) = tokio::join!(client.run(server.port()), server.run());
+
+        assert!(server_result.is_ok());
+        assert_eq!(server_cert.unwrap().to_proto(), server.cert());
+    }
+
     #[tokio::test]
     async fn should_perform_tls_handshake_if_multiple_clients_allowed() {
         let registry = TlsRegistry::new();
@@ -68,6 +94,114 @@ mod handshakes {
         assert!(client_result.is_ok());
         assert_peer_node_eq(authenticated_client.unwrap(), CLIENT_ID_2);
     }
+
+    #[tokio::test]
+    async fn should_perform_tls_handshake_between_nodes_of_crypto_registry() {
+        let registry = CryptoRegistryBuilder::new()
+            .with_node(1, SERVER_ID_1)
+            .with_node(1, CLIENT_ID_1)
+            .with_subnet(1, subnet_test_id(1), &[SERVER_ID_1, CLIENT_ID_1])
+            .build();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let allowed_clients = AllowedClients::new(
+            SomeOrAllNodes::Some(vec![CLIENT_ID_1].into_iter().collect()),
+            HashSet::new(),
+        )
+        .unwrap();
+
+        let server = async {
+            let (tcp_stream, _) = listener.accept().await.unwrap();
+            registry
+                .crypto(SERVER_ID_1)
+                .perform_tls_server_handshake(tcp_stream, Arc::new(allowed_clients), REG_V1)
+                .await
+        };
+        let client = async {
+            let tcp_stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            registry
+                .crypto(CLIENT_ID_1)
+                .perform_tls_client_handshake(tcp_stream, SERVER_ID_1, REG_V1)
+                .await
+        };
+        let (server_result, client_result) = tokio::join!(server, client);
+
+        assert!(client_result.is_ok());
+        assert_peer_node_eq(server_result.unwrap().1, CLIENT_ID_1);
+    }
+
+    #[tokio::test]
+    async fn should_resolve_allowed_clients_from_provider_during_handshake() {
+        let registry = CryptoRegistryBuilder::new()
+            .with_node(1, SERVER_ID_1)
+            .with_node(1, CLIENT_ID_1)
+            .with_subnet(1, subnet_test_id(1), &[SERVER_ID_1, CLIENT_ID_1])
+            .build();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let allowed_nodes = Arc::new(RwLock::new(btreeset! {CLIENT_ID_2}));
+        let allowed_clients: Arc<dyn AllowedClientsProvider> = allowed_nodes.clone();
+        let (accepting_tx, accepting_rx) = oneshot::channel();
+
+        let server = async {
+            accepting_tx.send(()).unwrap();
+            let (tcp_stream, _) = listener.accept().await.unwrap();
+            registry
+                .crypto(SERVER_ID_1)
+                .perform_tls_server_handshake(tcp_stream, allowed_clients, REG_V1)
+                .await
+        };
+        let client = async {
+            accepting_rx.await.unwrap();
+            // The server is already accepting connections with the provider,
+            // so the client is only allowed if the provider is resolved when
+            // the handshake starts.
+            allowed_nodes.write().unwrap().insert(CLIENT_ID_1);
+            let tcp_stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            registry
+                .crypto(CLIENT_ID_1)
+                .perform_tls_client_handshake(tcp_stream, SERVER_ID_1, REG_V1)
+                .await
+        };
+        let (server_result, client_result) = tokio::join!(server, client);
+
+        assert!(client_result.is_ok());
+        assert_peer_node_eq(server_result.unwrap().1, CLIENT_ID_1);
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_provider_has_no_allowed_clients() {
+        let registry = CryptoRegistryBuilder::new()
+            .with_node(1, SERVER_ID_1)
+            .with_node(1, CLIENT_ID_1)
+            .with_subnet(1, subnet_test_id(1), &[SERVER_ID_1, CLIENT_ID_1])
+            .build();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let allowed_clients: Arc<dyn AllowedClientsProvider> =
+            Arc::new(RwLock::new(BTreeSet::new()));
+
+        let server = async {
+            let (tcp_stream, _) = listener.accept().await.unwrap();
+            registry
+                .crypto(SERVER_ID_1)
+                .perform_tls_server_handshake(tcp_stream, allowed_clients, REG_V1)
+                .await
+        };
+        let client = async {
+            let tcp_stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            registry
+                .crypto(CLIENT_ID_1)
+                .perform_tls_client_handshake(tcp_stream, SERVER_ID_1, REG_V1)
+                .await
+        };
+        let (server_result, _client_result) = tokio::join!(server, client);
+
+        assert_eq!(
+            server_result.unwrap_err(),
+            TlsServerHandshakeError::AllowedClientsUnavailable(AllowedClientsError::ClientsEmpty)
+        );
+    }
 }
 
 mod server_with_certs {
@@ -76,6 +210,7 @@ mod server_with_certs {
     use ic_crypto_test_utils::tls::x509_certificates::{
         ed25519_key_pair, x509_public_key_cert, CertWithPrivateKey,
     };
+    use ic_crypto_tls_interfaces::PeerNotAllowedError;
     use openssl::hash::MessageDigest;
 
     #[tokio::test]
@@ -96,6 +231,50 @@ mod server_with_certs {
         assert_peer_cert_eq(server_result.unwrap(), allowed_cert_proto);
     }
 
+    #[tokio::test]
+    async fn should_return_error_if_allowed_cert_is_revoked() {
+        let registry = TlsRegistry::new();
+        let allowed_cert = CertWithPrivateKey::builder().build_ed25519();
+        let allowed_cert_proto = x509_public_key_cert(&allowed_cert.x509());
+        let server = Server::builder(SERVER_ID_1)
+            .add_allowed_client_cert(allowed_cert_proto.clone())
+            .revoke_client_cert(allowed_cert_proto)
+            .build(registry.get());
+        let client = CustomClient::builder()
+            .with_client_auth(allowed_cert)
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(client.run(server.port()), server.run());
+
+        assert_eq!(
+            server_result.unwrap_err(),
+            TlsServerHandshakeError::ClientNotAllowed(PeerNotAllowedError::CertificateRevoked)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_perform_tls_handshake_with_cert_if_other_cert_is_revoked() {
+        let registry = TlsRegistry::new();
+        let allowed_cert = CertWithPrivateKey::builder().build_ed25519();
+        let allowed_cert_proto = x509_public_key_cert(&allowed_cert.x509());
+        let revoked_cert_proto =
+            x509_public_key_cert(&CertWithPrivateKey::builder().build_ed25519().x509());
+        let server = Server::builder(SERVER_ID_1)
+            .add_allowed_client_cert(allowed_cert_proto.clone())
+            .add_allowed_client_cert(revoked_cert_proto.clone())
+            .revoke_client_cert(revoked_cert_proto)
+            .build(registry.get());
+        let client = CustomClient::builder()
+            .with_client_auth(allowed_cert)
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(client.run(server.port()), server.run());
+
+        assert_peer_cert_eq(server_result.unwrap(), allowed_cert_proto);
+    }
+
     #[tokio::test]
     async fn should_perform_tls_handshake_with_ca_cert() {
         const CLIENT_CA_CN: &str = "certificate authority";
@@ -237,6 +416,7 @@ mod server_with_certs {
         let (_, server_result) = tokio::join!(client.run(server.port()), server.run());
 
         assert_handshake_server_error_containing(&server_result, "certificate verify failed");
+        assert_handshake_server_error_kind(&server_result, HandshakeFailureKind::BadCertSignature);
     }
 }
 
@@ -344,16 +524,266 @@ mod server_allowing_all_nodes {
     }
 }
 
+mod server_with_client_cas {
+    use super::*;
+    use ic_crypto_test_utils::tls::custom_client::CustomClient;
+    use ic_crypto_test_utils::tls::x509_certificates::{
+        ed25519_key_pair, x509_public_key_cert, CertWithPrivateKey,
+    };
+    use ic_crypto_tls_interfaces::{ClientCas, PeerNotAllowedError, TlsCertRevocationCheck};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::X509;
+
+    const CLIENT_CA_CN: &str = "certificate authority";
+    const CLIENT_INTERMEDIATE_CA_CN: &str = "intermediate certificate authority";
+    const CLIENT_LEAF_CN: &str = "boundary node";
+
+    struct RevokedCert(TlsPublicKeyCert);
+
+    impl TlsCertRevocationCheck for RevokedCert {
+        fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+            cert == &self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn should_authenticate_client_with_cert_issued_by_ca() {
+        let ca_key_pair = ed25519_key_pair();
+        let leaf_cert = leaf_cert_issued_by(ca_key_pair.clone(), CLIENT_CA_CN);
+        let leaf_cert_proto = x509_public_key_cert(&leaf_cert.x509());
+        let ca_cert = ca_cert(ca_key_pair);
+        let registry = TlsRegistry::new();
+        let server = server_with_client_ca(&ca_cert, &registry);
+        let client = CustomClient::builder()
+            .with_client_auth(leaf_cert)
+            .with_extra_chain_certs(vec![ca_cert.clone()])
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(
+            client.run(server.port()),
+            server.run_with_ca_client_auth(client_cas(&ca_cert, 0))
+        );
+
+        assert_peer_cert_eq(server_result.unwrap(), leaf_cert_proto);
+    }
+
+    #[tokio::test]
+    async fn should_authenticate_client_with_intermediate_ca_if_allowed() {
+        let (leaf_cert, intermediate_ca_cert, ca_cert) = leaf_cert_with_intermediate_ca();
+        let leaf_cert_proto = x509_public_key_cert(&leaf_cert.x509());
+        let registry = TlsRegistry::new();
+        let server = server_with_client_ca(&ca_cert, &registry);
+        let client = CustomClient::builder()
+            .with_client_auth(leaf_cert)
+            .with_extra_chain_certs(vec![intermediate_ca_cert, ca_cert.clone()])
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(
+            client.run(server.port()),
+            server.run_with_ca_client_auth(client_cas(&ca_cert, 1))
+        );
+
+        assert_peer_cert_eq(server_result.unwrap(), leaf_cert_proto);
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_client_has_too_many_intermediate_cas() {
+        let (leaf_cert, intermediate_ca_cert, ca_cert) = leaf_cert_with_intermediate_ca();
+        let registry = TlsRegistry::new();
+        let server = server_with_client_ca(&ca_cert, &registry);
+        let client = CustomClient::builder()
+            .with_client_auth(leaf_cert)
+            .with_extra_chain_certs(vec![intermediate_ca_cert, ca_cert.clone()])
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(
+            client.run(server.port()),
+            server.run_with_ca_client_auth(client_cas(&ca_cert, 0))
+        );
+
+        assert!(matches!(
+            server_result,
+            Err(TlsServerHandshakeError::HandshakeError { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_client_cert_is_not_issued_by_ca() {
+        let ca_cert = ca_cert(ed25519_key_pair());
+        let registry = TlsRegistry::new();
+        let server = server_with_client_ca(&ca_cert, &registry);
+        let client = CustomClient::builder()
+            .with_client_auth(CertWithPrivateKey::builder().build_ed25519())
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(
+            client.run(server.port()),
+            server.run_with_ca_client_auth(client_cas(&ca_cert, 0))
+        );
+
+        assert_handshake_server_error_containing(&server_result, "certificate verify failed");
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_client_cert_is_revoked() {
+        let ca_key_pair = ed25519_key_pair();
+        let leaf_cert = leaf_cert_issued_by(ca_key_pair.clone(), CLIENT_CA_CN);
+        let revoked_cert = TlsPublicKeyCert::new_from_x509(leaf_cert.x509()).unwrap();
+        let ca_cert = ca_cert(ca_key_pair);
+        let registry = TlsRegistry::new();
+        let server = server_with_client_ca(&ca_cert, &registry);
+        let client = CustomClient::builder()
+            .with_client_auth(leaf_cert)
+            .with_extra_chain_certs(vec![ca_cert.clone()])
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(
+            client.run(server.port()),
+            server.run_with_ca_client_auth(
+                client_cas(&ca_cert, 0).with_revocation_check(Arc::new(RevokedCert(revoked_cert)))
+            )
+        );
+
+        assert_eq!(
+            server_result.unwrap_err(),
+            TlsServerHandshakeError::ClientNotAllowed(PeerNotAllowedError::CertificateRevoked)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_client_does_not_authenticate() {
+        let ca_cert = ca_cert(ed25519_key_pair());
+        let registry = TlsRegistry::new();
+        let server = server_with_client_ca(&ca_cert, &registry);
+        let client = CustomClient::builder()
+            .without_client_auth()
+            .build(server.cert());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        let (_, server_result) = tokio::join!(
+            client.run(server.port()),
+            server.run_with_ca_client_auth(client_cas(&ca_cert, 0))
+        );
+
+        assert!(matches!(
+            server_result,
+            Err(TlsServerHandshakeError::HandshakeError { .. })
+        ));
+    }
+
+    /// The CA is also added as allowed client certificate, because the server
+    /// requires allowed clients for its other handshakes.
+    fn server_with_client_ca(ca_cert: &X509, registry: &TlsRegistry) -> Server {
+        Server::builder(SERVER_ID_1)
+            .add_allowed_client_cert(x509_public_key_cert(ca_cert))
+            .build(registry.get())
+    }
+
+    fn client_cas(ca_cert: &X509, max_intermediate_cas: u32) -> ClientCas {
+        let ca_cert = TlsPublicKeyCert::new_from_x509(ca_cert.clone()).unwrap();
+        ClientCas::new(vec![ca_cert].into_iter().collect(), max_intermediate_cas).unwrap()
+    }
+
+    fn ca_cert(ca_key_pair: PKey<Private>) -> X509 {
+        CertWithPrivateKey::builder()
+            .cn(CLIENT_CA_CN.to_string())
+            .set_ca_key_usage_extension()
+            .build(ca_key_pair, MessageDigest::null())
+            .x509()
+    }
+
+    fn leaf_cert_issued_by(issuer_key_pair: PKey<Private>, issuer_cn: &str) -> CertWithPrivateKey {
+        CertWithPrivateKey::builder()
+            .cn(CLIENT_LEAF_CN.to_string())
+            .with_ca_signing(issuer_key_pair, issuer_cn.to_string())
+            .build(ed25519_key_pair(), MessageDigest::null())
+    }
+
+    fn leaf_cert_with_intermediate_ca() -> (CertWithPrivateKey, X509, X509) {
+        let ca_key_pair = ed25519_key_pair();
+        let intermediate_ca_key_pair = ed25519_key_pair();
+        let leaf_cert =
+            leaf_cert_issued_by(intermediate_ca_key_pair.clone(), CLIENT_INTERMEDIATE_CA_CN);
+        let intermediate_ca_cert = CertWithPrivateKey::builder()
+            .cn(CLIENT_INTERMEDIATE_CA_CN.to_string())
+            .set_ca_key_usage_extension()
+            .with_ca_signing(ca_key_pair.clone(), CLIENT_CA_CN.to_string())
+            .build(intermediate_ca_key_pair, MessageDigest::null())
+            .x509();
+        (leaf_cert, intermediate_ca_cert, ca_cert(ca_key_pair))
+    }
+}
+
+mod server_allowing_all_nodes_except_some {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_perform_handshake_if_client_is_not_excluded() {
+        const EXCLUDED_NODE: NodeId = CLIENT_ID_2;
+        let registry = TlsRegistry::new();
+        let server = Server::builder(SERVER_ID_1)
+            .allow_all_nodes_except(EXCLUDED_NODE)
+            .build(registry.get());
+        let client = Client::builder(CLIENT_ID_1, SERVER_ID_1).build(registry.get());
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_node_record(CLIENT_ID_1)
+            .add_cert(CLIENT_ID_1, client.cert())
+            .add_node_record(EXCLUDED_NODE)
+            .add_cert(
+                EXCLUDED_NODE,
+                generate_cert_using_temp_crypto(EXCLUDED_NODE),
+            )
+            .update();
+
+        let (client_result, authenticated_client) =
+            tokio::join!(client.run(server.port()), server.run());
+
+        assert!(client_result.is_ok());
+        assert_peer_node_eq(authenticated_client.unwrap(), CLIENT_ID_1);
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_client_is_excluded() {
+        const EXCLUDED_CLIENT: NodeId = CLIENT_ID_1;
+        let registry = TlsRegistry::new();
+        let server = Server::builder(SERVER_ID_1)
+            .allow_all_nodes_except(EXCLUDED_CLIENT)
+            .build(registry.get());
+        let client = Client::builder(EXCLUDED_CLIENT, SERVER_ID_1).build(registry.get());
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_node_record(EXCLUDED_CLIENT)
+            .add_cert(EXCLUDED_CLIENT, client.cert())
+            .add_node_record(CLIENT_ID_2)
+            .add_cert(CLIENT_ID_2, generate_cert_using_temp_crypto(CLIENT_ID_2))
+            .update();
+
+        let (_client_result, server_result) = tokio::join!(client.run(server.port()), server.run());
+
+        assert_handshake_server_error_containing(&server_result, "certificate verify failed");
+    }
+}
+
 mod server {
     use super::*;
     use crate::tls_utils::REG_V1;
+    use ic_crypto::TlsHandshakeLimits;
     use ic_crypto_test_utils::tls::custom_client::CustomClient;
     use ic_crypto_test_utils::tls::x509_certificates::{
         ed25519_key_pair, x509_public_key_cert, CertWithPrivateKey,
     };
-    use ic_crypto_tls_interfaces::PeerNotAllowedError;
+    use ic_crypto_tls_interfaces::{HandshakeOverload, PeerNotAllowedError};
     use openssl::hash::MessageDigest;
     use openssl::ssl::SslVersion;
+    use std::time::Duration;
+    use tokio::net::TcpStream;
 
     #[tokio::test]
     async fn should_return_error_if_client_not_allowed_and_allowed_clients_exist() {
@@ -381,6 +811,56 @@ mod server {
         assert_handshake_server_error_containing(&server_result, "certificate verify failed");
     }
 
+    #[tokio::test]
+    async fn should_return_error_if_handshake_exceeds_limits() {
+        let registry = TlsRegistry::new();
+        let (server_builder, client_builder) =
+            matching_server_and_client_builders(SERVER_ID_1, CLIENT_ID_1);
+        let server = server_builder
+            .with_handshake_limits(TlsHandshakeLimits {
+                max_concurrent_handshakes: None,
+                max_handshakes_per_source_per_second: Some(0),
+            })
+            .build(registry.get());
+        let client = client_builder.build(registry.get());
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let (client_result, server_result) = tokio::join!(client.run(server.port()), server.run());
+
+        assert!(matches!(
+            server_result,
+            Err(TlsServerHandshakeError::HandshakeRejectedOverload(
+                HandshakeOverload::SourceRateExceeded { limit: 0, .. }
+            ))
+        ));
+        assert!(client_result.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_client_stalls_handshake() {
+        const TIMEOUT: Duration = Duration::from_millis(200);
+        let registry = TlsRegistry::new();
+        let server = Server::builder(SERVER_ID_1)
+            .add_allowed_client(CLIENT_ID_1)
+            .with_handshake_timeout(TIMEOUT)
+            .build(registry.get());
+        registry.add_cert(SERVER_ID_1, server.cert()).update();
+
+        // The client connects, but never starts the handshake.
+        let _tcp_stream = TcpStream::connect(("127.0.0.1", server.port()))
+            .await
+            .expect("failed to connect");
+        let server_result = server.run().await;
+
+        assert_eq!(
+            server_result.unwrap_err(),
+            TlsServerHandshakeError::Timeout { timeout: TIMEOUT }
+        );
+    }
+
     #[tokio::test]
     async fn should_return_error_if_client_cert_in_registry_is_malformed() {
         let (server, client, registry) = matching_server_and_client(SERVER_ID_1, CLIENT_ID_1);
@@ -550,7 +1030,8 @@ mod server {
         assert_handshake_server_error_containing(
             &server_result,
             "tls_early_post_process_client_hello:unsupported protocol",
-        )
+        );
+        assert_handshake_server_error_kind(&server_result, HandshakeFailureKind::UnsupportedVersion)
     }
 
     #[tokio::test]
@@ -572,7 +1053,8 @@ mod server {
 
         let (_, server_result) = tokio::join!(client.run(server.port()), server.run());
 
-        assert_handshake_server_error_containing(&server_result, "no shared cipher")
+        assert_handshake_server_error_containing(&server_result, "no shared cipher");
+        assert_handshake_server_error_kind(&server_result, HandshakeFailureKind::CipherMismatch)
     }
 
     #[tokio::test]
@@ -720,7 +1202,7 @@ mod server {
         //   but then the Registry check of the cert will fail, and we get a
         //   ClientNotAllowed error.
         match server_result.unwrap_err() {
-            TlsServerHandshakeError::HandshakeError { internal_error } => {
+            TlsServerHandshakeError::HandshakeError { internal_error, .. } => {
                 assert_string_contains(internal_error, "certificate verify failed");
             }
             TlsServerHandshakeError::ClientNotAllowed(PeerNotAllowedError::CertificatesDiffer) => {}
@@ -1042,6 +1524,7 @@ mod client {
     use ic_crypto_tls_interfaces::{PeerNotAllowedError, TlsClientHandshakeError};
     use openssl::hash::MessageDigest;
     use openssl::ssl::SslVersion;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn should_return_error_if_client_cert_in_registry_is_malformed() {
@@ -1060,6 +1543,31 @@ mod client {
         );
     }
 
+    #[tokio::test]
+    async fn should_return_error_if_server_stalls_handshake() {
+        const TIMEOUT: Duration = Duration::from_millis(200);
+        let registry = TlsRegistry::new();
+        // The server accepts connections, but is never run and therefore never
+        // responds to the handshake.
+        let server = Server::builder(SERVER_ID_1)
+            .add_allowed_client(CLIENT_ID_1)
+            .build(registry.get());
+        let client = Client::builder(CLIENT_ID_1, SERVER_ID_1)
+            .with_handshake_timeout(TIMEOUT)
+            .build(registry.get());
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let result = client.run(server.port()).await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            TlsClientHandshakeError::Timeout { timeout: TIMEOUT }
+        );
+    }
+
     #[tokio::test]
     async fn should_return_error_if_server_cert_in_registry_is_malformed() {
         // the server is only required so the client can connect somewhere
@@ -1225,7 +1733,8 @@ mod client {
 
         let (client_result, _) = tokio::join!(client.run(server.port()), server.run());
 
-        assert_handshake_client_error_containing(&client_result, "tlsv1 alert protocol version")
+        assert_handshake_client_error_containing(&client_result, "tlsv1 alert protocol version");
+        assert_handshake_client_error_kind(&client_result, HandshakeFailureKind::UnsupportedVersion)
     }
 
     #[tokio::test]
@@ -1244,7 +1753,9 @@ mod client {
 
         let (client_result, _) = tokio::join!(client.run(server.port()), server.run());
 
-        assert_handshake_client_error_containing(&client_result, "sslv3 alert handshake failure")
+        assert_handshake_client_error_containing(&client_result, "sslv3 alert handshake failure");
+        // The alert does not tell the client why the server failed.
+        assert_handshake_client_error_kind(&client_result, HandshakeFailureKind::Other)
     }
 
     #[tokio::test]
@@ -1473,6 +1984,59 @@ mod client {
 
         assert_handshake_client_error_containing(&client_result, "certificate verify failed");
     }
+
+    #[tokio::test]
+    async fn should_perform_tls_handshake_with_pinned_server_cert_not_in_registry() {
+        // The registry remains empty: neither the server's nor the client's
+        // certificate is looked up.
+        let registry = TlsRegistry::new();
+        let client = Client::builder(CLIENT_ID_1, SERVER_ID_1).build(registry.get());
+        let server = CustomServer::builder().build(
+            CertWithPrivateKey::builder()
+                .cn(SERVER_ID_1.to_string())
+                .build_ed25519(),
+            vec![client.cert()],
+        );
+        let server_cert = server.cert();
+
+        let (client_result, _) = tokio::join!(
+            client.run_with_pinned_cert(server.port(), server_cert.clone()),
+            server.run()
+        );
+
+        let expected_cert = TlsPublicKeyCert::new_from_der(server_cert.certificate_der).unwrap();
+        assert_eq!(
+            client_result.unwrap(),
+            AuthenticatedPeer::Cert(expected_cert)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_error_if_server_cert_differs_from_pinned_cert() {
+        let registry = TlsRegistry::new();
+        let client = Client::builder(CLIENT_ID_1, SERVER_ID_1).build(registry.get());
+        let server = CustomServer::builder()
+            .expect_error("sslv3 alert bad certificate")
+            .build(
+                CertWithPrivateKey::builder()
+                    .cn(SERVER_ID_1.to_string())
+                    .build_ed25519(),
+                vec![client.cert()],
+            );
+        let pinned_cert = x509_public_key_cert(
+            &CertWithPrivateKey::builder()
+                .cn(SERVER_ID_1.to_string())
+                .build_ed25519()
+                .x509(),
+        );
+
+        let (client_result, _) = tokio::join!(
+            client.run_with_pinned_cert(server.port(), pinned_cert),
+            server.run()
+        );
+
+        assert_handshake_client_error_containing(&client_result, "certificate verify failed");
+    }
 }
 
 mod communication {
@@ -1525,6 +2089,357 @@ mod communication {
     }
 }
 
+mod vectored_writes {
+    use super::*;
+    use std::io::IoSlice;
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    const CHUNKS: [&str; 3] = ["hello ", "from ", "client"];
+
+    #[tokio::test]
+    async fn should_send_vectored_message_over_stream() {
+        let (server, client) = server_expecting_chunks_and_client();
+
+        let client_task = async {
+            let mut tls_stream = client.run_and_return_stream(server.port()).await.unwrap();
+            write_all_vectored(&mut tls_stream, &CHUNKS).await;
+            tls_stream.shutdown().await
+        };
+        let (shutdown_result, server_result) = tokio::join!(client_task, server.run());
+
+        assert!(shutdown_result.is_ok());
+        assert_peer_node_eq(server_result.unwrap(), CLIENT_ID_1);
+    }
+
+    #[tokio::test]
+    async fn should_send_vectored_message_over_write_half() {
+        let (server, client) = server_expecting_chunks_and_client();
+
+        let client_task = async {
+            let tls_stream = client.run_and_return_stream(server.port()).await.unwrap();
+            let (_read_half, mut write_half) = tls_stream.split();
+            write_all_vectored(&mut write_half, &CHUNKS).await;
+            write_half.shutdown().await
+        };
+        let (shutdown_result, server_result) = tokio::join!(client_task, server.run());
+
+        assert!(shutdown_result.is_ok());
+        assert_peer_node_eq(server_result.unwrap(), CLIENT_ID_1);
+    }
+
+    fn server_expecting_chunks_and_client() -> (Server, Client) {
+        let registry = TlsRegistry::new();
+        let (server_builder, client_builder) =
+            matching_server_and_client_builders(SERVER_ID_1, CLIENT_ID_1);
+        let server = server_builder
+            .expect_msg_from_client(&CHUNKS.concat())
+            .build(registry.get());
+        let client = client_builder.build(registry.get());
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+        (server, client)
+    }
+
+    /// Writes all chunks with `write_vectored`, which may write only a prefix
+    /// of the chunks at a time.
+    async fn write_all_vectored<W: AsyncWrite + Unpin>(writer: &mut W, chunks: &[&str]) {
+        let mut remaining: Vec<&[u8]> = chunks.iter().map(|chunk| chunk.as_bytes()).collect();
+        while !remaining.is_empty() {
+            let slices: Vec<_> = remaining.iter().map(|chunk| IoSlice::new(chunk)).collect();
+            let mut written = writer.write_vectored(&slices).await.unwrap();
+            while written > 0 {
+                if written >= remaining[0].len() {
+                    written -= remaining.remove(0).len();
+                } else {
+                    remaining[0] = &remaining[0][written..];
+                    written = 0;
+                }
+            }
+        }
+        writer.flush().await.unwrap();
+    }
+}
+
+mod keying_material {
+    use super::*;
+
+    const LABEL: &str = "EXPORTER-test";
+
+    async fn export_on_both_sides(
+        server_context: &[u8],
+        client_context: &[u8],
+        len: usize,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let (server, client, registry) = matching_server_and_client(SERVER_ID_1, CLIENT_ID_1);
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let (client_result, server_result) = tokio::join!(
+            client.run_and_export_keying_material(server.port(), LABEL, client_context, len),
+            server.run_and_export_keying_material(LABEL, server_context, len)
+        );
+        (server_result.unwrap(), client_result.unwrap())
+    }
+
+    #[tokio::test]
+    async fn should_export_equal_keying_material_on_both_peers() {
+        let (server_material, client_material) =
+            export_on_both_sides(b"context", b"context", 32).await;
+
+        assert_eq!(server_material.len(), 32);
+        assert_eq!(server_material, client_material);
+    }
+
+    #[tokio::test]
+    async fn should_export_different_keying_material_for_different_contexts() {
+        let (server_material, client_material) =
+            export_on_both_sides(b"context", b"other context", 32).await;
+
+        assert_ne!(server_material, client_material);
+    }
+
+    #[tokio::test]
+    async fn should_export_different_keying_material_in_different_sessions() {
+        let (first_session, _) = export_on_both_sides(b"context", b"context", 32).await;
+        let (second_session, _) = export_on_both_sides(b"context", b"context", 32).await;
+
+        assert_ne!(first_session, second_session);
+    }
+}
+
+mod unsplit {
+    use super::*;
+    use ic_crypto_tls_interfaces::TlsStream;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn should_shut_down_reunited_stream_after_sending_message() {
+        let registry = TlsRegistry::new();
+        let (server_builder, client_builder) =
+            matching_server_and_client_builders(SERVER_ID_1, CLIENT_ID_1);
+        let msg = "hello from client";
+        let server = server_builder
+            .expect_msg_from_client(msg)
+            .build(registry.get());
+        let client = client_builder.build(registry.get());
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let client_task = async {
+            let tls_stream = client.run_and_return_stream(server.port()).await.unwrap();
+            let (read_half, mut write_half) = tls_stream.split();
+            write_half.write_all(msg.as_bytes()).await.unwrap();
+            let mut tls_stream = TlsStream::unsplit(read_half, write_half).unwrap();
+            tls_stream.shutdown().await
+        };
+        let (shutdown_result, server_result) = tokio::join!(client_task, server.run());
+
+        assert!(shutdown_result.is_ok());
+        assert_peer_node_eq(server_result.unwrap(), CLIENT_ID_1);
+    }
+
+    #[tokio::test]
+    async fn should_return_halves_if_they_are_from_different_streams() {
+        let (read_half_1, write_half_1) = established_stream().await.split();
+        let (read_half_2, write_half_2) = established_stream().await.split();
+
+        let error = TlsStream::unsplit(read_half_1, write_half_2).unwrap_err();
+        let (read_half_1, write_half_2) = (error.read_half, error.write_half);
+
+        assert!(TlsStream::unsplit(read_half_1, write_half_1).is_ok());
+        assert!(TlsStream::unsplit(read_half_2, write_half_2).is_ok());
+    }
+
+    async fn established_stream() -> TlsStream {
+        let (server, client, registry) = matching_server_and_client(SERVER_ID_1, CLIENT_ID_1);
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let (client_result, server_result) =
+            tokio::join!(client.run_and_return_stream(server.port()), server.run());
+        assert!(server_result.is_ok());
+        client_result.unwrap()
+    }
+}
+
+mod graceful_shutdown {
+    use super::*;
+    use ic_crypto_tls_interfaces::{TlsShutdownError, TlsStream};
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn should_shut_down_gracefully_on_both_peers() {
+        let (client_stream, server_stream) = established_streams().await;
+
+        let (client_result, server_result) = tokio::join!(
+            client_stream.shutdown_gracefully(TIMEOUT),
+            server_stream.shutdown_gracefully(TIMEOUT)
+        );
+
+        assert_eq!(client_result, Ok(()));
+        assert_eq!(server_result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn should_discard_data_sent_by_peer_before_its_close_notify() {
+        let (client_stream, mut server_stream) = established_streams().await;
+
+        let server_task = async {
+            server_stream.write_all(b"unread data").await.unwrap();
+            server_stream.shutdown_gracefully(TIMEOUT).await
+        };
+        let (client_result, server_result) =
+            tokio::join!(client_stream.shutdown_gracefully(TIMEOUT), server_task);
+
+        assert_eq!(client_result, Ok(()));
+        assert_eq!(server_result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn should_time_out_if_peer_does_not_send_close_notify() {
+        let (client_stream, _server_stream) = established_streams().await;
+
+        let result = client_stream
+            .shutdown_gracefully(Duration::from_millis(100))
+            .await;
+
+        assert_eq!(result, Err(TlsShutdownError::Timeout));
+    }
+
+    async fn established_streams() -> (TlsStream, TlsStream) {
+        let (server, client, registry) = matching_server_and_client(SERVER_ID_1, CLIENT_ID_1);
+        registry
+            .add_cert(SERVER_ID_1, server.cert())
+            .add_cert(CLIENT_ID_1, client.cert())
+            .update();
+
+        let (client_result, server_result) = tokio::join!(
+            client.run_and_return_stream(server.port()),
+            server.run_and_return_stream()
+        );
+        (client_result.unwrap(), server_result.unwrap())
+    }
+}
+
+mod peer_revalidation {
+    use super::*;
+    use ic_crypto_tls_interfaces::{PeerRevalidationError, TlsHandshake};
+    use ic_registry_keys::make_crypto_tls_cert_key;
+    use ic_types::RegistryVersion;
+
+    const REG_V1: RegistryVersion = RegistryVersion::new(1);
+    const REG_V2: RegistryVersion = RegistryVersion::new(2);
+
+    fn registry_with_certs(
+        certs: &[(NodeId, RegistryVersion, Option<X509PublicKeyCert>)],
+    ) -> Arc<FakeRegistryClient> {
+        let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+        for (node_id, version, cert) in certs {
+            data_provider
+                .add(&make_crypto_tls_cert_key(*node_id), *version, cert.clone())
+                .expect("failed to add TLS cert to registry");
+        }
+        let registry = Arc::new(FakeRegistryClient::new(data_provider as Arc<_>));
+        registry.update_to_latest_version();
+        registry
+    }
+
+    #[test]
+    fn should_revalidate_node_with_unchanged_cert() {
+        let cert = generate_cert_using_temp_crypto(CLIENT_ID_1);
+        let registry = registry_with_certs(&[(CLIENT_ID_1, REG_V1, Some(cert))]);
+        let (crypto, _) = temp_crypto_component_with_tls_keys(registry, SERVER_ID_1);
+
+        let result = crypto.revalidate_peer(&AuthenticatedPeer::Node(CLIENT_ID_1), REG_V1, REG_V1);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn should_fail_revalidation_if_node_removed() {
+        let cert = generate_cert_using_temp_crypto(CLIENT_ID_1);
+        let registry = registry_with_certs(&[
+            (CLIENT_ID_1, REG_V1, Some(cert)),
+            (CLIENT_ID_1, REG_V2, None),
+        ]);
+        let (crypto, _) = temp_crypto_component_with_tls_keys(registry, SERVER_ID_1);
+
+        let result = crypto.revalidate_peer(&AuthenticatedPeer::Node(CLIENT_ID_1), REG_V1, REG_V2);
+
+        assert_eq!(
+            result,
+            Err(PeerRevalidationError::NodeRemoved {
+                node_id: CLIENT_ID_1,
+                registry_version: REG_V2,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_revalidation_if_cert_rotated() {
+        let registry = registry_with_certs(&[
+            (
+                CLIENT_ID_1,
+                REG_V1,
+                Some(generate_cert_using_temp_crypto(CLIENT_ID_1)),
+            ),
+            (
+                CLIENT_ID_1,
+                REG_V2,
+                Some(generate_cert_using_temp_crypto(CLIENT_ID_1)),
+            ),
+        ]);
+        let (crypto, _) = temp_crypto_component_with_tls_keys(registry, SERVER_ID_1);
+
+        let result = crypto.revalidate_peer(&AuthenticatedPeer::Node(CLIENT_ID_1), REG_V1, REG_V2);
+
+        assert_eq!(
+            result,
+            Err(PeerRevalidationError::CertificateRotated {
+                node_id: CLIENT_ID_1,
+                registry_version: REG_V2,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_revalidation_if_cert_malformed() {
+        let registry = registry_with_certs(&[(CLIENT_ID_1, REG_V1, Some(malformed_cert()))]);
+        let (crypto, _) = temp_crypto_component_with_tls_keys(registry, SERVER_ID_1);
+
+        let result = crypto.revalidate_peer(&AuthenticatedPeer::Node(CLIENT_ID_1), REG_V1, REG_V1);
+
+        assert!(matches!(
+            result,
+            Err(PeerRevalidationError::MalformedCertificate(_))
+        ));
+    }
+
+    #[test]
+    fn should_always_revalidate_explicitly_trusted_cert() {
+        let cert = generate_cert_using_temp_crypto(CLIENT_ID_1);
+        let registry = registry_with_certs(&[]);
+        let (crypto, _) = temp_crypto_component_with_tls_keys(registry, SERVER_ID_1);
+        let peer =
+            AuthenticatedPeer::Cert(TlsPublicKeyCert::new_from_der(cert.certificate_der).unwrap());
+
+        let result = crypto.revalidate_peer(&peer, REG_V1, REG_V2);
+
+        assert_eq!(result, Ok(()));
+    }
+}
+
 fn matching_server_and_client(
     server_node_id: NodeId,
     client_node_id: NodeId,
@@ -1566,15 +2481,15 @@ fn assert_handshake_server_error_containing(
     error_substring: &str,
 ) {
     let error = server_result.clone().unwrap_err();
-    if let TlsServerHandshakeError::HandshakeError { internal_error } = error {
+    if let TlsServerHandshakeError::HandshakeError { internal_error, .. } = error {
         assert_string_contains(internal_error, error_substring);
     } else {
         panic!("expected HandshakeError error, got {}", error)
     }
 }
 
-fn assert_malformed_self_cert_client_error_containing(
-    client_result: &Result<(), TlsClientHandshakeError>,
+fn assert_malformed_self_cert_client_error_containing<T: Clone + Debug>(
+    client_result: &Result<T, TlsClientHandshakeError>,
     error_substring: &str,
 ) {
     let error = client_result.clone().unwrap_err();
@@ -1585,8 +2500,8 @@ fn assert_malformed_self_cert_client_error_containing(
     }
 }
 
-fn assert_malformed_server_cert_client_error_containing(
-    client_result: &Result<(), TlsClientHandshakeError>,
+fn assert_malformed_server_cert_client_error_containing<T: Clone + Debug>(
+    client_result: &Result<T, TlsClientHandshakeError>,
     error_substring: &str,
 ) {
     let error = client_result.clone().unwrap_err();
@@ -1600,18 +2515,42 @@ fn assert_malformed_server_cert_client_error_containing(
     }
 }
 
-fn assert_handshake_client_error_containing(
-    client_result: &Result<(), TlsClientHandshakeError>,
+fn assert_handshake_client_error_containing<T: Clone + Debug>(
+    client_result: &Result<T, TlsClientHandshakeError>,
     error_substring: &str,
 ) {
     let error = client_result.clone().unwrap_err();
-    if let TlsClientHandshakeError::HandshakeError { internal_error } = error {
+    if let TlsClientHandshakeError::HandshakeError { internal_error, .. } = error {
         assert_string_contains(internal_error, error_substring);
     } else {
         panic!("expected HandshakeError error, got {}", error)
     }
 }
 
+fn assert_handshake_server_error_kind(
+    server_result: &Result<AuthenticatedPeer, TlsServerHandshakeError>,
+    expected_kind: HandshakeFailureKind,
+) {
+    let error = server_result.clone().unwrap_err();
+    if let TlsServerHandshakeError::HandshakeError { kind, .. } = error {
+        assert_eq!(kind, expected_kind);
+    } else {
+        panic!("expected HandshakeError error, got {}", error)
+    }
+}
+
+fn assert_handshake_client_error_kind<T: Clone + Debug>(
+    client_result: &Result<T, TlsClientHandshakeError>,
+    expected_kind: HandshakeFailureKind,
+) {
+    let error = client_result.clone().unwrap_err();
+    if let TlsClientHandshakeError::HandshakeError { kind, .. } = error {
+        assert_eq!(kind, expected_kind);
+    } else {
+        panic!("expected HandshakeError error, got {}", error)
+    }
+}
+
 fn assert_malformed_client_cert_server_error_containing(
     server_result: &Result<AuthenticatedPeer, TlsServerHandshakeError>,
     error_substring: &str,
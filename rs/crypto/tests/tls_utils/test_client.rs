@@ -2,11 +2,14 @@
 use crate::tls_utils::{temp_crypto_component_with_tls_keys, REG_V1};
 use ic_crypto::utils::TempCryptoComponent;
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
-use ic_crypto_tls_interfaces::{TlsClientHandshakeError, TlsHandshake, TlsReadHalf, TlsWriteHalf};
+use ic_crypto_tls_interfaces::{
+    AuthenticatedPeer, TlsClientHandshakeError, TlsHandshake, TlsReadHalf, TlsStream, TlsWriteHalf,
+};
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_registry_client::fake::FakeRegistryClient;
 use ic_types::NodeId;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
@@ -16,6 +19,7 @@ pub struct ClientBuilder {
     msg_expected_from_server: Option<String>,
     msg_for_server: Option<String>,
     expected_error_substring_when_reading_stream: Option<String>,
+    handshake_timeout: Option<Duration>,
 }
 
 impl ClientBuilder {
@@ -34,8 +38,16 @@ impl ClientBuilder {
         self
     }
 
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
     pub fn build(self, registry: Arc<FakeRegistryClient>) -> Client {
-        let (crypto, cert) = temp_crypto_component_with_tls_keys(registry, self.node_id);
+        let (mut crypto, cert) = temp_crypto_component_with_tls_keys(registry, self.node_id);
+        if let Some(timeout) = self.handshake_timeout {
+            crypto = crypto.with_tls_handshake_timeout(timeout);
+        }
         Client {
             crypto,
             server_node_id: self.server_node_id,
@@ -67,15 +79,18 @@ impl Client {
             msg_expected_from_server: None,
             msg_for_server: None,
             expected_error_substring_when_reading_stream: None,
+            handshake_timeout: None,
         }
     }
 
-    pub async fn run(self, server_port: u16) -> Result<(), TlsClientHandshakeError> {
+    /// Performs the handshake and returns the server certificate from the
+    /// registry that the server was authenticated with.
+    pub async fn run(self, server_port: u16) -> Result<TlsPublicKeyCert, TlsClientHandshakeError> {
         let tcp_stream = TcpStream::connect(("127.0.0.1", server_port))
             .await
             .expect("failed to connect");
 
-        let tls_stream = self
+        let (tls_stream, server_cert) = self
             .crypto
             .perform_tls_client_handshake(tcp_stream, self.server_node_id, REG_V1)
             .await?;
@@ -86,7 +101,65 @@ impl Client {
         self.send_msg_to_server_if_configured(tls_write_half).await;
         self.expect_error_substring_when_reading_stream_if_configured(&mut tls_read_half)
             .await;
-        Ok(())
+        Ok(server_cert)
+    }
+
+    /// Performs the handshake with the server pinned to `expected_server_cert`
+    /// instead of looking up the server's certificate in the registry.
+    pub async fn run_with_pinned_cert(
+        self,
+        server_port: u16,
+        expected_server_cert: X509PublicKeyCert,
+    ) -> Result<AuthenticatedPeer, TlsClientHandshakeError> {
+        let tcp_stream = TcpStream::connect(("127.0.0.1", server_port))
+            .await
+            .expect("failed to connect");
+        let expected_server_cert =
+            TlsPublicKeyCert::new_from_der(expected_server_cert.certificate_der).unwrap();
+
+        let (_tls_stream, authenticated_server) = self
+            .crypto
+            .perform_tls_client_handshake_with_pinned_cert(tcp_stream, expected_server_cert)
+            .await?;
+        Ok(authenticated_server)
+    }
+
+    /// Performs the handshake and returns `len` bytes of keying material
+    /// exported from the session for `label` and `context`.
+    pub async fn run_and_export_keying_material(
+        self,
+        server_port: u16,
+        label: &str,
+        context: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>, TlsClientHandshakeError> {
+        let tcp_stream = TcpStream::connect(("127.0.0.1", server_port))
+            .await
+            .expect("failed to connect");
+
+        let (tls_stream, _server_cert) = self
+            .crypto
+            .perform_tls_client_handshake(tcp_stream, self.server_node_id, REG_V1)
+            .await?;
+        Ok(tls_stream
+            .export_keying_material(label, context, len)
+            .expect("failed to export keying material"))
+    }
+
+    /// Performs the handshake and returns the TLS stream without using it.
+    pub async fn run_and_return_stream(
+        self,
+        server_port: u16,
+    ) -> Result<TlsStream, TlsClientHandshakeError> {
+        let tcp_stream = TcpStream::connect(("127.0.0.1", server_port))
+            .await
+            .expect("failed to connect");
+
+        let (tls_stream, _server_cert) = self
+            .crypto
+            .perform_tls_client_handshake(tcp_stream, self.server_node_id, REG_V1)
+            .await?;
+        Ok(tls_stream)
     }
 
     async fn send_msg_to_server_if_configured(&self, mut tls_write_half: TlsWriteHalf) {
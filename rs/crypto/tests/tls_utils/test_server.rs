@@ -1,17 +1,19 @@
 #![allow(clippy::unwrap_used)]
 use crate::tls_utils::{temp_crypto_component_with_tls_keys, REG_V1};
 use ic_crypto::utils::TempCryptoComponent;
+use ic_crypto::TlsHandshakeLimits;
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
 use ic_crypto_tls_interfaces::{
-    AllowedClients, AuthenticatedPeer, Peer, SomeOrAllNodes, TlsHandshake, TlsReadHalf,
-    TlsServerHandshakeError, TlsWriteHalf,
+    AllowedClients, AuthenticatedPeer, ClientCas, Peer, SomeOrAllNodes, TlsHandshake, TlsReadHalf,
+    TlsServerHandshakeError, TlsStream, TlsWriteHalf,
 };
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_registry_client::fake::FakeRegistryClient;
 use ic_types::NodeId;
 use proptest::std_facade::BTreeSet;
 use std::collections::HashSet;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 
@@ -21,6 +23,9 @@ pub struct ServerBuilder {
     msg_expected_from_client: Option<String>,
     allowed_nodes: Option<SomeOrAllNodes>,
     allowed_certs: HashSet<TlsPublicKeyCert>,
+    revoked_certs: Option<HashSet<TlsPublicKeyCert>>,
+    handshake_limits: Option<TlsHandshakeLimits>,
+    handshake_timeout: Option<Duration>,
 }
 
 impl ServerBuilder {
@@ -49,7 +54,7 @@ impl ServerBuilder {
                 self.allowed_nodes = Some(SomeOrAllNodes::Some(nodes));
                 self
             }
-            Some(SomeOrAllNodes::All) => {
+            Some(SomeOrAllNodes::All) | Some(SomeOrAllNodes::AllExcept(_)) => {
                 panic!("invalid use of builder: cannot add node if all nodes are allowed")
             }
         }
@@ -65,6 +70,28 @@ impl ServerBuilder {
                 "invalid use of builder: cannot allow all nodes if some individual nodes are allowed"
             ),
             Some(SomeOrAllNodes::All) => self,
+            Some(SomeOrAllNodes::AllExcept(_)) => panic!(
+                "invalid use of builder: cannot allow all nodes if some nodes are excluded"
+            ),
+        }
+    }
+
+    pub fn allow_all_nodes_except(mut self, excluded: NodeId) -> ServerBuilder {
+        match self.allowed_nodes {
+            None => {
+                let mut excluded_nodes = BTreeSet::new();
+                excluded_nodes.insert(excluded);
+                self.allowed_nodes = Some(SomeOrAllNodes::AllExcept(excluded_nodes));
+                self
+            }
+            Some(SomeOrAllNodes::AllExcept(mut excluded_nodes)) => {
+                excluded_nodes.insert(excluded);
+                self.allowed_nodes = Some(SomeOrAllNodes::AllExcept(excluded_nodes));
+                self
+            }
+            Some(SomeOrAllNodes::Some(_)) | Some(SomeOrAllNodes::All) => panic!(
+                "invalid use of builder: cannot exclude nodes if individual nodes or all nodes are allowed"
+            ),
         }
     }
 
@@ -75,15 +102,46 @@ impl ServerBuilder {
         self
     }
 
+    /// Rejects clients that present `cert`, even if it is an allowed client
+    /// certificate.
+    pub fn revoke_client_cert(mut self, cert: X509PublicKeyCert) -> ServerBuilder {
+        let cert = TlsPublicKeyCert::new_from_der(cert.certificate_der)
+            .expect("failed to construct TlsPublicKeyCert from DER");
+        self.revoked_certs
+            .get_or_insert_with(HashSet::new)
+            .insert(cert);
+        self
+    }
+
+    pub fn with_handshake_limits(mut self, limits: TlsHandshakeLimits) -> ServerBuilder {
+        self.handshake_limits = Some(limits);
+        self
+    }
+
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> ServerBuilder {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
     pub fn build(self, registry: Arc<FakeRegistryClient>) -> Server {
         let listener = std::net::TcpListener::bind(("0.0.0.0", 0)).expect("failed to bind");
-        let (crypto, cert) = temp_crypto_component_with_tls_keys(registry, self.node_id);
-        let allowed_clients = AllowedClients::new(
+        let (mut crypto, cert) = temp_crypto_component_with_tls_keys(registry, self.node_id);
+        if let Some(limits) = self.handshake_limits {
+            crypto = crypto.with_tls_handshake_limits(limits);
+        }
+        if let Some(timeout) = self.handshake_timeout {
+            crypto = crypto.with_tls_handshake_timeout(timeout);
+        }
+        let mut allowed_clients = AllowedClients::new(
             self.allowed_nodes
                 .unwrap_or_else(|| SomeOrAllNodes::Some(BTreeSet::new())),
             self.allowed_certs,
         )
         .expect("failed to construct allowed clients");
+        if let Some(revoked_certs) = self.revoked_certs {
+            allowed_clients =
+                allowed_clients.with_revocation_check(Arc::new(RwLock::new(revoked_certs)));
+        }
         Server {
             listener,
             crypto,
@@ -114,6 +172,9 @@ impl Server {
             msg_expected_from_client: None,
             allowed_nodes: None,
             allowed_certs: HashSet::new(),
+            revoked_certs: None,
+            handshake_limits: None,
+            handshake_timeout: None,
         }
     }
 
@@ -122,7 +183,11 @@ impl Server {
 
         let (tls_stream, authenticated_node) = self
             .crypto
-            .perform_tls_server_handshake(tcp_stream, self.allowed_clients.clone(), REG_V1)
+            .perform_tls_server_handshake(
+                tcp_stream,
+                Arc::new(self.allowed_clients.clone()),
+                REG_V1,
+            )
             .await?;
         let (tls_read_half, tls_write_half) = tls_stream.split();
 
@@ -132,6 +197,44 @@ impl Server {
         Ok(authenticated_node)
     }
 
+    /// Performs the handshake and returns `len` bytes of keying material
+    /// exported from the session for `label` and `context`.
+    pub async fn run_and_export_keying_material(
+        self,
+        label: &str,
+        context: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>, TlsServerHandshakeError> {
+        let tcp_stream = self.accept_connection_on_listener().await;
+
+        let (tls_stream, _authenticated_node) = self
+            .crypto
+            .perform_tls_server_handshake(
+                tcp_stream,
+                Arc::new(self.allowed_clients.clone()),
+                REG_V1,
+            )
+            .await?;
+        Ok(tls_stream
+            .export_keying_material(label, context, len)
+            .expect("failed to export keying material"))
+    }
+
+    /// Performs the handshake and returns the TLS stream without using it.
+    pub async fn run_and_return_stream(self) -> Result<TlsStream, TlsServerHandshakeError> {
+        let tcp_stream = self.accept_connection_on_listener().await;
+
+        let (tls_stream, _authenticated_node) = self
+            .crypto
+            .perform_tls_server_handshake(
+                tcp_stream,
+                Arc::new(self.allowed_clients.clone()),
+                REG_V1,
+            )
+            .await?;
+        Ok(tls_stream)
+    }
+
     pub async fn run_with_optional_client_auth(self) -> Result<Peer, TlsServerHandshakeError> {
         let tcp_stream = self.accept_connection_on_listener().await;
 
@@ -151,6 +254,24 @@ impl Server {
         Ok(peer)
     }
 
+    pub async fn run_with_ca_client_auth(
+        self,
+        client_cas: ClientCas,
+    ) -> Result<AuthenticatedPeer, TlsServerHandshakeError> {
+        let tcp_stream = self.accept_connection_on_listener().await;
+
+        let (tls_stream, authenticated_peer) = self
+            .crypto
+            .perform_tls_server_handshake_with_ca_client_auth(tcp_stream, client_cas, REG_V1)
+            .await?;
+        let (tls_read_half, tls_write_half) = tls_stream.split();
+
+        self.send_msg_to_client_if_configured(tls_write_half).await;
+        self.expect_msg_from_client_if_configured(tls_read_half)
+            .await;
+        Ok(authenticated_peer)
+    }
+
     pub async fn run_without_client_auth(self) -> Result<(), TlsServerHandshakeError> {
         let tcp_stream = self.accept_connection_on_listener().await;
 
@@ -213,7 +334,7 @@ impl Server {
     pub fn allowed_clients(&self) -> &BTreeSet<NodeId> {
         match self.allowed_clients.nodes() {
             SomeOrAllNodes::Some(nodes) => nodes,
-            SomeOrAllNodes::All => unimplemented!(),
+            SomeOrAllNodes::All | SomeOrAllNodes::AllExcept(_) => unimplemented!(),
         }
     }
 }
@@ -1,9 +1,8 @@
 //! This module performs TLS keypair generation. It allows to generate an X.509
 //! public key certificate together with its private key.
 use super::*;
-use ic_crypto_internal_tls::keygen::generate_tls_key_pair;
+use ic_crypto_internal_tls::keygen::TlsCertBuilder;
 use ic_crypto_tls_interfaces::TlsPublicKeyCert;
-use openssl::asn1::Asn1Time;
 use rand::rngs::OsRng;
 use rand::Rng;
 
@@ -35,9 +34,9 @@ use rand::Rng;
 /// * if the generated X509 certificate is malformed
 pub fn generate_tls_keys(common_name: &str, not_after: &str) -> (TlsPublicKeyCert, TlsPrivateKey) {
     let serial: [u8; 19] = OsRng::default().gen();
-    let not_after =
-        Asn1Time::from_str_x509(not_after).expect("unable to parse not after as ASN1Time");
-    let (cert, secret_key) = generate_tls_key_pair(common_name, serial, &not_after);
+    let (cert, secret_key) = TlsCertBuilder::new(common_name, serial)
+        .with_not_after(not_after)
+        .build();
     (
         // We panic here, because we *shouldn't* generate a malformed cert.
         TlsPublicKeyCert::new_from_x509(cert).expect("Generated X509 certificate is malformed"),
@@ -77,7 +77,7 @@ fn should_return_error_if_not_after_is_in_the_past() {
 }
 
 #[test]
-#[should_panic(expected = "unable to parse not after as ASN1Time")]
+#[should_panic(expected = "invalid X.509 certificate expiration date (not_after)")]
 fn should_return_error_if_not_after_cannot_be_parsed() {
     const INVALID_NOT_AFTER: &str = "cannot be parsed as date";
 
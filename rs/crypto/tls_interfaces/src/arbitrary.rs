@@ -0,0 +1,286 @@
+//! Proptest strategies for the TLS interface types.
+//!
+//! They allow crates using the TLS handshake to property-test how they handle
+//! every kind of handshake error and peer. The module is available with the
+//! `test_utils` feature.
+use crate::test_utils::self_signed_cert_with_key;
+use crate::{
+    AllowedClients, AllowedClientsError, AuthenticatedPeer, HandshakeFailureKind,
+    HandshakeOverload, MalformedPeerCertificateError, PeerNotAllowedError, SomeOrAllNodes,
+    TlsClientHandshakeError, TlsPublicKeyCert, TlsServerHandshakeError,
+};
+use ic_types::registry::{RegistryClientError, RegistryDataProviderError};
+use ic_types::{NodeId, PrincipalId, RegistryVersion};
+use proptest::collection::{btree_set, vec};
+use proptest::prelude::*;
+use std::net::IpAddr;
+use std::time::Duration;
+
+pub fn arb_node_id() -> impl Strategy<Value = NodeId> {
+    any::<u64>().prop_map(|n| NodeId::from(PrincipalId::new_node_test_id(n)))
+}
+
+pub fn arb_registry_version() -> impl Strategy<Value = RegistryVersion> {
+    any::<u64>().prop_map(RegistryVersion::from)
+}
+
+/// Registry client errors, except for transport errors of the data provider,
+/// which cannot be constructed outside of the registry transport crate.
+pub fn arb_registry_client_error() -> impl Strategy<Value = RegistryClientError> {
+    prop_oneof![
+        arb_registry_version()
+            .prop_map(|version| RegistryClientError::VersionNotAvailable { version }),
+        Just(RegistryClientError::DataProviderQueryFailed {
+            source: RegistryDataProviderError::Timeout,
+        }),
+        any::<String>().prop_map(|error| RegistryClientError::PollLockFailed { error }),
+        any::<usize>()
+            .prop_map(|retries| RegistryClientError::PollingLatestVersionFailed { retries }),
+    ]
+}
+
+/// Self-signed Ed25519 certificates that differ in their serial number. Their
+/// common name is not a node ID.
+pub fn arb_tls_public_key_cert() -> impl Strategy<Value = TlsPublicKeyCert> {
+    any::<u32>().prop_map(|serial| self_signed_cert(&format!("arbitrary-{}", serial), serial))
+}
+
+/// Self-signed Ed25519 certificates with the given node ID as common name,
+/// like the certificates that nodes register in the registry.
+pub fn arb_cert_for_node(node_id: NodeId) -> impl Strategy<Value = TlsPublicKeyCert> {
+    any::<u32>().prop_map(move |serial| self_signed_cert(&node_id.get().to_string(), serial))
+}
+
+/// Node IDs together with a certificate for the node.
+pub fn arb_node_with_cert() -> impl Strategy<Value = (NodeId, TlsPublicKeyCert)> {
+    arb_node_id().prop_flat_map(|node_id| (Just(node_id), arb_cert_for_node(node_id)))
+}
+
+fn self_signed_cert(common_name: &str, serial: u32) -> TlsPublicKeyCert {
+    let (_key, cert) = self_signed_cert_with_key(common_name, serial);
+    TlsPublicKeyCert::new_from_x509(cert).expect("failed to create certificate")
+}
+
+fn arb_timeout() -> impl Strategy<Value = Duration> {
+    any::<u64>().prop_map(Duration::from_millis)
+}
+
+fn arb_cert_der() -> impl Strategy<Value = Option<Vec<u8>>> {
+    proptest::option::of(vec(any::<u8>(), 0..32))
+}
+
+impl Arbitrary for MalformedPeerCertificateError {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<String>()
+            .prop_map(|internal_error| MalformedPeerCertificateError { internal_error })
+            .boxed()
+    }
+}
+
+impl Arbitrary for PeerNotAllowedError {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed),
+            Just(PeerNotAllowedError::CertificatesDiffer),
+            Just(PeerNotAllowedError::CertificateRevoked),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for AllowedClientsError {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<String>().prop_map(|internal_error| {
+                AllowedClientsError::MalformedCertProto { internal_error }
+            }),
+            Just(AllowedClientsError::ClientsEmpty),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for HandshakeFailureKind {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(HandshakeFailureKind::PeerClosed),
+            Just(HandshakeFailureKind::BadCertSignature),
+            Just(HandshakeFailureKind::UnsupportedVersion),
+            Just(HandshakeFailureKind::CipherMismatch),
+            Just(HandshakeFailureKind::IoError),
+            Just(HandshakeFailureKind::Other),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for HandshakeOverload {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<usize>()
+                .prop_map(|limit| HandshakeOverload::TooManyConcurrentHandshakes { limit }),
+            (any::<IpAddr>(), any::<u32>()).prop_map(|(source_ip, limit)| {
+                HandshakeOverload::SourceRateExceeded { source_ip, limit }
+            }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for TlsServerHandshakeError {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            arb_registry_client_error().prop_map(TlsServerHandshakeError::RegistryError),
+            (arb_node_id(), arb_registry_version()).prop_map(|(node_id, registry_version)| {
+                TlsServerHandshakeError::CertificateNotInRegistry {
+                    node_id,
+                    registry_version,
+                }
+            }),
+            any::<String>().prop_map(|internal_error| {
+                TlsServerHandshakeError::MalformedSelfCertificate { internal_error }
+            }),
+            any::<MalformedPeerCertificateError>()
+                .prop_map(TlsServerHandshakeError::MalformedClientCertificate),
+            (
+                any::<String>(),
+                arb_cert_der(),
+                proptest::option::of(any::<String>())
+            )
+                .prop_map(|(description, cert_der, internal_error)| {
+                    TlsServerHandshakeError::CreateAcceptorError {
+                        description,
+                        cert_der,
+                        internal_error,
+                    }
+                }),
+            (any::<HandshakeFailureKind>(), any::<String>()).prop_map(|(kind, internal_error)| {
+                TlsServerHandshakeError::HandshakeError {
+                    kind,
+                    internal_error,
+                }
+            }),
+            any::<PeerNotAllowedError>().prop_map(TlsServerHandshakeError::ClientNotAllowed),
+            any::<AllowedClientsError>()
+                .prop_map(TlsServerHandshakeError::AllowedClientsUnavailable),
+            Just(TlsServerHandshakeError::UnauthenticatedClient),
+            // Nested to stay within the number of strategies `prop_oneof!`
+            // supports without boxing.
+            prop_oneof![
+                any::<HandshakeOverload>()
+                    .prop_map(TlsServerHandshakeError::HandshakeRejectedOverload),
+                arb_timeout().prop_map(|timeout| TlsServerHandshakeError::Timeout { timeout }),
+            ],
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for TlsClientHandshakeError {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            arb_registry_client_error().prop_map(TlsClientHandshakeError::RegistryError),
+            (arb_node_id(), arb_registry_version()).prop_map(|(node_id, registry_version)| {
+                TlsClientHandshakeError::CertificateNotInRegistry {
+                    node_id,
+                    registry_version,
+                }
+            }),
+            any::<String>().prop_map(|internal_error| {
+                TlsClientHandshakeError::MalformedSelfCertificate { internal_error }
+            }),
+            Just(TlsClientHandshakeError::SelfCertificateNotFound),
+            any::<MalformedPeerCertificateError>()
+                .prop_map(TlsClientHandshakeError::MalformedServerCertificate),
+            (
+                any::<String>(),
+                arb_cert_der(),
+                arb_cert_der(),
+                any::<String>()
+            )
+                .prop_map(
+                    |(description, client_cert_der, server_cert_der, internal_error)| {
+                        TlsClientHandshakeError::CreateConnectorError {
+                            description,
+                            client_cert_der,
+                            server_cert_der,
+                            internal_error,
+                        }
+                    }
+                ),
+            (any::<HandshakeFailureKind>(), any::<String>()).prop_map(|(kind, internal_error)| {
+                TlsClientHandshakeError::HandshakeError {
+                    kind,
+                    internal_error,
+                }
+            }),
+            any::<PeerNotAllowedError>().prop_map(TlsClientHandshakeError::ServerNotAllowed),
+            arb_timeout().prop_map(|timeout| TlsClientHandshakeError::Timeout { timeout }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for AuthenticatedPeer {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            arb_node_id().prop_map(AuthenticatedPeer::Node),
+            arb_tls_public_key_cert().prop_map(AuthenticatedPeer::Cert),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for SomeOrAllNodes {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            btree_set(arb_node_id(), 0..5).prop_map(SomeOrAllNodes::Some),
+            Just(SomeOrAllNodes::All),
+            btree_set(arb_node_id(), 0..5).prop_map(SomeOrAllNodes::AllExcept),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for AllowedClients {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        (
+            any::<SomeOrAllNodes>(),
+            vec(arb_tls_public_key_cert(), 0..3),
+        )
+            .prop_filter_map("allowed clients must not be empty", |(nodes, certs)| {
+                AllowedClients::new(nodes, certs.into_iter().collect()).ok()
+            })
+            .boxed()
+    }
+}
@@ -0,0 +1,105 @@
+use crate::revocation::OptionalRevocationCheck;
+use crate::{TlsCertRevocationCheck, TlsPublicKeyCert, TrustStore};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::Arc;
+
+/// The largest supported number of intermediate CA certificates between a
+/// client certificate and the CA it chains to.
+pub const MAX_INTERMEDIATE_CLIENT_CAS: u32 = 4;
+
+#[derive(Clone)]
+/// Certificate authorities that issue client certificates, e.g., the CAs with
+/// which operators of boundary nodes issue certificates to their fleets.
+///
+/// In a handshake with client authentication by CA, a client is
+/// authenticated if it presents a certificate chain that ends in one of the
+/// CAs, has at most `max_intermediate_cas` intermediate CA certificates, and
+/// contains no certificate that the revocation check (if any) considers
+/// revoked.
+pub struct ClientCas {
+    cas: TrustStore,
+    max_intermediate_cas: u32,
+    revocation_check: OptionalRevocationCheck,
+}
+
+impl ClientCas {
+    /// Creates `ClientCas` without a revocation check.
+    ///
+    /// # Errors
+    /// * `ClientCasError::CasEmpty` if `cas` is empty.
+    /// * `ClientCasError::TooManyIntermediateCas` if `max_intermediate_cas`
+    ///   exceeds `MAX_INTERMEDIATE_CLIENT_CAS`.
+    pub fn new(cas: TrustStore, max_intermediate_cas: u32) -> Result<Self, ClientCasError> {
+        if cas.is_empty() {
+            return Err(ClientCasError::CasEmpty);
+        }
+        if max_intermediate_cas > MAX_INTERMEDIATE_CLIENT_CAS {
+            return Err(ClientCasError::TooManyIntermediateCas {
+                max_intermediate_cas,
+                limit: MAX_INTERMEDIATE_CLIENT_CAS,
+            });
+        }
+        Ok(Self {
+            cas,
+            max_intermediate_cas,
+            revocation_check: OptionalRevocationCheck::default(),
+        })
+    }
+
+    /// Rejects clients whose certificate chain contains a certificate that
+    /// `revocation_check` considers revoked.
+    pub fn with_revocation_check(
+        mut self,
+        revocation_check: Arc<dyn TlsCertRevocationCheck>,
+    ) -> Self {
+        self.revocation_check = OptionalRevocationCheck::new(revocation_check);
+        self
+    }
+
+    /// Access the trusted CAs.
+    pub fn cas(&self) -> &TrustStore {
+        &self.cas
+    }
+
+    /// The maximum number of intermediate CA certificates in a client's
+    /// certificate chain.
+    pub fn max_intermediate_cas(&self) -> u32 {
+        self.max_intermediate_cas
+    }
+
+    /// Returns true if there is a revocation check and it considers `cert`
+    /// revoked.
+    pub fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.revocation_check.is_revoked(cert)
+    }
+}
+
+impl Debug for ClientCas {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCas")
+            .field("cas", &self.cas)
+            .field("max_intermediate_cas", &self.max_intermediate_cas)
+            .field("has_revocation_check", &self.revocation_check.is_some())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The client CAs could not be created.
+pub enum ClientCasError {
+    /// Attempted to create `ClientCas` without any CA.
+    CasEmpty,
+    /// Attempted to allow more intermediate CA certificates than supported.
+    TooManyIntermediateCas {
+        max_intermediate_cas: u32,
+        limit: u32,
+    },
+}
+
+impl Display for ClientCasError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ClientCasError {}
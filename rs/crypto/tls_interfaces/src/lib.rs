@@ -6,22 +6,40 @@ use async_trait::async_trait;
 use core::fmt;
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_types::registry::RegistryClientError;
-use ic_types::{NodeId, RegistryVersion};
+use ic_types::{NodeId, RegistryVersion, Time};
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
 use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
 use openssl::x509::X509;
+use revocation::OptionalRevocationCheck;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::{BTreeSet, HashSet};
 use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::io;
+use std::io::IoSlice;
+use std::net::IpAddr;
 use std::pin::Pin;
+use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf, ReadHalf, WriteHalf};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
 use tokio_openssl::SslStream;
 
+#[cfg(any(test, feature = "test_utils"))]
+pub mod arbitrary;
+mod client_cas;
+mod revocation;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
 #[cfg(test)]
 mod tests;
+mod trust_store;
+
+pub use client_cas::{ClientCas, ClientCasError, MAX_INTERMEDIATE_CLIENT_CAS};
+pub use revocation::{TlsCertRevocationCheck, TlsCertRevocationFile};
+pub use trust_store::{CertFingerprint, TrustStore, TrustStoreError};
 
 #[derive(Clone, Debug, Serialize)]
 /// An X.509 certificate
@@ -79,6 +97,53 @@ impl TlsPublicKeyCert {
         }
     }
 
+    /// Returns the SHA-256 fingerprint of the certificate
+    pub fn fingerprint(&self) -> CertFingerprint {
+        let mut fingerprint = [0; 32];
+        fingerprint.copy_from_slice(&self.hash_cached);
+        CertFingerprint(fingerprint)
+    }
+
+    /// Returns the start of the certificate's validity period
+    pub fn not_before(&self) -> &Asn1TimeRef {
+        self.cert.not_before()
+    }
+
+    /// Returns the end of the certificate's validity period
+    pub fn not_after(&self) -> &Asn1TimeRef {
+        self.cert.not_after()
+    }
+
+    /// Returns true if `time` lies within the certificate's validity period,
+    /// both ends included. The validity period has a resolution of seconds,
+    /// so `time` is truncated to seconds.
+    pub fn is_valid_at(&self, time: Time) -> bool {
+        let seconds = time.as_nanos_since_unix_epoch() / 1_000_000_000;
+        match Asn1Time::from_unix(seconds as i64) {
+            Ok(time) => self.not_before() <= time && self.not_after() >= time,
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the subject common name, or `None` if the subject does not
+    /// have exactly one common name or if it is not valid UTF-8.
+    pub fn subject_cn(&self) -> Option<String> {
+        self.single_subject_cn().ok()
+    }
+
+    fn single_subject_cn(&self) -> Result<String, String> {
+        let mut entries = self.cert.subject_name().entries_by_nid(Nid::COMMONNAME);
+        let entry = entries.next().ok_or("Missing subject common name")?;
+        if entries.next().is_some() {
+            return Err("Too many subject common names".to_string());
+        }
+        entry
+            .data()
+            .as_utf8()
+            .map(|common_name| common_name.to_string())
+            .map_err(|e| format!("ASN1 to UTF-8 conversion error: {}", e))
+    }
+
     fn hash(cert: &X509) -> Result<Vec<u8>, TlsPublicKeyCertCreationError> {
         let hash = cert
             .digest(MessageDigest::sha256())
@@ -158,10 +223,46 @@ pub enum TlsServerHandshakeError {
         internal_error: Option<String>,
     },
     HandshakeError {
+        kind: HandshakeFailureKind,
         internal_error: String,
     },
     ClientNotAllowed(PeerNotAllowedError),
+    AllowedClientsUnavailable(AllowedClientsError),
     UnauthenticatedClient,
+    HandshakeRejectedOverload(HandshakeOverload),
+    Timeout {
+        timeout: Duration,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Why the TLS protocol part of a handshake failed, as far as it can be told
+/// from the error of the TLS library. Callers can branch on it instead of
+/// parsing the `internal_error` of a `HandshakeError`, e.g., to retry a
+/// handshake only if the connection was lost.
+pub enum HandshakeFailureKind {
+    /// The peer closed the connection during the handshake.
+    PeerClosed,
+    /// The certificate of the peer, or the handshake signature made with it,
+    /// failed verification.
+    BadCertSignature,
+    /// The peers do not support a common TLS version.
+    UnsupportedVersion,
+    /// The peers do not support a common cipher suite or signature algorithm.
+    CipherMismatch,
+    /// Reading from or writing to the connection failed.
+    IoError,
+    /// Any other failure, see the `internal_error` for details.
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// The limit that caused a server handshake to be rejected before it started.
+pub enum HandshakeOverload {
+    /// The maximum number of handshakes in flight was reached.
+    TooManyConcurrentHandshakes { limit: usize },
+    /// The source of the connection started too many handshakes recently.
+    SourceRateExceeded { source_ip: IpAddr, limit: u32 },
 }
 
 impl Display for TlsServerHandshakeError {
@@ -200,6 +301,9 @@ pub enum PeerNotAllowedError {
     /// Peer's certificate offered during the handshake
     /// doesn't match the trusted certificate.
     CertificatesDiffer,
+    /// A certificate in the chain offered by the peer during the handshake
+    /// is revoked.
+    CertificateRevoked,
 }
 
 impl From<PeerNotAllowedError> for TlsServerHandshakeError {
@@ -220,6 +324,7 @@ pub enum TlsClientHandshakeError {
     MalformedSelfCertificate {
         internal_error: String,
     },
+    SelfCertificateNotFound,
     MalformedServerCertificate(MalformedPeerCertificateError),
     CreateConnectorError {
         description: String,
@@ -228,9 +333,13 @@ pub enum TlsClientHandshakeError {
         internal_error: String,
     },
     HandshakeError {
+        kind: HandshakeFailureKind,
         internal_error: String,
     },
     ServerNotAllowed(PeerNotAllowedError),
+    Timeout {
+        timeout: Duration,
+    },
 }
 
 impl Display for TlsClientHandshakeError {
@@ -253,6 +362,95 @@ impl From<PeerNotAllowedError> for TlsClientHandshakeError {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Errors from re-validating an authenticated peer against a newer registry
+/// version. Please refer to `TlsHandshake::revalidate_peer` for detailed
+/// error variant descriptions.
+pub enum PeerRevalidationError {
+    RegistryError(RegistryClientError),
+    NodeRemoved {
+        node_id: NodeId,
+        registry_version: RegistryVersion,
+    },
+    CertificateRotated {
+        node_id: NodeId,
+        registry_version: RegistryVersion,
+    },
+    MalformedCertificate(MalformedPeerCertificateError),
+}
+
+impl Display for PeerRevalidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for PeerRevalidationError {}
+
+impl From<MalformedPeerCertificateError> for PeerRevalidationError {
+    fn from(malformed_peer_cert_error: MalformedPeerCertificateError) -> Self {
+        PeerRevalidationError::MalformedCertificate(malformed_peer_cert_error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Errors from exporting keying material of a TLS session.
+///
+/// The error only describes why the export failed. It never contains keying
+/// material or other secret state of the session, so it is safe to log.
+pub struct TlsKeyingMaterialExportError {
+    pub internal_error: String,
+}
+
+impl Display for TlsKeyingMaterialExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for TlsKeyingMaterialExportError {}
+
+/// The halves passed to `TlsStream::unsplit` were not split from the same
+/// stream.
+pub struct TlsStreamUnsplitError {
+    pub read_half: TlsReadHalf,
+    pub write_half: TlsWriteHalf,
+}
+
+impl fmt::Debug for TlsStreamUnsplitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsStreamUnsplitError").finish()
+    }
+}
+
+impl Display for TlsStreamUnsplitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The read and write halves are not from the same TLS stream"
+        )
+    }
+}
+
+impl std::error::Error for TlsStreamUnsplitError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Errors from gracefully shutting down a `TlsStream`.
+pub enum TlsShutdownError {
+    /// The peer's close_notify did not arrive within the timeout.
+    Timeout,
+    /// Sending the close_notify or receiving the peer's close_notify failed.
+    Io { internal_error: String },
+}
+
+impl Display for TlsShutdownError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for TlsShutdownError {}
+
 /// A stream over a secure connection protected by TLS.
 pub struct TlsStream {
     ssl_stream: SslStream<TcpStream>,
@@ -270,6 +468,85 @@ impl TlsStream {
         let (read_half, write_half) = tokio::io::split(self.ssl_stream);
         (TlsReadHalf::new(read_half), TlsWriteHalf::new(write_half))
     }
+
+    /// Reunites the halves returned by `split` into the original stream, e.g.,
+    /// to shut the stream down gracefully. This is the counterpart of
+    /// `tokio::io::ReadHalf::unsplit`.
+    ///
+    /// # Errors
+    /// * TlsStreamUnsplitError if the halves were not split from the same
+    ///   stream. The error returns both halves to the caller.
+    pub fn unsplit(
+        read_half: TlsReadHalf,
+        write_half: TlsWriteHalf,
+    ) -> Result<Self, TlsStreamUnsplitError> {
+        if !read_half.read_half.is_pair_of(&write_half.write_half) {
+            return Err(TlsStreamUnsplitError {
+                read_half,
+                write_half,
+            });
+        }
+        Ok(Self::new(
+            read_half.read_half.unsplit(write_half.write_half),
+        ))
+    }
+
+    /// Derives `len` bytes of keying material from the TLS session using the
+    /// exporter of RFC 5705 (see also RFC 8446, section 7.5).
+    ///
+    /// Both peers of a session derive the same keying material for the same
+    /// `label` and `context`, and the keying material of different sessions is
+    /// independent. This allows higher-layer protocols to bind secrets or
+    /// tokens to the TLS channel. By convention, `label` starts with
+    /// `"EXPORTER-"`.
+    ///
+    /// # Errors
+    /// * TlsKeyingMaterialExportError if the keying material cannot be
+    ///   derived, e.g., because `len` is too large.
+    pub fn export_keying_material(
+        &self,
+        label: &str,
+        context: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>, TlsKeyingMaterialExportError> {
+        let mut keying_material = vec![0; len];
+        self.ssl_stream
+            .ssl()
+            .export_keying_material(&mut keying_material, label, Some(context))
+            .map_err(|e| TlsKeyingMaterialExportError {
+                internal_error: format!("Error exporting keying material: {}", e),
+            })?;
+        Ok(keying_material)
+    }
+
+    /// Closes the connection gracefully: sends a close_notify alert to the
+    /// peer, waits for the peer's close_notify, and then closes the TCP
+    /// connection.
+    ///
+    /// Application data that the peer sends before its close_notify is
+    /// discarded. The TCP connection is closed also if an error occurs.
+    ///
+    /// # Errors
+    /// * TlsShutdownError::Timeout if the peer's close_notify did not arrive
+    ///   within `timeout`.
+    /// * TlsShutdownError::Io if sending the close_notify or receiving the
+    ///   peer's close_notify failed, e.g., because the TCP connection was
+    ///   reset.
+    pub async fn shutdown_gracefully(mut self, timeout: Duration) -> Result<(), TlsShutdownError> {
+        tokio::time::timeout(timeout, self.send_and_await_close_notify())
+            .await
+            .map_err(|_elapsed| TlsShutdownError::Timeout)?
+            .map_err(|e| TlsShutdownError::Io {
+                internal_error: format!("Error shutting down TLS stream: {}", e),
+            })
+    }
+
+    async fn send_and_await_close_notify(&mut self) -> io::Result<()> {
+        self.ssl_stream.shutdown().await?;
+        let mut discarded = [0; 1024];
+        while self.ssl_stream.read(&mut discarded).await? > 0 {}
+        Ok(())
+    }
 }
 
 impl AsyncRead for TlsStream {
@@ -291,6 +568,18 @@ impl AsyncWrite for TlsStream {
         Pin::new(&mut self.ssl_stream).poll_write(cx, buf)
     }
 
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.ssl_stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.ssl_stream.is_write_vectored()
+    }
+
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
         Pin::new(&mut self.ssl_stream).poll_flush(cx)
     }
@@ -344,6 +633,18 @@ impl AsyncWrite for TlsWriteHalf {
         Pin::new(&mut self.write_half).poll_write(cx, buf)
     }
 
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.write_half).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.write_half.is_write_vectored()
+    }
+
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
         Pin::new(&mut self.write_half).poll_flush(cx)
     }
@@ -375,6 +676,11 @@ pub trait TlsHandshake {
     /// * Client authentication: mandatory, with ed25519 certificate
     /// * Maximum number of intermediate CA certificates: 1
     ///
+    /// The allowed clients are obtained from the `allowed_clients` provider
+    /// for the `registry_version` when the handshake starts, so that a
+    /// provider shared by many acceptors can follow changes of the
+    /// membership.
+    ///
     /// To determine whether the peer (that successfully performed the
     /// handshake) is an allowed client, the following steps are taken:
     /// 1. Determine the peer's node ID N_claimed from the _subject name_ of
@@ -417,8 +723,17 @@ pub trait TlsHandshake {
     ///   not in `allowed_clients`, or if the client's certificate presented in
     ///   the handshake does not exactly match the client's certificate in the
     ///   registry.
+    /// * TlsServerHandshakeError::AllowedClientsUnavailable if
+    ///   `allowed_clients` cannot provide the allowed clients for
+    ///   `registry_version`, in which case no handshake is attempted.
     /// * TlsServerHandshakeError::UnauthenticatedClient if the client did not
     ///   authenticate using a client certificate.
+    /// * TlsServerHandshakeError::HandshakeRejectedOverload if the handshake
+    ///   exceeds the handshake limits the crypto component was configured
+    ///   with, in which case no handshake is attempted.
+    /// * TlsServerHandshakeError::Timeout if the handshake does not complete
+    ///   within the handshake timeout the crypto component was configured
+    ///   with.
     ///
     /// # Panics
     /// * If the secret key corresponding to the server certificate cannot be
@@ -427,7 +742,7 @@ pub trait TlsHandshake {
     async fn perform_tls_server_handshake(
         &self,
         tcp_stream: TcpStream,
-        allowed_clients: AllowedClients,
+        allowed_clients: Arc<dyn AllowedClientsProvider>,
         registry_version: RegistryVersion,
     ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError>;
 
@@ -506,6 +821,12 @@ pub trait TlsHandshake {
     ///   not in `allowed_authenticating_clients`, or if the client's
     ///   certificate presented in the handshake does not exactly match the
     ///   client's certificate in the registry.
+    /// * TlsServerHandshakeError::HandshakeRejectedOverload if the handshake
+    ///   exceeds the handshake limits the crypto component was configured
+    ///   with, in which case no handshake is attempted.
+    /// * TlsServerHandshakeError::Timeout if the handshake does not complete
+    ///   within the handshake timeout the crypto component was configured
+    ///   with.
     ///
     /// # Panics
     /// * If the secret key corresponding to the server certificate cannot be
@@ -543,6 +864,12 @@ pub trait TlsHandshake {
     ///   configuring the server for accepting connections from clients.
     /// * TlsServerHandshakeError::HandshakeError if there is an error during
     ///   the TLS handshake, or the handshake fails.
+    /// * TlsServerHandshakeError::HandshakeRejectedOverload if the handshake
+    ///   exceeds the handshake limits the crypto component was configured
+    ///   with, in which case no handshake is attempted.
+    /// * TlsServerHandshakeError::Timeout if the handshake does not complete
+    ///   within the handshake timeout the crypto component was configured
+    ///   with.
     ///
     /// # Panics
     /// * If the secret key corresponding to the server certificate cannot be
@@ -554,6 +881,66 @@ pub trait TlsHandshake {
         registry_version: RegistryVersion,
     ) -> Result<TlsStream, TlsServerHandshakeError>;
 
+    /// Transforms a TCP stream into a TLS stream by performing a TLS server
+    /// handshake in which the client must authenticate with a certificate
+    /// issued by one of the `client_cas`. Such clients, e.g., boundary nodes
+    /// with certificates issued by their operator, are not in the registry.
+    ///
+    /// For the handshake, the server uses the following configuration:
+    /// * Minimum protocol version: TLS 1.3
+    /// * Supported signature algorithms: ed25519
+    /// * Allowed cipher suites: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384
+    /// * Client authentication: mandatory, with ed25519 certificate
+    /// * Maximum number of intermediate CA certificates:
+    ///   `client_cas.max_intermediate_cas()`
+    ///
+    /// The TLS handshake only succeeds if the certificate chain that the
+    /// client presents is valid and ends in one of the `client_cas`. After the
+    /// handshake, the client is rejected if the revocation check of
+    /// `client_cas` considers any certificate in the chain revoked.
+    ///
+    /// The given `tcp_stream` is consumed. If an error is returned, the TCP
+    /// connection is therefore dropped.
+    ///
+    /// Returns the TLS stream together with the leaf of the client's
+    /// certificate chain as `AuthenticatedPeer::Cert`.
+    ///
+    /// # Errors
+    /// * TlsServerHandshakeError::RegistryError if the registry cannot be
+    ///   accessed.
+    /// * TlsServerHandshakeError::CertificateNotInRegistry if the node's own
+    ///   server certificate is not found in the registry.
+    /// * TlsServerHandshakeError::MalformedSelfCertificate if the node's own
+    ///   server certificate is malformed.
+    /// * TlsServerHandshakeError::MalformedClientCertificate if a certificate
+    ///   in the chain offered by the client is malformed.
+    /// * TlsServerHandshakeError::CreateAcceptorError if there is a problem
+    ///   configuring the server for accepting connections from clients.
+    /// * TlsServerHandshakeError::HandshakeError if there is an error during
+    ///   the TLS handshake, or the handshake fails, e.g., because the client's
+    ///   certificate chain does not end in one of the `client_cas`.
+    /// * TlsServerHandshakeError::ClientNotAllowed if a certificate in the
+    ///   client's certificate chain is revoked.
+    /// * TlsServerHandshakeError::UnauthenticatedClient if the client did not
+    ///   authenticate using a client certificate.
+    /// * TlsServerHandshakeError::HandshakeRejectedOverload if the handshake
+    ///   exceeds the handshake limits the crypto component was configured
+    ///   with, in which case no handshake is attempted.
+    /// * TlsServerHandshakeError::Timeout if the handshake does not complete
+    ///   within the handshake timeout the crypto component was configured
+    ///   with.
+    ///
+    /// # Panics
+    /// * If the secret key corresponding to the server certificate cannot be
+    ///   found or is malformed in the server's secret key store. Note that this
+    ///   is an error in the setup of the node and registry.
+    async fn perform_tls_server_handshake_with_ca_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        client_cas: ClientCas,
+        registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError>;
+
     /// Transforms a TCP stream into a TLS stream by first performing a TLS
     /// client handshake and then verifying that the peer is the given `server`.
     ///
@@ -576,6 +963,9 @@ pub trait TlsHandshake {
     /// The given `tcp_stream` is consumed. If an error is returned, the TCP
     /// connection is therefore dropped.
     ///
+    /// Returns the TLS stream together with the certificate C_registry that
+    /// the server was authenticated with, e.g., for audit logs.
+    ///
     /// # Errors
     /// * TlsClientHandshakeError::RegistryError if the registry cannot be
     ///   accessed.
@@ -595,6 +985,9 @@ pub trait TlsHandshake {
     ///   not equal `server`, or if the server's certificate presented in the
     ///   handshake does not exactly match the `server`'s certificate in the
     ///   registry.
+    /// * TlsClientHandshakeError::Timeout if the handshake does not complete
+    ///   within the handshake timeout the crypto component was configured
+    ///   with.
     ///
     /// # Panics
     /// * If the secret key corresponding to the client certificate cannot be
@@ -605,15 +998,96 @@ pub trait TlsHandshake {
         tcp_stream: TcpStream,
         server: NodeId,
         registry_version: RegistryVersion,
-    ) -> Result<TlsStream, TlsClientHandshakeError>;
+    ) -> Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError>;
+
+    /// Transforms a TCP stream into a TLS stream by first performing a TLS
+    /// client handshake and then verifying that the peer presented exactly
+    /// the `expected_cert`.
+    ///
+    /// This is for servers that are not in the registry, or whose
+    /// certificate the caller obtained through another channel. Neither the
+    /// client's own certificate nor the server's certificate is looked up in
+    /// the registry: the client uses its certificate from its local public
+    /// key store.
+    ///
+    /// For the handshake, the client uses the same configuration as
+    /// `perform_tls_client_handshake`, with `expected_cert` as the only
+    /// trusted server certificate.
+    ///
+    /// The given `tcp_stream` is consumed. If an error is returned, the TCP
+    /// connection is therefore dropped.
+    ///
+    /// Returns the TLS stream together with the authenticated server, which
+    /// is always `AuthenticatedPeer::Cert(expected_cert)`.
+    ///
+    /// # Errors
+    /// * TlsClientHandshakeError::SelfCertificateNotFound if the node's own
+    ///   client certificate is not in its public key store.
+    /// * TlsClientHandshakeError::MalformedSelfCertificate if the node's own
+    ///   client certificate is malformed.
+    /// * TlsClientHandshakeError::CreateConnectorError if there is a problem
+    ///   configuring the TLS client for connecting to the server.
+    /// * TlsClientHandshakeError::HandshakeError if there is an error during
+    ///   the TLS handshake, or the handshake fails.
+    /// * TlsClientHandshakeError::ServerNotAllowed if the server's certificate
+    ///   presented in the handshake does not exactly match `expected_cert`.
+    /// * TlsClientHandshakeError::Timeout if the handshake does not complete
+    ///   within the handshake timeout the crypto component was configured
+    ///   with.
+    ///
+    /// # Panics
+    /// * If the secret key corresponding to the client certificate cannot be
+    ///   found or is malformed in the client's secret key store. Note that this
+    ///   is an error in the setup of the node.
+    async fn perform_tls_client_handshake_with_pinned_cert(
+        &self,
+        tcp_stream: TcpStream,
+        expected_cert: TlsPublicKeyCert,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsClientHandshakeError>;
+
+    /// Re-validates a peer that authenticated in a TLS handshake performed at
+    /// `handshake_registry_version` against the (newer) `registry_version`.
+    ///
+    /// This allows long-lived connections to be dropped once the peer is no
+    /// longer trusted, without performing a new handshake:
+    /// * A peer authenticated as `AuthenticatedPeer::Node` is valid if the
+    ///   registry at `registry_version` contains a TLS certificate for the
+    ///   node that is equal to the node's certificate at
+    ///   `handshake_registry_version`, i.e., the certificate that the peer
+    ///   presented in the handshake.
+    /// * A peer authenticated as `AuthenticatedPeer::Cert` was explicitly
+    ///   trusted by the caller rather than through the registry, and is
+    ///   therefore always valid.
+    ///
+    /// # Errors
+    /// * PeerRevalidationError::RegistryError if the registry cannot be
+    ///   accessed.
+    /// * PeerRevalidationError::NodeRemoved if the registry at
+    ///   `registry_version` does not contain a TLS certificate for the node.
+    /// * PeerRevalidationError::CertificateRotated if the node's TLS
+    ///   certificate at `registry_version` differs from the one at
+    ///   `handshake_registry_version`, or if the latter does not exist.
+    /// * PeerRevalidationError::MalformedCertificate if a certificate of the
+    ///   node in the registry is malformed.
+    fn revalidate_peer(
+        &self,
+        peer: &AuthenticatedPeer,
+        handshake_registry_version: RegistryVersion,
+        registry_version: RegistryVersion,
+    ) -> Result<(), PeerRevalidationError>;
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 /// A list of allowed TLS peers (and their trusted certificates),
 /// which can be `All` to allow any node to connect.
+///
+/// Optionally, the trusted certificates are subject to a revocation check,
+/// so that a compromised certificate can be rejected without rebuilding the
+/// `AllowedClients` of every acceptor.
 pub struct AllowedClients {
     nodes: SomeOrAllNodes,
-    certs: HashSet<TlsPublicKeyCert>,
+    trust_store: TrustStore,
+    revocation_check: OptionalRevocationCheck,
 }
 
 impl AllowedClients {
@@ -621,7 +1095,20 @@ impl AllowedClients {
         nodes: SomeOrAllNodes,
         certs: HashSet<TlsPublicKeyCert>,
     ) -> Result<Self, AllowedClientsError> {
-        let allowed_clients = Self { nodes, certs };
+        Self::new_with_trust_store(nodes, TrustStore::from(certs))
+    }
+
+    /// Create an `AllowedClients` with a set of nodes, and the certificates
+    /// of a trust store.
+    pub fn new_with_trust_store(
+        nodes: SomeOrAllNodes,
+        trust_store: TrustStore,
+    ) -> Result<Self, AllowedClientsError> {
+        let allowed_clients = Self {
+            nodes,
+            trust_store,
+            revocation_check: OptionalRevocationCheck::default(),
+        };
         Self::ensure_clients_not_empty(&allowed_clients)?;
         Ok(allowed_clients)
     }
@@ -638,22 +1125,92 @@ impl AllowedClients {
 
     /// Access the allowed certificates.
     pub fn certs(&self) -> &HashSet<TlsPublicKeyCert> {
-        &self.certs
+        self.trust_store.certs()
+    }
+
+    /// Access the trust store of the allowed certificates.
+    pub fn trust_store(&self) -> &TrustStore {
+        &self.trust_store
+    }
+
+    /// Rejects clients that authenticate with one of the allowed
+    /// certificates if `revocation_check` considers a certificate of the
+    /// client's certificate chain revoked. Clients that authenticate as nodes
+    /// are not affected, because nodes are removed from the registry instead.
+    pub fn with_revocation_check(
+        mut self,
+        revocation_check: Arc<dyn TlsCertRevocationCheck>,
+    ) -> Self {
+        self.revocation_check = OptionalRevocationCheck::new(revocation_check);
+        self
+    }
+
+    /// Returns true if there is a revocation check and it considers `cert`
+    /// revoked.
+    pub fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.revocation_check.is_revoked(cert)
     }
 
     fn ensure_clients_not_empty(candidate: &Self) -> Result<(), AllowedClientsError> {
         match &candidate.nodes {
             SomeOrAllNodes::Some(node_ids) => {
-                if node_ids.is_empty() && candidate.certs.is_empty() {
+                if node_ids.is_empty() && candidate.trust_store.is_empty() {
                     return Err(AllowedClientsError::ClientsEmpty);
                 }
             }
-            SomeOrAllNodes::All => { /* All is considered non-empty */ }
+            SomeOrAllNodes::All | SomeOrAllNodes::AllExcept(_) => {
+                /* All and AllExcept are considered non-empty */
+            }
         }
         Ok(())
     }
 }
 
+impl fmt::Debug for AllowedClients {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllowedClients")
+            .field("nodes", &self.nodes)
+            .field("trust_store", &self.trust_store)
+            .field("has_revocation_check", &self.revocation_check.is_some())
+            .finish()
+    }
+}
+
+/// Provides the allowed clients of a server handshake when the handshake
+/// starts, instead of when the acceptor is set up.
+pub trait AllowedClientsProvider: Send + Sync {
+    /// Returns the clients that are allowed to connect at `registry_version`.
+    fn allowed_clients(
+        &self,
+        registry_version: RegistryVersion,
+    ) -> Result<AllowedClients, AllowedClientsError>;
+}
+
+/// Fixed allowed clients, regardless of the registry version.
+impl AllowedClientsProvider for AllowedClients {
+    fn allowed_clients(
+        &self,
+        _registry_version: RegistryVersion,
+    ) -> Result<AllowedClients, AllowedClientsError> {
+        Ok(self.clone())
+    }
+}
+
+/// The nodes currently in the set, e.g., the peers that a node is supposed to
+/// be connected to, which are updated whenever the membership changes.
+impl AllowedClientsProvider for RwLock<BTreeSet<NodeId>> {
+    fn allowed_clients(
+        &self,
+        _registry_version: RegistryVersion,
+    ) -> Result<AllowedClients, AllowedClientsError> {
+        let nodes = self
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        AllowedClients::new_with_nodes(nodes)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// The allowed clients could not be created.
 pub enum AllowedClientsError {
@@ -666,10 +1223,24 @@ pub enum AllowedClientsError {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-/// A list of node IDs, or "all nodes"
+/// A list of node IDs, "all nodes", or "all nodes except a list of node IDs"
 pub enum SomeOrAllNodes {
     Some(BTreeSet<NodeId>),
     All,
+    /// All nodes except the listed ones, e.g. to temporarily exclude a
+    /// misbehaving node without enumerating every other node.
+    AllExcept(BTreeSet<NodeId>),
+}
+
+impl SomeOrAllNodes {
+    /// Returns true if `node_id` is one of the nodes.
+    pub fn contains(&self, node_id: &NodeId) -> bool {
+        match self {
+            SomeOrAllNodes::Some(nodes) => nodes.contains(node_id),
+            SomeOrAllNodes::All => true,
+            SomeOrAllNodes::AllExcept(excluded_nodes) => !excluded_nodes.contains(node_id),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
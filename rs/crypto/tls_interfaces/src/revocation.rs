@@ -0,0 +1,102 @@
+use crate::{TlsPublicKeyCert, TrustStore, TrustStoreError};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Checks whether a certificate was revoked, e.g., against a certificate
+/// revocation list maintained by the operator of the issuing CA.
+pub trait TlsCertRevocationCheck: Send + Sync {
+    /// Returns true if `cert` is revoked and must no longer be accepted.
+    fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool;
+}
+
+/// The certificates currently in the set are revoked. The owner of the set
+/// keeps it up to date, and every acceptor sharing the set sees the changes
+/// immediately.
+impl TlsCertRevocationCheck for RwLock<HashSet<TlsPublicKeyCert>> {
+    fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains(cert)
+    }
+}
+
+/// A revocation list kept in a file as a bundle of PEM-encoded certificates,
+/// in the format of a `TrustStore`.
+///
+/// The certificates in the file when it was last (re)loaded are revoked. The
+/// owner calls `reload` when the file changed, e.g., after the operator
+/// revoked another certificate, and every acceptor sharing the list sees the
+/// new revocations immediately.
+pub struct TlsCertRevocationFile {
+    path: PathBuf,
+    revoked_certs: RwLock<TrustStore>,
+}
+
+impl TlsCertRevocationFile {
+    /// Loads the revocation list from the file at `path`.
+    ///
+    /// # Errors
+    /// * `TrustStoreError` if the file cannot be read or does not contain a
+    ///   valid bundle of PEM-encoded certificates.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TrustStoreError> {
+        let path = path.as_ref().to_path_buf();
+        let revoked_certs = RwLock::new(TrustStore::load(&path)?);
+        Ok(Self {
+            path,
+            revoked_certs,
+        })
+    }
+
+    /// Loads the revocation list from the file again.
+    ///
+    /// # Errors
+    /// * `TrustStoreError` if the file cannot be read or does not contain a
+    ///   valid bundle of PEM-encoded certificates. The previously loaded
+    ///   revocations then remain in effect.
+    pub fn reload(&self) -> Result<(), TrustStoreError> {
+        let revoked_certs = TrustStore::load(&self.path)?;
+        *self
+            .revoked_certs
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = revoked_certs;
+        Ok(())
+    }
+
+    /// The path of the revocation list.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TlsCertRevocationCheck for TlsCertRevocationFile {
+    fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.revoked_certs
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains(cert)
+    }
+}
+
+/// The optional revocation check of a set of trusted certificates, shared by
+/// `ClientCas` and `AllowedClients`.
+#[derive(Clone, Default)]
+pub(crate) struct OptionalRevocationCheck(Option<Arc<dyn TlsCertRevocationCheck>>);
+
+impl OptionalRevocationCheck {
+    pub(crate) fn new(revocation_check: Arc<dyn TlsCertRevocationCheck>) -> Self {
+        Self(Some(revocation_check))
+    }
+
+    /// Returns true if there is a revocation check and it considers `cert`
+    /// revoked.
+    pub(crate) fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.0
+            .as_ref()
+            .map_or(false, |revocation_check| revocation_check.is_revoked(cert))
+    }
+
+    pub(crate) fn is_some(&self) -> bool {
+        self.0.is_some()
+    }
+}
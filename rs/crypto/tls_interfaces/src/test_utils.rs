@@ -0,0 +1,343 @@
+//! Test doubles for `TlsHandshake`.
+//!
+//! * `MockTlsHandshake` is a mockall mock for tests that set expectations on
+//!   the individual handshake calls.
+//! * `SelfSignedTlsHandshake` performs real TLS handshakes, but with keys and
+//!   certificates generated in memory instead of keys from the secret key
+//!   store and certificates from the registry.
+//!
+//! The module is available with the `test_utils` feature.
+use crate::{
+    AllowedClients, AllowedClientsProvider, AuthenticatedPeer, ClientCas, HandshakeFailureKind,
+    MalformedPeerCertificateError, Peer, PeerNotAllowedError, PeerRevalidationError,
+    TlsClientHandshakeError, TlsHandshake, TlsPublicKeyCert, TlsServerHandshakeError, TlsStream,
+};
+use async_trait::async_trait;
+use ic_crypto_internal_tls::keygen::TlsCertBuilder;
+use ic_types::{NodeId, PrincipalId, RegistryVersion};
+use mockall::mock;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslVerifyMode, SslVersion};
+use openssl::x509::X509;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_openssl::SslStream;
+
+mock! {
+    pub TlsHandshake {}
+
+    #[async_trait]
+    pub trait TlsHandshake {
+        async fn perform_tls_server_handshake(
+            &self,
+            tcp_stream: TcpStream,
+            allowed_clients: Arc<dyn AllowedClientsProvider>,
+            registry_version: RegistryVersion,
+        ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError>;
+
+        async fn perform_tls_server_handshake_temp_with_optional_client_auth(
+            &self,
+            tcp_stream: TcpStream,
+            allowed_authenticating_clients: AllowedClients,
+            registry_version: RegistryVersion,
+        ) -> Result<(TlsStream, Peer), TlsServerHandshakeError>;
+
+        async fn perform_tls_server_handshake_without_client_auth(
+            &self,
+            tcp_stream: TcpStream,
+            registry_version: RegistryVersion,
+        ) -> Result<TlsStream, TlsServerHandshakeError>;
+
+        async fn perform_tls_server_handshake_with_ca_client_auth(
+            &self,
+            tcp_stream: TcpStream,
+            client_cas: ClientCas,
+            registry_version: RegistryVersion,
+        ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError>;
+
+        async fn perform_tls_client_handshake(
+            &self,
+            tcp_stream: TcpStream,
+            server: NodeId,
+            registry_version: RegistryVersion,
+        ) -> Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError>;
+
+        async fn perform_tls_client_handshake_with_pinned_cert(
+            &self,
+            tcp_stream: TcpStream,
+            expected_cert: TlsPublicKeyCert,
+        ) -> Result<(TlsStream, AuthenticatedPeer), TlsClientHandshakeError>;
+
+        fn revalidate_peer(
+            &self,
+            peer: &AuthenticatedPeer,
+            handshake_registry_version: RegistryVersion,
+            registry_version: RegistryVersion,
+        ) -> Result<(), PeerRevalidationError>;
+    }
+}
+
+/// A `TlsHandshake` for the node `node_id` that performs real TLS 1.3
+/// handshakes with a self-signed certificate whose common name is the node
+/// ID.
+///
+/// Peers are identified by the common name of their certificate alone, which
+/// is not verified against the registry, so any two instances can connect to
+/// each other. Clients are authenticated as `AuthenticatedPeer::Cert` if
+/// their certificate is in the trust store of the allowed clients, and as
+/// `AuthenticatedPeer::Node` if their node ID is allowed. In handshakes with
+/// client authentication by CA, clients must present a certificate that is
+/// one of the CAs or directly issued by one, intermediate CAs are not
+/// supported. The registry versions are ignored and peers are always valid on
+/// revalidation.
+pub struct SelfSignedTlsHandshake {
+    node_id: NodeId,
+    key: PKey<Private>,
+    cert: X509,
+}
+
+impl SelfSignedTlsHandshake {
+    pub fn new(node_id: NodeId) -> Self {
+        let (key, cert) = self_signed_cert_with_key(&node_id.get().to_string(), 1);
+        Self { node_id, key, cert }
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    /// The certificate this node presents in handshakes.
+    pub fn cert(&self) -> TlsPublicKeyCert {
+        TlsPublicKeyCert::new_from_x509(self.cert.clone()).expect("invalid certificate")
+    }
+
+    /// Returns an `Ssl` that presents the certificate of this node and
+    /// accepts any certificate of the peer, which is checked after the
+    /// handshake.
+    fn ssl(&self, verify_mode: SslVerifyMode) -> Ssl {
+        let mut builder = SslContext::builder(SslMethod::tls()).expect("failed to create context");
+        builder
+            .set_min_proto_version(Some(SslVersion::TLS1_3))
+            .expect("failed to set protocol version");
+        builder
+            .set_certificate(&self.cert)
+            .expect("failed to set certificate");
+        builder
+            .set_private_key(&self.key)
+            .expect("failed to set private key");
+        builder.set_verify_callback(verify_mode, |_preverify_ok, _store| true);
+        Ssl::new(&builder.build()).expect("failed to create SSL")
+    }
+
+    async fn connect(
+        &self,
+        tcp_stream: TcpStream,
+    ) -> Result<(SslStream<TcpStream>, TlsPublicKeyCert), TlsClientHandshakeError> {
+        let mut ssl_stream = SslStream::new(self.ssl(SslVerifyMode::PEER), tcp_stream)
+            .expect("failed to create stream");
+        Pin::new(&mut ssl_stream).connect().await.map_err(|e| {
+            TlsClientHandshakeError::HandshakeError {
+                kind: HandshakeFailureKind::Other,
+                internal_error: format!("Handshake failed in tokio_openssl:connect: {}", e),
+            }
+        })?;
+        let server_cert = ssl_stream.ssl().peer_certificate().ok_or_else(|| {
+            TlsClientHandshakeError::HandshakeError {
+                kind: HandshakeFailureKind::Other,
+                internal_error: "Missing server certificate during handshake.".to_string(),
+            }
+        })?;
+        let server_cert = TlsPublicKeyCert::new_from_x509(server_cert)
+            .map_err(|e| MalformedPeerCertificateError::new(&e.internal_error))?;
+        Ok((ssl_stream, server_cert))
+    }
+
+    async fn accept(
+        &self,
+        tcp_stream: TcpStream,
+        verify_mode: SslVerifyMode,
+    ) -> Result<SslStream<TcpStream>, TlsServerHandshakeError> {
+        let mut ssl_stream =
+            SslStream::new(self.ssl(verify_mode), tcp_stream).expect("failed to create stream");
+        Pin::new(&mut ssl_stream).accept().await.map_err(|e| {
+            TlsServerHandshakeError::HandshakeError {
+                kind: HandshakeFailureKind::Other,
+                internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
+            }
+        })?;
+        Ok(ssl_stream)
+    }
+
+    /// Authenticates the client of `ssl_stream`, if it presented a
+    /// certificate.
+    fn authenticate_client(
+        ssl_stream: &SslStream<TcpStream>,
+        allowed_clients: &AllowedClients,
+    ) -> Result<Option<AuthenticatedPeer>, TlsServerHandshakeError> {
+        let client_cert = match ssl_stream.ssl().peer_certificate() {
+            None => return Ok(None),
+            Some(cert) => TlsPublicKeyCert::new_from_x509(cert)
+                .map_err(|e| MalformedPeerCertificateError::new(&e.internal_error))?,
+        };
+        if allowed_clients.trust_store().contains(&client_cert) {
+            if allowed_clients.is_revoked(&client_cert) {
+                return Err(PeerNotAllowedError::CertificateRevoked.into());
+            }
+            return Ok(Some(AuthenticatedPeer::Cert(client_cert)));
+        }
+        let node_id = node_id_from_cert(&client_cert)
+            .ok_or(PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed)?;
+        if !allowed_clients.nodes().contains(&node_id) {
+            return Err(PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed.into());
+        }
+        Ok(Some(AuthenticatedPeer::Node(node_id)))
+    }
+}
+
+#[async_trait]
+impl TlsHandshake for SelfSignedTlsHandshake {
+    async fn perform_tls_server_handshake(
+        &self,
+        tcp_stream: TcpStream,
+        allowed_clients: Arc<dyn AllowedClientsProvider>,
+        registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
+        let allowed_clients = allowed_clients
+            .allowed_clients(registry_version)
+            .map_err(TlsServerHandshakeError::AllowedClientsUnavailable)?;
+        let ssl_stream = self
+            .accept(
+                tcp_stream,
+                SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+            )
+            .await?;
+        let peer = Self::authenticate_client(&ssl_stream, &allowed_clients)?
+            .ok_or(TlsServerHandshakeError::UnauthenticatedClient)?;
+        Ok((TlsStream::new(ssl_stream), peer))
+    }
+
+    async fn perform_tls_server_handshake_temp_with_optional_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        allowed_authenticating_clients: AllowedClients,
+        _registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, Peer), TlsServerHandshakeError> {
+        let ssl_stream = self.accept(tcp_stream, SslVerifyMode::PEER).await?;
+        let peer = match Self::authenticate_client(&ssl_stream, &allowed_authenticating_clients)? {
+            Some(peer) => Peer::Authenticated(peer),
+            None => Peer::Unauthenticated,
+        };
+        Ok((TlsStream::new(ssl_stream), peer))
+    }
+
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        _registry_version: RegistryVersion,
+    ) -> Result<TlsStream, TlsServerHandshakeError> {
+        let ssl_stream = self.accept(tcp_stream, SslVerifyMode::NONE).await?;
+        Ok(TlsStream::new(ssl_stream))
+    }
+
+    async fn perform_tls_server_handshake_with_ca_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        client_cas: ClientCas,
+        _registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
+        let ssl_stream = self
+            .accept(
+                tcp_stream,
+                SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+            )
+            .await?;
+        let client_cert = match ssl_stream.ssl().peer_certificate() {
+            None => return Err(TlsServerHandshakeError::UnauthenticatedClient),
+            Some(cert) => TlsPublicKeyCert::new_from_x509(cert)
+                .map_err(|e| MalformedPeerCertificateError::new(&e.internal_error))?,
+        };
+        let issued_by_ca = client_cas.cas().certs().iter().any(|ca| {
+            ca == &client_cert
+                || ca
+                    .as_x509()
+                    .public_key()
+                    .and_then(|ca_key| client_cert.as_x509().verify(&ca_key))
+                    .unwrap_or(false)
+        });
+        if !issued_by_ca {
+            return Err(TlsServerHandshakeError::HandshakeError {
+                kind: HandshakeFailureKind::BadCertSignature,
+                internal_error: "certificate verify failed: not issued by a client CA".to_string(),
+            });
+        }
+        if client_cas.is_revoked(&client_cert) {
+            return Err(PeerNotAllowedError::CertificateRevoked.into());
+        }
+        Ok((
+            TlsStream::new(ssl_stream),
+            AuthenticatedPeer::Cert(client_cert),
+        ))
+    }
+
+    async fn perform_tls_client_handshake(
+        &self,
+        tcp_stream: TcpStream,
+        server: NodeId,
+        _registry_version: RegistryVersion,
+    ) -> Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError> {
+        let (ssl_stream, server_cert) = self.connect(tcp_stream).await?;
+        if node_id_from_cert(&server_cert) != Some(server) {
+            return Err(PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed.into());
+        }
+        Ok((TlsStream::new(ssl_stream), server_cert))
+    }
+
+    async fn perform_tls_client_handshake_with_pinned_cert(
+        &self,
+        tcp_stream: TcpStream,
+        expected_cert: TlsPublicKeyCert,
+    ) -> Result<(TlsStream, AuthenticatedPeer), TlsClientHandshakeError> {
+        let (ssl_stream, server_cert) = self.connect(tcp_stream).await?;
+        if server_cert != expected_cert {
+            return Err(PeerNotAllowedError::CertificatesDiffer.into());
+        }
+        Ok((
+            TlsStream::new(ssl_stream),
+            AuthenticatedPeer::Cert(expected_cert),
+        ))
+    }
+
+    fn revalidate_peer(
+        &self,
+        _peer: &AuthenticatedPeer,
+        _handshake_registry_version: RegistryVersion,
+        _registry_version: RegistryVersion,
+    ) -> Result<(), PeerRevalidationError> {
+        Ok(())
+    }
+}
+
+fn node_id_from_cert(cert: &TlsPublicKeyCert) -> Option<NodeId> {
+    let common_name = cert
+        .as_x509()
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()?
+        .data()
+        .as_utf8()
+        .ok()?;
+    PrincipalId::from_str(&common_name).ok().map(NodeId::from)
+}
+
+/// Returns an Ed25519 key and a certificate for it that is self-signed with
+/// the given common name and serial number, generated like the certificates
+/// of nodes.
+pub(crate) fn self_signed_cert_with_key(common_name: &str, serial: u32) -> (PKey<Private>, X509) {
+    let mut serial_bytes = [0; 19];
+    serial_bytes[15..].copy_from_slice(&serial.to_be_bytes());
+    let (cert, key) = TlsCertBuilder::new(common_name, serial_bytes).build();
+    (key, cert)
+}
@@ -174,14 +174,86 @@ mod tls_public_key_cert {
 
         assert_eq!(serialized, proto_serialized);
     }
+
+    mod validity {
+        use crate::TlsPublicKeyCert;
+        use ic_crypto_test_utils::tls::x509_certificates::{CertBuilder, CertWithPrivateKey};
+        use ic_types::{NodeId, PrincipalId, Time};
+
+        const NOT_BEFORE_SECS: u64 = 1_600_000_000;
+
+        #[test]
+        fn should_return_validity_period() {
+            let cert = tls_cert(node_cert_builder(node_id()).not_after("20300101000000Z"));
+
+            assert_eq!(
+                cert.not_before().to_string(),
+                "Sep 13 12:26:40 2020 GMT".to_string()
+            );
+            assert_eq!(
+                cert.not_after().to_string(),
+                "Jan  1 00:00:00 2030 GMT".to_string()
+            );
+        }
+
+        #[test]
+        fn should_be_valid_within_validity_period_only() {
+            let cert = tls_cert(node_cert_builder(node_id()).not_after("20300101000000Z"));
+
+            assert!(!cert.is_valid_at(secs(NOT_BEFORE_SECS - 1)));
+            assert!(cert.is_valid_at(secs(NOT_BEFORE_SECS)));
+            assert!(cert.is_valid_at(secs(1_800_000_000)));
+            assert!(cert.is_valid_at(secs(1_893_456_000)));
+            assert!(!cert.is_valid_at(secs(1_893_456_001)));
+        }
+
+        #[test]
+        fn should_return_subject_cn() {
+            let cert = tls_cert(CertWithPrivateKey::builder().cn("Spock".to_string()));
+
+            assert_eq!(cert.subject_cn(), Some("Spock".to_string()));
+        }
+
+        #[test]
+        fn should_not_return_subject_cn_if_duplicated() {
+            let cert = tls_cert(CertWithPrivateKey::builder().with_duplicate_subject_cn());
+
+            assert_eq!(cert.subject_cn(), None);
+        }
+
+        fn node_cert_builder(node_id: NodeId) -> CertBuilder {
+            CertWithPrivateKey::builder()
+                .cn(node_id.get().to_string())
+                .not_before_unix(NOT_BEFORE_SECS as i64)
+                .not_after("99991231235959Z")
+        }
+
+        fn tls_cert(builder: CertBuilder) -> TlsPublicKeyCert {
+            TlsPublicKeyCert::new_from_x509(builder.build_ed25519().x509()).unwrap()
+        }
+
+        fn node_id() -> NodeId {
+            NodeId::from(PrincipalId::new_node_test_id(1))
+        }
+
+        fn secs(secs: u64) -> Time {
+            Time::from_nanos_since_unix_epoch(secs * 1_000_000_000)
+        }
+    }
 }
 
 mod allowed_clients {
-    use crate::{AllowedClients, AllowedClientsError, SomeOrAllNodes, TlsPublicKeyCert};
+    use crate::{
+        AllowedClients, AllowedClientsError, AllowedClientsProvider, SomeOrAllNodes,
+        TlsPublicKeyCert,
+    };
     use ic_crypto_test_utils::tls::x509_certificates::generate_ed25519_cert;
-    use ic_types::{NodeId, PrincipalId};
+    use ic_types::{NodeId, PrincipalId, RegistryVersion};
     use maplit::btreeset;
     use std::collections::{BTreeSet, HashSet};
+    use std::sync::{Arc, RwLock};
+
+    const REG_V1: RegistryVersion = RegistryVersion::new(1);
 
     #[test]
     fn should_correctly_construct_with_new() {
@@ -234,6 +306,28 @@ mod allowed_clients {
         assert!(allowed_clients.certs().is_empty());
     }
 
+    #[test]
+    fn should_correctly_construct_with_new_with_all_nodes_except_some() {
+        let excluded_nodes = SomeOrAllNodes::AllExcept(btreeset! {node_id(1)});
+
+        let allowed_clients = AllowedClients::new(excluded_nodes.clone(), HashSet::new()).unwrap();
+
+        assert_eq!(allowed_clients.nodes(), &excluded_nodes);
+        assert!(allowed_clients.certs().is_empty());
+    }
+
+    #[test]
+    fn should_contain_nodes_according_to_variant() {
+        let some = SomeOrAllNodes::Some(btreeset! {node_id(1)});
+        let all_except = SomeOrAllNodes::AllExcept(btreeset! {node_id(1)});
+
+        assert!(some.contains(&node_id(1)));
+        assert!(!some.contains(&node_id(2)));
+        assert!(SomeOrAllNodes::All.contains(&node_id(1)));
+        assert!(!all_except.contains(&node_id(1)));
+        assert!(all_except.contains(&node_id(2)));
+    }
+
     #[test]
     fn should_correctly_construct_with_new_with_nodes() {
         let nodes = btreeset! {node_id(1)};
@@ -263,7 +357,663 @@ mod allowed_clients {
         );
     }
 
+    #[test]
+    fn should_provide_clone_of_allowed_clients() {
+        let allowed_clients = AllowedClients::new_with_nodes(btreeset! {node_id(1)}).unwrap();
+
+        let provided = allowed_clients.allowed_clients(REG_V1).unwrap();
+
+        assert_eq!(provided.nodes(), allowed_clients.nodes());
+        assert_eq!(provided.certs(), allowed_clients.certs());
+    }
+
+    #[test]
+    fn should_provide_current_nodes_of_shared_node_set() {
+        let nodes = RwLock::new(btreeset! {node_id(1)});
+        assert_eq!(
+            nodes.allowed_clients(REG_V1).unwrap().nodes(),
+            &SomeOrAllNodes::Some(btreeset! {node_id(1)})
+        );
+
+        nodes.write().unwrap().insert(node_id(2));
+
+        assert_eq!(
+            nodes.allowed_clients(REG_V1).unwrap().nodes(),
+            &SomeOrAllNodes::Some(btreeset! {node_id(1), node_id(2)})
+        );
+    }
+
+    #[test]
+    fn should_consider_certs_revoked_according_to_revocation_check() {
+        let (revoked, valid) = (cert(), cert());
+        let allowed_clients = AllowedClients::new(
+            SomeOrAllNodes::Some(BTreeSet::new()),
+            vec![revoked.clone(), valid.clone()].into_iter().collect(),
+        )
+        .unwrap();
+        assert!(!allowed_clients.is_revoked(&revoked));
+
+        let allowed_clients = allowed_clients.with_revocation_check(Arc::new(RwLock::new(
+            vec![revoked.clone()].into_iter().collect::<HashSet<_>>(),
+        )));
+
+        assert!(allowed_clients.is_revoked(&revoked));
+        assert!(!allowed_clients.is_revoked(&valid));
+    }
+
+    #[test]
+    fn should_fail_to_provide_clients_if_shared_node_set_empty() {
+        let nodes = RwLock::new(BTreeSet::new());
+
+        assert_eq!(
+            nodes.allowed_clients(REG_V1).unwrap_err(),
+            AllowedClientsError::ClientsEmpty
+        );
+    }
+
     fn node_id(id: u64) -> NodeId {
         NodeId::from(PrincipalId::new_node_test_id(id))
     }
+
+    fn cert() -> TlsPublicKeyCert {
+        TlsPublicKeyCert::new_from_x509(generate_ed25519_cert().1)
+            .expect("failed to create TlsPublicKeyCert from X509")
+    }
+
+    mod properties {
+        use crate::arbitrary::{arb_node_id, arb_tls_public_key_cert};
+        use crate::{AllowedClients, AllowedClientsError, SomeOrAllNodes};
+        use proptest::collection::{btree_set, vec};
+        use proptest::prelude::*;
+        use std::collections::{BTreeSet, HashSet};
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(32))]
+
+            #[test]
+            fn should_construct_with_new_unless_nodes_and_certs_empty(
+                nodes in any::<SomeOrAllNodes>(),
+                certs in vec(arb_tls_public_key_cert(), 0..3),
+            ) {
+                let certs: HashSet<_> = certs.into_iter().collect();
+                let clients_empty =
+                    nodes == SomeOrAllNodes::Some(BTreeSet::new()) && certs.is_empty();
+
+                match AllowedClients::new(nodes.clone(), certs.clone()) {
+                    Ok(allowed_clients) => {
+                        prop_assert!(!clients_empty);
+                        prop_assert_eq!(allowed_clients.nodes(), &nodes);
+                        prop_assert_eq!(allowed_clients.certs(), &certs);
+                    }
+                    Err(error) => {
+                        prop_assert!(clients_empty);
+                        prop_assert_eq!(error, AllowedClientsError::ClientsEmpty);
+                    }
+                }
+            }
+
+            #[test]
+            fn should_construct_with_new_with_nodes_unless_nodes_empty(
+                nodes in btree_set(arb_node_id(), 0..5),
+            ) {
+                match AllowedClients::new_with_nodes(nodes.clone()) {
+                    Ok(allowed_clients) => {
+                        prop_assert!(!nodes.is_empty());
+                        prop_assert_eq!(allowed_clients.nodes(), &SomeOrAllNodes::Some(nodes));
+                        prop_assert!(allowed_clients.certs().is_empty());
+                    }
+                    Err(error) => {
+                        prop_assert!(nodes.is_empty());
+                        prop_assert_eq!(error, AllowedClientsError::ClientsEmpty);
+                    }
+                }
+            }
+        }
+    }
+}
+
+mod trust_store {
+    use crate::{CertFingerprint, TlsPublicKeyCert, TrustStore, TrustStoreError};
+    use ic_crypto_test_utils::tls::x509_certificates::generate_ed25519_cert;
+
+    #[test]
+    fn should_add_and_lookup_by_fingerprint() {
+        let cert = cert();
+        let mut trust_store = TrustStore::new();
+
+        assert!(trust_store.add(cert.clone()));
+        assert!(!trust_store.add(cert.clone()));
+
+        assert_eq!(trust_store.len(), 1);
+        assert!(trust_store.contains(&cert));
+        assert_eq!(trust_store.get(&cert.fingerprint()), Some(&cert));
+        assert_eq!(trust_store.get(&CertFingerprint([0; 32])), None);
+    }
+
+    #[test]
+    fn should_remove_by_fingerprint() {
+        let (cert_1, cert_2) = (cert(), cert());
+        let mut trust_store: TrustStore =
+            vec![cert_1.clone(), cert_2.clone()].into_iter().collect();
+
+        assert_eq!(
+            trust_store.remove(&cert_1.fingerprint()),
+            Some(cert_1.clone())
+        );
+        assert_eq!(trust_store.remove(&cert_1.fingerprint()), None);
+
+        assert!(!trust_store.contains(&cert_1));
+        assert!(trust_store.contains(&cert_2));
+    }
+
+    #[test]
+    fn should_have_fingerprint_of_der() {
+        let cert = cert();
+        let fingerprint = openssl::sha::sha256(cert.as_der());
+
+        assert_eq!(cert.fingerprint(), CertFingerprint(fingerprint));
+        assert_eq!(cert.fingerprint().to_string().len(), 64);
+    }
+
+    #[test]
+    fn should_load_saved_trust_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trust_store.pem");
+        let trust_store: TrustStore = vec![cert(), cert()].into_iter().collect();
+
+        trust_store.save(&path).unwrap();
+
+        assert_eq!(TrustStore::load(&path).unwrap(), trust_store);
+    }
+
+    #[test]
+    fn should_overwrite_saved_trust_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trust_store.pem");
+        let mut trust_store: TrustStore = vec![cert(), cert()].into_iter().collect();
+        trust_store.save(&path).unwrap();
+        let fingerprint = trust_store.certs().iter().next().unwrap().fingerprint();
+        trust_store.remove(&fingerprint);
+
+        trust_store.save(&path).unwrap();
+
+        assert_eq!(TrustStore::load(&path).unwrap(), trust_store);
+    }
+
+    #[test]
+    fn should_fail_to_load_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = TrustStore::load(dir.path().join("missing.pem"));
+
+        assert!(matches!(result, Err(TrustStoreError::Io { .. })));
+    }
+
+    #[test]
+    fn should_fail_to_load_malformed_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trust_store.pem");
+        std::fs::write(&path, "-----BEGIN CERTIFICATE-----\nnot base64\n").unwrap();
+
+        let result = TrustStore::load(&path);
+
+        assert!(matches!(
+            result,
+            Err(TrustStoreError::MalformedBundle { .. })
+        ));
+    }
+
+    fn cert() -> TlsPublicKeyCert {
+        TlsPublicKeyCert::new_from_x509(generate_ed25519_cert().1)
+            .expect("failed to create TlsPublicKeyCert from X509")
+    }
+}
+
+mod client_cas {
+    use crate::{
+        ClientCas, ClientCasError, TlsCertRevocationCheck, TlsPublicKeyCert, TrustStore,
+        MAX_INTERMEDIATE_CLIENT_CAS,
+    };
+    use ic_crypto_test_utils::tls::x509_certificates::generate_ed25519_cert;
+    use std::sync::Arc;
+
+    struct RevokedCerts(TrustStore);
+
+    impl TlsCertRevocationCheck for RevokedCerts {
+        fn is_revoked(&self, cert: &TlsPublicKeyCert) -> bool {
+            self.0.contains(cert)
+        }
+    }
+
+    #[test]
+    fn should_correctly_construct_with_new() {
+        let cas: TrustStore = vec![cert()].into_iter().collect();
+
+        let client_cas = ClientCas::new(cas.clone(), 1).unwrap();
+
+        assert_eq!(client_cas.cas(), &cas);
+        assert_eq!(client_cas.max_intermediate_cas(), 1);
+    }
+
+    #[test]
+    fn should_fail_on_new_if_cas_empty() {
+        assert_eq!(
+            ClientCas::new(TrustStore::new(), 1).unwrap_err(),
+            ClientCasError::CasEmpty
+        );
+    }
+
+    #[test]
+    fn should_fail_on_new_if_too_many_intermediate_cas() {
+        let cas: TrustStore = vec![cert()].into_iter().collect();
+
+        assert_eq!(
+            ClientCas::new(cas, MAX_INTERMEDIATE_CLIENT_CAS + 1).unwrap_err(),
+            ClientCasError::TooManyIntermediateCas {
+                max_intermediate_cas: MAX_INTERMEDIATE_CLIENT_CAS + 1,
+                limit: MAX_INTERMEDIATE_CLIENT_CAS,
+            }
+        );
+    }
+
+    #[test]
+    fn should_not_consider_certs_revoked_without_revocation_check() {
+        let ca = cert();
+        let client_cas = ClientCas::new(vec![ca.clone()].into_iter().collect(), 0).unwrap();
+
+        assert!(!client_cas.is_revoked(&ca));
+    }
+
+    #[test]
+    fn should_consider_certs_revoked_according_to_revocation_check() {
+        let (ca, revoked, valid) = (cert(), cert(), cert());
+        let client_cas = ClientCas::new(vec![ca].into_iter().collect(), 0)
+            .unwrap()
+            .with_revocation_check(Arc::new(RevokedCerts(
+                vec![revoked.clone()].into_iter().collect(),
+            )));
+
+        assert!(client_cas.is_revoked(&revoked));
+        assert!(!client_cas.is_revoked(&valid));
+    }
+
+    fn cert() -> TlsPublicKeyCert {
+        TlsPublicKeyCert::new_from_x509(generate_ed25519_cert().1)
+            .expect("failed to create TlsPublicKeyCert from X509")
+    }
+}
+
+mod revocation {
+    use crate::{
+        AllowedClients, ClientCas, SomeOrAllNodes, TlsCertRevocationCheck, TlsCertRevocationFile,
+        TlsPublicKeyCert, TrustStore, TrustStoreError,
+    };
+    use ic_crypto_test_utils::tls::x509_certificates::generate_ed25519_cert;
+    use std::collections::HashSet;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn should_consider_certs_revoked_once_added_to_shared_revocation_set() {
+        let cert = cert();
+        let revoked_certs = RwLock::new(HashSet::new());
+        assert!(!revoked_certs.is_revoked(&cert));
+
+        revoked_certs.write().unwrap().insert(cert.clone());
+
+        assert!(revoked_certs.is_revoked(&cert));
+    }
+
+    #[test]
+    fn should_consider_certs_in_revocation_file_revoked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revoked.pem");
+        let (revoked, valid) = (cert(), cert());
+        revoked_certs(&[&revoked]).save(&path).unwrap();
+
+        let revocation_file = TlsCertRevocationFile::load(&path).unwrap();
+
+        assert_eq!(revocation_file.path(), path.as_path());
+        assert!(revocation_file.is_revoked(&revoked));
+        assert!(!revocation_file.is_revoked(&valid));
+    }
+
+    #[test]
+    fn should_fail_to_load_missing_revocation_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = TlsCertRevocationFile::load(dir.path().join("missing.pem"));
+
+        assert!(matches!(result, Err(TrustStoreError::Io { .. })));
+    }
+
+    #[test]
+    fn should_consider_certs_revoked_once_added_to_revocation_file_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revoked.pem");
+        let (revoked_first, revoked_later) = (cert(), cert());
+        revoked_certs(&[&revoked_first]).save(&path).unwrap();
+        let revocation_file = TlsCertRevocationFile::load(&path).unwrap();
+
+        revoked_certs(&[&revoked_first, &revoked_later])
+            .save(&path)
+            .unwrap();
+        assert!(!revocation_file.is_revoked(&revoked_later));
+        revocation_file.reload().unwrap();
+
+        assert!(revocation_file.is_revoked(&revoked_first));
+        assert!(revocation_file.is_revoked(&revoked_later));
+    }
+
+    #[test]
+    fn should_keep_previous_revocations_if_reload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revoked.pem");
+        let revoked = cert();
+        revoked_certs(&[&revoked]).save(&path).unwrap();
+        let revocation_file = TlsCertRevocationFile::load(&path).unwrap();
+
+        std::fs::write(&path, "-----BEGIN CERTIFICATE-----\nnot base64\n").unwrap();
+
+        assert!(matches!(
+            revocation_file.reload(),
+            Err(TrustStoreError::MalformedBundle { .. })
+        ));
+        assert!(revocation_file.is_revoked(&revoked));
+    }
+
+    #[test]
+    fn should_share_revocation_file_between_client_cas_and_allowed_clients() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("revoked.pem");
+        let revoked = cert();
+        TrustStore::new().save(&path).unwrap();
+        let revocation_file = Arc::new(TlsCertRevocationFile::load(&path).unwrap());
+        let client_cas = ClientCas::new(vec![cert()].into_iter().collect(), 0)
+            .unwrap()
+            .with_revocation_check(revocation_file.clone());
+        let allowed_clients = AllowedClients::new(SomeOrAllNodes::All, HashSet::new())
+            .unwrap()
+            .with_revocation_check(revocation_file.clone());
+        assert!(!client_cas.is_revoked(&revoked));
+        assert!(!allowed_clients.is_revoked(&revoked));
+
+        revoked_certs(&[&revoked]).save(&path).unwrap();
+        revocation_file.reload().unwrap();
+
+        assert!(client_cas.is_revoked(&revoked));
+        assert!(allowed_clients.is_revoked(&revoked));
+    }
+
+    fn revoked_certs(certs: &[&TlsPublicKeyCert]) -> TrustStore {
+        certs.iter().map(|cert| (*cert).clone()).collect()
+    }
+
+    fn cert() -> TlsPublicKeyCert {
+        TlsPublicKeyCert::new_from_x509(generate_ed25519_cert().1)
+            .expect("failed to create TlsPublicKeyCert from X509")
+    }
+}
+
+mod arbitrary {
+    use crate::{AllowedClients, TlsClientHandshakeError, TlsServerHandshakeError};
+    use proptest::prelude::*;
+    use proptest::strategy::ValueTree;
+    use proptest::test_runner::TestRunner;
+    use std::collections::HashSet;
+    use std::mem::discriminant;
+
+    const NUM_SERVER_HANDSHAKE_ERROR_VARIANTS: usize = 11;
+    const NUM_CLIENT_HANDSHAKE_ERROR_VARIANTS: usize = 9;
+
+    #[test]
+    fn should_generate_all_server_handshake_error_variants() {
+        let variants: HashSet<_> = sample::<TlsServerHandshakeError>(1000)
+            .iter()
+            .map(discriminant)
+            .collect();
+
+        assert_eq!(variants.len(), NUM_SERVER_HANDSHAKE_ERROR_VARIANTS);
+    }
+
+    #[test]
+    fn should_generate_all_client_handshake_error_variants() {
+        let variants: HashSet<_> = sample::<TlsClientHandshakeError>(1000)
+            .iter()
+            .map(discriminant)
+            .collect();
+
+        assert_eq!(variants.len(), NUM_CLIENT_HANDSHAKE_ERROR_VARIANTS);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn should_generate_valid_allowed_clients(allowed_clients in any::<AllowedClients>()) {
+            prop_assert!(AllowedClients::new_with_trust_store(
+                allowed_clients.nodes().clone(),
+                allowed_clients.trust_store().clone()
+            )
+            .is_ok());
+        }
+    }
+
+    fn sample<T: Arbitrary>(count: usize) -> Vec<T> {
+        let strategy = any::<T>();
+        let mut runner = TestRunner::deterministic();
+        (0..count)
+            .map(|_| strategy.new_tree(&mut runner).unwrap().current())
+            .collect()
+    }
+}
+
+mod test_utils {
+    use crate::test_utils::{MockTlsHandshake, SelfSignedTlsHandshake};
+    use crate::{
+        AllowedClients, AuthenticatedPeer, ClientCas, PeerNotAllowedError, PeerRevalidationError,
+        TlsCertRevocationCheck, TlsClientHandshakeError, TlsHandshake, TlsPublicKeyCert,
+        TlsServerHandshakeError, TlsStream,
+    };
+    use ic_types::{NodeId, PrincipalId, RegistryVersion};
+    use maplit::btreeset;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const REG_V1: RegistryVersion = RegistryVersion::new(1);
+
+    fn node(n: u64) -> NodeId {
+        NodeId::from(PrincipalId::new_node_test_id(n))
+    }
+
+    async fn tcp_stream_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap());
+        let (client, server) = tokio::join!(client, listener.accept());
+        (client.unwrap(), server.unwrap().0)
+    }
+
+    async fn handshake(
+        client: &SelfSignedTlsHandshake,
+        server: &SelfSignedTlsHandshake,
+        allowed_clients: AllowedClients,
+    ) -> (
+        Result<(TlsStream, TlsPublicKeyCert), TlsClientHandshakeError>,
+        Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError>,
+    ) {
+        let (client_stream, server_stream) = tcp_stream_pair().await;
+        tokio::join!(
+            client.perform_tls_client_handshake(client_stream, server.node_id(), REG_V1),
+            server.perform_tls_server_handshake(server_stream, Arc::new(allowed_clients), REG_V1)
+        )
+    }
+
+    #[tokio::test]
+    async fn should_perform_handshake_between_self_signed_nodes() {
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+
+        let (client_result, server_result) = handshake(
+            &client,
+            &server,
+            AllowedClients::new_with_nodes(btreeset! {node(1)}).unwrap(),
+        )
+        .await;
+
+        let (mut client_stream, server_cert) = client_result.unwrap();
+        let (mut server_stream, peer) = server_result.unwrap();
+        assert_eq!(server_cert, server.cert());
+        assert_eq!(peer, AuthenticatedPeer::Node(node(1)));
+        client_stream.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        server_stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn should_reject_client_that_is_not_allowed() {
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+
+        let (_, server_result) = handshake(
+            &client,
+            &server,
+            AllowedClients::new_with_nodes(btreeset! {node(3)}).unwrap(),
+        )
+        .await;
+
+        assert!(matches!(
+            server_result,
+            Err(TlsServerHandshakeError::ClientNotAllowed(
+                PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_reject_server_with_other_node_id() {
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+        let (client_stream, server_stream) = tcp_stream_pair().await;
+
+        let (client_result, _) = tokio::join!(
+            client.perform_tls_client_handshake(client_stream, node(3), REG_V1),
+            server.perform_tls_server_handshake_without_client_auth(server_stream, REG_V1)
+        );
+
+        assert!(matches!(
+            client_result,
+            Err(TlsClientHandshakeError::ServerNotAllowed(
+                PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_authenticate_server_by_pinned_cert() {
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+        let (client_stream, server_stream) = tcp_stream_pair().await;
+
+        let (client_result, _) = tokio::join!(
+            client.perform_tls_client_handshake_with_pinned_cert(client_stream, server.cert()),
+            server.perform_tls_server_handshake_without_client_auth(server_stream, REG_V1)
+        );
+
+        assert_eq!(
+            client_result.unwrap().1,
+            AuthenticatedPeer::Cert(server.cert())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_server_with_other_than_pinned_cert() {
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+        let other = SelfSignedTlsHandshake::new(node(2));
+        let (client_stream, server_stream) = tcp_stream_pair().await;
+
+        let (client_result, _) = tokio::join!(
+            client.perform_tls_client_handshake_with_pinned_cert(client_stream, other.cert()),
+            server.perform_tls_server_handshake_without_client_auth(server_stream, REG_V1)
+        );
+
+        assert!(matches!(
+            client_result,
+            Err(TlsClientHandshakeError::ServerNotAllowed(
+                PeerNotAllowedError::CertificatesDiffer
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_authenticate_client_by_ca() {
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+        let client_cas = ClientCas::new(vec![client.cert()].into_iter().collect(), 0).unwrap();
+        let (client_stream, server_stream) = tcp_stream_pair().await;
+
+        let (client_result, server_result) = tokio::join!(
+            client.perform_tls_client_handshake(client_stream, server.node_id(), REG_V1),
+            server.perform_tls_server_handshake_with_ca_client_auth(
+                server_stream,
+                client_cas,
+                REG_V1
+            )
+        );
+
+        assert!(client_result.is_ok());
+        assert_eq!(
+            server_result.unwrap().1,
+            AuthenticatedPeer::Cert(client.cert())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_client_with_revoked_cert() {
+        struct AllRevoked;
+        impl TlsCertRevocationCheck for AllRevoked {
+            fn is_revoked(&self, _cert: &TlsPublicKeyCert) -> bool {
+                true
+            }
+        }
+        let client = SelfSignedTlsHandshake::new(node(1));
+        let server = SelfSignedTlsHandshake::new(node(2));
+        let client_cas = ClientCas::new(vec![client.cert()].into_iter().collect(), 0)
+            .unwrap()
+            .with_revocation_check(Arc::new(AllRevoked));
+        let (client_stream, server_stream) = tcp_stream_pair().await;
+
+        let (_, server_result) = tokio::join!(
+            client.perform_tls_client_handshake(client_stream, server.node_id(), REG_V1),
+            server.perform_tls_server_handshake_with_ca_client_auth(
+                server_stream,
+                client_cas,
+                REG_V1
+            )
+        );
+
+        assert!(matches!(
+            server_result,
+            Err(TlsServerHandshakeError::ClientNotAllowed(
+                PeerNotAllowedError::CertificateRevoked
+            ))
+        ));
+    }
+
+    #[test]
+    fn should_return_expected_result_from_mock() {
+        let error = PeerRevalidationError::NodeRemoved {
+            node_id: node(1),
+            registry_version: REG_V1,
+        };
+        let mut mock = MockTlsHandshake::new();
+        mock.expect_revalidate_peer()
+            .times(1)
+            .return_const(Err(error.clone()));
+
+        let result = mock.revalidate_peer(&AuthenticatedPeer::Node(node(1)), REG_V1, REG_V1);
+
+        assert_eq!(result, Err(error));
+    }
 }
@@ -0,0 +1,175 @@
+use crate::{TlsPublicKeyCert, TlsPublicKeyCertCreationError};
+use openssl::x509::X509;
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The SHA-256 fingerprint of the DER encoding of an X.509 certificate.
+pub struct CertFingerprint(pub [u8; 32]);
+
+impl Display for CertFingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// A set of explicitly trusted certificates (trust anchors).
+///
+/// In TLS handshakes, a peer is trusted if the root of the certificate chain
+/// it presents is contained in the trust store. Certificates can be looked up
+/// by their SHA-256 fingerprint, e.g., to remove a trust anchor given only
+/// its fingerprint.
+///
+/// A trust store can be persisted to disk as a bundle of PEM-encoded
+/// certificates, which is the format commonly used for CA bundles.
+pub struct TrustStore {
+    certs: HashSet<TlsPublicKeyCert>,
+}
+
+impl TrustStore {
+    /// Creates an empty trust store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `cert` to the trust store. Returns `false` if the certificate
+    /// was already contained.
+    pub fn add(&mut self, cert: TlsPublicKeyCert) -> bool {
+        self.certs.insert(cert)
+    }
+
+    /// Removes the certificate with the given `fingerprint` from the trust
+    /// store, and returns it if it was contained.
+    pub fn remove(&mut self, fingerprint: &CertFingerprint) -> Option<TlsPublicKeyCert> {
+        let cert = self.get(fingerprint)?.clone();
+        self.certs.take(&cert)
+    }
+
+    /// Returns the certificate with the given `fingerprint`, if contained.
+    pub fn get(&self, fingerprint: &CertFingerprint) -> Option<&TlsPublicKeyCert> {
+        self.certs
+            .iter()
+            .find(|cert| cert.fingerprint() == *fingerprint)
+    }
+
+    /// Returns whether `cert` is contained in the trust store.
+    pub fn contains(&self, cert: &TlsPublicKeyCert) -> bool {
+        self.certs.contains(cert)
+    }
+
+    /// Access the trusted certificates.
+    pub fn certs(&self) -> &HashSet<TlsPublicKeyCert> {
+        &self.certs
+    }
+
+    pub fn len(&self) -> usize {
+        self.certs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty()
+    }
+
+    /// Loads a trust store from a file containing a bundle of PEM-encoded
+    /// certificates.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TrustStoreError> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path).map_err(|e| TrustStoreError::io(path, e))?;
+        let certs = X509::stack_from_pem(&pem).map_err(|e| TrustStoreError::MalformedBundle {
+            path: path.to_path_buf(),
+            internal_error: format!("Error parsing PEM: {}", e),
+        })?;
+        certs
+            .into_iter()
+            .map(|cert| {
+                TlsPublicKeyCert::new_from_x509(cert).map_err(
+                    |TlsPublicKeyCertCreationError { internal_error }| {
+                        TrustStoreError::MalformedBundle {
+                            path: path.to_path_buf(),
+                            internal_error,
+                        }
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Saves the trust store to a file as a bundle of PEM-encoded
+    /// certificates, ordered by fingerprint.
+    ///
+    /// The bundle is first written to a temporary file next to `path`, which
+    /// then replaces `path`, so that a crash does not leave a partially
+    /// written trust store behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TrustStoreError> {
+        let path = path.as_ref();
+        let mut certs: Vec<_> = self.certs.iter().collect();
+        certs.sort_by_key(|cert| cert.fingerprint());
+        let mut pem = vec![];
+        for cert in certs {
+            let cert_pem =
+                cert.as_x509()
+                    .to_pem()
+                    .map_err(|e| TrustStoreError::MalformedBundle {
+                        path: path.to_path_buf(),
+                        internal_error: format!("Error encoding PEM: {}", e),
+                    })?;
+            pem.extend_from_slice(&cert_pem);
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, &pem).map_err(|e| TrustStoreError::io(&tmp_path, e))?;
+        std::fs::rename(&tmp_path, path).map_err(|e| TrustStoreError::io(path, e))
+    }
+}
+
+impl FromIterator<TlsPublicKeyCert> for TrustStore {
+    fn from_iter<I: IntoIterator<Item = TlsPublicKeyCert>>(iter: I) -> Self {
+        Self {
+            certs: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl From<HashSet<TlsPublicKeyCert>> for TrustStore {
+    fn from(certs: HashSet<TlsPublicKeyCert>) -> Self {
+        Self { certs }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Errors from loading or saving a `TrustStore`.
+pub enum TrustStoreError {
+    /// The trust store file could not be read or written.
+    Io {
+        path: PathBuf,
+        internal_error: String,
+    },
+    /// The trust store file does not contain a valid bundle of PEM-encoded
+    /// certificates.
+    MalformedBundle {
+        path: PathBuf,
+        internal_error: String,
+    },
+}
+
+impl TrustStoreError {
+    fn io(path: &Path, error: std::io::Error) -> Self {
+        TrustStoreError::Io {
+            path: path.to_path_buf(),
+            internal_error: error.to_string(),
+        }
+    }
+}
+
+impl Display for TrustStoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for TrustStoreError {}
@@ -0,0 +1,325 @@
+//! A listener for TLS connections on several local addresses.
+//!
+//! `TlsListener` owns one TCP listener per local address, typically one for
+//! IPv4 and one for IPv6, and performs the TLS server handshake (with optional
+//! client authentication) for every accepted connection. The connections for
+//! which the handshake succeeded are yielded as a stream of
+//! `(TlsStream, Peer, SocketAddr)`.
+//!
+//! Each handshake runs in its own task, so a slow or failing client does not
+//! delay the connections of other clients. The number of concurrent
+//! handshakes is bounded, and handshakes that do not complete in time are
+//! aborted. Failed accepts and handshakes are logged and counted in the
+//! metrics, but are not yielded by the stream.
+#![forbid(unsafe_code)]
+#![deny(clippy::unwrap_used)]
+
+use futures::Stream;
+use ic_crypto_tls_interfaces::{AllowedClients, Peer, TlsHandshake, TlsStream};
+use ic_interfaces::registry::RegistryClient;
+use ic_logger::{warn, ReplicaLogger};
+use ic_metrics::MetricsRegistry;
+use metrics::{TlsListenerMetrics, STATUS_ERROR, STATUS_SUCCESS, STATUS_TIMEOUT};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+
+mod metrics;
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of pending TCP connections per listener.
+const LISTEN_BACKLOG: i32 = 1024;
+/// Maximum number of established TLS connections that have not yet been
+/// taken from the stream.
+const ESTABLISHED_CONNECTIONS_CAPACITY: usize = 128;
+/// Delay before accepting again after a failed accept, so that e.g. running
+/// out of file descriptors does not result in a busy loop.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+/// Maximum number of handshakes in progress at the same time. While this many
+/// handshakes are in progress, no further connections are accepted, so they
+/// queue up in the listen backlog instead.
+const MAX_CONCURRENT_HANDSHAKES: usize = 1024;
+/// Maximum duration of a handshake, so that clients that stall the handshake
+/// do not hold on to a handshake slot indefinitely.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TLS connection for which the server handshake succeeded.
+pub type TlsConnection = (TlsStream, Peer, SocketAddr);
+
+/// A stream of TLS connections accepted on one or more local addresses.
+///
+/// See the module documentation for details. Dropping the listener closes the
+/// TCP listeners; handshakes in progress are completed, but their connections
+/// are dropped.
+pub struct TlsListener {
+    local_addrs: Vec<SocketAddr>,
+    connections: mpsc::Receiver<TlsConnection>,
+    accept_tasks: Vec<JoinHandle<()>>,
+}
+
+impl TlsListener {
+    /// Binds to `port` on both the IPv4 and the IPv6 wildcard address.
+    ///
+    /// The IPv6 listener only accepts IPv6 connections, so that both
+    /// listeners can use the same port. If `port` is 0, the two listeners are
+    /// assigned different ports; see `local_addrs`.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind_dual_stack(
+        port: u16,
+        tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
+        registry_client: Arc<dyn RegistryClient>,
+        allowed_authenticating_clients: AllowedClients,
+        metrics_registry: &MetricsRegistry,
+        log: ReplicaLogger,
+    ) -> io::Result<Self> {
+        Self::bind(
+            &[
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)),
+            ],
+            tls_handshake,
+            registry_client,
+            allowed_authenticating_clients,
+            metrics_registry,
+            log,
+        )
+    }
+
+    /// Binds a listener to each of the given addresses.
+    ///
+    /// Every accepted connection is authenticated using
+    /// `TlsHandshake::perform_tls_server_handshake_temp_with_optional_client_auth`
+    /// with the given `allowed_authenticating_clients` and the latest registry
+    /// version. As for that method, it is the responsibility of the caller to
+    /// check whether the yielded `Peer` is authenticated.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind(
+        addrs: &[SocketAddr],
+        tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
+        registry_client: Arc<dyn RegistryClient>,
+        allowed_authenticating_clients: AllowedClients,
+        metrics_registry: &MetricsRegistry,
+        log: ReplicaLogger,
+    ) -> io::Result<Self> {
+        Self::bind_with_limits(
+            addrs,
+            tls_handshake,
+            registry_client,
+            allowed_authenticating_clients,
+            metrics_registry,
+            log,
+            MAX_CONCURRENT_HANDSHAKES,
+            HANDSHAKE_TIMEOUT,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn bind_with_limits(
+        addrs: &[SocketAddr],
+        tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
+        registry_client: Arc<dyn RegistryClient>,
+        allowed_authenticating_clients: AllowedClients,
+        metrics_registry: &MetricsRegistry,
+        log: ReplicaLogger,
+        max_concurrent_handshakes: usize,
+        handshake_timeout: Duration,
+    ) -> io::Result<Self> {
+        let listeners = addrs
+            .iter()
+            .map(bind_tcp_listener)
+            .collect::<io::Result<Vec<_>>>()?;
+        let local_addrs = listeners
+            .iter()
+            .map(TcpListener::local_addr)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let handshaker = Arc::new(Handshaker {
+            tls_handshake,
+            registry_client,
+            allowed_authenticating_clients,
+            handshake_permits: Arc::new(Semaphore::new(max_concurrent_handshakes)),
+            handshake_timeout,
+            metrics: TlsListenerMetrics::new(metrics_registry),
+            log,
+        });
+        let (sender, connections) = mpsc::channel(ESTABLISHED_CONNECTIONS_CAPACITY);
+        let accept_tasks = listeners
+            .into_iter()
+            .map(|listener| {
+                tokio::spawn(accept_loop(
+                    listener,
+                    Arc::clone(&handshaker),
+                    sender.clone(),
+                ))
+            })
+            .collect();
+
+        Ok(Self {
+            local_addrs,
+            connections,
+            accept_tasks,
+        })
+    }
+
+    /// The local addresses of the listeners, in the order in which they were
+    /// given to `bind`.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+}
+
+impl Stream for TlsListener {
+    type Item = TlsConnection;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.connections.poll_recv(cx)
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        for accept_task in &self.accept_tasks {
+            accept_task.abort();
+        }
+    }
+}
+
+/// Binds a non-blocking TCP listener to `addr`. IPv6 listeners do not accept
+/// IPv4 connections.
+fn bind_tcp_listener(addr: &SocketAddr) -> io::Result<TcpListener> {
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    let domain = match addr {
+        SocketAddr::V4(_) => Domain::ipv4(),
+        SocketAddr::V6(_) => Domain::ipv6(),
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SockAddr::from(*addr))?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into_tcp_listener())
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    handshaker: Arc<Handshaker>,
+    sender: mpsc::Sender<TlsConnection>,
+) {
+    loop {
+        let permit = match Arc::clone(&handshaker.handshake_permits)
+            .acquire_owned()
+            .await
+        {
+            Ok(permit) => permit,
+            // The semaphore is never closed.
+            Err(_) => return,
+        };
+        match listener.accept().await {
+            Ok((tcp_stream, peer_addr)) => {
+                let handshaker = Arc::clone(&handshaker);
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let connection = handshaker.handshake(tcp_stream, peer_addr).await;
+                    // Free the slot before waiting for the connection to be
+                    // taken from the stream.
+                    drop(permit);
+                    if let Some(connection) = connection {
+                        // The send only fails if the listener was dropped, in
+                        // which case the connection is dropped as well.
+                        let _ = sender.send(connection).await;
+                    }
+                });
+            }
+            Err(e) => {
+                handshaker.metrics.accept_errors.inc();
+                warn!(handshaker.log, "Failed to accept TCP connection: {}", e);
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+            }
+        }
+    }
+}
+
+/// The state shared by the handshakes of all accepted connections.
+struct Handshaker {
+    tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
+    registry_client: Arc<dyn RegistryClient>,
+    allowed_authenticating_clients: AllowedClients,
+    /// One permit per handshake that may be in progress.
+    handshake_permits: Arc<Semaphore>,
+    handshake_timeout: Duration,
+    metrics: TlsListenerMetrics,
+    log: ReplicaLogger,
+}
+
+impl Handshaker {
+    async fn handshake(
+        &self,
+        tcp_stream: TcpStream,
+        peer_addr: SocketAddr,
+    ) -> Option<TlsConnection> {
+        let start_time = Instant::now();
+        let registry_version = self.registry_client.get_latest_version();
+        self.metrics.handshakes_in_progress.inc();
+        let result = tokio::time::timeout(
+            self.handshake_timeout,
+            self.tls_handshake
+                .perform_tls_server_handshake_temp_with_optional_client_auth(
+                    tcp_stream,
+                    self.allowed_authenticating_clients.clone(),
+                    registry_version,
+                ),
+        )
+        .await;
+        self.metrics.handshakes_in_progress.dec();
+        self.metrics
+            .handshake_duration
+            .observe(start_time.elapsed().as_secs_f64());
+
+        match result {
+            Ok(Ok((tls_stream, peer))) => {
+                self.metrics
+                    .handshakes
+                    .with_label_values(&[STATUS_SUCCESS])
+                    .inc();
+                Some((tls_stream, peer, peer_addr))
+            }
+            Ok(Err(e)) => {
+                self.metrics
+                    .handshakes
+                    .with_label_values(&[STATUS_ERROR])
+                    .inc();
+                warn!(
+                    self.log,
+                    "TLS handshake with {} failed at registry version {}: {}",
+                    peer_addr,
+                    registry_version,
+                    e
+                );
+                None
+            }
+            Err(_) => {
+                self.metrics
+                    .handshakes
+                    .with_label_values(&[STATUS_TIMEOUT])
+                    .inc();
+                warn!(
+                    self.log,
+                    "TLS handshake with {} timed out after {:?}", peer_addr, self.handshake_timeout
+                );
+                None
+            }
+        }
+    }
+}
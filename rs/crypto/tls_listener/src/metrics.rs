@@ -0,0 +1,41 @@
+use ic_metrics::buckets::decimal_buckets;
+use ic_metrics::MetricsRegistry;
+use prometheus::{Histogram, IntCounter, IntCounterVec, IntGauge};
+
+/// Label values of `tls_listener_handshakes_total`.
+pub(crate) const STATUS_SUCCESS: &str = "success";
+pub(crate) const STATUS_ERROR: &str = "error";
+pub(crate) const STATUS_TIMEOUT: &str = "timeout";
+
+pub(crate) struct TlsListenerMetrics {
+    pub accept_errors: IntCounter,
+    pub handshakes: IntCounterVec,
+    pub handshakes_in_progress: IntGauge,
+    pub handshake_duration: Histogram,
+}
+
+impl TlsListenerMetrics {
+    pub fn new(metrics_registry: &MetricsRegistry) -> Self {
+        Self {
+            accept_errors: metrics_registry.int_counter(
+                "tls_listener_accept_errors_total",
+                "Number of errors while accepting TCP connections.",
+            ),
+            handshakes: metrics_registry.int_counter_vec(
+                "tls_listener_handshakes_total",
+                "Number of TLS server handshakes on accepted connections, by status.",
+                &["status"],
+            ),
+            handshakes_in_progress: metrics_registry.int_gauge(
+                "tls_listener_handshakes_in_progress",
+                "Number of TLS server handshakes currently in progress.",
+            ),
+            handshake_duration: metrics_registry.histogram(
+                "tls_listener_handshake_duration_seconds",
+                "Duration of TLS server handshakes on accepted connections, in seconds.",
+                // 1ms, 2ms, 5ms, ..., 10s, 20s, 50s
+                decimal_buckets(-3, 1),
+            ),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+#![allow(clippy::unwrap_used)]
+use super::*;
+use futures::StreamExt;
+use ic_crypto_tls_interfaces::test_utils::{MockTlsHandshake, SelfSignedTlsHandshake};
+use ic_crypto_tls_interfaces::{HandshakeFailureKind, SomeOrAllNodes, TlsServerHandshakeError};
+use ic_logger::replica_logger::no_op_logger;
+use ic_registry_client::fake::FakeRegistryClient;
+use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
+use ic_test_utilities::metrics::{fetch_int_counter_vec, fetch_int_gauge, labels};
+use ic_types::{NodeId, PrincipalId};
+use std::collections::HashSet;
+
+/// A `TlsHandshake` whose server handshakes fail.
+fn failing_tls_handshake() -> Arc<MockTlsHandshake> {
+    let mut tls_handshake = MockTlsHandshake::new();
+    tls_handshake
+        .expect_perform_tls_server_handshake_temp_with_optional_client_auth()
+        .returning(|_, _, _| {
+            Err(TlsServerHandshakeError::HandshakeError {
+                kind: HandshakeFailureKind::Other,
+                internal_error: "handshake failed".to_string(),
+            })
+        });
+    Arc::new(tls_handshake)
+}
+
+/// A `TlsHandshake` whose server handshakes never complete with the clients
+/// of the tests, which never send a TLS client hello.
+fn stalling_tls_handshake() -> Arc<SelfSignedTlsHandshake> {
+    Arc::new(SelfSignedTlsHandshake::new(NodeId::from(
+        PrincipalId::new_node_test_id(1),
+    )))
+}
+
+fn registry_client() -> Arc<FakeRegistryClient> {
+    Arc::new(FakeRegistryClient::new(Arc::new(
+        ProtoRegistryDataProvider::new(),
+    )))
+}
+
+fn listener(addrs: &[SocketAddr], metrics_registry: &MetricsRegistry) -> TlsListener {
+    TlsListener::bind(
+        addrs,
+        failing_tls_handshake(),
+        registry_client(),
+        AllowedClients::new(SomeOrAllNodes::All, HashSet::new()).unwrap(),
+        metrics_registry,
+        no_op_logger(),
+    )
+    .unwrap()
+}
+
+/// A listener on a single local address whose handshakes never complete.
+fn stalling_listener(
+    metrics_registry: &MetricsRegistry,
+    max_concurrent_handshakes: usize,
+    handshake_timeout: Duration,
+) -> TlsListener {
+    TlsListener::bind_with_limits(
+        &[SocketAddr::from((Ipv4Addr::LOCALHOST, 0))],
+        stalling_tls_handshake(),
+        registry_client(),
+        AllowedClients::new(SomeOrAllNodes::All, HashSet::new()).unwrap(),
+        metrics_registry,
+        no_op_logger(),
+        max_concurrent_handshakes,
+        handshake_timeout,
+    )
+    .unwrap()
+}
+
+fn handshakes(metrics_registry: &MetricsRegistry, status: &str) -> u64 {
+    fetch_int_counter_vec(metrics_registry, "tls_listener_handshakes_total")
+        .get(&labels(&[("status", status)]))
+        .copied()
+        .unwrap_or(0)
+}
+
+fn handshakes_in_progress(metrics_registry: &MetricsRegistry) -> u64 {
+    fetch_int_gauge(metrics_registry, "tls_listener_handshakes_in_progress").unwrap()
+}
+
+async fn wait_for(condition: impl Fn() -> bool, description: &str) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out waiting for {}", description);
+}
+
+async fn wait_for_handshakes(metrics_registry: &MetricsRegistry, status: &str, expected: u64) {
+    wait_for(
+        || handshakes(metrics_registry, status) >= expected,
+        &format!("{} handshakes with status {}", expected, status),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn should_bind_to_each_address() {
+    let listener = listener(
+        &[
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+        ],
+        &MetricsRegistry::new(),
+    );
+
+    let local_addrs = listener.local_addrs();
+
+    assert_eq!(local_addrs.len(), 2);
+    assert!(local_addrs.iter().all(|addr| addr.port() != 0));
+    assert_ne!(local_addrs[0], local_addrs[1]);
+}
+
+#[tokio::test]
+async fn should_keep_accepting_after_failed_handshakes() {
+    let metrics_registry = MetricsRegistry::new();
+    let listener = listener(
+        &[
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+        ],
+        &metrics_registry,
+    );
+
+    for (i, addr) in listener.local_addrs().to_vec().into_iter().enumerate() {
+        let _client = TcpStream::connect(addr).await.unwrap();
+        wait_for_handshakes(&metrics_registry, STATUS_ERROR, i as u64 + 1).await;
+    }
+
+    assert_eq!(handshakes(&metrics_registry, STATUS_SUCCESS), 0);
+    assert_eq!(handshakes_in_progress(&metrics_registry), 0);
+}
+
+#[tokio::test]
+async fn should_not_yield_connections_with_failed_handshakes() {
+    let metrics_registry = MetricsRegistry::new();
+    let mut listener = listener(
+        &[SocketAddr::from((Ipv4Addr::LOCALHOST, 0))],
+        &metrics_registry,
+    );
+    let addr = listener.local_addrs()[0];
+
+    let _client = TcpStream::connect(addr).await.unwrap();
+    wait_for_handshakes(&metrics_registry, STATUS_ERROR, 1).await;
+
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), listener.next())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn should_fail_to_bind_address_in_use() {
+    let listener_1 = listener(
+        &[SocketAddr::from((Ipv4Addr::LOCALHOST, 0))],
+        &MetricsRegistry::new(),
+    );
+
+    let result = TlsListener::bind(
+        listener_1.local_addrs(),
+        failing_tls_handshake(),
+        registry_client(),
+        AllowedClients::new(SomeOrAllNodes::All, HashSet::new()).unwrap(),
+        &MetricsRegistry::new(),
+        no_op_logger(),
+    );
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn should_abort_handshakes_that_time_out() {
+    let metrics_registry = MetricsRegistry::new();
+    let listener = stalling_listener(&metrics_registry, 1, Duration::from_millis(10));
+    let addr = listener.local_addrs()[0];
+
+    let _client_1 = TcpStream::connect(addr).await.unwrap();
+    wait_for_handshakes(&metrics_registry, STATUS_TIMEOUT, 1).await;
+    // The slot of the aborted handshake is free again.
+    let _client_2 = TcpStream::connect(addr).await.unwrap();
+    wait_for_handshakes(&metrics_registry, STATUS_TIMEOUT, 2).await;
+
+    assert_eq!(handshakes(&metrics_registry, STATUS_ERROR), 0);
+}
+
+#[tokio::test]
+async fn should_limit_concurrent_handshakes() {
+    let metrics_registry = MetricsRegistry::new();
+    let listener = stalling_listener(&metrics_registry, 2, Duration::from_secs(60));
+    let addr = listener.local_addrs()[0];
+
+    let mut clients = vec![];
+    for _ in 0..3 {
+        clients.push(TcpStream::connect(addr).await.unwrap());
+    }
+    wait_for(
+        || handshakes_in_progress(&metrics_registry) == 2,
+        "2 handshakes in progress",
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(handshakes_in_progress(&metrics_registry), 2);
+}
@@ -1,5 +1,5 @@
 use ic_crypto_internal_threshold_sig_bls12381 as bls12_381;
-use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
+use ic_types::crypto::{threshold_sig::ThresholdSigPublicKey, CryptoResult};
 use std::io::{Error, ErrorKind, Result};
 use std::path::Path;
 
@@ -45,6 +45,17 @@ pub fn parse_threshold_sig_key(pem_file: &Path) -> Result<ThresholdSigPublicKey>
     Ok(ThresholdSigPublicKey::from(pubkey_bytes))
 }
 
+/// Decodes a DER-encoded threshold signature public key, as found in the
+/// `/subnet/<subnet_id>/public_key` leaves of the state tree.
+///
+/// # Error
+/// * `CryptoError::MalformedPublicKey` if the bytes are not a DER-encoded
+///   BLS12-381 public key.
+pub fn threshold_sig_public_key_from_der(bytes: &[u8]) -> CryptoResult<ThresholdSigPublicKey> {
+    let pubkey_bytes = bls12_381::api::public_key_from_der(bytes)?;
+    Ok(ThresholdSigPublicKey::from(pubkey_bytes))
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::unwrap_used)]
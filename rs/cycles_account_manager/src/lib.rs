@@ -19,7 +19,8 @@ use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{CanisterState, SystemState};
 use ic_types::{
     ic00::{
-        CanisterIdRecord, InstallCodeArgs, Method, Payload, SetControllerArgs, UpdateSettingsArgs,
+        CanisterIdRecord, ExportCanisterArgs, ExportCanisterChunkArgs, ImportCanisterChunkArgs,
+        InstallCodeArgs, Method, Payload, SetControllerArgs, UpdateSettingsArgs,
     },
     messages::{
         is_subnet_message, Request, Response, SignedIngressContent,
@@ -235,6 +236,24 @@ impl CyclesAccountManager {
             .consumed_cycles_since_replica_started += NominalCycles::from_cycles(cycles);
     }
 
+    /// Updates the metric `consumed_cycles_by_heartbeats_since_replica_started`
+    /// with the cost of the `num_instructions` executed by the heartbeat of the
+    /// canister.
+    ///
+    /// Note that the cycles are already included in
+    /// `consumed_cycles_since_replica_started`. This metric only allows
+    /// telling apart the share of heartbeats from the share of messages.
+    pub fn observe_heartbeat_execution_cycles(
+        &self,
+        system_state: &mut SystemState,
+        num_instructions: NumInstructions,
+    ) {
+        system_state
+            .canister_metrics
+            .consumed_cycles_by_heartbeats_since_replica_started +=
+            NominalCycles::from_cycles(self.execution_cost(num_instructions));
+    }
+
     /// Subtracts the corresponding cycles worth of the provided
     /// `num_instructions` from the canister's balance.
     ///
@@ -317,12 +336,14 @@ impl CyclesAccountManager {
             // message.
             match Method::from_str(ingress.method_name()) {
                 Ok(Method::ProvisionalCreateCanisterWithCycles)
+                | Ok(Method::ProvisionalCreateCanistersWithCycles)
                 | Ok(Method::ProvisionalTopUpCanister) => {
                     // Provisional methods are free.
                     None
                 }
                 Ok(Method::StartCanister)
                 | Ok(Method::CanisterStatus)
+                | Ok(Method::CanisterMetrics)
                 | Ok(Method::DeleteCanister)
                 | Ok(Method::UninstallCode)
                 | Ok(Method::StopCanister) => match CanisterIdRecord::decode(ingress.arg()) {
@@ -341,10 +362,31 @@ impl CyclesAccountManager {
                     Ok(record) => Some(record.get_canister_id()),
                     Err(_) => return Err(IngressInductionCostError::InvalidSubnetPayload),
                 },
+                Ok(Method::ExportCanister) => match ExportCanisterArgs::decode(ingress.arg()) {
+                    Ok(record) => Some(record.get_canister_id()),
+                    Err(_) => return Err(IngressInductionCostError::InvalidSubnetPayload),
+                },
+                Ok(Method::ExportCanisterChunk) => {
+                    match ExportCanisterChunkArgs::decode(ingress.arg()) {
+                        Ok(record) => Some(record.get_canister_id()),
+                        Err(_) => return Err(IngressInductionCostError::InvalidSubnetPayload),
+                    }
+                }
+                Ok(Method::ImportCanisterChunk) => {
+                    match ImportCanisterChunkArgs::decode(ingress.arg()) {
+                        Ok(record) => Some(record.get_canister_id()),
+                        Err(_) => return Err(IngressInductionCostError::InvalidSubnetPayload),
+                    }
+                }
                 Ok(Method::CreateCanister)
                 | Ok(Method::SetupInitialDKG)
                 | Ok(Method::DepositCycles)
+                | Ok(Method::ImportCanister)
+                | Ok(Method::CanisterSelfDestruct)
                 | Ok(Method::RawRand)
+                | Ok(Method::CostCall)
+                | Ok(Method::CostCreateCanister)
+                | Ok(Method::CostExecution)
                 | Ok(Method::SignWithECDSA)
                 | Err(_) => {
                     return Err(IngressInductionCostError::UnknownSubnetMethod);
@@ -356,21 +398,31 @@ impl CyclesAccountManager {
         };
 
         match paying_canister {
-            Some(paying_canister) => {
-                let bytes_to_charge = ingress.arg().len()
-                    + ingress.method_name().len()
-                    + ingress.nonce().map(|n| n.len()).unwrap_or(0);
-                let cost = self.config.ingress_message_reception_fee
-                    + self.config.ingress_byte_reception_fee * bytes_to_charge;
-                Ok(IngressInductionCost::Fee {
-                    payer: paying_canister,
-                    cost,
-                })
-            }
+            Some(paying_canister) => Ok(IngressInductionCost::Fee {
+                payer: paying_canister,
+                cost: self.ingress_induction_fee(
+                    ingress.method_name(),
+                    ingress.arg(),
+                    ingress.nonce(),
+                ),
+            }),
             None => Ok(IngressInductionCost::Free),
         }
     }
 
+    /// Returns the fee for inducting an ingress message with the given method
+    /// name, argument and nonce into the ingress queue of a canister.
+    pub fn ingress_induction_fee(
+        &self,
+        method_name: &str,
+        arg: &[u8],
+        nonce: Option<&Vec<u8>>,
+    ) -> Cycles {
+        let bytes_to_charge = arg.len() + method_name.len() + nonce.map(|n| n.len()).unwrap_or(0);
+        self.config.ingress_message_reception_fee
+            + self.config.ingress_byte_reception_fee * bytes_to_charge
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     //
     // Storage
@@ -441,16 +493,7 @@ impl CyclesAccountManager {
         canister_compute_allocation: ComputeAllocation,
         request: &Request,
     ) -> Result<(), CanisterOutOfCyclesError> {
-        // The total amount charged is the fee to do the xnet call (request +
-        // response) + the fee to send the request + the fee for the largest
-        // possible response + the fee for executing the largest allowed
-        // response when it eventually arrives.
-        let fee = self.config.xnet_call_fee
-            + self.config.xnet_byte_transmission_fee
-                * Cycles::from(request.payload_size_bytes().get())
-            + self.config.xnet_byte_transmission_fee
-                * Cycles::from(MAX_INTER_CANISTER_PAYLOAD_IN_BYTES.get())
-            + self.execution_cost(self.max_num_instructions);
+        let fee = self.request_cost(request.payload_size_bytes());
         self.consume_with_threshold(
             system_state,
             fee,
@@ -462,6 +505,21 @@ impl CyclesAccountManager {
         )
     }
 
+    /// Returns the amount of cycles withdrawn when sending a request whose
+    /// method name and payload take `payload_size` bytes, see
+    /// [`Self::withdraw_request_cycles`].
+    pub fn request_cost(&self, payload_size: NumBytes) -> Cycles {
+        // The total amount charged is the fee to do the xnet call (request +
+        // response) + the fee to send the request + the fee for the largest
+        // possible response + the fee for executing the largest allowed
+        // response when it eventually arrives.
+        self.config.xnet_call_fee
+            + self.config.xnet_byte_transmission_fee * Cycles::from(payload_size.get())
+            + self.config.xnet_byte_transmission_fee
+                * Cycles::from(MAX_INTER_CANISTER_PAYLOAD_IN_BYTES.get())
+            + self.execution_cost(self.max_num_instructions)
+    }
+
     /// Refunds the cycles from the response. In particular, adds leftover
     /// cycles from the what was reserved when the corresponding `Request` was
     /// sent earlier.
@@ -570,7 +628,6 @@ impl CyclesAccountManager {
     }
 
     /// Adds `cycles` worth of cycles to the canister's balance.
-    /// The cycles balance added in a single go is limited to u64::max_value()
     /// Returns the amount of cycles that does not fit in the balance.
     pub fn add_cycles(&self, system_state: &mut SystemState, cycles_to_add: Cycles) -> Cycles {
         let cycles = self.check_max_cycles_can_add(system_state.cycles_balance, cycles_to_add);
@@ -10,7 +10,7 @@ use ic_test_utilities::{
     state::{new_canister_state, SystemStateBuilder},
     types::{
         ids::{canister_test_id, subnet_test_id, user_test_id},
-        messages::SignedIngressBuilder,
+        messages::{RequestBuilder, SignedIngressBuilder},
     },
     with_test_replica_logger,
 };
@@ -735,6 +735,30 @@ fn consume_cycles_updates_consumed_cycles() {
     );
 }
 
+#[test]
+fn withdraw_request_cycles_withdraws_request_cost() {
+    let mut system_state = SystemStateBuilder::new().build();
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let request = RequestBuilder::new()
+        .method_name("method".to_string())
+        .method_payload(vec![0; 100])
+        .build();
+
+    cycles_account_manager
+        .withdraw_request_cycles(
+            &mut system_state,
+            NumBytes::from(0),
+            ComputeAllocation::default(),
+            &request,
+        )
+        .unwrap();
+
+    assert_eq!(
+        system_state.cycles_balance,
+        INITIAL_CYCLES - cycles_account_manager.request_cost(NumBytes::from(106))
+    );
+}
+
 #[test]
 fn verify_refund() {
     let mut system_state = SystemStateBuilder::new().build();
@@ -754,3 +778,30 @@ fn verify_refund() {
         initial_consumed_cycles - NominalCycles::from(cycles)
     );
 }
+
+#[test]
+fn observe_heartbeat_execution_cycles_only_updates_heartbeat_metric() {
+    let mut system_state = SystemStateBuilder::new().build();
+    let cycles_account_manager = CyclesAccountManagerBuilder::new()
+        .with_subnet_type(SubnetType::Application)
+        .build();
+
+    let consumed_cycles_before = system_state
+        .canister_metrics
+        .consumed_cycles_since_replica_started;
+    let num_instructions = NumInstructions::from(1_000_000);
+    cycles_account_manager.observe_heartbeat_execution_cycles(&mut system_state, num_instructions);
+
+    assert_eq!(
+        system_state
+            .canister_metrics
+            .consumed_cycles_by_heartbeats_since_replica_started,
+        NominalCycles::from_cycles(cycles_account_manager.execution_cost(num_instructions))
+    );
+    assert_eq!(
+        system_state
+            .canister_metrics
+            .consumed_cycles_since_replica_started,
+        consumed_cycles_before
+    );
+}
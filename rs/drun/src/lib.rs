@@ -1,4 +1,7 @@
 //! Standalone interface for testing application canisters.
+//!
+//! The [`replay`] module provides a library API to re-execute blocks against
+//! a checkpoint and detect divergent states.
 
 use crate::message::{msg_stream_from_file, Message};
 use hex::encode;
@@ -44,6 +47,7 @@ use std::sync::{Arc, Mutex};
 use std::{thread::sleep, time::Duration};
 
 mod message;
+pub mod replay;
 
 // drun will panic if it takes more than this many batches
 // until a response for a message is received
@@ -181,7 +185,7 @@ pub fn run_drun(uo: DrunOptions) -> Result<(), String> {
         &cfg.state_manager,
         ic_types::malicious_flags::MaliciousFlags::default(),
     ));
-    let (_, ingress_history_writer, http_query_handler, scheduler, ingress_hist_reader) =
+    let (_, ingress_history_writer, http_query_handler, scheduler, ingress_hist_reader, _) =
         setup_execution(
             log.clone().into(),
             &metrics_registry,
@@ -0,0 +1,415 @@
+//! Deterministic replay of blocks against a checkpoint.
+//!
+//! A [`Replayer`] loads the latest checkpoint found in the state root of the
+//! given config and executes a sequence of [`ReplayBlock`]s on top of it, one
+//! batch per block, exactly as message routing would on a replica. After each
+//! block the hash of the resulting state is compared with the hash recorded
+//! by the replica whose execution is being reproduced, if any. Divergent
+//! states are passed to a [`DivergenceHook`], e.g., [`DumpCanisterHeaps`], so
+//! that they can be inspected offline.
+use crate::{get_registry, setup_logger};
+use ic_config::{subnet_config::SubnetConfigs, Config};
+use ic_cycles_account_manager::CyclesAccountManager;
+use ic_execution_environment::setup_execution;
+use ic_interfaces::{
+    messaging::MessageRouting,
+    state_manager::{StateManager, StateReader},
+};
+use ic_messaging::MessageRoutingImpl;
+use ic_metrics::MetricsRegistry;
+use ic_registry_subnet_type::SubnetType;
+use ic_replicated_state::{CanisterState, PageMap, ReplicatedState};
+use ic_state_manager::StateManagerImpl;
+use ic_test_utilities::consensus::fake::FakeVerifier;
+use ic_types::{
+    batch::{Batch, BatchPayload, IngressPayload, XNetPayload},
+    messages::{Response, SignedIngress},
+    replica_config::ReplicaConfig,
+    time::Time,
+    CanisterId, CryptoHashOfPartialState, Height, NodeId, PrincipalId, Randomness, RegistryVersion,
+    SubnetId,
+};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{thread::sleep, time::Duration};
+
+// The replayer gives up if a delivered batch is not committed within this
+// many polls of the state manager.
+const MAX_POLLS_UNTIL_COMMITTED: u64 = 10000;
+// how long to wait between polls
+const WAIT_PER_POLL: Duration = Duration::from_millis(5);
+
+const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024;
+
+pub struct ReplayOptions {
+    /// The config of the replica. The checkpoint to replay from is loaded from
+    /// the state root of its state manager config.
+    pub cfg: Config,
+    pub subnet_id: SubnetId,
+    pub subnet_type: SubnetType,
+    pub log_file: Option<PathBuf>,
+}
+
+/// The contents of a block that are relevant for execution.
+#[derive(Clone, Debug)]
+pub struct ReplayBlock {
+    pub time: Time,
+    pub randomness: Randomness,
+    /// The registry version the block was executed at. The registry of the
+    /// replayer only has version 1, with the subnet of the replayed replica.
+    pub registry_version: RegistryVersion,
+    pub ingress: Vec<SignedIngress>,
+    pub xnet: XNetPayload,
+    pub consensus_responses: Vec<Response>,
+    pub requires_full_state_hash: bool,
+    /// The hash of the state the replica computed after executing the block.
+    /// The replayed state is not checked if `None`.
+    pub expected_state_hash: Option<CryptoHashOfPartialState>,
+}
+
+/// A replayed state whose hash differs from the expected one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub height: Height,
+    pub expected: CryptoHashOfPartialState,
+    pub actual: CryptoHashOfPartialState,
+}
+
+/// Called for every replayed state that diverges from the expected one.
+pub trait DivergenceHook {
+    fn on_divergence(&mut self, divergence: &Divergence, state: &ReplicatedState);
+}
+
+/// Ignores divergences, i.e., they are only part of the [`ReplayReport`].
+pub struct NoopHook;
+
+impl DivergenceHook for NoopHook {
+    fn on_divergence(&mut self, _divergence: &Divergence, _state: &ReplicatedState) {}
+}
+
+/// Writes the Wasm heaps of the canisters in a divergent state to
+/// `<dir>/<height>/<canister_id>.heap`. The files are sparse: pages that were
+/// never written read as zero.
+pub struct DumpCanisterHeaps {
+    dir: PathBuf,
+    canisters: Option<BTreeSet<CanisterId>>,
+}
+
+impl DumpCanisterHeaps {
+    /// Dumps the heaps of all canisters.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            canisters: None,
+        }
+    }
+
+    /// Dumps only the heaps of `canisters`.
+    pub fn only(dir: PathBuf, canisters: BTreeSet<CanisterId>) -> Self {
+        Self {
+            dir,
+            canisters: Some(canisters),
+        }
+    }
+
+    fn dump(&self, height: Height, canister: &CanisterState) -> std::io::Result<()> {
+        let execution_state = match &canister.execution_state {
+            Some(execution_state) => execution_state,
+            None => return Ok(()),
+        };
+        let dir = self.dir.join(height.to_string());
+        fs::create_dir_all(&dir)?;
+        let file = File::create(dir.join(format!("{}.heap", canister.canister_id())))?;
+        file.set_len(execution_state.heap_size.get() as u64 * WASM_PAGE_SIZE_IN_BYTES)?;
+        write_page_map(&file, &execution_state.page_map)
+    }
+}
+
+impl DivergenceHook for DumpCanisterHeaps {
+    fn on_divergence(&mut self, divergence: &Divergence, state: &ReplicatedState) {
+        for canister in state.canisters_iter() {
+            let selected = self.canisters.as_ref().map_or(true, |canisters| {
+                canisters.contains(&canister.canister_id())
+            });
+            if selected {
+                self.dump(divergence.height, canister)
+                    .unwrap_or_else(|err| {
+                        panic!(
+                            "Failed to dump the heap of canister {} at height {}: {}",
+                            canister.canister_id(),
+                            divergence.height,
+                            err
+                        )
+                    });
+            }
+        }
+    }
+}
+
+fn write_page_map(file: &File, page_map: &PageMap) -> std::io::Result<()> {
+    for (index, contents) in page_map.host_pages_iter() {
+        if contents.iter().any(|byte| *byte != 0) {
+            file.write_all_at(contents, index.get() * contents.len() as u64)?;
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of [`Replayer::replay`].
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// The number of blocks that were executed.
+    pub replayed_blocks: u64,
+    pub divergences: Vec<Divergence>,
+}
+
+/// Executes blocks on top of a checkpoint, see the module documentation.
+pub struct Replayer {
+    state_manager: Arc<StateManagerImpl>,
+    message_routing: MessageRoutingImpl,
+}
+
+impl Replayer {
+    pub fn new(options: ReplayOptions) -> Self {
+        let ReplayOptions {
+            cfg,
+            subnet_id,
+            subnet_type,
+            log_file,
+        } = options;
+        let subnet_config = SubnetConfigs::default().own_subnet_config(subnet_type);
+        let replica_config = ReplicaConfig {
+            node_id: NodeId::from(PrincipalId::new_node_test_id(27)),
+            subnet_id,
+        };
+        let log = match log_file {
+            Some(log_file) => setup_logger(log_file),
+            None => slog::Logger::root(slog::Discard, slog::o!()),
+        };
+
+        let metrics_registry = MetricsRegistry::new();
+        let registry = get_registry(
+            &metrics_registry,
+            subnet_id,
+            subnet_type,
+            &[replica_config.node_id],
+        );
+
+        let cycles_account_manager = Arc::new(CyclesAccountManager::new(
+            subnet_config.scheduler_config.max_instructions_per_message,
+            cfg.hypervisor.max_cycles_per_canister,
+            subnet_type,
+            subnet_id,
+            subnet_config.cycles_account_manager_config,
+        ));
+
+        let state_manager = Arc::new(StateManagerImpl::new(
+            Arc::new(FakeVerifier::new()),
+            subnet_id,
+            subnet_type,
+            log.clone().into(),
+            &metrics_registry,
+            &cfg.state_manager,
+            ic_types::malicious_flags::MaliciousFlags::default(),
+        ));
+        let (_, ingress_history_writer, _, scheduler, _, _) = setup_execution(
+            log.clone().into(),
+            &metrics_registry,
+            subnet_id,
+            subnet_type,
+            subnet_config.scheduler_config,
+            cfg.hypervisor.clone(),
+            Arc::clone(&cycles_account_manager),
+            Arc::clone(&state_manager) as Arc<_>,
+        );
+
+        let message_routing = MessageRoutingImpl::new(
+            Arc::clone(&state_manager) as _,
+            Arc::clone(&state_manager) as _,
+            Arc::clone(&ingress_history_writer) as _,
+            scheduler,
+            cycles_account_manager,
+            subnet_id,
+            &metrics_registry,
+            log.into(),
+            Arc::clone(&registry) as _,
+        );
+
+        Self {
+            state_manager,
+            message_routing,
+        }
+    }
+
+    /// Returns the height of the latest replayed state, i.e., the height of
+    /// the checkpoint before any block was replayed.
+    pub fn height(&self) -> Height {
+        self.state_manager.latest_state_height()
+    }
+
+    /// Returns the latest replayed state.
+    pub fn latest_state(&self) -> Arc<ReplicatedState> {
+        self.state_manager.get_latest_state().take()
+    }
+
+    /// Executes `block` and compares the hash of the resulting state with the
+    /// expected one. Returns the divergence, if any, after passing it to
+    /// `hook`.
+    pub fn replay_block(
+        &self,
+        block: ReplayBlock,
+        hook: &mut dyn DivergenceHook,
+    ) -> Result<Option<Divergence>, String> {
+        let height = self.message_routing.expected_batch_height();
+        let batch = Batch {
+            batch_number: height,
+            requires_full_state_hash: block.requires_full_state_hash,
+            payload: BatchPayload {
+                ingress: IngressPayload::from(block.ingress),
+                xnet: block.xnet,
+            },
+            randomness: block.randomness,
+            registry_version: block.registry_version,
+            time: block.time,
+            consensus_responses: block.consensus_responses,
+        };
+        self.deliver_and_wait(batch)?;
+
+        let expected = match block.expected_state_hash {
+            Some(expected) => expected,
+            None => return Ok(None),
+        };
+        let actual = self.state_hash_at(height)?;
+        if actual == expected {
+            return Ok(None);
+        }
+        let divergence = Divergence {
+            height,
+            expected,
+            actual,
+        };
+        let state = self
+            .state_manager
+            .get_state_at(height)
+            .map_err(|err| format!("Failed to read the state at height {}: {}", height, err))?;
+        hook.on_divergence(&divergence, state.get_ref());
+        Ok(Some(divergence))
+    }
+
+    /// Executes `blocks` in order, see [`Self::replay_block`]. If
+    /// `stop_at_divergence` is set, the replay stops after the first divergent
+    /// block.
+    pub fn replay(
+        &self,
+        blocks: impl IntoIterator<Item = ReplayBlock>,
+        hook: &mut dyn DivergenceHook,
+        stop_at_divergence: bool,
+    ) -> Result<ReplayReport, String> {
+        let mut report = ReplayReport::default();
+        for block in blocks {
+            let divergence = self.replay_block(block, hook)?;
+            report.replayed_blocks += 1;
+            if let Some(divergence) = divergence {
+                report.divergences.push(divergence);
+                if stop_at_divergence {
+                    break;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Delivers `batch` and blocks until the resulting state is committed.
+    fn deliver_and_wait(&self, batch: Batch) -> Result<(), String> {
+        let height = batch.batch_number;
+        for _ in 0..MAX_POLLS_UNTIL_COMMITTED {
+            if self.message_routing.expected_batch_height() == height {
+                // The batch queue may be full, in which case we retry.
+                let _ = self.message_routing.deliver_batch(batch.clone());
+            } else if self.state_manager.latest_state_height() >= height {
+                return Ok(());
+            }
+            sleep(WAIT_PER_POLL);
+        }
+        Err(format!(
+            "Batch {} was not committed within {} polls",
+            height, MAX_POLLS_UNTIL_COMMITTED
+        ))
+    }
+
+    fn state_hash_at(&self, height: Height) -> Result<CryptoHashOfPartialState, String> {
+        self.state_manager
+            .list_state_hashes_to_certify()
+            .into_iter()
+            .find(|(h, _)| *h == height)
+            .map(|(_, hash)| hash)
+            .ok_or_else(|| format!("No state hash available at height {}", height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::types::ids::subnet_test_id;
+    use ic_types::crypto::CryptoHash;
+    use ic_types::time::UNIX_EPOCH;
+
+    fn replayer(cfg: Config) -> Replayer {
+        Replayer::new(ReplayOptions {
+            cfg,
+            subnet_id: subnet_test_id(1),
+            subnet_type: SubnetType::Application,
+            log_file: None,
+        })
+    }
+
+    fn block(expected_state_hash: Option<CryptoHashOfPartialState>) -> ReplayBlock {
+        ReplayBlock {
+            time: UNIX_EPOCH,
+            randomness: Randomness::from([0; 32]),
+            registry_version: RegistryVersion::from(1),
+            ingress: vec![],
+            xnet: XNetPayload::default(),
+            consensus_responses: vec![],
+            requires_full_state_hash: false,
+            expected_state_hash,
+        }
+    }
+
+    #[test]
+    fn replaying_a_recorded_block_reproduces_its_state_hash() {
+        let (cfg, _tmpdir) = Config::temp_config();
+        let recorder = replayer(cfg);
+        let height = recorder.message_routing.expected_batch_height();
+        assert_eq!(recorder.replay_block(block(None), &mut NoopHook), Ok(None));
+        let recorded = recorder.state_hash_at(height).unwrap();
+
+        let (cfg, _tmpdir) = Config::temp_config();
+        let report = replayer(cfg)
+            .replay(vec![block(Some(recorded))], &mut NoopHook, true)
+            .unwrap();
+
+        assert_eq!(report.replayed_blocks, 1);
+        assert_eq!(report.divergences, vec![]);
+    }
+
+    #[test]
+    fn replay_reports_divergent_state_hash() {
+        let (cfg, _tmpdir) = Config::temp_config();
+        let replayer = replayer(cfg);
+        let height = replayer.message_routing.expected_batch_height();
+        let expected = CryptoHashOfPartialState::from(CryptoHash(vec![0; 32]));
+
+        let divergence = replayer
+            .replay_block(block(Some(expected.clone())), &mut NoopHook)
+            .unwrap()
+            .expect("Expected the replayed state to diverge");
+
+        assert_eq!(divergence.height, height);
+        assert_eq!(divergence.expected, expected);
+        assert_eq!(divergence.actual, replayer.state_hash_at(height).unwrap());
+    }
+}
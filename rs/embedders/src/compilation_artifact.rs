@@ -0,0 +1,324 @@
+//! The on-disk format of compiled Wasm modules.
+//!
+//! Compiling a module is expensive, so the compiled code is persisted to
+//! survive replica restarts and to avoid recompiling the same module on
+//! install and for queries. Loading compiled code that was produced for a
+//! different module, by a different instrumentation or by a different
+//! version of Wasmtime is unsafe, so every artifact records what it was
+//! produced from and is only loaded if that matches the current replica.
+//!
+//! An artifact is laid out as follows, integers are little-endian:
+//!
+//! | bytes | contents                                              |
+//! |-------|-------------------------------------------------------|
+//! | 4     | [`ARTIFACT_MAGIC`]                                    |
+//! | 4     | [`ARTIFACT_FORMAT_VERSION`]                           |
+//! | 32    | SHA-256 of all following bytes                        |
+//! | 4     | length of the header                                  |
+//! | ...   | [`ArtifactHeader`] encoded as JSON                    |
+//! | ...   | the code serialized by the embedder                   |
+use ic_crypto_sha::Sha256;
+use ic_wasm_types::InstructionCostOverrides;
+use ic_wasm_utils::instrumentation::INSTRUMENTATION_VERSION;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+/// The first bytes of every artifact.
+pub const ARTIFACT_MAGIC: [u8; 4] = *b"ICWA";
+
+/// The version of the layout of artifacts. It must be increased whenever the
+/// layout or the encoding of the header changes.
+pub const ARTIFACT_FORMAT_VERSION: u32 = 1;
+
+const CHECKSUM_LEN: usize = 32;
+const PREFIX_LEN: usize = ARTIFACT_MAGIC.len() + 4 + CHECKSUM_LEN;
+
+/// Describes what a compiled artifact was produced from. An artifact may
+/// only be loaded if its header equals the header expected by the replica.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactHeader {
+    /// The SHA-256 hash of the uninstrumented module.
+    pub module_hash: [u8; 32],
+    pub instrumentation_version: u32,
+    pub write_barriers: bool,
+    pub instruction_cost_overrides: InstructionCostOverrides,
+    pub wasmtime_version: String,
+}
+
+impl ArtifactHeader {
+    /// Returns the header of a module with the given hash compiled by this
+    /// replica with the given instrumentation settings.
+    pub fn new(
+        module_hash: [u8; 32],
+        write_barriers: bool,
+        instruction_cost_overrides: InstructionCostOverrides,
+    ) -> Self {
+        Self {
+            module_hash,
+            instrumentation_version: INSTRUMENTATION_VERSION,
+            write_barriers,
+            instruction_cost_overrides,
+            wasmtime_version: wasmtime_environ::VERSION.to_string(),
+        }
+    }
+}
+
+/// The reasons why an artifact cannot be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArtifactError {
+    Io(String),
+    Truncated,
+    BadMagic,
+    UnsupportedFormatVersion(u32),
+    ChecksumMismatch,
+    MalformedHeader(String),
+    /// The artifact is intact but was produced from something else than
+    /// expected. The field names the first difference.
+    Incompatible(&'static str),
+    /// The embedder failed to load the compiled code.
+    Deserialization(String),
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "Failed to access compiled artifact: {}", err),
+            Self::Truncated => write!(f, "Compiled artifact is truncated"),
+            Self::BadMagic => write!(f, "Not a compiled artifact"),
+            Self::UnsupportedFormatVersion(version) => write!(
+                f,
+                "Unsupported compiled artifact format version {}, expected {}",
+                version, ARTIFACT_FORMAT_VERSION
+            ),
+            Self::ChecksumMismatch => write!(f, "Checksum of compiled artifact does not match"),
+            Self::MalformedHeader(err) => {
+                write!(f, "Malformed header of compiled artifact: {}", err)
+            }
+            Self::Incompatible(field) => {
+                write!(f, "Compiled artifact has an incompatible {}", field)
+            }
+            Self::Deserialization(err) => {
+                write!(f, "Failed to load compiled artifact: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+/// A compiled module together with the header describing what it was
+/// produced from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledArtifact {
+    pub header: ArtifactHeader,
+    pub serialized_module: Vec<u8>,
+}
+
+impl CompiledArtifact {
+    pub fn encode(&self) -> Vec<u8> {
+        let header = serde_json::to_vec(&self.header).expect("failed to encode artifact header");
+        let mut body = Vec::with_capacity(4 + header.len() + self.serialized_module.len());
+        body.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        body.extend_from_slice(&header);
+        body.extend_from_slice(&self.serialized_module);
+
+        let mut bytes = Vec::with_capacity(PREFIX_LEN + body.len());
+        bytes.extend_from_slice(&ARTIFACT_MAGIC);
+        bytes.extend_from_slice(&ARTIFACT_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&Sha256::hash(&body));
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    /// Decodes an artifact and verifies its integrity. The result still has to
+    /// be checked with [`Self::check_compatible`] before it is loaded.
+    pub fn decode(bytes: &[u8]) -> Result<Self, ArtifactError> {
+        if bytes.len() < ARTIFACT_MAGIC.len() {
+            return Err(ArtifactError::Truncated);
+        }
+        let (magic, rest) = bytes.split_at(ARTIFACT_MAGIC.len());
+        if magic != ARTIFACT_MAGIC {
+            return Err(ArtifactError::BadMagic);
+        }
+        let (version, rest) = split_u32(rest)?;
+        if version != ARTIFACT_FORMAT_VERSION {
+            return Err(ArtifactError::UnsupportedFormatVersion(version));
+        }
+        if rest.len() < CHECKSUM_LEN {
+            return Err(ArtifactError::Truncated);
+        }
+        let (checksum, body) = rest.split_at(CHECKSUM_LEN);
+        if checksum != Sha256::hash(body) {
+            return Err(ArtifactError::ChecksumMismatch);
+        }
+        let (header_len, rest) = split_u32(body)?;
+        if rest.len() < header_len as usize {
+            return Err(ArtifactError::Truncated);
+        }
+        let (header, serialized_module) = rest.split_at(header_len as usize);
+        let header = serde_json::from_slice(header)
+            .map_err(|err| ArtifactError::MalformedHeader(err.to_string()))?;
+        Ok(Self {
+            header,
+            serialized_module: serialized_module.to_vec(),
+        })
+    }
+
+    /// Returns an error if the artifact was not produced from what `expected`
+    /// describes.
+    pub fn check_compatible(&self, expected: &ArtifactHeader) -> Result<(), ArtifactError> {
+        let header = &self.header;
+        if header.module_hash != expected.module_hash {
+            return Err(ArtifactError::Incompatible("module hash"));
+        }
+        if header.instrumentation_version != expected.instrumentation_version {
+            return Err(ArtifactError::Incompatible("instrumentation version"));
+        }
+        if header.write_barriers != expected.write_barriers {
+            return Err(ArtifactError::Incompatible("write barrier setting"));
+        }
+        if header.instruction_cost_overrides != expected.instruction_cost_overrides {
+            return Err(ArtifactError::Incompatible("instruction cost overrides"));
+        }
+        if header.wasmtime_version != expected.wasmtime_version {
+            return Err(ArtifactError::Incompatible("Wasmtime version"));
+        }
+        Ok(())
+    }
+
+    /// Writes the artifact to `path`. The file is replaced atomically, so
+    /// that a crash never leaves a partially written artifact behind.
+    pub fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(&self.encode())?;
+        file.as_file().sync_all()?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    pub fn read_from_file(path: &Path) -> Result<Self, ArtifactError> {
+        let bytes = std::fs::read(path).map_err(|err| ArtifactError::Io(err.to_string()))?;
+        Self::decode(&bytes)
+    }
+}
+
+fn split_u32(bytes: &[u8]) -> Result<(u32, &[u8]), ArtifactError> {
+    if bytes.len() < 4 {
+        return Err(ArtifactError::Truncated);
+    }
+    let (value, rest) = bytes.split_at(4);
+    Ok((u32::from_le_bytes(value.try_into().unwrap()), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_wasm_types::OpcodeClass;
+
+    fn artifact() -> CompiledArtifact {
+        CompiledArtifact {
+            header: ArtifactHeader::new(
+                [7; 32],
+                true,
+                InstructionCostOverrides::new().with_cost(OpcodeClass::Float, 3),
+            ),
+            serialized_module: vec![1, 2, 3, 4, 5],
+        }
+    }
+
+    #[test]
+    fn encoded_artifact_can_be_decoded() {
+        let artifact = artifact();
+        assert_eq!(CompiledArtifact::decode(&artifact.encode()), Ok(artifact));
+    }
+
+    #[test]
+    fn corrupted_artifact_is_rejected() {
+        let mut bytes = artifact().encode();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert_eq!(
+            CompiledArtifact::decode(&bytes),
+            Err(ArtifactError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn truncated_artifact_is_rejected() {
+        let bytes = artifact().encode();
+        assert_eq!(
+            CompiledArtifact::decode(&bytes[..PREFIX_LEN - 1]),
+            Err(ArtifactError::Truncated)
+        );
+        assert_eq!(
+            CompiledArtifact::decode(&bytes[..bytes.len() - 1]),
+            Err(ArtifactError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn artifact_of_other_format_version_is_rejected() {
+        let mut bytes = artifact().encode();
+        bytes[ARTIFACT_MAGIC.len()..ARTIFACT_MAGIC.len() + 4]
+            .copy_from_slice(&(ARTIFACT_FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            CompiledArtifact::decode(&bytes),
+            Err(ArtifactError::UnsupportedFormatVersion(
+                ARTIFACT_FORMAT_VERSION + 1
+            ))
+        );
+        assert_eq!(
+            CompiledArtifact::decode(b"\0asm"),
+            Err(ArtifactError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn incompatible_artifact_is_detected() {
+        let artifact = artifact();
+        assert_eq!(artifact.check_compatible(&artifact.header), Ok(()));
+
+        let mut expected = artifact.header.clone();
+        expected.module_hash = [8; 32];
+        assert_eq!(
+            artifact.check_compatible(&expected),
+            Err(ArtifactError::Incompatible("module hash"))
+        );
+
+        let mut expected = artifact.header.clone();
+        expected.instrumentation_version += 1;
+        assert_eq!(
+            artifact.check_compatible(&expected),
+            Err(ArtifactError::Incompatible("instrumentation version"))
+        );
+
+        let mut expected = artifact.header.clone();
+        expected.instruction_cost_overrides = InstructionCostOverrides::new();
+        assert_eq!(
+            artifact.check_compatible(&expected),
+            Err(ArtifactError::Incompatible("instruction cost overrides"))
+        );
+
+        let mut expected = artifact.header.clone();
+        expected.wasmtime_version = "0.0.0".to_string();
+        assert_eq!(
+            artifact.check_compatible(&expected),
+            Err(ArtifactError::Incompatible("Wasmtime version"))
+        );
+    }
+
+    #[test]
+    fn artifact_survives_round_trip_through_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("module.artifact");
+        let artifact = artifact();
+
+        artifact.write_to_file(&path).unwrap();
+
+        assert_eq!(CompiledArtifact::read_from_file(&path), Ok(artifact));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod compilation_artifact;
 pub mod cow_memory_creator;
 mod signal_handler;
 pub mod wasm_executor;
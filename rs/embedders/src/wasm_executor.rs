@@ -1,3 +1,4 @@
+use crate::compilation_artifact::{ArtifactError, ArtifactHeader, CompiledArtifact};
 use crate::cow_memory_creator::CowMemoryCreator;
 use crate::{
     wasmtime_embedder::WasmtimeInstance, WasmExecutionInput, WasmExecutionOutput, WasmtimeEmbedder,
@@ -5,37 +6,57 @@ use crate::{
 use ic_config::embedders::PersistenceType;
 use ic_cow_state::{CowMemoryManager, MappedState};
 use ic_interfaces::execution_environment::{
-    HypervisorError, HypervisorResult, InstanceStats, SystemApi,
+    HypervisorError, HypervisorResult, InstanceStats, SystemApi, TrapCode,
 };
-use ic_logger::ReplicaLogger;
-use ic_metrics::buckets::decimal_buckets_with_zero;
+use ic_logger::{debug, info, warn, ReplicaLogger};
+use ic_metrics::buckets::{decimal_buckets, decimal_buckets_with_zero};
 use ic_metrics::MetricsRegistry;
-use ic_replicated_state::{EmbedderCache, PageDelta, PageIndex};
+pub use ic_replicated_state::CompilationTrigger;
+use ic_replicated_state::{num_bytes_from, EmbedderCache, PageDelta, PageIndex};
 use ic_system_api::{ApiType, NonReplicatedQueryKind, SystemApiImpl, SystemStateAccessorDirect};
 use ic_types::{
     methods::{FuncRef, SystemMethod, WasmMethod},
-    NumInstructions,
+    CanisterId, NumInstructions,
 };
-use ic_wasm_types::BinaryEncodedWasm;
+use ic_wasm_types::{BinaryEncodedWasm, InstructionCostOverrides};
 use ic_wasm_utils::validation::WasmImportsDetails;
 use ic_wasm_utils::{
-    instrumentation::{instrument, InstructionCostTable},
-    validation::{validate_wasm_binary, WasmValidationLimits},
+    instrumentation::{
+        instrument, instrument_with_write_barriers, InstructionCostTable, WRITE_BARRIER_MAX_PAGES,
+        WRITE_BARRIER_PAGE_SIZE_LOG2,
+    },
+    validation::{validate_wasm_binary, WasmAllowLists, WasmValidationLimits},
 };
 use memory_tracker::DirtyPageTracking;
-use prometheus::{Histogram, IntCounter};
-use std::sync::Arc;
+use prometheus::{HistogramVec, IntCounter, IntCounterVec};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 struct WasmExecutorConfig {
     max_globals: usize,
     max_functions: usize,
+    allow_lists: WasmAllowLists,
+    write_barriers: bool,
+    compiled_artifacts_dir: Option<PathBuf>,
 }
 
 impl WasmExecutorConfig {
-    pub fn new(max_globals: usize, max_functions: usize) -> Self {
+    pub fn new(
+        max_globals: usize,
+        max_functions: usize,
+        allow_lists: WasmAllowLists,
+        write_barriers: bool,
+        compiled_artifacts_dir: Option<PathBuf>,
+    ) -> Self {
         Self {
             max_globals,
             max_functions,
+            allow_lists,
+            // The write barriers record pages of the size of the OS pages
+            // tracked by the memory tracker.
+            write_barriers: write_barriers
+                && *ic_sys::PAGE_SIZE == 1 << WRITE_BARRIER_PAGE_SIZE_LOG2,
+            compiled_artifacts_dir,
         }
     }
 }
@@ -54,7 +75,11 @@ struct WasmExecutorMetrics {
     imports_msg_cycles_refunded: IntCounter,
     imports_msg_cycles_accept: IntCounter,
     imports_mint_cycles: IntCounter,
-    compile: Histogram,
+    compile: HistogramVec,
+    compilation_cache: IntCounterVec,
+    compiled_module_size: HistogramVec,
+    traps: IntCounterVec,
+    failed_memory_grows: IntCounter,
 }
 
 impl WasmExecutorMetrics {
@@ -93,35 +118,103 @@ impl WasmExecutorMetrics {
                 "execution_wasm_imports_mint_cycles",
                 "The number of Wasm modules that import ic0.mint_cycles",
             ),
-            compile: metrics_registry.histogram(
+            compile: metrics_registry.histogram_vec(
                 "execution_wasm_compile",
-                "The duration of Wasm module compilation including validation and instrumentation",
+                "The duration of Wasm module compilation including validation and instrumentation, by trigger",
                 decimal_buckets_with_zero(-4, 1),
+                &["trigger"],
+            ),
+            compilation_cache: metrics_registry.int_counter_vec(
+                "execution_wasm_compilation_cache_total",
+                "The number of lookups of compiled Wasm modules, by trigger and result (hit or miss)",
+                &["trigger", "result"],
+            ),
+            compiled_module_size: metrics_registry.histogram_vec(
+                "execution_wasm_compiled_module_size_bytes",
+                "The size of the instrumented Wasm modules that were compiled, by trigger",
+                // 1KB, 2KB, 5KB, …, 100MB, 200MB, 500MB
+                decimal_buckets(3, 8),
+                &["trigger"],
+            ),
+            traps: metrics_registry.int_counter_vec(
+                "execution_wasm_traps_total",
+                "The number of executions that ended in a trap, by kind of trap",
+                &["trap_kind"],
+            ),
+            failed_memory_grows: metrics_registry.int_counter(
+                "execution_wasm_failed_memory_grows_total",
+                "The number of memory.grow calls that failed, see the log for the canisters",
             ),
         }
     }
 }
 
+/// Returns the kind of trap that `err` represents, used to label the trap
+/// metric, or `None` if the execution failed for another reason.
+///
+/// The canister is deliberately not a label, as it would make the number of
+/// time series unbounded. It is logged instead.
+fn trap_kind(err: &HypervisorError) -> Option<&'static str> {
+    match err {
+        HypervisorError::Trapped(trap_code) => Some(match trap_code {
+            TrapCode::HeapOutOfBounds => "heap_out_of_bounds",
+            TrapCode::StableMemoryOutOfBounds => "stable_memory_out_of_bounds",
+            TrapCode::StableMemoryTooBigFor32Bit => "stable_memory_too_big_for_32_bit",
+            TrapCode::IntegerDivByZero => "integer_div_by_zero",
+            TrapCode::Unreachable => "unreachable",
+            TrapCode::TableOutOfBounds => "table_out_of_bounds",
+            TrapCode::CyclesAmountTooBigFor64Bit => "cycles_amount_too_big_for_64_bit",
+            TrapCode::Other => "other",
+        }),
+        HypervisorError::StackOverflow => Some("stack_overflow"),
+        HypervisorError::CalledTrap(_) => Some("called_trap"),
+        _ => None,
+    }
+}
+
+/// A module compiled by the `WasmExecutor`, together with the instruction
+/// cost overrides it was instrumented with and whether it contains write
+/// barriers.
+struct CompiledModule {
+    instruction_cost_overrides: InstructionCostOverrides,
+    write_barriers: bool,
+    embedder_cache: EmbedderCache,
+}
+
 /// An executor that can process any message (query or not).
 pub struct WasmExecutor {
     wasm_embedder: WasmtimeEmbedder,
     config: WasmExecutorConfig,
     metrics: WasmExecutorMetrics,
+    // The overrides applied when compiling modules for execution. Modules that
+    // were compiled with different overrides are recompiled before execution.
+    instruction_cost_overrides: RwLock<InstructionCostOverrides>,
     log: ReplicaLogger,
 }
 
 impl WasmExecutor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         wasm_embedder: WasmtimeEmbedder,
         max_globals: usize,
         max_functions: usize,
+        allow_lists: WasmAllowLists,
+        write_barriers: bool,
+        compiled_artifacts_dir: Option<PathBuf>,
         metrics_registry: &MetricsRegistry,
         log: ReplicaLogger,
     ) -> Self {
         Self {
             wasm_embedder,
-            config: WasmExecutorConfig::new(max_globals, max_functions),
+            config: WasmExecutorConfig::new(
+                max_globals,
+                max_functions,
+                allow_lists,
+                write_barriers,
+                compiled_artifacts_dir,
+            ),
             metrics: WasmExecutorMetrics::new(metrics_registry),
+            instruction_cost_overrides: RwLock::new(InstructionCostOverrides::default()),
             log,
         }
     }
@@ -150,17 +243,144 @@ impl WasmExecutor {
         }
     }
 
+    /// Returns the instruction cost overrides applied when compiling modules
+    /// for execution.
+    pub fn instruction_cost_overrides(&self) -> InstructionCostOverrides {
+        self.instruction_cost_overrides.read().unwrap().clone()
+    }
+
+    /// Sets the instruction cost overrides applied when compiling modules for
+    /// execution.
+    pub fn set_instruction_cost_overrides(&self, overrides: InstructionCostOverrides) {
+        *self.instruction_cost_overrides.write().unwrap() = overrides;
+    }
+
+    /// Records a lookup of a compiled module, e.g., in the embedder cache of
+    /// the execution state or in the compilation cache of the query handler.
+    pub fn observe_compilation_cache_lookup(&self, trigger: CompilationTrigger, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.metrics
+            .compilation_cache
+            .with_label_values(&[trigger.as_str(), result])
+            .inc();
+    }
+
+    /// Records the trap that ended an execution of `canister_id`, if any.
+    fn observe_trap(&self, canister_id: CanisterId, err: &HypervisorError) {
+        if let Some(kind) = trap_kind(err) {
+            self.metrics.traps.with_label_values(&[kind]).inc();
+            debug!(self.log, "Canister {} trapped: {}", canister_id, kind);
+        }
+    }
+
+    /// Records the number of `memory.grow` calls of an execution of
+    /// `canister_id` that failed, in the aggregate counter and, per canister,
+    /// in the log.
+    fn observe_failed_memory_grows(&self, canister_id: CanisterId, failed_memory_grows: u64) {
+        if failed_memory_grows > 0 {
+            self.metrics.failed_memory_grows.inc_by(failed_memory_grows);
+            info!(
+                self.log,
+                "Canister {}: {} memory.grow calls failed", canister_id, failed_memory_grows
+            );
+        }
+    }
+
+    /// Validates, instruments and compiles the given Wasm binary, using the
+    /// given instruction cost overrides.
+    ///
+    /// If a directory for compiled artifacts is configured, a compatible
+    /// artifact of the module is loaded from there instead, and newly compiled
+    /// modules are persisted there.
     pub fn compile(
         &self,
         wasm_binary: &BinaryEncodedWasm,
         persistence_type: PersistenceType,
+        instruction_cost_overrides: &InstructionCostOverrides,
+        trigger: CompilationTrigger,
     ) -> HypervisorResult<EmbedderCache> {
-        let _timer = self.metrics.compile.start_timer();
+        let artifact_path = match &self.config.compiled_artifacts_dir {
+            Some(dir) => dir.join(hex::encode(wasm_binary.hash_sha256())),
+            None => {
+                return self.compile_module(
+                    wasm_binary,
+                    persistence_type,
+                    instruction_cost_overrides,
+                    trigger,
+                )
+            }
+        };
+        if artifact_path.exists() {
+            match CompiledArtifact::read_from_file(&artifact_path).and_then(|artifact| {
+                self.load_compiled_artifact(
+                    wasm_binary,
+                    persistence_type,
+                    instruction_cost_overrides,
+                    &artifact,
+                )
+            }) {
+                Ok(embedder_cache) => return Ok(embedder_cache),
+                Err(err) => warn!(
+                    self.log,
+                    "Failed to load compiled module from {}: {}",
+                    artifact_path.display(),
+                    err
+                ),
+            }
+        }
+        let embedder_cache = self.compile_module(
+            wasm_binary,
+            persistence_type,
+            instruction_cost_overrides,
+            trigger,
+        )?;
+        if let Err(err) =
+            self.persist_compiled_artifact(wasm_binary, &embedder_cache, &artifact_path)
+        {
+            warn!(
+                self.log,
+                "Failed to persist compiled module to {}: {}",
+                artifact_path.display(),
+                err
+            );
+        }
+        Ok(embedder_cache)
+    }
+
+    fn persist_compiled_artifact(
+        &self,
+        wasm_binary: &BinaryEncodedWasm,
+        embedder_cache: &EmbedderCache,
+        path: &Path,
+    ) -> Result<(), String> {
+        let artifact = self
+            .compiled_artifact(wasm_binary, embedder_cache)
+            .map_err(|err| err.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        artifact.write_to_file(path).map_err(|err| err.to_string())
+    }
+
+    fn compile_module(
+        &self,
+        wasm_binary: &BinaryEncodedWasm,
+        persistence_type: PersistenceType,
+        instruction_cost_overrides: &InstructionCostOverrides,
+        trigger: CompilationTrigger,
+    ) -> HypervisorResult<EmbedderCache> {
+        let _timer = self
+            .metrics
+            .compile
+            .with_label_values(&[trigger.as_str()])
+            .start_timer();
         validate_wasm_binary(
             wasm_binary,
             WasmValidationLimits {
                 max_globals: self.config.max_globals,
                 max_functions: self.config.max_functions,
+                allow_lists: self.config.allow_lists.clone(),
+                ..Default::default()
             },
         )
         .map_err(HypervisorError::from)
@@ -171,9 +391,80 @@ impl WasmExecutor {
                     .inc_by(details.reserved_exports as u64);
             }
             self.observe_metrics(&details.imports_details);
-            instrument(&wasm_binary, &InstructionCostTable::new()).map_err(HypervisorError::from)
+            let instruction_cost_table =
+                InstructionCostTable::new().with_overrides(instruction_cost_overrides.clone());
+            if self.config.write_barriers {
+                instrument_with_write_barriers(&wasm_binary, &instruction_cost_table)
+            } else {
+                instrument(&wasm_binary, &instruction_cost_table)
+            }
+            .map_err(HypervisorError::from)
+        })
+        .and_then(|output| {
+            self.metrics
+                .compiled_module_size
+                .with_label_values(&[trigger.as_str()])
+                .observe(output.binary.len() as f64);
+            self.wasm_embedder.compile(persistence_type, &output.binary)
+        })
+        .map(|embedder_cache| {
+            EmbedderCache::new(CompiledModule {
+                instruction_cost_overrides: instruction_cost_overrides.clone(),
+                write_barriers: self.config.write_barriers,
+                embedder_cache,
+            })
         })
-        .and_then(|output| self.wasm_embedder.compile(persistence_type, &output.binary))
+    }
+
+    /// Returns the artifact persisting `cache`, which [`Self::compile`]
+    /// returned for `wasm_binary`.
+    fn compiled_artifact(
+        &self,
+        wasm_binary: &BinaryEncodedWasm,
+        cache: &EmbedderCache,
+    ) -> HypervisorResult<CompiledArtifact> {
+        let compiled_module = cache
+            .downcast::<CompiledModule>()
+            .expect("incompatible embedder cache, expected CompiledModule");
+        let serialized_module = self
+            .wasm_embedder
+            .serialize(&compiled_module.embedder_cache)?;
+        Ok(CompiledArtifact {
+            header: ArtifactHeader::new(
+                wasm_binary.hash_sha256(),
+                compiled_module.write_barriers,
+                compiled_module.instruction_cost_overrides.clone(),
+            ),
+            serialized_module,
+        })
+    }
+
+    /// Loads a module of `wasm_binary` from an artifact returned by
+    /// [`Self::compiled_artifact`] instead of compiling it. Fails if the
+    /// artifact was produced for another module or by a replica instrumenting
+    /// or compiling modules differently, in which case the module has to be
+    /// compiled.
+    fn load_compiled_artifact(
+        &self,
+        wasm_binary: &BinaryEncodedWasm,
+        persistence_type: PersistenceType,
+        instruction_cost_overrides: &InstructionCostOverrides,
+        artifact: &CompiledArtifact,
+    ) -> Result<EmbedderCache, ArtifactError> {
+        artifact.check_compatible(&ArtifactHeader::new(
+            wasm_binary.hash_sha256(),
+            self.config.write_barriers,
+            instruction_cost_overrides.clone(),
+        ))?;
+        let embedder_cache = self
+            .wasm_embedder
+            .deserialize(persistence_type, &artifact.serialized_module)
+            .map_err(|err| ArtifactError::Deserialization(err.to_string()))?;
+        Ok(EmbedderCache::new(CompiledModule {
+            instruction_cost_overrides: instruction_cost_overrides.clone(),
+            write_barriers: self.config.write_barriers,
+            embedder_cache,
+        }))
     }
 
     pub fn process(
@@ -191,14 +482,33 @@ impl WasmExecutor {
         let canister_id = system_state.canister_id;
         let system_state_accessor =
             SystemStateAccessorDirect::new(system_state, cycles_account_manager);
-        if execution_state.embedder_cache.is_none() {
+        let instruction_cost_overrides = self.instruction_cost_overrides();
+        let is_compiled = execution_state
+            .embedder_cache
+            .as_ref()
+            .and_then(|cache| cache.downcast::<CompiledModule>())
+            .map_or(false, |module| {
+                module.instruction_cost_overrides == instruction_cost_overrides
+            });
+        // The first compilation after an install or upgrade is attributed to
+        // it rather than to the execution of the message.
+        let trigger = execution_state
+            .pending_compilation_trigger
+            .take()
+            .unwrap_or(CompilationTrigger::Execution);
+        self.observe_compilation_cache_lookup(trigger, is_compiled);
+        if !is_compiled {
             // The wasm_binary stored in the `ExecutionState` is not
             // instrumented so instrument it before compiling. Further, due to
             // IC upgrades, it is possible that the `validate_wasm_binary()`
-            // function has changed, so also validate the binary.
+            // function has changed, so also validate the binary. The module
+            // is also recompiled if the instruction cost overrides changed
+            // since it was compiled.
             match self.compile(
                 &execution_state.wasm_binary,
                 execution_state.persistence_type(),
+                &instruction_cost_overrides,
+                trigger,
             ) {
                 Ok(cache) => execution_state.embedder_cache = Some(cache),
                 Err(err) => {
@@ -219,7 +529,10 @@ impl WasmExecutor {
         // TODO(EXC-176): we should combine this with the hypervisor so that
         // we make the decision of whether or not to commit modifications in
         // a single place instead.
-        let memory_creator = if execution_state.cow_mem_mgr.is_valid() {
+        // In-memory persistence bypasses the copy-on-write state entirely.
+        let use_cow_memory = execution_state.cow_mem_mgr.is_valid()
+            && !self.wasm_embedder.uses_in_memory_persistence();
+        let memory_creator = if use_cow_memory {
             match &func_ref {
                 FuncRef::Method(WasmMethod::Update(_))
                 | FuncRef::Method(WasmMethod::System(_))
@@ -237,19 +550,34 @@ impl WasmExecutor {
 
         let commit_dirty_pages = func_ref.to_commit();
 
+        let compiled_module = execution_state
+            .embedder_cache
+            .as_ref()
+            .and_then(|cache| cache.downcast::<CompiledModule>())
+            .expect("the module was compiled above");
+
+        // Write barriers are used for heaps whose pages all fit into the
+        // write barrier bitmap. If the heap grows beyond that during the
+        // execution, all of its pages are considered dirty.
+        let heap_os_pages =
+            num_bytes_from(execution_state.heap_size).get() / *ic_sys::PAGE_SIZE as u64;
         let dirty_page_tracking = match &api_type {
             ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery {
                 query_kind: NonReplicatedQueryKind::Pure,
                 ..
             }
             | ApiType::InspectMessage { .. } => DirtyPageTracking::Ignore,
+            _ if compiled_module.write_barriers && heap_os_pages <= WRITE_BARRIER_MAX_PAGES => {
+                DirtyPageTracking::Barrier
+            }
             _ => DirtyPageTracking::Track,
         };
 
         let mut instance = self.wasm_embedder.new_instance(
             canister_id,
-            &execution_state.embedder_cache.as_ref().unwrap(),
+            &compiled_module.embedder_cache,
             &execution_state.exported_globals,
             execution_state.heap_size,
             memory_creator,
@@ -281,8 +609,8 @@ impl WasmExecutor {
             let run_result = instance.run(&mut system_api, func_ref);
             match run_result {
                 Ok(run_result) => {
-                    if dirty_page_tracking == DirtyPageTracking::Track {
-                        if execution_state.cow_mem_mgr.is_valid() && commit_dirty_pages {
+                    if dirty_page_tracking != DirtyPageTracking::Ignore {
+                        if use_cow_memory && commit_dirty_pages {
                             let mapped_state = execution_state.mapped_state.take();
                             let pages: Vec<u64> =
                                 run_result.dirty_pages.iter().map(|p| p.get()).collect();
@@ -299,6 +627,7 @@ impl WasmExecutor {
                     system_api.set_execution_error(err);
                 }
             };
+            self.observe_failed_memory_grows(canister_id, system_api.failed_memory_grows());
             (
                 system_api.take_execution_result(),
                 instance.get_num_instructions(),
@@ -306,6 +635,9 @@ impl WasmExecutor {
                 instance.get_stats(),
             )
         };
+        if let Err(err) = &execution_result {
+            self.observe_trap(canister_id, err);
+        }
 
         WasmExecutionOutput {
             wasm_result: execution_result,
@@ -317,7 +649,15 @@ impl WasmExecutor {
     }
 
     pub fn compile_count_for_testing(&self) -> u64 {
-        self.metrics.compile.get_sample_count()
+        CompilationTrigger::ALL
+            .iter()
+            .map(|trigger| {
+                self.metrics
+                    .compile
+                    .with_label_values(&[trigger.as_str()])
+                    .get_sample_count()
+            })
+            .sum()
     }
 }
 
@@ -5,6 +5,8 @@ pub use host_memory::WasmtimeMemoryCreator;
 
 mod signal_stack;
 mod system_api;
+mod wasmtime_api;
+mod write_barrier;
 
 #[cfg(test)]
 mod wasmtime_embedder_tests;
@@ -16,11 +18,11 @@ use ic_config::embedders::{Config, PersistenceType};
 use ic_interfaces::execution_environment::{
     HypervisorError, HypervisorResult, InstanceStats, SystemApi, TrapCode,
 };
-use ic_logger::{debug, ReplicaLogger};
+use ic_logger::{debug, MessageContext, ReplicaLogger, WithMessageContext};
 use ic_replicated_state::{EmbedderCache, Global, NumWasmPages, PageIndex, PageMap};
 use ic_types::{
     methods::{FuncRef, WasmMethod},
-    CanisterId, NumInstructions,
+    CanisterId, InstructionBudget, NumInstructions,
 };
 use ic_wasm_types::{BinaryEncodedWasm, WasmEngineError};
 use memory_tracker::{DirtyPageTracking, SigsegvMemoryTracker};
@@ -30,14 +32,16 @@ use std::convert::TryFrom;
 use std::rc::Rc;
 use std::sync::Arc;
 use system_api::SystemApiHandle;
-use wasmtime::{unix::StoreExt, Memory, Mutability, Store, Val, ValType};
+use wasmtime::{unix::StoreExt, Val};
+use wasmtime_api::{Instance, InstanceGlobal, Memory, Store};
+use write_barrier::WriteBarrierBitmap;
 
 fn trap_to_error(err: anyhow::Error) -> HypervisorError {
     let message = format!("{}", err);
     let re_signature_mismatch =
         regex::Regex::new("expected \\d+ arguments, got \\d+").expect("signature mismatch regex");
     if message.contains("wasm trap: call stack exhausted") {
-        HypervisorError::Trapped(TrapCode::StackOverflow)
+        HypervisorError::StackOverflow
     } else if message.contains("wasm trap: out of bounds memory access") {
         HypervisorError::Trapped(TrapCode::HeapOutOfBounds)
     } else if message.contains("wasm trap: integer divide by zero") {
@@ -59,28 +63,88 @@ fn trap_to_error(err: anyhow::Error) -> HypervisorError {
 pub struct WasmtimeEmbedder {
     log: ReplicaLogger,
     max_wasm_stack_size: usize,
+    static_memory_guard_size: u64,
+    dynamic_memory_guard_size: u64,
+    /// Whether the config selects `PersistenceType::InMemory`, which takes
+    /// precedence over the persistence type of the execution state.
+    in_memory: bool,
 }
 
 impl WasmtimeEmbedder {
     pub fn new(config: Config, log: ReplicaLogger) -> Self {
         let Config {
+            persistence_type,
             max_wasm_stack_size,
+            static_memory_guard_size,
+            dynamic_memory_guard_size,
             ..
         } = config;
 
         WasmtimeEmbedder {
             log,
             max_wasm_stack_size,
+            static_memory_guard_size,
+            dynamic_memory_guard_size,
+            in_memory: persistence_type == PersistenceType::InMemory,
         }
     }
 
+    /// Returns true if memories are persisted with
+    /// `PersistenceType::InMemory`, regardless of the persistence type passed
+    /// to [`Self::compile`].
+    pub fn uses_in_memory_persistence(&self) -> bool {
+        self.in_memory
+    }
+
     pub fn compile(
         &self,
         persistence_type: PersistenceType,
         wasm_binary: &BinaryEncodedWasm,
     ) -> HypervisorResult<EmbedderCache> {
+        let (engine, cached_mem_creator) = self.engine(persistence_type)?;
+        let module = wasmtime::Module::new(&engine, wasm_binary.as_slice()).map_err(|_| {
+            HypervisorError::WasmEngineError(WasmEngineError::FailedToInstantiateModule)
+        })?;
+        Ok(EmbedderCache::new((module, cached_mem_creator)))
+    }
+
+    /// Serializes the compiled code of a module returned by [`Self::compile`]
+    /// or [`Self::deserialize`].
+    pub fn serialize(&self, cache: &EmbedderCache) -> HypervisorResult<Vec<u8>> {
+        let (module, _) = cache
+            .downcast::<(wasmtime::Module, Option<CowMemoryCreatorProxy>)>()
+            .expect("incompatible embedder cache, expected BinaryEncodedWasm");
+        module
+            .serialize()
+            .map_err(|_| HypervisorError::WasmEngineError(WasmEngineError::FailedToSerializeModule))
+    }
+
+    /// Loads a module serialized with [`Self::serialize`] without compiling
+    /// it again. The module must have been compiled for the same
+    /// `persistence_type`.
+    pub fn deserialize(
+        &self,
+        persistence_type: PersistenceType,
+        serialized_module: &[u8],
+    ) -> HypervisorResult<EmbedderCache> {
+        let (engine, cached_mem_creator) = self.engine(persistence_type)?;
+        let module = wasmtime::Module::deserialize(&engine, serialized_module).map_err(|_| {
+            HypervisorError::WasmEngineError(WasmEngineError::FailedToDeserializeModule)
+        })?;
+        Ok(EmbedderCache::new((module, cached_mem_creator)))
+    }
+
+    fn engine(
+        &self,
+        persistence_type: PersistenceType,
+    ) -> HypervisorResult<(wasmtime::Engine, Option<CowMemoryCreatorProxy>)> {
         let mut config = wasmtime::Config::default();
         ic_wasm_utils::ensure_determinism(&mut config);
+        let persistence_type = if self.in_memory {
+            PersistenceType::InMemory
+        } else {
+            persistence_type
+        };
         let cached_mem_creator = match persistence_type {
             PersistenceType::Sigsegv => {
                 let raw_creator = MmapMemoryCreator {};
@@ -88,12 +152,15 @@ impl WasmtimeEmbedder {
                 config.with_host_memory(mem_creator);
                 None
             }
-            _ /*Pagemap*/ => {
-                let raw_creator = CowMemoryCreatorProxy::new(Arc::new(CowMemoryCreator::new_uninitialized()));
+            PersistenceType::Pagemap => {
+                let raw_creator =
+                    CowMemoryCreatorProxy::new(Arc::new(CowMemoryCreator::new_uninitialized()));
                 let mem_creator = Arc::new(WasmtimeMemoryCreator::new(raw_creator.clone()));
                 config.with_host_memory(mem_creator);
                 Some(raw_creator)
             }
+            // Wasmtime allocates the memories itself.
+            PersistenceType::InMemory => None,
         };
 
         config
@@ -102,7 +169,11 @@ impl WasmtimeEmbedder {
             // the memory is always static.
             .static_memory_maximum_size(
                 wasmtime_environ::WASM_PAGE_SIZE as u64 * wasmtime_environ::WASM_MAX_PAGES as u64,
-            );
+            )
+            // unmapped regions after linear memories that turn out-of-bounds
+            // accesses into traps without explicit bounds checks.
+            .static_memory_guard_size(self.static_memory_guard_size)
+            .dynamic_memory_guard_size(self.dynamic_memory_guard_size);
 
         // Wasmtime requires that the async stack is larger than the Wasm stack.
         // Since both `config.async_stack_size()` and `config.max_wasm_stack()` check
@@ -143,10 +214,7 @@ impl WasmtimeEmbedder {
         let engine = wasmtime::Engine::new(&config).map_err(|_| {
             HypervisorError::WasmEngineError(WasmEngineError::FailedToInitializeEngine)
         })?;
-        let module = wasmtime::Module::new(&engine, wasm_binary.as_slice()).map_err(|_| {
-            HypervisorError::WasmEngineError(WasmEngineError::FailedToInstantiateModule)
-        })?;
-        Ok(EmbedderCache::new((module, cached_mem_creator)))
+        Ok((engine, cached_mem_creator))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -171,6 +239,11 @@ impl WasmtimeEmbedder {
                     and both happen if persistence type is Pagemap"
         );
 
+        // All log entries of the instance, including those reporting traps and
+        // failed system calls, refer to the canister.
+        let log = self
+            .log
+            .with_message_context(&MessageContext::new().with_canister_id(canister_id));
         let store = Store::new(&module.engine());
         let system_api_handle = SystemApiHandle::new();
         let canister_num_instructions_global = Rc::new(RefCell::new(None));
@@ -179,8 +252,8 @@ impl WasmtimeEmbedder {
         // because wasmtime::Global internally references Store and it would
         // create a cyclic reference. Since Store holds both the global and our
         // syscalls, syscalls won't outlive the global
-        let linker: wasmtime::Linker = system_api::syscalls(
-            self.log.clone(),
+        let linker = system_api::syscalls(
+            log.clone(),
             canister_id,
             &store,
             system_api_handle.clone(),
@@ -210,12 +283,16 @@ impl WasmtimeEmbedder {
                 linker
                     .instantiate(&module)
                     .expect("failed to create Wasmtime instance"),
-                PersistenceType::Sigsegv,
+                if self.in_memory {
+                    PersistenceType::InMemory
+                } else {
+                    PersistenceType::Sigsegv
+                },
             )
         };
 
         // in wasmtime only exported globals are accessible
-        let instance_globals: Vec<_> = instance.exports().filter_map(|e| e.into_global()).collect();
+        let instance_globals = wasmtime_api::exported_globals(&instance);
 
         if exported_globals.len() > instance_globals.len() {
             panic!(
@@ -231,31 +308,27 @@ impl WasmtimeEmbedder {
             .enumerate()
             .zip(instance_globals.iter())
         {
-            if instance_global.ty().mutability() == Mutability::Var {
-                instance_global
-                    .set(match v {
-                        Global::I32(val) => Val::I32(*val),
-                        Global::I64(val) => Val::I64(*val),
-                        Global::F32(val) => Val::F32((val).to_bits()),
-                        Global::F64(val) => Val::F64((val).to_bits()),
-                    })
-                    .unwrap_or_else(|e| {
-                        let v = match v {
-                            Global::I32(val) => (val).to_string(),
-                            Global::I64(val) => (val).to_string(),
-                            Global::F32(val) => (val).to_string(),
-                            Global::F64(val) => (val).to_string(),
-                        };
-                        panic!("error while setting exported global {} to {}: {}", ix, v, e)
-                    })
+            if wasmtime_api::is_mutable(instance_global) {
+                wasmtime_api::set_global(instance_global, v).unwrap_or_else(|e| {
+                    let v = match v {
+                        Global::I32(val) => (val).to_string(),
+                        Global::I64(val) => (val).to_string(),
+                        Global::F32(val) => (val).to_string(),
+                        Global::F64(val) => (val).to_string(),
+                    };
+                    panic!("error while setting exported global {} to {}: {}", ix, v, e)
+                })
             } else {
-                debug!(
-                    self.log,
-                    "skipping initialization of immutable global {}", ix
-                );
+                debug!(log, "skipping initialization of immutable global {}", ix);
             }
         }
 
+        let write_barrier_bitmap = WriteBarrierBitmap::lookup(|name| instance.get_global(name));
+        assert!(
+            dirty_page_tracking != DirtyPageTracking::Barrier || write_barrier_bitmap.is_some(),
+            "dirty page tracking with write barriers requires a module instrumented with write barriers"
+        );
+
         let instance_memory = instance
             .get_memory("memory")
             .map(|instance_memory| {
@@ -276,20 +349,29 @@ impl WasmtimeEmbedder {
             .map(Arc::new);
 
         // if `wasmtime::Instance` does not have memory we don't need a memory tracker
-        let memory_tracker = instance_memory.as_ref().map(|instance_memory| {
-            let page_map = match persistence_type {
-                PersistenceType::Sigsegv => page_map,
-                PersistenceType::Pagemap => None,
-            };
-            sigsegv_memory_tracker(
-                persistence_type,
-                Arc::downgrade(instance_memory),
-                &store,
-                page_map,
-                self.log.clone(),
-                dirty_page_tracking,
-            )
-        });
+        let (memory_tracker, in_memory_page_map) = match (persistence_type, &instance_memory) {
+            (_, None) => (None, None),
+            (PersistenceType::InMemory, Some(instance_memory)) => {
+                let page_map = page_map.unwrap_or_default();
+                copy_page_map_to_memory(&page_map, instance_memory);
+                (None, Some(page_map))
+            }
+            (_, Some(instance_memory)) => {
+                let page_map = match persistence_type {
+                    PersistenceType::Sigsegv => page_map,
+                    PersistenceType::Pagemap | PersistenceType::InMemory => None,
+                };
+                let memory_tracker = sigsegv_memory_tracker(
+                    persistence_type,
+                    Arc::downgrade(instance_memory),
+                    &store,
+                    page_map,
+                    log.clone(),
+                    dirty_page_tracking,
+                );
+                (Some(memory_tracker), None)
+            }
+        };
         let signal_stack = WasmtimeSignalStack::new();
 
         // canister_num_instructions_global is an Option because some wasmtime tests
@@ -303,9 +385,12 @@ impl WasmtimeEmbedder {
             instance,
             instance_memory,
             memory_tracker,
+            in_memory_page_map,
             signal_stack,
             canister_num_instructions_global,
-            log: self.log.clone(),
+            dirty_page_tracking,
+            write_barrier_bitmap,
+            log,
             instance_stats: InstanceStats {
                 accessed_pages: 0,
                 dirty_pages: 0,
@@ -314,10 +399,23 @@ impl WasmtimeEmbedder {
     }
 }
 
+/// Initializes a memory allocated by Wasmtime with the contents of
+/// `page_map`. Pages beyond the end of the memory are ignored.
+fn copy_page_map_to_memory(page_map: &PageMap, memory: &Memory) {
+    // SAFETY: no Wasm code runs on the instance while its memory is written.
+    let data = unsafe { wasmtime_api::memory_data_mut(memory) };
+    for (index, contents) in page_map.host_pages_iter() {
+        let start = index.get() as usize * contents.len();
+        if let Some(page) = data.get_mut(start..start + contents.len()) {
+            page.copy_from_slice(contents);
+        }
+    }
+}
+
 fn sigsegv_memory_tracker(
     persistence_type: PersistenceType,
-    instance_memory: std::sync::Weak<wasmtime::Memory>,
-    store: &wasmtime::Store,
+    instance_memory: std::sync::Weak<Memory>,
+    store: &Store,
     page_map: Option<PageMap>,
     log: ReplicaLogger,
     dirty_page_tracking: DirtyPageTracking,
@@ -380,15 +478,20 @@ fn sigsegv_memory_tracker(
 /// Encapsulates a Wasmtime instance on the Internet Computer.
 pub struct WasmtimeInstance {
     system_api_handle: SystemApiHandle,
-    instance: wasmtime::Instance,
+    instance: Instance,
     // if instance memory exists we need to keep the Arc alive as long as the
     // Instance is alive. This is because we are sending the Weak pointer to a
     // signal handler.
     #[allow(dead_code)]
-    instance_memory: Option<Arc<wasmtime::Memory>>,
+    instance_memory: Option<Arc<Memory>>,
     memory_tracker: Option<Rc<SigsegvMemoryTracker>>,
+    /// The page map the memory was initialized from, if the persistence type
+    /// is `InMemory`. Dirty pages are found by comparing against it.
+    in_memory_page_map: Option<PageMap>,
     signal_stack: WasmtimeSignalStack,
-    canister_num_instructions_global: Rc<RefCell<Option<wasmtime::Global>>>,
+    canister_num_instructions_global: Rc<RefCell<Option<InstanceGlobal>>>,
+    dirty_page_tracking: DirtyPageTracking,
+    write_barrier_bitmap: Option<WriteBarrierBitmap>,
     log: ReplicaLogger,
     instance_stats: InstanceStats,
 }
@@ -411,6 +514,12 @@ impl WasmtimeInstance {
     }
 
     fn dirty_pages(&self) -> Vec<PageIndex> {
+        if let (DirtyPageTracking::Barrier, Some(bitmap)) =
+            (self.dirty_page_tracking, self.write_barrier_bitmap.as_ref())
+        {
+            let heap_pages = self.memory().map_or(0, |mem| mem.data_size()) / *ic_sys::PAGE_SIZE;
+            return bitmap.dirty_pages(heap_pages as u64);
+        }
         if let Some(memory_tracker) = self.memory_tracker.as_ref() {
             let speculatively_dirty_pages = memory_tracker.take_speculatively_dirty_pages();
             let dirty_pages = memory_tracker.take_dirty_pages();
@@ -422,6 +531,20 @@ impl WasmtimeInstance {
                         .filter_map(|p| memory_tracker.validate_speculatively_dirty_page(p)),
                 )
                 .collect::<Vec<PageIndex>>()
+        } else if let (Some(page_map), Ok(memory)) =
+            (self.in_memory_page_map.as_ref(), self.memory())
+        {
+            if self.dirty_page_tracking == DirtyPageTracking::Ignore {
+                return vec![];
+            }
+            // SAFETY: the instance does not run while its memory is read.
+            let data = unsafe { wasmtime_api::memory_data(&memory) };
+            data.chunks(*ic_sys::PAGE_SIZE)
+                .enumerate()
+                .map(|(index, contents)| (PageIndex::from(index as u64), contents))
+                .filter(|(index, contents)| page_map.get_page(*index) != *contents)
+                .map(|(index, _)| index)
+                .collect()
         } else {
             debug!(
                 self.log,
@@ -496,7 +619,15 @@ impl WasmtimeInstance {
                 exported_globals: self.get_exported_globals(),
                 dirty_pages,
             }),
-            Err(err) => Err(err),
+            Err(err) => {
+                if let HypervisorError::Trapped(trap_code) = &err {
+                    debug!(
+                        self.log,
+                        "Canister trapped with {} while executing {:?}", trap_code, func_ref
+                    );
+                }
+                Err(err)
+            }
         }
     }
 
@@ -504,7 +635,8 @@ impl WasmtimeInstance {
     pub fn set_num_instructions(&mut self, num_instructions: NumInstructions) {
         match &*self.canister_num_instructions_global.borrow_mut() {
             Some(num_instructions_global) => {
-                match num_instructions_global.set(Val::I64(num_instructions.get() as i64)) {
+                let counter = InstructionBudget::new(num_instructions).to_wasm_counter();
+                match wasmtime_api::set_i64(num_instructions_global, counter) {
                     Ok(_) => (),
                     Err(e) => panic!("couldn't set the num_instructions counter: {:?}", e),
                 }
@@ -516,11 +648,9 @@ impl WasmtimeInstance {
     /// Returns the number of instructions left.
     pub fn get_num_instructions(&self) -> NumInstructions {
         match &*self.canister_num_instructions_global.borrow() {
-            Some(num_instructions) => match num_instructions.get() {
-                Val::I64(num_instructions_i64) => {
-                    NumInstructions::from(num_instructions_i64.max(0) as u64)
-                }
-                _ => panic!("invalid num_instructions counter type"),
+            Some(num_instructions) => match wasmtime_api::get_i64(num_instructions) {
+                Some(counter) => InstructionBudget::from_wasm_counter(counter).remaining(),
+                None => panic!("invalid num_instructions counter type"),
             },
             None => panic!("couldn't find the num_instructions counter in the canister globals"),
         }
@@ -533,16 +663,9 @@ impl WasmtimeInstance {
 
     /// Returns a list of exported globals.
     pub fn get_exported_globals(&self) -> Vec<Global> {
-        self.instance
-            .exports()
-            .filter_map(|e| e.into_global())
-            .map(|g| match g.ty().content() {
-                ValType::I32 => Global::I32(g.get().i32().expect("global i32")),
-                ValType::I64 => Global::I64(g.get().i64().expect("global i64")),
-                ValType::F32 => Global::F32(g.get().f32().expect("global f32")),
-                ValType::F64 => Global::F64(g.get().f64().expect("global f64")),
-                _ => panic!("unexpected global value type"),
-            })
+        wasmtime_api::exported_globals(&self.instance)
+            .iter()
+            .map(wasmtime_api::get_global)
             .collect()
     }
 
@@ -554,7 +677,7 @@ impl WasmtimeInstance {
     /// only valid while the Instance object is kept alive.
     pub unsafe fn heap_addr(&self) -> *const u8 {
         self.memory()
-            .map(|mem| mem.data_unchecked().as_ptr())
+            .map(|mem| wasmtime_api::memory_data(&mem).as_ptr())
             .unwrap_or_else(|_| std::ptr::null())
     }
 
@@ -1,11 +1,15 @@
+use super::wasmtime_api::{
+    caller_global, caller_memory, define_func, get_i64, memory_data_mut, new_linker, new_trap,
+    set_i64, Caller, InstanceGlobal, Linker, Memory, Store, Trap,
+};
+use super::write_barrier::WriteBarrierBitmap;
 use ic_interfaces::execution_environment::{HypervisorError, SystemApi};
 use ic_logger::{error, info, ReplicaLogger};
-use ic_types::{CanisterId, Cycles, NumBytes};
+use ic_types::{CanisterId, Cycles, InstructionBudget, NumBytes};
 use std::cell::{RefCell, RefMut};
 use std::convert::TryFrom;
 use std::ops::DerefMut;
 use std::rc::Rc;
-use wasmtime::{Caller, Linker, Store, Trap, Val};
 
 #[derive(Clone)]
 pub struct SystemApiHandle {
@@ -35,8 +39,8 @@ impl SystemApiHandle {
     }
 }
 
-fn process_err(api: &mut dyn SystemApi, e: HypervisorError) -> wasmtime::Trap {
-    let t = wasmtime::Trap::new(format! {"{}", e});
+fn process_err(api: &mut dyn SystemApi, e: HypervisorError) -> Trap {
+    let t = new_trap(format! {"{}", e});
     api.set_execution_error(e);
     t
 }
@@ -47,14 +51,14 @@ fn process_err(api: &mut dyn SystemApi, e: HypervisorError) -> wasmtime::Trap {
 struct MemoryCharger {
     log: ReplicaLogger,
     canister_id: CanisterId,
-    num_instructions_global: std::rc::Weak<RefCell<Option<wasmtime::Global>>>,
+    num_instructions_global: std::rc::Weak<RefCell<Option<InstanceGlobal>>>,
 }
 
 impl MemoryCharger {
     fn new(
         log: ReplicaLogger,
         canister_id: CanisterId,
-        num_instructions_global: std::rc::Weak<RefCell<Option<wasmtime::Global>>>,
+        num_instructions_global: std::rc::Weak<RefCell<Option<InstanceGlobal>>>,
     ) -> Self {
         Self {
             log,
@@ -98,12 +102,11 @@ impl MemoryCharger {
             Some(counter) => counter,
         };
 
-        match counter.get() {
-            Val::I64(current_instructions) => {
-                let fee = api
-                    .get_num_instructions_from_bytes(NumBytes::from(num_bytes))
-                    .get() as i64;
-                if current_instructions < fee {
+        match get_i64(counter) {
+            Some(current_instructions) => {
+                let mut budget = InstructionBudget::from_wasm_counter(current_instructions);
+                let fee = api.get_num_instructions_from_bytes(NumBytes::from(num_bytes));
+                if budget.charge(fee).is_err() {
                     info!(
                         self.log,
                         "Canister {}: ran out of instructions.  Current {}, fee {}",
@@ -113,8 +116,8 @@ impl MemoryCharger {
                     );
                     return Err(process_err(api, HypervisorError::OutOfInstructions));
                 }
-                let updated_instructions = current_instructions - fee;
-                if let Err(err) = counter.set(Val::I64(updated_instructions)) {
+                let updated_instructions = budget.to_wasm_counter();
+                if let Err(err) = set_i64(counter, updated_instructions) {
                     error!(
                         self.log,
                         "[EXC-BUG] Canister {}: Setting instructions from {} to {} failed with {}",
@@ -127,12 +130,11 @@ impl MemoryCharger {
                 }
                 Ok(())
             }
-            others => {
+            None => {
                 error!(
                     self.log,
-                    "[EXC-BUG] Canister {}: expected value of type I64 instead got {:?}",
+                    "[EXC-BUG] Canister {}: expected the instructions counter to be of type I64",
                     self.canister_id,
-                    others,
                 );
                 Err(process_err(api, HypervisorError::OutOfInstructions))
             }
@@ -145,768 +147,705 @@ pub(crate) fn syscalls(
     canister_id: CanisterId,
     store: &Store,
     api: SystemApiHandle,
-    num_instructions_global: std::rc::Weak<RefCell<Option<wasmtime::Global>>>,
+    num_instructions_global: std::rc::Weak<RefCell<Option<InstanceGlobal>>>,
 ) -> Linker {
-    fn get_memory(
-        caller: Caller<'_>,
-        api: &mut dyn SystemApi,
-    ) -> Result<wasmtime::Memory, wasmtime::Trap> {
-        caller
-            .get_export("memory")
+    fn get_memory(caller: &Caller<'_>, api: &mut dyn SystemApi) -> Result<Memory, Trap> {
+        caller_memory(caller, "memory")
             .ok_or_else(|| {
                 HypervisorError::ContractViolation(
                     "WebAssembly module must define memory".to_string(),
                 )
             })
-            .and_then(|ext| {
-                ext.into_memory().ok_or_else(|| {
-                    HypervisorError::ContractViolation(
-                        "export 'memory' is not a memory".to_string(),
-                    )
-                })
-            })
             .map_err(|e| process_err(&mut *api, e))
     }
 
-    let memory_charger = MemoryCharger::new(log, canister_id, num_instructions_global);
-    let mut linker = Linker::new(&store);
-
-    linker
-        .func("ic0", "msg_caller_copy", {
-            let api = api.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                api.ic0_msg_caller_copy(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_caller_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_caller_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0::msg_caller_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_arg_data_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_arg_data_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0::msg_arg_data_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_arg_data_copy", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_msg_arg_data_copy(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_method_name_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_method_name_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0::msg_metohd_name_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_method_name_copy", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_msg_method_name_copy(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "accept_message", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_accept_message()
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_reply_data_append", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, src: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_msg_reply_data_append(src as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_reply", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_reply().map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_reject_code", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_reject_code()
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_reject", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, src: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_msg_reject(src as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_reject_msg_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_reject_msg_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_msg_reject_msg_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_reject_msg_copy", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_msg_reject_msg_copy(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "canister_self_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_canister_self_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_canister_self_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "canister_self_copy", {
-            let api = api.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                api.ic0_canister_self_copy(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "controller_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_controller_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_controller_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "controller_copy", {
-            let api = api.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                api.ic0_controller_copy(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "debug_print", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, offset: i32, length: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), length as u64)?;
-                api.ic0_debug_print(offset as u32, length as u32, memory);
-                Ok(())
-            }
-        })
-        .unwrap();
+    // Records a write of the system API to the heap in the write barrier
+    // bitmap, if the module was instrumented with write barriers.
+    fn record_heap_write(caller: &Caller<'_>, dst: u64, size: u64) {
+        let bitmap = WriteBarrierBitmap::lookup(|name| caller_global(caller, name));
+        if let Some(bitmap) = bitmap {
+            bitmap.mark(dst, size);
+        }
+    }
 
-    linker
-        .func("ic0", "trap", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, offset: i32, length: i32| -> Result<(), _> {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), length as u64)?;
-                let trap = api.ic0_trap(offset as u32, length as u32, memory);
-                Err(process_err(&mut *api, trap))
-            }
-        })
-        .unwrap();
+    let memory_charger = MemoryCharger::new(log, canister_id, num_instructions_global);
+    let mut linker = new_linker(store);
+
+    define_func(&mut linker, "ic0", "msg_caller_copy", {
+        let api = api.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_msg_caller_copy(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "call_simple", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>,
-                  callee_src: i32,
-                  callee_size: i32,
-                  name_src: i32,
-                  name_len: i32,
-                  reply_fun: i32,
-                  reply_env: i32,
-                  reject_fun: i32,
-                  reject_env: i32,
-                  src: i32,
-                  len: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), len as u64)?;
-                api.ic0_call_simple(
-                    callee_src as u32,
-                    callee_size as u32,
-                    name_src as u32,
-                    name_len as u32,
-                    reply_fun as u32,
-                    reply_env as u32,
-                    reject_fun as u32,
-                    reject_env as u32,
-                    src as u32,
-                    len as u32,
-                    memory,
-                )
+    define_func(&mut linker, "ic0", "msg_caller_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_caller_size()
                 .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+                .and_then(|s| {
+                    i32::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0::msg_caller_size failed: {}", e)))
+                })
+        }
+    });
 
-    linker
-        .func("ic0", "call_new", {
-            let api = api.clone();
-            move |caller: Caller<'_>,
-                  callee_src: i32,
-                  callee_size: i32,
-                  name_src: i32,
-                  name_len: i32,
-                  reply_fun: i32,
-                  reply_env: i32,
-                  reject_fun: i32,
-                  reject_env: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                api.ic0_call_new(
-                    callee_src as u32,
-                    callee_size as u32,
-                    name_src as u32,
-                    name_len as u32,
-                    reply_fun as u32,
-                    reply_env as u32,
-                    reject_fun as u32,
-                    reject_env as u32,
-                    memory,
-                )
+    define_func(&mut linker, "ic0", "msg_arg_data_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_arg_data_size()
                 .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+                .and_then(|s| {
+                    i32::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0::msg_arg_data_size failed: {}", e)))
+                })
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_arg_data_copy", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_msg_arg_data_copy(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_arg_data_next_chunk", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, dst: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_msg_arg_data_next_chunk(dst as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+                .map(|copied| copied as i32)
+        }
+    });
 
-    linker
-        .func("ic0", "call_data_append", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, src: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_call_data_append(src as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "msg_method_name_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_method_name_size()
+                .map_err(|e| process_err(&mut *api, e))
+                .and_then(|s| {
+                    i32::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0::msg_metohd_name_size failed: {}", e)))
+                })
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_method_name_copy", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_msg_method_name_copy(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "call_on_cleanup", {
-            let api = api.clone();
-            move |fun: i32, env: i32| {
-                let mut api = api.get_system_api();
-                api.ic0_call_on_cleanup(fun as u32, env as u32)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "accept_message", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_accept_message()
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_reply_data_append", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, src: i32, size: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_msg_reply_data_append(src as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "call_cycles_add", {
-            let api = api.clone();
-            move |amount: i64| {
-                let mut api = api.get_system_api();
-                api.ic0_call_cycles_add(amount as u64)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "msg_reply", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_reply().map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "call_cycles_add128", {
-            let api = api.clone();
-            move |amount_high: i64, amount_low: i64| {
-                let mut api = api.get_system_api();
-                api.ic0_call_cycles_add128(Cycles::from_parts(
-                    amount_high as u64,
-                    amount_low as u64,
-                ))
+    define_func(&mut linker, "ic0", "msg_reject_code", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_reject_code()
                 .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_reject", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, src: i32, size: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_msg_reject(src as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "call_perform", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_call_perform()
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "msg_reject_msg_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_reject_msg_size()
+                .map_err(|e| process_err(&mut *api, e))
+                .and_then(|s| {
+                    i32::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0_msg_reject_msg_size failed: {}", e)))
+                })
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_reject_msg_copy", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_msg_reject_msg_copy(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_stable_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_stable_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "canister_self_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_canister_self_size()
+                .map_err(|e| process_err(&mut *api, e))
+                .and_then(|s| {
+                    i32::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0_canister_self_size failed: {}", e)))
+                })
+        }
+    });
+
+    define_func(&mut linker, "ic0", "canister_self_copy", {
+        let api = api.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_canister_self_copy(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable_grow", {
-            let api = api.clone();
-            move |additional_pages: i32| {
-                let mut api = api.get_system_api();
-                api.ic0_stable_grow(additional_pages as u32)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "controller_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_controller_size()
+                .map_err(|e| process_err(&mut *api, e))
+                .and_then(|s| {
+                    i32::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0_controller_size failed: {}", e)))
+                })
+        }
+    });
+
+    define_func(&mut linker, "ic0", "controller_copy", {
+        let api = api.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_controller_copy(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "debug_print", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, offset: i32, length: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), length as u64)?;
+            api.ic0_debug_print(offset as u32, length as u32, memory);
+            Ok(())
+        }
+    });
+
+    define_func(&mut linker, "ic0", "trap", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, offset: i32, length: i32| -> Result<(), _> {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), length as u64)?;
+            let trap = api.ic0_trap(offset as u32, length as u32, memory);
+            Err(process_err(&mut *api, trap))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "call_simple", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>,
+              callee_src: i32,
+              callee_size: i32,
+              name_src: i32,
+              name_len: i32,
+              reply_fun: i32,
+              reply_env: i32,
+              reject_fun: i32,
+              reject_env: i32,
+              src: i32,
+              len: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), len as u64)?;
+            api.ic0_call_simple(
+                callee_src as u32,
+                callee_size as u32,
+                name_src as u32,
+                name_len as u32,
+                reply_fun as u32,
+                reply_env as u32,
+                reject_fun as u32,
+                reject_env as u32,
+                src as u32,
+                len as u32,
+                memory,
+            )
+            .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "call_new", {
+        let api = api.clone();
+        move |caller: Caller<'_>,
+              callee_src: i32,
+              callee_size: i32,
+              name_src: i32,
+              name_len: i32,
+              reply_fun: i32,
+              reply_env: i32,
+              reject_fun: i32,
+              reject_env: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_call_new(
+                callee_src as u32,
+                callee_size as u32,
+                name_src as u32,
+                name_len as u32,
+                reply_fun as u32,
+                reply_env as u32,
+                reject_fun as u32,
+                reject_env as u32,
+                memory,
+            )
+            .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "call_data_append", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, src: i32, size: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_call_data_append(src as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable_read", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_stable_read(dst as u32, offset as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "call_on_cleanup", {
+        let api = api.clone();
+        move |fun: i32, env: i32| {
+            let mut api = api.get_system_api();
+            api.ic0_call_on_cleanup(fun as u32, env as u32)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable_write", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, offset: i32, src: i32, size: i32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_stable_write(offset as u32, src as u32, size as u32, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "call_cycles_add", {
+        let api = api.clone();
+        move |amount: i64| {
+            let mut api = api.get_system_api();
+            api.ic0_call_cycles_add(amount as u64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable64_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_stable64_size()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i64::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_stable64_size failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "call_cycles_add128", {
+        let api = api.clone();
+        move |amount_high: i64, amount_low: i64| {
+            let mut api = api.get_system_api();
+            api.ic0_call_cycles_add128(Cycles::from_parts(amount_high as u64, amount_low as u64))
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable64_grow", {
-            let api = api.clone();
-            move |additional_pages: i64| {
-                let mut api = api.get_system_api();
-                api.ic0_stable64_grow(additional_pages as u64)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "call_perform", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_call_perform()
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "call_queue_available", {
+        let api = api.clone();
+        move |caller: Caller<'_>, callee_src: i32, callee_size: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_call_queue_available(callee_src as u32, callee_size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "stable64_read", {
-            let api = api.clone();
-            let memory_charger = memory_charger.clone();
-            move |caller: Caller<'_>, dst: i64, offset: i64, size: i64| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_stable64_read(dst as u64, offset as u64, size as u64, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "stable_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_stable_size()
+                .map_err(|e| process_err(&mut *api, e))
+                .and_then(|s| {
+                    i32::try_from(s).map_err(|e| new_trap(format!("ic0_stable_size failed: {}", e)))
+                })
+        }
+    });
 
-    linker
-        .func("ic0", "stable64_write", {
-            let api = api.clone();
-            move |caller: Caller<'_>, offset: i64, src: i64, size: i64| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
-                api.ic0_stable64_write(offset as u64, src as u64, size as u64, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "stable_grow", {
+        let api = api.clone();
+        move |additional_pages: i32| {
+            let mut api = api.get_system_api();
+            api.ic0_stable_grow(additional_pages as u32)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "stable_read", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, dst: i32, offset: i32, size: i32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u32 as u64, size as u32 as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_stable_read(dst as u32, offset as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "stable_write", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, offset: i32, src: i32, size: i32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_stable_write(offset as u32, src as u32, size as u32, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "time", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_time()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .map(|s| s.as_nanos_since_unix_epoch())
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "stable64_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_stable64_size()
+                .map_err(|e| process_err(&mut *api, e))
+                .and_then(|s| {
+                    i64::try_from(s)
+                        .map_err(|e| new_trap(format!("ic0_stable64_size failed: {}", e)))
+                })
+        }
+    });
 
-    linker
-        .func("ic0", "canister_cycle_balance", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_canister_cycle_balance()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i64::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_canister_cycle_balance failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "stable64_grow", {
+        let api = api.clone();
+        move |additional_pages: i64| {
+            let mut api = api.get_system_api();
+            api.ic0_stable64_grow(additional_pages as u64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "stable64_read", {
+        let api = api.clone();
+        let memory_charger = memory_charger.clone();
+        move |caller: Caller<'_>, dst: i64, offset: i64, size: i64| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u64, size as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_stable64_read(dst as u64, offset as u64, size as u64, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "stable64_write", {
+        let api = api.clone();
+        move |caller: Caller<'_>, offset: i64, src: i64, size: i64| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            memory_charger.charge_for_memory_used(api.deref_mut(), size as u64)?;
+            api.ic0_stable64_write(offset as u64, src as u64, size as u64, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "canister_cycles_balance128", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                match api.ic0_canister_cycles_balance128() {
-                    Ok((high, low)) => Ok((high as i64, low as i64)),
-                    Err(err) => Err(process_err(&mut *api, err)),
+    define_func(&mut linker, "ic0", "time", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_time()
+                .map_err(|e| process_err(&mut *api, e))
+                .map(|s| s.as_nanos_since_unix_epoch())
+        }
+    });
+
+    define_func(&mut linker, "ic0", "canister_cycle_balance", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            // The amount is returned to the canister as an unsigned
+            // 64-bit value in an `i64`.
+            api.ic0_canister_cycle_balance()
+                .map(|cycles| cycles as i64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "canister_cycles_balance128", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            match api.ic0_canister_cycles_balance128() {
+                Ok(cycles) => {
+                    let (high, low) = cycles.into_parts();
+                    Ok((high as i64, low as i64))
                 }
+                Err(err) => Err(process_err(&mut *api, err)),
             }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_cycles_available", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_cycles_available()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i64::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_msg_cycles_available failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_cycles_available128", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                match api.ic0_msg_cycles_available128() {
-                    Ok((high, low)) => Ok((high as i64, low as i64)),
-                    Err(err) => Err(process_err(&mut *api, err)),
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_cycles_available", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_cycles_available()
+                .map(|cycles| cycles as i64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_cycles_available128", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            match api.ic0_msg_cycles_available128() {
+                Ok(cycles) => {
+                    let (high, low) = cycles.into_parts();
+                    Ok((high as i64, low as i64))
                 }
+                Err(err) => Err(process_err(&mut *api, err)),
             }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_cycles_refunded", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_msg_cycles_refunded()
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i64::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_msg_cycles_refunded failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_cycles_refunded128", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                match api.ic0_msg_cycles_refunded128() {
-                    Ok((high, low)) => Ok((high as i64, low as i64)),
-                    Err(err) => Err(process_err(&mut *api, err)),
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_cycles_refunded", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_msg_cycles_refunded()
+                .map(|cycles| cycles as i64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_cycles_refunded128", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            match api.ic0_msg_cycles_refunded128() {
+                Ok(cycles) => {
+                    let (high, low) = cycles.into_parts();
+                    Ok((high as i64, low as i64))
                 }
+                Err(err) => Err(process_err(&mut *api, err)),
             }
-        })
-        .unwrap();
-
-    linker
-        .func("ic0", "msg_cycles_accept", {
-            let api = api.clone();
-            move |amount: i64| {
-                let mut api = api.get_system_api();
-                api.ic0_msg_cycles_accept(amount as u64)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+        }
+    });
 
-    linker
-        .func("ic0", "msg_cycles_accept128", {
-            let api = api.clone();
-            move |amount_high: i64, amount_low: i64| {
-                let mut api = api.get_system_api();
-                match api.ic0_msg_cycles_accept128(Cycles::from_parts(
-                    amount_high as u64,
-                    amount_low as u64,
-                )) {
-                    Ok((high, low)) => Ok((high as i64, low as i64)),
-                    Err(err) => Err(process_err(&mut *api, err)),
+    define_func(&mut linker, "ic0", "msg_cycles_accept", {
+        let api = api.clone();
+        move |amount: i64| {
+            let mut api = api.get_system_api();
+            api.ic0_msg_cycles_accept(amount as u64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "msg_cycles_accept128", {
+        let api = api.clone();
+        move |amount_high: i64, amount_low: i64| {
+            let mut api = api.get_system_api();
+            match api
+                .ic0_msg_cycles_accept128(Cycles::from_parts(amount_high as u64, amount_low as u64))
+            {
+                Ok(cycles) => {
+                    let (high, low) = cycles.into_parts();
+                    Ok((high as i64, low as i64))
                 }
+                Err(err) => Err(process_err(&mut *api, err)),
             }
-        })
-        .unwrap();
-
-    linker
-        .func("__", "out_of_instructions", {
-            let api = api.clone();
-            move || -> Result<(), _> {
-                let mut api = api.get_system_api();
-                let err = api.out_of_instructions();
-                Err(process_err(&mut *api, err))
-            }
-        })
-        .unwrap();
-
-    linker
-        .func("__", "update_available_memory", {
-            let api = api.clone();
-            move |native_memory_grow_res: i32, additional_pages: i32| {
-                let mut api = api.get_system_api();
-                api.update_available_memory(native_memory_grow_res, additional_pages as u32)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+        }
+    });
+
+    define_func(&mut linker, "__", "out_of_instructions", {
+        let api = api.clone();
+        move || -> Result<(), _> {
+            let mut api = api.get_system_api();
+            let err = api.out_of_instructions();
+            Err(process_err(&mut *api, err))
+        }
+    });
 
-    linker
-        .func("ic0", "canister_status", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_canister_status()
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "__", "update_available_memory", {
+        let api = api.clone();
+        move |native_memory_grow_res: i32, additional_pages: i32| {
+            let mut api = api.get_system_api();
+            api.update_available_memory(native_memory_grow_res, additional_pages as u32)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "certified_data_set", {
-            let api = api.clone();
-            move |caller: Caller<'_>, src: u32, size: u32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                api.ic0_certified_data_set(src, size, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "canister_status", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_canister_status()
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "certified_data_set", {
+        let api = api.clone();
+        move |caller: Caller<'_>, src: u32, size: u32| {
+            let mut api = api.get_system_api();
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_certified_data_set(src, size, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "data_certificate_present", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_data_certificate_present()
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "data_certificate_present", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_data_certificate_present()
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "data_certificate_size", {
-            let api = api.clone();
-            move || {
-                let mut api = api.get_system_api();
-                api.ic0_data_certificate_size()
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "data_certificate_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_data_certificate_size()
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "data_certificate_copy", {
+        let api = api.clone();
+        move |caller: Caller<'_>, dst: u32, offset: u32, size: u32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u64, size as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_data_certificate_copy(dst, offset, size, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "data_certificate_copy", {
-            let api = api.clone();
-            move |caller: Caller<'_>, dst: u32, offset: u32, size: u32| {
-                let mut api = api.get_system_api();
-                let mem = get_memory(caller, &mut *api)?;
-                let memory = unsafe { mem.data_unchecked_mut() };
-                api.ic0_data_certificate_copy(dst, offset, size, memory)
-                    .map_err(|e| process_err(&mut *api, e))
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "root_key_size", {
+        let api = api.clone();
+        move || {
+            let mut api = api.get_system_api();
+            api.ic0_root_key_size()
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
+
+    define_func(&mut linker, "ic0", "root_key_copy", {
+        let api = api.clone();
+        move |caller: Caller<'_>, dst: u32, offset: u32, size: u32| {
+            let mut api = api.get_system_api();
+            record_heap_write(&caller, dst as u64, size as u64);
+            let mem = get_memory(&caller, &mut *api)?;
+            let memory = unsafe { memory_data_mut(&mem) };
+            api.ic0_root_key_copy(dst, offset, size, memory)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
-    linker
-        .func("ic0", "mint_cycles", {
-            move |amount: i64| {
-                let mut api = api.get_system_api();
-                api.ic0_mint_cycles(amount as u64)
-                    .map_err(|e| process_err(&mut *api, e))
-                    .and_then(|s| {
-                        i64::try_from(s).map_err(|e| {
-                            wasmtime::Trap::new(format!("ic0_mint_cycles failed: {}", e))
-                        })
-                    })
-            }
-        })
-        .unwrap();
+    define_func(&mut linker, "ic0", "mint_cycles", {
+        move |amount: i64| {
+            let mut api = api.get_system_api();
+            api.ic0_mint_cycles(amount as u64)
+                .map(|cycles| cycles as i64)
+                .map_err(|e| process_err(&mut *api, e))
+        }
+    });
 
     linker
 }
@@ -0,0 +1,144 @@
+//! Helpers for the wasmtime calls made by the syscalls and when creating an
+//! instance: defining host functions, accessing the memory and the globals of
+//! the caller, and reading and writing the exported globals.
+//!
+//! The types are the ones of wasmtime, re-exported; compilation and running
+//! an instance use wasmtime directly.
+
+use ic_replicated_state::Global;
+use ic_wasm_utils::instrumentation::is_write_barrier_export;
+use wasmtime::{IntoFunc, Mutability, Val, ValType};
+
+pub(crate) use wasmtime::{Caller, Instance, Linker, Memory, Store, Trap};
+
+/// A global of an instance.
+pub(crate) type InstanceGlobal = wasmtime::Global;
+
+/// Creates the linker that resolves the imports of modules instantiated in
+/// `store`.
+pub(crate) fn new_linker(store: &Store) -> Linker {
+    Linker::new(store)
+}
+
+/// Defines the host function `module`.`name` in `linker`.
+///
+/// # Panics
+/// * if the function is already defined.
+pub(crate) fn define_func<Params, Results>(
+    linker: &mut Linker,
+    module: &str,
+    name: &str,
+    func: impl IntoFunc<Params, Results>,
+) {
+    linker
+        .func(module, name, func)
+        .unwrap_or_else(|e| panic!("failed to define {}.{}: {}", module, name, e));
+}
+
+/// Creates a trap that aborts the execution with `message`.
+pub(crate) fn new_trap(message: String) -> Trap {
+    Trap::new(message)
+}
+
+/// Returns the memory exported as `name` by the instance calling a host
+/// function, or `None` if there is no such export or it is not a memory.
+pub(crate) fn caller_memory(caller: &Caller<'_>, name: &str) -> Option<Memory> {
+    caller
+        .get_export(name)
+        .and_then(|export| export.into_memory())
+}
+
+/// Returns the global exported as `name` by the instance calling a host
+/// function, or `None` if there is no such export or it is not a global.
+pub(crate) fn caller_global(caller: &Caller<'_>, name: &str) -> Option<InstanceGlobal> {
+    caller
+        .get_export(name)
+        .and_then(|export| export.into_global())
+}
+
+/// Returns the contents of `memory`.
+///
+/// # Safety
+/// The returned slice aliases the memory of the instance: it must not outlive
+/// the instance it was obtained from, and the instance must not run while the
+/// slice is alive.
+pub(crate) unsafe fn memory_data(memory: &Memory) -> &[u8] {
+    memory.data_unchecked()
+}
+
+/// Returns the contents of `memory` for writing.
+///
+/// # Safety
+/// The returned slice aliases the memory of the instance: it must not outlive
+/// the host function call or the instance it was obtained in, and the memory
+/// must not be grown while the slice is alive.
+pub(crate) unsafe fn memory_data_mut(memory: &Memory) -> &mut [u8] {
+    memory.data_unchecked_mut()
+}
+
+/// Returns the value of an `i64` global, or `None` if the global has a
+/// different type.
+pub(crate) fn get_i64(global: &InstanceGlobal) -> Option<i64> {
+    global.get().i64()
+}
+
+/// Sets the value of an `i64` global.
+pub(crate) fn set_i64(global: &InstanceGlobal, value: i64) -> Result<(), String> {
+    global.set(Val::I64(value)).map_err(|e| e.to_string())
+}
+
+/// Returns the value of an `i32` global, or `None` if the global has a
+/// different type.
+pub(crate) fn get_i32(global: &InstanceGlobal) -> Option<i32> {
+    global.get().i32()
+}
+
+/// Sets the value of an `i32` global.
+pub(crate) fn set_i32(global: &InstanceGlobal, value: i32) -> Result<(), String> {
+    global.set(Val::I32(value)).map_err(|e| e.to_string())
+}
+
+/// Returns the value of `global` as it is persisted in the execution state.
+///
+/// # Panics
+/// * if the global is not of a number type.
+pub(crate) fn get_global(global: &InstanceGlobal) -> Global {
+    match global.ty().content() {
+        ValType::I32 => Global::I32(global.get().i32().expect("global i32")),
+        ValType::I64 => Global::I64(global.get().i64().expect("global i64")),
+        ValType::F32 => Global::F32(global.get().f32().expect("global f32")),
+        ValType::F64 => Global::F64(global.get().f64().expect("global f64")),
+        _ => panic!("unexpected global value type"),
+    }
+}
+
+/// Returns true if the value of `global` can be changed.
+pub(crate) fn is_mutable(global: &InstanceGlobal) -> bool {
+    global.ty().mutability() == Mutability::Var
+}
+
+/// Sets `global` to a value persisted in the execution state.
+pub(crate) fn set_global(global: &InstanceGlobal, value: &Global) -> Result<(), String> {
+    global
+        .set(match value {
+            Global::I32(val) => Val::I32(*val),
+            Global::I64(val) => Val::I64(*val),
+            Global::F32(val) => Val::F32(val.to_bits()),
+            Global::F64(val) => Val::F64(val.to_bits()),
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the globals exported by `instance` in the order of their exports.
+///
+/// The globals of the write barrier bitmap are left out: whether a module
+/// contains write barriers is a setting of the node, so the bitmap must not
+/// become part of the replicated execution state. It starts out empty in every
+/// new instance instead.
+pub(crate) fn exported_globals(instance: &Instance) -> Vec<InstanceGlobal> {
+    instance
+        .exports()
+        .filter(|export| !is_write_barrier_export(export.name()))
+        .filter_map(|export| export.into_global())
+        .collect()
+}
@@ -1,7 +1,7 @@
-use super::system_api;
+use super::{system_api, wasmtime_api};
 use ic_interfaces::execution_environment::{ExecutionParameters, SubnetAvailableMemory};
 use ic_logger::replica_logger::no_op_logger;
-use ic_replicated_state::SystemState;
+use ic_replicated_state::{Global, SystemState};
 use ic_system_api::{ApiType, SystemApiImpl};
 use ic_test_utilities::{
     cycles_account_manager::CyclesAccountManagerBuilder, types::ids::canister_test_id,
@@ -44,6 +44,8 @@ fn test_wasmtime_system_api() {
             canister_memory_limit,
             subnet_available_memory: MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             compute_allocation: ComputeAllocation::default(),
+            canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+            root_key: vec![],
         },
         no_op_logger(),
     );
@@ -96,3 +98,49 @@ fn test_wasmtime_system_api() {
         .call(&[])
         .expect("call failed");
 }
+
+#[test]
+fn test_wasmtime_api_globals_round_trip() {
+    let engine = Engine::new(&Config::default()).expect("Failed to initialize Wasmtime engine");
+    let store = Store::new(&engine);
+    let wat = r#"
+    (module
+      (global (export "g_i32") (mut i32) (i32.const 0))
+      (global (export "g_i64") (mut i64) (i64.const 0))
+      (global (export "g_f32") (mut f32) (f32.const 0))
+      (global (export "g_f64") (mut f64) (f64.const 0))
+      (global (export "g_const") i32 (i32.const 7))
+    )"#;
+    let module = Module::new(
+        &engine,
+        wabt::wat2wasm(&wat).expect("failed to compile Wasm source"),
+    )
+    .expect("failed to instantiate module");
+    let instance = wasmtime_api::new_linker(&store)
+        .instantiate(&module)
+        .expect("failed to instantiate instance");
+    let values = vec![
+        Global::I32(-1),
+        Global::I64(1 << 40),
+        Global::F32(0.5),
+        Global::F64(-2.25),
+    ];
+
+    let globals = wasmtime_api::exported_globals(&instance);
+    assert_eq!(globals.len(), 5);
+    for (global, value) in globals.iter().zip(values.iter()) {
+        assert!(wasmtime_api::is_mutable(global));
+        wasmtime_api::set_global(global, value).expect("failed to set global");
+    }
+    assert!(!wasmtime_api::is_mutable(&globals[4]));
+
+    let mut expected = values;
+    expected.push(Global::I32(7));
+    assert_eq!(
+        globals
+            .iter()
+            .map(wasmtime_api::get_global)
+            .collect::<Vec<_>>(),
+        expected
+    );
+}
@@ -0,0 +1,69 @@
+//! Access to the bitmap of pages written by a module instrumented with write
+//! barriers, see `instrument_with_write_barriers`.
+
+use super::wasmtime_api::{get_i32, get_i64, set_i32, set_i64, InstanceGlobal};
+use ic_replicated_state::PageIndex;
+use ic_wasm_utils::instrumentation::{
+    write_barrier_bitmap_export, WRITE_BARRIER_BITMAP_WORDS, WRITE_BARRIER_MAX_PAGES,
+    WRITE_BARRIER_OVERFLOW_EXPORT, WRITE_BARRIER_PAGE_SIZE_LOG2,
+};
+
+/// The globals of an instance making up the write barrier bitmap.
+pub(crate) struct WriteBarrierBitmap {
+    words: Vec<InstanceGlobal>,
+    overflow: InstanceGlobal,
+}
+
+impl WriteBarrierBitmap {
+    /// Looks up the globals of the bitmap using `get_global`, which returns
+    /// the exported global of the given name. Returns `None` if the module was
+    /// not instrumented with write barriers.
+    pub(crate) fn lookup(get_global: impl Fn(&str) -> Option<InstanceGlobal>) -> Option<Self> {
+        let words = (0..WRITE_BARRIER_BITMAP_WORDS)
+            .map(|word| get_global(&write_barrier_bitmap_export(word)))
+            .collect::<Option<Vec<_>>>()?;
+        let overflow = get_global(WRITE_BARRIER_OVERFLOW_EXPORT)?;
+        Some(Self { words, overflow })
+    }
+
+    /// Records a write of `size` bytes at `offset` of the heap that did not
+    /// go through a write barrier, e.g., a write of the system API.
+    pub(crate) fn mark(&self, offset: u64, size: u64) {
+        if size == 0 {
+            return;
+        }
+        let first_page = offset >> WRITE_BARRIER_PAGE_SIZE_LOG2;
+        let last_page = offset.saturating_add(size - 1) >> WRITE_BARRIER_PAGE_SIZE_LOG2;
+        for page in first_page..=last_page {
+            if page >= WRITE_BARRIER_MAX_PAGES {
+                set_i32(&self.overflow, 1).expect("failed to set the write barrier overflow flag");
+                return;
+            }
+            let word = &self.words[(page / 64) as usize];
+            let bits = get_i64(word).expect("write barrier bitmap word i64");
+            set_i64(word, bits | 1 << (page % 64))
+                .expect("failed to update the write barrier bitmap");
+        }
+    }
+
+    /// Returns the recorded pages within a heap of `heap_pages` pages. If a
+    /// page beyond the capacity of the bitmap was written, all pages of the
+    /// heap are conservatively considered dirty.
+    pub(crate) fn dirty_pages(&self, heap_pages: u64) -> Vec<PageIndex> {
+        let overflow = get_i32(&self.overflow).expect("write barrier overflow flag i32");
+        if overflow != 0 {
+            return (0..heap_pages).map(PageIndex::new).collect();
+        }
+        let mut dirty_pages = vec![];
+        for (word_ix, word) in self.words.iter().enumerate() {
+            let bits = get_i64(word).expect("write barrier bitmap word i64") as u64;
+            for bit in 0..64 {
+                let page = word_ix as u64 * 64 + bit;
+                if bits & (1 << bit) != 0 && page < heap_pages {
+                    dirty_pages.push(PageIndex::new(page));
+                }
+            }
+        }
+        dirty_pages
+    }
+}
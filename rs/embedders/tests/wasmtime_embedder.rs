@@ -20,12 +20,20 @@ fn execution_parameters() -> ExecutionParameters {
         canister_memory_limit: ic_types::NumBytes::from(4 << 30),
         subnet_available_memory: SubnetAvailableMemory::new(NumBytes::new(std::u64::MAX)),
         compute_allocation: ComputeAllocation::default(),
+        canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+        root_key: vec![],
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use ic_embedders::compilation_artifact::CompiledArtifact;
+    use ic_embedders::wasm_executor::{CompilationTrigger, WasmExecutor};
+    use ic_metrics::MetricsRegistry;
+    use ic_test_utilities::metrics::fetch_histogram_vec_count;
     use ic_test_utilities::types::ids::canister_test_id;
+    use ic_wasm_types::InstructionCostOverrides;
+    use ic_wasm_utils::validation::WasmAllowLists;
     use memory_tracker::DirtyPageTracking;
 
     use super::*;
@@ -176,18 +184,118 @@ mod tests {
         );
 
         match result {
-            Ok(_) => panic!("Expected a HypervisorError::Trapped"),
+            Ok(_) => panic!("Expected a HypervisorError::StackOverflow"),
             Err(err) => {
                 assert_eq!(
                     err,
-                    ic_interfaces::execution_environment::HypervisorError::Trapped(
-                        ic_interfaces::execution_environment::TrapCode::StackOverflow
-                    )
+                    ic_interfaces::execution_environment::HypervisorError::StackOverflow
                 );
             }
         }
     }
 
+    /// Runs the `canister_update f` method of the given module with the given
+    /// embedder configuration.
+    fn run_update_f(
+        config: ic_config::embedders::Config,
+        wat: &str,
+    ) -> ic_interfaces::execution_environment::HypervisorResult<()> {
+        let log = logger();
+        let embedder = WasmtimeEmbedder::new(config, log.clone());
+        let compiled = embedder
+            .compile(PersistenceType::Sigsegv, &wat2wasm(wat).expect("wat"))
+            .expect("compiled");
+        let mut instance = embedder.new_instance(
+            canister_test_id(1),
+            &compiled,
+            &[],
+            NumWasmPages::from(1),
+            None,
+            None,
+            DirtyPageTracking::Track,
+        );
+        let cycles_account_manager = Arc::new(CyclesAccountManagerBuilder::new().build());
+        let system_state_accessor = ic_system_api::SystemStateAccessorDirect::new(
+            SystemStateBuilder::default().build(),
+            cycles_account_manager,
+        );
+        let mut api = ic_system_api::SystemApiImpl::new(
+            ic_system_api::ApiType::init(mock_time(), vec![], user_test_id(24).get()),
+            system_state_accessor,
+            NumBytes::from(0),
+            execution_parameters(),
+            log,
+        );
+        instance
+            .run(
+                &mut api,
+                FuncRef::Method(WasmMethod::Update("f".to_string())),
+            )
+            .map(|_| ())
+    }
+
+    #[test]
+    fn stack_overflow_depends_on_configured_stack_size() {
+        // Recurses 10000 times with 20 locals per frame, which needs more than
+        // 64 KiB but less than the default stack.
+        let wat = r#"
+          (module
+            (func $rec (param $n i32)
+              (local i64) (local i64) (local i64) (local i64) (local i64)
+              (local i64) (local i64) (local i64) (local i64) (local i64)
+              (local i64) (local i64) (local i64) (local i64) (local i64)
+              (local i64) (local i64) (local i64) (local i64) (local i64)
+              (if (i32.gt_u (local.get $n) (i32.const 0))
+                (then (call $rec (i32.sub (local.get $n) (i32.const 1)))))
+            )
+            (func (export "canister_update f")
+              (call $rec (i32.const 10000))
+            )
+            (memory 1)
+          )
+        "#;
+
+        assert_eq!(
+            run_update_f(ic_config::embedders::Config::default(), wat),
+            Ok(())
+        );
+
+        let mut config = ic_config::embedders::Config::default();
+        config.max_wasm_stack_size = 64 * 1024;
+        let err = run_update_f(config, wat).unwrap_err();
+        assert_eq!(
+            err,
+            ic_interfaces::execution_environment::HypervisorError::StackOverflow
+        );
+        let user_error = err.into_user_error(&canister_test_id(1));
+        assert_eq!(
+            user_error.code(),
+            ic_types::user_error::ErrorCode::CanisterTrapped
+        );
+        assert!(user_error.description().contains("stack overflow"));
+    }
+
+    #[test]
+    fn custom_guard_sizes_do_not_affect_execution() {
+        let mut config = ic_config::embedders::Config::default();
+        config.static_memory_guard_size = 1 << 30;
+        config.dynamic_memory_guard_size = 1 << 20;
+
+        let result = run_update_f(
+            config,
+            r#"
+          (module
+            (func (export "canister_update f")
+              (i32.store (i32.const 0) (i32.const 42))
+            )
+            (memory 1)
+          )
+        "#,
+        );
+
+        assert_eq!(result, Ok(()));
+    }
+
     #[test]
     // takes a Wasm with two mutable globals and checks whether we can set and get
     // their values.
@@ -409,4 +517,160 @@ mod tests {
             DirtyPageTracking::Track,
         );
     }
+
+    #[test]
+    // Checks that with in-memory persistence the memory is initialized from
+    // the page map and the pages written by the execution are reported dirty.
+    fn in_memory_persistence_reads_page_map_and_reports_dirty_pages() {
+        use ic_replicated_state::page_map::{Buffer, PageMap};
+
+        let log = logger();
+        let wasm = &wat2wasm(
+            r#"
+                    (module
+                      (memory (export "memory") 1)
+                      (func (export "canister_update test")
+                        ;; Copy the byte at address 10 to the last byte of the memory.
+                        (i32.store8 (i32.const 65535) (i32.load8_u (i32.const 10)))
+                      )
+                    )"#,
+        )
+        .unwrap();
+        let mut buffer = Buffer::new(PageMap::default());
+        buffer.write(&[42], 10);
+        let page_map = buffer.into_page_map();
+
+        let embedder = WasmtimeEmbedder::new(
+            ic_config::embedders::Config {
+                persistence_type: PersistenceType::InMemory,
+                ..Default::default()
+            },
+            log.clone(),
+        );
+        assert!(embedder.uses_in_memory_persistence());
+        let mut inst = embedder.new_instance(
+            canister_test_id(1),
+            &embedder.compile(PersistenceType::Sigsegv, wasm).unwrap(),
+            &[],
+            NumWasmPages::from(1),
+            None,
+            Some(page_map),
+            DirtyPageTracking::Track,
+        );
+
+        let cycles_account_manager = Arc::new(CyclesAccountManagerBuilder::new().build());
+        let system_state = SystemStateBuilder::default().build();
+        let system_state_accessor =
+            ic_system_api::SystemStateAccessorDirect::new(system_state, cycles_account_manager);
+        let res = inst
+            .run(
+                &mut ic_system_api::SystemApiImpl::new(
+                    ic_system_api::ApiType::init(mock_time(), vec![], user_test_id(24).get()),
+                    system_state_accessor,
+                    ic_types::NumBytes::from(0),
+                    execution_parameters(),
+                    log,
+                ),
+                FuncRef::Method(WasmMethod::Update("test".to_string())),
+            )
+            .unwrap();
+
+        // Only the last page of the memory was written.
+        assert_eq!(res.dirty_pages.len(), 1);
+        assert!(res.dirty_pages[0].get() > 0);
+        let last_byte = unsafe { *inst.heap_addr().add(65535) };
+        assert_eq!(last_byte, 42);
+    }
+
+    #[test]
+    fn deserialized_module_exports_same_globals() {
+        let wasm = &wat2wasm(
+            r#"
+                (module
+                    (global (export "g") (mut i64) (i64.const 42))
+                )"#,
+        )
+        .unwrap();
+
+        let embedder = WasmtimeEmbedder::new(ic_config::embedders::Config::default(), logger());
+        let compiled = embedder.compile(PersistenceType::Sigsegv, wasm).unwrap();
+        let serialized = embedder.serialize(&compiled).unwrap();
+        let deserialized = embedder
+            .deserialize(PersistenceType::Sigsegv, &serialized)
+            .unwrap();
+
+        let instance = embedder.new_instance(
+            canister_test_id(1),
+            &deserialized,
+            &[],
+            NumWasmPages::from(0),
+            None,
+            None,
+            DirtyPageTracking::Track,
+        );
+        assert_eq!(instance.get_exported_globals(), vec![Global::I64(42)]);
+    }
+
+    #[test]
+    fn deserializing_garbage_fails() {
+        let embedder = WasmtimeEmbedder::new(ic_config::embedders::Config::default(), logger());
+
+        assert_eq!(
+            embedder
+                .deserialize(PersistenceType::Sigsegv, &[1, 2, 3])
+                .err(),
+            Some(
+                ic_interfaces::execution_environment::HypervisorError::WasmEngineError(
+                    ic_wasm_types::WasmEngineError::FailedToDeserializeModule
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn compiled_module_is_persisted_and_loaded_after_restart() {
+        let wasm = wat2wasm(r#"(module (func (export "canister_update test")))"#).unwrap();
+        let artifacts_dir = tempfile::tempdir().unwrap();
+        let new_executor = |metrics_registry: &MetricsRegistry| {
+            let config = ic_config::embedders::Config::default();
+            WasmExecutor::new(
+                WasmtimeEmbedder::new(config.clone(), logger()),
+                config.max_globals,
+                config.max_functions,
+                WasmAllowLists::default(),
+                config.write_barriers,
+                Some(artifacts_dir.path().to_path_buf()),
+                metrics_registry,
+                logger(),
+            )
+        };
+        let compile = |executor: &WasmExecutor| {
+            executor
+                .compile(
+                    &wasm,
+                    PersistenceType::Sigsegv,
+                    &InstructionCostOverrides::default(),
+                    CompilationTrigger::Execution,
+                )
+                .unwrap()
+        };
+        let compilations = |metrics_registry: &MetricsRegistry| {
+            fetch_histogram_vec_count(metrics_registry, "execution_wasm_compile")
+                .values()
+                .sum::<u64>()
+        };
+
+        let metrics_registry = MetricsRegistry::new();
+        compile(&new_executor(&metrics_registry));
+        assert_eq!(compilations(&metrics_registry), 1);
+        let artifact = CompiledArtifact::read_from_file(
+            &artifacts_dir.path().join(hex::encode(wasm.hash_sha256())),
+        )
+        .unwrap();
+        assert_eq!(artifact.header.module_hash, wasm.hash_sha256());
+
+        let metrics_registry = MetricsRegistry::new();
+        compile(&new_executor(&metrics_registry));
+        assert_eq!(compilations(&metrics_registry), 0);
+    }
 }
@@ -2,26 +2,25 @@ use ic_config::embedders::{Config, PersistenceType};
 use ic_embedders::{wasmtime_embedder::WasmtimeInstance, InstanceRunResult, WasmtimeEmbedder};
 use ic_interfaces::execution_environment::{ExecutionParameters, SubnetAvailableMemory};
 use ic_logger::{replica_logger::no_op_logger, ReplicaLogger};
-use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::NumWasmPages;
 use ic_sys::PAGE_SIZE;
-use ic_system_api::{ApiType, SystemApiImpl};
+use ic_system_api::{test_utils::ApiTypeBuilder, SystemApiImpl};
 use ic_test_utilities::{
     cycles_account_manager::CyclesAccountManagerBuilder,
-    mock_time,
     state::SystemStateBuilder,
     types::ids::{call_context_test_id, subnet_test_id, user_test_id},
     with_test_replica_logger,
 };
 use ic_types::{
     methods::{FuncRef, WasmMethod},
-    CanisterId, ComputeAllocation, Cycles, NumBytes, NumInstructions, PrincipalId,
+    ComputeAllocation, NumBytes, NumInstructions, PrincipalId,
 };
 use ic_wasm_types::BinaryEncodedWasm;
-use ic_wasm_utils::instrumentation::{instrument, InstructionCostTable};
+use ic_wasm_utils::instrumentation::{
+    instrument, instrument_with_write_barriers, InstructionCostTable,
+};
 use lazy_static::lazy_static;
-use maplit::btreemap;
 use proptest::prelude::*;
 use std::collections::BTreeSet;
 use std::sync::Arc;
@@ -40,13 +39,6 @@ fn test_api_for_update(
     subnet_type: SubnetType,
 ) -> SystemApiImpl<ic_system_api::SystemStateAccessorDirect> {
     let caller = caller.unwrap_or_else(|| user_test_id(24).get());
-    let subnet_id = subnet_test_id(1);
-    let routing_table = Arc::new(RoutingTable::new(btreemap! {
-        CanisterIdRange{ start: CanisterId::from(0), end: CanisterId::from(0xff) } => subnet_id,
-    }));
-    let subnet_records = Arc::new(btreemap! {
-        subnet_id => subnet_type,
-    });
     let system_state = SystemStateBuilder::default().build();
     let cycles_account_manager = Arc::new(
         CyclesAccountManagerBuilder::new()
@@ -59,17 +51,13 @@ fn test_api_for_update(
     let system_state_accessor =
         ic_system_api::SystemStateAccessorDirect::new(system_state, cycles_account_manager);
     SystemApiImpl::new(
-        ApiType::update(
-            mock_time(),
-            payload,
-            Cycles::from(0),
-            caller,
-            call_context_test_id(13),
-            subnet_id,
-            subnet_type,
-            routing_table,
-            subnet_records,
-        ),
+        ApiTypeBuilder::new()
+            .with_incoming_payload(payload)
+            .with_caller(caller)
+            .with_call_context_id(call_context_test_id(13))
+            .with_own_subnet_id(subnet_test_id(1))
+            .with_own_subnet_type(subnet_type)
+            .build_update(),
         system_state_accessor,
         canister_current_memory_usage,
         ExecutionParameters {
@@ -77,6 +65,8 @@ fn test_api_for_update(
             canister_memory_limit,
             subnet_available_memory: MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             compute_allocation: ComputeAllocation::default(),
+            canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+            root_key: vec![],
         },
         log,
     )
@@ -121,10 +111,37 @@ fn make_module_wat(heap_size: usize) -> String {
         )
       )
 
+      ;; write to memory using store instructions only: the payload is
+      ;; copied to heap[4;size] first, and moved to heap[addr;size] byte by
+      ;; byte, zeroing heap[4;size] again
+      (func $write_bytes_with_stores
+        (local $addr i32) (local $size i32) (local $i i32)
+        (call $ic0_msg_arg_data_copy
+          (i32.const 0) ;; dst
+          (i32.const 0) ;; off
+          (i32.const 4) ;; len
+        )
+        (local.set $addr (i32.load (i32.const 0)))
+        (local.set $size (i32.sub (call $ic0_msg_arg_data_size) (i32.const 4)))
+        (call $ic0_msg_arg_data_copy (i32.const 4) (i32.const 4) (local.get $size))
+        (block $done
+          (loop $copy
+            (br_if $done (i32.ge_u (local.get $i) (local.get $size)))
+            (i32.store8
+              (i32.add (local.get $addr) (local.get $i))
+              (i32.load8_u (i32.add (i32.const 4) (local.get $i))))
+            (i32.store8 (i32.add (i32.const 4) (local.get $i)) (i32.const 0))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $copy)
+          )
+        )
+      )
+
       (memory $memory {})
       (export "memory" (memory $memory))
       (export "canister_query dump_heap" (func $dump_heap))
       (export "canister_update write_bytes" (func $write_bytes))
+      (export "canister_update write_bytes_with_stores" (func $write_bytes_with_stores))
     )"#,
         heap_size
     )
@@ -150,7 +167,12 @@ fn random_writes(heap_size: usize, num_writes: usize) -> impl Strategy<Value = V
     prop::collection::vec(write_strategy, 1..num_writes)
 }
 
-fn write_bytes(inst: &mut WasmtimeInstance, dst: u32, bytes: &[u8]) -> InstanceRunResult {
+fn write_bytes(
+    inst: &mut WasmtimeInstance,
+    method: &str,
+    dst: u32,
+    bytes: &[u8],
+) -> InstanceRunResult {
     println!(
         "write_bytes(dst: {}, page: {}, bytes: {:?})",
         dst,
@@ -163,7 +185,7 @@ fn write_bytes(inst: &mut WasmtimeInstance, dst: u32, bytes: &[u8]) -> InstanceR
     let mut api = test_api_for_update(no_op_logger(), None, payload, SubnetType::Application);
     inst.run(
         &mut api,
-        FuncRef::Method(WasmMethod::Update("write_bytes".to_string())),
+        FuncRef::Method(WasmMethod::Update(method.to_string())),
     )
     .expect("call to write_bytes failed")
 }
@@ -177,6 +199,8 @@ fn buf_apply_write(heap: &mut Vec<u8>, write: &Write) {
 
 const TEST_HEAP_SIZE_BYTES: usize = WASM_PAGE_SIZE_BYTES * TEST_NUM_PAGES;
 const TEST_NUM_PAGES: usize = 800;
+// Small enough for all OS pages to fit into the write barrier bitmap.
+const TEST_NUM_PAGES_WRITE_BARRIERS: usize = 16;
 const TEST_NUM_WRITES: usize = 2000;
 const WASM_PAGE_SIZE_BYTES: usize = 65536;
 const BYTES_PER_INSTRUCTION: usize = 1;
@@ -198,15 +222,29 @@ mod tests {
     use memory_tracker::DirtyPageTracking;
     use proptest::strategy::ValueTree;
 
-    fn apply_writes_and_check_heap(writes: Vec<Write>, dirty_page_tracking: DirtyPageTracking) {
+    // Applies the writes using the given method and checks that the heap
+    // contents and, unless ignored, the dirty pages match the writes. Returns
+    // the dirty pages and the number of instructions consumed by all writes.
+    fn apply_writes_and_check_heap(
+        writes: Vec<Write>,
+        heap_num_pages: usize,
+        method: &str,
+        dirty_page_tracking: DirtyPageTracking,
+    ) -> (Vec<u64>, NumInstructions) {
         with_test_replica_logger(|log| {
-            let wat = make_module_wat(TEST_NUM_PAGES);
+            let heap_size_bytes = heap_num_pages * WASM_PAGE_SIZE_BYTES;
+            let wat = make_module_wat(heap_num_pages);
             let wasm = wat2wasm(&wat).unwrap();
 
-            let output_instrumentation = instrument(&wasm, &InstructionCostTable::new()).unwrap();
+            let output_instrumentation = match dirty_page_tracking {
+                DirtyPageTracking::Barrier => {
+                    instrument_with_write_barriers(&wasm, &InstructionCostTable::new()).unwrap()
+                }
+                _ => instrument(&wasm, &InstructionCostTable::new()).unwrap(),
+            };
 
             // We will perform identical writes to wasm module's heap and this buffer.
-            let mut test_heap = vec![0; TEST_HEAP_SIZE_BYTES];
+            let mut test_heap = vec![0; heap_size_bytes];
             // Use SIGSEGV tracking and later compare against /proc/pic/pagemap.
             let config = Config {
                 persistence_type: PersistenceType::Sigsegv,
@@ -218,6 +256,7 @@ mod tests {
                 .unwrap();
             let mut page_map = PageMap::default();
             let mut dirty_pages: BTreeSet<u64> = BTreeSet::new();
+            let mut num_instructions_consumed = NumInstructions::from(0);
 
             for write in &writes {
                 let mut instance = embedder.new_instance(
@@ -235,7 +274,8 @@ mod tests {
                 buf_apply_write(&mut test_heap, write);
 
                 // Apply the write to the Wasm instance.
-                let result = write_bytes(&mut instance, write.dst, &write.bytes);
+                let result = write_bytes(&mut instance, method, write.dst, &write.bytes);
+                num_instructions_consumed += MAX_NUM_INSTRUCTIONS - instance.get_num_instructions();
 
                 // Compare the written regions.
                 let wasm_heap: &[u8] = unsafe {
@@ -247,39 +287,39 @@ mod tests {
                 let end = start + write.bytes.len();
                 assert_eq!(wasm_heap[start..end], test_heap[start..end]);
 
-                if dirty_page_tracking == DirtyPageTracking::Track {
+                if dirty_page_tracking != DirtyPageTracking::Ignore {
                     dirty_pages.extend(result.dirty_pages.iter().map(|x| x.get()));
 
                     // Verify that wasm heap and test buffer are the same.
-                    let i = result.dirty_pages.last().unwrap().get();
-                    let offset = i as usize * *PAGE_SIZE as usize;
-                    let page1 = unsafe { test_heap.as_ptr().add(offset) };
-                    let page2 = unsafe { wasm_heap.as_ptr().add(offset) };
-                    let pages_match = unsafe {
-                        libc::memcmp(
-                            page1 as *const libc::c_void,
-                            page2 as *const libc::c_void,
-                            *PAGE_SIZE,
-                        )
-                    };
-                    assert!(
-                        pages_match == 0,
-                        "page({}) of test buffer and Wasm heap doesn't match",
-                        i
-                    );
+                    for page in result.dirty_pages.iter() {
+                        let offset = page.get() as usize * *PAGE_SIZE as usize;
+                        let page1 = unsafe { test_heap.as_ptr().add(offset) };
+                        let page2 = unsafe { wasm_heap.as_ptr().add(offset) };
+                        let pages_match = unsafe {
+                            libc::memcmp(
+                                page1 as *const libc::c_void,
+                                page2 as *const libc::c_void,
+                                *PAGE_SIZE,
+                            )
+                        };
+                        assert!(
+                            pages_match == 0,
+                            "page({}) of test buffer and Wasm heap doesn't match",
+                            page.get()
+                        );
+                    }
                     page_map.update(compute_page_delta(&instance, &result.dirty_pages));
                 }
             }
 
-            if dirty_page_tracking == DirtyPageTracking::Track {
-                for i in 0..TEST_NUM_PAGES {
+            let dirty_pages = dirty_pages.iter().cloned().collect::<Vec<u64>>();
+            if dirty_page_tracking != DirtyPageTracking::Ignore {
+                for i in 0..heap_num_pages {
                     let wasm_page = page_map.get_page(PageIndex::new(i as u64));
                     let test_page = &test_heap[i * *PAGE_SIZE..(i + 1) * *PAGE_SIZE];
                     assert_eq!(wasm_page[..], test_page[..]);
                 }
 
-                let sigsegv_dirty_pages = dirty_pages.iter().cloned().collect::<Vec<u64>>();
-
                 let writes_pages: Vec<u64> = {
                     let mut result = BTreeSet::new();
                     // Pre-populate with page(0). This is because despite 0 does
@@ -287,18 +327,24 @@ mod tests {
                     // page(0) by copying the 4-byte value to addr=0.
                     result.insert(0);
                     // Add the target pages.
-                    result.extend(writes.iter().map(|w| w.dst as u64 / *PAGE_SIZE as u64));
+                    result.extend(writes.iter().flat_map(|w| {
+                        let first_page = w.dst as u64 / *PAGE_SIZE as u64;
+                        let last_page =
+                            (w.dst as u64 + w.bytes.len() as u64 - 1) / *PAGE_SIZE as u64;
+                        first_page..=last_page
+                    }));
                     result.iter().cloned().collect()
                 };
 
-                // Check SIGSEGV against expected.
+                // Check the tracked dirty pages against expected.
                 assert_eq!(
-                sigsegv_dirty_pages,
-                writes_pages,
-                "dirty pages returned by SIGSEGV tracking (left) don't match the expected value (right)"
-            );
+                    dirty_pages, writes_pages,
+                    "dirty pages returned by {:?} tracking (left) don't match the expected value (right)",
+                    dirty_page_tracking
+                );
             }
-        });
+            (dirty_pages, num_instructions_consumed)
+        })
     }
 
     fn random_payload() -> Vec<u8> {
@@ -506,7 +552,12 @@ mod tests {
             .filter(|w| !w.bytes.is_empty())
             .cloned()
             .collect();
-        apply_writes_and_check_heap(writes, DirtyPageTracking::Track);
+        apply_writes_and_check_heap(
+            writes,
+            TEST_NUM_PAGES,
+            "write_bytes",
+            DirtyPageTracking::Track,
+        );
     }
 
     #[test]
@@ -523,6 +574,67 @@ mod tests {
             .filter(|w| !w.bytes.is_empty())
             .cloned()
             .collect();
-        apply_writes_and_check_heap(writes, DirtyPageTracking::Ignore);
+        apply_writes_and_check_heap(
+            writes,
+            TEST_NUM_PAGES,
+            "write_bytes",
+            DirtyPageTracking::Ignore,
+        );
+    }
+
+    fn random_small_heap_writes() -> Vec<Write> {
+        let mut runner = proptest::test_runner::TestRunner::deterministic();
+        random_writes(
+            TEST_NUM_PAGES_WRITE_BARRIERS * WASM_PAGE_SIZE_BYTES,
+            TEST_NUM_WRITES,
+        )
+        .new_tree(&mut runner)
+        .unwrap()
+        .current()
+        .iter()
+        .filter(|w| !w.bytes.is_empty())
+        .cloned()
+        .collect()
+    }
+
+    #[test]
+    fn wasmtime_random_memory_writes_write_barriers() {
+        // Writes of the system API are recorded in the bitmap by the host.
+        apply_writes_and_check_heap(
+            random_small_heap_writes(),
+            TEST_NUM_PAGES_WRITE_BARRIERS,
+            "write_bytes",
+            DirtyPageTracking::Barrier,
+        );
+        // Writes of store instructions are recorded by the write barriers.
+        apply_writes_and_check_heap(
+            random_small_heap_writes(),
+            TEST_NUM_PAGES_WRITE_BARRIERS,
+            "write_bytes_with_stores",
+            DirtyPageTracking::Barrier,
+        );
+    }
+
+    #[test]
+    fn write_barriers_match_signal_based_tracking() {
+        for method in &["write_bytes", "write_bytes_with_stores"] {
+            let (tracked_pages, tracked_instructions) = apply_writes_and_check_heap(
+                random_small_heap_writes(),
+                TEST_NUM_PAGES_WRITE_BARRIERS,
+                method,
+                DirtyPageTracking::Track,
+            );
+            let (barrier_pages, barrier_instructions) = apply_writes_and_check_heap(
+                random_small_heap_writes(),
+                TEST_NUM_PAGES_WRITE_BARRIERS,
+                method,
+                DirtyPageTracking::Barrier,
+            );
+            // Both modes must be interchangeable without affecting the
+            // replicated state: the dirty pages are the same and the write
+            // barriers are not charged to the canister.
+            assert_eq!(tracked_pages, barrier_pages);
+            assert_eq!(tracked_instructions, barrier_instructions);
+        }
     }
 }
@@ -1,16 +1,24 @@
+mod canister_export;
+
 use crate::{
     canister_settings::CanisterSettings,
     hypervisor::Hypervisor,
     types::{IngressResponse, Response},
-    util::GOVERNANCE_CANISTER_ID,
+    util::{GOVERNANCE_CANISTER_ID, ROOT_CANISTER_ID},
 };
 use candid::Decode;
+use canister_export::{memory_chunk, memory_chunks, write_chunk, CanisterExport, ExportedModule};
 use ic_base_types::NumSeconds;
+use ic_certified_vars::verify_certified_reply;
 use ic_cow_state::CowMemoryManager;
+use ic_crypto::threshold_sig_public_key_from_der;
+use ic_crypto_sha::Sha256;
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_ic00_types::{
-    CanisterIdRecord, CanisterStatusResultV2, InstallCodeArgs, Method as Ic00Method,
-    SetControllerArgs, UpdateSettingsArgs,
+    CanisterIdRecord, CanisterMemory, CanisterMetricsResult, CanisterRoundMetrics,
+    CanisterStatusResultV2, ExportCanisterArgs, ExportCanisterChunkArgs, ExportCanisterResult,
+    ImportCanisterArgs, ImportCanisterChunkArgs, InstallCodeArgs, Method as Ic00Method,
+    Payload as Ic00Payload, SetControllerArgs, UpdateSettingsArgs, IC_00,
 };
 use ic_interfaces::execution_environment::{
     ExecutionParameters, HypervisorError, IngressHistoryWriter, MessageAcceptanceError,
@@ -18,25 +26,42 @@ use ic_interfaces::execution_environment::{
 use ic_logger::{error, fatal, info, ReplicaLogger};
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
 use ic_replicated_state::{
-    CallOrigin, CanisterState, CanisterStatus, ExecutionState, ReplicatedState, SchedulerState,
-    SystemState,
+    CallOrigin, CanisterState, CanisterStatus, CompilationTrigger, ExecutionState, Global,
+    ReplicatedState, SchedulerState, SystemState,
 };
 use ic_state_layout::{CanisterLayout, CheckpointLayout, RwPolicy};
 use ic_types::{
     ingress::IngressStatus,
     messages::{
-        CanisterInstallMode, Payload, RejectContext, Response as CanisterResponse,
-        StopCanisterContext,
+        CanisterInstallMode, HttpCanisterUpdate, MessageId, Payload, RejectContext,
+        Response as CanisterResponse, StopCanisterContext,
     },
     user_error::{ErrorCode, RejectCode, UserError},
     CanisterId, CanisterStatusType, ComputeAllocation, Cycles, Height, InstallCodeContext,
-    MemoryAllocation, NumBytes, NumInstructions, PrincipalId, SubnetId, Time, UserId,
+    MemoryAllocation, NumBytes, NumInstructions, PrincipalId, PriorityClass, SubnetId, Time,
+    UserId,
 };
 use ic_utils::ic_features::cow_state_feature;
-use ic_wasm_utils::validation::WasmValidationLimits;
+use ic_wasm_utils::{
+    instrumentation::persistent_globals,
+    stable_compat::stable_compat_hash,
+    validation::{WasmAllowLists, WasmValidationLimits},
+};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use std::{collections::BTreeSet, convert::TryFrom, mem, str::FromStr, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    mem,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+/// How long the certificate of an exported canister stays valid for its
+/// import: long enough to update the routing table between export and import.
+/// Imported exports are remembered for as long, so that none is imported twice.
+pub(crate) const MAX_CANISTER_EXPORT_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct InstallCodeResult {
@@ -65,6 +90,42 @@ fn canister_is_empty(canister: &CanisterState) -> bool {
     canister.execution_state.is_none() && canister.system_state.stable_memory_size.get() == 0
 }
 
+/// Returns the values of the persistent globals of `execution_state`, keyed by
+/// the names under which the module exports them.
+fn persistent_global_values(
+    execution_state: &ExecutionState,
+) -> Result<BTreeMap<String, Global>, HypervisorError> {
+    Ok(persistent_globals(&execution_state.wasm_binary)?
+        .into_iter()
+        .filter_map(|(name, position)| {
+            execution_state
+                .exported_globals
+                .get(position)
+                .map(|value| (name, *value))
+        })
+        .collect())
+}
+
+/// Sets the persistent globals of `execution_state` to the values in `values`.
+/// Globals that the new module does not export anymore or whose type changed
+/// keep the value they were initialized with.
+fn restore_persistent_globals(
+    execution_state: &mut ExecutionState,
+    values: &BTreeMap<String, Global>,
+) -> Result<(), HypervisorError> {
+    for (name, position) in persistent_globals(&execution_state.wasm_binary)? {
+        if let (Some(old), Some(new)) = (
+            values.get(&name),
+            execution_state.exported_globals.get_mut(position),
+        ) {
+            if old.type_name() == new.type_name() {
+                *new = *old;
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub(crate) struct CanisterMgrConfig {
     pub(crate) subnet_memory_capacity: NumBytes,
@@ -73,9 +134,12 @@ pub(crate) struct CanisterMgrConfig {
     pub(crate) default_freeze_threshold: NumSeconds,
     pub(crate) max_globals: usize,
     pub(crate) max_functions: usize,
+    pub(crate) wasm_import_allow_list: BTreeMap<String, Vec<String>>,
+    pub(crate) wasm_export_allow_list: Option<Vec<String>>,
     pub(crate) compute_capacity: u64,
     pub(crate) own_subnet_id: SubnetId,
     pub(crate) max_controllers: usize,
+    pub(crate) max_canister_creation_batch_size: u64,
 }
 
 impl CanisterMgrConfig {
@@ -87,8 +151,11 @@ impl CanisterMgrConfig {
         default_freeze_threshold: NumSeconds,
         max_globals: usize,
         max_functions: usize,
+        wasm_import_allow_list: BTreeMap<String, Vec<String>>,
+        wasm_export_allow_list: Option<Vec<String>>,
         own_subnet_id: SubnetId,
         max_controllers: usize,
+        max_canister_creation_batch_size: u64,
         num_cores: usize,
     ) -> Self {
         Self {
@@ -98,11 +165,28 @@ impl CanisterMgrConfig {
             default_freeze_threshold,
             max_globals,
             max_functions,
+            wasm_import_allow_list,
+            wasm_export_allow_list,
             own_subnet_id,
             max_controllers,
+            max_canister_creation_batch_size,
             compute_capacity: 100 * num_cores as u64,
         }
     }
+
+    /// The limits that Wasm modules installed on canisters are validated
+    /// against.
+    fn wasm_validation_limits(&self) -> WasmValidationLimits {
+        WasmValidationLimits {
+            max_globals: self.max_globals,
+            max_functions: self.max_functions,
+            allow_lists: WasmAllowLists::new(
+                &self.wasm_import_allow_list,
+                self.wasm_export_allow_list.as_deref(),
+            ),
+            ..Default::default()
+        }
+    }
 }
 
 /// The entity responsible for managing canisters (creation, installing, etc.)
@@ -168,9 +252,16 @@ impl CanisterManager {
             | Ok(Ic00Method::CreateCanister)
             | Ok(Ic00Method::SetupInitialDKG)
             | Ok(Ic00Method::SignWithECDSA)
+            // The imported canister does not exist yet, so there is no canister
+            // that could pay for the ingress message.
+            | Ok(Ic00Method::ImportCanister)
+            // Only a canister can destroy itself.
+            | Ok(Ic00Method::CanisterSelfDestruct)
             // "DepositCycles" can be called by anyone however as ingress message
             // cannot carry cycles, it does not make sense to allow them from users.
-            | Ok(Ic00Method::DepositCycles) => Err(MessageAcceptanceError::CanisterRejected),
+            | Ok(Ic00Method::DepositCycles)
+            // "CanisterMetrics" is a query, see `get_canister_metrics`.
+            | Ok(Ic00Method::CanisterMetrics) => Err(MessageAcceptanceError::CanisterRejected),
 
             // These methods are only valid if they are sent by the controller
             // of the canister. We assume that the canister always wants to
@@ -195,11 +286,32 @@ impl CanisterManager {
                 Err(_) => Err(MessageAcceptanceError::CanisterRejected),
                 Ok(args) => is_sender_controller(args.get_canister_id(), sender, state),
             },
+            Ok(Ic00Method::ExportCanister) => match Decode!(&payload, ExportCanisterArgs) {
+                Err(_) => Err(MessageAcceptanceError::CanisterRejected),
+                Ok(args) => is_sender_controller(args.get_canister_id(), sender, state),
+            },
+            Ok(Ic00Method::ExportCanisterChunk) => {
+                match Decode!(&payload, ExportCanisterChunkArgs) {
+                    Err(_) => Err(MessageAcceptanceError::CanisterRejected),
+                    Ok(args) => is_sender_controller(args.get_canister_id(), sender, state),
+                }
+            }
+            Ok(Ic00Method::ImportCanisterChunk) => {
+                match Decode!(&payload, ImportCanisterChunkArgs) {
+                    Err(_) => Err(MessageAcceptanceError::CanisterRejected),
+                    Ok(args) => is_sender_controller(args.get_canister_id(), sender, state),
+                }
+            }
 
-            // Nobody pays for `raw_rand`, so this cannot be used via ingress messages
-            Ok(Ic00Method::RawRand) => Err(MessageAcceptanceError::CanisterRejected),
+            // Nobody pays for `raw_rand` and the cost queries, so they cannot be
+            // used via ingress messages
+            Ok(Ic00Method::RawRand)
+            | Ok(Ic00Method::CostCall)
+            | Ok(Ic00Method::CostCreateCanister)
+            | Ok(Ic00Method::CostExecution) => Err(MessageAcceptanceError::CanisterRejected),
 
             Ok(Ic00Method::ProvisionalCreateCanisterWithCycles)
+            | Ok(Ic00Method::ProvisionalCreateCanistersWithCycles)
             | Ok(Ic00Method::ProvisionalTopUpCanister) => {
                 if provisional_whitelist.contains(sender.get_ref()) {
                     Ok(())
@@ -289,13 +401,28 @@ impl CanisterManager {
         if let Some(freezing_threshold) = settings.freezing_threshold {
             canister.system_state.freeze_threshold = freezing_threshold;
         }
+        if let Some(priority_class) = settings.priority_class {
+            canister.scheduler_state.priority_class = priority_class;
+        }
+        if let Some(self_destruct_enabled) = settings.self_destruct_enabled {
+            canister.system_state.self_destruct_enabled = self_destruct_enabled;
+        }
+        if let Some(heartbeat_instruction_limit) = settings.heartbeat_instruction_limit {
+            canister.scheduler_state.heartbeat_instruction_limit =
+                Some(heartbeat_instruction_limit);
+        }
     }
 
     /// Tries to apply the requested settings on the canister identified by
     /// `canister_id`.
+    ///
+    /// `sender_is_on_nns` tells whether the sender is a canister on the NNS
+    /// subnet. Only the governance and root canisters there may change the
+    /// priority class.
     pub(crate) fn update_settings(
         &self,
         sender: PrincipalId,
+        sender_is_on_nns: bool,
         settings: CanisterSettings,
         canister: &mut CanisterState,
         total_subnet_compute_allocation_used: u64,
@@ -303,6 +430,7 @@ impl CanisterManager {
     ) -> Result<(), CanisterManagerError> {
         // Verify controller.
         self.validate_controller(&canister, &sender)?;
+        self.validate_priority_class(sender, sender_is_on_nns, settings.priority_class())?;
         self.validate_compute_allocation(
             total_subnet_compute_allocation_used,
             &canister,
@@ -345,6 +473,14 @@ impl CanisterManager {
             );
         }
 
+        if let Err(err) = self.validate_priority_class(
+            sender,
+            sender_subnet_id == state.metadata.network_topology.nns_subnet_id,
+            settings.priority_class(),
+        ) {
+            return (Err(err), cycles);
+        }
+
         if cycles < self.cycles_account_manager.canister_creation_fee() {
             return (
                 Err(CanisterManagerError::CreateCanisterNotEnoughCycles {
@@ -378,6 +514,46 @@ impl CanisterManager {
         }
     }
 
+    /// Returns `settings` with the freezing threshold, memory allocation and
+    /// heartbeat instruction limit taken from the template canister
+    /// `template_id` if they are not set.
+    ///
+    /// The template canister must be controlled by `sender`. Controllers and
+    /// the compute allocation are never inherited: the former default to the
+    /// sender and the latter is a scarce subnet resource that should only be
+    /// reserved explicitly. The same holds for the priority class and for
+    /// allowing the canister to destroy itself.
+    pub(crate) fn settings_from_template(
+        &self,
+        sender: PrincipalId,
+        template_id: CanisterId,
+        settings: CanisterSettings,
+        state: &ReplicatedState,
+    ) -> Result<CanisterSettings, CanisterManagerError> {
+        let template = state
+            .canister_state(&template_id)
+            .ok_or(CanisterManagerError::CanisterNotFound(template_id))?;
+        self.validate_controller(template, &sender)?;
+        Ok(CanisterSettings::new(
+            settings.controller(),
+            settings.controllers(),
+            settings.compute_allocation(),
+            settings
+                .memory_allocation()
+                .or_else(|| Some(template.memory_allocation())),
+            settings
+                .freezing_threshold()
+                .or(Some(template.system_state.freeze_threshold)),
+        )
+        .with_priority_class(settings.priority_class())
+        .with_self_destruct_enabled(settings.self_destruct_enabled())
+        .with_heartbeat_instruction_limit(
+            settings
+                .heartbeat_instruction_limit()
+                .or(template.scheduler_state.heartbeat_instruction_limit),
+        ))
+    }
+
     /// Installs code to a canister.
     ///
     /// Only the controller of the canister can install code.
@@ -440,6 +616,14 @@ impl CanisterManager {
         if let Err(err) = self.validate_controller(&old_canister, &context.sender) {
             return (execution_parameters.instruction_limit, Err(err));
         }
+        if let Err(err) = self.validate_import_complete(&old_canister) {
+            return (execution_parameters.instruction_limit, Err(err));
+        }
+        if context.mode == CanisterInstallMode::Upgrade && !context.skip_stable_compat_check {
+            if let Err(err) = self.validate_stable_compat(&old_canister, &context.wasm_module) {
+                return (execution_parameters.instruction_limit, Err(err));
+            }
+        }
         match context.mode {
             CanisterInstallMode::Install => {
                 if !canister_is_empty(old_canister) {
@@ -567,6 +751,83 @@ impl CanisterManager {
         Ok(())
     }
 
+    /// Uninstalls the code of a canister on its own request and moves its
+    /// remaining cycles, together with the `cycles` attached to the request,
+    /// to `beneficiary`.
+    ///
+    /// Only the canister itself can request this, and only if its controllers
+    /// enabled it in the canister settings. The beneficiary must be another
+    /// canister on this subnet. Returns the cycles to refund to the sender,
+    /// which are all of `cycles` if the request is rejected.
+    pub(crate) fn self_destruct(
+        &self,
+        canister_id: CanisterId,
+        beneficiary: CanisterId,
+        sender: PrincipalId,
+        cycles: Cycles,
+        state: &mut ReplicatedState,
+    ) -> (Result<(), CanisterManagerError>, Cycles) {
+        if sender != canister_id.get() {
+            return (
+                Err(CanisterManagerError::SelfDestructNotBySelf {
+                    canister_id,
+                    sender,
+                }),
+                cycles,
+            );
+        }
+        if beneficiary == canister_id || state.canister_state(&beneficiary).is_none() {
+            return (
+                Err(CanisterManagerError::SelfDestructInvalidBeneficiary {
+                    canister_id,
+                    beneficiary,
+                }),
+                cycles,
+            );
+        }
+
+        let time = state.time();
+        let path = state.path().to_owned();
+        let canister = match state.canister_state_mut(&canister_id) {
+            Some(canister) => canister,
+            None => {
+                return (
+                    Err(CanisterManagerError::CanisterNotFound(canister_id)),
+                    cycles,
+                )
+            }
+        };
+        if !canister.system_state.self_destruct_enabled {
+            return (
+                Err(CanisterManagerError::SelfDestructNotEnabled(canister_id)),
+                cycles,
+            );
+        }
+
+        let rejects = uninstall_canister(&self.log, canister, &path, time);
+        let remaining_cycles =
+            std::mem::replace(&mut canister.system_state.cycles_balance, Cycles::zero()) + cycles;
+        crate::util::process_responses(rejects, state, Arc::clone(&self.ingress_history_writer));
+
+        // The beneficiary exists, as checked above. Cycles that exceed its
+        // maximum balance stay with the uninstalled canister.
+        let not_deposited = self.deposit_cycles(
+            state.canister_state_mut(&beneficiary).unwrap(),
+            remaining_cycles,
+        );
+        if let Some(canister) = state.canister_state_mut(&canister_id) {
+            canister.system_state.cycles_balance += not_deposited;
+        }
+        info!(
+            self.log,
+            "Canister {} destroyed itself and moved {} cycles to {}",
+            canister_id,
+            remaining_cycles - not_deposited,
+            beneficiary
+        );
+        (Ok(()), Cycles::zero())
+    }
+
     /// Signals a canister to stop.
     ///
     /// If the canister is running, then the canister is marked as "stopping".
@@ -652,6 +913,7 @@ impl CanisterManager {
         canister: &mut CanisterState,
     ) -> Result<Vec<StopCanisterContext>, CanisterManagerError> {
         self.validate_controller(&canister, &sender)?;
+        self.validate_import_complete(&canister)?;
 
         let stop_contexts = match &mut canister.system_state.status {
             CanisterStatus::Stopping { stop_contexts, .. } => mem::replace(stop_contexts, vec![]),
@@ -727,6 +989,7 @@ impl CanisterManager {
         let settings = CanisterSettings::new(Some(new_controller), None, None, None, None);
         self.update_settings(
             sender,
+            false,
             settings,
             canister,
             compute_allocation_used,
@@ -765,47 +1028,311 @@ impl CanisterManager {
 
         self.validate_canister_is_stopped(&canister_to_delete)?;
 
-        // Once a canister is stopped, it stops accepting new messages, so this should
-        // never happen.
-        assert!(
-            !canister_to_delete.has_input(),
-            "Trying to delete canister {} while having messages in its input queue.",
-            canister_to_delete.canister_id()
+        // When a canister is deleted:
+        // - its state is permanently deleted, and
+        // - its cycles are discarded.
+        self.remove_stopped_canister(canister_id_to_delete, state);
+        Ok(())
+    }
+
+    /// Returns the chunk at `offset` of a memory of a canister that is about
+    /// to be exported with `export_canister`.
+    ///
+    /// The canister must be `Stopped` and only its controllers can read it.
+    pub(crate) fn export_canister_chunk(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        memory: CanisterMemory,
+        offset: u64,
+        state: &ReplicatedState,
+    ) -> Result<Vec<u8>, CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        self.validate_controller(&canister, &sender)?;
+        if canister.status() != CanisterStatusType::Stopped {
+            return Err(CanisterManagerError::ExportCanisterNotStopped(canister_id));
+        }
+
+        let invalid_export = |message| CanisterManagerError::InvalidCanisterExport {
+            canister_id,
+            message,
+        };
+        let (page_map, size_in_wasm_pages) = match memory {
+            CanisterMemory::Wasm => match &canister.execution_state {
+                Some(execution_state) => (
+                    &execution_state.page_map,
+                    execution_state.heap_size.get() as u64,
+                ),
+                None => return Err(invalid_export("the canister has no Wasm memory".into())),
+            },
+            CanisterMemory::Stable => (
+                &canister.system_state.stable_memory,
+                canister.system_state.stable_memory_size.get(),
+            ),
+        };
+        memory_chunk(page_map, size_in_wasm_pages, offset).map_err(invalid_export)
+    }
+
+    /// Exports a canister so that it can be imported on `target_subnet` with
+    /// `import_canister`.
+    ///
+    /// The canister must be `Stopped` and only its controllers can export it.
+    /// The canister is removed from this subnet in the same step, so that its
+    /// cycles never exist on both subnets. Its memories must therefore be read
+    /// with `export_canister_chunk` beforehand; the export only lists the
+    /// hashes of their chunks.
+    pub(crate) fn export_canister(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        target_subnet: SubnetId,
+        state: &mut ReplicatedState,
+    ) -> Result<CanisterExport, CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        self.validate_controller(&canister, &sender)?;
+        if canister.status() != CanisterStatusType::Stopped {
+            return Err(CanisterManagerError::ExportCanisterNotStopped(canister_id));
+        }
+        if target_subnet == self.config.own_subnet_id
+            || !state
+                .metadata
+                .network_topology
+                .subnets
+                .contains_key(&target_subnet)
+        {
+            return Err(CanisterManagerError::InvalidCanisterExport {
+                canister_id,
+                message: format!("{} is not a valid target subnet", target_subnet),
+            });
+        }
+
+        let mut chunks = Vec::new();
+        let module = canister.execution_state.as_ref().map(|execution_state| {
+            chunks.extend(memory_chunks(
+                &execution_state.page_map,
+                execution_state.heap_size.get() as u64,
+                false,
+            ));
+            ExportedModule {
+                wasm_module_hash: execution_state.wasm_binary.hash_sha256(),
+                heap_size: execution_state.heap_size.get(),
+                exported_globals: execution_state.exported_globals.clone(),
+            }
+        });
+        let system_state = &canister.system_state;
+        chunks.extend(memory_chunks(
+            &system_state.stable_memory,
+            system_state.stable_memory_size.get(),
+            true,
+        ));
+        let export = CanisterExport {
+            canister_id,
+            target_subnet,
+            controllers: system_state.controllers.clone(),
+            compute_allocation: canister.scheduler_state.compute_allocation,
+            memory_allocation: system_state.memory_allocation,
+            freeze_threshold: system_state.freeze_threshold.get(),
+            cycles_balance: system_state.cycles_balance,
+            certified_data: system_state.certified_data.clone(),
+            priority_class: canister.scheduler_state.priority_class,
+            heartbeat_instruction_limit: canister.scheduler_state.heartbeat_instruction_limit,
+            self_destruct_enabled: system_state.self_destruct_enabled,
+            module,
+            stable_memory_size: system_state.stable_memory_size.get(),
+            chunks,
+        };
+
+        self.remove_stopped_canister(canister_id, state);
+
+        info!(
+            self.log,
+            "Successfully exported canister, canister_id: {}, target_subnet: {}",
+            canister_id,
+            target_subnet.get()
         );
 
-        // This scenario should be impossible because:
-        //
-        // 1) A stopped canister does not accept new messages.
-        //
-        // 2) A canister is transitioned to a stopped state at the end of a round.
-        //
-        // 3) All output messages are cleared by the `StreamBuilder` at the end of
-        //    every round.
-        //
-        // Because the canister is already stopped, it must have been stopped in a
-        // previous round (2), had its output queued emptied in a previous round (3),
-        // and the output queue is still empty because it didn't accept any new
-        // messages (1).
-        assert!(
-            !canister_to_delete.has_output(),
-            "Trying to delete canister {} while having messages in its output queue.",
-            canister_to_delete.canister_id()
+        Ok(export)
+    }
+
+    /// Imports a canister exported on another subnet with `export_canister`.
+    ///
+    /// `args.certificate` must be a certificate of the source subnet for the
+    /// reply to `args.export_request`, the ingress message that exported the
+    /// canister to this subnet. An export can only be imported once, and only
+    /// by one of the controllers it records. The canister must be routed to
+    /// this subnet and must not exist yet.
+    ///
+    /// The imported canister is `Stopped` and its memories are empty until all
+    /// their chunks are transferred with `import_canister_chunk`.
+    pub(crate) fn import_canister(
+        &self,
+        sender: PrincipalId,
+        args: ImportCanisterArgs,
+        state: &mut ReplicatedState,
+    ) -> Result<CanisterId, CanisterManagerError> {
+        let canister_id = args.get_canister_id();
+        let (export, message_id, certificate_time) = self.verify_canister_export(&args, state)?;
+        if !export.controllers.contains(&sender) {
+            return Err(CanisterManagerError::CanisterInvalidController {
+                canister_id,
+                controllers_expected: export.controllers,
+                controller_provided: sender,
+            });
+        }
+        let subnet_id = self.config.own_subnet_id;
+        if state
+            .metadata
+            .network_topology
+            .routing_table
+            .route(canister_id.get())
+            != Some(subnet_id)
+        {
+            return Err(CanisterManagerError::CanisterNotHostedBySubnet {
+                canister_id,
+                subnet_id,
+            });
+        }
+        self.validate_canister_id_available(&state, &canister_id)?;
+
+        let invalid_export = |message: &str| CanisterManagerError::InvalidCanisterExport {
+            canister_id,
+            message: message.to_string(),
+        };
+        let layout = canister_layout(state.path(), &canister_id);
+        let execution_state = match &export.module {
+            None => {
+                if !args.wasm_module.is_empty() {
+                    return Err(invalid_export("the exported canister has no module"));
+                }
+                None
+            }
+            Some(module) => {
+                if Sha256::hash(&args.wasm_module) != module.wasm_module_hash {
+                    return Err(invalid_export(
+                        "the Wasm module does not match the exported module hash",
+                    ));
+                }
+                let mut execution_state = ExecutionState::new(
+                    args.wasm_module,
+                    layout.raw_path(),
+                    self.config.wasm_validation_limits(),
+                )
+                .map_err(|err| CanisterManagerError::from((canister_id, err)))?;
+                execution_state.heap_size = module.heap_size();
+                execution_state.exported_globals = module.exported_globals.clone();
+                Some(execution_state)
+            }
+        };
+        let mut system_state = SystemState::new_stopped(
+            canister_id,
+            sender,
+            export.cycles_balance,
+            export.freeze_threshold(),
         );
+        system_state.controllers = export.controllers.clone();
+        system_state.memory_allocation = export.memory_allocation;
+        system_state.certified_data = export.certified_data.clone();
+        system_state.self_destruct_enabled = export.self_destruct_enabled;
+        system_state.stable_memory_size = export.stable_memory_size();
+        system_state.pending_import_chunks = export.chunks.clone();
+        let scheduler_state = SchedulerState {
+            priority_class: export.priority_class,
+            heartbeat_instruction_limit: export.heartbeat_instruction_limit,
+            ..SchedulerState::default()
+        };
+        let mut canister = CanisterState::new(system_state, execution_state, scheduler_state);
 
-        // When a canister is deleted:
-        // - its state is permanently deleted, and
-        // - its cycles are discarded.
+        self.validate_compute_allocation(
+            state.total_compute_allocation(),
+            &canister,
+            Some(export.compute_allocation),
+        )?;
+        canister.scheduler_state.compute_allocation = export.compute_allocation;
+        let memory_taken = match export.memory_allocation {
+            MemoryAllocation::Reserved(bytes) => {
+                if bytes < canister.memory_usage() {
+                    return Err(CanisterManagerError::NotEnoughMemoryAllocationGiven {
+                        canister_id,
+                        memory_allocation_given: export.memory_allocation,
+                        memory_usage_needed: canister.memory_usage(),
+                    });
+                }
+                bytes
+            }
+            MemoryAllocation::BestEffort => canister.memory_usage(),
+        };
+        let total_memory_taken = state.total_memory_taken();
+        if total_memory_taken + memory_taken > self.config.subnet_memory_capacity {
+            return Err(CanisterManagerError::SubnetMemoryCapacityOverSubscribed {
+                requested: memory_taken,
+                available: self.config.subnet_memory_capacity - total_memory_taken,
+            });
+        }
 
-        // Take out the canister from `ReplicatedState`.
-        let _canister_to_delete = state.take_canister_state(&canister_id_to_delete).unwrap();
+        state.put_canister_state(canister);
+        self.record_imported_canister_export(message_id, certificate_time, state);
 
-        let layout = canister_layout(state.path(), &canister_id_to_delete);
-        layout
-            .mark_deleted()
-            .expect("failed to mark canister as deleted on the filesystem");
+        info!(
+            self.log,
+            "Successfully imported canister, canister_id: {}, subnet_id: {}",
+            canister_id,
+            subnet_id.get()
+        );
+
+        Ok(canister_id)
+    }
+
+    /// Writes a chunk of a memory of a canister imported with
+    /// `import_canister`.
+    ///
+    /// Only the controllers of the canister can write chunks, and only the
+    /// chunks listed in the export, each of them once.
+    pub(crate) fn import_canister_chunk(
+        &self,
+        sender: PrincipalId,
+        args: ImportCanisterChunkArgs,
+        state: &mut ReplicatedState,
+    ) -> Result<(), CanisterManagerError> {
+        let canister_id = args.get_canister_id();
+        let canister = match state.canister_state_mut(&canister_id) {
+            Some(canister) => canister,
+            None => return Err(CanisterManagerError::CanisterNotFound(canister_id)),
+        };
+        self.validate_controller(&canister, &sender)?;
 
-        // The canister has now been removed from `ReplicatedState` and is dropped
-        // once the function is out of scope.
+        let stable_memory = args.memory == CanisterMemory::Stable;
+        let hash = Sha256::hash(&args.bytes);
+        let position = canister
+            .system_state
+            .pending_import_chunks
+            .iter()
+            .position(|chunk| {
+                chunk.stable_memory == stable_memory
+                    && chunk.offset == args.offset
+                    && chunk.hash == hash
+            })
+            .ok_or_else(|| CanisterManagerError::InvalidCanisterExport {
+                canister_id,
+                message: format!(
+                    "no pending chunk at offset {} matches the given bytes",
+                    args.offset
+                ),
+            })?;
+        let page_map = if stable_memory {
+            &mut canister.system_state.stable_memory
+        } else {
+            match canister.execution_state.as_mut() {
+                Some(execution_state) => &mut execution_state.page_map,
+                None => {
+                    return Err(CanisterManagerError::InvalidCanisterExport {
+                        canister_id,
+                        message: "the canister has no Wasm memory".to_string(),
+                    })
+                }
+            }
+        };
+        write_chunk(page_map, args.offset, &args.bytes);
+        canister.system_state.pending_import_chunks.remove(position);
         Ok(())
     }
 
@@ -837,12 +1364,12 @@ impl CanisterManager {
         let execution_state = match ExecutionState::new(
             context.wasm_module,
             layout.raw_path(),
-            WasmValidationLimits {
-                max_globals: self.config.max_globals,
-                max_functions: self.config.max_functions,
-            },
+            self.config.wasm_validation_limits(),
         ) {
-            Ok(execution_state) => Some(execution_state),
+            Ok(mut execution_state) => {
+                execution_state.pending_compilation_trigger = Some(CompilationTrigger::Install);
+                Some(execution_state)
+            }
             Err(err) => {
                 return (
                     execution_parameters.instruction_limit,
@@ -959,6 +1486,17 @@ impl CanisterManager {
             Err(err) => return (instructions_limit, Err((canister_id, err).into())),
         }
 
+        // The persistent globals are carried over to the new module.
+        let persistent_globals = match new_canister
+            .execution_state
+            .as_ref()
+            .map(persistent_global_values)
+            .transpose()
+        {
+            Ok(values) => values.unwrap_or_default(),
+            Err(err) => return (instructions_limit, Err((canister_id, err).into())),
+        };
+
         // Wipe the heap first
         if cow_state_feature::is_enabled(cow_state_feature::cow_state) {
             new_canister
@@ -974,13 +1512,13 @@ impl CanisterManager {
         new_canister.execution_state = match ExecutionState::new(
             context.wasm_module,
             layout.raw_path(),
-            WasmValidationLimits {
-                max_globals: self.config.max_globals,
-                max_functions: self.config.max_functions,
-            },
+            self.config.wasm_validation_limits(),
         ) {
             Err(err) => return (instructions_limit, Err((canister_id, err).into())),
-            Ok(execution_state) => Some(execution_state),
+            Ok(mut execution_state) => {
+                execution_state.pending_compilation_trigger = Some(CompilationTrigger::Upgrade);
+                Some(execution_state)
+            }
         };
 
         let (mut new_canister, result) = self.hypervisor.execute_empty(new_canister);
@@ -988,6 +1526,11 @@ impl CanisterManager {
             Ok(()) => (),
             Err(err) => return (instructions_limit, Err((canister_id, err).into())),
         }
+        if let Some(execution_state) = new_canister.execution_state.as_mut() {
+            if let Err(err) = restore_persistent_globals(execution_state, &persistent_globals) {
+                return (instructions_limit, Err((canister_id, err).into()));
+            }
+        }
 
         // Update allocations.  This must happen after we have created the new
         // execution state so that we fairly account for the memory requirements
@@ -1068,6 +1611,9 @@ impl CanisterManager {
             return Err(CanisterManagerError::SenderNotInWhitelist(sender));
         }
 
+        // Provisional creation is not a governance path, whoever the sender is.
+        self.validate_priority_class(sender, false, settings.priority_class())?;
+
         let cycles = match cycles_amount {
             Some(cycles_amount) => Cycles::from(cycles_amount),
             None => self.config.default_provisional_cycles_balance,
@@ -1102,27 +1648,9 @@ impl CanisterManager {
         let new_canister_id = self.generate_new_canister_id(state)?;
         self.validate_canister_id_available(&state, &new_canister_id)?;
 
-        // Take the fee out of the cycles that are going to be added as the canister's
-        // initial balance.
-        let mut cycles = cycles - creation_fee;
-        cycles = self
-            .cycles_account_manager
-            .check_max_cycles_can_add(Cycles::from(0), cycles);
-
         // Canister id available. Create the new canister.
-        let mut system_state = SystemState::new_running(
-            new_canister_id,
-            sender,
-            cycles,
-            self.config.default_freeze_threshold,
-        );
-
-        self.cycles_account_manager
-            .observe_consumed_cycles(&mut system_state, creation_fee);
-        let scheduler_state = SchedulerState::default();
-        let mut new_canister = CanisterState::new(system_state, None, scheduler_state);
-
-        self.do_update_settings(settings, &mut new_canister);
+        let new_canister =
+            self.new_canister_state(new_canister_id, sender, cycles, creation_fee, settings);
 
         // Add new canister to the replicated state.
         state.put_canister_state(new_canister);
@@ -1137,7 +1665,142 @@ impl CanisterManager {
         Ok(new_canister_id)
     }
 
-    /// Adds cycles to the canister.
+    /// Creates `count` canisters with the cycles amount and the settings
+    /// specified and inserts them into `ReplicatedState`.
+    ///
+    /// Like `create_canister_with_cycles`, this method is meant to only be
+    /// invoked by a list of whitelisted principals, e.g., to set up load tests
+    /// on test and system subnets. The canister ids are allocated in one go
+    /// and either all canisters are created or none is.
+    ///
+    /// Returns the auto-generated ids of the new canisters in the order they
+    /// were allocated.
+    pub(crate) fn create_canisters_with_cycles(
+        &self,
+        sender: PrincipalId,
+        count: u64,
+        cycles_amount: Option<u64>,
+        settings: CanisterSettings,
+        state: &mut ReplicatedState,
+        provisional_whitelist: &ProvisionalWhitelist,
+    ) -> Result<Vec<CanisterId>, CanisterManagerError> {
+        if !provisional_whitelist.contains(&sender) {
+            return Err(CanisterManagerError::SenderNotInWhitelist(sender));
+        }
+
+        if count == 0 || count > self.config.max_canister_creation_batch_size {
+            return Err(CanisterManagerError::InvalidCanisterCreationBatchSize {
+                requested: count,
+                max: self.config.max_canister_creation_batch_size,
+            });
+        }
+
+        self.validate_priority_class(sender, false, settings.priority_class())?;
+
+        let cycles = match cycles_amount {
+            Some(cycles_amount) => Cycles::from(cycles_amount),
+            None => self.config.default_provisional_cycles_balance,
+        };
+
+        let total_subnet_compute_allocation_used = state.total_compute_allocation();
+        let total_subnet_memory_taken = state.total_memory_taken();
+        let settings = self.validate_settings(
+            settings,
+            total_subnet_compute_allocation_used,
+            total_subnet_memory_taken,
+        )?;
+        // The settings apply to every canister of the batch, so the subnet
+        // must have the capacity for all of them.
+        if let Some(memory_allocation) = settings.memory_allocation {
+            let requested = memory_allocation.bytes().get().saturating_mul(count);
+            if requested.saturating_add(total_subnet_memory_taken.get())
+                > self.config.subnet_memory_capacity.get()
+            {
+                return Err(CanisterManagerError::SubnetMemoryCapacityOverSubscribed {
+                    requested: NumBytes::from(requested),
+                    available: self.config.subnet_memory_capacity - total_subnet_memory_taken,
+                });
+            }
+        }
+        if let Some(compute_allocation) = settings.compute_allocation {
+            let requested = compute_allocation.as_percent().saturating_mul(count);
+            if requested.saturating_add(total_subnet_compute_allocation_used)
+                >= self.config.compute_capacity
+            {
+                return Err(
+                    CanisterManagerError::SubnetComputeCapacityOverSubscribedByBatch {
+                        count,
+                        requested,
+                        available: self.config.compute_capacity
+                            - total_subnet_compute_allocation_used
+                            - 1,
+                    },
+                );
+            }
+        }
+
+        let new_canister_ids = self.generate_new_canister_ids(state, count)?;
+        for new_canister_id in &new_canister_ids {
+            self.validate_canister_id_available(&state, new_canister_id)?;
+        }
+
+        // No creation fee applied.
+        for new_canister_id in &new_canister_ids {
+            let new_canister = self.new_canister_state(
+                *new_canister_id,
+                sender,
+                cycles,
+                Cycles::new(0),
+                settings.clone(),
+            );
+            state.put_canister_state(new_canister);
+        }
+
+        info!(
+            self.log,
+            "Successfully created {} canisters, first canister_id: {}, subnet_id: {}",
+            count,
+            new_canister_ids[0].to_string(),
+            self.config.own_subnet_id.get()
+        );
+
+        Ok(new_canister_ids)
+    }
+
+    /// Returns the state of a new canister with the given id and settings.
+    /// The creation fee is taken out of `cycles`.
+    fn new_canister_state(
+        &self,
+        canister_id: CanisterId,
+        sender: PrincipalId,
+        cycles: Cycles,
+        creation_fee: Cycles,
+        settings: ValidatedCanisterSettings,
+    ) -> CanisterState {
+        // Take the fee out of the cycles that are going to be added as the canister's
+        // initial balance.
+        let mut cycles = cycles - creation_fee;
+        cycles = self
+            .cycles_account_manager
+            .check_max_cycles_can_add(Cycles::from(0), cycles);
+
+        let mut system_state = SystemState::new_running(
+            canister_id,
+            sender,
+            cycles,
+            self.config.default_freeze_threshold,
+        );
+
+        self.cycles_account_manager
+            .observe_consumed_cycles(&mut system_state, creation_fee);
+        let scheduler_state = SchedulerState::default();
+        let mut new_canister = CanisterState::new(system_state, None, scheduler_state);
+
+        self.do_update_settings(settings, &mut new_canister);
+        new_canister
+    }
+
+    /// Adds cycles to the canister.
     pub(crate) fn add_cycles(
         &self,
         sender: PrincipalId,
@@ -1175,6 +1838,50 @@ impl CanisterManager {
         Ok(())
     }
 
+    // An upgrade preserves stable memory, so the new module must be able to
+    // read what the installed module wrote. If both modules declare their
+    // stable memory schema, the declarations must be equal.
+    fn validate_stable_compat(
+        &self,
+        canister: &CanisterState,
+        wasm_module: &[u8],
+    ) -> Result<(), CanisterManagerError> {
+        let installed_hash = match canister
+            .execution_state
+            .as_ref()
+            .and_then(|execution_state| stable_compat_hash(execution_state.wasm_binary.as_slice()))
+        {
+            Some(hash) => hash,
+            None => return Ok(()),
+        };
+        match stable_compat_hash(wasm_module) {
+            Some(new_hash) if new_hash != installed_hash => {
+                Err(CanisterManagerError::StableMemoryIncompatible {
+                    canister_id: canister.canister_id(),
+                    installed_hash,
+                    new_hash,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Only the governance and root canisters on the NNS subnet may change the
+    // priority class of a canister.
+    fn validate_priority_class(
+        &self,
+        sender: PrincipalId,
+        sender_is_on_nns: bool,
+        priority_class: Option<PriorityClass>,
+    ) -> Result<(), CanisterManagerError> {
+        let sender_is_nns_governance = sender_is_on_nns
+            && (sender == GOVERNANCE_CANISTER_ID.get() || sender == ROOT_CANISTER_ID.get());
+        if priority_class.is_some() && !sender_is_nns_governance {
+            return Err(CanisterManagerError::PriorityClassNotAllowed(sender));
+        }
+        Ok(())
+    }
+
     fn validate_canister_id_available(
         &self,
         state: &ReplicatedState,
@@ -1251,6 +1958,158 @@ impl CanisterManager {
         Ok(())
     }
 
+    // Removes a stopped canister from `ReplicatedState` and marks its state on
+    // the filesystem as deleted.
+    fn remove_stopped_canister(&self, canister_id: CanisterId, state: &mut ReplicatedState) {
+        let canister = state.take_canister_state(&canister_id).unwrap();
+
+        // Once a canister is stopped, it stops accepting new messages, so this should
+        // never happen.
+        assert!(
+            !canister.has_input(),
+            "Trying to remove canister {} while having messages in its input queue.",
+            canister_id
+        );
+
+        // This scenario should be impossible because:
+        //
+        // 1) A stopped canister does not accept new messages.
+        //
+        // 2) A canister is transitioned to a stopped state at the end of a round.
+        //
+        // 3) All output messages are cleared by the `StreamBuilder` at the end of
+        //    every round.
+        //
+        // Because the canister is already stopped, it must have been stopped in a
+        // previous round (2), had its output queued emptied in a previous round (3),
+        // and the output queue is still empty because it didn't accept any new
+        // messages (1).
+        assert!(
+            !canister.has_output(),
+            "Trying to remove canister {} while having messages in its output queue.",
+            canister_id
+        );
+
+        let layout = canister_layout(state.path(), &canister_id);
+        layout
+            .mark_deleted()
+            .expect("failed to mark canister as deleted on the filesystem");
+    }
+
+    // Checks that `args` carry a certificate of the source subnet for the
+    // reply to an `export_canister` call that exported `args.canister_id` to
+    // this subnet, and that this export was not imported before.
+    //
+    // Returns the export together with the ID of the ingress message that
+    // exported the canister and the time of the certificate.
+    fn verify_canister_export(
+        &self,
+        args: &ImportCanisterArgs,
+        state: &ReplicatedState,
+    ) -> Result<(CanisterExport, MessageId, Time), CanisterManagerError> {
+        let canister_id = args.get_canister_id();
+        let own_subnet_id = self.config.own_subnet_id;
+        let invalid_export = |message: String| CanisterManagerError::InvalidCanisterExport {
+            canister_id,
+            message,
+        };
+
+        let source_subnet = args.get_source_subnet();
+        let source_subnet_topology =
+            match state.metadata.network_topology.subnets.get(&source_subnet) {
+                Some(topology) if source_subnet != own_subnet_id => topology,
+                _ => {
+                    return Err(invalid_export(format!(
+                        "{} is not a valid source subnet",
+                        source_subnet
+                    )))
+                }
+            };
+        let source_subnet_pk =
+            threshold_sig_public_key_from_der(&source_subnet_topology.public_key)
+                .map_err(|err| invalid_export(err.to_string()))?;
+
+        let export_request: HttpCanisterUpdate = serde_cbor::from_slice(&args.export_request)
+            .map_err(|err| invalid_export(format!("malformed export request: {}", err)))?;
+        if export_request.canister_id.0 != IC_00.get().to_vec()
+            || export_request.method_name != Ic00Method::ExportCanister.to_string()
+        {
+            return Err(invalid_export(
+                "the export request does not call export_canister".to_string(),
+            ));
+        }
+        let export_args = ExportCanisterArgs::decode(&export_request.arg.0)
+            .map_err(|err| invalid_export(err.to_string()))?;
+        if export_args.get_canister_id() != canister_id
+            || export_args.get_target_subnet() != own_subnet_id
+        {
+            return Err(invalid_export(format!(
+                "the export request exports canister {} to subnet {}",
+                export_args.get_canister_id(),
+                export_args.get_target_subnet()
+            )));
+        }
+
+        let message_id = export_request.id();
+        let (certificate_time, reply) =
+            verify_certified_reply(&args.certificate, &source_subnet_pk, &message_id)
+                .map_err(|err| invalid_export(format!("invalid certificate: {}", err)))?;
+        if certificate_time + MAX_CANISTER_EXPORT_AGE < state.time() {
+            return Err(invalid_export("the certificate has expired".to_string()));
+        }
+        if state
+            .metadata
+            .imported_canister_exports
+            .contains_key(&message_id)
+        {
+            return Err(invalid_export(
+                "the export has already been imported".to_string(),
+            ));
+        }
+
+        let export = ExportCanisterResult::decode(&reply)
+            .map_err(|err| invalid_export(err.to_string()))
+            .and_then(|result| CanisterExport::decode(&result.manifest).map_err(invalid_export))?;
+        Ok((export, message_id, certificate_time))
+    }
+
+    // Records that the export with `message_id` was imported, so that it cannot
+    // be imported again, and forgets the exports whose certificates expired.
+    fn record_imported_canister_export(
+        &self,
+        message_id: MessageId,
+        certificate_time: Time,
+        state: &mut ReplicatedState,
+    ) {
+        let time = state.time();
+        let imported_exports = &mut state.metadata.imported_canister_exports;
+        let expired: Vec<MessageId> = imported_exports
+            .iter()
+            .filter(|(_, certificate_time)| **certificate_time + MAX_CANISTER_EXPORT_AGE < time)
+            .map(|(message_id, _)| message_id.clone())
+            .collect();
+        for message_id in expired {
+            imported_exports.remove(&message_id);
+        }
+        imported_exports.insert(message_id, certificate_time);
+    }
+
+    // The memories of an imported canister are incomplete until all of their
+    // chunks have been transferred, so the canister must not run before.
+    fn validate_import_complete(
+        &self,
+        canister: &CanisterState,
+    ) -> Result<(), CanisterManagerError> {
+        let pending_chunks = canister.system_state.pending_import_chunks.len();
+        if pending_chunks > 0 {
+            return Err(CanisterManagerError::CanisterImportIncomplete {
+                canister_id: canister.canister_id(),
+                pending_chunks,
+            });
+        }
+        Ok(())
+    }
+
     fn validate_canister_is_stopped(
         &self,
         canister: &CanisterState,
@@ -1270,25 +2129,51 @@ impl CanisterManager {
         &self,
         state: &mut ReplicatedState,
     ) -> Result<CanisterId, CanisterManagerError> {
-        let canister_id_ranges = state
-            .metadata
-            .network_topology
+        let mut canister_ids = self.generate_new_canister_ids(state, 1)?;
+        Ok(canister_ids.remove(0))
+    }
+
+    // Allocates `count` consecutive canister ids, see the warning on
+    // `generate_new_canister_id`. Ids in the reserved ranges of the subnet are
+    // skipped. No id is allocated if the subnet does not have enough of them
+    // left.
+    fn generate_new_canister_ids(
+        &self,
+        state: &mut ReplicatedState,
+        count: u64,
+    ) -> Result<Vec<CanisterId>, CanisterManagerError> {
+        let network_topology = &state.metadata.network_topology;
+        let canister_id_ranges = network_topology
             .routing_table
             .ranges(self.config.own_subnet_id);
-        if state.metadata.generated_id_counter as u128 >= canister_id_ranges.total_count() {
-            error!(
-                self.log,
-                "Subnet is full.  Total allowed is {} and generated_count is {}",
-                canister_id_ranges.total_count(),
-                state.metadata.generated_id_counter
-            );
-            return Err(CanisterManagerError::SubnetOutOfCanisterIds {
-                allowed: canister_id_ranges.total_count(),
-            });
+        let reserved_canister_id_ranges = network_topology
+            .subnets
+            .get(&self.config.own_subnet_id)
+            .map(|subnet| subnet.reserved_canister_id_ranges.clone())
+            .unwrap_or_default();
+
+        let mut counter = state.metadata.generated_id_counter;
+        let mut canister_ids = Vec::with_capacity(count as usize);
+        while (canister_ids.len() as u64) < count {
+            if counter as u128 >= canister_id_ranges.total_count() {
+                error!(
+                    self.log,
+                    "Subnet is full.  Total allowed is {} and generated_count is {}",
+                    canister_id_ranges.total_count(),
+                    counter
+                );
+                return Err(CanisterManagerError::SubnetOutOfCanisterIds {
+                    allowed: canister_id_ranges.total_count(),
+                });
+            }
+            let canister_id = canister_id_ranges.locate(counter);
+            counter += 1;
+            if !reserved_canister_id_ranges.contains(canister_id) {
+                canister_ids.push(canister_id);
+            }
         }
-        let canister_id = canister_id_ranges.locate(state.metadata.generated_id_counter);
-        state.metadata.generated_id_counter += 1;
-        Ok(canister_id)
+        state.metadata.generated_id_counter = counter;
+        Ok(canister_ids)
     }
 
     fn validate_canister_exists<'a>(
@@ -1333,6 +2218,13 @@ pub(crate) enum CanisterManagerError {
         requested: ComputeAllocation,
         available: u64,
     },
+    /// The total compute allocation of a batch of `count` canisters exceeds
+    /// the remaining compute capacity.
+    SubnetComputeCapacityOverSubscribedByBatch {
+        count: u64,
+        requested: u64,
+        available: u64,
+    },
     SubnetMemoryCapacityOverSubscribed {
         requested: NumBytes,
         available: NumBytes,
@@ -1340,6 +2232,19 @@ pub(crate) enum CanisterManagerError {
     Hypervisor(CanisterId, HypervisorError),
     DeleteCanisterNotStopped(CanisterId),
     DeleteCanisterSelf(CanisterId),
+    ExportCanisterNotStopped(CanisterId),
+    CanisterNotHostedBySubnet {
+        canister_id: CanisterId,
+        subnet_id: SubnetId,
+    },
+    InvalidCanisterExport {
+        canister_id: CanisterId,
+        message: String,
+    },
+    CanisterImportIncomplete {
+        canister_id: CanisterId,
+        pending_chunks: usize,
+    },
     SenderNotInWhitelist(PrincipalId),
     NotEnoughMemoryAllocationGiven {
         canister_id: CanisterId,
@@ -1359,10 +2264,29 @@ pub(crate) enum CanisterManagerError {
     SubnetOutOfCanisterIds {
         allowed: u128,
     },
+    InvalidCanisterCreationBatchSize {
+        requested: u64,
+        max: u64,
+    },
+    PriorityClassNotAllowed(PrincipalId),
+    SelfDestructNotBySelf {
+        canister_id: CanisterId,
+        sender: PrincipalId,
+    },
+    SelfDestructNotEnabled(CanisterId),
+    SelfDestructInvalidBeneficiary {
+        canister_id: CanisterId,
+        beneficiary: CanisterId,
+    },
 
     InvalidSettings {
         message: String,
     },
+    StableMemoryIncompatible {
+        canister_id: CanisterId,
+        installed_hash: Vec<u8>,
+        new_hash: Vec<u8>,
+    },
 }
 
 impl From<CanisterManagerError> for UserError {
@@ -1384,6 +2308,16 @@ impl From<CanisterManagerError> for UserError {
                         available
                     ))
             }
+            SubnetComputeCapacityOverSubscribedByBatch { count, requested, available } => {
+                Self::new(
+                    ErrorCode::SubnetOversubscribed,
+                    format!(
+                        "{} canisters requested a total compute allocation of {}% which cannot be satisfied because the Subnet's remaining compute capacity is {}%",
+                        count,
+                        requested,
+                        available
+                    ))
+            }
             CanisterNotFound(canister_id) => {
                 Self::new(
                     ErrorCode::CanisterNotFound,
@@ -1441,6 +2375,42 @@ impl From<CanisterManagerError> for UserError {
                     )
                 )
             }
+            ExportCanisterNotStopped(canister_id) => {
+                Self::new(
+                    ErrorCode::CanisterNotStopped,
+                    format!(
+                        "Canister {} must be stopped before it is exported.",
+                        canister_id,
+                    )
+                )
+            }
+            CanisterNotHostedBySubnet { canister_id, subnet_id } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Canister {} cannot be imported because it is not routed to subnet {}.",
+                        canister_id, subnet_id,
+                    )
+                )
+            }
+            InvalidCanisterExport { canister_id, message } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Invalid export of canister {}: {}",
+                        canister_id, message,
+                    )
+                )
+            }
+            CanisterImportIncomplete { canister_id, pending_chunks } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Canister {} is still being imported: {} memory chunks are pending.",
+                        canister_id, pending_chunks,
+                    )
+                )
+            }
             SenderNotInWhitelist(_) => {
                 // Methods that are whitelisted are private and should be invisible to users
                 // outside of the whitelist. Therefore, not finding the sender in the whitelist is
@@ -1492,15 +2462,73 @@ impl From<CanisterManagerError> for UserError {
                     ),
                 )
             }
+            InvalidCanisterCreationBatchSize { requested, max } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Could not create canisters: requested a batch of {} canisters but the batch size must be between 1 and {}",
+                        requested, max,
+                    ),
+                )
+            }
+            PriorityClassNotAllowed(sender) => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Only the NNS governance and root canisters may set the priority class of a canister, but the sender is {}",
+                        sender,
+                    ),
+                )
+            }
+            SelfDestructNotBySelf { canister_id, sender } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Only canister {} itself may request its self-destruction, but the sender is {}",
+                        canister_id, sender,
+                    ),
+                )
+            }
+            SelfDestructNotEnabled(canister_id) => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "The controllers of canister {} have not enabled self-destruction in its settings",
+                        canister_id,
+                    ),
+                )
+            }
+            SelfDestructInvalidBeneficiary { canister_id, beneficiary } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Canister {} cannot move its cycles to {}: the beneficiary must be another canister on the same subnet",
+                        canister_id, beneficiary,
+                    ),
+                )
+            }
             InvalidSettings { message } => {
                 Self::new(ErrorCode::CanisterContractViolation,
                           format!("Could not validate the settings: {} ", message),
                 )
             }
+            StableMemoryIncompatible { canister_id, installed_hash, new_hash } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Canister {} cannot be upgraded: the new module declares stable memory schema {} but the installed module declares {}. Set skip_stable_compat_check to upgrade anyway.",
+                        canister_id, to_hex(&new_hash), to_hex(&installed_hash),
+                    ),
+                )
+            }
         }
     }
 }
 
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 impl From<(CanisterId, HypervisorError)> for CanisterManagerError {
     fn from(val: (CanisterId, HypervisorError)) -> Self {
         CanisterManagerError::Hypervisor(val.0, val.1)
@@ -1556,6 +2584,40 @@ pub(crate) fn truncate_canister_stable_memory(
     }
 }
 
+/// Returns the execution counters of the most recent rounds in which the
+/// canister executed messages. Only the controllers of the canister can
+/// retrieve them.
+///
+/// `canister_metrics` is a query of the management canister, so this only
+/// reads the state, see `InternalHttpQueryHandler`.
+pub(crate) fn get_canister_metrics(
+    sender: PrincipalId,
+    canister: &CanisterState,
+) -> Result<CanisterMetricsResult, CanisterManagerError> {
+    if !canister.controllers().contains(&sender) {
+        return Err(CanisterManagerError::CanisterInvalidController {
+            canister_id: canister.canister_id(),
+            controllers_expected: canister.system_state.controllers.clone(),
+            controller_provided: sender,
+        });
+    }
+
+    let rounds = canister
+        .system_state
+        .canister_metrics
+        .recent_execution_metrics
+        .rounds()
+        .map(|round| CanisterRoundMetrics {
+            time_nanos: round.time.as_nanos_since_unix_epoch(),
+            updates_executed: round.updates_executed,
+            instructions_executed: round.instructions_executed.get(),
+            traps: round.traps,
+            memory_grow_events: round.memory_grow_events,
+        })
+        .collect();
+    Ok(CanisterMetricsResult { rounds })
+}
+
 /// Uninstalls a canister.
 ///
 /// See https://sdk.dfinity.org/docs/interface-spec/index.html#ic-uninstall_code
@@ -1640,12 +2702,16 @@ pub fn uninstall_canister(
     rejects
 }
 
+#[derive(Clone)]
 struct ValidatedCanisterSettings {
     pub controller: Option<PrincipalId>,
     pub controllers: Option<Vec<PrincipalId>>,
     pub compute_allocation: Option<ComputeAllocation>,
     pub memory_allocation: Option<MemoryAllocation>,
     pub freezing_threshold: Option<NumSeconds>,
+    pub priority_class: Option<PriorityClass>,
+    pub self_destruct_enabled: Option<bool>,
+    pub heartbeat_instruction_limit: Option<NumInstructions>,
 }
 
 impl TryFrom<(CanisterSettings, usize)> for ValidatedCanisterSettings {
@@ -1681,6 +2747,9 @@ impl TryFrom<(CanisterSettings, usize)> for ValidatedCanisterSettings {
             compute_allocation: settings.compute_allocation(),
             memory_allocation: settings.memory_allocation(),
             freezing_threshold: settings.freezing_threshold(),
+            priority_class: settings.priority_class(),
+            self_destruct_enabled: settings.self_destruct_enabled(),
+            heartbeat_instruction_limit: settings.heartbeat_instruction_limit(),
         })
     }
 }
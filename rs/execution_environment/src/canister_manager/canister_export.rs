@@ -0,0 +1,160 @@
+//! The manifest of a canister that is migrated to another subnet.
+//!
+//! Migrating a stopped canister takes two steps. On the source subnet, its
+//! controller first downloads the non-zero chunks of its memories with
+//! `export_canister_chunk` and then calls `export_canister`, which removes the
+//! canister and replies with a [`CanisterExport`]. The reply is certified by
+//! the source subnet as part of the status of the ingress message. On the
+//! target subnet, `import_canister` verifies that certificate, recreates the
+//! canister with empty memories and records the hashes of the chunks still to
+//! be transferred, which `import_canister_chunk` then fills in one by one.
+//!
+//! Memories are transferred as byte ranges rather than host pages, so that the
+//! transfer does not depend on the page size of either replica.
+use ic_base_types::NumSeconds;
+use ic_crypto_sha::Sha256;
+use ic_replicated_state::{
+    page_map::{Buffer, PAGE_SIZE},
+    Global, NumWasmPages, NumWasmPages64, PageMap, PendingImportChunk,
+};
+use ic_types::{
+    CanisterId, ComputeAllocation, Cycles, MemoryAllocation, NumInstructions, PrincipalId,
+    PriorityClass, SubnetId,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The size of the chunks in which memories are transferred, small enough for
+/// a chunk to fit into a single message.
+pub(crate) const EXPORT_CHUNK_SIZE: u64 = 1 << 20;
+
+const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024;
+
+/// The exported state of a canister, see the module documentation.
+///
+/// Query allocations are only passed to `install_code` and are not part of
+/// the state of a canister, so there is nothing to export for them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CanisterExport {
+    pub canister_id: CanisterId,
+    /// The only subnet that can import the canister.
+    pub target_subnet: SubnetId,
+    pub controllers: BTreeSet<PrincipalId>,
+    pub compute_allocation: ComputeAllocation,
+    pub memory_allocation: MemoryAllocation,
+    pub freeze_threshold: u64,
+    pub cycles_balance: Cycles,
+    pub certified_data: Vec<u8>,
+    pub priority_class: PriorityClass,
+    pub heartbeat_instruction_limit: Option<NumInstructions>,
+    pub self_destruct_enabled: bool,
+    /// The module of the canister, if code is installed.
+    pub module: Option<ExportedModule>,
+    /// The size of the stable memory in Wasm pages.
+    pub stable_memory_size: u64,
+    /// The non-zero chunks of both memories. All other bytes are zero.
+    pub chunks: Vec<PendingImportChunk>,
+}
+
+/// The Wasm module of an exported canister and the state of its instance.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ExportedModule {
+    /// The SHA-256 hash of the module, which is passed to `import_canister`.
+    pub wasm_module_hash: [u8; 32],
+    /// The size of the Wasm memory in Wasm pages.
+    pub heap_size: u32,
+    pub exported_globals: Vec<Global>,
+}
+
+impl CanisterExport {
+    /// Encodes the export into the manifest that is returned to the controller.
+    pub fn encode(&self) -> Vec<u8> {
+        serde_cbor::to_vec(self).expect("failed to encode canister export")
+    }
+
+    /// Decodes a manifest produced by [`Self::encode`].
+    pub fn decode(manifest: &[u8]) -> Result<Self, String> {
+        serde_cbor::from_slice(manifest).map_err(|err| err.to_string())
+    }
+
+    pub fn freeze_threshold(&self) -> NumSeconds {
+        NumSeconds::from(self.freeze_threshold)
+    }
+
+    pub fn stable_memory_size(&self) -> NumWasmPages64 {
+        NumWasmPages64::from(self.stable_memory_size)
+    }
+}
+
+impl ExportedModule {
+    pub fn heap_size(&self) -> NumWasmPages {
+        NumWasmPages::from(self.heap_size)
+    }
+}
+
+/// Returns the hashes of the non-zero chunks of a memory of
+/// `size_in_wasm_pages` backed by `page_map`.
+pub(crate) fn memory_chunks(
+    page_map: &PageMap,
+    size_in_wasm_pages: u64,
+    stable_memory: bool,
+) -> Vec<PendingImportChunk> {
+    let size_in_bytes = memory_size_in_bytes(size_in_wasm_pages);
+    // All bytes past the last host page of the page map are zero.
+    let end = size_in_bytes.min(page_map.num_host_pages() as u64 * *PAGE_SIZE as u64);
+    let buffer = Buffer::new(page_map.clone());
+    (0..end)
+        .step_by(EXPORT_CHUNK_SIZE as usize)
+        .filter_map(|offset| {
+            let bytes = read_chunk(&buffer, offset, size_in_bytes);
+            if bytes.iter().all(|byte| *byte == 0) {
+                return None;
+            }
+            Some(PendingImportChunk {
+                stable_memory,
+                offset,
+                hash: Sha256::hash(&bytes),
+            })
+        })
+        .collect()
+}
+
+/// Returns the chunk at `offset` of a memory of `size_in_wasm_pages` backed by
+/// `page_map`.
+///
+/// Returns an error if `offset` is not the start of a chunk of the memory.
+pub(crate) fn memory_chunk(
+    page_map: &PageMap,
+    size_in_wasm_pages: u64,
+    offset: u64,
+) -> Result<Vec<u8>, String> {
+    let size_in_bytes = memory_size_in_bytes(size_in_wasm_pages);
+    if offset % EXPORT_CHUNK_SIZE != 0 || offset >= size_in_bytes {
+        return Err(format!(
+            "offset {} is not the start of a chunk of a memory of {} bytes",
+            offset, size_in_bytes
+        ));
+    }
+    Ok(read_chunk(
+        &Buffer::new(page_map.clone()),
+        offset,
+        size_in_bytes,
+    ))
+}
+
+/// Writes an imported chunk at `offset` into `page_map`.
+pub(crate) fn write_chunk(page_map: &mut PageMap, offset: u64, bytes: &[u8]) {
+    let mut buffer = Buffer::new(std::mem::take(page_map));
+    buffer.write(bytes, offset as usize);
+    *page_map = buffer.into_page_map();
+}
+
+fn read_chunk(buffer: &Buffer, offset: u64, size_in_bytes: u64) -> Vec<u8> {
+    let mut bytes = vec![0; EXPORT_CHUNK_SIZE.min(size_in_bytes - offset) as usize];
+    buffer.read(&mut bytes, offset as usize);
+    bytes
+}
+
+fn memory_size_in_bytes(size_in_wasm_pages: u64) -> u64 {
+    size_in_wasm_pages.saturating_mul(WASM_PAGE_SIZE_IN_BYTES)
+}
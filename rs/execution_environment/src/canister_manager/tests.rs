@@ -1,29 +1,38 @@
 use crate::{
     canister_manager::{
-        canister_layout, uninstall_canister, CanisterManager, CanisterManagerError,
-        CanisterMgrConfig, StopCanisterResult,
+        canister_export::{CanisterExport, ExportedModule},
+        canister_layout, get_canister_metrics, uninstall_canister, CanisterManager,
+        CanisterManagerError, CanisterMgrConfig, StopCanisterResult, MAX_CANISTER_EXPORT_AGE,
     },
     canister_settings::CanisterSettings,
     hypervisor::Hypervisor,
     types::{IngressResponse, Response},
+    util::GOVERNANCE_CANISTER_ID,
     IngressHistoryWriterImpl, QueryExecutionType,
 };
 use assert_matches::assert_matches;
 use ic_base_types::NumSeconds;
 use ic_config::execution_environment::Config;
+use ic_crypto::{combined_threshold_signature_and_public_key, threshold_sig_public_key_to_der};
+use ic_crypto_tree_hash::{Label, MixedHashTree};
 use ic_cycles_account_manager::CyclesAccountManager;
+use ic_ic00_types::{
+    CanisterMemory, CanisterMetricsResult, CanisterRoundMetrics, ExportCanisterArgs,
+    ExportCanisterResult, ImportCanisterArgs, ImportCanisterChunkArgs, Method as Ic00Method,
+    Payload, IC_00,
+};
 use ic_interfaces::execution_environment::{
     ExecutionParameters, HypervisorError, SubnetAvailableMemory,
 };
 use ic_logger::replica_logger::no_op_logger;
 use ic_metrics::MetricsRegistry;
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
-use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
+use ic_registry_routing_table::{CanisterIdRange, CanisterIdRanges, RoutingTable};
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::canister_state::testing::CanisterStateTesting;
 use ic_replicated_state::{
-    page_map, CallContextManager, CallOrigin, CanisterStatus, NumWasmPages64, PageMap,
-    ReplicatedState,
+    page_map, CallContextManager, CallOrigin, CanisterStatus, Global, NumWasmPages64, PageMap,
+    PendingImportChunk, ReplicatedState, SubnetTopology,
 };
 use ic_test_utilities::{
     cycles_account_manager::CyclesAccountManagerBuilder,
@@ -43,16 +52,22 @@ use ic_test_utilities::{
 use ic_types::messages::StopCanisterContext;
 use ic_types::nominal_cycles::NominalCycles;
 use ic_types::{
+    consensus::certification::CertificationContent,
+    crypto::CryptoHash,
     ingress::{IngressStatus, WasmResult},
-    messages::{CallbackId, CanisterInstallMode, RequestOrResponse},
+    messages::{
+        Blob, CallbackId, CanisterInstallMode, Certificate, HttpCanisterUpdate, MessageId,
+        RequestOrResponse,
+    },
     user_error::{ErrorCode, UserError},
-    CanisterId, CanisterStatusType, ComputeAllocation, Cycles, InstallCodeContext,
-    MemoryAllocation, NumBytes, NumInstructions, QueryAllocation, SubnetId,
+    CanisterId, CanisterStatusType, ComputeAllocation, CryptoHashOfPartialState, Cycles,
+    InstallCodeContext, MemoryAllocation, NumBytes, NumInstructions, PrincipalId, PriorityClass,
+    QueryAllocation, Randomness, SubnetId,
 };
 use ic_wasm_types::WasmValidationError;
 use lazy_static::lazy_static;
 use maplit::{btreemap, btreeset};
-use std::{collections::BTreeSet, convert::TryFrom, path::Path, sync::Arc};
+use std::{collections::BTreeSet, convert::TryFrom, path::Path, sync::Arc, time::Duration};
 
 const CANISTER_CREATION_FEE: Cycles = Cycles::new(100_000_000_000);
 const CANISTER_FREEZE_BALANCE_RESERVE: Cycles = Cycles::new(5_000_000_000_000);
@@ -63,6 +78,7 @@ const MEMORY_CAPACITY: NumBytes = NumBytes::new(8 * 1024 * 1024 * 1024); // 8GiB
 const MAX_GLOBALS: usize = 200;
 const MAX_FUNCTIONS: usize = 6000;
 const MAX_CONTROLLERS: usize = 10;
+const MAX_CANISTER_CREATION_BATCH_SIZE: u64 = 100;
 const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024; // 64KiB
 
 lazy_static! {
@@ -75,12 +91,15 @@ lazy_static! {
         canister_memory_limit: NumBytes::new(std::u64::MAX),
         subnet_available_memory: MAX_SUBNET_AVAILABLE_MEMORY.clone(),
         compute_allocation: ComputeAllocation::default(),
+        canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+        root_key: vec![],
     };
 }
 
 struct CanisterManagerBuilder {
     cycles_account_manager: CyclesAccountManager,
     subnet_id: SubnetId,
+    config: Config,
 }
 
 impl CanisterManagerBuilder {
@@ -94,6 +113,11 @@ impl CanisterManagerBuilder {
         self
     }
 
+    fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
     fn build(self) -> CanisterManager {
         let subnet_type = SubnetType::Application;
         let metrics_registry = MetricsRegistry::new();
@@ -103,7 +127,7 @@ impl CanisterManagerBuilder {
         ));
         let cycles_account_manager = Arc::new(self.cycles_account_manager);
         let hypervisor = Hypervisor::new(
-            Config::default(),
+            self.config.clone(),
             1,
             &metrics_registry,
             self.subnet_id,
@@ -112,10 +136,13 @@ impl CanisterManagerBuilder {
             Arc::clone(&cycles_account_manager),
         );
         let hypervisor = Arc::new(hypervisor);
+        let mut canister_manager_config = canister_manager_config(self.subnet_id);
+        canister_manager_config.wasm_import_allow_list = self.config.wasm_import_allow_list;
+        canister_manager_config.wasm_export_allow_list = self.config.wasm_export_allow_list;
         CanisterManager::new(
             hypervisor,
             no_op_logger(),
-            canister_manager_config(self.subnet_id),
+            canister_manager_config,
             cycles_account_manager,
             ingress_history_writer,
         )
@@ -127,6 +154,7 @@ impl Default for CanisterManagerBuilder {
         Self {
             cycles_account_manager: CyclesAccountManagerBuilder::new().build(),
             subnet_id: subnet_test_id(1),
+            config: Config::default(),
         }
     }
 }
@@ -139,8 +167,11 @@ fn canister_manager_config(subnet_id: SubnetId) -> CanisterMgrConfig {
         NumSeconds::from(100_000),
         MAX_GLOBALS,
         MAX_FUNCTIONS,
+        Config::default().wasm_import_allow_list,
+        Config::default().wasm_export_allow_list,
         subnet_id,
         MAX_CONTROLLERS,
+        MAX_CANISTER_CREATION_BATCH_SIZE,
         1,
     )
 }
@@ -936,6 +967,95 @@ fn create_canister_sets_correct_allocations() {
     });
 }
 
+#[test]
+fn create_canister_inherits_settings_from_template() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        let memory_allocation = MemoryAllocation::try_from(NumBytes::from(1 << 20)).unwrap();
+        let template_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_test_id(1),
+                *INITIAL_CYCLES,
+                CanisterSettings::new(
+                    None,
+                    None,
+                    None,
+                    Some(memory_allocation),
+                    Some(NumSeconds::from(1_000)),
+                ),
+                &mut state,
+            )
+            .0
+            .unwrap();
+
+        // The explicitly set freezing threshold takes precedence.
+        let settings = canister_manager
+            .settings_from_template(
+                sender,
+                template_id,
+                CanisterSettings::new(None, None, None, None, Some(NumSeconds::from(7))),
+                &state,
+            )
+            .unwrap();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_test_id(1),
+                *INITIAL_CYCLES,
+                settings,
+                &mut state,
+            )
+            .0
+            .unwrap();
+
+        let canister = state.canister_state(&canister_id).unwrap();
+        assert_eq!(canister.memory_allocation(), memory_allocation);
+        assert_eq!(canister.system_state.freeze_threshold, NumSeconds::from(7));
+        assert_eq!(canister.controllers(), &btreeset! {sender});
+    });
+}
+
+#[test]
+fn create_canister_from_template_fails_if_sender_is_not_controller() {
+    with_setup(|canister_manager, mut state, _| {
+        let template_id = canister_manager
+            .create_canister(
+                canister_test_id(1).get(),
+                subnet_test_id(1),
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+
+        let sender = canister_test_id(2).get();
+        assert_matches!(
+            canister_manager.settings_from_template(
+                sender,
+                template_id,
+                CanisterSettings::default(),
+                &state,
+            ),
+            Err(CanisterManagerError::CanisterInvalidController {
+                canister_id,
+                controller_provided,
+                ..
+            }) if canister_id == template_id && controller_provided == sender
+        );
+        assert_matches!(
+            canister_manager.settings_from_template(
+                sender,
+                canister_test_id(100),
+                CanisterSettings::default(),
+                &state,
+            ),
+            Err(CanisterManagerError::CanisterNotFound(_))
+        );
+    });
+}
+
 #[test]
 fn create_canister_updates_consumed_cycles_metric_correctly() {
     with_setup(|canister_manager, mut state, _| {
@@ -1267,7 +1387,8 @@ fn install_puts_canister_back_after_invalid_wasm() {
                 Err(CanisterManagerError::Hypervisor(
                     canister_id,
                     HypervisorError::InvalidWasm(WasmValidationError::InvalidImportSection(
-                        "Only memory imported from env.memory is allowed.".to_string()
+                        "Module imports memory 'foo.memory', only memory imported from env.memory is allowed."
+                            .to_string()
                     ))
                 ))
             )
@@ -1278,6 +1399,60 @@ fn install_puts_canister_back_after_invalid_wasm() {
     });
 }
 
+#[test]
+fn install_code_rejects_wasm_export_not_in_configured_allow_list() {
+    let subnet_id = subnet_test_id(1);
+    let canister_manager = CanisterManagerBuilder::default()
+        .with_subnet_id(subnet_id)
+        .with_config(Config {
+            wasm_export_allow_list: Some(vec!["canister_query *".to_string()]),
+            ..Config::default()
+        })
+        .build();
+    let tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
+    let mut state = initial_state(tmpdir.path(), subnet_id);
+    let wasm = wabt::wat2wasm(
+        r#"(module
+              (func $read)
+              (export "canister_query read" (func $read))
+              (export "canister_update write" (func $read)))"#,
+    )
+    .unwrap();
+
+    let sender = canister_test_id(1).get();
+    let canister_id = canister_manager
+        .create_canister(
+            sender,
+            subnet_test_id(1),
+            *INITIAL_CYCLES,
+            CanisterSettings::default(),
+            &mut state,
+        )
+        .0
+        .unwrap();
+
+    assert_eq!(
+        canister_manager
+            .install_code(
+                InstallCodeContextBuilder::default()
+                    .sender(sender)
+                    .canister_id(canister_id)
+                    .wasm_module(wasm)
+                    .build(),
+                &mut state,
+                EXECUTION_PARAMETERS.clone(),
+            )
+            .1,
+        Err(CanisterManagerError::Hypervisor(
+            canister_id,
+            HypervisorError::InvalidWasm(WasmValidationError::InvalidExportSection(
+                "Exporting function 'canister_update write' is not allowed, the allowed functions are [canister_query *]."
+                    .to_string()
+            ))
+        ))
+    );
+}
+
 #[test]
 fn reinstall_clears_stable_memory() {
     with_setup(|canister_manager, mut state, _| {
@@ -1690,6 +1865,56 @@ fn get_canister_status_with_incorrect_controller() {
     });
 }
 
+#[test]
+fn get_canister_metrics_returns_recent_rounds() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_test_id(1),
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+
+        let canister = state.canister_state_mut(&canister_id).unwrap();
+        let round = canister
+            .system_state
+            .canister_metrics
+            .recent_execution_metrics
+            .round_mut(mock_time());
+        round.updates_executed = 3;
+        round.instructions_executed = NumInstructions::from(1_000);
+        round.traps = 1;
+
+        assert_eq!(
+            get_canister_metrics(sender, canister),
+            Ok(CanisterMetricsResult {
+                rounds: vec![CanisterRoundMetrics {
+                    time_nanos: mock_time().as_nanos_since_unix_epoch(),
+                    updates_executed: 3,
+                    instructions_executed: 1_000,
+                    traps: 1,
+                    memory_grow_events: 0,
+                }]
+            })
+        );
+
+        let other_sender = user_test_id(1).get();
+        assert_eq!(
+            get_canister_metrics(other_sender, canister),
+            Err(CanisterManagerError::CanisterInvalidController {
+                canister_id,
+                controllers_expected: btreeset! {sender},
+                controller_provided: other_sender,
+            })
+        );
+    });
+}
+
 #[test]
 fn get_canister_status_of_running_canister() {
     with_setup(|canister_manager, mut state, _| {
@@ -2041,129 +2266,415 @@ fn create_canister_with_cycles_sender_in_whitelist() {
 }
 
 #[test]
-fn can_get_canister_balance() {
+fn create_canisters_with_cycles_creates_whole_batch() {
     with_setup(|canister_manager, mut state, _| {
-        let canister_id = canister_test_id(0);
         let sender = canister_test_id(1).get();
-        let gas = Cycles::from(100);
-        let canister = get_running_canister_with_args(canister_id, sender, gas);
-        state.put_canister_state(canister);
+        let canister_ids = canister_manager
+            .create_canisters_with_cycles(
+                sender,
+                10,
+                Some(123),
+                CanisterSettings::default(),
+                &mut state,
+                &ProvisionalWhitelist::Set(btreeset! { sender }),
+            )
+            .unwrap();
 
-        let mut canister = state.canister_state_mut(&canister_id).unwrap();
-        assert_matches!(
-            canister_manager.get_canister_status( sender, &mut canister),
-            Ok(res) if res.cycles() == gas.get()
+        assert_eq!(canister_ids.len(), 10);
+        assert_eq!(
+            canister_ids.iter().collect::<BTreeSet<_>>().len(),
+            canister_ids.len()
         );
+        assert_eq!(state.metadata.generated_id_counter, 10);
+        for canister_id in canister_ids {
+            let canister = state.canister_state(&canister_id).unwrap();
+            assert_eq!(canister.system_state.cycles_balance, Cycles::from(123));
+            assert!(canister.controllers().contains(&sender));
+        }
     });
 }
 
-#[test]
-fn add_cycles_sender_in_whitelist() {
-    let subnet_id = subnet_test_id(1);
-    let subnet_type = SubnetType::Application;
-    let cycles_account_manager = CyclesAccountManagerBuilder::new()
-        .with_subnet_type(subnet_type)
-        .build();
-
-    let canister_manager = CanisterManagerBuilder::default()
-        .with_subnet_id(subnet_id)
-        .with_cycles_account_manager(cycles_account_manager)
-        .build();
-
-    let canister_id = canister_test_id(0);
-    let canister = get_running_canister(canister_id);
-    let sender = canister_test_id(1).get();
-
-    let tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
-    let mut state = initial_state(tmpdir.path(), subnet_id);
-    let initial_cycles = canister.system_state.cycles_balance;
-    state.put_canister_state(canister);
-
-    let mut canister = state.canister_state_mut(&canister_id).unwrap();
-    canister_manager
-        .add_cycles(
-            sender,
-            Some(123),
-            &mut canister,
-            &ProvisionalWhitelist::Set(btreeset! { canister_test_id(1).get() }),
-        )
-        .unwrap();
-
-    // Verify cycles are set as expected.
-    let canister = state.take_canister_state(&canister_id).unwrap();
-    assert_eq!(
-        canister.system_state.cycles_balance,
-        initial_cycles + Cycles::from(123),
+fn reserve_canister_ids(state: &mut ReplicatedState, subnet_id: SubnetId, start: u64, end: u64) {
+    state.metadata.network_topology.subnets.insert(
+        subnet_id,
+        SubnetTopology {
+            reserved_canister_id_ranges: CanisterIdRanges::from(vec![CanisterIdRange {
+                start: CanisterId::from(start),
+                end: CanisterId::from(end),
+            }]),
+            ..SubnetTopology::default()
+        },
     );
 }
 
 #[test]
-fn add_cycles_sender_not_in_whitelist() {
-    with_setup(|canister_manager, mut state, _| {
-        let canister_id = canister_test_id(0);
-        let canister = get_running_canister(canister_id);
+fn create_canisters_with_cycles_skips_reserved_canister_ids() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        reserve_canister_ids(&mut state, subnet_id, 2, 5);
         let sender = canister_test_id(1).get();
+        let canister_ids = canister_manager
+            .create_canisters_with_cycles(
+                sender,
+                4,
+                None,
+                CanisterSettings::default(),
+                &mut state,
+                &ProvisionalWhitelist::Set(btreeset! { sender }),
+            )
+            .unwrap();
 
-        state.put_canister_state(canister);
-
-        // By default, the `CanisterManager`'s whitelist is set to `None`.
-        // A call to `add_cycles` should fail.
-        let mut canister = state.canister_state_mut(&canister_id).unwrap();
         assert_eq!(
-            canister_manager.add_cycles(
-                sender,
-                Some(123),
-                &mut canister,
-                &ProvisionalWhitelist::Set(BTreeSet::new()),
-            ),
-            Err(CanisterManagerError::SenderNotInWhitelist(sender))
+            canister_ids,
+            vec![
+                CanisterId::from(0),
+                CanisterId::from(1),
+                CanisterId::from(6),
+                CanisterId::from(7)
+            ]
         );
+        assert_eq!(state.metadata.generated_id_counter, 8);
     });
 }
 
 #[test]
-fn installing_a_canister_with_not_enough_memory_allocation_fails() {
-    with_setup(|canister_manager, mut state, _| {
+fn create_canister_fails_if_only_reserved_canister_ids_are_left() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        reserve_canister_ids(&mut state, subnet_id, 1, 0xff);
         let sender = canister_test_id(1).get();
-        let sender_subnet_id = subnet_test_id(1);
+        let whitelist = ProvisionalWhitelist::Set(btreeset! { sender });
         let canister_id = canister_manager
-            .create_canister(
+            .create_canister_with_cycles(
                 sender,
-                sender_subnet_id,
-                *INITIAL_CYCLES,
+                None,
                 CanisterSettings::default(),
                 &mut state,
+                &whitelist,
             )
-            .0
             .unwrap();
+        assert_eq!(canister_id, CanisterId::from(0));
 
-        // Give just 10 bytes of memory allocation which should result in an error.
-        let memory_allocation = MemoryAllocation::try_from(NumBytes::from(10)).unwrap();
-        let res = canister_manager.install_code(
-            InstallCodeContextBuilder::default()
-                .sender(sender)
-                .canister_id(canister_id)
-                .wasm_module(
-                    ic_test_utilities::universal_canister::UNIVERSAL_CANISTER_WASM.to_vec(),
-                )
-                .memory_allocation(memory_allocation)
-                .build(),
-            &mut state,
-            EXECUTION_PARAMETERS.clone(),
-        );
-        assert_eq!(res.0, MAX_NUM_INSTRUCTIONS);
-        assert_matches!(
-            res.1,
-            Err(CanisterManagerError::NotEnoughMemoryAllocationGiven { .. })
+        assert_eq!(
+            canister_manager.create_canister_with_cycles(
+                sender,
+                None,
+                CanisterSettings::default(),
+                &mut state,
+                &whitelist,
+            ),
+            Err(CanisterManagerError::SubnetOutOfCanisterIds { allowed: 0x100 })
         );
+        assert_eq!(state.metadata.generated_id_counter, 1);
+    });
+}
 
-        // Install the canister.
-        canister_manager
-            .install_code(
-                InstallCodeContextBuilder::default()
-                    .sender(sender)
-                    .canister_id(canister_id)
-                    .wasm_module(
+#[test]
+fn create_canisters_with_cycles_fails_for_invalid_batch_size() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        for count in [0, MAX_CANISTER_CREATION_BATCH_SIZE + 1].iter() {
+            assert_eq!(
+                canister_manager.create_canisters_with_cycles(
+                    sender,
+                    *count,
+                    None,
+                    CanisterSettings::default(),
+                    &mut state,
+                    &ProvisionalWhitelist::Set(btreeset! { sender }),
+                ),
+                Err(CanisterManagerError::InvalidCanisterCreationBatchSize {
+                    requested: *count,
+                    max: MAX_CANISTER_CREATION_BATCH_SIZE,
+                })
+            );
+        }
+        assert_eq!(state.canisters_iter().count(), 0);
+    });
+}
+
+#[test]
+fn create_canisters_with_cycles_fails_if_batch_exceeds_compute_capacity() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        let settings = CanisterSettings::new(
+            None,
+            None,
+            Some(ComputeAllocation::try_from(60).unwrap()),
+            None,
+            None,
+        );
+        assert_eq!(
+            canister_manager.create_canisters_with_cycles(
+                sender,
+                MAX_CANISTER_CREATION_BATCH_SIZE,
+                None,
+                settings,
+                &mut state,
+                &ProvisionalWhitelist::Set(btreeset! { sender }),
+            ),
+            Err(
+                CanisterManagerError::SubnetComputeCapacityOverSubscribedByBatch {
+                    count: MAX_CANISTER_CREATION_BATCH_SIZE,
+                    requested: 60 * MAX_CANISTER_CREATION_BATCH_SIZE,
+                    available: 99,
+                }
+            )
+        );
+        assert_eq!(state.canisters_iter().count(), 0);
+        assert_eq!(state.metadata.generated_id_counter, 0);
+    });
+}
+
+#[test]
+fn create_canisters_with_cycles_sender_not_in_whitelist() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        assert_eq!(
+            canister_manager.create_canisters_with_cycles(
+                sender,
+                10,
+                None,
+                CanisterSettings::default(),
+                &mut state,
+                &ProvisionalWhitelist::Set(BTreeSet::new()),
+            ),
+            Err(CanisterManagerError::SenderNotInWhitelist(sender))
+        );
+    });
+}
+
+#[test]
+fn priority_class_can_be_set_by_nns_governance() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = GOVERNANCE_CANISTER_ID.get();
+        // The subnet of the test state is the NNS subnet.
+        let nns_subnet_id = state.metadata.network_topology.nns_subnet_id;
+        let settings =
+            CanisterSettings::default().with_priority_class(Some(PriorityClass::Elevated));
+        let canister_id = canister_manager
+            .create_canister(sender, nns_subnet_id, *INITIAL_CYCLES, settings, &mut state)
+            .0
+            .unwrap();
+        assert_eq!(
+            state
+                .canister_state(&canister_id)
+                .unwrap()
+                .scheduler_state
+                .priority_class,
+            PriorityClass::Elevated
+        );
+
+        let compute_allocation_used = state.total_compute_allocation();
+        let memory_allocation_used = state.total_memory_taken();
+        let mut canister = state.canister_state_mut(&canister_id).unwrap();
+        canister_manager
+            .update_settings(
+                sender,
+                true,
+                CanisterSettings::default().with_priority_class(Some(PriorityClass::Normal)),
+                &mut canister,
+                compute_allocation_used,
+                memory_allocation_used,
+            )
+            .unwrap();
+        assert_eq!(
+            canister.scheduler_state.priority_class,
+            PriorityClass::Normal
+        );
+    });
+}
+
+#[test]
+fn priority_class_cannot_be_set_by_other_nns_canisters() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(5).get();
+        let nns_subnet_id = state.metadata.network_topology.nns_subnet_id;
+
+        assert_eq!(
+            canister_manager
+                .create_canister(
+                    sender,
+                    nns_subnet_id,
+                    *INITIAL_CYCLES,
+                    CanisterSettings::default().with_priority_class(Some(PriorityClass::Elevated)),
+                    &mut state,
+                )
+                .0,
+            Err(CanisterManagerError::PriorityClassNotAllowed(sender))
+        );
+    });
+}
+
+#[test]
+fn priority_class_cannot_be_set_outside_of_nns() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_test_id(1),
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+
+        let compute_allocation_used = state.total_compute_allocation();
+        let memory_allocation_used = state.total_memory_taken();
+        let mut canister = state.canister_state_mut(&canister_id).unwrap();
+        assert_eq!(
+            canister_manager.update_settings(
+                sender,
+                false,
+                CanisterSettings::default().with_priority_class(Some(PriorityClass::Elevated)),
+                &mut canister,
+                compute_allocation_used,
+                memory_allocation_used,
+            ),
+            Err(CanisterManagerError::PriorityClassNotAllowed(sender))
+        );
+        assert_eq!(
+            canister.scheduler_state.priority_class,
+            PriorityClass::Normal
+        );
+
+        assert_eq!(
+            canister_manager.create_canister_with_cycles(
+                sender,
+                None,
+                CanisterSettings::default().with_priority_class(Some(PriorityClass::Elevated)),
+                &mut state,
+                &ProvisionalWhitelist::Set(btreeset! { sender }),
+            ),
+            Err(CanisterManagerError::PriorityClassNotAllowed(sender))
+        );
+    });
+}
+
+#[test]
+fn can_get_canister_balance() {
+    with_setup(|canister_manager, mut state, _| {
+        let canister_id = canister_test_id(0);
+        let sender = canister_test_id(1).get();
+        let gas = Cycles::from(100);
+        let canister = get_running_canister_with_args(canister_id, sender, gas);
+        state.put_canister_state(canister);
+
+        let mut canister = state.canister_state_mut(&canister_id).unwrap();
+        assert_matches!(
+            canister_manager.get_canister_status( sender, &mut canister),
+            Ok(res) if res.cycles() == gas.get()
+        );
+    });
+}
+
+#[test]
+fn add_cycles_sender_in_whitelist() {
+    let subnet_id = subnet_test_id(1);
+    let subnet_type = SubnetType::Application;
+    let cycles_account_manager = CyclesAccountManagerBuilder::new()
+        .with_subnet_type(subnet_type)
+        .build();
+
+    let canister_manager = CanisterManagerBuilder::default()
+        .with_subnet_id(subnet_id)
+        .with_cycles_account_manager(cycles_account_manager)
+        .build();
+
+    let canister_id = canister_test_id(0);
+    let canister = get_running_canister(canister_id);
+    let sender = canister_test_id(1).get();
+
+    let tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
+    let mut state = initial_state(tmpdir.path(), subnet_id);
+    let initial_cycles = canister.system_state.cycles_balance;
+    state.put_canister_state(canister);
+
+    let mut canister = state.canister_state_mut(&canister_id).unwrap();
+    canister_manager
+        .add_cycles(
+            sender,
+            Some(123),
+            &mut canister,
+            &ProvisionalWhitelist::Set(btreeset! { canister_test_id(1).get() }),
+        )
+        .unwrap();
+
+    // Verify cycles are set as expected.
+    let canister = state.take_canister_state(&canister_id).unwrap();
+    assert_eq!(
+        canister.system_state.cycles_balance,
+        initial_cycles + Cycles::from(123),
+    );
+}
+
+#[test]
+fn add_cycles_sender_not_in_whitelist() {
+    with_setup(|canister_manager, mut state, _| {
+        let canister_id = canister_test_id(0);
+        let canister = get_running_canister(canister_id);
+        let sender = canister_test_id(1).get();
+
+        state.put_canister_state(canister);
+
+        // By default, the `CanisterManager`'s whitelist is set to `None`.
+        // A call to `add_cycles` should fail.
+        let mut canister = state.canister_state_mut(&canister_id).unwrap();
+        assert_eq!(
+            canister_manager.add_cycles(
+                sender,
+                Some(123),
+                &mut canister,
+                &ProvisionalWhitelist::Set(BTreeSet::new()),
+            ),
+            Err(CanisterManagerError::SenderNotInWhitelist(sender))
+        );
+    });
+}
+
+#[test]
+fn installing_a_canister_with_not_enough_memory_allocation_fails() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = canister_test_id(1).get();
+        let sender_subnet_id = subnet_test_id(1);
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                sender_subnet_id,
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+
+        // Give just 10 bytes of memory allocation which should result in an error.
+        let memory_allocation = MemoryAllocation::try_from(NumBytes::from(10)).unwrap();
+        let res = canister_manager.install_code(
+            InstallCodeContextBuilder::default()
+                .sender(sender)
+                .canister_id(canister_id)
+                .wasm_module(
+                    ic_test_utilities::universal_canister::UNIVERSAL_CANISTER_WASM.to_vec(),
+                )
+                .memory_allocation(memory_allocation)
+                .build(),
+            &mut state,
+            EXECUTION_PARAMETERS.clone(),
+        );
+        assert_eq!(res.0, MAX_NUM_INSTRUCTIONS);
+        assert_matches!(
+            res.1,
+            Err(CanisterManagerError::NotEnoughMemoryAllocationGiven { .. })
+        );
+
+        // Install the canister.
+        canister_manager
+            .install_code(
+                InstallCodeContextBuilder::default()
+                    .sender(sender)
+                    .canister_id(canister_id)
+                    .wasm_module(
                         ic_test_utilities::universal_canister::UNIVERSAL_CANISTER_WASM.to_vec(),
                     )
                     .build(),
@@ -2373,6 +2884,7 @@ fn failed_upgrade_hooks_consume_instructions() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Install,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -2390,6 +2902,7 @@ fn failed_upgrade_hooks_consume_instructions() {
                 memory_allocation: None,
                 mode: CanisterInstallMode::Upgrade,
                 query_allocation: QueryAllocation::default(),
+                skip_stable_compat_check: false,
             },
             &mut state,
             EXECUTION_PARAMETERS.clone(),
@@ -2491,6 +3004,7 @@ fn failed_install_hooks_consume_instructions() {
                 memory_allocation: None,
                 mode: CanisterInstallMode::Install,
                 query_allocation: QueryAllocation::default(),
+                skip_stable_compat_check: false,
             },
             &mut state,
             EXECUTION_PARAMETERS.clone(),
@@ -2580,6 +3094,7 @@ fn install_code_respects_instruction_limit() {
             memory_allocation: None,
             mode: CanisterInstallMode::Install,
             query_allocation: QueryAllocation::default(),
+            skip_stable_compat_check: false,
         },
         &mut state,
         ExecutionParameters {
@@ -2751,6 +3266,7 @@ fn lower_memory_allocation_than_usage_fails() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Install,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -2773,6 +3289,7 @@ fn lower_memory_allocation_than_usage_fails() {
         assert_matches!(
             canister_manager.update_settings(
                 sender,
+                false,
                 settings,
                 &mut canister,
                 compute_allocation_used,
@@ -2814,6 +3331,7 @@ fn test_install_when_updating_memory_allocation_via_canister_settings() {
                         memory_allocation: None,
                         mode: CanisterInstallMode::Install,
                         query_allocation: QueryAllocation::default(),
+                        skip_stable_compat_check: false,
                     },
                     &mut state,
                     EXECUTION_PARAMETERS.clone(),
@@ -2839,6 +3357,7 @@ fn test_install_when_updating_memory_allocation_via_canister_settings() {
         canister_manager
             .update_settings(
                 sender,
+                false,
                 settings,
                 &mut canister,
                 compute_allocation_used,
@@ -2857,6 +3376,7 @@ fn test_install_when_updating_memory_allocation_via_canister_settings() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Install,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -2900,6 +3420,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Install,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -2927,6 +3448,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
                         memory_allocation: None,
                         mode: CanisterInstallMode::Upgrade,
                         query_allocation: QueryAllocation::default(),
+                        skip_stable_compat_check: false,
                     },
                     &mut state,
                     EXECUTION_PARAMETERS.clone(),
@@ -2955,6 +3477,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
         canister_manager
             .update_settings(
                 sender,
+                false,
                 settings,
                 &mut canister,
                 compute_allocation_used,
@@ -2973,6 +3496,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Upgrade,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -2984,8 +3508,6 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
 
 #[test]
 fn uninstall_code_can_be_invoked_by_governance_canister() {
-    use crate::util::GOVERNANCE_CANISTER_ID;
-
     let canister_manager = CanisterManagerBuilder::default().build();
     let mut state = ReplicatedStateBuilder::new()
         .with_canister(
@@ -3022,10 +3544,241 @@ fn uninstall_code_can_be_invoked_by_governance_canister() {
     );
 }
 
-#[test]
-fn test_install_when_setting_memory_allocation_to_zero() {
-    with_setup(|canister_manager, mut state, subnet_id| {
-        let wasm = ic_test_utilities::universal_canister::UNIVERSAL_CANISTER_WASM.to_vec();
+fn state_with_self_destructing_canister() -> ReplicatedState {
+    ReplicatedStateBuilder::new()
+        .with_canister(
+            CanisterStateBuilder::new()
+                .with_canister_id(canister_test_id(0))
+                .with_cycles(1_000_000_000_u64)
+                .with_wasm(vec![1, 2, 3])
+                .build(),
+        )
+        .with_canister(
+            CanisterStateBuilder::new()
+                .with_canister_id(canister_test_id(1))
+                .with_cycles(0_u64)
+                .build(),
+        )
+        .build()
+}
+
+#[test]
+fn self_destruct_uninstalls_canister_and_moves_cycles_to_beneficiary() {
+    let canister_manager = CanisterManagerBuilder::default().build();
+    let mut state = state_with_self_destructing_canister();
+    let canister_id = canister_test_id(0);
+    let beneficiary = canister_test_id(1);
+    state
+        .canister_state_mut(&canister_id)
+        .unwrap()
+        .system_state
+        .self_destruct_enabled = true;
+
+    let (result, cycles_to_return) = canister_manager.self_destruct(
+        canister_id,
+        beneficiary,
+        canister_id.get(),
+        Cycles::from(100),
+        &mut state,
+    );
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(cycles_to_return, Cycles::from(0));
+    let canister = state.canister_state(&canister_id).unwrap();
+    assert_eq!(canister.execution_state, None);
+    assert_eq!(canister.system_state.cycles_balance, Cycles::from(0));
+    assert_eq!(
+        state
+            .canister_state(&beneficiary)
+            .unwrap()
+            .system_state
+            .cycles_balance,
+        Cycles::from(1_000_000_100)
+    );
+}
+
+#[test]
+fn self_destruct_fails_if_not_enabled() {
+    let canister_manager = CanisterManagerBuilder::default().build();
+    let mut state = state_with_self_destructing_canister();
+    let canister_id = canister_test_id(0);
+
+    let (result, cycles_to_return) = canister_manager.self_destruct(
+        canister_id,
+        canister_test_id(1),
+        canister_id.get(),
+        Cycles::from(100),
+        &mut state,
+    );
+
+    assert_eq!(
+        result,
+        Err(CanisterManagerError::SelfDestructNotEnabled(canister_id))
+    );
+    assert_eq!(cycles_to_return, Cycles::from(100));
+    let canister = state.canister_state(&canister_id).unwrap();
+    assert!(canister.execution_state.is_some());
+    assert_eq!(
+        canister.system_state.cycles_balance,
+        Cycles::from(1_000_000_000)
+    );
+}
+
+#[test]
+fn self_destruct_fails_if_not_requested_by_canister_itself() {
+    let canister_manager = CanisterManagerBuilder::default().build();
+    let mut state = state_with_self_destructing_canister();
+    let canister_id = canister_test_id(0);
+    let sender = canister_test_id(1).get();
+    state
+        .canister_state_mut(&canister_id)
+        .unwrap()
+        .system_state
+        .self_destruct_enabled = true;
+
+    let (result, _) = canister_manager.self_destruct(
+        canister_id,
+        canister_test_id(1),
+        sender,
+        Cycles::from(0),
+        &mut state,
+    );
+
+    assert_eq!(
+        result,
+        Err(CanisterManagerError::SelfDestructNotBySelf {
+            canister_id,
+            sender
+        })
+    );
+    assert!(state
+        .canister_state(&canister_id)
+        .unwrap()
+        .execution_state
+        .is_some());
+}
+
+#[test]
+fn self_destruct_fails_if_beneficiary_is_not_another_local_canister() {
+    let canister_manager = CanisterManagerBuilder::default().build();
+    let mut state = state_with_self_destructing_canister();
+    let canister_id = canister_test_id(0);
+    state
+        .canister_state_mut(&canister_id)
+        .unwrap()
+        .system_state
+        .self_destruct_enabled = true;
+
+    for beneficiary in &[canister_id, canister_test_id(2)] {
+        let (result, _) = canister_manager.self_destruct(
+            canister_id,
+            *beneficiary,
+            canister_id.get(),
+            Cycles::from(0),
+            &mut state,
+        );
+        assert_eq!(
+            result,
+            Err(CanisterManagerError::SelfDestructInvalidBeneficiary {
+                canister_id,
+                beneficiary: *beneficiary
+            })
+        );
+    }
+    assert!(state
+        .canister_state(&canister_id)
+        .unwrap()
+        .execution_state
+        .is_some());
+}
+
+#[test]
+fn self_destruct_can_be_enabled_by_controllers() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        let sender = canister_test_id(1).get();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_id,
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+        assert!(
+            !state
+                .canister_state(&canister_id)
+                .unwrap()
+                .system_state
+                .self_destruct_enabled
+        );
+
+        let compute_allocation_used = state.total_compute_allocation();
+        let memory_allocation_used = state.total_memory_taken();
+        let mut canister = state.canister_state_mut(&canister_id).unwrap();
+        canister_manager
+            .update_settings(
+                sender,
+                false,
+                CanisterSettings::default().with_self_destruct_enabled(Some(true)),
+                &mut canister,
+                compute_allocation_used,
+                memory_allocation_used,
+            )
+            .unwrap();
+        assert!(canister.system_state.self_destruct_enabled);
+    });
+}
+
+#[test]
+fn heartbeat_instruction_limit_can_be_set_by_controllers() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        let sender = canister_test_id(1).get();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_id,
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+        assert_eq!(
+            state
+                .canister_state(&canister_id)
+                .unwrap()
+                .scheduler_state
+                .heartbeat_instruction_limit,
+            None
+        );
+
+        let compute_allocation_used = state.total_compute_allocation();
+        let memory_allocation_used = state.total_memory_taken();
+        let mut canister = state.canister_state_mut(&canister_id).unwrap();
+        canister_manager
+            .update_settings(
+                sender,
+                false,
+                CanisterSettings::default()
+                    .with_heartbeat_instruction_limit(Some(NumInstructions::from(1_000))),
+                &mut canister,
+                compute_allocation_used,
+                memory_allocation_used,
+            )
+            .unwrap();
+        assert_eq!(
+            canister.scheduler_state.heartbeat_instruction_limit,
+            Some(NumInstructions::from(1_000))
+        );
+    });
+}
+
+#[test]
+fn test_install_when_setting_memory_allocation_to_zero() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        let wasm = ic_test_utilities::universal_canister::UNIVERSAL_CANISTER_WASM.to_vec();
 
         let sender = canister_test_id(100).get();
         let settings = CanisterSettings::new(None, None, None, None, None);
@@ -3050,6 +3803,7 @@ fn test_install_when_setting_memory_allocation_to_zero() {
         canister_manager
             .update_settings(
                 sender,
+                false,
                 settings,
                 &mut canister,
                 compute_allocation_used,
@@ -3068,6 +3822,7 @@ fn test_install_when_setting_memory_allocation_to_zero() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Install,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -3106,6 +3861,7 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Install,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -3129,6 +3885,7 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
         canister_manager
             .update_settings(
                 sender,
+                false,
                 settings,
                 &mut canister,
                 compute_allocation_used,
@@ -3147,6 +3904,7 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
                     memory_allocation: None,
                     mode: CanisterInstallMode::Upgrade,
                     query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
                 },
                 &mut state,
                 EXECUTION_PARAMETERS.clone(),
@@ -3155,3 +3913,777 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
             .unwrap();
     })
 }
+
+fn with_migration_setup<F>(f: F)
+where
+    F: FnOnce(CanisterManager, ReplicatedState, CanisterManager, ReplicatedState),
+{
+    let source_subnet = subnet_test_id(1);
+    let target_subnet = subnet_test_id(2);
+    let source_tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
+    let target_tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
+    let mut source_state = initial_state(source_tmpdir.path(), source_subnet);
+    source_state
+        .metadata
+        .network_topology
+        .subnets
+        .insert(target_subnet, SubnetTopology::default());
+    f(
+        CanisterManagerBuilder::default()
+            .with_subnet_id(source_subnet)
+            .build(),
+        source_state,
+        CanisterManagerBuilder::default()
+            .with_subnet_id(target_subnet)
+            .build(),
+        initial_state(target_tmpdir.path(), target_subnet),
+    )
+}
+
+// Signs a certificate with the key derived from `seed` for `reply` being the
+// reply to the ingress message `message_id`, and returns it together with the
+// DER encoded public key.
+fn certify_reply(message_id: &MessageId, reply: Vec<u8>, seed: u8) -> (Vec<u8>, Vec<u8>) {
+    let mut encoded_time = vec![];
+    leb128::write::unsigned(&mut encoded_time, mock_time().as_nanos_since_unix_epoch()).unwrap();
+    fn labeled(label: &[u8], tree: MixedHashTree) -> MixedHashTree {
+        MixedHashTree::Labeled(Label::from(label), Box::new(tree))
+    }
+    let tree = MixedHashTree::Fork(Box::new((
+        labeled(
+            b"request_status",
+            labeled(
+                message_id.as_bytes(),
+                labeled(b"reply", MixedHashTree::Leaf(reply)),
+            ),
+        ),
+        labeled(b"time", MixedHashTree::Leaf(encoded_time)),
+    )));
+    let root_hash = CryptoHashOfPartialState::from(CryptoHash(tree.digest().to_vec()));
+    let (signature, public_key) = combined_threshold_signature_and_public_key(
+        Randomness::from([seed; 32]),
+        &CertificationContent::new(root_hash),
+    );
+    let certificate = Certificate {
+        tree,
+        signature: Blob(signature.get().0),
+        delegation: None,
+    };
+    (
+        serde_cbor::to_vec(&certificate).unwrap(),
+        threshold_sig_public_key_to_der(public_key).unwrap(),
+    )
+}
+
+// Exports `canister_id` from `source_state` to the subnet of `target_state`
+// and returns the arguments to import it there. The public key that certifies
+// the export is registered in the network topology of `target_state`.
+fn export_canister_for_import(
+    canister_manager: &CanisterManager,
+    source_state: &mut ReplicatedState,
+    target_state: &mut ReplicatedState,
+    controller: PrincipalId,
+    canister_id: CanisterId,
+    wasm_module: Vec<u8>,
+) -> ImportCanisterArgs {
+    let source_subnet = source_state.metadata.own_subnet_id;
+    let target_subnet = target_state.metadata.own_subnet_id;
+    let export_request = HttpCanisterUpdate {
+        canister_id: Blob(IC_00.get().to_vec()),
+        method_name: Ic00Method::ExportCanister.to_string(),
+        arg: Blob(ExportCanisterArgs::new(canister_id, target_subnet).encode()),
+        sender: Blob(controller.to_vec()),
+        ingress_expiry: 0,
+        nonce: None,
+    };
+    let export = canister_manager
+        .export_canister(controller, canister_id, target_subnet, source_state)
+        .unwrap();
+    let reply = ExportCanisterResult {
+        manifest: export.encode(),
+    }
+    .encode();
+    let (certificate, public_key) = certify_reply(&export_request.id(), reply, 0);
+    target_state.metadata.network_topology.subnets.insert(
+        source_subnet,
+        SubnetTopology {
+            public_key,
+            ..Default::default()
+        },
+    );
+    ImportCanisterArgs::new(
+        canister_id,
+        source_subnet,
+        certificate,
+        serde_cbor::to_vec(&export_request).unwrap(),
+        wasm_module,
+    )
+}
+
+#[test]
+fn canister_export_survives_encoding() {
+    let export = CanisterExport {
+        canister_id: canister_test_id(1),
+        target_subnet: subnet_test_id(2),
+        controllers: btreeset! {canister_test_id(3).get(), user_test_id(4).get()},
+        compute_allocation: ComputeAllocation::try_from(5).unwrap(),
+        memory_allocation: MemoryAllocation::try_from(NumBytes::from(6 << 20)).unwrap(),
+        freeze_threshold: 7,
+        cycles_balance: Cycles::new(8),
+        certified_data: vec![9; 32],
+        priority_class: PriorityClass::Elevated,
+        heartbeat_instruction_limit: Some(NumInstructions::from(10)),
+        self_destruct_enabled: true,
+        module: Some(ExportedModule {
+            wasm_module_hash: [11; 32],
+            heap_size: 12,
+            exported_globals: vec![
+                Global::I32(13),
+                Global::I64(14),
+                Global::F32(15.0),
+                Global::F64(16.0),
+            ],
+        }),
+        stable_memory_size: 17,
+        chunks: vec![
+            PendingImportChunk {
+                stable_memory: false,
+                offset: 0,
+                hash: [18; 32],
+            },
+            PendingImportChunk {
+                stable_memory: true,
+                offset: 1 << 20,
+                hash: [19; 32],
+            },
+        ],
+    };
+
+    assert_eq!(CanisterExport::decode(&export.encode()), Ok(export));
+}
+
+#[test]
+fn export_running_canister_fails() {
+    with_migration_setup(|canister_manager, mut state, _, target_state| {
+        let canister_id = canister_test_id(0);
+        let controller = canister_test_id(1).get();
+        state.put_canister_state(get_running_canister_with_args(
+            canister_id,
+            controller,
+            *INITIAL_CYCLES,
+        ));
+
+        assert_eq!(
+            canister_manager.export_canister(
+                controller,
+                canister_id,
+                target_state.metadata.own_subnet_id,
+                &mut state
+            ),
+            Err(CanisterManagerError::ExportCanisterNotStopped(canister_id))
+        );
+        assert!(state.canister_state(&canister_id).is_some());
+    });
+}
+
+#[test]
+fn export_canister_with_incorrect_controller_fails() {
+    with_migration_setup(|canister_manager, mut state, _, target_state| {
+        let canister_id = canister_test_id(0);
+        let controller = canister_test_id(1).get();
+        let wrong_controller = canister_test_id(2).get();
+        state.put_canister_state(get_stopped_canister_with_controller(
+            canister_id,
+            controller,
+        ));
+
+        assert_eq!(
+            canister_manager.export_canister(
+                wrong_controller,
+                canister_id,
+                target_state.metadata.own_subnet_id,
+                &mut state
+            ),
+            Err(CanisterManagerError::CanisterInvalidController {
+                canister_id,
+                controllers_expected: btreeset! {controller},
+                controller_provided: wrong_controller,
+            })
+        );
+        assert!(state.canister_state(&canister_id).is_some());
+    });
+}
+
+#[test]
+fn export_canister_to_unknown_subnet_fails() {
+    with_migration_setup(|canister_manager, mut state, _, _| {
+        let canister_id = canister_test_id(0);
+        let controller = canister_test_id(1).get();
+        state.put_canister_state(get_stopped_canister_with_controller(
+            canister_id,
+            controller,
+        ));
+
+        assert_matches!(
+            canister_manager.export_canister(
+                controller,
+                canister_id,
+                subnet_test_id(3),
+                &mut state
+            ),
+            Err(CanisterManagerError::InvalidCanisterExport { .. })
+        );
+        assert!(state.canister_state(&canister_id).is_some());
+    });
+}
+
+#[test]
+fn exported_canister_can_be_imported_on_another_subnet() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let controller = canister_test_id(42).get();
+            let canister_id = canister_manager
+                .create_canister(
+                    controller,
+                    state.metadata.own_subnet_id,
+                    *INITIAL_CYCLES,
+                    CanisterSettings::default(),
+                    &mut state,
+                )
+                .0
+                .unwrap();
+            let wasm_module = wabt::wat2wasm(COUNTER_WAT).unwrap();
+            canister_manager
+                .install_code(
+                    InstallCodeContextBuilder::default()
+                        .sender(controller)
+                        .canister_id(canister_id)
+                        .wasm_module(wasm_module.clone())
+                        .build(),
+                    &mut state,
+                    EXECUTION_PARAMETERS.clone(),
+                )
+                .1
+                .unwrap();
+
+            let mut canister = state.take_canister_state(&canister_id).unwrap();
+            canister.system_state.stable_memory_size = NumWasmPages64::new(1);
+            let mut buf = page_map::Buffer::new(PageMap::default());
+            buf.write(&[7; 10], 100);
+            canister.system_state.stable_memory = buf.into_page_map();
+            canister.system_state.certified_data = vec![1, 2, 3];
+            canister.system_state.self_destruct_enabled = true;
+            canister.system_state.status = CanisterStatus::Stopped;
+            canister.scheduler_state.priority_class = PriorityClass::Elevated;
+            canister.scheduler_state.heartbeat_instruction_limit =
+                Some(NumInstructions::from(1_000));
+            let old_canister = canister.clone();
+            state.put_canister_state(canister);
+
+            // The memories are small enough to fit into a single chunk each.
+            let wasm_memory = canister_manager
+                .export_canister_chunk(controller, canister_id, CanisterMemory::Wasm, 0, &state)
+                .unwrap();
+            let stable_memory = canister_manager
+                .export_canister_chunk(controller, canister_id, CanisterMemory::Stable, 0, &state)
+                .unwrap();
+            assert_eq!(stable_memory.len() as u64, WASM_PAGE_SIZE_IN_BYTES);
+
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                wasm_module,
+            );
+            assert_eq!(state.canister_state(&canister_id), None);
+
+            assert_eq!(
+                target_canister_manager.import_canister(controller, args, &mut target_state),
+                Ok(canister_id)
+            );
+
+            // The canister cannot run before its memories are transferred.
+            let mut new_canister = target_state.take_canister_state(&canister_id).unwrap();
+            assert_eq!(new_canister.system_state.pending_import_chunks.len(), 2);
+            assert_eq!(
+                target_canister_manager.start_canister(controller, &mut new_canister),
+                Err(CanisterManagerError::CanisterImportIncomplete {
+                    canister_id,
+                    pending_chunks: 2,
+                })
+            );
+            target_state.put_canister_state(new_canister);
+
+            for (memory, bytes) in vec![
+                (CanisterMemory::Wasm, wasm_memory),
+                (CanisterMemory::Stable, stable_memory),
+            ] {
+                target_canister_manager
+                    .import_canister_chunk(
+                        controller,
+                        ImportCanisterChunkArgs::new(canister_id, memory, 0, bytes),
+                        &mut target_state,
+                    )
+                    .unwrap();
+            }
+
+            let new_canister = target_state.canister_state(&canister_id).unwrap();
+            assert!(new_canister.system_state.pending_import_chunks.is_empty());
+            assert_eq!(new_canister.status(), CanisterStatusType::Stopped);
+            assert_eq!(new_canister.controllers(), old_canister.controllers());
+            assert_eq!(
+                new_canister.system_state.cycles_balance,
+                old_canister.system_state.cycles_balance
+            );
+            assert_eq!(new_canister.system_state.certified_data, vec![1, 2, 3]);
+            assert!(new_canister.system_state.self_destruct_enabled);
+            assert_eq!(
+                new_canister.scheduler_state.priority_class,
+                PriorityClass::Elevated
+            );
+            assert_eq!(
+                new_canister.scheduler_state.heartbeat_instruction_limit,
+                Some(NumInstructions::from(1_000))
+            );
+            assert_eq!(
+                new_canister.system_state.stable_memory_size,
+                NumWasmPages64::new(1)
+            );
+            let mut stable_memory = [0; 10];
+            page_map::Buffer::new(new_canister.system_state.stable_memory.clone())
+                .read(&mut stable_memory, 100);
+            assert_eq!(stable_memory, [7; 10]);
+
+            let old_execution_state = old_canister.execution_state.as_ref().unwrap();
+            let new_execution_state = new_canister.execution_state.as_ref().unwrap();
+            assert_eq!(
+                new_execution_state.wasm_binary,
+                old_execution_state.wasm_binary
+            );
+            assert_eq!(new_execution_state.heap_size, old_execution_state.heap_size);
+            assert_eq!(
+                new_execution_state.exported_globals,
+                old_execution_state.exported_globals
+            );
+            // The counter initialized by `canister_start` and `canister_init`.
+            let mut counter = [0; 4];
+            page_map::Buffer::new(new_execution_state.page_map.clone()).read(&mut counter, 0);
+            assert_eq!(counter, [42, 0, 0, 0]);
+        },
+    );
+}
+
+#[test]
+fn import_canister_with_incorrect_controller_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0);
+            let controller = canister_test_id(1).get();
+            let wrong_controller = canister_test_id(2).get();
+            state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+
+            assert_eq!(
+                target_canister_manager.import_canister(wrong_controller, args, &mut target_state),
+                Err(CanisterManagerError::CanisterInvalidController {
+                    canister_id,
+                    controllers_expected: btreeset! {controller},
+                    controller_provided: wrong_controller,
+                })
+            );
+            assert_eq!(target_state.canister_state(&canister_id), None);
+        },
+    );
+}
+
+#[test]
+fn import_existing_canister_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0);
+            let controller = canister_test_id(1).get();
+            state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+            target_state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+
+            assert_eq!(
+                target_canister_manager.import_canister(controller, args, &mut target_state),
+                Err(CanisterManagerError::CanisterAlreadyExists(canister_id))
+            );
+        },
+    );
+}
+
+#[test]
+fn import_canister_not_routed_to_subnet_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0x100);
+            let controller = canister_test_id(1).get();
+            state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+
+            assert_eq!(
+                target_canister_manager.import_canister(controller, args, &mut target_state),
+                Err(CanisterManagerError::CanisterNotHostedBySubnet {
+                    canister_id,
+                    subnet_id: target_state.metadata.own_subnet_id,
+                })
+            );
+        },
+    );
+}
+
+#[test]
+fn import_canister_twice_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0);
+            let controller = canister_test_id(1).get();
+            state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+            assert_eq!(
+                target_canister_manager.import_canister(
+                    controller,
+                    args.clone(),
+                    &mut target_state
+                ),
+                Ok(canister_id)
+            );
+            target_canister_manager
+                .delete_canister(controller, canister_id, &mut target_state)
+                .unwrap();
+
+            assert_matches!(
+                target_canister_manager.import_canister(controller, args, &mut target_state),
+                Err(CanisterManagerError::InvalidCanisterExport { .. })
+            );
+            assert_eq!(target_state.canister_state(&canister_id), None);
+        },
+    );
+}
+
+#[test]
+fn import_canister_with_certificate_of_another_key_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0);
+            let controller = canister_test_id(1).get();
+            state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+            let (_, public_key) = certify_reply(&message_test_id(0), vec![], 1);
+            target_state
+                .metadata
+                .network_topology
+                .subnets
+                .get_mut(&state.metadata.own_subnet_id)
+                .unwrap()
+                .public_key = public_key;
+
+            assert_matches!(
+                target_canister_manager.import_canister(controller, args, &mut target_state),
+                Err(CanisterManagerError::InvalidCanisterExport { .. })
+            );
+            assert_eq!(target_state.canister_state(&canister_id), None);
+        },
+    );
+}
+
+#[test]
+fn import_canister_with_expired_certificate_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0);
+            let controller = canister_test_id(1).get();
+            state.put_canister_state(get_stopped_canister_with_controller(
+                canister_id,
+                controller,
+            ));
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+            target_state.metadata.batch_time =
+                mock_time() + MAX_CANISTER_EXPORT_AGE + Duration::from_secs(1);
+
+            assert_matches!(
+                target_canister_manager.import_canister(controller, args, &mut target_state),
+                Err(CanisterManagerError::InvalidCanisterExport { .. })
+            );
+            assert_eq!(target_state.canister_state(&canister_id), None);
+        },
+    );
+}
+
+#[test]
+fn import_canister_chunk_not_in_export_fails() {
+    with_migration_setup(
+        |canister_manager, mut state, target_canister_manager, mut target_state| {
+            let canister_id = canister_test_id(0);
+            let controller = canister_test_id(1).get();
+            let mut canister = get_stopped_canister_with_controller(canister_id, controller);
+            canister.system_state.stable_memory_size = NumWasmPages64::new(1);
+            let mut buf = page_map::Buffer::new(PageMap::default());
+            buf.write(&[7; 10], 100);
+            canister.system_state.stable_memory = buf.into_page_map();
+            state.put_canister_state(canister);
+            let args = export_canister_for_import(
+                &canister_manager,
+                &mut state,
+                &mut target_state,
+                controller,
+                canister_id,
+                vec![],
+            );
+            target_canister_manager
+                .import_canister(controller, args, &mut target_state)
+                .unwrap();
+
+            let mut bytes = vec![0; WASM_PAGE_SIZE_IN_BYTES as usize];
+            bytes[100] = 8;
+            assert_matches!(
+                target_canister_manager.import_canister_chunk(
+                    controller,
+                    ImportCanisterChunkArgs::new(canister_id, CanisterMemory::Stable, 0, bytes),
+                    &mut target_state,
+                ),
+                Err(CanisterManagerError::InvalidCanisterExport { .. })
+            );
+            let canister = target_state.canister_state(&canister_id).unwrap();
+            assert_eq!(canister.system_state.pending_import_chunks.len(), 1);
+        },
+    );
+}
+
+#[test]
+fn upgrade_carries_over_persistent_globals() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        let sender = canister_test_id(100).get();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_id,
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+        let wat = r#"
+        (module
+            (func (export "canister_pre_upgrade")
+                (global.set $counter (i64.const 42))
+                (global.set $flag (i32.const 1))
+                (global.set $other (i64.const 13))
+            )
+            (global $counter (export "__persistent_counter") (mut i64) (i64.const 0))
+            (global $flag (export "__persistent_flag") (mut i32) (i32.const 0))
+            (global $other (export "other") (mut i64) (i64.const 0))
+        )"#;
+        canister_manager
+            .install_code(
+                InstallCodeContext {
+                    sender,
+                    canister_id,
+                    wasm_module: wabt::wat2wasm(wat).unwrap(),
+                    arg: vec![],
+                    compute_allocation: None,
+                    memory_allocation: None,
+                    mode: CanisterInstallMode::Install,
+                    query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
+                },
+                &mut state,
+                EXECUTION_PARAMETERS.clone(),
+            )
+            .1
+            .unwrap();
+
+        // The new module exports the globals in a different order and changes
+        // the type of `__persistent_flag`, so it keeps its initial value. The
+        // post-upgrade hook traps if any of the globals has an unexpected
+        // value.
+        let wat = r#"
+        (module
+            (func (export "canister_post_upgrade")
+                (if (i64.ne (global.get $counter) (i64.const 42)) (then unreachable))
+                (if (i64.ne (global.get $flag) (i64.const 5)) (then unreachable))
+                (if (i64.ne (global.get $other) (i64.const 0)) (then unreachable))
+            )
+            (global $other (export "other") (mut i64) (i64.const 0))
+            (global $flag (export "__persistent_flag") (mut i64) (i64.const 5))
+            (global $counter (export "__persistent_counter") (mut i64) (i64.const 0))
+        )"#;
+        canister_manager
+            .install_code(
+                InstallCodeContext {
+                    sender,
+                    canister_id,
+                    wasm_module: wabt::wat2wasm(wat).unwrap(),
+                    arg: vec![],
+                    compute_allocation: None,
+                    memory_allocation: None,
+                    mode: CanisterInstallMode::Upgrade,
+                    query_allocation: QueryAllocation::default(),
+                    skip_stable_compat_check: false,
+                },
+                &mut state,
+                EXECUTION_PARAMETERS.clone(),
+            )
+            .1
+            .unwrap();
+    });
+}
+
+/// Returns an empty module that declares `schema` as its stable memory schema.
+fn module_with_stable_compat_hash(schema: &[u8]) -> Vec<u8> {
+    let name = ic_wasm_utils::stable_compat::STABLE_COMPAT_SECTION_NAME.as_bytes();
+    let mut wasm = wabt::wat2wasm("(module)").unwrap();
+    // A custom section with LEB128 sizes that fit in a single byte.
+    wasm.push(0);
+    wasm.push((1 + name.len() + schema.len()) as u8);
+    wasm.push(name.len() as u8);
+    wasm.extend_from_slice(name);
+    wasm.extend_from_slice(schema);
+    wasm
+}
+
+#[test]
+fn upgrade_fails_if_stable_memory_schema_changes_unless_forced() {
+    with_setup(|canister_manager, mut state, subnet_id| {
+        let sender = canister_test_id(100).get();
+        let canister_id = canister_manager
+            .create_canister(
+                sender,
+                subnet_id,
+                *INITIAL_CYCLES,
+                CanisterSettings::default(),
+                &mut state,
+            )
+            .0
+            .unwrap();
+        let context = |mode, schema: &[u8], skip_stable_compat_check| InstallCodeContext {
+            sender,
+            canister_id,
+            wasm_module: module_with_stable_compat_hash(schema),
+            arg: vec![],
+            compute_allocation: None,
+            memory_allocation: None,
+            mode,
+            query_allocation: QueryAllocation::default(),
+            skip_stable_compat_check,
+        };
+        canister_manager
+            .install_code(
+                context(CanisterInstallMode::Install, b"v1", false),
+                &mut state,
+                EXECUTION_PARAMETERS.clone(),
+            )
+            .1
+            .unwrap();
+
+        // Upgrading to a module with the same schema succeeds.
+        canister_manager
+            .install_code(
+                context(CanisterInstallMode::Upgrade, b"v1", false),
+                &mut state,
+                EXECUTION_PARAMETERS.clone(),
+            )
+            .1
+            .unwrap();
+
+        let (instructions_left, result) = canister_manager.install_code(
+            context(CanisterInstallMode::Upgrade, b"v2", false),
+            &mut state,
+            EXECUTION_PARAMETERS.clone(),
+        );
+        assert_eq!(instructions_left, MAX_NUM_INSTRUCTIONS);
+        assert_eq!(
+            result,
+            Err(CanisterManagerError::StableMemoryIncompatible {
+                canister_id,
+                installed_hash: b"v1".to_vec(),
+                new_hash: b"v2".to_vec(),
+            })
+        );
+
+        canister_manager
+            .install_code(
+                context(CanisterInstallMode::Upgrade, b"v2", true),
+                &mut state,
+                EXECUTION_PARAMETERS.clone(),
+            )
+            .1
+            .unwrap();
+        assert_eq!(
+            state
+                .canister_state(&canister_id)
+                .unwrap()
+                .execution_state
+                .as_ref()
+                .unwrap()
+                .wasm_binary
+                .as_slice(),
+            module_with_stable_compat_hash(b"v2").as_slice()
+        );
+    });
+}
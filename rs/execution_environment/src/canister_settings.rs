@@ -1,21 +1,24 @@
 use ic_base_types::{NumBytes, NumSeconds};
-use ic_ic00_types::CanisterSettingsArgs;
+use ic_ic00_types::{CanisterPriorityClass, CanisterSettingsArgs};
 use ic_types::{
     user_error::{ErrorCode, UserError},
     ComputeAllocation, InvalidComputeAllocationError, InvalidMemoryAllocationError,
-    MemoryAllocation, PrincipalId,
+    MemoryAllocation, NumInstructions, PrincipalId, PriorityClass,
 };
 use num_traits::cast::ToPrimitive;
 use std::convert::TryFrom;
 
 /// Struct used for decoding CanisterSettingsArgs
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub(crate) struct CanisterSettings {
     controller: Option<PrincipalId>,
     controllers: Option<Vec<PrincipalId>>,
     compute_allocation: Option<ComputeAllocation>,
     memory_allocation: Option<MemoryAllocation>,
     freezing_threshold: Option<NumSeconds>,
+    priority_class: Option<PriorityClass>,
+    self_destruct_enabled: Option<bool>,
+    heartbeat_instruction_limit: Option<NumInstructions>,
 }
 
 impl CanisterSettings {
@@ -32,9 +35,30 @@ impl CanisterSettings {
             compute_allocation,
             memory_allocation,
             freezing_threshold,
+            priority_class: None,
+            self_destruct_enabled: None,
+            heartbeat_instruction_limit: None,
         }
     }
 
+    pub fn with_priority_class(mut self, priority_class: Option<PriorityClass>) -> Self {
+        self.priority_class = priority_class;
+        self
+    }
+
+    pub fn with_self_destruct_enabled(mut self, self_destruct_enabled: Option<bool>) -> Self {
+        self.self_destruct_enabled = self_destruct_enabled;
+        self
+    }
+
+    pub fn with_heartbeat_instruction_limit(
+        mut self,
+        heartbeat_instruction_limit: Option<NumInstructions>,
+    ) -> Self {
+        self.heartbeat_instruction_limit = heartbeat_instruction_limit;
+        self
+    }
+
     pub fn controller(&self) -> Option<PrincipalId> {
         self.controller
     }
@@ -54,6 +78,18 @@ impl CanisterSettings {
     pub fn freezing_threshold(&self) -> Option<NumSeconds> {
         self.freezing_threshold
     }
+
+    pub fn priority_class(&self) -> Option<PriorityClass> {
+        self.priority_class
+    }
+
+    pub fn self_destruct_enabled(&self) -> Option<bool> {
+        self.self_destruct_enabled
+    }
+
+    pub fn heartbeat_instruction_limit(&self) -> Option<NumInstructions> {
+        self.heartbeat_instruction_limit
+    }
 }
 
 impl TryFrom<CanisterSettingsArgs> for CanisterSettings {
@@ -83,13 +119,30 @@ impl TryFrom<CanisterSettingsArgs> for CanisterSettings {
             None => None,
         };
 
+        let priority_class = input
+            .priority_class
+            .map(|priority_class| match priority_class {
+                CanisterPriorityClass::Normal => PriorityClass::Normal,
+                CanisterPriorityClass::Elevated => PriorityClass::Elevated,
+            });
+
+        let heartbeat_instruction_limit = match input.heartbeat_instruction_limit {
+            Some(limit) => Some(NumInstructions::from(limit.0.to_u64().ok_or(
+                UpdateSettingsError::HeartbeatInstructionLimitOutOfRange { provided: limit },
+            )?)),
+            None => None,
+        };
+
         Ok(CanisterSettings::new(
             input.controller,
             input.controllers,
             compute_allocation,
             memory_allocation,
             freezing_threshold,
-        ))
+        )
+        .with_priority_class(priority_class)
+        .with_self_destruct_enabled(input.self_destruct_enabled)
+        .with_heartbeat_instruction_limit(heartbeat_instruction_limit))
     }
 }
 
@@ -108,6 +161,7 @@ pub enum UpdateSettingsError {
     ComputeAllocation(InvalidComputeAllocationError),
     MemoryAllocation(InvalidMemoryAllocationError),
     FreezingThresholdOutOfRange { provided: candid::Nat },
+    HeartbeatInstructionLimitOutOfRange { provided: candid::Nat },
 }
 
 impl From<UpdateSettingsError> for UserError {
@@ -136,6 +190,15 @@ impl From<UpdateSettingsError> for UserError {
                     provided
                 ),
             ),
+            UpdateSettingsError::HeartbeatInstructionLimitOutOfRange { provided } => {
+                UserError::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Heartbeat instruction limit expected to be in the range of [0..2^64-1], got {}",
+                        provided
+                    ),
+                )
+            }
         }
     }
 }
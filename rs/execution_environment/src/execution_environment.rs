@@ -10,10 +10,13 @@ use ic_base_types::PrincipalId;
 use ic_config::execution_environment::Config as ExecutionConfig;
 use ic_cycles_account_manager::{CyclesAccountManager, IngressInductionCost};
 use ic_ic00_types::{
-    CanisterIdRecord, CanisterSettingsArgs, CreateCanisterArgs, EmptyBlob, InstallCodeArgs,
-    Method as Ic00Method, Payload as Ic00Payload, ProvisionalCreateCanisterWithCyclesArgs,
-    ProvisionalTopUpCanisterArgs, SetControllerArgs, SetupInitialDKGArgs, UpdateSettingsArgs,
-    IC_00,
+    CanisterIdRecord, CanisterIdsRecord, CanisterSelfDestructArgs, CanisterSettingsArgs,
+    CostCallArgs, CostExecutionArgs, CreateCanisterArgs, CyclesCostRecord, EmptyBlob,
+    ExportCanisterArgs, ExportCanisterChunkArgs, ExportCanisterChunkResult, ExportCanisterResult,
+    ImportCanisterArgs, ImportCanisterChunkArgs, InstallCodeArgs, Method as Ic00Method,
+    Payload as Ic00Payload, ProvisionalCreateCanisterWithCyclesArgs,
+    ProvisionalCreateCanistersWithCyclesArgs, ProvisionalTopUpCanisterArgs, SetControllerArgs,
+    SetupInitialDKGArgs, UpdateSettingsArgs, IC_00,
 };
 use ic_interfaces::{
     execution_environment::{
@@ -22,14 +25,14 @@ use ic_interfaces::{
     },
     messages::{CanisterInputMessage, RequestOrIngress},
 };
-use ic_logger::{error, fatal, info, ReplicaLogger};
+use ic_logger::{error, fatal, in_message_context, info, MessageContext, ReplicaLogger};
 use ic_metrics::{MetricsRegistry, Timer};
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
 use ic_registry_routing_table::RoutingTable;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
     metadata_state::subnet_call_context_manager::{SetupInitialDkgContext, SignWithEcdsaContext},
-    CallContextAction, CallOrigin, CanisterState, ReplicatedState,
+    CallContextAction, CallOrigin, CanisterState, ReplicatedState, SystemTask,
 };
 use ic_types::{
     crypto::threshold_sig::ni_dkg::NiDkgTargetId,
@@ -42,6 +45,7 @@ use ic_types::{
     CanisterId, CanisterStatusType, ComputeAllocation, Cycles, InstallCodeContext, NumBytes,
     NumInstructions, SubnetId, Time, UserId,
 };
+use ic_wasm_types::InstructionCostOverrides;
 #[cfg(test)]
 use mockall::automock;
 use rand::RngCore;
@@ -86,6 +90,7 @@ pub trait ExecutionEnvironment: Sync + Send {
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> ExecuteMessageResult<CanisterState>;
 
@@ -96,6 +101,26 @@ pub trait ExecutionEnvironment: Sync + Send {
         instructions_limit: NumInstructions,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
+        time: Time,
+        subnet_available_memory: SubnetAvailableMemory,
+    ) -> (
+        CanisterState,
+        NumInstructions,
+        Result<NumBytes, CanisterHeartbeatError>,
+    );
+
+    /// Executes a system task of a given canister, e.g. its
+    /// `on_low_wasm_memory` hook. Fails in the same way as a heartbeat.
+    #[allow(clippy::too_many_arguments)]
+    fn execute_canister_system_task(
+        &self,
+        canister_state: CanisterState,
+        task: SystemTask,
+        instructions_limit: NumInstructions,
+        routing_table: Arc<RoutingTable>,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         time: Time,
         subnet_available_memory: SubnetAvailableMemory,
     ) -> (
@@ -109,13 +134,19 @@ pub trait ExecutionEnvironment: Sync + Send {
     fn subnet_available_memory(&self, state: &ReplicatedState) -> NumBytes;
 
     /// Builds execution parameters for the given canister with the given
-    /// instruction limit and available subnet memory counter.
+    /// instruction limit, available subnet memory counter and root key.
     fn execution_parameters(
         &self,
         canister: &CanisterState,
         instruction_limit: NumInstructions,
         subnet_available_memory: SubnetAvailableMemory,
+        root_key: &[u8],
     ) -> ExecutionParameters;
+
+    /// Sets the overrides of the instruction costs that canister code is
+    /// instrumented with. Called at the beginning of every round with the
+    /// overrides configured in the subnet record.
+    fn set_instruction_cost_overrides(&self, overrides: &InstructionCostOverrides);
 }
 
 /// Struct that is responsible for executing update type message messages on
@@ -201,8 +232,11 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
                                 };
                                 let result = match CanisterSettings::try_from(settings) {
                                     Err(err) => (Some((Err(err.into()), cycles)), instructions_limit),
-                                    Ok(settings) =>
-                                        (Some(self.create_canister(*msg.sender(), cycles, settings, &mut state)), instructions_limit)
+                                    Ok(settings) => match self.inherit_template_settings(*msg.sender(), args.template_canister_id, settings, &state) {
+                                        Err(err) => (Some((Err(err), cycles)), instructions_limit),
+                                        Ok(settings) =>
+                                            (Some(self.create_canister(*msg.sender(), cycles, settings, &mut state)), instructions_limit)
+                                    }
                                 };
                                 info!(
                                     self.log,
@@ -240,6 +274,14 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
                                 canister_memory_limit: self.config.max_canister_memory_size,
                                 subnet_available_memory,
                                 compute_allocation: ComputeAllocation::default(),
+                                canister_message_memory_capacity: self
+                                    .config
+                                    .canister_message_memory_capacity,
+                                root_key: state
+                                    .metadata
+                                    .network_topology
+                                    .nns_subnet_public_key()
+                                    .to_vec(),
                             };
 
                             let (instructions_left, result) = self.canister_manager.install_code(
@@ -296,6 +338,25 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
                 (Some((res, msg.take_cycles())), instructions_limit)
             }
 
+            Ok(Ic00Method::CanisterSelfDestruct) => match CanisterSelfDestructArgs::decode(payload)
+            {
+                Err(err) => (
+                    Some((Err(err.into()), msg.take_cycles())),
+                    instructions_limit,
+                ),
+                Ok(args) => {
+                    let (res, cycles_to_return) = self.canister_manager.self_destruct(
+                        args.get_canister_id(),
+                        args.get_beneficiary(),
+                        *msg.sender(),
+                        msg.take_cycles(),
+                        &mut state,
+                    );
+                    let res = res.map(|()| EmptyBlob::encode()).map_err(|err| err.into());
+                    (Some((res, cycles_to_return)), instructions_limit)
+                }
+            },
+
             Ok(Ic00Method::UpdateSettings) => {
                 let res = match UpdateSettingsArgs::decode(payload) {
                     Err(err) => Err(err.into()),
@@ -353,6 +414,54 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
                 (Some((res, msg.take_cycles())), instructions_limit)
             }
 
+            Ok(Ic00Method::CanisterMetrics) => {
+                let res = Err(UserError::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Management canister method '{}' can only be called as a query",
+                        msg.method_name()
+                    ),
+                ));
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
+            Ok(Ic00Method::ExportCanisterChunk) => {
+                let res = match ExportCanisterChunkArgs::decode(payload) {
+                    Err(err) => Err(err.into()),
+                    Ok(args) => self.export_canister_chunk(*msg.sender(), args, &state),
+                };
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
+            Ok(Ic00Method::ExportCanister) => {
+                let res = match ExportCanisterArgs::decode(payload) {
+                    Err(err) => Err(err.into()),
+                    Ok(args) => self.export_canister(
+                        *msg.sender(),
+                        args.get_canister_id(),
+                        args.get_target_subnet(),
+                        &mut state,
+                    ),
+                };
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
+            Ok(Ic00Method::ImportCanister) => {
+                let res = match ImportCanisterArgs::decode(payload) {
+                    Err(err) => Err(err.into()),
+                    Ok(args) => self.import_canister(*msg.sender(), args, &mut state),
+                };
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
+            Ok(Ic00Method::ImportCanisterChunk) => {
+                let res = match ImportCanisterChunkArgs::decode(payload) {
+                    Err(err) => Err(err.into()),
+                    Ok(args) => self.import_canister_chunk(*msg.sender(), args, &mut state),
+                };
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
             Ok(Ic00Method::StartCanister) => {
                 let res = match CanisterIdRecord::decode(payload) {
                     Err(err) => Err(err.into()),
@@ -413,6 +522,41 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
                 (Some((res, msg.take_cycles())), instructions_limit)
             }
 
+            Ok(Ic00Method::CostCreateCanister) => {
+                let res = EmptyBlob::decode(payload)
+                    .map(|()| {
+                        let fee = self.cycles_account_manager.canister_creation_fee();
+                        CyclesCostRecord::new(fee.get()).encode()
+                    })
+                    .map_err(|err| err.into());
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
+            Ok(Ic00Method::CostCall) => {
+                let res = CostCallArgs::decode(payload)
+                    .map(|args| {
+                        let payload_size = args.method_name_size.saturating_add(args.payload_size);
+                        let cost = self
+                            .cycles_account_manager
+                            .request_cost(NumBytes::from(payload_size));
+                        CyclesCostRecord::new(cost.get()).encode()
+                    })
+                    .map_err(|err| err.into());
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
+            Ok(Ic00Method::CostExecution) => {
+                let res = CostExecutionArgs::decode(payload)
+                    .map(|args| {
+                        let cost = self
+                            .cycles_account_manager
+                            .execution_cost(NumInstructions::from(args.num_instructions));
+                        CyclesCostRecord::new(cost.get()).encode()
+                    })
+                    .map_err(|err| err.into());
+                (Some((res, msg.take_cycles())), instructions_limit)
+            }
+
             Ok(Ic00Method::DepositCycles) => match CanisterIdRecord::decode(payload) {
                 Err(err) => (
                     Some((Err(err.into()), msg.take_cycles())),
@@ -519,6 +663,39 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
                 (Some((res, Cycles::zero())), instructions_limit)
             }
 
+            Ok(Ic00Method::ProvisionalCreateCanistersWithCycles) => {
+                let res = match ProvisionalCreateCanistersWithCyclesArgs::decode(payload) {
+                    Err(err) => Err(err.into()),
+                    Ok(args) => match args.count_to_u64() {
+                        None => Err(UserError::new(
+                            ErrorCode::CanisterContractViolation,
+                            format!("Invalid number of canisters: {}", args.count),
+                        )),
+                        Some(count) => {
+                            let cycles_amount = args.to_u64();
+                            match CanisterSettings::try_from(args.settings) {
+                                Ok(settings) => self
+                                    .canister_manager
+                                    .create_canisters_with_cycles(
+                                        *msg.sender(),
+                                        count,
+                                        cycles_amount,
+                                        settings,
+                                        &mut state,
+                                        provisional_whitelist,
+                                    )
+                                    .map(|canister_ids| {
+                                        CanisterIdsRecord::from(canister_ids).encode()
+                                    })
+                                    .map_err(|err| err.into()),
+                                Err(err) => Err(err.into()),
+                            }
+                        }
+                    },
+                };
+                (Some((res, Cycles::zero())), instructions_limit)
+            }
+
             Ok(Ic00Method::ProvisionalTopUpCanister) => {
                 let res = match ProvisionalTopUpCanisterArgs::decode(payload) {
                     Err(err) => Err(err.into()),
@@ -574,131 +751,44 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
 
     fn execute_canister_message(
         &self,
-        mut canister: CanisterState,
+        canister: CanisterState,
         instructions_limit: NumInstructions,
         msg: CanisterInputMessage,
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> ExecuteMessageResult<CanisterState> {
-        let (should_refund_remaining_cycles, mut res) = match msg {
-            CanisterInputMessage::Request(request) => {
-                let memory_usage = canister.memory_usage();
-                let compute_allocation = canister.scheduler_state.compute_allocation;
-                if let Err(err) = self.cycles_account_manager.withdraw_execution_cycles(
-                    &mut canister.system_state,
-                    memory_usage,
-                    compute_allocation,
-                    instructions_limit,
-                ) {
-                    // Canister is out of cycles. Reject the request.
-                    let canister_id = canister.canister_id();
-                    return self.reject_request(
-                        canister,
-                        instructions_limit,
-                        request,
-                        RejectContext {
-                            code: RejectCode::SysTransient,
-                            message: format!(
-                                "Canister {} is out of cycles: {}",
-                                canister_id,
-                                err.to_string()
-                            ),
-                        },
-                        NumBytes::from(0),
-                    );
-                }
-                (
-                    true,
-                    self.execute_canister_request(
-                        canister,
-                        request,
-                        instructions_limit,
-                        time,
-                        routing_table,
-                        subnet_records,
-                        subnet_available_memory,
-                    ),
-                )
-            }
-
-            CanisterInputMessage::Ingress(ingress) => {
-                let memory_usage = canister.memory_usage();
-                let compute_allocation = canister.scheduler_state.compute_allocation;
-                if let Err(err) = self.cycles_account_manager.withdraw_execution_cycles(
-                    &mut canister.system_state,
-                    memory_usage,
-                    compute_allocation,
-                    instructions_limit,
-                ) {
-                    // Canister is out of cycles. Reject the request.
-                    let canister_id = canister.canister_id();
-                    return ExecuteMessageResult {
-                        canister,
-                        num_instructions_left: instructions_limit,
-                        ingress_status: Some((
-                            ingress.message_id,
-                            IngressStatus::Failed {
-                                receiver: canister_id.get(),
-                                user_id: ingress.source,
-                                error: UserError::new(
-                                    ErrorCode::CanisterOutOfCycles,
-                                    format!(
-                                        "Canister {} is out of cycles: {}",
-                                        canister_id,
-                                        err.to_string()
-                                    ),
-                                ),
-                                time,
-                            },
-                        )),
-                        heap_delta: NumBytes::from(0),
-                    };
-                }
-                (
-                    true,
-                    self.execute_ingress(
-                        canister,
-                        ingress,
-                        instructions_limit,
-                        time,
-                        routing_table,
-                        subnet_records,
-                        subnet_available_memory,
-                    ),
-                )
-            }
-
-            CanisterInputMessage::Response(response) => self.execute_canister_response(
+        // Log entries of all components involved in the execution, e.g., the
+        // embedder and crypto, refer to the canister and message.
+        let mut context = MessageContext::new().with_canister_id(canister.canister_id());
+        if let CanisterInputMessage::Ingress(ingress) = &msg {
+            context = context.with_message_id(ingress.message_id.clone());
+        }
+        let mut result = in_message_context(&context, || {
+            self.execute_canister_input(
                 canister,
-                response,
                 instructions_limit,
+                msg,
                 time,
                 routing_table,
                 subnet_records,
+                root_key,
                 subnet_available_memory,
-            ),
-        };
-
-        if should_refund_remaining_cycles {
-            // Clone the `cycles_account_manager` to avoid having to require 'static
-            // lifetime bound on `self`.
-            let cycles_account_manager = Arc::clone(&self.cycles_account_manager);
-
-            // Refund the canister with any cycles left after message execution.
-            cycles_account_manager
-                .refund_execution_cycles(&mut res.canister.system_state, res.num_instructions_left);
-        }
-        res
+            )
+        });
+        self.update_on_low_wasm_memory_condition(&mut result.canister);
+        result
     }
 
     fn execute_canister_heartbeat(
         &self,
-        mut canister: CanisterState,
+        canister: CanisterState,
         instructions_limit: NumInstructions,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         time: Time,
         subnet_available_memory: SubnetAvailableMemory,
     ) -> (
@@ -706,59 +796,43 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
         NumInstructions,
         Result<NumBytes, CanisterHeartbeatError>,
     ) {
-        if canister.status() != CanisterStatusType::Running {
-            let status = canister.status();
-            return (
-                canister,
-                instructions_limit,
-                Err(CanisterHeartbeatError::CanisterNotRunning { status }),
-            );
-        }
-
-        let memory_usage = canister.memory_usage();
-        let compute_allocation = canister.scheduler_state.compute_allocation;
-        if self
-            .cycles_account_manager
-            .withdraw_execution_cycles(
-                &mut canister.system_state,
-                memory_usage,
-                compute_allocation,
-                instructions_limit,
-            )
-            .is_err()
-        {
-            return (
-                canister,
-                instructions_limit,
-                Err(CanisterHeartbeatError::OutOfCycles),
-            );
-        }
-
-        let execution_parameters =
-            self.execution_parameters(&canister, instructions_limit, subnet_available_memory);
-
-        let (mut canister, num_instructions_left, result) =
-            self.hypervisor.execute_canister_heartbeat(
-                canister,
-                routing_table,
-                subnet_records,
-                time,
-                execution_parameters,
-            );
-
-        // Clone the `cycles_account_manager` to avoid having to require 'static
-        // lifetime bound on `self`.
-        let cycles_account_manager = Arc::clone(&self.cycles_account_manager);
-
-        // Refund the canister with any cycles left after message execution.
-        cycles_account_manager
-            .refund_execution_cycles(&mut canister.system_state, num_instructions_left);
-        let result = match result {
-            Ok(heap_delta) => Ok(heap_delta),
-            Err(err) => Err(CanisterHeartbeatError::CanisterExecutionFailed(err)),
-        };
+        self.execute_canister_system_method(
+            canister,
+            None,
+            instructions_limit,
+            routing_table,
+            subnet_records,
+            root_key,
+            time,
+            subnet_available_memory,
+        )
+    }
 
-        (canister, num_instructions_left, result)
+    fn execute_canister_system_task(
+        &self,
+        canister: CanisterState,
+        task: SystemTask,
+        instructions_limit: NumInstructions,
+        routing_table: Arc<RoutingTable>,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
+        time: Time,
+        subnet_available_memory: SubnetAvailableMemory,
+    ) -> (
+        CanisterState,
+        NumInstructions,
+        Result<NumBytes, CanisterHeartbeatError>,
+    ) {
+        self.execute_canister_system_method(
+            canister,
+            Some(task),
+            instructions_limit,
+            routing_table,
+            subnet_records,
+            root_key,
+            time,
+            subnet_available_memory,
+        )
     }
 
     fn execution_parameters(
@@ -766,14 +840,22 @@ impl ExecutionEnvironment for ExecutionEnvironmentImpl {
         canister: &CanisterState,
         instruction_limit: NumInstructions,
         subnet_available_memory: SubnetAvailableMemory,
+        root_key: &[u8],
     ) -> ExecutionParameters {
         ExecutionParameters {
             instruction_limit,
             canister_memory_limit: canister.memory_limit(self.config.max_canister_memory_size),
             subnet_available_memory,
             compute_allocation: canister.scheduler_state.compute_allocation,
+            canister_message_memory_capacity: self.config.canister_message_memory_capacity,
+            root_key: root_key.to_vec(),
         }
     }
+
+    fn set_instruction_cost_overrides(&self, overrides: &InstructionCostOverrides) {
+        self.hypervisor
+            .set_instruction_cost_overrides(overrides.clone());
+    }
 }
 
 impl ExecutionEnvironmentImpl {
@@ -795,8 +877,11 @@ impl ExecutionEnvironmentImpl {
             config.default_freeze_threshold,
             config.max_globals,
             config.max_functions,
+            config.wasm_import_allow_list.clone(),
+            config.wasm_export_allow_list.clone(),
             own_subnet_id,
             config.max_controllers,
+            config.max_canister_creation_batch_size,
             num_cores,
         );
         let canister_manager = CanisterManager::new(
@@ -818,6 +903,116 @@ impl ExecutionEnvironmentImpl {
         }
     }
 
+    // Executes the heartbeat of the canister if `task` is `None` and the given
+    // system task otherwise. The canister pays for the instructions used.
+    #[allow(clippy::too_many_arguments)]
+    fn execute_canister_system_method(
+        &self,
+        mut canister: CanisterState,
+        task: Option<SystemTask>,
+        instructions_limit: NumInstructions,
+        routing_table: Arc<RoutingTable>,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
+        time: Time,
+        subnet_available_memory: SubnetAvailableMemory,
+    ) -> (
+        CanisterState,
+        NumInstructions,
+        Result<NumBytes, CanisterHeartbeatError>,
+    ) {
+        if canister.status() != CanisterStatusType::Running {
+            let status = canister.status();
+            return (
+                canister,
+                instructions_limit,
+                Err(CanisterHeartbeatError::CanisterNotRunning { status }),
+            );
+        }
+
+        let memory_usage = canister.memory_usage();
+        let compute_allocation = canister.scheduler_state.compute_allocation;
+        if self
+            .cycles_account_manager
+            .withdraw_execution_cycles(
+                &mut canister.system_state,
+                memory_usage,
+                compute_allocation,
+                instructions_limit,
+            )
+            .is_err()
+        {
+            return (
+                canister,
+                instructions_limit,
+                Err(CanisterHeartbeatError::OutOfCycles),
+            );
+        }
+
+        let execution_parameters = self.execution_parameters(
+            &canister,
+            instructions_limit,
+            subnet_available_memory,
+            root_key,
+        );
+
+        let context = MessageContext::new().with_canister_id(canister.canister_id());
+        let (mut canister, num_instructions_left, result) =
+            in_message_context(&context, || match task {
+                None => self.hypervisor.execute_canister_heartbeat(
+                    canister,
+                    routing_table,
+                    subnet_records,
+                    time,
+                    execution_parameters,
+                ),
+                Some(task) => self.hypervisor.execute_canister_system_task(
+                    canister,
+                    task,
+                    routing_table,
+                    subnet_records,
+                    time,
+                    execution_parameters,
+                ),
+            });
+
+        // Clone the `cycles_account_manager` to avoid having to require 'static
+        // lifetime bound on `self`.
+        let cycles_account_manager = Arc::clone(&self.cycles_account_manager);
+
+        // Refund the canister with any cycles left after message execution.
+        cycles_account_manager
+            .refund_execution_cycles(&mut canister.system_state, num_instructions_left);
+        if task.is_none() {
+            cycles_account_manager.observe_heartbeat_execution_cycles(
+                &mut canister.system_state,
+                instructions_limit - num_instructions_left,
+            );
+        }
+        self.update_on_low_wasm_memory_condition(&mut canister);
+        let result = match result {
+            Ok(heap_delta) => Ok(heap_delta),
+            Err(err) => Err(CanisterHeartbeatError::CanisterExecutionFailed(err)),
+        };
+
+        (canister, num_instructions_left, result)
+    }
+
+    // Enqueues the `on_low_wasm_memory` hook of the canister if it can no
+    // longer allocate `low_wasm_memory_threshold` bytes of Wasm memory and
+    // drops or re-arms the hook once it can again.
+    fn update_on_low_wasm_memory_condition(&self, canister: &mut CanisterState) {
+        let is_low_on_wasm_memory = canister.exports_on_low_wasm_memory_method()
+            && canister.is_low_on_wasm_memory(
+                self.config.max_canister_memory_size,
+                self.config.low_wasm_memory_threshold,
+            );
+        canister
+            .system_state
+            .task_queue
+            .set_on_low_wasm_memory_condition(is_low_on_wasm_memory);
+    }
+
     fn create_canister(
         &self,
         sender: PrincipalId,
@@ -844,6 +1039,32 @@ impl ExecutionEnvironmentImpl {
         }
     }
 
+    // Fills in the settings of a new canister that are not set from the
+    // template canister, if any.
+    fn inherit_template_settings(
+        &self,
+        sender: PrincipalId,
+        template_canister_id: Option<PrincipalId>,
+        settings: CanisterSettings,
+        state: &ReplicatedState,
+    ) -> Result<CanisterSettings, UserError> {
+        let template_id = match template_canister_id {
+            None => return Ok(settings),
+            Some(template_id) => CanisterId::new(template_id).map_err(|err| {
+                UserError::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!(
+                        "Converting template canister id {} failed with {}",
+                        template_id, err
+                    ),
+                )
+            })?,
+        };
+        self.canister_manager
+            .settings_from_template(sender, template_id, settings, state)
+            .map_err(|err| err.into())
+    }
+
     fn update_settings(
         &self,
         sender: PrincipalId,
@@ -853,11 +1074,14 @@ impl ExecutionEnvironmentImpl {
     ) -> Result<Vec<u8>, UserError> {
         let compute_allocation_used = state.total_compute_allocation();
         let memory_allocation_used = state.total_memory_taken();
+        let sender_is_on_nns = state.find_subnet_id(sender).ok()
+            == Some(state.metadata.network_topology.nns_subnet_id);
 
         let mut canister = get_canister_mut(canister_id, state)?;
         self.canister_manager
             .update_settings(
                 sender,
+                sender_is_on_nns,
                 settings,
                 &mut canister,
                 compute_allocation_used,
@@ -925,6 +1149,66 @@ impl ExecutionEnvironmentImpl {
             .map_err(|err| err.into())
     }
 
+    fn export_canister_chunk(
+        &self,
+        sender: PrincipalId,
+        args: ExportCanisterChunkArgs,
+        state: &ReplicatedState,
+    ) -> Result<Vec<u8>, UserError> {
+        self.canister_manager
+            .export_canister_chunk(
+                sender,
+                args.get_canister_id(),
+                args.memory,
+                args.offset,
+                state,
+            )
+            .map(|bytes| ExportCanisterChunkResult { bytes }.encode())
+            .map_err(|err| err.into())
+    }
+
+    fn export_canister(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        target_subnet: SubnetId,
+        state: &mut ReplicatedState,
+    ) -> Result<Vec<u8>, UserError> {
+        self.canister_manager
+            .export_canister(sender, canister_id, target_subnet, state)
+            .map(|export| {
+                ExportCanisterResult {
+                    manifest: export.encode(),
+                }
+                .encode()
+            })
+            .map_err(|err| err.into())
+    }
+
+    fn import_canister(
+        &self,
+        sender: PrincipalId,
+        args: ImportCanisterArgs,
+        state: &mut ReplicatedState,
+    ) -> Result<Vec<u8>, UserError> {
+        self.canister_manager
+            .import_canister(sender, args, state)
+            .map(|canister_id| CanisterIdRecord::from(canister_id).encode())
+            .map_err(|err| err.into())
+    }
+
+    fn import_canister_chunk(
+        &self,
+        sender: PrincipalId,
+        args: ImportCanisterChunkArgs,
+        state: &mut ReplicatedState,
+    ) -> Result<Vec<u8>, UserError> {
+        self.canister_manager
+            .import_canister_chunk(sender, args, state)
+            .map(|()| EmptyBlob::encode())
+            .map_err(|err| err.into())
+    }
+
     fn stop_canister(
         &self,
         canister_id: CanisterId,
@@ -975,6 +1259,7 @@ impl ExecutionEnvironmentImpl {
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> (bool, ExecuteMessageResult<CanisterState>) {
         let call_context_manager = match canister.status() {
@@ -1114,7 +1399,7 @@ impl ExecutionEnvironmentImpl {
             )
         } else {
             let execution_parameters =
-                self.execution_parameters(&canister, cycles, subnet_available_memory);
+                self.execution_parameters(&canister, cycles, subnet_available_memory, root_key);
             let (mut canister, cycles, heap_delta, result) = self.hypervisor.execute_callback(
                 canister,
                 &call_origin,
@@ -1182,6 +1467,7 @@ impl ExecutionEnvironmentImpl {
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> ExecuteMessageResult<CanisterState> {
         if CanisterStatusType::Running != canister.status() {
@@ -1200,7 +1486,7 @@ impl ExecutionEnvironmentImpl {
         }
 
         if canister.exports_query_method(req.method_name.clone()) {
-            self.execute_query_method_for_request(canister, req, cycles, time)
+            self.execute_query_method_for_request(canister, req, cycles, time, root_key)
         } else {
             self.execute_update_method_for_request(
                 canister,
@@ -1209,6 +1495,7 @@ impl ExecutionEnvironmentImpl {
                 time,
                 routing_table,
                 subnet_records,
+                root_key,
                 subnet_available_memory,
             )
         }
@@ -1249,13 +1536,14 @@ impl ExecutionEnvironmentImpl {
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> ExecuteMessageResult<CanisterState> {
         let sender = req.sender;
         let reply_callback = req.sender_reply_callback;
 
         let execution_parameters =
-            self.execution_parameters(&canister, cycles, subnet_available_memory);
+            self.execution_parameters(&canister, cycles, subnet_available_memory, root_key);
 
         let (mut canister, cycles, action, heap_delta) = self.hypervisor.execute_update(
             canister,
@@ -1282,13 +1570,14 @@ impl ExecutionEnvironmentImpl {
         req: Request,
         cycles: NumInstructions,
         time: Time,
+        root_key: &[u8],
     ) -> ExecuteMessageResult<CanisterState> {
         // Letting the canister grow arbitrarily when executing the
         // query is fine as we do not persist state modifications.
         let subnet_available_memory =
             SubnetAvailableMemory::new(self.config.subnet_memory_capacity);
         let execution_parameters =
-            self.execution_parameters(&canister, cycles, subnet_available_memory);
+            self.execution_parameters(&canister, cycles, subnet_available_memory, root_key);
         let (mut canister, cycles, result) = self.hypervisor.execute_query(
             QueryExecutionType::Replicated,
             req.method_name.as_str(),
@@ -1383,6 +1672,7 @@ impl ExecutionEnvironmentImpl {
                         &canister,
                         self.config.max_instructions_for_message_acceptance_calls,
                         subnet_available_memory,
+                        state.metadata.network_topology.nns_subnet_public_key(),
                     );
                     self.hypervisor.execute_inspect_message(
                         canister.clone(),
@@ -1398,6 +1688,133 @@ impl ExecutionEnvironmentImpl {
         }
     }
 
+    // Executes a message from the input queue of a canister.
+    #[allow(clippy::too_many_arguments)]
+    fn execute_canister_input(
+        &self,
+        mut canister: CanisterState,
+        instructions_limit: NumInstructions,
+        msg: CanisterInputMessage,
+        time: Time,
+        routing_table: Arc<RoutingTable>,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
+        subnet_available_memory: SubnetAvailableMemory,
+    ) -> ExecuteMessageResult<CanisterState> {
+        let (should_refund_remaining_cycles, mut res) = match msg {
+            CanisterInputMessage::Request(request) => {
+                let memory_usage = canister.memory_usage();
+                let compute_allocation = canister.scheduler_state.compute_allocation;
+                if let Err(err) = self.cycles_account_manager.withdraw_execution_cycles(
+                    &mut canister.system_state,
+                    memory_usage,
+                    compute_allocation,
+                    instructions_limit,
+                ) {
+                    // Canister is out of cycles. Reject the request.
+                    let canister_id = canister.canister_id();
+                    return self.reject_request(
+                        canister,
+                        instructions_limit,
+                        request,
+                        RejectContext {
+                            code: RejectCode::SysTransient,
+                            message: format!(
+                                "Canister {} is out of cycles: {}",
+                                canister_id,
+                                err.to_string()
+                            ),
+                        },
+                        NumBytes::from(0),
+                    );
+                }
+                (
+                    true,
+                    self.execute_canister_request(
+                        canister,
+                        request,
+                        instructions_limit,
+                        time,
+                        routing_table,
+                        subnet_records,
+                        root_key,
+                        subnet_available_memory,
+                    ),
+                )
+            }
+
+            CanisterInputMessage::Ingress(ingress) => {
+                let memory_usage = canister.memory_usage();
+                let compute_allocation = canister.scheduler_state.compute_allocation;
+                if let Err(err) = self.cycles_account_manager.withdraw_execution_cycles(
+                    &mut canister.system_state,
+                    memory_usage,
+                    compute_allocation,
+                    instructions_limit,
+                ) {
+                    // Canister is out of cycles. Reject the request.
+                    let canister_id = canister.canister_id();
+                    return ExecuteMessageResult {
+                        canister,
+                        num_instructions_left: instructions_limit,
+                        ingress_status: Some((
+                            ingress.message_id,
+                            IngressStatus::Failed {
+                                receiver: canister_id.get(),
+                                user_id: ingress.source,
+                                error: UserError::new(
+                                    ErrorCode::CanisterOutOfCycles,
+                                    format!(
+                                        "Canister {} is out of cycles: {}",
+                                        canister_id,
+                                        err.to_string()
+                                    ),
+                                ),
+                                time,
+                            },
+                        )),
+                        heap_delta: NumBytes::from(0),
+                    };
+                }
+                (
+                    true,
+                    self.execute_ingress(
+                        canister,
+                        ingress,
+                        instructions_limit,
+                        time,
+                        routing_table,
+                        subnet_records,
+                        root_key,
+                        subnet_available_memory,
+                    ),
+                )
+            }
+
+            CanisterInputMessage::Response(response) => self.execute_canister_response(
+                canister,
+                response,
+                instructions_limit,
+                time,
+                routing_table,
+                subnet_records,
+                root_key,
+                subnet_available_memory,
+            ),
+        };
+
+        if should_refund_remaining_cycles {
+            // Clone the `cycles_account_manager` to avoid having to require 'static
+            // lifetime bound on `self`.
+            let cycles_account_manager = Arc::clone(&self.cycles_account_manager);
+
+            // Refund the canister with any cycles left after message execution.
+            cycles_account_manager
+                .refund_execution_cycles(&mut res.canister.system_state, res.num_instructions_left);
+        }
+        res
+    }
+
     // Execute an ingress message.
     #[allow(clippy::too_many_arguments)]
     fn execute_ingress(
@@ -1408,6 +1825,7 @@ impl ExecutionEnvironmentImpl {
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> ExecuteMessageResult<CanisterState> {
         if CanisterStatusType::Running != canister.status() {
@@ -1440,7 +1858,7 @@ impl ExecutionEnvironmentImpl {
         assert!(ingress.expiry_time >= time);
 
         if canister.exports_query_method(ingress.method_name.clone()) {
-            self.execute_query_method_for_ingress(canister, ingress, cycles, time)
+            self.execute_query_method_for_ingress(canister, ingress, cycles, time, root_key)
         } else {
             self.execute_update_method_for_ingress(
                 canister,
@@ -1449,6 +1867,7 @@ impl ExecutionEnvironmentImpl {
                 time,
                 routing_table,
                 subnet_records,
+                root_key,
                 subnet_available_memory,
             )
         }
@@ -1464,13 +1883,14 @@ impl ExecutionEnvironmentImpl {
         time: Time,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         subnet_available_memory: SubnetAvailableMemory,
     ) -> ExecuteMessageResult<CanisterState> {
         let message_id = ingress.message_id.clone();
         let source = ingress.source;
 
         let execution_parameters =
-            self.execution_parameters(&canister, cycles, subnet_available_memory);
+            self.execution_parameters(&canister, cycles, subnet_available_memory, root_key);
         let (mut canister, cycles, action, heap_delta) = self.hypervisor.execute_update(
             canister,
             RequestOrIngress::Ingress(ingress),
@@ -1496,13 +1916,14 @@ impl ExecutionEnvironmentImpl {
         ingress: Ingress,
         cycles: NumInstructions,
         time: Time,
+        root_key: &[u8],
     ) -> ExecuteMessageResult<CanisterState> {
         // Letting the canister grow arbitrarily when executing the
         // query is fine as we do not persist state modifications.
         let subnet_available_memory =
             SubnetAvailableMemory::new(self.config.subnet_memory_capacity);
         let execution_parameters =
-            self.execution_parameters(&canister, cycles, subnet_available_memory);
+            self.execution_parameters(&canister, cycles, subnet_available_memory, root_key);
         let (canister, cycles, result) = self.hypervisor.execute_query(
             QueryExecutionType::Replicated,
             ingress.method_name.as_str(),
@@ -0,0 +1,474 @@
+//! A test harness for single-subnet execution scenarios.
+//!
+//! `ExecutionTestBuilder` assembles the components that tests otherwise have
+//! to wire up by hand (`Hypervisor`, `CanisterManager`, `ExecutionEnvironment`,
+//! the scheduler, the query handler, a routing table and a state) and returns
+//! an `ExecutionTest` that allows installing canisters, sending ingress
+//! messages, executing rounds and inspecting the resulting state.
+//!
+//! ```ignore
+//! let mut test = ExecutionTestBuilder::new().build();
+//! let canister_id = test.canister_from_wat(WAT).unwrap();
+//! let result = test.ingress(canister_id, "update", vec![]);
+//! assert_eq!(result, Ok(WasmResult::Reply(vec![])));
+//! ```
+use crate::{
+    canister_manager::{CanisterManager, CanisterManagerError, CanisterMgrConfig},
+    canister_settings::CanisterSettings,
+    hypervisor::Hypervisor,
+    query_handler::HttpQueryHandlerImpl,
+    scheduler::SchedulerImpl,
+    ExecutionEnvironmentImpl, IngressHistoryWriterImpl,
+};
+use ic_config::{execution_environment::Config, subnet_config::SchedulerConfig};
+use ic_cycles_account_manager::CyclesAccountManager;
+use ic_interfaces::execution_environment::{
+    ExecutionParameters, QueryHandler, Scheduler, SubnetAvailableMemory,
+};
+use ic_logger::{replica_logger::no_op_logger, ReplicaLogger};
+use ic_metrics::MetricsRegistry;
+use ic_registry_provisional_whitelist::ProvisionalWhitelist;
+use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
+use ic_registry_subnet_type::SubnetType;
+use ic_replicated_state::{CanisterState, ReplicatedState};
+use ic_test_utilities::{
+    cycles_account_manager::CyclesAccountManagerBuilder,
+    state_manager::FakeStateManager,
+    types::{
+        ids::{message_test_id, subnet_test_id, user_test_id},
+        messages::{IngressBuilder, InstallCodeContextBuilder},
+    },
+    universal_canister::UNIVERSAL_CANISTER_WASM,
+};
+use ic_types::{
+    ingress::{IngressStatus, WasmResult},
+    messages::{CanisterInstallMode, MessageId, UserQuery},
+    user_error::UserError,
+    CanisterId, ComputeAllocation, Cycles, ExecutionRound, NumBytes, NumInstructions, Randomness,
+    SubnetId, Time, UserId,
+};
+use maplit::btreemap;
+use std::{collections::BTreeSet, sync::Arc};
+use tempfile::TempDir;
+
+const INITIAL_CANISTER_CYCLES: Cycles = Cycles::new(1_000_000_000_000_000);
+const INSTRUCTION_LIMIT: NumInstructions = NumInstructions::new(1_000_000_000);
+const MAX_ROUNDS_PER_INGRESS: usize = 100;
+
+/// A builder for `ExecutionTest`.
+pub(crate) struct ExecutionTestBuilder {
+    subnet_type: SubnetType,
+    scheduler_config: SchedulerConfig,
+    execution_config: Config,
+    instruction_limit: NumInstructions,
+    initial_canister_cycles: Cycles,
+    log: ReplicaLogger,
+}
+
+impl Default for ExecutionTestBuilder {
+    fn default() -> Self {
+        Self {
+            subnet_type: SubnetType::Application,
+            scheduler_config: SchedulerConfig::application_subnet(),
+            execution_config: Config::default(),
+            instruction_limit: INSTRUCTION_LIMIT,
+            initial_canister_cycles: INITIAL_CANISTER_CYCLES,
+            log: no_op_logger(),
+        }
+    }
+}
+
+impl ExecutionTestBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the type of the subnet and the matching default scheduler
+    /// configuration.
+    pub fn with_subnet_type(mut self, subnet_type: SubnetType) -> Self {
+        self.subnet_type = subnet_type;
+        self.scheduler_config = SchedulerConfig::default_for_subnet_type(subnet_type);
+        self
+    }
+
+    pub fn with_scheduler_config(mut self, scheduler_config: SchedulerConfig) -> Self {
+        self.scheduler_config = scheduler_config;
+        self
+    }
+
+    pub fn with_execution_config(mut self, execution_config: Config) -> Self {
+        self.execution_config = execution_config;
+        self
+    }
+
+    /// Sets the instruction limit used for installing code.
+    pub fn with_instruction_limit(mut self, instruction_limit: NumInstructions) -> Self {
+        self.instruction_limit = instruction_limit;
+        self
+    }
+
+    /// Sets the cycles balance of canisters created by `ExecutionTest`.
+    pub fn with_initial_canister_cycles(mut self, initial_canister_cycles: Cycles) -> Self {
+        self.initial_canister_cycles = initial_canister_cycles;
+        self
+    }
+
+    pub fn with_log(mut self, log: ReplicaLogger) -> Self {
+        self.log = log;
+        self
+    }
+
+    pub fn build(self) -> ExecutionTest {
+        let own_subnet_id = subnet_test_id(1);
+        let metrics_registry = MetricsRegistry::new();
+        let cycles_account_manager = Arc::new(
+            CyclesAccountManagerBuilder::new()
+                .with_subnet_id(own_subnet_id)
+                .with_subnet_type(self.subnet_type)
+                .build(),
+        );
+
+        let tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
+        let mut state = ReplicatedState::new_rooted_at(
+            own_subnet_id,
+            self.subnet_type,
+            tmpdir.path().to_path_buf(),
+        );
+        state.metadata.network_topology.routing_table = RoutingTable::new(btreemap! {
+            CanisterIdRange{ start: CanisterId::from(0), end: CanisterId::from(0xff) } => own_subnet_id,
+        });
+        state.metadata.network_topology.nns_subnet_id = own_subnet_id;
+
+        let hypervisor = Arc::new(Hypervisor::new(
+            self.execution_config.clone(),
+            1,
+            &metrics_registry,
+            own_subnet_id,
+            self.subnet_type,
+            self.log.clone(),
+            Arc::clone(&cycles_account_manager),
+        ));
+        let ingress_history_writer = Arc::new(IngressHistoryWriterImpl::new(
+            self.log.clone(),
+            &metrics_registry,
+        ));
+        let canister_manager = CanisterManager::new(
+            Arc::clone(&hypervisor),
+            self.log.clone(),
+            CanisterMgrConfig::new(
+                self.execution_config.subnet_memory_capacity,
+                self.execution_config.max_cycles_per_canister,
+                self.execution_config.default_provisional_cycles_balance,
+                self.execution_config.default_freeze_threshold,
+                self.execution_config.max_globals,
+                self.execution_config.max_functions,
+                self.execution_config.wasm_import_allow_list.clone(),
+                self.execution_config.wasm_export_allow_list.clone(),
+                own_subnet_id,
+                self.execution_config.max_controllers,
+                self.execution_config.max_canister_creation_batch_size,
+                self.scheduler_config.scheduler_cores,
+            ),
+            Arc::clone(&cycles_account_manager),
+            Arc::clone(&ingress_history_writer) as Arc<_>,
+        );
+        let exec_env = Arc::new(ExecutionEnvironmentImpl::new(
+            self.log.clone(),
+            Arc::clone(&hypervisor),
+            Arc::clone(&ingress_history_writer) as Arc<_>,
+            &metrics_registry,
+            own_subnet_id,
+            self.scheduler_config.scheduler_cores,
+            self.execution_config.clone(),
+            Arc::clone(&cycles_account_manager),
+        ));
+        let scheduler = SchedulerImpl::new(
+            self.scheduler_config,
+            own_subnet_id,
+            Arc::clone(&ingress_history_writer) as Arc<_>,
+            exec_env as Arc<_>,
+            Arc::clone(&cycles_account_manager),
+            &metrics_registry,
+            self.log.clone(),
+            None,
+        );
+        let query_handler = HttpQueryHandlerImpl::new(
+            self.log,
+            hypervisor,
+            own_subnet_id,
+            self.subnet_type,
+            self.execution_config,
+            &metrics_registry,
+            Arc::new(FakeStateManager::new()),
+            Arc::clone(&cycles_account_manager),
+            self.instruction_limit,
+        );
+
+        ExecutionTest {
+            state: Some(state),
+            own_subnet_id,
+            user_id: user_test_id(1),
+            instruction_limit: self.instruction_limit,
+            initial_canister_cycles: self.initial_canister_cycles,
+            round: ExecutionRound::from(0),
+            next_message_id: 0,
+            canister_manager,
+            scheduler,
+            query_handler,
+            cycles_account_manager,
+            metrics_registry,
+            _tmpdir: tmpdir,
+        }
+    }
+}
+
+/// A single-subnet execution environment that tests drive by installing
+/// canisters, sending messages and executing rounds.
+pub(crate) struct ExecutionTest {
+    // The state is moved in and out of the scheduler on every round, hence
+    // the `Option`. It is always `Some` outside of `execute_round()`.
+    state: Option<ReplicatedState>,
+    own_subnet_id: SubnetId,
+    user_id: UserId,
+    instruction_limit: NumInstructions,
+    initial_canister_cycles: Cycles,
+    round: ExecutionRound,
+    next_message_id: u64,
+    canister_manager: CanisterManager,
+    scheduler: SchedulerImpl,
+    query_handler: HttpQueryHandlerImpl,
+    cycles_account_manager: Arc<CyclesAccountManager>,
+    metrics_registry: MetricsRegistry,
+    // Keeps the directory of the state alive for the duration of the test.
+    _tmpdir: TempDir,
+}
+
+impl ExecutionTest {
+    pub fn state(&self) -> &ReplicatedState {
+        self.state.as_ref().unwrap()
+    }
+
+    pub fn state_mut(&mut self) -> &mut ReplicatedState {
+        self.state.as_mut().unwrap()
+    }
+
+    pub fn canister_state(&self, canister_id: CanisterId) -> &CanisterState {
+        self.state().canister_state(&canister_id).unwrap()
+    }
+
+    pub fn canister_state_mut(&mut self, canister_id: CanisterId) -> &mut CanisterState {
+        self.state_mut().canister_state_mut(&canister_id).unwrap()
+    }
+
+    pub fn cycles_balance(&self, canister_id: CanisterId) -> Cycles {
+        self.canister_state(canister_id).system_state.cycles_balance
+    }
+
+    /// The user id used as the sender of ingress messages and queries and as
+    /// the controller of created canisters.
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    /// The number of rounds executed so far.
+    pub fn round(&self) -> ExecutionRound {
+        self.round
+    }
+
+    pub fn metrics_registry(&self) -> &MetricsRegistry {
+        &self.metrics_registry
+    }
+
+    pub fn cycles_account_manager(&self) -> &CyclesAccountManager {
+        &self.cycles_account_manager
+    }
+
+    pub fn scheduler(&self) -> &SchedulerImpl {
+        &self.scheduler
+    }
+
+    pub fn query_handler(&self) -> &HttpQueryHandlerImpl {
+        &self.query_handler
+    }
+
+    /// Creates an empty canister controlled by `user_id()`.
+    pub fn create_canister(&mut self, cycles: Cycles) -> CanisterId {
+        let sender = self.user_id.get();
+        let own_subnet_id = self.own_subnet_id;
+        let mut state = self.state.take().unwrap();
+        let (result, _) = self.canister_manager.create_canister(
+            sender,
+            own_subnet_id,
+            cycles,
+            CanisterSettings::default(),
+            &mut state,
+        );
+        self.state = Some(state);
+        result.unwrap()
+    }
+
+    /// Installs the given Wasm binary on an existing canister.
+    pub fn install_canister(
+        &mut self,
+        canister_id: CanisterId,
+        wasm_binary: Vec<u8>,
+    ) -> Result<(), CanisterManagerError> {
+        self.install_code(canister_id, CanisterInstallMode::Install, wasm_binary)
+    }
+
+    /// Upgrades the given canister to the given Wasm binary.
+    pub fn upgrade_canister(
+        &mut self,
+        canister_id: CanisterId,
+        wasm_binary: Vec<u8>,
+    ) -> Result<(), CanisterManagerError> {
+        self.install_code(canister_id, CanisterInstallMode::Upgrade, wasm_binary)
+    }
+
+    fn install_code(
+        &mut self,
+        canister_id: CanisterId,
+        mode: CanisterInstallMode,
+        wasm_binary: Vec<u8>,
+    ) -> Result<(), CanisterManagerError> {
+        let context = InstallCodeContextBuilder::default()
+            .sender(self.user_id.get())
+            .canister_id(canister_id)
+            .mode(mode)
+            .wasm_module(wasm_binary)
+            .build();
+        let execution_parameters = ExecutionParameters {
+            instruction_limit: self.instruction_limit,
+            canister_memory_limit: NumBytes::from(u64::MAX),
+            subnet_available_memory: SubnetAvailableMemory::new(NumBytes::from(u64::MAX)),
+            compute_allocation: ComputeAllocation::default(),
+            canister_message_memory_capacity: NumBytes::from(u64::MAX),
+            root_key: vec![],
+        };
+        let mut state = self.state.take().unwrap();
+        let (_, result) =
+            self.canister_manager
+                .install_code(context, &mut state, execution_parameters);
+        self.state = Some(state);
+        result.map(|_| ())
+    }
+
+    /// Creates a canister and installs the given Wasm binary on it.
+    pub fn canister_from_binary(
+        &mut self,
+        wasm_binary: Vec<u8>,
+    ) -> Result<CanisterId, CanisterManagerError> {
+        let canister_id = self.create_canister(self.initial_canister_cycles);
+        self.install_canister(canister_id, wasm_binary)?;
+        Ok(canister_id)
+    }
+
+    /// Creates a canister and installs the module given in the WebAssembly
+    /// text format on it.
+    pub fn canister_from_wat(&mut self, wat: &str) -> Result<CanisterId, CanisterManagerError> {
+        self.canister_from_binary(wabt::wat2wasm(wat).unwrap())
+    }
+
+    /// Creates a canister running the universal canister.
+    pub fn universal_canister(&mut self) -> CanisterId {
+        self.canister_from_binary(UNIVERSAL_CANISTER_WASM.to_vec())
+            .unwrap()
+    }
+
+    /// Enqueues an ingress message in the input queue of the canister
+    /// without executing it. Returns the id of the message that can be used
+    /// to look up its status after executing rounds.
+    pub fn ingress_raw(
+        &mut self,
+        canister_id: CanisterId,
+        method_name: &str,
+        method_payload: Vec<u8>,
+    ) -> MessageId {
+        let message_id = message_test_id(self.next_message_id);
+        self.next_message_id += 1;
+        let ingress = IngressBuilder::new()
+            .source(self.user_id)
+            .receiver(canister_id)
+            .method_name(method_name)
+            .method_payload(method_payload)
+            .message_id(message_id.clone())
+            .build();
+        self.canister_state_mut(canister_id).push_ingress(ingress);
+        message_id
+    }
+
+    /// Enqueues an ingress message and executes rounds until the message
+    /// reaches a terminal state.
+    ///
+    /// Panics if the message does not complete within a bounded number of
+    /// rounds.
+    pub fn ingress(
+        &mut self,
+        canister_id: CanisterId,
+        method_name: &str,
+        method_payload: Vec<u8>,
+    ) -> Result<WasmResult, UserError> {
+        let message_id = self.ingress_raw(canister_id, method_name, method_payload);
+        for _ in 0..MAX_ROUNDS_PER_INGRESS {
+            self.execute_round();
+            match self.ingress_status(&message_id) {
+                IngressStatus::Completed { result, .. } => return Ok(result),
+                IngressStatus::Failed { error, .. } => return Err(error),
+                IngressStatus::Received { .. }
+                | IngressStatus::Processing { .. }
+                | IngressStatus::Unknown => {}
+            }
+        }
+        panic!(
+            "Ingress message {} did not complete within {} rounds",
+            message_id, MAX_ROUNDS_PER_INGRESS
+        );
+    }
+
+    pub fn ingress_status(&self, message_id: &MessageId) -> IngressStatus {
+        self.state().get_ingress_status(message_id)
+    }
+
+    /// Executes a non-replicated query against the current state.
+    pub fn query(
+        &self,
+        canister_id: CanisterId,
+        method_name: &str,
+        method_payload: Vec<u8>,
+    ) -> Result<WasmResult, UserError> {
+        self.query_handler.query(
+            UserQuery {
+                source: self.user_id,
+                receiver: canister_id,
+                method_name: method_name.to_string(),
+                method_payload,
+                ingress_expiry: 0,
+                nonce: None,
+            },
+            Arc::new(self.state().clone()),
+            vec![],
+        )
+    }
+
+    /// Executes a single round with the given batch time.
+    pub fn execute_round_at(&mut self, time: Time) {
+        self.round.inc_assign();
+        let mut state = self.state.take().unwrap();
+        let time_of_previous_batch = state.time();
+        state.metadata.batch_time = time;
+        let state = self.scheduler.execute_round(
+            state,
+            Randomness::from([0; 32]),
+            time_of_previous_batch,
+            self.round,
+            ProvisionalWhitelist::Set(BTreeSet::new()),
+        );
+        self.state = Some(state);
+    }
+
+    /// Executes a single round without advancing the time.
+    pub fn execute_round(&mut self) {
+        let time = self.state().time();
+        self.execute_round_at(time);
+    }
+}
@@ -4,7 +4,8 @@ use ic_config::{embedders::Config as EmbeddersConfig, execution_environment::Con
 use ic_cow_state::{error::CowError, CowMemoryManager};
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_embedders::{
-    wasm_executor::WasmExecutor, WasmExecutionInput, WasmExecutionOutput, WasmtimeEmbedder,
+    wasm_executor::{CompilationTrigger, WasmExecutor},
+    WasmExecutionInput, WasmExecutionOutput, WasmtimeEmbedder,
 };
 use ic_interfaces::execution_environment::{
     ExecutionParameters, HypervisorError, HypervisorResult, MessageAcceptanceError,
@@ -18,18 +19,20 @@ use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::EmbedderCache;
 use ic_replicated_state::{
     page_map::allocated_pages_count, CallContextAction, CallOrigin, CanisterState, ExecutionState,
-    SchedulerState, SystemState,
+    SchedulerState, SystemState, SystemTask,
 };
 use ic_sys::PAGE_SIZE;
 use ic_system_api::{ApiType, NonReplicatedQueryKind};
 use ic_types::{
+    ic00::IC_00,
     ingress::WasmResult,
     messages::Payload,
     methods::{Callback, FuncRef, SystemMethod, WasmMethod},
     CanisterStatusType, ComputeAllocation, Cycles, NumBytes, NumInstructions, PrincipalId,
     SubnetId, Time,
 };
-use ic_wasm_types::BinaryEncodedWasm;
+use ic_wasm_types::{BinaryEncodedWasm, InstructionCostOverrides};
+use ic_wasm_utils::validation::WasmAllowLists;
 use prometheus::{Histogram, IntCounterVec, IntGauge};
 use std::{collections::BTreeMap, sync::Arc};
 
@@ -95,6 +98,9 @@ pub struct Hypervisor {
     own_subnet_type: SubnetType,
     log: ReplicaLogger,
     cycles_account_manager: Arc<CyclesAccountManager>,
+    legacy_data_certificate_in_replicated_queries: bool,
+    max_instructions_per_http_transform: NumInstructions,
+    max_http_transform_response_size: NumBytes,
 }
 
 impl Hypervisor {
@@ -188,6 +194,7 @@ impl Hypervisor {
             routing_table,
             subnet_records,
         );
+        let instruction_limit = execution_parameters.instruction_limit;
         let output = execute(
             api_type,
             system_state.clone(),
@@ -200,6 +207,8 @@ impl Hypervisor {
             Arc::clone(&self.wasm_executor),
         );
 
+        let trapped = output.wasm_result.is_err();
+        let instructions_executed = instruction_limit - output.num_instructions_left;
         let (mut system_state, heap_delta) = if output.wasm_result.is_ok() {
             (
                 output.system_state,
@@ -216,8 +225,16 @@ impl Hypervisor {
             .unwrap()
             .on_canister_result(call_context_id, output.wasm_result);
 
-        let canister =
+        let mut canister =
             CanisterState::from_parts(Some(output.execution_state), system_state, scheduler_state);
+        record_execution_metrics(
+            &mut canister,
+            time,
+            true,
+            instructions_executed,
+            trapped,
+            memory_usage,
+        );
         (canister, output.num_instructions_left, action, heap_delta)
     }
 
@@ -286,6 +303,13 @@ impl Hypervisor {
                         Some(Arc::new(execution_state.cow_mem_mgr.get_map()));
                 }
 
+                // The data certificate is only available in non-replicated
+                // queries, unless the legacy behavior is enabled.
+                let data_certificate = if self.legacy_data_certificate_in_replicated_queries {
+                    data_certificate
+                } else {
+                    None
+                };
                 let api_type =
                     ApiType::replicated_query(time, payload.to_vec(), caller, data_certificate);
                 // As we are executing the query in the replicated mode, we do
@@ -376,6 +400,122 @@ impl Hypervisor {
         }
     }
 
+    /// Executes the transform function `method` of an HTTP outcall made by
+    /// `canister` on the `response` of the remote server.
+    ///
+    /// The transform runs like a replicated query: it cannot make calls and
+    /// its modifications to the canister's state are rolled back. Unlike a
+    /// query, it runs for at most `max_instructions_per_http_transform`
+    /// instructions, whatever the instruction limit of `execution_parameters`,
+    /// its reply is limited to `max_http_transform_response_size` and the
+    /// instructions it executes are charged to the canister, so that a
+    /// malicious transform cannot consume time of the subnet for free.
+    pub fn execute_http_transform(
+        &self,
+        method: &str,
+        response: &[u8],
+        canister: CanisterState,
+        time: Time,
+        mut execution_parameters: ExecutionParameters,
+    ) -> (
+        CanisterState,
+        NumInstructions,
+        HypervisorResult<Option<WasmResult>>,
+    ) {
+        // Validate that the canister is running.
+        if CanisterStatusType::Running != canister.status() {
+            return (
+                canister,
+                execution_parameters.instruction_limit,
+                Err(HypervisorError::CanisterStopped),
+            );
+        }
+
+        let method = WasmMethod::Query(method.to_string());
+        let memory_usage = canister.memory_usage();
+        let compute_allocation = canister.scheduler_state.compute_allocation;
+        let (execution_state, mut system_state, scheduler_state) = canister.into_parts();
+
+        // Validate that the Wasm module is present.
+        let mut execution_state = match execution_state {
+            None => {
+                return (
+                    CanisterState::from_parts(None, system_state, scheduler_state),
+                    execution_parameters.instruction_limit,
+                    Err(HypervisorError::WasmModuleNotFound),
+                );
+            }
+            Some(state) => state,
+        };
+
+        // Validate that the Wasm module exports the method.
+        if !execution_state.exports_method(&method) {
+            return (
+                CanisterState::from_parts(Some(execution_state), system_state, scheduler_state),
+                execution_parameters.instruction_limit,
+                Err(HypervisorError::MethodNotFound(method)),
+            );
+        }
+
+        let instruction_limit = std::cmp::min(
+            execution_parameters.instruction_limit,
+            self.max_instructions_per_http_transform,
+        );
+        if let Err(err) = self.cycles_account_manager.withdraw_execution_cycles(
+            &mut system_state,
+            memory_usage,
+            compute_allocation,
+            instruction_limit,
+        ) {
+            return (
+                CanisterState::from_parts(Some(execution_state), system_state, scheduler_state),
+                execution_parameters.instruction_limit,
+                Err(HypervisorError::InsufficientCyclesBalance {
+                    available: err.available,
+                    requested: err.requested,
+                }),
+            );
+        }
+        let instructions_not_granted = execution_parameters.instruction_limit - instruction_limit;
+        execution_parameters.instruction_limit = instruction_limit;
+
+        if execution_state.cow_mem_mgr.is_valid() {
+            execution_state.mapped_state = Some(Arc::new(execution_state.cow_mem_mgr.get_map()));
+        }
+        let api_type = ApiType::transform(
+            time,
+            response.to_vec(),
+            IC_00.get(),
+            self.max_http_transform_response_size,
+        );
+        // The transform must not modify the canister, so execute on clones of
+        // the system and execution states.
+        let output = execute(
+            api_type,
+            system_state.clone(),
+            memory_usage,
+            execution_parameters,
+            FuncRef::Method(method),
+            execution_state.clone(),
+            Arc::clone(&self.cycles_account_manager),
+            Arc::clone(&self.metrics),
+            Arc::clone(&self.wasm_executor),
+        );
+
+        // Besides the embedder cache, only the cycles charged for the
+        // execution are kept.
+        execution_state.embedder_cache = output.execution_state.embedder_cache;
+        self.cycles_account_manager
+            .refund_execution_cycles(&mut system_state, output.num_instructions_left);
+        let canister =
+            CanisterState::from_parts(Some(execution_state), system_state, scheduler_state);
+        (
+            canister,
+            output.num_instructions_left + instructions_not_granted,
+            output.wasm_result,
+        )
+    }
+
     /// Execute a callback.
     ///
     /// Callbacks are executed when a canister receives a response to an
@@ -478,10 +618,12 @@ impl Hypervisor {
             }
         };
 
+        let memory_usage = canister.memory_usage();
+        let instruction_limit = execution_parameters.instruction_limit;
         let output = execute(
             api_type,
             canister.system_state.clone(),
-            canister.memory_usage(),
+            memory_usage,
             execution_parameters.clone(),
             func_ref,
             canister.execution_state.take().unwrap(),
@@ -496,7 +638,7 @@ impl Hypervisor {
         let call_origin = call_origin.clone();
 
         canister.execution_state = Some(output.execution_state);
-        match output.wasm_result {
+        let (mut canister, num_instructions_left, heap_delta, result) = match output.wasm_result {
             result @ Ok(_) => {
                 // Executing the reply/reject closure succeeded.
                 canister.system_state = output.system_state;
@@ -575,7 +717,16 @@ impl Hypervisor {
                     }
                 }
             }
-        }
+        };
+        record_execution_metrics(
+            &mut canister,
+            time,
+            false,
+            instruction_limit - num_instructions_left,
+            result.is_err(),
+            memory_usage,
+        );
+        (canister, num_instructions_left, heap_delta, result)
     }
 
     /// Executes the system method `canister_start`.
@@ -663,6 +814,8 @@ impl Hypervisor {
                 canister_memory_limit: NumBytes::from(0),
                 subnet_available_memory: SubnetAvailableMemory::new(NumBytes::from(0)),
                 compute_allocation: ComputeAllocation::zero(),
+                canister_message_memory_capacity: NumBytes::from(0),
+                root_key: vec![],
             },
             FuncRef::Method(method),
             execution_state,
@@ -935,7 +1088,54 @@ impl Hypervisor {
         time: Time,
         execution_parameters: ExecutionParameters,
     ) -> (CanisterState, NumInstructions, HypervisorResult<NumBytes>) {
-        let method = WasmMethod::System(SystemMethod::CanisterHeartbeat);
+        self.execute_system_method_without_caller(
+            SystemMethod::CanisterHeartbeat,
+            canister,
+            routing_table,
+            subnet_records,
+            time,
+            execution_parameters,
+        )
+    }
+
+    /// Executes the system method of the given system task. The method runs
+    /// with the same System API as `canister_heartbeat`.
+    ///
+    /// Returns the same values as `execute_canister_heartbeat`.
+    #[allow(clippy::type_complexity)]
+    pub fn execute_canister_system_task(
+        &self,
+        canister: CanisterState,
+        task: SystemTask,
+        routing_table: Arc<RoutingTable>,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        time: Time,
+        execution_parameters: ExecutionParameters,
+    ) -> (CanisterState, NumInstructions, HypervisorResult<NumBytes>) {
+        self.execute_system_method_without_caller(
+            task.method(),
+            canister,
+            routing_table,
+            subnet_records,
+            time,
+            execution_parameters,
+        )
+    }
+
+    // Executes a system method that is triggered by the system rather than by
+    // a caller, so there is nobody to reply to. Such executions share the
+    // `Heartbeat` call origin.
+    #[allow(clippy::type_complexity)]
+    fn execute_system_method_without_caller(
+        &self,
+        system_method: SystemMethod,
+        canister: CanisterState,
+        routing_table: Arc<RoutingTable>,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        time: Time,
+        execution_parameters: ExecutionParameters,
+    ) -> (CanisterState, NumInstructions, HypervisorResult<NumBytes>) {
+        let method = WasmMethod::System(system_method);
         let memory_usage = canister.memory_usage();
         let (execution_state, mut system_state, scheduler_state) = canister.into_parts();
 
@@ -1000,12 +1200,40 @@ impl Hypervisor {
         }
     }
 
+    /// Compiles the given Wasm binary with the given instruction cost
+    /// overrides. The `trigger` labels the compilation metrics.
     pub fn compile(
         &self,
         wasm_binary: &BinaryEncodedWasm,
         persistence_type: PersistenceType,
+        instruction_cost_overrides: &InstructionCostOverrides,
+        trigger: CompilationTrigger,
     ) -> HypervisorResult<EmbedderCache> {
-        self.wasm_executor.compile(wasm_binary, persistence_type)
+        self.wasm_executor.compile(
+            wasm_binary,
+            persistence_type,
+            instruction_cost_overrides,
+            trigger,
+        )
+    }
+
+    /// Records a lookup of a compiled module in a compilation cache.
+    pub fn observe_compilation_cache_lookup(&self, trigger: CompilationTrigger, hit: bool) {
+        self.wasm_executor
+            .observe_compilation_cache_lookup(trigger, hit)
+    }
+
+    /// Returns the instruction cost overrides that canister code is currently
+    /// compiled with.
+    pub fn instruction_cost_overrides(&self) -> InstructionCostOverrides {
+        self.wasm_executor.instruction_cost_overrides()
+    }
+
+    /// Sets the instruction cost overrides that canister code is compiled
+    /// with. Canister code compiled with different overrides is recompiled
+    /// before its next execution.
+    pub fn set_instruction_cost_overrides(&self, overrides: InstructionCostOverrides) {
+        self.wasm_executor.set_instruction_cost_overrides(overrides)
     }
 
     pub fn new(
@@ -1021,12 +1249,19 @@ impl Hypervisor {
         embedder_config.persistence_type = config.persistence_type;
         embedder_config.num_runtime_generic_threads = num_runtime_threads;
         embedder_config.num_runtime_query_threads = std::cmp::min(num_runtime_threads, 4);
+        embedder_config.compiled_artifacts_dir = config.compiled_artifacts_dir.clone();
 
         let wasm_embedder = WasmtimeEmbedder::new(embedder_config.clone(), log.clone());
         let wasm_executor = WasmExecutor::new(
             wasm_embedder,
             embedder_config.max_globals,
             embedder_config.max_functions,
+            WasmAllowLists::new(
+                &config.wasm_import_allow_list,
+                config.wasm_export_allow_list.as_deref(),
+            ),
+            embedder_config.write_barriers,
+            embedder_config.compiled_artifacts_dir.clone(),
             metrics_registry,
             log.clone(),
         );
@@ -1038,6 +1273,10 @@ impl Hypervisor {
             own_subnet_type,
             log,
             cycles_account_manager,
+            legacy_data_certificate_in_replicated_queries: config
+                .legacy_data_certificate_in_replicated_queries,
+            max_instructions_per_http_transform: config.max_instructions_per_http_transform,
+            max_http_transform_response_size: config.max_http_transform_response_size,
         }
     }
 
@@ -1047,6 +1286,35 @@ impl Hypervisor {
     }
 }
 
+// Records an execution of an update method or callback in the recent
+// execution metrics of the canister, which are returned by the
+// `canister_metrics` method of the management canister.
+fn record_execution_metrics(
+    canister: &mut CanisterState,
+    time: Time,
+    is_update: bool,
+    instructions_executed: NumInstructions,
+    trapped: bool,
+    memory_usage_before: NumBytes,
+) {
+    let memory_grew = canister.memory_usage() > memory_usage_before;
+    let round = canister
+        .system_state
+        .canister_metrics
+        .recent_execution_metrics
+        .round_mut(time);
+    if is_update {
+        round.updates_executed += 1;
+    }
+    round.instructions_executed += instructions_executed;
+    if trapped {
+        round.traps += 1;
+    }
+    if memory_grew {
+        round.memory_grow_events += 1;
+    }
+}
+
 /// Executes a Wasm function.
 ///
 /// The function returns an updated execution state as well as an updated
@@ -2,11 +2,14 @@ mod canister_manager;
 mod canister_settings;
 mod execution_environment;
 mod execution_environment_metrics;
+#[cfg(test)]
+pub(crate) mod execution_test;
 mod history;
 mod hypervisor;
 mod ingress_message_filter;
 mod metrics;
 mod query_handler;
+mod round_state_dump;
 mod scheduler;
 mod types;
 mod util;
@@ -18,11 +21,12 @@ use ic_config::{execution_environment::Config, subnet_config::SchedulerConfig};
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_interfaces::{
     execution_environment::{
-        IngressHistoryReader, IngressHistoryWriter, IngressMessageFilter, QueryHandler, Scheduler,
+        IngressHistoryReader, IngressHistoryWriter, IngressMessageFilter, QueryHandler,
+        RoundStateDumpReader, Scheduler,
     },
     state_manager::StateReader,
 };
-use ic_logger::ReplicaLogger;
+use ic_logger::{warn, ReplicaLogger};
 use ic_metrics::MetricsRegistry;
 use ic_registry_routing_table::RoutingTable;
 use ic_registry_subnet_type::SubnetType;
@@ -31,6 +35,7 @@ use ic_system_api::NonReplicatedQueryKind;
 use ic_types::{messages::CallContextId, SubnetId};
 use ingress_message_filter::IngressMessageFilterImpl;
 use query_handler::HttpQueryHandlerImpl;
+use round_state_dump::LatestRoundStateDump;
 use scheduler::SchedulerImpl;
 use std::sync::Arc;
 
@@ -74,6 +79,7 @@ pub fn setup_execution(
     Arc<dyn QueryHandler<State = ReplicatedState>>,
     Box<dyn Scheduler<State = ReplicatedState>>,
     Box<dyn IngressHistoryReader>,
+    Option<Arc<dyn RoundStateDumpReader>>,
 ) {
     let hypervisor = Arc::new(Hypervisor::new(
         config.clone(),
@@ -101,6 +107,17 @@ pub fn setup_execution(
         config.clone(),
         Arc::clone(&cycles_account_manager),
     ));
+    let round_state_dump = if !config.round_state_dump_enabled {
+        None
+    } else if cfg!(feature = "round_state_dump") {
+        Some(Arc::new(LatestRoundStateDump::default()))
+    } else {
+        warn!(
+            logger,
+            "Ignoring round_state_dump_enabled: the replica was built without the round_state_dump feature."
+        );
+        None
+    };
     let http_query_handler = Arc::new(HttpQueryHandlerImpl::new(
         logger.clone(),
         hypervisor,
@@ -109,6 +126,8 @@ pub fn setup_execution(
         config,
         &metrics_registry,
         Arc::clone(&state_reader),
+        Arc::clone(&cycles_account_manager),
+        scheduler_config.max_instructions_per_message,
     ));
 
     let ingress_message_filter = Box::new(IngressMessageFilterImpl::new(Arc::clone(&exec_env)));
@@ -121,6 +140,7 @@ pub fn setup_execution(
         Arc::clone(&&cycles_account_manager),
         &metrics_registry,
         logger,
+        round_state_dump.clone(),
     ));
 
     (
@@ -129,5 +149,6 @@ pub fn setup_execution(
         http_query_handler,
         scheduler,
         ingress_history_reader,
+        round_state_dump.map(|dump| dump as Arc<_>),
     )
 }
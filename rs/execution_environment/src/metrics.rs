@@ -91,6 +91,27 @@ impl QueryHandlerMetrics {
             },
         }
     }
+
+    /// Returns the values recorded so far for each phase of query handling.
+    #[cfg(test)]
+    pub fn snapshot(&self) -> QueryHandlerMetricsSnapshot {
+        QueryHandlerMetricsSnapshot {
+            query: self.query.snapshot(),
+            query_initial_call: self.query_initial_call.snapshot(),
+            query_retry_call: self.query_retry_call.snapshot(),
+            query_spawned_calls: self.query_spawned_calls.snapshot(),
+        }
+    }
+}
+
+/// The values of `QueryHandlerMetrics` at some point in time.
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct QueryHandlerMetricsSnapshot {
+    pub query: ScopedMetricsSnapshot,
+    pub query_initial_call: ScopedMetricsSnapshot,
+    pub query_retry_call: ScopedMetricsSnapshot,
+    pub query_spawned_calls: ScopedMetricsSnapshot,
 }
 
 /// A common set of metrics for various phases of execution
@@ -107,6 +128,33 @@ pub(crate) struct ScopedMetrics {
     pub messages: Histogram,
 }
 
+impl ScopedMetrics {
+    /// Returns the number of times the phase was measured and the total
+    /// number of instructions and messages executed in it.
+    #[cfg(test)]
+    pub fn snapshot(&self) -> ScopedMetricsSnapshot {
+        // Instruction counts stay far below 2^53, so the sums of the
+        // histograms are exact.
+        ScopedMetricsSnapshot {
+            count: self.instructions.get_sample_count(),
+            instructions: NumInstructions::from(self.instructions.get_sample_sum() as u64),
+            messages: NumMessages::from(self.messages.get_sample_sum() as u64),
+        }
+    }
+}
+
+/// The values of `ScopedMetrics` at some point in time.
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ScopedMetricsSnapshot {
+    /// The number of times the phase was measured.
+    pub count: u64,
+    /// The total number of instructions executed in the phase.
+    pub instructions: NumInstructions,
+    /// The total number of messages executed in the phase.
+    pub messages: NumMessages,
+}
+
 /// A convenience helper for measuring `ScopedMetrics`
 ///
 /// It simplifies measuring of metrics for a hierarchy of phases and sub-phases
@@ -317,6 +365,30 @@ mod tests {
         assert_eq!(10, round_metrics.messages.get_sample_sum() as u64);
     }
 
+    #[test]
+    fn snapshot_reports_totals() {
+        let mr = MetricsRegistry::new();
+        let metrics = ScopedMetrics {
+            duration: duration_histogram("duration_seconds", "...", &mr),
+            instructions: instructions_histogram("instructions", "...", &mr),
+            messages: messages_histogram("messages", "...", &mr),
+        };
+
+        for _ in 0..3 {
+            let scope = MeasurementScope::root(&metrics);
+            scope.add(NumInstructions::from(1_000_000_007), NumMessages::from(2));
+        }
+
+        assert_eq!(
+            metrics.snapshot(),
+            ScopedMetricsSnapshot {
+                count: 3,
+                instructions: NumInstructions::from(3_000_000_021),
+                messages: NumMessages::from(6),
+            }
+        );
+    }
+
     #[test]
     fn multiple_nested_scopes() {
         let mr = MetricsRegistry::new();
@@ -7,30 +7,44 @@ mod query_context;
 #[cfg(test)]
 mod tests;
 
+#[cfg(test)]
+use crate::metrics::QueryHandlerMetricsSnapshot;
 use crate::{
+    canister_manager::get_canister_metrics,
     hypervisor::Hypervisor,
     metrics::{MeasurementScope, QueryHandlerMetrics},
+    QueryExecutionType,
 };
 use compilation_cache::CompilationCache;
 use ic_config::execution_environment::Config;
 use ic_crypto_tree_hash::{flatmap, Label, LabeledTree, LabeledTree::SubTree};
+use ic_cycles_account_manager::CyclesAccountManager;
+use ic_ic00_types::{CanisterIdRecord, Method as Ic00Method, Payload as Ic00Payload, IC_00};
 use ic_interfaces::{
-    execution_environment::{QueryHandler, SubnetAvailableMemory},
+    execution_environment::{
+        ExecutionCostEstimate, ExecutionParameters, HypervisorError, QueryExecutionError,
+        QueryHandler, SubnetAvailableMemory,
+    },
+    messages::RequestOrIngress,
     state_manager::StateReader,
 };
 use ic_logger::ReplicaLogger;
-use ic_metrics::MetricsRegistry;
+use ic_metrics::{executor_monitor::ExecutorMonitor, MetricsRegistry};
 use ic_registry_subnet_type::SubnetType;
-use ic_replicated_state::ReplicatedState;
+use ic_replicated_state::{CallContextAction, ReplicatedState};
 use ic_types::{
     ingress::WasmResult,
-    messages::{Blob, Certificate, CertificateDelegation, UserQuery},
+    messages::{Blob, Certificate, CertificateDelegation, Ingress, UserQuery},
     user_error::{ErrorCode, UserError},
-    CanisterId, SubnetId,
+    CanisterId, Height, NumInstructions, SubnetId, Time,
 };
 use query_allocations::QueryAllocationsUsed;
 use serde::Serialize;
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 
 const QUERY_EXECUTION_THREADS: usize = 1;
 
@@ -75,6 +89,61 @@ fn get_latest_certified_state_and_data_certificate(
         })
 }
 
+/// Returns the height of a committed state that contains `canister_id` if the
+/// latest certified state does not, i.e., if the canister was created after
+/// the certified height.
+fn height_of_uncertified_canister(
+    state_reader: &dyn StateReader<State = ReplicatedState>,
+    certified_state: &ReplicatedState,
+    canister_id: &CanisterId,
+) -> Option<Height> {
+    if certified_state.canister_state(canister_id).is_some() {
+        return None;
+    }
+    let latest_state = state_reader.get_latest_state();
+    latest_state
+        .get_ref()
+        .canister_state(canister_id)
+        .map(|_| latest_state.height())
+}
+
+/// Returns the earliest height at which a certified state is expected to be
+/// available, for queries that find no certified state.
+fn next_certified_height(state_reader: &dyn StateReader<State = ReplicatedState>) -> Height {
+    std::cmp::max(
+        state_reader.latest_certified_height().increment(),
+        state_reader.latest_state_height(),
+    )
+}
+
+/// Answers a query to the management canister from the state, without
+/// executing any canister.
+fn query_management_canister(
+    query: &UserQuery,
+    state: &ReplicatedState,
+) -> Result<WasmResult, UserError> {
+    match Ic00Method::from_str(&query.method_name) {
+        Ok(Ic00Method::CanisterMetrics) => {
+            let canister_id = CanisterIdRecord::decode(&query.method_payload)?.get_canister_id();
+            let canister = state.canister_state(&canister_id).ok_or_else(|| {
+                UserError::new(
+                    ErrorCode::CanisterNotFound,
+                    format!("Canister {} not found", canister_id),
+                )
+            })?;
+            let metrics = get_canister_metrics(query.source.get(), canister)?;
+            Ok(WasmResult::Reply(metrics.encode()))
+        }
+        _ => Err(UserError::new(
+            ErrorCode::CanisterMethodNotFound,
+            format!(
+                "Management canister has no query method '{}'",
+                query.method_name
+            ),
+        )),
+    }
+}
+
 fn label<T: Into<Label>>(t: T) -> Label {
     t.into()
 }
@@ -88,6 +157,8 @@ struct InternalHttpQueryHandlerImpl {
     compilation_cache: Arc<RwLock<CompilationCache>>,
     config: Config,
     metrics: QueryHandlerMetrics,
+    cycles_account_manager: Arc<CyclesAccountManager>,
+    max_instructions_per_message: NumInstructions,
 }
 
 /// Struct that is responsible for handling queries sent by user.
@@ -95,9 +166,11 @@ pub(crate) struct HttpQueryHandlerImpl {
     internal: Arc<InternalHttpQueryHandlerImpl>,
     state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
     threadpool: rayon::ThreadPool,
+    threadpool_monitor: ExecutorMonitor,
 }
 
 impl InternalHttpQueryHandlerImpl {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         log: ReplicaLogger,
         hypervisor: Arc<Hypervisor>,
@@ -105,6 +178,8 @@ impl InternalHttpQueryHandlerImpl {
         own_subnet_type: SubnetType,
         config: Config,
         metrics_registry: &MetricsRegistry,
+        cycles_account_manager: Arc<CyclesAccountManager>,
+        max_instructions_per_message: NumInstructions,
     ) -> Self {
         Self {
             log,
@@ -115,6 +190,8 @@ impl InternalHttpQueryHandlerImpl {
             compilation_cache: Arc::new(RwLock::new(CompilationCache::new())),
             config,
             metrics: QueryHandlerMetrics::new(metrics_registry),
+            cycles_account_manager,
+            max_instructions_per_message,
         }
     }
 
@@ -124,6 +201,16 @@ impl InternalHttpQueryHandlerImpl {
         state: Arc<ReplicatedState>,
         data_certificate: Vec<u8>,
     ) -> Result<WasmResult, UserError> {
+        let span = tracing::info_span!(
+            "query",
+            canister_id = %query.receiver,
+            method_name = %query.method_name,
+            source = %query.source,
+        );
+        let _enter = span.enter();
+        if query.receiver == IC_00 {
+            return query_management_canister(&query, &state);
+        }
         let measurement_scope = MeasurementScope::root(&self.metrics.query);
         // Note that This assumes that the QueryHandler is always called with the
         // "latest" state.  If and when we start supporting queries against older
@@ -152,12 +239,124 @@ impl InternalHttpQueryHandlerImpl {
             self.compilation_cache.clone(),
             subnet_available_memory,
             max_canister_memory_size,
+            self.config.canister_message_memory_capacity,
+            self.config.deterministic_raw_rand_in_queries,
         );
         context.run(query, &self.metrics, &measurement_scope)
     }
+
+    fn estimate_execution_cost(
+        &self,
+        query: UserQuery,
+        state: Arc<ReplicatedState>,
+    ) -> Result<ExecutionCostEstimate, UserError> {
+        let canister = state
+            .canister_state(&query.receiver)
+            .ok_or_else(|| {
+                UserError::new(
+                    ErrorCode::CanisterNotFound,
+                    format!("Canister {} not found", query.receiver),
+                )
+            })?
+            .clone();
+        let canister_id = canister.canister_id();
+
+        let ingress_induction_cycles = self.cycles_account_manager.ingress_induction_fee(
+            &query.method_name,
+            &query.method_payload,
+            query.nonce.as_ref(),
+        );
+
+        // The execution is charged for as if it was an ingress message,
+        // executed with the per-message instruction limit. As with queries,
+        // the canister may grow arbitrarily because nothing is persisted.
+        let time = state.time();
+        let instruction_limit = self.max_instructions_per_message;
+        let execution_parameters = ExecutionParameters {
+            instruction_limit,
+            canister_memory_limit: canister.memory_limit(self.config.max_canister_memory_size),
+            subnet_available_memory: SubnetAvailableMemory::new(self.config.subnet_memory_capacity),
+            compute_allocation: canister.scheduler_state.compute_allocation,
+            canister_message_memory_capacity: self.config.canister_message_memory_capacity,
+            root_key: state
+                .metadata
+                .network_topology
+                .nns_subnet_public_key()
+                .to_vec(),
+        };
+
+        let instructions_left = if canister.exports_query_method(query.method_name.clone()) {
+            let (_, instructions_left, result) = self.hypervisor.execute_query(
+                QueryExecutionType::Replicated,
+                query.method_name.as_str(),
+                query.method_payload.as_slice(),
+                *query.source.get_ref(),
+                canister,
+                None,
+                time,
+                execution_parameters,
+            );
+            // Failures that precede the execution of any code, e.g. a stopped
+            // canister, are not charged for and are reported instead.
+            match result {
+                Err(err) if instructions_left == instruction_limit => {
+                    return Err(err.into_user_error(&canister_id))
+                }
+                _ => instructions_left,
+            }
+        } else {
+            let ingress = Ingress {
+                message_id: query.id(),
+                source: query.source,
+                receiver: query.receiver,
+                method_name: query.method_name,
+                method_payload: query.method_payload,
+                expiry_time: Time::from_nanos_since_unix_epoch(query.ingress_expiry),
+            };
+            let routing_table = Arc::new(state.metadata.network_topology.routing_table.clone());
+            let subnet_records: Arc<BTreeMap<SubnetId, SubnetType>> = Arc::new(
+                state
+                    .metadata
+                    .network_topology
+                    .subnets
+                    .iter()
+                    .map(|(subnet_id, subnet_topology)| (*subnet_id, subnet_topology.subnet_type))
+                    .collect(),
+            );
+            let (_, instructions_left, action, _) = self.hypervisor.execute_update(
+                canister,
+                RequestOrIngress::Ingress(ingress),
+                time,
+                routing_table,
+                subnet_records,
+                execution_parameters,
+            );
+            if let CallContextAction::Fail { error, .. } = action {
+                match error {
+                    HypervisorError::CanisterStopped
+                    | HypervisorError::WasmModuleNotFound
+                    | HypervisorError::MethodNotFound(_) => {
+                        return Err(error.into_user_error(&canister_id))
+                    }
+                    _ => {}
+                }
+            }
+            instructions_left
+        };
+
+        let instructions_used = instruction_limit - instructions_left;
+        Ok(ExecutionCostEstimate {
+            instructions_used,
+            execution_cycles: self
+                .cycles_account_manager
+                .execution_cost(instructions_used),
+            ingress_induction_cycles,
+        })
+    }
 }
 
 impl HttpQueryHandlerImpl {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         log: ReplicaLogger,
         hypervisor: Arc<Hypervisor>,
@@ -166,6 +365,8 @@ impl HttpQueryHandlerImpl {
         config: Config,
         metrics_registry: &MetricsRegistry,
         state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
+        cycles_account_manager: Arc<CyclesAccountManager>,
+        max_instructions_per_message: NumInstructions,
     ) -> Self {
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(QUERY_EXECUTION_THREADS)
@@ -182,11 +383,20 @@ impl HttpQueryHandlerImpl {
                 own_subnet_type,
                 config,
                 metrics_registry,
+                cycles_account_manager,
+                max_instructions_per_message,
             )),
             state_reader,
             threadpool: pool,
+            threadpool_monitor: ExecutorMonitor::new(metrics_registry, "query_execution"),
         }
     }
+
+    /// Returns the metrics recorded for the queries handled so far.
+    #[cfg(test)]
+    pub(crate) fn metrics_snapshot(&self) -> QueryHandlerMetricsSnapshot {
+        self.internal.metrics.snapshot()
+    }
 }
 
 impl QueryHandler for HttpQueryHandlerImpl {
@@ -205,23 +415,48 @@ impl QueryHandler for HttpQueryHandlerImpl {
         &self,
         query: UserQuery,
         certificate_delegation: Option<CertificateDelegation>,
-        callback: Box<dyn FnOnce(Result<WasmResult, UserError>) + Send + 'static>,
+        callback: Box<dyn FnOnce(Result<WasmResult, QueryExecutionError>) + Send + 'static>,
     ) {
         let internal = Arc::clone(&self.internal);
         let state_reader = Arc::clone(&self.state_reader);
+        let queued = self.threadpool_monitor.enqueue();
         self.threadpool.spawn(move || {
+            queued.start();
             let v = match get_latest_certified_state_and_data_certificate(
-                state_reader,
+                Arc::clone(&state_reader),
                 certificate_delegation,
                 query.receiver,
             ) {
-                Some((state, cert)) => internal.query(query, state, cert),
-                None => Err(UserError::new(
-                    ErrorCode::CertifiedStateUnavailable,
-                    "Certified state is not available yet. Please try again...",
-                )),
+                Some((state, cert)) => match height_of_uncertified_canister(
+                    state_reader.as_ref(),
+                    &state,
+                    &query.receiver,
+                ) {
+                    Some(height) => Err(QueryExecutionError::RetryAtHeight {
+                        height,
+                        description: format!(
+                            "Canister {} does not exist in the certified state yet.",
+                            query.receiver
+                        ),
+                    }),
+                    None => internal
+                        .query(query, state, cert)
+                        .map_err(QueryExecutionError::from),
+                },
+                None => Err(QueryExecutionError::RetryAtHeight {
+                    height: next_certified_height(state_reader.as_ref()),
+                    description: "Certified state is not available yet.".to_string(),
+                }),
             };
             callback(v);
         });
     }
+
+    fn estimate_execution_cost(
+        &self,
+        query: UserQuery,
+        state: Arc<Self::State>,
+    ) -> Result<ExecutionCostEstimate, UserError> {
+        self.internal.estimate_execution_cost(query, state)
+    }
 }
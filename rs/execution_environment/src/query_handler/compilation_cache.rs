@@ -1,16 +1,20 @@
 use ic_config::embedders::PersistenceType;
 use ic_replicated_state::{EmbedderCache, ExecutionState};
 use ic_types::CanisterId;
+use ic_wasm_types::InstructionCostOverrides;
 use std::collections::HashMap;
 
 /// The key that uniquely identifies the compiled code.
 /// Note that instead of storing the whole wasm source code, we store only the
-/// SHA-256 hash assuming that there will be no collisions
+/// SHA-256 hash assuming that there will be no collisions.
+/// The instruction cost overrides are part of the key because they determine
+/// the instrumentation of the code.
 #[derive(Clone, Hash, Eq, PartialEq, Debug)]
 pub(crate) struct Key {
     canister_id: CanisterId,
     persistence_type: PersistenceType,
     wasm_source_hash: [u8; 32],
+    instruction_cost_overrides: InstructionCostOverrides,
 }
 
 /// Caches compiled code for queries calls.
@@ -28,16 +32,19 @@ impl CompilationCache {
         }
     }
 
-    // Gets the compiled code for the given execution state.
+    // Gets the compiled code for the given execution state and instruction
+    // cost overrides.
     pub(crate) fn get(
         &self,
         canister_id: CanisterId,
         state: &ExecutionState,
+        instruction_cost_overrides: &InstructionCostOverrides,
     ) -> Option<EmbedderCache> {
         let key = Key {
             canister_id,
             persistence_type: state.persistence_type(),
             wasm_source_hash: state.wasm_binary.hash_sha256(),
+            instruction_cost_overrides: instruction_cost_overrides.clone(),
         };
         self.cache.get(&key).cloned()
     }
@@ -49,12 +56,14 @@ impl CompilationCache {
         &mut self,
         canister_id: CanisterId,
         state: &ExecutionState,
+        instruction_cost_overrides: &InstructionCostOverrides,
         embedder_cache: EmbedderCache,
     ) -> EmbedderCache {
         let key = Key {
             canister_id,
             persistence_type: state.persistence_type(),
             wasm_source_hash: state.wasm_binary.hash_sha256(),
+            instruction_cost_overrides: instruction_cost_overrides.clone(),
         };
         self.cache
             .insert(key, embedder_cache.clone())
@@ -34,6 +34,14 @@
 //!
 //! - For a lack of a better strategy, always prioritise responses over
 //! requests.
+//!
+//! - Queries cannot call the management canister, as non-replicated execution
+//! cannot change the state of the subnet. If enabled in the config, calls to
+//! `raw_rand` are the exception: they are answered with bytes derived from the
+//! state time and the calling canister. These are the same for all replicas
+//! and all queries against the same state, so they are not secure randomness,
+//! but they spare canisters that share code between updates and queries from
+//! trapping.
 
 use super::{compilation_cache::CompilationCache, query_allocations::QueryAllocationsUsed};
 use crate::{
@@ -41,7 +49,11 @@ use crate::{
     metrics::{MeasurementScope, QueryHandlerMetrics},
     QueryExecutionType,
 };
+use candid::Encode;
 use ic_base_types::NumBytes;
+use ic_crypto_sha::Sha256;
+use ic_embedders::wasm_executor::CompilationTrigger;
+use ic_ic00_types::{EmptyBlob, Method as Ic00Method, IC_00};
 use ic_interfaces::execution_environment::{
     ExecutionParameters, HypervisorError, HypervisorResult, SubnetAvailableMemory,
 };
@@ -63,6 +75,7 @@ use ic_types::{
 };
 use std::{
     collections::BTreeMap,
+    str::FromStr,
     sync::{Arc, RwLock},
 };
 
@@ -115,6 +128,8 @@ pub(super) struct QueryContext<'a> {
     compilation_cache: Arc<RwLock<CompilationCache>>,
     subnet_available_memory: SubnetAvailableMemory,
     max_canister_memory_size: NumBytes,
+    canister_message_memory_capacity: NumBytes,
+    deterministic_raw_rand: bool,
 }
 
 impl<'a> QueryContext<'a> {
@@ -130,6 +145,8 @@ impl<'a> QueryContext<'a> {
         compilation_cache: Arc<RwLock<CompilationCache>>,
         subnet_available_memory: SubnetAvailableMemory,
         max_canister_memory_size: NumBytes,
+        canister_message_memory_capacity: NumBytes,
+        deterministic_raw_rand: bool,
     ) -> Self {
         let routing_table = Arc::new(state.metadata.network_topology.routing_table.clone());
         Self {
@@ -147,6 +164,8 @@ impl<'a> QueryContext<'a> {
             routing_table,
             subnet_available_memory,
             max_canister_memory_size,
+            canister_message_memory_capacity,
+            deterministic_raw_rand,
         }
     }
 
@@ -388,6 +407,34 @@ impl<'a> QueryContext<'a> {
         }
     }
 
+    // If the canister state was loaded from a checkpoint then its embedder cache is
+    // empty, which means that the embedder will compile Wasm code before executing
+    // it. Since all state changes are thrown away after the query execution, the
+    // next query will also observe an empty embedder cache and will recompiled
+    // Wasm code. To avoid such redundant recompilations we cache the compilation
+    // results and prefill the embedder cache before executing the query.
+    //
+    // Records in the current span whether compiled code was found, either in the
+    // embedder cache or in the compilation cache.
+    fn prefill_embedder_cache(&mut self, canister: &mut CanisterState) {
+        let canister_id = canister.system_state.canister_id;
+        match &mut canister.execution_state {
+            Some(execution_state) if execution_state.embedder_cache.is_none() => {
+                if let Some(embedder_cache) =
+                    self.lookup_embedder_cache_or_compile(canister_id, &execution_state)
+                {
+                    // It is okay to modify the `embedder_cache` field of the execution state
+                    // because all state changes will be anyway thrown away after execution.
+                    execution_state.embedder_cache = Some(embedder_cache);
+                }
+            }
+            execution_state => {
+                tracing::Span::current()
+                    .record("compilation_cache_hit", &execution_state.is_some());
+            }
+        }
+    }
+
     // Returns the compiled code for the given canister and state.
     // More specifically, it returns:
     // - Some(cache_code) if the code is already in the compilation cache.
@@ -395,16 +442,23 @@ impl<'a> QueryContext<'a> {
     // - None if compilation failes.
     // In the second case the compiled code is inserted into the compilation cache
     // to speed up future queries.
+    // Whether the compilation cache was hit is recorded in the current span.
     fn lookup_embedder_cache_or_compile(
         &mut self,
         canister_id: CanisterId,
         execution_state: &ExecutionState,
     ) -> Option<EmbedderCache> {
-        let maybe_embedder_cache = self
-            .compilation_cache
-            .read()
-            .unwrap()
-            .get(canister_id, execution_state);
+        let instruction_cost_overrides = self.hypervisor.instruction_cost_overrides();
+        let maybe_embedder_cache = self.compilation_cache.read().unwrap().get(
+            canister_id,
+            execution_state,
+            &instruction_cost_overrides,
+        );
+        tracing::Span::current().record("compilation_cache_hit", &maybe_embedder_cache.is_some());
+        self.hypervisor.observe_compilation_cache_lookup(
+            CompilationTrigger::QueryRecompile,
+            maybe_embedder_cache.is_some(),
+        );
         match maybe_embedder_cache {
             Some(embedder_cache) => {
                 // Cache hit: return the result from the compilation cache.
@@ -416,6 +470,8 @@ impl<'a> QueryContext<'a> {
                 match self.hypervisor.compile(
                     &execution_state.wasm_binary,
                     execution_state.persistence_type(),
+                    &instruction_cost_overrides,
+                    CompilationTrigger::QueryRecompile,
                 ) {
                     Ok(embedder_cache) => {
                         // Another thread may have already compiled the code and updated the
@@ -425,6 +481,7 @@ impl<'a> QueryContext<'a> {
                         let embedder_cache = self.compilation_cache.write().unwrap().insert(
                             canister_id,
                             execution_state,
+                            &instruction_cost_overrides,
                             embedder_cache,
                         );
                         Some(embedder_cache)
@@ -450,24 +507,17 @@ impl<'a> QueryContext<'a> {
         query_kind: NonReplicatedQueryKind,
         measurement_scope: &MeasurementScope,
     ) -> (CanisterState, HypervisorResult<Option<WasmResult>>) {
-        // If the canister state was loaded from a checkpoint then its embedder cache is
-        // empty, which means that the embedder will compile Wasm code before executing
-        // it. Since all state changes are thrown away after the query execution, the
-        // next query will also observe an empty embedder cache and will recompiled
-        // Wasm code. To avoid such redundant recompilations we cache the compilation
-        // results and prefill the embedder cache before executing the query.
-        if let Some(execution_state) = &mut canister.execution_state {
-            if execution_state.embedder_cache.is_none() {
-                if let Some(embedder_cache) = self.lookup_embedder_cache_or_compile(
-                    canister.system_state.canister_id,
-                    &execution_state,
-                ) {
-                    // It is okay to modify the `embedder_cache` field of the execution state
-                    // because all state changes will be anyway thrown away after execution.
-                    execution_state.embedder_cache = Some(embedder_cache);
-                }
-            }
-        }
+        // One span per node of the call graph. The fields that are only known
+        // during execution are recorded later.
+        let span = tracing::info_span!(
+            "execute_query",
+            canister_id = %canister.canister_id(),
+            method_name,
+            instructions = tracing::field::Empty,
+            compilation_cache_hit = tracing::field::Empty,
+        );
+        let _enter = span.enter();
+        self.prefill_embedder_cache(&mut canister);
 
         let call_context_id = self.new_call_context(&mut canister, call_origin);
         let instruction_limit = self
@@ -492,6 +542,7 @@ impl<'a> QueryContext<'a> {
             execution_parameters,
         );
         let instructions_executed = instruction_limit - instructions_left;
+        span.record("instructions", &instructions_executed.get());
         measurement_scope.add(instructions_executed, NumMessages::from(1));
         self.query_allocations_used
             .write()
@@ -515,6 +566,14 @@ impl<'a> QueryContext<'a> {
         HypervisorResult<Option<WasmResult>>,
     ) {
         let canister_id = canister.canister_id();
+        let span = tracing::info_span!(
+            "execute_callback",
+            canister_id = %canister_id,
+            instructions = tracing::field::Empty,
+            compilation_cache_hit = tracing::field::Empty,
+        );
+        let _enter = span.enter();
+        self.prefill_embedder_cache(&mut canister);
         // As we have executed a request on the canister earlier, it must
         // contain a call context manager hence the following should not fail.
         let call_context_manager = canister
@@ -566,6 +625,7 @@ impl<'a> QueryContext<'a> {
                 execution_parameters,
             );
         let instructions_executed = instruction_limit - instructions_left;
+        span.record("instructions", &instructions_executed.get());
         measurement_scope.add(instructions_executed, NumMessages::from(1));
         self.query_allocations_used
             .write()
@@ -603,6 +663,26 @@ impl<'a> QueryContext<'a> {
         }
     }
 
+    // Requests to `ic:00` are addressed to the own subnet by the time they are
+    // enqueued, see `ic0.call_simple`.
+    fn is_raw_rand_request(&self, request: &Request) -> bool {
+        (request.receiver == IC_00 || request.receiver.get() == self.own_subnet_id.get())
+            && matches!(
+                Ic00Method::from_str(&request.method_name),
+                Ok(Ic00Method::RawRand)
+            )
+    }
+
+    // Derives the bytes returned by `raw_rand` to `caller` from the time of
+    // the state. Anyone can compute them, see the module docs.
+    fn pseudo_random_bytes(&self, caller: &CanisterId) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.write(b"ic-query-raw-rand");
+        hasher.write(&self.state.time().as_nanos_since_unix_epoch().to_be_bytes());
+        hasher.write(caller.get_ref().as_slice());
+        hasher.finish().to_vec()
+    }
+
     // Executes a query sent from one canister to another. If a loop in the call
     // graph is detected, then an error is returned.
     fn handle_request(
@@ -614,6 +694,18 @@ impl<'a> QueryContext<'a> {
         // a request, there should not be any outstanding responses.
         assert!(self.outstanding_response.is_none());
 
+        if self.deterministic_raw_rand && self.is_raw_rand_request(&request) {
+            let payload = match EmptyBlob::decode(&request.method_payload) {
+                Ok(()) => {
+                    let bytes = self.pseudo_random_bytes(&request.sender);
+                    Payload::Data(Encode!(&bytes).unwrap())
+                }
+                Err(err) => Payload::Reject(RejectContext::from(UserError::from(err))),
+            };
+            self.outstanding_response = Some(generate_response(request, payload));
+            return None;
+        }
+
         let canister_id = request.receiver;
         // As we do not support loops in the call graph, the canister that we
         // want to execute a request on should not already be loaded.
@@ -935,6 +1027,13 @@ impl<'a> QueryContext<'a> {
             canister_memory_limit: canister.memory_limit(self.max_canister_memory_size),
             subnet_available_memory: self.subnet_available_memory.clone(),
             compute_allocation: canister.scheduler_state.compute_allocation,
+            canister_message_memory_capacity: self.canister_message_memory_capacity,
+            root_key: self
+                .state
+                .metadata
+                .network_topology
+                .nns_subnet_public_key()
+                .to_vec(),
         }
     }
 }
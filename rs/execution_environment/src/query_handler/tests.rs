@@ -1,421 +1,436 @@
-use crate::{
-    canister_manager::{CanisterManager, CanisterMgrConfig},
-    canister_settings::CanisterSettings,
-    hypervisor::Hypervisor,
-    HttpQueryHandlerImpl, IngressHistoryWriterImpl,
-};
-use ic_base_types::NumSeconds;
+use crate::execution_test::ExecutionTestBuilder;
+use candid::Decode;
 use ic_config::execution_environment::Config;
-use ic_interfaces::execution_environment::{
-    ExecutionParameters, QueryHandler, SubnetAvailableMemory,
+use ic_ic00_types::{
+    CanisterIdRecord, CanisterMetricsResult, CanisterRoundMetrics, EmptyBlob, Payload, IC_00,
 };
-use ic_metrics::MetricsRegistry;
-use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
-use ic_registry_subnet_type::SubnetType;
-use ic_replicated_state::ReplicatedState;
+use ic_interfaces::execution_environment::{QueryExecutionError, QueryHandler};
+use ic_replicated_state::SubnetTopology;
 use ic_test_utilities::{
-    cycles_account_manager::CyclesAccountManagerBuilder,
-    state_manager::FakeStateManager,
-    types::{
-        ids::{canister_test_id, subnet_test_id, user_test_id},
-        messages::InstallCodeContextBuilder,
-    },
+    metrics::{fetch_histogram_vec_count, fetch_int_counter_vec, labels},
+    types::ids::user_test_id,
     universal_canister::{call_args, wasm, UNIVERSAL_CANISTER_WASM},
-    with_test_replica_logger,
 };
-use ic_types::{ingress::WasmResult, messages::UserQuery, ComputeAllocation};
-use ic_types::{CanisterId, Cycles, NumBytes, NumInstructions, SubnetId};
-use maplit::btreemap;
-use std::{path::Path, sync::Arc};
-
-const CYCLE_BALANCE: Cycles = Cycles::new(100_000_000_000_000);
-const INSTRUCTION_LIMIT: NumInstructions = NumInstructions::new(1_000_000_000);
-const MEMORY_CAPACITY: NumBytes = NumBytes::new(1_000_000_000);
-
-fn with_setup<F>(f: F)
-where
-    F: FnOnce(HttpQueryHandlerImpl, CanisterManager, ReplicatedState),
-{
-    fn canister_manager_config(subnet_id: SubnetId) -> CanisterMgrConfig {
-        CanisterMgrConfig::new(
-            MEMORY_CAPACITY,
-            Some(CYCLE_BALANCE),
-            CYCLE_BALANCE,
-            NumSeconds::from(100_000),
-            1000,
-            1000,
-            subnet_id,
-            1000,
-            1,
+use ic_types::{
+    ingress::WasmResult, messages::UserQuery, user_error::ErrorCode, CanisterId, Height,
+    NumInstructions, NumMessages, UserId,
+};
+use std::sync::Arc;
+
+#[test]
+fn query_metrics_are_reported() {
+    // In this test we have two canisters A and B.
+    // Canister A handles the user query by calling canister B.
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_a = test.universal_canister();
+    let canister_b = test.universal_canister();
+    let output = test.query(
+        canister_a,
+        "query",
+        wasm()
+            .inter_query(
+                canister_b,
+                call_args().other_side(wasm().reply_data(&b"pong".to_vec())),
+            )
+            .build(),
+    );
+    assert_eq!(output, Ok(WasmResult::Reply(b"pong".to_vec())));
+    let metrics = test.query_handler().metrics_snapshot();
+    assert_eq!(1, metrics.query.count);
+    assert!(0 < metrics.query.instructions.get());
+    // We expect four messages:
+    // - canister_a.query() as pure
+    // - canister_a.query() as stateful
+    // - canister_b.query() as stateful
+    // - canister_a.on_reply()
+    assert_eq!(NumMessages::from(4), metrics.query.messages);
+    assert_eq!(1, metrics.query_initial_call.count);
+    assert!(0 < metrics.query_initial_call.instructions.get());
+    assert_eq!(NumMessages::from(1), metrics.query_initial_call.messages);
+    assert_eq!(1, metrics.query_retry_call.count);
+    assert_eq!(1, metrics.query_spawned_calls.count);
+    assert!(0 < metrics.query_spawned_calls.instructions.get());
+    assert_eq!(NumMessages::from(2), metrics.query_spawned_calls.messages);
+    assert_eq!(
+        metrics.query.instructions,
+        metrics.query_initial_call.instructions
+            + metrics.query_retry_call.instructions
+            + metrics.query_spawned_calls.instructions
+    );
+}
+
+#[test]
+fn query_instructions_are_deterministic() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_a = test.universal_canister();
+    let canister_b = test.universal_canister();
+    let payload = wasm()
+        .stable_grow(1)
+        .inter_query(
+            canister_b,
+            call_args().other_side(wasm().reply_data(&b"pong".to_vec())),
         )
-    }
+        .build();
 
-    fn initial_state(path: &Path, subnet_id: SubnetId) -> ReplicatedState {
-        let routing_table = RoutingTable::new(btreemap! {
-            CanisterIdRange{ start: CanisterId::from(0), end: CanisterId::from(0xff) } => subnet_id,
-        });
-        let mut state =
-            ReplicatedState::new_rooted_at(subnet_id, SubnetType::Application, path.to_path_buf());
-        state.metadata.network_topology.routing_table = routing_table;
-        state.metadata.network_topology.nns_subnet_id = subnet_id;
-        state
+    test.query(canister_a, "query", payload.clone()).unwrap();
+    let first = test.query_handler().metrics_snapshot();
+    test.query(canister_a, "query", payload).unwrap();
+    let second = test.query_handler().metrics_snapshot();
+
+    assert_eq!(2, second.query.count);
+    assert_eq!(
+        first.query.instructions,
+        second.query.instructions - first.query.instructions
+    );
+    assert_eq!(
+        first.query_initial_call.instructions,
+        second.query_initial_call.instructions - first.query_initial_call.instructions
+    );
+    assert_eq!(
+        first.query_spawned_calls.instructions,
+        second.query_spawned_calls.instructions - first.query_spawned_calls.instructions
+    );
+}
+
+#[test]
+fn query_without_certified_state_returns_retry_hint() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    let query = UserQuery {
+        source: test.user_id(),
+        receiver: canister_id,
+        method_name: "query".to_string(),
+        method_payload: wasm().reply().build(),
+        ingress_expiry: 0,
+        nonce: None,
+    };
+
+    // The state manager of the test has not certified any state.
+    let (tx, rx) = std::sync::mpsc::channel();
+    test.query_handler().query_latest_certified_state(
+        query,
+        None,
+        Box::new(move |result| tx.send(result).unwrap()),
+    );
+
+    match rx.recv().unwrap() {
+        Err(QueryExecutionError::RetryAtHeight { height, .. }) => {
+            assert_eq!(height, Height::from(1))
+        }
+        result => panic!("Expected a retry hint, got {:?}", result),
     }
+}
+
+#[test]
+fn query_call_with_side_effects() {
+    // In this test we have two canisters A and B.
+    // Canister A does a side-effectful operation (stable_grow) and then
+    // calls canister B. The side effect must happen once and only once.
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_a = test.universal_canister();
+    let canister_b = test.universal_canister();
+    let output = test.query(
+        canister_a,
+        "query",
+        wasm()
+            .stable_grow(10)
+            .inter_query(
+                canister_b,
+                call_args()
+                    .other_side(wasm().reply_data(&b"ignore".to_vec()))
+                    .on_reply(wasm().stable_size().reply_int()),
+            )
+            .build(),
+    );
+    assert_eq!(output, Ok(WasmResult::Reply(10_i32.to_le_bytes().to_vec())));
+}
+
+#[test]
+fn query_compilied_once() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    // The canister was compiled during installation.
+    assert_eq!(1, test.query_handler().internal.hypervisor.compile_count());
+    // Drop the embedder cache to force compilation during query handling.
+    test.canister_state_mut(canister_id)
+        .execution_state
+        .as_mut()
+        .unwrap()
+        .embedder_cache = None;
+
+    let result = test.query(canister_id, "query", wasm().reply().build());
+    assert!(result.is_ok());
+
+    // Now we expect the compilation counter to increase because the query
+    // had to compile.
+    assert_eq!(2, test.query_handler().internal.hypervisor.compile_count());
+
+    let result = test.query(canister_id, "query", wasm().reply().build());
+    assert!(result.is_ok());
 
-    with_test_replica_logger(|log| {
-        let subnet_id = subnet_test_id(1);
-        let subnet_type = SubnetType::Application;
-        let metrics_registry = MetricsRegistry::new();
-        let cycles_account_manager = Arc::new(CyclesAccountManagerBuilder::new().build());
-        let hypervisor = Hypervisor::new(
-            Config::default(),
-            1,
-            &metrics_registry,
-            subnet_id,
-            subnet_type,
-            log.clone(),
-            Arc::clone(&cycles_account_manager),
-        );
-        let hypervisor = Arc::new(hypervisor);
-        let ingress_history_writer = Arc::new(IngressHistoryWriterImpl::new(
-            log.clone(),
-            &metrics_registry,
-        ));
-        let canister_manager = CanisterManager::new(
-            Arc::clone(&hypervisor) as Arc<_>,
-            log.clone(),
-            canister_manager_config(subnet_id),
-            cycles_account_manager,
-            ingress_history_writer,
-        );
-        let tmpdir = tempfile::Builder::new().prefix("test").tempdir().unwrap();
-        let state = initial_state(tmpdir.path(), subnet_id);
-        let query_handler = HttpQueryHandlerImpl::new(
-            log,
-            hypervisor,
-            subnet_id,
-            subnet_type,
-            Config::default(),
-            &metrics_registry,
-            Arc::new(FakeStateManager::new()),
-        );
-        f(query_handler, canister_manager, state);
-    });
+    // The last query should have reused the compiled code.
+    assert_eq!(2, test.query_handler().internal.hypervisor.compile_count());
+
+    // The compilations are attributed to the installation and to the query.
+    let compilations = fetch_histogram_vec_count(test.metrics_registry(), "execution_wasm_compile");
+    assert_eq!(
+        Some(&1),
+        compilations.get(&labels(&[("trigger", "install")]))
+    );
+    assert_eq!(None, compilations.get(&labels(&[("trigger", "execution")])));
+    assert_eq!(
+        Some(&1),
+        compilations.get(&labels(&[("trigger", "query_recompile")]))
+    );
+    let lookups = fetch_int_counter_vec(
+        test.metrics_registry(),
+        "execution_wasm_compilation_cache_total",
+    );
+    assert_eq!(
+        Some(&1),
+        lookups.get(&labels(&[
+            ("trigger", "query_recompile"),
+            ("result", "miss")
+        ]))
+    );
+    assert_eq!(
+        Some(&1),
+        lookups.get(&labels(&[
+            ("trigger", "query_recompile"),
+            ("result", "hit")
+        ]))
+    );
 }
 
-fn universal_canister(
-    canister_manager: &CanisterManager,
-    state: &mut ReplicatedState,
-) -> CanisterId {
-    let sender = canister_test_id(1).get();
-    let sender_subnet_id = subnet_test_id(1);
-    let canister_id = canister_manager
-        .create_canister(
-            sender,
-            sender_subnet_id,
-            CYCLE_BALANCE,
-            CanisterSettings::default(),
-            state,
-        )
-        .0
+#[test]
+fn upgrade_compilation_is_attributed_to_the_upgrade() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    test.upgrade_canister(canister_id, UNIVERSAL_CANISTER_WASM.to_vec())
         .unwrap();
+    assert_eq!(2, test.query_handler().internal.hypervisor.compile_count());
 
-    canister_manager
-        .install_code(
-            InstallCodeContextBuilder::default()
-                .sender(sender)
-                .canister_id(canister_id)
-                .wasm_module(UNIVERSAL_CANISTER_WASM.to_vec())
-                .build(),
-            state,
-            ExecutionParameters {
-                instruction_limit: INSTRUCTION_LIMIT,
-                canister_memory_limit: MEMORY_CAPACITY,
-                subnet_available_memory: SubnetAvailableMemory::new(MEMORY_CAPACITY),
-                compute_allocation: ComputeAllocation::default(),
-            },
-        )
-        .1
+    let result = test.query(canister_id, "query", wasm().reply().build());
+    assert!(result.is_ok());
+
+    // The upgraded module was compiled once, when the upgrade executed it,
+    // and the query reused it.
+    assert_eq!(2, test.query_handler().internal.hypervisor.compile_count());
+    let compilations = fetch_histogram_vec_count(test.metrics_registry(), "execution_wasm_compile");
+    assert_eq!(
+        Some(&1),
+        compilations.get(&labels(&[("trigger", "install")]))
+    );
+    assert_eq!(
+        Some(&1),
+        compilations.get(&labels(&[("trigger", "upgrade")]))
+    );
+    assert_eq!(None, compilations.get(&labels(&[("trigger", "execution")])));
+    assert_eq!(
+        None,
+        compilations.get(&labels(&[("trigger", "query_recompile")]))
+    );
+}
+
+#[test]
+fn estimate_execution_cost_does_not_persist_changes() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    let balance_before = test.cycles_balance(canister_id);
+    let user_query = |method_name: &str| UserQuery {
+        source: test.user_id(),
+        receiver: canister_id,
+        method_name: method_name.to_string(),
+        method_payload: wasm().stable_grow(1).reply().build(),
+        ingress_expiry: 0,
+        nonce: None,
+    };
+
+    let estimate = test
+        .query_handler()
+        .estimate_execution_cost(user_query("update"), Arc::new(test.state().clone()))
         .unwrap();
-    canister_id
+    assert!(estimate.instructions_used > NumInstructions::from(0));
+    assert_eq!(
+        estimate.execution_cycles,
+        test.cycles_account_manager()
+            .execution_cost(estimate.instructions_used)
+    );
+    assert!(
+        estimate.ingress_induction_cycles
+            > test.cycles_account_manager().ingress_message_received_fee()
+    );
+
+    // Nothing was charged and the stable memory did not grow.
+    assert_eq!(balance_before, test.cycles_balance(canister_id));
+    assert_eq!(
+        0,
+        test.canister_state(canister_id)
+            .system_state
+            .stable_memory_size
+            .get()
+    );
+
+    let err = test
+        .query_handler()
+        .estimate_execution_cost(user_query("unknown"), Arc::new(test.state().clone()))
+        .unwrap_err();
+    assert_eq!(ErrorCode::CanisterMethodNotFound, err.code());
+}
+
+fn raw_rand_query() -> Vec<u8> {
+    wasm()
+        .call_simple(
+            IC_00,
+            "raw_rand",
+            call_args().other_side(EmptyBlob::encode()),
+        )
+        .build()
 }
 
 #[test]
-fn query_metrics_are_reported() {
-    with_setup(|query_handler, canister_manager, mut state| {
-        // In this test we have two canisters A and B.
-        // Canister A handles the user query by calling canister B.
-
-        let canister_a = universal_canister(&canister_manager, &mut state);
-        let canister_b = universal_canister(&canister_manager, &mut state);
-        let output = query_handler.query(
-            UserQuery {
-                source: user_test_id(2),
-                receiver: canister_a,
-                method_name: "query".to_string(),
-                method_payload: wasm()
-                    .inter_query(
-                        canister_b,
-                        call_args().other_side(wasm().reply_data(&b"pong".to_vec())),
-                    )
-                    .build(),
-                ingress_expiry: 0,
-                nonce: None,
-            },
-            Arc::new(state),
-            vec![],
-        );
-        assert_eq!(output, Ok(WasmResult::Reply(b"pong".to_vec())));
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query
-                .duration
-                .get_sample_count()
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query
-                .instructions
-                .get_sample_count()
-        );
-        assert!(
-            0 < query_handler
-                .internal
-                .metrics
-                .query
-                .instructions
-                .get_sample_sum() as u64
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query
-                .messages
-                .get_sample_count()
-        );
-        // We expect four messages:
-        // - canister_a.query() as pure
-        // - canister_a.query() as stateful
-        // - canister_b.query() as stateful
-        // - canister_a.on_reply()
-        assert_eq!(
-            4,
-            query_handler
-                .internal
-                .metrics
-                .query
-                .messages
-                .get_sample_sum() as u64
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_initial_call
-                .duration
-                .get_sample_count()
-        );
-        assert!(
-            0 < query_handler
-                .internal
-                .metrics
-                .query_initial_call
-                .instructions
-                .get_sample_sum() as u64
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_initial_call
-                .instructions
-                .get_sample_count()
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_initial_call
-                .messages
-                .get_sample_count()
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_initial_call
-                .messages
-                .get_sample_sum() as u64
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_retry_call
-                .duration
-                .get_sample_count()
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_spawned_calls
-                .duration
-                .get_sample_count()
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_spawned_calls
-                .instructions
-                .get_sample_count()
-        );
-        assert!(
-            0 < query_handler
-                .internal
-                .metrics
-                .query_spawned_calls
-                .instructions
-                .get_sample_sum() as u64
-        );
-        assert_eq!(
-            1,
-            query_handler
-                .internal
-                .metrics
-                .query_spawned_calls
-                .messages
-                .get_sample_count()
-        );
-        assert_eq!(
-            2,
-            query_handler
-                .internal
-                .metrics
-                .query_spawned_calls
-                .messages
-                .get_sample_sum() as u64
-        );
-        assert_eq!(
-            query_handler
-                .internal
-                .metrics
-                .query
-                .instructions
-                .get_sample_sum() as u64,
-            query_handler
-                .internal
-                .metrics
-                .query_initial_call
-                .instructions
-                .get_sample_sum() as u64
-                + query_handler
-                    .internal
-                    .metrics
-                    .query_retry_call
-                    .instructions
-                    .get_sample_sum() as u64
-                + query_handler
-                    .internal
-                    .metrics
-                    .query_spawned_calls
-                    .instructions
-                    .get_sample_sum() as u64
+fn raw_rand_in_queries_is_rejected_by_default() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    let output = test.query(canister_id, "query", raw_rand_query());
+    assert!(matches!(output, Ok(WasmResult::Reject(_))));
+}
+
+#[test]
+fn raw_rand_in_queries_is_deterministic_if_enabled() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_execution_config(Config {
+            deterministic_raw_rand_in_queries: true,
+            ..Config::default()
+        })
+        .build();
+    let canister_a = test.universal_canister();
+    let canister_b = test.universal_canister();
+
+    let random_bytes = |canister_id| match test.query(canister_id, "query", raw_rand_query()) {
+        Ok(WasmResult::Reply(reply)) => Decode!(&reply, Vec<u8>).unwrap(),
+        output => panic!("unexpected output: {:?}", output),
+    };
+    let bytes_a = random_bytes(canister_a);
+    assert_eq!(32, bytes_a.len());
+    assert_eq!(bytes_a, random_bytes(canister_a));
+    assert_ne!(bytes_a, random_bytes(canister_b));
+}
+
+#[test]
+fn query_sees_root_key_of_queried_state() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test
+        .canister_from_wat(
+            r#"
+            (module
+              (import "ic0" "root_key_size" (func $root_key_size (result i32)))
+              (import "ic0" "root_key_copy" (func $root_key_copy (param i32 i32 i32)))
+              (import "ic0" "msg_reply_data_append"
+                (func $msg_reply_data_append (param i32 i32)))
+              (import "ic0" "msg_reply" (func $msg_reply))
+              (func (export "canister_query root_key")
+                (call $root_key_copy (i32.const 0) (i32.const 0) (call $root_key_size))
+                (call $msg_reply_data_append (i32.const 0) (call $root_key_size))
+                (call $msg_reply))
+              (memory 1))
+            "#,
         )
-    });
+        .unwrap();
+    let network_topology = &mut test.state_mut().metadata.network_topology;
+    network_topology.subnets.insert(
+        network_topology.nns_subnet_id,
+        SubnetTopology {
+            public_key: vec![1, 2, 3],
+            ..SubnetTopology::default()
+        },
+    );
+
+    // No round has executed since the key was added to the state.
+    let output = test.query(canister_id, "root_key", vec![]);
+    assert_eq!(output, Ok(WasmResult::Reply(vec![1, 2, 3])));
+}
+
+fn canister_metrics_query(source: UserId, canister_id: CanisterId) -> UserQuery {
+    UserQuery {
+        source,
+        receiver: IC_00,
+        method_name: "canister_metrics".to_string(),
+        method_payload: CanisterIdRecord::from(canister_id).encode(),
+        ingress_expiry: 0,
+        nonce: None,
+    }
 }
 
 #[test]
-fn query_call_with_side_effects() {
-    with_setup(|query_handler, canister_manager, mut state| {
-        // In this test we have two canisters A and B.
-        // Canister A does a side-effectful operation (stable_grow) and then
-        // calls canister B. The side effect must happen once and only once.
-
-        let canister_a = universal_canister(&canister_manager, &mut state);
-        let canister_b = universal_canister(&canister_manager, &mut state);
-        let output = query_handler.query(
-            UserQuery {
-                source: user_test_id(2),
-                receiver: canister_a,
-                method_name: "query".to_string(),
-                method_payload: wasm()
-                    .stable_grow(10)
-                    .inter_query(
-                        canister_b,
-                        call_args()
-                            .other_side(wasm().reply_data(&b"ignore".to_vec()))
-                            .on_reply(wasm().stable_size().reply_int()),
-                    )
-                    .build(),
-                ingress_expiry: 0,
-                nonce: None,
-            },
-            Arc::new(state),
-            vec![],
-        );
-        assert_eq!(output, Ok(WasmResult::Reply(10_i32.to_le_bytes().to_vec())));
-    });
+fn canister_metrics_are_returned_by_management_canister_query() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    let time = test.state().time();
+    let round = test
+        .canister_state_mut(canister_id)
+        .system_state
+        .canister_metrics
+        .recent_execution_metrics
+        .round_mut(time);
+    round.updates_executed = 2;
+    round.instructions_executed = NumInstructions::from(500);
+
+    let output = test.query_handler().query(
+        canister_metrics_query(test.user_id(), canister_id),
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+
+    let reply = match output {
+        Ok(WasmResult::Reply(reply)) => reply,
+        output => panic!("unexpected output: {:?}", output),
+    };
+    assert_eq!(
+        CanisterMetricsResult::decode(&reply).unwrap(),
+        CanisterMetricsResult {
+            rounds: vec![CanisterRoundMetrics {
+                time_nanos: time.as_nanos_since_unix_epoch(),
+                updates_executed: 2,
+                instructions_executed: 500,
+                traps: 0,
+                memory_grow_events: 0,
+            }]
+        }
+    );
 }
 
 #[test]
-fn query_compilied_once() {
-    with_setup(|query_handler, canister_manager, mut state| {
-        let canister_id = universal_canister(&canister_manager, &mut state);
-        let canister = state.canister_state_mut(&canister_id).unwrap();
-        // The canister was compiled during installation.
-        assert_eq!(1, query_handler.internal.hypervisor.compile_count());
-        // Drop the embedder cache to force compilation during query handling.
-        canister.execution_state.as_mut().unwrap().embedder_cache = None;
-
-        let result = query_handler.query(
-            UserQuery {
-                source: user_test_id(2),
-                receiver: canister_id,
-                method_name: "query".to_string(),
-                method_payload: wasm().reply().build(),
-                ingress_expiry: 0,
-                nonce: None,
-            },
-            Arc::new(state.clone()),
-            vec![],
-        );
-        assert!(result.is_ok());
-
-        // Now we expect the compilation counter to increase because the query
-        // had to compile.
-        assert_eq!(2, query_handler.internal.hypervisor.compile_count());
-
-        let result = query_handler.query(
-            UserQuery {
-                source: user_test_id(2),
-                receiver: canister_id,
-                method_name: "query".to_string(),
-                method_payload: wasm().reply().build(),
-                ingress_expiry: 0,
-                nonce: None,
-            },
-            Arc::new(state),
+fn canister_metrics_are_only_returned_to_controllers() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    assert_ne!(user_test_id(2), test.user_id());
+
+    let err = test
+        .query_handler()
+        .query(
+            canister_metrics_query(user_test_id(2), canister_id),
+            Arc::new(test.state().clone()),
             vec![],
-        );
-        assert!(result.is_ok());
+        )
+        .unwrap_err();
+
+    assert_eq!(ErrorCode::CanisterInvalidController, err.code());
+}
+
+#[test]
+fn other_management_canister_methods_are_not_queries() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister();
+    let query = UserQuery {
+        method_name: "canister_status".to_string(),
+        ..canister_metrics_query(test.user_id(), canister_id)
+    };
+
+    let err = test
+        .query_handler()
+        .query(query, Arc::new(test.state().clone()), vec![])
+        .unwrap_err();
 
-        // The last query should have reused the compiled code.
-        assert_eq!(2, query_handler.internal.hypervisor.compile_count());
-    });
+    assert_eq!(ErrorCode::CanisterMethodNotFound, err.code());
 }
@@ -0,0 +1,182 @@
+//! A redacted summary of the latest execution round, for debugging live
+//! incidents.
+//!
+//! The summary is only recorded if the crate is built with the
+//! `round_state_dump` feature and `round_state_dump_enabled` is set in the
+//! execution environment config. It contains canister IDs, counts and sizes,
+//! but no message contents or callers.
+use ic_interfaces::execution_environment::RoundStateDumpReader;
+use ic_replicated_state::ReplicatedState;
+use ic_types::{CanisterId, ExecutionRound, NumBytes};
+use serde::Serialize;
+use std::sync::RwLock;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct RoundStateDump {
+    pub round: u64,
+    pub batch_time_nanos: u64,
+    pub memory: MemoryUsageSummary,
+    /// The canisters that still have messages or tasks after the round, in the
+    /// order in which the scheduler picked canisters in the round.
+    pub next_scheduled: Vec<String>,
+    pub canisters: Vec<CanisterSummary>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct MemoryUsageSummary {
+    pub subnet_available_memory_bytes: u64,
+    pub heap_delta_estimate_bytes: u64,
+    pub heap_delta_capacity_bytes: u64,
+    /// The memory used by the canister queues of all canisters.
+    pub message_memory_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct CanisterSummary {
+    pub canister_id: String,
+    pub ingress_queue_len: usize,
+    pub input_queue_len: usize,
+    pub output_queue_len: usize,
+    pub memory_usage_bytes: u64,
+    pub accumulated_priority: i64,
+}
+
+impl RoundStateDump {
+    /// Summarizes `state` after executing `round`, in which the canisters
+    /// were scheduled in the order `ordered_canister_ids`.
+    pub fn new(
+        state: &ReplicatedState,
+        round: ExecutionRound,
+        ordered_canister_ids: &[CanisterId],
+        subnet_available_memory: NumBytes,
+        heap_delta_capacity: NumBytes,
+    ) -> Self {
+        let canisters: Vec<_> = state
+            .canister_states
+            .values()
+            .map(|canister| {
+                let queues = &canister.system_state.queues;
+                CanisterSummary {
+                    canister_id: canister.canister_id().to_string(),
+                    ingress_queue_len: queues.ingress_queue_message_count(),
+                    input_queue_len: queues.input_queues_message_count(),
+                    output_queue_len: queues.output_queues_message_count(),
+                    memory_usage_bytes: canister.memory_usage().get(),
+                    accumulated_priority: canister.scheduler_state.accumulated_priority.value(),
+                }
+            })
+            .collect();
+        let next_scheduled = ordered_canister_ids
+            .iter()
+            .filter(|canister_id| {
+                state
+                    .canister_states
+                    .get(canister_id)
+                    .map_or(false, |canister| {
+                        canister.has_input() || canister.has_pending_system_tasks()
+                    })
+            })
+            .map(|canister_id| canister_id.to_string())
+            .collect();
+        let message_memory_bytes = state
+            .canister_states
+            .values()
+            .map(|canister| canister.system_state.queues.memory_usage() as u64)
+            .sum();
+        Self {
+            round: round.get(),
+            batch_time_nanos: state.time().as_nanos_since_unix_epoch(),
+            memory: MemoryUsageSummary {
+                subnet_available_memory_bytes: subnet_available_memory.get(),
+                heap_delta_estimate_bytes: state.metadata.heap_delta_estimate.get(),
+                heap_delta_capacity_bytes: heap_delta_capacity.get(),
+                message_memory_bytes,
+            },
+            next_scheduled,
+            canisters,
+        }
+    }
+}
+
+/// The summary of the latest round, written by the scheduler after every
+/// round and read by the HTTP handler.
+#[derive(Default)]
+pub(crate) struct LatestRoundStateDump(RwLock<Option<RoundStateDump>>);
+
+impl LatestRoundStateDump {
+    pub fn set(&self, dump: RoundStateDump) {
+        *self.0.write().unwrap() = Some(dump);
+    }
+}
+
+impl RoundStateDumpReader for LatestRoundStateDump {
+    fn latest_round_state_dump(&self) -> Option<String> {
+        self.0
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|dump| serde_json::to_string(dump).expect("failed to encode round state dump"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::{
+        state::{CanisterStateBuilder, ReplicatedStateBuilder},
+        types::ids::canister_test_id,
+        types::messages::IngressBuilder,
+    };
+
+    #[test]
+    fn dump_summarizes_queues_and_schedule() {
+        let busy = canister_test_id(1);
+        let idle = canister_test_id(2);
+        let state = ReplicatedStateBuilder::new()
+            .with_canister(
+                CanisterStateBuilder::new()
+                    .with_canister_id(busy)
+                    .with_ingress(IngressBuilder::default().receiver(busy).build())
+                    .build(),
+            )
+            .with_canister(CanisterStateBuilder::new().with_canister_id(idle).build())
+            .build();
+
+        let dump = RoundStateDump::new(
+            &state,
+            ExecutionRound::from(7),
+            &[idle, busy],
+            NumBytes::from(100),
+            NumBytes::from(200),
+        );
+
+        assert_eq!(dump.round, 7);
+        assert_eq!(dump.next_scheduled, vec![busy.to_string()]);
+        assert_eq!(dump.canisters.len(), 2);
+        assert_eq!(dump.canisters[0].canister_id, busy.to_string());
+        assert_eq!(dump.canisters[0].ingress_queue_len, 1);
+        assert_eq!(dump.canisters[1].ingress_queue_len, 0);
+        assert_eq!(dump.memory.subnet_available_memory_bytes, 100);
+        assert_eq!(dump.memory.heap_delta_capacity_bytes, 200);
+    }
+
+    #[test]
+    fn latest_dump_is_served_as_json() {
+        let latest = LatestRoundStateDump::default();
+        assert_eq!(latest.latest_round_state_dump(), None);
+
+        let state = ReplicatedStateBuilder::new().build();
+        latest.set(RoundStateDump::new(
+            &state,
+            ExecutionRound::from(3),
+            &[],
+            NumBytes::from(0),
+            NumBytes::from(0),
+        ));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&latest.latest_round_state_dump().unwrap()).unwrap();
+        assert_eq!(json["round"], 3);
+        assert!(json["canisters"].as_array().unwrap().is_empty());
+    }
+}
@@ -5,6 +5,7 @@ use crate::{
         duration_histogram, instructions_histogram, messages_histogram, MeasurementScope,
         ScopedMetrics,
     },
+    round_state_dump::{LatestRoundStateDump, RoundStateDump},
     util::process_responses,
 };
 use ic_config::subnet_config::SchedulerConfig;
@@ -15,7 +16,7 @@ use ic_interfaces::{
     execution_environment::{IngressHistoryWriter, Scheduler, SubnetAvailableMemory},
     messages::CanisterInputMessage,
 };
-use ic_logger::{debug, info, new_logger, warn, ReplicaLogger};
+use ic_logger::{debug, in_message_context, info, new_logger, warn, MessageContext, ReplicaLogger};
 use ic_metrics::{
     buckets::{decimal_buckets, linear_buckets},
     MetricsRegistry,
@@ -29,8 +30,9 @@ use ic_types::{
     ingress::{IngressStatus, WasmResult},
     messages::{Ingress, MessageId, Payload, Response, StopCanisterContext},
     user_error::{ErrorCode, UserError},
-    AccumulatedPriority, CanisterId, CanisterStatusType, ComputeAllocation, ExecutionRound,
-    InstallCodeContext, MemoryAllocation, NumBytes, NumInstructions, Randomness, SubnetId, Time,
+    AccumulatedPriority, CanisterId, CanisterStatusType, ComputeAllocation, ExecutionRound, Height,
+    InstallCodeContext, InstructionBudget, MemoryAllocation, NumBytes, NumInstructions, Randomness,
+    SubnetId, Time,
 };
 use ic_types::{nominal_cycles::NominalCycles, NumMessages};
 use num_rational::Ratio;
@@ -53,15 +55,20 @@ struct SchedulerMetrics {
     charge_resource_allocation_and_use_duration: Histogram,
     compute_utilization_per_core: Histogram,
     instructions_consumed_per_message: Histogram,
+    instructions_consumed_per_heartbeat: Histogram,
     instructions_consumed_per_round: Histogram,
+    heartbeat_instructions_consumed_per_round: Histogram,
     executable_canisters_per_round: Histogram,
     expired_ingress_messages_count: IntCounter,
     ingress_history_length: IntGauge,
     msg_execution_duration: Histogram,
     registered_canisters: IntGaugeVec,
     consumed_cycles_since_replica_started: Gauge,
+    consumed_cycles_by_heartbeats_since_replica_started: Gauge,
     input_queue_messages: IntGaugeVec,
     input_queues_size_bytes: IntGaugeVec,
+    queues_reserved_slots: IntGauge,
+    queues_memory_usage_bytes: IntGauge,
     canister_messages_where_cycles_were_charged: IntCounter,
     current_heap_delta: IntGauge,
     round_skipped_due_to_current_heap_delta_above_limit: IntCounter,
@@ -69,6 +76,11 @@ struct SchedulerMetrics {
     inner_loop_consumed_non_zero_instructions_count: IntCounter,
     inner_round_loop_consumed_max_instructions: IntCounter,
     num_canisters_uninstalled_out_of_cycles: IntCounter,
+    round_instructions_used: IntGauge,
+    round_instruction_limit: IntGauge,
+    round_instruction_utilization: Gauge,
+    round_messages_deferred_due_to_instruction_limit: IntGauge,
+    round_slice_tail_waste_instructions: IntGauge,
     round: ScopedMetrics,
     round_consensus_queue: ScopedMetrics,
     round_subnet_queue: ScopedMetrics,
@@ -113,12 +125,24 @@ impl SchedulerMetrics {
                 // 1, 2, 5, …, 1M, 2M, 5M
                 decimal_buckets(0, 6),
             ),
+            instructions_consumed_per_heartbeat: metrics_registry.histogram(
+                "scheduler_instructions_consumed_per_heartbeat",
+                "Wasm instructions consumed per heartbeat execution.",
+                // 1, 2, 5, …, 1M, 2M, 5M
+                decimal_buckets(0, 6),
+            ),
             instructions_consumed_per_round: metrics_registry.histogram(
                 "scheduler_instructions_consumed_per_round",
                 "Wasm instructions consumed per round.",
                 // 1, 2, 5, …, 1M, 2M, 5M
                 decimal_buckets(0, 6),
             ),
+            heartbeat_instructions_consumed_per_round: metrics_registry.histogram(
+                "scheduler_heartbeat_instructions_consumed_per_round",
+                "Wasm instructions consumed by heartbeats per round.",
+                // 1, 2, 5, …, 1M, 2M, 5M
+                decimal_buckets(0, 6),
+            ),
             executable_canisters_per_round: metrics_registry.histogram(
                 "scheduler_executable_canisters_per_round",
                 "Number of canisters that can be executed per round.",
@@ -157,6 +181,10 @@ impl SchedulerMetrics {
                 "replicated_state_consumed_cycles_since_replica_started",
                 "Number of cycles consumed since replica started",
             ),
+            consumed_cycles_by_heartbeats_since_replica_started: metrics_registry.gauge(
+                "replicated_state_consumed_cycles_by_heartbeats_since_replica_started",
+                "Number of cycles consumed by heartbeats since replica started",
+            ),
             input_queue_messages: metrics_registry.int_gauge_vec(
                 "execution_input_queue_messages",
                 "Count of messages currently enqueued in input queues, by message kind.",
@@ -167,6 +195,14 @@ impl SchedulerMetrics {
                 "Byte size of input queues, by message kind.",
                 &[LABEL_MESSAGE_KIND],
             ),
+            queues_reserved_slots: metrics_registry.int_gauge(
+                "execution_queues_reserved_slots",
+                "Count of response slots currently reserved in canister queues.",
+            ),
+            queues_memory_usage_bytes: metrics_registry.int_gauge(
+                "execution_queues_memory_usage_bytes",
+                "Message memory used by canister queues, including response reservations.",
+            ),
             canister_messages_where_cycles_were_charged: metrics_registry.int_counter(
                 "scheduler_canister_messages_where_cycles_were_charged",
                 "Total number of canister messages which resulted in cycles being charged.",
@@ -199,6 +235,26 @@ impl SchedulerMetrics {
                 "scheduler_num_canisters_uninstalled_out_of_cycles",
                 "The number of canisters that were uninstalled because they ran out of cycles."
             ),
+            round_instructions_used: metrics_registry.int_gauge(
+                "scheduler_round_instructions_used",
+                "Instructions used by canister execution in the last round, counted on the busiest thread of each iteration.",
+            ),
+            round_instruction_limit: metrics_registry.int_gauge(
+                "scheduler_round_instruction_limit",
+                "The per-round instruction limit that applied to the last round.",
+            ),
+            round_instruction_utilization: metrics_registry.gauge(
+                "scheduler_round_instruction_utilization",
+                "Ratio of the instructions used in the last round to the per-round instruction limit.",
+            ),
+            round_messages_deferred_due_to_instruction_limit: metrics_registry.int_gauge(
+                "scheduler_round_messages_deferred_due_to_instruction_limit",
+                "Number of canister input messages left for later rounds because the last round reached its instruction limit.",
+            ),
+            round_slice_tail_waste_instructions: metrics_registry.int_gauge(
+                "scheduler_round_slice_tail_waste_instructions",
+                "Instructions of the last round's limit that stayed unused because another message would not have fit.",
+            ),
             round: ScopedMetrics {
                 duration: duration_histogram(
                     "execution_round_duration_seconds",
@@ -358,6 +414,11 @@ impl SchedulerMetrics {
             .set(consumed_cycles.get() as f64);
     }
 
+    fn observe_consumed_cycles_by_heartbeats(&self, consumed_cycles: NominalCycles) {
+        self.consumed_cycles_by_heartbeats_since_replica_started
+            .set(consumed_cycles.get() as f64);
+    }
+
     fn observe_input_messages(&self, kind: &str, message_count: usize) {
         self.input_queue_messages
             .with_label_values(&[kind])
@@ -369,6 +430,84 @@ impl SchedulerMetrics {
             .with_label_values(&[kind])
             .set(message_bytes as i64);
     }
+
+    fn observe_queues_reservations(&self, reserved_slots: usize, memory_usage_bytes: usize) {
+        self.queues_reserved_slots.set(reserved_slots as i64);
+        self.queues_memory_usage_bytes
+            .set(memory_usage_bytes as i64);
+    }
+
+    fn observe_round_report(&self, report: &RoundReport) {
+        self.round_instructions_used
+            .set(report.instructions_used.get() as i64);
+        self.round_instruction_limit
+            .set(report.instruction_limit.get() as i64);
+        self.round_instruction_utilization
+            .set(report.instruction_utilization());
+        self.round_messages_deferred_due_to_instruction_limit
+            .set(report.messages_deferred as i64);
+        self.round_slice_tail_waste_instructions
+            .set(report.slice_tail_waste.get() as i64);
+    }
+}
+
+/// Summary of the canister execution in a round, from which the round
+/// utilization metrics are derived.
+#[derive(Debug, PartialEq)]
+struct RoundReport {
+    /// Instructions used by canister execution, where each iteration of the
+    /// inner round contributes the instructions of its busiest thread.
+    instructions_used: NumInstructions,
+    instruction_limit: NumInstructions,
+    /// Canister input messages that are left for later rounds because the
+    /// round reached its instruction limit.
+    messages_deferred: usize,
+    /// The part of the instruction limit that was left unused because it was
+    /// too small to execute another message.
+    slice_tail_waste: NumInstructions,
+}
+
+impl RoundReport {
+    fn new<'a>(
+        instructions_used: NumInstructions,
+        config: &SchedulerConfig,
+        canisters: impl Iterator<Item = &'a CanisterState>,
+    ) -> Self {
+        let instruction_limit = config.max_instructions_per_round;
+        let instructions_left = instruction_limit.saturating_sub(instructions_used);
+        // The round is cut short by the limit if another message could not
+        // have been executed within it.
+        let messages_deferred = if instructions_left < config.max_instructions_per_message {
+            canisters
+                .map(|canister| {
+                    let queues = &canister.system_state.queues;
+                    queues.ingress_queue_message_count() + queues.input_queues_message_count()
+                })
+                .sum()
+        } else {
+            0
+        };
+        // Unused instructions only count as waste if there was work to use
+        // them for.
+        let slice_tail_waste = if messages_deferred > 0 {
+            instructions_left
+        } else {
+            NumInstructions::from(0)
+        };
+        Self {
+            instructions_used,
+            instruction_limit,
+            messages_deferred,
+            slice_tail_waste,
+        }
+    }
+
+    fn instruction_utilization(&self) -> f64 {
+        if self.instruction_limit.get() == 0 {
+            return 0.0;
+        }
+        self.instructions_used.get() as f64 / self.instruction_limit.get() as f64
+    }
 }
 
 #[derive(Clone)]
@@ -389,6 +528,15 @@ impl CanisterExecutionLimits {
                 .max_message_duration_before_warn_in_seconds,
         }
     }
+
+    /// Returns the number of instructions the heartbeat of `canister` may
+    /// consume, which is its own limit capped at the limit per message.
+    fn instruction_limit_per_heartbeat(&self, canister: &CanisterState) -> NumInstructions {
+        match canister.scheduler_state.heartbeat_instruction_limit {
+            Some(limit) => std::cmp::min(limit, self.instruction_limit_per_message),
+            None => self.instruction_limit_per_message,
+        }
+    }
 }
 
 pub(crate) struct SchedulerImpl {
@@ -400,6 +548,7 @@ pub(crate) struct SchedulerImpl {
     metrics: Arc<SchedulerMetrics>,
     log: ReplicaLogger,
     thread_pool: RefCell<scoped_threadpool::Pool>,
+    round_state_dump: Option<Arc<LatestRoundStateDump>>,
 }
 
 // Indicates whether the heartbeat method of a canister should be run on not.
@@ -508,6 +657,18 @@ fn apply_scheduler_strategy(
         }
     }
 
+    // Canisters of a higher priority class go first regardless of their round
+    // priorities. This happens only after the accounting above, so that it
+    // does not change which canisters are charged for the round. The sort is
+    // stable, so canisters of the same class keep their order.
+    round_priorities.sort_by_key(|(canister_id, _priority)| {
+        std::cmp::Reverse(
+            all_canister_states[canister_id]
+                .scheduler_state
+                .priority_class,
+        )
+    });
+
     // Return the ordered canister ids.
     round_priorities
         .iter()
@@ -528,6 +689,7 @@ fn filter_idle_canisters(
         .filter(|canister_id| {
             let canister = all_canister_states.get(canister_id).unwrap();
             canister.has_input()
+                || canister.has_pending_system_tasks()
                 || (heartbeat_handling == HeartbeatHandling::Execute
                     && canister.exports_heartbeat_method())
         })
@@ -569,6 +731,7 @@ impl SchedulerImpl {
         cycles_account_manager: Arc<CyclesAccountManager>,
         metrics_registry: &MetricsRegistry,
         log: ReplicaLogger,
+        round_state_dump: Option<Arc<LatestRoundStateDump>>,
     ) -> Self {
         let scheduler_cores = config.scheduler_cores as u32;
         Self {
@@ -580,6 +743,7 @@ impl SchedulerImpl {
             cycles_account_manager,
             metrics: Arc::new(SchedulerMetrics::new(metrics_registry)),
             log,
+            round_state_dump,
         }
     }
 
@@ -610,7 +774,9 @@ impl SchedulerImpl {
                 MeasurementScope::nested(&self.metrics.round_inner_iteration, &measurement_scope);
             let mut loop_config = self.config.clone();
 
-            loop_config.max_instructions_per_round -= total_instructions_consumed;
+            loop_config.max_instructions_per_round = loop_config
+                .max_instructions_per_round
+                .saturating_sub(total_instructions_consumed);
 
             // We execute heartbeat methods only in the first iteration.
             let heartbeat_handling = if is_first_iteration {
@@ -661,6 +827,7 @@ impl SchedulerImpl {
                 self.exec_env.subnet_available_memory(&state) / self.config.scheduler_cores as u64,
                 Arc::new(state.metadata.network_topology.routing_table.clone()),
                 subnet_records,
+                state.metadata.network_topology.nns_subnet_public_key(),
                 heartbeat_handling,
                 &measurement_scope,
             );
@@ -735,6 +902,11 @@ impl SchedulerImpl {
         self.metrics
             .executable_canisters_per_round
             .observe(executable_canister_ids.len() as f64);
+        self.metrics.observe_round_report(&RoundReport::new(
+            total_instructions_consumed,
+            &self.config,
+            state.canisters_iter(),
+        ));
 
         state
     }
@@ -758,6 +930,7 @@ impl SchedulerImpl {
         subnet_available_memory: NumBytes,
         routing_table: Arc<RoutingTable>,
         subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+        root_key: &[u8],
         heartbeat_handling: HeartbeatHandling,
         measurement_scope: &MeasurementScope,
     ) -> (
@@ -806,19 +979,24 @@ impl SchedulerImpl {
                 let logger = new_logger!(self.log; messaging.round => round_id.get());
                 let canister_execution_limits = canister_execution_limits.clone();
                 scope.execute(move || {
-                    *result = execute_canisters_on_thread(
-                        canisters,
-                        exec_env,
-                        canister_execution_limits,
-                        metrics,
-                        round_id,
-                        time,
-                        SubnetAvailableMemory::new(subnet_available_memory),
-                        routing_table,
-                        subnet_records,
-                        heartbeat_handling,
-                        logger,
-                    );
+                    // The round is executing the batch of the same height.
+                    let context = MessageContext::new().with_height(Height::from(round_id.get()));
+                    *result = in_message_context(&context, || {
+                        execute_canisters_on_thread(
+                            canisters,
+                            exec_env,
+                            canister_execution_limits,
+                            metrics,
+                            round_id,
+                            time,
+                            SubnetAvailableMemory::new(subnet_available_memory),
+                            routing_table,
+                            subnet_records,
+                            root_key,
+                            heartbeat_handling,
+                            logger,
+                        )
+                    });
                 });
             }
         });
@@ -828,12 +1006,14 @@ impl SchedulerImpl {
         let mut canisters = Vec::new();
         let mut ingress_results = Vec::new();
         let mut total_instructions_executed = NumInstructions::from(0);
+        let mut total_heartbeat_instructions_executed = NumInstructions::from(0);
         let mut max_instructions_executed_per_thread = NumInstructions::from(0);
         let mut heap_delta = NumBytes::from(0);
         for mut result in results_by_thread.into_iter() {
             canisters.append(&mut result.canisters);
             ingress_results.append(&mut result.ingress_results);
             total_instructions_executed += result.instructions_executed;
+            total_heartbeat_instructions_executed += result.heartbeat_instructions_executed;
             max_instructions_executed_per_thread = std::cmp::max(
                 max_instructions_executed_per_thread,
                 result.instructions_executed,
@@ -846,6 +1026,11 @@ impl SchedulerImpl {
         self.metrics
             .instructions_consumed_per_round
             .observe(total_instructions_executed.get() as f64);
+        if heartbeat_handling == HeartbeatHandling::Execute {
+            self.metrics
+                .heartbeat_instructions_consumed_per_round
+                .observe(total_heartbeat_instructions_executed.get() as f64);
+        }
         (
             canisters,
             ingress_results,
@@ -1020,6 +1205,8 @@ impl Scheduler for SchedulerImpl {
             .current_heap_delta
             .set(state.metadata.heap_delta_estimate.get() as i64);
         self.metrics.execute_round_called.inc();
+        self.exec_env
+            .set_instruction_cost_overrides(&state.metadata.instruction_cost_overrides);
 
         debug!(
             round_log,
@@ -1100,9 +1287,8 @@ impl Scheduler for SchedulerImpl {
             // The value of the limit for subnet messages is chosen quite arbitrarily
             // as a quarter of the fixed limit. Any other value in the same ballpark would
             // work here.
-            let max_instructions_per_round_for_subnet_messages =
-                self.config.max_instructions_per_round / 4;
-            let mut total_instructions_consumed = NumInstructions::from(0);
+            let mut subnet_messages_budget =
+                InstructionBudget::new(self.config.max_instructions_per_round / 4);
 
             while let Some(msg) = state.subnet_queues.pop_input() {
                 let instructions_limit_per_message =
@@ -1118,16 +1304,17 @@ impl Scheduler for SchedulerImpl {
                 );
 
                 state = new_state;
-                let instructions_consumed = instructions_limit_per_message - instructions_left;
-                total_instructions_consumed += instructions_consumed;
+                let instructions_consumed =
+                    instructions_limit_per_message.saturating_sub(instructions_left);
+                subnet_messages_budget.consume(instructions_consumed);
                 measurement_scope.add(instructions_consumed, NumMessages::from(1));
                 // We check for the limit after the subnet message execution to ensure progress
                 // in the case when `instruction_limit_per_message` >
-                // `max_instructions_per_round_for_subnet_messages`.
+                // the budget for subnet messages.
                 // This means that we will exceed the limit by at most
                 // `instruction_limit_per_message` and that is okay since the limit was set as
                 // a heuristic anyway.
-                if total_instructions_consumed >= max_instructions_per_round_for_subnet_messages {
+                if subnet_messages_budget.is_exhausted() {
                     break;
                 }
             }
@@ -1169,6 +1356,15 @@ impl Scheduler for SchedulerImpl {
         state.prune_ingress_history();
         self.charge_canisters_for_resource_allocation_and_usage(&mut state, time_of_previous_batch);
         observe_replicated_state_metrics(&state, &self.metrics);
+        if let Some(round_state_dump) = &self.round_state_dump {
+            round_state_dump.set(RoundStateDump::new(
+                &state,
+                current_round,
+                &ordered_canister_ids,
+                self.exec_env.subnet_available_memory(&state),
+                self.config.subnet_heap_delta_capacity,
+            ));
+        }
         state
     }
 }
@@ -1194,17 +1390,41 @@ fn observe_instructions_consumed_per_message(
     );
 }
 
+// Same as `observe_instructions_consumed_per_message()` but records the
+// instructions of heartbeat executions separately, so that they do not get
+// mixed up with the instructions of messages.
+fn observe_instructions_consumed_per_heartbeat(
+    metrics: &SchedulerMetrics,
+    consumed_instructions: NumInstructions,
+    instruction_limit_per_heartbeat: NumInstructions,
+) {
+    if consumed_instructions.get() > 0 {
+        metrics.canister_messages_where_cycles_were_charged.inc();
+    }
+    metrics
+        .instructions_consumed_per_heartbeat
+        .observe(consumed_instructions.get() as f64);
+    assert!(
+        consumed_instructions <= instruction_limit_per_heartbeat,
+        "Heartbeat execution consumed too many instructions: limit={} consumed={}",
+        instruction_limit_per_heartbeat,
+        consumed_instructions
+    );
+}
+
 // This struct holds the result of a single execution thread.
 #[derive(Default)]
 struct ExecutionThreadResult {
     canisters: Vec<CanisterState>,
     ingress_results: Vec<(MessageId, IngressStatus)>,
     instructions_executed: NumInstructions,
+    heartbeat_instructions_executed: NumInstructions,
     messages_executed: NumMessages,
     heap_delta: NumBytes,
 }
 
 // Executes the given canisters one by one. For each canister it
+// - runs the pending system tasks of the canister,
 // - runs the heartbeat handler of the canister if needed,
 // - executes all messages of the canister.
 // The execution stops if `total_instruction_limit` is reached
@@ -1220,6 +1440,7 @@ fn execute_canisters_on_thread(
     subnet_available_memory: SubnetAvailableMemory,
     routing_table: Arc<RoutingTable>,
     subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+    root_key: &[u8],
     heartbeat_handling: HeartbeatHandling,
     logger: ReplicaLogger,
 ) -> ExecutionThreadResult {
@@ -1232,6 +1453,7 @@ fn execute_canisters_on_thread(
     let mut canisters = vec![];
     let mut ingress_results = vec![];
     let mut total_instructions_executed = NumInstructions::from(0);
+    let mut total_heartbeat_instructions_executed = NumInstructions::from(0);
     let mut total_messages_executed = NumMessages::from(0);
     let mut total_heap_delta = NumBytes::from(0);
 
@@ -1247,20 +1469,28 @@ fn execute_canisters_on_thread(
             continue;
         }
 
-        // Run heartbeat before processing the messages. Otherwise, if there are many
-        // messages, we may reach the instruction limit before running heartbeat.
-        if heartbeat_handling == HeartbeatHandling::Execute && canister.exports_heartbeat_method() {
+        // Run system tasks first, so that e.g. the `on_low_wasm_memory` hook
+        // gets a chance to free memory before more messages allocate it.
+        while canister.has_pending_system_tasks() {
+            if total_instructions_executed + canister_execution_limits.instruction_limit_per_message
+                > canister_execution_limits.total_instruction_limit
+            {
+                break;
+            }
             let measurement_scope = MeasurementScope::nested(
-                &metrics.round_inner_iteration_thread_heartbeat,
+                &metrics.round_inner_iteration_thread_message,
                 &measurement_scope,
             );
+            let task = canister.system_state.task_queue.pop_front().unwrap();
             let timer = metrics.msg_execution_duration.start_timer();
             let (new_canister, num_instructions_left, result) = exec_env
-                .execute_canister_heartbeat(
+                .execute_canister_system_task(
                     canister,
+                    task,
                     canister_execution_limits.instruction_limit_per_message,
                     Arc::clone(&routing_table),
                     Arc::clone(&subnet_records),
+                    root_key,
                     time,
                     subnet_available_memory.clone(),
                 );
@@ -1283,6 +1513,45 @@ fn execute_canisters_on_thread(
             drop(timer);
         }
 
+        // Run heartbeat before processing the messages. Otherwise, if there are many
+        // messages, we may reach the instruction limit before running heartbeat.
+        if heartbeat_handling == HeartbeatHandling::Execute && canister.exports_heartbeat_method() {
+            let measurement_scope = MeasurementScope::nested(
+                &metrics.round_inner_iteration_thread_heartbeat,
+                &measurement_scope,
+            );
+            let timer = metrics.msg_execution_duration.start_timer();
+            let instruction_limit_per_heartbeat =
+                canister_execution_limits.instruction_limit_per_heartbeat(&canister);
+            let (new_canister, num_instructions_left, result) = exec_env
+                .execute_canister_heartbeat(
+                    canister,
+                    instruction_limit_per_heartbeat,
+                    Arc::clone(&routing_table),
+                    Arc::clone(&subnet_records),
+                    root_key,
+                    time,
+                    subnet_available_memory.clone(),
+                );
+            let heap_delta = match result {
+                Ok(heap_delta) => heap_delta,
+                Err(_) => NumBytes::from(0),
+            };
+            let instructions_consumed = instruction_limit_per_heartbeat - num_instructions_left;
+            measurement_scope.add(instructions_consumed, NumMessages::from(1));
+            observe_instructions_consumed_per_heartbeat(
+                &metrics,
+                instructions_consumed,
+                instruction_limit_per_heartbeat,
+            );
+            canister = new_canister;
+            total_instructions_executed += instructions_consumed;
+            total_heartbeat_instructions_executed += instructions_consumed;
+            total_messages_executed.inc_assign();
+            total_heap_delta += heap_delta;
+            drop(timer);
+        }
+
         // Process all messages of the canister until
         // - either its input queue is empty.
         // - or the instruction limit is reached.
@@ -1310,6 +1579,7 @@ fn execute_canisters_on_thread(
                 time,
                 Arc::clone(&routing_table),
                 Arc::clone(&subnet_records),
+                root_key,
                 subnet_available_memory.clone(),
             );
             let instructions_consumed = canister_execution_limits.instruction_limit_per_message
@@ -1363,6 +1633,7 @@ fn execute_canisters_on_thread(
         canisters,
         ingress_results,
         instructions_executed: total_instructions_executed,
+        heartbeat_instructions_executed: total_heartbeat_instructions_executed,
         messages_executed: total_messages_executed,
         heap_delta: total_heap_delta,
     }
@@ -1375,11 +1646,14 @@ fn observe_replicated_state_metrics(state: &ReplicatedState, metrics: &Scheduler
     let mut num_stopped_canisters = 0;
 
     let mut consumed_cycles_total = NominalCycles::new(0);
+    let mut consumed_cycles_by_heartbeats_total = NominalCycles::new(0);
 
     let mut ingress_queue_message_count = 0;
     let mut ingress_queue_size_bytes = 0;
     let mut input_queues_message_count = 0;
     let mut input_queues_size_bytes = 0;
+    let mut queues_reserved_slots = 0;
+    let mut queues_memory_usage_bytes = 0;
 
     state.canisters_iter().for_each(|canister| {
         match canister.status() {
@@ -1391,14 +1665,21 @@ fn observe_replicated_state_metrics(state: &ReplicatedState, metrics: &Scheduler
             .system_state
             .canister_metrics
             .consumed_cycles_since_replica_started;
+        consumed_cycles_by_heartbeats_total += canister
+            .system_state
+            .canister_metrics
+            .consumed_cycles_by_heartbeats_since_replica_started;
         let queues = &canister.system_state.queues;
         ingress_queue_message_count += queues.ingress_queue_message_count();
         ingress_queue_size_bytes += queues.ingress_queue_size_bytes();
         input_queues_message_count += queues.input_queues_message_count();
         input_queues_size_bytes += queues.input_queues_size_bytes();
+        queues_reserved_slots += queues.reserved_slots();
+        queues_memory_usage_bytes += queues.memory_usage();
     });
 
     metrics.observe_consumed_cycles(consumed_cycles_total);
+    metrics.observe_consumed_cycles_by_heartbeats(consumed_cycles_by_heartbeats_total);
 
     let observe_reading = |status: CanisterStatusType, num: i64| {
         metrics
@@ -1414,6 +1695,7 @@ fn observe_replicated_state_metrics(state: &ReplicatedState, metrics: &Scheduler
     metrics.observe_input_queues_size_bytes(MESSAGE_KIND_INGRESS, ingress_queue_size_bytes);
     metrics.observe_input_messages(MESSAGE_KIND_CANISTER, input_queues_message_count);
     metrics.observe_input_queues_size_bytes(MESSAGE_KIND_CANISTER, input_queues_size_bytes);
+    metrics.observe_queues_reservations(queues_reserved_slots, queues_memory_usage_bytes);
 
     metrics
         .ingress_history_length
@@ -1446,10 +1728,18 @@ fn get_instructions_limit_for_subnet_message(
     use Ic00Method::*;
     match Ic00Method::from_str(&method_name) {
         Ok(method) => match method {
-            CanisterStatus
+            CanisterMetrics
+            | CanisterStatus
+            | CostCall
+            | CostCreateCanister
+            | CostExecution
             | CreateCanister
             | DeleteCanister
             | DepositCycles
+            | ExportCanister
+            | ExportCanisterChunk
+            | ImportCanister
+            | ImportCanisterChunk
             | RawRand
             | SetController
             | SetupInitialDKG
@@ -1459,6 +1749,7 @@ fn get_instructions_limit_for_subnet_message(
             | UninstallCode
             | UpdateSettings
             | ProvisionalCreateCanisterWithCycles
+            | ProvisionalCreateCanistersWithCycles
             | ProvisionalTopUpCanister => config.max_instructions_per_message,
             InstallCode => match InstallCodeArgs::decode(payload) {
                 Err(_) => config.max_instructions_per_message,
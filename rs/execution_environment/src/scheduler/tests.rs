@@ -1,6 +1,7 @@
 use super::*;
 #[cfg(test)]
 use crate::execution_environment::MockExecutionEnvironment;
+use crate::execution_test::ExecutionTestBuilder;
 use candid::Encode;
 use ic_base_types::NumSeconds;
 use ic_config::subnet_config::SchedulerConfig;
@@ -13,11 +14,15 @@ use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
 use ic_replicated_state::canister_state::QUEUE_INDEX_NONE;
 use ic_replicated_state::{
     canister_state::testing::CanisterStateTesting, CallOrigin, ExportedFunctions, NumWasmPages64,
+    SystemTask,
 };
 use ic_test_utilities::{
     cycles_account_manager::CyclesAccountManagerBuilder,
     history::MockIngressHistory,
-    metrics::{fetch_histogram_stats, fetch_int_counter, fetch_int_gauge_vec, metric_vec},
+    metrics::{
+        fetch_gauge, fetch_histogram_stats, fetch_int_counter, fetch_int_gauge,
+        fetch_int_gauge_vec, metric_vec,
+    },
     mock_time,
     state::{
         arb_replicated_state, get_initial_state, get_running_canister, get_stopped_canister,
@@ -36,7 +41,7 @@ use ic_types::{
     methods::WasmMethod,
     time::UNIX_EPOCH,
     user_error::{ErrorCode, UserError},
-    ComputeAllocation, Cycles, NumBytes,
+    ComputeAllocation, Cycles, NumBytes, PriorityClass,
 };
 use lazy_static::lazy_static;
 use maplit::btreemap;
@@ -356,10 +361,14 @@ fn test_multiple_interations_of_inner_loop() {
         .expect_subnet_available_memory()
         .times(..)
         .returning(move |_| NumBytes::from(10));
+    exec_env
+        .expect_set_instruction_cost_overrides()
+        .times(..)
+        .return_const(());
     exec_env
         .expect_execute_canister_message()
         .times(2)
-        .returning(move |mut canister, _, msg, _, _, _, _| {
+        .returning(move |mut canister, _, msg, _, _, _, _, _| {
             let canister0 = canister_test_id(0);
             let canister1 = canister_test_id(1);
             let canister_id = canister.canister_id();
@@ -1165,7 +1174,7 @@ fn execute_heartbeat_once_per_round_in_system_subnet() {
     exec_env
         .expect_execute_canister_heartbeat()
         .times(1)
-        .returning(move |canister, instruction_limit, _, _, _, _| {
+        .returning(move |canister, instruction_limit, _, _, _, _, _| {
             (
                 canister,
                 instruction_limit - NumInstructions::from(1),
@@ -1231,7 +1240,7 @@ fn execute_heartbeat_before_messages() {
     exec_env
         .expect_execute_canister_heartbeat()
         .times(1)
-        .returning(move |canister, instruction_limit, _, _, _, _| {
+        .returning(move |canister, instruction_limit, _, _, _, _, _| {
             (
                 canister,
                 instruction_limit - NumInstructions::from(1),
@@ -1272,6 +1281,72 @@ fn execute_heartbeat_before_messages() {
     );
 }
 
+#[test]
+fn execute_system_tasks_before_messages() {
+    // This test sets up a canister with a pending `on_low_wasm_memory` hook and
+    // three messages. The instruction limit per round allows only a single
+    // call. That call should be the hook.
+    let scheduler_test_fixture = SchedulerTestFixture {
+        scheduler_config: SchedulerConfig {
+            scheduler_cores: 1,
+            max_instructions_per_round: NumInstructions::from(1),
+            max_instructions_per_message: NumInstructions::from(1),
+            ..SchedulerConfig::application_subnet()
+        },
+        metrics_registry: MetricsRegistry::new(),
+        canister_num: 1,
+        message_num_per_canister: 3,
+    };
+    let mut exec_env = default_exec_env_mock(
+        &scheduler_test_fixture,
+        0,
+        NumInstructions::from(1),
+        NumBytes::new(0),
+    );
+    exec_env
+        .expect_execute_canister_system_task()
+        .times(1)
+        .returning(move |canister, task, instruction_limit, _, _, _, _, _| {
+            assert_eq!(task, SystemTask::OnLowWasmMemory);
+            (
+                canister,
+                instruction_limit - NumInstructions::from(1),
+                Ok(NumBytes::new(1)),
+            )
+        });
+    let exec_env = Arc::new(exec_env);
+
+    let ingress_history_writer = default_ingress_history_writer_mock(0);
+    let ingress_history_writer = Arc::new(ingress_history_writer);
+    scheduler_test(
+        &scheduler_test_fixture,
+        |scheduler| {
+            let mut state = get_initial_state(
+                scheduler_test_fixture.canister_num,
+                scheduler_test_fixture.message_num_per_canister,
+            );
+            for canister in state.canisters_iter_mut() {
+                canister
+                    .system_state
+                    .task_queue
+                    .set_on_low_wasm_memory_condition(true);
+            }
+            let state = scheduler.execute_round(
+                state,
+                Randomness::from([0; 32]),
+                UNIX_EPOCH,
+                ExecutionRound::from(1),
+                ProvisionalWhitelist::Set(BTreeSet::new()),
+            );
+            for canister in state.canisters_iter() {
+                assert!(!canister.has_pending_system_tasks());
+            }
+        },
+        ingress_history_writer,
+        exec_env,
+    );
+}
+
 #[test]
 fn execute_multiple_heartbeats() {
     // This tests multiple canisters with heartbeat methods running over mutiple
@@ -1299,7 +1374,7 @@ fn execute_multiple_heartbeats() {
     exec_env
         .expect_execute_canister_heartbeat()
         .times(number_of_canisters * number_of_rounds)
-        .returning(move |canister, instruction_limit, _, _, _, _| {
+        .returning(move |canister, instruction_limit, _, _, _, _, _| {
             (
                 canister,
                 instruction_limit - NumInstructions::from(1),
@@ -1360,11 +1435,15 @@ fn can_record_metrics_single_scheduler_thread() {
 
         let mut exec_env = MockExecutionEnvironment::new();
         let canister_id = canister_test_id(0);
+        exec_env
+            .expect_set_instruction_cost_overrides()
+            .times(..)
+            .return_const(());
 
         exec_env
             .expect_execute_canister_message()
             .times(1)
-            .returning(move |canister, _, _, _, _, _, _| ExecuteMessageResult {
+            .returning(move |canister, _, _, _, _, _, _, _| ExecuteMessageResult {
                 canister,
                 num_instructions_left: NumInstructions::from(0),
                 ingress_status: Some((
@@ -1383,7 +1462,7 @@ fn can_record_metrics_single_scheduler_thread() {
             exec_env
                 .expect_execute_canister_message()
                 .times(1)
-                .returning(move |canister, _, _, _, _, _, _| ExecuteMessageResult {
+                .returning(move |canister, _, _, _, _, _, _, _| ExecuteMessageResult {
                     canister,
                     num_instructions_left: NumInstructions::from(1),
                     ingress_status: Some((
@@ -1451,6 +1530,7 @@ fn can_record_metrics_single_scheduler_thread() {
             cycles_account_manager,
             &metrics_registry,
             log,
+            None,
         );
 
         let measurement_scope = MeasurementScope::root(&scheduler.metrics.round_inner_iteration);
@@ -1602,6 +1682,96 @@ fn can_record_metrics_for_a_round() {
     );
 }
 
+#[test]
+fn can_record_round_instruction_utilization() {
+    // Three canisters with five messages each, but the round limit only allows
+    // ten messages. The remaining instruction does not fit another message.
+    let num_instructions_consumed_per_msg = NumInstructions::from(5);
+    let scheduler_test_fixture = SchedulerTestFixture {
+        scheduler_config: SchedulerConfig {
+            scheduler_cores: 1,
+            max_instructions_per_round: NumInstructions::from(51),
+            max_instructions_per_message: num_instructions_consumed_per_msg,
+            ..SchedulerConfig::application_subnet()
+        },
+        metrics_registry: MetricsRegistry::new(),
+        canister_num: 3,
+        message_num_per_canister: 5,
+    };
+    let exec_env = default_exec_env_mock(
+        &scheduler_test_fixture,
+        10,
+        num_instructions_consumed_per_msg,
+        NumBytes::new(0),
+    );
+    let exec_env = Arc::new(exec_env);
+
+    let ingress_history_writer = default_ingress_history_writer_mock(10);
+    let ingress_history_writer = Arc::new(ingress_history_writer);
+    scheduler_test(
+        &scheduler_test_fixture,
+        |scheduler| {
+            let state = get_initial_state(
+                scheduler_test_fixture.canister_num,
+                scheduler_test_fixture.message_num_per_canister,
+            );
+            scheduler.execute_round(
+                state,
+                Randomness::from([0; 32]),
+                UNIX_EPOCH,
+                ExecutionRound::from(1),
+                ProvisionalWhitelist::Set(BTreeSet::new()),
+            );
+
+            let registry = &scheduler_test_fixture.metrics_registry;
+            assert_eq!(
+                fetch_int_gauge(registry, "scheduler_round_instructions_used"),
+                Some(50)
+            );
+            assert_eq!(
+                fetch_int_gauge(registry, "scheduler_round_instruction_limit"),
+                Some(51)
+            );
+            assert_eq!(
+                fetch_gauge(registry, "scheduler_round_instruction_utilization"),
+                Some(50.0 / 51.0)
+            );
+            assert_eq!(
+                fetch_int_gauge(
+                    registry,
+                    "scheduler_round_messages_deferred_due_to_instruction_limit"
+                ),
+                Some(5)
+            );
+            assert_eq!(
+                fetch_int_gauge(registry, "scheduler_round_slice_tail_waste_instructions"),
+                Some(1)
+            );
+        },
+        ingress_history_writer,
+        exec_env,
+    );
+}
+
+#[test]
+fn round_report_has_no_waste_without_deferred_messages() {
+    let config = SchedulerConfig {
+        max_instructions_per_round: NumInstructions::from(51),
+        max_instructions_per_message: NumInstructions::from(5),
+        ..SchedulerConfig::application_subnet()
+    };
+    let report = RoundReport::new(NumInstructions::from(50), &config, std::iter::empty());
+    assert_eq!(
+        report,
+        RoundReport {
+            instructions_used: NumInstructions::from(50),
+            instruction_limit: NumInstructions::from(51),
+            messages_deferred: 0,
+            slice_tail_waste: NumInstructions::from(0),
+        }
+    );
+}
+
 #[test]
 fn requested_method_does_not_exist() {
     let num_instructions_consumed_per_msg = NumInstructions::from(5);
@@ -1709,6 +1879,10 @@ fn stopping_canisters_are_stopped_when_they_are_ready() {
         .expect_subnet_available_memory()
         .times(..)
         .returning(move |_| NumBytes::from(10));
+    exec_env
+        .expect_set_instruction_cost_overrides()
+        .times(..)
+        .return_const(());
     let exec_env = Arc::new(exec_env);
 
     // Expect ingress history writer to be called twice to respond to
@@ -1780,6 +1954,10 @@ fn stopping_canisters_are_not_stopped_if_not_ready() {
         .expect_subnet_available_memory()
         .times(..)
         .returning(move |_| NumBytes::from(10));
+    exec_env
+        .expect_set_instruction_cost_overrides()
+        .times(..)
+        .return_const(());
 
     // Expect ingress history writer to never be called since the canister
     // isn't ready to be stopped.
@@ -2174,7 +2352,7 @@ fn heartbeat_metrics_are_recorded() {
     exec_env
         .expect_execute_canister_heartbeat()
         .times(1)
-        .returning(move |canister, _, _, _, _, _| {
+        .returning(move |canister, _, _, _, _, _, _| {
             (canister, NumInstructions::from(0), Ok(NumBytes::new(1)))
         });
     let exec_env = Arc::new(exec_env);
@@ -2243,6 +2421,98 @@ fn heartbeat_metrics_are_recorded() {
     );
 }
 
+#[test]
+fn heartbeat_is_limited_and_accounted_separately_from_messages() {
+    // This test sets up a canister on a system subnet with a heartbeat method
+    // and a heartbeat instruction limit that is lower than the message limit.
+    let scheduler_test_fixture = SchedulerTestFixture {
+        scheduler_config: SchedulerConfig {
+            scheduler_cores: 1,
+            max_instructions_per_round: NumInstructions::from(1000),
+            max_instructions_per_message: NumInstructions::from(100),
+            ..SchedulerConfig::system_subnet()
+        },
+        metrics_registry: MetricsRegistry::new(),
+        canister_num: 1,
+        message_num_per_canister: 2,
+    };
+    let mut exec_env = default_exec_env_mock(
+        &scheduler_test_fixture,
+        2,
+        NumInstructions::from(1),
+        NumBytes::new(0),
+    );
+    exec_env
+        .expect_execute_canister_heartbeat()
+        .times(1)
+        .returning(move |canister, instruction_limit, _, _, _, _, _| {
+            assert_eq!(instruction_limit, NumInstructions::from(10));
+            (canister, NumInstructions::from(3), Ok(NumBytes::new(1)))
+        });
+    let exec_env = Arc::new(exec_env);
+
+    let ingress_history_writer = default_ingress_history_writer_mock(2);
+    let ingress_history_writer = Arc::new(ingress_history_writer);
+    scheduler_test(
+        &scheduler_test_fixture,
+        |scheduler| {
+            let mut state = get_initial_state(
+                scheduler_test_fixture.canister_num,
+                scheduler_test_fixture.message_num_per_canister,
+            );
+            for canister in state.canisters_iter_mut() {
+                canister.scheduler_state.heartbeat_instruction_limit =
+                    Some(NumInstructions::from(10));
+                if let Some(ref mut execution_state) = canister.execution_state {
+                    execution_state.exports = ExportedFunctions::new(
+                        [WasmMethod::System(SystemMethod::CanisterHeartbeat)]
+                            .iter()
+                            .cloned()
+                            .collect(),
+                    );
+                }
+            }
+            scheduler.execute_round(
+                state,
+                Randomness::from([0; 32]),
+                UNIX_EPOCH,
+                ExecutionRound::from(1),
+                ProvisionalWhitelist::Set(BTreeSet::new()),
+            );
+            assert_eq!(
+                1,
+                scheduler
+                    .metrics
+                    .instructions_consumed_per_heartbeat
+                    .get_sample_count(),
+            );
+            assert_eq!(
+                7,
+                scheduler
+                    .metrics
+                    .instructions_consumed_per_heartbeat
+                    .get_sample_sum() as u64,
+            );
+            assert_eq!(
+                2,
+                scheduler
+                    .metrics
+                    .instructions_consumed_per_message
+                    .get_sample_count(),
+            );
+            assert_eq!(
+                7,
+                scheduler
+                    .metrics
+                    .heartbeat_instructions_consumed_per_round
+                    .get_sample_sum() as u64,
+            );
+        },
+        ingress_history_writer,
+        exec_env,
+    );
+}
+
 #[test]
 fn execution_round_does_not_too_early() {
     // In this test we have 2 canisters with 10 input messages that execute 10
@@ -2386,6 +2656,104 @@ fn replicated_state_metrics_different_canister_statuses() {
     );
 }
 
+#[test]
+fn elevated_canisters_are_ordered_first() {
+    let scheduler_cores = 2;
+    let mut canister_states = BTreeMap::new();
+    for i in 0..10 {
+        let mut canister = get_running_canister(canister_test_id(i));
+        // Canisters with a small id would be ordered first otherwise.
+        canister.scheduler_state.compute_allocation = ComputeAllocation::try_from(10 - i).unwrap();
+        if i >= 7 {
+            canister.scheduler_state.priority_class = PriorityClass::Elevated;
+        }
+        canister_states.insert(canister_test_id(i), canister);
+    }
+
+    for round in 1..20 {
+        let ordered_canister_ids = apply_scheduler_strategy(
+            scheduler_cores,
+            ExecutionRound::new(round),
+            &mut canister_states,
+        );
+
+        let mut elevated_canister_ids = ordered_canister_ids[..3].to_vec();
+        elevated_canister_ids.sort();
+        assert_eq!(
+            elevated_canister_ids,
+            vec![
+                canister_test_id(7),
+                canister_test_id(8),
+                canister_test_id(9)
+            ]
+        );
+    }
+}
+
+#[test]
+fn elevated_canisters_do_not_change_priority_accounting() {
+    let scheduler_cores = 2;
+    let number_of_canisters = 10;
+    let mut baseline_canister_states = BTreeMap::new();
+    for i in 0..number_of_canisters {
+        baseline_canister_states.insert(
+            canister_test_id(i),
+            get_running_canister(canister_test_id(i)),
+        );
+    }
+    let mut canister_states = baseline_canister_states.clone();
+    for i in 7..number_of_canisters {
+        canister_states
+            .get_mut(&canister_test_id(i))
+            .unwrap()
+            .scheduler_state
+            .priority_class = PriorityClass::Elevated;
+    }
+
+    let accumulated_priorities = |canister_states: &BTreeMap<CanisterId, CanisterState>| {
+        canister_states
+            .iter()
+            .map(|(canister_id, canister)| {
+                (
+                    *canister_id,
+                    canister.scheduler_state.accumulated_priority.value(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    for round in 1..50 {
+        apply_scheduler_strategy(
+            scheduler_cores,
+            ExecutionRound::new(round),
+            &mut baseline_canister_states,
+        );
+        let ordered_canister_ids = apply_scheduler_strategy(
+            scheduler_cores,
+            ExecutionRound::new(round),
+            &mut canister_states,
+        );
+
+        // The elevated canisters go first, ...
+        assert!(ordered_canister_ids[..3].iter().all(|canister_id| {
+            canister_states[canister_id].scheduler_state.priority_class == PriorityClass::Elevated
+        }));
+        // ... but the canisters are charged as if none of them was elevated,
+        let priorities = accumulated_priorities(&canister_states);
+        assert_eq!(
+            priorities,
+            accumulated_priorities(&baseline_canister_states)
+        );
+        // ... so that the accumulated priorities still sum up to zero.
+        assert_eq!(
+            priorities
+                .iter()
+                .map(|(_canister_id, priority)| priority)
+                .sum::<i64>(),
+            0
+        );
+    }
+}
+
 proptest! {
     // In the following tests we use a notion of `minimum_executed_messages` per
     // execution round. The minimum is defined as `min(available_messages,
@@ -2700,10 +3068,14 @@ fn default_exec_env_mock(
         .expect_subnet_available_memory()
         .times(..)
         .returning(move |_| NumBytes::from(10));
+    exec_env
+        .expect_set_instruction_cost_overrides()
+        .times(..)
+        .return_const(());
     exec_env
         .expect_execute_canister_message()
         .times(calls)
-        .returning(move |canister, _, msg, _, _, _, _| {
+        .returning(move |canister, _, msg, _, _, _, _, _| {
             if let CanisterInputMessage::Ingress(msg) = msg {
                 ExecuteMessageResult {
                     canister: canister.clone(),
@@ -2756,6 +3128,7 @@ fn scheduler_test(
             cycles_account_manager,
             &test_fixture.metrics_registry,
             log,
+            None,
         );
         run_test(scheduler);
     });
@@ -2783,3 +3156,38 @@ prop_compose! {
         (NumInstructions::from(num_instructions_consumed_per_msg), NumInstructions::from(max_instructions_per_round))
     }
 }
+
+#[test]
+fn heartbeat_cycles_are_accounted_separately_from_messages() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test
+        .canister_from_wat(
+            r#"
+            (module
+              (import "ic0" "msg_reply" (func $msg_reply))
+              (func (export "canister_heartbeat"))
+              (func (export "canister_update update") (call $msg_reply))
+              (memory 1))
+            "#,
+        )
+        .unwrap();
+
+    test.execute_round();
+    let heartbeat_cycles = test
+        .canister_state(canister_id)
+        .system_state
+        .canister_metrics
+        .consumed_cycles_by_heartbeats_since_replica_started;
+    assert!(heartbeat_cycles > NominalCycles::from(0));
+
+    let result = test.ingress(canister_id, "update", vec![]);
+    assert_eq!(result, Ok(WasmResult::Reply(vec![])));
+    let canister_metrics = &test
+        .canister_state(canister_id)
+        .system_state
+        .canister_metrics;
+    assert!(
+        canister_metrics.consumed_cycles_since_replica_started
+            > canister_metrics.consumed_cycles_by_heartbeats_since_replica_started
+    );
+}
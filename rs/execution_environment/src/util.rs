@@ -5,6 +5,7 @@ use ic_types::CanisterId;
 use std::sync::Arc;
 
 pub(crate) const GOVERNANCE_CANISTER_ID: CanisterId = CanisterId::from_u64(1);
+pub(crate) const ROOT_CANISTER_ID: CanisterId = CanisterId::from_u64(3);
 
 /// Sends responses to their callers.
 ///
@@ -5,7 +5,7 @@ use ic_execution_environment::{
 };
 use ic_interfaces::execution_environment::{
     ExecutionParameters, HypervisorError, HypervisorError::ContractViolation, HypervisorResult,
-    SubnetAvailableMemory, TrapCode,
+    MessageAcceptanceError, SubnetAvailableMemory, TrapCode,
 };
 use ic_interfaces::messages::RequestOrIngress;
 use ic_logger::replica_logger::no_op_logger;
@@ -15,16 +15,16 @@ use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::page_map::MemoryRegion;
 use ic_replicated_state::{
     canister_state::testing::CanisterQueuesTesting, CallContextAction, CallOrigin, CanisterState,
-    ExecutionState, Global, NumWasmPages, NumWasmPages64, SystemState,
+    ExecutionState, Global, NumWasmPages, NumWasmPages64, SystemState, SystemTask,
 };
 use ic_replicated_state::{PageIndex, PageMap};
 use ic_sys::PAGE_SIZE;
-use ic_system_api::ApiType;
+use ic_system_api::{ApiType, NonReplicatedQueryKind};
 use ic_test_utilities::types::messages::{IngressBuilder, RequestBuilder};
 use ic_test_utilities::{
     assert_utils::assert_balance_equals,
     cycles_account_manager::CyclesAccountManagerBuilder,
-    metrics::{fetch_histogram_stats, HistogramStats},
+    metrics::{fetch_histogram_stats, fetch_int_counter_vec, labels, HistogramStats},
     mock_time,
     state::{
         canister_from_exec_state, get_stopped_canister, get_stopping_canister, SystemStateBuilder,
@@ -41,7 +41,7 @@ use ic_types::{
     PrincipalId, SubnetId, Time, UserId,
 };
 use ic_utils::ic_features::cow_state_feature;
-use ic_wasm_utils::validation::WasmValidationLimits;
+use ic_wasm_utils::validation::{WasmAllowLists, WasmValidationLimits};
 use lazy_static::lazy_static;
 use maplit::btreemap;
 use proptest::prelude::*;
@@ -68,6 +68,8 @@ fn execution_parameters(
         canister_memory_limit: canister.memory_limit(NumBytes::new(std::u64::MAX)),
         subnet_available_memory: MAX_SUBNET_AVAILABLE_MEMORY.clone(),
         compute_allocation: canister.scheduler_state.compute_allocation,
+        canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+        root_key: vec![],
     }
 }
 
@@ -128,6 +130,13 @@ fn test_api_type_for_reject(reject_context: RejectContext) -> ApiType {
 }
 
 pub fn with_hypervisor<F>(f: F)
+where
+    F: FnOnce(Hypervisor, std::path::PathBuf),
+{
+    with_hypervisor_and_config(config(), f)
+}
+
+fn with_hypervisor_and_config<F>(config: ic_config::execution_environment::Config, f: F)
 where
     F: FnOnce(Hypervisor, std::path::PathBuf),
 {
@@ -136,7 +145,7 @@ where
         let metrics_registry = MetricsRegistry::new();
         let cycles_account_manager = Arc::new(CyclesAccountManagerBuilder::new().build());
         let hypervisor = Hypervisor::new(
-            config(),
+            config,
             1,
             &metrics_registry,
             subnet_test_id(1),
@@ -261,6 +270,22 @@ fn execute(
     system_state: SystemState,
     wast: &str,
     func_ref: FuncRef,
+) -> Result<Option<WasmResult>, HypervisorError> {
+    execute_with_metrics(
+        api_type,
+        system_state,
+        wast,
+        func_ref,
+        &MetricsRegistry::new(),
+    )
+}
+
+fn execute_with_metrics(
+    api_type: ApiType,
+    system_state: SystemState,
+    wast: &str,
+    func_ref: FuncRef,
+    metrics_registry: &MetricsRegistry,
 ) -> Result<Option<WasmResult>, HypervisorError> {
     let canister_root = tempfile::Builder::new()
         .prefix("test")
@@ -274,8 +299,7 @@ fn execute(
         WasmValidationLimits::default(),
     )
     .unwrap();
-    let metrics_registry = MetricsRegistry::new();
-    let metrics = Arc::new(HypervisorMetrics::new(&metrics_registry));
+    let metrics = Arc::new(HypervisorMetrics::new(metrics_registry));
     let config = config();
 
     let mut embedder_config = ic_config::embedders::Config::new();
@@ -286,7 +310,10 @@ fn execute(
         wasm_embedder,
         embedder_config.max_globals,
         embedder_config.max_functions,
-        &metrics_registry,
+        WasmAllowLists::default(),
+        embedder_config.write_barriers,
+        embedder_config.compiled_artifacts_dir.clone(),
+        metrics_registry,
         no_op_logger(),
     );
 
@@ -295,6 +322,8 @@ fn execute(
         canister_memory_limit: NumBytes::from(4 << 30),
         subnet_available_memory: MAX_SUBNET_AVAILABLE_MEMORY.clone(),
         compute_allocation: ComputeAllocation::default(),
+        canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+        root_key: vec![],
     };
 
     hypervisor_execute(
@@ -333,6 +362,52 @@ fn test_method_not_found_error() {
     });
 }
 
+#[test]
+fn traps_are_counted_by_kind() {
+    let wast = r#"(module
+                  (import "ic0" "trap" (func $ic_trap (param i32 i32)))
+                  (func $unreachable unreachable)
+                  (func $div_by_zero
+                    (drop (i32.div_u (i32.const 1) (i32.const 0))))
+                  (func $out_of_bounds
+                    (drop (i32.load (i32.const 65536))))
+                  (func $called_trap
+                    (call $ic_trap (i32.const 0) (i32.const 0)))
+                  (memory 1)
+                  (export "canister_update unreachable" (func $unreachable))
+                  (export "canister_update div_by_zero" (func $div_by_zero))
+                  (export "canister_update out_of_bounds" (func $out_of_bounds))
+                  (export "canister_update called_trap" (func $called_trap))
+            )"#;
+    let metrics_registry = MetricsRegistry::new();
+    for method in &[
+        "unreachable",
+        "div_by_zero",
+        "out_of_bounds",
+        "called_trap",
+        "called_trap",
+    ] {
+        let wasm_result = execute_with_metrics(
+            test_api_type_for_update(None, vec![]),
+            SystemStateBuilder::default().build(),
+            wast,
+            FuncRef::Method(WasmMethod::Update(method.to_string())),
+            &metrics_registry,
+        );
+        assert!(wasm_result.is_err());
+    }
+
+    assert_eq!(
+        fetch_int_counter_vec(&metrics_registry, "execution_wasm_traps_total"),
+        btreemap! {
+            labels(&[("trap_kind", "unreachable")]) => 1,
+            labels(&[("trap_kind", "integer_div_by_zero")]) => 1,
+            labels(&[("trap_kind", "heap_out_of_bounds")]) => 1,
+            labels(&[("trap_kind", "called_trap")]) => 2,
+        }
+    );
+}
+
 #[test]
 // Runs unavailable table function
 fn test_function_not_found_error() {
@@ -2046,6 +2121,524 @@ fn test_execute_query_msg_caller() {
     });
 }
 
+const DATA_CERTIFICATE_PRESENT_WAT: &str = r#"
+        (module
+          (import "ic0" "data_certificate_present"
+            (func $ic0_data_certificate_present (result i32)))
+          (import "ic0" "msg_reply" (func $msg_reply))
+          (import "ic0" "msg_reply_data_append"
+            (func $msg_reply_data_append (param i32) (param i32)))
+
+          (func $test
+            ;; heap[0] = data_certificate_present()
+            (i32.store8 (i32.const 0) (call $ic0_data_certificate_present))
+            (call $msg_reply_data_append (i32.const 0) (i32.const 1))
+            (call $msg_reply))
+
+          (memory $memory 1)
+          (export "memory" (memory $memory))
+          (export "canister_query query_test" (func $test))
+        )"#;
+
+// Executes `DATA_CERTIFICATE_PRESENT_WAT` as a replicated query that is
+// given a data certificate and returns the result of
+// `ic0.data_certificate_present`.
+fn data_certificate_present_in_replicated_query(
+    hypervisor: Hypervisor,
+    tmp_path: std::path::PathBuf,
+) -> u8 {
+    let wasm_binary = wabt::wat2wasm(DATA_CERTIFICATE_PRESENT_WAT).unwrap();
+    let execution_state =
+        ExecutionState::new(wasm_binary, tmp_path, WasmValidationLimits::default()).unwrap();
+    let canister = canister_from_exec_state(execution_state);
+    let execution_parameters = execution_parameters(&canister, MAX_NUM_INSTRUCTIONS);
+    let (_, _, result) = hypervisor.execute_query(
+        QueryExecutionType::Replicated,
+        "query_test",
+        &[],
+        user_test_id(12).get(),
+        canister,
+        Some(vec![1, 2, 3]),
+        mock_time(),
+        execution_parameters,
+    );
+    match result {
+        Ok(Some(WasmResult::Reply(payload))) => payload[0],
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn data_certificate_is_not_present_in_replicated_queries() {
+    with_hypervisor(|hypervisor, tmp_path| {
+        assert_eq!(
+            data_certificate_present_in_replicated_query(hypervisor, tmp_path),
+            0
+        );
+    });
+}
+
+#[test]
+fn data_certificate_is_present_in_replicated_queries_with_legacy_behavior() {
+    let config = ic_config::execution_environment::Config {
+        legacy_data_certificate_in_replicated_queries: true,
+        ..config()
+    };
+    with_hypervisor_and_config(config, |hypervisor, tmp_path| {
+        assert_eq!(
+            data_certificate_present_in_replicated_query(hypervisor, tmp_path),
+            1
+        );
+    });
+}
+
+/// The entry points through which the hypervisor executes canister code,
+/// covering every API type that `ic0.data_certificate_*` can be called with.
+#[derive(Clone, Copy, Debug)]
+enum DataCertificateEntryPoint {
+    Start,
+    Init,
+    PreUpgrade,
+    PostUpgrade,
+    Update,
+    ReplicatedQuery,
+    NonReplicatedQuery,
+    InspectMessage,
+    ReplyCallback,
+    RejectCallback,
+    Heartbeat,
+}
+
+impl DataCertificateEntryPoint {
+    const ALL: [DataCertificateEntryPoint; 11] = [
+        DataCertificateEntryPoint::Start,
+        DataCertificateEntryPoint::Init,
+        DataCertificateEntryPoint::PreUpgrade,
+        DataCertificateEntryPoint::PostUpgrade,
+        DataCertificateEntryPoint::Update,
+        DataCertificateEntryPoint::ReplicatedQuery,
+        DataCertificateEntryPoint::NonReplicatedQuery,
+        DataCertificateEntryPoint::InspectMessage,
+        DataCertificateEntryPoint::ReplyCallback,
+        DataCertificateEntryPoint::RejectCallback,
+        DataCertificateEntryPoint::Heartbeat,
+    ];
+
+    /// The name of the mode of the API type the entry point runs with, as
+    /// it appears in errors.
+    fn mode(self) -> &'static str {
+        match self {
+            DataCertificateEntryPoint::Start => "start",
+            DataCertificateEntryPoint::Init | DataCertificateEntryPoint::PostUpgrade => "init",
+            DataCertificateEntryPoint::PreUpgrade => "pre upgrade",
+            DataCertificateEntryPoint::Update => "update",
+            DataCertificateEntryPoint::ReplicatedQuery => "replicated query",
+            DataCertificateEntryPoint::NonReplicatedQuery => "non replicated query",
+            DataCertificateEntryPoint::InspectMessage => "inspect message",
+            DataCertificateEntryPoint::ReplyCallback => "reply callback",
+            DataCertificateEntryPoint::RejectCallback => "reject callback",
+            DataCertificateEntryPoint::Heartbeat => "heartbeat",
+        }
+    }
+}
+
+const DATA_CERTIFICATE: [u8; 3] = [1, 2, 3];
+
+// Checks that the data certificate is not present.
+const DATA_CERTIFICATE_NOT_PRESENT: &str = r#"
+            (if (call $ic0_data_certificate_present) (then (unreachable)))"#;
+
+const DATA_CERTIFICATE_SIZE: &str = r#"
+            (drop (call $ic0_data_certificate_size))"#;
+
+const DATA_CERTIFICATE_COPY: &str = r#"
+            (call $ic0_data_certificate_copy (i32.const 0) (i32.const 0) (i32.const 0))"#;
+
+// Checks that the data certificate is present and replies with it.
+const DATA_CERTIFICATE_REPLY: &str = r#"
+            (if (i32.eqz (call $ic0_data_certificate_present)) (then (unreachable)))
+            (call $ic0_data_certificate_copy
+                (i32.const 0) (i32.const 0) (call $ic0_data_certificate_size))
+            (call $msg_reply_data_append (i32.const 0) (call $ic0_data_certificate_size))
+            (call $msg_reply)"#;
+
+// Runs `body` in the given entry point of a canister and returns the result.
+// Queries are passed `DATA_CERTIFICATE`.
+fn execute_data_certificate_body(
+    hypervisor: &Hypervisor,
+    tmp_path: PathBuf,
+    entry_point: DataCertificateEntryPoint,
+    body: &str,
+) -> HypervisorResult<Option<WasmResult>> {
+    let wat = format!(
+        r#"
+        (module
+          (import "ic0" "data_certificate_present"
+            (func $ic0_data_certificate_present (result i32)))
+          (import "ic0" "data_certificate_size"
+            (func $ic0_data_certificate_size (result i32)))
+          (import "ic0" "data_certificate_copy"
+            (func $ic0_data_certificate_copy (param i32 i32 i32)))
+          (import "ic0" "msg_reply" (func $msg_reply))
+          (import "ic0" "msg_reply_data_append"
+            (func $msg_reply_data_append (param i32 i32)))
+          (import "ic0" "accept_message" (func $accept_message))
+
+          (func $test {})
+          (func $inspect (call $test) (call $accept_message))
+          (func $callback (param $env i32) (call $test))
+
+          (table funcref (elem $callback))
+          (memory $memory 1)
+          (export "memory" (memory $memory))
+          (start $test)
+          (export "canister_init" (func $test))
+          (export "canister_pre_upgrade" (func $test))
+          (export "canister_post_upgrade" (func $test))
+          (export "canister_heartbeat" (func $test))
+          (export "canister_inspect_message" (func $inspect))
+          (export "canister_update update" (func $test))
+          (export "canister_query query" (func $test))
+        )"#,
+        body
+    );
+    let execution_state = ExecutionState::new(
+        wabt::wat2wasm(wat).unwrap(),
+        tmp_path,
+        WasmValidationLimits::default(),
+    )
+    .unwrap();
+    let mut canister = canister_from_exec_state(execution_state);
+    let execution_parameters = execution_parameters(&canister, MAX_NUM_INSTRUCTIONS);
+    let (_, _, routing_table, subnet_records) = setup();
+    let system_method_result = |result: HypervisorResult<NumBytes>| result.map(|_| None);
+    match entry_point {
+        DataCertificateEntryPoint::Start => system_method_result(
+            hypervisor
+                .execute_canister_start(canister, execution_parameters)
+                .2,
+        ),
+        DataCertificateEntryPoint::Init => system_method_result(
+            hypervisor
+                .execute_canister_init(
+                    canister,
+                    test_caller(),
+                    EMPTY_PAYLOAD.as_slice(),
+                    mock_time(),
+                    execution_parameters,
+                )
+                .2,
+        ),
+        DataCertificateEntryPoint::PreUpgrade => system_method_result(
+            hypervisor
+                .execute_canister_pre_upgrade(
+                    canister,
+                    test_caller(),
+                    mock_time(),
+                    execution_parameters,
+                )
+                .2,
+        ),
+        DataCertificateEntryPoint::PostUpgrade => system_method_result(
+            hypervisor
+                .execute_canister_post_upgrade(
+                    canister,
+                    test_caller(),
+                    EMPTY_PAYLOAD.as_slice(),
+                    mock_time(),
+                    execution_parameters,
+                )
+                .2,
+        ),
+        DataCertificateEntryPoint::Heartbeat => system_method_result(
+            hypervisor
+                .execute_canister_heartbeat(
+                    canister,
+                    routing_table,
+                    subnet_records,
+                    mock_time(),
+                    execution_parameters,
+                )
+                .2,
+        ),
+        DataCertificateEntryPoint::Update => {
+            let ingress = IngressBuilder::new().method_name("update").build();
+            let (_, _, action, _) = hypervisor.execute_update(
+                canister,
+                RequestOrIngress::Ingress(ingress),
+                mock_time(),
+                routing_table,
+                subnet_records,
+                execution_parameters,
+            );
+            match action {
+                CallContextAction::Reply { payload, .. } => Ok(Some(WasmResult::Reply(payload))),
+                CallContextAction::NoResponse { .. } => Ok(None),
+                CallContextAction::Fail { error, .. } => Err(error),
+                action => panic!("unexpected action {:?}", action),
+            }
+        }
+        DataCertificateEntryPoint::ReplicatedQuery
+        | DataCertificateEntryPoint::NonReplicatedQuery => {
+            let query_execution_type = match entry_point {
+                DataCertificateEntryPoint::ReplicatedQuery => QueryExecutionType::Replicated,
+                _ => QueryExecutionType::NonReplicated {
+                    call_context_id: call_context_test_id(13),
+                    routing_table,
+                    query_kind: NonReplicatedQueryKind::Pure,
+                },
+            };
+            hypervisor
+                .execute_query(
+                    query_execution_type,
+                    "query",
+                    EMPTY_PAYLOAD.as_slice(),
+                    test_caller(),
+                    canister,
+                    Some(DATA_CERTIFICATE.to_vec()),
+                    mock_time(),
+                    execution_parameters,
+                )
+                .2
+        }
+        DataCertificateEntryPoint::InspectMessage => {
+            match hypervisor.execute_inspect_message(
+                canister,
+                test_caller(),
+                "update".to_string(),
+                EMPTY_PAYLOAD,
+                mock_time(),
+                execution_parameters,
+            ) {
+                Ok(()) => Ok(None),
+                Err(MessageAcceptanceError::CanisterExecutionFailed(err)) => Err(err),
+                Err(err) => panic!("unexpected error {:?}", err),
+            }
+        }
+        DataCertificateEntryPoint::ReplyCallback | DataCertificateEntryPoint::RejectCallback => {
+            let call_origin = CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5));
+            let call_context_id = canister
+                .system_state
+                .call_context_manager_mut()
+                .unwrap()
+                .new_call_context(call_origin.clone(), Cycles::from(0));
+            let payload = match entry_point {
+                DataCertificateEntryPoint::ReplyCallback => Payload::Data(EMPTY_PAYLOAD),
+                _ => Payload::Reject(RejectContext::new(
+                    RejectCode::CanisterReject,
+                    "rejected".to_string(),
+                )),
+            };
+            hypervisor
+                .execute_callback(
+                    canister,
+                    &call_origin,
+                    Callback::new(
+                        call_context_id,
+                        Cycles::from(0),
+                        WasmClosure::new(0, 0),
+                        WasmClosure::new(0, 0),
+                        None,
+                    ),
+                    payload,
+                    Cycles::from(0),
+                    mock_time(),
+                    routing_table,
+                    subnet_records,
+                    execution_parameters,
+                )
+                .3
+        }
+    }
+}
+
+// Checks `ic0.data_certificate_*` in every entry point. The certificate is
+// only available in non-replicated queries and, if `legacy` is set, in
+// replicated queries.
+fn check_data_certificate_in_every_entry_point(legacy: bool) {
+    let config = ic_config::execution_environment::Config {
+        legacy_data_certificate_in_replicated_queries: legacy,
+        ..config()
+    };
+    for entry_point in DataCertificateEntryPoint::ALL.iter().copied() {
+        with_hypervisor_and_config(config.clone(), |hypervisor, tmp_path| {
+            let present = match entry_point {
+                DataCertificateEntryPoint::NonReplicatedQuery => true,
+                DataCertificateEntryPoint::ReplicatedQuery => legacy,
+                _ => false,
+            };
+            let run = |body: &str| {
+                execute_data_certificate_body(&hypervisor, tmp_path.clone(), entry_point, body)
+            };
+            if present {
+                assert_eq!(
+                    run(DATA_CERTIFICATE_REPLY),
+                    Ok(Some(WasmResult::Reply(DATA_CERTIFICATE.to_vec()))),
+                    "{:?}",
+                    entry_point
+                );
+            } else {
+                assert_eq!(
+                    run(DATA_CERTIFICATE_NOT_PRESENT),
+                    Ok(None),
+                    "{:?}",
+                    entry_point
+                );
+                for (body, method) in &[
+                    (DATA_CERTIFICATE_SIZE, "ic0_data_certificate_size"),
+                    (DATA_CERTIFICATE_COPY, "ic0_data_certificate_copy"),
+                ] {
+                    assert_eq!(
+                        run(body),
+                        Err(HypervisorError::ContractViolation(format!(
+                            "\"{}\" cannot be executed in {} mode",
+                            method,
+                            entry_point.mode()
+                        ))),
+                        "{:?}",
+                        entry_point
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[test]
+fn data_certificate_is_only_available_in_non_replicated_queries() {
+    check_data_certificate_in_every_entry_point(false);
+}
+
+#[test]
+fn data_certificate_is_also_available_in_replicated_queries_with_legacy_behavior() {
+    check_data_certificate_in_every_entry_point(true);
+}
+
+const HTTP_TRANSFORM_WAT: &str = r#"
+        (module
+          (import "ic0" "msg_reply" (func $msg_reply))
+          (import "ic0" "msg_reply_data_append"
+            (func $msg_reply_data_append (param i32 i32)))
+          (import "ic0" "msg_arg_data_size" (func $msg_arg_data_size (result i32)))
+          (import "ic0" "msg_arg_data_copy"
+            (func $msg_arg_data_copy (param i32 i32 i32)))
+          (import "ic0" "call_new"
+            (func $call_new
+              (param i32 i32)
+              (param $method_name_src i32) (param $method_name_len i32)
+              (param $reply_fun i32) (param $reply_env i32)
+              (param $reject_fun i32) (param $reject_env i32)))
+
+          ;; Replies with the response of the remote server.
+          (func $echo
+            (call $msg_arg_data_copy (i32.const 0) (i32.const 0) (call $msg_arg_data_size))
+            ;; Overwrite the heap to check that the change is rolled back.
+            (i32.store8 (i32.const 100) (i32.const 1))
+            (call $msg_reply_data_append (i32.const 0) (call $msg_arg_data_size))
+            (call $msg_reply))
+
+          (func $spin
+            (loop $forever (br $forever)))
+
+          (func $call
+            (call $call_new
+              (i32.const 0) (i32.const 10)
+              (i32.const 0) (i32.const 4)
+              (i32.const 0) (i32.const 0)
+              (i32.const 0) (i32.const 0)))
+
+          (memory $memory 1)
+          (export "memory" (memory $memory))
+          (export "canister_query echo" (func $echo))
+          (export "canister_query spin" (func $spin))
+          (export "canister_query call" (func $call))
+        )"#;
+
+// Executes the transform `method` of `HTTP_TRANSFORM_WAT` on `response`.
+fn execute_http_transform(
+    hypervisor: &Hypervisor,
+    tmp_path: std::path::PathBuf,
+    method: &str,
+    response: &[u8],
+) -> (
+    CanisterState,
+    CanisterState,
+    HypervisorResult<Option<WasmResult>>,
+) {
+    let wasm_binary = wabt::wat2wasm(HTTP_TRANSFORM_WAT).unwrap();
+    let execution_state =
+        ExecutionState::new(wasm_binary, tmp_path, WasmValidationLimits::default()).unwrap();
+    let mut canister = canister_from_exec_state(execution_state);
+    canister.system_state.cycles_balance = INITIAL_CYCLES;
+    canister.system_state.freeze_threshold = 0.into();
+    let execution_parameters = execution_parameters(&canister, MAX_NUM_INSTRUCTIONS);
+    let (new_canister, _, result) = hypervisor.execute_http_transform(
+        method,
+        response,
+        canister.clone(),
+        mock_time(),
+        execution_parameters,
+    );
+    (canister, new_canister, result)
+}
+
+#[test]
+fn http_transform_replies_and_is_charged_to_the_canister() {
+    with_hypervisor(|hypervisor, tmp_path| {
+        let (canister, new_canister, result) =
+            execute_http_transform(&hypervisor, tmp_path, "echo", b"response");
+        assert_eq!(result, Ok(Some(WasmResult::Reply(b"response".to_vec()))));
+
+        // Only the cycles balance of the canister changes.
+        assert!(new_canister.system_state.cycles_balance < INITIAL_CYCLES);
+        assert_eq!(new_canister.execution_state, canister.execution_state);
+    });
+}
+
+#[test]
+fn http_transform_cannot_make_calls() {
+    with_hypervisor(|hypervisor, tmp_path| {
+        let (_, _, result) = execute_http_transform(&hypervisor, tmp_path, "call", &[]);
+        assert_eq!(
+            result,
+            Err(HypervisorError::ContractViolation(
+                "\"ic0_call_new\" cannot be executed in transform mode".to_string()
+            ))
+        );
+    });
+}
+
+#[test]
+fn http_transform_reply_is_limited() {
+    let config = ic_config::execution_environment::Config {
+        max_http_transform_response_size: NumBytes::new(4),
+        ..config()
+    };
+    with_hypervisor_and_config(config, |hypervisor, tmp_path| {
+        let (_, _, result) = execute_http_transform(&hypervisor, tmp_path, "echo", b"response");
+        assert!(matches!(result, Err(HypervisorError::ContractViolation(_))));
+    });
+}
+
+#[test]
+fn http_transform_is_limited_independently_of_the_message() {
+    let config = ic_config::execution_environment::Config {
+        max_instructions_per_http_transform: NumInstructions::new(10_000),
+        ..config()
+    };
+    with_hypervisor_and_config(config, |hypervisor, tmp_path| {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let (_, new_canister, result) = execute_http_transform(&hypervisor, tmp_path, "spin", &[]);
+        assert_eq!(result, Err(HypervisorError::OutOfInstructions));
+        // The canister pays for the instructions of the transform only, not
+        // for the instruction limit of the message.
+        assert_eq!(
+            new_canister.system_state.cycles_balance,
+            INITIAL_CYCLES - cycles_account_manager.execution_cost(NumInstructions::new(10_000))
+        );
+    });
+}
+
 #[test]
 // Tests that ic0_msg_arg_data_copy cannot be accessed in a reject callback
 fn sys_api_call_arg_data_copy_fail() {
@@ -2115,6 +2708,118 @@ fn sys_api_call_arg_data_copy() {
     });
 }
 
+#[test]
+// streams a multi-megabyte payload through a single 64 KiB buffer and replies
+// with the number of bytes read and the sum of all bytes modulo 2^32
+fn sys_api_call_arg_data_next_chunk() {
+    let payload: Vec<u8> = (0..4 * 1024 * 1024 + 5).map(|i| (i % 251) as u8).collect();
+    let total = payload.len() as u32;
+    let sum = payload
+        .iter()
+        .fold(0u32, |acc, b| acc.wrapping_add(*b as u32));
+    let mut expected_reply = total.to_le_bytes().to_vec();
+    expected_reply.extend_from_slice(&sum.to_le_bytes());
+    with_hypervisor(|hypervisor, tmp_path| {
+        assert_eq!(
+            execute_update(
+                &hypervisor,
+                r#"
+                (module
+                  (import "ic0" "msg_reply" (func $msg_reply))
+                  (import "ic0" "msg_reply_data_append"
+                    (func $msg_reply_data_append (param i32 i32)))
+                  (import "ic0" "msg_arg_data_next_chunk"
+                    (func $msg_arg_data_next_chunk (param i32 i32) (result i32)))
+                  (func $test
+                    (local $copied i32)
+                    (local $total i32)
+                    (local $sum i32)
+                    (local $i i32)
+                    (block $done
+                      (loop $chunks
+                        ;; read the next chunk into the second page
+                        (local.set $copied
+                          (call $msg_arg_data_next_chunk (i32.const 65536) (i32.const 65536)))
+                        (br_if $done (i32.eqz (local.get $copied)))
+                        (local.set $total (i32.add (local.get $total) (local.get $copied)))
+                        (local.set $i (i32.const 0))
+                        (block $chunk_done
+                          (loop $bytes
+                            (br_if $chunk_done (i32.ge_u (local.get $i) (local.get $copied)))
+                            (local.set $sum
+                              (i32.add (local.get $sum)
+                                (i32.load8_u (i32.add (i32.const 65536) (local.get $i)))))
+                            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                            (br $bytes)))
+                        (br $chunks)))
+                    (i32.store (i32.const 0) (local.get $total))
+                    (i32.store (i32.const 4) (local.get $sum))
+                    (call $msg_reply_data_append (i32.const 0) (i32.const 8))
+                    (call $msg_reply))
+                  (memory (;0;) 2)
+                  (export "memory" (memory 0))
+                  (export "canister_update test" (func $test)))"#,
+                "test",
+                payload,
+                None,
+                tmp_path,
+            )
+            .2,
+            CallContextAction::Reply {
+                payload: expected_reply,
+                refund: Cycles::from(0),
+            },
+        );
+    });
+}
+
+#[test]
+// every chunk of the payload is charged for the bytes it may copy
+fn sys_api_call_arg_data_next_chunk_charges_per_chunk() {
+    let wast = r#"
+        (module
+          (import "ic0" "msg_reply" (func $msg_reply))
+          (import "ic0" "msg_arg_data_next_chunk"
+            (func $msg_arg_data_next_chunk (param i32 i32) (result i32)))
+          (func $test
+            (block $done
+              (loop $chunks
+                (br_if $done
+                  (i32.eqz (call $msg_arg_data_next_chunk (i32.const 0) (i32.const 65536))))
+                (br $chunks)))
+            (call $msg_reply))
+          (memory (;0;) 1)
+          (export "memory" (memory 0))
+          (export "canister_update test" (func $test)))"#;
+    let instructions_used = |payload_size: usize| {
+        let mut used = NumInstructions::from(0);
+        with_hypervisor(|hypervisor, tmp_path| {
+            let (_, instructions_left, action, _) = execute_update(
+                &hypervisor,
+                wast,
+                "test",
+                vec![1; payload_size],
+                None,
+                tmp_path,
+            );
+            assert_eq!(
+                action,
+                CallContextAction::Reply {
+                    payload: vec![],
+                    refund: Cycles::from(0),
+                }
+            );
+            used = MAX_NUM_INSTRUCTIONS - instructions_left;
+        });
+        used
+    };
+    let one_mib = 1024 * 1024;
+    assert!(
+        instructions_used(3 * one_mib) - instructions_used(one_mib)
+            >= NumInstructions::from(2 * one_mib as u64)
+    );
+}
+
 #[test]
 // calls reject
 fn sys_api_call_reject() {
@@ -3277,6 +3982,62 @@ fn canister_metrics_are_recorded() {
     });
 }
 
+#[test]
+fn recent_execution_metrics_are_recorded() {
+    with_hypervisor(|hypervisor, tmp_path| {
+        let wast = r#"
+                (module
+                  (func $grow
+                    (drop (memory.grow (i32.const 1))))
+                  (func $trap
+                    (unreachable))
+                  (export "canister_update grow" (func $grow))
+                  (export "canister_update trap" (func $trap))
+                  (memory (;0;) 1)
+                  (export "memory" (memory 0))
+                )
+            "#;
+        let (canister, num_instructions_left, _, _) = execute_update(
+            &hypervisor,
+            wast,
+            "grow",
+            EMPTY_PAYLOAD,
+            None,
+            tmp_path.clone(),
+        );
+        let rounds: Vec<_> = canister
+            .system_state
+            .canister_metrics
+            .recent_execution_metrics
+            .rounds()
+            .cloned()
+            .collect();
+        assert_eq!(rounds.len(), 1);
+        assert_eq!(rounds[0].time, mock_time());
+        assert_eq!(rounds[0].updates_executed, 1);
+        assert_eq!(
+            rounds[0].instructions_executed,
+            MAX_NUM_INSTRUCTIONS - num_instructions_left
+        );
+        assert_eq!(rounds[0].traps, 0);
+        assert_eq!(rounds[0].memory_grow_events, 1);
+
+        let (canister, _, _, _) =
+            execute_update(&hypervisor, wast, "trap", EMPTY_PAYLOAD, None, tmp_path);
+        let round = canister
+            .system_state
+            .canister_metrics
+            .recent_execution_metrics
+            .rounds()
+            .next()
+            .cloned()
+            .unwrap();
+        assert_eq!(round.updates_executed, 1);
+        assert_eq!(round.traps, 1);
+        assert_eq!(round.memory_grow_events, 0);
+    });
+}
+
 #[test]
 fn executing_non_existing_method_does_not_consume_cycles() {
     with_test_replica_logger(|log| {
@@ -3507,6 +4268,7 @@ fn test_system_method_is_callable(system_method: SystemMethod) {
             SystemMethod::CanisterInspectMessage => unimplemented!(),
             SystemMethod::Empty => unimplemented!(),
             SystemMethod::CanisterHeartbeat => unimplemented!("We don't need this test."),
+            SystemMethod::CanisterOnLowWasmMemory => unimplemented!("We don't need this test."),
         };
 
         assert!(
@@ -3599,6 +4361,14 @@ fn test_non_existing_system_method(system_method: SystemMethod) {
                 mock_time(),
                 execution_parameters,
             ),
+            SystemMethod::CanisterOnLowWasmMemory => hypervisor.execute_canister_system_task(
+                canister,
+                SystemTask::OnLowWasmMemory,
+                routing_table,
+                subnet_records,
+                mock_time(),
+                execution_parameters,
+            ),
         };
 
         assert!(
@@ -3639,6 +4409,11 @@ fn test_non_existing_canister_heartbeat() {
     test_non_existing_system_method(SystemMethod::CanisterHeartbeat);
 }
 
+#[test]
+fn test_non_existing_canister_on_low_wasm_memory() {
+    test_non_existing_system_method(SystemMethod::CanisterOnLowWasmMemory);
+}
+
 #[test]
 fn canister_init_can_set_mutable_globals() {
     with_hypervisor(|hypervisor, tmp_path| {
@@ -45,8 +45,8 @@ use ic_test_utilities::{
 use ic_types::{
     ic00,
     ic00::{
-        CanisterIdRecord, CanisterStatusResultV2, EmptyBlob, InstallCodeArgs, Method,
-        Payload as Ic00Payload, IC_00,
+        CanisterIdRecord, CanisterStatusResultV2, CostCallArgs, CostExecutionArgs,
+        CyclesCostRecord, EmptyBlob, InstallCodeArgs, Method, Payload as Ic00Payload, IC_00,
     },
     ingress::{IngressStatus, WasmResult},
     messages::{
@@ -214,6 +214,7 @@ fn test_outgoing_messages(
             mock_time(),
             routing_table,
             subnet_records,
+            &[],
             MAX_SUBNET_AVAILABLE_MEMORY.clone(),
         );
 
@@ -823,6 +824,7 @@ fn stopping_canister_rejects_requests() {
                 mock_time(),
                 routing_table,
                 subnet_records,
+                &[],
                 MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             );
             assert_eq!(
@@ -863,6 +865,7 @@ fn stopping_canister_rejects_ingress() {
                         mock_time(),
                         routing_table,
                         subnet_records,
+                        &[],
                         MAX_SUBNET_AVAILABLE_MEMORY.clone(),
                     )
                     .ingress_status
@@ -919,6 +922,7 @@ fn stopped_canister_rejects_requests() {
                 mock_time(),
                 routing_table,
                 subnet_records,
+                &[],
                 MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             );
             assert_eq!(
@@ -956,6 +960,7 @@ fn stopped_canister_rejects_ingress() {
                 mock_time(),
                 routing_table,
                 subnet_records,
+                &[],
                 MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             );
 
@@ -1675,6 +1680,111 @@ fn subnet_ingress_message_on_create_canister_fails() {
     });
 }
 
+// Executes a request from `sender` to the management canister and returns the
+// payload of the response.
+fn execute_subnet_request(
+    exec_env: &ExecutionEnvironmentImpl,
+    mut state: ReplicatedState,
+    sender: CanisterId,
+    method: Method,
+    payload: Vec<u8>,
+) -> Payload {
+    let receiver = CanisterId::new(subnet_test_id(1).get()).unwrap();
+    state
+        .subnet_queues
+        .push_input(
+            QUEUE_INDEX_NONE,
+            RequestOrResponse::Request(
+                RequestBuilder::new()
+                    .sender(sender)
+                    .receiver(receiver)
+                    .method_name(method)
+                    .method_payload(payload)
+                    .build(),
+            ),
+        )
+        .unwrap();
+    let mut state = exec_env
+        .execute_subnet_message(
+            state.subnet_queues.pop_input().unwrap(),
+            state,
+            MAX_NUM_INSTRUCTIONS,
+            &mut mock_random_number_generator(),
+            &ProvisionalWhitelist::Set(BTreeSet::new()),
+            MAX_SUBNET_AVAILABLE_MEMORY.clone(),
+        )
+        .0;
+    match state.subnet_queues.pop_canister_output(&sender).unwrap().1 {
+        RequestOrResponse::Response(response) => response.response_payload,
+        RequestOrResponse::Request(request) => panic!("Unexpected request: {:?}", request),
+    }
+}
+
+#[test]
+fn cost_create_canister_returns_creation_fee() {
+    with_setup(SubnetType::Application, |exec_env, state, _, _, _| {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+
+        let payload = execute_subnet_request(
+            &exec_env,
+            state,
+            canister_test_id(1),
+            Method::CostCreateCanister,
+            EmptyBlob::encode(),
+        );
+
+        assert_eq!(
+            payload,
+            Payload::Data(
+                CyclesCostRecord::new(cycles_account_manager.canister_creation_fee().get())
+                    .encode()
+            )
+        );
+    });
+}
+
+#[test]
+fn cost_call_returns_cycles_withdrawn_for_request() {
+    with_setup(SubnetType::Application, |exec_env, state, _, _, _| {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+
+        let payload = execute_subnet_request(
+            &exec_env,
+            state,
+            canister_test_id(1),
+            Method::CostCall,
+            CostCallArgs::new(10, 1000).encode(),
+        );
+
+        let cost = cycles_account_manager.request_cost(NumBytes::from(1010));
+        assert_eq!(
+            payload,
+            Payload::Data(CyclesCostRecord::new(cost.get()).encode())
+        );
+    });
+}
+
+#[test]
+fn cost_execution_returns_cost_of_instructions() {
+    with_setup(SubnetType::Application, |exec_env, state, _, _, _| {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+
+        let payload = execute_subnet_request(
+            &exec_env,
+            state,
+            canister_test_id(1),
+            Method::CostExecution,
+            CostExecutionArgs::new(1_000_000).encode(),
+        );
+
+        let cost = cycles_account_manager.execution_cost(NumInstructions::from(1_000_000));
+        assert_eq!(
+            payload,
+            Payload::Data(CyclesCostRecord::new(cost.get()).encode())
+        );
+    });
+}
+
 #[test]
 fn subnet_canister_request_bad_candid_payload() {
     with_setup(SubnetType::Application, |exec_env, mut state, _, _, _| {
@@ -2338,6 +2448,7 @@ fn can_update_canisters_cycles_account_when_an_ingress_is_executed() {
                 mock_time(),
                 routing_table,
                 subnet_records,
+                &[],
                 MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             );
 
@@ -2382,6 +2493,7 @@ fn can_reject_a_request_when_canister_is_out_of_cycles() {
                 mock_time(),
                 routing_table,
                 subnet_records,
+                &[],
                 MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             );
             assert_eq!(
@@ -2437,6 +2549,7 @@ fn can_reject_an_ingress_when_canister_is_out_of_cycles() {
                 mock_time(),
                 routing_table,
                 subnet_records,
+                &[],
                 MAX_SUBNET_AVAILABLE_MEMORY.clone(),
             );
             assert_eq!(
@@ -2479,6 +2592,7 @@ fn canister_heartbeat_doesnt_run_when_canister_is_stopped() {
                     MAX_NUM_INSTRUCTIONS,
                     routing_table,
                     subnet_records,
+                    &[],
                     mock_time(),
                     MAX_SUBNET_AVAILABLE_MEMORY.clone(),
                 )
@@ -2507,6 +2621,7 @@ fn canister_heartbeat_doesnt_run_when_canister_is_stopping() {
                     MAX_NUM_INSTRUCTIONS,
                     routing_table,
                     subnet_records,
+                    &[],
                     mock_time(),
                     MAX_SUBNET_AVAILABLE_MEMORY.clone(),
                 )
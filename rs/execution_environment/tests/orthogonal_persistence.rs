@@ -28,6 +28,8 @@ fn execution_parameters() -> ExecutionParameters {
         canister_memory_limit: NumBytes::new(std::u64::MAX),
         subnet_available_memory: SubnetAvailableMemory::new(NumBytes::new(std::u64::MAX)),
         compute_allocation: ComputeAllocation::default(),
+        canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+        root_key: vec![],
     }
 }
 
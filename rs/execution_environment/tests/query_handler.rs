@@ -37,7 +37,7 @@ fn query_non_existent() {
         let cycles_account_manager = Arc::new(CyclesAccountManagerBuilder::new().build());
         let state_manager = Arc::new(FakeStateManager::new());
 
-        let (_, _, query_handler, _, _) = setup_execution(
+        let (_, _, query_handler, _, _, _) = setup_execution(
             log,
             &metrics_registry,
             subnet_id,
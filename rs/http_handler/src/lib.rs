@@ -11,6 +11,7 @@ mod common;
 mod dashboard;
 mod metrics;
 mod read;
+mod round_state;
 mod status;
 mod submit;
 mod types;
@@ -25,7 +26,7 @@ use ic_crypto_tree_hash::Path;
 use ic_interfaces::{
     consensus_pool::ConsensusPoolCache,
     crypto::IngressSigVerifier,
-    execution_environment::{IngressMessageFilter, QueryHandler},
+    execution_environment::{IngressMessageFilter, QueryHandler, RoundStateDumpReader},
     p2p::IngressEventHandler,
     registry::RegistryClient,
     state_manager::StateReader,
@@ -41,7 +42,7 @@ use ic_types::{
         HttpRequestEnvelope, ReplicaHealthStatus,
     },
     time::current_time_and_expiry_time,
-    SubnetId,
+    NodeId, SubnetId,
 };
 use leaky_bucket::RateLimiter;
 use metrics::HttpHandlerMetrics;
@@ -117,6 +118,7 @@ const UNKNOWN_LABEL: &str = "unknown";
 struct HttpHandler {
     log: ReplicaLogger,
     config: Config,
+    node_id: NodeId,
     subnet_id: SubnetId,
     nns_subnet_id: SubnetId,
     subnet_type: SubnetType,
@@ -131,6 +133,7 @@ struct HttpHandler {
     ingress_sender: Arc<dyn IngressEventHandler>,
     consensus_pool_cache: Arc<dyn ConsensusPoolCache>,
     malicious_flags: MaliciousFlags,
+    round_state_dump: Option<Arc<dyn RoundStateDumpReader>>,
 
     delegation_from_nns: Arc<RwLock<Option<CertificateDelegation>>>,
     health_status: Arc<RwLock<ReplicaHealthStatus>>,
@@ -234,6 +237,7 @@ pub async fn start_server(
     registry_client: Arc<dyn RegistryClient>,
     tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
     ingress_verifier: Arc<dyn IngressSigVerifier + Send + Sync>,
+    node_id: NodeId,
     subnet_id: SubnetId,
     nns_subnet_id: SubnetId,
     log: ReplicaLogger,
@@ -242,13 +246,18 @@ pub async fn start_server(
     ingress_message_filter: Arc<dyn IngressMessageFilter<State = ReplicatedState>>,
     subnet_type: SubnetType,
     malicious_flags: MaliciousFlags,
+    round_state_dump: Option<Arc<dyn RoundStateDumpReader>>,
 ) -> Result<(), Error> {
     let metrics = Arc::new(HttpHandlerMetrics::new(&metrics_registry));
+    metrics
+        .executor_monitor
+        .start_scheduling_probe(&tokio::runtime::Handle::current());
 
     let http_handler = Arc::new(HttpHandler::new(
         config,
         registry_client,
         tls_handshake,
+        node_id,
         subnet_id,
         subnet_type,
         nns_subnet_id,
@@ -261,6 +270,7 @@ pub async fn start_server(
         backup_spool_path,
         ingress_message_filter,
         malicious_flags,
+        round_state_dump,
     ));
 
     info!(log, "Starting HTTP server...");
@@ -395,6 +405,7 @@ impl HttpHandler {
         config: Config,
         registry_client: Arc<dyn RegistryClient>,
         tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
+        node_id: NodeId,
         subnet_id: SubnetId,
         subnet_type: SubnetType,
         nns_subnet_id: SubnetId,
@@ -407,11 +418,13 @@ impl HttpHandler {
         backup_spool_path: Option<PathBuf>,
         ingress_message_filter: Arc<dyn IngressMessageFilter<State = ReplicatedState>>,
         malicious_flags: MaliciousFlags,
+        round_state_dump: Option<Arc<dyn RoundStateDumpReader>>,
     ) -> Self {
         Self {
             config,
             registry_client,
             tls_handshake,
+            node_id,
             subnet_id,
             subnet_type,
             nns_subnet_id,
@@ -426,6 +439,7 @@ impl HttpHandler {
             delegation_from_nns: Arc::new(RwLock::new(None)),
             health_status: Arc::new(RwLock::new(ReplicaHealthStatus::Starting)),
             malicious_flags,
+            round_state_dump,
         }
     }
 }
@@ -540,6 +554,7 @@ fn needs_all_preconditions(request_type: &RequestType) -> bool {
             | RequestType::Dashboard
             | RequestType::Status
             | RequestType::Artifacts(_)
+            | RequestType::RoundState
     )
 }
 
@@ -653,7 +668,9 @@ async fn route_to_handlers(
             status::handle(
                 &http_handler.log,
                 &http_handler.config,
+                http_handler.node_id,
                 http_handler.nns_subnet_id,
+                http_handler.registry_client.as_ref(),
                 http_handler.state_reader.as_ref(),
                 http_handler.health_status.read().unwrap().clone(),
             ),
@@ -689,6 +706,13 @@ async fn route_to_handlers(
             },
             ApiReqType::Unknown,
         ),
+        RequestType::RoundState => (
+            match &http_handler.round_state_dump {
+                Some(reader) => round_state::handle(reader.as_ref()),
+                None => common::make_response(StatusCode::NOT_FOUND, ""),
+            },
+            ApiReqType::Unknown,
+        ),
     }
 }
 
@@ -944,6 +968,7 @@ fn validate_parts(parts: &http::request::Parts) -> Result<RequestType, Response<
             "/api/v2/status" => Ok(RequestType::Status),
             "/" | "/_/" => Ok(RequestType::RedirectToDashboard),
             HTTP_DASHBOARD_URL_PATH => Ok(RequestType::Dashboard),
+            "/_/round_state" => Ok(RequestType::RoundState),
             other => match other.split('/').collect::<Vec<&str>>().as_slice() {
                 ["", "_", "artifacts", height] => match height.parse::<u64>() {
                     Ok(val) => Ok(RequestType::Artifacts(val)),
@@ -2,6 +2,7 @@ use crate::types::*;
 use hyper::StatusCode;
 use ic_metrics::{
     buckets::{add_bucket, decimal_buckets},
+    executor_monitor::ExecutorMonitor,
     MetricsRegistry,
 };
 use ic_types::time::{current_time_and_expiry_time, Time};
@@ -25,6 +26,7 @@ pub(crate) struct HttpHandlerMetrics {
     forbidden_requests: Arc<IntCounterVec>,
     internal_errors: Arc<IntCounterVec>,
     unreliable_request_acceptance_duration: Arc<HistogramVec>,
+    pub(crate) executor_monitor: ExecutorMonitor,
 }
 
 // There is a mismatch between the labels and the public spec.
@@ -99,6 +101,7 @@ impl HttpHandlerMetrics {
                 decimal_buckets(-3, 1),
                 &["type", "request_type"],
             )),
+            executor_monitor: ExecutorMonitor::new(metrics_registry, "http_handler"),
         }
     }
 
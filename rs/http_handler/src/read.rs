@@ -8,7 +8,7 @@ use crate::{
 use hyper::{Body, Response, StatusCode};
 use ic_crypto_tree_hash::{sparse_labeled_tree_from_paths, Label, Path};
 use ic_interfaces::crypto::IngressSigVerifier;
-use ic_interfaces::execution_environment::QueryHandler;
+use ic_interfaces::execution_environment::{QueryExecutionError, QueryHandler};
 use ic_interfaces::state_manager::StateReader;
 use ic_logger::{info, trace, ReplicaLogger};
 use ic_replicated_state::ReplicatedState;
@@ -24,12 +24,17 @@ use ic_types::{
     user_error::RejectCode,
     RegistryVersion, Time, UserId,
 };
-use ic_validator::{get_authorized_canisters, CanisterIdSet};
+use ic_validator::{get_authorized_canisters, validate_target, CanisterIdSet};
 use std::convert::TryFrom;
 use std::sync::Arc;
 
 const MAX_READ_STATE_REQUEST_IDS: u8 = 100;
 
+/// The header of a `503 Service Unavailable` response to a query that carries
+/// the height of the certified state against which the query should be
+/// retried.
+const RETRY_AT_HEIGHT_HEADER: &str = "x-ic-retry-at-height";
+
 enum VerifyPathsError {
     InvalidPath,
     InvalidRequestId,
@@ -153,8 +158,8 @@ async fn handle_query(
     targets: CanisterIdSet,
     metrics: Arc<HttpHandlerMetrics>,
 ) -> Response<Body> {
-    if !targets.contains(&query.receiver) {
-        return common::make_response(StatusCode::UNAUTHORIZED, "Unauthorized.");
+    if let Err(err) = validate_target(&targets, &query.receiver) {
+        return common::make_response(StatusCode::UNAUTHORIZED, &err.to_string());
     }
     metrics.observe_unreliable_request_acceptance_duration(
         RequestType::Read,
@@ -200,7 +205,23 @@ async fn handle_query(
             common::cbor_response(&response)
         }
 
-        Err(user_error) => {
+        Err(QueryExecutionError::RetryAtHeight {
+            height,
+            description,
+        }) => {
+            info!(
+                log,
+                "Could not perform query on canister: {} Retry at height {}.", description, height
+            );
+            let mut response = common::make_response(StatusCode::SERVICE_UNAVAILABLE, &description);
+            response.headers_mut().insert(
+                RETRY_AT_HEIGHT_HEADER,
+                hyper::header::HeaderValue::from(height.get()),
+            );
+            response
+        }
+
+        Err(QueryExecutionError::UserError(user_error)) => {
             info!(log, "Could not perform query on canister: {}", user_error);
             let response = HttpQueryResponse::Rejected {
                 reject_code: user_error.reject_code() as u64,
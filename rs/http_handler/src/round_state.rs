@@ -0,0 +1,24 @@
+//! Module that deals with requests to /_/round_state
+
+use crate::common;
+use hyper::{header, Body, Response, StatusCode};
+use ic_interfaces::execution_environment::RoundStateDumpReader;
+
+/// Handles a call to /_/round_state. Returns a JSON summary of the latest
+/// execution round, or a 503 response if no round was executed yet.
+pub(crate) fn handle(reader: &dyn RoundStateDumpReader) -> Response<Body> {
+    match reader.latest_round_state_dump() {
+        Some(json) => {
+            let mut response = common::make_response(StatusCode::OK, &json);
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+            response
+        }
+        None => common::make_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No execution round has completed yet.",
+        ),
+    }
+}
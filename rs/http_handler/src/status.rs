@@ -2,20 +2,23 @@
 use crate::common;
 use hyper::{Body, Response};
 use ic_config::http_handler::Config;
-use ic_interfaces::state_manager::StateReader;
+use ic_interfaces::{registry::RegistryClient, state_manager::StateReader};
 use ic_logger::{trace, warn, ReplicaLogger};
+use ic_registry_client::helper::node::NodeRegistry;
 use ic_replicated_state::ReplicatedState;
 use ic_types::{
-    messages::{Blob, HttpStatusResponse, ReplicaHealthStatus},
+    messages::{Blob, HttpStatusResponse, NodeAttestationStatus, ReplicaHealthStatus},
     replica_version::REPLICA_BINARY_HASH,
-    ReplicaVersion, SubnetId,
+    NodeId, ReplicaVersion, SubnetId,
 };
 
 /// Handles a call to /api/v1/status
 pub(crate) fn handle(
     log: &ReplicaLogger,
     config: &Config,
+    node_id: NodeId,
     nns_subnet_id: SubnetId,
+    registry_client: &dyn RegistryClient,
     state_reader: &dyn StateReader<State = ReplicatedState>,
     replica_health_status: ReplicaHealthStatus,
 ) -> Response<Body> {
@@ -58,6 +61,24 @@ pub(crate) fn handle(
         impl_version: Some(ReplicaVersion::default().to_string()),
         impl_hash: REPLICA_BINARY_HASH.get().map(|s| s.to_string()),
         replica_health_status: Some(replica_health_status),
+        attestation_status: attestation_status(registry_client, node_id),
     };
     common::cbor_response(&response)
 }
+
+/// Returns whether the node record of this node in the latest registry
+/// version carries an attestation report, or `None` if there is no record.
+fn attestation_status(
+    registry_client: &dyn RegistryClient,
+    node_id: NodeId,
+) -> Option<NodeAttestationStatus> {
+    let node_record = registry_client
+        .get_transport_info(node_id, registry_client.get_latest_version())
+        .ok()
+        .flatten()?;
+    Some(if node_record.attestation_report.is_empty() {
+        NodeAttestationStatus::NotAttested
+    } else {
+        NodeAttestationStatus::Attested
+    })
+}
@@ -191,7 +191,11 @@ pub(crate) async fn handle(
     let ingress_log_entry = msg.log_entry();
     // TODO: remove the spawn blocking once the ingress sender API allows
     // non-blocking op.
-    match tokio::task::spawn_blocking(move || ingress_sender.on_ingress_message(msg)).await {
+    match metrics
+        .executor_monitor
+        .spawn_blocking(move || ingress_sender.on_ingress_message(msg))
+        .await
+    {
         Err(err) => {
             metrics.observe_internal_error(
                 &RequestType::Submit,
@@ -61,6 +61,8 @@ pub(crate) enum RequestType {
     CatchUpPackage,
     /// A request for backup artifacts of the given height
     Artifacts(u64),
+    /// A request for the summary of the latest execution round
+    RoundState,
 }
 
 impl RequestType {
@@ -75,6 +77,7 @@ impl RequestType {
             Dashboard => "dashboard",
             CatchUpPackage => "catch-up-package",
             Artifacts(_) => "artifacts",
+            RoundState => "round_state",
         }
     }
 }
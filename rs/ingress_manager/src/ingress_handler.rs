@@ -8,7 +8,7 @@ use ic_interfaces::{
         ChangeSet, IngressPool,
     },
 };
-use ic_logger::{debug, warn};
+use ic_logger::{debug, in_message_context, warn, MessageContext};
 use ic_types::{
     artifact::{IngressMessageAttribute, IngressMessageId},
     ingress::{IngressStatus, MAX_INGRESS_TTL},
@@ -88,13 +88,18 @@ impl IngressHandler for IngressManager {
 
             // Check signatures, remove from unvalidated if they can't be
             // verified, add to validated otherwise.
-            if let Err(err) = validate_request(
-                ingress_message.as_ref(),
-                self.ingress_signature_crypto.as_ref(),
-                current_time,
-                registry_version,
-                &self.malicious_flags,
-            ) {
+            let context = MessageContext::new()
+                .with_message_id(ingress_object.message_id.clone())
+                .with_canister_id(ingress_message.canister_id());
+            if let Err(err) = in_message_context(&context, || {
+                validate_request(
+                    ingress_message.as_ref(),
+                    self.ingress_signature_crypto.as_ref(),
+                    current_time,
+                    registry_version,
+                    &self.malicious_flags,
+                )
+            }) {
                 debug!(
                     self.log,
                     "ingress_message_remove_unvalidated";
@@ -12,7 +12,7 @@ use ic_interfaces::{
     ingress_pool::{IngressPoolSelect, SelectResult},
     validation::{ValidationError, ValidationResult},
 };
-use ic_logger::{error, warn};
+use ic_logger::{error, in_message_context, warn, MessageContext};
 use ic_registry_client::helper::subnet::IngressMessageSettings;
 use ic_replicated_state::ReplicatedState;
 use ic_types::{
@@ -304,13 +304,18 @@ impl IngressManager {
 
         // Do not include the message if it is considered invalid with
         // respect to the given context (expiry & registry_version).
-        if let Err(err) = validate_request(
-            signed_ingress.as_ref(),
-            self.ingress_signature_crypto.as_ref(),
-            context.time,
-            context.registry_version,
-            &self.malicious_flags,
-        ) {
+        let message_context = MessageContext::new()
+            .with_message_id(MessageId::from(&ingress_id))
+            .with_canister_id(signed_ingress.canister_id());
+        if let Err(err) = in_message_context(&message_context, || {
+            validate_request(
+                signed_ingress.as_ref(),
+                self.ingress_signature_crypto.as_ref(),
+                context.time,
+                context.registry_version,
+                &self.malicious_flags,
+            )
+        }) {
             let message_id = MessageId::from(&ingress_id);
             return Err(ValidationError::Permanent(match err {
                 RequestValidationError::InvalidIngressExpiry(msg)
@@ -15,6 +15,7 @@ mod errors;
 mod dkg;
 
 pub use dkg::DkgAlgorithm;
+pub use sign::threshold_sig::domains::THRESHOLD_SIG_DOMAINS;
 pub use sign::threshold_sig::ni_dkg::{LoadTranscriptResult, NiDkgAlgorithm};
 
 mod sign;
@@ -26,6 +27,7 @@ pub use sign::CanisterSigVerifier;
 pub use sign::IngressSigVerifier;
 pub use sign::MultiSigVerifier;
 pub use sign::MultiSigner;
+pub use sign::ThresholdSigPublicKeyShares;
 pub use sign::ThresholdSigVerifier;
 pub use sign::ThresholdSigVerifierByPublicKey;
 pub use sign::ThresholdSigner;
@@ -79,6 +81,8 @@ pub trait Crypto:
     // RandomTape
     + ThresholdSigner<RandomTapeContent>
     + ThresholdSigVerifier<RandomTapeContent>
+    // Public key shares of threshold signers
+    + ThresholdSigPublicKeyShares
     // Traits for signing/verifying a MerkleRoot
     // (both Multi- and ThresholdSig) will be added at a later stage.
     //
@@ -123,5 +127,6 @@ impl<T> Crypto for T where
         + ThresholdSigVerifier<RandomBeaconContent>
         + ThresholdSigner<RandomTapeContent>
         + ThresholdSigVerifier<RandomTapeContent>
+        + ThresholdSigPublicKeyShares
 {
 }
@@ -16,7 +16,9 @@ impl ErrorReplication for CryptoError {
             // true, as the registry is guaranteed to be consistent across replicas
             CryptoError::PublicKeyNotFound { .. } | CryptoError::TlsCertNotFound { .. } => true,
             // panic, as during signature verification no secret keys are involved
-            CryptoError::SecretKeyNotFound { .. } | CryptoError::TlsSecretKeyNotFound { .. } => {
+            CryptoError::SecretKeyNotFound { .. }
+            | CryptoError::SecretKeyRetired { .. }
+            | CryptoError::TlsSecretKeyNotFound { .. } => {
                 panic!("Unexpected error {}, no secret keys involved", &self)
             }
             // true tentatively, but may change to panic! in the future:
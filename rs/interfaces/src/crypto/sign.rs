@@ -23,15 +23,18 @@
 //! Please refer to the trait documentation for details.
 
 use crate::crypto::hash::{
-    DOMAIN_BLOCK, DOMAIN_CATCH_UP_CONTENT, DOMAIN_CERTIFICATION_CONTENT, DOMAIN_DEALING_CONTENT,
-    DOMAIN_FINALIZATION_CONTENT, DOMAIN_NOTARIZATION_CONTENT, DOMAIN_RANDOM_BEACON_CONTENT,
-    DOMAIN_RANDOM_TAPE_CONTENT,
+    DOMAIN_BLOCK, DOMAIN_DEALING_CONTENT, DOMAIN_FINALIZATION_CONTENT, DOMAIN_NOTARIZATION_CONTENT,
+};
+use crate::crypto::sign::threshold_sig::domains::{
+    THRESHOLD_SIG_DOMAIN_CATCH_UP, THRESHOLD_SIG_DOMAIN_CERTIFICATION,
+    THRESHOLD_SIG_DOMAIN_RANDOM_BEACON, THRESHOLD_SIG_DOMAIN_RANDOM_TAPE,
 };
 use ic_types::crypto::{
     BasicSigOf, CanisterSigOf, CombinedMultiSigOf, CryptoResult, IndividualMultiSigOf,
     SignedBytesWithoutDomainSeparator, UserPublicKey,
 };
 use ic_types::messages::{Delegation, MessageId, WebAuthnEnvelope};
+use ic_types::node_rewards::NodeHeartbeatContent;
 use ic_types::{
     consensus::{
         certification::CertificationContent, dkg::DealingContent, Block, CatchUpContent,
@@ -45,12 +48,16 @@ use std::convert::TryFrom;
 
 pub mod threshold_sig;
 
-pub use threshold_sig::{ThresholdSigVerifier, ThresholdSigVerifierByPublicKey, ThresholdSigner};
+pub use threshold_sig::{
+    ThresholdSigPublicKeyShares, ThresholdSigVerifier, ThresholdSigVerifierByPublicKey,
+    ThresholdSigner,
+};
 
 pub mod canister_threshold_sign;
 
 const SIG_DOMAIN_IC_REQUEST_AUTH_DELEGATION: &str = "ic-request-auth-delegation";
 const SIG_DOMAIN_IC_REQUEST: &str = "ic-request";
+const SIG_DOMAIN_NODE_HEARTBEAT: &str = "ic-node-heartbeat";
 
 /// `Signable` represents an object whose byte-vector representation
 /// can be signed using a digital signature scheme.
@@ -98,6 +105,7 @@ mod private {
     impl SignatureDomainSeal for CatchUpContentProtobufBytes {}
     impl SignatureDomainSeal for RandomBeaconContent {}
     impl SignatureDomainSeal for RandomTapeContent {}
+    impl SignatureDomainSeal for NodeHeartbeatContent {}
     impl SignatureDomainSeal for SignableMock {}
 }
 
@@ -146,13 +154,13 @@ impl SignatureDomain for MessageId {
 
 impl SignatureDomain for CertificationContent {
     fn domain(&self) -> Vec<u8> {
-        domain_with_prepended_length(DOMAIN_CERTIFICATION_CONTENT)
+        domain_with_prepended_length(THRESHOLD_SIG_DOMAIN_CERTIFICATION)
     }
 }
 
 impl SignatureDomain for CatchUpContent {
     fn domain(&self) -> Vec<u8> {
-        domain_with_prepended_length(DOMAIN_CATCH_UP_CONTENT)
+        domain_with_prepended_length(THRESHOLD_SIG_DOMAIN_CATCH_UP)
     }
 }
 
@@ -161,19 +169,25 @@ impl SignatureDomain for CatchUpContent {
 // necessarily needing to deserialize them into CatchUpContent.
 impl SignatureDomain for CatchUpContentProtobufBytes {
     fn domain(&self) -> Vec<u8> {
-        domain_with_prepended_length(DOMAIN_CATCH_UP_CONTENT)
+        domain_with_prepended_length(THRESHOLD_SIG_DOMAIN_CATCH_UP)
     }
 }
 
 impl SignatureDomain for RandomBeaconContent {
     fn domain(&self) -> Vec<u8> {
-        domain_with_prepended_length(DOMAIN_RANDOM_BEACON_CONTENT)
+        domain_with_prepended_length(THRESHOLD_SIG_DOMAIN_RANDOM_BEACON)
     }
 }
 
 impl SignatureDomain for RandomTapeContent {
     fn domain(&self) -> Vec<u8> {
-        domain_with_prepended_length(DOMAIN_RANDOM_TAPE_CONTENT)
+        domain_with_prepended_length(THRESHOLD_SIG_DOMAIN_RANDOM_TAPE)
+    }
+}
+
+impl SignatureDomain for NodeHeartbeatContent {
+    fn domain(&self) -> Vec<u8> {
+        domain_with_prepended_length(SIG_DOMAIN_NODE_HEARTBEAT)
     }
 }
 
@@ -190,7 +204,7 @@ impl SignatureDomain for RandomTapeContent {
 //         domain_with_prepended_length(SOME_DOMAIN)
 //     }
 // }
-fn domain_with_prepended_length(domain: &str) -> Vec<u8> {
+pub(crate) fn domain_with_prepended_length(domain: &str) -> Vec<u8> {
     let domain_len = u8::try_from(domain.len()).expect("domain too long");
     let mut ret = vec![domain_len];
     ret.extend(domain.as_bytes());
@@ -219,6 +233,15 @@ impl SignableMock {
             signed_bytes_without_domain,
         }
     }
+
+    /// Creates a mock signed in the given `domain`, e.g., to check that
+    /// signatures do not verify across domains.
+    pub fn new_in_domain(domain: &str, signed_bytes_without_domain: Vec<u8>) -> Self {
+        Self {
+            domain: domain_with_prepended_length(domain),
+            signed_bytes_without_domain,
+        }
+    }
 }
 
 impl SignatureDomain for SignableMock {
@@ -1,10 +1,12 @@
 use crate::crypto::Signable;
 use ic_base_types::{NodeId, SubnetId};
 use ic_types::crypto::threshold_sig::ni_dkg::DkgId;
+use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
 use ic_types::crypto::{CombinedThresholdSigOf, CryptoResult, ThresholdSigShareOf};
 use ic_types::RegistryVersion;
 use std::collections::BTreeMap;
 
+pub mod domains;
 pub mod ni_dkg;
 
 /// A Crypto Component interface to create threshold signature shares.
@@ -25,6 +27,10 @@ pub trait ThresholdSigner<T: Signable> {
     ///   calling this method.
     /// * `CryptoError::SecretKeyNotFound` if the secret key is not present in
     ///   the secret key store.
+    /// * `CryptoError::SecretKeyRetired` if the secret key was recently
+    ///   removed from the secret key store because the transcript for `dkg_id`
+    ///   is no longer active. This is expected when signing races with the
+    ///   removal of keys at an epoch boundary, and is not a sign of key loss.
     // TODO (CRP-479): switch to Result<ThresholdSigShareOf<T>,
     // ThresholdSigDataNotFoundError>
     fn sign_threshold(&self, message: &T, dkg_id: DkgId) -> CryptoResult<ThresholdSigShareOf<T>>;
@@ -112,6 +118,37 @@ pub trait ThresholdSigVerifier<T: Signable> {
     ) -> CryptoResult<()>;
 }
 
+/// A Crypto Component interface to look up the public key shares of threshold
+/// signers.
+///
+/// The public key shares are derived from the public coefficients of a loaded
+/// DKG transcript and cached by the crypto component, so looking up a share is
+/// cheap once it was computed (e.g., when verifying a signature share). The
+/// same preconditions as for `ThresholdSigVerifier` apply.
+pub trait ThresholdSigPublicKeyShares {
+    /// Returns the public key share (also called individual public key) of
+    /// `signer` for the DKG instance with ID `dkg_id`. This is the key with
+    /// which the threshold signature shares of `signer` are verified.
+    ///
+    /// # Errors
+    /// * `CryptoError::ThresholdSigDataNotFound` if the threshold signature
+    ///   data store does not contain the transcript data for the `dkg_id`.
+    ///   This error indicates that `DkgAlgorithm::load_transcript` must be
+    ///   called prior to calling this method.
+    /// * `CryptoError::InvalidArgument` if the transcript data does not
+    ///   contain a node index for the given `signer`.
+    ///
+    /// # Panics
+    /// * This method panics if calculating the public key share fails, which
+    ///   would happen if the implementations of DKG and threshold signatures
+    ///   are not aligned.
+    fn threshold_sig_public_key_share(
+        &self,
+        dkg_id: DkgId,
+        signer: NodeId,
+    ) -> CryptoResult<ThresholdSigPublicKey>;
+}
+
 /// A Crypto Component interface to verify threshold signatures by a subnet's
 /// public key.
 pub trait ThresholdSigVerifierByPublicKey<T: Signable> {
@@ -0,0 +1,103 @@
+//! Domain separators of messages signed with threshold signatures.
+//!
+//! All messages that a subnet signs with `ThresholdSigner::sign_threshold` are
+//! signed with keys derived from the same NI-DKG transcripts. The domain
+//! separator is therefore the only thing that prevents a threshold signature
+//! on one kind of message, e.g., a random tape, from being accepted as a
+//! signature on another kind of message, e.g., a random beacon. To rule this
+//! out, all such domain separators are listed here and checked for uniqueness
+//! at compile time.
+//!
+//! Note that `CatchUpContentProtobufBytes` intentionally uses the domain of
+//! `CatchUpContent`, as it is the protobuf encoding of the same content.
+use crate::crypto::hash::{
+    DOMAIN_CATCH_UP_CONTENT, DOMAIN_CERTIFICATION_CONTENT, DOMAIN_RANDOM_BEACON_CONTENT,
+    DOMAIN_RANDOM_TAPE_CONTENT,
+};
+
+/// The domain separator of `RandomBeaconContent`.
+pub const THRESHOLD_SIG_DOMAIN_RANDOM_BEACON: &str = DOMAIN_RANDOM_BEACON_CONTENT;
+/// The domain separator of `RandomTapeContent`.
+pub const THRESHOLD_SIG_DOMAIN_RANDOM_TAPE: &str = DOMAIN_RANDOM_TAPE_CONTENT;
+/// The domain separator of `CertificationContent`.
+pub const THRESHOLD_SIG_DOMAIN_CERTIFICATION: &str = DOMAIN_CERTIFICATION_CONTENT;
+/// The domain separator of `CatchUpContent` and `CatchUpContentProtobufBytes`.
+pub const THRESHOLD_SIG_DOMAIN_CATCH_UP: &str = DOMAIN_CATCH_UP_CONTENT;
+
+/// All domain separators used with threshold signatures.
+///
+/// A new kind of threshold-signed message must add its domain separator here.
+pub const THRESHOLD_SIG_DOMAINS: [&str; 4] = [
+    THRESHOLD_SIG_DOMAIN_RANDOM_BEACON,
+    THRESHOLD_SIG_DOMAIN_RANDOM_TAPE,
+    THRESHOLD_SIG_DOMAIN_CERTIFICATION,
+    THRESHOLD_SIG_DOMAIN_CATCH_UP,
+];
+
+// Fails to compile (by underflowing the array length) if two threshold
+// signature domains are equal.
+const _: [(); 0 - !all_distinct(&THRESHOLD_SIG_DOMAINS) as usize] = [];
+
+/// Returns whether all `domains` are pairwise distinct.
+const fn all_distinct(domains: &[&str]) -> bool {
+    let mut i = 0;
+    while i < domains.len() {
+        let mut j = i + 1;
+        while j < domains.len() {
+            if str_eq(domains[i], domains[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign::{domain_with_prepended_length, SignatureDomain};
+    use ic_types::consensus::certification::CertificationContent;
+    use ic_types::consensus::{RandomBeaconContent, RandomTapeContent};
+    use ic_types::crypto::{CryptoHash, CryptoHashOf};
+    use ic_types::Height;
+
+    #[test]
+    fn should_detect_equal_domains() {
+        assert!(all_distinct(&["a", "ab", "b"]));
+        assert!(!all_distinct(&["a", "ab", "ab"]));
+        assert!(all_distinct(&[]));
+    }
+
+    #[test]
+    fn should_use_registered_domains_for_threshold_signed_messages() {
+        let registered: Vec<_> = THRESHOLD_SIG_DOMAINS
+            .iter()
+            .map(|domain| domain_with_prepended_length(domain))
+            .collect();
+        let beacon =
+            RandomBeaconContent::new(Height::from(1), CryptoHashOf::from(CryptoHash(vec![])));
+        let tape = RandomTapeContent::new(Height::from(1));
+        let certification = CertificationContent::new(CryptoHashOf::from(CryptoHash(vec![])));
+
+        assert!(registered.contains(&beacon.domain()));
+        assert!(registered.contains(&tape.domain()));
+        assert!(registered.contains(&certification.domain()));
+    }
+}
@@ -86,6 +86,11 @@ pub struct ExecutionParameters {
     pub canister_memory_limit: NumBytes,
     pub subnet_available_memory: SubnetAvailableMemory,
     pub compute_allocation: ComputeAllocation,
+    pub canister_message_memory_capacity: NumBytes,
+    /// The root key exposed to canisters via `ic0.root_key_copy`, i.e. the
+    /// public key of the NNS subnet in the network topology of the state that
+    /// is executed on.
+    pub root_key: Vec<u8>,
 }
 
 /// The data structure returned by
@@ -105,6 +110,37 @@ pub struct ExecuteMessageResult<CanisterState> {
 
 pub type HypervisorResult<T> = Result<T, HypervisorError>;
 
+/// Errors returned by [`QueryHandler::query_latest_certified_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryExecutionError {
+    /// The query was executed and failed, or was rejected before execution.
+    UserError(UserError),
+    /// The query cannot be answered from the state that this replica has
+    /// certified, e.g., because no state is certified yet or because the
+    /// target canister does not exist in the certified state. It is expected
+    /// to succeed against the certified state at `height`, either on this
+    /// replica later on or on another replica of the subnet.
+    RetryAtHeight { height: Height, description: String },
+}
+
+impl From<UserError> for QueryExecutionError {
+    fn from(err: UserError) -> Self {
+        Self::UserError(err)
+    }
+}
+
+impl std::fmt::Display for QueryExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UserError(err) => write!(f, "{}", err),
+            Self::RetryAtHeight {
+                height,
+                description,
+            } => write!(f, "{} Retry at height {}.", description, height),
+        }
+    }
+}
+
 /// Interface for the component to execute queries on canisters.  It can be used
 /// by the HttpHandler and other system components to execute queries.
 pub trait QueryHandler: Send + Sync {
@@ -127,12 +163,39 @@ pub trait QueryHandler: Send + Sync {
     // The callee must call the callback with the appropriate result when the
     // computation has finished. The callback can be called inlined (immediately)
     // before the function returns. The callback should not block the thread.
+    // If the certified state lags behind the state the query needs, the
+    // callback receives `QueryExecutionError::RetryAtHeight`.
     fn query_latest_certified_state(
         &self,
         query: UserQuery,
         certificate_delegation: Option<CertificateDelegation>,
-        callback: Box<dyn FnOnce(Result<WasmResult, UserError>) + Send + 'static>,
+        callback: Box<dyn FnOnce(Result<WasmResult, QueryExecutionError>) + Send + 'static>,
     );
+
+    /// Estimates the cost of sending `query` to the canister as an ingress
+    /// message, by executing it against `state` without persisting any of its
+    /// effects.
+    ///
+    /// Returns an error if the message cannot be executed at all, e.g. if the
+    /// canister or the method does not exist. Traps and rejects still yield an
+    /// estimate, as the execution is charged for regardless.
+    fn estimate_execution_cost(
+        &self,
+        query: UserQuery,
+        state: Arc<Self::State>,
+    ) -> Result<ExecutionCostEstimate, UserError>;
+}
+
+/// The estimated cost of executing a message, as returned by
+/// `QueryHandler::estimate_execution_cost`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecutionCostEstimate {
+    /// The number of instructions executed.
+    pub instructions_used: NumInstructions,
+    /// The cycles charged to the canister for the execution.
+    pub execution_cycles: Cycles,
+    /// The cycles charged to the canister for inducting the message.
+    pub ingress_induction_cycles: Cycles,
 }
 
 /// Interface for the component to filter out ingress messages that
@@ -186,6 +249,14 @@ pub trait IngressHistoryReader: Send + Sync {
     ) -> Result<Box<dyn Fn(&MessageId) -> IngressStatus>, IngressHistoryError>;
 }
 
+/// Interface for reading a summary of the latest execution round, for
+/// debugging live incidents.
+pub trait RoundStateDumpReader: Send + Sync {
+    /// Returns the summary of the latest round encoded as JSON, or `None` if
+    /// no round has been executed yet.
+    fn latest_round_state_dump(&self) -> Option<String>;
+}
+
 /// Interface for updating the history of ingress messages.
 pub trait IngressHistoryWriter: Send + Sync {
     /// Type of state this Writer can update.
@@ -244,6 +315,18 @@ pub trait SystemApi {
         heap: &mut [u8],
     ) -> HypervisorResult<()>;
 
+    /// Copies the next at most `size` bytes of msg.payload to
+    /// memory[dst..dst+size] and returns the number of bytes copied. Every
+    /// call continues where the previous one stopped, so that a canister can
+    /// process a large payload in chunks instead of copying it into its heap
+    /// as a whole. Returns 0 once the whole payload was read.
+    fn ic0_msg_arg_data_next_chunk(
+        &mut self,
+        dst: u32,
+        size: u32,
+        heap: &mut [u8],
+    ) -> HypervisorResult<u32>;
+
     /// Used to look up the size of the method_name that the message wants to
     /// call. Can only be called in the context of inspecting messages.
     fn ic0_msg_method_name_size(&self) -> HypervisorResult<u32>;
@@ -430,6 +513,20 @@ pub trait SystemApi {
     /// `ic0.call_*` calls trap.
     fn ic0_call_perform(&mut self) -> HypervisorResult<i32>;
 
+    /// Returns how many more calls to the canister specified by
+    /// callee_src/callee_size can be enqueued with `ic0.call_perform` before
+    /// the queues to the callee are full. This allows canisters to apply
+    /// backpressure instead of finding out from failing `ic0.call_perform`s.
+    ///
+    /// Other limits, e.g., on the canister's cycles balance, are not taken
+    /// into account.
+    fn ic0_call_queue_available(
+        &self,
+        callee_src: u32,
+        callee_size: u32,
+        heap: &[u8],
+    ) -> HypervisorResult<i32>;
+
     /// Returns the current size of the stable memory in WebAssembly pages.
     fn ic0_stable_size(&self) -> HypervisorResult<u32>;
 
@@ -515,8 +612,13 @@ pub trait SystemApi {
     fn out_of_instructions(&self) -> HypervisorError;
 
     /// This system call is not part of the public spec. It's called after a
-    /// native `memory.grow` has been called to check whether there's enough
-    /// available memory left.
+    /// native `memory.grow` has been called to reserve the grown pages from
+    /// the available memory of the canister and the subnet. Like
+    /// `ic0.stable_grow`, growing is only charged the fixed instruction cost of
+    /// the call, not per grown byte.
+    ///
+    /// Returns `native_memory_grow_res` if the grow succeeded and the pages
+    /// could be reserved, and an `OutOfMemory` error if they could not be.
     fn update_available_memory(
         &mut self,
         native_memory_grow_res: i32,
@@ -532,7 +634,10 @@ pub trait SystemApi {
     fn ic0_canister_cycle_balance(&self) -> HypervisorResult<u64>;
 
     /// Returns the current balance in cycles.
-    fn ic0_canister_cycles_balance128(&self) -> HypervisorResult<(u64, u64)>;
+    ///
+    /// The amount is split into its high and low 64 bits only when it is
+    /// returned to the canister.
+    fn ic0_canister_cycles_balance128(&self) -> HypervisorResult<Cycles>;
 
     /// (deprecated) Please use `ic0_msg_cycles_available128` instead.
     /// This API supports only 64-bit values.
@@ -543,7 +648,10 @@ pub trait SystemApi {
     fn ic0_msg_cycles_available(&self) -> HypervisorResult<u64>;
 
     /// Cycles sent in the current call and still available.
-    fn ic0_msg_cycles_available128(&self) -> HypervisorResult<(u64, u64)>;
+    ///
+    /// Always zero in queries and when inspecting ingress messages, which
+    /// carry no cycles.
+    fn ic0_msg_cycles_available128(&self) -> HypervisorResult<Cycles>;
 
     /// (deprecated) Please use `ic0_msg_cycles_refunded128` instead.
     /// This API supports only 64-bit values.
@@ -554,7 +662,9 @@ pub trait SystemApi {
     fn ic0_msg_cycles_refunded(&self) -> HypervisorResult<u64>;
 
     /// Cycles that came back with the response, as a refund.
-    fn ic0_msg_cycles_refunded128(&self) -> HypervisorResult<(u64, u64)>;
+    ///
+    /// Always zero in queries and when inspecting ingress messages.
+    fn ic0_msg_cycles_refunded128(&self) -> HypervisorResult<Cycles>;
 
     /// (deprecated) Please use `ic0_msg_cycles_accept128` instead.
     /// This API supports only 64-bit values.
@@ -597,7 +707,7 @@ pub trait SystemApi {
     /// EXE-117: the last point is not properly handled yet.  In particular, a
     /// refund can come back to the canister after this call finishes which
     /// causes the canister's balance to overflow.
-    fn ic0_msg_cycles_accept128(&mut self, max_amount: Cycles) -> HypervisorResult<(u64, u64)>;
+    fn ic0_msg_cycles_accept128(&mut self, max_amount: Cycles) -> HypervisorResult<Cycles>;
 
     /// Sets the certified data for the canister.
     /// See: https://sdk.dfinity.org/docs/interface-spec/index.html#system-api-certified-data
@@ -625,6 +735,19 @@ pub trait SystemApi {
         heap: &mut [u8],
     ) -> HypervisorResult<()>;
 
+    /// Returns the size of the DER-encoded public key of the NNS subnet,
+    /// which is the root of trust of all certificates issued by the IC.
+    fn ic0_root_key_size(&self) -> HypervisorResult<i32>;
+
+    /// Copies the DER-encoded public key of the NNS subnet into the heap.
+    fn ic0_root_key_copy(
+        &self,
+        dst: u32,
+        offset: u32,
+        size: u32,
+        heap: &mut [u8],
+    ) -> HypervisorResult<()>;
+
     /// Returns the current status of the canister.  `1` indicates
     /// running, `2` indicates stopping, and `3` indicates stopped.
     fn ic0_canister_status(&self) -> HypervisorResult<u32>;
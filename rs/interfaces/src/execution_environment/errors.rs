@@ -9,7 +9,6 @@ use serde::{Deserialize, Serialize};
 /// Various traps that a canister can create.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum TrapCode {
-    StackOverflow,
     HeapOutOfBounds,
     StableMemoryOutOfBounds,
     StableMemoryTooBigFor32Bit,
@@ -23,7 +22,6 @@ pub enum TrapCode {
 impl std::fmt::Display for TrapCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::StackOverflow => write!(f, "stack overflow"),
             Self::HeapOutOfBounds => write!(f, "heap out of bounds"),
             Self::StableMemoryOutOfBounds => write!(f, "stable memory out of bounds"),
             Self::StableMemoryTooBigFor32Bit => write!(
@@ -107,6 +105,9 @@ pub enum HypervisorError {
     /// Canister Wasm trapped (e.g. by executing the `unreachable`
     /// instruction or dividing by zero).
     Trapped(TrapCode),
+    /// Canister Wasm exhausted its native stack, e.g. due to unbounded
+    /// recursion.
+    StackOverflow,
     /// Canister explicitly called `ic.trap`.
     CalledTrap(String),
     /// An attempt was made to execute a message on a canister that does not
@@ -236,6 +237,13 @@ impl HypervisorError {
                 E::CanisterTrapped,
                 format!("Canister {} trapped: {}", canister_id, code),
             ),
+            Self::StackOverflow => UserError::new(
+                E::CanisterTrapped,
+                format!(
+                    "Canister {} trapped: stack overflow. The canister exhausted its stack, e.g. due to unbounded recursion",
+                    canister_id
+                ),
+            ),
             Self::CalledTrap(msg) => UserError::new(
                 E::CanisterCalledTrap,
                 format!("Canister {} trapped explicitly: {}", canister_id, msg),
@@ -314,6 +322,7 @@ impl HypervisorError {
             HypervisorError::InvalidWasm(_) => "InvalidWasm",
             HypervisorError::InstrumentationFailed(_) => "InstrumentationFailed",
             HypervisorError::Trapped(_) => "Trapped",
+            HypervisorError::StackOverflow => "StackOverflow",
             HypervisorError::CalledTrap(_) => "CalledTrap",
             HypervisorError::WasmModuleNotFound => "WasmModuleNotFound",
             HypervisorError::OutOfMemory => "OutOfMemory",
@@ -75,6 +75,10 @@ impl MemoryArea {
 pub enum DirtyPageTracking {
     Ignore,
     Track,
+    /// Dirty pages are recorded by write barriers inserted into the Wasm code
+    /// instead of the signal handler, so the memory tracker maps pages as
+    /// read/write right away, as with `Ignore`.
+    Barrier,
 }
 
 /// Specifies whether the memory access that caused the signal was a read access
@@ -276,6 +280,9 @@ impl SigsegvMemoryTracker {
             PersistenceType::Pagemap => {
                 unsafe { mprotect(addr, size, ProtFlags::PROT_NONE)? };
             }
+            PersistenceType::InMemory => {
+                unreachable!("In-memory persistence does not track memory accesses")
+            }
         }
 
         Ok(tracker)
@@ -299,6 +306,9 @@ impl SigsegvMemoryTracker {
             PersistenceType::Pagemap => {
                 sigsegv_fault_handler_old(self, &self.page_map, fault_address)
             }
+            PersistenceType::InMemory => {
+                unreachable!("In-memory persistence does not track memory accesses")
+            }
         }
     }
 
@@ -566,7 +576,7 @@ pub fn sigsegv_fault_handler_new(
     let mut accessed_bitmap = tracker.accessed_bitmap.borrow_mut();
 
     match (access_kind, tracker.dirty_page_tracking) {
-        (_, DirtyPageTracking::Ignore) => {
+        (_, DirtyPageTracking::Ignore) | (_, DirtyPageTracking::Barrier) => {
             // We don't care about dirty pages here, so we can set up the page mapping for
             // for multiple pages as read/write right away.
             let prefetch_range = range_from_count(faulting_page, MAX_PAGES_TO_MAP);
@@ -43,6 +43,7 @@ struct CertifiedSlicePoolMetrics {
     take_messages: Histogram,
     take_gced_messages: Histogram,
     take_size_bytes: Histogram,
+    take_size_estimate_error: Histogram,
 }
 
 pub const METRIC_POOL_SIZE_BYTES: &str = "xnet_pool_size_bytes";
@@ -50,6 +51,7 @@ pub const METRIC_TAKE_COUNT: &str = "xnet_pool_take_count";
 pub const METRIC_TAKE_MESSAGES: &str = "xnet_pool_take_messages";
 pub const METRIC_TAKE_SIZE_BYTES: &str = "xnet_pool_take_size_bytes";
 pub const METRIC_TAKE_GCED_MESSAGES: &str = "xnet_pool_take_gced_messages";
+pub const METRIC_TAKE_SIZE_ESTIMATE_ERROR: &str = "xnet_pool_take_size_estimate_error_ratio";
 
 pub const LABEL_STATUS: &str = "status";
 
@@ -86,6 +88,12 @@ impl CertifiedSlicePoolMetrics {
                 // 100 B - 5 MB
                 decimal_buckets(2, 6)
             ),
+            take_size_estimate_error: metrics_registry.histogram(
+                METRIC_TAKE_SIZE_ESTIMATE_ERROR,
+                "Relative error of the estimated byte size of slices returned by successful XNet slice pool takes, compared to their encoded size.",
+                // -50% - +50%
+                vec![-0.5, -0.2, -0.1, -0.05, -0.02, -0.01, 0.0, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5]
+            ),
         }
     }
 
@@ -109,6 +117,16 @@ impl CertifiedSlicePoolMetrics {
     fn observe_take_size_bytes(&self, size_bytes: usize) {
         self.take_size_bytes.observe(size_bytes as f64);
     }
+
+    /// Observes the relative error of the estimated byte size of a slice
+    /// returned by a successful pool take, compared to its encoded size.
+    fn observe_take_size_estimate(&self, estimated_bytes: usize, encoded_bytes: usize) {
+        if encoded_bytes == 0 {
+            return;
+        }
+        self.take_size_estimate_error
+            .observe((estimated_bytes as f64 - encoded_bytes as f64) / encoded_bytes as f64);
+    }
 }
 
 use InvalidAppend::*;
@@ -169,7 +187,7 @@ mod messages {
     use super::*;
 
     /// Wrapper around slice messages plus transient metadata.
-    #[derive(Debug, PartialEq)]
+    #[derive(Clone, Debug, PartialEq)]
     pub(super) struct Messages {
         /// Slice messages.
         ///
@@ -333,7 +351,7 @@ mod messages {
 }
 
 /// Unpacked `CertifiedStreamSlice::payload`, plus transient metadata.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 struct Payload {
     /// The intended destination subnet of this stream slice.
     subnet_id: Label,
@@ -718,7 +736,7 @@ impl From<Payload> for Vec<u8> {
 /// An unpacked `CertifiedStreamSlice`: a slice of the stream of messages
 /// produced by a subnet together with a cryptographic proof that the majority
 /// of that subnet agrees on it.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct UnpackedStreamSlice {
     /// Stream slice contents.
     payload: Payload,
@@ -787,6 +805,49 @@ impl UnpackedStreamSlice {
         }
     }
 
+    /// Takes a page of the slice, i.e. a packed prefix that meets the given
+    /// limits, with `byte_limit` applying to the actual encoded size of the
+    /// page rather than to its estimated size. Returns the page (if one can be
+    /// created) and the remaining slice (if any).
+    ///
+    /// `take_prefix()` relies on size estimates, so whenever the packed prefix
+    /// turns out larger than `byte_limit`, a shorter prefix is taken from the
+    /// original slice instead, with the limit lowered by the excess.
+    ///
+    /// Returns `Err(InvalidPayload)` or `Err(WitnessPruningFailed)` if
+    /// `self.payload` is malformed.
+    fn take_page(
+        self,
+        msg_limit: Option<usize>,
+        byte_limit: Option<usize>,
+    ) -> CertifiedSliceResult<(Option<SlicePage>, Option<Self>)> {
+        let byte_limit = match byte_limit {
+            Some(byte_limit) => byte_limit,
+            None => {
+                let (prefix, postfix) = self.take_prefix(msg_limit, None)?;
+                return Ok((prefix.map(SlicePage::from), postfix));
+            }
+        };
+
+        let mut estimate_limit = byte_limit;
+        loop {
+            let (prefix, postfix) = self.clone().take_prefix(msg_limit, Some(estimate_limit))?;
+            let page = match prefix {
+                Some(prefix) => SlicePage::from(prefix),
+                None => return Ok((None, postfix)),
+            };
+
+            if page.encoded_bytes <= byte_limit {
+                return Ok((Some(page), postfix));
+            }
+            if page.message_count == 0 {
+                // Not even a header-only slice fits.
+                return Ok((None, Some(self)));
+            }
+            estimate_limit = estimate_limit.saturating_sub(page.encoded_bytes - byte_limit);
+        }
+    }
+
     /// Garbage collects the slice: drops all messages before
     /// `cutoff.message_index` and updates the witness. If all messages were
     /// dropped and `cutoff.signal_index` is beyond `signals_end`, the slice is
@@ -882,6 +943,35 @@ impl CountBytes for UnpackedStreamSlice {
     }
 }
 
+/// A packed prefix taken from an `UnpackedStreamSlice`, see
+/// `UnpackedStreamSlice::take_page()`.
+struct SlicePage {
+    slice: CertifiedStreamSlice,
+    message_count: usize,
+
+    /// The estimated byte size of the page, as per `count_bytes()`.
+    estimated_bytes: usize,
+
+    /// The actual byte size of the page, as per
+    /// `certified_slice_encoded_bytes()`.
+    encoded_bytes: usize,
+}
+
+impl From<UnpackedStreamSlice> for SlicePage {
+    fn from(unpacked: UnpackedStreamSlice) -> Self {
+        let message_count = unpacked.payload.len();
+        let estimated_bytes = unpacked.count_bytes();
+        let slice = unpacked.pack();
+        let encoded_bytes = certified_slice_encoded_bytes(&slice);
+        Self {
+            slice,
+            message_count,
+            estimated_bytes,
+            encoded_bytes,
+        }
+    }
+}
+
 /// Returns a deterministic byte size estimate for the provided certified slice,
 /// the exact same as `UnpackedStreamSlice::try_from(packed)?.count_bytes()`. Or
 /// an error, if the payload cannot be unpacked.
@@ -896,6 +986,13 @@ pub fn certified_slice_count_bytes(packed: &CertifiedStreamSlice) -> CertifiedSl
     ))
 }
 
+/// Returns the actual byte size of the provided certified slice, i.e. the
+/// size of its encoded payload and witness plus the size of its
+/// certification. This is what `certified_slice_count_bytes()` estimates.
+pub fn certified_slice_encoded_bytes(packed: &CertifiedStreamSlice) -> usize {
+    packed.payload.len() + packed.merkle_proof.len() + packed.certification.count_bytes()
+}
+
 /// Common `CountBytes` implementation for `CertifiedStreamSlice` and
 /// `UnpackedStreamSlice`.
 ///
@@ -1054,6 +1151,8 @@ impl CertifiedSlicePool {
     /// Takes a sub-slice of the stream from `subnet_id` starting at `begin`,
     /// respecting the given message count and byte limits; or, if the provided
     /// `byte_limit` is too small for a header-only slice, returns `Ok(None)`).
+    /// `byte_limit` applies to the encoded size of the returned slice, which
+    /// is returned along with it.
     ///
     /// If all messages are taken, the slice is removed from the pool.
     ///
@@ -1069,12 +1168,13 @@ impl CertifiedSlicePool {
         byte_limit: Option<usize>,
     ) -> CertifiedSliceResult<Option<(CertifiedStreamSlice, usize)>> {
         match self.take_slice_impl(subnet_id, begin, msg_limit, byte_limit) {
-            Ok(Some(slice)) => {
-                let slice_count_bytes = slice.count_bytes();
+            Ok(Some(page)) => {
                 self.metrics.observe_take(STATUS_SUCCESS);
-                self.metrics.observe_take_message_count(slice.payload.len());
-                self.metrics.observe_take_size_bytes(slice_count_bytes);
-                Ok(Some((slice.pack(), slice_count_bytes)))
+                self.metrics.observe_take_message_count(page.message_count);
+                self.metrics.observe_take_size_bytes(page.encoded_bytes);
+                self.metrics
+                    .observe_take_size_estimate(page.estimated_bytes, page.encoded_bytes);
+                Ok(Some((page.slice, page.encoded_bytes)))
             }
             Ok(None) => {
                 self.metrics.observe_take(STATUS_NONE);
@@ -1090,7 +1190,7 @@ impl CertifiedSlicePool {
 
     /// Helper function to allow easy instrumentation of `take_slice` results.
     ///
-    /// On success, returns a prefix respecting the given limits.
+    /// On success, returns a page respecting the given limits.
     ///
     /// Returns `Err(InvalidPayload)` or `Err(WitnessPruningFailed)` if
     /// `self.payload` is malformed. Returns `Err(TakeBeforeSliceBegin)` if
@@ -1102,7 +1202,7 @@ impl CertifiedSlicePool {
         begin: Option<&ExpectedIndices>,
         msg_limit: Option<usize>,
         byte_limit: Option<usize>,
-    ) -> CertifiedSliceResult<Option<UnpackedStreamSlice>> {
+    ) -> CertifiedSliceResult<Option<SlicePage>> {
         // Update the stream position in case we bail out early with no slice returned.
         begin.map(|begin| self.stream_positions.insert(subnet_id, begin.clone()));
 
@@ -1131,26 +1231,24 @@ impl CertifiedSlicePool {
             }
         }
 
-        let mut prefix_message_count = slice.payload.len();
         self.metrics
-            .observe_take_messages_gced(original_message_count - prefix_message_count);
+            .observe_take_messages_gced(original_message_count - slice.payload.len());
         let signals_end = slice.payload.header.signals_end();
 
-        let (prefix, slice) = slice.take_prefix(msg_limit, byte_limit)?;
+        let (page, slice) = slice.take_page(msg_limit, byte_limit)?;
 
         // Put back the rest of the slice, if any.
         if let Some(slice) = slice {
-            prefix_message_count -= slice.payload.len();
             self.slices.insert(subnet_id, slice);
         }
 
-        if let Some(prefix) = prefix {
-            // A prefix is being returned, update stream position accordingly.
+        if let Some(page) = page {
+            // A page is being returned, update stream position accordingly.
             if let Some(stream_indices) = self.stream_positions.get_mut(&subnet_id) {
-                stream_indices.message_index += StreamIndex::from(prefix_message_count as u64);
+                stream_indices.message_index += StreamIndex::from(page.message_count as u64);
                 stream_indices.signal_index = stream_indices.signal_index.max(signals_end);
             }
-            Ok(Some(prefix))
+            Ok(Some(page))
         } else {
             Ok(None)
         }
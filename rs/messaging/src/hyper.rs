@@ -213,7 +213,10 @@ impl Accept for TlsAccept {
                         let future = async move {
                             tls.perform_tls_server_handshake(
                                 conn.into_inner(),
-                                AllowedClients::new(SomeOrAllNodes::All, HashSet::new()).unwrap(),
+                                Arc::new(
+                                    AllowedClients::new(SomeOrAllNodes::All, HashSet::new())
+                                        .unwrap(),
+                                ),
                                 registry_version,
                             )
                             .await
@@ -354,7 +357,7 @@ impl Service<Uri> for TlsConnector {
             match connection_type {
                 ConnectionType::Raw => Ok(TlsConnection(ConnectionState::Unencrypted(tcp_stream))),
                 ConnectionType::Tls => {
-                    let tls_stream = tls
+                    let (tls_stream, _server_cert) = tls
                         .perform_tls_client_handshake(
                             tcp_stream,
                             xnet_auth.node_id,
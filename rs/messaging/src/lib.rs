@@ -15,5 +15,6 @@ pub(crate) mod xnet_uri;
 pub use message_routing::MessageRoutingImpl;
 pub use xnet_endpoint::{XNetEndpoint, XNetEndpointConfig};
 pub use xnet_payload_builder::{
-    testing as xnet_payload_builder_testing, ExpectedIndices, XNetPayloadBuilderImpl,
+    testing as xnet_payload_builder_testing, ExpectedIndices, XNetClientConfig,
+    XNetPayloadBuilderImpl,
 };
@@ -23,6 +23,7 @@ use ic_registry_client::helper::{
     subnet::{SubnetListRegistry, SubnetRegistry},
 };
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
+use ic_registry_routing_table::CanisterIdRanges;
 use ic_registry_subnet_features::SubnetFeatures;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
@@ -37,12 +38,14 @@ use ic_types::{
     CanisterId, Height, NodeId, NumBytes, RegistryVersion, SubnetId,
 };
 use ic_utils::thread::JoinOnDrop;
+use ic_wasm_types::{InstructionCostOverrides, OpcodeClass};
 #[cfg(test)]
 use mockall::automock;
-use prometheus::{Histogram, HistogramVec, IntCounterVec, IntGauge};
+use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge};
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::convert::TryFrom;
 use std::ops::Range;
+use std::str::FromStr;
 use std::sync::mpsc::{sync_channel, TrySendError};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread::sleep;
@@ -68,6 +71,8 @@ const PHASE_COMMIT: &str = "commit";
 
 const METRIC_PROCESS_BATCH_DURATION: &str = "mr_process_batch_duration_seconds";
 const METRIC_PROCESS_BATCH_PHASE_DURATION: &str = "mr_process_batch_phase_duration_seconds";
+const METRIC_TIMED_OUT_REQUESTS: &str = "mr_timed_out_requests_total";
+const METRIC_TIMED_OUT_REQUESTS_PER_CANISTER: &str = "mr_timed_out_requests_per_canister";
 
 /// Records the timestamp when all messages before the given index (down to the
 /// previous `MessageTime`) were first added to / learned about in a stream.
@@ -236,6 +241,11 @@ pub(crate) struct MessageRoutingMetrics {
     /// for the extra copies of the state that the protocol has to store for
     /// correct operations.
     canisters_memory_usage_bytes: IntGauge,
+    /// Number of best-effort requests timed out.
+    timed_out_requests: IntCounter,
+    /// Number of best-effort requests timed out in a round, per canister with
+    /// timed out requests.
+    timed_out_requests_per_canister: Histogram,
 }
 
 impl MessageRoutingMetrics {
@@ -271,6 +281,28 @@ impl MessageRoutingMetrics {
                 "canister_memory_usage_bytes",
                 "Total memory footprint of all canisters on this subnet.",
             ),
+            timed_out_requests: metrics_registry.int_counter(
+                METRIC_TIMED_OUT_REQUESTS,
+                "Number of best-effort requests timed out in canister queues.",
+            ),
+            timed_out_requests_per_canister: metrics_registry.histogram(
+                METRIC_TIMED_OUT_REQUESTS_PER_CANISTER,
+                "Number of best-effort requests timed out in a round, per canister with timed out requests.",
+                // 1 - 500 (the capacity of a queue)
+                decimal_buckets(0, 2),
+            ),
+        }
+    }
+
+    /// Records the number of timed out requests of every canister that had
+    /// any in the current round.
+    pub(crate) fn observe_timed_out_requests(
+        &self,
+        timed_out_requests: &BTreeMap<CanisterId, usize>,
+    ) {
+        for count in timed_out_requests.values() {
+            self.timed_out_requests.inc_by(*count as u64);
+            self.timed_out_requests_per_canister.observe(*count as f64);
         }
     }
 }
@@ -471,12 +503,15 @@ impl BatchProcessorImpl {
             let public_key =
                 get_subnet_public_key(Arc::clone(&self.registry), *subnet_id, registry_version)?;
             let subnet_type = self.get_subnet_type(*subnet_id, registry_version);
+            let reserved_canister_id_ranges =
+                self.get_reserved_canister_id_ranges(*subnet_id, registry_version);
             subnets.insert(
                 *subnet_id,
                 SubnetTopology {
                     public_key,
                     nodes,
                     subnet_type,
+                    reserved_canister_id_ranges,
                 },
             );
         }
@@ -544,6 +579,28 @@ impl BatchProcessorImpl {
         SubnetType::try_from(record.subnet_type).expect("Could not parse SubnetType")
     }
 
+    fn get_reserved_canister_id_ranges(
+        &self,
+        subnet_id: SubnetId,
+        registry_version: RegistryVersion,
+    ) -> CanisterIdRanges {
+        let record = self.get_subnet_record(subnet_id, registry_version);
+        match record
+            .reserved_canister_id_ranges
+            .map(CanisterIdRanges::try_from)
+            .transpose()
+        {
+            Ok(ranges) => ranges.unwrap_or_default(),
+            // This can only happen if the registry is corrupted, so better to crash.
+            Err(err) => fatal!(
+                self.log,
+                "Failed to decode the reserved canister id ranges of subnet {}: {}",
+                subnet_id,
+                err
+            ),
+        }
+    }
+
     fn get_subnet_features(
         &self,
         subnet_id: SubnetId,
@@ -552,6 +609,35 @@ impl BatchProcessorImpl {
         let record = self.get_subnet_record(subnet_id, registry_version);
         record.features.unwrap_or_default().into()
     }
+
+    fn get_instruction_cost_overrides(
+        &self,
+        subnet_id: SubnetId,
+        registry_version: RegistryVersion,
+    ) -> InstructionCostOverrides {
+        let record = self.get_subnet_record(subnet_id, registry_version);
+        record
+            .instruction_cost_overrides
+            .into_iter()
+            .filter_map(|cost_override| {
+                match OpcodeClass::from_str(&cost_override.opcode_class) {
+                    Ok(class) => Some((class, cost_override.cost)),
+                    Err(err) => {
+                        // Ignore overrides that this replica version does not
+                        // know rather than failing to execute the batch.
+                        warn!(
+                            every_n_seconds => 300,
+                            self.log,
+                            "Ignoring instruction cost override at registry version {}: {}",
+                            registry_version,
+                            err
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
 }
 
 fn get_subnet_public_key(
@@ -616,6 +702,8 @@ impl BatchProcessor for BatchProcessorImpl {
         let provisional_whitelist = self.get_provisional_whitelist(batch.registry_version);
         let subnet_features =
             self.get_subnet_features(state.metadata.own_subnet_id, batch.registry_version);
+        let instruction_cost_overrides = self
+            .get_instruction_cost_overrides(state.metadata.own_subnet_id, batch.registry_version);
 
         let batch_requires_full_state_hash = batch.requires_full_state_hash;
         let mut state_after_round = self.state_machine.execute_round(
@@ -624,6 +712,7 @@ impl BatchProcessor for BatchProcessorImpl {
             batch,
             provisional_whitelist,
             subnet_features,
+            instruction_cost_overrides,
         );
         self.observe_canisters_memory_usage(&state_after_round);
 
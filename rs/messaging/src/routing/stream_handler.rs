@@ -506,5 +506,6 @@ fn reject_code_for_state_error(err: &StateError) -> RejectCode {
         StateError::CanisterStopping(_) => RejectCode::CanisterReject,
         StateError::UnknownSubnetMethod(_) => RejectCode::CanisterReject,
         StateError::InvalidSubnetPayload => RejectCode::CanisterReject,
+        StateError::OutOfMemory { .. } => RejectCode::SysTransient,
     }
 }
@@ -49,6 +49,7 @@ const LABEL_STATUS: &str = "status";
 
 const LABEL_VALUE_SUCCESS: &str = "success";
 const LABEL_VALUE_DUPLICATE: &str = "duplicate";
+const LABEL_VALUE_EXPIRED: &str = "expired";
 
 impl VsrMetrics {
     fn new(metrics_registry: &MetricsRegistry) -> Self {
@@ -75,6 +76,7 @@ impl VsrMetrics {
         for status in &[
             LABEL_VALUE_SUCCESS,
             LABEL_VALUE_DUPLICATE,
+            LABEL_VALUE_EXPIRED,
             LABEL_VALUE_CANISTER_NOT_FOUND,
             LABEL_VALUE_CANISTER_STOPPED,
             LABEL_VALUE_CANISTER_STOPPING,
@@ -165,6 +167,7 @@ impl ValidSetRuleImpl {
                     StateError::CanisterOutOfCycles { .. } => ErrorCode::CanisterOutOfCycles,
                     StateError::UnknownSubnetMethod(_) => ErrorCode::CanisterOutOfCycles,
                     StateError::InvalidSubnetPayload => ErrorCode::CanisterOutOfCycles,
+                    StateError::OutOfMemory { .. } => ErrorCode::CanisterOutOfMemory,
                     StateError::QueueFull { .. } => unreachable!("Unexpected error: {}", err),
                 };
                 self.ingress_history_writer.set_status(
@@ -185,8 +188,28 @@ impl ValidSetRuleImpl {
     }
 
     /// Checks whether the given message has already been inducted.
-    fn is_duplicate(&self, state: &ReplicatedState, msg: &SignedIngressContent) -> bool {
-        state.get_ingress_status(&msg.id()) != IngressStatus::Unknown
+    /// Returns the status of `msg` in the ingress history, if it was already
+    /// inducted.
+    ///
+    /// The ingress history doubles as the deduplication window: the status of
+    /// an inducted message is kept for at least `MAX_INGRESS_TTL` after it
+    /// reached a terminal status, which covers the expiry time of the message.
+    fn existing_status(
+        &self,
+        state: &ReplicatedState,
+        msg: &SignedIngressContent,
+    ) -> Option<IngressStatus> {
+        match state.get_ingress_status(&msg.id()) {
+            IngressStatus::Unknown => None,
+            status => Some(status),
+        }
+    }
+
+    /// Returns true if `msg` expired before the time of `state`. Its status
+    /// may already have been pruned from the ingress history, so inducting it
+    /// could execute a retried message a second time.
+    fn is_expired(&self, state: &ReplicatedState, msg: &SignedIngressContent) -> bool {
+        msg.ingress_expiry() < state.time()
     }
 
     /// Records the result of inducting an ingress message.
@@ -305,11 +328,19 @@ impl ValidSetRule for ValidSetRuleImpl {
     fn induct_messages(&self, state: &mut ReplicatedState, msgs: Vec<SignedIngressContent>) {
         for msg in msgs {
             let message_id = msg.id();
-            if !self.is_duplicate(&state, &msg) {
-                self.induct_message(state, msg);
-            } else {
+            if let Some(status) = self.existing_status(&state, &msg) {
+                // Leave the status untouched, so that the retried message
+                // resolves to the outcome of the original one.
                 self.observe_inducted_ingress_status(LABEL_VALUE_DUPLICATE);
-                debug!(self.log, "Didn't induct duplicate message {}", message_id);
+                debug!(
+                    self.log,
+                    "Didn't induct duplicate message {} with status {:?}", message_id, status
+                );
+            } else if self.is_expired(&state, &msg) {
+                self.observe_inducted_ingress_status(LABEL_VALUE_EXPIRED);
+                debug!(self.log, "Didn't induct expired message {}", message_id);
+            } else {
+                self.induct_message(state, msg);
             }
         }
     }
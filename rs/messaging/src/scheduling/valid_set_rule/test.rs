@@ -22,7 +22,7 @@ use ic_test_utilities::{
 };
 use ic_types::{
     ic00::IC_00,
-    ingress::IngressStatus,
+    ingress::{IngressStatus, WasmResult},
     messages::{MessageId, SignedIngressContent},
     CanisterId,
 };
@@ -467,6 +467,98 @@ fn dont_induct_duplicate_messages() {
     });
 }
 
+#[test]
+// A retried message that already completed keeps its status and is not
+// executed a second time.
+fn dont_induct_retry_of_completed_message() {
+    with_test_replica_logger(|log| {
+        let canister_id = canister_test_id(0);
+        let metrics_registry = MetricsRegistry::new();
+        let valid_set_rule = ValidSetRuleImpl::new(
+            Arc::new(NoopIngressHistoryWriter),
+            Arc::new(CyclesAccountManagerBuilder::new().build()),
+            &metrics_registry,
+            subnet_test_id(1),
+            log,
+        );
+
+        let mut state = ReplicatedState::new_rooted_at(
+            subnet_test_id(1),
+            SubnetType::Application,
+            "NOT_USED".into(),
+        );
+        insert_canister(&mut state, canister_id);
+
+        let msg: SignedIngressContent = SignedIngressBuilder::new()
+            .canister_id(canister_id)
+            .build()
+            .into();
+        let status = IngressStatus::Completed {
+            receiver: canister_id.get(),
+            user_id: user_test_id(0),
+            result: WasmResult::Reply(vec![1, 2, 3]),
+            time: mock_time(),
+        };
+        state.set_ingress_status(msg.id(), status.clone());
+
+        valid_set_rule.induct_messages(&mut state, vec![msg.clone()]);
+        assert_eq!(ingress_queue_size(&state, canister_id), 0);
+        assert_eq!(state.get_ingress_status(&msg.id()), status);
+        assert_inducted_ingress_messages_eq(
+            metric_vec(&[(&[(LABEL_STATUS, LABEL_VALUE_DUPLICATE)], 1)]),
+            &metrics_registry,
+        );
+    });
+}
+
+#[test]
+fn dont_induct_expired_message() {
+    with_test_replica_logger(|log| {
+        let canister_id = canister_test_id(0);
+        let metrics_registry = MetricsRegistry::new();
+        let valid_set_rule = ValidSetRuleImpl::new(
+            Arc::new(NoopIngressHistoryWriter),
+            Arc::new(CyclesAccountManagerBuilder::new().build()),
+            &metrics_registry,
+            subnet_test_id(1),
+            log,
+        );
+
+        let mut state = ReplicatedState::new_rooted_at(
+            subnet_test_id(1),
+            SubnetType::Application,
+            "NOT_USED".into(),
+        );
+        insert_canister(&mut state, canister_id);
+        state.metadata.batch_time = mock_time() + Duration::from_secs(1);
+
+        let expired_msg: SignedIngressContent = SignedIngressBuilder::new()
+            .canister_id(canister_id)
+            .expiry_time(mock_time())
+            .build()
+            .into();
+        let msg: SignedIngressContent = SignedIngressBuilder::new()
+            .canister_id(canister_id)
+            .expiry_time(state.time())
+            .build()
+            .into();
+
+        valid_set_rule.induct_messages(&mut state, vec![expired_msg.clone(), msg]);
+        assert_eq!(ingress_queue_size(&state, canister_id), 1);
+        assert_eq!(
+            state.get_ingress_status(&expired_msg.id()),
+            IngressStatus::Unknown
+        );
+        assert_inducted_ingress_messages_eq(
+            metric_vec(&[
+                (&[(LABEL_STATUS, LABEL_VALUE_SUCCESS)], 1),
+                (&[(LABEL_STATUS, LABEL_VALUE_EXPIRED)], 1),
+            ]),
+            &metrics_registry,
+        );
+    });
+}
+
 #[test]
 fn canister_on_application_subnet_charges_for_ingress() {
     let own_subnet_type = SubnetType::Application;
@@ -7,6 +7,7 @@ use ic_registry_provisional_whitelist::ProvisionalWhitelist;
 use ic_registry_subnet_features::SubnetFeatures;
 use ic_replicated_state::{NetworkTopology, ReplicatedState};
 use ic_types::{batch::Batch, ExecutionRound};
+use ic_wasm_types::InstructionCostOverrides;
 use std::sync::Arc;
 
 #[cfg(test)]
@@ -24,6 +25,7 @@ pub(crate) trait StateMachine: Send {
         batch: Batch,
         provisional_whitelist: ProvisionalWhitelist,
         subnet_features: SubnetFeatures,
+        instruction_cost_overrides: InstructionCostOverrides,
     ) -> ReplicatedState;
 }
 pub(crate) struct StateMachineImpl {
@@ -69,6 +71,7 @@ impl StateMachine for StateMachineImpl {
         batch: Batch,
         provisional_whitelist: ProvisionalWhitelist,
         subnet_features: SubnetFeatures,
+        instruction_cost_overrides: InstructionCostOverrides,
     ) -> ReplicatedState {
         let phase_timer = Timer::start();
 
@@ -78,8 +81,15 @@ impl StateMachine for StateMachineImpl {
         metadata.batch_time = batch.time;
         metadata.network_topology = network_topology;
         metadata.own_subnet_features = subnet_features;
+        metadata.instruction_cost_overrides = instruction_cost_overrides;
         state.set_system_metadata(metadata);
 
+        // Time out best-effort requests whose deadline has passed before
+        // inducting new messages, so that their reject responses are
+        // delivered in this round.
+        let timed_out_requests = state.time_out_requests();
+        self.metrics.observe_timed_out_requests(&timed_out_requests);
+
         // Preprocess messages and add messages to the induction pool through the Demux.
         let mut state_with_messages = self.demux.process_payload(state, batch.payload);
         if !state_with_messages.consensus_queue.is_empty() {
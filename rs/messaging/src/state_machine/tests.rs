@@ -96,6 +96,7 @@ fn test_fixture(provided_batch: &Batch) -> StateMachineTestFixture {
             public_key: vec![0, 1, 2, 3],
             nodes: BTreeMap::new(),
             subnet_type: SubnetType::Application,
+            reserved_canister_id_ranges: Default::default(),
         },
     );
 
@@ -147,6 +148,7 @@ fn state_machine_populates_network_topology() {
             provided_batch,
             ProvisionalWhitelist::Set(BTreeSet::new()),
             Default::default(),
+            Default::default(),
         );
 
         assert_eq!(state.metadata.network_topology, fixture.network_topology);
@@ -173,6 +175,7 @@ fn test_delivered_batch(provided_batch: Batch) {
             provided_batch,
             ProvisionalWhitelist::Set(BTreeSet::new()),
             Default::default(),
+            Default::default(),
         );
     });
 }
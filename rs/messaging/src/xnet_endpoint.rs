@@ -248,6 +248,8 @@ impl<'a> XNetEndpoint {
                         config.address, e
                     )
                 });
+            // Serves HTTP/1.1 as well as HTTP/2, which XNet clients use, on the
+            // same TLS connections.
             (
                 addr,
                 builder
@@ -1,10 +1,10 @@
 use super::*;
 use bytes::Bytes;
+use ic_crypto_tls_interfaces::test_utils::MockTlsHandshake;
 use ic_interfaces::state_manager::{CertificationScope, StateManager};
 use ic_protobuf::{messaging::xnet::v1 as pb, proxy::ProtoProxy};
 use ic_replicated_state::{ReplicatedState, Stream};
 use ic_test_utilities::{
-    crypto::fake_tls_handshake::FakeTlsHandshake,
     metrics::{
         fetch_histogram_stats, fetch_histogram_vec_count, metric_vec, HistogramStats, MetricVec,
     },
@@ -67,7 +67,7 @@ impl Default for EndpointTestFixture {
             metrics: MetricsRegistry::new(),
             state_manager: Arc::new(FakeStateManager::new()),
             registry_client: Arc::new(MockRegistryClient::new()),
-            tls_handshake: Arc::new(FakeTlsHandshake::new()),
+            tls_handshake: Arc::new(MockTlsHandshake::new()),
         }
     }
 }
@@ -75,16 +75,21 @@ pub struct XNetPayloadBuilderMetrics {
     pub validate_payload_duration: HistogramVec,
     /// Track outstanding background query tasks
     pub outstanding_queries: IntGauge,
+    /// Fraction of the byte limit used by built payloads.
+    pub payload_byte_limit_utilization: Histogram,
 }
 
 pub const METRIC_BUILD_PAYLOAD_DURATION: &str = "xnet_builder_build_payload_duration_seconds";
 pub const METRIC_PULL_ATTEMPT_COUNT: &str = "xnet_builder_pull_attempt_count";
 pub const METRIC_QUERY_SLICE_DURATION: &str = "xnet_builder_query_slice_duration_seconds";
 pub const METRIC_RESPONSE_BODY_SIZE: &str = "xnet_builder_response_body_size_bytes";
+pub const METRIC_QUERY_STATUS_COUNT: &str = "xnet_builder_query_status_count";
 pub const METRIC_SLICE_MESSAGES: &str = "xnet_builder_slice_messages";
 pub const METRIC_SLICE_PAYLOAD_SIZE: &str = "xnet_builder_slice_payload_size_bytes";
 pub const METRIC_VALIDATE_PAYLOAD_DURATION: &str = "xnet_builder_validate_payload_duration_seconds";
 pub const METRIC_OUTSTANDING_XNET_QUERIES: &str = "xnet_builder_outstanding_queries";
+pub const METRIC_PAYLOAD_BYTE_LIMIT_UTILIZATION: &str =
+    "xnet_builder_payload_byte_limit_utilization_ratio";
 
 pub const LABEL_STATUS: &str = "status";
 pub const LABEL_PROXIMITY: &str = "proximity";
@@ -142,6 +147,11 @@ impl XNetPayloadBuilderMetrics {
                 METRIC_OUTSTANDING_XNET_QUERIES,
                 "Number of xnet queries that have not finished",
             ),
+            payload_byte_limit_utilization: metrics_registry.histogram(
+                METRIC_PAYLOAD_BYTE_LIMIT_UTILIZATION,
+                "Fraction of the byte limit used by non-empty built payloads.",
+                vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 0.95, 0.99, 1.0],
+            ),
         }
     }
 
@@ -269,6 +279,40 @@ impl XNetPayloadBuilderImpl {
         subnet_id: SubnetId,
         metrics_registry: &MetricsRegistry,
         log: ReplicaLogger,
+    ) -> XNetPayloadBuilderImpl {
+        Self::new_with_xnet_client_config(
+            state_manager,
+            certified_stream_store,
+            tls_handshake,
+            registry,
+            runtime_handle,
+            node_id,
+            subnet_id,
+            metrics_registry,
+            log,
+            XNetClientConfig::default(),
+        )
+    }
+
+    /// Creates a new `XNetPayloadBuilderImpl`, like `new()`, whose XNet client
+    /// queries slices according to `xnet_client_config`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if reading the node's own `node_operator_id` from the registry
+    /// fails.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_xnet_client_config(
+        state_manager: Arc<dyn StateManager<State = ReplicatedState>>,
+        certified_stream_store: Arc<dyn CertifiedStreamStore>,
+        tls_handshake: Arc<dyn TlsHandshake + Send + Sync>,
+        registry: Arc<dyn RegistryClient>,
+        runtime_handle: runtime::Handle,
+        node_id: NodeId,
+        subnet_id: SubnetId,
+        metrics_registry: &MetricsRegistry,
+        log: ReplicaLogger,
+        xnet_client_config: XNetClientConfig,
     ) -> XNetPayloadBuilderImpl {
         let proximity_map = Arc::new(ProximityMap::new(
             node_id,
@@ -281,6 +325,7 @@ impl XNetPayloadBuilderImpl {
             runtime_handle.clone(),
             tls_handshake,
             proximity_map.clone(),
+            xnet_client_config,
         ));
 
         let slice_pool = Arc::new(Mutex::new(CertifiedSlicePool::new(metrics_registry)));
@@ -692,6 +737,13 @@ impl XNetPayloadBuilderImpl {
             }
         }
 
+        if !stream_slices.is_empty() && byte_limit.get() > 0 {
+            let payload_bytes = byte_limit.get() as usize - bytes_left;
+            self.metrics
+                .payload_byte_limit_utilization
+                .observe(payload_bytes as f64 / byte_limit.get() as f64);
+        }
+
         // Collect all successfully queried slices into an `XNetPayload`.
         Ok(XNetPayload { stream_slices })
     }
@@ -1292,32 +1344,61 @@ pub trait XNetClient: Sync + Send {
     ) -> Result<CertifiedStreamSlice, XNetClientError>;
 }
 
+/// Configuration of the XNet client used by `XNetPayloadBuilderImpl` to query
+/// stream slices from remote subnets.
+#[derive(Clone, Debug)]
+pub struct XNetClientConfig {
+    /// Time limit for a query, including connection setup and reading the
+    /// response body.
+    pub request_timeout: Duration,
+
+    /// Whether to query over HTTP/2 only, multiplexing all concurrent queries
+    /// to a peer over a single TLS connection. Otherwise, HTTP/1.1 is used.
+    pub http2_only: bool,
+}
+
+impl Default for XNetClientConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(5),
+            http2_only: true,
+        }
+    }
+}
+
 /// The default `XNetClient` implementation, wrapping an HTTP client (for both
 /// configuration and connection pooling).
 struct XNetClientImpl {
     /// An HTTP client to be used for querying.
     http_client: Client<TlsConnector, Request<Body>>,
 
+    /// Time limit for a query.
+    request_timeout: Duration,
+
     /// Response body (encoded slice) size.
     response_body_size: HistogramVec,
 
+    /// Completed queries, by HTTP response status or error.
+    query_status_count: IntCounterVec,
+
     /// Proximity map to update after every query with the time-to-first-byte.
     proximity_map: Arc<ProximityMap>,
 }
 
 impl XNetClientImpl {
-    /// Creates a new `XNetClientImpl` with a request timeout of 1 second and at
-    /// most 1 idle connection per host.
+    /// Creates a new `XNetClientImpl` with the given `config` and at most 1
+    /// idle connection per peer.
     fn new(
         metrics_registry: &MetricsRegistry,
         runtime_handle: runtime::Handle,
         tls: Arc<dyn TlsHandshake + Send + Sync>,
         proximity_map: Arc<ProximityMap>,
+        config: XNetClientConfig,
     ) -> XNetClientImpl {
-        // TODO(MR-28) Make timeout configurable.
         let http_client: Client<TlsConnector, _> = Client::builder()
             .pool_idle_timeout(Some(Duration::from_secs(600)))
             .pool_max_idle_per_host(1)
+            .http2_only(config.http2_only)
             .executor(ExecuteOnRuntime(runtime_handle))
             .build(TlsConnector::new(tls));
 
@@ -1331,9 +1412,17 @@ impl XNetClientImpl {
         response_body_size.with_label_values(&[STATUS_SUCCESS]);
         response_body_size.with_label_values(&[STATUS_DECODE_ERROR]);
 
+        let query_status_count = metrics_registry.int_counter_vec(
+            METRIC_QUERY_STATUS_COUNT,
+            "Completed XNet queries, by HTTP response status or error.",
+            &[LABEL_STATUS],
+        );
+
         XNetClientImpl {
             http_client,
+            request_timeout: config.request_timeout,
             response_body_size,
+            query_status_count,
             proximity_map,
         }
     }
@@ -1345,8 +1434,7 @@ impl XNetClient for XNetClientImpl {
         &self,
         endpoint: &EndpointLocator,
     ) -> Result<CertifiedStreamSlice, XNetClientError> {
-        // TODO(MR-28) Make timeout configurable.
-        let result = tokio::time::timeout(Duration::from_secs(5), async {
+        let result = tokio::time::timeout(self.request_timeout, async {
             let request_start = Instant::now();
             let result = self.http_client.get(endpoint.url.clone()).await;
             // While this is not exactly roundtrip time (it may include multiple roundtrips
@@ -1374,7 +1462,18 @@ impl XNetClient for XNetClientImpl {
         })
         .await;
 
-        let (status, bytes) = result.map_err(|_| XNetClientError::Timeout)??;
+        let (status, bytes) = match result.map_err(|_| XNetClientError::Timeout) {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) | Err(e) => {
+                self.query_status_count
+                    .with_label_values(&[&e.to_label_value()])
+                    .inc();
+                return Err(e);
+            }
+        };
+        self.query_status_count
+            .with_label_values(&[&http_status_label(status)])
+            .inc();
 
         match status {
             StatusCode::OK => match pb::CertifiedStreamSlice::proxy_decode(bytes.as_ref()) {
@@ -1445,13 +1544,18 @@ impl XNetClientError {
             XNetClientError::Timeout => "Timeout".to_string(),
             XNetClientError::RequestFailed(..) => "RequestFailed".to_string(),
             XNetClientError::NoContent => "NoContent".to_string(),
-            XNetClientError::ErrorResponse(status, _) => format!("HTTP_{}", status.as_u16()),
+            XNetClientError::ErrorResponse(status, _) => http_status_label(*status),
             XNetClientError::BodyReadError(..) => "BodyReadError".to_string(),
             XNetClientError::ProxyDecodeError(..) => STATUS_DECODE_ERROR.to_string(),
         }
     }
 }
 
+/// Maps an HTTP response status to a `status` label value.
+fn http_status_label(status: StatusCode) -> String {
+    format!("HTTP_{}", status.as_u16())
+}
+
 /// Internal functionality, exposed for use by integration tests.
 pub mod testing {
     use super::*;
@@ -1459,8 +1563,9 @@ pub mod testing {
     pub use super::{
         EndpointLocator, GenRangeFn, PoolRefillTask, ProximityMap, RefillTaskHandle, XNetClient,
         XNetClientError, XNetEndpointResolver, XNetPayloadBuilderMetrics, LABEL_STATUS,
-        METRIC_BUILD_PAYLOAD_DURATION, METRIC_SLICE_MESSAGES, METRIC_SLICE_PAYLOAD_SIZE,
-        POOL_SLICE_BYTE_SIZE_MAX, STATUS_SUCCESS, SYSTEM_SUBNET_STREAM_MSG_LIMIT,
+        METRIC_BUILD_PAYLOAD_DURATION, METRIC_PAYLOAD_BYTE_LIMIT_UTILIZATION,
+        METRIC_SLICE_MESSAGES, METRIC_SLICE_PAYLOAD_SIZE, POOL_SLICE_BYTE_SIZE_MAX, STATUS_SUCCESS,
+        SYSTEM_SUBNET_STREAM_MSG_LIMIT,
     };
 
     /// Puts the provided slice into the payload builder's slice pool.
@@ -3,11 +3,11 @@
 use super::test_fixtures::*;
 use super::*;
 use assert_matches::assert_matches;
+use ic_crypto_tls_interfaces::test_utils::MockTlsHandshake;
 use ic_interfaces::{certified_stream_store::DecodeStreamError, state_manager::StateReader};
 use ic_registry_subnet_type::SubnetType;
 use ic_test_utilities::{
     certified_stream_store::MockCertifiedStreamStore,
-    crypto::fake_tls_handshake::FakeTlsHandshake,
     state_manager::FakeStateManager,
     types::ids::{subnet_test_id, SUBNET_1, SUBNET_2, SUBNET_3, SUBNET_4, SUBNET_5},
     with_test_replica_logger,
@@ -47,7 +47,7 @@ async fn expected_stream_indices() {
 
         // A registry that has entries for `SUBNET_1` through `SUBNET_5`.
         let (registry, _urls) = get_registry_and_urls_for_test(5, expected_indices.clone());
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
         let state_manager = Arc::new(state_manager);
         let xnet_payload_builder = XNetPayloadBuilderImpl::new(
             Arc::clone(&state_manager) as Arc<_>,
@@ -303,7 +303,7 @@ async fn validate_slice_invalid_signature() {
 
         let state_manager = FakeStateManager::new();
         let state_manager = Arc::new(state_manager);
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
         let registry = get_empty_registry_for_test();
         let xnet_payload_builder = XNetPayloadBuilderImpl::new(
             state_manager,
@@ -459,7 +459,7 @@ fn get_xnet_payload_builder_for_test(
 ) -> XNetPayloadBuilderImpl {
     let registry = get_empty_registry_for_test();
     let state_manager = Arc::new(state_manager);
-    let tls_handshake = Arc::new(FakeTlsHandshake::new());
+    let tls_handshake = Arc::new(MockTlsHandshake::new());
     XNetPayloadBuilderImpl::new(
         Arc::clone(&state_manager) as Arc<_>,
         state_manager,
@@ -1,13 +1,13 @@
 use super::test_fixtures::*;
 use super::*;
 use assert_matches::assert_matches;
+use ic_crypto_tls_interfaces::test_utils::MockTlsHandshake;
 use ic_interfaces::{
     messaging::{InvalidXNetPayload, XNetTransientValidationError},
     state_manager::StateManagerError,
 };
 use ic_test_utilities::{
     certified_stream_store::MockCertifiedStreamStore,
-    crypto::fake_tls_handshake::FakeTlsHandshake,
     metrics::{
         fetch_histogram_stats, fetch_histogram_vec_count, fetch_int_counter_vec, metric_vec,
         HistogramStats, MetricVec,
@@ -232,7 +232,7 @@ async fn validate_duplicate_messages_against_state_only() {
         };
         let state_manager = Arc::new(state_manager);
         let registry = get_empty_registry_for_test();
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
         let xnet_payload_builder = XNetPayloadBuilderImpl::new(
             Arc::clone(&state_manager) as Arc<_>,
             state_manager,
@@ -313,7 +313,7 @@ async fn validate_state_removed() {
             .return_const(Err(StateManagerError::StateRemoved(CERTIFIED_HEIGHT)));
         let state_manager = Arc::new(state_manager);
         let registry = get_empty_registry_for_test();
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
         let xnet_payload_builder = XNetPayloadBuilderImpl::new(
             Arc::clone(&state_manager) as Arc<_>,
             certified_stream_store,
@@ -350,7 +350,7 @@ async fn validate_state_not_yet_committed() {
         let state_manager = Arc::new(state_manager);
 
         let registry = get_empty_registry_for_test();
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
         let xnet_payload_builder = XNetPayloadBuilderImpl::new(
             Arc::clone(&state_manager) as Arc<_>,
             state_manager,
@@ -384,7 +384,7 @@ async fn validate_state_not_yet_committed() {
 /// `RegistryClient` with valid payloads and expected indices.
 pub(crate) struct PayloadBuilderTestFixture {
     pub state_manager: Arc<FakeStateManager>,
-    pub tls_handshake: Arc<FakeTlsHandshake>,
+    pub tls_handshake: Arc<MockTlsHandshake>,
     pub registry: Arc<dyn RegistryClient>,
     pub validation_context: ValidationContext,
     pub metrics: MetricsRegistry,
@@ -399,7 +399,7 @@ impl PayloadBuilderTestFixture {
     /// and registry entries plus matching URLs for the given number of subnets.
     pub fn with_xnet_state(subnet_count: u8) -> Self {
         let state_manager = Arc::new(FakeStateManager::new());
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
 
         let (payloads, expected_indices) = get_xnet_state_for_testing(&*state_manager);
         let (registry, subnet_urls) =
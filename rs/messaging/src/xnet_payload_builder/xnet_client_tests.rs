@@ -3,16 +3,21 @@
 
 use super::test_fixtures::*;
 use super::*;
+use crate::XNetEndpoint;
 use hyper::Uri;
+use ic_crypto_tls_interfaces::test_utils::MockTlsHandshake;
+use ic_interfaces::state_manager::CertificationScope;
 use ic_protobuf::messaging::xnet::v1 as pb;
 use ic_protobuf::proxy::ProxyDecodeError;
 use ic_test_utilities::{
-    crypto::fake_tls_handshake::FakeTlsHandshake,
-    metrics::{fetch_histogram_vec_count, metric_vec, MetricVec},
+    metrics::{fetch_histogram_vec_count, fetch_int_counter_vec, metric_vec, MetricVec},
+    registry::MockRegistryClient,
+    state_manager::FakeStateManager,
     types::ids::SUBNET_6,
     with_test_replica_logger,
 };
 use ic_types::{xnet::CertifiedStreamSlice, SubnetId};
+use maplit::btreemap;
 use std::io::Cursor;
 use std::sync::Arc;
 use std::{net::SocketAddr, sync::Barrier};
@@ -48,8 +53,13 @@ fn make_xnet_client(metrics: &MetricsRegistry, log: ReplicaLogger) -> XNetClient
     XNetClientImpl::new(
         &metrics,
         tokio::runtime::Handle::current(),
-        Arc::new(FakeTlsHandshake::new()) as Arc<_>,
+        Arc::new(MockTlsHandshake::new()) as Arc<_>,
         Arc::new(ProximityMap::new(LOCAL_NODE, registry, &metrics, log)),
+        // `tiny_http` only speaks HTTP/1.1.
+        XNetClientConfig {
+            http2_only: false,
+            ..XNetClientConfig::default()
+        },
     )
 }
 
@@ -79,6 +89,10 @@ async fn query_success() {
         ]),
         response_counts(&metrics)
     );
+    assert_eq!(
+        metric_vec(&[(&[("status", "HTTP_200")], 1)]),
+        query_status_counts(&metrics)
+    );
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -168,6 +182,10 @@ async fn query_no_content() {
         ]),
         response_counts(&metrics)
     );
+    assert_eq!(
+        metric_vec(&[(&[("status", "HTTP_204")], 1)]),
+        query_status_counts(&metrics)
+    );
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -194,6 +212,10 @@ async fn query_error_response() {
         ]),
         response_counts(&metrics)
     );
+    assert_eq!(
+        metric_vec(&[(&[("status", "HTTP_500")], 1)]),
+        query_status_counts(&metrics)
+    );
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -242,6 +264,10 @@ async fn query_request_timeout() {
         ]),
         response_counts(metrics)
     );
+    assert_eq!(
+        metric_vec(&[(&[("status", "Timeout")], 1)]),
+        query_status_counts(metrics)
+    );
 }
 
 // For some reason `bind()` on Darwin behaves the same as `bind() + listen()`,
@@ -289,6 +315,69 @@ async fn query_request_failed() {
     );
 }
 
+/// Tests querying a slice from an `XNetEndpoint` over HTTP/2, as the replica
+/// does.
+///
+/// Heavyweight test that starts an `XNetEndpoint` and queries it with the
+/// default `XNetClientConfig`.
+#[test]
+fn query_xnet_endpoint_over_http2() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let metrics = MetricsRegistry::new();
+
+    let state_manager = Arc::new(FakeStateManager::new());
+    let (_height, mut state) = state_manager.take_tip();
+    state.put_streams(btreemap![DST_SUBNET => generate_stream(&StreamConfig {
+        message_begin: STREAM_BEGIN,
+        message_end: STREAM_END,
+        signal_end: 0,
+    })]);
+    state_manager.commit_and_certify(state, CERTIFIED_HEIGHT, CertificationScope::Metadata);
+
+    let result = with_test_replica_logger(|log| {
+        let xnet_endpoint = XNetEndpoint::new(
+            rt.handle().clone(),
+            state_manager,
+            Arc::new(MockTlsHandshake::new()),
+            Arc::new(MockRegistryClient::new()),
+            Default::default(),
+            &MetricsRegistry::new(),
+            log.clone(),
+        );
+        let xnet_client = XNetClientImpl::new(
+            &metrics,
+            rt.handle().clone(),
+            Arc::new(MockTlsHandshake::new()) as Arc<_>,
+            Arc::new(ProximityMap::new(
+                LOCAL_NODE,
+                get_empty_registry_for_test(),
+                &metrics,
+                log,
+            )),
+            XNetClientConfig::default(),
+        );
+        let endpoint = EndpointLocator {
+            node_id: LOCAL_NODE,
+            url: format!(
+                "http://aaaaa-aa.1@127.0.0.1:{}/api/v1/stream/{}?msg_begin={}",
+                xnet_endpoint.server_port(),
+                DST_SUBNET,
+                STREAM_BEGIN
+            )
+            .parse::<Uri>()
+            .unwrap(),
+            proximity: PeerLocation::Local,
+        };
+        rt.block_on(xnet_client.query(&endpoint))
+    });
+
+    assert_eq!(get_stream_slice_for_testing(), result.unwrap());
+    assert_eq!(
+        metric_vec(&[(&[("status", "HTTP_200")], 1)]),
+        query_status_counts(&metrics)
+    );
+}
+
 /// Returns the result of invoking `xnet_client.query()` against an HTTP server
 /// in a spawned thread that processes a single request using `handle_request`.
 fn do_xnet_client_query<H: Fn(Request) + Send + 'static>(
@@ -373,3 +462,9 @@ fn get_stream_slice_for_testing() -> CertifiedStreamSlice {
 pub fn response_counts(metrics: &MetricsRegistry) -> MetricVec<u64> {
     fetch_histogram_vec_count(metrics, METRIC_RESPONSE_BODY_SIZE)
 }
+
+/// Fetches the values of the `METRIC_QUERY_STATUS_COUNT` counters for all
+/// label values.
+fn query_status_counts(metrics: &MetricsRegistry) -> MetricVec<u64> {
+    fetch_int_counter_vec(metrics, METRIC_QUERY_STATUS_COUNT)
+}
@@ -3,9 +3,9 @@ use ic_canonical_state::LabelLike;
 use ic_crypto_tree_hash::{flat_map::FlatMap, Label, LabeledTree};
 use ic_messaging::{
     certified_slice_pool::{
-        certified_slice_count_bytes, testing, CertifiedSliceError, CertifiedSlicePool,
-        InvalidAppend, InvalidSlice, UnpackedStreamSlice, LABEL_STATUS, STATUS_NONE,
-        STATUS_SUCCESS,
+        certified_slice_count_bytes, certified_slice_encoded_bytes, testing, CertifiedSliceError,
+        CertifiedSlicePool, InvalidAppend, InvalidSlice, UnpackedStreamSlice, LABEL_STATUS,
+        STATUS_NONE, STATUS_SUCCESS,
     },
     ExpectedIndices,
 };
@@ -416,8 +416,7 @@ proptest! {
             let unpacked_witness_bytes = testing::witness_count_bytes(&unpacked);
             assert_almost_equal(packed_witness_bytes, unpacked_witness_bytes, 5, 10);
 
-            let packed_bytes =
-                slice.payload.len() + slice.merkle_proof.len() + slice.certification.count_bytes();
+            let packed_bytes = certified_slice_encoded_bytes(&slice);
             let unpacked_bytes = unpacked.count_bytes();
             assert_almost_equal(packed_bytes, unpacked_bytes, 5, 0);
         }
@@ -673,6 +672,41 @@ proptest! {
                 fixture.fetch_pool_take_gced_messages()
             );
             assert_eq!(2, fixture.fetch_pool_take_size_bytes().count);
+            // The estimation error was observed for both returned slices.
+            assert_eq!(2, fixture.fetch_pool_take_size_estimate_error().count);
+        });
+    }
+
+    #[test]
+    fn pool_take_slice_respects_encoded_byte_limit((stream, from, msg_count) in arb_stream_slice(2, 100)) {
+        with_test_replica_logger(|log| {
+            let fixture = StateManagerFixture::new(log).with_stream(DST_SUBNET, stream);
+            let slice = fixture.get_slice(DST_SUBNET, from, msg_count);
+            let slice_bytes = certified_slice_encoded_bytes(&slice);
+
+            let mut pool = CertifiedSlicePool::new(&MetricsRegistry::new());
+            pool.put(SRC_SUBNET, slice).unwrap();
+
+            // Take a page just one byte short of the whole slice.
+            let (page, page_bytes) = pool
+                .take_slice(SRC_SUBNET, None, None, Some(slice_bytes - 1))
+                .unwrap()
+                .unwrap();
+
+            // The returned size is the exact encoded size of the page.
+            assert_eq!(certified_slice_encoded_bytes(&page), page_bytes);
+            assert!(page_bytes < slice_bytes);
+
+            // The page is a prefix of the slice and the rest is still pooled.
+            let page_msg_count = testing::slice_len(&UnpackedStreamSlice::try_from(page.clone()).unwrap());
+            assert!(page_msg_count < msg_count);
+            assert_opt_slices_eq(
+                Some(fixture.get_slice(DST_SUBNET, from, page_msg_count)),
+                Some(page),
+            );
+            let (_, slice_begin, pooled_msg_count, _) = pool.slice_stats(SRC_SUBNET);
+            assert_eq!(Some(from + StreamIndex::new(page_msg_count as u64)), slice_begin);
+            assert_eq!(msg_count - page_msg_count, pooled_msg_count);
         });
     }
 
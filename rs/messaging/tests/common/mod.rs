@@ -8,7 +8,7 @@ use ic_interfaces::{
 use ic_logger::ReplicaLogger;
 use ic_messaging::certified_slice_pool::{
     UnpackedStreamSlice, METRIC_POOL_SIZE_BYTES, METRIC_TAKE_COUNT, METRIC_TAKE_GCED_MESSAGES,
-    METRIC_TAKE_MESSAGES, METRIC_TAKE_SIZE_BYTES,
+    METRIC_TAKE_MESSAGES, METRIC_TAKE_SIZE_BYTES, METRIC_TAKE_SIZE_ESTIMATE_ERROR,
 };
 use ic_metrics::MetricsRegistry;
 use ic_registry_subnet_type::SubnetType;
@@ -155,6 +155,11 @@ impl StateManagerFixture {
     pub fn fetch_pool_take_size_bytes(&self) -> HistogramStats {
         fetch_histogram_stats(&self.metrics, METRIC_TAKE_SIZE_BYTES).unwrap()
     }
+
+    /// Returns the `METRIC_TAKE_SIZE_ESTIMATE_ERROR` histogram's stats.
+    pub fn fetch_pool_take_size_estimate_error(&self) -> HistogramStats {
+        fetch_histogram_stats(&self.metrics, METRIC_TAKE_SIZE_ESTIMATE_ERROR).unwrap()
+    }
 }
 
 prop_compose! {
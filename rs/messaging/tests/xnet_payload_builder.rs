@@ -1,11 +1,14 @@
 use async_trait::async_trait;
+use ic_crypto_tls_interfaces::test_utils::MockTlsHandshake;
 use ic_interfaces::{
     certified_stream_store::CertifiedStreamStore, messaging::XNetPayloadBuilder,
     registry::RegistryClient,
 };
 use ic_logger::ReplicaLogger;
 use ic_messaging::{
-    certified_slice_pool::{CertifiedSlicePool, UnpackedStreamSlice},
+    certified_slice_pool::{
+        certified_slice_encoded_bytes, CertifiedSlicePool, UnpackedStreamSlice,
+    },
     xnet_payload_builder_testing::*,
     ExpectedIndices, XNetPayloadBuilderImpl,
 };
@@ -23,7 +26,6 @@ use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::metadata_state::Stream;
 use ic_state_manager::StateManagerImpl;
 use ic_test_utilities::{
-    crypto::fake_tls_handshake::FakeTlsHandshake,
     metrics::{
         fetch_histogram_stats, fetch_histogram_vec_count, metric_vec, HistogramStats, MetricVec,
     },
@@ -70,7 +72,7 @@ struct XNetPayloadBuilderFixture {
 impl XNetPayloadBuilderFixture {
     fn new(fixture: StateManagerFixture) -> Self {
         let state_manager = Arc::new(fixture.state_manager);
-        let tls_handshake = Arc::new(FakeTlsHandshake::new());
+        let tls_handshake = Arc::new(MockTlsHandshake::new());
 
         // Throwaway runtime, we don't need registry polling or pool refill.
         let runtime_handle = tokio::runtime::Runtime::new().unwrap().handle().clone();
@@ -123,7 +125,7 @@ impl XNetPayloadBuilderFixture {
     }
 
     /// Pools the provided slice coming from a given subnet and returns its byte
-    /// size, as evaluated by `UnpackedStreamSlice::count_bytes()`.
+    /// encoded size, as evaluated by `certified_slice_encoded_bytes()`.
     fn pool_slice(
         &self,
         subnet_id: SubnetId,
@@ -133,9 +135,7 @@ impl XNetPayloadBuilderFixture {
         log: &ReplicaLogger,
     ) -> usize {
         let certified_slice = in_slice(stream, from, from, msg_count, log);
-        let slice_size_bytes = UnpackedStreamSlice::try_from(certified_slice.clone())
-            .unwrap()
-            .count_bytes();
+        let slice_size_bytes = certified_slice_encoded_bytes(&certified_slice);
         pool_slice(&self.xnet_payload_builder, subnet_id, certified_slice);
         slice_size_bytes
     }
@@ -155,6 +155,11 @@ impl XNetPayloadBuilderFixture {
     fn slice_payload_size_stats(&self) -> HistogramStats {
         fetch_histogram_stats(&self.metrics, METRIC_SLICE_PAYLOAD_SIZE).unwrap()
     }
+
+    /// Fetches the `METRIC_PAYLOAD_BYTE_LIMIT_UTILIZATION` histogram's stats.
+    fn payload_byte_limit_utilization_stats(&self) -> HistogramStats {
+        fetch_histogram_stats(&self.metrics, METRIC_PAYLOAD_BYTE_LIMIT_UTILIZATION).unwrap()
+    }
 }
 
 /// Generates a `RegistryClient` with an own node record (the minimum necessary
@@ -356,8 +361,9 @@ proptest! {
             slice_bytes_sum += xnet_payload_builder.pool_slice(SUBNET_2, &stream2, from2, msg_count2, &log);
 
             // Build a payload with a byte limit just under the total size of the 2 slices.
+            let byte_limit = slice_bytes_sum - 1;
             let payload = xnet_payload_builder
-                .get_xnet_payload(slice_bytes_sum - 1);
+                .get_xnet_payload(byte_limit);
 
             // Payload should contain 2 slices.
             assert_eq!(
@@ -366,12 +372,13 @@ proptest! {
                 "Expecting 2 slices in payload, got {}",
                 payload.len()
             );
-            // And exactly one message should be missing.
+            // And at least one message should be missing, as the second slice
+            // is paged down until its encoded size fits the bytes left.
             let msg_count: usize = payload
                 .values()
                 .map(|slice| slice.messages().map(|m| m.len()).unwrap_or(0))
                 .sum();
-            assert_eq!(msg_count1 + msg_count2 - 1, msg_count);
+            assert!(msg_count < msg_count1 + msg_count2);
 
             assert_eq!(
                 metric_vec(&[(&[(LABEL_STATUS, STATUS_SUCCESS)], 1)]),
@@ -380,11 +387,16 @@ proptest! {
             assert_eq!(
                 HistogramStats {
                     count: 2,
-                    sum: (msg_count1 + msg_count2 - 1) as f64
+                    sum: msg_count as f64
                 },
                 xnet_payload_builder.slice_messages_stats()
             );
             assert_eq!(2, xnet_payload_builder.slice_payload_size_stats().count);
+            // The payload fills the byte limit almost completely, without exceeding it.
+            let utilization = xnet_payload_builder.payload_byte_limit_utilization_stats();
+            assert_eq!(1, utilization.count);
+            assert!(utilization.sum <= 1.0);
+            assert!(utilization.sum > 0.9);
         });
     }
 
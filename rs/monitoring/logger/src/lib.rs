@@ -5,8 +5,10 @@ use slog_scope::GlobalLoggerGuard;
 use std::io;
 use std::sync::{Arc, Mutex};
 
+pub mod message_context;
 pub mod replica_logger;
 pub use ic_context_logger::{debug, error, fatal, info, info_sample, log, new_logger, trace, warn};
+pub use message_context::{in_message_context, MessageContext, WithMessageContext};
 use replica_logger::LogEntryLogger;
 pub use replica_logger::ReplicaLogger;
 
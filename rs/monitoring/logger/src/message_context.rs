@@ -0,0 +1,242 @@
+//! Propagation of the message being handled to log entries.
+//!
+//! A message is typically handled by several components, e.g., it is
+//! validated by crypto, executed by the execution environment, and run by the
+//! embedder. To correlate the log entries created by all of them, the message
+//! ID, canister ID, and height of the message being handled are attached to
+//! every log entry as `message_context`.
+//!
+//! The context can be attached in two ways:
+//! * `in_message_context` sets the context of the current thread for the
+//!   duration of a closure. All log entries created on the thread in the
+//!   meantime, by any `ReplicaLogger`, include the context. This is how the
+//!   context reaches components that don't know which message they handle,
+//!   such as crypto.
+//! * `WithMessageContext::with_message_context` returns a logger that always
+//!   includes the context, e.g., for loggers handed over to other threads or
+//!   stored for the lifetime of an object.
+//!
+//! In both cases, fields that are not set are inherited from the enclosing
+//! context, so each layer only needs to set the fields it knows about.
+use crate::ReplicaLogger;
+use ic_protobuf::log::message_context_log_entry::v1::MessageContextLogEntry;
+use ic_types::{messages::MessageId, CanisterId, Height};
+use std::cell::RefCell;
+
+thread_local! {
+    /// The message context of the current thread.
+    static CURRENT_MESSAGE_CONTEXT: RefCell<Option<MessageContextLogEntry>> = RefCell::new(None);
+}
+
+/// Identifies the message that is being handled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageContext {
+    pub message_id: Option<MessageId>,
+    pub canister_id: Option<CanisterId>,
+    pub height: Option<Height>,
+}
+
+impl MessageContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_message_id(mut self, message_id: MessageId) -> Self {
+        self.message_id = Some(message_id);
+        self
+    }
+
+    pub fn with_canister_id(mut self, canister_id: CanisterId) -> Self {
+        self.canister_id = Some(canister_id);
+        self
+    }
+
+    pub fn with_height(mut self, height: Height) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Returns the log entry for this context, taking the fields that are not
+    /// set from `outer`.
+    fn merge_into(&self, outer: Option<&MessageContextLogEntry>) -> MessageContextLogEntry {
+        let mut entry = outer.cloned().unwrap_or_default();
+        if let Some(message_id) = &self.message_id {
+            entry.message_id = Some(message_id.to_string());
+        }
+        if let Some(canister_id) = &self.canister_id {
+            entry.canister_id = Some(canister_id.to_string());
+        }
+        if let Some(height) = self.height {
+            entry.height = Some(height.get());
+        }
+        entry
+    }
+}
+
+/// Runs `f` with `context` as the message context of the current thread.
+///
+/// All log entries created on the current thread while `f` runs include the
+/// context, unless the logger used has an explicit context overriding it.
+/// Contexts can be nested: the fields not set in `context` are inherited from
+/// the enclosing call.
+pub fn in_message_context<T, F: FnOnce() -> T>(context: &MessageContext, f: F) -> T {
+    let previous = CURRENT_MESSAGE_CONTEXT.with(|current| {
+        let merged = context.merge_into(current.borrow().as_ref());
+        current.replace(Some(merged))
+    });
+    // Restores the previous context even if `f` panics.
+    let _guard = RestoreMessageContext(previous);
+    f()
+}
+
+/// Returns the message context of the current thread, if any.
+pub fn current_message_context() -> Option<MessageContextLogEntry> {
+    CURRENT_MESSAGE_CONTEXT.with(|current| current.borrow().clone())
+}
+
+struct RestoreMessageContext(Option<MessageContextLogEntry>);
+
+impl Drop for RestoreMessageContext {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT_MESSAGE_CONTEXT.with(|current| current.replace(previous));
+    }
+}
+
+/// Attaches a `MessageContext` to a logger.
+pub trait WithMessageContext {
+    /// Returns a logger that includes `context` in all log entries. Fields
+    /// not set in `context` are kept from the context of `self`.
+    fn with_message_context(&self, context: &MessageContext) -> Self;
+}
+
+impl WithMessageContext for ReplicaLogger {
+    fn with_message_context(&self, context: &MessageContext) -> Self {
+        let mut log_entry = self.get_context();
+        log_entry.message_context = Some(context.merge_into(log_entry.message_context.as_ref()));
+        self.with_new_context(log_entry)
+    }
+}
+
+/// Merges the message context of the current thread into `explicit`, the
+/// context set on the logger, whose fields take precedence.
+pub(crate) fn effective_message_context(
+    explicit: Option<MessageContextLogEntry>,
+) -> Option<MessageContextLogEntry> {
+    match (current_message_context(), explicit) {
+        (None, explicit) => explicit,
+        (Some(current), None) => Some(current),
+        (Some(current), Some(explicit)) => Some(MessageContextLogEntry {
+            message_id: explicit.message_id.or(current.message_id),
+            canister_id: explicit.canister_id.or(current.canister_id),
+            height: explicit.height.or(current.height),
+        }),
+    }
+}
+
+/// Formats the context as a prefix of the log message, e.g.,
+/// `h:12/c:rwlgt-iiaaa-aaaaa-aaaaa-cai/m:4f2b.../`.
+pub(crate) fn message_context_prefix(context: &MessageContextLogEntry) -> String {
+    let mut prefix = String::new();
+    if let Some(height) = context.height {
+        prefix.push_str(&format!("h:{}/", height));
+    }
+    if let Some(canister_id) = &context.canister_id {
+        prefix.push_str(&format!("c:{}/", canister_id));
+    }
+    if let Some(message_id) = &context.message_id {
+        prefix.push_str(&format!("m:{}/", message_id));
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replica_logger::no_op_logger;
+    use ic_types::messages::EXPECTED_MESSAGE_ID_LENGTH;
+
+    fn message_id() -> MessageId {
+        MessageId::from([1; EXPECTED_MESSAGE_ID_LENGTH])
+    }
+
+    fn canister_id() -> CanisterId {
+        CanisterId::from_u64(42)
+    }
+
+    #[test]
+    fn nested_contexts_inherit_unset_fields() {
+        in_message_context(&MessageContext::new().with_height(Height::from(7)), || {
+            in_message_context(
+                &MessageContext::new()
+                    .with_canister_id(canister_id())
+                    .with_message_id(message_id()),
+                || {
+                    assert_eq!(
+                        current_message_context(),
+                        Some(MessageContextLogEntry {
+                            message_id: Some(message_id().to_string()),
+                            canister_id: Some(canister_id().to_string()),
+                            height: Some(7),
+                        })
+                    );
+                },
+            );
+            assert_eq!(
+                current_message_context(),
+                Some(MessageContextLogEntry {
+                    message_id: None,
+                    canister_id: None,
+                    height: Some(7),
+                })
+            );
+        });
+        assert_eq!(current_message_context(), None);
+    }
+
+    #[test]
+    fn context_is_restored_after_panic() {
+        let result = std::panic::catch_unwind(|| {
+            in_message_context(&MessageContext::new().with_height(Height::from(1)), || {
+                panic!("boom")
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(current_message_context(), None);
+    }
+
+    #[test]
+    fn explicit_logger_context_takes_precedence() {
+        let log = no_op_logger()
+            .with_message_context(&MessageContext::new().with_canister_id(canister_id()));
+        let explicit = log.get_context().message_context;
+
+        let effective = in_message_context(
+            &MessageContext::new()
+                .with_canister_id(CanisterId::from_u64(1))
+                .with_height(Height::from(3)),
+            || effective_message_context(explicit),
+        );
+        assert_eq!(
+            effective,
+            Some(MessageContextLogEntry {
+                message_id: None,
+                canister_id: Some(canister_id().to_string()),
+                height: Some(3),
+            })
+        );
+    }
+
+    #[test]
+    fn prefix_contains_only_set_fields() {
+        let context = MessageContextLogEntry {
+            message_id: None,
+            canister_id: Some(canister_id().to_string()),
+            height: Some(12),
+        };
+        assert_eq!(
+            message_context_prefix(&context),
+            format!("h:12/c:{}/", canister_id())
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::message_context::{effective_message_context, message_context_prefix};
 use ic_context_logger::{ContextLogger, LogMetadata, Logger};
 use ic_protobuf::log::log_entry::v1::LogEntry;
 use std::collections::HashMap;
@@ -95,12 +96,22 @@ impl Logger<LogEntry> for LogEntryLogger {
         log_entry.module = module.clone();
         log_entry.message = message.clone();
         log_entry.line = metadata.line;
+        log_entry.message_context = effective_message_context(log_entry.message_context.take());
 
         let net_context = format!("s:{}/n:{}/", log_entry.subnet_id, log_entry.node_id);
+        let message_context = log_entry
+            .message_context
+            .as_ref()
+            .map(message_context_prefix)
+            .unwrap_or_default();
 
-        // Example:
+        // Examples:
         // s:0/n:0/ic_consensus/certifier Received 0 hash(es) to be certified in 11.26µs
-        let message = format!("{}{}/{} {}", net_context, crate_, module, message);
+        // s:0/n:0/h:12/c:rwlgt-iiaaa-aaaaa-aaaaa-cai/ic_embedders/system_api Trapped
+        let message = format!(
+            "{}{}{}/{} {}",
+            net_context, message_context, crate_, module, message
+        );
 
         let kv = slog::o!("log_entry" => log_entry);
 
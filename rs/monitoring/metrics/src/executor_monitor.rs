@@ -0,0 +1,187 @@
+//! Metrics that tell executor starvation apart from slow subsystems.
+//!
+//! An [`ExecutorMonitor`] measures, for a single executor:
+//!
+//! * the scheduling delay of the tokio runtime, i.e. the time between
+//!   spawning a task and the task being polled for the first time, using a
+//!   probe task that is spawned periodically;
+//! * the depth of the queue of blocking tasks that were submitted but have not
+//!   started yet, and the time they spend in the queue. This works for the
+//!   tokio blocking pool as well as for other thread pools, see
+//!   [`ExecutorMonitor::enqueue`].
+//!
+//! All metrics carry an `executor` label, so that several executors can be
+//! monitored within the same process.
+
+use crate::buckets::decimal_buckets;
+use crate::MetricsRegistry;
+use prometheus::{Histogram, HistogramOpts, IntGauge, Opts};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// The time between two consecutive scheduling delay probes.
+pub const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Measures scheduling delays and queue depths of an executor.
+#[derive(Clone)]
+pub struct ExecutorMonitor {
+    scheduling_delay: Histogram,
+    queue_depth: IntGauge,
+    queue_delay: Histogram,
+}
+
+impl ExecutorMonitor {
+    /// Creates the metrics of the executor with the given name in
+    /// `metrics_registry`.
+    pub fn new(metrics_registry: &MetricsRegistry, executor: &str) -> Self {
+        // 1us - 5s
+        let delay_opts = |name: &str, help: &str| {
+            HistogramOpts::new(name, help)
+                .const_label("executor", executor)
+                .buckets(decimal_buckets(-6, 0))
+        };
+        Self {
+            scheduling_delay: metrics_registry.register(
+                Histogram::with_opts(delay_opts(
+                    "executor_scheduling_delay_seconds",
+                    "Time between spawning a task on the executor and its first poll, in seconds.",
+                ))
+                .unwrap(),
+            ),
+            queue_depth: metrics_registry.register(
+                IntGauge::with_opts(
+                    Opts::new(
+                        "executor_queue_depth",
+                        "Number of blocking tasks that were submitted to the executor but have not started yet.",
+                    )
+                    .const_label("executor", executor),
+                )
+                .unwrap(),
+            ),
+            queue_delay: metrics_registry.register(
+                Histogram::with_opts(delay_opts(
+                    "executor_queue_delay_seconds",
+                    "Time blocking tasks spend in the queue of the executor before they start, in seconds.",
+                ))
+                .unwrap(),
+            ),
+        }
+    }
+
+    /// Periodically spawns a probe task on `runtime` that records the
+    /// scheduling delay of the runtime. The returned task runs until it is
+    /// aborted or the runtime shuts down.
+    pub fn start_scheduling_probe(&self, runtime: &Handle) -> JoinHandle<()> {
+        let scheduling_delay = self.scheduling_delay.clone();
+        let probe_runtime = runtime.clone();
+        runtime.spawn(async move {
+            let mut interval = tokio::time::interval(PROBE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let spawned_at = Instant::now();
+                let scheduling_delay = scheduling_delay.clone();
+                probe_runtime.spawn(async move {
+                    scheduling_delay.observe(spawned_at.elapsed().as_secs_f64());
+                });
+            }
+        })
+    }
+
+    /// Like `tokio::task::spawn_blocking`, but records the time `f` spends in
+    /// the queue of the blocking pool.
+    pub fn spawn_blocking<F, R>(&self, f: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let queued = self.enqueue();
+        tokio::task::spawn_blocking(move || {
+            queued.start();
+            f()
+        })
+    }
+
+    /// Records that a task was submitted to the executor. The task must call
+    /// [`QueuedTask::start`] once it starts running; dropping the returned
+    /// value without starting removes the task from the queue depth without
+    /// recording a queue delay.
+    pub fn enqueue(&self) -> QueuedTask {
+        self.queue_depth.inc();
+        QueuedTask {
+            queue_depth: self.queue_depth.clone(),
+            queue_delay: self.queue_delay.clone(),
+            enqueued_at: Instant::now(),
+        }
+    }
+}
+
+/// A task in the queue of an executor, see [`ExecutorMonitor::enqueue`].
+pub struct QueuedTask {
+    queue_depth: IntGauge,
+    queue_delay: Histogram,
+    enqueued_at: Instant,
+}
+
+impl QueuedTask {
+    /// Records that the task left the queue and started running.
+    pub fn start(self) {
+        self.queue_delay
+            .observe(self.enqueued_at.elapsed().as_secs_f64());
+    }
+}
+
+impl Drop for QueuedTask {
+    fn drop(&mut self) {
+        self.queue_depth.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_depth_tracks_queued_tasks() {
+        let monitor = ExecutorMonitor::new(&MetricsRegistry::new(), "test");
+        let first = monitor.enqueue();
+        let second = monitor.enqueue();
+        assert_eq!(monitor.queue_depth.get(), 2);
+
+        first.start();
+        assert_eq!(monitor.queue_depth.get(), 1);
+        assert_eq!(monitor.queue_delay.get_sample_count(), 1);
+
+        drop(second);
+        assert_eq!(monitor.queue_depth.get(), 0);
+        assert_eq!(monitor.queue_delay.get_sample_count(), 1);
+    }
+
+    #[test]
+    fn executors_can_share_a_registry() {
+        let metrics_registry = MetricsRegistry::new();
+        let first = ExecutorMonitor::new(&metrics_registry, "first");
+        let second = ExecutorMonitor::new(&metrics_registry, "second");
+        first.enqueue().start();
+        assert_eq!(first.queue_delay.get_sample_count(), 1);
+        assert_eq!(second.queue_delay.get_sample_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn spawn_blocking_records_queue_delay() {
+        let monitor = ExecutorMonitor::new(&MetricsRegistry::new(), "test");
+        assert_eq!(monitor.spawn_blocking(|| 42).await.unwrap(), 42);
+        assert_eq!(monitor.queue_depth.get(), 0);
+        assert_eq!(monitor.queue_delay.get_sample_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn probe_records_scheduling_delays() {
+        let monitor = ExecutorMonitor::new(&MetricsRegistry::new(), "test");
+        let probe = monitor.start_scheduling_probe(&Handle::current());
+        tokio::time::sleep(3 * PROBE_INTERVAL).await;
+        tokio::task::yield_now().await;
+        probe.abort();
+        assert!(monitor.scheduling_delay.get_sample_count() >= 1);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod buckets;
+pub mod executor_monitor;
 #[cfg(target_os = "linux")]
 pub mod process_collector;
 pub mod registry;
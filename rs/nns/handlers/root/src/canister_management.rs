@@ -145,6 +145,7 @@ async fn install_code(
         compute_allocation: payload.compute_allocation,
         memory_allocation: payload.memory_allocation,
         query_allocation: payload.query_allocation,
+        skip_stable_compat_check: None,
     };
     // Warning: despite dfn_core::call returning a Result, it actually traps when
     // the callee traps! Use the public cdk instead, which does not have this
@@ -320,6 +321,7 @@ async fn try_to_create_and_install_canister(
         compute_allocation: payload.compute_allocation,
         memory_allocation: payload.memory_allocation,
         query_allocation: payload.query_allocation,
+        skip_stable_compat_check: None,
     };
     let install_res: Result<(), (Option<i32>, String)> = call(
         CanisterId::ic_00(),
@@ -50,6 +50,11 @@ pub struct NodeManagerArgs {
     /// The path to the version file.
     #[structopt(long, parse(from_os_str))]
     pub(crate) version_file: Option<PathBuf>,
+
+    /// Validate the Replica config file, print the diagnostics as JSON to
+    /// stdout and exit. Exits with a non-zero code if the config has errors.
+    #[structopt(long)]
+    pub(crate) validate_config: bool,
 }
 
 impl NodeManagerArgs {
@@ -81,6 +86,28 @@ impl NodeManagerArgs {
         Config::load_with_tmpdir(config_source, tmpdir)
     }
 
+    /// If `--validate-config` is set, validates `self.replica_config_file`,
+    /// prints the diagnostics and exits the process.
+    pub fn validate_config_if_requested(&self) {
+        if !self.validate_config {
+            return;
+        }
+        let tmpdir = tempfile::Builder::new()
+            .prefix("ic_config")
+            .tempdir()
+            .unwrap();
+        let diagnostics = Config::validate_source(
+            &ConfigSource::File(self.replica_config_file.clone()),
+            Config::new(tmpdir.path().to_path_buf()),
+        );
+        let valid = diagnostics.iter().all(|diagnostic| !diagnostic.is_error());
+        println!(
+            "{}",
+            serde_json::json!({ "valid": valid, "diagnostics": diagnostics })
+        );
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
     pub(crate) fn get_metrics_addr(&self) -> SocketAddr {
         self.metrics_listen_addr.unwrap_or_else(|| {
             SocketAddrV4::new("0.0.0.0".parse().expect("can't fail"), PROMETHEUS_HTTP_PORT).into()
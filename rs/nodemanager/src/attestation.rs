@@ -0,0 +1,318 @@
+//! Collection of hardware attestation evidence for the node.
+//!
+//! On machines running as an AMD SEV-SNP guest, the node manager obtains a
+//! measurement report from the secure processor that binds the node's public
+//! keys to the measured guest image. The report is included in the node
+//! registration, so that it can be verified independently of the node.
+//!
+//! Platforms without attestation support are represented by
+//! `NoAttestationPlatform`, for which collection is a no-op.
+
+use crate::error::{NodeManagerError, NodeManagerResult};
+use crate::metrics::NodeManagerMetrics;
+use ic_crypto_sha::Sha256;
+use ic_logger::{info, warn, ReplicaLogger};
+use ic_protobuf::crypto::v1::NodePublicKeys;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Size of the caller-provided data that is embedded in a SEV-SNP report.
+pub(crate) const REPORT_DATA_SIZE: usize = 64;
+
+/// Directory of the kernel's configfs-tsm interface for attestation reports.
+const TSM_REPORT_DIR: &str = "/sys/kernel/config/tsm/report";
+/// The configfs-tsm provider that backs SEV-SNP guests.
+const SEV_GUEST_PROVIDER: &str = "sev_guest";
+
+/// Label values of `node_attestation_reports_total`.
+const STATUS_COLLECTED: &str = "collected";
+const STATUS_FAILED: &str = "failed";
+
+/// A platform that is able to produce attestation reports.
+pub(crate) trait AttestationPlatform: Send + Sync {
+    /// A human readable name of the platform, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Returns whether the platform produces attestation reports at all.
+    fn is_supported(&self) -> bool;
+
+    /// Returns an attestation report embedding the given `report_data`, or
+    /// `None` if the platform does not support attestation.
+    fn collect_report(
+        &self,
+        report_data: &[u8; REPORT_DATA_SIZE],
+    ) -> NodeManagerResult<Option<Vec<u8>>>;
+}
+
+/// The platform of machines without attestation support.
+pub(crate) struct NoAttestationPlatform;
+
+impl AttestationPlatform for NoAttestationPlatform {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn is_supported(&self) -> bool {
+        false
+    }
+
+    fn collect_report(
+        &self,
+        _report_data: &[u8; REPORT_DATA_SIZE],
+    ) -> NodeManagerResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+/// An AMD SEV-SNP guest, whose reports are requested through the kernel's
+/// configfs-tsm interface.
+pub(crate) struct SevSnpPlatform {
+    report_dir: PathBuf,
+}
+
+impl SevSnpPlatform {
+    /// Returns the SEV-SNP platform if the kernel exposes the configfs-tsm
+    /// report interface, and `None` otherwise. Whether the interface is backed
+    /// by SEV-SNP is checked whenever a report is requested.
+    pub(crate) fn detect() -> Option<Self> {
+        let platform = Self {
+            report_dir: PathBuf::from(TSM_REPORT_DIR),
+        };
+        if platform.report_dir.is_dir() {
+            Some(platform)
+        } else {
+            None
+        }
+    }
+}
+
+impl AttestationPlatform for SevSnpPlatform {
+    fn name(&self) -> &'static str {
+        "sev-snp"
+    }
+
+    fn is_supported(&self) -> bool {
+        true
+    }
+
+    fn collect_report(
+        &self,
+        report_data: &[u8; REPORT_DATA_SIZE],
+    ) -> NodeManagerResult<Option<Vec<u8>>> {
+        // Each report is requested through a fresh entry, which the kernel
+        // populates with the report once `inblob` has been written.
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let entry = self.report_dir.join(format!("nodemanager-{}", nanos));
+        fs::create_dir(&entry).map_err(|e| NodeManagerError::dir_create_error(&entry, e))?;
+        let result = read_report(&entry, report_data);
+        // Leftover entries do not affect later reports, so the entry is
+        // removed on a best-effort basis.
+        let _ = fs::remove_dir(&entry);
+        result.map(Some)
+    }
+}
+
+fn read_report(entry: &Path, report_data: &[u8; REPORT_DATA_SIZE]) -> NodeManagerResult<Vec<u8>> {
+    let inblob = entry.join("inblob");
+    fs::write(&inblob, &report_data[..])
+        .map_err(|e| NodeManagerError::file_write_error(&inblob, e))?;
+
+    let provider = entry.join("provider");
+    let provider = fs::read_to_string(&provider)
+        .map_err(|e| NodeManagerError::file_open_error(&provider, e))?;
+    if provider.trim() != SEV_GUEST_PROVIDER {
+        return Err(NodeManagerError::AttestationError(format!(
+            "Unexpected attestation report provider: {:?}",
+            provider.trim()
+        )));
+    }
+
+    let outblob = entry.join("outblob");
+    fs::read(&outblob).map_err(|e| NodeManagerError::file_open_error(&outblob, e))
+}
+
+/// Returns the platform of this machine: SEV-SNP if available, and a no-op
+/// platform otherwise.
+pub(crate) fn detect_platform() -> Box<dyn AttestationPlatform> {
+    match SevSnpPlatform::detect() {
+        Some(platform) => Box::new(platform),
+        None => Box::new(NoAttestationPlatform),
+    }
+}
+
+/// Returns the data embedded in attestation reports of a node with the given
+/// public keys: the SHA-256 hash of the node signing key and the TLS
+/// certificate, padded with zeros.
+pub(crate) fn report_data_for_keys(node_pks: &NodePublicKeys) -> [u8; REPORT_DATA_SIZE] {
+    let mut hasher = Sha256::new();
+    if let Some(node_signing_pk) = &node_pks.node_signing_pk {
+        hasher.write(&node_signing_pk.key_value);
+    }
+    if let Some(tls_certificate) = &node_pks.tls_certificate {
+        hasher.write(&tls_certificate.certificate_der);
+    }
+    let digest = hasher.finish();
+
+    let mut report_data = [0; REPORT_DATA_SIZE];
+    report_data[..digest.len()].copy_from_slice(&digest);
+    report_data
+}
+
+/// Collects attestation reports from the platform and keeps the attestation
+/// metrics up to date.
+pub(crate) struct Attestation {
+    platform: Box<dyn AttestationPlatform>,
+    metrics: Arc<NodeManagerMetrics>,
+    logger: ReplicaLogger,
+}
+
+impl Attestation {
+    pub(crate) fn new(
+        platform: Box<dyn AttestationPlatform>,
+        metrics: Arc<NodeManagerMetrics>,
+        logger: ReplicaLogger,
+    ) -> Self {
+        info!(logger, "Attestation platform: {}", platform.name());
+        metrics
+            .node_attestation_supported
+            .set(platform.is_supported() as i64);
+        Self {
+            platform,
+            metrics,
+            logger,
+        }
+    }
+
+    /// Returns an attestation report binding the given node public keys, or
+    /// `None` if the platform does not support attestation or collection
+    /// failed.
+    pub(crate) fn collect_for_keys(&self, node_pks: &NodePublicKeys) -> Option<Vec<u8>> {
+        if !self.platform.is_supported() {
+            return None;
+        }
+        match self
+            .platform
+            .collect_report(&report_data_for_keys(node_pks))
+        {
+            Ok(report) => {
+                self.metrics
+                    .node_attestation_reports
+                    .with_label_values(&[STATUS_COLLECTED])
+                    .inc();
+                report
+            }
+            Err(e) => {
+                self.metrics
+                    .node_attestation_reports
+                    .with_label_values(&[STATUS_FAILED])
+                    .inc();
+                warn!(
+                    self.logger,
+                    "Failed to collect {} attestation report: {}",
+                    self.platform.name(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_metrics::MetricsRegistry;
+    use ic_protobuf::registry::crypto::v1::{PublicKey, X509PublicKeyCert};
+
+    struct FakePlatform {
+        result: fn() -> NodeManagerResult<Option<Vec<u8>>>,
+    }
+
+    impl AttestationPlatform for FakePlatform {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn is_supported(&self) -> bool {
+            true
+        }
+
+        fn collect_report(
+            &self,
+            report_data: &[u8; REPORT_DATA_SIZE],
+        ) -> NodeManagerResult<Option<Vec<u8>>> {
+            (self.result)().map(|report| report.map(|_| report_data.to_vec()))
+        }
+    }
+
+    fn node_pks(key: u8) -> NodePublicKeys {
+        NodePublicKeys {
+            node_signing_pk: Some(PublicKey {
+                key_value: vec![key; 32],
+                ..PublicKey::default()
+            }),
+            tls_certificate: Some(X509PublicKeyCert {
+                certificate_der: vec![1, 2, 3],
+            }),
+            ..NodePublicKeys::default()
+        }
+    }
+
+    fn attestation(platform: Box<dyn AttestationPlatform>) -> Attestation {
+        let metrics = Arc::new(NodeManagerMetrics::new(&MetricsRegistry::new()));
+        Attestation::new(platform, metrics, no_op_logger())
+    }
+
+    fn reports(attestation: &Attestation, status: &str) -> u64 {
+        attestation
+            .metrics
+            .node_attestation_reports
+            .with_label_values(&[status])
+            .get()
+    }
+
+    #[test]
+    fn report_data_binds_node_keys() {
+        let report_data = report_data_for_keys(&node_pks(1));
+        assert_ne!(report_data, report_data_for_keys(&node_pks(2)));
+        assert_eq!(report_data, report_data_for_keys(&node_pks(1)));
+        assert_eq!(report_data[32..], [0; 32]);
+    }
+
+    #[test]
+    fn unsupported_platform_is_a_no_op() {
+        let attestation = attestation(Box::new(NoAttestationPlatform));
+        assert_eq!(attestation.collect_for_keys(&node_pks(1)), None);
+        assert_eq!(attestation.metrics.node_attestation_supported.get(), 0);
+        assert_eq!(reports(&attestation, STATUS_COLLECTED), 0);
+        assert_eq!(reports(&attestation, STATUS_FAILED), 0);
+    }
+
+    #[test]
+    fn collected_report_embeds_report_data() {
+        let attestation = attestation(Box::new(FakePlatform {
+            result: || Ok(Some(vec![])),
+        }));
+        assert_eq!(
+            attestation.collect_for_keys(&node_pks(1)),
+            Some(report_data_for_keys(&node_pks(1)).to_vec())
+        );
+        assert_eq!(attestation.metrics.node_attestation_supported.get(), 1);
+        assert_eq!(reports(&attestation, STATUS_COLLECTED), 1);
+    }
+
+    #[test]
+    fn failed_collection_is_counted() {
+        let attestation = attestation(Box::new(FakePlatform {
+            result: || Err(NodeManagerError::AttestationError("test".to_string())),
+        }));
+        assert_eq!(attestation.collect_for_keys(&node_pks(1)), None);
+        assert_eq!(reports(&attestation, STATUS_FAILED), 1);
+    }
+}
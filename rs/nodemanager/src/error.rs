@@ -61,6 +61,9 @@ pub enum NodeManagerError {
 
     /// An error occurred with a release package
     ReleasePackageError(ReleaseError),
+
+    /// Hardware attestation evidence could not be collected
+    AttestationError(String),
 }
 
 impl NodeManagerError {
@@ -169,6 +172,7 @@ impl fmt::Display for NodeManagerError {
                 subnet_id, registry_version,
             ),
             NodeManagerError::UpgradeError(msg) => write!(f, "Failed to upgrade: {}", msg),
+            NodeManagerError::AttestationError(msg) => write!(f, "Attestation failed: {}", msg),
         }
     }
 }
@@ -33,6 +33,7 @@
 //! system to read.
 
 pub mod args;
+mod attestation;
 mod catch_up_package_provider;
 mod crypto_helper;
 mod error;
@@ -40,9 +41,13 @@ mod firewall;
 mod metrics;
 mod nns_registry_replicator;
 pub mod node_manager;
+mod node_reward_reporter;
 mod registration;
 mod registry_helper;
 mod release_package;
 mod release_package_provider;
+mod replica_cgroup;
+mod replica_output;
 mod replica_process;
+mod time_sync;
 mod utils;
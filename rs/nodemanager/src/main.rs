@@ -6,6 +6,7 @@ use structopt::StructOpt;
 #[tokio::main]
 async fn main() {
     let args = NodeManagerArgs::from_args();
+    args.validate_config_if_requested();
     let mut node_manager = NodeManager::start(args)
         .await
         .expect("Failed to start node manager");
@@ -1,4 +1,4 @@
-use prometheus::{IntCounter, IntGauge};
+use prometheus::{GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec};
 
 pub const PROMETHEUS_HTTP_PORT: u16 = 9091;
 
@@ -8,6 +8,48 @@ pub struct NodeManagerMetrics {
     pub resident_mem_used: IntGauge,
     /// Registry version last used to succesfully fetch datacenter information
     pub datacenter_registry_version: IntGauge,
+    /// Node heartbeats reported to the NNS, by status (`success`, `dropped`)
+    pub node_heartbeat_reports: IntCounterVec,
+    /// Errors while reporting node heartbeats, by kind
+    pub node_heartbeat_report_errors: IntCounterVec,
+    /// Creation time of the last heartbeat successfully reported to the NNS
+    pub node_heartbeat_last_reported_timestamp: IntGauge,
+    /// 1 if the node's platform supports hardware attestation, 0 otherwise
+    pub node_attestation_supported: IntGauge,
+    /// Attestation reports requested from the platform, by status
+    /// (`collected`, `failed`)
+    pub node_attestation_reports: IntCounterVec,
+    /// 1 if the replica runs in a cgroup with resource limits, 0 otherwise
+    pub replica_cgroup_enabled: IntGauge,
+    /// Memory currently used by the replica cgroup
+    pub replica_cgroup_memory_current_bytes: IntGauge,
+    /// Memory events of the replica cgroup since its creation, by event
+    /// (`high`, `max`, `oom`, `oom_kill`)
+    pub replica_cgroup_memory_events: IntGaugeVec,
+    /// CPU throttling of the replica cgroup since its creation, by stat
+    /// (`nr_throttled`, `throttled_usec`)
+    pub replica_cgroup_cpu_throttling: IntGaugeVec,
+    /// Time processes of the replica cgroup stalled waiting for a resource,
+    /// by resource (`cpu`, `memory`, `io`)
+    pub replica_cgroup_pressure_stall_seconds: GaugeVec,
+    /// Panics reported in the output of the replica
+    pub replica_panics: IntCounter,
+    /// Time of the last panic reported in the output of the replica
+    pub replica_last_panic_timestamp: IntGauge,
+    /// ERROR-level log lines in the output of the replica
+    pub replica_error_log_lines: IntCounter,
+    /// Bursts of ERROR-level log lines in the output of the replica
+    pub replica_error_bursts: IntCounter,
+    /// Restarts of the replica delayed because it is crash looping
+    pub replica_crash_loop_backoffs: IntCounter,
+    /// Offset of the time of an NTP server to the local clock, by server;
+    /// positive if the local clock is behind
+    pub time_sync_offset_seconds: GaugeVec,
+    /// Queries of NTP servers, by status (`success`, `failed`)
+    pub time_sync_queries: IntCounterVec,
+    /// 1 if the local clock is skewed by more than the tolerated offset, 0
+    /// otherwise
+    pub time_sync_degraded: IntGauge,
 }
 
 impl NodeManagerMetrics {
@@ -25,6 +67,86 @@ impl NodeManagerMetrics {
                 "datacenter_registry_version",
                 "Registry version last used to successfully fetch datacenter information",
             ),
+            node_heartbeat_reports: metrics_registry.int_counter_vec(
+                "node_heartbeat_reports_total",
+                "Number of node heartbeats reported to the NNS, by status",
+                &["status"],
+            ),
+            node_heartbeat_report_errors: metrics_registry.int_counter_vec(
+                "node_heartbeat_report_errors_total",
+                "Number of errors while reporting node heartbeats to the NNS, by kind",
+                &["kind"],
+            ),
+            node_heartbeat_last_reported_timestamp: metrics_registry.int_gauge(
+                "node_heartbeat_last_reported_timestamp_seconds",
+                "Creation time of the last node heartbeat successfully reported to the NNS, in seconds since the Unix epoch",
+            ),
+            node_attestation_supported: metrics_registry.int_gauge(
+                "node_attestation_supported",
+                "1 if the platform of the node supports hardware attestation (SEV-SNP), 0 otherwise",
+            ),
+            node_attestation_reports: metrics_registry.int_counter_vec(
+                "node_attestation_reports_total",
+                "Number of attestation reports requested from the platform, by status",
+                &["status"],
+            ),
+            replica_cgroup_enabled: metrics_registry.int_gauge(
+                "replica_cgroup_enabled",
+                "1 if the replica runs in a cgroup with resource limits, 0 otherwise",
+            ),
+            replica_cgroup_memory_current_bytes: metrics_registry.int_gauge(
+                "replica_cgroup_memory_current_bytes",
+                "Memory currently used by the processes of the replica cgroup, in bytes",
+            ),
+            replica_cgroup_memory_events: metrics_registry.int_gauge_vec(
+                "replica_cgroup_memory_events",
+                "Number of memory events of the replica cgroup since its creation, by event",
+                &["event"],
+            ),
+            replica_cgroup_cpu_throttling: metrics_registry.int_gauge_vec(
+                "replica_cgroup_cpu_throttling",
+                "CPU throttling of the replica cgroup since its creation, by stat",
+                &["stat"],
+            ),
+            replica_cgroup_pressure_stall_seconds: metrics_registry.gauge_vec(
+                "replica_cgroup_pressure_stall_seconds",
+                "Total time some processes of the replica cgroup stalled waiting for a resource, by resource",
+                &["resource"],
+            ),
+            replica_panics: metrics_registry.int_counter(
+                "replica_panics_total",
+                "Number of panics reported in the output of the replica",
+            ),
+            replica_last_panic_timestamp: metrics_registry.int_gauge(
+                "replica_last_panic_timestamp_seconds",
+                "Time of the last panic reported in the output of the replica, in seconds since the Unix epoch",
+            ),
+            replica_error_log_lines: metrics_registry.int_counter(
+                "replica_error_log_lines_total",
+                "Number of ERROR-level log lines in the output of the replica",
+            ),
+            replica_error_bursts: metrics_registry.int_counter(
+                "replica_error_bursts_total",
+                "Number of bursts of ERROR-level log lines in the output of the replica",
+            ),
+            replica_crash_loop_backoffs: metrics_registry.int_counter(
+                "replica_crash_loop_backoffs_total",
+                "Number of replica restarts delayed because the replica panicked repeatedly",
+            ),
+            time_sync_offset_seconds: metrics_registry.gauge_vec(
+                "time_sync_offset_seconds",
+                "Offset of the time of an NTP server to the local clock, by server, in seconds; positive if the local clock is behind",
+                &["server"],
+            ),
+            time_sync_queries: metrics_registry.int_counter_vec(
+                "time_sync_queries_total",
+                "Number of queries of NTP servers, by status",
+                &["status"],
+            ),
+            time_sync_degraded: metrics_registry.int_gauge(
+                "time_sync_degraded",
+                "1 if the local clock is skewed by more than the tolerated offset, 0 otherwise",
+            ),
         }
     }
 }
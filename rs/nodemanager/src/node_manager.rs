@@ -1,14 +1,19 @@
 use crate::args::NodeManagerArgs;
+use crate::attestation::{detect_platform, Attestation};
 use crate::catch_up_package_provider::CatchUpPackageProvider;
 use crate::crypto_helper::setup_crypto;
 use crate::firewall::Firewall;
 use crate::metrics::NodeManagerMetrics;
 use crate::nns_registry_replicator::NnsRegistryReplicator;
+use crate::node_reward_reporter::NodeRewardReporter;
 use crate::registration::NodeRegistration;
 use crate::registry_helper::RegistryHelper;
 use crate::release_package::ReleasePackage;
 use crate::release_package_provider::ReleasePackageProvider;
+use crate::replica_cgroup::ReplicaCgroup;
+use crate::replica_output::ReplicaOutputMonitor;
 use crate::replica_process::ReplicaProcess;
+use crate::time_sync::TimeSyncMonitor;
 use crate::utils;
 use ic_config::registry_client::DataProviderConfig;
 use ic_config::{
@@ -20,7 +25,7 @@ use ic_crypto::CryptoComponentForNonReplicaProcess;
 use ic_crypto_tls_interfaces::TlsHandshake;
 use ic_interfaces::{crypto::KeyManager, registry::RegistryClient};
 use ic_logger::{info, new_replica_logger, warn, LoggerImpl, ReplicaLogger};
-use ic_metrics::MetricsRegistry;
+use ic_metrics::{executor_monitor::ExecutorMonitor, MetricsRegistry};
 use ic_metrics_exporter::MetricsRuntimeImpl;
 use ic_registry_common::local_store::{LocalStore, LocalStoreImpl};
 use slog_async::AsyncGuard;
@@ -37,6 +42,9 @@ pub struct NodeManager {
     // for tokio 1.0+ we can use `tokio::task::JoinHandle`
     release_package: Arc<std::sync::atomic::AtomicBool>,
     firewall: Arc<std::sync::atomic::AtomicBool>,
+    node_reward_reporter: Arc<std::sync::atomic::AtomicBool>,
+    replica_cgroup: Arc<std::sync::atomic::AtomicBool>,
+    time_sync: Arc<std::sync::atomic::AtomicBool>,
     replica_process: Arc<Mutex<ReplicaProcess>>,
 }
 
@@ -52,6 +60,13 @@ impl NodeManager {
     /// data centers. If a new data center is added, node manager will
     /// generate a new firewall configuration allowing access from the
     /// IP range specified in the DC record.
+    ///
+    /// If enabled in the configuration, a fourth task periodically reports
+    /// heartbeats signed with the node signing key to the NNS for node
+    /// rewards, a fifth task exports the resource usage of the replica
+    /// cgroup as metrics, and a sixth task monitors the offset of the local
+    /// clock to NTP servers. The replica start can be delayed until the
+    /// clock is synchronized.
     pub async fn start(args: NodeManagerArgs) -> Result<Self, ()> {
         args.create_dirs();
         let metrics_addr = args.get_metrics_addr();
@@ -106,13 +121,23 @@ impl NodeManager {
             crypto.clone(),
         );
         let metrics = Arc::new(metrics);
+        ExecutorMonitor::new(&metrics_registry, "node_manager")
+            .start_scheduling_probe(&tokio::runtime::Handle::current());
+
+        let attestation = Arc::new(Attestation::new(
+            detect_platform(),
+            Arc::clone(&metrics),
+            logger.clone(),
+        ));
 
         let mut registration = NodeRegistration::new(
             logger.clone(),
+            node_id,
             config.clone(),
             Arc::clone(&registry.registry_client),
             Arc::clone(&crypto) as Arc<dyn KeyManager>,
             registry_local_store.clone(),
+            attestation,
         );
         // initialize the registry local store. Will not return if the nns is not
         // reachable.
@@ -150,7 +175,19 @@ impl NodeManager {
         ));
 
         let slog_logger = logger.inner_logger.root.clone();
-        let replica_process = Arc::new(Mutex::new(ReplicaProcess::new(slog_logger.clone())));
+        let replica_cgroup = ReplicaCgroup::new(
+            config.replica_cgroup.clone(),
+            Arc::clone(&metrics),
+            logger.clone(),
+        );
+        let replica_process = Arc::new(Mutex::new(ReplicaProcess::new(
+            slog_logger.clone(),
+            replica_cgroup.setup(),
+            Arc::new(ReplicaOutputMonitor::new(
+                Arc::clone(&metrics),
+                slog_logger.clone(),
+            )),
+        )));
         let ic_binary_directory = args
             .ic_binary_directory
             .as_ref()
@@ -158,6 +195,12 @@ impl NodeManager {
             .clone();
         let mut fallback_version_file = ic_binary_directory.clone();
         fallback_version_file.push("version.txt");
+        let time_sync = TimeSyncMonitor::new(
+            config.time_sync.clone(),
+            Arc::clone(&metrics),
+            logger.clone(),
+        );
+        time_sync.delay_replica_start_until_synchronized().await;
         let release_package = ReleasePackage::start(
             Arc::clone(&registry),
             replica_process.clone(),
@@ -183,6 +226,17 @@ impl NodeManager {
             logger.clone(),
         )
         .start();
+        let node_reward_reporter = NodeRewardReporter::new(
+            Arc::clone(&registry),
+            crypto.clone(),
+            node_id,
+            config.node_reward_reporter.clone(),
+            Arc::clone(&metrics),
+            logger.clone(),
+        )
+        .start();
+        let replica_cgroup = replica_cgroup.start();
+        let time_sync = time_sync.start();
         Ok(Self {
             logger,
             _async_log_guard,
@@ -191,6 +245,9 @@ impl NodeManager {
             release_package,
             replica_process,
             firewall,
+            node_reward_reporter,
+            replica_cgroup,
+            time_sync,
         })
     }
 
@@ -206,6 +263,15 @@ impl NodeManager {
         self.firewall
             .as_ref()
             .store(false, std::sync::atomic::Ordering::Relaxed);
+        self.node_reward_reporter
+            .as_ref()
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        self.replica_cgroup
+            .as_ref()
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        self.time_sync
+            .as_ref()
+            .store(false, std::sync::atomic::Ordering::Relaxed);
         let e = self.replica_process.clone().lock().unwrap().stop();
         warn!(self.logger, "unable to stop replica: {:?}", e);
     }
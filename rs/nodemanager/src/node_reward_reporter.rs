@@ -0,0 +1,345 @@
+use crate::metrics::NodeManagerMetrics;
+use crate::registration::generate_nonce;
+use crate::registry_helper::RegistryHelper;
+use candid::{CandidType, Encode};
+use ic_canister_client::{Agent, Sender};
+use ic_config::node_reward_reporter::Config as NodeRewardReporterConfig;
+use ic_interfaces::crypto::BasicSigner;
+use ic_logger::{info, warn, ReplicaLogger};
+use ic_registry_client::helper::subnet::SubnetRegistry;
+use ic_types::crypto::SignedBytesWithoutDomainSeparator;
+use ic_types::node_rewards::{NodeHeartbeatContent, SignedNodeHeartbeat};
+use ic_types::time::current_time;
+use ic_types::{CanisterId, NodeId, PrincipalId, RegistryVersion};
+use rand::prelude::*;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Label values of `node_heartbeat_reports_total`.
+pub(crate) const STATUS_SUCCESS: &str = "success";
+pub(crate) const STATUS_DROPPED: &str = "dropped";
+/// Label values of `node_heartbeat_report_errors_total`.
+pub(crate) const ERROR_SIGN: &str = "sign";
+pub(crate) const ERROR_NO_NNS_URLS: &str = "no_nns_urls";
+pub(crate) const ERROR_SEND: &str = "send";
+
+/// The argument of the configured method receiving the heartbeats.
+///
+/// The content is passed as the bytes that were signed (without the domain
+/// separator), so that the canister can verify the signature against the
+/// node signing key in the registry before decoding them.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub(crate) struct ReportNodeHeartbeatPayload {
+    pub node_id: PrincipalId,
+    pub registry_version: u64,
+    pub content: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl From<&SignedNodeHeartbeat> for ReportNodeHeartbeatPayload {
+    fn from(heartbeat: &SignedNodeHeartbeat) -> Self {
+        Self {
+            node_id: heartbeat.content.node_id.get(),
+            registry_version: heartbeat.content.registry_version.get(),
+            content: heartbeat.content.as_signed_bytes_without_domain_separator(),
+            signature: heartbeat.signature.get_ref().0.clone(),
+        }
+    }
+}
+
+/// Periodically reports a heartbeat signed with the node signing key to the
+/// NNS, as verifiable evidence of the node's up-time for node rewards.
+///
+/// Delivery of a heartbeat is retried with exponential backoff against
+/// randomly chosen NNS nodes. Heartbeats that cannot be delivered within
+/// `max_attempts` are dropped; the next heartbeat supersedes them.
+pub(crate) struct NodeRewardReporter {
+    registry: Arc<RegistryHelper>,
+    signer: Arc<dyn BasicSigner<NodeHeartbeatContent> + Send + Sync>,
+    node_id: NodeId,
+    config: NodeRewardReporterConfig,
+    /// The canister and method that receive the heartbeats.
+    endpoint: Option<(CanisterId, String)>,
+    metrics: Arc<NodeManagerMetrics>,
+    logger: ReplicaLogger,
+    started_at: Instant,
+    sequence_number: u64,
+
+    // If false, do not start or terminate the background task
+    enabled: Arc<AtomicBool>,
+}
+
+impl NodeRewardReporter {
+    pub(crate) fn new(
+        registry: Arc<RegistryHelper>,
+        signer: Arc<dyn BasicSigner<NodeHeartbeatContent> + Send + Sync>,
+        node_id: NodeId,
+        config: NodeRewardReporterConfig,
+        metrics: Arc<NodeManagerMetrics>,
+        logger: ReplicaLogger,
+    ) -> Self {
+        let endpoint = match (&config.canister_id, &config.method_name) {
+            (Some(canister_id), Some(method_name)) => match CanisterId::from_str(canister_id) {
+                Ok(canister_id) => Some((canister_id, method_name.clone())),
+                Err(e) => {
+                    warn!(
+                        logger,
+                        "Invalid canister id {} for node heartbeats: {:?}", canister_id, e
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+        if !config.enabled {
+            info!(
+                logger,
+                "Node reward reporting is disabled. Node manager does not report heartbeats."
+            );
+        } else if endpoint.is_none() {
+            warn!(
+                logger,
+                "No canister is configured to receive node heartbeats. Node manager does not report heartbeats."
+            );
+        }
+        let enabled = Arc::new(AtomicBool::new(config.enabled && endpoint.is_some()));
+        Self {
+            registry,
+            signer,
+            node_id,
+            config,
+            endpoint,
+            metrics,
+            logger,
+            started_at: Instant::now(),
+            sequence_number: 0,
+            enabled,
+        }
+    }
+
+    pub(crate) fn start(self) -> Arc<AtomicBool> {
+        let result = self.enabled.clone();
+        tokio::spawn(background_task(self));
+        result
+    }
+
+    /// Creates the next heartbeat and signs it with the node signing key at
+    /// the given registry version.
+    fn sign_next_heartbeat(
+        &mut self,
+        registry_version: RegistryVersion,
+    ) -> Option<SignedNodeHeartbeat> {
+        let content = NodeHeartbeatContent {
+            node_id: self.node_id,
+            registry_version,
+            timestamp: current_time(),
+            sequence_number: self.sequence_number,
+            uptime_seconds: self.started_at.elapsed().as_secs(),
+        };
+        self.sequence_number += 1;
+
+        match self
+            .signer
+            .sign_basic(&content, self.node_id, registry_version)
+        {
+            Ok(signature) => Some(SignedNodeHeartbeat { content, signature }),
+            Err(e) => {
+                self.metrics
+                    .node_heartbeat_report_errors
+                    .with_label_values(&[ERROR_SIGN])
+                    .inc();
+                warn!(
+                    self.logger,
+                    "Failed to sign node heartbeat at registry version {}: {:?}",
+                    registry_version,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns the HTTP endpoints of all NNS nodes, in random order.
+    fn nns_urls(&self, registry_version: RegistryVersion) -> Vec<Url> {
+        let nns_subnet_id = match self
+            .registry
+            .registry_client
+            .get_root_subnet_id(registry_version)
+        {
+            Ok(Some(subnet_id)) => subnet_id,
+            other => {
+                warn!(
+                    self.logger,
+                    "Failed to get the NNS subnet id at registry version {}: {:?}",
+                    registry_version,
+                    other
+                );
+                return vec![];
+            }
+        };
+        let mut urls: Vec<Url> = self
+            .registry
+            .get_node_urls(nns_subnet_id, registry_version)
+            .into_iter()
+            .flatten()
+            .collect();
+        urls.shuffle(&mut thread_rng());
+        urls
+    }
+
+    /// Delivers the heartbeat to the NNS, retrying with exponential backoff
+    /// up to `max_attempts` times. Returns whether the delivery succeeded.
+    async fn report(&self, heartbeat: &SignedNodeHeartbeat) -> bool {
+        let (canister_id, method_name) = match &self.endpoint {
+            Some(endpoint) => endpoint,
+            None => return false,
+        };
+        let urls = self.nns_urls(heartbeat.content.registry_version);
+        if urls.is_empty() {
+            self.metrics
+                .node_heartbeat_report_errors
+                .with_label_values(&[ERROR_NO_NNS_URLS])
+                .inc();
+            return false;
+        }
+        let payload = Encode!(&ReportNodeHeartbeatPayload::from(heartbeat))
+            .expect("Could not encode node heartbeat payload.");
+
+        for (attempt, url) in (0..self.config.max_attempts).zip(urls.iter().cycle()) {
+            if attempt > 0 {
+                tokio::time::sleep(retry_backoff(&self.config, attempt)).await;
+            }
+            let agent = Agent::new(url.clone(), Sender::Anonymous);
+            match agent
+                .execute_update(canister_id, method_name, payload.clone(), generate_nonce())
+                .await
+            {
+                Ok(_) => return true,
+                Err(e) => {
+                    self.metrics
+                        .node_heartbeat_report_errors
+                        .with_label_values(&[ERROR_SEND])
+                        .inc();
+                    warn!(
+                        self.logger,
+                        "Failed to report node heartbeat {} to {} (attempt {}/{}): {}",
+                        heartbeat.content.sequence_number,
+                        url,
+                        attempt + 1,
+                        self.config.max_attempts,
+                        e
+                    );
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Returns the delay before the given retry (`attempt >= 1`): the initial
+/// backoff doubled for every further retry, capped at the maximum backoff.
+fn retry_backoff(config: &NodeRewardReporterConfig, attempt: u32) -> Duration {
+    let backoff_ms = 2u64
+        .checked_pow(attempt.saturating_sub(1))
+        .and_then(|factor| config.initial_retry_backoff_ms.checked_mul(factor))
+        .unwrap_or(u64::MAX)
+        .min(config.max_retry_backoff_ms);
+    Duration::from_millis(backoff_ms)
+}
+
+async fn background_task(mut reporter: NodeRewardReporter) {
+    let interval = Duration::from_secs(reporter.config.report_interval_secs);
+    loop {
+        if !reporter.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let registry_version = reporter.registry.get_latest_version();
+        if let Some(heartbeat) = reporter.sign_next_heartbeat(registry_version) {
+            if reporter.report(&heartbeat).await {
+                reporter
+                    .metrics
+                    .node_heartbeat_reports
+                    .with_label_values(&[STATUS_SUCCESS])
+                    .inc();
+                reporter.metrics.node_heartbeat_last_reported_timestamp.set(
+                    (heartbeat.content.timestamp.as_nanos_since_unix_epoch() / 1_000_000_000)
+                        as i64,
+                );
+            } else {
+                reporter
+                    .metrics
+                    .node_heartbeat_reports
+                    .with_label_values(&[STATUS_DROPPED])
+                    .inc();
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_interfaces::crypto::Signable;
+    use ic_test_utilities::types::ids::node_test_id;
+    use ic_types::crypto::{BasicSig, BasicSigOf};
+    use ic_types::time::UNIX_EPOCH;
+
+    fn config(initial_ms: u64, max_ms: u64) -> NodeRewardReporterConfig {
+        NodeRewardReporterConfig {
+            initial_retry_backoff_ms: initial_ms,
+            max_retry_backoff_ms: max_ms,
+            ..NodeRewardReporterConfig::default()
+        }
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_maximum() {
+        let config = config(1_000, 10_000);
+        let backoffs: Vec<_> = (1..=6)
+            .map(|attempt| retry_backoff(&config, attempt).as_millis())
+            .collect();
+        assert_eq!(backoffs, vec![1_000, 2_000, 4_000, 8_000, 10_000, 10_000]);
+    }
+
+    #[test]
+    fn retry_backoff_does_not_overflow() {
+        let config = config(u64::MAX / 2, 3_600_000);
+        assert_eq!(retry_backoff(&config, 3), Duration::from_millis(3_600_000));
+        assert_eq!(
+            retry_backoff(&config, 100),
+            Duration::from_millis(3_600_000)
+        );
+    }
+
+    #[test]
+    fn payload_contains_signed_bytes() {
+        let content = NodeHeartbeatContent {
+            node_id: node_test_id(1),
+            registry_version: RegistryVersion::from(7),
+            timestamp: UNIX_EPOCH,
+            sequence_number: 3,
+            uptime_seconds: 42,
+        };
+        let heartbeat = SignedNodeHeartbeat {
+            content: content.clone(),
+            signature: BasicSigOf::new(BasicSig(vec![1, 2, 3])),
+        };
+
+        let payload = ReportNodeHeartbeatPayload::from(&heartbeat);
+
+        assert_eq!(payload.node_id, node_test_id(1).get());
+        assert_eq!(payload.registry_version, 7);
+        assert_eq!(payload.signature, vec![1, 2, 3]);
+        // The signed bytes are the content prefixed with the domain separator.
+        let signed_bytes = content.as_signed_bytes();
+        assert!(signed_bytes.ends_with(&payload.content));
+        assert!(signed_bytes.len() > payload.content.len());
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use crate::attestation::Attestation;
 use crate::error::{NodeManagerError, NodeManagerResult};
 use candid::Encode;
 use ic_canister_client::{Agent, Sender};
@@ -11,19 +12,22 @@ use ic_config::{
 };
 use ic_crypto_utils_threshold_sig::parse_threshold_sig_key;
 use ic_interfaces::crypto::KeyManager;
-use ic_interfaces::registry::{RegistryClient, ZERO_REGISTRY_VERSION};
+use ic_interfaces::registry::{RegistryClient, RegistryTransportRecord, ZERO_REGISTRY_VERSION};
 use ic_logger::{info, warn, ReplicaLogger};
 use ic_nns_constants::REGISTRY_CANISTER_ID;
+use ic_registry_common::certified_snapshot::read_certified_snapshot;
 use ic_registry_common::local_store::{
     Changelog, ChangelogEntry, KeyMutation, LocalStoreImpl, LocalStoreReader, LocalStoreWriter,
 };
 use ic_registry_common::registry::RegistryCanister;
 use ic_sys::utility_command::UtilityCommand;
 use ic_types::transport::TransportConfig;
-use ic_types::RegistryVersion;
+use ic_types::{NodeId, RegistryVersion, Time};
 use prost::Message;
 use rand::prelude::*;
-use registry_canister::mutations::do_add_node::AddNodePayload;
+use registry_canister::mutations::{
+    do_add_node::AddNodePayload, do_update_node_public_keys::UpdateNodePublicKeysPayload,
+};
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
@@ -31,27 +35,33 @@ use url::Url;
 
 pub(crate) struct NodeRegistration {
     log: ReplicaLogger,
+    node_id: NodeId,
     node_config: Config,
     registry_client: Arc<dyn RegistryClient>,
     key_manager: Arc<dyn KeyManager>,
     local_store: Arc<LocalStoreImpl>,
+    attestation: Arc<Attestation>,
 }
 
 impl NodeRegistration {
     ///
     pub(crate) fn new(
         log: ReplicaLogger,
+        node_id: NodeId,
         node_config: Config,
         registry_client: Arc<dyn RegistryClient>,
         key_manager: Arc<dyn KeyManager>,
         local_store: Arc<LocalStoreImpl>,
+        attestation: Arc<Attestation>,
     ) -> Self {
         Self {
             log,
+            node_id,
             node_config,
             registry_client,
             key_manager,
             local_store,
+            attestation,
         }
     }
 
@@ -59,7 +69,9 @@ impl NodeRegistration {
     /// registered already.
     ///
     /// If the node has not been registered, retries registering the node using
-    /// one of the nns nodes in `nns_node_list`.
+    /// one of the nns nodes in `nns_node_list`. If the node is registered with
+    /// different keys, e.g. because they were rotated, its keys and
+    /// attestation report are updated instead.
     pub(crate) async fn register_node(&mut self) {
         self.initialize_local_store().await;
 
@@ -92,16 +104,6 @@ impl NodeRegistration {
             .expect("Could not read registry local store.")
             .is_empty()
         {
-            let nns_urls = self
-                .node_config
-                .registration
-                .nns_url
-                .clone()
-                .expect("Registry Local Store is empty and no NNS Url configured.")
-                .split(',')
-                .map(|s| Url::parse(s).expect("Could not parse registration NNS url from config"))
-                .collect::<Vec<Url>>();
-
             let nns_pub_key_path = self
                 .node_config
                 .registration
@@ -112,6 +114,37 @@ impl NodeRegistration {
             let nns_pub_key = parse_threshold_sig_key(&nns_pub_key_path)
                 .expect("Could not parse configured NNS Public Key file.");
 
+            // A snapshot shipped with the node image allows to set up the
+            // local store without a reachable NNS. Later versions are fetched
+            // incrementally by the registry replicator.
+            if let Some(snapshot_file) = &self.node_config.registration.nns_registry_snapshot_file {
+                match read_certified_snapshot(snapshot_file, &nns_pub_key) {
+                    Ok((records, t)) => {
+                        info!(
+                            self.log,
+                            "Initializing registry local store from snapshot {:?}", snapshot_file
+                        );
+                        self.store_certified_records(records, t);
+                        return;
+                    }
+                    Err(e) => warn!(
+                        self.log,
+                        "Could not initialize registry local store from snapshot, falling back to the NNS: {}",
+                        e
+                    ),
+                }
+            }
+
+            let nns_urls = self
+                .node_config
+                .registration
+                .nns_url
+                .clone()
+                .expect("Registry Local Store is empty and no NNS Url configured.")
+                .split(',')
+                .map(|s| Url::parse(s).expect("Could not parse registration NNS url from config"))
+                .collect::<Vec<Url>>();
+
             let registry_canister = RegistryCanister::new(nns_urls);
             while self
                 .local_store
@@ -125,29 +158,7 @@ impl NodeRegistration {
                 {
                     Ok((mut records, _, t)) if !records.is_empty() => {
                         records.sort_by_key(|tr| tr.version);
-                        let changelog = records.iter().fold(Changelog::default(), |mut cl, r| {
-                            let rel_version = (r.version - ZERO_REGISTRY_VERSION).get();
-                            if cl.len() < rel_version as usize {
-                                cl.push(ChangelogEntry::default());
-                            }
-                            cl.last_mut().unwrap().push(KeyMutation {
-                                key: r.key.clone(),
-                                value: r.value.clone(),
-                            });
-                            cl
-                        });
-
-                        changelog
-                            .into_iter()
-                            .enumerate()
-                            .try_for_each(|(i, cle)| {
-                                let v = ZERO_REGISTRY_VERSION + RegistryVersion::from(i as u64 + 1);
-                                self.local_store.store(v, cle)
-                            })
-                            .expect("Could not write to local store.");
-                        self.local_store
-                            .update_certified_time(t.as_nanos_since_unix_epoch())
-                            .expect("Could not store certified time");
+                        self.store_certified_records(records, t);
                         return;
                     }
                     Err(e) => warn!(
@@ -161,6 +172,34 @@ impl NodeRegistration {
         }
     }
 
+    /// Writes the given records, sorted by version and starting at version 1,
+    /// to the local store, together with the time they were certified at.
+    fn store_certified_records(&self, records: Vec<RegistryTransportRecord>, t: Time) {
+        let changelog = records.iter().fold(Changelog::default(), |mut cl, r| {
+            let rel_version = (r.version - ZERO_REGISTRY_VERSION).get();
+            if cl.len() < rel_version as usize {
+                cl.push(ChangelogEntry::default());
+            }
+            cl.last_mut().unwrap().push(KeyMutation {
+                key: r.key.clone(),
+                value: r.value.clone(),
+            });
+            cl
+        });
+
+        changelog
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(i, cle)| {
+                let v = ZERO_REGISTRY_VERSION + RegistryVersion::from(i as u64 + 1);
+                self.local_store.store(v, cle)
+            })
+            .expect("Could not write to local store.");
+        self.local_store
+            .update_certified_time(t.as_nanos_since_unix_epoch())
+            .expect("Could not store certified time");
+    }
+
     // postcondition: we are registered with the NNS
     async fn retry_register_node(&mut self) {
         let mut version = self.registry_client.get_latest_version();
@@ -216,7 +255,26 @@ impl NodeRegistration {
             res
         };
 
-        let add_node_payload = self.assemble_add_node_message();
+        // A node whose record exists already only needs its keys replaced.
+        let (method_name, payload) = if self
+            .registry_client
+            .get_transport_info(self.node_id, version)
+            .ok()
+            .flatten()
+            .is_some()
+        {
+            (
+                "update_node_public_keys",
+                Encode!(&self.assemble_update_node_public_keys_message())
+                    .expect("Could not encode payload for update_node_public_keys-call."),
+            )
+        } else {
+            (
+                "add_node",
+                Encode!(&self.assemble_add_node_message())
+                    .expect("Could not encode payload for add_node-call."),
+            )
+        };
 
         let read_public_key = UtilityCommand::read_public_key(None, None);
         let hsm_pub_key = loop {
@@ -249,14 +307,16 @@ impl NodeRegistration {
             if let Err(e) = agent
                 .execute_update(
                     &REGISTRY_CANISTER_ID,
-                    "add_node",
-                    Encode!(&add_node_payload)
-                        .expect("Could not encode payload for add_node-call."),
+                    method_name,
+                    payload.clone(),
                     generate_nonce(),
                 )
                 .await
             {
-                warn!(self.log, "Error when sending add node request: {:?}", e);
+                warn!(
+                    self.log,
+                    "Error when sending {} request: {:?}", method_name, e
+                );
             };
             tokio::time::sleep(Duration::from_secs(2)).await;
         }
@@ -264,6 +324,7 @@ impl NodeRegistration {
 
     fn assemble_add_node_message(&self) -> AddNodePayload {
         let node_pub_keys = self.key_manager.node_public_keys();
+        let attestation_report = self.attestation.collect_for_keys(&node_pub_keys);
 
         AddNodePayload {
             // These four are raw bytes because sadly we can't marshal between pb and candid...
@@ -291,6 +352,22 @@ impl NodeRegistration {
                 &self.node_config.metrics,
             )
             .expect("Invalid endpoints in metrics config."),
+            attestation_report,
+        }
+    }
+
+    fn assemble_update_node_public_keys_message(&self) -> UpdateNodePublicKeysPayload {
+        let node_pub_keys = self.key_manager.node_public_keys();
+        let attestation_report = self.attestation.collect_for_keys(&node_pub_keys);
+
+        UpdateNodePublicKeysPayload {
+            node_signing_pk: protobuf_to_vec(node_pub_keys.node_signing_pk.unwrap()),
+            committee_signing_pk: protobuf_to_vec(node_pub_keys.committee_signing_pk.unwrap()),
+            ni_dkg_dealing_encryption_pk: protobuf_to_vec(
+                node_pub_keys.dkg_dealing_encryption_pk.unwrap(),
+            ),
+            transport_tls_cert: protobuf_to_vec(node_pub_keys.tls_certificate.unwrap()),
+            attestation_report,
         }
     }
 
@@ -403,7 +480,7 @@ fn get_endpoint(log: &ReplicaLogger, ip_addr: String, port: u16) -> NodeManagerR
 
 /// Create a nonce to be included with the ingress message sent to the node
 /// handler.
-fn generate_nonce() -> Vec<u8> {
+pub(crate) fn generate_nonce() -> Vec<u8> {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
@@ -449,6 +526,7 @@ mod tests {
                     queue_size: 1,
                 },
             ],
+            multiplex_flows: false,
         };
 
         with_test_replica_logger(|log| {
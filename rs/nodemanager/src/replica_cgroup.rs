@@ -0,0 +1,367 @@
+use crate::{
+    error::{NodeManagerError, NodeManagerResult},
+    metrics::NodeManagerMetrics,
+};
+use ic_config::replica_cgroup::Config as ReplicaCgroupConfig;
+use ic_logger::{info, warn, ReplicaLogger};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The controllers needed to enforce the limits of the replica cgroup.
+const CONTROLLERS: &str = "+memory +cpu +io";
+
+/// Label values of `replica_cgroup_memory_events`, as listed in
+/// `memory.events`.
+const MEMORY_EVENTS: &[&str] = &["high", "max", "oom", "oom_kill"];
+/// Label values of `replica_cgroup_cpu_throttling`, as listed in `cpu.stat`.
+const CPU_THROTTLING_STATS: &[&str] = &["nr_throttled", "throttled_usec"];
+/// Label values of `replica_cgroup_pressure_stall_seconds`.
+const PRESSURE_RESOURCES: &[&str] = &["cpu", "memory", "io"];
+
+/// Runs the replica in a cgroup (v2) that limits its memory, CPU and IO
+/// usage, and exports the throttling events of the cgroup as metrics.
+pub(crate) struct ReplicaCgroup {
+    config: ReplicaCgroupConfig,
+    metrics: Arc<NodeManagerMetrics>,
+    logger: ReplicaLogger,
+
+    // If false, do not start or terminate the background task
+    enabled: Arc<AtomicBool>,
+}
+
+impl ReplicaCgroup {
+    pub(crate) fn new(
+        config: ReplicaCgroupConfig,
+        metrics: Arc<NodeManagerMetrics>,
+        logger: ReplicaLogger,
+    ) -> Self {
+        let enabled = Arc::new(AtomicBool::new(config.enabled));
+        Self {
+            config,
+            metrics,
+            logger,
+            enabled,
+        }
+    }
+
+    /// Creates the cgroup and applies the configured limits. Returns the path
+    /// of the cgroup to run the replica in, or `None` if the cgroup is
+    /// disabled or could not be set up. In the latter case, the replica runs
+    /// without limits.
+    pub(crate) fn setup(&self) -> Option<PathBuf> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        match self.create_and_apply_limits() {
+            Ok(()) => {
+                info!(
+                    self.logger,
+                    "Running the replica in cgroup {:?}", self.config.cgroup_path
+                );
+                self.metrics.replica_cgroup_enabled.set(1);
+                Some(self.config.cgroup_path.clone())
+            }
+            Err(e) => {
+                warn!(
+                    self.logger,
+                    "Failed to set up the replica cgroup, running the replica without limits: {}",
+                    e
+                );
+                self.enabled.store(false, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn create_and_apply_limits(&self) -> NodeManagerResult<()> {
+        let cgroup_path = &self.config.cgroup_path;
+        if let Err(e) = fs::create_dir(cgroup_path) {
+            if e.kind() != io::ErrorKind::AlreadyExists {
+                return Err(NodeManagerError::IoError(
+                    format!("Failed to create cgroup {:?}", cgroup_path),
+                    e,
+                ));
+            }
+        }
+        // Make the controllers available in the cgroup. This fails if they
+        // are not available in the parent either, in which case writing the
+        // limits below fails too.
+        if let Some(parent) = cgroup_path.parent() {
+            let subtree_control = parent.join("cgroup.subtree_control");
+            if let Err(e) = fs::write(&subtree_control, CONTROLLERS) {
+                warn!(
+                    self.logger,
+                    "Failed to enable cgroup controllers in {:?}: {}", subtree_control, e
+                );
+            }
+        }
+
+        let memory_max = match self.config.memory_max_bytes {
+            Some(bytes) => bytes.to_string(),
+            None => "max".to_string(),
+        };
+        write_cgroup_file(cgroup_path, "memory.max", &memory_max)?;
+        if let Some(cpu_weight) = self.config.cpu_weight {
+            write_cgroup_file(cgroup_path, "cpu.weight", &cpu_weight.to_string())?;
+        }
+        // `io.max` takes one device per write.
+        for limit in &self.config.io_max {
+            write_cgroup_file(cgroup_path, "io.max", &limit.to_io_max_line())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn start(self) -> Arc<AtomicBool> {
+        let result = self.enabled.clone();
+        tokio::spawn(background_task(self));
+        result
+    }
+
+    /// Updates the cgroup metrics from the cgroup's statistics files.
+    fn observe(&self) {
+        let cgroup_path = &self.config.cgroup_path;
+        if let Ok(contents) = fs::read_to_string(cgroup_path.join("memory.current")) {
+            if let Ok(bytes) = contents.trim().parse::<i64>() {
+                self.metrics.replica_cgroup_memory_current_bytes.set(bytes);
+            }
+        }
+        if let Ok(contents) = fs::read_to_string(cgroup_path.join("memory.events")) {
+            let events = parse_flat_keyed(&contents);
+            for event in MEMORY_EVENTS {
+                if let Some(count) = events.get(*event) {
+                    self.metrics
+                        .replica_cgroup_memory_events
+                        .with_label_values(&[*event])
+                        .set(*count as i64);
+                }
+            }
+        }
+        if let Ok(contents) = fs::read_to_string(cgroup_path.join("cpu.stat")) {
+            let stats = parse_flat_keyed(&contents);
+            for stat in CPU_THROTTLING_STATS {
+                if let Some(value) = stats.get(*stat) {
+                    self.metrics
+                        .replica_cgroup_cpu_throttling
+                        .with_label_values(&[*stat])
+                        .set(*value as i64);
+                }
+            }
+        }
+        for resource in PRESSURE_RESOURCES {
+            let path = cgroup_path.join(format!("{}.pressure", resource));
+            if let Some(total_usec) = fs::read_to_string(path)
+                .ok()
+                .and_then(|contents| parse_pressure_total(&contents))
+            {
+                self.metrics
+                    .replica_cgroup_pressure_stall_seconds
+                    .with_label_values(&[*resource])
+                    .set(total_usec as f64 / 1_000_000.0);
+            }
+        }
+    }
+}
+
+async fn background_task(cgroup: ReplicaCgroup) {
+    let interval = Duration::from_secs(cgroup.config.metrics_interval_secs);
+    loop {
+        if !cgroup.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        cgroup.observe();
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn write_cgroup_file(cgroup_path: &Path, file: &str, value: &str) -> NodeManagerResult<()> {
+    let path = cgroup_path.join(file);
+    fs::write(&path, value).map_err(|e| NodeManagerError::file_write_error(&path, e))
+}
+
+/// Makes the process spawned by `command` move itself into the cgroup at
+/// `cgroup_path` before it executes the program, so that all processes it
+/// spawns are subject to the limits of the cgroup.
+pub(crate) fn move_into_cgroup_on_exec(
+    command: &mut Command,
+    cgroup_path: &Path,
+) -> io::Result<()> {
+    // The file is opened before forking, as only async-signal-safe operations
+    // may be performed between fork and exec. Writing `0` moves the writing
+    // process.
+    let procs = fs::OpenOptions::new()
+        .write(true)
+        .open(cgroup_path.join("cgroup.procs"))?;
+    unsafe {
+        command.pre_exec(move || (&procs).write_all(b"0"));
+    }
+    Ok(())
+}
+
+/// Parses a flat keyed cgroup file such as `memory.events` or `cpu.stat`,
+/// consisting of lines of the form `<key> <value>`.
+fn parse_flat_keyed(contents: &str) -> BTreeMap<&str, u64> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let key = fields.next()?;
+            let value = fields.next()?.parse().ok()?;
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Returns the total stall time in microseconds of the `some` line of a
+/// pressure stall information file, e.g. `io.pressure`.
+fn parse_pressure_total(contents: &str) -> Option<u64> {
+    contents
+        .lines()
+        .find(|line| line.starts_with("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("total="))?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_config::replica_cgroup::IoLimit;
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_metrics::MetricsRegistry;
+
+    fn replica_cgroup(config: ReplicaCgroupConfig) -> ReplicaCgroup {
+        ReplicaCgroup::new(
+            config,
+            Arc::new(NodeManagerMetrics::new(&MetricsRegistry::new())),
+            no_op_logger(),
+        )
+    }
+
+    #[test]
+    fn setup_writes_limits() {
+        let tmpdir = tempfile::Builder::new()
+            .prefix("test_replica_cgroup")
+            .tempdir()
+            .unwrap();
+        let cgroup_path = tmpdir.path().join("ic-replica");
+        let cgroup = replica_cgroup(ReplicaCgroupConfig {
+            enabled: true,
+            cgroup_path: cgroup_path.clone(),
+            memory_max_bytes: Some(1 << 30),
+            cpu_weight: Some(500),
+            io_max: vec![IoLimit {
+                device: "8:0".to_string(),
+                rbps: None,
+                wbps: Some(1 << 20),
+                riops: None,
+                wiops: None,
+            }],
+            ..ReplicaCgroupConfig::default()
+        });
+
+        assert_eq!(cgroup.setup(), Some(cgroup_path.clone()));
+        let read = |file: &str| fs::read_to_string(cgroup_path.join(file)).unwrap();
+        assert_eq!(read("memory.max"), "1073741824");
+        assert_eq!(read("cpu.weight"), "500");
+        assert_eq!(
+            read("io.max"),
+            "8:0 rbps=max wbps=1048576 riops=max wiops=max"
+        );
+        assert_eq!(
+            fs::read_to_string(tmpdir.path().join("cgroup.subtree_control")).unwrap(),
+            CONTROLLERS
+        );
+        assert_eq!(cgroup.metrics.replica_cgroup_enabled.get(), 1);
+    }
+
+    #[test]
+    fn setup_is_skipped_if_disabled() {
+        let cgroup = replica_cgroup(ReplicaCgroupConfig::default());
+        assert_eq!(cgroup.setup(), None);
+    }
+
+    #[test]
+    fn setup_failure_disables_cgroup() {
+        let cgroup = replica_cgroup(ReplicaCgroupConfig {
+            enabled: true,
+            cgroup_path: PathBuf::from("/this/path/does/not/exist/ic-replica"),
+            ..ReplicaCgroupConfig::default()
+        });
+        assert_eq!(cgroup.setup(), None);
+        assert!(!cgroup.enabled.load(Ordering::Relaxed));
+        assert_eq!(cgroup.metrics.replica_cgroup_enabled.get(), 0);
+    }
+
+    #[test]
+    fn observe_exports_throttling_events() {
+        let tmpdir = tempfile::Builder::new()
+            .prefix("test_replica_cgroup")
+            .tempdir()
+            .unwrap();
+        let write =
+            |file: &str, contents: &str| fs::write(tmpdir.path().join(file), contents).unwrap();
+        write("memory.current", "4096\n");
+        write("memory.events", "low 0\nhigh 2\nmax 5\noom 1\noom_kill 1\n");
+        write(
+            "cpu.stat",
+            "usage_usec 100\nuser_usec 60\nsystem_usec 40\nnr_periods 10\nnr_throttled 3\nthrottled_usec 1500\n",
+        );
+        write(
+            "io.pressure",
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total=2500000\nfull avg10=0.00 avg60=0.00 avg300=0.00 total=1000000\n",
+        );
+        let cgroup = replica_cgroup(ReplicaCgroupConfig {
+            enabled: true,
+            cgroup_path: tmpdir.path().to_path_buf(),
+            ..ReplicaCgroupConfig::default()
+        });
+
+        cgroup.observe();
+
+        let metrics = &cgroup.metrics;
+        assert_eq!(metrics.replica_cgroup_memory_current_bytes.get(), 4096);
+        let memory_event = |event| {
+            metrics
+                .replica_cgroup_memory_events
+                .with_label_values(&[event])
+                .get()
+        };
+        assert_eq!(memory_event("max"), 5);
+        assert_eq!(memory_event("oom_kill"), 1);
+        let cpu_stat = |stat| {
+            metrics
+                .replica_cgroup_cpu_throttling
+                .with_label_values(&[stat])
+                .get()
+        };
+        assert_eq!(cpu_stat("nr_throttled"), 3);
+        assert_eq!(cpu_stat("throttled_usec"), 1500);
+        assert!(
+            (metrics
+                .replica_cgroup_pressure_stall_seconds
+                .with_label_values(&["io"])
+                .get()
+                - 2.5)
+                .abs()
+                < f64::EPSILON
+        );
+    }
+
+    #[test]
+    fn parses_pressure_total() {
+        assert_eq!(
+            parse_pressure_total("some avg10=1.00 avg60=0.50 avg300=0.10 total=42\n"),
+            Some(42)
+        );
+        assert_eq!(parse_pressure_total("full avg10=1.00 total=42\n"), None);
+        assert_eq!(parse_pressure_total(""), None);
+    }
+}
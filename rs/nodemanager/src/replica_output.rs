@@ -0,0 +1,311 @@
+//! Monitors the output of the replica process.
+//!
+//! The node manager forwards the stdout and stderr of the replica to its own
+//! and inspects every line on the way. Panics and bursts of ERROR-level log
+//! lines are exported as metrics, so that they are visible before (and even
+//! if) they make the replica exit. If the replica panics repeatedly within a
+//! short time, it is considered to be crash looping and its restarts are
+//! delayed.
+use crate::metrics::NodeManagerMetrics;
+use slog::{info, warn};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The default panic hook prints a line like
+/// `thread 'main' panicked at 'message', src/main.rs:1:1`.
+const PANIC_PREFIX: &str = "thread '";
+const PANIC_SIGNATURE: &str = "' panicked at";
+
+/// Canisters can write arbitrary text to the output of the replica with
+/// `ic0.debug_print`, which the replica prefixes with `[Canister <id>]`.
+const CANISTER_OUTPUT_PREFIX: &str = "[Canister ";
+
+/// The level markers of ERROR- and CRITICAL-level lines written by `slog-term`.
+const ERROR_SIGNATURES: &[&str] = &[" ERRO ", " CRIT "];
+
+/// At least `ERROR_BURST_THRESHOLD` ERROR-level lines within
+/// `ERROR_BURST_WINDOW` are considered a burst.
+const ERROR_BURST_WINDOW: Duration = Duration::from_secs(10);
+const ERROR_BURST_THRESHOLD: usize = 100;
+
+/// At least `CRASH_LOOP_THRESHOLD` panics within `CRASH_LOOP_WINDOW` are
+/// considered a crash loop.
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(10 * 60);
+const CRASH_LOOP_THRESHOLD: usize = 3;
+
+/// How long restarts of a crash looping replica are delayed, in addition to
+/// the usual delay.
+pub(crate) const CRASH_LOOP_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq, Eq)]
+enum LineKind {
+    Panic,
+    Error,
+    Other,
+}
+
+fn classify(line: &str) -> LineKind {
+    if line.starts_with(CANISTER_OUTPUT_PREFIX) {
+        LineKind::Other
+    } else if is_panic(line) {
+        LineKind::Panic
+    } else if ERROR_SIGNATURES.iter().any(|level| line.contains(level)) {
+        LineKind::Error
+    } else {
+        LineKind::Other
+    }
+}
+
+/// Returns true if `line` is of the form `thread '<name>' panicked at ...`.
+fn is_panic(line: &str) -> bool {
+    line.strip_prefix(PANIC_PREFIX)
+        .map_or(false, |rest| rest.contains(PANIC_SIGNATURE))
+}
+
+#[derive(Default)]
+struct MonitorState {
+    recent_panics: VecDeque<Instant>,
+    recent_errors: VecDeque<Instant>,
+    in_error_burst: bool,
+}
+
+/// Removes the instants that are at least `window` older than `now`.
+fn expire(instants: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while let Some(oldest) = instants.front() {
+        if now.saturating_duration_since(*oldest) < window {
+            break;
+        }
+        instants.pop_front();
+    }
+}
+
+pub(crate) struct ReplicaOutputMonitor {
+    metrics: Arc<NodeManagerMetrics>,
+    log: slog::Logger,
+    state: Mutex<MonitorState>,
+}
+
+impl ReplicaOutputMonitor {
+    pub(crate) fn new(metrics: Arc<NodeManagerMetrics>, log: slog::Logger) -> Self {
+        Self {
+            metrics,
+            log,
+            state: Default::default(),
+        }
+    }
+
+    /// Spawns a thread that copies `output` line by line to `forward_to` and
+    /// observes every line.
+    pub(crate) fn spawn_forwarder<R, W>(
+        self: &Arc<Self>,
+        output: R,
+        mut forward_to: W,
+    ) -> std::thread::JoinHandle<()>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let monitor = Arc::clone(self);
+        std::thread::spawn(move || {
+            let mut output = BufReader::new(output);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match output.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        let _ = forward_to.write_all(&line);
+                        monitor.observe_line(&String::from_utf8_lossy(&line), Instant::now());
+                    }
+                    Err(e) => {
+                        warn!(monitor.log, "Failed to read replica output: {:?}", e);
+                        break;
+                    }
+                }
+            }
+            let _ = forward_to.flush();
+        })
+    }
+
+    fn observe_line(&self, line: &str, now: Instant) {
+        match classify(line) {
+            LineKind::Panic => self.observe_panic(now),
+            LineKind::Error => self.observe_error(now),
+            LineKind::Other => {}
+        }
+    }
+
+    fn observe_panic(&self, now: Instant) {
+        self.metrics.replica_panics.inc();
+        if let Ok(since_epoch) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            self.metrics
+                .replica_last_panic_timestamp
+                .set(since_epoch.as_secs() as i64);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.recent_panics.push_back(now);
+        expire(&mut state.recent_panics, now, CRASH_LOOP_WINDOW);
+    }
+
+    fn observe_error(&self, now: Instant) {
+        self.metrics.replica_error_log_lines.inc();
+        let mut state = self.state.lock().unwrap();
+        state.recent_errors.push_back(now);
+        expire(&mut state.recent_errors, now, ERROR_BURST_WINDOW);
+        if state.recent_errors.len() >= ERROR_BURST_THRESHOLD {
+            if !state.in_error_burst {
+                state.in_error_burst = true;
+                self.metrics.replica_error_bursts.inc();
+                info!(
+                    self.log,
+                    "Replica logged {} errors within {:?}",
+                    state.recent_errors.len(),
+                    ERROR_BURST_WINDOW
+                );
+            }
+        } else {
+            state.in_error_burst = false;
+        }
+    }
+
+    /// Returns true if the replica panicked at least `CRASH_LOOP_THRESHOLD`
+    /// times within the last `CRASH_LOOP_WINDOW`.
+    pub(crate) fn is_crash_looping(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        expire(&mut state.recent_panics, now, CRASH_LOOP_WINDOW);
+        state.recent_panics.len() >= CRASH_LOOP_THRESHOLD
+    }
+
+    pub(crate) fn record_crash_loop_backoff(&self) {
+        self.metrics.replica_crash_loop_backoffs.inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_metrics::MetricsRegistry;
+
+    fn monitor() -> ReplicaOutputMonitor {
+        ReplicaOutputMonitor::new(
+            Arc::new(NodeManagerMetrics::new(&MetricsRegistry::new())),
+            slog::Logger::root(slog::Discard, slog::o!()),
+        )
+    }
+
+    #[test]
+    fn lines_are_classified() {
+        assert_eq!(
+            classify("thread 'main' panicked at 'oops', src/main.rs:1:1\n"),
+            LineKind::Panic
+        );
+        assert_eq!(
+            classify("Oct 16 12:00:00.000 ERRO s:abc/n:def/ic_replica Failed\n"),
+            LineKind::Error
+        );
+        assert_eq!(
+            classify("Oct 16 12:00:00.000 CRIT s:abc/n:def/ic_replica Failed\n"),
+            LineKind::Error
+        );
+        assert_eq!(
+            classify("Oct 16 12:00:00.000 INFO s:abc/n:def/ic_replica ERROR\n"),
+            LineKind::Other
+        );
+        assert_eq!(
+            classify("Oct 16 12:00:00.000 INFO failed: thread 'main' panicked at 'oops'\n"),
+            LineKind::Other
+        );
+    }
+
+    #[test]
+    fn canister_output_is_ignored() {
+        assert_eq!(
+            classify("[Canister rwlgt-iiaaa-aaaaa-aaaaa-cai] thread 'main' panicked at 'oops'\n"),
+            LineKind::Other
+        );
+        assert_eq!(
+            classify("[Canister rwlgt-iiaaa-aaaaa-aaaaa-cai]  ERRO  failed\n"),
+            LineKind::Other
+        );
+    }
+
+    #[test]
+    fn panics_are_counted() {
+        let monitor = monitor();
+        monitor.observe_line("thread 'main' panicked at 'oops'", Instant::now());
+        assert_eq!(monitor.metrics.replica_panics.get(), 1);
+        assert!(monitor.metrics.replica_last_panic_timestamp.get() > 0);
+    }
+
+    #[test]
+    fn error_burst_is_counted_once() {
+        let monitor = monitor();
+        let now = Instant::now();
+        for _ in 0..2 * ERROR_BURST_THRESHOLD {
+            monitor.observe_line(" ERRO failed", now);
+        }
+        assert_eq!(
+            monitor.metrics.replica_error_log_lines.get(),
+            2 * ERROR_BURST_THRESHOLD as u64
+        );
+        assert_eq!(monitor.metrics.replica_error_bursts.get(), 1);
+
+        // Once the errors calm down, the next burst is counted again.
+        let later = now + ERROR_BURST_WINDOW;
+        monitor.observe_line(" ERRO failed", later);
+        for _ in 0..ERROR_BURST_THRESHOLD {
+            monitor.observe_line(" ERRO failed", later);
+        }
+        assert_eq!(monitor.metrics.replica_error_bursts.get(), 2);
+    }
+
+    #[test]
+    fn spread_out_errors_are_no_burst() {
+        let monitor = monitor();
+        let now = Instant::now();
+        for i in 0..2 * ERROR_BURST_THRESHOLD {
+            monitor.observe_line(" ERRO failed", now + ERROR_BURST_WINDOW / 10 * i as u32);
+        }
+        assert_eq!(monitor.metrics.replica_error_bursts.get(), 0);
+    }
+
+    #[test]
+    fn repeated_panics_are_a_crash_loop() {
+        let monitor = monitor();
+        let now = Instant::now();
+        for _ in 0..CRASH_LOOP_THRESHOLD - 1 {
+            monitor.observe_line("thread 'main' panicked at 'oops'", now);
+        }
+        assert!(!monitor.is_crash_looping(now));
+        monitor.observe_line("thread 'main' panicked at 'oops'", now);
+        assert!(monitor.is_crash_looping(now));
+        assert!(!monitor.is_crash_looping(now + CRASH_LOOP_WINDOW));
+    }
+
+    #[test]
+    fn forwarder_copies_output() {
+        let monitor = Arc::new(monitor());
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+
+        struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+        impl Write for SharedWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output: &[u8] = b"starting\nthread 'main' panicked at 'oops'\nno newline";
+        monitor
+            .spawn_forwarder(output, SharedWriter(Arc::clone(&forwarded)))
+            .join()
+            .unwrap();
+
+        assert_eq!(&forwarded.lock().unwrap()[..], output);
+        assert_eq!(monitor.metrics.replica_panics.get(), 1);
+    }
+}
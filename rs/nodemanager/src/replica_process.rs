@@ -1,10 +1,15 @@
+use crate::replica_cgroup::move_into_cgroup_on_exec;
+use crate::replica_output::{ReplicaOutputMonitor, CRASH_LOOP_BACKOFF};
 use ic_types::ReplicaVersion;
 use nix::{
     sys::signal::{self, Signal},
     unistd::Pid,
 };
 use slog::{debug, info, warn};
+use std::path::PathBuf;
+use std::process::Stdio;
 use std::sync::Mutex;
+use std::time::Instant;
 use std::{io::Result, sync::Arc};
 
 type PIDCell = Arc<Mutex<Option<Pid>>>;
@@ -24,16 +29,26 @@ pub(crate) struct ReplicaProcess {
     pub(crate) log: slog::Logger,
     pub(crate) join_handle: Option<std::thread::JoinHandle<()>>,
     pub(crate) stopping: bool,
+    /// The cgroup to run the replica in, if any.
+    pub(crate) cgroup_path: Option<PathBuf>,
+    /// Observes the output of the replica.
+    pub(crate) output_monitor: Arc<ReplicaOutputMonitor>,
 }
 
 impl ReplicaProcess {
-    pub(crate) fn new(logger: slog::Logger) -> Self {
+    pub(crate) fn new(
+        logger: slog::Logger,
+        cgroup_path: Option<PathBuf>,
+        output_monitor: Arc<ReplicaOutputMonitor>,
+    ) -> Self {
         Self {
             command: None,
             pid_cell: Default::default(),
             log: logger.clone(),
             join_handle: None,
             stopping: false,
+            cgroup_path,
+            output_monitor,
         }
     }
 
@@ -162,10 +177,22 @@ impl ReplicaProcess {
                 &replica_version,
                 &args
             );
-            let child = std::process::Command::new(replica_binary)
-                .args(&args)
-                .spawn()?;
+            let mut command = std::process::Command::new(replica_binary);
+            command.args(&args);
+            if let Some(cgroup_path) = &self.cgroup_path {
+                move_into_cgroup_on_exec(&mut command, cgroup_path)?;
+            }
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+            let mut child = command.spawn()?;
             debug!(self.log, "🚀 Process started. Pid: {}", child.id());
+            if let Some(stdout) = child.stdout.take() {
+                self.output_monitor
+                    .spawn_forwarder(stdout, std::io::stdout());
+            }
+            if let Some(stderr) = child.stderr.take() {
+                self.output_monitor
+                    .spawn_forwarder(stderr, std::io::stderr());
+            }
             self.set_pid(Pid::from_raw(child.id() as i32));
 
             self.join_handle = Some(std::thread::spawn(wait_on_exit(
@@ -183,6 +210,18 @@ impl ReplicaProcess {
             let join_handle = replica_process.lock().unwrap().join_handle.take();
             if let Some(join_handle) = join_handle {
                 join_handle.join().expect("join failed");
+                let (output_monitor, log) = {
+                    let guard = replica_process.lock().unwrap();
+                    (Arc::clone(&guard.output_monitor), guard.log.clone())
+                };
+                if output_monitor.is_crash_looping(Instant::now()) {
+                    output_monitor.record_crash_loop_backoff();
+                    warn!(
+                        log,
+                        "Replica panicked repeatedly, delaying restart by {:?}", CRASH_LOOP_BACKOFF
+                    );
+                    std::thread::sleep(CRASH_LOOP_BACKOFF);
+                }
             };
             let mut replica_process_guard = replica_process.lock().unwrap();
             if replica_process_guard.stopping {
@@ -0,0 +1,396 @@
+//! Monitors the synchronization of the local clock.
+//!
+//! The replica takes the timestamps of the blocks it proposes and validates
+//! from the local clock, so a badly skewed clock makes consensus misbehave
+//! without any visible error. The node manager periodically measures the
+//! offset of the local clock to NTP servers (SNTP, RFC 4330), exports the
+//! offsets as metrics and flags the node as degraded if the median offset
+//! exceeds the configured threshold. Optionally, the start of the replica is
+//! delayed until the clock is synchronized.
+use crate::metrics::NodeManagerMetrics;
+use ic_config::time_sync::Config as TimeSyncConfig;
+use ic_logger::{info, warn, ReplicaLogger};
+use std::convert::TryInto;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{lookup_host, UdpSocket};
+
+const NTP_PACKET_LEN: usize = 48;
+
+/// Seconds from the NTP epoch (1900-01-01) to the Unix epoch (1970-01-01).
+const NTP_UNIX_EPOCH_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// The first byte of a client request: leap indicator 0, version 4 and mode
+/// 3 (client).
+const NTP_CLIENT_REQUEST_HEADER: u8 = 0b00_100_011;
+const NTP_MODE_SERVER: u8 = 4;
+
+/// Offsets of the timestamps in an NTP packet.
+const NTP_ORIGINATE_TIMESTAMP: usize = 24;
+const NTP_RECEIVE_TIMESTAMP: usize = 32;
+const NTP_TRANSMIT_TIMESTAMP: usize = 40;
+
+#[derive(Debug, PartialEq)]
+enum ClockStatus {
+    /// The median offset is within the threshold.
+    Synchronized,
+    /// The median offset, in seconds, exceeds the threshold.
+    Skewed(f64),
+    /// No NTP server responded.
+    Unknown,
+}
+
+pub(crate) struct TimeSyncMonitor {
+    config: TimeSyncConfig,
+    metrics: Arc<NodeManagerMetrics>,
+    logger: ReplicaLogger,
+
+    // If false, do not start or terminate the background task
+    enabled: Arc<AtomicBool>,
+}
+
+impl TimeSyncMonitor {
+    pub(crate) fn new(
+        config: TimeSyncConfig,
+        metrics: Arc<NodeManagerMetrics>,
+        logger: ReplicaLogger,
+    ) -> Self {
+        let enabled = Arc::new(AtomicBool::new(config.enabled));
+        Self {
+            config,
+            metrics,
+            logger,
+            enabled,
+        }
+    }
+
+    /// If configured, waits until the clock is synchronized, but at most
+    /// `max_replica_start_delay_secs`.
+    pub(crate) async fn delay_replica_start_until_synchronized(&self) {
+        if !self.enabled.load(Ordering::Relaxed) || !self.config.delay_replica_start {
+            return;
+        }
+        let max_delay = Duration::from_secs(self.config.max_replica_start_delay_secs);
+        let start = Instant::now();
+        while self.check().await != ClockStatus::Synchronized {
+            if start.elapsed() >= max_delay {
+                warn!(
+                    self.logger,
+                    "Starting the replica although the clock is not synchronized after {:?}",
+                    max_delay
+                );
+                return;
+            }
+            info!(
+                self.logger,
+                "Delaying the replica start until the clock is synchronized"
+            );
+            tokio::time::sleep(Duration::from_secs(self.config.check_interval_secs)).await;
+        }
+    }
+
+    pub(crate) fn start(self) -> Arc<AtomicBool> {
+        let result = self.enabled.clone();
+        tokio::spawn(background_task(self));
+        result
+    }
+
+    /// Queries all NTP servers and updates the offset metrics and the
+    /// degraded flag.
+    async fn check(&self) -> ClockStatus {
+        let timeout = Duration::from_millis(self.config.query_timeout_ms);
+        let mut offsets = Vec::new();
+        for server in &self.config.ntp_servers {
+            match query_offset(server, timeout).await {
+                Ok(offset) => {
+                    self.metrics
+                        .time_sync_queries
+                        .with_label_values(&["success"])
+                        .inc();
+                    self.metrics
+                        .time_sync_offset_seconds
+                        .with_label_values(&[server])
+                        .set(offset);
+                    offsets.push(offset);
+                }
+                Err(e) => {
+                    self.metrics
+                        .time_sync_queries
+                        .with_label_values(&["failed"])
+                        .inc();
+                    warn!(self.logger, "Failed to query NTP server {}: {}", server, e);
+                }
+            }
+        }
+
+        let max_offset = Duration::from_millis(self.config.max_offset_ms).as_secs_f64();
+        let status = match median(offsets) {
+            Some(offset) if offset.abs() > max_offset => ClockStatus::Skewed(offset),
+            Some(_) => ClockStatus::Synchronized,
+            None => ClockStatus::Unknown,
+        };
+        match status {
+            ClockStatus::Synchronized => self.metrics.time_sync_degraded.set(0),
+            ClockStatus::Skewed(offset) => {
+                self.metrics.time_sync_degraded.set(1);
+                warn!(
+                    self.logger,
+                    "The NTP servers are {:.3}s off the local clock, more than the tolerated {}ms",
+                    offset,
+                    self.config.max_offset_ms
+                );
+            }
+            // Keep the last known state.
+            ClockStatus::Unknown => {}
+        }
+        status
+    }
+}
+
+async fn background_task(monitor: TimeSyncMonitor) {
+    let interval = Duration::from_secs(monitor.config.check_interval_secs);
+    loop {
+        if !monitor.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        monitor.check().await;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Returns the offset of the time of `server` to the local clock in seconds,
+/// i.e., the offset is positive if the local clock is behind.
+async fn query_offset(server: &str, timeout: Duration) -> io::Result<f64> {
+    tokio::time::timeout(timeout, async {
+        let server_addr = lookup_host(server)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for NTP server"))?;
+        let local_addr: SocketAddr = if server_addr.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local_addr).await?;
+        socket.connect(server_addr).await?;
+
+        let transmitted = ntp_timestamp(SystemTime::now());
+        socket.send(&client_request(transmitted)).await?;
+        let mut response = [0; NTP_PACKET_LEN];
+        let len = socket.recv(&mut response).await?;
+        let received = ntp_timestamp(SystemTime::now());
+        offset_from_response(&response[..len], transmitted, received)
+    })
+    .await
+    .map_err(|_elapsed| io::Error::new(io::ErrorKind::TimedOut, "no response from NTP server"))?
+}
+
+fn client_request(transmitted: u64) -> [u8; NTP_PACKET_LEN] {
+    let mut request = [0; NTP_PACKET_LEN];
+    request[0] = NTP_CLIENT_REQUEST_HEADER;
+    request[NTP_TRANSMIT_TIMESTAMP..NTP_TRANSMIT_TIMESTAMP + 8]
+        .copy_from_slice(&transmitted.to_be_bytes());
+    request
+}
+
+/// Computes the clock offset from the response to a request that was sent at
+/// `transmitted` and whose response arrived at `received`.
+fn offset_from_response(response: &[u8], transmitted: u64, received: u64) -> io::Result<f64> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if response.len() < NTP_PACKET_LEN {
+        return Err(invalid("NTP response is too short"));
+    }
+    if response[0] & 0b111 != NTP_MODE_SERVER {
+        return Err(invalid("NTP response is not from a server"));
+    }
+    // Stratum 0 marks a kiss-o'-death response, e.g., for rate limiting.
+    if response[1] == 0 {
+        return Err(invalid("NTP server sent a kiss-o'-death response"));
+    }
+    let timestamp =
+        |offset: usize| u64::from_be_bytes(response[offset..offset + 8].try_into().unwrap());
+    // The server echoes the transmit timestamp of the request, which rules
+    // out stale or spoofed responses.
+    if timestamp(NTP_ORIGINATE_TIMESTAMP) != transmitted {
+        return Err(invalid("NTP response does not match the request"));
+    }
+    let server_received = ntp_seconds(timestamp(NTP_RECEIVE_TIMESTAMP));
+    let server_transmitted = ntp_seconds(timestamp(NTP_TRANSMIT_TIMESTAMP));
+    Ok(((server_received - ntp_seconds(transmitted))
+        + (server_transmitted - ntp_seconds(received)))
+        / 2.0)
+}
+
+/// Converts `time` to an NTP timestamp, i.e., seconds since the NTP epoch as
+/// a 32.32 fixed-point number.
+fn ntp_timestamp(time: SystemTime) -> u64 {
+    let since_unix_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_unix_epoch.as_secs() + NTP_UNIX_EPOCH_OFFSET_SECS;
+    let fraction = (u64::from(since_unix_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+fn ntp_seconds(timestamp: u64) -> f64 {
+    (timestamp >> 32) as f64 + (timestamp & 0xffff_ffff) as f64 / (1u64 << 32) as f64
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_metrics::MetricsRegistry;
+
+    const HALF: u64 = 1 << 31;
+
+    fn response(originate: u64, server_received: u64, server_transmitted: u64) -> Vec<u8> {
+        let mut response = vec![0; NTP_PACKET_LEN];
+        response[0] = 0b00_100_100;
+        response[1] = 1;
+        response[24..32].copy_from_slice(&originate.to_be_bytes());
+        response[32..40].copy_from_slice(&server_received.to_be_bytes());
+        response[40..48].copy_from_slice(&server_transmitted.to_be_bytes());
+        response
+    }
+
+    /// Spawns an NTP server on localhost whose clock is `offset_secs` ahead
+    /// and that answers a single request.
+    fn spawn_ntp_server(offset_secs: u64) -> SocketAddr {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut request = [0; NTP_PACKET_LEN];
+            let (_, client) = socket.recv_from(&mut request).unwrap();
+            let transmitted = u64::from_be_bytes(request[40..48].try_into().unwrap());
+            let now = ntp_timestamp(SystemTime::now()) + (offset_secs << 32);
+            socket
+                .send_to(&response(transmitted, now, now), client)
+                .unwrap();
+        });
+        addr
+    }
+
+    fn monitor(ntp_servers: Vec<String>) -> TimeSyncMonitor {
+        TimeSyncMonitor::new(
+            TimeSyncConfig {
+                enabled: true,
+                ntp_servers,
+                ..TimeSyncConfig::default()
+            },
+            Arc::new(NodeManagerMetrics::new(&MetricsRegistry::new())),
+            no_op_logger(),
+        )
+    }
+
+    #[test]
+    fn ntp_timestamp_counts_from_1900() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_500);
+        assert_eq!(
+            ntp_timestamp(time),
+            ((NTP_UNIX_EPOCH_OFFSET_SECS + 1) << 32) | HALF
+        );
+        assert_eq!(ntp_seconds(ntp_timestamp(time)), 2_208_988_801.5);
+    }
+
+    #[test]
+    fn offset_is_computed_from_the_four_timestamps() {
+        // The request and the response take 1.5s each, and the server clock
+        // is 10s ahead.
+        let transmitted = 1_000 << 32;
+        let response = response(transmitted, (1_011 << 32) | HALF, (1_011 << 32) | HALF);
+        let received = 1_003 << 32;
+
+        assert_eq!(
+            offset_from_response(&response, transmitted, received).unwrap(),
+            10.0
+        );
+    }
+
+    #[test]
+    fn invalid_responses_are_rejected() {
+        let transmitted = 1_000 << 32;
+        let received = 1_001 << 32;
+        let valid = response(transmitted, transmitted, transmitted);
+        assert!(offset_from_response(&valid, transmitted, received).is_ok());
+
+        assert!(offset_from_response(&valid[..40], transmitted, received).is_err());
+        let mut client_mode = valid.clone();
+        client_mode[0] = NTP_CLIENT_REQUEST_HEADER;
+        assert!(offset_from_response(&client_mode, transmitted, received).is_err());
+        let mut kiss_of_death = valid.clone();
+        kiss_of_death[1] = 0;
+        assert!(offset_from_response(&kiss_of_death, transmitted, received).is_err());
+        assert!(offset_from_response(&valid, transmitted + 1, received).is_err());
+    }
+
+    #[test]
+    fn median_of_offsets() {
+        assert_eq!(median(vec![]), None);
+        assert_eq!(median(vec![0.1, -2.0, 0.3]), Some(0.1));
+        assert_eq!(median(vec![0.4, -2.0, 0.2, 3.0]), Some(0.3));
+    }
+
+    #[tokio::test]
+    async fn skewed_clock_is_flagged_as_degraded() {
+        let server = spawn_ntp_server(10).to_string();
+        let monitor = monitor(vec![server.clone()]);
+
+        match monitor.check().await {
+            ClockStatus::Skewed(offset) => assert!((offset - 10.0).abs() < 1.0),
+            status => panic!("unexpected status {:?}", status),
+        }
+        assert_eq!(monitor.metrics.time_sync_degraded.get(), 1);
+        let offset = monitor
+            .metrics
+            .time_sync_offset_seconds
+            .with_label_values(&[&server])
+            .get();
+        assert!((offset - 10.0).abs() < 1.0);
+    }
+
+    #[tokio::test]
+    async fn synchronized_clock_clears_degraded_flag() {
+        let monitor = monitor(vec![spawn_ntp_server(0).to_string()]);
+        monitor.metrics.time_sync_degraded.set(1);
+
+        assert_eq!(monitor.check().await, ClockStatus::Synchronized);
+        assert_eq!(monitor.metrics.time_sync_degraded.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_leaves_state_unknown() {
+        // Nothing answers on the port of a socket that was closed again.
+        let addr = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut monitor = monitor(vec![addr.to_string()]);
+        monitor.config.query_timeout_ms = 100;
+
+        assert_eq!(monitor.check().await, ClockStatus::Unknown);
+        assert_eq!(
+            monitor
+                .metrics
+                .time_sync_queries
+                .with_label_values(&["failed"])
+                .get(),
+            1
+        );
+        assert_eq!(monitor.metrics.time_sync_degraded.get(), 0);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::framework::file_tree_artifact_mgr::ArtifactChunkingTestImpl;
 use ic_config::execution_environment::Config as HypervisorConfig;
 use ic_config::subnet_config::SubnetConfigs;
+use ic_crypto_tls_interfaces::test_utils::MockTlsHandshake;
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_execution_environment::IngressHistoryReaderImpl;
 use ic_interfaces::{registry::RegistryClient, transport::Transport};
@@ -11,7 +12,6 @@ use ic_registry_subnet_type::SubnetType;
 use ic_replica_setup_ic_network::{create_networking_stack, P2PStateSyncClient};
 use ic_test_utilities::{
     consensus::make_catch_up_package_with_empty_transcript,
-    crypto::fake_tls_handshake::FakeTlsHandshake,
     crypto::CryptoReturningOk,
     message_routing::FakeMessageRouting,
     metrics::fetch_int_gauge,
@@ -91,7 +91,7 @@ fn execute_test(
             node_id,
             subnet_id,
             Some(transport),
-            Arc::new(FakeTlsHandshake::new()),
+            Arc::new(MockTlsHandshake::new()),
             Arc::clone(&state_manager) as Arc<_>,
             no_state_sync_client,
             xnet_payload_builder as Arc<_>,
@@ -241,7 +241,7 @@ fn execute_test_chunking_pool(
             node_id,
             subnet_id,
             Some(transport),
-            Arc::new(FakeTlsHandshake::new()),
+            Arc::new(MockTlsHandshake::new()),
             Arc::clone(&state_manager) as Arc<_>,
             state_sync_client,
             xnet_payload_builder,
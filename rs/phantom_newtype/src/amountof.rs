@@ -77,6 +77,25 @@ use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 /// assert_eq!(Apples::from(55), (1..=10_u64).map(Apples::from).sum());
 /// ```
 ///
+/// Amounts with an unsigned integer representation also support saturating
+/// and checked arithmetics:
+///
+/// ```
+/// use phantom_newtype::AmountOf;
+///
+/// enum MetricApple {}
+/// type Apples = AmountOf<MetricApple, u64>;
+///
+/// let max = Apples::from(u64::MAX);
+///
+/// assert_eq!(max.saturating_add(Apples::from(1)), max);
+/// assert_eq!(Apples::from(3).saturating_sub(Apples::from(5)), Apples::from(0));
+/// assert_eq!(max.saturating_mul(2), max);
+/// assert_eq!(max.checked_add(Apples::from(1)), None);
+/// assert_eq!(Apples::from(3).checked_sub(Apples::from(5)), None);
+/// assert_eq!(Apples::from(5).checked_sub(Apples::from(3)), Some(Apples::from(2)));
+/// ```
+///
 /// Multiplication of amounts is not supported: multiplying meters by
 /// meters gives square meters. However, you can scale an amount by a
 /// scalar; divide amounts; or divide amounts by scalars:
@@ -348,6 +367,47 @@ where
     }
 }
 
+/// Implements saturating and checked arithmetic for amounts represented by
+/// the given unsigned integer types.
+macro_rules! impl_saturating_and_checked_ops {
+    ($($repr:ty),*) => {
+        $(
+            impl<Unit> AmountOf<Unit, $repr> {
+                /// Returns the sum of both amounts, saturating at the maximum
+                /// amount instead of overflowing.
+                pub fn saturating_add(self, rhs: Self) -> Self {
+                    Self(self.0.saturating_add(rhs.0), PhantomData)
+                }
+
+                /// Returns the difference of both amounts, saturating at zero
+                /// instead of underflowing.
+                pub fn saturating_sub(self, rhs: Self) -> Self {
+                    Self(self.0.saturating_sub(rhs.0), PhantomData)
+                }
+
+                /// Returns the amount scaled by `rhs`, saturating at the
+                /// maximum amount instead of overflowing.
+                pub fn saturating_mul(self, rhs: $repr) -> Self {
+                    Self(self.0.saturating_mul(rhs), PhantomData)
+                }
+
+                /// Returns the sum of both amounts, or `None` on overflow.
+                pub fn checked_add(self, rhs: Self) -> Option<Self> {
+                    self.0.checked_add(rhs.0).map(|sum| Self(sum, PhantomData))
+                }
+
+                /// Returns the difference of both amounts, or `None` on
+                /// underflow.
+                pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+                    self.0.checked_sub(rhs.0).map(|diff| Self(diff, PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+impl_saturating_and_checked_ops!(u32, u64, u128);
+
 impl<Unit, Repr> SubAssign for AmountOf<Unit, Repr>
 where
     Repr: SubAssign,
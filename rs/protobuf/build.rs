@@ -178,6 +178,16 @@ fn build_log_proto() {
         malicious_behaviour
     );
 
+    add_log_proto_derives!(
+        config,
+        MessageContextLogEntry,
+        "log.message_context_log_entry.v1",
+        message_context,
+        message_id,
+        canister_id,
+        height
+    );
+
     compile_protos(config, &["def/log/log_entry/v1/log_entry.proto"]);
 }
 
@@ -35,6 +35,7 @@ use registry_canister::{
         do_remove_nodes_from_subnet::RemoveNodesFromSubnetPayload,
         do_update_icp_xdr_conversion_rate::UpdateIcpXdrConversionRatePayload,
         do_update_node_operator_config::UpdateNodeOperatorConfigPayload,
+        do_update_node_public_keys::UpdateNodePublicKeysPayload,
         do_update_subnet::UpdateSubnetPayload,
         do_update_subnet_replica::UpdateSubnetReplicaVersionPayload,
     },
@@ -477,6 +478,22 @@ fn remove_node_directly() {
     });
 }
 
+#[export_name = "canister_update update_node_public_keys"]
+fn update_node_public_keys() {
+    // This method can be called by anyone
+    println!(
+        "{}call: {} from: {}",
+        LOG_PREFIX,
+        "update_node_public_keys".to_string(),
+        dfn_core::api::caller()
+    );
+    over_may_reject(candid_one, |payload: UpdateNodePublicKeysPayload| {
+        let result = registry_mut().do_update_node_public_keys(payload);
+        recertify_registry();
+        result
+    });
+}
+
 #[export_name = "canister_update update_node_operator_config"]
 fn update_node_operator_config() {
     check_caller_is_governance_and_log("update_node_operator_config");
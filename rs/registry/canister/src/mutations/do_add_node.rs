@@ -61,7 +61,12 @@ impl Registry {
         }
 
         // 3. Validate keys and get the node id
-        let (node_id, valid_pks) = valid_keys_from_payload(&payload)?;
+        let (node_id, valid_pks) = valid_node_public_keys(
+            &payload.node_signing_pk,
+            &payload.committee_signing_pk,
+            &payload.ni_dkg_dealing_encryption_pk,
+            &payload.transport_tls_cert,
+        )?;
 
         println!("{}do_add_node: The node id is {:?}", LOG_PREFIX, node_id);
 
@@ -82,6 +87,7 @@ impl Registry {
             private_api: vec![],
             prometheus_metrics: vec![],
             xnet_api: vec![],
+            attestation_report: payload.attestation_report.clone().unwrap_or_default(),
         };
 
         // 5. Update registry with the new subnet data
@@ -153,6 +159,10 @@ pub struct AddNodePayload {
     pub http_endpoint: String,
     pub p2p_flow_endpoints: Vec<String>,
     pub prometheus_metrics_endpoint: String,
+
+    // Hardware attestation report (e.g. SEV-SNP) binding the node signing key
+    // and the TLS certificate, if the node's platform supports attestation.
+    pub attestation_report: Option<Vec<u8>>,
 }
 
 /// Parses the ConnectionEndpoint string
@@ -196,39 +206,41 @@ pub fn flow_endpoint_from_string(endpoint: &str) -> FlowEndpoint {
     }
 }
 
-/// Validates the payload and extracts node's public keys
-fn valid_keys_from_payload(
-    payload: &AddNodePayload,
+/// Validates the raw public keys of a node and derives its node id from them
+pub(crate) fn valid_node_public_keys(
+    node_signing_pk: &[u8],
+    committee_signing_pk: &[u8],
+    ni_dkg_dealing_encryption_pk: &[u8],
+    transport_tls_cert: &[u8],
 ) -> Result<(NodeId, ValidNodePublicKeys), String> {
     // 1. verify that the keys we got are not empty
-    if payload.node_signing_pk.is_empty() {
+    if node_signing_pk.is_empty() {
         return Err(String::from("node_signing_pk is empty"));
     };
-    if payload.committee_signing_pk.is_empty() {
+    if committee_signing_pk.is_empty() {
         return Err(String::from("committee_signing_pk is empty"));
     };
-    if payload.ni_dkg_dealing_encryption_pk.is_empty() {
+    if ni_dkg_dealing_encryption_pk.is_empty() {
         return Err(String::from("ni_dkg_dealing_encryption_pk is empty"));
     };
-    if payload.transport_tls_cert.is_empty() {
+    if transport_tls_cert.is_empty() {
         return Err(String::from("transport_tls_cert is empty"));
     };
 
     // 2. get the keys for verification -- for that, we need to create
     // NodePublicKeys first
-    let node_signing_pk = PublicKey::decode(&payload.node_signing_pk[..])
+    let node_signing_pk = PublicKey::decode(node_signing_pk)
         .map_err(|e| format!("node_signing_pk is not in the expected format: {:?}", e))?;
-    let committee_signing_pk =
-        PublicKey::decode(&payload.committee_signing_pk[..]).map_err(|e| {
-            format!(
-                "committee_signing_pk is not in the expected format: {:?}",
-                e
-            )
-        })?;
-    let tls_certificate = X509PublicKeyCert::decode(&payload.transport_tls_cert[..])
+    let committee_signing_pk = PublicKey::decode(committee_signing_pk).map_err(|e| {
+        format!(
+            "committee_signing_pk is not in the expected format: {:?}",
+            e
+        )
+    })?;
+    let tls_certificate = X509PublicKeyCert::decode(transport_tls_cert)
         .map_err(|e| format!("transport_tls_cert is not in the expected format: {:?}", e))?;
-    let dkg_dealing_encryption_pk = PublicKey::decode(&payload.ni_dkg_dealing_encryption_pk[..])
-        .map_err(|e| {
+    let dkg_dealing_encryption_pk =
+        PublicKey::decode(ni_dkg_dealing_encryption_pk).map_err(|e| {
             format!(
                 "ni_dkg_dealing_encryption_pk is not in the expected format: {:?}",
                 e
@@ -308,6 +320,7 @@ mod tests {
             http_endpoint: "127.0.0.1:8123".to_string(),
             p2p_flow_endpoints: vec!["123,127.0.0.1:10000".to_string()],
             prometheus_metrics_endpoint: "127.0.0.1:5555".to_string(),
+            attestation_report: None,
         };
     }
 
@@ -317,6 +330,17 @@ mod tests {
         buf
     }
 
+    fn valid_keys_from_payload(
+        payload: &AddNodePayload,
+    ) -> Result<(NodeId, ValidNodePublicKeys), String> {
+        valid_node_public_keys(
+            &payload.node_signing_pk,
+            &payload.committee_signing_pk,
+            &payload.ni_dkg_dealing_encryption_pk,
+            &payload.transport_tls_cert,
+        )
+    }
+
     #[test]
     fn empty_node_signing_key_is_detected() {
         let payload = PAYLOAD.clone();
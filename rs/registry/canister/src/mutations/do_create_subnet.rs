@@ -178,6 +178,8 @@ impl Registry {
             max_instructions_per_install_code: payload.max_instructions_per_install_code,
 
             features: Some(payload.features.into()),
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         };
 
         // 4. Update registry with the new subnet data
@@ -308,6 +310,8 @@ impl From<CreateSubnetPayload> for SubnetRecord {
             max_instructions_per_round: val.max_instructions_per_round,
             max_instructions_per_install_code: val.max_instructions_per_install_code,
             features: Some(val.features.into()),
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         }
     }
 }
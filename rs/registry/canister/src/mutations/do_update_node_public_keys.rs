@@ -0,0 +1,108 @@
+use crate::{
+    common::LOG_PREFIX,
+    mutations::{
+        common::{decode_registry_value, encode_or_panic},
+        do_add_node::valid_node_public_keys,
+    },
+    registry::Registry,
+};
+
+use std::convert::TryFrom;
+
+use candid::{CandidType, Deserialize};
+#[cfg(target_arch = "wasm32")]
+use dfn_core::println;
+
+use ic_base_types::PrincipalId;
+use ic_protobuf::registry::node::v1::NodeRecord;
+use ic_registry_keys::{make_crypto_node_key, make_crypto_tls_cert_key, make_node_record_key};
+use ic_registry_transport::{pb::v1::RegistryValue, update};
+use ic_types::crypto::KeyPurpose;
+
+impl Registry {
+    /// Replaces the public keys of an existing node, e.g. after the node
+    /// rotated them.
+    ///
+    /// The node signing key determines the node id and hence can't be
+    /// rotated; it only identifies the node whose keys are replaced. This
+    /// method is called by the node operator tied to the node.
+    pub fn do_update_node_public_keys(
+        &mut self,
+        payload: UpdateNodePublicKeysPayload,
+    ) -> Result<(), String> {
+        println!("{}do_update_node_public_keys: {:?}", LOG_PREFIX, payload);
+
+        // 1. Validate the keys and get the node id
+        let (node_id, valid_pks) = valid_node_public_keys(
+            &payload.node_signing_pk,
+            &payload.committee_signing_pk,
+            &payload.ni_dkg_dealing_encryption_pk,
+            &payload.transport_tls_cert,
+        )?;
+
+        // 2. Check that the node record exists
+        let node_key = make_node_record_key(node_id);
+        let RegistryValue {
+            value: node_record,
+            version: _,
+            deletion_marker: _,
+        } = self
+            .get(node_key.as_bytes(), self.latest_version())
+            .map_or(Err(format!(
+                "{}do_update_node_public_keys: Node Id {:} not found in the registry, aborting key update.",
+                LOG_PREFIX, node_id)), Ok)?;
+        let mut node_record = decode_registry_value::<NodeRecord>(node_record.to_vec());
+
+        // 3. Get the caller ID and check that it matches the node's NO
+        let caller = dfn_core::api::caller();
+        if PrincipalId::try_from(&node_record.node_operator_id[..]).ok() != Some(caller) {
+            return Err(format!(
+                "{}do_update_node_public_keys: The caller {}, does not match this Node's Operator id.",
+                LOG_PREFIX, caller
+            ));
+        }
+
+        // 4. Replace the keys and the attestation report, which was produced
+        // for the new keys
+        node_record.attestation_report = payload.attestation_report.unwrap_or_default();
+        let mutations = vec![
+            update(node_key.as_bytes().to_vec(), encode_or_panic(&node_record)),
+            update(
+                make_crypto_node_key(node_id, KeyPurpose::CommitteeSigning)
+                    .as_bytes()
+                    .to_vec(),
+                encode_or_panic(valid_pks.committee_signing_key()),
+            ),
+            update(
+                make_crypto_node_key(node_id, KeyPurpose::DkgDealingEncryption)
+                    .as_bytes()
+                    .to_vec(),
+                encode_or_panic(valid_pks.dkg_dealing_encryption_key()),
+            ),
+            update(
+                make_crypto_tls_cert_key(node_id).as_bytes().to_vec(),
+                encode_or_panic(valid_pks.tls_certificate()),
+            ),
+        ];
+
+        // Check invariants before applying mutations
+        self.maybe_apply_mutation_internal(mutations);
+
+        Ok(())
+    }
+}
+
+/// The payload of an update request to replace the public keys of a node.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UpdateNodePublicKeysPayload {
+    // Raw bytes of the protobuf, but these should be PublicKey
+    pub node_signing_pk: Vec<u8>,
+    pub committee_signing_pk: Vec<u8>,
+    pub ni_dkg_dealing_encryption_pk: Vec<u8>,
+    // Raw bytes of the protobuf, but these should be X509PublicKeyCert
+    pub transport_tls_cert: Vec<u8>,
+
+    // Hardware attestation report (e.g. SEV-SNP) binding the node signing key
+    // and the new TLS certificate, if the node's platform supports attestation.
+    pub attestation_report: Option<Vec<u8>>,
+}
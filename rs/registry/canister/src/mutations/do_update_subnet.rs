@@ -4,12 +4,13 @@ use candid::{CandidType, Deserialize};
 use dfn_core::println;
 
 use ic_base_types::SubnetId;
-use ic_protobuf::registry::subnet::v1::SubnetRecord;
+use ic_protobuf::registry::subnet::v1::{InstructionCostOverride, SubnetRecord};
 use ic_registry_keys::make_subnet_record_key;
 use ic_registry_subnet_features::SubnetFeatures;
 use ic_registry_subnet_type::SubnetType;
 use ic_registry_transport::pb::v1::{registry_mutation, RegistryMutation};
 use ic_types::p2p::build_default_gossip_config;
+use std::collections::BTreeMap;
 
 /// Updates the subnet's configuration in the registry.
 ///
@@ -80,6 +81,9 @@ pub struct UpdateSubnetPayload {
     pub max_instructions_per_round: Option<u64>,
     pub max_instructions_per_install_code: Option<u64>,
     pub features: Option<SubnetFeatures>,
+    /// Replaces all overrides of the costs of classes of Wasm instructions,
+    /// keyed by the name of the class.
+    pub instruction_cost_overrides: Option<BTreeMap<String, u64>>,
 }
 
 #[macro_use]
@@ -161,6 +165,7 @@ fn merge_subnet_record(
         max_instructions_per_round,
         max_instructions_per_install_code,
         features,
+        instruction_cost_overrides,
     } = payload;
 
     maybe_set!(subnet_record, ingress_bytes_per_block_soft_cap);
@@ -202,6 +207,13 @@ fn merge_subnet_record(
     maybe_set!(subnet_record, max_instructions_per_install_code);
 
     maybe_set_option!(subnet_record, features);
+
+    if let Some(instruction_cost_overrides) = instruction_cost_overrides {
+        subnet_record.instruction_cost_overrides = instruction_cost_overrides
+            .into_iter()
+            .map(|(opcode_class, cost)| InstructionCostOverride { opcode_class, cost })
+            .collect();
+    }
     subnet_record
 }
 
@@ -243,6 +255,8 @@ mod tests {
             max_instructions_per_round: 7_000_000_000,
             max_instructions_per_install_code: 200_000_000_000,
             features: None,
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         };
 
         let payload = UpdateSubnetPayload {
@@ -277,6 +291,9 @@ mod tests {
             features: Some(SubnetFeatures {
                 ecdsa_signatures: false,
             }),
+            instruction_cost_overrides: Some(
+                vec![("memory_grow".to_string(), 300)].into_iter().collect(),
+            ),
         };
 
         assert_eq!(
@@ -314,6 +331,11 @@ mod tests {
                     }
                     .into()
                 ),
+                instruction_cost_overrides: vec![InstructionCostOverride {
+                    opcode_class: "memory_grow".to_string(),
+                    cost: 300,
+                }],
+                reserved_canister_id_ranges: None,
             }
         );
     }
@@ -348,6 +370,8 @@ mod tests {
             max_instructions_per_round: 7_000_000_000,
             max_instructions_per_install_code: 200_000_000_000,
             features: None,
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         };
 
         let payload = UpdateSubnetPayload {
@@ -380,6 +404,7 @@ mod tests {
             max_instructions_per_round: Some(8_000_000_000),
             max_instructions_per_install_code: None,
             features: None,
+            instruction_cost_overrides: None,
         };
 
         assert_eq!(
@@ -412,6 +437,8 @@ mod tests {
                 max_instructions_per_round: 8_000_000_000,
                 max_instructions_per_install_code: 200_000_000_000,
                 features: None,
+                instruction_cost_overrides: vec![],
+                reserved_canister_id_ranges: None,
             }
         );
     }
@@ -441,6 +468,8 @@ mod tests {
             max_instructions_per_round: 7_000_000_000,
             max_instructions_per_install_code: 200_000_000_000,
             features: None,
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         };
 
         let payload = UpdateSubnetPayload {
@@ -473,6 +502,7 @@ mod tests {
             max_instructions_per_round: None,
             max_instructions_per_install_code: None,
             features: None,
+            instruction_cost_overrides: None,
         };
 
         merge_subnet_record(subnet_record, payload);
@@ -499,6 +529,8 @@ mod tests {
             max_instructions_per_round: 7_000_000_000,
             max_instructions_per_install_code: 200_000_000_000,
             features: None,
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         };
 
         let payload = UpdateSubnetPayload {
@@ -531,6 +563,7 @@ mod tests {
             max_instructions_per_round: None,
             max_instructions_per_install_code: None,
             features: None,
+            instruction_cost_overrides: None,
         };
 
         assert_eq!(
@@ -563,6 +596,8 @@ mod tests {
                 max_instructions_per_round: 7_000_000_000,
                 max_instructions_per_install_code: 200_000_000_000,
                 features: None,
+                instruction_cost_overrides: vec![],
+                reserved_canister_id_ranges: None,
             }
         );
     }
@@ -14,6 +14,7 @@ pub mod do_remove_nodes_from_subnet;
 pub mod do_set_firewall_config;
 pub mod do_update_icp_xdr_conversion_rate;
 pub mod do_update_node_operator_config;
+pub mod do_update_node_public_keys;
 pub mod do_update_subnet;
 pub mod do_update_subnet_replica;
 mod routing_table;
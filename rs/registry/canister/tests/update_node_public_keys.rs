@@ -0,0 +1,195 @@
+use dfn_candid::candid;
+
+use ic_canister_client::Sender;
+use ic_nns_constants::ids::{
+    TEST_NEURON_1_OWNER_KEYPAIR, TEST_NEURON_1_OWNER_PRINCIPAL, TEST_USER1_KEYPAIR,
+};
+use ic_nns_test_utils::{
+    itest_helpers::{local_test_on_nns_subnet, set_up_registry_canister},
+    registry::{get_value, invariant_compliant_mutation_as_atomic_req, prepare_add_node_payload},
+};
+use ic_protobuf::registry::{
+    crypto::v1::X509PublicKeyCert, node::v1::NodeRecord, node_operator::v1::NodeOperatorRecord,
+};
+use ic_registry_keys::{
+    make_crypto_tls_cert_key, make_node_operator_record_key, make_node_record_key,
+};
+use ic_registry_transport::pb::v1::{
+    registry_mutation, RegistryAtomicMutateRequest, RegistryMutation,
+};
+use ic_types::NodeId;
+use registry_canister::{
+    init::RegistryCanisterInitPayloadBuilder,
+    mutations::{
+        do_add_node::AddNodePayload, do_update_node_public_keys::UpdateNodePublicKeysPayload,
+    },
+};
+
+use prost::Message;
+
+#[test]
+fn node_public_keys_and_attestation_report_are_updated() {
+    local_test_on_nns_subnet(|runtime| async move {
+        let registry = set_up_registry_canister(
+            &runtime,
+            RegistryCanisterInitPayloadBuilder::new()
+                .push_init_mutate_request(invariant_compliant_mutation_as_atomic_req())
+                .push_init_mutate_request(init_mutation_with_node_allowance(100))
+                .build(),
+        )
+        .await;
+
+        let (mut payload, node_pks, node_id) = prepare_add_node_payload();
+        payload.attestation_report = Some(vec![1, 2, 3]);
+        let response: Result<NodeId, String> = registry
+            .update_from_sender(
+                "add_node",
+                candid,
+                (payload.clone(),),
+                &Sender::from_keypair(&TEST_NEURON_1_OWNER_KEYPAIR),
+            )
+            .await;
+        assert!(response.is_ok());
+
+        let node_record =
+            get_value::<NodeRecord>(&registry, make_node_record_key(node_id).as_bytes()).await;
+        assert_eq!(node_record.attestation_report, vec![1, 2, 3]);
+
+        let response: Result<(), String> = registry
+            .update_from_sender(
+                "update_node_public_keys",
+                candid,
+                (update_payload(&payload, Some(vec![4, 5, 6])),),
+                &Sender::from_keypair(&TEST_NEURON_1_OWNER_KEYPAIR),
+            )
+            .await;
+        assert!(response.is_ok());
+
+        let node_record =
+            get_value::<NodeRecord>(&registry, make_node_record_key(node_id).as_bytes()).await;
+        assert_eq!(node_record.attestation_report, vec![4, 5, 6]);
+        let transport_tls_certificate_record =
+            get_value::<X509PublicKeyCert>(&registry, make_crypto_tls_cert_key(node_id).as_bytes())
+                .await;
+        assert_eq!(
+            transport_tls_certificate_record,
+            node_pks.tls_certificate.unwrap()
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+fn node_public_keys_are_not_updated_by_other_principal() {
+    local_test_on_nns_subnet(|runtime| async move {
+        let registry = set_up_registry_canister(
+            &runtime,
+            RegistryCanisterInitPayloadBuilder::new()
+                .push_init_mutate_request(invariant_compliant_mutation_as_atomic_req())
+                .push_init_mutate_request(init_mutation_with_node_allowance(100))
+                .build(),
+        )
+        .await;
+
+        let (mut payload, _node_pks, node_id) = prepare_add_node_payload();
+        payload.attestation_report = Some(vec![1, 2, 3]);
+        let response: Result<NodeId, String> = registry
+            .update_from_sender(
+                "add_node",
+                candid,
+                (payload.clone(),),
+                &Sender::from_keypair(&TEST_NEURON_1_OWNER_KEYPAIR),
+            )
+            .await;
+        assert!(response.is_ok());
+
+        // Issue a request with an unauthorized sender, which should fail.
+        let response: Result<(), String> = registry
+            .update_from_sender(
+                "update_node_public_keys",
+                candid,
+                (update_payload(&payload, None),),
+                &Sender::from_keypair(&TEST_USER1_KEYPAIR),
+            )
+            .await;
+        assert!(response.is_err());
+
+        // The attestation report should be unchanged
+        let node_record =
+            get_value::<NodeRecord>(&registry, make_node_record_key(node_id).as_bytes()).await;
+        assert_eq!(node_record.attestation_report, vec![1, 2, 3]);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn node_public_keys_of_unknown_node_are_not_updated() {
+    local_test_on_nns_subnet(|runtime| async move {
+        let registry = set_up_registry_canister(
+            &runtime,
+            RegistryCanisterInitPayloadBuilder::new()
+                .push_init_mutate_request(invariant_compliant_mutation_as_atomic_req())
+                .push_init_mutate_request(init_mutation_with_node_allowance(100))
+                .build(),
+        )
+        .await;
+
+        let (payload, _node_pks, node_id) = prepare_add_node_payload();
+        let response: Result<(), String> = registry
+            .update_from_sender(
+                "update_node_public_keys",
+                candid,
+                (update_payload(&payload, None),),
+                &Sender::from_keypair(&TEST_NEURON_1_OWNER_KEYPAIR),
+            )
+            .await;
+        assert!(response.is_err());
+
+        // The record should still not be there
+        let node_record =
+            get_value::<NodeRecord>(&registry, make_node_record_key(node_id).as_bytes()).await;
+        assert_eq!(node_record, NodeRecord::default());
+
+        Ok(())
+    });
+}
+
+fn update_payload(
+    payload: &AddNodePayload,
+    attestation_report: Option<Vec<u8>>,
+) -> UpdateNodePublicKeysPayload {
+    UpdateNodePublicKeysPayload {
+        node_signing_pk: payload.node_signing_pk.clone(),
+        committee_signing_pk: payload.committee_signing_pk.clone(),
+        ni_dkg_dealing_encryption_pk: payload.ni_dkg_dealing_encryption_pk.clone(),
+        transport_tls_cert: payload.transport_tls_cert.clone(),
+        attestation_report,
+    }
+}
+
+fn init_mutation_with_node_allowance(node_allowance: u64) -> RegistryAtomicMutateRequest {
+    let node_operator_record = NodeOperatorRecord {
+        node_operator_principal_id: TEST_NEURON_1_OWNER_PRINCIPAL.to_vec(),
+        node_allowance,
+        // This doesn't go through Governance validation
+        node_provider_principal_id: vec![],
+    };
+    RegistryAtomicMutateRequest {
+        mutations: vec![RegistryMutation {
+            mutation_type: registry_mutation::Type::Insert as i32,
+            key: make_node_operator_record_key(*TEST_NEURON_1_OWNER_PRINCIPAL)
+                .as_bytes()
+                .to_vec(),
+            value: protobuf_to_vec(&node_operator_record),
+        }],
+        preconditions: vec![],
+    }
+}
+
+fn protobuf_to_vec<M: Message>(entry: &M) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    entry.encode(&mut buf).expect("This must not fail");
+    buf
+}
@@ -64,6 +64,7 @@ fn test_the_anonymous_user_cannot_update_a_subnets_configuration() {
             max_instructions_per_round: Some(8_000_000_000),
             max_instructions_per_install_code: Some(300_000_000_000),
             features: None,
+            instruction_cost_overrides: None,
         };
 
         // The anonymous end-user tries to update a subnet's configuration, bypassing
@@ -135,6 +136,8 @@ fn test_a_canister_other_than_the_proposals_canister_cannot_update_a_subnets_con
             max_instructions_per_round: 7_000_000_000,
             max_instructions_per_install_code: 200_000_000_000,
             features: None,
+            instruction_cost_overrides: vec![],
+            reserved_canister_id_ranges: None,
         };
 
         // An attacker got a canister that is trying to pass for the proposals
@@ -187,6 +190,7 @@ fn test_a_canister_other_than_the_proposals_canister_cannot_update_a_subnets_con
             max_instructions_per_round: Some(8_000_000_000),
             max_instructions_per_install_code: Some(300_000_000_000),
             features: None,
+            instruction_cost_overrides: None,
         };
 
         // The attacker canister tries to update the subnet's configuration, pretending
@@ -259,6 +263,8 @@ fn test_the_proposals_canister_can_update_a_subnets_configuration() {
                             max_instructions_per_round: 7_000_000_000,
                             max_instructions_per_install_code: 200_000_000_000,
                             features: None,
+                            instruction_cost_overrides: vec![],
+                            reserved_canister_id_ranges: None,
                         }),
                     )],
                     preconditions: vec![],
@@ -302,6 +308,7 @@ fn test_the_proposals_canister_can_update_a_subnets_configuration() {
             max_instructions_per_round: Some(8_000_000_000),
             max_instructions_per_install_code: Some(300_000_000_000),
             features: None,
+            instruction_cost_overrides: None,
         };
 
         // Attempt to update the subnet's configuration. Since the update happens from
@@ -350,6 +357,8 @@ fn test_the_proposals_canister_can_update_a_subnets_configuration() {
                 max_instructions_per_round: 8_000_000_000,
                 max_instructions_per_install_code: 300_000_000_000,
                 features: None,
+                instruction_cost_overrides: vec![],
+                reserved_canister_id_ranges: None,
             }
         );
 
@@ -0,0 +1,128 @@
+use crate::certification::decode_certified_deltas;
+use ic_interfaces::registry::RegistryTransportRecord;
+use ic_nns_constants::REGISTRY_CANISTER_ID;
+use ic_types::{crypto::threshold_sig::ThresholdSigPublicKey, Time};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CertifiedSnapshotError {
+    #[error("could not read registry snapshot file {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("registry snapshot file {path:?} failed verification: {reason}")]
+    InvalidSnapshot { path: PathBuf, reason: String },
+}
+
+/// Reads the snapshot file at `path` and verifies it against the NNS public
+/// key. Returns the contained records, sorted by version, and the time at
+/// which they were certified.
+pub fn read_certified_snapshot<P>(
+    path: P,
+    nns_public_key: &ThresholdSigPublicKey,
+) -> Result<(Vec<RegistryTransportRecord>, Time), CertifiedSnapshotError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|source| CertifiedSnapshotError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let (records, _latest_version, certified_time) =
+        decode_certified_deltas(0, &REGISTRY_CANISTER_ID, nns_public_key, &bytes[..]).map_err(
+            |err| CertifiedSnapshotError::InvalidSnapshot {
+                path: path.to_path_buf(),
+                reason: format!("{:?}", err),
+            },
+        )?;
+    Ok((sorted_snapshot_records(path, records)?, certified_time))
+}
+
+fn sorted_snapshot_records(
+    path: &Path,
+    mut records: Vec<RegistryTransportRecord>,
+) -> Result<Vec<RegistryTransportRecord>, CertifiedSnapshotError> {
+    if records.is_empty() {
+        return Err(CertifiedSnapshotError::InvalidSnapshot {
+            path: path.to_path_buf(),
+            reason: "the snapshot contains no records".to_string(),
+        });
+    }
+    records.sort_by_key(|record| record.version);
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_crypto::combined_threshold_signature_and_public_key;
+    use ic_interfaces::crypto::SignableMock;
+    use ic_types::{Randomness, RegistryVersion};
+
+    fn nns_public_key() -> ThresholdSigPublicKey {
+        let (_sig, public_key) = combined_threshold_signature_and_public_key(
+            Randomness::from([0; 32]),
+            &SignableMock::new(vec![]),
+        );
+        public_key
+    }
+
+    fn record(key: &str, version: u64) -> RegistryTransportRecord {
+        RegistryTransportRecord {
+            key: key.to_string(),
+            version: RegistryVersion::from(version),
+            value: Some(vec![version as u8]),
+        }
+    }
+
+    #[test]
+    fn sorts_snapshot_records_by_version() {
+        let records =
+            sorted_snapshot_records(Path::new("snapshot"), vec![record("b", 2), record("a", 1)])
+                .unwrap();
+
+        assert_eq!(records, vec![record("a", 1), record("b", 2)]);
+    }
+
+    #[test]
+    fn rejects_empty_snapshot() {
+        let result = sorted_snapshot_records(Path::new("snapshot"), vec![]);
+
+        assert!(
+            matches!(result, Err(CertifiedSnapshotError::InvalidSnapshot { .. })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn rejects_snapshot_that_is_not_certified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registry.snapshot");
+        std::fs::write(&path, b"not a certified response").unwrap();
+
+        let result = read_certified_snapshot(&path, &nns_public_key());
+
+        assert!(
+            matches!(result, Err(CertifiedSnapshotError::InvalidSnapshot { .. })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn reports_missing_snapshot_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = read_certified_snapshot(dir.path().join("missing"), &nns_public_key());
+
+        assert!(
+            matches!(result, Err(CertifiedSnapshotError::Io { .. })),
+            "{:?}",
+            result
+        );
+    }
+}
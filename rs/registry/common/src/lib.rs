@@ -1,4 +1,5 @@
 pub(crate) mod certification;
+pub mod certified_snapshot;
 pub mod data_provider;
 pub mod local_store;
 pub mod pb;
@@ -3,8 +3,9 @@ mod proto;
 use candid::Decode;
 use ic_base_types::{CanisterId, PrincipalId, SubnetId};
 use ic_ic00_types::{
-    CanisterIdRecord, InstallCodeArgs, Method as Ic00Method, Payload, ProvisionalTopUpCanisterArgs,
-    SetControllerArgs, UpdateSettingsArgs,
+    CanisterIdRecord, CanisterSelfDestructArgs, ExportCanisterArgs, ExportCanisterChunkArgs,
+    ImportCanisterArgs, ImportCanisterChunkArgs, InstallCodeArgs, Method as Ic00Method, Payload,
+    ProvisionalTopUpCanisterArgs, SetControllerArgs, UpdateSettingsArgs,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, convert::TryFrom, str::FromStr, sync::Arc};
@@ -34,7 +35,11 @@ pub fn resolve_destination(
     match method {
         Ok(Ic00Method::CreateCanister)
         | Ok(Ic00Method::RawRand)
+        | Ok(Ic00Method::CostCall)
+        | Ok(Ic00Method::CostCreateCanister)
+        | Ok(Ic00Method::CostExecution)
         | Ok(Ic00Method::ProvisionalCreateCanisterWithCycles)
+        | Ok(Ic00Method::ProvisionalCreateCanistersWithCycles)
         | Ok(Ic00Method::SignWithECDSA) => Ok(own_subnet),
         // This message needs to be routed to the NNS subnet.  We assume that
         // this message can only be sent by canisters on the NNS subnet hence
@@ -66,7 +71,52 @@ pub fn resolve_destination(
                 ResolveDestinationError::SubnetNotFound(canister_id, Ic00Method::SetController)
             })
         }
+        Ok(Ic00Method::ExportCanister) => {
+            let args = ExportCanisterArgs::decode(payload)?;
+            let canister_id = args.get_canister_id();
+            routing_table.route(canister_id.get()).ok_or({
+                ResolveDestinationError::SubnetNotFound(canister_id, Ic00Method::ExportCanister)
+            })
+        }
+        Ok(Ic00Method::ExportCanisterChunk) => {
+            let args = ExportCanisterChunkArgs::decode(payload)?;
+            let canister_id = args.get_canister_id();
+            routing_table.route(canister_id.get()).ok_or({
+                ResolveDestinationError::SubnetNotFound(
+                    canister_id,
+                    Ic00Method::ExportCanisterChunk,
+                )
+            })
+        }
+        Ok(Ic00Method::ImportCanisterChunk) => {
+            let args = ImportCanisterChunkArgs::decode(payload)?;
+            let canister_id = args.get_canister_id();
+            routing_table.route(canister_id.get()).ok_or({
+                ResolveDestinationError::SubnetNotFound(
+                    canister_id,
+                    Ic00Method::ImportCanisterChunk,
+                )
+            })
+        }
+        Ok(Ic00Method::ImportCanister) => {
+            let args = ImportCanisterArgs::decode(payload)?;
+            let canister_id = args.get_canister_id();
+            routing_table.route(canister_id.get()).ok_or({
+                ResolveDestinationError::SubnetNotFound(canister_id, Ic00Method::ImportCanister)
+            })
+        }
+        Ok(Ic00Method::CanisterSelfDestruct) => {
+            let args = CanisterSelfDestructArgs::decode(payload)?;
+            let canister_id = args.get_canister_id();
+            routing_table.route(canister_id.get()).ok_or({
+                ResolveDestinationError::SubnetNotFound(
+                    canister_id,
+                    Ic00Method::CanisterSelfDestruct,
+                )
+            })
+        }
         Ok(Ic00Method::CanisterStatus)
+        | Ok(Ic00Method::CanisterMetrics)
         | Ok(Ic00Method::StartCanister)
         | Ok(Ic00Method::StopCanister)
         | Ok(Ic00Method::DeleteCanister)
@@ -217,6 +267,23 @@ impl CanisterIdRanges {
             self.total_count()
         );
     }
+
+    /// Returns true if `canister_id` falls into one of the ranges.
+    pub fn contains(&self, canister_id: CanisterId) -> bool {
+        self.0
+            .iter()
+            .any(|range| range.start <= canister_id && canister_id <= range.end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<CanisterIdRange>> for CanisterIdRanges {
+    fn from(ranges: Vec<CanisterIdRange>) -> Self {
+        Self(ranges)
+    }
 }
 
 /// A helper function to help insert a new subnet to the routing table
@@ -602,6 +669,18 @@ mod test {
         assert!(cid > CanisterId::from(0x10000));
     }
 
+    #[test]
+    fn canister_id_ranges_contains() {
+        let ranges = new_canister_id_ranges(vec![(0x10, 0x1f), (0x100, 0x100)]);
+        assert!(!ranges.contains(CanisterId::from(0xf)));
+        assert!(ranges.contains(CanisterId::from(0x10)));
+        assert!(ranges.contains(CanisterId::from(0x1f)));
+        assert!(!ranges.contains(CanisterId::from(0x20)));
+        assert!(ranges.contains(CanisterId::from(0x100)));
+        assert!(!ranges.contains(CanisterId::from(0x101)));
+        assert!(!CanisterIdRanges::default().contains(CanisterId::from(0x10)));
+    }
+
     #[test]
     fn route_when_principal_corresponds_to_subnet() {
         // Valid routing table
@@ -194,7 +194,7 @@ fn criterion_calls(criterion: &mut Criterion) {
         ic_types::malicious_flags::MaliciousFlags::default(),
     ));
 
-    let (_, ingress_history_writer, _, scheduler, ingress_hist_reader) = setup_execution(
+    let (_, ingress_history_writer, _, scheduler, ingress_hist_reader, _) = setup_execution(
         bench_replica.log.clone(),
         &bench_replica.metrics_registry,
         bench_replica.replica_config.subnet_id,
@@ -1,3 +1,4 @@
 pub mod args;
 pub mod setup;
 pub mod setup_p2p;
+pub mod span_exporter;
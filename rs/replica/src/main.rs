@@ -11,7 +11,7 @@ use ic_logger::info;
 use ic_metrics::MetricsRegistry;
 use ic_metrics_exporter::MetricsRuntimeImpl;
 use ic_registry_client::helper::subnet::SubnetRegistry;
-use ic_replica::{args::ReplicaArgs, setup};
+use ic_replica::{args::ReplicaArgs, setup, span_exporter};
 use ic_types::{replica_version::REPLICA_BINARY_HASH, PrincipalId, ReplicaVersion, SubnetId};
 use ic_utils::ic_features::*;
 use nix::unistd::{setpgid, Pid};
@@ -139,6 +139,7 @@ async fn run() -> io::Result<()> {
     let config = Config::load_with_tmpdir(config_source, tmpdir.path().to_path_buf());
 
     let (logger, _async_log_guard) = setup::get_replica_logger(&config);
+    span_exporter::init(&config.tracing, logger.clone());
 
     let optional_nns_key_path = match &replica_args {
         Ok(ReplicaArgs {
@@ -293,6 +294,7 @@ async fn run() -> io::Result<()> {
         consensus_pool_cache,
         ingress_message_filter,
         _xnet_endpoint,
+        round_state_dump,
     ) = ic_replica::setup_p2p::construct_ic_stack(
         logger.clone(),
         config.clone(),
@@ -320,6 +322,7 @@ async fn run() -> io::Result<()> {
         registry,
         Arc::clone(&crypto) as Arc<dyn TlsHandshake + Send + Sync>,
         Arc::clone(&crypto) as Arc<dyn IngressSigVerifier + Send + Sync>,
+        node_id,
         subnet_id,
         root_subnet_id,
         logger.clone(),
@@ -328,6 +331,7 @@ async fn run() -> io::Result<()> {
         Arc::from(ingress_message_filter),
         subnet_type,
         malicious_behaviour.malicious_flags.clone(),
+        round_state_dump,
     ));
 
     tokio::time::sleep(Duration::from_millis(5000)).await;
@@ -3,7 +3,7 @@ use ic_consensus::certification::VerifierImpl;
 use ic_crypto::CryptoComponent;
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_execution_environment::setup_execution;
-use ic_interfaces::execution_environment::IngressMessageFilter;
+use ic_interfaces::execution_environment::{IngressMessageFilter, RoundStateDumpReader};
 use ic_interfaces::registry::LocalStoreCertifiedTimeReader;
 use ic_interfaces::{
     certified_stream_store::CertifiedStreamStore, consensus_pool::ConsensusPoolCache,
@@ -44,6 +44,7 @@ pub fn construct_ic_stack(
     Arc<dyn ConsensusPoolCache>,
     Box<dyn IngressMessageFilter<State = ReplicatedState>>,
     XNetEndpoint,
+    Option<Arc<dyn RoundStateDumpReader>>,
 )> {
     let cycles_account_manager = Arc::new(CyclesAccountManager::new(
         subnet_config.scheduler_config.max_instructions_per_message,
@@ -68,6 +69,7 @@ pub fn construct_ic_stack(
         http_query_handler,
         scheduler,
         ingress_history_reader,
+        round_state_dump,
     ) = setup_execution(
         replica_logger.clone(),
         &metrics_registry,
@@ -190,5 +192,6 @@ pub fn construct_ic_stack(
         consensus_pool_cache,
         ingress_message_filter,
         xnet_endpoint,
+        round_state_dump,
     ))
 }
@@ -0,0 +1,512 @@
+//! Export of `tracing` spans to an OpenTelemetry collector.
+//!
+//! The replica records spans for operations that are worth debugging across
+//! components, e.g., the execution of a query and of every node of its call
+//! graph. `SpanExporter` is a `tracing` subscriber that keeps track of these
+//! spans and queues them for export once they are closed. A background task
+//! periodically sends the queued spans to the configured collector using the
+//! OpenTelemetry protocol over HTTP with JSON encoding (OTLP/HTTP).
+//!
+//! Events are not recorded; they are logged through the replica logger.
+
+use ic_config::tracing::Config as TracingConfig;
+use ic_logger::{info, warn, ReplicaLogger};
+use rand::Rng;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use url::Url;
+
+/// The path of the OTLP/HTTP endpoint for traces, relative to the collector.
+const OTLP_TRACES_PATH: &str = "v1/traces";
+/// Maximum number of spans sent to the collector in a single request.
+const MAX_SPANS_PER_EXPORT: usize = 1_000;
+
+/// Installs a `SpanExporter` as the global `tracing` subscriber and spawns
+/// the task exporting the spans, if `config` specifies a collector.
+/// Otherwise, spans are not recorded at all.
+///
+/// Must be called from within a tokio runtime.
+pub fn init(config: &TracingConfig, log: ReplicaLogger) {
+    let endpoint = match &config.otlp_endpoint {
+        Some(endpoint) => endpoint,
+        None => return,
+    };
+    let traces_url = match endpoint.join(OTLP_TRACES_PATH) {
+        Ok(url) => url,
+        Err(e) => {
+            warn!(log, "Invalid OTLP endpoint {}: {}", endpoint, e);
+            return;
+        }
+    };
+    let (exporter, finished_spans) = SpanExporter::new(config.max_queued_spans);
+    if let Err(e) = tracing::subscriber::set_global_default(exporter) {
+        warn!(log, "Failed to install the span exporter: {}", e);
+        return;
+    }
+    info!(log, "Exporting tracing spans to {}", traces_url);
+    tokio::spawn(export_task(
+        finished_spans,
+        traces_url,
+        config.service_name.clone(),
+        Duration::from_millis(config.export_interval_ms),
+        log,
+    ));
+}
+
+/// The value of a span attribute.
+#[derive(Clone, Debug, PartialEq)]
+enum AttributeValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+}
+
+/// A span that has been closed and is ready for export.
+#[derive(Clone, Debug, PartialEq)]
+struct FinishedSpan {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    name: &'static str,
+    start_time: SystemTime,
+    end_time: SystemTime,
+    attributes: Vec<(&'static str, AttributeValue)>,
+}
+
+/// The state of a span that has not been closed yet.
+struct OpenSpan {
+    span: FinishedSpan,
+    /// The number of handles of the span; the span is closed once the last
+    /// handle is dropped.
+    ref_count: usize,
+}
+
+thread_local! {
+    /// The spans entered on the current thread, innermost last.
+    static ENTERED_SPANS: RefCell<Vec<Id>> = RefCell::new(vec![]);
+}
+
+/// A `tracing` subscriber that queues all closed spans for export.
+struct SpanExporter {
+    next_id: AtomicU64,
+    open_spans: Mutex<HashMap<Id, OpenSpan>>,
+    finished_spans: SyncSender<FinishedSpan>,
+}
+
+impl SpanExporter {
+    /// Creates an exporter that queues up to `max_queued_spans` finished
+    /// spans in the returned receiver.
+    fn new(max_queued_spans: usize) -> (Self, Receiver<FinishedSpan>) {
+        let (finished_spans, receiver) = mpsc::sync_channel(max_queued_spans);
+        let exporter = Self {
+            next_id: AtomicU64::new(1),
+            open_spans: Mutex::new(HashMap::new()),
+            finished_spans,
+        };
+        (exporter, receiver)
+    }
+
+    fn current_span(&self) -> Option<Id> {
+        ENTERED_SPANS.with(|spans| spans.borrow().last().cloned())
+    }
+}
+
+impl Subscriber for SpanExporter {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span()
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let parent = if attributes.is_contextual() {
+            self.current_span()
+        } else {
+            attributes.parent().cloned()
+        };
+
+        let mut open_spans = self.open_spans.lock().unwrap();
+        let parent_ids = parent
+            .and_then(|parent| open_spans.get(&parent))
+            .map(|parent| (parent.span.trace_id, parent.span.span_id));
+        let mut rng = rand::thread_rng();
+        let (trace_id, parent_span_id) = match parent_ids {
+            Some((trace_id, parent_span_id)) => (trace_id, Some(parent_span_id)),
+            None => (rng.gen(), None),
+        };
+        let mut span = FinishedSpan {
+            trace_id,
+            span_id: rng.gen(),
+            parent_span_id,
+            name: attributes.metadata().name(),
+            start_time: SystemTime::now(),
+            end_time: UNIX_EPOCH,
+            attributes: vec![],
+        };
+        attributes.record(&mut AttributeVisitor(&mut span.attributes));
+        open_spans.insert(id.clone(), OpenSpan { span, ref_count: 1 });
+        id
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(open_span) = self.open_spans.lock().unwrap().get_mut(span) {
+            values.record(&mut AttributeVisitor(&mut open_span.span.attributes));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        ENTERED_SPANS.with(|spans| spans.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED_SPANS.with(|spans| {
+            let mut spans = spans.borrow_mut();
+            if let Some(position) = spans.iter().rposition(|entered| entered == span) {
+                spans.remove(position);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(open_span) = self.open_spans.lock().unwrap().get_mut(span) {
+            open_span.ref_count += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut open_spans = self.open_spans.lock().unwrap();
+        let closed = match open_spans.get_mut(&span) {
+            Some(open_span) => {
+                open_span.ref_count -= 1;
+                open_span.ref_count == 0
+            }
+            None => false,
+        };
+        if closed {
+            if let Some(OpenSpan { mut span, .. }) = open_spans.remove(&span) {
+                span.end_time = SystemTime::now();
+                // If the queue is full, the span is dropped rather than
+                // blocking the traced code.
+                let _ = self.finished_spans.try_send(span);
+            }
+        }
+        closed
+    }
+}
+
+/// Records the fields of a span as attributes, replacing earlier values of
+/// the same field.
+struct AttributeVisitor<'a>(&'a mut Vec<(&'static str, AttributeValue)>);
+
+impl AttributeVisitor<'_> {
+    fn set(&mut self, field: &Field, value: AttributeValue) {
+        let name = field.name();
+        match self.0.iter_mut().find(|(key, _)| *key == name) {
+            Some(attribute) => attribute.1 = value,
+            None => self.0.push((name, value)),
+        }
+    }
+}
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, AttributeValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(value) => self.set(field, AttributeValue::Int(value)),
+            Err(_) => self.set(field, AttributeValue::String(value.to_string())),
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, AttributeValue::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, AttributeValue::String(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field, AttributeValue::String(format!("{:?}", value)));
+    }
+}
+
+/// Periodically sends the finished spans to the collector at `traces_url`.
+async fn export_task(
+    finished_spans: Receiver<FinishedSpan>,
+    traces_url: Url,
+    service_name: String,
+    export_interval: Duration,
+    log: ReplicaLogger,
+) {
+    let client = reqwest::Client::new();
+    loop {
+        tokio::time::sleep(export_interval).await;
+        loop {
+            let spans: Vec<_> = finished_spans
+                .try_iter()
+                .take(MAX_SPANS_PER_EXPORT)
+                .collect();
+            if spans.is_empty() {
+                break;
+            }
+            let request = export_trace_service_request(&service_name, &spans);
+            let result = client
+                .post(traces_url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(request.to_string())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn!(
+                    every_n_seconds => 60,
+                    log,
+                    "Failed to export {} spans to {}: {}",
+                    spans.len(),
+                    traces_url,
+                    e
+                );
+                break;
+            }
+        }
+    }
+}
+
+/// Encodes `spans` as an OTLP `ExportTraceServiceRequest` in the JSON
+/// encoding of OTLP/HTTP.
+fn export_trace_service_request(service_name: &str, spans: &[FinishedSpan]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute_json(
+                    "service.name",
+                    &AttributeValue::String(service_name.to_string()),
+                )],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "ic-replica" },
+                "spans": spans.iter().map(span_json).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+fn span_json(span: &FinishedSpan) -> Value {
+    let mut value = json!({
+        "traceId": hex::encode(span.trace_id),
+        "spanId": hex::encode(span.span_id),
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": unix_nanos(span.start_time).to_string(),
+        "endTimeUnixNano": unix_nanos(span.end_time).to_string(),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(key, value)| attribute_json(key, value))
+            .collect::<Vec<_>>(),
+    });
+    if let Some(parent_span_id) = span.parent_span_id {
+        value["parentSpanId"] = json!(hex::encode(parent_span_id));
+    }
+    value
+}
+
+fn attribute_json(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::Bool(value) => json!({ "boolValue": value }),
+        // 64-bit integers are encoded as strings in OTLP/JSON.
+        AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+        AttributeValue::String(value) => json!({ "stringValue": value }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `f` with a new `SpanExporter` as the default subscriber and
+    /// returns the spans it finished, in the order they were closed.
+    fn finished_spans(max_queued_spans: usize, f: impl FnOnce()) -> Vec<FinishedSpan> {
+        let (exporter, finished_spans) = SpanExporter::new(max_queued_spans);
+        tracing::subscriber::with_default(exporter, f);
+        finished_spans.try_iter().collect()
+    }
+
+    #[test]
+    fn nested_spans_belong_to_the_same_trace() {
+        let spans = finished_spans(10, || {
+            let outer = tracing::info_span!("outer");
+            let _enter = outer.enter();
+            tracing::info_span!("inner").in_scope(|| {});
+        });
+
+        assert_eq!(spans.len(), 2);
+        let (inner, outer) = (&spans[0], &spans[1]);
+        assert_eq!(inner.name, "inner");
+        assert_eq!(outer.name, "outer");
+        assert_eq!(inner.trace_id, outer.trace_id);
+        assert_eq!(inner.parent_span_id, Some(outer.span_id));
+        assert_eq!(outer.parent_span_id, None);
+        assert!(inner.start_time >= outer.start_time);
+        assert!(inner.end_time <= outer.end_time);
+    }
+
+    #[test]
+    fn sibling_root_spans_start_new_traces() {
+        let spans = finished_spans(10, || {
+            tracing::info_span!("first").in_scope(|| {});
+            tracing::info_span!("second").in_scope(|| {});
+        });
+
+        assert_eq!(spans.len(), 2);
+        assert_ne!(spans[0].trace_id, spans[1].trace_id);
+        assert_eq!(spans[1].parent_span_id, None);
+    }
+
+    #[test]
+    fn span_is_finished_when_its_last_handle_is_dropped() {
+        let (exporter, receiver) = SpanExporter::new(10);
+        tracing::subscriber::with_default(exporter, || {
+            let span = tracing::info_span!("cloned");
+            let clone = span.clone();
+            drop(span);
+            assert_eq!(receiver.try_iter().count(), 0);
+            drop(clone);
+            assert_eq!(receiver.try_iter().count(), 1);
+        });
+    }
+
+    #[test]
+    fn fields_recorded_later_replace_empty_and_earlier_values() {
+        let spans = finished_spans(10, || {
+            let span = tracing::info_span!(
+                "execute",
+                method_name = "query",
+                instructions = tracing::field::Empty,
+                compilation_cache_hit = false,
+                large = tracing::field::Empty,
+            );
+            span.record("instructions", &42u64);
+            span.record("compilation_cache_hit", &true);
+            span.record("large", &u64::MAX);
+        });
+
+        assert_eq!(
+            spans[0].attributes,
+            vec![
+                ("method_name", AttributeValue::String("query".to_string())),
+                ("compilation_cache_hit", AttributeValue::Bool(true)),
+                ("instructions", AttributeValue::Int(42)),
+                ("large", AttributeValue::String(u64::MAX.to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_are_dropped_when_the_queue_is_full() {
+        let spans = finished_spans(1, || {
+            tracing::info_span!("first").in_scope(|| {});
+            tracing::info_span!("second").in_scope(|| {});
+        });
+
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "first");
+    }
+
+    #[test]
+    fn events_are_not_recorded() {
+        let spans = finished_spans(10, || {
+            tracing::info!("not a span");
+        });
+
+        assert!(spans.is_empty());
+    }
+
+    #[test]
+    fn spans_are_encoded_as_otlp_json() {
+        let span = FinishedSpan {
+            trace_id: [1; 16],
+            span_id: [2; 8],
+            parent_span_id: Some([3; 8]),
+            name: "execute_query",
+            start_time: UNIX_EPOCH + Duration::from_nanos(1_000),
+            end_time: UNIX_EPOCH + Duration::from_nanos(2_000),
+            attributes: vec![
+                ("instructions", AttributeValue::Int(42)),
+                ("compilation_cache_hit", AttributeValue::Bool(true)),
+            ],
+        };
+
+        assert_eq!(
+            export_trace_service_request("replica", &[span]),
+            json!({
+                "resourceSpans": [{
+                    "resource": {
+                        "attributes": [
+                            { "key": "service.name", "value": { "stringValue": "replica" } },
+                        ],
+                    },
+                    "scopeSpans": [{
+                        "scope": { "name": "ic-replica" },
+                        "spans": [{
+                            "traceId": "01010101010101010101010101010101",
+                            "spanId": "0202020202020202",
+                            "parentSpanId": "0303030303030303",
+                            "name": "execute_query",
+                            "kind": 1,
+                            "startTimeUnixNano": "1000",
+                            "endTimeUnixNano": "2000",
+                            "attributes": [
+                                { "key": "instructions", "value": { "intValue": "42" } },
+                                {
+                                    "key": "compilation_cache_hit",
+                                    "value": { "boolValue": true },
+                                },
+                            ],
+                        }],
+                    }],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn root_span_is_encoded_without_parent() {
+        let span = FinishedSpan {
+            trace_id: [1; 16],
+            span_id: [2; 8],
+            parent_span_id: None,
+            name: "query",
+            start_time: UNIX_EPOCH,
+            end_time: UNIX_EPOCH,
+            attributes: vec![],
+        };
+
+        assert!(span_json(&span).get("parentSpanId").is_none());
+    }
+}
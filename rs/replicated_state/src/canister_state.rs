@@ -4,7 +4,9 @@ pub mod system_state;
 
 use crate::canister_state::system_state::{CanisterStatus, SystemState};
 use crate::StateError;
-pub use execution_state::{EmbedderCache, ExecutionState, ExportedFunctions, Global};
+pub use execution_state::{
+    CompilationTrigger, EmbedderCache, ExecutionState, ExportedFunctions, Global,
+};
 use ic_interfaces::messages::CanisterInputMessage;
 use ic_types::methods::SystemMethod;
 use ic_types::{
@@ -12,10 +14,13 @@ use ic_types::{
     methods::WasmMethod,
     xnet::QueueId,
     AccumulatedPriority, CanisterId, CanisterStatusType, ComputeAllocation, ExecutionRound,
-    MemoryAllocation, NumBytes, PrincipalId, QueueIndex,
+    MemoryAllocation, NumBytes, NumInstructions, PrincipalId, PriorityClass, QueueIndex,
+    MAX_WASM_MEMORY_IN_BYTES,
 };
 use phantom_newtype::AmountOf;
-pub use queues::{CanisterQueues, QUEUE_INDEX_NONE};
+pub use queues::{
+    CanisterQueues, DEFAULT_QUEUE_CAPACITY, MAX_RESPONSE_COUNT_BYTES, QUEUE_INDEX_NONE,
+};
 use std::collections::BTreeSet;
 use std::convert::From;
 
@@ -35,6 +40,16 @@ pub struct SchedulerState {
     /// rounds. In the scheduler analysis documentation, this value is the entry
     /// in the vector d that corresponds to this canister.
     pub accumulated_priority: AccumulatedPriority,
+
+    /// The class of a canister takes precedence over its accumulated priority
+    /// when ordering the canisters of a round. Only canisters on the NNS
+    /// subnet may change it.
+    pub priority_class: PriorityClass,
+
+    /// The maximum number of instructions the canister's heartbeat may consume
+    /// in a round. `None` means that it is only bounded by the instruction
+    /// limit per message, which also caps any value set here.
+    pub heartbeat_instruction_limit: Option<NumInstructions>,
 }
 
 impl Default for SchedulerState {
@@ -43,6 +58,8 @@ impl Default for SchedulerState {
             last_full_execution_round: ExecutionRound::from(0),
             compute_allocation: ComputeAllocation::default(),
             accumulated_priority: AccumulatedPriority::default(),
+            priority_class: PriorityClass::default(),
+            heartbeat_instruction_limit: None,
         }
     }
 }
@@ -178,6 +195,28 @@ impl CanisterState {
         }
     }
 
+    /// Returns true if fewer than `threshold` bytes of Wasm memory can still
+    /// be allocated, either because the canister is close to its memory limit
+    /// or because its Wasm heap is close to the maximum Wasm memory size.
+    pub fn is_low_on_wasm_memory(&self, default_limit: NumBytes, threshold: NumBytes) -> bool {
+        let execution_state = match &self.execution_state {
+            Some(execution_state) => execution_state,
+            None => return false,
+        };
+        let remaining_in_limit = self
+            .memory_limit(default_limit)
+            .get()
+            .saturating_sub(self.memory_usage().get());
+        let remaining_in_heap = MAX_WASM_MEMORY_IN_BYTES
+            .saturating_sub(num_bytes_from(execution_state.heap_size).get());
+        remaining_in_limit.min(remaining_in_heap) < threshold.get()
+    }
+
+    /// Returns true if the canister has system tasks to execute.
+    pub fn has_pending_system_tasks(&self) -> bool {
+        !self.system_state.task_queue.is_empty()
+    }
+
     /// Returns the current compute allocation for the canister.
     pub fn compute_allocation(&self) -> ComputeAllocation {
         self.scheduler_state.compute_allocation
@@ -194,6 +233,16 @@ impl CanisterState {
         }
     }
 
+    /// Returns true if the canister exports the `canister_on_low_wasm_memory`
+    /// system method.
+    pub fn exports_on_low_wasm_memory_method(&self) -> bool {
+        match &self.execution_state {
+            Some(execution_state) => execution_state
+                .exports_method(&WasmMethod::System(SystemMethod::CanisterOnLowWasmMemory)),
+            None => false,
+        }
+    }
+
     /// Returns true if the canister contains an exported query method with the
     /// name provided, false otherwise.
     pub fn exports_query_method(&self, method_name: String) -> bool {
@@ -49,6 +49,41 @@ impl std::fmt::Debug for EmbedderCache {
     }
 }
 
+/// The reason for compiling a Wasm module, used to label the compilation
+/// metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompilationTrigger {
+    /// The module of a canister that is installed or reinstalled.
+    Install,
+    /// The new module of a canister that is upgraded.
+    Upgrade,
+    /// A module that is missing from the compilation cache of the query
+    /// handler.
+    QueryRecompile,
+    /// A module that is not compiled (with the current instruction cost
+    /// overrides) when a message is executed, e.g., after loading a
+    /// checkpoint.
+    Execution,
+}
+
+impl CompilationTrigger {
+    pub const ALL: [CompilationTrigger; 4] = [
+        CompilationTrigger::Install,
+        CompilationTrigger::Upgrade,
+        CompilationTrigger::QueryRecompile,
+        CompilationTrigger::Execution,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompilationTrigger::Install => "install",
+            CompilationTrigger::Upgrade => "upgrade",
+            CompilationTrigger::QueryRecompile => "query_recompile",
+            CompilationTrigger::Execution => "execution",
+        }
+    }
+}
+
 /// An enum representing the possible values of a global variable.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Global {
@@ -200,6 +235,12 @@ pub struct ExecutionState {
 
     /// Mapped state of the current execution
     pub mapped_state: Option<Arc<MappedStateImpl>>,
+
+    /// What the next compilation of the module is attributed to in the
+    /// metrics if it happens in an execution, e.g., `Install` for the module
+    /// of a canister that was just installed. Reset by that compilation and
+    /// not persisted in checkpoints.
+    pub pending_compilation_trigger: Option<CompilationTrigger>,
 }
 
 // We have to implement it by hand as embedder_cache can not be compared for
@@ -277,6 +318,7 @@ impl ExecutionState {
             last_executed_round: ExecutionRound::from(0),
             cow_mem_mgr,
             mapped_state: None,
+            pending_compilation_trigger: None,
         };
 
         Ok(execution_state)
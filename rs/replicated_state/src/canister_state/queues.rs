@@ -8,9 +8,10 @@ use ic_protobuf::{
     types::v1 as pb_types,
 };
 use ic_types::{
-    messages::{Ingress, Request, RequestOrResponse, Response},
+    messages::{Ingress, Payload, RejectContext, Request, RequestOrResponse, Response},
+    user_error::RejectCode,
     xnet::{QueueId, SessionId},
-    CanisterId, CountBytes, QueueIndex,
+    CanisterId, CountBytes, NumBytes, QueueIndex, Time,
 };
 use queue::{IngressQueue, InputQueue, OutputQueue};
 use std::convert::{From, TryFrom};
@@ -21,6 +22,13 @@ use std::{
 
 pub const DEFAULT_QUEUE_CAPACITY: usize = 500;
 
+/// Upper bound on the size of a response. Every reserved response slot
+/// accounts for this many bytes of message memory until the response is
+/// enqueued, since the actual size of the response is not known in advance.
+///
+/// Equal to `ic_types::messages::MAX_XNET_PAYLOAD_IN_BYTES`.
+pub const MAX_RESPONSE_COUNT_BYTES: usize = 2202009;
+
 /// "None" queue index used internally by Message Routing for reject responses
 /// generated e.g. when a request cannot be inducted due to a full input queue
 /// (and enqueuing the response into the output queue might also fail).
@@ -36,6 +44,11 @@ pub const QUEUE_INDEX_NONE: QueueIndex = QueueIndex::new(std::u64::MAX);
 /// Encapsulates the `InductionPool` component described in the spec. The reason
 /// for bundling together the induction pool and output queues is to reliably
 /// implement backpressure via queue reservations for response messages.
+///
+/// Requests may be tagged with a deadline (best-effort requests). Requests
+/// still enqueued after their deadline are timed out by `time_out_requests()`,
+/// which replaces them with reject responses to their senders. Deadlines are
+/// preserved by induction on the same subnet, but not across subnets.
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct CanisterQueues {
     /// Queue of ingress (user) messages.
@@ -56,6 +69,11 @@ pub struct CanisterQueues {
 
     /// Byte size of input queues (queues + messages).
     input_queues_size_bytes: usize,
+
+    /// Number of response slots reserved across all input and output queues.
+    /// Each reservation holds `MAX_RESPONSE_COUNT_BYTES` of message memory
+    /// until the response is enqueued.
+    reserved_slots: usize,
 }
 
 impl CanisterQueues {
@@ -103,26 +121,50 @@ impl CanisterQueues {
         &mut self,
         index: QueueIndex,
         msg: RequestOrResponse,
+    ) -> Result<(), (StateError, RequestOrResponse)> {
+        self.push_input_with_deadline(index, msg, None)
+    }
+
+    /// Pushes a canister-to-canister message into the induction pool, same as
+    /// `push_input()`. If the message is a `Request` with a `deadline`, it is
+    /// timed out by `time_out_requests()` unless executed before the
+    /// deadline. Responses never have a deadline.
+    pub fn push_input_with_deadline(
+        &mut self,
+        index: QueueIndex,
+        msg: RequestOrResponse,
+        deadline: Option<Time>,
     ) -> Result<(), (StateError, RequestOrResponse)> {
         let sender = msg.sender();
-        let input_queue = match msg {
-            RequestOrResponse::Request(_) => {
-                let (input_queue, output_queue) = self.get_or_insert_queues(&sender);
-                if let Err(e) = input_queue.check_has_slot() {
-                    return Err((e, msg));
-                }
-                if let Err(e) = output_queue.reserve_slot() {
-                    return Err((e, msg));
-                }
-                input_queue
+        let is_request = matches!(msg, RequestOrResponse::Request(_));
+        let msg_size_bytes = InputQueue::message_size_bytes(&msg);
+        let input_queue = if is_request {
+            let (input_queue, output_queue) = self.get_or_insert_queues(&sender);
+            if let Err(e) = input_queue.check_has_slot() {
+                return Err((e, msg));
+            }
+            if let Err(e) = output_queue.reserve_slot() {
+                return Err((e, msg));
             }
-            RequestOrResponse::Response(_) => match self.input_queues.get_mut(&sender) {
+            if let Err(e) = input_queue.push(index, msg, deadline) {
+                // Do not leak the response reservation if the request could
+                // not be enqueued after all.
+                output_queue.release_reserved_slot();
+                return Err(e);
+            }
+            self.reserved_slots += 1;
+            self.input_queues.get_mut(&sender).unwrap()
+        } else {
+            let input_queue = match self.input_queues.get_mut(&sender) {
                 Some(queue) => queue,
                 None => return Err((StateError::QueueFull { capacity: 0 }, msg)),
-            },
+            };
+            input_queue.push(index, msg, None)?;
+            // The reservation made when the request was sent is released now
+            // that the response has been inducted.
+            self.reserved_slots -= 1;
+            input_queue
         };
-        let msg_size_bytes = InputQueue::message_size_bytes(&msg);
-        input_queue.push(index, msg)?;
 
         // Add sender canister ID to the input schedule queue if it isn't already there.
         // Sender was not scheduled iff its input queue was empty before the push (i.e.
@@ -140,6 +182,7 @@ impl CanisterQueues {
                 self.input_queues_size_bytes
             )
         );
+        self.debug_assert_reserved_slots();
 
         Ok(())
     }
@@ -215,6 +258,19 @@ impl CanisterQueues {
     /// Returns a `QueueFull` error along with the provided message if either
     /// the output queue or the matching input queue is full.
     pub fn push_output_request(&mut self, msg: Request) -> Result<(), (StateError, Request)> {
+        self.push_output_request_with_deadline(msg, None)
+    }
+
+    /// Pushes a `Request` type message into the relevant output queue, same
+    /// as `push_output_request()`. If a `deadline` is given, the request is a
+    /// best-effort request: unless it is inducted before the deadline, it is
+    /// timed out by `time_out_requests()` and a reject response is delivered
+    /// to the sender instead.
+    pub fn push_output_request_with_deadline(
+        &mut self,
+        msg: Request,
+        deadline: Option<Time>,
+    ) -> Result<(), (StateError, Request)> {
         let (input_queue, output_queue) = self.get_or_insert_queues(&msg.receiver);
 
         if let Err(e) = output_queue.check_has_slot() {
@@ -223,8 +279,69 @@ impl CanisterQueues {
         if let Err(e) = input_queue.reserve_slot() {
             return Err((e, msg));
         }
+        if let Err(e) = output_queue.push_request(msg, deadline) {
+            // Do not leak the response reservation if the request could not
+            // be enqueued after all.
+            input_queue.release_reserved_slot();
+            return Err(e);
+        }
 
-        output_queue.push_request(msg)
+        self.reserved_slots += 1;
+        self.debug_assert_reserved_slots();
+        Ok(())
+    }
+
+    /// Pushes a `Request` type message into the relevant output queue, same
+    /// as `push_output_request()`, but only if the request and the
+    /// `MAX_RESPONSE_COUNT_BYTES` reserved for its response fit into the
+    /// given message memory pool.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `OutOfMemory` error along with the provided message if the
+    /// memory used by the queues and their reservations, plus the memory
+    /// required by the request, exceeds `message_memory_capacity`.
+    ///
+    /// Returns a `QueueFull` error along with the provided message if either
+    /// the output queue or the matching input queue is full.
+    pub fn push_output_request_with_memory_limit(
+        &mut self,
+        msg: Request,
+        message_memory_capacity: NumBytes,
+    ) -> Result<(), (StateError, Request)> {
+        let requested = NumBytes::from(
+            (OutputQueue::request_size_bytes(&msg) + MAX_RESPONSE_COUNT_BYTES) as u64,
+        );
+        let available = NumBytes::from(
+            message_memory_capacity
+                .get()
+                .saturating_sub(self.memory_usage() as u64),
+        );
+        if requested > available {
+            return Err((
+                StateError::OutOfMemory {
+                    requested,
+                    available,
+                },
+                msg,
+            ));
+        }
+        self.push_output_request(msg)
+    }
+
+    /// Returns how many more requests to `receiver` can be pushed with
+    /// `push_output_request()` before either the output queue or the matching
+    /// input queue, which holds the response reservations, is full.
+    pub fn available_output_request_slots(&self, receiver: &CanisterId) -> usize {
+        let input_slots = self
+            .input_queues
+            .get(receiver)
+            .map_or(DEFAULT_QUEUE_CAPACITY, |queue| queue.available_slots());
+        let output_slots = self
+            .output_queues
+            .get(receiver)
+            .map_or(DEFAULT_QUEUE_CAPACITY, |queue| queue.available_slots());
+        input_slots.min(output_slots)
     }
 
     /// Pushes a `Response` type message into the relevant output queue. The
@@ -244,6 +361,55 @@ impl CanisterQueues {
             .get_mut(receiver)
             .unwrap()
             .push_response(msg);
+        // The reservation made when the request was inducted is released now
+        // that the response has been enqueued.
+        self.reserved_slots -= 1;
+        self.debug_assert_reserved_slots();
+    }
+
+    /// Times out all requests in input and output queues whose deadline is at
+    /// or before `current_time`.
+    ///
+    /// Every timed out request is replaced by a `SysTransient` reject
+    /// response to its sender, refunding the attached cycles: timed out
+    /// output requests are rejected into the matching input queue; timed out
+    /// input requests into the matching output queue. Either way, the reject
+    /// response takes the slot reserved for the actual response.
+    ///
+    /// Returns the number of timed out requests.
+    pub fn time_out_requests(&mut self, current_time: Time) -> usize {
+        let mut timed_out_output_requests = Vec::new();
+        for output_queue in self.output_queues.values_mut() {
+            timed_out_output_requests.append(&mut output_queue.time_out_requests(current_time));
+        }
+
+        let mut timed_out_input_requests = Vec::new();
+        for input_queue in self.input_queues.values_mut() {
+            timed_out_input_requests.append(&mut input_queue.time_out_requests(current_time));
+        }
+        if !timed_out_input_requests.is_empty() {
+            let input_queues = &self.input_queues;
+            self.input_schedule
+                .retain(|sender| input_queues[sender].num_messages() > 0);
+            let (message_count, size_bytes) = Self::input_queues_stats(&self.input_queues);
+            self.input_queues_message_count = message_count;
+            self.input_queues_size_bytes = size_bytes;
+        }
+
+        let timed_out_count = timed_out_output_requests.len() + timed_out_input_requests.len();
+        for request in timed_out_output_requests {
+            let response = timed_out_reject_response(request);
+            if let Err((err, response)) = self.push_input(QUEUE_INDEX_NONE, response.into()) {
+                unreachable!(
+                    "Failed to enqueue reject response {:?} into reserved slot: {}",
+                    response, err
+                );
+            }
+        }
+        for request in timed_out_input_requests {
+            self.push_output_response(timed_out_reject_response(request));
+        }
+        timed_out_count
     }
 
     /// Returns an iterator that consumes all output messages.
@@ -291,6 +457,40 @@ impl CanisterQueues {
         self.input_queues_size_bytes
     }
 
+    /// Returns the number of messages enqueued in canister output queues.
+    ///
+    /// Time complexity: O(num_output_queues).
+    pub fn output_queues_message_count(&self) -> usize {
+        self.output_queues.values().map(|q| q.num_messages()).sum()
+    }
+
+    /// Returns the number of response slots reserved across all input and
+    /// output queues.
+    pub fn reserved_slots(&self) -> usize {
+        self.reserved_slots
+    }
+
+    /// Returns the message memory held by response reservations, i.e.
+    /// `MAX_RESPONSE_COUNT_BYTES` for every reserved slot.
+    pub fn reserved_slots_size_bytes(&self) -> usize {
+        self.reserved_slots * MAX_RESPONSE_COUNT_BYTES
+    }
+
+    /// Returns the total message memory used by canister input and output
+    /// queues (queues + messages), plus the memory held by response
+    /// reservations. Ingress messages are not included.
+    ///
+    /// Time complexity: O(num_output_queues).
+    pub fn memory_usage(&self) -> usize {
+        self.input_queues_size_bytes
+            + self
+                .output_queues
+                .values()
+                .map(|q| q.count_bytes())
+                .sum::<usize>()
+            + self.reserved_slots_size_bytes()
+    }
+
     fn get_or_insert_queues(
         &mut self,
         canister_id: &CanisterId,
@@ -309,6 +509,28 @@ impl CanisterQueues {
         (input_queue, output_queue)
     }
 
+    /// Computes the total number of reserved slots across `input_queues` and
+    /// `output_queues`.
+    fn reserved_slots_stats(
+        input_queues: &BTreeMap<CanisterId, InputQueue>,
+        output_queues: &BTreeMap<CanisterId, OutputQueue>,
+    ) -> usize {
+        input_queues
+            .values()
+            .map(|q| q.reserved_slots())
+            .chain(output_queues.values().map(|q| q.reserved_slots()))
+            .sum()
+    }
+
+    /// Checks (in debug builds only) that the tracked number of reserved slots
+    /// matches the reservations held by the individual queues.
+    fn debug_assert_reserved_slots(&self) {
+        debug_assert_eq!(
+            Self::reserved_slots_stats(&self.input_queues, &self.output_queues),
+            self.reserved_slots
+        );
+    }
+
     /// Computes total message count and total byte size for `input_queues`.
     fn input_queues_stats(input_queues: &BTreeMap<CanisterId, InputQueue>) -> (usize, usize) {
         let mut message_count = 0;
@@ -321,6 +543,20 @@ impl CanisterQueues {
     }
 }
 
+/// Generates the reject `Response` for a `Request` that has timed out.
+fn timed_out_reject_response(request: Request) -> Response {
+    Response {
+        originator: request.sender,
+        respondent: request.receiver,
+        originator_reply_callback: request.sender_reply_callback,
+        refund: request.payment,
+        response_payload: Payload::Reject(RejectContext::new(
+            RejectCode::SysTransient,
+            format!("Request to {} timed out", request.receiver),
+        )),
+    }
+}
+
 impl From<&CanisterQueues> for pb_queues::CanisterQueues {
     fn from(item: &CanisterQueues) -> Self {
         Self {
@@ -392,6 +628,8 @@ impl TryFrom<pb_queues::CanisterQueues> for CanisterQueues {
             output_queues.insert(can_id, oq);
         }
 
+        let reserved_slots = Self::reserved_slots_stats(&input_queues, &output_queues);
+
         let mut input_schedule = VecDeque::new();
         for can_id in item.input_schedule.into_iter() {
             let c = CanisterId::try_from(can_id)?;
@@ -405,6 +643,7 @@ impl TryFrom<pb_queues::CanisterQueues> for CanisterQueues {
             output_queues,
             input_queues_message_count,
             input_queues_size_bytes,
+            reserved_slots,
         })
     }
 }
@@ -454,10 +693,7 @@ pub mod testing {
         }
 
         fn output_message_count(&self) -> usize {
-            self.output_queues
-                .iter()
-                .map(|(_, q)| q.num_messages())
-                .sum()
+            self.output_queues_message_count()
         }
     }
 }
@@ -465,12 +701,13 @@ pub mod testing {
 #[cfg(test)]
 mod test {
     use super::super::CanisterInputMessage;
+    use super::testing::CanisterQueuesTesting;
     use super::*;
     use ic_test_utilities::types::{
         ids::{canister_test_id, message_test_id, user_test_id},
         messages::{IngressBuilder, RequestBuilder, ResponseBuilder},
     };
-    use ic_types::time::current_time_and_expiry_time;
+    use ic_types::{time::current_time_and_expiry_time, Cycles};
     use std::convert::TryInto;
 
     #[test]
@@ -547,6 +784,191 @@ mod test {
         );
     }
 
+    #[test]
+    fn max_response_count_bytes_matches_max_xnet_payload() {
+        assert_eq!(
+            MAX_RESPONSE_COUNT_BYTES as u64,
+            ic_types::messages::MAX_XNET_PAYLOAD_IN_BYTES.get()
+        );
+    }
+
+    #[test]
+    /// Reservations are made when requests are enqueued and released exactly
+    /// when the matching responses are enqueued.
+    fn reserved_slots_are_released_on_response() {
+        let this = canister_test_id(13);
+        let other = canister_test_id(14);
+        let mut queues = CanisterQueues::default();
+        assert_eq!(0, queues.reserved_slots());
+        assert_eq!(0, queues.reserved_slots_size_bytes());
+
+        // Outgoing request reserves a slot in the input queue.
+        queues
+            .push_output_request(
+                RequestBuilder::default()
+                    .sender(this)
+                    .receiver(other)
+                    .build(),
+            )
+            .unwrap();
+        // Incoming request reserves a slot in the output queue.
+        queues
+            .push_input(
+                QueueIndex::from(0),
+                RequestBuilder::default()
+                    .sender(other)
+                    .receiver(this)
+                    .build()
+                    .into(),
+            )
+            .unwrap();
+        assert_eq!(2, queues.reserved_slots());
+        assert_eq!(
+            2 * MAX_RESPONSE_COUNT_BYTES,
+            queues.reserved_slots_size_bytes()
+        );
+        assert!(queues.memory_usage() > 2 * MAX_RESPONSE_COUNT_BYTES);
+
+        queues.push_output_response(
+            ResponseBuilder::default()
+                .respondent(this)
+                .originator(other)
+                .build(),
+        );
+        assert_eq!(1, queues.reserved_slots());
+
+        queues
+            .push_input(
+                QueueIndex::from(1),
+                ResponseBuilder::default()
+                    .respondent(other)
+                    .originator(this)
+                    .build()
+                    .into(),
+            )
+            .unwrap();
+        assert_eq!(0, queues.reserved_slots());
+        assert_eq!(0, queues.reserved_slots_size_bytes());
+    }
+
+    #[test]
+    /// Available output request slots account for both queued requests and
+    /// response reservations.
+    fn available_output_request_slots_until_queue_full() {
+        let this = canister_test_id(13);
+        let other = canister_test_id(14);
+        let mut queues = CanisterQueues::default();
+        assert_eq!(
+            DEFAULT_QUEUE_CAPACITY,
+            queues.available_output_request_slots(&other)
+        );
+
+        // An incoming request reserves a slot in the output queue.
+        queues
+            .push_input(
+                QueueIndex::from(0),
+                RequestBuilder::default()
+                    .sender(other)
+                    .receiver(this)
+                    .build()
+                    .into(),
+            )
+            .unwrap();
+        assert_eq!(
+            DEFAULT_QUEUE_CAPACITY - 1,
+            queues.available_output_request_slots(&other)
+        );
+
+        for _ in 0..DEFAULT_QUEUE_CAPACITY - 1 {
+            queues
+                .push_output_request(
+                    RequestBuilder::default()
+                        .sender(this)
+                        .receiver(other)
+                        .build(),
+                )
+                .unwrap();
+        }
+        assert_eq!(0, queues.available_output_request_slots(&other));
+        assert_eq!(
+            StateError::QueueFull {
+                capacity: DEFAULT_QUEUE_CAPACITY
+            },
+            queues
+                .push_output_request(
+                    RequestBuilder::default()
+                        .sender(this)
+                        .receiver(other)
+                        .build()
+                )
+                .unwrap_err()
+                .0
+        );
+
+        // Queues to other canisters are not affected.
+        assert_eq!(
+            DEFAULT_QUEUE_CAPACITY,
+            queues.available_output_request_slots(&canister_test_id(15))
+        );
+    }
+
+    #[test]
+    /// Rejected responses do not release any reservation.
+    fn unexpected_response_does_not_release_reservation() {
+        let this = canister_test_id(13);
+        let other = canister_test_id(14);
+        let mut queues = CanisterQueues::default();
+        queues
+            .push_input(
+                QueueIndex::from(0),
+                RequestBuilder::default()
+                    .sender(other)
+                    .receiver(this)
+                    .build()
+                    .into(),
+            )
+            .unwrap();
+        queues
+            .push_input(
+                QUEUE_INDEX_NONE,
+                ResponseBuilder::default()
+                    .respondent(other)
+                    .originator(this)
+                    .build()
+                    .into(),
+            )
+            .unwrap_err();
+        assert_eq!(1, queues.reserved_slots());
+    }
+
+    #[test]
+    /// Requests are only enqueued if they and the reservation for their
+    /// response fit into the message memory pool.
+    fn push_output_request_with_memory_limit() {
+        let this = canister_test_id(13);
+        let mut queues = CanisterQueues::default();
+        let capacity = NumBytes::from(MAX_RESPONSE_COUNT_BYTES as u64 + 10_000);
+
+        queues
+            .push_output_request_with_memory_limit(
+                RequestBuilder::default().sender(this).build(),
+                capacity,
+            )
+            .unwrap();
+        assert_eq!(1, queues.reserved_slots());
+
+        match queues.push_output_request_with_memory_limit(
+            RequestBuilder::default().sender(this).build(),
+            capacity,
+        ) {
+            Err((StateError::OutOfMemory { requested, .. }, _)) => {
+                assert!(requested.get() > MAX_RESPONSE_COUNT_BYTES as u64)
+            }
+            res => panic!("Expected OutOfMemory error, got {:?}", res),
+        }
+        assert_eq!(1, queues.reserved_slots());
+    }
+
     #[test]
     /// Can push one request to the induction pool.
     fn can_push_input_request() {
@@ -904,4 +1326,125 @@ mod test {
 
         assert_eq!(queues, decoded);
     }
+
+    #[test]
+    /// Output requests past their deadline are replaced by reject responses
+    /// in the matching input queue, refunding the attached cycles.
+    fn time_out_output_requests() {
+        let this = canister_test_id(13);
+        let other = canister_test_id(14);
+        let deadline = Time::from_nanos_since_unix_epoch(1_000);
+        let mut queues = CanisterQueues::default();
+        queues
+            .push_output_request_with_deadline(
+                RequestBuilder::default()
+                    .sender(this)
+                    .receiver(other)
+                    .payment(Cycles::from(42))
+                    .build(),
+                Some(deadline),
+            )
+            .unwrap();
+        queues
+            .push_output_request(
+                RequestBuilder::default()
+                    .sender(this)
+                    .receiver(other)
+                    .build(),
+            )
+            .unwrap();
+        assert_eq!(2, queues.reserved_slots());
+
+        // Nothing times out before the deadline.
+        assert_eq!(
+            0,
+            queues.time_out_requests(Time::from_nanos_since_unix_epoch(999))
+        );
+        assert_eq!(1, queues.time_out_requests(deadline));
+
+        assert_eq!(1, queues.output_message_count());
+        assert_eq!(1, queues.reserved_slots());
+        match queues.pop_input().expect("could not pop a message") {
+            CanisterInputMessage::Response(response) => {
+                assert_eq!(this, response.originator);
+                assert_eq!(other, response.respondent);
+                assert_eq!(Cycles::from(42), response.refund);
+                match response.response_payload {
+                    Payload::Reject(context) => assert_eq!(RejectCode::SysTransient, context.code),
+                    payload => panic!("unexpected payload: {:?}", payload),
+                }
+            }
+            msg => panic!("unexpected message popped: {:?}", msg),
+        }
+        assert!(!queues.has_input());
+    }
+
+    #[test]
+    /// Input requests past their deadline are replaced by reject responses in
+    /// the matching output queue and are no longer scheduled.
+    fn time_out_input_requests() {
+        let this = canister_test_id(13);
+        let other = canister_test_id(14);
+        let deadline = Time::from_nanos_since_unix_epoch(1_000);
+        let mut queues = CanisterQueues::default();
+        queues
+            .push_input_with_deadline(
+                QueueIndex::from(0),
+                RequestBuilder::default()
+                    .sender(other)
+                    .receiver(this)
+                    .build()
+                    .into(),
+                Some(deadline),
+            )
+            .unwrap();
+        assert_eq!(1, queues.input_queues_message_count());
+
+        assert_eq!(1, queues.time_out_requests(deadline));
+
+        assert!(!queues.has_input());
+        assert_eq!(0, queues.input_queues_message_count());
+        assert!(queues.input_schedule.is_empty());
+        assert_eq!(0, queues.reserved_slots());
+        match queues.pop_canister_output(&other) {
+            Some((_, RequestOrResponse::Response(response))) => {
+                assert_eq!(other, response.originator);
+                assert_eq!(this, response.respondent);
+            }
+            msg => panic!("unexpected output message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    /// Deadlines survive an encode-decode roundtrip.
+    fn encode_roundtrip_with_deadlines() {
+        let this = canister_test_id(13);
+        let other = canister_test_id(14);
+        let deadline = Time::from_nanos_since_unix_epoch(1_000);
+        let mut queues = CanisterQueues::default();
+        queues
+            .push_output_request(
+                RequestBuilder::default()
+                    .sender(this)
+                    .receiver(other)
+                    .build(),
+            )
+            .unwrap();
+        queues
+            .push_output_request_with_deadline(
+                RequestBuilder::default()
+                    .sender(this)
+                    .receiver(other)
+                    .build(),
+                Some(deadline),
+            )
+            .unwrap();
+
+        let encoded: pb_queues::CanisterQueues = (&queues).into();
+        let mut decoded: CanisterQueues = encoded.try_into().unwrap();
+        assert_eq!(queues, decoded);
+
+        assert_eq!(1, decoded.time_out_requests(deadline));
+        assert_eq!(1, decoded.output_message_count());
+    }
 }
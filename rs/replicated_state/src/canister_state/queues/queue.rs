@@ -5,7 +5,7 @@ use ic_protobuf::state::{ingress::v1 as pb_ingress, queues::v1 as pb_queues};
 use ic_types::CountBytes;
 use ic_types::{
     messages::{Ingress, Request, RequestOrResponse, Response},
-    QueueIndex,
+    QueueIndex, Time,
 };
 use std::{
     collections::VecDeque,
@@ -27,6 +27,17 @@ fn pop_queue<T: std::clone::Clone>(queue: &mut VecDeque<Arc<T>>) -> Option<T> {
     })
 }
 
+/// Unwraps timed out messages, which can only be requests, since responses
+/// never have a deadline.
+fn into_requests(msgs: Vec<RequestOrResponse>) -> Vec<Request> {
+    msgs.into_iter()
+        .map(|msg| match msg {
+            RequestOrResponse::Request(req) => req,
+            RequestOrResponse::Response(_) => unreachable!("Responses have no deadline"),
+        })
+        .collect()
+}
+
 /// A FIFO queue that enforces an upper bound on the number of slots used and
 /// reserved. Pushing an item into the queue or reserving a slot may fail if the
 /// queue is full. Pushing an item into a reserved slot will always succeed
@@ -34,9 +45,14 @@ fn pop_queue<T: std::clone::Clone>(queue: &mut VecDeque<Arc<T>>) -> Option<T> {
 ///
 /// Stores items inside an `Arc` making it cheaper to copy the queue for
 /// creating snapshots.
+///
+/// Items may optionally be tagged with a deadline, after which they can be
+/// timed out (i.e. removed from anywhere in the queue).
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct QueueWithReservation<T: std::clone::Clone> {
     queue: VecDeque<Arc<T>>,
+    /// Deadlines of the items in `queue`, in the same order.
+    deadlines: VecDeque<Option<Time>>,
     /// Maximum number of messages allowed in the `queue` above.
     capacity: usize,
     /// Number of slots in the above `queue` currently reserved.  A slot must
@@ -53,6 +69,7 @@ impl<T: std::clone::Clone + CountBytes> QueueWithReservation<T> {
 
         Self {
             queue,
+            deadlines: VecDeque::new(),
             capacity,
             num_slots_reserved: 0,
             size_bytes,
@@ -77,14 +94,30 @@ impl<T: std::clone::Clone + CountBytes> QueueWithReservation<T> {
         Ok(())
     }
 
-    /// Pushes an item into the queue if not full, returns
-    /// `Err(StateError::QueueFull)` along with the provided item otherwise.
-    fn push(&mut self, msg: T) -> Result<(), (StateError, T)> {
+    /// Releases a previously reserved slot, e.g. when the operation that
+    /// required the reservation could not be completed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no reserved slot.
+    fn release_reserved_slot(&mut self) {
+        assert!(
+            self.num_slots_reserved > 0,
+            "Attempted to release a reservation from a queue without reserved slots"
+        );
+        self.num_slots_reserved -= 1;
+    }
+
+    /// Pushes an item with an optional deadline into the queue if not full,
+    /// returns `Err(StateError::QueueFull)` along with the provided item
+    /// otherwise.
+    fn push(&mut self, msg: T, deadline: Option<Time>) -> Result<(), (StateError, T)> {
         if let Err(e) = self.check_has_slot() {
             return Err((e, msg));
         }
         self.size_bytes += Self::message_size_bytes(&msg);
         self.queue.push_back(Arc::new(msg));
+        self.deadlines.push_back(deadline);
         debug_assert_eq!(Self::size_bytes(&self.queue), self.size_bytes);
         Ok(())
     }
@@ -96,6 +129,7 @@ impl<T: std::clone::Clone + CountBytes> QueueWithReservation<T> {
             self.num_slots_reserved -= 1;
             self.size_bytes += Self::message_size_bytes(&msg);
             self.queue.push_back(Arc::new(msg));
+            self.deadlines.push_back(None);
             debug_assert_eq!(Self::size_bytes(&self.queue), self.size_bytes);
             Ok(())
         } else {
@@ -107,6 +141,7 @@ impl<T: std::clone::Clone + CountBytes> QueueWithReservation<T> {
     fn pop(&mut self) -> Option<T> {
         let res = pop_queue(&mut self.queue);
         if let Some(msg) = res.as_ref() {
+            self.deadlines.pop_front();
             self.size_bytes -= Self::message_size_bytes(&msg);
             debug_assert_eq!(Self::size_bytes(&self.queue), self.size_bytes);
         }
@@ -119,11 +154,50 @@ impl<T: std::clone::Clone + CountBytes> QueueWithReservation<T> {
         self.queue.front().map(|msg| Arc::clone(msg))
     }
 
+    /// Returns the deadline of the item at the head of the queue, if any.
+    fn peek_deadline(&self) -> Option<Time> {
+        self.deadlines.front().copied().flatten()
+    }
+
+    /// Removes and returns all items with a deadline at or before
+    /// `current_time`, preserving the order of the remaining items.
+    fn time_out(&mut self, current_time: Time) -> Vec<T> {
+        let mut timed_out = Vec::new();
+        let mut i = 0;
+        while i < self.queue.len() {
+            match self.deadlines[i] {
+                Some(deadline) if deadline <= current_time => {
+                    self.deadlines.remove(i);
+                    let msg = match Arc::try_unwrap(self.queue.remove(i).unwrap()) {
+                        Ok(owned_value) => owned_value,
+                        Err(shared_ref) => (*shared_ref).clone(),
+                    };
+                    self.size_bytes -= Self::message_size_bytes(&msg);
+                    timed_out.push(msg);
+                }
+                _ => i += 1,
+            }
+        }
+        debug_assert_eq!(Self::size_bytes(&self.queue), self.size_bytes);
+        timed_out
+    }
+
     /// Number of actual messages in the queue.
     fn num_messages(&self) -> usize {
         self.queue.len()
     }
 
+    /// Number of slots currently reserved for responses.
+    fn reserved_slots(&self) -> usize {
+        self.num_slots_reserved
+    }
+
+    /// Number of slots that are neither taken by a message nor reserved.
+    fn available_slots(&self) -> usize {
+        self.capacity
+            .saturating_sub(self.queue.len() + self.num_slots_reserved)
+    }
+
     /// Calculates the size in bytes of a `QueueWithReservation` holding the
     /// given items.
     ///
@@ -148,9 +222,23 @@ impl CountBytes for QueueWithReservation<RequestOrResponse> {
     }
 }
 
-impl From<&QueueWithReservation<RequestOrResponse>> for Vec<pb_queues::RequestOrResponse> {
+impl From<&QueueWithReservation<RequestOrResponse>> for pb_queues::InputOutputQueue {
     fn from(item: &QueueWithReservation<RequestOrResponse>) -> Self {
-        item.queue.iter().map(|rr| rr.as_ref().into()).collect()
+        let deadlines = if item.deadlines.iter().any(Option::is_some) {
+            item.deadlines
+                .iter()
+                .map(|deadline| deadline.map_or(0, |d| d.as_nanos_since_unix_epoch()))
+                .collect()
+        } else {
+            vec![]
+        };
+        Self {
+            queue: item.queue.iter().map(|rr| rr.as_ref().into()).collect(),
+            ind: 0,
+            capacity: item.capacity as u64,
+            num_slots_reserved: item.num_slots_reserved as u64,
+            deadlines,
+        }
     }
 }
 
@@ -174,6 +262,25 @@ impl TryFrom<pb_queues::InputOutputQueue> for QueueWithReservation<RequestOrResp
             )));
         }
 
+        if !item.deadlines.is_empty() && item.deadlines.len() != item.queue.len() {
+            return Err(ProxyDecodeError::Other(format!(
+                "QueueWithReservation: {} deadlines for {} messages",
+                item.deadlines.len(),
+                item.queue.len(),
+            )));
+        }
+
+        let deadlines = if item.deadlines.is_empty() {
+            item.queue.iter().map(|_| None).collect()
+        } else {
+            item.deadlines
+                .iter()
+                .map(|&nanos| match nanos {
+                    0 => None,
+                    nanos => Some(Time::from_nanos_since_unix_epoch(nanos)),
+                })
+                .collect()
+        };
         let queue = item
             .queue
             .into_iter()
@@ -183,6 +290,7 @@ impl TryFrom<pb_queues::InputOutputQueue> for QueueWithReservation<RequestOrResp
 
         Ok(QueueWithReservation {
             queue,
+            deadlines,
             capacity: super::DEFAULT_QUEUE_CAPACITY,
             num_slots_reserved: item.num_slots_reserved as usize,
             size_bytes,
@@ -213,10 +321,14 @@ impl InputQueue {
         self.queue.check_has_slot()
     }
 
+    /// Pushes a message with the given `QueueIndex` into the queue. Requests
+    /// may have a deadline, after which they are timed out; responses are
+    /// pushed into a reserved slot and never have a deadline.
     pub(super) fn push(
         &mut self,
         msg_ind: QueueIndex,
         msg: RequestOrResponse,
+        deadline: Option<Time>,
     ) -> Result<(), (StateError, RequestOrResponse)> {
         if msg_ind == self.ind {
             self.ind.inc_assign();
@@ -228,15 +340,25 @@ impl InputQueue {
             );
         }
         match msg {
-            RequestOrResponse::Request(_) => self.queue.push(msg),
+            RequestOrResponse::Request(_) => self.queue.push(msg, deadline),
             RequestOrResponse::Response(_) => self.queue.push_into_reserved_slot(msg),
         }
     }
 
+    /// Removes and returns all requests with a deadline at or before
+    /// `current_time`.
+    pub(super) fn time_out_requests(&mut self, current_time: Time) -> Vec<Request> {
+        into_requests(self.queue.time_out(current_time))
+    }
+
     pub(super) fn reserve_slot(&mut self) -> Result<(), StateError> {
         self.queue.reserve_slot()
     }
 
+    pub(super) fn release_reserved_slot(&mut self) {
+        self.queue.release_reserved_slot()
+    }
+
     pub(super) fn pop(&mut self) -> Option<RequestOrResponse> {
         self.queue.pop()
     }
@@ -246,6 +368,16 @@ impl InputQueue {
         self.queue.num_messages()
     }
 
+    /// Returns the number of slots reserved for responses.
+    pub(super) fn reserved_slots(&self) -> usize {
+        self.queue.reserved_slots()
+    }
+
+    /// Returns the number of slots available for messages or reservations.
+    pub(super) fn available_slots(&self) -> usize {
+        self.queue.available_slots()
+    }
+
     /// Returns an estimate of the size of a message in bytes.
     pub(super) fn message_size_bytes(msg: &RequestOrResponse) -> usize {
         QueueWithReservation::message_size_bytes(msg)
@@ -262,10 +394,8 @@ impl CountBytes for InputQueue {
 impl From<&InputQueue> for pb_queues::InputOutputQueue {
     fn from(item: &InputQueue) -> Self {
         Self {
-            queue: (&item.queue).into(),
             ind: item.ind.get(),
-            capacity: item.queue.capacity as u64,
-            num_slots_reserved: item.queue.num_slots_reserved as u64,
+            ..(&item.queue).into()
         }
     }
 }
@@ -303,9 +433,14 @@ impl OutputQueue {
         self.queue.check_has_slot()
     }
 
-    pub(super) fn push_request(&mut self, msg: Request) -> Result<(), (StateError, Request)> {
+    /// Pushes a request with an optional deadline into the queue.
+    pub(super) fn push_request(
+        &mut self,
+        msg: Request,
+        deadline: Option<Time>,
+    ) -> Result<(), (StateError, Request)> {
         if let Err((err, RequestOrResponse::Request(msg))) =
-            self.queue.push(RequestOrResponse::Request(msg))
+            self.queue.push(RequestOrResponse::Request(msg), deadline)
         {
             return Err((err, msg));
         }
@@ -322,6 +457,10 @@ impl OutputQueue {
         self.queue.reserve_slot()
     }
 
+    pub(super) fn release_reserved_slot(&mut self) {
+        self.queue.release_reserved_slot()
+    }
+
     pub(crate) fn pop(&mut self) -> Option<(QueueIndex, RequestOrResponse)> {
         match self.queue.pop() {
             None => None,
@@ -345,10 +484,47 @@ impl OutputQueue {
         }
     }
 
+    /// Returns the deadline of the message that `pop` would return, if any.
+    pub(crate) fn peek_deadline(&self) -> Option<Time> {
+        self.queue.peek_deadline()
+    }
+
+    /// Removes and returns all requests with a deadline at or before
+    /// `current_time`. The queue index of the remaining messages is not
+    /// affected.
+    pub(super) fn time_out_requests(&mut self, current_time: Time) -> Vec<Request> {
+        into_requests(self.queue.time_out(current_time))
+    }
+
     /// Number of actual messages in the queue
     pub fn num_messages(&self) -> usize {
         self.queue.num_messages()
     }
+
+    /// Returns the number of slots reserved for responses.
+    pub(super) fn reserved_slots(&self) -> usize {
+        self.queue.reserved_slots()
+    }
+
+    /// Returns the number of slots available for messages or reservations.
+    pub(super) fn available_slots(&self) -> usize {
+        self.queue.available_slots()
+    }
+
+    /// Returns an estimate of the size of a request in bytes, once enqueued.
+    pub(super) fn request_size_bytes(msg: &Request) -> usize {
+        size_of::<Arc<RequestOrResponse>>()
+            + size_of::<RequestOrResponse>()
+            + msg.method_name.len()
+            + msg.method_payload.len()
+    }
+}
+
+impl CountBytes for OutputQueue {
+    fn count_bytes(&self) -> usize {
+        size_of::<Self>() - size_of::<QueueWithReservation<RequestOrResponse>>()
+            + self.queue.count_bytes()
+    }
 }
 
 impl std::iter::Iterator for OutputQueue {
@@ -362,10 +538,8 @@ impl std::iter::Iterator for OutputQueue {
 impl From<&OutputQueue> for pb_queues::InputOutputQueue {
     fn from(item: &OutputQueue) -> Self {
         Self {
-            queue: (&item.queue).into(),
             ind: item.ind.get(),
-            capacity: item.queue.capacity as u64,
-            num_slots_reserved: item.queue.num_slots_reserved as u64,
+            ..(&item.queue).into()
         }
     }
 }
@@ -505,6 +679,7 @@ mod tests {
             .push(
                 QueueIndex::from(0),
                 RequestBuilder::default().build().into(),
+                None,
             )
             .expect("could push");
         assert_ne!(input_queue.num_messages(), 0);
@@ -520,7 +695,8 @@ mod tests {
                 Ok(()),
                 input_queue.push(
                     QueueIndex::from(index as u64),
-                    RequestBuilder::default().build().into()
+                    RequestBuilder::default().build().into(),
+                    None
                 )
             );
         }
@@ -537,7 +713,7 @@ mod tests {
             msg_queue.push_back(req.clone());
             assert_eq!(
                 Ok(()),
-                input_queue.push(QueueIndex::from(index as u64), req)
+                input_queue.push(QueueIndex::from(index as u64), req, None)
             );
         }
         while !msg_queue.is_empty() {
@@ -558,12 +734,14 @@ mod tests {
             .push(
                 QueueIndex::from(0),
                 RequestBuilder::default().build().into(),
+                None,
             )
             .unwrap();
 
         input_queue.push(
             QueueIndex::from(0),
             RequestBuilder::default().build().into(),
+            None,
         );
     }
 
@@ -577,6 +755,7 @@ mod tests {
             .push(
                 QueueIndex::from(0),
                 RequestBuilder::default().build().into(),
+                None,
             )
             .unwrap();
 
@@ -584,6 +763,7 @@ mod tests {
             .push(
                 super::super::QUEUE_INDEX_NONE,
                 RequestBuilder::default().build().into(),
+                None,
             )
             .unwrap();
 
@@ -591,6 +771,7 @@ mod tests {
             .push(
                 QueueIndex::from(1),
                 RequestBuilder::default().build().into(),
+                None,
             )
             .unwrap();
 
@@ -611,6 +792,7 @@ mod tests {
                 .push(
                     QueueIndex::from(index as u64),
                     RequestBuilder::default().build().into(),
+                    None,
                 )
                 .unwrap();
         }
@@ -625,6 +807,7 @@ mod tests {
                 .push(
                     QueueIndex::from(capacity as u64 / 2),
                     RequestBuilder::default().build().into(),
+                    None,
                 )
                 .map_err(|(err, _)| err),
             Err(StateError::QueueFull { capacity })
@@ -635,6 +818,7 @@ mod tests {
                 .push(
                     super::super::QUEUE_INDEX_NONE,
                     RequestBuilder::default().build().into(),
+                    None,
                 )
                 .map_err(|(err, _)| err),
             Err(StateError::QueueFull { capacity })
@@ -653,10 +837,33 @@ mod tests {
             .push(
                 QueueIndex::from(0),
                 ResponseBuilder::default().build().into(),
+                None,
             )
             .unwrap_err();
     }
 
+    #[test]
+    fn input_queue_released_slot_can_be_reserved_again() {
+        let mut queue = InputQueue::new(1);
+        queue.reserve_slot().unwrap();
+        assert_eq!(queue.reserved_slots(), 1);
+        assert_eq!(
+            queue.check_has_slot(),
+            Err(StateError::QueueFull { capacity: 1 })
+        );
+
+        queue.release_reserved_slot();
+        assert_eq!(queue.reserved_slots(), 0);
+        queue.reserve_slot().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "without reserved slots")]
+    fn output_queue_release_without_reservation_panics() {
+        let mut queue = OutputQueue::new(1);
+        queue.release_reserved_slot();
+    }
+
     #[test]
     fn output_queue_constructor_test() {
         let capacity: usize = 14;
@@ -675,7 +882,7 @@ mod tests {
         let mut output_queue = OutputQueue::new(capacity);
         for _index in 0..capacity / 2 {
             output_queue
-                .push_request(RequestBuilder::default().build())
+                .push_request(RequestBuilder::default().build(), None)
                 .unwrap();
         }
         for _index in capacity / 2..capacity {
@@ -686,7 +893,7 @@ mod tests {
         // Now push an extraneous message in
         assert_eq!(
             output_queue
-                .push_request(RequestBuilder::default().build())
+                .push_request(RequestBuilder::default().build(), None)
                 .map_err(|(err, _)| err),
             Err(StateError::QueueFull { capacity })
         );
@@ -707,7 +914,7 @@ mod tests {
         for _ in 0..capacity {
             let req = RequestBuilder::default().build();
             msgs_list.push_back(RequestOrResponse::from(req.clone()));
-            output_queue.push_request(req).unwrap();
+            output_queue.push_request(req, None).unwrap();
         }
 
         for expected_index in 0..capacity {
@@ -1,4 +1,5 @@
 mod call_context_manager;
+mod task_queue;
 
 use crate::{CanisterQueues, NumWasmPages64, PageMap, StateError};
 pub use call_context_manager::{CallContext, CallContextAction, CallContextManager, CallOrigin};
@@ -11,14 +12,18 @@ use ic_protobuf::{
 use ic_types::{
     messages::{Ingress, Request, RequestOrResponse, Response, StopCanisterContext},
     nominal_cycles::NominalCycles,
-    CanisterId, Cycles, MemoryAllocation, NumBytes, PrincipalId, QueueIndex,
+    CanisterId, Cycles, MemoryAllocation, NumBytes, NumInstructions, PrincipalId, QueueIndex, Time,
 };
 use lazy_static::lazy_static;
 use maplit::btreeset;
 use serde::{Deserialize, Serialize};
 use std::convert::{TryFrom, TryInto};
 use std::str::FromStr;
-use std::{collections::BTreeSet, sync::Arc};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::Arc,
+};
+pub use task_queue::{OnLowWasmMemoryHookStatus, SystemTask, TaskQueue};
 
 lazy_static! {
     static ref DEFAULT_PRINCIPAL_MULTIPLE_CONTROLLERS: PrincipalId =
@@ -38,6 +43,111 @@ pub struct CanisterMetrics {
     pub executed: u64,
     pub interruped_during_execution: u64,
     pub consumed_cycles_since_replica_started: NominalCycles,
+    pub consumed_cycles_by_heartbeats_since_replica_started: NominalCycles,
+    pub recent_execution_metrics: RecentExecutionMetrics,
+}
+
+/// The maximum number of rounds for which `RecentExecutionMetrics` keeps the
+/// execution counters of a canister.
+pub const MAX_RECENT_EXECUTION_ROUNDS: usize = 100;
+
+/// Execution counters of a canister in a single round. The round is
+/// identified by the time of its batch, which is the same for all messages
+/// executed in the round.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutionRoundMetrics {
+    pub time: Time,
+    /// The number of update methods executed.
+    pub updates_executed: u64,
+    /// The number of instructions executed by update methods and callbacks.
+    pub instructions_executed: NumInstructions,
+    /// The number of executions that trapped.
+    pub traps: u64,
+    /// The number of executions after which the canister used more memory.
+    pub memory_grow_events: u64,
+}
+
+impl ExecutionRoundMetrics {
+    pub fn new(time: Time) -> Self {
+        Self {
+            time,
+            updates_executed: 0,
+            instructions_executed: NumInstructions::from(0),
+            traps: 0,
+            memory_grow_events: 0,
+        }
+    }
+}
+
+/// The execution counters of the last `MAX_RECENT_EXECUTION_ROUNDS` rounds in
+/// which the canister executed messages, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecentExecutionMetrics(VecDeque<ExecutionRoundMetrics>);
+
+impl RecentExecutionMetrics {
+    /// Returns the counters of the round with batch time `time`. If the
+    /// counters of a later round were already recorded, returns those so that
+    /// the ring stays ordered; if no counters were recorded for `time` yet,
+    /// starts a new round and evicts the oldest one if the ring is full.
+    pub fn round_mut(&mut self, time: Time) -> &mut ExecutionRoundMetrics {
+        let is_new_round = match self.0.back() {
+            Some(last) => last.time < time,
+            None => true,
+        };
+        if is_new_round {
+            if self.0.len() == MAX_RECENT_EXECUTION_ROUNDS {
+                self.0.pop_front();
+            }
+            self.0.push_back(ExecutionRoundMetrics::new(time));
+        }
+        self.0.back_mut().unwrap()
+    }
+
+    /// Returns the counters of the recorded rounds, oldest first.
+    pub fn rounds(&self) -> impl Iterator<Item = &ExecutionRoundMetrics> {
+        self.0.iter()
+    }
+}
+
+impl From<&ExecutionRoundMetrics> for pb::ExecutionRoundMetrics {
+    fn from(item: &ExecutionRoundMetrics) -> Self {
+        Self {
+            time_nanos: item.time.as_nanos_since_unix_epoch(),
+            updates_executed: item.updates_executed,
+            instructions_executed: item.instructions_executed.get(),
+            traps: item.traps,
+            memory_grow_events: item.memory_grow_events,
+        }
+    }
+}
+
+impl From<pb::ExecutionRoundMetrics> for ExecutionRoundMetrics {
+    fn from(value: pb::ExecutionRoundMetrics) -> Self {
+        Self {
+            time: Time::from_nanos_since_unix_epoch(value.time_nanos),
+            updates_executed: value.updates_executed,
+            instructions_executed: NumInstructions::from(value.instructions_executed),
+            traps: value.traps,
+            memory_grow_events: value.memory_grow_events,
+        }
+    }
+}
+
+impl From<&RecentExecutionMetrics> for Vec<pb::ExecutionRoundMetrics> {
+    fn from(item: &RecentExecutionMetrics) -> Self {
+        item.rounds().map(|round| round.into()).collect()
+    }
+}
+
+impl From<Vec<pb::ExecutionRoundMetrics>> for RecentExecutionMetrics {
+    fn from(value: Vec<pb::ExecutionRoundMetrics>) -> Self {
+        let mut rounds: VecDeque<ExecutionRoundMetrics> =
+            value.into_iter().map(|round| round.into()).collect();
+        while rounds.len() > MAX_RECENT_EXECUTION_ROUNDS {
+            rounds.pop_front();
+        }
+        Self(rounds)
+    }
 }
 
 /// State that is controlled and owned by the system (IC).
@@ -84,6 +194,58 @@ pub struct SystemState {
     ///     2. executing the operation and return `cycles_spent`
     ///     3. reimburse the canister with `cycles_reserved` - `cycles_spent`
     pub cycles_balance: Cycles,
+
+    /// System tasks, such as the `on_low_wasm_memory` hook, that are executed
+    /// before the messages of the canister.
+    pub task_queue: TaskQueue,
+
+    /// Whether the controllers allow the canister to uninstall itself with
+    /// the `canister_self_destruct` management method.
+    pub self_destruct_enabled: bool,
+
+    /// The memory chunks of a canister imported from another subnet that have
+    /// not been transferred yet. The canister cannot be started and no code
+    /// can be installed on it before all of them arrived.
+    pub pending_import_chunks: Vec<PendingImportChunk>,
+}
+
+/// A chunk of the memory of an imported canister, identified by the hash of
+/// its contents.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingImportChunk {
+    /// Whether the chunk belongs to the stable memory rather than to the Wasm
+    /// memory.
+    pub stable_memory: bool,
+    pub offset: u64,
+    /// The SHA-256 hash of the contents of the chunk.
+    pub hash: [u8; 32],
+}
+
+impl From<&PendingImportChunk> for pb::PendingImportChunk {
+    fn from(item: &PendingImportChunk) -> Self {
+        Self {
+            stable_memory: item.stable_memory,
+            offset: item.offset,
+            hash: item.hash.to_vec(),
+        }
+    }
+}
+
+impl TryFrom<pb::PendingImportChunk> for PendingImportChunk {
+    type Error = ProxyDecodeError;
+    fn try_from(value: pb::PendingImportChunk) -> Result<Self, Self::Error> {
+        let hash = <[u8; 32]>::try_from(value.hash.as_slice()).map_err(|_| {
+            ProxyDecodeError::ValueOutOfRange {
+                typ: "PendingImportChunk::hash",
+                err: format!("expected 32 bytes, got {}", value.hash.len()),
+            }
+        })?;
+        Ok(Self {
+            stable_memory: value.stable_memory,
+            offset: value.offset,
+            hash,
+        })
+    }
 }
 
 /// A wrapper around the different canister statuses.
@@ -235,6 +397,9 @@ impl SystemState {
             status,
             certified_data: Default::default(),
             canister_metrics: CanisterMetrics::default(),
+            task_queue: TaskQueue::default(),
+            self_destruct_enabled: false,
+            pending_import_chunks: Vec::new(),
         }
     }
 
@@ -329,6 +494,53 @@ impl SystemState {
         self.queues.push_output_request(msg)
     }
 
+    /// Returns how many more requests to `receiver` the canister can enqueue
+    /// before the queues to `receiver` are full. See
+    /// `CanisterQueues::available_output_request_slots` for details.
+    pub fn available_output_request_slots(&self, receiver: &CanisterId) -> usize {
+        self.queues.available_output_request_slots(receiver)
+    }
+
+    /// Pushes a best-effort `Request` with the given `deadline` into the
+    /// relevant output queue, same as `push_output_request()`. See
+    /// `CanisterQueues::push_output_request_with_deadline` for details.
+    pub fn push_output_request_with_deadline(
+        &mut self,
+        msg: Request,
+        deadline: Time,
+    ) -> Result<(), (StateError, Request)> {
+        assert_eq!(
+            msg.sender, self.canister_id,
+            "Expected `Request` to have been sent by canister id {}, but instead got {}",
+            self.canister_id, msg.sender
+        );
+        self.queues
+            .push_output_request_with_deadline(msg, Some(deadline))
+    }
+
+    /// Pushes a `Request` type message into the relevant output queue, same as
+    /// `push_output_request()`, provided that the request and the reservation
+    /// for its response fit into `message_memory_capacity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `OutOfMemory` error along with the provided message if the
+    /// message memory pool is exhausted; or a `QueueFull` error if either the
+    /// output queue or the matching input queue is full.
+    pub fn push_output_request_with_memory_limit(
+        &mut self,
+        msg: Request,
+        message_memory_capacity: NumBytes,
+    ) -> Result<(), (StateError, Request)> {
+        assert_eq!(
+            msg.sender, self.canister_id,
+            "Expected `Request` to have been sent by canister id {}, but instead got {}",
+            self.canister_id, msg.sender
+        );
+        self.queues
+            .push_output_request_with_memory_limit(msg, message_memory_capacity)
+    }
+
     /// Pushes a `Response` type message into the relevant output queue. The
     /// protocol should have already reserved a slot, so this cannot fail. The
     /// canister is also refunded the excess cycles that was reserved for
@@ -393,6 +605,17 @@ impl SystemState {
         &mut self,
         index: QueueIndex,
         msg: RequestOrResponse,
+    ) -> Result<(), (StateError, RequestOrResponse)> {
+        self.push_input_with_deadline(index, msg, None)
+    }
+
+    /// Pushes a `RequestOrResponse` with an optional deadline into the
+    /// induction pool, same as `push_input()`.
+    pub(crate) fn push_input_with_deadline(
+        &mut self,
+        index: QueueIndex,
+        msg: RequestOrResponse,
+        deadline: Option<Time>,
     ) -> Result<(), (StateError, RequestOrResponse)> {
         assert_eq!(
             msg.receiver(),
@@ -402,7 +625,9 @@ impl SystemState {
             msg.receiver()
         );
         match self.status {
-            CanisterStatus::Running { .. } => self.queues.push_input(index, msg),
+            CanisterStatus::Running { .. } => {
+                self.queues.push_input_with_deadline(index, msg, deadline)
+            }
             CanisterStatus::Stopping { .. } => {
                 // Responses are accepted and requests are rejected.
                 match msg {
@@ -0,0 +1,165 @@
+use ic_protobuf::{proxy::ProxyDecodeError, state::canister_state_bits::v1 as pb};
+use ic_types::methods::SystemMethod;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+/// A task that the system executes on a canister in addition to the messages
+/// in its input queues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemTask {
+    /// Runs the `canister_on_low_wasm_memory` method of the canister, giving it
+    /// a chance to free memory before allocations start failing.
+    OnLowWasmMemory,
+}
+
+impl SystemTask {
+    /// Returns the system method that is executed for the task.
+    pub fn method(&self) -> SystemMethod {
+        match self {
+            SystemTask::OnLowWasmMemory => SystemMethod::CanisterOnLowWasmMemory,
+        }
+    }
+}
+
+/// The status of the `on_low_wasm_memory` hook of a canister.
+///
+/// The hook is executed at most once each time the canister runs low on Wasm
+/// memory: the status moves from `ConditionNotSatisfied` to `Ready` when the
+/// condition is first observed, to `Executed` once the hook ran and back to
+/// `ConditionNotSatisfied` only after the canister freed enough memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnLowWasmMemoryHookStatus {
+    ConditionNotSatisfied,
+    Ready,
+    Executed,
+}
+
+impl Default for OnLowWasmMemoryHookStatus {
+    fn default() -> Self {
+        OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+    }
+}
+
+/// The queue of system tasks of a canister. The scheduler executes the tasks
+/// in order before the messages of the canister.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskQueue {
+    queue: VecDeque<SystemTask>,
+    on_low_wasm_memory_hook_status: OnLowWasmMemoryHookStatus,
+}
+
+impl TaskQueue {
+    /// Returns true if no tasks are pending.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns the number of pending tasks.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the next task without removing it from the queue.
+    pub fn front(&self) -> Option<&SystemTask> {
+        self.queue.front()
+    }
+
+    /// Removes the next task from the queue. The caller is expected to
+    /// execute the returned task.
+    pub fn pop_front(&mut self) -> Option<SystemTask> {
+        let task = self.queue.pop_front();
+        if task == Some(SystemTask::OnLowWasmMemory) {
+            self.on_low_wasm_memory_hook_status = OnLowWasmMemoryHookStatus::Executed;
+        }
+        task
+    }
+
+    pub fn on_low_wasm_memory_hook_status(&self) -> OnLowWasmMemoryHookStatus {
+        self.on_low_wasm_memory_hook_status
+    }
+
+    /// Updates the status of the `on_low_wasm_memory` hook given whether the
+    /// canister is currently low on Wasm memory. Enqueues the hook if the
+    /// condition became satisfied and drops a pending hook if it no longer
+    /// is.
+    pub fn set_on_low_wasm_memory_condition(&mut self, is_satisfied: bool) {
+        use OnLowWasmMemoryHookStatus::*;
+        match (self.on_low_wasm_memory_hook_status, is_satisfied) {
+            (ConditionNotSatisfied, true) => {
+                self.queue.push_back(SystemTask::OnLowWasmMemory);
+                self.on_low_wasm_memory_hook_status = Ready;
+            }
+            (Ready, false) => {
+                self.queue
+                    .retain(|task| *task != SystemTask::OnLowWasmMemory);
+                self.on_low_wasm_memory_hook_status = ConditionNotSatisfied;
+            }
+            (Executed, false) => {
+                self.on_low_wasm_memory_hook_status = ConditionNotSatisfied;
+            }
+            (ConditionNotSatisfied, false) | (Ready, true) | (Executed, true) => {}
+        }
+    }
+}
+
+impl From<&TaskQueue> for pb::TaskQueue {
+    fn from(item: &TaskQueue) -> Self {
+        Self {
+            queue: item
+                .queue
+                .iter()
+                .map(|task| match task {
+                    SystemTask::OnLowWasmMemory => pb::SystemTask::OnLowWasmMemory as i32,
+                })
+                .collect(),
+            on_low_wasm_memory_hook_status: match item.on_low_wasm_memory_hook_status {
+                OnLowWasmMemoryHookStatus::ConditionNotSatisfied => {
+                    pb::OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+                }
+                OnLowWasmMemoryHookStatus::Ready => pb::OnLowWasmMemoryHookStatus::Ready,
+                OnLowWasmMemoryHookStatus::Executed => pb::OnLowWasmMemoryHookStatus::Executed,
+            } as i32,
+        }
+    }
+}
+
+impl TryFrom<pb::TaskQueue> for TaskQueue {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb::TaskQueue) -> Result<Self, Self::Error> {
+        let mut queue = VecDeque::with_capacity(value.queue.len());
+        for task in value.queue {
+            queue.push_back(
+                match pb::SystemTask::from_i32(task).unwrap_or(pb::SystemTask::Unspecified) {
+                    pb::SystemTask::Unspecified => {
+                        return Err(ProxyDecodeError::ValueOutOfRange {
+                            typ: "TaskQueue::queue",
+                            err: task.to_string(),
+                        })
+                    }
+                    pb::SystemTask::OnLowWasmMemory => SystemTask::OnLowWasmMemory,
+                },
+            );
+        }
+        let status = value.on_low_wasm_memory_hook_status;
+        let on_low_wasm_memory_hook_status = match pb::OnLowWasmMemoryHookStatus::from_i32(status)
+            .unwrap_or(pb::OnLowWasmMemoryHookStatus::Unspecified)
+        {
+            pb::OnLowWasmMemoryHookStatus::Unspecified => {
+                return Err(ProxyDecodeError::ValueOutOfRange {
+                    typ: "TaskQueue::on_low_wasm_memory_hook_status",
+                    err: status.to_string(),
+                })
+            }
+            pb::OnLowWasmMemoryHookStatus::ConditionNotSatisfied => {
+                OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+            }
+            pb::OnLowWasmMemoryHookStatus::Ready => OnLowWasmMemoryHookStatus::Ready,
+            pb::OnLowWasmMemoryHookStatus::Executed => OnLowWasmMemoryHookStatus::Executed,
+        };
+        Ok(Self {
+            queue,
+            on_low_wasm_memory_hook_status,
+        })
+    }
+}
@@ -9,10 +9,11 @@ pub use canister_state::{
     num_bytes_from, num_bytes_try_from64,
     system_state::{
         CallContext, CallContextAction, CallContextManager, CallOrigin, CanisterMetrics,
-        CanisterStatus, SystemState,
+        CanisterStatus, ExecutionRoundMetrics, OnLowWasmMemoryHookStatus, PendingImportChunk,
+        RecentExecutionMetrics, SystemState, SystemTask, TaskQueue,
     },
-    CanisterQueues, CanisterState, EmbedderCache, ExecutionState, ExportedFunctions, Global,
-    NumWasmPages, NumWasmPages64, SchedulerState,
+    CanisterQueues, CanisterState, CompilationTrigger, EmbedderCache, ExecutionState,
+    ExportedFunctions, Global, NumWasmPages, NumWasmPages64, SchedulerState,
 };
 pub use metadata_state::{NetworkTopology, NodeTopology, Stream, SubnetTopology, SystemMetadata};
 pub use page_map::{PageDelta, PageIndex, PageMap};
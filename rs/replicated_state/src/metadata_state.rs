@@ -2,7 +2,7 @@ pub mod subnet_call_context_manager;
 
 use crate::metadata_state::subnet_call_context_manager::SubnetCallContextManager;
 
-use ic_registry_routing_table::RoutingTable;
+use ic_registry_routing_table::{CanisterIdRanges, RoutingTable};
 use ic_registry_subnet_features::SubnetFeatures;
 use ic_registry_subnet_type::SubnetType;
 use ic_types::{
@@ -15,6 +15,7 @@ use ic_types::{
     xnet::{StreamHeader, StreamIndex, StreamIndexedQueue, StreamSlice},
     CountBytes, CryptoHashOfPartialState, NodeId, NumBytes, PrincipalId, SubnetId,
 };
+use ic_wasm_types::InstructionCostOverrides;
 use std::{
     collections::{BTreeMap, BTreeSet},
     mem::size_of,
@@ -65,6 +66,13 @@ pub struct SystemMetadata {
 
     pub own_subnet_features: SubnetFeatures,
 
+    /// Overrides of the costs of classes of Wasm instructions, as configured
+    /// in the subnet record.
+    ///
+    /// This field is not persisted: Message Routing sets it from the registry
+    /// before executing every batch.
+    pub instruction_cost_overrides: InstructionCostOverrides,
+
     /// Asynchronously handled subnet messages.
     pub subnet_call_context_manager: SubnetCallContextManager,
 
@@ -104,6 +112,12 @@ pub struct SystemMetadata {
     /// always be <= this field + (the maximum delta capacity of the subnet /
     /// 2).
     pub heap_delta_estimate: NumBytes,
+
+    /// The ingress messages that exported the canisters imported into this
+    /// subnet, together with the time of the certificates of their replies.
+    /// Every export can only be imported once. Entries are dropped once their
+    /// certificates are too old to be accepted anyway.
+    pub imported_canister_exports: BTreeMap<MessageId, Time>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -123,6 +137,17 @@ impl Default for NetworkTopology {
     }
 }
 
+impl NetworkTopology {
+    /// Returns the public key of the NNS subnet, or an empty slice if the NNS
+    /// subnet is not part of the topology.
+    pub fn nns_subnet_public_key(&self) -> &[u8] {
+        self.subnets
+            .get(&self.nns_subnet_id)
+            .map(|subnet| subnet.public_key.as_slice())
+            .unwrap_or_default()
+    }
+}
+
 impl From<&NetworkTopology> for pb_metadata::NetworkTopology {
     fn from(item: &NetworkTopology) -> Self {
         Self {
@@ -179,6 +204,9 @@ pub struct SubnetTopology {
     pub public_key: Vec<u8>,
     pub nodes: BTreeMap<NodeId, NodeTopology>,
     pub subnet_type: SubnetType,
+    /// Canister ids within the subnet's ranges that are reserved for system
+    /// canisters and never allocated by `create_canister`.
+    pub reserved_canister_id_ranges: CanisterIdRanges,
 }
 
 impl From<&SubnetTopology> for pb_metadata::SubnetTopology {
@@ -194,6 +222,7 @@ impl From<&SubnetTopology> for pb_metadata::SubnetTopology {
                 })
                 .collect(),
             subnet_type: i32::from(item.subnet_type),
+            reserved_canister_id_ranges: Some(item.reserved_canister_id_ranges.clone().into()),
         }
     }
 }
@@ -219,6 +248,11 @@ impl TryFrom<pb_metadata::SubnetTopology> for SubnetTopology {
             // field before we actually use it. We pick the value of least
             // privilege just to be sure.
             subnet_type: SubnetType::try_from(item.subnet_type).unwrap_or(SubnetType::Application),
+            reserved_canister_id_ranges: item
+                .reserved_canister_id_ranges
+                .map(CanisterIdRanges::try_from)
+                .transpose()?
+                .unwrap_or_default(),
         })
     }
 }
@@ -275,6 +309,16 @@ impl From<&SystemMetadata> for pb_metadata::SystemMetadata {
             certification_version: item.certification_version,
             heap_delta_estimate: item.heap_delta_estimate.get(),
             own_subnet_features: Some(item.own_subnet_features.into()),
+            imported_canister_exports: item
+                .imported_canister_exports
+                .iter()
+                .map(
+                    |(message_id, certificate_time)| pb_metadata::ImportedCanisterExport {
+                        message_id: message_id.as_bytes().to_vec(),
+                        certificate_time_nanos: certificate_time.as_nanos_since_unix_epoch(),
+                    },
+                )
+                .collect(),
         }
     }
 }
@@ -292,6 +336,13 @@ impl TryFrom<pb_metadata::SystemMetadata> for SystemMetadata {
                 try_from_option_field(entry.subnet_stream, "SystemMetadata::streams::V")?,
             );
         }
+        let mut imported_canister_exports = BTreeMap::new();
+        for entry in item.imported_canister_exports {
+            imported_canister_exports.insert(
+                entry.message_id.as_slice().try_into()?,
+                Time::from_nanos_since_unix_epoch(entry.certificate_time_nanos),
+            );
+        }
         Ok(Self {
             own_subnet_id: subnet_id_try_from_protobuf(try_from_option_field(
                 item.own_subnet_id,
@@ -302,6 +353,8 @@ impl TryFrom<pb_metadata::SystemMetadata> for SystemMetadata {
             // properly set this value.
             own_subnet_type: SubnetType::default(),
             own_subnet_features: item.own_subnet_features.unwrap_or_default().into(),
+            // Not persisted, see the documentation of the field.
+            instruction_cost_overrides: InstructionCostOverrides::default(),
             generated_id_counter: item.generated_id_counter,
             prev_state_hash: item.prev_state_hash.map(|b| CryptoHash(b).into()),
             batch_time: Time::from_nanos_since_unix_epoch(item.batch_time_nanos),
@@ -322,6 +375,7 @@ impl TryFrom<pb_metadata::SystemMetadata> for SystemMetadata {
             },
 
             heap_delta_estimate: NumBytes::from(item.heap_delta_estimate),
+            imported_canister_exports,
         })
     }
 }
@@ -339,12 +393,14 @@ impl SystemMetadata {
             network_topology: Default::default(),
             subnet_call_context_manager: Default::default(),
             own_subnet_features: SubnetFeatures::default(),
+            instruction_cost_overrides: InstructionCostOverrides::default(),
             // StateManager populates proper values of these fields before
             // committing each state.
             prev_state_hash: Default::default(),
             state_sync_version: 0,
             certification_version: 0,
             heap_delta_estimate: NumBytes::from(0),
+            imported_canister_exports: BTreeMap::new(),
         }
     }
 
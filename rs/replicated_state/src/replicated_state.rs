@@ -45,6 +45,13 @@ pub enum StateError {
     /// Message enqueuing failed due to calling a subnet method with
     /// an invalid payload.
     InvalidSubnetPayload,
+
+    /// Message enqueuing failed because the message and the reservation for
+    /// its response would exceed the available message memory.
+    OutOfMemory {
+        requested: NumBytes,
+        available: NumBytes,
+    },
 }
 
 pub const LABEL_VALUE_CANISTER_NOT_FOUND: &str = "CanisterNotFound";
@@ -54,6 +61,7 @@ pub const LABEL_VALUE_CANISTER_STOPPING: &str = "CanisterStopping";
 pub const LABEL_VALUE_CANISTER_OUT_OF_CYCLES: &str = "CanisterOutOfCycles";
 pub const LABEL_VALUE_UNKNOWN_SUBNET_METHOD: &str = "UnknownSubnetMethod";
 pub const LABEL_VALUE_INVALID_SUBNET_PAYLOAD: &str = "InvalidSubnetPayload";
+pub const LABEL_VALUE_OUT_OF_MEMORY: &str = "OutOfMemory";
 
 impl StateError {
     /// Returns a string representation of the `StateError` variant name to be
@@ -67,6 +75,7 @@ impl StateError {
             StateError::CanisterOutOfCycles { .. } => LABEL_VALUE_CANISTER_OUT_OF_CYCLES,
             StateError::UnknownSubnetMethod(_) => LABEL_VALUE_UNKNOWN_SUBNET_METHOD,
             StateError::InvalidSubnetPayload => LABEL_VALUE_INVALID_SUBNET_PAYLOAD,
+            StateError::OutOfMemory { .. } => LABEL_VALUE_OUT_OF_MEMORY,
         }
     }
 }
@@ -106,6 +115,14 @@ impl std::fmt::Display for StateError {
                 f,
                 "Cannot enqueue management message. Candid payload is invalid."
             ),
+            StateError::OutOfMemory {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Cannot enqueue message. Out of memory: requested {}, available {}",
+                requested, available
+            ),
         }
     }
 }
@@ -393,7 +410,12 @@ impl ReplicatedState {
                 };
 
                 while let Some((_, msg)) = source_output_queue.peek() {
-                    match dest_canister.push_input(QUEUE_INDEX_NONE, msg) {
+                    let deadline = source_output_queue.peek_deadline();
+                    match dest_canister.system_state.push_input_with_deadline(
+                        QUEUE_INDEX_NONE,
+                        msg,
+                        deadline,
+                    ) {
                         Err(_) => break,
                         Ok(()) => match source_output_queue.pop() {
                             Some(_) => (),
@@ -409,4 +431,27 @@ impl ReplicatedState {
         }
         self.put_canister_states(canisters);
     }
+
+    /// Times out the best-effort requests in the queues of all canisters
+    /// whose deadline has passed as of the current batch time, see
+    /// `CanisterQueues::time_out_requests`.
+    ///
+    /// Returns the number of timed out requests of every canister that had
+    /// any.
+    pub fn time_out_requests(&mut self) -> BTreeMap<CanisterId, usize> {
+        let current_time = self.time();
+        self.canister_states
+            .iter_mut()
+            .filter_map(|(canister_id, canister)| {
+                match canister
+                    .system_state
+                    .queues_mut()
+                    .time_out_requests(current_time)
+                {
+                    0 => None,
+                    timed_out => Some((*canister_id, timed_out)),
+                }
+            })
+            .collect()
+    }
 }
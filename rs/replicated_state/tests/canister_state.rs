@@ -1,13 +1,13 @@
 #[cfg(test)]
 mod canister_state {
-    use ic_replicated_state::StateError;
+    use ic_replicated_state::{num_bytes_from, NumWasmPages, StateError};
     use ic_test_utilities::state::{
-        get_running_canister, get_stopped_canister, get_stopping_canister,
+        get_running_canister, get_stopped_canister, get_stopping_canister, CanisterStateBuilder,
     };
     use ic_test_utilities::types::ids::canister_test_id;
     use ic_test_utilities::types::messages::{RequestBuilder, ResponseBuilder};
     use ic_types::messages::RequestOrResponse;
-    use ic_types::QueueIndex;
+    use ic_types::{NumBytes, QueueIndex, MAX_WASM_MEMORY_IN_BYTES};
 
     #[test]
     fn running_canister_accepts_requests() {
@@ -120,4 +120,28 @@ mod canister_state {
             Err((StateError::CanisterStopped(canister_test_id(0)), response))
         );
     }
+
+    #[test]
+    fn canister_close_to_its_memory_limit_is_low_on_wasm_memory() {
+        let canister = CanisterStateBuilder::new()
+            .with_wasm(vec![0; 100])
+            .with_memory_allocation(1_000)
+            .build();
+        let default_limit = NumBytes::new(1 << 40);
+
+        assert!(!canister.is_low_on_wasm_memory(default_limit, NumBytes::new(500)));
+        assert!(canister.is_low_on_wasm_memory(default_limit, NumBytes::new(1_000)));
+    }
+
+    #[test]
+    fn canister_close_to_the_maximum_wasm_heap_is_low_on_wasm_memory() {
+        let mut canister = CanisterStateBuilder::new().with_wasm(vec![]).build();
+        let page_size = num_bytes_from(NumWasmPages::new(1));
+        let max_pages = (MAX_WASM_MEMORY_IN_BYTES / page_size.get()) as usize;
+        canister.execution_state.as_mut().unwrap().heap_size = NumWasmPages::new(max_pages - 1);
+        let default_limit = NumBytes::new(1 << 40);
+
+        assert!(!canister.is_low_on_wasm_memory(default_limit, page_size));
+        assert!(canister.is_low_on_wasm_memory(default_limit, page_size + NumBytes::new(1)));
+    }
 }
@@ -0,0 +1,260 @@
+//! Round-trip tests between the canister state types and their protobuf
+//! encodings.
+//!
+//! Besides checking that `decode(encode(x)) == x`, the tests check that the
+//! decoding tolerates fields it does not know about (as written by a newer
+//! replica version) and fields that are missing (as written by an older
+//! replica version), so that replica upgrades and downgrades cannot silently
+//! drop canister state.
+
+use ic_protobuf::state::canister_state_bits::v1 as pb;
+use ic_protobuf::state::queues::v1 as pb_queues;
+use ic_replicated_state::canister_state::QUEUE_INDEX_NONE;
+use ic_replicated_state::{CallContextManager, CallOrigin, CanisterQueues, CanisterStatus};
+use ic_types::{
+    messages::{CallContextId, CallbackId, MessageId, Request, RequestOrResponse},
+    methods::{Callback, WasmClosure},
+    time::Time,
+    Cycles,
+};
+use ic_types_test_utils::arbitrary;
+use proptest::prelude::*;
+use prost::Message;
+use std::convert::TryFrom;
+
+/// Encodes `msg` and appends two fields with tags unknown to any replica
+/// version: a varint and a length-delimited one.
+fn encode_with_unknown_fields<M: Message>(msg: &M) -> Vec<u8> {
+    let mut buf = Vec::new();
+    msg.encode(&mut buf).unwrap();
+    // Field 1000, wire type 0 (varint), value 42.
+    buf.extend_from_slice(&[0xc0, 0x3e, 42]);
+    // Field 1001, wire type 2 (length-delimited), 3 bytes.
+    buf.extend_from_slice(&[0xca, 0x3e, 3, 1, 2, 3]);
+    buf
+}
+
+prop_compose! {
+    fn arb_message_id()(bytes in any::<[u8; 32]>()) -> MessageId {
+        MessageId::from(bytes)
+    }
+}
+
+fn arb_call_origin() -> impl Strategy<Value = CallOrigin> {
+    prop_oneof![
+        (arbitrary::user_id(), arb_message_id())
+            .prop_map(|(user_id, message_id)| CallOrigin::Ingress(user_id, message_id)),
+        (arbitrary::canister_id(), any::<u64>()).prop_map(|(canister_id, callback)| {
+            CallOrigin::CanisterUpdate(canister_id, CallbackId::from(callback))
+        }),
+        arbitrary::user_id().prop_map(CallOrigin::Query),
+        (arbitrary::canister_id(), any::<u64>()).prop_map(|(canister_id, callback)| {
+            CallOrigin::CanisterQuery(canister_id, CallbackId::from(callback))
+        }),
+        Just(CallOrigin::Heartbeat),
+    ]
+}
+
+prop_compose! {
+    /// Returns a non-zero deadline, zero being the encoding of "no deadline".
+    fn arb_deadline()(nanos in 1..u64::MAX) -> Time {
+        Time::from_nanos_since_unix_epoch(nanos)
+    }
+}
+
+prop_compose! {
+    fn arb_wasm_closure()(func_idx in any::<u32>(), env in any::<u32>()) -> WasmClosure {
+        WasmClosure::new(func_idx, env)
+    }
+}
+
+prop_compose! {
+    /// Returns a `CallContextManager` with up to `max_contexts` call contexts
+    /// and up to `max_callbacks` callbacks referencing them.
+    fn arb_call_context_manager(max_contexts: usize, max_callbacks: usize)(
+        contexts in prop::collection::vec(
+            (arb_call_origin(), any::<u64>(), any::<bool>(), any::<bool>()),
+            1..=max_contexts,
+        ),
+        callbacks in prop::collection::vec(
+            (
+                any::<usize>(),
+                any::<u64>(),
+                arb_wasm_closure(),
+                arb_wasm_closure(),
+                prop::option::of(arb_wasm_closure()),
+            ),
+            0..=max_callbacks,
+        ),
+    ) -> CallContextManager {
+        let mut ccm = CallContextManager::default();
+        let mut ids = Vec::new();
+        for (origin, cycles, responded, deleted) in contexts {
+            let id = ccm.new_call_context(origin, Cycles::from(cycles));
+            let context = ccm.call_contexts_mut().get_mut(&id).unwrap();
+            if responded {
+                context.mark_responded();
+            }
+            if deleted {
+                context.mark_deleted();
+            }
+            ids.push(id);
+        }
+        for (index, cycles, on_reply, on_reject, on_cleanup) in callbacks {
+            let call_context_id: CallContextId = ids[index % ids.len()];
+            ccm.register_callback(Callback::new(
+                call_context_id,
+                Cycles::from(cycles),
+                on_reply,
+                on_reject,
+                on_cleanup,
+            ));
+        }
+        ccm
+    }
+}
+
+fn arb_canister_status() -> impl Strategy<Value = CanisterStatus> {
+    prop_oneof![
+        arb_call_context_manager(5, 5).prop_map(|call_context_manager| CanisterStatus::Running {
+            call_context_manager
+        }),
+        arb_call_context_manager(5, 5).prop_map(|call_context_manager| {
+            CanisterStatus::Stopping {
+                call_context_manager,
+                stop_contexts: vec![],
+            }
+        }),
+        Just(CanisterStatus::Stopped),
+    ]
+}
+
+prop_compose! {
+    /// Returns `CanisterQueues` holding the given input and output requests,
+    /// each with an optional deadline.
+    fn arb_canister_queues(max_requests: usize)(
+        input_requests in prop::collection::vec(
+            (arbitrary::request(), prop::option::of(arb_deadline())),
+            0..=max_requests,
+        ),
+        output_requests in prop::collection::vec(
+            (arbitrary::request(), prop::option::of(arb_deadline())),
+            0..=max_requests,
+        ),
+    ) -> CanisterQueues {
+        build_queues(input_requests, output_requests)
+    }
+}
+
+fn build_queues(
+    input_requests: Vec<(Request, Option<Time>)>,
+    output_requests: Vec<(Request, Option<Time>)>,
+) -> CanisterQueues {
+    let mut queues = CanisterQueues::default();
+    for (request, deadline) in input_requests {
+        // The requests come from arbitrary senders, so there is no stream
+        // index to check them against.
+        queues
+            .push_input_with_deadline(
+                QUEUE_INDEX_NONE,
+                RequestOrResponse::Request(request),
+                deadline,
+            )
+            .unwrap();
+    }
+    for (request, deadline) in output_requests {
+        queues
+            .push_output_request_with_deadline(request, deadline)
+            .unwrap();
+    }
+    queues
+}
+
+/// Removes all deadlines from the encoded queues, as if they were written by
+/// a replica version that did not support deadlines.
+fn strip_deadlines(queues: &mut pb_queues::CanisterQueues) {
+    for entry in queues
+        .input_queues
+        .iter_mut()
+        .chain(queues.output_queues.iter_mut())
+    {
+        if let Some(queue) = entry.queue.as_mut() {
+            queue.deadlines.clear();
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn call_context_manager_roundtrip(ccm in arb_call_context_manager(10, 10)) {
+        let encoded = pb::CallContextManager::from(&ccm);
+        let decoded = CallContextManager::try_from(encoded.clone()).unwrap();
+        prop_assert_eq!(&ccm, &decoded);
+        // Encoding is deterministic, so re-encoding yields the same message.
+        prop_assert_eq!(encoded, pb::CallContextManager::from(&decoded));
+    }
+
+    #[test]
+    fn call_context_manager_tolerates_unknown_fields(ccm in arb_call_context_manager(10, 10)) {
+        let bytes = encode_with_unknown_fields(&pb::CallContextManager::from(&ccm));
+        let encoded = pb::CallContextManager::decode(bytes.as_slice()).unwrap();
+        prop_assert_eq!(ccm, CallContextManager::try_from(encoded).unwrap());
+    }
+
+    #[test]
+    fn canister_status_roundtrip(status in arb_canister_status()) {
+        let encoded = pb::canister_state_bits::CanisterStatus::from(&status);
+        prop_assert_eq!(status, CanisterStatus::try_from(encoded).unwrap());
+    }
+
+    #[test]
+    fn canister_queues_roundtrip(queues in arb_canister_queues(10)) {
+        let encoded = pb_queues::CanisterQueues::from(&queues);
+        let decoded = CanisterQueues::try_from(encoded.clone()).unwrap();
+        prop_assert_eq!(&queues, &decoded);
+        prop_assert_eq!(encoded, pb_queues::CanisterQueues::from(&decoded));
+    }
+
+    #[test]
+    fn canister_queues_tolerate_unknown_fields(queues in arb_canister_queues(10)) {
+        let bytes = encode_with_unknown_fields(&pb_queues::CanisterQueues::from(&queues));
+        let encoded = pb_queues::CanisterQueues::decode(bytes.as_slice()).unwrap();
+        prop_assert_eq!(queues, CanisterQueues::try_from(encoded).unwrap());
+    }
+
+    #[test]
+    fn canister_queues_without_deadlines_decode_as_before(
+        input_requests in prop::collection::vec(
+            (arbitrary::request(), prop::option::of(arb_deadline())),
+            0..10,
+        ),
+        output_requests in prop::collection::vec(
+            (arbitrary::request(), prop::option::of(arb_deadline())),
+            0..10,
+        ),
+    ) {
+        let without_deadlines = |requests: &[(Request, Option<Time>)]| {
+            requests
+                .iter()
+                .map(|(request, _)| (request.clone(), None))
+                .collect::<Vec<_>>()
+        };
+        let expected = build_queues(
+            without_deadlines(&input_requests),
+            without_deadlines(&output_requests),
+        );
+
+        // Queues without any deadlines encode no deadlines at all, i.e. exactly
+        // as a replica version that does not know about deadlines would.
+        let mut encoded = pb_queues::CanisterQueues::from(&expected);
+        for entry in encoded.input_queues.iter().chain(encoded.output_queues.iter()) {
+            prop_assert!(entry.queue.as_ref().unwrap().deadlines.is_empty());
+        }
+
+        // And queues written by such a replica version decode to the same
+        // messages, none of which has a deadline.
+        encoded = pb_queues::CanisterQueues::from(&build_queues(input_requests, output_requests));
+        strip_deadlines(&mut encoded);
+        prop_assert_eq!(expected, CanisterQueues::try_from(encoded).unwrap());
+    }
+}
@@ -1,10 +1,18 @@
 use ic_base_types::{NumBytes, NumSeconds};
-use ic_replicated_state::SystemState;
+use ic_protobuf::state::canister_state_bits::v1 as pb;
+use ic_replicated_state::{
+    canister_state::system_state::MAX_RECENT_EXECUTION_ROUNDS, OnLowWasmMemoryHookStatus,
+    RecentExecutionMetrics, SystemState, SystemTask, TaskQueue,
+};
 use ic_test_utilities::types::{
     ids::{canister_test_id, user_test_id},
     messages::{RequestBuilder, ResponseBuilder},
 };
-use ic_types::{freeze_threshold_cycles, messages::RequestOrResponse, Cycles, QueueIndex};
+use ic_types::{
+    freeze_threshold_cycles, messages::RequestOrResponse, time::Time, Cycles, NumInstructions,
+    QueueIndex,
+};
+use std::convert::TryFrom;
 
 #[test]
 fn correct_charging_target_canister_for_a_response() {
@@ -46,3 +54,96 @@ fn correct_charging_target_canister_for_a_response() {
     // the response
     assert_eq!(initial_cycles_balance, system_state.cycles_balance);
 }
+
+#[test]
+fn recent_execution_metrics_aggregate_rounds_and_evict_oldest() {
+    let mut metrics = RecentExecutionMetrics::default();
+    let round_time = |i: u64| Time::from_nanos_since_unix_epoch(1_000 + i);
+
+    for i in 0..(MAX_RECENT_EXECUTION_ROUNDS as u64 + 10) {
+        // Two executions in every round.
+        for _ in 0..2 {
+            let round = metrics.round_mut(round_time(i));
+            round.updates_executed += 1;
+            round.instructions_executed += NumInstructions::from(5);
+        }
+    }
+
+    let rounds: Vec<_> = metrics.rounds().collect();
+    assert_eq!(rounds.len(), MAX_RECENT_EXECUTION_ROUNDS);
+    assert_eq!(rounds.first().unwrap().time, round_time(10));
+    assert_eq!(
+        rounds.last().unwrap().time,
+        round_time(MAX_RECENT_EXECUTION_ROUNDS as u64 + 9)
+    );
+    for round in rounds {
+        assert_eq!(round.updates_executed, 2);
+        assert_eq!(round.instructions_executed, NumInstructions::from(10));
+    }
+}
+
+#[test]
+fn recent_execution_metrics_roundtrip_through_protobuf() {
+    let mut metrics = RecentExecutionMetrics::default();
+    for i in 0..3 {
+        let round = metrics.round_mut(Time::from_nanos_since_unix_epoch(i));
+        round.updates_executed = i;
+        round.instructions_executed = NumInstructions::from(100 * i);
+        round.traps = 1;
+        round.memory_grow_events = 2;
+    }
+
+    let pb_metrics: Vec<pb::ExecutionRoundMetrics> = (&metrics).into();
+    assert_eq!(RecentExecutionMetrics::from(pb_metrics), metrics);
+}
+
+#[test]
+fn on_low_wasm_memory_hook_is_enqueued_once_per_low_memory_episode() {
+    let mut task_queue = TaskQueue::default();
+    task_queue.set_on_low_wasm_memory_condition(false);
+    assert!(task_queue.is_empty());
+
+    task_queue.set_on_low_wasm_memory_condition(true);
+    task_queue.set_on_low_wasm_memory_condition(true);
+    assert_eq!(task_queue.len(), 1);
+    assert_eq!(
+        task_queue.on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::Ready
+    );
+
+    assert_eq!(task_queue.pop_front(), Some(SystemTask::OnLowWasmMemory));
+    assert_eq!(
+        task_queue.on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::Executed
+    );
+
+    // The hook is not enqueued again while the canister stays low on memory.
+    task_queue.set_on_low_wasm_memory_condition(true);
+    assert!(task_queue.is_empty());
+
+    // Once the canister freed memory, the hook is armed again.
+    task_queue.set_on_low_wasm_memory_condition(false);
+    task_queue.set_on_low_wasm_memory_condition(true);
+    assert_eq!(task_queue.front(), Some(&SystemTask::OnLowWasmMemory));
+}
+
+#[test]
+fn pending_on_low_wasm_memory_hook_is_dropped_when_memory_is_freed() {
+    let mut task_queue = TaskQueue::default();
+    task_queue.set_on_low_wasm_memory_condition(true);
+    task_queue.set_on_low_wasm_memory_condition(false);
+    assert!(task_queue.is_empty());
+    assert_eq!(
+        task_queue.on_low_wasm_memory_hook_status(),
+        OnLowWasmMemoryHookStatus::ConditionNotSatisfied
+    );
+}
+
+#[test]
+fn task_queue_roundtrip_through_protobuf() {
+    let mut task_queue = TaskQueue::default();
+    task_queue.set_on_low_wasm_memory_condition(true);
+
+    let pb_task_queue = pb::TaskQueue::from(&task_queue);
+    assert_eq!(TaskQueue::try_from(pb_task_queue).unwrap(), task_queue);
+}
@@ -477,6 +477,7 @@ async fn create_canister(controller_id: PrincipalId, cycles: Cycles) -> Result<C
                     controller: Some(controller_id),
                     ..CanisterSettingsArgs::default()
                 }),
+                template_canister_id: None,
             },
             dfn_core::api::Funds::new(cycles.get().try_into().unwrap()),
         )
@@ -20,6 +20,7 @@ pub async fn install_code<Arg: IntoWire>(
         compute_allocation: None,
         memory_allocation: Some(candid::Nat::from(8 * 1024 * 1024 * 1024u64)),
         query_allocation: None,
+        skip_stable_compat_check: None,
     };
     dfn_core::api::call_with_cleanup(
         IC_00,
@@ -12,10 +12,11 @@ use ic_protobuf::{
 };
 use ic_replicated_state::{
     CallContextManager, CanisterStatus, ExportedFunctions, Global, NumWasmPages, NumWasmPages64,
+    PendingImportChunk, RecentExecutionMetrics, TaskQueue,
 };
 use ic_types::{
     nominal_cycles::NominalCycles, AccumulatedPriority, CanisterId, ComputeAllocation, Cycles,
-    ExecutionRound, Height, MemoryAllocation, PrincipalId,
+    ExecutionRound, Height, MemoryAllocation, NumInstructions, PrincipalId, PriorityClass,
 };
 use ic_wasm_types::BinaryEncodedWasm;
 use std::convert::{From, TryFrom, TryInto};
@@ -159,6 +160,8 @@ pub struct CanisterStateBits {
     pub call_context_manager: Option<CallContextManager>,
     pub compute_allocation: ComputeAllocation,
     pub accumulated_priority: AccumulatedPriority,
+    pub priority_class: PriorityClass,
+    pub heartbeat_instruction_limit: Option<NumInstructions>,
     pub execution_state_bits: Option<ExecutionStateBits>,
     pub memory_allocation: MemoryAllocation,
     pub freeze_threshold: NumSeconds,
@@ -170,7 +173,12 @@ pub struct CanisterStateBits {
     pub interruped_during_execution: u64,
     pub certified_data: Vec<u8>,
     pub consumed_cycles_since_replica_started: NominalCycles,
+    pub consumed_cycles_by_heartbeats_since_replica_started: NominalCycles,
     pub stable_memory_size: NumWasmPages64,
+    pub recent_execution_metrics: RecentExecutionMetrics,
+    pub task_queue: TaskQueue,
+    pub self_destruct_enabled: bool,
+    pub pending_import_chunks: Vec<PendingImportChunk>,
 }
 
 /// `StateLayout` provides convenience functions to construct correct
@@ -954,6 +962,11 @@ impl From<CanisterStateBits> for pb_canister_state_bits::CanisterStateBits {
             call_context_manager: item.call_context_manager.as_ref().map(|v| v.into()),
             compute_allocation: item.compute_allocation.as_percent(),
             accumulated_priority: item.accumulated_priority.value(),
+            priority_class: match item.priority_class {
+                PriorityClass::Normal => pb_canister_state_bits::PriorityClass::Normal,
+                PriorityClass::Elevated => pb_canister_state_bits::PriorityClass::Elevated,
+            } as i32,
+            heartbeat_instruction_limit: item.heartbeat_instruction_limit.map(|limit| limit.get()),
             execution_state_bits: item.execution_state_bits.as_ref().map(|v| v.into()),
             memory_allocation: item.memory_allocation.bytes().get(),
             freeze_threshold: item.freeze_threshold.get(),
@@ -976,6 +989,17 @@ impl From<CanisterStateBits> for pb_canister_state_bits::CanisterStateBits {
                 Err(_) => u32::MAX,
             },
             stable_memory_size64: item.stable_memory_size.get(),
+            consumed_cycles_by_heartbeats_since_replica_started: Some(
+                (&item.consumed_cycles_by_heartbeats_since_replica_started).into(),
+            ),
+            recent_execution_metrics: (&item.recent_execution_metrics).into(),
+            task_queue: Some((&item.task_queue).into()),
+            self_destruct_enabled: item.self_destruct_enabled,
+            pending_import_chunks: item
+                .pending_import_chunks
+                .iter()
+                .map(|chunk| chunk.into())
+                .collect(),
         }
     }
 }
@@ -1001,6 +1025,14 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
             Err(_) => NominalCycles::default(),
         };
 
+        let consumed_cycles_by_heartbeats_since_replica_started = match try_from_option_field(
+            value.consumed_cycles_by_heartbeats_since_replica_started,
+            "CanisterStateBits::consumed_cycles_by_heartbeats_since_replica_started",
+        ) {
+            Ok(consumed_cycles) => consumed_cycles,
+            Err(_) => NominalCycles::default(),
+        };
+
         let mut controllers = BTreeSet::new();
         for controller in value.controllers.into_iter() {
             controllers.insert(PrincipalId::try_from(controller)?);
@@ -1035,6 +1067,19 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
             value.stable_memory_size as u64
         };
 
+        let priority_class =
+            match pb_canister_state_bits::PriorityClass::from_i32(value.priority_class) {
+                Some(pb_canister_state_bits::PriorityClass::Unspecified)
+                | Some(pb_canister_state_bits::PriorityClass::Normal) => PriorityClass::Normal,
+                Some(pb_canister_state_bits::PriorityClass::Elevated) => PriorityClass::Elevated,
+                None => {
+                    return Err(ProxyDecodeError::ValueOutOfRange {
+                        typ: "CanisterStateBits::priority_class",
+                        err: value.priority_class.to_string(),
+                    })
+                }
+            };
+
         Ok(Self {
             controllers,
             last_full_execution_round: value.last_full_execution_round.into(),
@@ -1046,6 +1091,10 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
                 },
             )?,
             accumulated_priority: value.accumulated_priority.into(),
+            priority_class,
+            heartbeat_instruction_limit: value
+                .heartbeat_instruction_limit
+                .map(NumInstructions::from),
             execution_state_bits,
             memory_allocation: MemoryAllocation::try_from(NumBytes::from(value.memory_allocation))
                 .map_err(|e| ProxyDecodeError::ValueOutOfRange {
@@ -1064,7 +1113,20 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
             interruped_during_execution: value.interruped_during_execution,
             certified_data: value.certified_data,
             consumed_cycles_since_replica_started,
+            consumed_cycles_by_heartbeats_since_replica_started,
             stable_memory_size: NumWasmPages64::from(stable_memory_size),
+            recent_execution_metrics: value.recent_execution_metrics.into(),
+            task_queue: value
+                .task_queue
+                .map(TaskQueue::try_from)
+                .transpose()?
+                .unwrap_or_default(),
+            self_destruct_enabled: value.self_destruct_enabled,
+            pending_import_chunks: value
+                .pending_import_chunks
+                .into_iter()
+                .map(PendingImportChunk::try_from)
+                .collect::<Result<_, _>>()?,
         })
     }
 }
@@ -1122,6 +1184,8 @@ mod test {
             call_context_manager: None,
             compute_allocation: ComputeAllocation::try_from(0).unwrap(),
             accumulated_priority: AccumulatedPriority::from(0),
+            priority_class: PriorityClass::Normal,
+            heartbeat_instruction_limit: None,
             execution_state_bits: None,
             memory_allocation: MemoryAllocation::default(),
             freeze_threshold: NumSeconds::from(0),
@@ -1133,7 +1197,12 @@ mod test {
             interruped_during_execution: 0,
             certified_data: vec![],
             consumed_cycles_since_replica_started: NominalCycles::from(0),
+            consumed_cycles_by_heartbeats_since_replica_started: NominalCycles::from(0),
             stable_memory_size: NumWasmPages64::from(0),
+            recent_execution_metrics: RecentExecutionMetrics::default(),
+            task_queue: TaskQueue::default(),
+            self_destruct_enabled: false,
+            pending_import_chunks: vec![],
         };
 
         let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
@@ -1160,6 +1229,8 @@ mod test {
             call_context_manager: None,
             compute_allocation: ComputeAllocation::try_from(0).unwrap(),
             accumulated_priority: AccumulatedPriority::from(0),
+            priority_class: PriorityClass::Normal,
+            heartbeat_instruction_limit: None,
             execution_state_bits: None,
             memory_allocation: MemoryAllocation::default(),
             freeze_threshold: NumSeconds::from(0),
@@ -1171,7 +1242,12 @@ mod test {
             interruped_during_execution: 0,
             certified_data: vec![],
             consumed_cycles_since_replica_started: NominalCycles::from(0),
+            consumed_cycles_by_heartbeats_since_replica_started: NominalCycles::from(0),
             stable_memory_size: NumWasmPages64::from(0),
+            recent_execution_metrics: RecentExecutionMetrics::default(),
+            task_queue: TaskQueue::default(),
+            self_destruct_enabled: false,
+            pending_import_chunks: vec![],
         };
 
         let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
@@ -1200,6 +1276,8 @@ mod test {
             call_context_manager: None,
             compute_allocation: ComputeAllocation::try_from(0).unwrap(),
             accumulated_priority: AccumulatedPriority::from(0),
+            priority_class: PriorityClass::Normal,
+            heartbeat_instruction_limit: None,
             execution_state_bits: None,
             memory_allocation: MemoryAllocation::default(),
             freeze_threshold: NumSeconds::from(0),
@@ -1211,7 +1289,12 @@ mod test {
             interruped_during_execution: 0,
             certified_data: vec![],
             consumed_cycles_since_replica_started: NominalCycles::from(0),
+            consumed_cycles_by_heartbeats_since_replica_started: NominalCycles::from(0),
             stable_memory_size: NumWasmPages64::from(0),
+            recent_execution_metrics: RecentExecutionMetrics::default(),
+            task_queue: TaskQueue::default(),
+            self_destruct_enabled: false,
+            pending_import_chunks: vec![],
         };
 
         let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
@@ -1220,4 +1303,50 @@ mod test {
 
         assert_eq!(canister_state_bits.controllers, controllers)
     }
+
+    #[test]
+    fn test_encode_decode_priority_class() {
+        let canister_state_bits = CanisterStateBits {
+            controllers: BTreeSet::new(),
+            last_full_execution_round: ExecutionRound::from(0),
+            call_context_manager: None,
+            compute_allocation: ComputeAllocation::try_from(0).unwrap(),
+            accumulated_priority: AccumulatedPriority::from(0),
+            priority_class: PriorityClass::Elevated,
+            heartbeat_instruction_limit: None,
+            execution_state_bits: None,
+            memory_allocation: MemoryAllocation::default(),
+            freeze_threshold: NumSeconds::from(0),
+            cycles_balance: Cycles::from(0),
+            status: CanisterStatus::Stopped,
+            scheduled_as_first: 0,
+            skipped_round_due_to_no_messages: 0,
+            executed: 0,
+            interruped_during_execution: 0,
+            certified_data: vec![],
+            consumed_cycles_since_replica_started: NominalCycles::from(0),
+            consumed_cycles_by_heartbeats_since_replica_started: NominalCycles::from(0),
+            stable_memory_size: NumWasmPages64::from(0),
+            recent_execution_metrics: RecentExecutionMetrics::default(),
+            task_queue: TaskQueue::default(),
+            self_destruct_enabled: false,
+            pending_import_chunks: vec![],
+        };
+
+        let mut pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+        assert_eq!(
+            CanisterStateBits::try_from(pb_bits.clone())
+                .unwrap()
+                .priority_class,
+            PriorityClass::Elevated
+        );
+
+        // Canisters checkpointed before priority classes existed are of the
+        // normal class.
+        pb_bits.priority_class = pb_canister_state_bits::PriorityClass::Unspecified as i32;
+        assert_eq!(
+            CanisterStateBits::try_from(pb_bits).unwrap().priority_class,
+            PriorityClass::Normal
+        );
+    }
 }
@@ -73,6 +73,10 @@ pub fn make_checkpoint(
                 call_context_manager: canister_state.system_state.call_context_manager().cloned(),
                 compute_allocation: canister_state.scheduler_state.compute_allocation,
                 accumulated_priority: canister_state.scheduler_state.accumulated_priority,
+                priority_class: canister_state.scheduler_state.priority_class,
+                heartbeat_instruction_limit: canister_state
+                    .scheduler_state
+                    .heartbeat_instruction_limit,
                 memory_allocation: canister_state.system_state.memory_allocation,
                 freeze_threshold: canister_state.system_state.freeze_threshold,
                 cycles_balance: canister_state.system_state.cycles_balance,
@@ -96,7 +100,19 @@ pub fn make_checkpoint(
                     .system_state
                     .canister_metrics
                     .consumed_cycles_since_replica_started,
+                consumed_cycles_by_heartbeats_since_replica_started: canister_state
+                    .system_state
+                    .canister_metrics
+                    .consumed_cycles_by_heartbeats_since_replica_started,
                 stable_memory_size: canister_state.system_state.stable_memory_size,
+                recent_execution_metrics: canister_state
+                    .system_state
+                    .canister_metrics
+                    .recent_execution_metrics
+                    .clone(),
+                task_queue: canister_state.system_state.task_queue.clone(),
+                self_destruct_enabled: canister_state.system_state.self_destruct_enabled,
+                pending_import_chunks: canister_state.system_state.pending_import_chunks.clone(),
             }
             .into(),
         )?;
@@ -165,6 +181,7 @@ pub fn load_checkpoint<P: ReadPolicy>(
                         canister_layout.raw_path(),
                     )),
                     mapped_state: None,
+                    pending_compilation_trigger: None,
                 })
             }
             None => None,
@@ -189,6 +206,9 @@ pub fn load_checkpoint<P: ReadPolicy>(
             interruped_during_execution: canister_state_bits.interruped_during_execution,
             consumed_cycles_since_replica_started: canister_state_bits
                 .consumed_cycles_since_replica_started,
+            consumed_cycles_by_heartbeats_since_replica_started: canister_state_bits
+                .consumed_cycles_by_heartbeats_since_replica_started,
+            recent_execution_metrics: canister_state_bits.recent_execution_metrics,
         };
         let system_state = SystemState {
             canister_id: *canister_id,
@@ -202,6 +222,9 @@ pub fn load_checkpoint<P: ReadPolicy>(
             certified_data: canister_state_bits.certified_data,
             canister_metrics,
             cycles_balance: canister_state_bits.cycles_balance,
+            task_queue: canister_state_bits.task_queue,
+            self_destruct_enabled: canister_state_bits.self_destruct_enabled,
+            pending_import_chunks: canister_state_bits.pending_import_chunks,
         };
 
         canister_states.insert(
@@ -213,6 +236,8 @@ pub fn load_checkpoint<P: ReadPolicy>(
                     last_full_execution_round: canister_state_bits.last_full_execution_round,
                     compute_allocation: canister_state_bits.compute_allocation,
                     accumulated_priority: canister_state_bits.accumulated_priority,
+                    priority_class: canister_state_bits.priority_class,
+                    heartbeat_instruction_limit: canister_state_bits.heartbeat_instruction_limit,
                 },
             },
         );
@@ -492,6 +517,7 @@ mod tests {
                     can_layout.unwrap().raw_path(),
                 )),
                 mapped_state: None,
+                pending_compilation_trigger: None,
             };
             canister_state.execution_state = Some(execution_state);
             canister_state.system_state.stable_memory_size = NumWasmPages64::new(1);
@@ -68,6 +68,8 @@ pub struct StateManagerMetrics {
     resident_state_count: IntGauge,
     state_sync_size: IntCounterVec,
     state_sync_duration: HistogramVec,
+    state_sync_throttled: IntCounterVec,
+    state_sync_throttle_available_bytes: IntGauge,
     state_size: IntGauge,
 }
 
@@ -150,6 +152,25 @@ impl StateManagerMetrics {
             state_sync_size.with_label_values(&[*op]);
         }
 
+        let state_sync_throttled = metrics_registry.int_counter_vec(
+            "state_sync_throttled_total",
+            "Number of times state sync downloads were throttled, indexed by reason ('concurrency', 'bandwidth').",
+            &["reason"],
+        );
+
+        // Note [Metrics preallocation]
+        for reason in &[
+            state_sync::throttle::LABEL_CONCURRENCY,
+            state_sync::throttle::LABEL_BANDWIDTH,
+        ] {
+            state_sync_throttled.with_label_values(&[*reason]);
+        }
+
+        let state_sync_throttle_available_bytes = metrics_registry.int_gauge(
+            "state_sync_throttle_available_bytes",
+            "Number of bytes state sync may currently download before being throttled (negative while in debt).",
+        );
+
         let state_size = metrics_registry.int_gauge(
             "state_manager_state_size_bytes",
             "Total size of the state on disk in bytes.",
@@ -180,6 +201,8 @@ impl StateManagerMetrics {
             resident_state_count,
             state_sync_size,
             state_sync_duration,
+            state_sync_throttled,
+            state_sync_throttle_available_bytes,
             state_size,
         }
     }
@@ -399,6 +422,7 @@ pub struct StateManagerImpl {
     latest_certified_height: AtomicU64,
     /// The last height passed to remove_states_below()
     requested_to_remove_states_below: AtomicU64,
+    state_sync_throttle: Arc<state_sync::throttle::StateSyncThrottle>,
     _state_hasher_handle: JoinOnDrop<()>,
     _deallocation_handle: JoinOnDrop<()>,
 }
@@ -765,6 +789,11 @@ impl StateManagerImpl {
 
         report_last_diverged_checkpoint(&log, &metrics, &state_layout);
 
+        let state_sync_throttle = Arc::new(state_sync::throttle::StateSyncThrottle::new(
+            config.state_sync().clone(),
+            metrics.clone(),
+        ));
+
         Self {
             log,
             metrics,
@@ -778,6 +807,7 @@ impl StateManagerImpl {
             latest_state_height,
             latest_certified_height,
             requested_to_remove_states_below: AtomicU64::new(oldest_required_state.get()),
+            state_sync_throttle,
             _state_hasher_handle,
             _deallocation_handle,
         }
@@ -802,6 +832,7 @@ impl StateManagerImpl {
             self.latest_manifest(),
             self.metrics.clone(),
             self.own_subnet_type,
+            Arc::clone(&self.state_sync_throttle),
         ))
     }
 
@@ -1,4 +1,5 @@
 pub(crate) mod chunkable;
+pub(crate) mod throttle;
 
 use super::StateManagerImpl;
 use ic_crypto::crypto_hash;
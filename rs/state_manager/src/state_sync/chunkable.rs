@@ -1,5 +1,6 @@
 use crate::{
     manifest::{filter_out_zero_chunks, DiffScript},
+    state_sync::throttle::StateSyncThrottle,
     CheckpointRef, StateManagerMetrics,
 };
 use ic_cow_state::{CowMemoryManager, CowMemoryManagerImpl, MappedState};
@@ -22,6 +23,7 @@ use ic_types::{
 use std::collections::{HashMap, HashSet};
 use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 
 /// The state of the communication with up-to-date nodes.
@@ -51,6 +53,7 @@ pub struct IncompleteState {
     metrics: StateManagerMetrics,
     started_at: Instant,
     own_subnet_type: SubnetType,
+    throttle: Arc<StateSyncThrottle>,
 }
 
 impl Drop for IncompleteState {
@@ -101,6 +104,7 @@ impl IncompleteState {
         manifest_with_checkpoint_ref: Option<(Manifest, CheckpointRef)>,
         metrics: StateManagerMetrics,
         own_subnet_type: SubnetType,
+        throttle: Arc<StateSyncThrottle>,
     ) -> Self {
         Self {
             log,
@@ -115,6 +119,7 @@ impl IncompleteState {
             metrics,
             started_at: Instant::now(),
             own_subnet_type,
+            throttle,
         }
     }
 
@@ -636,8 +641,10 @@ impl Chunkable for IncompleteState {
                 manifest: _,
                 ref fetch_chunks,
             } => {
+                let allowed = self.throttle.allowed_chunks(fetch_chunks.len());
                 let ids: Vec<_> = fetch_chunks
                     .iter()
+                    .take(allowed)
                     .map(|id| ChunkId::new(*id as u32))
                     .collect();
                 Box::new(ids.into_iter())
@@ -659,6 +666,9 @@ impl Chunkable for IncompleteState {
                 return Err(ChunkVerificationFailed);
             }
         };
+        // The chunk has been downloaded regardless of whether it turns out to
+        // be valid, so it counts against the bandwidth budget.
+        self.throttle.on_chunk_received(payload.len());
 
         match &mut self.state {
             DownloadState::Complete(ref artifact) => {
@@ -0,0 +1,197 @@
+use crate::StateManagerMetrics;
+use ic_config::state_manager::StateSyncConfig;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Label value of `state_sync_throttled_total` used when fewer chunks were
+/// offered for download because of the concurrent chunk limit.
+pub(crate) const LABEL_CONCURRENCY: &str = "concurrency";
+/// Label value of `state_sync_throttled_total` used when no chunks were
+/// offered for download because the bandwidth budget was exhausted.
+pub(crate) const LABEL_BANDWIDTH: &str = "bandwidth";
+
+/// Token bucket holding the number of bytes that state sync may still
+/// download. The balance may become negative, since the size of a chunk is
+/// only charged once it has been received; downloads resume as soon as the
+/// balance is positive again.
+#[derive(Debug)]
+struct TokenBucket {
+    available_bytes: i64,
+    last_refill: Instant,
+}
+
+/// Limits the concurrency and bandwidth of state sync downloads.
+///
+/// A single throttle is shared by all states being synced by a node, so the
+/// limits apply to the node as a whole.
+pub(crate) struct StateSyncThrottle {
+    config: StateSyncConfig,
+    bucket: Mutex<TokenBucket>,
+    metrics: StateManagerMetrics,
+}
+
+impl StateSyncThrottle {
+    pub(crate) fn new(config: StateSyncConfig, metrics: StateManagerMetrics) -> Self {
+        let available_bytes = config.max_burst_bytes as i64;
+        metrics
+            .state_sync_throttle_available_bytes
+            .set(available_bytes);
+        Self {
+            config,
+            bucket: Mutex::new(TokenBucket {
+                available_bytes,
+                last_refill: Instant::now(),
+            }),
+            metrics,
+        }
+    }
+
+    /// Returns the maximum number of chunks that may be requested right now,
+    /// out of `num_missing` chunks that are still missing. Returns `0` if the
+    /// bandwidth budget is exhausted.
+    pub(crate) fn allowed_chunks(&self, num_missing: usize) -> usize {
+        self.allowed_chunks_at(num_missing, Instant::now())
+    }
+
+    fn allowed_chunks_at(&self, num_missing: usize, now: Instant) -> usize {
+        if num_missing == 0 {
+            return 0;
+        }
+
+        if self.bandwidth_throttled() {
+            let mut bucket = self.bucket.lock().unwrap();
+            self.refill(&mut bucket, now);
+            if bucket.available_bytes <= 0 {
+                self.metrics
+                    .state_sync_throttled
+                    .with_label_values(&[LABEL_BANDWIDTH])
+                    .inc();
+                return 0;
+            }
+        }
+
+        let max_chunks = self.config.max_concurrent_chunks;
+        if max_chunks != 0 && num_missing > max_chunks {
+            self.metrics
+                .state_sync_throttled
+                .with_label_values(&[LABEL_CONCURRENCY])
+                .inc();
+            return max_chunks;
+        }
+        num_missing
+    }
+
+    /// Charges the size of a received chunk against the bandwidth budget.
+    pub(crate) fn on_chunk_received(&self, size_bytes: usize) {
+        self.on_chunk_received_at(size_bytes, Instant::now())
+    }
+
+    fn on_chunk_received_at(&self, size_bytes: usize, now: Instant) {
+        if !self.bandwidth_throttled() {
+            return;
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, now);
+        bucket.available_bytes = bucket.available_bytes.saturating_sub(size_bytes as i64);
+        self.metrics
+            .state_sync_throttle_available_bytes
+            .set(bucket.available_bytes);
+    }
+
+    fn bandwidth_throttled(&self) -> bool {
+        self.config.max_bandwidth_bytes_per_sec != 0
+    }
+
+    /// Adds the bytes accrued since the last refill, up to the burst size.
+    fn refill(&self, bucket: &mut TokenBucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let accrued =
+            (elapsed.as_secs_f64() * self.config.max_bandwidth_bytes_per_sec as f64) as i64;
+        if accrued == 0 {
+            return;
+        }
+        bucket.available_bytes = bucket
+            .available_bytes
+            .saturating_add(accrued)
+            .min(self.config.max_burst_bytes as i64);
+        bucket.last_refill = now;
+        self.metrics
+            .state_sync_throttle_available_bytes
+            .set(bucket.available_bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_metrics::MetricsRegistry;
+    use std::time::Duration;
+
+    fn throttle(config: StateSyncConfig) -> StateSyncThrottle {
+        StateSyncThrottle::new(config, StateManagerMetrics::new(&MetricsRegistry::new()))
+    }
+
+    fn throttled_count(throttle: &StateSyncThrottle, label: &str) -> u64 {
+        throttle
+            .metrics
+            .state_sync_throttled
+            .with_label_values(&[label])
+            .get()
+    }
+
+    #[test]
+    fn default_config_does_not_throttle() {
+        let throttle = throttle(StateSyncConfig::default());
+        assert_eq!(throttle.allowed_chunks(10_000), 10_000);
+        throttle.on_chunk_received(1 << 30);
+        assert_eq!(throttle.allowed_chunks(10_000), 10_000);
+        assert_eq!(throttled_count(&throttle, LABEL_CONCURRENCY), 0);
+        assert_eq!(throttled_count(&throttle, LABEL_BANDWIDTH), 0);
+    }
+
+    #[test]
+    fn concurrent_chunks_are_limited() {
+        let throttle = throttle(StateSyncConfig {
+            max_concurrent_chunks: 5,
+            ..StateSyncConfig::default()
+        });
+        assert_eq!(throttle.allowed_chunks(3), 3);
+        assert_eq!(throttle.allowed_chunks(10), 5);
+        assert_eq!(throttled_count(&throttle, LABEL_CONCURRENCY), 1);
+    }
+
+    #[test]
+    fn bandwidth_is_limited_and_refilled() {
+        let throttle = throttle(StateSyncConfig {
+            max_concurrent_chunks: 0,
+            max_bandwidth_bytes_per_sec: 1000,
+            max_burst_bytes: 2000,
+        });
+        let start = throttle.bucket.lock().unwrap().last_refill;
+
+        assert_eq!(throttle.allowed_chunks_at(10, start), 10);
+        // Exceed the burst size, putting the bucket into debt.
+        throttle.on_chunk_received_at(3000, start);
+        assert_eq!(throttle.allowed_chunks_at(10, start), 0);
+        assert_eq!(throttled_count(&throttle, LABEL_BANDWIDTH), 1);
+
+        // After one second the debt is paid off, but nothing is available yet.
+        assert_eq!(
+            throttle.allowed_chunks_at(10, start + Duration::from_secs(1)),
+            0
+        );
+        // After another half a second 500 bytes are available again.
+        assert_eq!(
+            throttle.allowed_chunks_at(10, start + Duration::from_millis(1500)),
+            10
+        );
+        assert_eq!(
+            throttle.metrics.state_sync_throttle_available_bytes.get(),
+            500
+        );
+
+        // The bucket never holds more than the burst size.
+        throttle.allowed_chunks_at(10, start + Duration::from_secs(100));
+        assert_eq!(throttle.bucket.lock().unwrap().available_bytes, 2000);
+    }
+}
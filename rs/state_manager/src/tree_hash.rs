@@ -189,6 +189,7 @@ mod tests {
                 last_executed_round: ExecutionRound::from(0),
                 cow_mem_mgr: Arc::new(CowMemoryManagerImpl::open_readwrite(tmpdir.path().into())),
                 mapped_state: None,
+                pending_compilation_trigger: None,
             };
             canister_state.execution_state = Some(execution_state);
 
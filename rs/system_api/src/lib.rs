@@ -1,6 +1,8 @@
 mod request_in_prep;
 mod system_state_accessor;
 mod system_state_accessor_direct;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
 
 use ic_ic00_types::IC_00;
 use ic_interfaces::execution_environment::{
@@ -101,6 +103,19 @@ pub enum ApiType {
         max_reply_size: NumBytes,
     },
 
+    /// For executing the transform function of an HTTP outcall on the
+    /// response of the remote server. The function has the permissions of a
+    /// replicated query, except that it sees no data certificate, and its
+    /// reply is limited to `max_reply_size`.
+    Transform {
+        time: Time,
+        incoming_payload: Vec<u8>,
+        caller: PrincipalId,
+        response_data: Vec<u8>,
+        response_status: ResponseStatus,
+        max_reply_size: NumBytes,
+    },
+
     NonReplicatedQuery {
         time: Time,
         incoming_payload: Vec<u8>,
@@ -275,6 +290,22 @@ impl ApiType {
         }
     }
 
+    pub fn transform(
+        time: Time,
+        incoming_payload: Vec<u8>,
+        caller: PrincipalId,
+        max_reply_size: NumBytes,
+    ) -> Self {
+        Self::Transform {
+            time,
+            incoming_payload,
+            caller,
+            response_data: vec![],
+            response_status: ResponseStatus::NotRepliedYet,
+            max_reply_size,
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn non_replicated_query(
         time: Time,
@@ -394,6 +425,7 @@ impl ApiType {
             ApiType::Heartbeat { .. } => "heartbeat",
             ApiType::Update { .. } => "update",
             ApiType::ReplicatedQuery { .. } => "replicated query",
+            ApiType::Transform { .. } => "transform",
             ApiType::NonReplicatedQuery { .. } => "non replicated query",
             ApiType::ReplyCallback { .. } => "reply callback",
             ApiType::RejectCallback { .. } => "reject callback",
@@ -489,6 +521,14 @@ pub struct SystemApiImpl<A: SystemStateAccessor> {
     memory_usage: MemoryUsage,
 
     execution_parameters: ExecutionParameters,
+
+    // The offset into the incoming payload from which the next call to
+    // `ic0.msg_arg_data_next_chunk` continues copying.
+    msg_arg_data_offset: usize,
+
+    // The number of `memory.grow` calls that failed, either natively or
+    // because there was not enough available memory.
+    failed_memory_grows: u64,
 }
 
 impl<A: SystemStateAccessor> SystemApiImpl<A> {
@@ -513,9 +553,16 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             memory_usage,
             execution_parameters,
             log,
+            msg_arg_data_offset: 0,
+            failed_memory_grows: 0,
         }
     }
 
+    /// Returns the number of `memory.grow` calls that failed so far.
+    pub fn failed_memory_grows(&self) -> u64 {
+        self.failed_memory_grows
+    }
+
     pub fn take_execution_result(&mut self) -> HypervisorResult<Option<WasmResult>> {
         if let Some(err) = self.execution_error.take() {
             return Err(err);
@@ -541,6 +588,9 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             | ApiType::ReplicatedQuery {
                 response_status, ..
             }
+            | ApiType::Transform {
+                response_status, ..
+            }
             | ApiType::NonReplicatedQuery {
                 response_status, ..
             }
@@ -563,6 +613,35 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
         ))
     }
 
+    /// Returns the data certificate that `ic0.data_certificate_*` expose to
+    /// the canister.
+    ///
+    /// Only queries carry a certificate. The hypervisor passes one to a
+    /// replicated query only if the legacy behavior is enabled, see
+    /// `legacy_data_certificate_in_replicated_queries` in the execution
+    /// config; otherwise the certificate is only available in non-replicated
+    /// queries, as specified by the interface specification.
+    fn data_certificate(&self) -> Option<&Vec<u8>> {
+        match &self.api_type {
+            ApiType::Start { .. }
+            | ApiType::Init { .. }
+            | ApiType::Heartbeat { .. }
+            | ApiType::Update { .. }
+            | ApiType::ReplyCallback { .. }
+            | ApiType::RejectCallback { .. }
+            | ApiType::Cleanup { .. }
+            | ApiType::PreUpgrade { .. }
+            | ApiType::InspectMessage { .. }
+            | ApiType::Transform { .. } => None,
+            ApiType::ReplicatedQuery {
+                data_certificate, ..
+            }
+            | ApiType::NonReplicatedQuery {
+                data_certificate, ..
+            } => data_certificate.as_ref(),
+        }
+    }
+
     fn get_response_info(&mut self) -> Option<(&mut Vec<u8>, &NumBytes, &mut ResponseStatus)> {
         match &mut self.api_type {
             ApiType::Start { .. }
@@ -583,6 +662,12 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
                 max_reply_size,
                 ..
             }
+            | ApiType::Transform {
+                response_data,
+                response_status,
+                max_reply_size,
+                ..
+            }
             | ApiType::NonReplicatedQuery {
                 response_data,
                 response_status,
@@ -611,6 +696,7 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             | ApiType::Heartbeat { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::Update { .. }
             | ApiType::PreUpgrade { .. }
@@ -627,6 +713,7 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             | ApiType::Heartbeat { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::Update { .. }
             | ApiType::ReplyCallback { .. }
@@ -645,6 +732,7 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::Cleanup { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::NonReplicatedQuery { .. }
@@ -678,10 +766,7 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
         }
     }
 
-    fn ic0_canister_cycles_balance_helper(
-        &self,
-        method_name: &str,
-    ) -> HypervisorResult<(u64, u64)> {
+    fn ic0_canister_cycles_balance_helper(&self, method_name: &str) -> HypervisorResult<Cycles> {
         match &self.api_type {
             ApiType::Start {} => Err(self.error_for(method_name)),
             ApiType::Init { .. }
@@ -689,30 +774,30 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
             | ApiType::RejectCallback { .. }
             | ApiType::InspectMessage { .. } => {
-                let res = self
-                    .system_state_accessor
-                    .canister_cycles_balance()
-                    .into_parts();
-                Ok(res)
+                Ok(self.system_state_accessor.canister_cycles_balance())
             }
         }
     }
 
-    fn ic0_msg_cycles_available_helper(&self, method_name: &str) -> HypervisorResult<(u64, u64)> {
+    fn ic0_msg_cycles_available_helper(&self, method_name: &str) -> HypervisorResult<Cycles> {
         match &self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::Heartbeat { .. }
             | ApiType::Cleanup { .. }
-            | ApiType::ReplicatedQuery { .. }
-            | ApiType::PreUpgrade { .. }
+            | ApiType::Transform { .. }
+            | ApiType::PreUpgrade { .. } => Err(self.error_for(method_name)),
+            // Queries and ingress inspection run without a call context, so
+            // no cycles are ever attached to the message they handle.
+            ApiType::ReplicatedQuery { .. }
             | ApiType::NonReplicatedQuery { .. }
-            | ApiType::InspectMessage { .. } => Err(self.error_for(method_name)),
+            | ApiType::InspectMessage { .. } => Ok(Cycles::zero()),
             ApiType::Update {
                 call_context_id, ..
             }
@@ -723,28 +808,30 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
                 call_context_id, ..
             } => self
                 .system_state_accessor
-                .msg_cycles_available(call_context_id)
-                .map(|cycles| cycles.into_parts()),
+                .msg_cycles_available(call_context_id),
         }
     }
 
-    fn ic0_msg_cycles_refunded_helper(&self, method_name: &str) -> HypervisorResult<(u64, u64)> {
+    fn ic0_msg_cycles_refunded_helper(&self, method_name: &str) -> HypervisorResult<Cycles> {
         match &self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::Heartbeat { .. }
             | ApiType::Cleanup { .. }
-            | ApiType::ReplicatedQuery { .. }
-            | ApiType::NonReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::PreUpgrade { .. }
-            | ApiType::Update { .. }
-            | ApiType::InspectMessage { .. } => Err(self.error_for(method_name)),
+            | ApiType::Update { .. } => Err(self.error_for(method_name)),
+            // A query or an inspected ingress message never sent a call, so
+            // there is nothing that could have been refunded.
+            ApiType::ReplicatedQuery { .. }
+            | ApiType::NonReplicatedQuery { .. }
+            | ApiType::InspectMessage { .. } => Ok(Cycles::zero()),
             ApiType::ReplyCallback {
                 incoming_cycles, ..
             }
             | ApiType::RejectCallback {
                 incoming_cycles, ..
-            } => Ok((*incoming_cycles).into_parts()),
+            } => Ok(*incoming_cycles),
         }
     }
 
@@ -752,16 +839,19 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
         &mut self,
         method_name: &str,
         max_amount: Cycles,
-    ) -> HypervisorResult<(u64, u64)> {
+    ) -> HypervisorResult<Cycles> {
         match &mut self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::Heartbeat { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::Cleanup { .. }
-            | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. } => Err(self.error_for(method_name)),
+            // No cycles are available, see `ic0_msg_cycles_available_helper`,
+            // so accepting any amount accepts nothing.
+            ApiType::ReplicatedQuery { .. }
             | ApiType::NonReplicatedQuery { .. }
-            | ApiType::InspectMessage { .. } => Err(self.error_for(method_name)),
+            | ApiType::InspectMessage { .. } => Ok(Cycles::zero()),
             ApiType::Update {
                 call_context_id, ..
             }
@@ -772,8 +862,7 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
                 call_context_id, ..
             } => Ok(self
                 .system_state_accessor
-                .msg_cycles_accept(call_context_id, max_amount)
-                .into_parts()),
+                .msg_cycles_accept(call_context_id, max_amount)),
         }
     }
 
@@ -784,6 +873,7 @@ impl<A: SystemStateAccessor> SystemApiImpl<A> {
             | ApiType::Heartbeat { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::InspectMessage { .. }
             | ApiType::NonReplicatedQuery { .. } => (),
@@ -834,6 +924,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             ApiType::Init { caller, .. }
             | ApiType::Update { caller, .. }
             | ApiType::ReplicatedQuery { caller, .. }
+            | ApiType::Transform { caller, .. }
             | ApiType::NonReplicatedQuery { caller, .. }
             | ApiType::PreUpgrade { caller, .. }
             | ApiType::InspectMessage { caller, .. } => Ok(caller.as_slice().len() as u32),
@@ -856,6 +947,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             ApiType::Init { caller, .. }
             | ApiType::Update { caller, .. }
             | ApiType::ReplicatedQuery { caller, .. }
+            | ApiType::Transform { caller, .. }
             | ApiType::PreUpgrade { caller, .. }
             | ApiType::InspectMessage { caller, .. }
             | ApiType::NonReplicatedQuery { caller, .. } => {
@@ -888,6 +980,9 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::ReplicatedQuery {
                 incoming_payload, ..
             }
+            | ApiType::Transform {
+                incoming_payload, ..
+            }
             | ApiType::InspectMessage {
                 incoming_payload, ..
             }
@@ -922,6 +1017,9 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::ReplicatedQuery {
                 incoming_payload, ..
             }
+            | ApiType::Transform {
+                incoming_payload, ..
+            }
             | ApiType::InspectMessage {
                 incoming_payload, ..
             }
@@ -942,6 +1040,51 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
         }
     }
 
+    fn ic0_msg_arg_data_next_chunk(
+        &mut self,
+        dst: u32,
+        size: u32,
+        heap: &mut [u8],
+    ) -> HypervisorResult<u32> {
+        let incoming_payload = match &self.api_type {
+            ApiType::Start { .. }
+            | ApiType::Heartbeat { .. }
+            | ApiType::Cleanup { .. }
+            | ApiType::RejectCallback { .. }
+            | ApiType::PreUpgrade { .. } => {
+                return Err(self.error_for("ic0_msg_arg_data_next_chunk"))
+            }
+            ApiType::Init {
+                incoming_payload, ..
+            }
+            | ApiType::Update {
+                incoming_payload, ..
+            }
+            | ApiType::ReplyCallback {
+                incoming_payload, ..
+            }
+            | ApiType::ReplicatedQuery {
+                incoming_payload, ..
+            }
+            | ApiType::Transform {
+                incoming_payload, ..
+            }
+            | ApiType::InspectMessage {
+                incoming_payload, ..
+            }
+            | ApiType::NonReplicatedQuery {
+                incoming_payload, ..
+            } => incoming_payload,
+        };
+        valid_subslice("ic0.msg_arg_data_next_chunk heap", dst, size, heap)?;
+        let offset = self.msg_arg_data_offset;
+        let size = (size as usize).min(incoming_payload.len() - offset);
+        let dst = dst as usize;
+        heap[dst..dst + size].copy_from_slice(&incoming_payload[offset..offset + size]);
+        self.msg_arg_data_offset += size;
+        Ok(size as u32)
+    }
+
     fn ic0_msg_method_name_size(&self) -> HypervisorResult<u32> {
         match &self.api_type {
             ApiType::Start { .. }
@@ -952,6 +1095,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::ReplyCallback { .. }
             | ApiType::Heartbeat { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::Init { .. } => Err(self.error_for("ic0_msg_method_name_size")),
             ApiType::InspectMessage { method_name, .. } => Ok(method_name.len() as u32),
@@ -974,6 +1118,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::ReplyCallback { .. }
             | ApiType::Heartbeat { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::Init { .. } => Err(self.error_for("ic0_msg_method_name_copy")),
             ApiType::InspectMessage { method_name, .. } => {
@@ -1001,6 +1146,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::ReplyCallback { .. }
             | ApiType::Heartbeat { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::Init { .. } => Err(self.error_for("ic0_accept_message")),
             ApiType::InspectMessage {
@@ -1135,6 +1281,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Cleanup { .. }
             | ApiType::Update { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::ReplyCallback { .. }
             | ApiType::RejectCallback { .. }
@@ -1162,6 +1309,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Cleanup { .. }
             | ApiType::Update { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1186,6 +1334,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1210,6 +1359,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1245,6 +1395,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Init { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery {
                 query_kind: NonReplicatedQueryKind::Pure,
                 ..
@@ -1344,10 +1495,12 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
                 match self.system_state_accessor.push_output_request(
                     self.memory_usage.current_usage,
                     self.execution_parameters.compute_allocation,
+                    self.execution_parameters.canister_message_memory_capacity,
                     msg,
                 ) {
                     Ok(()) => Ok(0),
                     Err((StateError::QueueFull { .. }, request))
+                    | Err((StateError::OutOfMemory { .. }, request))
                     | Err((StateError::CanisterOutOfCycles { .. }, request)) => {
                         self.system_state_accessor
                             .canister_cycles_refund(request.payment);
@@ -1379,6 +1532,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery {
                 query_kind: NonReplicatedQueryKind::Pure,
                 ..
@@ -1431,6 +1585,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery {
                 query_kind: NonReplicatedQueryKind::Pure,
                 ..
@@ -1468,6 +1623,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery {
                 query_kind: NonReplicatedQueryKind::Pure,
                 ..
@@ -1517,11 +1673,66 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
     // are if the canister does not have sufficient cycles to send the request
     // or the output queues are full. In this case, we need to perform the
     // necessary cleanups.
+    fn ic0_call_queue_available(
+        &self,
+        callee_src: u32,
+        callee_size: u32,
+        heap: &[u8],
+    ) -> HypervisorResult<i32> {
+        let own_subnet_id = match &self.api_type {
+            ApiType::Start { .. }
+            | ApiType::Init { .. }
+            | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
+            | ApiType::NonReplicatedQuery {
+                query_kind: NonReplicatedQueryKind::Pure,
+                ..
+            }
+            | ApiType::Cleanup { .. }
+            | ApiType::PreUpgrade { .. }
+            | ApiType::InspectMessage { .. } => {
+                return Err(self.error_for("ic0_call_queue_available"))
+            }
+            ApiType::Update { own_subnet_id, .. }
+            | ApiType::NonReplicatedQuery {
+                own_subnet_id,
+                query_kind: NonReplicatedQueryKind::Stateful,
+                ..
+            }
+            | ApiType::Heartbeat { own_subnet_id, .. }
+            | ApiType::ReplyCallback { own_subnet_id, .. }
+            | ApiType::RejectCallback { own_subnet_id, .. } => *own_subnet_id,
+        };
+
+        let callee = {
+            let bytes = valid_subslice(
+                "ic0.call_queue_available callee_src",
+                callee_src,
+                callee_size,
+                heap,
+            )?;
+            PrincipalId::try_from(bytes).map_err(HypervisorError::InvalidPrincipalId)?
+        };
+        // Calls to ic:00 are routed to a subnet depending on the method and
+        // the payload. Report the queue to the own subnet, which handles most
+        // of them.
+        let receiver = if callee == IC_00.get() {
+            CanisterId::new(own_subnet_id.get()).unwrap()
+        } else {
+            CanisterId::new(callee).map_err(HypervisorError::InvalidCanisterId)?
+        };
+        let slots = self
+            .system_state_accessor
+            .available_output_request_slots(receiver);
+        Ok(i32::try_from(slots).unwrap_or(i32::MAX))
+    }
+
     fn ic0_call_perform(&mut self) -> HypervisorResult<i32> {
         match &mut self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery {
                 query_kind: NonReplicatedQueryKind::Pure,
                 ..
@@ -1583,10 +1794,12 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
                 match self.system_state_accessor.push_output_request(
                     self.memory_usage.current_usage,
                     self.execution_parameters.compute_allocation,
+                    self.execution_parameters.canister_message_memory_capacity,
                     req,
                 ) {
                     Ok(()) => Ok(0),
                     Err((StateError::QueueFull { .. }, request))
+                    | Err((StateError::OutOfMemory { .. }, request))
                     | Err((StateError::CanisterOutOfCycles { .. }, request)) => {
                         self.system_state_accessor
                             .canister_cycles_refund(request.payment);
@@ -1632,10 +1845,12 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
                 match self.system_state_accessor.push_output_request(
                     self.memory_usage.current_usage,
                     self.execution_parameters.compute_allocation,
+                    self.execution_parameters.canister_message_memory_capacity,
                     req,
                 ) {
                     Ok(()) => Ok(0),
                     Err((StateError::QueueFull { .. }, request))
+                    | Err((StateError::OutOfMemory { .. }, request))
                     | Err((StateError::CanisterOutOfCycles { .. }, request)) => {
                         self.system_state_accessor
                             .unregister_callback(request.sender_reply_callback);
@@ -1657,6 +1872,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1673,6 +1889,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1708,6 +1925,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1732,6 +1950,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1754,6 +1973,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1770,6 +1990,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1805,6 +2026,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1829,6 +2051,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Update { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::PreUpgrade { .. }
             | ApiType::ReplyCallback { .. }
@@ -1852,6 +2075,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::Cleanup { time, .. }
             | ApiType::NonReplicatedQuery { time, .. }
             | ApiType::ReplicatedQuery { time, .. }
+            | ApiType::Transform { time, .. }
             | ApiType::PreUpgrade { time, .. }
             | ApiType::ReplyCallback { time, .. }
             | ApiType::RejectCallback { time, .. }
@@ -1869,115 +2093,75 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
         additional_pages: u32,
     ) -> HypervisorResult<i32> {
         if native_memory_grow_res == -1 {
+            self.failed_memory_grows += 1;
             return Ok(-1);
         }
         match self.memory_usage.increase_usage(additional_pages as u64) {
             Ok(()) => Ok(native_memory_grow_res),
-            Err(_err) => Err(HypervisorError::OutOfMemory),
+            Err(_err) => {
+                self.failed_memory_grows += 1;
+                Err(HypervisorError::OutOfMemory)
+            }
         }
     }
 
     fn ic0_canister_cycle_balance(&self) -> HypervisorResult<u64> {
-        let (high_amount, low_amount) =
-            self.ic0_canister_cycles_balance_helper("ic0_canister_cycles_balance")?;
-        if high_amount != 0 {
-            return Err(HypervisorError::Trapped(CyclesAmountTooBigFor64Bit));
-        }
-        Ok(low_amount)
+        let balance = self.ic0_canister_cycles_balance_helper("ic0_canister_cycles_balance")?;
+        cycles_to_u64(balance)
     }
 
-    fn ic0_canister_cycles_balance128(&self) -> HypervisorResult<(u64, u64)> {
+    fn ic0_canister_cycles_balance128(&self) -> HypervisorResult<Cycles> {
         self.ic0_canister_cycles_balance_helper("ic0_canister_cycles_balance128")
     }
 
     fn ic0_msg_cycles_available(&self) -> HypervisorResult<u64> {
-        let (high_amount, low_amount) =
-            self.ic0_msg_cycles_available_helper("ic0_msg_cycles_available")?;
-        if high_amount != 0 {
-            return Err(HypervisorError::Trapped(CyclesAmountTooBigFor64Bit));
-        }
-        Ok(low_amount)
+        let available = self.ic0_msg_cycles_available_helper("ic0_msg_cycles_available")?;
+        cycles_to_u64(available)
     }
 
-    fn ic0_msg_cycles_available128(&self) -> HypervisorResult<(u64, u64)> {
+    fn ic0_msg_cycles_available128(&self) -> HypervisorResult<Cycles> {
         self.ic0_msg_cycles_available_helper("ic0_msg_cycles_available128")
     }
 
     fn ic0_msg_cycles_refunded(&self) -> HypervisorResult<u64> {
-        let (high_amount, low_amount) =
-            self.ic0_msg_cycles_refunded_helper("ic0_msg_cycles_refunded")?;
-        if high_amount != 0 {
-            return Err(HypervisorError::Trapped(CyclesAmountTooBigFor64Bit));
-        }
-        Ok(low_amount)
+        let refunded = self.ic0_msg_cycles_refunded_helper("ic0_msg_cycles_refunded")?;
+        cycles_to_u64(refunded)
     }
 
-    fn ic0_msg_cycles_refunded128(&self) -> HypervisorResult<(u64, u64)> {
+    fn ic0_msg_cycles_refunded128(&self) -> HypervisorResult<Cycles> {
         self.ic0_msg_cycles_refunded_helper("ic0_msg_cycles_refunded128")
     }
 
     fn ic0_msg_cycles_accept(&mut self, max_amount: u64) -> HypervisorResult<u64> {
         // Cannot accept more than max_amount.
-        let (high_amount, low_amount) =
+        let accepted =
             self.ic0_msg_cycles_accept_helper("ic0_msg_cycles_accept", Cycles::from(max_amount))?;
-        if high_amount != 0 {
-            error!(
-                self.log,
-                "ic0_msg_cycles_accept cannot accept more than max_amount {}; accepted amount {}",
-                max_amount,
-                Cycles::from_parts(high_amount, low_amount).get()
-            )
+        match u64::try_from(accepted.get()) {
+            Ok(accepted) => Ok(accepted),
+            Err(_) => {
+                error!(
+                    self.log,
+                    "ic0_msg_cycles_accept cannot accept more than max_amount {}; accepted amount {}",
+                    max_amount,
+                    accepted
+                );
+                Ok(max_amount)
+            }
         }
-        Ok(low_amount)
     }
 
-    fn ic0_msg_cycles_accept128(&mut self, max_amount: Cycles) -> HypervisorResult<(u64, u64)> {
+    fn ic0_msg_cycles_accept128(&mut self, max_amount: Cycles) -> HypervisorResult<Cycles> {
         self.ic0_msg_cycles_accept_helper("ic0_msg_cycles_accept128", max_amount)
     }
 
     fn ic0_data_certificate_present(&self) -> HypervisorResult<i32> {
-        match &self.api_type {
-            ApiType::Start { .. }
-            | ApiType::Init { .. }
-            | ApiType::ReplyCallback { .. }
-            | ApiType::RejectCallback { .. }
-            | ApiType::Cleanup { .. }
-            | ApiType::PreUpgrade { .. }
-            | ApiType::InspectMessage { .. }
-            | ApiType::Update { .. }
-            | ApiType::Heartbeat { .. } => Ok(0),
-            ApiType::ReplicatedQuery {
-                data_certificate, ..
-            }
-            | ApiType::NonReplicatedQuery {
-                data_certificate, ..
-            } => match data_certificate {
-                Some(_) => Ok(1),
-                None => Ok(0),
-            },
-        }
+        Ok(self.data_certificate().is_some() as i32)
     }
 
     fn ic0_data_certificate_size(&self) -> HypervisorResult<i32> {
-        match &self.api_type {
-            ApiType::Start { .. }
-            | ApiType::Init { .. }
-            | ApiType::Heartbeat { .. }
-            | ApiType::Update { .. }
-            | ApiType::ReplyCallback { .. }
-            | ApiType::RejectCallback { .. }
-            | ApiType::Cleanup { .. }
-            | ApiType::PreUpgrade { .. }
-            | ApiType::InspectMessage { .. } => Err(self.error_for("ic0_data_certificate_size")),
-            ApiType::ReplicatedQuery {
-                data_certificate, ..
-            }
-            | ApiType::NonReplicatedQuery {
-                data_certificate, ..
-            } => match data_certificate {
-                Some(data_certificate) => Ok(data_certificate.len() as i32),
-                None => Err(self.error_for("ic0_data_certificate_size")),
-            },
+        match self.data_certificate() {
+            Some(data_certificate) => Ok(data_certificate.len() as i32),
+            None => Err(self.error_for("ic0_data_certificate_size")),
         }
     }
 
@@ -1988,62 +2172,95 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
         size: u32,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
-        match &self.api_type {
-            ApiType::Start { .. }
-            | ApiType::Init { .. }
-            | ApiType::Heartbeat { .. }
-            | ApiType::Update { .. }
-            | ApiType::ReplyCallback { .. }
-            | ApiType::RejectCallback { .. }
-            | ApiType::Cleanup { .. }
-            | ApiType::PreUpgrade { .. }
-            | ApiType::InspectMessage { .. } => Err(self.error_for("ic0_data_certificate_copy")),
-            ApiType::ReplicatedQuery {
-                data_certificate, ..
-            }
-            | ApiType::NonReplicatedQuery {
-                data_certificate, ..
-            } => match data_certificate {
-                Some(data_certificate) => {
-                    let (dst, offset, size) = (dst as usize, offset as usize, size as usize);
-
-                    let (upper_bound, overflow) = offset.overflowing_add(size);
-                    if overflow || upper_bound > data_certificate.len() {
-                        return Err(ContractViolation(format!(
-                            "ic0_data_certificate_copy failed because offset + size is out \
-                                 of bounds. Found offset = {} and size = {} while offset + size \
-                                 must be <= {}",
-                            offset,
-                            size,
-                            data_certificate.len(),
-                        )));
-                    }
+        match self.data_certificate() {
+            Some(data_certificate) => {
+                let (dst, offset, size) = (dst as usize, offset as usize, size as usize);
 
-                    let (upper_bound, overflow) = dst.overflowing_add(size);
-                    if overflow || upper_bound > heap.len() {
-                        return Err(ContractViolation(format!(
-                            "ic0_data_certificate_copy failed because dst + size is out \
-                                 of bounds. Found dst = {} and size = {} while dst + size \
-                                 must be <= {}",
-                            dst,
-                            size,
-                            heap.len(),
-                        )));
-                    }
+                let (upper_bound, overflow) = offset.overflowing_add(size);
+                if overflow || upper_bound > data_certificate.len() {
+                    return Err(ContractViolation(format!(
+                        "ic0_data_certificate_copy failed because offset + size is out \
+                             of bounds. Found offset = {} and size = {} while offset + size \
+                             must be <= {}",
+                        offset,
+                        size,
+                        data_certificate.len(),
+                    )));
+                }
 
-                    // Copy the certificate into the canister.
-                    heap[dst..dst + size].copy_from_slice(&data_certificate[offset..offset + size]);
-                    Ok(())
+                let (upper_bound, overflow) = dst.overflowing_add(size);
+                if overflow || upper_bound > heap.len() {
+                    return Err(ContractViolation(format!(
+                        "ic0_data_certificate_copy failed because dst + size is out \
+                             of bounds. Found dst = {} and size = {} while dst + size \
+                             must be <= {}",
+                        dst,
+                        size,
+                        heap.len(),
+                    )));
                 }
-                None => Err(self.error_for("ic0_data_certificate_size")),
-            },
+
+                // Copy the certificate into the canister.
+                heap[dst..dst + size].copy_from_slice(&data_certificate[offset..offset + size]);
+                Ok(())
+            }
+            None => Err(self.error_for("ic0_data_certificate_copy")),
+        }
+    }
+
+    fn ic0_root_key_size(&self) -> HypervisorResult<i32> {
+        match &self.api_type {
+            ApiType::Start { .. } => Err(self.error_for("ic0_root_key_size")),
+            _ => Ok(self.execution_parameters.root_key.len() as i32),
+        }
+    }
+
+    fn ic0_root_key_copy(
+        &self,
+        dst: u32,
+        offset: u32,
+        size: u32,
+        heap: &mut [u8],
+    ) -> HypervisorResult<()> {
+        if let ApiType::Start { .. } = self.api_type {
+            return Err(self.error_for("ic0_root_key_copy"));
+        }
+        let (dst, offset, size) = (dst as usize, offset as usize, size as usize);
+
+        let (upper_bound, overflow) = offset.overflowing_add(size);
+        if overflow || upper_bound > self.execution_parameters.root_key.len() {
+            return Err(ContractViolation(format!(
+                "ic0_root_key_copy failed because offset + size is out \
+                     of bounds. Found offset = {} and size = {} while offset + size \
+                     must be <= {}",
+                offset,
+                size,
+                self.execution_parameters.root_key.len(),
+            )));
+        }
+
+        let (upper_bound, overflow) = dst.overflowing_add(size);
+        if overflow || upper_bound > heap.len() {
+            return Err(ContractViolation(format!(
+                "ic0_root_key_copy failed because dst + size is out \
+                     of bounds. Found dst = {} and size = {} while dst + size \
+                     must be <= {}",
+                dst,
+                size,
+                heap.len(),
+            )));
         }
+
+        heap[dst..dst + size]
+            .copy_from_slice(&self.execution_parameters.root_key[offset..offset + size]);
+        Ok(())
     }
 
     fn ic0_certified_data_set(&mut self, src: u32, size: u32, heap: &[u8]) -> HypervisorResult<()> {
         match &mut self.api_type {
             ApiType::Start { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::Cleanup { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::InspectMessage { .. } => Err(self.error_for("ic0_certified_data_set")),
@@ -2086,6 +2303,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
         match &self.api_type {
             ApiType::Start { .. } => Err(self.error_for("ic0_canister_status")),
             ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::Init { .. }
             | ApiType::Cleanup { .. }
@@ -2111,6 +2329,7 @@ impl<A: SystemStateAccessor> SystemApi for SystemApiImpl<A> {
             | ApiType::PreUpgrade { .. }
             | ApiType::Cleanup { .. }
             | ApiType::ReplicatedQuery { .. }
+            | ApiType::Transform { .. }
             | ApiType::NonReplicatedQuery { .. }
             | ApiType::InspectMessage { .. } => Err(self.error_for("ic0_mint_cycles")),
             ApiType::Update { .. }
@@ -2169,15 +2388,24 @@ pub(crate) fn valid_subslice<'a>(
     Ok(&slice[src..src + len])
 }
 
+/// Converts an amount of cycles to the result of a legacy 64-bit cycles
+/// system call. This is the only place where the system API narrows cycles
+/// to 64 bits: all accounting is done on 128-bit values, and the call traps
+/// if the amount does not fit instead of silently truncating it.
+fn cycles_to_u64(cycles: Cycles) -> HypervisorResult<u64> {
+    u64::try_from(cycles.get()).map_err(|_| HypervisorError::Trapped(CyclesAmountTooBigFor64Bit))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_utils::ApiTypeBuilder;
     use ic_base_types::NumSeconds;
     use ic_cycles_account_manager::CyclesAccountManager;
     use ic_logger::replica_logger::no_op_logger;
     use ic_registry_routing_table::CanisterIdRange;
     use ic_registry_subnet_type::SubnetType;
-    use ic_replicated_state::{CallOrigin, SystemState};
+    use ic_replicated_state::{canister_state::DEFAULT_QUEUE_CAPACITY, CallOrigin, SystemState};
     use ic_test_utilities::{
         cycles_account_manager::CyclesAccountManagerBuilder,
         mock_time,
@@ -2186,6 +2414,7 @@ mod test {
     };
     use ic_types::{messages::CallbackId, ComputeAllocation, NumInstructions};
     use maplit::btreemap;
+    use proptest::prelude::*;
     use std::convert::TryInto;
 
     const INITIAL_CYCLES: Cycles = Cycles::new(1 << 40);
@@ -2198,6 +2427,8 @@ mod test {
             canister_memory_limit: NumBytes::new(4 << 30),
             subnet_available_memory: SubnetAvailableMemory::new(NumBytes::new(std::u64::MAX)),
             compute_allocation: ComputeAllocation::default(),
+            canister_message_memory_capacity: NumBytes::new(std::u64::MAX),
+            root_key: vec![],
         }
     }
 
@@ -2363,6 +2594,7 @@ mod test {
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2382,6 +2614,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2391,6 +2624,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -2427,6 +2662,7 @@ mod test {
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2446,6 +2682,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2455,6 +2692,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_supported(api.ic0_msg_cycles_available());
@@ -2483,6 +2722,7 @@ mod test {
 
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_supported(api.ic0_msg_caller_size());
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
@@ -2504,6 +2744,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2513,6 +2754,64 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_canister_cycle_balance());
+        assert_api_supported(api.ic0_canister_cycles_balance128());
+        assert_api_supported(api.ic0_msg_cycles_available());
+        assert_api_supported(api.ic0_msg_cycles_available128());
+        assert_api_supported(api.ic0_msg_cycles_refunded());
+        assert_api_supported(api.ic0_msg_cycles_refunded128());
+        assert_api_supported(api.ic0_msg_cycles_accept(0));
+        assert_api_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
+        assert_api_supported(api.ic0_data_certificate_present());
+        assert_api_not_supported(api.ic0_data_certificate_size());
+        assert_api_not_supported(api.ic0_data_certificate_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_certified_data_set(0, 0, &[]));
+        assert_api_supported(api.ic0_canister_status());
+        assert_api_not_supported(api.ic0_mint_cycles(0));
+    }
+
+    #[test]
+    fn test_transform_support() {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let system_state = SystemStateBuilder::default().build();
+        let mut api = get_system_api(
+            ApiTypeBuilder::new().build_transform(NumBytes::new(1024)),
+            system_state,
+            cycles_account_manager,
+        );
+
+        assert_api_supported(api.ic0_msg_arg_data_size());
+        assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_caller_size());
+        assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_method_name_size());
+        assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_accept_message());
+        assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
+        assert_api_not_supported(api.ic0_msg_reject_code());
+        assert_api_not_supported(api.ic0_msg_reject_msg_size());
+        assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_canister_self_size());
+        assert_api_supported(api.ic0_canister_self_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_controller_size());
+        assert_api_supported(api.ic0_controller_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_call_simple(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, &[]));
+        assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
+        assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
+        assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+        assert_api_not_supported(api.ic0_call_cycles_add(0));
+        assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
+        assert_api_supported(api.ic0_stable_size());
+        assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_stable64_size());
+        assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -2529,6 +2828,30 @@ mod test {
         assert_api_not_supported(api.ic0_mint_cycles(0));
     }
 
+    #[test]
+    fn test_transform_reply_is_limited() {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let system_state = SystemStateBuilder::default().build();
+        let mut api = get_system_api(
+            ApiTypeBuilder::new().build_transform(NumBytes::new(10)),
+            system_state,
+            cycles_account_manager,
+        );
+        let heap = [1; 11];
+
+        assert_eq!(api.ic0_msg_reply_data_append(0, 6, &heap), Ok(()));
+        assert!(matches!(
+            api.ic0_msg_reply_data_append(0, 5, &heap),
+            Err(ContractViolation(_))
+        ));
+        assert_eq!(api.ic0_msg_reply_data_append(0, 4, &heap), Ok(()));
+        assert_eq!(api.ic0_msg_reply(), Ok(()));
+        assert_eq!(
+            api.take_execution_result(),
+            Ok(Some(WasmResult::Reply(vec![1; 10])))
+        );
+    }
+
     #[test]
     fn test_canister_pure_query_support() {
         let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
@@ -2541,6 +2864,7 @@ mod test {
 
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_supported(api.ic0_msg_caller_size());
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
@@ -2562,6 +2886,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2571,14 +2896,16 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
-        assert_api_not_supported(api.ic0_msg_cycles_available());
-        assert_api_not_supported(api.ic0_msg_cycles_available128());
-        assert_api_not_supported(api.ic0_msg_cycles_refunded());
-        assert_api_not_supported(api.ic0_msg_cycles_refunded128());
-        assert_api_not_supported(api.ic0_msg_cycles_accept(0));
-        assert_api_not_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
+        assert_api_supported(api.ic0_msg_cycles_available());
+        assert_api_supported(api.ic0_msg_cycles_available128());
+        assert_api_supported(api.ic0_msg_cycles_refunded());
+        assert_api_supported(api.ic0_msg_cycles_refunded128());
+        assert_api_supported(api.ic0_msg_cycles_accept(0));
+        assert_api_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
         assert_api_supported(api.ic0_data_certificate_present());
         assert_api_not_supported(api.ic0_data_certificate_size());
         assert_api_not_supported(api.ic0_data_certificate_copy(0, 0, 0, &mut []));
@@ -2611,6 +2938,7 @@ mod test {
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2630,6 +2958,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2639,14 +2968,16 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
-        assert_api_not_supported(api.ic0_msg_cycles_available());
-        assert_api_not_supported(api.ic0_msg_cycles_available128());
-        assert_api_not_supported(api.ic0_msg_cycles_refunded());
-        assert_api_not_supported(api.ic0_msg_cycles_refunded128());
-        assert_api_not_supported(api.ic0_msg_cycles_accept(0));
-        assert_api_not_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
+        assert_api_supported(api.ic0_msg_cycles_available());
+        assert_api_supported(api.ic0_msg_cycles_available128());
+        assert_api_supported(api.ic0_msg_cycles_refunded());
+        assert_api_supported(api.ic0_msg_cycles_refunded128());
+        assert_api_supported(api.ic0_msg_cycles_accept(0));
+        assert_api_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
         assert_api_supported(api.ic0_data_certificate_present());
         assert_api_supported(api.ic0_data_certificate_size());
         assert_api_supported(api.ic0_data_certificate_copy(0, 0, 0, &mut []));
@@ -2684,6 +3015,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2703,6 +3035,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2712,6 +3045,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_supported(api.ic0_msg_cycles_available());
@@ -2736,6 +3071,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2755,6 +3091,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_write(0, 0, 0, &[]));
@@ -2764,6 +3101,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_supported(api.ic0_msg_cycles_available());
@@ -2800,6 +3139,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2819,6 +3159,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2828,6 +3169,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_supported(api.ic0_msg_cycles_available());
@@ -2862,6 +3205,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -2881,6 +3225,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2890,6 +3235,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_supported(api.ic0_msg_cycles_available());
@@ -2918,6 +3265,7 @@ mod test {
 
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_supported(api.ic0_msg_caller_size());
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
@@ -2939,6 +3287,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -2948,6 +3297,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -2972,6 +3323,7 @@ mod test {
 
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_caller_size());
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
@@ -2993,6 +3345,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_not_supported(api.ic0_stable_size());
         assert_api_not_supported(api.ic0_stable_grow(1));
         assert_api_not_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -3002,6 +3355,8 @@ mod test {
         assert_api_not_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_not_supported(api.ic0_time());
+        assert_api_not_supported(api.ic0_root_key_size());
+        assert_api_not_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_canister_cycle_balance());
         assert_api_not_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -3032,6 +3387,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_reply());
@@ -3051,6 +3407,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -3060,6 +3417,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -3095,6 +3454,7 @@ mod test {
         assert_api_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_msg_arg_data_size());
         assert_api_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_supported(api.ic0_msg_method_name_size());
         assert_api_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_accept_message());
@@ -3114,6 +3474,7 @@ mod test {
         assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_not_supported(api.ic0_call_cycles_add(0));
         assert_api_not_supported(api.ic0_call_perform());
+        assert_api_not_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -3123,14 +3484,16 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
-        assert_api_not_supported(api.ic0_msg_cycles_available());
-        assert_api_not_supported(api.ic0_msg_cycles_available128());
-        assert_api_not_supported(api.ic0_msg_cycles_refunded());
-        assert_api_not_supported(api.ic0_msg_cycles_refunded128());
-        assert_api_not_supported(api.ic0_msg_cycles_accept(0));
-        assert_api_not_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
+        assert_api_supported(api.ic0_msg_cycles_available());
+        assert_api_supported(api.ic0_msg_cycles_available128());
+        assert_api_supported(api.ic0_msg_cycles_refunded());
+        assert_api_supported(api.ic0_msg_cycles_refunded128());
+        assert_api_supported(api.ic0_msg_cycles_accept(0));
+        assert_api_supported(api.ic0_msg_cycles_accept128(Cycles::zero()));
         assert_api_supported(api.ic0_data_certificate_present());
         assert_api_not_supported(api.ic0_data_certificate_size());
         assert_api_not_supported(api.ic0_data_certificate_copy(0, 0, 0, &mut []));
@@ -3161,6 +3524,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -3180,6 +3544,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -3189,6 +3554,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_grow(1));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -3230,6 +3597,7 @@ mod test {
         assert_api_not_supported(api.ic0_msg_caller_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_arg_data_size());
         assert_api_not_supported(api.ic0_msg_arg_data_copy(0, 0, 0, &mut []));
+        assert_api_not_supported(api.ic0_msg_arg_data_next_chunk(0, 0, &mut []));
         assert_api_not_supported(api.ic0_msg_method_name_size());
         assert_api_not_supported(api.ic0_msg_method_name_copy(0, 0, 0, &mut []));
         assert_api_not_supported(api.ic0_accept_message());
@@ -3249,6 +3617,7 @@ mod test {
         assert_api_supported(api.ic0_call_on_cleanup(0, 0));
         assert_api_supported(api.ic0_call_cycles_add(0));
         assert_api_supported(api.ic0_call_perform());
+        assert_api_supported(api.ic0_call_queue_available(0, 0, &[]));
         assert_api_supported(api.ic0_stable_size());
         assert_api_supported(api.ic0_stable_grow(1));
         assert_api_supported(api.ic0_stable_read(0, 0, 0, &mut []));
@@ -3258,6 +3627,8 @@ mod test {
         assert_api_supported(api.ic0_stable64_read(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_stable64_write(0, 0, 0, &[]));
         assert_api_supported(api.ic0_time());
+        assert_api_supported(api.ic0_root_key_size());
+        assert_api_supported(api.ic0_root_key_copy(0, 0, 0, &mut []));
         assert_api_supported(api.ic0_canister_cycle_balance());
         assert_api_supported(api.ic0_canister_cycles_balance128());
         assert_api_not_supported(api.ic0_msg_cycles_available());
@@ -3295,6 +3666,52 @@ mod test {
         assert!(valid_subslice("", 4, 1, &[1, 2, 3, 4]).is_err());
     }
 
+    #[test]
+    fn test_msg_arg_data_next_chunk_streams_payload() {
+        let (subnet_id, subnet_type, routing_table, subnet_records) = setup();
+        let payload: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let api_type = ApiType::update(
+            mock_time(),
+            payload.clone(),
+            Cycles::from(0),
+            user_test_id(1).get(),
+            CallContextId::from(1),
+            subnet_id,
+            subnet_type,
+            routing_table,
+            subnet_records,
+        );
+        let system_state = get_new_running_system_state(
+            Cycles::from(1_000_000_000_000u128),
+            SubnetType::Application,
+        );
+        let mut api = get_system_api(
+            api_type,
+            system_state,
+            CyclesAccountManagerBuilder::new().build(),
+        );
+
+        // Reuse a single 64 KiB buffer in the heap for all chunks.
+        let chunk_size = 64 * 1024;
+        let mut heap = vec![0; chunk_size + 10];
+        let mut streamed = vec![];
+        loop {
+            let copied = api
+                .ic0_msg_arg_data_next_chunk(10, chunk_size as u32, &mut heap)
+                .unwrap() as usize;
+            if copied == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&heap[10..10 + copied]);
+        }
+        assert_eq!(streamed, payload);
+
+        // The chunk must fit into the heap.
+        assert!(api
+            .ic0_msg_arg_data_next_chunk(10, chunk_size as u32 + 1, &mut heap)
+            .is_err());
+    }
+
     #[test]
     fn test_discard_cycles_charge_by_new_call() {
         let cycles_amount = Cycles::from(1_000_000_000_000u128);
@@ -3461,8 +3878,7 @@ mod test {
             api.ic0_canister_cycle_balance(),
             Err(HypervisorError::Trapped(CyclesAmountTooBigFor64Bit))
         );
-        let (high, low) = api.ic0_canister_cycles_balance128().unwrap();
-        assert_eq!(Cycles::from_parts(high, low), cycles_amount);
+        assert_eq!(api.ic0_canister_cycles_balance128().unwrap(), cycles_amount);
     }
 
     #[test]
@@ -3485,8 +3901,7 @@ mod test {
             api.ic0_msg_cycles_available(),
             Err(HypervisorError::Trapped(CyclesAmountTooBigFor64Bit))
         );
-        let (high, low) = api.ic0_msg_cycles_available128().unwrap();
-        assert_eq!(Cycles::from_parts(high, low), available_cycles);
+        assert_eq!(api.ic0_msg_cycles_available128().unwrap(), available_cycles);
     }
 
     #[test]
@@ -3505,8 +3920,33 @@ mod test {
             api.ic0_msg_cycles_refunded(),
             Err(HypervisorError::Trapped(CyclesAmountTooBigFor64Bit))
         );
-        let (high, low) = api.ic0_msg_cycles_refunded128().unwrap();
-        assert_eq!(Cycles::from_parts(high, low), incoming_cycles);
+        assert_eq!(api.ic0_msg_cycles_refunded128().unwrap(), incoming_cycles);
+    }
+
+    #[test]
+    fn msg_cycles_introspection_returns_zero_without_call_context() {
+        for api_type in vec![
+            ApiTypeBuilder::new().build_replicated_query(),
+            ApiTypeBuilder::new().build_pure_query(),
+            ApiTypeBuilder::new().build_stateful_query(),
+            ApiTypeBuilder::new().build_inspect_message(),
+        ] {
+            let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+            let system_state = SystemStateBuilder::default().build();
+            let mut api = get_system_api(api_type, system_state, cycles_account_manager);
+            let balance = api.ic0_canister_cycles_balance128().unwrap();
+
+            assert_eq!(api.ic0_msg_cycles_available(), Ok(0));
+            assert_eq!(api.ic0_msg_cycles_available128(), Ok(Cycles::zero()));
+            assert_eq!(api.ic0_msg_cycles_refunded(), Ok(0));
+            assert_eq!(api.ic0_msg_cycles_refunded128(), Ok(Cycles::zero()));
+            assert_eq!(api.ic0_msg_cycles_accept(100), Ok(0));
+            assert_eq!(
+                api.ic0_msg_cycles_accept128(Cycles::from(100)),
+                Ok(Cycles::zero())
+            );
+            assert_eq!(api.ic0_canister_cycles_balance128(), Ok(balance));
+        }
     }
 
     #[test]
@@ -3766,6 +4206,70 @@ mod test {
         assert_eq!(system_state.cycles_balance, initial_cycles);
     }
 
+    #[test]
+    fn call_queue_available_decreases_with_performed_calls() {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let mut system_state = SystemStateBuilder::new().build();
+        system_state
+            .call_context_manager_mut()
+            .unwrap()
+            .new_call_context(
+                CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+                Cycles::from(40),
+            );
+        let mut api = get_system_api(get_update_api_type(), system_state, cycles_account_manager);
+        let callee = canister_test_id(34).get();
+        let mut heap = callee.as_slice().to_vec();
+        let callee_size = heap.len() as u32;
+        heap.resize(1024, 0);
+
+        assert_eq!(
+            api.ic0_call_queue_available(0, callee_size, &heap),
+            Ok(DEFAULT_QUEUE_CAPACITY as i32)
+        );
+        api.ic0_call_new(0, callee_size, 100, 10, 0, 0, 0, 0, &heap)
+            .unwrap();
+        assert_eq!(api.ic0_call_perform(), Ok(0));
+        assert_eq!(
+            api.ic0_call_queue_available(0, callee_size, &heap),
+            Ok(DEFAULT_QUEUE_CAPACITY as i32 - 1)
+        );
+        // Queues to other canisters are not affected.
+        let other = canister_test_id(35).get();
+        assert_eq!(
+            api.ic0_call_queue_available(0, callee_size, other.as_slice()),
+            Ok(DEFAULT_QUEUE_CAPACITY as i32)
+        );
+    }
+
+    #[test]
+    fn root_key_can_be_copied() {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let root_key = vec![1, 2, 3, 4, 5];
+        let system_state_accessor = SystemStateAccessorDirect::new(
+            SystemStateBuilder::new().build(),
+            Arc::new(cycles_account_manager),
+        );
+        let api = SystemApiImpl::new(
+            get_update_api_type(),
+            system_state_accessor,
+            CANISTER_CURRENT_MEMORY_USAGE,
+            ExecutionParameters {
+                root_key: root_key.clone(),
+                ..execution_parameters()
+            },
+            no_op_logger(),
+        );
+
+        assert_eq!(api.ic0_root_key_size(), Ok(root_key.len() as i32));
+        let mut heap = vec![0; 8];
+        api.ic0_root_key_copy(1, 2, 3, &mut heap).unwrap();
+        assert_eq!(heap, vec![0, 3, 4, 5, 0, 0, 0, 0]);
+
+        assert!(api.ic0_root_key_copy(0, 3, 3, &mut heap).is_err());
+        assert!(api.ic0_root_key_copy(6, 0, 3, &mut heap).is_err());
+    }
+
     #[test]
     fn mint_all_cycles() {
         let cycles_account_manager = CyclesAccountManagerBuilder::new()
@@ -3813,6 +4317,67 @@ mod test {
         );
     }
 
+    #[test]
+    fn data_certificate_is_only_exposed_in_queries_that_carry_one() {
+        let certificate = vec![1, 2, 3];
+        let builder = || ApiTypeBuilder::new().with_data_certificate(certificate.clone());
+        let api_types = vec![
+            ("start", builder().build_start(), false),
+            ("init", builder().build_init(), false),
+            ("update", builder().build_update(), false),
+            ("replicated query", builder().build_replicated_query(), true),
+            ("pure query", builder().build_pure_query(), true),
+            ("stateful query", builder().build_stateful_query(), true),
+            ("reply callback", builder().build_reply_callback(), false),
+            ("reject callback", builder().build_reject_callback(), false),
+            ("cleanup", builder().build_cleanup(), false),
+            ("pre_upgrade", builder().build_pre_upgrade(), false),
+            ("inspect_message", builder().build_inspect_message(), false),
+            ("heartbeat", builder().build_heartbeat(), false),
+            (
+                "transform",
+                builder().build_transform(NumBytes::new(1024)),
+                false,
+            ),
+            // Queries without a certificate, e.g., replicated queries unless
+            // the legacy behavior is enabled.
+            (
+                "replicated query without certificate",
+                ApiTypeBuilder::new().build_replicated_query(),
+                false,
+            ),
+            (
+                "pure query without certificate",
+                ApiTypeBuilder::new().build_pure_query(),
+                false,
+            ),
+        ];
+
+        for (name, api_type, exposed) in api_types {
+            let api = get_system_api(
+                api_type,
+                SystemStateBuilder::default().build(),
+                CyclesAccountManagerBuilder::new().build(),
+            );
+            let mut heap = vec![0; certificate.len()];
+            if exposed {
+                assert_eq!(api.ic0_data_certificate_present(), Ok(1), "{}", name);
+                assert_eq!(api.ic0_data_certificate_size(), Ok(3), "{}", name);
+                assert_eq!(
+                    api.ic0_data_certificate_copy(0, 0, 3, &mut heap),
+                    Ok(()),
+                    "{}",
+                    name
+                );
+                assert_eq!(heap, certificate, "{}", name);
+            } else {
+                assert_eq!(api.ic0_data_certificate_present(), Ok(0), "{}", name);
+                assert_api_not_supported(api.ic0_data_certificate_size());
+                assert_api_not_supported(api.ic0_data_certificate_copy(0, 0, 3, &mut heap));
+            }
+        }
+    }
+
     #[test]
     fn mint_cycles_fails_caller_not_on_nns() {
         let system_state = SystemStateBuilder::default().build();
@@ -3883,4 +4448,120 @@ mod test {
         api.update_available_memory(0, 10).unwrap_err();
         assert_eq!(subnet_available_memory.get(), wasm_page_size_bytes);
     }
+
+    #[test]
+    fn update_available_memory_counts_failed_grows() {
+        let subnet_available_memory = SubnetAvailableMemory::new(NumBytes::from(64 << 10));
+        let system_state = SystemStateBuilder::default().build();
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let system_state_accessor =
+            SystemStateAccessorDirect::new(system_state, Arc::new(cycles_account_manager));
+        let mut api = SystemApiImpl::new(
+            get_update_api_type(),
+            system_state_accessor,
+            CANISTER_CURRENT_MEMORY_USAGE,
+            ExecutionParameters {
+                subnet_available_memory: subnet_available_memory.clone(),
+                ..execution_parameters()
+            },
+            no_op_logger(),
+        );
+
+        // A failed native grow reserves nothing.
+        assert_eq!(api.update_available_memory(-1, 1), Ok(-1));
+        assert_eq!(subnet_available_memory.get(), NumBytes::from(64 << 10));
+        assert_eq!(api.failed_memory_grows(), 1);
+
+        api.update_available_memory(0, 2).unwrap_err();
+        assert_eq!(api.failed_memory_grows(), 2);
+
+        api.update_available_memory(0, 1).unwrap();
+        assert_eq!(subnet_available_memory.get(), NumBytes::from(0));
+        assert_eq!(api.failed_memory_grows(), 2);
+    }
+
+    fn expected_u64_result(cycles: u128) -> HypervisorResult<u64> {
+        u64::try_from(cycles).map_err(|_| HypervisorError::Trapped(CyclesAmountTooBigFor64Bit))
+    }
+
+    proptest! {
+        #[test]
+        fn cycles_balance_is_not_truncated(balance in any::<u128>()) {
+            let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+            let system_state =
+                get_new_running_system_state(Cycles::from(balance), SubnetType::Application);
+            let api = get_system_api(get_update_api_type(), system_state, cycles_account_manager);
+
+            prop_assert_eq!(api.ic0_canister_cycles_balance128(), Ok(Cycles::from(balance)));
+            prop_assert_eq!(api.ic0_canister_cycle_balance(), expected_u64_result(balance));
+        }
+
+        #[test]
+        fn msg_cycles_available_is_not_truncated(available in any::<u128>()) {
+            let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+            let mut system_state =
+                get_new_running_system_state(INITIAL_CYCLES, SubnetType::Application);
+            system_state
+                .call_context_manager_mut()
+                .unwrap()
+                .new_call_context(
+                    CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+                    Cycles::from(available),
+                );
+            let api = get_system_api(get_update_api_type(), system_state, cycles_account_manager);
+
+            prop_assert_eq!(api.ic0_msg_cycles_available128(), Ok(Cycles::from(available)));
+            prop_assert_eq!(api.ic0_msg_cycles_available(), expected_u64_result(available));
+        }
+
+        #[test]
+        fn msg_cycles_refunded_is_not_truncated(refunded in any::<u128>()) {
+            let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+            let system_state = get_new_running_system_state(INITIAL_CYCLES, SubnetType::Application);
+            let api = get_system_api(
+                get_reply_api_type(Cycles::from(refunded)),
+                system_state,
+                cycles_account_manager,
+            );
+
+            prop_assert_eq!(api.ic0_msg_cycles_refunded128(), Ok(Cycles::from(refunded)));
+            prop_assert_eq!(api.ic0_msg_cycles_refunded(), expected_u64_result(refunded));
+        }
+
+        #[test]
+        fn msg_cycles_accept128_does_not_overflow(
+            balance in any::<u128>(),
+            available in any::<u128>(),
+            max_amount in any::<u128>(),
+        ) {
+            // Canisters on system subnets have no balance limit, so the
+            // accepted amount is only bounded by `available` and
+            // `max_amount`.
+            let cycles_account_manager = CyclesAccountManagerBuilder::new()
+                .with_subnet_type(SubnetType::System)
+                .build();
+            let mut system_state =
+                get_new_running_system_state(Cycles::from(balance), SubnetType::System);
+            system_state
+                .call_context_manager_mut()
+                .unwrap()
+                .new_call_context(
+                    CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+                    Cycles::from(available),
+                );
+            let mut api =
+                get_system_api(get_update_api_type(), system_state, cycles_account_manager);
+
+            let accepted = api.ic0_msg_cycles_accept128(Cycles::from(max_amount)).unwrap();
+            prop_assert_eq!(accepted, Cycles::from(available.min(max_amount)));
+            prop_assert_eq!(
+                api.ic0_msg_cycles_available128(),
+                Ok(Cycles::from(available - accepted.get()))
+            );
+            prop_assert_eq!(
+                api.ic0_canister_cycles_balance128(),
+                Ok(Cycles::from(balance.saturating_add(accepted.get())))
+            );
+        }
+    }
 }
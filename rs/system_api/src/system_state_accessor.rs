@@ -96,14 +96,20 @@ pub trait SystemStateAccessor {
     /// Unregister callback for call return.
     fn unregister_callback(&self, callback_id: CallbackId) -> Option<Callback>;
 
-    /// Pushes outgoing request.
+    /// Pushes outgoing request, provided that it and the reservation for its
+    /// response fit into `message_memory_capacity`.
     fn push_output_request(
         &self,
         canister_current_memory_usage: NumBytes,
         canister_compute_allocation: ComputeAllocation,
+        message_memory_capacity: NumBytes,
         msg: Request,
     ) -> Result<(), (StateError, Request)>;
 
+    /// Number of requests to `receiver` that can be pushed before the
+    /// queues to `receiver` are full.
+    fn available_output_request_slots(&self, receiver: CanisterId) -> usize;
+
     /// Current status of canister.
     fn canister_status(&self) -> CanisterStatus;
 }
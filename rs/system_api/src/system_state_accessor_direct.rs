@@ -334,6 +334,7 @@ impl SystemStateAccessor for SystemStateAccessorDirect {
         &self,
         canister_current_memory_usage: NumBytes,
         compute_allocation: ComputeAllocation,
+        message_memory_capacity: NumBytes,
         msg: Request,
     ) -> Result<(), (StateError, Request)> {
         if let Err(err) = self.cycles_account_manager.withdraw_request_cycles(
@@ -351,7 +352,15 @@ impl SystemStateAccessor for SystemStateAccessorDirect {
                 msg,
             ));
         }
-        self.system_state.borrow_mut().push_output_request(msg)
+        self.system_state
+            .borrow_mut()
+            .push_output_request_with_memory_limit(msg, message_memory_capacity)
+    }
+
+    fn available_output_request_slots(&self, receiver: CanisterId) -> usize {
+        self.system_state
+            .borrow()
+            .available_output_request_slots(&receiver)
     }
 
     fn canister_status(&self) -> CanisterStatus {
@@ -415,6 +424,7 @@ mod test {
             system_state_accessor.push_output_request(
                 NumBytes::from(0),
                 ComputeAllocation::default(),
+                NumBytes::new(u64::MAX),
                 request.clone()
             ),
             Err((
@@ -466,6 +476,7 @@ mod test {
             system_state_accessor.push_output_request(
                 NumBytes::from(0),
                 ComputeAllocation::default(),
+                NumBytes::new(u64::MAX),
                 request.clone()
             ),
             Err((
@@ -501,6 +512,7 @@ mod test {
             system_state_accessor.push_output_request(
                 NumBytes::from(0),
                 ComputeAllocation::default(),
+                NumBytes::new(u64::MAX),
                 RequestBuilder::default()
                     .sender(canister_test_id(0))
                     .build(),
@@ -509,6 +521,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn push_output_request_fails_out_of_message_memory() {
+        let cycles_account_manager = Arc::new(
+            CyclesAccountManagerBuilder::new()
+                .with_max_num_instructions(MAX_NUM_INSTRUCTIONS)
+                .build(),
+        );
+
+        let system_state = SystemState::new_running(
+            canister_test_id(0),
+            user_test_id(1).get(),
+            INITIAL_CYCLES,
+            NumSeconds::from(100_000),
+        );
+
+        let system_state_accessor =
+            SystemStateAccessorDirect::new(system_state, Arc::clone(&cycles_account_manager));
+
+        let request = RequestBuilder::default()
+            .sender(canister_test_id(0))
+            .build();
+        match system_state_accessor.push_output_request(
+            NumBytes::from(0),
+            ComputeAllocation::default(),
+            NumBytes::from(1024),
+            request.clone(),
+        ) {
+            Err((StateError::OutOfMemory { .. }, req)) => assert_eq!(req, request),
+            res => panic!("Expected OutOfMemory error, got {:?}", res),
+        }
+    }
+
     #[test]
     fn correct_charging_source_canister_for_a_request() {
         let subnet_type = SubnetType::Application;
@@ -548,7 +592,12 @@ mod test {
 
         // Enqueue the Request.
         system_state_accessor
-            .push_output_request(NumBytes::from(0), ComputeAllocation::default(), request)
+            .push_output_request(
+                NumBytes::from(0),
+                ComputeAllocation::default(),
+                NumBytes::new(u64::MAX),
+                request,
+            )
             .unwrap();
 
         // Assume the destination canister got the message and prepared a response
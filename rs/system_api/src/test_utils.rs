@@ -0,0 +1,303 @@
+//! Builders of `ApiType`s for tests.
+//!
+//! Most `ApiType` constructors take a long list of parameters, only a few of
+//! which matter to any given test. `ApiTypeBuilder` provides defaults for all
+//! of them, so tests only set what they care about:
+//!
+//! ```
+//! use ic_system_api::test_utils::ApiTypeBuilder;
+//!
+//! let api_type = ApiTypeBuilder::new()
+//!     .with_incoming_payload(vec![1, 2, 3])
+//!     .build_update();
+//! assert_eq!(api_type.as_str(), "update");
+//! ```
+use crate::{ApiType, NonReplicatedQueryKind};
+use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
+use ic_registry_subnet_type::SubnetType;
+use ic_types::{
+    messages::{CallContextId, RejectContext},
+    time::UNIX_EPOCH,
+    user_error::RejectCode,
+    CanisterId, Cycles, NumBytes, PrincipalId, SubnetId, Time,
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Builds `ApiType`s of any kind from a common set of parameters.
+///
+/// By default, the message is executed at `UNIX_EPOCH` on an application
+/// subnet, to which the routing table assigns all canisters, with an empty
+/// payload, no cycles and a test user as caller. Parameters that do not apply
+/// to the built kind of `ApiType` are ignored.
+#[derive(Clone)]
+pub struct ApiTypeBuilder {
+    time: Time,
+    incoming_payload: Vec<u8>,
+    incoming_cycles: Cycles,
+    caller: PrincipalId,
+    call_context_id: CallContextId,
+    own_subnet_id: SubnetId,
+    own_subnet_type: SubnetType,
+    routing_table: Option<Arc<RoutingTable>>,
+    subnet_records: Option<Arc<BTreeMap<SubnetId, SubnetType>>>,
+    data_certificate: Option<Vec<u8>>,
+    method_name: String,
+    reject_context: RejectContext,
+    replied: bool,
+}
+
+impl Default for ApiTypeBuilder {
+    fn default() -> Self {
+        Self {
+            time: UNIX_EPOCH,
+            incoming_payload: vec![],
+            incoming_cycles: Cycles::from(0),
+            caller: PrincipalId::new_user_test_id(0),
+            call_context_id: CallContextId::from(0),
+            own_subnet_id: SubnetId::from(PrincipalId::new_subnet_test_id(0)),
+            own_subnet_type: SubnetType::Application,
+            routing_table: None,
+            subnet_records: None,
+            data_certificate: None,
+            method_name: String::new(),
+            reject_context: RejectContext {
+                code: RejectCode::CanisterReject,
+                message: String::new(),
+            },
+            replied: false,
+        }
+    }
+}
+
+impl ApiTypeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_time(mut self, time: Time) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn with_incoming_payload(mut self, incoming_payload: Vec<u8>) -> Self {
+        self.incoming_payload = incoming_payload;
+        self
+    }
+
+    pub fn with_incoming_cycles(mut self, incoming_cycles: Cycles) -> Self {
+        self.incoming_cycles = incoming_cycles;
+        self
+    }
+
+    pub fn with_caller(mut self, caller: PrincipalId) -> Self {
+        self.caller = caller;
+        self
+    }
+
+    pub fn with_call_context_id(mut self, call_context_id: CallContextId) -> Self {
+        self.call_context_id = call_context_id;
+        self
+    }
+
+    pub fn with_own_subnet_id(mut self, own_subnet_id: SubnetId) -> Self {
+        self.own_subnet_id = own_subnet_id;
+        self
+    }
+
+    pub fn with_own_subnet_type(mut self, own_subnet_type: SubnetType) -> Self {
+        self.own_subnet_type = own_subnet_type;
+        self
+    }
+
+    /// Sets the routing table. By default, all canisters are assigned to the
+    /// own subnet.
+    pub fn with_routing_table(mut self, routing_table: Arc<RoutingTable>) -> Self {
+        self.routing_table = Some(routing_table);
+        self
+    }
+
+    /// Sets the subnet types of all subnets. By default, only the own subnet
+    /// is known.
+    pub fn with_subnet_records(
+        mut self,
+        subnet_records: Arc<BTreeMap<SubnetId, SubnetType>>,
+    ) -> Self {
+        self.subnet_records = Some(subnet_records);
+        self
+    }
+
+    pub fn with_data_certificate(mut self, data_certificate: Vec<u8>) -> Self {
+        self.data_certificate = Some(data_certificate);
+        self
+    }
+
+    /// Sets the name of the method inspected by `canister_inspect_message`.
+    pub fn with_method_name<S: Into<String>>(mut self, method_name: S) -> Self {
+        self.method_name = method_name.into();
+        self
+    }
+
+    /// Sets the reject received by a reject callback.
+    pub fn with_reject_context(mut self, reject_context: RejectContext) -> Self {
+        self.reject_context = reject_context;
+        self
+    }
+
+    /// Sets whether the call context of a callback was already replied.
+    pub fn with_replied(mut self, replied: bool) -> Self {
+        self.replied = replied;
+        self
+    }
+
+    fn routing_table(&self) -> Arc<RoutingTable> {
+        self.routing_table.clone().unwrap_or_else(|| {
+            let mut map = BTreeMap::new();
+            map.insert(
+                CanisterIdRange {
+                    start: CanisterId::from(0),
+                    end: CanisterId::from(u64::MAX),
+                },
+                self.own_subnet_id,
+            );
+            Arc::new(RoutingTable::new(map))
+        })
+    }
+
+    fn subnet_records(&self) -> Arc<BTreeMap<SubnetId, SubnetType>> {
+        self.subnet_records.clone().unwrap_or_else(|| {
+            let mut subnet_records = BTreeMap::new();
+            subnet_records.insert(self.own_subnet_id, self.own_subnet_type);
+            Arc::new(subnet_records)
+        })
+    }
+
+    pub fn build_start(self) -> ApiType {
+        ApiType::start()
+    }
+
+    pub fn build_init(self) -> ApiType {
+        ApiType::init(self.time, self.incoming_payload, self.caller)
+    }
+
+    pub fn build_update(self) -> ApiType {
+        let routing_table = self.routing_table();
+        let subnet_records = self.subnet_records();
+        ApiType::update(
+            self.time,
+            self.incoming_payload,
+            self.incoming_cycles,
+            self.caller,
+            self.call_context_id,
+            self.own_subnet_id,
+            self.own_subnet_type,
+            routing_table,
+            subnet_records,
+        )
+    }
+
+    pub fn build_replicated_query(self) -> ApiType {
+        ApiType::replicated_query(
+            self.time,
+            self.incoming_payload,
+            self.caller,
+            self.data_certificate,
+        )
+    }
+
+    /// Builds the execution of an HTTP outcall transform function whose reply
+    /// is limited to `max_reply_size`.
+    pub fn build_transform(self, max_reply_size: NumBytes) -> ApiType {
+        ApiType::transform(
+            self.time,
+            self.incoming_payload,
+            self.caller,
+            max_reply_size,
+        )
+    }
+
+    /// Builds a non-replicated query of the given kind. Stateful queries can
+    /// call other canisters, see `NonReplicatedQueryKind`.
+    pub fn build_non_replicated_query(self, query_kind: NonReplicatedQueryKind) -> ApiType {
+        let routing_table = self.routing_table();
+        ApiType::non_replicated_query(
+            self.time,
+            self.incoming_payload,
+            self.caller,
+            self.call_context_id,
+            self.own_subnet_id,
+            routing_table,
+            self.data_certificate,
+            query_kind,
+        )
+    }
+
+    /// Builds a non-replicated query that does not call other canisters.
+    pub fn build_pure_query(self) -> ApiType {
+        self.build_non_replicated_query(NonReplicatedQueryKind::Pure)
+    }
+
+    /// Builds a non-replicated query that can call other canisters.
+    pub fn build_stateful_query(self) -> ApiType {
+        self.build_non_replicated_query(NonReplicatedQueryKind::Stateful)
+    }
+
+    pub fn build_reply_callback(self) -> ApiType {
+        let routing_table = self.routing_table();
+        let subnet_records = self.subnet_records();
+        ApiType::reply_callback(
+            self.time,
+            self.incoming_payload,
+            self.incoming_cycles,
+            self.call_context_id,
+            self.replied,
+            self.own_subnet_id,
+            self.own_subnet_type,
+            routing_table,
+            subnet_records,
+        )
+    }
+
+    pub fn build_reject_callback(self) -> ApiType {
+        let routing_table = self.routing_table();
+        let subnet_records = self.subnet_records();
+        ApiType::reject_callback(
+            self.time,
+            self.reject_context,
+            self.incoming_cycles,
+            self.call_context_id,
+            self.replied,
+            self.own_subnet_id,
+            self.own_subnet_type,
+            routing_table,
+            subnet_records,
+        )
+    }
+
+    pub fn build_pre_upgrade(self) -> ApiType {
+        ApiType::pre_upgrade(self.time, self.caller)
+    }
+
+    pub fn build_inspect_message(self) -> ApiType {
+        ApiType::inspect_message(
+            self.caller,
+            self.method_name,
+            self.incoming_payload,
+            self.time,
+        )
+    }
+
+    pub fn build_heartbeat(self) -> ApiType {
+        ApiType::heartbeat(
+            self.time,
+            self.call_context_id,
+            self.own_subnet_id,
+            self.own_subnet_type,
+            self.routing_table(),
+            self.subnet_records(),
+        )
+    }
+
+    pub fn build_cleanup(self) -> ApiType {
+        ApiType::Cleanup { time: self.time }
+    }
+}
@@ -1,5 +1,5 @@
 pub mod basic_utilities;
-pub mod fake_tls_handshake;
+pub mod registry;
 
 pub use ic_crypto_test_utils::files as temp_dir;
 
@@ -9,10 +9,11 @@ use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::ni_dkg_groth20_bls12_
 use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::{
     ni_dkg_groth20_bls12_381, CspNiDkgDealing, CspNiDkgTranscript,
 };
+use ic_crypto_internal_types::sign::threshold_sig::public_key::bls12_381;
 use ic_interfaces::crypto::{
     BasicSigVerifier, BasicSigVerifierByPublicKey, BasicSigner, CanisterSigVerifier, DkgAlgorithm,
-    KeyManager, LoadTranscriptResult, NiDkgAlgorithm, ThresholdSigVerifier,
-    ThresholdSigVerifierByPublicKey, ThresholdSigner,
+    KeyManager, LoadTranscriptResult, NiDkgAlgorithm, ThresholdSigPublicKeyShares,
+    ThresholdSigVerifier, ThresholdSigVerifierByPublicKey, ThresholdSigner,
 };
 use ic_interfaces::crypto::{MultiSigVerifier, MultiSigner, Signable};
 use ic_interfaces::registry::RegistryClient;
@@ -31,6 +32,7 @@ use ic_types::crypto::threshold_sig::ni_dkg::errors::verify_dealing_error::DkgVe
 use ic_types::crypto::threshold_sig::ni_dkg::{
     config::NiDkgConfig, DkgId, NiDkgDealing, NiDkgId, NiDkgTag, NiDkgTranscript,
 };
+use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
 use ic_types::crypto::{
     BasicSig, BasicSigOf, CanisterSigOf, CombinedMultiSig, CombinedMultiSigOf,
     CombinedThresholdSig, CombinedThresholdSigOf, CryptoResult, IndividualMultiSig,
@@ -290,6 +292,18 @@ impl<T: Signable> ThresholdSigVerifier<T> for CryptoReturningOk {
     }
 }
 
+impl ThresholdSigPublicKeyShares for CryptoReturningOk {
+    fn threshold_sig_public_key_share(
+        &self,
+        _dkg_id: DkgId,
+        _signer: NodeId,
+    ) -> CryptoResult<ThresholdSigPublicKey> {
+        Ok(ThresholdSigPublicKey::from(bls12_381::PublicKeyBytes(
+            [0; bls12_381::PublicKeyBytes::SIZE],
+        )))
+    }
+}
+
 impl<T: Signable> ThresholdSigVerifierByPublicKey<T> for CryptoReturningOk {
     fn verify_combined_threshold_sig_by_public_key(
         &self,
@@ -0,0 +1,287 @@
+//! Fixtures that populate a fake registry with coherent node, TLS and subnet
+//! records.
+//!
+//! Tests that perform TLS handshakes or otherwise rely on the crypto component
+//! looking up node material in the registry should use
+//! [`CryptoRegistryBuilder`] instead of adding the protobufs by hand, so that
+//! node records, TLS certificates and subnet memberships are consistent with
+//! each other and with the secret keys held by the nodes' crypto components.
+use crate::registry::SubnetRecordBuilder;
+use ic_crypto::utils::TempCryptoComponent;
+use ic_crypto_tls_interfaces::TlsPublicKeyCert;
+use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
+use ic_protobuf::registry::node::v1::NodeRecord;
+use ic_protobuf::registry::subnet::v1::{SubnetListRecord, SubnetRecord};
+use ic_registry_client::fake::FakeRegistryClient;
+use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
+use ic_registry_keys::{
+    make_crypto_tls_cert_key, make_node_record_key, make_subnet_list_record_key,
+    make_subnet_record_key,
+};
+use ic_types::{NodeId, RegistryVersion, SubnetId};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// Builds a [`CryptoRegistry`], i.e., a fake registry together with the
+/// crypto components of the nodes registered in it.
+///
+/// Records can be added at multiple registry versions. The registry is updated
+/// to the latest version when the fixture is built.
+///
+/// # Example
+/// ```no_run
+/// use ic_test_utilities::crypto::registry::CryptoRegistryBuilder;
+/// use ic_test_utilities::types::ids::{node_test_id, subnet_test_id};
+///
+/// let registry = CryptoRegistryBuilder::new()
+///     .with_node(1, node_test_id(1))
+///     .with_node(1, node_test_id(2))
+///     .with_subnet(1, subnet_test_id(1), &[node_test_id(1), node_test_id(2)])
+///     .without_node(2, node_test_id(2))
+///     .build();
+/// ```
+pub struct CryptoRegistryBuilder {
+    data_provider: Arc<ProtoRegistryDataProvider>,
+    registry: Arc<FakeRegistryClient>,
+    crypto_components: BTreeMap<NodeId, (TempCryptoComponent, TlsPublicKeyCert)>,
+    subnets_by_version: BTreeMap<RegistryVersion, BTreeSet<SubnetId>>,
+}
+
+impl Default for CryptoRegistryBuilder {
+    fn default() -> Self {
+        let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+        let registry = Arc::new(FakeRegistryClient::new(Arc::clone(&data_provider) as Arc<_>));
+        Self {
+            data_provider,
+            registry,
+            crypto_components: BTreeMap::new(),
+            subnets_by_version: BTreeMap::new(),
+        }
+    }
+}
+
+impl CryptoRegistryBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers `node_id` at registry `version`, i.e., adds its node record
+    /// and its TLS certificate.
+    ///
+    /// The TLS key pair is generated by the CSP of a new crypto component for
+    /// the node the first time the node is added. Adding the node again at a
+    /// later version registers the same certificate.
+    pub fn with_node(mut self, version: u64, node_id: NodeId) -> Self {
+        let registry = Arc::clone(&self.registry);
+        let (_crypto, cert) = self.crypto_components.entry(node_id).or_insert_with(|| {
+            TempCryptoComponent::new_with_tls_key_generation(registry as Arc<_>, node_id)
+        });
+        let cert = cert.to_proto();
+        self.add_node_record(version, node_id, Some(NodeRecord::default()));
+        self.with_tls_cert(version, node_id, cert)
+    }
+
+    /// Adds `cert` as the TLS certificate of `node_id` at registry `version`,
+    /// without creating a crypto component for the node. This is useful for
+    /// nodes whose certificate is not generated by the CSP, e.g., malformed
+    /// certificates or certificates of a custom TLS client.
+    pub fn with_tls_cert(self, version: u64, node_id: NodeId, cert: X509PublicKeyCert) -> Self {
+        self.data_provider
+            .add(
+                &make_crypto_tls_cert_key(node_id),
+                RegistryVersion::from(version),
+                Some(cert),
+            )
+            .expect("failed to add TLS cert to registry");
+        self
+    }
+
+    /// Removes the node record and the TLS certificate of `node_id` at
+    /// registry `version`. The crypto component of the node is kept.
+    pub fn without_node(self, version: u64, node_id: NodeId) -> Self {
+        self.add_node_record(version, node_id, None);
+        self.data_provider
+            .add::<X509PublicKeyCert>(
+                &make_crypto_tls_cert_key(node_id),
+                RegistryVersion::from(version),
+                None,
+            )
+            .expect("failed to remove TLS cert from registry");
+        self
+    }
+
+    /// Adds a subnet record with the given `members` at registry `version`.
+    /// The subnet is part of the subnet list from `version` on.
+    pub fn with_subnet(self, version: u64, subnet_id: SubnetId, members: &[NodeId]) -> Self {
+        self.with_subnet_record(
+            version,
+            subnet_id,
+            SubnetRecordBuilder::from(members).build(),
+        )
+    }
+
+    /// Like [`Self::with_subnet`], but with a custom subnet `record`.
+    pub fn with_subnet_record(
+        mut self,
+        version: u64,
+        subnet_id: SubnetId,
+        record: SubnetRecord,
+    ) -> Self {
+        let version = RegistryVersion::from(version);
+        self.data_provider
+            .add(&make_subnet_record_key(subnet_id), version, Some(record))
+            .expect("failed to add subnet record to registry");
+        self.subnets_by_version
+            .entry(version)
+            .or_default()
+            .insert(subnet_id);
+        self
+    }
+
+    /// Writes the subnet lists and updates the registry to the latest
+    /// version.
+    pub fn build(self) -> CryptoRegistry {
+        let mut subnets = BTreeSet::new();
+        for (version, added_subnets) in self.subnets_by_version {
+            subnets.extend(added_subnets);
+            let subnet_list_record = SubnetListRecord {
+                subnets: subnets
+                    .iter()
+                    .map(|subnet_id| subnet_id.get().into_vec())
+                    .collect(),
+            };
+            self.data_provider
+                .add(
+                    &make_subnet_list_record_key(),
+                    version,
+                    Some(subnet_list_record),
+                )
+                .expect("failed to add subnet list to registry");
+        }
+        self.registry.update_to_latest_version();
+        CryptoRegistry {
+            data_provider: self.data_provider,
+            registry: self.registry,
+            crypto_components: self.crypto_components,
+        }
+    }
+
+    fn add_node_record(&self, version: u64, node_id: NodeId, record: Option<NodeRecord>) {
+        self.data_provider
+            .add(
+                &make_node_record_key(node_id),
+                RegistryVersion::from(version),
+                record,
+            )
+            .expect("failed to add node record to registry");
+    }
+}
+
+/// A fake registry populated by a [`CryptoRegistryBuilder`], together with
+/// the crypto components of the registered nodes.
+pub struct CryptoRegistry {
+    data_provider: Arc<ProtoRegistryDataProvider>,
+    registry: Arc<FakeRegistryClient>,
+    crypto_components: BTreeMap<NodeId, (TempCryptoComponent, TlsPublicKeyCert)>,
+}
+
+impl CryptoRegistry {
+    /// Returns the registry client, which is updated to the latest version.
+    pub fn get(&self) -> Arc<FakeRegistryClient> {
+        Arc::clone(&self.registry)
+    }
+
+    /// Returns the data provider backing the registry, for tests that add
+    /// further records. Call [`FakeRegistryClient::update_to_latest_version`]
+    /// afterwards.
+    pub fn data_provider(&self) -> Arc<ProtoRegistryDataProvider> {
+        Arc::clone(&self.data_provider)
+    }
+
+    /// Returns the crypto component of `node_id`.
+    ///
+    /// # Panics
+    /// * if the node was not added with [`CryptoRegistryBuilder::with_node`].
+    pub fn crypto(&self, node_id: NodeId) -> &TempCryptoComponent {
+        &self.node(node_id).0
+    }
+
+    /// Returns the TLS certificate of `node_id` generated by its crypto
+    /// component.
+    ///
+    /// # Panics
+    /// * if the node was not added with [`CryptoRegistryBuilder::with_node`].
+    pub fn tls_cert(&self, node_id: NodeId) -> &TlsPublicKeyCert {
+        &self.node(node_id).1
+    }
+
+    fn node(&self, node_id: NodeId) -> &(TempCryptoComponent, TlsPublicKeyCert) {
+        self.crypto_components
+            .get(&node_id)
+            .unwrap_or_else(|| panic!("no crypto component for node {}", node_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ids::{node_test_id, subnet_test_id};
+    use ic_registry_client::helper::crypto::CryptoRegistry as _;
+    use ic_registry_client::helper::subnet::{SubnetListRegistry, SubnetRegistry};
+
+    #[test]
+    fn should_register_nodes_with_certs_of_their_crypto_components() {
+        let registry = CryptoRegistryBuilder::new()
+            .with_node(1, node_test_id(1))
+            .with_node(1, node_test_id(2))
+            .build();
+
+        for node_id in &[node_test_id(1), node_test_id(2)] {
+            let cert = registry
+                .get()
+                .get_tls_certificate(*node_id, RegistryVersion::from(1))
+                .unwrap();
+            assert_eq!(cert, Some(registry.tls_cert(*node_id).to_proto()));
+        }
+    }
+
+    #[test]
+    fn should_track_membership_across_versions() {
+        let registry = CryptoRegistryBuilder::new()
+            .with_node(1, node_test_id(1))
+            .with_node(1, node_test_id(2))
+            .with_subnet(1, subnet_test_id(1), &[node_test_id(1), node_test_id(2)])
+            .with_subnet(2, subnet_test_id(1), &[node_test_id(1)])
+            .with_subnet(2, subnet_test_id(2), &[node_test_id(2)])
+            .without_node(3, node_test_id(2))
+            .build();
+        let client = registry.get();
+
+        assert_eq!(
+            client
+                .get_node_ids_on_subnet(subnet_test_id(1), RegistryVersion::from(1))
+                .unwrap(),
+            Some(vec![node_test_id(1), node_test_id(2)])
+        );
+        assert_eq!(
+            client
+                .get_node_ids_on_subnet(subnet_test_id(1), RegistryVersion::from(2))
+                .unwrap(),
+            Some(vec![node_test_id(1)])
+        );
+        assert_eq!(
+            client.get_subnet_ids(RegistryVersion::from(1)).unwrap(),
+            Some(vec![subnet_test_id(1)])
+        );
+        assert_eq!(
+            client.get_subnet_ids(RegistryVersion::from(2)).unwrap(),
+            Some(vec![subnet_test_id(1), subnet_test_id(2)])
+        );
+        assert_eq!(
+            client
+                .get_tls_certificate(node_test_id(2), RegistryVersion::from(3))
+                .unwrap(),
+            None
+        );
+    }
+}
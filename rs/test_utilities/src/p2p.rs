@@ -331,6 +331,7 @@ pub fn get_replica_transport_config(
             server_port: port,
             queue_size: 8,
         }],
+        multiplex_flows: false,
     }
 }
 
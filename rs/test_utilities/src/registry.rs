@@ -166,6 +166,8 @@ pub fn test_subnet_record() -> SubnetRecord {
         max_instructions_per_round: 7_000_000_000,
         max_instructions_per_install_code: 200_000_000_000,
         features: None,
+        instruction_cost_overrides: vec![],
+        reserved_canister_id_ranges: None,
     }
 }
 
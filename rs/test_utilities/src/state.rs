@@ -360,6 +360,7 @@ pub fn initial_execution_state(p: Option<std::path::PathBuf>) -> ExecutionState
         last_executed_round: ExecutionRound::from(0),
         cow_mem_mgr: Arc::new(cow_mem_mgr),
         mapped_state: None,
+        pending_compilation_trigger: None,
     }
 }
 
@@ -66,6 +66,7 @@ impl Default for InstallCodeContextBuilder {
                 memory_allocation: None,
                 mode: CanisterInstallMode::Install,
                 query_allocation: QueryAllocation::default(),
+                skip_stable_compat_check: false,
             },
         }
     }
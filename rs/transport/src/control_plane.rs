@@ -5,16 +5,24 @@
 //! to/from subnet peers. The component also manages re-establishment
 //! of severed connections.
 //!
+//! If `TransportConfig::multiplex_flows` is set, only the first configured
+//! flow with a peer has a connection of its own. All flows with the peer are
+//! multiplexed over that connection, and the other flows wait in
+//! `ConnectionState::WaitingForMux` until it is established.
+//!
 //! The control plane module implements control plane functionality for
 //! [`TransportImpl`](../types/struct.TransportImpl.html).
 
+use crate::multiplexer::{MuxConfig, MuxedConnection};
 use crate::types::{
     ClientState, Connecting, ConnectionRole, ConnectionState, FlowState, PeerState, QueueSize,
     ServerPort, ServerPortState, TransportImpl,
 };
 use crate::utils::{get_flow_ips, get_flow_label, SendQueueImpl};
 use futures::future::{AbortHandle, Abortable, Aborted};
-use ic_crypto_tls_interfaces::{AllowedClients, AuthenticatedPeer, TlsReadHalf, TlsWriteHalf};
+use ic_crypto_tls_interfaces::{
+    AllowedClientsProvider, AuthenticatedPeer, TlsReadHalf, TlsWriteHalf,
+};
 use ic_interfaces::transport::AsyncTransportEventHandler;
 use ic_logger::{error, info, warn, ReplicaLogger};
 use ic_protobuf::registry::node::v1::NodeRecord;
@@ -64,6 +72,7 @@ impl TransportImpl {
             self.allowed_clients.write().unwrap().insert(*peer_id);
         }
         *self.registry_version.write().unwrap() = registry_version;
+        self.revalidate_connections(client_state, registry_version);
         info!(
             self.log,
             "ControlPlane::start_peer_connections(): client_type = {:?}, node_id = {:?} peer_id = {:?}",
@@ -108,6 +117,7 @@ impl TransportImpl {
             }
         }
         client_state.peer_map.remove(&peer_id);
+        self.revalidate_connections(client_state, registry_version);
 
         info!(
             self.log,
@@ -118,6 +128,58 @@ impl TransportImpl {
         Ok(())
     }
 
+    /// Re-validates the peers of all connected flows of a transport client
+    /// against `registry_version`, and drops the connections of the peers
+    /// that are no longer trusted, e.g., because they were removed from the
+    /// registry or rotated their TLS certificate since the connection was
+    /// established. The dropped flows are re-established as for
+    /// `retry_connection`, which requires a new handshake at
+    /// `registry_version`.
+    ///
+    /// Peers that left the subnet are removed by `stop_peer_connections`,
+    /// which drops their connections as well.
+    fn revalidate_connections(
+        &self,
+        client_state: &mut ClientState,
+        registry_version: RegistryVersion,
+    ) {
+        let accept_ports = &client_state.accept_ports;
+        for (peer_id, peer_state) in client_state.peer_map.iter_mut() {
+            let peer = AuthenticatedPeer::Node(*peer_id);
+            for flow_state in peer_state.flow_map.values_mut() {
+                let handshake_registry_version = match &flow_state.connection_state {
+                    ConnectionState::Connected(connected) => connected.registry_version,
+                    _ => continue,
+                };
+                if handshake_registry_version >= registry_version {
+                    continue;
+                }
+                if let Err(e) =
+                    self.crypto
+                        .revalidate_peer(&peer, handshake_registry_version, registry_version)
+                {
+                    let flow_id = flow_state.flow_id;
+                    warn!(
+                        self.log,
+                        "ControlPlane::revalidate_connections(): dropping connection: \
+                         node_id = {:?}, flow = {:?}, handshake_registry_version = {}, \
+                         registry_version = {}, error = {:?}",
+                        self.node_id,
+                        flow_id,
+                        handshake_registry_version,
+                        registry_version,
+                        e
+                    );
+                    self.control_plane_metrics
+                        .revalidation_failed
+                        .with_label_values(&[&peer_id.to_string(), &flow_id.flow_tag.to_string()])
+                        .inc();
+                    let _ = self.reconnect_flow(&flow_id, flow_state, accept_ports);
+                }
+            }
+        }
+    }
+
     /// Starts all connections to a peer and initializes the corresponding data
     /// structures and tasks
     fn start_peer(
@@ -142,7 +204,14 @@ impl TransportImpl {
         for flow_config in &self.config.p2p_flows {
             let flow_tag = FlowTag::from(flow_config.flow_tag);
             queue_size_map.insert(flow_tag, QueueSize::from(flow_config.queue_size));
-            if role == ConnectionRole::Server {
+            let connection_state = if self.is_multiplexed(flow_tag) {
+                Some(ConnectionState::WaitingForMux)
+            } else if role == ConnectionRole::Server {
+                Some(ConnectionState::Listening)
+            } else {
+                None
+            };
+            if let Some(connection_state) = connection_state {
                 let peer_ip = flow_ips
                     .get(&flow_tag)
                     .map_or("Unknown Peer IP".to_string(), |x| x.to_string());
@@ -156,7 +225,7 @@ impl TransportImpl {
                     flow_id,
                     flow_config.flow_tag.to_string(),
                     flow_label.clone(),
-                    ConnectionState::Listening,
+                    connection_state,
                     Box::new(SendQueueImpl::new(
                         flow_label,
                         &flow_tag,
@@ -174,6 +243,11 @@ impl TransportImpl {
         }
 
         for flow_endpoint in &peer_record.p2p_flow_endpoints {
+            let flow_tag = FlowTag::from(flow_endpoint.flow_tag);
+            if peer_state.flow_map.contains_key(&flow_tag) {
+                // The flow is multiplexed and has no connection of its own.
+                continue;
+            }
             let endpoint = match &flow_endpoint.endpoint {
                 Some(x) => x,
                 None => {
@@ -186,7 +260,6 @@ impl TransportImpl {
                 }
             };
 
-            let flow_tag = FlowTag::from(flow_endpoint.flow_tag);
             let queue_size = match queue_size_map.get(&flow_tag) {
                 Some(queue_size) => queue_size,
                 None => {
@@ -444,6 +517,7 @@ impl TransportImpl {
         flow_tag: FlowTag,
         local_addr: SocketAddr,
         peer_addr: SocketAddr,
+        registry_version: RegistryVersion,
         tls_reader: TlsReadHalf,
         tls_writer: TlsWriteHalf,
     ) -> Result<(), TransportErrorCode> {
@@ -453,15 +527,32 @@ impl TransportImpl {
             peer_id,
             flow_tag,
         };
-        self.on_connect(
-            flow_id,
-            role,
-            peer_addr,
-            Box::new(tls_reader),
-            Box::new(tls_writer),
-        )
-        .await
-        .map_err(|e| {
+        let ret = match self.mux_flow_tag() {
+            None => {
+                self.on_connect(
+                    flow_id,
+                    role,
+                    peer_addr,
+                    registry_version,
+                    Box::new(tls_reader),
+                    Box::new(tls_writer),
+                )
+                .await
+            }
+            Some(mux_flow_tag) if mux_flow_tag == flow_tag => {
+                self.on_mux_connect(
+                    flow_id,
+                    role,
+                    peer_addr,
+                    registry_version,
+                    tls_reader,
+                    tls_writer,
+                )
+                .await
+            }
+            Some(_) => Err(TransportErrorCode::FlowNotFound),
+        };
+        ret.map_err(|e| {
             warn!(
                 every_n_seconds => 30,
                 self.log,
@@ -479,8 +570,75 @@ impl TransportImpl {
         })
     }
 
-    /// Retries to establish a connection
-    pub(crate) fn retry_connection(&self, flow_id: &FlowId) -> Result<(), TransportErrorCode> {
+    /// Multiplexes all flows with a peer over the connection established for
+    /// the flow `flow_id`, and passes the stream of each flow to the data
+    /// plane. The connection is closed once the data plane drops the streams
+    /// of all flows.
+    async fn on_mux_connect(
+        &self,
+        flow_id: FlowId,
+        role: ConnectionRole,
+        peer_addr: SocketAddr,
+        registry_version: RegistryVersion,
+        tls_reader: TlsReadHalf,
+        tls_writer: TlsWriteHalf,
+    ) -> Result<(), TransportErrorCode> {
+        let flow_tags: Vec<FlowTag> = self
+            .config
+            .p2p_flows
+            .iter()
+            .map(|flow_config| FlowTag::from(flow_config.flow_tag))
+            .collect();
+        let connection = Arc::new(MuxedConnection::new(
+            tls_reader,
+            tls_writer,
+            &flow_tags,
+            MuxConfig::default(),
+            &self.tokio_runtime,
+        ));
+        // The flow of the connection is the first flow, so that a duplicate
+        // connection is rejected before any multiplexed flow is set up.
+        for flow_tag in flow_tags {
+            let (reader, writer) = connection
+                .open_stream(flow_tag)
+                .map_err(|_| TransportErrorCode::FlowConnectionDown)?
+                .into_io(Arc::clone(&connection));
+            let mux_flow_id = FlowId {
+                flow_tag,
+                ..flow_id
+            };
+            let ret = self
+                .on_connect(
+                    mux_flow_id,
+                    role,
+                    peer_addr,
+                    registry_version,
+                    Box::new(reader),
+                    Box::new(writer),
+                )
+                .await;
+            if mux_flow_id == flow_id {
+                ret?;
+            } else if let Err(e) = ret {
+                warn!(
+                    self.log,
+                    "ControlPlane::on_mux_connect(): failed to add flow: \
+                     node_id = {:?}, flow = {:?}, error = {:?}",
+                    self.node_id,
+                    mux_flow_id,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Retries to establish a connection. Returns the flows that went down
+    /// with it, which are all flows with the peer if flows are multiplexed.
+    pub(crate) fn retry_connection(
+        &self,
+        flow_id: &FlowId,
+    ) -> Result<Vec<FlowId>, TransportErrorCode> {
         warn!(
             self.log,
             "ControlPlane::retry_connection(): node_id = {:?}, flow = {:?}", self.node_id, flow_id,
@@ -498,11 +656,41 @@ impl TransportImpl {
             .peer_map
             .get_mut(&flow_id.peer_id)
             .ok_or(TransportErrorCode::PeerNotFound)?;
-        let flow_state = peer_state
-            .flow_map
-            .get_mut(&flow_id.flow_tag)
-            .ok_or(TransportErrorCode::FlowNotFound)?;
+        if self.mux_flow_tag().is_none() {
+            let flow_state = peer_state
+                .flow_map
+                .get_mut(&flow_id.flow_tag)
+                .ok_or(TransportErrorCode::FlowNotFound)?;
+            self.reconnect_flow(flow_id, flow_state, &client_state.accept_ports)?;
+            return Ok(vec![*flow_id]);
+        }
 
+        let mut flow_ids = Vec::new();
+        for flow_state in peer_state.flow_map.values_mut() {
+            let flow_id = flow_state.flow_id;
+            if self
+                .reconnect_flow(&flow_id, flow_state, &client_state.accept_ports)
+                .is_ok()
+            {
+                flow_ids.push(flow_id);
+            }
+        }
+        if flow_ids.is_empty() {
+            return Err(TransportErrorCode::FlowConnectionDown);
+        }
+        Ok(flow_ids)
+    }
+
+    /// Tears down the connection of a connected flow, and then either waits
+    /// for the peer to reconnect (if we are the server) or reconnects to the
+    /// peer (if we are the client). A multiplexed flow waits for the
+    /// connection of the flow it is multiplexed over instead.
+    fn reconnect_flow(
+        &self,
+        flow_id: &FlowId,
+        flow_state: &mut FlowState,
+        accept_ports: &HashMap<FlowTag, ServerPortState>,
+    ) -> Result<(), TransportErrorCode> {
         let sa = match &flow_state.connection_state {
             ConnectionState::Connected(sa) => sa,
             _ => {
@@ -511,7 +699,16 @@ impl TransportImpl {
             }
         };
 
-        if Self::connection_role(&self.node_id, &flow_id.peer_id) == ConnectionRole::Server {
+        if self.is_multiplexed(flow_id.flow_tag) {
+            flow_state.update(ConnectionState::WaitingForMux);
+            warn!(
+                self.log,
+                "ControlPlane::process_disconnect(): node_id = {:?}, flow = {:?}, \
+                    waiting for the multiplexed connection",
+                self.node_id,
+                flow_id,
+            );
+        } else if Self::connection_role(&self.node_id, &flow_id.peer_id) == ConnectionRole::Server {
             // We are the server, wait for the peer to connect
             flow_state.update(ConnectionState::Listening);
             warn!(
@@ -523,7 +720,7 @@ impl TransportImpl {
             );
         } else {
             // reconnect if we have a listener
-            if client_state.accept_ports.contains_key(&flow_id.flow_tag) {
+            if accept_ports.contains_key(&flow_id.flow_tag) {
                 let socket_addr = sa.peer_addr;
                 let connecting_task = self.spawn_connect_task(
                     flow_id.client_type,
@@ -610,27 +807,10 @@ impl TransportImpl {
     ) -> Result<(), TransportErrorCode> {
         let local_addr = Self::sock_addr(stream.local_addr())?;
         let peer_addr = Self::sock_addr(stream.peer_addr())?;
-        let allowed_clients = {
-            let allowed_clients = self.allowed_clients.read().unwrap().clone();
-            match AllowedClients::new_with_nodes(allowed_clients) {
-                Err(e) => {
-                    self.control_plane_metrics
-                        .tcp_server_handshake_failed
-                        .with_label_values(&[&flow_tag.to_string()])
-                        .inc();
-                    warn!(
-                        every_n_seconds => 30,
-                        self.log,
-                        "ControlPlane::tls_server_handshake() no allowed clients: failed local_addr = {:?} node_id = {:?}, peer_addr = {:?}, error = {:?}",
-                     local_addr,
-                     self.node_id,
-                     peer_addr,
-                     e);
-                    return Err(TransportErrorCode::PeerTlsInfoNotFound);
-                }
-                Ok(allowed_clients) => allowed_clients,
-            }
-        };
+        // The allowed clients are resolved by the handshake itself, so that
+        // peers added or removed while the handshake is queued are taken
+        // into account.
+        let allowed_clients: Arc<dyn AllowedClientsProvider> = self.allowed_clients.clone();
         let registry_version = *self.registry_version.read().unwrap();
         let ret = tokio::time::timeout(
             Duration::from_secs(TLS_HANDSHAKE_TIMEOUT_SECONDS),
@@ -697,6 +877,7 @@ impl TransportImpl {
             flow_tag,
             local_addr,
             peer_addr,
+            registry_version,
             tls_reader,
             tls_writer,
         )
@@ -741,8 +922,10 @@ impl TransportImpl {
             return Err(TransportErrorCode::TimeoutExpired);
         }
 
-        let (tls_reader, tls_writer) = ret.unwrap().map(|tls_stream| tls_stream.split()).map_err(
-            |e| {
+        let (tls_reader, tls_writer) = ret
+            .unwrap()
+            .map(|(tls_stream, _server_cert)| tls_stream.split())
+            .map_err(|e| {
                 self.control_plane_metrics
                     .tcp_client_handshake_failed
                     .with_label_values(&[&flow_tag.to_string()])
@@ -759,8 +942,7 @@ impl TransportImpl {
                     e
                 );
                 TransportErrorCode::PeerTlsInfoNotFound
-            },
-        )?;
+            })?;
 
         self.process_handshake_result(
             peer_id,
@@ -769,6 +951,7 @@ impl TransportImpl {
             flow_tag,
             local_addr,
             peer_addr,
+            registry_version,
             tls_reader,
             tls_writer,
         )
@@ -882,6 +1065,25 @@ impl TransportImpl {
         }
     }
 
+    /// Returns the flow whose connection carries all flows with a peer, if
+    /// flows are multiplexed
+    fn mux_flow_tag(&self) -> Option<FlowTag> {
+        if !self.config.multiplex_flows {
+            return None;
+        }
+        self.config
+            .p2p_flows
+            .first()
+            .map(|flow_config| FlowTag::from(flow_config.flow_tag))
+    }
+
+    /// Returns true if the flow is multiplexed over the connection of another
+    /// flow
+    fn is_multiplexed(&self, flow_tag: FlowTag) -> bool {
+        self.mux_flow_tag()
+            .map_or(false, |mux_flow_tag| mux_flow_tag != flow_tag)
+    }
+
     /// Parses the `connect()` result and returns the status
     fn connect_status(connect_result: std::io::Result<TcpStream>) -> ConnectStatus {
         if let Ok(stream) = connect_result {
@@ -913,9 +1115,15 @@ impl TransportImpl {
             return Err(TransportErrorCode::TransportClientAlreadyRegistered);
         }
 
-        // Bind to the server ports.
+        // Bind to the server ports. Multiplexed flows have no connections of
+        // their own.
         let mut listeners = Vec::new();
-        for flow_config in &self.config.p2p_flows {
+        for flow_config in self
+            .config
+            .p2p_flows
+            .iter()
+            .filter(|flow_config| !self.is_multiplexed(FlowTag::from(flow_config.flow_tag)))
+        {
             let server_addr = SocketAddr::new(self.node_ip, flow_config.server_port);
             listeners.push((
                 flow_config.flow_tag,
@@ -1044,6 +1252,7 @@ mod tests {
             let mut client_config_1 = TransportConfig {
                 node_ip: "0.0.0.0".to_string(),
                 p2p_flows: Vec::new(),
+                multiplex_flows: false,
             };
             let flow_internal_1 = TransportFlowConfig {
                 flow_tag: FLOW_TAG_1,
@@ -1064,6 +1273,7 @@ mod tests {
             let mut client_config_2 = TransportConfig {
                 node_ip: "0.0.0.0".to_string(),
                 p2p_flows: Vec::new(),
+                multiplex_flows: false,
             };
             let flow_internal_2 = TransportFlowConfig {
                 flow_tag: FLOW_TAG_2,
@@ -19,15 +19,16 @@
 
 use crate::metrics::DataPlaneMetrics;
 use crate::types::{
-    Connected, ConnectionRole, ConnectionState, SendQueueReader, TransportHeader, TransportImpl,
-    TRANSPORT_FLAGS_IS_HEARTBEAT, TRANSPORT_FLAGS_SENDER_ERROR, TRANSPORT_HEADER_SIZE,
+    Connected, ConnectionRole, ConnectionState, FlowReader, FlowWriter, SendQueueReader,
+    TransportHeader, TransportImpl, TRANSPORT_FLAGS_IS_HEARTBEAT, TRANSPORT_FLAGS_SENDER_ERROR,
+    TRANSPORT_HEADER_SIZE,
 };
-use ic_crypto_tls_interfaces::{TlsReadHalf, TlsWriteHalf};
 use ic_interfaces::transport::AsyncTransportEventHandler;
 use ic_logger::warn;
 use ic_types::transport::{
     FlowId, TransportErrorCode, TransportFlowInfo, TransportPayload, TransportStateChange,
 };
+use ic_types::RegistryVersion;
 
 use futures::future::{AbortHandle, Abortable, Aborted};
 use std::convert::TryInto;
@@ -127,7 +128,7 @@ impl TransportImpl {
         flow_id: FlowId,
         flow_label: String,
         mut send_queue_reader: Box<dyn SendQueueReader + Send + Sync>,
-        mut writer: Box<TlsWriteHalf>,
+        mut writer: FlowWriter,
         metrics: DataPlaneMetrics,
         state: Weak<TransportImpl>,
     ) {
@@ -209,7 +210,7 @@ impl TransportImpl {
         flow_id: FlowId,
         flow_label: String,
         event_handler: Arc<dyn AsyncTransportEventHandler>,
-        mut reader: Box<TlsReadHalf>,
+        mut reader: FlowReader,
         metrics: DataPlaneMetrics,
         state: Weak<TransportImpl>,
     ) {
@@ -289,7 +290,7 @@ impl TransportImpl {
     /// socket. The timeout is for each socket read (header, payload chunks)
     /// and not the full message.
     async fn read_one_message(
-        reader: &mut Box<TlsReadHalf>,
+        reader: &mut FlowReader,
         timeout: Duration,
     ) -> Result<(TransportHeader, Option<TransportPayload>), ReadError> {
         // Read the hdr
@@ -325,7 +326,7 @@ impl TransportImpl {
 
     /// Reads the requested bytes from the socket with a timeout
     async fn read_from_socket(
-        reader: &mut Box<TlsReadHalf>,
+        reader: &mut FlowReader,
         buf: &mut [u8],
         timeout: Duration,
     ) -> Result<(), ReadError> {
@@ -343,13 +344,18 @@ impl TransportImpl {
 
     /// Handle peer disconnect.
     async fn on_disconnect(&self, flow_id: FlowId) {
-        if let Err(e) = self.retry_connection(&flow_id) {
-            warn!(
-                self.log,
-                "DataPlane::on_disconnect(): retry_connection error {:?}: flow: {:?}", flow_id, e
-            );
-            return;
-        }
+        let flow_ids = match self.retry_connection(&flow_id) {
+            Ok(flow_ids) => flow_ids,
+            Err(e) => {
+                warn!(
+                    self.log,
+                    "DataPlane::on_disconnect(): retry_connection error {:?}: flow: {:?}",
+                    flow_id,
+                    e
+                );
+                return;
+            }
+        };
         let event_handler = {
             let mut cl_map = self.client_map.write().unwrap();
             let client_state = match cl_map.get_mut(&flow_id.client_type) {
@@ -358,12 +364,14 @@ impl TransportImpl {
             };
             client_state.event_handler.clone()
         };
-        event_handler
-            .state_changed(TransportStateChange::PeerFlowDown(TransportFlowInfo {
-                peer_id: flow_id.peer_id,
-                flow_tag: flow_id.flow_tag,
-            }))
-            .await;
+        for flow_id in flow_ids {
+            event_handler
+                .state_changed(TransportStateChange::PeerFlowDown(TransportFlowInfo {
+                    peer_id: flow_id.peer_id,
+                    flow_tag: flow_id.flow_tag,
+                }))
+                .await;
+        }
     }
 
     /// Handle connection setup. Starts flow read and write tasks.
@@ -372,8 +380,9 @@ impl TransportImpl {
         flow_id: FlowId,
         role: ConnectionRole,
         peer_addr: SocketAddr,
-        reader: Box<TlsReadHalf>,
-        writer: Box<TlsWriteHalf>,
+        registry_version: RegistryVersion,
+        reader: FlowReader,
+        writer: FlowWriter,
     ) -> Result<Arc<dyn AsyncTransportEventHandler>, TransportErrorCode> {
         let mut client_map = self.client_map.write().unwrap();
         let client_state = match client_map.get_mut(&flow_id.client_type) {
@@ -461,6 +470,7 @@ impl TransportImpl {
             read_task: read_abort_handle,
             write_task: write_abort_handle,
             role,
+            registry_version,
         };
         flow_state.update(ConnectionState::Connected(connected_state));
         Ok(event_handler)
@@ -472,10 +482,11 @@ impl TransportImpl {
         flow_id: FlowId,
         role: ConnectionRole,
         peer_addr: SocketAddr,
-        reader: Box<TlsReadHalf>,
-        writer: Box<TlsWriteHalf>,
+        registry_version: RegistryVersion,
+        reader: FlowReader,
+        writer: FlowWriter,
     ) -> Result<(), TransportErrorCode> {
-        self.on_connect_setup(flow_id, role, peer_addr, reader, writer)?
+        self.on_connect_setup(flow_id, role, peer_addr, registry_version, reader, writer)?
             // Notify the client that peer flow is up.
             .state_changed(TransportStateChange::PeerFlowUp(TransportFlowInfo {
                 peer_id: flow_id.peer_id,
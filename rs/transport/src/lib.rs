@@ -31,10 +31,22 @@
 //! messages (artifact chunks), for ingress manager, consensus (incl DKG and
 //! certification) and state sync. Thus, Transport has to handle 3 x 3 flows per
 //! peer for Gossip.
+//!
+//! Instead of one connection per flow, the flows to a peer share a single TLS
+//! connection if `TransportConfig::multiplex_flows` is set. The connection is
+//! established on the server port of the first flow, and the
+//! [`multiplexer`](multiplexer/index.html) provides independent flow control
+//! per flow.
+//!
+//! The set of peers follows the registry: the
+//! [`peer_discovery`](peer_discovery/index.html) task turns changes of the
+//! subnet's node records into peer set updates for transport.
 
 mod control_plane;
 mod data_plane;
 mod metrics;
+pub mod multiplexer;
+pub mod peer_discovery;
 pub mod transport;
 mod types;
 mod utils;
@@ -17,6 +17,7 @@ pub(crate) struct ControlPlaneMetrics {
     pub(crate) tcp_client_handshake_failed: IntCounterVec,
     pub(crate) tcp_client_handshake_success: IntCounterVec,
     pub(crate) retry_connection: IntCounterVec,
+    pub(crate) revalidation_failed: IntCounterVec,
 }
 
 impl ControlPlaneMetrics {
@@ -82,6 +83,11 @@ impl ControlPlaneMetrics {
                 "Connection retries to reconnect to a peer from Transport",
                 &["peer_id", "flow_tag"],
             ),
+            revalidation_failed: metrics_registry.int_counter_vec(
+                "transport_revalidation_failed",
+                "Connections dropped because the peer failed re-validation at a newer registry version",
+                &["peer_id", "flow_tag"],
+            ),
         }
     }
 }
@@ -253,3 +259,35 @@ impl SendQueueMetrics {
         }
     }
 }
+
+#[derive(Clone)]
+pub(crate) struct PeerDiscoveryMetrics {
+    pub(crate) updates: IntCounterVec,
+    pub(crate) registry_errors: IntCounter,
+    pub(crate) registry_version: IntGauge,
+    pub(crate) peers: IntGauge,
+}
+
+impl PeerDiscoveryMetrics {
+    pub(crate) fn new(metrics_registry: &MetricsRegistry) -> Self {
+        Self {
+            updates: metrics_registry.int_counter_vec(
+                "transport_peer_discovery_updates_total",
+                "Peer set updates derived from the registry, by kind",
+                &["kind"],
+            ),
+            registry_errors: metrics_registry.int_counter(
+                "transport_peer_discovery_registry_errors_total",
+                "Failures to read the node records of the subnet from the registry",
+            ),
+            registry_version: metrics_registry.int_gauge(
+                "transport_peer_discovery_registry_version",
+                "Registry version of the current peer set",
+            ),
+            peers: metrics_registry.int_gauge(
+                "transport_peer_discovery_peers",
+                "Number of peers in the current peer set",
+            ),
+        }
+    }
+}
@@ -0,0 +1,768 @@
+//! Multiplexing of flows over a single connection
+//!
+//! The multiplexer allows several flows to a peer, e.g. the gossip flows of
+//! consensus, state sync and ingress relay, to share a single authenticated
+//! connection (usually a `TlsStream`) instead of one connection per flow.
+//! Each flow is carried by a *stream* identified by its `FlowTag`. Both ends
+//! of the connection declare the same set of flows when the connection is
+//! created, so no stream negotiation is needed: a frame for any other flow is
+//! a protocol violation and closes the connection, which bounds the number of
+//! streams a peer can make this end keep state for.
+//!
+//! Messages are split into frames of at most `MuxConfig::max_frame_size`
+//! bytes. The frames of one message are sent back to back on their stream,
+//! and frames of different streams are interleaved on the connection. Every
+//! frame starts with a fixed size header:
+//!
+//!   * version (u8, currently 0)
+//!   * frame type (u8): data or window update
+//!   * flags (u8): whether a data frame ends a message
+//!   * reserved (u8, currently 0)
+//!   * flow tag (u32, little endian)
+//!   * length (u32, little endian): of the data following a data frame, or
+//!     the window increment of a window update frame
+//!
+//! Streams have independent, credit based flow control: a sender may only
+//! have `MuxConfig::initial_window_size` bytes in flight that the receiving
+//! client has not consumed yet. As the client consumes the frames of a
+//! message, the receiver returns their credit to the sender with window
+//! update frames, so messages may be larger than the window. A slow client
+//! thus only stalls its own stream, not the whole connection.
+
+use ic_types::transport::FlowTag;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf,
+};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::task::JoinHandle;
+
+/// The size (in bytes) of the frame header
+pub const MUX_FRAME_HEADER_SIZE: usize = 12;
+
+const MUX_VERSION: u8 = 0;
+const FRAME_TYPE_DATA: u8 = 0;
+const FRAME_TYPE_WINDOW_UPDATE: u8 = 1;
+/// Flag: the data frame is the last frame of a message
+const FRAME_FLAGS_END_OF_MESSAGE: u8 = 1;
+
+/// The buffer size of the byte streams returned by `MuxedStream::into_io`
+const BYTE_STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Configuration of a multiplexed connection. Both ends of a connection must
+/// use the same configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MuxConfig {
+    /// The number of bytes a sender may have in flight per stream.
+    pub initial_window_size: u32,
+    /// The maximum number of data bytes in a frame. Must not exceed
+    /// `initial_window_size`.
+    pub max_frame_size: u32,
+}
+
+impl Default for MuxConfig {
+    fn default() -> Self {
+        Self {
+            initial_window_size: 256 * 1024,
+            max_frame_size: 16 * 1024,
+        }
+    }
+}
+
+/// Errors of a multiplexed connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MuxError {
+    /// The flow was not declared when the connection was created.
+    UnknownFlow(FlowTag),
+    /// The stream for the flow tag was already opened on this end.
+    StreamAlreadyOpen(FlowTag),
+    /// The connection was closed, either locally, by the peer, or because of
+    /// an IO error or a protocol violation.
+    ConnectionClosed,
+}
+
+/// A frame header, serialized manually to maintain a fixed size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FrameHeader {
+    frame_type: u8,
+    flags: u8,
+    flow_tag: u32,
+    length: u32,
+}
+
+impl FrameHeader {
+    fn encode(&self) -> [u8; MUX_FRAME_HEADER_SIZE] {
+        let mut bytes = [0; MUX_FRAME_HEADER_SIZE];
+        bytes[0] = MUX_VERSION;
+        bytes[1] = self.frame_type;
+        bytes[2] = self.flags;
+        bytes[4..8].copy_from_slice(&self.flow_tag.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.length.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a header, or returns `None` if it has an unknown version or
+    /// frame type.
+    fn decode(bytes: &[u8; MUX_FRAME_HEADER_SIZE]) -> Option<Self> {
+        if bytes[0] != MUX_VERSION
+            || (bytes[1] != FRAME_TYPE_DATA && bytes[1] != FRAME_TYPE_WINDOW_UPDATE)
+        {
+            return None;
+        }
+        Some(Self {
+            frame_type: bytes[1],
+            flags: bytes[2],
+            flow_tag: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            length: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        })
+    }
+}
+
+fn encode_frame(header: FrameHeader, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MUX_FRAME_HEADER_SIZE + data.len());
+    frame.extend_from_slice(&header.encode());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// The data of a received frame
+struct DataFrame {
+    data: Vec<u8>,
+    end_of_message: bool,
+}
+
+/// A message to send, together with the channel to report the result on.
+type OutgoingMessage = (Vec<u8>, oneshot::Sender<Result<(), MuxError>>);
+
+/// The state of a stream, created for each declared flow when the connection
+/// is created.
+struct StreamState {
+    /// Delivers received frames to the `MuxedStream`. `None` once the
+    /// connection is closed.
+    incoming: Option<mpsc::UnboundedSender<DataFrame>>,
+    /// The receiving end of `incoming` until the stream is opened locally.
+    pending_receiver: Option<mpsc::UnboundedReceiver<DataFrame>>,
+    /// The number of bytes the peer may still send before it has to wait for
+    /// a window update.
+    receive_window: u32,
+    /// The number of bytes that may be sent before waiting for a window
+    /// update from the peer.
+    send_window: Arc<Semaphore>,
+}
+
+impl StreamState {
+    fn new(config: &MuxConfig) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            incoming: Some(sender),
+            pending_receiver: Some(receiver),
+            receive_window: config.initial_window_size,
+            send_window: Arc::new(Semaphore::new(config.initial_window_size as usize)),
+        }
+    }
+}
+
+/// State shared by the connection, its streams, and its IO tasks.
+struct Shared {
+    config: MuxConfig,
+    streams: Mutex<HashMap<FlowTag, StreamState>>,
+    closed: Mutex<bool>,
+}
+
+impl Shared {
+    /// Closes all streams: pending and future receives return `None`, and
+    /// sends fail.
+    fn close(&self) {
+        *self.closed.lock().unwrap() = true;
+        for stream in self.streams.lock().unwrap().values_mut() {
+            stream.incoming = None;
+            stream.send_window.close();
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        *self.closed.lock().unwrap()
+    }
+
+    /// Handles a received frame. Returns `false` if the peer violated the
+    /// protocol.
+    fn on_frame(&self, header: FrameHeader, data: Vec<u8>) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = match streams.get_mut(&FlowTag::from(header.flow_tag)) {
+            Some(stream) => stream,
+            None => return false,
+        };
+        match header.frame_type {
+            FRAME_TYPE_WINDOW_UPDATE => {
+                // The peer cannot return more credit than is in flight.
+                let available = stream.send_window.available_permits() as u64;
+                if available + header.length as u64 > self.config.initial_window_size as u64 {
+                    return false;
+                }
+                stream.send_window.add_permits(header.length as usize);
+                true
+            }
+            _ => {
+                if header.length > stream.receive_window {
+                    return false;
+                }
+                stream.receive_window -= header.length;
+                if let Some(incoming) = &stream.incoming {
+                    let _ = incoming.send(DataFrame {
+                        data,
+                        end_of_message: header.flags & FRAME_FLAGS_END_OF_MESSAGE != 0,
+                    });
+                }
+                true
+            }
+        }
+    }
+}
+
+/// A connection that multiplexes the streams of several flows.
+///
+/// The connection is closed when it is dropped, or when the underlying IO
+/// fails.
+pub struct MuxedConnection {
+    shared: Arc<Shared>,
+    frames: mpsc::UnboundedSender<Vec<u8>>,
+    runtime: Handle,
+    read_task: JoinHandle<()>,
+    write_task: JoinHandle<()>,
+}
+
+impl MuxedConnection {
+    /// Starts multiplexing the streams of `flow_tags` over the connection
+    /// with the given read and write halves, spawning the IO tasks on
+    /// `runtime`.
+    ///
+    /// # Panics
+    /// If `config.max_frame_size` is zero or exceeds
+    /// `config.initial_window_size`.
+    pub fn new<R, W>(
+        mut reader: R,
+        mut writer: W,
+        flow_tags: &[FlowTag],
+        config: MuxConfig,
+        runtime: &Handle,
+    ) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        assert!(
+            config.max_frame_size > 0 && config.max_frame_size <= config.initial_window_size,
+            "invalid multiplexer config {:?}",
+            config
+        );
+        let streams = flow_tags
+            .iter()
+            .map(|flow_tag| (*flow_tag, StreamState::new(&config)))
+            .collect();
+        let shared = Arc::new(Shared {
+            config,
+            streams: Mutex::new(streams),
+            closed: Mutex::new(false),
+        });
+        let (frames, mut frames_receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+
+        let shared_cl = Arc::clone(&shared);
+        let write_task = runtime.spawn(async move {
+            while let Some(frame) = frames_receiver.recv().await {
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+            shared_cl.close();
+        });
+
+        let shared_cl = Arc::clone(&shared);
+        let read_task = runtime.spawn(async move {
+            loop {
+                let mut header_bytes = [0; MUX_FRAME_HEADER_SIZE];
+                if reader.read_exact(&mut header_bytes).await.is_err() {
+                    break;
+                }
+                let header = match FrameHeader::decode(&header_bytes) {
+                    Some(header) => header,
+                    None => break,
+                };
+                let mut data = Vec::new();
+                if header.frame_type == FRAME_TYPE_DATA {
+                    if header.length > shared_cl.config.max_frame_size {
+                        break;
+                    }
+                    data.resize(header.length as usize, 0);
+                    if reader.read_exact(&mut data).await.is_err() {
+                        break;
+                    }
+                }
+                if !shared_cl.on_frame(header, data) {
+                    break;
+                }
+            }
+            shared_cl.close();
+        });
+
+        Self {
+            shared,
+            frames,
+            runtime: runtime.clone(),
+            read_task,
+            write_task,
+        }
+    }
+
+    /// Opens the stream of the given flow. The peer must open the stream with
+    /// the same flow tag to exchange messages on it.
+    pub fn open_stream(&self, flow_tag: FlowTag) -> Result<MuxedStream, MuxError> {
+        if self.shared.is_closed() {
+            return Err(MuxError::ConnectionClosed);
+        }
+        let mut streams = self.shared.streams.lock().unwrap();
+        let stream = streams
+            .get_mut(&flow_tag)
+            .ok_or(MuxError::UnknownFlow(flow_tag))?;
+        let incoming = stream
+            .pending_receiver
+            .take()
+            .ok_or(MuxError::StreamAlreadyOpen(flow_tag))?;
+
+        // Messages are framed one after the other by a task per stream, so
+        // that the frames of concurrently sent messages do not interleave,
+        // and a message is sent completely even if its sender stops waiting.
+        let (outgoing, mut outgoing_receiver) = mpsc::unbounded_channel::<OutgoingMessage>();
+        let send_window = Arc::clone(&stream.send_window);
+        let frames = self.frames.clone();
+        let max_frame_size = self.shared.config.max_frame_size as usize;
+        self.runtime.spawn(async move {
+            while let Some((message, result_sender)) = outgoing_receiver.recv().await {
+                let result =
+                    send_frames(flow_tag, &message, max_frame_size, &send_window, &frames).await;
+                let _ = result_sender.send(result);
+            }
+        });
+
+        Ok(MuxedStream {
+            sender: StreamSender { outgoing },
+            receiver: StreamReceiver {
+                flow_tag,
+                shared: Arc::clone(&self.shared),
+                incoming,
+                frames: self.frames.clone(),
+                partial_message: Vec::new(),
+            },
+        })
+    }
+
+    /// Returns `true` if the connection was closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.is_closed()
+    }
+}
+
+impl Drop for MuxedConnection {
+    fn drop(&mut self) {
+        self.read_task.abort();
+        self.write_task.abort();
+        self.shared.close();
+    }
+}
+
+/// Splits `message` into data frames of the stream of `flow_tag`, waiting for
+/// window updates from the peer if the message does not fit into the send
+/// window.
+async fn send_frames(
+    flow_tag: FlowTag,
+    message: &[u8],
+    max_frame_size: usize,
+    send_window: &Semaphore,
+    frames: &mpsc::UnboundedSender<Vec<u8>>,
+) -> Result<(), MuxError> {
+    let num_frames = std::cmp::max(1, (message.len() + max_frame_size - 1) / max_frame_size);
+    for (i, start) in (0..num_frames).map(|i| (i, i * max_frame_size)) {
+        let data = &message[start..std::cmp::min(start + max_frame_size, message.len())];
+        if !data.is_empty() {
+            send_window
+                .acquire_many(data.len() as u32)
+                .await
+                .map_err(|_| MuxError::ConnectionClosed)?
+                .forget();
+        }
+        let header = FrameHeader {
+            frame_type: FRAME_TYPE_DATA,
+            flags: if i + 1 == num_frames {
+                FRAME_FLAGS_END_OF_MESSAGE
+            } else {
+                0
+            },
+            flow_tag: flow_tag.get(),
+            length: data.len() as u32,
+        };
+        frames
+            .send(encode_frame(header, data))
+            .map_err(|_| MuxError::ConnectionClosed)?;
+    }
+    Ok(())
+}
+
+/// The sending side of a `MuxedStream`.
+struct StreamSender {
+    outgoing: mpsc::UnboundedSender<OutgoingMessage>,
+}
+
+impl StreamSender {
+    async fn send(&self, message: Vec<u8>) -> Result<(), MuxError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.outgoing
+            .send((message, result_sender))
+            .map_err(|_| MuxError::ConnectionClosed)?;
+        result_receiver
+            .await
+            .unwrap_or(Err(MuxError::ConnectionClosed))
+    }
+}
+
+/// The receiving side of a `MuxedStream`.
+struct StreamReceiver {
+    flow_tag: FlowTag,
+    shared: Arc<Shared>,
+    incoming: mpsc::UnboundedReceiver<DataFrame>,
+    frames: mpsc::UnboundedSender<Vec<u8>>,
+    /// The frames of the message being received, kept across calls to
+    /// `recv`, so that cancelling `recv` does not lose them.
+    partial_message: Vec<u8>,
+}
+
+impl StreamReceiver {
+    async fn recv(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let frame = self.incoming.recv().await?;
+            self.return_credit(frame.data.len() as u32);
+            self.partial_message.extend_from_slice(&frame.data);
+            if frame.end_of_message {
+                return Some(std::mem::take(&mut self.partial_message));
+            }
+        }
+    }
+
+    /// Returns the credit of a consumed frame to the peer.
+    fn return_credit(&self, length: u32) {
+        if length == 0 {
+            return;
+        }
+        if let Some(stream) = self.shared.streams.lock().unwrap().get_mut(&self.flow_tag) {
+            stream.receive_window += length;
+        }
+        let header = FrameHeader {
+            frame_type: FRAME_TYPE_WINDOW_UPDATE,
+            flags: 0,
+            flow_tag: self.flow_tag.get(),
+            length,
+        };
+        let _ = self.frames.send(encode_frame(header, &[]));
+    }
+}
+
+/// The stream of a single flow over a `MuxedConnection`.
+pub struct MuxedStream {
+    sender: StreamSender,
+    receiver: StreamReceiver,
+}
+
+impl MuxedStream {
+    pub fn flow_tag(&self) -> FlowTag {
+        self.receiver.flow_tag
+    }
+
+    /// Sends a message, waiting for window updates from the peer if the
+    /// message does not fit into the send window. Messages are sent in the
+    /// order in which `send` is called.
+    pub async fn send(&self, message: &[u8]) -> Result<(), MuxError> {
+        self.sender.send(message.to_vec()).await
+    }
+
+    /// Receives the next message, or returns `None` once the connection is
+    /// closed. Receiving a message returns its size as credit to the peer.
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        self.receiver.recv().await
+    }
+
+    /// Turns the stream into a byte stream for clients that do their own
+    /// framing, such as the data plane of transport. Every write on the
+    /// returned write half is sent as a message, and the messages received
+    /// are concatenated on the returned read half.
+    ///
+    /// The byte stream keeps `connection` open until both of its halves are
+    /// dropped.
+    pub fn into_io(
+        self,
+        connection: Arc<MuxedConnection>,
+    ) -> (ReadHalf<DuplexStream>, WriteHalf<DuplexStream>) {
+        let (local, remote) = tokio::io::duplex(BYTE_STREAM_BUFFER_SIZE);
+        let (mut local_reader, mut local_writer) = tokio::io::split(local);
+        let runtime = connection.runtime.clone();
+        let Self {
+            sender,
+            mut receiver,
+        } = self;
+
+        // Dropping both returned halves ends the pump of outgoing messages,
+        // which then ends the pump of incoming messages through `closed`.
+        let (closed, mut closed_receiver) = oneshot::channel::<()>();
+        let connection_cl = Arc::clone(&connection);
+        runtime.spawn(async move {
+            let _connection = connection_cl;
+            let _closed = closed;
+            let mut buffer = vec![0; BYTE_STREAM_BUFFER_SIZE];
+            loop {
+                match local_reader.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if sender.send(buffer[..n].to_vec()).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        runtime.spawn(async move {
+            let _connection = connection;
+            loop {
+                let message = tokio::select! {
+                    message = receiver.recv() => message,
+                    _ = &mut closed_receiver => None,
+                };
+                match message {
+                    Some(message) => {
+                        if local_writer.write_all(&message).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            }
+        });
+
+        tokio::io::split(remote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    const FLOWS: [u32; 2] = [1, 2];
+
+    fn flow_tags() -> Vec<FlowTag> {
+        FLOWS.iter().map(|tag| FlowTag::from(*tag)).collect()
+    }
+
+    fn connected_pair(config: MuxConfig) -> (MuxedConnection, MuxedConnection) {
+        let (a, b) = tokio::io::duplex(1024 * 1024);
+        let runtime = Handle::current();
+        let (a_reader, a_writer) = tokio::io::split(a);
+        let (b_reader, b_writer) = tokio::io::split(b);
+        (
+            MuxedConnection::new(a_reader, a_writer, &flow_tags(), config, &runtime),
+            MuxedConnection::new(b_reader, b_writer, &flow_tags(), config, &runtime),
+        )
+    }
+
+    #[test]
+    fn frame_header_roundtrip() {
+        let header = FrameHeader {
+            frame_type: FRAME_TYPE_DATA,
+            flags: FRAME_FLAGS_END_OF_MESSAGE,
+            flow_tag: 1234,
+            length: 5678,
+        };
+        assert_eq!(FrameHeader::decode(&header.encode()), Some(header));
+
+        let mut unknown_version = header.encode();
+        unknown_version[0] = 1;
+        assert_eq!(FrameHeader::decode(&unknown_version), None);
+    }
+
+    #[tokio::test]
+    async fn messages_are_delivered_per_flow() {
+        let (client, server) = connected_pair(MuxConfig::default());
+        let client_a = client.open_stream(FlowTag::from(1)).unwrap();
+        let client_b = client.open_stream(FlowTag::from(2)).unwrap();
+
+        // Messages sent before the peer opens the stream are buffered.
+        let large = vec![7; 100 * 1024];
+        client_a.send(b"a1").await.unwrap();
+        client_b.send(&large).await.unwrap();
+        client_a.send(b"").await.unwrap();
+        client_a.send(b"a2").await.unwrap();
+
+        let mut server_a = server.open_stream(FlowTag::from(1)).unwrap();
+        let mut server_b = server.open_stream(FlowTag::from(2)).unwrap();
+        assert_eq!(server_a.recv().await.unwrap(), b"a1".to_vec());
+        assert_eq!(server_a.recv().await.unwrap(), Vec::<u8>::new());
+        assert_eq!(server_a.recv().await.unwrap(), b"a2".to_vec());
+        assert_eq!(server_b.recv().await.unwrap(), large);
+    }
+
+    #[tokio::test]
+    async fn messages_larger_than_the_window_are_delivered() {
+        let config = MuxConfig {
+            initial_window_size: 1024,
+            max_frame_size: 256,
+        };
+        let (client, server) = connected_pair(config);
+        let client_stream = client.open_stream(FlowTag::from(1)).unwrap();
+        let mut server_stream = server.open_stream(FlowTag::from(1)).unwrap();
+
+        let message: Vec<u8> = (0..10 * 1024).map(|i| i as u8).collect();
+        let (sent, received) = tokio::join!(client_stream.send(&message), server_stream.recv());
+        sent.unwrap();
+        assert_eq!(received.unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn concurrent_messages_are_not_interleaved() {
+        let config = MuxConfig {
+            initial_window_size: 1024,
+            max_frame_size: 256,
+        };
+        let (client, server) = connected_pair(config);
+        let client_stream = client.open_stream(FlowTag::from(1)).unwrap();
+        let mut server_stream = server.open_stream(FlowTag::from(1)).unwrap();
+
+        let (first, second, received) = tokio::join!(
+            client_stream.send(&[1; 4096]),
+            client_stream.send(&[2; 4096]),
+            async {
+                let mut received = Vec::new();
+                for _ in 0..2 {
+                    received.push(server_stream.recv().await.unwrap());
+                }
+                received
+            }
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(received, vec![vec![1; 4096], vec![2; 4096]]);
+    }
+
+    #[tokio::test]
+    async fn stream_can_only_be_opened_once() {
+        let (client, _server) = connected_pair(MuxConfig::default());
+        let _stream = client.open_stream(FlowTag::from(1)).unwrap();
+
+        assert_eq!(
+            client.open_stream(FlowTag::from(1)).err(),
+            Some(MuxError::StreamAlreadyOpen(FlowTag::from(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn only_declared_streams_can_be_opened() {
+        let (client, _server) = connected_pair(MuxConfig::default());
+
+        assert_eq!(
+            client.open_stream(FlowTag::from(3)).err(),
+            Some(MuxError::UnknownFlow(FlowTag::from(3)))
+        );
+    }
+
+    #[tokio::test]
+    async fn frame_for_undeclared_stream_closes_the_connection() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(a);
+        let connection = MuxedConnection::new(
+            reader,
+            writer,
+            &flow_tags(),
+            MuxConfig::default(),
+            &Handle::current(),
+        );
+        let mut stream = connection.open_stream(FlowTag::from(1)).unwrap();
+
+        let (_peer_reader, mut peer_writer) = tokio::io::split(b);
+        let header = FrameHeader {
+            frame_type: FRAME_TYPE_DATA,
+            flags: FRAME_FLAGS_END_OF_MESSAGE,
+            flow_tag: 3,
+            length: 1,
+        };
+        peer_writer
+            .write_all(&encode_frame(header, &[0]))
+            .await
+            .unwrap();
+
+        assert_eq!(stream.recv().await, None);
+        assert!(connection.is_closed());
+    }
+
+    #[tokio::test]
+    async fn slow_stream_does_not_block_other_streams() {
+        let config = MuxConfig {
+            initial_window_size: 1024,
+            max_frame_size: 256,
+        };
+        let (client, server) = connected_pair(config);
+        let slow = client.open_stream(FlowTag::from(1)).unwrap();
+        let fast = client.open_stream(FlowTag::from(2)).unwrap();
+        let mut server_slow = server.open_stream(FlowTag::from(1)).unwrap();
+        let mut server_fast = server.open_stream(FlowTag::from(2)).unwrap();
+
+        // The slow stream's window is exhausted while its messages are not
+        // consumed.
+        slow.send(&[1; 1024]).await.unwrap();
+        assert!(timeout(Duration::from_millis(100), slow.send(&[2; 1]))
+            .await
+            .is_err());
+
+        // The fast stream is not affected.
+        for i in 0..10u8 {
+            fast.send(&[i; 1000]).await.unwrap();
+            assert_eq!(server_fast.recv().await.unwrap(), vec![i; 1000]);
+        }
+
+        // Consuming the slow stream's message opens its window again, and the
+        // message that did not fit is still sent.
+        assert_eq!(server_slow.recv().await.unwrap(), vec![1; 1024]);
+        assert_eq!(server_slow.recv().await.unwrap(), vec![2; 1]);
+    }
+
+    #[tokio::test]
+    async fn byte_streams_are_carried_over_streams() {
+        let (client, server) = connected_pair(MuxConfig::default());
+        let client = Arc::new(client);
+        let server = Arc::new(server);
+        let client_stream = client.open_stream(FlowTag::from(1)).unwrap();
+        let server_stream = server.open_stream(FlowTag::from(1)).unwrap();
+        let (_client_reader, mut client_writer) = client_stream.into_io(Arc::clone(&client));
+        let (mut server_reader, _server_writer) = server_stream.into_io(Arc::clone(&server));
+
+        let bytes: Vec<u8> = (0..100 * 1024).map(|i| i as u8).collect();
+        client_writer.write_all(&bytes).await.unwrap();
+        let mut received = vec![0; bytes.len()];
+        server_reader.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, bytes);
+    }
+
+    #[tokio::test]
+    async fn streams_are_closed_with_the_connection() {
+        let (client, server) = connected_pair(MuxConfig::default());
+        let stream = client.open_stream(FlowTag::from(1)).unwrap();
+        let mut server_stream = server.open_stream(FlowTag::from(1)).unwrap();
+
+        drop(client);
+
+        assert_eq!(server_stream.recv().await, None);
+        assert!(server.is_closed());
+        assert_eq!(
+            server.open_stream(FlowTag::from(2)).err(),
+            Some(MuxError::ConnectionClosed)
+        );
+        assert_eq!(stream.send(b"late").await, Err(MuxError::ConnectionClosed));
+    }
+}
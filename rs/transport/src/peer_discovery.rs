@@ -0,0 +1,403 @@
+//! Peer discovery driven by registry changes
+//!
+//! The peer discovery task polls the registry for the node records of a
+//! subnet and converts changes into typed peer set updates: peers joining or
+//! leaving the subnet, and peers whose node record (e.g. their address)
+//! changed. Transport clients apply the updates with
+//! [`apply_peer_set_update`], so that address changes take effect within
+//! seconds rather than at the next full reconnect cycle.
+//!
+//! Registry changes often come in bursts of consecutive versions. The task
+//! therefore waits for a debounce period after detecting a new registry
+//! version and emits the combined updates of the whole burst.
+
+use crate::metrics::PeerDiscoveryMetrics;
+use ic_interfaces::registry::RegistryClient;
+use ic_interfaces::transport::Transport;
+use ic_logger::{warn, ReplicaLogger};
+use ic_metrics::MetricsRegistry;
+use ic_protobuf::registry::node::v1::NodeRecord;
+use ic_registry_client::helper::subnet::SubnetTransportRegistry;
+use ic_types::transport::{TransportClientType, TransportErrorCode};
+use ic_types::{NodeId, RegistryVersion, SubnetId};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// A change to the set of peers of a subnet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeerSetUpdate {
+    /// The peer joined the subnet.
+    PeerAdded {
+        peer_id: NodeId,
+        node_record: NodeRecord,
+        registry_version: RegistryVersion,
+    },
+    /// The node record of the peer changed, e.g. its address.
+    PeerChanged {
+        peer_id: NodeId,
+        node_record: NodeRecord,
+        registry_version: RegistryVersion,
+    },
+    /// The peer left the subnet.
+    PeerRemoved {
+        peer_id: NodeId,
+        registry_version: RegistryVersion,
+    },
+}
+
+impl PeerSetUpdate {
+    fn kind(&self) -> &'static str {
+        match self {
+            PeerSetUpdate::PeerAdded { .. } => "added",
+            PeerSetUpdate::PeerChanged { .. } => "changed",
+            PeerSetUpdate::PeerRemoved { .. } => "removed",
+        }
+    }
+}
+
+/// Returns the updates that turn the `old` peer set into the `new` one.
+fn diff_peer_sets(
+    old: &BTreeMap<NodeId, NodeRecord>,
+    new: &BTreeMap<NodeId, NodeRecord>,
+    registry_version: RegistryVersion,
+) -> Vec<PeerSetUpdate> {
+    let removed = old
+        .keys()
+        .filter(|peer_id| !new.contains_key(peer_id))
+        .map(|peer_id| PeerSetUpdate::PeerRemoved {
+            peer_id: *peer_id,
+            registry_version,
+        });
+    let added_or_changed = new
+        .iter()
+        .filter_map(|(peer_id, node_record)| match old.get(peer_id) {
+            None => Some(PeerSetUpdate::PeerAdded {
+                peer_id: *peer_id,
+                node_record: node_record.clone(),
+                registry_version,
+            }),
+            Some(old_record) if old_record != node_record => Some(PeerSetUpdate::PeerChanged {
+                peer_id: *peer_id,
+                node_record: node_record.clone(),
+                registry_version,
+            }),
+            Some(_) => None,
+        });
+    removed.chain(added_or_changed).collect()
+}
+
+/// Tracks the peers of a subnet as recorded in the registry.
+pub struct PeerDiscovery {
+    registry_client: Arc<dyn RegistryClient>,
+    subnet_id: SubnetId,
+    node_id: NodeId,
+    peers: BTreeMap<NodeId, NodeRecord>,
+    registry_version: RegistryVersion,
+    metrics: PeerDiscoveryMetrics,
+    log: ReplicaLogger,
+}
+
+impl PeerDiscovery {
+    /// Creates a peer discovery for the peers of `node_id` on `subnet_id`.
+    /// The initial peer set is empty, so the first poll reports all peers as
+    /// added.
+    pub fn new(
+        registry_client: Arc<dyn RegistryClient>,
+        subnet_id: SubnetId,
+        node_id: NodeId,
+        metrics_registry: &MetricsRegistry,
+        log: ReplicaLogger,
+    ) -> Self {
+        Self {
+            registry_client,
+            subnet_id,
+            node_id,
+            peers: BTreeMap::new(),
+            registry_version: RegistryVersion::from(0),
+            metrics: PeerDiscoveryMetrics::new(metrics_registry),
+            log,
+        }
+    }
+
+    /// Returns the updates to the peer set since the last poll, based on the
+    /// latest registry version. If the subnet record cannot be read, the peer
+    /// set is left unchanged.
+    pub fn poll(&mut self) -> Vec<PeerSetUpdate> {
+        let registry_version = self.registry_client.get_latest_version();
+        if registry_version == self.registry_version {
+            return Vec::new();
+        }
+        let node_records = match self
+            .registry_client
+            .get_subnet_transport_infos(self.subnet_id, registry_version)
+        {
+            Ok(Some(node_records)) => node_records,
+            Ok(None) => Vec::new(),
+            Err(err) => {
+                warn!(
+                    self.log,
+                    "PeerDiscovery: failed to read node records of subnet {} at version {}: {:?}",
+                    self.subnet_id,
+                    registry_version,
+                    err
+                );
+                self.metrics.registry_errors.inc();
+                return Vec::new();
+            }
+        };
+        let peers: BTreeMap<NodeId, NodeRecord> = node_records
+            .into_iter()
+            .filter(|(peer_id, _)| *peer_id != self.node_id)
+            .collect();
+
+        let updates = diff_peer_sets(&self.peers, &peers, registry_version);
+        for update in &updates {
+            self.metrics
+                .updates
+                .with_label_values(&[update.kind()])
+                .inc();
+        }
+        self.peers = peers;
+        self.registry_version = registry_version;
+        self.metrics
+            .registry_version
+            .set(registry_version.get() as i64);
+        self.metrics.peers.set(self.peers.len() as i64);
+        updates
+    }
+
+    /// Starts polling the registry every `poll_interval` on `runtime`. Once
+    /// a new registry version is detected, the task waits for `debounce`
+    /// before sending the combined updates through the returned channel.
+    ///
+    /// The task stops when the receiver is dropped.
+    pub fn start(
+        mut self,
+        runtime: &Handle,
+        poll_interval: Duration,
+        debounce: Duration,
+    ) -> (mpsc::UnboundedReceiver<Vec<PeerSetUpdate>>, JoinHandle<()>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = runtime.spawn(async move {
+            let mut interval = tokio::time::interval(poll_interval);
+            loop {
+                interval.tick().await;
+                if sender.is_closed() {
+                    return;
+                }
+                if self.registry_client.get_latest_version() == self.registry_version {
+                    continue;
+                }
+                tokio::time::sleep(debounce).await;
+                let updates = self.poll();
+                if !updates.is_empty() && sender.send(updates).is_err() {
+                    return;
+                }
+            }
+        });
+        (receiver, task)
+    }
+}
+
+/// Applies a peer set update to the connections of the given transport
+/// client. A changed peer is reconnected using its new node record.
+pub fn apply_peer_set_update(
+    transport: &dyn Transport,
+    client_type: TransportClientType,
+    update: &PeerSetUpdate,
+) -> Result<(), TransportErrorCode> {
+    match update {
+        PeerSetUpdate::PeerAdded {
+            peer_id,
+            node_record,
+            registry_version,
+        } => transport.start_connections(client_type, peer_id, node_record, *registry_version),
+        PeerSetUpdate::PeerChanged {
+            peer_id,
+            node_record,
+            registry_version,
+        } => {
+            transport.stop_connections(client_type, peer_id, *registry_version)?;
+            transport.start_connections(client_type, peer_id, node_record, *registry_version)
+        }
+        PeerSetUpdate::PeerRemoved {
+            peer_id,
+            registry_version,
+        } => transport.stop_connections(client_type, peer_id, *registry_version),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_protobuf::registry::node::v1::ConnectionEndpoint;
+    use ic_protobuf::registry::subnet::v1::SubnetRecord;
+    use ic_registry_client::fake::FakeRegistryClient;
+    use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
+    use ic_registry_keys::{make_node_record_key, make_subnet_record_key};
+    use ic_test_utilities::types::ids::{node_test_id, subnet_test_id};
+    use ic_test_utilities::with_test_replica_logger;
+
+    fn node_record(ip_addr: &str) -> NodeRecord {
+        NodeRecord {
+            p2p_flow_endpoints: vec![],
+            http: Some(ConnectionEndpoint {
+                ip_addr: ip_addr.to_string(),
+                port: 8080,
+                protocol: 0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a version of the subnet with the given members to the registry.
+    fn add_subnet_version(
+        data_provider: &ProtoRegistryDataProvider,
+        version: u64,
+        members: &[(u64, &str)],
+    ) {
+        let version = RegistryVersion::from(version);
+        let subnet_record = SubnetRecord {
+            membership: members
+                .iter()
+                .map(|(id, _)| node_test_id(*id).get().into_vec())
+                .collect(),
+            ..Default::default()
+        };
+        data_provider
+            .add(
+                &make_subnet_record_key(subnet_test_id(1)),
+                version,
+                Some(subnet_record),
+            )
+            .unwrap();
+        for (id, ip_addr) in members {
+            data_provider
+                .add(
+                    &make_node_record_key(node_test_id(*id)),
+                    version,
+                    Some(node_record(ip_addr)),
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn diff_reports_added_changed_and_removed_peers() {
+        let version = RegistryVersion::from(2);
+        let old = vec![
+            (node_test_id(1), node_record("10.0.0.1")),
+            (node_test_id(2), node_record("10.0.0.2")),
+            (node_test_id(3), node_record("10.0.0.3")),
+        ]
+        .into_iter()
+        .collect();
+        let new = vec![
+            (node_test_id(1), node_record("10.0.0.1")),
+            (node_test_id(2), node_record("10.0.0.22")),
+            (node_test_id(4), node_record("10.0.0.4")),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            diff_peer_sets(&old, &new, version),
+            vec![
+                PeerSetUpdate::PeerRemoved {
+                    peer_id: node_test_id(3),
+                    registry_version: version,
+                },
+                PeerSetUpdate::PeerChanged {
+                    peer_id: node_test_id(2),
+                    node_record: node_record("10.0.0.22"),
+                    registry_version: version,
+                },
+                PeerSetUpdate::PeerAdded {
+                    peer_id: node_test_id(4),
+                    node_record: node_record("10.0.0.4"),
+                    registry_version: version,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn poll_tracks_registry_versions() {
+        with_test_replica_logger(|log| {
+            let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+            add_subnet_version(&data_provider, 1, &[(1, "10.0.0.1"), (2, "10.0.0.2")]);
+            let registry_client = Arc::new(FakeRegistryClient::new(data_provider.clone()));
+            registry_client.update_to_latest_version();
+            let mut discovery = PeerDiscovery::new(
+                registry_client.clone(),
+                subnet_test_id(1),
+                node_test_id(1),
+                &MetricsRegistry::new(),
+                log,
+            );
+
+            // The own node is not a peer.
+            assert_eq!(
+                discovery.poll(),
+                vec![PeerSetUpdate::PeerAdded {
+                    peer_id: node_test_id(2),
+                    node_record: node_record("10.0.0.2"),
+                    registry_version: RegistryVersion::from(1),
+                }]
+            );
+            assert_eq!(discovery.poll(), vec![]);
+
+            add_subnet_version(&data_provider, 2, &[(1, "10.0.0.1"), (2, "10.0.0.22")]);
+            registry_client.update_to_latest_version();
+            assert_eq!(
+                discovery.poll(),
+                vec![PeerSetUpdate::PeerChanged {
+                    peer_id: node_test_id(2),
+                    node_record: node_record("10.0.0.22"),
+                    registry_version: RegistryVersion::from(2),
+                }]
+            );
+        });
+    }
+
+    #[tokio::test]
+    async fn task_debounces_registry_updates() {
+        let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+        add_subnet_version(&data_provider, 1, &[(1, "10.0.0.1")]);
+        let registry_client = Arc::new(FakeRegistryClient::new(data_provider.clone()));
+        registry_client.update_to_latest_version();
+        let discovery = PeerDiscovery::new(
+            registry_client.clone(),
+            subnet_test_id(1),
+            node_test_id(1),
+            &MetricsRegistry::new(),
+            ic_logger::replica_logger::no_op_logger(),
+        );
+        let (mut updates, _task) = discovery.start(
+            &Handle::current(),
+            Duration::from_millis(10),
+            Duration::from_millis(200),
+        );
+
+        // Two registry versions within the debounce period result in a single
+        // batch of updates.
+        add_subnet_version(&data_provider, 2, &[(1, "10.0.0.1"), (2, "10.0.0.2")]);
+        registry_client.update_to_latest_version();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        add_subnet_version(&data_provider, 3, &[(1, "10.0.0.1"), (2, "10.0.0.22")]);
+        registry_client.update_to_latest_version();
+
+        assert_eq!(
+            updates.recv().await.unwrap(),
+            vec![PeerSetUpdate::PeerAdded {
+                peer_id: node_test_id(2),
+                node_record: node_record("10.0.0.22"),
+                registry_version: RegistryVersion::from(3),
+            }]
+        );
+    }
+}
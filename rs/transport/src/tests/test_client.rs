@@ -495,6 +495,7 @@ fn generate_config_and_registry(node_id: &NodeId) -> ConfigAndRecords {
                         queue_size: 1024,
                     },
                 ],
+                multiplex_flows: false,
             });
         }
 
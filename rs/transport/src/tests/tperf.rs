@@ -640,6 +640,7 @@ fn generate_config_and_registry(
             server_port: FLOW_PORT as u16,
             queue_size: 8192,
         }],
+        multiplex_flows: false,
     };
 
     let mut node_records = Vec::new();
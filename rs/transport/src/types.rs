@@ -18,6 +18,7 @@ use std::fmt::{self, Debug, Formatter};
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::{Arc, RwLock, Weak};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::runtime::Handle;
 use tokio::time::Duration;
 
@@ -33,6 +34,12 @@ pub struct QueueSizeTag;
 /// Type definition for a queue's size
 pub type QueueSize = AmountOf<QueueSizeTag, usize>;
 
+/// The read half of the connection of a flow, either a TLS connection or a
+/// stream multiplexed over the TLS connection of another flow
+pub(crate) type FlowReader = Box<dyn AsyncRead + Send + Unpin>;
+/// The write half of the connection of a flow
+pub(crate) type FlowWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
 /// The size (in bytes) of the transport header
 pub const TRANSPORT_HEADER_SIZE: usize = 8;
 
@@ -113,7 +120,7 @@ pub(crate) struct TransportImpl {
 }
 
 /// Our role in a connection
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum ConnectionRole {
     /// We connect to the peer as a client
     Client,
@@ -213,6 +220,9 @@ pub(crate) enum ConnectionState {
     Connecting(Connecting),
     /// Connection established
     Connected(Connected),
+    /// The flow is multiplexed over the connection of another flow, waiting
+    /// for that connection to be established
+    WaitingForMux,
 }
 
 /// Info about a flow in ConnectionState::Connecting
@@ -237,6 +247,9 @@ pub(crate) struct Connected {
 
     /// Our role
     pub role: ConnectionRole,
+
+    /// The registry version at which the peer was authenticated
+    pub registry_version: RegistryVersion,
 }
 
 impl ConnectionState {
@@ -272,6 +285,7 @@ impl ConnectionState {
                 }
             }
             Self::Connected(s) => match next_state {
+                Self::WaitingForMux => valid = true,
                 Self::Listening => {
                     if s.role == ConnectionRole::Server {
                         valid = true;
@@ -284,6 +298,11 @@ impl ConnectionState {
                 }
                 _ => (),
             },
+            Self::WaitingForMux => {
+                if let Self::Connected(_) = next_state {
+                    valid = true;
+                }
+            }
         }
         valid
     }
@@ -293,6 +312,7 @@ impl ConnectionState {
             Self::Listening => 1,
             Self::Connecting(_) => 2,
             Self::Connected(_) => 3,
+            Self::WaitingForMux => 4,
         }
     }
 }
@@ -313,10 +333,13 @@ impl Debug for ConnectionState {
             Self::Connected(state) => {
                 write!(
                     f,
-                    "ConnectionState::Connected(peer = {:?}, role = {:?})",
-                    state.peer_addr, state.role
+                    "ConnectionState::Connected(peer = {:?}, role = {:?}, registry_version = {})",
+                    state.peer_addr, state.role, state.registry_version
                 )
             }
+            Self::WaitingForMux => {
+                write!(f, "ConnectionState::WaitingForMux")
+            }
         }
     }
 }
@@ -391,6 +414,7 @@ mod tests {
             read_task,
             write_task,
             role,
+            registry_version: RegistryVersion::from(1),
         })
     }
 
@@ -414,6 +438,7 @@ mod tests {
             (connecting_state(), false),
             (connected_state(ConnectionRole::Server), true),
             (connected_state(ConnectionRole::Client), false),
+            (ConnectionState::WaitingForMux, false),
         ];
         verify_state_transitions(state, expected);
     }
@@ -426,6 +451,7 @@ mod tests {
             (connecting_state(), false),
             (connected_state(ConnectionRole::Server), false),
             (connected_state(ConnectionRole::Client), true),
+            (ConnectionState::WaitingForMux, false),
         ];
         verify_state_transitions(state, expected);
     }
@@ -438,6 +464,7 @@ mod tests {
             (connecting_state(), false),
             (connected_state(ConnectionRole::Server), false),
             (connected_state(ConnectionRole::Client), false),
+            (ConnectionState::WaitingForMux, true),
         ];
         verify_state_transitions(state, expected);
 
@@ -447,6 +474,20 @@ mod tests {
             (connecting_state(), true),
             (connected_state(ConnectionRole::Server), false),
             (connected_state(ConnectionRole::Client), false),
+            (ConnectionState::WaitingForMux, true),
+        ];
+        verify_state_transitions(state, expected);
+    }
+
+    #[test]
+    fn test_connection_state_machine_waiting_for_mux() {
+        let state = ConnectionState::WaitingForMux;
+        let expected = vec![
+            (ConnectionState::Listening, false),
+            (connecting_state(), false),
+            (connected_state(ConnectionRole::Server), true),
+            (connected_state(ConnectionRole::Client), true),
+            (ConnectionState::WaitingForMux, false),
         ];
         verify_state_transitions(state, expected);
     }
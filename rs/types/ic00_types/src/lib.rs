@@ -19,10 +19,19 @@ pub const MAX_CONTROLLERS: usize = 10;
 #[derive(Debug, EnumString, EnumIter, ToString, Copy, Clone)]
 #[strum(serialize_all = "snake_case")]
 pub enum Method {
+    CanisterMetrics,
+    CanisterSelfDestruct,
     CanisterStatus,
+    CostCall,
+    CostCreateCanister,
+    CostExecution,
     CreateCanister,
     DeleteCanister,
     DepositCycles,
+    ExportCanister,
+    ExportCanisterChunk,
+    ImportCanister,
+    ImportCanisterChunk,
     InstallCode,
     RawRand,
     SetController,
@@ -36,6 +45,7 @@ pub enum Method {
     // These methods are added for the Mercury I release.
     // They should be removed afterwards.
     ProvisionalCreateCanisterWithCycles,
+    ProvisionalCreateCanistersWithCycles,
     ProvisionalTopUpCanister,
 }
 
@@ -256,6 +266,228 @@ impl CanisterStatusResultV2 {
 
 impl Payload<'_> for CanisterStatusResultV2 {}
 
+/// Struct used for encoding/decoding
+/// `(record {
+///     time_nanos: nat64;
+///     updates_executed: nat64;
+///     instructions_executed: nat64;
+///     traps: nat64;
+///     memory_grow_events: nat64;
+/// })`
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct CanisterRoundMetrics {
+    pub time_nanos: u64,
+    pub updates_executed: u64,
+    pub instructions_executed: u64,
+    pub traps: u64,
+    pub memory_grow_events: u64,
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     rounds: vec canister_round_metrics;
+/// })`
+///
+/// The rounds are the most recent ones in which the canister executed
+/// messages, oldest first.
+#[derive(CandidType, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct CanisterMetricsResult {
+    pub rounds: Vec<CanisterRoundMetrics>,
+}
+
+impl Payload<'_> for CanisterMetricsResult {}
+
+/// Struct used for encoding/decoding
+/// `variant { wasm; stable }`
+#[derive(Copy, Clone, CandidType, Deserialize, Debug, PartialEq, Eq)]
+pub enum CanisterMemory {
+    #[serde(rename = "wasm")]
+    Wasm,
+    #[serde(rename = "stable")]
+    Stable,
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id: principal;
+///     memory: variant { wasm; stable };
+///     offset: nat64;
+/// })`
+#[derive(CandidType, Debug, Deserialize)]
+pub struct ExportCanisterChunkArgs {
+    canister_id: PrincipalId,
+    pub memory: CanisterMemory,
+    pub offset: u64,
+}
+
+impl ExportCanisterChunkArgs {
+    pub fn new(canister_id: CanisterId, memory: CanisterMemory, offset: u64) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            memory,
+            offset,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+}
+
+impl Payload<'_> for ExportCanisterChunkArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     bytes: blob;
+/// })`
+#[derive(CandidType, Debug, Deserialize, Eq, PartialEq)]
+pub struct ExportCanisterChunkResult {
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+impl Payload<'_> for ExportCanisterChunkResult {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id: principal;
+///     target_subnet: principal;
+/// })`
+#[derive(CandidType, Debug, Deserialize)]
+pub struct ExportCanisterArgs {
+    canister_id: PrincipalId,
+    target_subnet: PrincipalId,
+}
+
+impl ExportCanisterArgs {
+    pub fn new(canister_id: CanisterId, target_subnet: SubnetId) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            target_subnet: target_subnet.get(),
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    pub fn get_target_subnet(&self) -> SubnetId {
+        SubnetId::from(self.target_subnet)
+    }
+}
+
+impl Payload<'_> for ExportCanisterArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     manifest: blob;
+/// })`
+///
+/// The manifest describes the exported canister. It is the reply to an
+/// ingress message and hence certified by the source subnet, which allows
+/// the subnet the canister is migrated to to verify it in `import_canister`.
+#[derive(CandidType, Debug, Deserialize, Eq, PartialEq)]
+pub struct ExportCanisterResult {
+    #[serde(with = "serde_bytes")]
+    pub manifest: Vec<u8>,
+}
+
+impl Payload<'_> for ExportCanisterResult {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id: principal;
+///     source_subnet: principal;
+///     certificate: blob;
+///     export_request: blob;
+///     wasm_module: blob;
+/// })`
+///
+/// `export_request` is the content of the ingress message that called
+/// `export_canister` and `certificate` the certificate of its reply, as
+/// returned by `read_state` on the source subnet.
+#[derive(Clone, CandidType, Debug, Deserialize)]
+pub struct ImportCanisterArgs {
+    canister_id: PrincipalId,
+    source_subnet: PrincipalId,
+    #[serde(with = "serde_bytes")]
+    pub certificate: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub export_request: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub wasm_module: Vec<u8>,
+}
+
+impl ImportCanisterArgs {
+    pub fn new(
+        canister_id: CanisterId,
+        source_subnet: SubnetId,
+        certificate: Vec<u8>,
+        export_request: Vec<u8>,
+        wasm_module: Vec<u8>,
+    ) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            source_subnet: source_subnet.get(),
+            certificate,
+            export_request,
+            wasm_module,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    pub fn get_source_subnet(&self) -> SubnetId {
+        SubnetId::from(self.source_subnet)
+    }
+}
+
+impl Payload<'_> for ImportCanisterArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id: principal;
+///     memory: variant { wasm; stable };
+///     offset: nat64;
+///     bytes: blob;
+/// })`
+#[derive(CandidType, Debug, Deserialize)]
+pub struct ImportCanisterChunkArgs {
+    canister_id: PrincipalId,
+    pub memory: CanisterMemory,
+    pub offset: u64,
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+impl ImportCanisterChunkArgs {
+    pub fn new(
+        canister_id: CanisterId,
+        memory: CanisterMemory,
+        offset: u64,
+        bytes: Vec<u8>,
+    ) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            memory,
+            offset,
+            bytes,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+}
+
+impl Payload<'_> for ImportCanisterChunkArgs {}
+
 /// Struct used for encoding/decoding
 /// `(record {
 ///     mode : variant { install; reinstall; upgrade };
@@ -265,6 +497,7 @@ impl Payload<'_> for CanisterStatusResultV2 {}
 ///     compute_allocation: opt nat;
 ///     memory_allocation: opt nat;
 ///     query_allocation: opt nat;
+///     skip_stable_compat_check: opt bool;
 /// })`
 #[derive(Clone, CandidType, Deserialize, Debug)]
 pub struct InstallCodeArgs {
@@ -276,6 +509,9 @@ pub struct InstallCodeArgs {
     pub compute_allocation: Option<candid::Nat>,
     pub memory_allocation: Option<candid::Nat>,
     pub query_allocation: Option<candid::Nat>,
+    /// Forces an upgrade even if the stable memory schemas declared by the
+    /// installed and the new module differ.
+    pub skip_stable_compat_check: Option<bool>,
 }
 
 impl std::fmt::Display for InstallCodeArgs {
@@ -309,6 +545,11 @@ impl std::fmt::Display for InstallCodeArgs {
                 .as_ref()
                 .map(|value| format!("{}", value))
         )?;
+        writeln!(
+            f,
+            "  skip_stable_compat_check: {:?}",
+            &self.skip_stable_compat_check
+        )?;
         writeln!(f, "}}")
     }
 }
@@ -333,6 +574,7 @@ impl InstallCodeArgs {
             compute_allocation: compute_allocation.map(candid::Nat::from),
             memory_allocation: memory_allocation.map(candid::Nat::from),
             query_allocation: query_allocation.map(candid::Nat::from),
+            skip_stable_compat_check: None,
         }
     }
 
@@ -383,6 +625,10 @@ impl Payload<'_> for UpdateSettingsArgs {}
 ///     controllers: opt vec principal;
 ///     compute_allocation: opt nat;
 ///     memory_allocation: opt nat;
+///     freezing_threshold: opt nat;
+///     priority_class: opt canister_priority_class;
+///     self_destruct_enabled: opt bool;
+///     heartbeat_instruction_limit: opt nat;
 /// })`
 #[derive(Default, Clone, CandidType, Deserialize, Debug)]
 pub struct CanisterSettingsArgs {
@@ -391,6 +637,23 @@ pub struct CanisterSettingsArgs {
     pub compute_allocation: Option<candid::Nat>,
     pub memory_allocation: Option<candid::Nat>,
     pub freezing_threshold: Option<candid::Nat>,
+    /// Can only be set by canisters on the NNS subnet.
+    pub priority_class: Option<CanisterPriorityClass>,
+    /// Allows the canister to call `canister_self_destruct` on itself.
+    pub self_destruct_enabled: Option<bool>,
+    /// The maximum number of instructions the canister's heartbeat may
+    /// consume in a round, capped at the instruction limit per message.
+    pub heartbeat_instruction_limit: Option<candid::Nat>,
+}
+
+/// Struct used for encoding/decoding
+/// `variant { normal; elevated }`
+#[derive(Copy, Clone, CandidType, Deserialize, Debug, PartialEq, Eq)]
+pub enum CanisterPriorityClass {
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "elevated")]
+    Elevated,
 }
 
 impl Payload<'_> for CanisterSettingsArgs {}
@@ -398,10 +661,14 @@ impl Payload<'_> for CanisterSettingsArgs {}
 /// Struct used for encoding/decoding
 /// `(record {
 ///     settings : opt canister_settings;
+///     template_canister_id : opt principal;
 /// })`
 #[derive(Default, Clone, CandidType, Deserialize)]
 pub struct CreateCanisterArgs {
     pub settings: Option<CanisterSettingsArgs>,
+    /// A canister controlled by the caller whose settings are used for the
+    /// settings not specified in `settings`.
+    pub template_canister_id: Option<PrincipalId>,
 }
 
 impl CreateCanisterArgs {
@@ -455,6 +722,37 @@ impl SetControllerArgs {
 
 impl Payload<'_> for SetControllerArgs {}
 
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+///     beneficiary : principal;
+/// })`
+#[derive(CandidType, Deserialize, Debug)]
+pub struct CanisterSelfDestructArgs {
+    canister_id: PrincipalId,
+    beneficiary: PrincipalId,
+}
+
+impl CanisterSelfDestructArgs {
+    pub fn new(canister_id: CanisterId, beneficiary: CanisterId) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            beneficiary: beneficiary.into(),
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    /// Returns the canister that receives the remaining cycles.
+    pub fn get_beneficiary(&self) -> CanisterId {
+        CanisterId::new(self.beneficiary).unwrap()
+    }
+}
+
+impl Payload<'_> for CanisterSelfDestructArgs {}
+
 /// Struct used for encoding/decoding
 /// `(record {
 ///     node_ids : vec principal;
@@ -576,6 +874,69 @@ impl ProvisionalCreateCanisterWithCyclesArgs {
 
 impl Payload<'_> for ProvisionalCreateCanisterWithCyclesArgs {}
 
+/// Struct used for encoding/decoding
+/// `(record {
+///     count : nat;
+///     amount : opt nat;
+///     settings : opt canister_settings;
+/// })`
+#[derive(CandidType, Deserialize, Debug)]
+pub struct ProvisionalCreateCanistersWithCyclesArgs {
+    pub count: candid::Nat,
+    pub amount: Option<candid::Nat>,
+    pub settings: Option<CanisterSettingsArgs>,
+}
+
+impl ProvisionalCreateCanistersWithCyclesArgs {
+    pub fn new(count: u64, amount: Option<u64>) -> Self {
+        Self {
+            count: candid::Nat::from(count),
+            amount: amount.map(candid::Nat::from),
+            settings: None,
+        }
+    }
+
+    pub fn count_to_u64(&self) -> Option<u64> {
+        self.count.0.to_u64()
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        match &self.amount {
+            Some(amount) => amount.0.to_u64(),
+            None => None,
+        }
+    }
+}
+
+impl Payload<'_> for ProvisionalCreateCanistersWithCyclesArgs {}
+
+/// Struct used for encoding/decoding `(record { canister_ids : vec principal })`.
+#[derive(CandidType, Deserialize, Debug)]
+pub struct CanisterIdsRecord {
+    canister_ids: Vec<PrincipalId>,
+}
+
+impl CanisterIdsRecord {
+    pub fn get_canister_ids(&self) -> Vec<CanisterId> {
+        // Safe as these were converted from CanisterIds when Self was
+        // constructed.
+        self.canister_ids
+            .iter()
+            .map(|canister_id| CanisterId::new(*canister_id).unwrap())
+            .collect()
+    }
+}
+
+impl Payload<'_> for CanisterIdsRecord {}
+
+impl From<Vec<CanisterId>> for CanisterIdsRecord {
+    fn from(canister_ids: Vec<CanisterId>) -> Self {
+        Self {
+            canister_ids: canister_ids.into_iter().map(CanisterId::get).collect(),
+        }
+    }
+}
+
 /// Struct used for encoding/decoding
 /// `(record {
 ///     canister_id : principal;
@@ -606,3 +967,59 @@ impl ProvisionalTopUpCanisterArgs {
 }
 
 impl Payload<'_> for ProvisionalTopUpCanisterArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     method_name_size : nat64;
+///     payload_size : nat64;
+/// })`
+#[derive(CandidType, Deserialize, Debug)]
+pub struct CostCallArgs {
+    pub method_name_size: u64,
+    pub payload_size: u64,
+}
+
+impl CostCallArgs {
+    pub fn new(method_name_size: u64, payload_size: u64) -> Self {
+        Self {
+            method_name_size,
+            payload_size,
+        }
+    }
+}
+
+impl Payload<'_> for CostCallArgs {}
+
+/// Struct used for encoding/decoding `(record { num_instructions : nat64 })`.
+#[derive(CandidType, Deserialize, Debug)]
+pub struct CostExecutionArgs {
+    pub num_instructions: u64,
+}
+
+impl CostExecutionArgs {
+    pub fn new(num_instructions: u64) -> Self {
+        Self { num_instructions }
+    }
+}
+
+impl Payload<'_> for CostExecutionArgs {}
+
+/// Struct used for encoding/decoding `(record { cycles : nat })`.
+#[derive(CandidType, Deserialize, Debug, PartialEq, Eq)]
+pub struct CyclesCostRecord {
+    cycles: candid::Nat,
+}
+
+impl CyclesCostRecord {
+    pub fn new(cycles: u128) -> Self {
+        Self {
+            cycles: candid::Nat::from(cycles),
+        }
+    }
+
+    pub fn to_u128(&self) -> Option<u128> {
+        self.cycles.0.to_u128()
+    }
+}
+
+impl Payload<'_> for CyclesCostRecord {}
@@ -126,6 +126,7 @@ pub enum AlgorithmId {
     EcdsaSecp256k1 = 12,
     IcCanisterSignature = 13,
     RsaSha256 = 14,
+    MegaSecp256k1 = 15,
 }
 
 impl From<CspThresholdSigPublicKey> for AlgorithmId {
@@ -172,6 +173,7 @@ impl From<i32> for AlgorithmId {
             11 => AlgorithmId::EcdsaP256,
             12 => AlgorithmId::EcdsaSecp256k1,
             13 => AlgorithmId::IcCanisterSignature,
+            15 => AlgorithmId::MegaSecp256k1,
             _ => AlgorithmId::Placeholder,
         }
     }
@@ -239,6 +241,14 @@ pub enum CryptoError {
         algorithm: AlgorithmId,
         key_id: KeyId,
     },
+    /// Secret key not found in SecretKeyStore because it was recently removed
+    /// as it is no longer active, e.g., when signing races with the removal
+    /// of the keys of the previous epoch. Unlike `SecretKeyNotFound`, this
+    /// does not indicate that a key was lost.
+    SecretKeyRetired {
+        algorithm: AlgorithmId,
+        key_id: KeyId,
+    },
     /// TLS secret key not found in SecretKeyStore.
     TlsSecretKeyNotFound { certificate_der: Vec<u8> },
     /// Secret key could not be parsed or is otherwise invalid.
@@ -327,6 +337,10 @@ impl CryptoError {
         matches!(self, CryptoError::SecretKeyNotFound { .. })
     }
 
+    pub fn is_secret_key_retired(&self) -> bool {
+        matches!(self, CryptoError::SecretKeyRetired { .. })
+    }
+
     pub fn is_malformed_secret_key(&self) -> bool {
         matches!(self, CryptoError::MalformedSecretKey { .. })
     }
@@ -412,6 +426,12 @@ impl fmt::Debug for CryptoError {
                 algorithm, key_id
             ),
 
+            CryptoError::SecretKeyRetired { algorithm, key_id } => write!(
+                f,
+                "Cannot find {:?} secret key with ID {:?} as it was retired",
+                algorithm, key_id
+            ),
+
             CryptoError::TlsSecretKeyNotFound { certificate_der } => write!(
                 f,
                 "Cannot find TLS secret key for certificate (DER encoding) 0x{}",
@@ -17,6 +17,7 @@ fn should_correctly_convert_i32_to_algorithm_id() {
     assert_eq!(AlgorithmId::from(11), AlgorithmId::EcdsaP256);
     assert_eq!(AlgorithmId::from(12), AlgorithmId::EcdsaSecp256k1);
     assert_eq!(AlgorithmId::from(13), AlgorithmId::IcCanisterSignature);
+    assert_eq!(AlgorithmId::from(15), AlgorithmId::MegaSecp256k1);
     assert_eq!(AlgorithmId::from(42), AlgorithmId::Placeholder);
 }
 
@@ -36,6 +37,7 @@ fn should_correctly_convert_algorithm_id_to_i32() {
     assert_eq!(AlgorithmId::EcdsaP256 as i32, 11);
     assert_eq!(AlgorithmId::EcdsaSecp256k1 as i32, 12);
     assert_eq!(AlgorithmId::IcCanisterSignature as i32, 13);
+    assert_eq!(AlgorithmId::MegaSecp256k1 as i32, 15);
 }
 
 pub fn set_of(node_ids: &[NodeId]) -> BTreeSet<NodeId> {
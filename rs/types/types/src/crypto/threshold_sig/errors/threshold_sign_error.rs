@@ -13,6 +13,14 @@ pub enum ThresholdSignError {
         algorithm: AlgorithmId,
         key_id: KeyId,
     },
+    /// The secret key was removed because the transcript is no longer
+    /// active, i.e., signing raced with the removal of keys at an epoch
+    /// boundary.
+    SecretKeyRetired {
+        dkg_id: DkgId,
+        algorithm: AlgorithmId,
+        key_id: KeyId,
+    },
 }
 
 impl fmt::Display for ThresholdSignError {
@@ -30,6 +38,12 @@ impl fmt::Display for ThresholdSignError {
                 Reloading the transcript does not help since the transcript has been loaded already.",
                 prefix, algorithm, dkg_id, key_id
             ),
+            ThresholdSignError::SecretKeyRetired { dkg_id, algorithm, key_id } => write!(
+                f,
+                "{}The threshold signing {:?} secret key for DKG ID {} with key id {} was retired \
+                from the secret key store, as the transcript is no longer active.",
+                prefix, algorithm, dkg_id, key_id
+            ),
         }
     }
 }
@@ -49,6 +63,11 @@ impl From<ThresholdSignError> for CryptoError {
                 // ThresholdSigDataNotFound must not be used here, see CRP-586.
                 CryptoError::SecretKeyNotFound { algorithm, key_id }
             }
+            ThresholdSignError::SecretKeyRetired {
+                dkg_id: _,
+                algorithm,
+                key_id,
+            } => CryptoError::SecretKeyRetired { algorithm, key_id },
         }
     }
 }
@@ -82,6 +82,9 @@ impl From<Cycles> for Vec<u8> {
     }
 }
 
+/// Truncates the amount to its low 64 bits. Accounting should be done on the
+/// full 128-bit value; use `u64::try_from(cycles.get())` where a 64-bit value
+/// is required, e.g., for the legacy 64-bit system API.
 impl From<Cycles> for u64 {
     fn from(val: Cycles) -> Self {
         val.0 as u64
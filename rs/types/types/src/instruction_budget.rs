@@ -0,0 +1,132 @@
+//! Overflow-safe accounting of the instructions available for execution.
+use crate::NumInstructions;
+use std::convert::TryFrom;
+
+/// The instructions available to an execution, e.g. of a message or of a
+/// round, out of a given limit.
+///
+/// All arithmetic saturates, so charging more than the remaining
+/// instructions exhausts the budget instead of wrapping around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionBudget {
+    limit: NumInstructions,
+    remaining: NumInstructions,
+}
+
+/// The budget did not cover the charged instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionBudgetExceeded {
+    pub remaining: NumInstructions,
+    pub requested: NumInstructions,
+}
+
+impl InstructionBudget {
+    /// Returns a budget with all of `limit` remaining.
+    pub fn new(limit: NumInstructions) -> Self {
+        Self {
+            limit,
+            remaining: limit,
+        }
+    }
+
+    /// Returns a budget with the instructions remaining according to the
+    /// `i64` instruction counter of a Wasm instance. A negative counter means
+    /// that no instructions remain.
+    pub fn from_wasm_counter(counter: i64) -> Self {
+        Self::new(NumInstructions::from(u64::try_from(counter).unwrap_or(0)))
+    }
+
+    /// Returns the remaining instructions as the value of an `i64`
+    /// instruction counter of a Wasm instance, capped at `i64::MAX`.
+    pub fn to_wasm_counter(&self) -> i64 {
+        i64::try_from(self.remaining.get()).unwrap_or(i64::MAX)
+    }
+
+    pub fn limit(&self) -> NumInstructions {
+        self.limit
+    }
+
+    pub fn remaining(&self) -> NumInstructions {
+        self.remaining
+    }
+
+    /// Returns the instructions consumed so far.
+    pub fn used(&self) -> NumInstructions {
+        self.limit.saturating_sub(self.remaining)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining.get() == 0
+    }
+
+    /// Charges `amount` instructions if they are covered by the budget.
+    /// Otherwise, returns an error and leaves the budget unchanged.
+    pub fn charge(&mut self, amount: NumInstructions) -> Result<(), InstructionBudgetExceeded> {
+        match self.remaining.checked_sub(amount) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                Ok(())
+            }
+            None => Err(InstructionBudgetExceeded {
+                remaining: self.remaining,
+                requested: amount,
+            }),
+        }
+    }
+
+    /// Consumes `amount` instructions, exhausting the budget if they are not
+    /// covered. Used where the instructions were already executed, e.g.
+    /// because the limit is only checked after the fact.
+    pub fn consume(&mut self, amount: NumInstructions) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn charge_never_overdraws(limit in any::<u64>(), amounts in prop::collection::vec(any::<u64>(), 0..20)) {
+            let mut budget = InstructionBudget::new(NumInstructions::from(limit));
+            for amount in amounts {
+                let before = budget;
+                match budget.charge(NumInstructions::from(amount)) {
+                    Ok(()) => prop_assert_eq!(budget.remaining().get(), before.remaining().get() - amount),
+                    Err(_) => {
+                        prop_assert!(amount > before.remaining().get());
+                        prop_assert_eq!(budget, before);
+                    }
+                }
+                prop_assert_eq!(budget.used() + budget.remaining(), budget.limit());
+            }
+        }
+
+        #[test]
+        fn consume_saturates_at_zero(limit in any::<u64>(), amounts in prop::collection::vec(any::<u64>(), 0..20)) {
+            let mut budget = InstructionBudget::new(NumInstructions::from(limit));
+            let mut total: u128 = 0;
+            for amount in amounts {
+                budget.consume(NumInstructions::from(amount));
+                total += amount as u128;
+            }
+            let expected = (limit as u128).saturating_sub(total) as u64;
+            prop_assert_eq!(budget.remaining(), NumInstructions::from(expected));
+            prop_assert_eq!(budget.is_exhausted(), expected == 0);
+        }
+
+        #[test]
+        fn wasm_counter_roundtrip(counter in any::<i64>()) {
+            let budget = InstructionBudget::from_wasm_counter(counter);
+            prop_assert_eq!(budget.to_wasm_counter(), counter.max(0));
+        }
+    }
+
+    #[test]
+    fn wasm_counter_is_capped() {
+        let budget = InstructionBudget::new(NumInstructions::from(u64::MAX));
+        assert_eq!(budget.to_wasm_counter(), i64::MAX);
+    }
+}
@@ -63,10 +63,12 @@ pub mod filetree_sync;
 pub mod funds;
 pub mod ic00;
 pub mod ingress;
+pub mod instruction_budget;
 pub mod malicious_behaviour;
 pub mod malicious_flags;
 pub mod messages;
 pub mod methods;
+pub mod node_rewards;
 pub mod nominal_cycles;
 pub mod p2p;
 pub mod registry;
@@ -78,6 +80,7 @@ pub mod transport;
 pub mod user_error;
 pub mod xnet;
 
+pub use crate::instruction_budget::InstructionBudget;
 use crate::messages::CanisterInstallMode;
 pub use crate::replica_version::ReplicaVersion;
 pub use crate::time::Time;
@@ -233,6 +236,22 @@ impl From<i64> for AccumulatedPriority {
     }
 }
 
+/// `PriorityClass` is a part of the SchedulerState. Canisters of a higher class
+/// are ordered before all canisters of a lower class in every round, so that
+/// critical system canisters are not starved by bulk workloads. Canisters of
+/// the same class are ordered by their accumulated priority.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, PartialOrd, Ord, Serialize, Hash)]
+pub enum PriorityClass {
+    Normal,
+    Elevated,
+}
+
+impl Default for PriorityClass {
+    fn default() -> Self {
+        PriorityClass::Normal
+    }
+}
+
 #[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, PartialOrd, Ord, Serialize, Hash)]
 /// Type to track how much budget the IC can spend on executing queries on
 /// canisters.  See `execution_environment/rs/query_handler.rs:Charging for
@@ -530,6 +549,9 @@ pub struct InstallCodeContext {
     pub compute_allocation: Option<ComputeAllocation>,
     pub memory_allocation: Option<MemoryAllocation>,
     pub query_allocation: QueryAllocation,
+    /// Whether to upgrade even if the stable memory schemas declared by the
+    /// installed and the new module differ.
+    pub skip_stable_compat_check: bool,
 }
 
 /// Errors that can occur when converting from (sender, [`InstallCodeArgs`]) to
@@ -639,6 +661,7 @@ impl TryFrom<(PrincipalId, InstallCodeArgs)> for InstallCodeContext {
             compute_allocation,
             memory_allocation,
             query_allocation,
+            skip_stable_compat_check: args.skip_stable_compat_check.unwrap_or(false),
         })
     }
 }
@@ -686,6 +709,7 @@ mod test {
             compute_allocation: Some(candid::Nat::from(u128::MAX)),
             memory_allocation: Some(candid::Nat::from(u128::MAX)),
             query_allocation: Some(candid::Nat::from(u128::MAX)),
+            skip_stable_compat_check: None,
         };
 
         assert!(InstallCodeContext::try_from((
@@ -14,8 +14,8 @@ pub use http::{
     Authentication, Certificate, CertificateDelegation, Delegation, HasCanisterId,
     HttpCanisterUpdate, HttpQueryResponse, HttpQueryResponseReply, HttpReadContent, HttpReadState,
     HttpReadStateResponse, HttpReply, HttpRequest, HttpRequestContent, HttpRequestEnvelope,
-    HttpResponseStatus, HttpStatusResponse, HttpSubmitContent, HttpUserQuery, RawHttpRequestVal,
-    ReadContent, ReplicaHealthStatus, SignedDelegation,
+    HttpResponseStatus, HttpStatusResponse, HttpSubmitContent, HttpUserQuery,
+    NodeAttestationStatus, RawHttpRequestVal, ReadContent, ReplicaHealthStatus, SignedDelegation,
 };
 pub use ic_base_types::CanisterInstallMode;
 use ic_base_types::{CanisterId, CanisterIdError, PrincipalId};
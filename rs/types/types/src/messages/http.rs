@@ -552,6 +552,15 @@ pub enum ReplicaHealthStatus {
     Healthy,
 }
 
+/// Whether the node submitted a hardware attestation report for its keys when
+/// it registered.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeAttestationStatus {
+    Attested,
+    NotAttested,
+}
+
 /// The response to `/api/v1/status`.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -565,6 +574,8 @@ pub struct HttpStatusResponse {
     pub impl_hash: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub replica_health_status: Option<ReplicaHealthStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation_status: Option<NodeAttestationStatus>,
 }
 
 #[cfg(test)]
@@ -704,6 +715,7 @@ mod test {
                 impl_version: Some("0.0".to_string()),
                 impl_hash: None,
                 replica_health_status: Some(ReplicaHealthStatus::Starting),
+                attestation_status: None,
             },
             Value::Map(btreemap! {
                 text("ic_api_version") => text("foobar"),
@@ -722,12 +734,14 @@ mod test {
                 impl_version: Some("0.0".to_string()),
                 impl_hash: None,
                 replica_health_status: Some(ReplicaHealthStatus::Healthy),
+                attestation_status: Some(NodeAttestationStatus::NotAttested),
             },
             Value::Map(btreemap! {
                 text("ic_api_version") => text("foobar"),
                 text("root_key") => bytes(&[1, 2, 3]),
                 text("impl_version") => text("0.0"),
                 text("replica_health_status") => text("healthy"),
+                text("attestation_status") => text("not_attested"),
             }),
         );
     }
@@ -741,6 +755,7 @@ mod test {
                 impl_version: Some("0.0".to_string()),
                 impl_hash: None,
                 replica_health_status: None,
+                attestation_status: None,
             },
             Value::Map(btreemap! {
                 text("ic_api_version") => text("foobar"),
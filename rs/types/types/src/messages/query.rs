@@ -1,7 +1,7 @@
 use crate::{
     messages::{
-        message_id::hash_of_map, HasCanisterId, HttpHandlerError, HttpUserQuery, MessageId,
-        RawHttpRequestVal,
+        message_id::hash_of_map, HasCanisterId, HttpHandlerError, HttpRequestContent,
+        HttpUserQuery, MessageId, RawHttpRequestVal,
     },
     CanisterId, PrincipalId, UserId,
 };
@@ -70,6 +70,24 @@ impl HasCanisterId for UserQuery {
     }
 }
 
+impl HttpRequestContent for UserQuery {
+    fn id(&self) -> MessageId {
+        UserQuery::id(self)
+    }
+
+    fn sender(&self) -> UserId {
+        self.source
+    }
+
+    fn ingress_expiry(&self) -> u64 {
+        self.ingress_expiry
+    }
+
+    fn nonce(&self) -> Option<Vec<u8>> {
+        self.nonce.clone()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::super::{Blob, HttpUserQuery};
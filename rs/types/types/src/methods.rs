@@ -89,6 +89,9 @@ impl From<&WasmMethod> for pb::WasmMethod {
                     SystemMethod::CanisterPostUpgrade => PbSystemMethod::CanisterPostUpgrade,
                     SystemMethod::CanisterInspectMessage => PbSystemMethod::CanisterInspectMessage,
                     SystemMethod::CanisterHeartbeat => PbSystemMethod::CanisterHeartbeat,
+                    SystemMethod::CanisterOnLowWasmMemory => {
+                        PbSystemMethod::CanisterOnLowWasmMemory
+                    }
                     SystemMethod::Empty => PbSystemMethod::Empty,
                 } as i32)),
             },
@@ -122,6 +125,9 @@ impl TryFrom<pb::WasmMethod> for WasmMethod {
                     PbSystemMethod::CanisterPostUpgrade => SystemMethod::CanisterPostUpgrade,
                     PbSystemMethod::CanisterInspectMessage => SystemMethod::CanisterInspectMessage,
                     PbSystemMethod::CanisterHeartbeat => SystemMethod::CanisterHeartbeat,
+                    PbSystemMethod::CanisterOnLowWasmMemory => {
+                        SystemMethod::CanisterOnLowWasmMemory
+                    }
                     PbSystemMethod::Empty => SystemMethod::Empty,
                 }))
             }
@@ -145,6 +151,9 @@ pub enum SystemMethod {
     CanisterInspectMessage,
     /// A system method that is run at regular intervals for cron support.
     CanisterHeartbeat,
+    /// A system method that is run when the Wasm memory of the canister runs
+    /// low, see `SystemTask::OnLowWasmMemory`.
+    CanisterOnLowWasmMemory,
     /// This is introduced as temporary scaffolding to aid in construction of
     /// the initial ExecutionState. This isn't used to execute any actual wasm
     /// but as a way to get to the wasm embedder from execution. Eventually, we
@@ -164,6 +173,7 @@ impl TryFrom<&str> for SystemMethod {
             "canister_start" => Ok(SystemMethod::CanisterStart),
             "canister_inspect_message" => Ok(SystemMethod::CanisterInspectMessage),
             "canister_heartbeat" => Ok(SystemMethod::CanisterHeartbeat),
+            "canister_on_low_wasm_memory" => Ok(SystemMethod::CanisterOnLowWasmMemory),
             "empty" => Ok(SystemMethod::Empty),
             _ => Err(format!("Cannot convert {} to SystemMethod.", value)),
         }
@@ -179,6 +189,7 @@ impl fmt::Display for SystemMethod {
             Self::CanisterStart => write!(f, "canister_start"),
             Self::CanisterInspectMessage => write!(f, "canister_inspect_message"),
             Self::CanisterHeartbeat => write!(f, "canister_heartbeat"),
+            Self::CanisterOnLowWasmMemory => write!(f, "canister_on_low_wasm_memory"),
             Self::Empty => write!(f, "empty"),
         }
     }
@@ -307,6 +318,7 @@ impl FuncRef {
             | Self::Method(WasmMethod::System(SystemMethod::CanisterPreUpgrade))
             | Self::Method(WasmMethod::System(SystemMethod::CanisterPostUpgrade))
             | Self::Method(WasmMethod::System(SystemMethod::CanisterHeartbeat))
+            | Self::Method(WasmMethod::System(SystemMethod::CanisterOnLowWasmMemory))
             | Self::UpdateClosure(_) => true,
             Self::QueryClosure(_)
             | Self::Method(WasmMethod::Query(_))
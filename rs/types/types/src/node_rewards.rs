@@ -0,0 +1,39 @@
+//! Types used by nodes to report their up-time to the NNS for node rewards.
+
+use crate::crypto::{BasicSigOf, SignedBytesWithoutDomainSeparator};
+use crate::{NodeId, RegistryVersion, Time};
+use serde::{Deserialize, Serialize};
+
+/// The content of a heartbeat attestation that a node periodically reports to
+/// the NNS as evidence that it is up and running.
+///
+/// The content is signed with the node signing key registered for `node_id` at
+/// `registry_version`, so that the receiving canister can verify it without
+/// trusting the caller of the update.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NodeHeartbeatContent {
+    /// The node issuing the heartbeat.
+    pub node_id: NodeId,
+    /// The registry version at which the node signing key can be looked up.
+    pub registry_version: RegistryVersion,
+    /// The time at which the heartbeat was created.
+    pub timestamp: Time,
+    /// Strictly increasing for every heartbeat created by a node manager
+    /// process. Resets to zero when the node manager restarts.
+    pub sequence_number: u64,
+    /// Seconds since the node manager process started.
+    pub uptime_seconds: u64,
+}
+
+impl SignedBytesWithoutDomainSeparator for NodeHeartbeatContent {
+    fn as_signed_bytes_without_domain_separator(&self) -> Vec<u8> {
+        serde_cbor::to_vec(&self).unwrap()
+    }
+}
+
+/// A heartbeat attestation signed with the node signing key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SignedNodeHeartbeat {
+    pub content: NodeHeartbeatContent,
+    pub signature: BasicSigOf<NodeHeartbeatContent>,
+}
@@ -55,6 +55,12 @@ pub struct TransportConfig {
 
     /// P2P specific config. In future, this will be made more generic.
     pub p2p_flows: Vec<TransportFlowConfig>,
+
+    /// If set, all flows with a peer are multiplexed over a single connection
+    /// on the server port of the first flow, instead of a connection per
+    /// flow. All nodes of a subnet must use the same setting.
+    #[serde(default)]
+    pub multiplex_flows: bool,
 }
 
 /// Per-flow config
@@ -42,8 +42,23 @@ pub enum WasmValidationError {
     TooManyGlobals { defined: usize, allowed: usize },
     /// Module contains too many functions.
     TooManyFunctions { defined: usize, allowed: usize },
+    /// Module defines or imports more than one memory, which requires the
+    /// multi-memory proposal that is not supported.
+    TooManyMemories { defined: usize, allowed: usize },
     /// Module defines an invalid index for a local function.
     InvalidFunctionIndex { index: usize, import_count: usize },
+    /// Module declares a start function that is not defined in the module.
+    InvalidStartFunction { index: usize, import_count: usize },
+    /// Module defines or imports a table whose initial size is too large.
+    TableTooLarge { size: usize, allowed: usize },
+    /// Module contains too many element segments.
+    TooManyElementSegments { defined: usize, allowed: usize },
+    /// The element segments of the module contain too many entries in total.
+    ElementSegmentsTooLarge { size: usize, allowed: usize },
+    /// Module contains too many data segments.
+    TooManyDataSegments { defined: usize, allowed: usize },
+    /// The data segments of the module contain too many bytes in total.
+    DataSegmentsTooLarge { size: usize, allowed: usize },
 }
 
 impl std::fmt::Display for WasmValidationError {
@@ -77,6 +92,12 @@ impl std::fmt::Display for WasmValidationError {
                 "Wasm module defined {} functions which exceeds the maximum number allowed {}.",
                 defined, allowed
             ),
+            Self::TooManyMemories { defined, allowed } => write!(
+                f,
+                "Wasm module defined {} memories which exceeds the maximum number allowed {}. \
+                 Multiple memories are not supported.",
+                defined, allowed
+            ),
             Self::InvalidFunctionIndex {
                 index,
                 import_count,
@@ -85,6 +106,40 @@ impl std::fmt::Display for WasmValidationError {
                 "Function has index {} but should start from {}.",
                 index, import_count
             ),
+            Self::InvalidStartFunction {
+                index,
+                import_count,
+            } => write!(
+                f,
+                "Start function has index {} which refers to an imported function, \
+                 the start function must be defined in the module (index {} or higher).",
+                index, import_count
+            ),
+            Self::TableTooLarge { size, allowed } => write!(
+                f,
+                "Wasm module has a table with initial size {} which exceeds the maximum size allowed {}.",
+                size, allowed
+            ),
+            Self::TooManyElementSegments { defined, allowed } => write!(
+                f,
+                "Wasm module defined {} element segments which exceeds the maximum number allowed {}.",
+                defined, allowed
+            ),
+            Self::ElementSegmentsTooLarge { size, allowed } => write!(
+                f,
+                "Wasm module has element segments with {} entries in total which exceeds the maximum allowed {}.",
+                size, allowed
+            ),
+            Self::TooManyDataSegments { defined, allowed } => write!(
+                f,
+                "Wasm module defined {} data segments which exceeds the maximum number allowed {}.",
+                defined, allowed
+            ),
+            Self::DataSegmentsTooLarge { size, allowed } => write!(
+                f,
+                "Wasm module has data segments with {} bytes in total which exceeds the maximum allowed {}.",
+                size, allowed
+            ),
         }
     }
 }
@@ -125,6 +180,8 @@ pub enum WasmEngineError {
     FailedToInstantiateModule,
     FailedToSetAsyncStack,
     FailedToSetWasmStack,
+    FailedToSerializeModule,
+    FailedToDeserializeModule,
 }
 
 impl std::fmt::Display for WasmEngineError {
@@ -142,6 +199,12 @@ impl std::fmt::Display for WasmEngineError {
             Self::FailedToSetAsyncStack => {
                 write!(f, "Failed to set async stack")
             }
+            Self::FailedToSerializeModule => {
+                write!(f, "Failed to serialize module")
+            }
+            Self::FailedToDeserializeModule => {
+                write!(f, "Failed to deserialize module")
+            }
         }
     }
 }
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+/// A class of Wasm instructions whose cost can be overridden as a whole.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum OpcodeClass {
+    /// `memory.grow`.
+    MemoryGrow,
+    /// Instructions operating on `f32` and `f64` values, except for loads
+    /// and stores.
+    Float,
+    /// Memory loads.
+    Load,
+    /// Memory stores.
+    Store,
+    /// Direct and indirect function calls.
+    Call,
+}
+
+impl OpcodeClass {
+    /// The name of the class, as used in the subnet record.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpcodeClass::MemoryGrow => "memory_grow",
+            OpcodeClass::Float => "float",
+            OpcodeClass::Load => "load",
+            OpcodeClass::Store => "store",
+            OpcodeClass::Call => "call",
+        }
+    }
+}
+
+impl fmt::Display for OpcodeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for OpcodeClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory_grow" => Ok(OpcodeClass::MemoryGrow),
+            "float" => Ok(OpcodeClass::Float),
+            "load" => Ok(OpcodeClass::Load),
+            "store" => Ok(OpcodeClass::Store),
+            "call" => Ok(OpcodeClass::Call),
+            _ => Err(format!("Unknown opcode class: {}", s)),
+        }
+    }
+}
+
+/// Overrides of the cost of classes of Wasm instructions.
+///
+/// The overrides are configured in the subnet record and take precedence over
+/// the default instruction costs when canister code is instrumented. They
+/// allow changing the pricing of instructions without a replica release.
+///
+/// As the overrides determine the instrumented code, compiled modules must be
+/// cached per set of overrides.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InstructionCostOverrides(BTreeMap<OpcodeClass, u64>);
+
+impl InstructionCostOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cost(mut self, class: OpcodeClass, cost: u64) -> Self {
+        self.0.insert(class, cost);
+        self
+    }
+
+    /// Returns the cost of instructions of the given class, if overridden.
+    pub fn get(&self, class: OpcodeClass) -> Option<u64> {
+        self.0.get(&class).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (OpcodeClass, u64)> + '_ {
+        self.0.iter().map(|(class, cost)| (*class, *cost))
+    }
+}
+
+impl FromIterator<(OpcodeClass, u64)> for InstructionCostOverrides {
+    fn from_iter<I: IntoIterator<Item = (OpcodeClass, u64)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
@@ -1,9 +1,11 @@
 //! A crate containing types useful for working with Wasm modules on the
 //! Internet Computer.
 mod errors;
+mod instruction_costs;
 
 pub use errors::{ParityWasmError, WasmEngineError, WasmInstrumentationError, WasmValidationError};
 use ic_utils::byte_slice_fmt::truncate_and_format;
+pub use instruction_costs::{InstructionCostOverrides, OpcodeClass};
 use std::{
     fmt,
     path::{Path, PathBuf},
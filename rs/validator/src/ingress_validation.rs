@@ -39,15 +39,20 @@ pub fn validate_request<C: HttpRequestContent + HasCanisterId>(
         registry_version,
         malicious_flags,
     )
-    .and_then(|targets| {
-        if targets.contains(&request.content().canister_id()) {
-            Ok(())
-        } else {
-            Err(CanisterNotInDelegationTargets(
-                request.content().canister_id(),
-            ))
-        }
-    })
+    .and_then(|targets| validate_target(&targets, &request.content().canister_id()))
+}
+
+/// Checks that `canister_id` is among the `targets` a request is authorized
+/// to act on, as returned by [get_authorized_canisters].
+pub fn validate_target(
+    targets: &CanisterIdSet,
+    canister_id: &CanisterId,
+) -> Result<(), RequestValidationError> {
+    if targets.contains(canister_id) {
+        Ok(())
+    } else {
+        Err(CanisterNotInDelegationTargets(*canister_id))
+    }
 }
 
 /// Returns the set of canisters that the request is authorized to act on.
@@ -202,25 +207,44 @@ fn validate_ingress_expiry<C: HttpRequestContent>(
 // Check if any of the sender delegation has expired with respect to the
 // `current_time`, and return an error if so.
 fn validate_sender_delegation_expiry(
-    sender_delegation: &Option<Vec<SignedDelegation>>,
+    signed_delegations: &[SignedDelegation],
     current_time: Time,
 ) -> Result<(), RequestValidationError> {
-    if let Some(delegations) = &sender_delegation {
-        for delegation in delegations.iter() {
-            let expiry = delegation.delegation().expiration();
-            if delegation.delegation().expiration() < current_time {
-                return Err(InvalidDelegationExpiry(format!(
-                    "Specified sender delegation has expired:\n\
-                     Provided expiry:    {}\n\
-                     Local replica time: {}",
-                    expiry, current_time,
-                )));
-            }
+    for delegation in signed_delegations.iter() {
+        let expiry = delegation.delegation().expiration();
+        if expiry < current_time {
+            return Err(InvalidDelegationExpiry(format!(
+                "Specified sender delegation has expired:\n\
+                 Provided expiry:    {}\n\
+                 Local replica time: {}",
+                expiry, current_time,
+            )));
         }
     }
     Ok(())
 }
 
+// Validates a chain of sender delegations starting at `signer_pubkey`: none of
+// the delegations may have expired with respect to `current_time` and each must
+// be signed by the key delegated to by its predecessor. Returns the public key
+// at the end of the chain and the set of canisters the chain restricts the
+// request to.
+fn validate_delegation_chain(
+    validator: &dyn IngressSigVerifier,
+    signer_pubkey: &[u8],
+    signed_delegations: &[SignedDelegation],
+    current_time: Time,
+    registry_version: RegistryVersion,
+) -> Result<(Vec<u8>, CanisterIdSet), RequestValidationError> {
+    validate_sender_delegation_expiry(signed_delegations, current_time)?;
+    validate_delegations(
+        validator,
+        signed_delegations,
+        signer_pubkey.to_vec(),
+        registry_version,
+    )
+}
+
 // Verifies that the user id matches the public key.  Returns an error if not.
 fn validate_user_id(sender_pubkey: &[u8], id: &UserId) -> Result<(), RequestValidationError> {
     if id.get_ref() == &PrincipalId::new_self_authenticating(sender_pubkey) {
@@ -238,17 +262,11 @@ fn validate_signature(
     current_time: Time,
     registry_version: RegistryVersion,
 ) -> Result<CanisterIdSet, RequestValidationError> {
-    validate_sender_delegation_expiry(&signature.sender_delegation, current_time)?;
-    let empty_vec = Vec::new();
-    let signed_delegations = match &signature.sender_delegation {
-        None => &empty_vec,
-        Some(delegations) => delegations,
-    };
-
-    let (pubkey, targets) = validate_delegations(
+    let (pubkey, targets) = validate_delegation_chain(
         validator,
-        signed_delegations.as_slice(),
-        signature.signer_pubkey.clone(),
+        &signature.signer_pubkey,
+        signature.sender_delegation.as_deref().unwrap_or_default(),
+        current_time,
         registry_version,
     )?;
 
@@ -908,4 +926,15 @@ mod tests {
             Ok(CanisterIdSet::All)
         );
     }
+
+    #[test]
+    fn validate_target_checks_membership() {
+        let targets = CanisterIdSet::Some(btreeset! {canister_test_id(1)});
+        assert!(validate_target(&targets, &canister_test_id(1)).is_ok());
+        assert_matches!(
+            validate_target(&targets, &canister_test_id(2)),
+            Err(CanisterNotInDelegationTargets(canister_id)) if canister_id == canister_test_id(2)
+        );
+        assert!(validate_target(&CanisterIdSet::All, &canister_test_id(2)).is_ok());
+    }
 }
@@ -8,6 +8,6 @@ mod ingress_validation;
 mod webauthn;
 
 pub use ingress_validation::{
-    get_authorized_canisters, validate_request, AuthenticationError, CanisterIdSet,
-    RequestValidationError,
+    get_authorized_canisters, validate_request, validate_target, AuthenticationError,
+    CanisterIdSet, RequestValidationError,
 };
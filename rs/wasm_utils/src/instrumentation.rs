@@ -82,18 +82,76 @@
 //! blocks to optimize for performance. The maximal overflow in that case is
 //! bound by the length of the longest execution path consisting of
 //! non-reentrant basic blocks.
+//!
+//! Optionally, [`instrument_with_write_barriers`] additionally inserts a write
+//! barrier in front of every store instruction. The barrier records the
+//! (4 KiB) pages written by the store in a bitmap of exported `i64` globals
+//! named `canister dirty_pages_<i>`. Pages beyond the capacity of the bitmap
+//! set the exported `i32` global `canister dirty_pages_overflow` instead. This
+//! allows the embedder to learn the dirty pages of small heaps without
+//! handling a signal on the first write to every page. The barriers are
+//! inserted after the metering, so they are not charged to the canister. The
+//! bitmap is only meaningful within a single execution and is not persisted
+//! with the other exported globals, see [`is_write_barrier_export`].
+//!
+//! Mutable globals that the module does not export are exported under
+//! internal names so that their values are persisted between executions.
+//! Mutable globals that the module itself exports with a name starting with
+//! [`PERSISTENT_GLOBAL_PREFIX`] are additionally carried over to the new
+//! module when the canister is upgraded, see [`persistent_globals`].
 
 use crate::errors::into_parity_wasm_error;
-use ic_wasm_types::{BinaryEncodedWasm, WasmInstrumentationError};
+use ic_wasm_types::{
+    BinaryEncodedWasm, InstructionCostOverrides, OpcodeClass, WasmInstrumentationError,
+};
 use parity_wasm::builder;
 use parity_wasm::elements::{
-    BlockType, ExportEntry, FuncBody, FunctionType, GlobalEntry, GlobalType, InitExpr, Instruction,
-    Instructions, Internal, Local, Module, Section, Type, ValueType,
+    BlockType, ExportEntry, FuncBody, FunctionType, GlobalEntry, GlobalType, ImportCountType,
+    InitExpr, Instruction, Instructions, Internal, Local, Module, Section, Type, ValueType,
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 const UPDATE_AVAILABLE_MEMORY_FN: u32 = 1; // because it's the second import
 
+/// The version of the instrumentation. It must be increased whenever the code
+/// produced by [`instrument`] or [`instrument_with_write_barriers`] changes
+/// for some input, so that compiled artifacts of modules instrumented by an
+/// older replica are not loaded.
+pub const INSTRUMENTATION_VERSION: u32 = 1;
+
+/// Log2 of the size of the pages recorded by write barriers, which matches the
+/// size of the OS pages tracked by the memory tracker.
+pub const WRITE_BARRIER_PAGE_SIZE_LOG2: u32 = 12;
+/// The number of `i64` globals making up the write barrier bitmap.
+pub const WRITE_BARRIER_BITMAP_WORDS: usize = 16;
+/// The number of pages that can be recorded in the write barrier bitmap.
+pub const WRITE_BARRIER_MAX_PAGES: u64 = 64 * WRITE_BARRIER_BITMAP_WORDS as u64;
+/// The name of the global that is set if a page beyond
+/// `WRITE_BARRIER_MAX_PAGES` was written.
+pub const WRITE_BARRIER_OVERFLOW_EXPORT: &str = "canister dirty_pages_overflow";
+
+/// The prefix of the names of exported mutable globals that keep their value
+/// across upgrades of the canister.
+pub const PERSISTENT_GLOBAL_PREFIX: &str = "__persistent_";
+/// The prefix of the names under which instrumentation exports the mutable
+/// globals the module does not export itself.
+const MUTABLE_GLOBAL_EXPORT_PREFIX: &str = "__persistent_mutable_global_";
+
+/// The prefix of the names of all globals exported for the write barriers.
+const WRITE_BARRIER_EXPORT_PREFIX: &str = "canister dirty_pages_";
+
+/// Returns the name of the global holding the given word of the write barrier
+/// bitmap.
+pub fn write_barrier_bitmap_export(word: usize) -> String {
+    format!("{}{}", WRITE_BARRIER_EXPORT_PREFIX, word)
+}
+
+/// Returns true if `name` is the name of one of the globals exported for the
+/// write barriers, i.e., a word of the bitmap or the overflow flag.
+pub fn is_write_barrier_export(name: &str) -> bool {
+    name.starts_with(WRITE_BARRIER_EXPORT_PREFIX)
+}
+
 // Converts a Wasm instruction to a string mnemonic.
 // TODO(EXC-221): Consider optimizing this to "cache" results, so we don't have
 // to extract the mnemomic each time this function is called.
@@ -105,14 +163,60 @@ fn instruction_to_mnemonic(i: &Instruction) -> String {
         .to_string()
 }
 
+// Returns the class of a Wasm instruction whose cost can be overridden, if
+// any.
+fn opcode_class(i: &Instruction) -> Option<OpcodeClass> {
+    use Instruction::*;
+    match i {
+        GrowMemory(_) => Some(OpcodeClass::MemoryGrow),
+        Call(_) | CallIndirect(_, _) => Some(OpcodeClass::Call),
+        I32Load(_, _)
+        | I64Load(_, _)
+        | F32Load(_, _)
+        | F64Load(_, _)
+        | I32Load8S(_, _)
+        | I32Load8U(_, _)
+        | I32Load16S(_, _)
+        | I32Load16U(_, _)
+        | I64Load8S(_, _)
+        | I64Load8U(_, _)
+        | I64Load16S(_, _)
+        | I64Load16U(_, _)
+        | I64Load32S(_, _)
+        | I64Load32U(_, _) => Some(OpcodeClass::Load),
+        I32Store(_, _)
+        | I64Store(_, _)
+        | F32Store(_, _)
+        | F64Store(_, _)
+        | I32Store8(_, _)
+        | I32Store16(_, _)
+        | I64Store8(_, _)
+        | I64Store16(_, _)
+        | I64Store32(_, _) => Some(OpcodeClass::Store),
+        _ => {
+            let mnemonic = instruction_to_mnemonic(i);
+            if mnemonic.starts_with("f32.") || mnemonic.starts_with("f64.") {
+                Some(OpcodeClass::Float)
+            } else {
+                None
+            }
+        }
+    }
+}
+
 /// The metering can be configured by providing a cost-per-instruction table and
 /// the default cost for an instruction in case it's not present in the cost
 /// table.
+///
+/// Overrides of the cost of whole classes of instructions take precedence over
+/// both.
 pub struct InstructionCostTable {
     // mapping of instruction mnemonic to its cost
     instruction_cost: HashMap<String, u64>,
     // default cost of an instruction (if not present in the cost table)
     default_cost: u64,
+    // costs of classes of instructions (e.g. configured in the registry)
+    overrides: InstructionCostOverrides,
 }
 
 impl InstructionCostTable {
@@ -130,9 +234,19 @@ impl InstructionCostTable {
         self
     }
 
-    // Returns the cost of a Wasm instruction from the cost table or the default
-    // cost if the instruction is not in the cost table.
+    pub fn with_overrides(mut self, overrides: InstructionCostOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    // Returns the cost of a Wasm instruction from the overrides, the cost table
+    // or the default cost if the instruction is in neither of them.
     fn cost(&self, i: &Instruction) -> u64 {
+        if !self.overrides.is_empty() {
+            if let Some(cost) = opcode_class(i).and_then(|class| self.overrides.get(class)) {
+                return cost;
+            }
+        }
         let mnemonic = instruction_to_mnemonic(i);
         *self
             .instruction_cost
@@ -161,6 +275,7 @@ impl Default for InstructionCostTable {
         Self {
             default_cost: 1,
             instruction_cost,
+            overrides: InstructionCostOverrides::default(),
         }
     }
 }
@@ -334,6 +449,23 @@ pub struct InstrumentationOutput {
 pub fn instrument(
     wasm: &BinaryEncodedWasm,
     instruction_cost_table: &InstructionCostTable,
+) -> Result<InstrumentationOutput, WasmInstrumentationError> {
+    instrument_module(wasm, instruction_cost_table, false)
+}
+
+/// Like [`instrument`], but also inserts write barriers recording the pages
+/// written by the module in the write barrier bitmap.
+pub fn instrument_with_write_barriers(
+    wasm: &BinaryEncodedWasm,
+    instruction_cost_table: &InstructionCostTable,
+) -> Result<InstrumentationOutput, WasmInstrumentationError> {
+    instrument_module(wasm, instruction_cost_table, true)
+}
+
+fn instrument_module(
+    wasm: &BinaryEncodedWasm,
+    instruction_cost_table: &InstructionCostTable,
+    write_barriers: bool,
 ) -> Result<InstrumentationOutput, WasmInstrumentationError> {
     let module = parity_wasm::deserialize_buffer::<Module>(wasm.as_slice()).map_err(|err| {
         WasmInstrumentationError::ParityDeserializeError(into_parity_wasm_error(err))
@@ -349,6 +481,9 @@ pub fn instrument(
     let instructions_counter_ix = num_globals;
     let set_counter_fn = num_functions;
     let get_counter_fn = num_functions + 1;
+    let write_barrier_fn = num_functions + 2;
+    let write_barrier_bitmap_ix = num_globals + 1;
+    let write_barrier_overflow_ix = write_barrier_bitmap_ix + WRITE_BARRIER_BITMAP_WORDS as u32;
     let start_fn_ix = module.start_section();
     if start_fn_ix.is_some() {
         module.clear_start_section();
@@ -391,6 +526,9 @@ pub fn instrument(
             let func_bodies = module.code_section_mut().unwrap().bodies_mut();
             for (func_ix, func_type) in func_types.into_iter().enumerate() {
                 inject_update_available_memory(&mut func_bodies[func_ix], &func_type);
+                if write_barriers {
+                    inject_write_barriers(&mut func_bodies[func_ix], &func_type, write_barrier_fn);
+                }
             }
         }
     }
@@ -450,12 +588,38 @@ pub fn instrument(
     }
 
     // push the instructions counter
-    let module = mbuilder
-        .with_global(GlobalEntry::new(
-            GlobalType::new(ValueType::I64, true),
-            InitExpr::new(vec![Instruction::I64Const(0), Instruction::End]),
-        ))
-        .build();
+    mbuilder = mbuilder.with_global(GlobalEntry::new(
+        GlobalType::new(ValueType::I64, true),
+        InitExpr::new(vec![Instruction::I64Const(0), Instruction::End]),
+    ));
+
+    if write_barriers {
+        // push the write barrier function, the bitmap and the overflow flag
+        mbuilder.push_function(write_barrier_function(
+            write_barrier_bitmap_ix,
+            write_barrier_overflow_ix,
+        ));
+        for word in 0..WRITE_BARRIER_BITMAP_WORDS {
+            mbuilder = mbuilder.with_global(GlobalEntry::new(
+                GlobalType::new(ValueType::I64, true),
+                InitExpr::new(vec![Instruction::I64Const(0), Instruction::End]),
+            ));
+            mbuilder.push_export(ExportEntry::new(
+                write_barrier_bitmap_export(word),
+                Internal::Global(write_barrier_bitmap_ix + word as u32),
+            ));
+        }
+        mbuilder = mbuilder.with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, true),
+            InitExpr::new(vec![Instruction::I32Const(0), Instruction::End]),
+        ));
+        mbuilder.push_export(ExportEntry::new(
+            WRITE_BARRIER_OVERFLOW_EXPORT.to_string(),
+            Internal::Global(write_barrier_overflow_ix),
+        ));
+    }
+
+    let module = mbuilder.build();
 
     let exports = module
         .export_section()
@@ -492,6 +656,52 @@ pub fn instrument(
     })
 }
 
+/// Returns the persistent globals of an instrumented module, i.e. the mutable
+/// globals the module exports under a name starting with
+/// [`PERSISTENT_GLOBAL_PREFIX`], keyed by their name.
+///
+/// The values are the positions of the globals among all exported globals,
+/// which is the order in which their values are kept in the execution state.
+/// Globals exported by the instrumentation are never persistent.
+pub fn persistent_globals(
+    wasm: &BinaryEncodedWasm,
+) -> Result<BTreeMap<String, usize>, WasmInstrumentationError> {
+    let module = parity_wasm::deserialize_buffer::<Module>(wasm.as_slice()).map_err(|err| {
+        WasmInstrumentationError::ParityDeserializeError(into_parity_wasm_error(err))
+    })?;
+    let num_imported_globals = module.import_count(ImportCountType::Global);
+    let global_entries = module
+        .global_section()
+        .map(|section| section.entries())
+        .unwrap_or_default();
+    let is_mutable = |ix: u32| {
+        (ix as usize)
+            .checked_sub(num_imported_globals)
+            .and_then(|ix| global_entries.get(ix))
+            .map_or(false, |entry| entry.global_type().is_mutable())
+    };
+
+    let mut result = BTreeMap::new();
+    let global_exports = module
+        .export_section()
+        .map(|section| section.entries())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|export| match export.internal() {
+            Internal::Global(ix) => Some((export.field(), *ix)),
+            _ => None,
+        });
+    for (position, (name, ix)) in global_exports.enumerate() {
+        if name.starts_with(PERSISTENT_GLOBAL_PREFIX)
+            && !name.starts_with(MUTABLE_GLOBAL_EXPORT_PREFIX)
+            && is_mutable(ix)
+        {
+            result.insert(name.to_string(), position);
+        }
+    }
+    Ok(result)
+}
+
 // Represents a hint about the context of each basic code block in Wasm.
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum Scope {
@@ -605,6 +815,178 @@ fn inject_update_available_memory(func_body: &mut FuncBody, func_type: &Function
     }
 }
 
+// Returns the type of the stored value, the number of written bytes and the
+// static offset of a store instruction.
+fn store_access(i: &Instruction) -> Option<(ValueType, u64, u32)> {
+    use Instruction::*;
+    match i {
+        I32Store(_, offset) => Some((ValueType::I32, 4, *offset)),
+        I64Store(_, offset) => Some((ValueType::I64, 8, *offset)),
+        F32Store(_, offset) => Some((ValueType::F32, 4, *offset)),
+        F64Store(_, offset) => Some((ValueType::F64, 8, *offset)),
+        I32Store8(_, offset) => Some((ValueType::I32, 1, *offset)),
+        I32Store16(_, offset) => Some((ValueType::I32, 2, *offset)),
+        I64Store8(_, offset) => Some((ValueType::I64, 1, *offset)),
+        I64Store16(_, offset) => Some((ValueType::I64, 2, *offset)),
+        I64Store32(_, offset) => Some((ValueType::I64, 4, *offset)),
+        _ => None,
+    }
+}
+
+// Inserts a call to the write barrier function in front of every store
+// instruction of the function. As the address of the store is below the stored
+// value on the stack, both are cached in locals:
+//
+// ```wasm
+// local.set $value
+// local.tee $address
+// i64.extend_i32_u
+// i64.const <offset>
+// i64.add
+// i64.const <size>
+// call $write_barrier
+// local.get $address
+// local.get $value
+// <store>
+// ```
+fn inject_write_barriers(
+    func_body: &mut FuncBody,
+    func_type: &FunctionType,
+    write_barrier_fn: u32,
+) {
+    let value_types = [
+        ValueType::I32,
+        ValueType::I64,
+        ValueType::F32,
+        ValueType::F64,
+    ];
+    if !func_body
+        .code()
+        .elements()
+        .iter()
+        .any(|i| store_access(i).is_some())
+    {
+        return;
+    }
+
+    // We inject a local to cache the address and one local per value type to
+    // cache the stored value.
+    let n_locals: u32 = func_body.locals().iter().map(Local::count).sum();
+    let address_local_ix = func_type.params().len() as u32 + n_locals;
+    func_body.locals_mut().push(Local::new(1, ValueType::I32));
+    for value_type in value_types.iter() {
+        func_body.locals_mut().push(Local::new(1, *value_type));
+    }
+    let value_local_ix = |value_type: ValueType| {
+        let position = value_types.iter().position(|t| *t == value_type).unwrap();
+        address_local_ix + 1 + position as u32
+    };
+
+    let code = func_body.code_mut();
+    let orig_elems = std::mem::replace(code.elements_mut(), Vec::new());
+    let mut elems: Vec<Instruction> = Vec::with_capacity(orig_elems.len());
+    for instr in orig_elems {
+        if let Some((value_type, size, offset)) = store_access(&instr) {
+            elems.extend_from_slice(&[
+                Instruction::SetLocal(value_local_ix(value_type)),
+                Instruction::TeeLocal(address_local_ix),
+                Instruction::I64ExtendUI32,
+                Instruction::I64Const(offset as i64),
+                Instruction::I64Add,
+                Instruction::I64Const(size as i64),
+                Instruction::Call(write_barrier_fn),
+                Instruction::GetLocal(address_local_ix),
+                Instruction::GetLocal(value_local_ix(value_type)),
+            ]);
+        }
+        elems.push(instr);
+    }
+    *code.elements_mut() = elems;
+}
+
+// Builds the write barrier function `(param $start i64) (param $size i64)`,
+// which marks all pages overlapping with the given byte range in the bitmap.
+// The bitmap word of a page is selected by a sequence of comparisons, which is
+// cheap for the small number of words.
+fn write_barrier_function(bitmap_ix: u32, overflow_ix: u32) -> builder::FunctionDefinition {
+    use Instruction::*;
+    const START: u32 = 0;
+    const SIZE: u32 = 1;
+    const PAGE: u32 = 2;
+    const LAST_PAGE: u32 = 3;
+    let page_size_log2 = WRITE_BARRIER_PAGE_SIZE_LOG2 as i64;
+
+    let mut instructions = vec![
+        GetLocal(START),
+        I64Const(page_size_log2),
+        I64ShrU,
+        SetLocal(PAGE),
+        GetLocal(START),
+        GetLocal(SIZE),
+        I64Add,
+        I64Const(1),
+        I64Sub,
+        I64Const(page_size_log2),
+        I64ShrU,
+        SetLocal(LAST_PAGE),
+        Loop(BlockType::NoResult),
+        GetLocal(PAGE),
+        I64Const(WRITE_BARRIER_MAX_PAGES as i64),
+        I64GeU,
+        If(BlockType::NoResult),
+        I32Const(1),
+        SetGlobal(overflow_ix),
+        Return,
+        End,
+    ];
+    for word in 0..WRITE_BARRIER_BITMAP_WORDS as u32 {
+        instructions.extend_from_slice(&[
+            GetLocal(PAGE),
+            I64Const(6),
+            I64ShrU,
+            I64Const(word as i64),
+            I64Eq,
+            If(BlockType::NoResult),
+            GetGlobal(bitmap_ix + word),
+            I64Const(1),
+            GetLocal(PAGE),
+            I64Const(63),
+            I64And,
+            I64Shl,
+            I64Or,
+            SetGlobal(bitmap_ix + word),
+            End,
+        ]);
+    }
+    instructions.extend_from_slice(&[
+        GetLocal(PAGE),
+        GetLocal(LAST_PAGE),
+        I64LtU,
+        If(BlockType::NoResult),
+        GetLocal(PAGE),
+        I64Const(1),
+        I64Add,
+        SetLocal(PAGE),
+        Br(1),
+        End,
+        End,
+        End,
+    ]);
+
+    builder::function()
+        .with_signature(
+            builder::signature()
+                .with_param(ValueType::I64)
+                .with_param(ValueType::I64)
+                .build_sig(),
+        )
+        .body()
+        .with_locals(vec![Local::new(2, ValueType::I64)])
+        .with_instructions(Instructions::new(instructions))
+        .build()
+        .build()
+}
+
 // This function scans through the Wasm code and creates an injection point
 // at the beginning of every basic block (straight-line sequence of instructions
 // with no branches). An injection point contains a "hint" about the context
@@ -755,7 +1137,7 @@ fn export_mutable_globals(module: Module) -> Module {
         for (ix, (mutable, exported)) in mutable_exported.into_iter().enumerate() {
             if mutable && !exported {
                 mbuilder.push_export(ExportEntry::new(
-                    format!("__persistent_mutable_global_{}", ix),
+                    format!("{}{}", MUTABLE_GLOBAL_EXPORT_PREFIX, ix),
                     Internal::Global(ix as u32),
                 ));
             }
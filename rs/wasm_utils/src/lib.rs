@@ -2,6 +2,7 @@ use wasmtime::Config;
 
 mod errors;
 pub mod instrumentation;
+pub mod stable_compat;
 pub mod validation;
 
 /// Sets Wasmtime flags to ensure deterministic execution.
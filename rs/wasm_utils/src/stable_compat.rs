@@ -0,0 +1,27 @@
+//! Declarations of the stable memory schema of canister modules.
+//!
+//! A module may declare the schema of the data that it keeps in stable memory
+//! in a custom section, typically as a hash of the schema. Upgrading a canister
+//! from a module that declares a schema to one that declares a different
+//! schema would leave the new module unable to read the preserved data, so
+//! such upgrades are refused unless explicitly forced.
+
+use parity_wasm::elements::Module;
+
+/// The name of the custom section in which a module declares its stable memory
+/// schema.
+pub const STABLE_COMPAT_SECTION_NAME: &str = "icp:stable_compat";
+
+/// Returns the contents of the stable-compat custom section of `wasm`.
+///
+/// Returns `None` if the module does not declare a stable memory schema or
+/// cannot be deserialized. If the module contains the section more than once,
+/// the first one is returned.
+pub fn stable_compat_hash(wasm: &[u8]) -> Option<Vec<u8>> {
+    let module = parity_wasm::deserialize_buffer::<Module>(wasm).ok()?;
+    let hash = module
+        .custom_sections()
+        .find(|section| section.name() == STABLE_COMPAT_SECTION_NAME)
+        .map(|section| section.payload().to_vec());
+    hash
+}
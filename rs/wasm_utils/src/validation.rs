@@ -8,7 +8,7 @@ use parity_wasm::elements::{
     Instruction::{self},
     Internal, Module, Section, Type, ValueType,
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Symbols that are reserved and cannot be exported by canisters.
 #[doc(hidden)] // pub for usage in tests
@@ -26,18 +26,114 @@ struct FunctionSignature {
     pub return_type: Vec<ValueType>,
 }
 
-/// Controls how many globals and functions are allowed in a Wasm module on the
-/// Internet Computer.
+/// A pattern matching the name of an imported or exported function.
+///
+/// A pattern ending with `*` matches all names starting with the part before
+/// the `*`. Any other pattern matches only the name it consists of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamePattern(String);
+
+impl NamePattern {
+    pub fn new<S: ToString>(pattern: S) -> Self {
+        Self(pattern.to_string())
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self.0.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == self.0,
+        }
+    }
+}
+
+impl std::fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Allow-lists of functions that a Wasm module can import and export. They
+/// are checked on top of the rules of the interface spec, e.g. an allowed
+/// import still needs to be a System API function with the right signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmAllowLists {
+    /// Patterns of the functions that can be imported, keyed by the name of
+    /// the module they are imported from. Importing from any other module is
+    /// rejected.
+    pub imports: BTreeMap<String, Vec<NamePattern>>,
+    /// Patterns of the functions that can be exported. `None` allows
+    /// exporting any function that is not reserved.
+    pub exports: Option<Vec<NamePattern>>,
+}
+
+impl WasmAllowLists {
+    /// Creates the allow-lists from the patterns of the functions that can
+    /// be imported, keyed by module name, and of those that can be exported.
+    pub fn new(imports: &BTreeMap<String, Vec<String>>, exports: Option<&[String]>) -> Self {
+        let patterns =
+            |patterns: &[String]| patterns.iter().map(NamePattern::new).collect::<Vec<_>>();
+        Self {
+            imports: imports
+                .iter()
+                .map(|(module, functions)| (module.clone(), patterns(functions)))
+                .collect(),
+            exports: exports.map(patterns),
+        }
+    }
+
+    /// Returns the patterns of all the functions the interface spec allows
+    /// a canister to export.
+    pub fn canister_exports() -> Vec<NamePattern> {
+        vec![
+            NamePattern::new("canister_update *"),
+            NamePattern::new("canister_query *"),
+            NamePattern::new("canister_init"),
+            NamePattern::new("canister_pre_upgrade"),
+            NamePattern::new("canister_post_upgrade"),
+            NamePattern::new("canister_inspect_message"),
+            NamePattern::new("canister_heartbeat"),
+            NamePattern::new("canister_on_low_wasm_memory"),
+        ]
+    }
+}
+
+impl Default for WasmAllowLists {
+    /// Allows importing any function of the System API and exporting any
+    /// function.
+    fn default() -> Self {
+        let mut imports = BTreeMap::new();
+        imports.insert(API_VERSION_IC0.to_string(), vec![NamePattern::new("*")]);
+        imports.insert(METHOD_MODULE.to_string(), vec![NamePattern::new("*")]);
+        Self {
+            imports,
+            exports: None,
+        }
+    }
+}
+
+/// Controls how many globals, functions, and table and segment entries are
+/// allowed in a Wasm module on the Internet Computer.
 //
-// Note that we define a struct with the two limits instead of just passing them
+// Note that we define a struct with the limits instead of just passing them
 // to `validate_wasm_binary` to make it easier and safer to use as a caller
-// without worrying about mixing the two up (since they're both of type
-// `usize`).
+// without worrying about mixing them up (since they're all of type `usize`).
 pub struct WasmValidationLimits {
     /// Maximum number of globals allowed in a module.
     pub max_globals: usize,
     /// Maximum number of functions allowed in a module.
     pub max_functions: usize,
+    /// Maximum initial size of a table defined or imported by a module.
+    pub max_table_size: usize,
+    /// Maximum number of element segments allowed in a module.
+    pub max_element_segments: usize,
+    /// Maximum number of entries of all element segments of a module.
+    pub max_element_segments_size: usize,
+    /// Maximum number of data segments allowed in a module.
+    pub max_data_segments: usize,
+    /// Maximum number of bytes of all data segments of a module.
+    pub max_data_segments_size: usize,
+    /// Functions that are allowed to be imported and exported by a module.
+    pub allow_lists: WasmAllowLists,
 }
 
 impl Default for WasmValidationLimits {
@@ -45,6 +141,12 @@ impl Default for WasmValidationLimits {
         Self {
             max_globals: 200,
             max_functions: 6000,
+            max_table_size: 1 << 20,
+            max_element_segments: 10_000,
+            max_element_segments_size: 1 << 20,
+            max_data_segments: 10_000,
+            max_data_segments_size: 32 << 20,
+            allow_lists: WasmAllowLists::default(),
         }
     }
 }
@@ -103,6 +205,16 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 },
             )],
         ),
+        (
+            "msg_arg_data_next_chunk",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ValueType::I32, ValueType::I32],
+                    return_type: vec![ValueType::I32],
+                },
+            )],
+        ),
         (
             "msg_method_name_size",
             vec![(
@@ -324,6 +436,16 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 },
             )],
         ),
+        (
+            "call_queue_available",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ValueType::I32, ValueType::I32],
+                    return_type: vec![ValueType::I32],
+                },
+            )],
+        ),
         // Debugging aids
         (
             "debug_print",
@@ -515,6 +637,26 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 },
             )],
         ),
+        (
+            "root_key_size",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![],
+                    return_type: vec![ValueType::I32],
+                },
+            )],
+        ),
+        (
+            "root_key_copy",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ValueType::I32, ValueType::I32, ValueType::I32],
+                    return_type: vec![],
+                },
+            )],
+        ),
         (
             "canister_status",
             vec![(
@@ -604,6 +746,13 @@ fn get_valid_exported_functions() -> HashMap<String, FunctionSignature> {
                 return_type: vec![],
             },
         ),
+        (
+            "canister_on_low_wasm_memory",
+            FunctionSignature {
+                param_types: vec![],
+                return_type: vec![],
+            },
+        ),
     ];
 
     valid_exported_functions
@@ -656,14 +805,51 @@ fn set_imports_details(import_details: &mut WasmImportsDetails, import_module: &
     }
 }
 
+// Checks that the function `field` imported from `import_module` is in the
+// given allow-lists.
+fn validate_allowed_import(
+    allow_lists: &WasmAllowLists,
+    import_module: &str,
+    field: &str,
+) -> Result<(), WasmValidationError> {
+    match allow_lists.imports.get(import_module) {
+        None => Err(WasmValidationError::InvalidImportSection(format!(
+            "Module imports function '{}' from '{}', but functions can only be imported from {:?}.",
+            field,
+            import_module,
+            allow_lists.imports.keys().collect::<Vec<_>>(),
+        ))),
+        Some(patterns) => {
+            if patterns.iter().any(|pattern| pattern.matches(field)) {
+                Ok(())
+            } else {
+                Err(WasmValidationError::InvalidImportSection(format!(
+                    "Module imports function '{}' from '{}' that is not allowed, the allowed functions are [{}].",
+                    field,
+                    import_module,
+                    patterns
+                        .iter()
+                        .map(|pattern| pattern.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )))
+            }
+        }
+    }
+}
+
 // Performs the following checks for the import section:
-// * If we import memory or table, we can only import from “env”.
+// * If we import memory or table, it can only be `env.memory` or `env.table`.
+// * Any imported functions are in the allow-lists.
 // * Any imported functions that appear in `valid_system_apis` have the correct
 //   signatures.
 //
 // Returns information about what IC0 methods are imported via
 // `WasmImportsDetails`.
-fn validate_import_section(module: &Module) -> Result<WasmImportsDetails, WasmValidationError> {
+fn validate_import_section(
+    module: &Module,
+    allow_lists: &WasmAllowLists,
+) -> Result<WasmImportsDetails, WasmValidationError> {
     let mut imports_details = WasmImportsDetails::default();
 
     if let Some(section) = module.import_section() {
@@ -673,6 +859,7 @@ fn validate_import_section(module: &Module) -> Result<WasmImportsDetails, WasmVa
             let field = entry.field();
             match entry.external() {
                 External::Function(index) => {
+                    validate_allowed_import(allow_lists, import_module, field)?;
                     set_imports_details(&mut imports_details, import_module, field);
                     match valid_system_apis.get(field) {
                         Some(signatures) => {
@@ -700,17 +887,19 @@ fn validate_import_section(module: &Module) -> Result<WasmImportsDetails, WasmVa
                     }
                 }
                 External::Table(_) => {
-                    if field == "table" && import_module != "env" {
-                        return Err(WasmValidationError::InvalidImportSection(
-                            "Only tables imported from env.table are allowed.".to_string(),
-                        ));
+                    if field != "table" || import_module != "env" {
+                        return Err(WasmValidationError::InvalidImportSection(format!(
+                            "Module imports table '{}.{}', only tables imported from env.table are allowed.",
+                            import_module, field,
+                        )));
                     }
                 }
                 External::Memory(_) => {
-                    if field == "memory" && import_module != "env" {
-                        return Err(WasmValidationError::InvalidImportSection(
-                            "Only memory imported from env.memory is allowed.".to_string(),
-                        ));
+                    if field != "memory" || import_module != "env" {
+                        return Err(WasmValidationError::InvalidImportSection(format!(
+                            "Module imports memory '{}.{}', only memory imported from env.memory is allowed.",
+                            import_module, field,
+                        )));
                     };
                 }
                 External::Global(_) => {
@@ -730,10 +919,14 @@ fn validate_import_section(module: &Module) -> Result<WasmImportsDetails, WasmVa
 // * Validates the signatures of other allowed exported functions (like
 //   `canister_init` or `canister_pre_upgrade`) if present.
 // * Validates that the canister doesn't export any reserved symbols
+// * Validates that all exported functions are in the allow-lists.
 //
 // Returns the number of exported functions that are not in the list of
 // allowed exports and whose name starts with the reserved "canister_" prefix.
-fn validate_export_section(module: &Module) -> Result<usize, WasmValidationError> {
+fn validate_export_section(
+    module: &Module,
+    allow_lists: &WasmAllowLists,
+) -> Result<usize, WasmValidationError> {
     let mut reserved_exports: usize = 0;
     if let Some(section) = module.export_section() {
         let mut seen_funcs: HashSet<&str> = HashSet::new();
@@ -747,6 +940,22 @@ fn validate_export_section(module: &Module) -> Result<usize, WasmValidationError
                 )));
             }
             if let Internal::Function(fn_index) = export.internal() {
+                if let Some(patterns) = &allow_lists.exports {
+                    if !patterns
+                        .iter()
+                        .any(|pattern| pattern.matches(export.field()))
+                    {
+                        return Err(WasmValidationError::InvalidExportSection(format!(
+                            "Exporting function '{}' is not allowed, the allowed functions are [{}].",
+                            export.field(),
+                            patterns
+                                .iter()
+                                .map(|pattern| pattern.to_string())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        )));
+                    }
+                }
                 let mut func_name = export.field();
                 // func_name holds either:
                 // - the entire exported non-IC function names, or
@@ -861,6 +1070,123 @@ fn validate_function_section(
     Ok(())
 }
 
+// Checks that the module defines or imports at most one memory. Modules
+// using the multi-memory proposal are rejected with a dedicated error
+// instead of the generic compilation failure reported by wasmtime.
+fn validate_memories(module: &Module) -> Result<(), WasmValidationError> {
+    const MAX_MEMORIES: usize = 1;
+    let imported_memories = module.import_count(ImportCountType::Memory);
+    let defined_memories = module
+        .memory_section()
+        .map_or(0, |section| section.entries().len());
+    let memories = imported_memories + defined_memories;
+    if memories > MAX_MEMORIES {
+        return Err(WasmValidationError::TooManyMemories {
+            defined: memories,
+            allowed: MAX_MEMORIES,
+        });
+    }
+    Ok(())
+}
+
+// Checks that the start function, if any, is defined in the module. An
+// imported start function would be a system API call made while the
+// canister is instantiated, i.e., outside of any message execution.
+fn validate_start_function(module: &Module) -> Result<(), WasmValidationError> {
+    if let Some(index) = module.start_section() {
+        let import_count = module.import_count(ImportCountType::Function);
+        if (index as usize) < import_count {
+            return Err(WasmValidationError::InvalidStartFunction {
+                index: index as usize,
+                import_count,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Checks that the initial size of the tables defined or imported by the
+// module doesn't exceed `max_table_size`. Tables are allocated and
+// initialized when the module is instantiated.
+fn validate_tables(module: &Module, max_table_size: usize) -> Result<(), WasmValidationError> {
+    let imported_tables = module
+        .import_section()
+        .into_iter()
+        .flat_map(|section| section.entries().iter())
+        .filter_map(|entry| match entry.external() {
+            External::Table(table) => Some(table),
+            _ => None,
+        });
+    let defined_tables = module
+        .table_section()
+        .into_iter()
+        .flat_map(|section| section.entries().iter());
+    for table in imported_tables.chain(defined_tables) {
+        let size = table.limits().initial() as usize;
+        if size > max_table_size {
+            return Err(WasmValidationError::TableTooLarge {
+                size,
+                allowed: max_table_size,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Checks that the number of element segments and their total number of
+// entries don't exceed the limits. Element segments are copied into tables
+// when the module is instantiated.
+fn validate_element_segments(
+    module: &Module,
+    max_element_segments: usize,
+    max_element_segments_size: usize,
+) -> Result<(), WasmValidationError> {
+    if let Some(section) = module.elements_section() {
+        let segments = section.entries();
+        if segments.len() > max_element_segments {
+            return Err(WasmValidationError::TooManyElementSegments {
+                defined: segments.len(),
+                allowed: max_element_segments,
+            });
+        }
+        let size: usize = segments.iter().map(|s| s.members().len()).sum();
+        if size > max_element_segments_size {
+            return Err(WasmValidationError::ElementSegmentsTooLarge {
+                size,
+                allowed: max_element_segments_size,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Checks that the number of data segments and their total size don't exceed
+// the limits. Data segments are copied into memory when the module is
+// instantiated.
+fn validate_data_segments(
+    module: &Module,
+    max_data_segments: usize,
+    max_data_segments_size: usize,
+) -> Result<(), WasmValidationError> {
+    if let Some(section) = module.data_section() {
+        let segments = section.entries();
+        if segments.len() > max_data_segments {
+            return Err(WasmValidationError::TooManyDataSegments {
+                defined: segments.len(),
+                allowed: max_data_segments,
+            });
+        }
+        let size: usize = segments.iter().map(|s| s.value().len()).sum();
+        if size > max_data_segments_size {
+            return Err(WasmValidationError::DataSegmentsTooLarge {
+                size,
+                allowed: max_data_segments_size,
+            });
+        }
+    }
+    Ok(())
+}
+
 fn can_compile(wasm: &BinaryEncodedWasm) -> Result<(), WasmValidationError> {
     let mut config = wasmtime::Config::default();
     ensure_determinism(&mut config);
@@ -903,27 +1229,52 @@ pub struct WasmValidationDetails {
 ///
 /// It constructs a module by parsing the input Wasm binary and then calls into
 /// more specific methods that validate different sections of the Wasm binary.
+/// Imported and exported functions are also checked against the allow-lists
+/// in `config`.
+///
 /// Currently, the sections we verify are:
+/// * Memory
 /// * Import
 /// * Export
 /// * Code
 /// * Data
 /// * Global
 /// * Function
+/// * Start
+/// * Table
+/// * Element
 ///
 /// Additionally, it ensures that the wasm binary can actually compile.
 pub fn validate_wasm_binary(
     wasm: &BinaryEncodedWasm,
     config: WasmValidationLimits,
 ) -> Result<WasmValidationDetails, WasmValidationError> {
+    let module = parity_wasm::deserialize_buffer::<Module>(wasm.as_slice());
+    // Check the number of memories before compiling because wasmtime would
+    // reject such modules with an opaque error.
+    if let Ok(module) = &module {
+        validate_memories(module)?;
+    }
     can_compile(&wasm)?;
-    let module = parity_wasm::deserialize_buffer::<Module>(wasm.as_slice())
+    let module = module
         .map_err(|err| WasmValidationError::ParityDeserializeError(into_parity_wasm_error(err)))?;
-    let imports_details = validate_import_section(&module)?;
-    let reserved_exports = validate_export_section(&module)?;
+    let imports_details = validate_import_section(&module, &config.allow_lists)?;
+    let reserved_exports = validate_export_section(&module, &config.allow_lists)?;
     validate_data_section(&module)?;
     validate_global_section(&module, config.max_globals)?;
     validate_function_section(&module, config.max_functions)?;
+    validate_start_function(&module)?;
+    validate_tables(&module, config.max_table_size)?;
+    validate_element_segments(
+        &module,
+        config.max_element_segments,
+        config.max_element_segments_size,
+    )?;
+    validate_data_segments(
+        &module,
+        config.max_data_segments,
+        config.max_data_segments_size,
+    )?;
     Ok(WasmValidationDetails {
         reserved_exports,
         imports_details,
@@ -0,0 +1,6 @@
+(module
+  (func $mulAdd (param f32 f32) (result f32)
+    (f32.add
+      (f32.mul (get_local 0) (get_local 1))
+      (get_local 1)))
+  (export "mulAdd" (func $mulAdd)))
@@ -1,5 +1,9 @@
-use ic_wasm_types::BinaryEncodedWasm;
-use ic_wasm_utils::instrumentation::{instrument, InstructionCostTable, Segments};
+use ic_wasm_types::{BinaryEncodedWasm, InstructionCostOverrides, OpcodeClass};
+use ic_wasm_utils::instrumentation::{
+    instrument, instrument_with_write_barriers, is_write_barrier_export, persistent_globals,
+    write_barrier_bitmap_export, InstructionCostTable, Segments, WRITE_BARRIER_BITMAP_WORDS,
+    WRITE_BARRIER_OVERFLOW_EXPORT,
+};
 use parity_wasm::elements::{self, Module};
 use pretty_assertions::assert_eq;
 use std::fs;
@@ -80,6 +84,16 @@ fn metering_app() {
     inject_and_cmp("app", &conf);
 }
 
+#[test]
+fn metering_float_overrides() {
+    // The override of the class takes precedence over the cost of the
+    // instruction.
+    let conf = InstructionCostTable::new()
+        .with_instruction_cost("f32.add".to_string(), 3)
+        .with_overrides(InstructionCostOverrides::new().with_cost(OpcodeClass::Float, 10));
+    inject_and_cmp("float_ops", &conf);
+}
+
 #[test]
 fn metering_app2() {
     inject_and_cmp("app2", &InstructionCostTable::new());
@@ -130,6 +144,84 @@ fn simple_loop() {
     inject_and_cmp("simple_loop", &InstructionCostTable::new());
 }
 
+#[test]
+fn write_barriers_export_bitmap_and_validate() {
+    let wasm = BinaryEncodedWasm::new(
+        wabt::wat2wasm(
+            r#"(module
+            (memory 1)
+            (func (export "store") (param i32 i64)
+                (i64.store offset=4 (local.get 0) (local.get 1))
+                (f32.store (local.get 0) (f32.const 1))
+            )
+        )"#,
+        )
+        .unwrap(),
+    );
+    let output = instrument_with_write_barriers(&wasm, &InstructionCostTable::new()).unwrap();
+    for word in 0..WRITE_BARRIER_BITMAP_WORDS {
+        assert!(output.exports.contains(&write_barrier_bitmap_export(word)));
+    }
+    assert!(output.exports.contains(WRITE_BARRIER_OVERFLOW_EXPORT));
+    wabt::Module::read_binary(
+        output.binary.as_slice(),
+        &wabt::ReadBinaryOptions::default(),
+    )
+    .unwrap()
+    .validate()
+    .expect("module instrumented with write barriers is invalid");
+
+    // Without write barriers, the bitmap is not exported.
+    let output = instrument(&wasm, &InstructionCostTable::new()).unwrap();
+    assert!(!output.exports.contains(WRITE_BARRIER_OVERFLOW_EXPORT));
+}
+
+#[test]
+fn write_barrier_exports_are_recognized() {
+    let wasm = BinaryEncodedWasm::new(
+        wabt::wat2wasm(
+            r#"(module
+            (memory 1)
+            (global (export "g") (mut i32) (i32.const 0))
+            (func (export "store") (param i32)
+                (i32.store (local.get 0) (global.get 0))
+            )
+        )"#,
+        )
+        .unwrap(),
+    );
+    let output = instrument_with_write_barriers(&wasm, &InstructionCostTable::new()).unwrap();
+    let write_barrier_exports = output
+        .exports
+        .iter()
+        .filter(|name| is_write_barrier_export(name))
+        .count();
+    assert_eq!(write_barrier_exports, WRITE_BARRIER_BITMAP_WORDS + 1);
+    assert!(!is_write_barrier_export("g"));
+    assert!(!is_write_barrier_export("canister counter_instructions"));
+}
+
+#[test]
+fn persistent_globals_are_exported_mutable_globals_with_prefix() {
+    let wat = r#"
+        (module
+            (global (export "__persistent_immutable") i32 (i32.const 0))
+            (global (mut i32) (i32.const 0))
+            (global (export "counter") (mut i64) (i64.const 0))
+            (global (export "__persistent_counter") (mut i64) (i64.const 0))
+        )"#;
+    let wasm = BinaryEncodedWasm::new(wabt::wat2wasm(wat).unwrap());
+    let output = instrument(&wasm, &InstructionCostTable::new()).unwrap();
+    // The unexported mutable global is exported by the instrumentation, but is
+    // not persistent.
+    assert!(output.exports.contains("__persistent_mutable_global_1"));
+    let globals = persistent_globals(&output.binary).unwrap();
+    assert_eq!(
+        globals.into_iter().collect::<Vec<_>>(),
+        vec![("__persistent_counter".to_string(), 2)]
+    );
+}
+
 #[test]
 fn test_get_data() {
     let output = instrument(
@@ -0,0 +1,41 @@
+use ic_wasm_utils::stable_compat::{stable_compat_hash, STABLE_COMPAT_SECTION_NAME};
+
+/// Returns an empty module with the given custom sections appended.
+fn module_with_custom_sections(sections: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut wasm = wabt::wat2wasm("(module)").unwrap();
+    for (name, payload) in sections {
+        let size = 1 + name.len() + payload.len();
+        assert!(
+            name.len() < 128 && size < 128,
+            "LEB128 sizes must fit a byte"
+        );
+        wasm.push(0);
+        wasm.push(size as u8);
+        wasm.push(name.len() as u8);
+        wasm.extend_from_slice(name.as_bytes());
+        wasm.extend_from_slice(payload);
+    }
+    wasm
+}
+
+#[test]
+fn returns_declared_hash() {
+    let wasm = module_with_custom_sections(&[
+        ("other", b"ignored"),
+        (STABLE_COMPAT_SECTION_NAME, b"hash"),
+    ]);
+
+    assert_eq!(stable_compat_hash(&wasm), Some(b"hash".to_vec()));
+}
+
+#[test]
+fn returns_none_without_declaration() {
+    let wasm = module_with_custom_sections(&[("other", b"hash")]);
+
+    assert_eq!(stable_compat_hash(&wasm), None);
+}
+
+#[test]
+fn returns_none_for_invalid_module() {
+    assert_eq!(stable_compat_hash(&[1, 2, 3]), None);
+}
@@ -1,8 +1,8 @@
 use assert_matches::assert_matches;
 use ic_wasm_types::{BinaryEncodedWasm, WasmValidationError};
 use ic_wasm_utils::validation::{
-    validate_wasm_binary, WasmImportsDetails, WasmValidationDetails, WasmValidationLimits,
-    RESERVED_SYMBOLS,
+    validate_wasm_binary, NamePattern, WasmAllowLists, WasmImportsDetails, WasmValidationDetails,
+    WasmValidationLimits, RESERVED_SYMBOLS,
 };
 
 fn wat2wasm(wat: &str) -> Result<BinaryEncodedWasm, wabt::Error> {
@@ -157,6 +157,20 @@ fn can_validate_canister_heartbeat_with_invalid_params() {
     );
 }
 
+#[test]
+fn can_validate_canister_on_low_wasm_memory_with_invalid_return() {
+    let wasm = wat2wasm(
+        r#"(module
+                  (func $x (result i32) (i32.const 0))
+                  (export "canister_on_low_wasm_memory" (func $x)))"#,
+    )
+    .unwrap();
+    assert_matches!(
+        validate_wasm_binary(&wasm, WasmValidationLimits::default()),
+        Err(WasmValidationError::InvalidFunctionSignature(_))
+    );
+}
+
 #[test]
 fn can_validate_canister_pre_upgrade_with_invalid_return() {
     let wasm = wat2wasm(
@@ -336,6 +350,113 @@ fn can_validate_module_with_wrong_import_module_for_func() {
     );
 }
 
+#[test]
+fn can_validate_import_section_with_memory_not_named_memory() {
+    let wasm = wat2wasm(r#"(module (import "env" "mem" (memory (;0;) 529)))"#).unwrap();
+    assert_eq!(
+        validate_wasm_binary(&wasm, WasmValidationLimits::default()),
+        Err(WasmValidationError::InvalidImportSection(
+            "Module imports memory 'env.mem', only memory imported from env.memory is allowed."
+                .to_string()
+        ))
+    );
+}
+
+#[test]
+fn can_validate_module_with_import_func_not_in_allow_list() {
+    let wasm = wat2wasm(r#"(module (import "ic0" "msg_reply" (func $reply)))"#).unwrap();
+    let mut allow_lists = WasmAllowLists::default();
+    allow_lists
+        .imports
+        .insert("ic0".to_string(), vec![NamePattern::new("msg_arg_data_*")]);
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                allow_lists,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::InvalidImportSection(
+            "Module imports function 'msg_reply' from 'ic0' that is not allowed, the allowed functions are [msg_arg_data_*]."
+                .to_string()
+        ))
+    );
+}
+
+#[test]
+fn can_validate_module_with_import_func_from_module_not_in_allow_list() {
+    let wasm = wat2wasm(r#"(module (import "foo" "msg_reply" (func $reply)))"#).unwrap();
+    assert_eq!(
+        validate_wasm_binary(&wasm, WasmValidationLimits::default()),
+        Err(WasmValidationError::InvalidImportSection(
+            "Module imports function 'msg_reply' from 'foo', but functions can only be imported from [\"ic0\", \"method\"]."
+                .to_string()
+        ))
+    );
+}
+
+#[test]
+fn can_validate_module_with_export_in_allow_list() {
+    let wasm = wat2wasm(
+        r#"(module
+              (func $read)
+              (export "canister_query read" (func $read))
+              (export "canister_heartbeat" (func $read)))"#,
+    )
+    .unwrap();
+    let allow_lists = WasmAllowLists {
+        exports: Some(WasmAllowLists::canister_exports()),
+        ..Default::default()
+    };
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                allow_lists,
+                ..Default::default()
+            }
+        ),
+        Ok(WasmValidationDetails::default())
+    );
+}
+
+#[test]
+fn can_validate_module_with_export_not_in_allow_list() {
+    let wasm = wat2wasm(
+        r#"(module
+              (func $read)
+              (export "canister_query read" (func $read))
+              (export "read" (func $read)))"#,
+    )
+    .unwrap();
+    let allow_lists = WasmAllowLists {
+        exports: Some(vec![NamePattern::new("canister_query *")]),
+        ..Default::default()
+    };
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                allow_lists,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::InvalidExportSection(
+            "Exporting function 'read' is not allowed, the allowed functions are [canister_query *]."
+                .to_string()
+        ))
+    );
+}
+
+#[test]
+fn name_pattern_matches_prefix_or_exact_name() {
+    assert!(NamePattern::new("canister_update *").matches("canister_update foo"));
+    assert!(!NamePattern::new("canister_update *").matches("canister_query foo"));
+    assert!(NamePattern::new("canister_init").matches("canister_init"));
+    assert!(!NamePattern::new("canister_init").matches("canister_init_2"));
+}
+
 #[test]
 fn can_validate_module_with_too_many_globals() {
     let wasm = wat2wasm(
@@ -353,7 +474,8 @@ fn can_validate_module_with_too_many_globals() {
             &wasm,
             WasmValidationLimits {
                 max_globals: 2,
-                max_functions: 1024
+                max_functions: 1024,
+                ..Default::default()
             }
         ),
         Err(WasmValidationError::TooManyGlobals {
@@ -383,7 +505,8 @@ fn can_validate_module_with_too_many_functions() {
             &wasm,
             WasmValidationLimits {
                 max_globals: 256,
-                max_functions: 5
+                max_functions: 5,
+                ..Default::default()
             }
         ),
         Err(WasmValidationError::TooManyFunctions {
@@ -515,3 +638,212 @@ fn can_validate_valid_export_section_with_invalid_function_index() {
         })
     );
 }
+
+#[test]
+fn can_reject_module_with_multiple_memories() {
+    // wabt does not support the multi-memory proposal, so the module is
+    // encoded by hand: a memory section with two memories of one page each.
+    let wasm = BinaryEncodedWasm::new(vec![
+        0x00, 0x61, 0x73, 0x6d, // magic
+        0x01, 0x00, 0x00, 0x00, // version
+        0x05, 0x05, // memory section, 5 bytes
+        0x02, // two memories
+        0x00, 0x01, // memory 0: min 1
+        0x00, 0x01, // memory 1: min 1
+    ]);
+    assert_matches!(
+        validate_wasm_binary(&wasm, WasmValidationLimits::default()),
+        Err(WasmValidationError::TooManyMemories {
+            defined: 2,
+            allowed: 1
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_imported_and_defined_memory() {
+    let wasm = BinaryEncodedWasm::new(vec![
+        0x00, 0x61, 0x73, 0x6d, // magic
+        0x01, 0x00, 0x00, 0x00, // version
+        0x02, 0x0f, // import section, 15 bytes
+        0x01, // one import
+        0x03, 0x65, 0x6e, 0x76, // "env"
+        0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, // "memory"
+        0x02, 0x00, 0x01, // memory, min 1
+        0x05, 0x03, // memory section, 3 bytes
+        0x01, // one memory
+        0x00, 0x01, // memory 0: min 1
+    ]);
+    assert_matches!(
+        validate_wasm_binary(&wasm, WasmValidationLimits::default()),
+        Err(WasmValidationError::TooManyMemories {
+            defined: 2,
+            allowed: 1
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_imported_start_function() {
+    let wasm = wat2wasm(
+        r#"(module
+                (import "ic0" "msg_reply" (func $reply))
+                (start $reply))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_wasm_binary(&wasm, WasmValidationLimits::default()),
+        Err(WasmValidationError::InvalidStartFunction {
+            index: 0,
+            import_count: 1
+        })
+    );
+}
+
+#[test]
+fn can_validate_module_with_defined_start_function() {
+    let wasm = wat2wasm(
+        r#"(module
+                (import "ic0" "msg_reply" (func $reply))
+                (func $start)
+                (start $start))"#,
+    )
+    .unwrap();
+    assert!(validate_wasm_binary(&wasm, WasmValidationLimits::default()).is_ok());
+}
+
+#[test]
+fn can_reject_module_with_too_large_table() {
+    let wasm = wat2wasm(r#"(module (table 101 funcref))"#).unwrap();
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                max_table_size: 100,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::TableTooLarge {
+            size: 101,
+            allowed: 100
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_too_large_imported_table() {
+    let wasm = wat2wasm(r#"(module (import "env" "table" (table 101 funcref)))"#).unwrap();
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                max_table_size: 100,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::TableTooLarge {
+            size: 101,
+            allowed: 100
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_too_many_element_segments() {
+    let wasm = wat2wasm(
+        r#"(module
+                (table 10 funcref)
+                (func $f)
+                (elem (i32.const 0) $f)
+                (elem (i32.const 1) $f)
+                (elem (i32.const 2) $f))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                max_element_segments: 2,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::TooManyElementSegments {
+            defined: 3,
+            allowed: 2
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_too_large_element_segments() {
+    let wasm = wat2wasm(
+        r#"(module
+                (table 10 funcref)
+                (func $f)
+                (elem (i32.const 0) $f $f $f)
+                (elem (i32.const 3) $f $f))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                max_element_segments_size: 4,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::ElementSegmentsTooLarge {
+            size: 5,
+            allowed: 4
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_too_many_data_segments() {
+    let wasm = wat2wasm(
+        r#"(module
+                (memory 1)
+                (data (i32.const 0) "a")
+                (data (i32.const 1) "b")
+                (data (i32.const 2) "c"))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                max_data_segments: 2,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::TooManyDataSegments {
+            defined: 3,
+            allowed: 2
+        })
+    );
+}
+
+#[test]
+fn can_reject_module_with_too_large_data_segments() {
+    let wasm = wat2wasm(
+        r#"(module
+                (memory 1)
+                (data (i32.const 0) "abc")
+                (data (i32.const 3) "de"))"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_wasm_binary(
+            &wasm,
+            WasmValidationLimits {
+                max_data_segments_size: 4,
+                ..Default::default()
+            }
+        ),
+        Err(WasmValidationError::DataSegmentsTooLarge {
+            size: 5,
+            allowed: 4
+        })
+    );
+}